@@ -0,0 +1,66 @@
+// SentientOS ZK Batch Verification Benchmark
+// Serial verification against batch_verify for 10, 50 and 100 contract proofs
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use ark_bn254::Fr;
+
+use sentient_os::core::constants;
+use sentient_os::zk::circuits::{DigestCircuit, ZkCircuit};
+use sentient_os::zk::contracts::{self, ZkContract};
+use sentient_os::zk::{setup, verify};
+
+// Constants
+const BATCH_SIZES: [usize; 3] = [10, 50, 100];
+
+/// Proofs of the example contract, as `batch_verify` takes them and unbundled
+fn fixtures(count: usize) -> (Vec<(ZkContract, Vec<u8>)>, Vec<(Vec<u8>, Vec<Fr>)>) {
+    let contract: ZkContract = serde_yaml::from_str(&contracts::example_contract())
+        .expect("Failed to parse example contract");
+    let circuit = DigestCircuit::new(&format!("{}.bench", contract.name));
+    
+    let mut bundles = Vec::with_capacity(count);
+    let mut proofs = Vec::with_capacity(count);
+    for i in 0..count {
+        let witness = (i as u64).to_le_bytes();
+        let proof = verify::generate_proof(&circuit, &witness).expect("Failed to generate proof");
+        let inputs = circuit.public_inputs(&witness).expect("Failed to compute public inputs");
+        bundles.push((contract.clone(), verify::bundle_proof(&proof, &inputs).expect("Failed to bundle proof")));
+        proofs.push((proof, inputs));
+    }
+    (bundles, proofs)
+}
+
+fn zk_batch(c: &mut Criterion) {
+    // Parameters are generated into a throwaway root, not the user's
+    let root = std::env::temp_dir().join(format!("sentientos-bench-{}", std::process::id()));
+    std::env::set_var(constants::ROOT_ENV, &root);
+    verify::init().expect("Failed to initialize ZK verification");
+    
+    let (bundles, proofs) = fixtures(*BATCH_SIZES.iter().max().unwrap());
+    let vk = setup::verifying_key(&bundles[0].0.name)
+        .expect("Failed to load verifying key")
+        .expect("Example contract has no parameters");
+    
+    let mut group = c.benchmark_group("zk_batch");
+    group.sample_size(10);
+    for size in BATCH_SIZES {
+        group.bench_with_input(BenchmarkId::new("serial", size), &size, |b, &size| {
+            b.iter(|| {
+                for (proof, inputs) in &proofs[..size] {
+                    assert!(verify::verify_proof(&vk, proof, inputs).unwrap());
+                }
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("batch", size), &size, |b, &size| {
+            b.iter(|| {
+                assert!(verify::batch_verify(&bundles[..size]).unwrap().into_iter().all(|valid| valid));
+            })
+        });
+    }
+    group.finish();
+    
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+criterion_group!(benches, zk_batch);
+criterion_main!(benches);
@@ -0,0 +1,56 @@
+// SentientOS Host ABI Bindings
+// Safe wrappers around the sentient.* functions provided by MatrixBox
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::string::String;
+use alloc::vec;
+
+/// Log levels understood by the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum Level {
+    Debug = 0,
+    Info = 1,
+    Warn = 2,
+    Error = 3,
+}
+
+mod sys {
+    #[link(wasm_import_module = "sentient")]
+    extern "C" {
+        pub fn log(level: i32, ptr: *const u8, len: i32);
+        pub fn now_ms() -> i64;
+        pub fn container_id(ptr: *mut u8, cap: i32) -> i32;
+    }
+}
+
+/// Write a message to the host log
+pub fn log(level: Level, message: &str) {
+    unsafe { sys::log(level as i32, message.as_ptr(), message.len() as i32) }
+}
+
+/// Log at info level
+pub fn info(message: &str) {
+    log(Level::Info, message)
+}
+
+/// Host wall-clock time in milliseconds since the epoch
+pub fn now_ms() -> i64 {
+    unsafe { sys::now_ms() }
+}
+
+/// ID the host assigned to this container
+pub fn container_id() -> String {
+    let mut buf = vec![0u8; 32];
+    loop {
+        let len = unsafe { sys::container_id(buf.as_mut_ptr(), buf.len() as i32) } as usize;
+        if len <= buf.len() {
+            buf.truncate(len);
+            return String::from_utf8_lossy(&buf).into_owned();
+        }
+        buf.resize(len, 0);
+    }
+}
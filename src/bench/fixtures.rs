@@ -0,0 +1,251 @@
+// SentientOS Benchmark Fixtures
+// Deterministic synthetic data so runs are comparable across machines
+
+use anyhow::{Result, Context};
+use std::path::Path;
+use std::fs;
+use std::collections::HashMap;
+
+use crate::core::constants;
+use crate::matrixbox::container::{self, Container};
+use crate::package::{Ecosystem, InstalledPackage, PackageRegistry};
+
+/// Marker file identifying a root directory the benchmarks may wipe
+pub const BENCH_ROOT_MARKER: &str = ".bench-root";
+
+/// Fixed timestamp used in generated metadata (2025-01-01T00:00:00Z)
+const FIXTURE_EPOCH: u64 = 1_735_689_600;
+
+/// Deterministic pseudo-random generator (SplitMix64)
+///
+/// Kept in-tree so fixture bytes never change with a dependency upgrade.
+pub struct FixtureRng(u64);
+
+impl FixtureRng {
+    /// Create a generator from a seed
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+    
+    /// Next 64 random bits
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+    
+    /// Fill a buffer with random bytes
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+/// Write a tree of `total_bytes` spread over nested directories
+///
+/// Files are 1 MiB except the last, in directories of 16 files each.
+pub fn synthetic_tree(dir: &Path, total_bytes: u64, seed: u64) -> Result<()> {
+    const FILE_SIZE: u64 = 1024 * 1024;
+    const FILES_PER_DIR: u64 = 16;
+    
+    let mut rng = FixtureRng::new(seed);
+    let mut buf = vec![0u8; FILE_SIZE as usize];
+    let mut written = 0;
+    let mut index = 0;
+    
+    while written < total_bytes {
+        let size = FILE_SIZE.min(total_bytes - written) as usize;
+        rng.fill(&mut buf[..size]);
+        
+        let sub_dir = dir.join(format!("d{:03}", index / FILES_PER_DIR));
+        fs::create_dir_all(&sub_dir)?;
+        fs::write(sub_dir.join(format!("f{:04}.bin", index)), &buf[..size])?;
+        
+        written += size as u64;
+        index += 1;
+    }
+    
+    Ok(())
+}
+
+/// A valid WASM module padded with a custom section to `size` bytes
+pub fn wasm_module(size: usize, seed: u64) -> Vec<u8> {
+    // Magic, version, then one custom section: id 0, LEB128 size, name, data
+    let mut module = vec![0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+    let name = b"bench";
+    let overhead = module.len() + 1 + 5 + 1 + name.len();
+    let data_len = size.saturating_sub(overhead);
+    let section_len = 1 + name.len() + data_len;
+    
+    module.push(0x00);
+    // Fixed-width 5-byte LEB128 so the total size is exact
+    for i in 0..5 {
+        let byte = ((section_len >> (7 * i)) & 0x7f) as u8;
+        module.push(if i < 4 { byte | 0x80 } else { byte });
+    }
+    module.push(name.len() as u8);
+    module.extend_from_slice(name);
+    
+    let mut data = vec![0u8; data_len];
+    FixtureRng::new(seed).fill(&mut data);
+    module.extend_from_slice(&data);
+    module
+}
+
+/// Write a container directory with a main.wasm of `wasm_size` bytes
+pub fn container_dir(dir: &Path, name: &str, wasm_size: usize, seed: u64) -> Result<Container> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join("main.wasm"), wasm_module(wasm_size, seed))?;
+    
+    let mut container = crate::matrixbox::app::default_manifest(name);
+    container.metadata.created_at = "2025-01-01T00:00:00+00:00".to_string();
+    container.path = Some(dir.to_path_buf());
+    container::save_container(&container)?;
+    
+    Ok(container)
+}
+
+/// Whether the root directory may be wiped by the benchmarks
+///
+/// Only an empty root, or one a previous benchmark run marked, qualifies.
+pub fn root_is_disposable() -> Result<bool> {
+    let root = constants::root_dir();
+    if !root.exists() {
+        return Ok(true);
+    }
+    
+    if root.join(BENCH_ROOT_MARKER).exists() {
+        return Ok(true);
+    }
+    
+    Ok(fs::read_dir(&root)?.next().is_none())
+}
+
+/// Empty the root directory, keeping only the benchmark marker
+pub fn reset_root() -> Result<()> {
+    if !root_is_disposable()? {
+        anyhow::bail!("Refusing to wipe {}: it is not a benchmark root", constants::root_dir().display());
+    }
+    
+    let root = constants::root_dir();
+    fs::create_dir_all(&root)?;
+    for entry in fs::read_dir(&root)?.filter_map(Result::ok) {
+        let path = entry.path();
+        if entry.file_name() == BENCH_ROOT_MARKER {
+            continue;
+        }
+        if path.is_dir() {
+            fs::remove_dir_all(&path)?;
+        } else {
+            fs::remove_file(&path)?;
+        }
+    }
+    
+    fs::write(root.join(BENCH_ROOT_MARKER), "")?;
+    Ok(())
+}
+
+/// Populate a reset root with packages, containers and snapshots
+pub fn populate_root(packages: usize, containers: usize, snapshots: usize, seed: u64) -> Result<()> {
+    let root = constants::root_dir();
+    let mut rng = FixtureRng::new(seed);
+    
+    // Package registry
+    let mut registry = PackageRegistry {
+        last_updated: FIXTURE_EPOCH,
+        packages: HashMap::new(),
+    };
+    for i in 0..packages {
+        let name = format!("bench-pkg-{:03}", i);
+        registry.packages.insert(format!("npm:{}", name), InstalledPackage {
+            name,
+            version: format!("1.{}.{}", i / 10, i % 10),
+            ecosystem: Ecosystem::Npm,
+            path: root.join("packages").join("npm").to_string_lossy().to_string(),
+            container_id: None,
+            installed_at: FIXTURE_EPOCH,
+            config: HashMap::new(),
+            mirror: None,
+        });
+    }
+    let package_dir = root.join(".package");
+    fs::create_dir_all(&package_dir)?;
+    fs::write(package_dir.join("registry.json"), serde_json::to_string_pretty(&registry)?)?;
+    
+    // Containers and their registry
+    let mut container_paths = HashMap::new();
+    for i in 0..containers {
+        let name = format!("bench-app-{:03}", i);
+        let dir = root.join(constants::CONTAINER_DIR).join(&name);
+        container_dir(&dir, &name, 64 * 1024, rng.next_u64())?;
+        container_paths.insert(format!("{:016x}", rng.next_u64()), dir.to_string_lossy().to_string());
+    }
+    let registry_dir = root.join(constants::CONTAINER_DIR).join("registry");
+    fs::create_dir_all(&registry_dir)?;
+    fs::write(registry_dir.join("registry.json"),
+              serde_json::to_string_pretty(&serde_json::json!({ "containers": container_paths }))?)?;
+    
+    // Snapshots with a small core component each
+    for i in 0..snapshots {
+        let id = format!("{}-bench-{:02}", FIXTURE_EPOCH + i as u64 * 3600, i);
+        let snapshot_dir = root.join(constants::HEAL_DIR).join("snapshots").join(&id);
+        synthetic_tree(&snapshot_dir.join("core"), 256 * 1024, rng.next_u64())?;
+        
+        let metadata = serde_json::json!({
+            "id": id,
+            "timestamp": FIXTURE_EPOCH + i as u64 * 3600,
+            "reason": "bench fixture",
+            "components": ["core"],
+            "content_hash": "",
+            "mode": "normal",
+        });
+        fs::write(snapshot_dir.join("metadata.json"), serde_json::to_string_pretty(&metadata)?)
+            .with_context(|| format!("Failed to write fixture snapshot {}", id))?;
+    }
+    
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn fixture_bytes_depend_only_on_the_seed() {
+        let mut first = [0u8; 20];
+        let mut second = [0u8; 20];
+        FixtureRng::new(7).fill(&mut first);
+        FixtureRng::new(7).fill(&mut second);
+        assert_eq!(first, second);
+        
+        FixtureRng::new(8).fill(&mut second);
+        assert_ne!(first, second);
+    }
+    
+    #[test]
+    fn wasm_modules_have_the_exact_requested_size() {
+        for size in [64, 1000, 200_000] {
+            let module = wasm_module(size, 1);
+            assert_eq!(module.len(), size);
+            assert_eq!(&module[..8], b"\0asm\x01\0\0\0");
+        }
+        assert_eq!(wasm_module(4096, 3), wasm_module(4096, 3));
+    }
+    
+    #[test]
+    fn synthetic_trees_split_into_megabyte_files() {
+        let dir = std::env::temp_dir().join(format!("sentient-fixture-tree-{}", std::process::id()));
+        synthetic_tree(&dir, 17 * 1024 * 1024 + 10, 1).unwrap();
+        
+        let size = |path: &str| fs::metadata(dir.join(path)).unwrap().len();
+        assert_eq!(size("d000/f0000.bin"), 1024 * 1024);
+        assert_eq!(size("d001/f0016.bin"), 1024 * 1024);
+        assert_eq!(size("d001/f0017.bin"), 10);
+        assert!(!dir.join("d001/f0018.bin").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
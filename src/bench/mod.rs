@@ -0,0 +1,633 @@
+// SentientOS Benchmark Suite
+// Measures cold start and hot-path latency and guards against regressions
+
+pub mod fixtures;
+
+use anyhow::{Result, Context};
+use tracing::{info, warn};
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::process::Command;
+use std::time::Instant;
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+
+use crate::core::constants;
+
+/// Version of the report format
+const REPORT_FORMAT: u32 = 1;
+
+/// Seed for all fixture data
+const FIXTURE_SEED: u64 = 0x5e47_1e47;
+
+/// Benchmark thresholds, stored under `bench` in system.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BenchConfig {
+    /// Allowed slowdown of a benchmark's median, in percent
+    pub default_threshold_pct: f64,
+    
+    /// Per-benchmark overrides of the allowed slowdown
+    pub thresholds: BTreeMap<String, f64>,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            default_threshold_pct: 10.0,
+            thresholds: BTreeMap::new(),
+        }
+    }
+}
+
+/// Machine the benchmarks ran on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Environment {
+    /// Operating system
+    pub os: String,
+    
+    /// CPU architecture
+    pub arch: String,
+    
+    /// CPU model, if known
+    pub cpu_model: Option<String>,
+    
+    /// Logical CPUs
+    pub cpus: usize,
+    
+    /// Total memory in KiB, if known
+    pub memory_kib: Option<u64>,
+    
+    /// SentientOS version
+    pub sentient_version: String,
+}
+
+/// Throughput of a benchmark
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Throughput {
+    /// Units per second
+    pub per_second: f64,
+    
+    /// Unit, e.g. `messages` or `MiB`
+    pub unit: String,
+}
+
+/// Timing of one benchmark
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchResult {
+    /// Benchmark name
+    pub name: String,
+    
+    /// Measured iterations, excluding warmup
+    pub iterations: usize,
+    
+    /// Median iteration time in milliseconds
+    pub median_ms: f64,
+    
+    /// Mean iteration time in milliseconds
+    pub mean_ms: f64,
+    
+    /// Fastest iteration in milliseconds
+    pub min_ms: f64,
+    
+    /// Slowest iteration in milliseconds
+    pub max_ms: f64,
+    
+    /// Standard deviation in milliseconds
+    pub stddev_ms: f64,
+    
+    /// Throughput, for benchmarks that process a known amount of work
+    pub throughput: Option<Throughput>,
+}
+
+/// A benchmark that could not run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedBench {
+    /// Benchmark name
+    pub name: String,
+    
+    /// Why it was skipped
+    pub reason: String,
+}
+
+/// Results of a benchmark run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchReport {
+    /// Report format version
+    pub format: u32,
+    
+    /// Run start time (RFC 3339)
+    pub started_at: String,
+    
+    /// Machine the run happened on
+    pub environment: Environment,
+    
+    /// Benchmark results
+    pub results: Vec<BenchResult>,
+    
+    /// Benchmarks that could not run
+    pub skipped: Vec<SkippedBench>,
+}
+
+/// Change of one benchmark against a baseline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchDelta {
+    /// Benchmark name
+    pub name: String,
+    
+    /// Baseline median in milliseconds
+    pub baseline_ms: f64,
+    
+    /// Current median in milliseconds
+    pub current_ms: f64,
+    
+    /// Change in percent; positive is slower
+    pub change_pct: f64,
+    
+    /// Allowed slowdown in percent
+    pub threshold_pct: f64,
+    
+    /// Whether the slowdown exceeds the threshold
+    pub regression: bool,
+}
+
+/// A benchmark case
+struct Bench {
+    /// Benchmark name
+    name: &'static str,
+    
+    /// Measured iterations
+    iterations: usize,
+    
+    /// Run the benchmark
+    run: fn(usize) -> Result<Measurement>,
+}
+
+/// Raw measurement of a benchmark
+struct Measurement {
+    /// Iteration times in milliseconds
+    samples: Vec<f64>,
+    
+    /// Units of work per iteration, with their name
+    work: Option<(f64, &'static str)>,
+}
+
+/// Names of all benchmarks, in run order
+pub fn bench_names() -> Vec<&'static str> {
+    benches().iter().map(|b| b.name).collect()
+}
+
+/// Run the benchmarks whose name contains `filter`, or all of them
+pub fn run(filter: Option<&str>) -> Result<BenchReport> {
+    let mut report = BenchReport {
+        format: REPORT_FORMAT,
+        started_at: chrono::Utc::now().to_rfc3339(),
+        environment: environment(),
+        results: Vec::new(),
+        skipped: Vec::new(),
+    };
+    
+    for bench in benches() {
+        if filter.map_or(false, |f| !bench.name.contains(f)) {
+            continue;
+        }
+        
+        info!("Running benchmark: {}", bench.name);
+        match (bench.run)(bench.iterations) {
+            Ok(measurement) => report.results.push(summarize(bench.name, measurement)),
+            Err(e) => {
+                warn!("Benchmark {} skipped: {:#}", bench.name, e);
+                report.skipped.push(SkippedBench {
+                    name: bench.name.to_string(),
+                    reason: format!("{:#}", e),
+                });
+            }
+        }
+    }
+    
+    Ok(report)
+}
+
+/// Write a report as JSON
+pub fn save_report(report: &BenchReport, path: &Path) -> Result<()> {
+    fs::write(path, serde_json::to_string_pretty(report)?)
+        .with_context(|| format!("Failed to write benchmark report {:?}", path))
+}
+
+/// Read a report written by `save_report`
+pub fn load_report(path: &Path) -> Result<BenchReport> {
+    let data = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read benchmark report {:?}", path))?;
+    serde_json::from_str(&data).with_context(|| format!("Invalid benchmark report {:?}", path))
+}
+
+/// Compare a report against a baseline by median time
+///
+/// `default_threshold` overrides the configured default; per-benchmark
+/// thresholds from the configuration still apply.
+pub fn compare(current: &BenchReport, baseline: &BenchReport, default_threshold: Option<f64>) -> Result<Vec<BenchDelta>> {
+    let config = load_config()?;
+    let default_threshold = default_threshold.unwrap_or(config.default_threshold_pct);
+    
+    if current.environment.arch != baseline.environment.arch || current.environment.cpus != baseline.environment.cpus {
+        warn!("Baseline was recorded on a different machine class ({} x{}); deltas may not be meaningful",
+              baseline.environment.arch, baseline.environment.cpus);
+    }
+    
+    let baseline_by_name: BTreeMap<&str, &BenchResult> = baseline.results.iter()
+        .map(|r| (r.name.as_str(), r))
+        .collect();
+    
+    Ok(current.results.iter()
+        .filter_map(|result| {
+            let base = baseline_by_name.get(result.name.as_str())?;
+            let change_pct = if base.median_ms > 0.0 {
+                (result.median_ms - base.median_ms) / base.median_ms * 100.0
+            } else {
+                0.0
+            };
+            let threshold_pct = config.thresholds.get(&result.name).copied().unwrap_or(default_threshold);
+            
+            Some(BenchDelta {
+                name: result.name.clone(),
+                baseline_ms: base.median_ms,
+                current_ms: result.median_ms,
+                change_pct,
+                threshold_pct,
+                regression: change_pct > threshold_pct,
+            })
+        })
+        .collect())
+}
+
+/// All benchmark cases
+fn benches() -> Vec<Bench> {
+    vec![
+        Bench { name: "init.empty_root", iterations: 5, run: bench_init_empty },
+        Bench { name: "init.populated_root", iterations: 5, run: bench_init_populated },
+        Bench { name: "snapshot.synthetic_100mb", iterations: 3, run: bench_snapshot },
+        Bench { name: "zk.execute_and_prove", iterations: 20, run: bench_zk },
+        Bench { name: "zk.verify_serial_10", iterations: 5, run: |n| bench_zk_verify(n, 10, false) },
+        Bench { name: "zk.verify_batch_10", iterations: 5, run: |n| bench_zk_verify(n, 10, true) },
+        Bench { name: "zk.verify_serial_50", iterations: 5, run: |n| bench_zk_verify(n, 50, false) },
+        Bench { name: "zk.verify_batch_50", iterations: 5, run: |n| bench_zk_verify(n, 50, true) },
+        Bench { name: "zk.verify_serial_100", iterations: 5, run: |n| bench_zk_verify(n, 100, false) },
+        Bench { name: "zk.verify_batch_100", iterations: 5, run: |n| bench_zk_verify(n, 100, true) },
+        Bench { name: "tso.pack_extract_10mb", iterations: 5, run: bench_tso },
+        Bench { name: "gossip.encode_decode", iterations: 10, run: bench_gossip },
+    ]
+}
+
+/// Cold start of a full init on an empty root
+fn bench_init_empty(iterations: usize) -> Result<Measurement> {
+    require_disposable_root()?;
+    let samples = measure(iterations, fixtures::reset_root, |_| cold_start())?;
+    fixtures::reset_root()?;
+    Ok(Measurement { samples, work: None })
+}
+
+/// Cold start of a full init on a root with 100 packages, 50 containers and 20 snapshots
+fn bench_init_populated(iterations: usize) -> Result<Measurement> {
+    require_disposable_root()?;
+    let setup = || {
+        fixtures::reset_root()?;
+        fixtures::populate_root(100, 50, 20, FIXTURE_SEED)
+    };
+    let samples = measure(iterations, setup, |_| cold_start())?;
+    fixtures::reset_root()?;
+    Ok(Measurement { samples, work: None })
+}
+
+/// Snapshot copy and hash of a synthetic 100 MiB tree
+fn bench_snapshot(iterations: usize) -> Result<Measurement> {
+    const TREE_BYTES: u64 = 100 * 1024 * 1024;
+    
+    let scratch = Scratch::new("snapshot")?;
+    let source = scratch.path.join("source");
+    fixtures::synthetic_tree(&source, TREE_BYTES, FIXTURE_SEED)?;
+    
+    let target = scratch.path.join("snapshot");
+    let setup = || {
+        if target.exists() {
+            fs::remove_dir_all(&target)?;
+        }
+        Ok(())
+    };
+    let samples = measure(iterations, setup, |_| crate::heal::snapshot::snapshot_tree(&source, &target).map(drop))?;
+    
+    Ok(Measurement { samples, work: Some((TREE_BYTES as f64 / (1024.0 * 1024.0), "MiB")) })
+}
+
+/// Contract method execution followed by proof generation
+fn bench_zk(iterations: usize) -> Result<Measurement> {
+    let contract: crate::zk::contracts::ZkContract = serde_yaml::from_str(&crate::zk::contracts::example_contract())
+        .context("Failed to parse example contract")?;
+    
+    let samples = measure(iterations, || Ok(()), |_| {
+        crate::zk::execute_contract_method(&contract, "get_counter", &[]).map(drop)
+    })?;
+    
+    Ok(Measurement { samples, work: None })
+}
+
+/// Verification of `count` proofs of the example contract, one by one or batched
+fn bench_zk_verify(iterations: usize, count: usize, batched: bool) -> Result<Measurement> {
+    use crate::zk::circuits::{DigestCircuit, ZkCircuit};
+    
+    let contract: crate::zk::contracts::ZkContract = serde_yaml::from_str(&crate::zk::contracts::example_contract())
+        .context("Failed to parse example contract")?;
+    let circuit = DigestCircuit::new(&format!("{}.bench", contract.name));
+    
+    let mut proofs = Vec::with_capacity(count);
+    let mut bundles = Vec::with_capacity(count);
+    for i in 0..count {
+        let witness = (i as u64 ^ FIXTURE_SEED).to_le_bytes();
+        let proof = crate::zk::verify::generate_proof(&circuit, &witness)?;
+        let inputs = circuit.public_inputs(&witness)?;
+        bundles.push((contract.clone(), crate::zk::verify::bundle_proof(&proof, &inputs)?));
+        proofs.push((proof, inputs));
+    }
+    let vk = crate::zk::setup::verifying_key(circuit.name())?
+        .context("Example contract has no parameters")?;
+    
+    let samples = measure(iterations, || Ok(()), |_| {
+        let valid = if batched {
+            crate::zk::verify::batch_verify(&bundles)?.into_iter().filter(|v| *v).count()
+        } else {
+            let mut valid = 0;
+            for (proof, inputs) in &proofs {
+                valid += crate::zk::verify::verify_proof(&vk, proof, inputs)? as usize;
+            }
+            valid
+        };
+        
+        if valid != count {
+            anyhow::bail!("{} of {} proofs failed verification", count - valid, count);
+        }
+        Ok(())
+    })?;
+    
+    Ok(Measurement { samples, work: Some((count as f64, "proofs")) })
+}
+
+/// Pack and extract of a container with a 10 MiB module
+fn bench_tso(iterations: usize) -> Result<Measurement> {
+    const MODULE_BYTES: usize = 10 * 1024 * 1024;
+    
+    let scratch = Scratch::new("tso")?;
+    let container = fixtures::container_dir(&scratch.path.join("bench-app"), "bench-app", MODULE_BYTES, FIXTURE_SEED)?;
+    let archive = scratch.path.join("bench-app.tso");
+    let extract_dir = scratch.path.join("extracted");
+    let container_dir = container.path.clone()
+        .ok_or_else(|| anyhow::anyhow!("Fixture container has no path"))?;
+    let config = crate::matrixbox::container::ContainerConfig {
+        name: container.name.clone(),
+        version: Some(container.version.clone()),
+        ..Default::default()
+    };
+    
+    let setup = || {
+        if extract_dir.exists() {
+            fs::remove_dir_all(&extract_dir)?;
+        }
+        Ok(())
+    };
+    let samples = measure(iterations, setup, |_| {
+        crate::matrixbox::tso::create_tso_archive(&container_dir, &archive, &config)?;
+        crate::matrixbox::tso::extract_tso_archive(&archive, &extract_dir)?;
+        Ok(())
+    })?;
+    
+    Ok(Measurement { samples, work: Some((MODULE_BYTES as f64 / (1024.0 * 1024.0), "MiB")) })
+}
+
+/// Gossip message encode and decode round trips
+fn bench_gossip(iterations: usize) -> Result<Measurement> {
+    use crate::gossip::protocol::{decode_message, encode_message, MessageType};
+    const MESSAGES: usize = 10_000;
+    
+    let mut payload = vec![0u8; 1024];
+    fixtures::FixtureRng::new(FIXTURE_SEED).fill(&mut payload);
+    
+    let samples = measure(iterations, || Ok(()), |_| {
+        for _ in 0..MESSAGES {
+            let encoded = encode_message("bench-node", MessageType::StateUpdate, &payload)?;
+            let (_, _, decoded) = decode_message(&encoded)?;
+            if decoded.len() != payload.len() {
+                anyhow::bail!("Gossip payload changed in round trip");
+            }
+        }
+        Ok(())
+    })?;
+    
+    Ok(Measurement { samples, work: Some((MESSAGES as f64, "messages")) })
+}
+
+/// Time `body` over `iterations` runs after one warmup, excluding `setup`
+fn measure<S, B>(iterations: usize, mut setup: S, mut body: B) -> Result<Vec<f64>>
+where
+    S: FnMut() -> Result<()>,
+    B: FnMut(usize) -> Result<()>,
+{
+    let mut samples = Vec::with_capacity(iterations);
+    
+    for i in 0..=iterations {
+        setup()?;
+        let started = Instant::now();
+        body(i)?;
+        let elapsed = started.elapsed().as_secs_f64() * 1000.0;
+        
+        // The first run warms caches and is discarded
+        if i > 0 {
+            samples.push(elapsed);
+        }
+    }
+    
+    Ok(samples)
+}
+
+/// Run a full system init in a fresh process
+fn cold_start() -> Result<()> {
+    let exe = std::env::current_exe().context("Failed to locate the sentient binary")?;
+    let output = Command::new(exe)
+        .arg("init")
+        .env("SENTIENT_LOG", "error")
+        .output()
+        .context("Failed to start init process")?;
+    
+    if !output.status.success() {
+        anyhow::bail!("init exited with {}: {}", output.status, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Fail unless the init benchmarks may wipe the root directory
+fn require_disposable_root() -> Result<()> {
+    if !fixtures::root_is_disposable()? {
+        anyhow::bail!("{} is in use; init benchmarks need an empty root or one containing {}",
+                      constants::root_dir().display(), fixtures::BENCH_ROOT_MARKER);
+    }
+    Ok(())
+}
+
+/// Statistics of a measurement
+fn summarize(name: &str, measurement: Measurement) -> BenchResult {
+    let mut samples = measurement.samples;
+    samples.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    
+    let n = samples.len().max(1) as f64;
+    let mean = samples.iter().sum::<f64>() / n;
+    let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / n;
+    let median = match samples.len() {
+        0 => 0.0,
+        len if len % 2 == 0 => (samples[len / 2 - 1] + samples[len / 2]) / 2.0,
+        len => samples[len / 2],
+    };
+    
+    BenchResult {
+        name: name.to_string(),
+        iterations: samples.len(),
+        median_ms: median,
+        mean_ms: mean,
+        min_ms: samples.first().copied().unwrap_or(0.0),
+        max_ms: samples.last().copied().unwrap_or(0.0),
+        stddev_ms: variance.sqrt(),
+        throughput: measurement.work.filter(|_| median > 0.0).map(|(units, unit)| Throughput {
+            per_second: units / (median / 1000.0),
+            unit: unit.to_string(),
+        }),
+    }
+}
+
+/// Describe the machine
+fn environment() -> Environment {
+    let cpuinfo = fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+    let meminfo = fs::read_to_string("/proc/meminfo").unwrap_or_default();
+    
+    Environment {
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        cpu_model: cpuinfo.lines()
+            .find(|l| l.starts_with("model name"))
+            .and_then(|l| l.split_once(':'))
+            .map(|(_, model)| model.trim().to_string()),
+        cpus: std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1),
+        memory_kib: meminfo.lines()
+            .find(|l| l.starts_with("MemTotal:"))
+            .and_then(|l| l.split_whitespace().nth(1))
+            .and_then(|kib| kib.parse().ok()),
+        sentient_version: crate::VERSION.to_string(),
+    }
+}
+
+/// Load the benchmark configuration
+fn load_config() -> Result<BenchConfig> {
+    let path = constants::root_dir().join(".config").join("system.json");
+    if !path.exists() {
+        return Ok(BenchConfig::default());
+    }
+    
+    let system_config: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    match system_config.get("bench") {
+        Some(value) => Ok(serde_json::from_value(value.clone())
+            .context("Invalid bench configuration in system.json")?),
+        None => Ok(BenchConfig::default()),
+    }
+}
+
+/// Temporary directory removed when dropped
+struct Scratch {
+    path: PathBuf,
+}
+
+impl Scratch {
+    fn new(name: &str) -> Result<Self> {
+        let path = std::env::temp_dir().join(format!("sentient-bench-{}-{}", name, std::process::id()));
+        if path.exists() {
+            fs::remove_dir_all(&path)?;
+        }
+        fs::create_dir_all(&path)?;
+        Ok(Self { path })
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn report(medians: &[(&str, f64)]) -> BenchReport {
+        BenchReport {
+            format: REPORT_FORMAT,
+            started_at: "2025-01-01T00:00:00+00:00".to_string(),
+            environment: environment(),
+            results: medians.iter()
+                .map(|(name, median)| summarize(name, Measurement { samples: vec![*median], work: None }))
+                .collect(),
+            skipped: Vec::new(),
+        }
+    }
+    
+    #[test]
+    fn summaries_report_median_spread_and_throughput() {
+        let result = summarize("odd", Measurement { samples: vec![30.0, 10.0, 20.0], work: Some((5.0, "MiB")) });
+        assert_eq!((result.iterations, result.median_ms, result.mean_ms), (3, 20.0, 20.0));
+        assert_eq!((result.min_ms, result.max_ms), (10.0, 30.0));
+        assert!((result.stddev_ms - (200.0f64 / 3.0).sqrt()).abs() < 1e-9);
+        let throughput = result.throughput.unwrap();
+        assert_eq!((throughput.per_second, throughput.unit.as_str()), (250.0, "MiB"));
+        
+        let even = summarize("even", Measurement { samples: vec![4.0, 1.0, 2.0, 3.0], work: None });
+        assert_eq!(even.median_ms, 2.5);
+        assert!(even.throughput.is_none());
+        
+        let empty = summarize("empty", Measurement { samples: Vec::new(), work: Some((1.0, "proofs")) });
+        assert_eq!((empty.iterations, empty.median_ms), (0, 0.0));
+        assert!(empty.throughput.is_none());
+    }
+    
+    #[test]
+    fn measurements_discard_the_warmup_and_set_up_every_run() {
+        let mut setups = 0;
+        let mut runs = Vec::new();
+        let samples = measure(3, || { setups += 1; Ok(()) }, |i| { runs.push(i); Ok(()) }).unwrap();
+        
+        assert_eq!(samples.len(), 3);
+        assert_eq!(setups, 4);
+        assert_eq!(runs, [0, 1, 2, 3]);
+        assert!(measure(3, || Ok(()), |_| anyhow::bail!("broken")).is_err());
+    }
+    
+    #[test]
+    fn comparisons_flag_slowdowns_beyond_the_threshold() {
+        let baseline = report(&[("fast", 10.0), ("slow", 10.0), ("zero", 0.0), ("retired", 5.0)]);
+        let current = report(&[("fast", 9.0), ("slow", 12.0), ("zero", 3.0), ("new", 1.0)]);
+        
+        let deltas = compare(&current, &baseline, Some(15.0)).unwrap();
+        let names: Vec<&str> = deltas.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["fast", "slow", "zero"]);
+        assert!((deltas[0].change_pct + 10.0).abs() < 1e-9 && !deltas[0].regression);
+        assert!((deltas[1].change_pct - 20.0).abs() < 1e-9 && deltas[1].regression);
+        assert_eq!((deltas[2].change_pct, deltas[2].regression), (0.0, false));
+        assert!(deltas.iter().all(|d| d.threshold_pct == 15.0));
+    }
+    
+    #[test]
+    fn reports_round_trip_through_json() {
+        let path = std::env::temp_dir().join(format!("sentient-bench-report-{}.json", std::process::id()));
+        let saved = report(&[("gossip.encode_decode", 42.0)]);
+        save_report(&saved, &path).unwrap();
+        
+        let loaded = load_report(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.format, REPORT_FORMAT);
+        assert_eq!(loaded.results[0].name, "gossip.encode_decode");
+        assert_eq!(loaded.results[0].median_ms, 42.0);
+        assert!(load_report(&path).is_err());
+    }
+}
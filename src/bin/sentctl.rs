@@ -20,7 +20,11 @@ enum Commands {
     },
     
     /// Verify full ZK proof chains across system
-    ZkVerify {},
+    ZkVerify {
+        /// Re-generate the stored proofs of verified contracts
+        #[arg(long)]
+        refresh: bool,
+    },
     
     /// Rollback to previous system state
     Rollback {
@@ -31,9 +35,17 @@ enum Commands {
     
     /// Build bootable OS image
     IsoBuild {
-        /// Output directory for ISO
+        /// Output path for the image
         #[arg(short, long)]
         output: Option<PathBuf>,
+        
+        /// Target architecture, instead of the configured one
+        #[arg(long)]
+        arch: Option<String>,
+        
+        /// Boot mode of the image: normal, recovery or zero
+        #[arg(long)]
+        mode: Option<String>,
     },
     
     /// Boot into minimal zero-mode runtime
@@ -75,6 +87,10 @@ enum Commands {
     #[command(subcommand)]
     Gossip(GossipCommands),
     
+    /// Peer networking
+    #[command(subcommand)]
+    Network(NetworkCommands),
+    
     /// Developer intent recording and playback
     #[command(subcommand)]
     Intent(IntentCommands),
@@ -110,6 +126,25 @@ enum Commands {
         #[arg(required = true)]
         module: String,
     },
+    
+    /// Show the state of each subsystem
+    Status {},
+    
+    /// Run the runtime in the foreground, serving other sentctl commands
+    Daemon {
+        /// Disable ZK proof enforcement
+        #[arg(long)]
+        zk: Option<bool>,
+        
+        #[command(subcommand)]
+        command: Option<DaemonCommands>,
+    },
+}
+
+#[derive(Subcommand)]
+enum DaemonCommands {
+    /// Stop the running daemon, shutting the runtime down in order
+    Stop {},
 }
 
 #[derive(Subcommand)]
@@ -133,6 +168,53 @@ enum MatrixboxCommands {
         #[arg(required = true)]
         id: String,
     },
+    
+    /// List files a container added (A), modified (M) or deleted (D)
+    Diff {
+        /// Container ID
+        #[arg(required = true)]
+        id: String,
+    },
+    
+    /// Show the host ports forwarded to a container
+    Ports {
+        /// Container ID
+        #[arg(required = true)]
+        id: String,
+    },
+    
+    /// Sign a container image with this node's image key
+    Sign {
+        /// Image directory
+        #[arg(required = true)]
+        image: PathBuf,
+    },
+    
+    /// Check that a container image is signed by a trusted key
+    VerifyImage {
+        /// Image directory
+        #[arg(required = true)]
+        image: PathBuf,
+    },
+    
+    /// Start or stop a set of containers from a compose file
+    Compose {
+        #[command(subcommand)]
+        command: ComposeCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum ComposeCommands {
+    /// Start the services of a compose file in dependency order
+    Up {
+        /// Compose file
+        #[arg(default_value = "sentient-compose.yaml")]
+        file: PathBuf,
+    },
+    
+    /// Stop and remove the services that are up, in reverse order
+    Down,
 }
 
 #[derive(Subcommand)]
@@ -183,19 +265,129 @@ enum HealCommands {
     
     /// Rebuild kernel space from last clean .boot
     Boot {},
+    
+    /// Take a snapshot now
+    Snapshot {
+        /// Why the snapshot is taken
+        #[arg(long, default_value = "manual")]
+        reason: String,
+        
+        /// Only store files changed since the base snapshot
+        #[arg(long)]
+        incremental: bool,
+        
+        /// Base of an incremental snapshot; defaults to the latest snapshot
+        #[arg(long, requires = "incremental")]
+        base: Option<String>,
+        
+        /// Only snapshot this container's data and registry entry
+        #[arg(long, conflicts_with = "incremental")]
+        container: Option<String>,
+        
+        /// Take the scheduled snapshot now and restart the schedule from it
+        #[arg(long, conflicts_with_all = ["incremental", "container"])]
+        now: bool,
+    },
+    
+    /// Merge a chain of incremental snapshots into one full snapshot
+    Compact {
+        /// Snapshot IDs of the chain, oldest first
+        #[arg(required = true)]
+        ids: Vec<String>,
+    },
+    
+    /// Delete old snapshots, keeping the panic fallback snapshot
+    Prune {
+        /// Keep at most this many snapshots
+        #[arg(long)]
+        keep: Option<usize>,
+        
+        /// Delete snapshots older than this many days
+        #[arg(long)]
+        max_age_days: Option<u64>,
+        
+        /// Always keep at least this many of the newest snapshots
+        #[arg(long)]
+        min_keep: Option<usize>,
+        
+        /// Show the snapshots that would be deleted without deleting them
+        #[arg(long)]
+        dry_run: bool,
+    },
+    
+    /// Check a snapshot against its recorded content hash and ZK proof
+    #[command(alias = "verify-snapshot")]
+    Verify {
+        /// Snapshot ID
+        #[arg(required = true)]
+        id: String,
+    },
+    
+    /// Restore one component from a snapshot, pausing only its subsystem
+    Rollback {
+        /// Component to restore, e.g. zk or auth
+        #[arg(long)]
+        component: String,
+        
+        /// Snapshot ID to restore it from
+        #[arg(long)]
+        snapshot: String,
+        
+        /// Show what would be restored without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    
+    /// Scheduled snapshots
+    #[command(subcommand)]
+    Schedule(ScheduleCommands),
+    
+    /// Rolling snapshot ring buffer
+    #[command(subcommand)]
+    Ring(RingCommands),
+}
+
+#[derive(Subcommand)]
+enum ScheduleCommands {
+    /// Show the snapshot interval and the last and next run times
+    Show {},
+}
+
+#[derive(Subcommand)]
+enum RingCommands {
+    /// Show which ring slots are occupied and how old their snapshots are
+    Status {},
 }
 
 #[derive(Subcommand)]
 enum PanicCommands {
     /// Recover from panic state using fallback
-    Recover {},
+    Recover {
+        /// Recover even from a critical panic or during a panic storm cooldown
+        #[arg(long)]
+        force: bool,
+    },
+    
+    /// Show the last panic and whether recovery is in a panic storm cooldown
+    Status {},
     
     /// Generate crash report from panic logs
     Report {
         /// Output directory for report
         #[arg(short, long)]
         output: Option<PathBuf>,
+        
+        /// Include every backtrace frame instead of the innermost 20
+        #[arg(long)]
+        full_backtrace: bool,
+        
+        /// Only include panics at or above this severity (low, medium, high or critical)
+        #[arg(long)]
+        severity_filter: Option<String>,
     },
+    
+    /// Send crash reports that could not be sent to the remote endpoint
+    SendPending {},
 }
 
 #[derive(Subcommand)]
@@ -218,6 +410,27 @@ enum GossipCommands {
     },
 }
 
+#[derive(Subcommand)]
+enum NetworkCommands {
+    /// TLS certificates of peer connections
+    #[command(subcommand)]
+    Tls(TlsCommands),
+}
+
+#[derive(Subcommand)]
+enum TlsCommands {
+    /// Generate a self-signed certificate and key for a development node
+    Init {
+        /// Name the certificate is issued to: the IP address or host name peers connect to
+        #[arg(long, default_value = "localhost")]
+        common_name: String,
+        
+        /// Directory to write cert.pem and key.pem to, instead of .network/tls
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
 #[derive(Subcommand)]
 enum IntentCommands {
     /// Start recording developer intent session
@@ -242,6 +455,10 @@ enum PackageCommands {
         /// Package ecosystem (native, linux, npm, python, java, rust, go)
         #[arg(short, long)]
         ecosystem: Option<String>,
+        
+        /// Install even if the license is on the deny list (audited)
+        #[arg(long)]
+        allow_denied_license: bool,
     },
     
     /// Remove an installed package
@@ -253,6 +470,10 @@ enum PackageCommands {
         /// Package ecosystem (native, linux, npm, python, java, rust, go)
         #[arg(short, long)]
         ecosystem: Option<String>,
+        
+        /// Delete immediately instead of moving to the trash
+        #[arg(long)]
+        purge: bool,
     },
     
     /// List installed packages
@@ -336,9 +557,12 @@ fn main() {
             other => crate::package::Ecosystem::Other(other.to_string()),
         })
     }
-
+    
     let cli = Cli::parse();
-
+    
+    // Recorded into the intent session, if one is active
+    let recording = sentient_os::intent::CommandRecording::start(&std::env::args().collect::<Vec<_>>());
+    
     // Match on the subcommand
     match &cli.command {
         Commands::Init { zk } => {
@@ -347,8 +571,11 @@ fn main() {
             // TODO: Implement actual initialization logic
         }
         
-        Commands::ZkVerify {} => {
+        Commands::ZkVerify { refresh } => {
             println!("Verifying ZK proof chains across system...");
+            if *refresh {
+                println!("Stored proofs of verified contracts will be re-generated");
+            }
             // TODO: Implement verification logic
         }
         
@@ -360,10 +587,27 @@ fn main() {
             // TODO: Implement rollback logic
         }
         
-        Commands::IsoBuild { output } => {
-            let out_dir = output.as_deref().unwrap_or(std::path::Path::new("./"));
-            println!("Building ISO image in: {:?}", out_dir);
-            // TODO: Implement ISO build logic
+        Commands::IsoBuild { output, arch, mode } => {
+            use sentient_os::boot;
+            
+            let output = output.clone().unwrap_or_else(|| PathBuf::from("sentientos.iso"));
+            let built = boot::load_boot_config().and_then(|mut config| {
+                if let Some(arch) = arch {
+                    config.arch = arch.clone();
+                }
+                if let Some(mode) = mode {
+                    config.mode = mode.parse()?;
+                }
+                let image = boot::create_bootable_image(&output.to_string_lossy(), &config)?;
+                Ok((config, image))
+            });
+            match built {
+                Ok((config, image)) => println!("Built {:?} image for {}: {}", config.mode, config.arch, image.display()),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit_failed(&recording, &e);
+                }
+            }
         }
         
         Commands::Boot { zero } => {
@@ -379,7 +623,18 @@ fn main() {
             match cmd {
                 TsoCommands::Run { container } => {
                     println!("Running container in MatrixBox: {}", container);
-                    // TODO: Implement container run logic
+                    let params = serde_json::json!({ "path": container, "args": [] });
+                    let run = sentient_os::daemon::call("run-container", params).and_then(|result| match result {
+                        Some(id) => Ok(id.as_str().unwrap_or_default().to_string()),
+                        None => sentient_os::matrixbox::run_container(container, &[]),
+                    });
+                    match run {
+                        Ok(id) => println!("Container {} started", id),
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            exit_failed(&recording, &e);
+                        }
+                    }
                 }
             }
         }
@@ -394,12 +649,101 @@ fn main() {
                     println!("Removing container: {}", id);
                     // TODO: Implement container removal logic
                 }
+                MatrixboxCommands::Diff { id } => {
+                    use sentient_os::matrixbox::container::{self, ChangeKind};
+                    
+                    let changes = container::container_filesystem(&id)
+                        .and_then(|fs| fs.ok_or_else(|| anyhow::anyhow!("Container {} has no filesystem layer", id)))
+                        .and_then(|fs| fs.diff());
+                    match changes {
+                        Ok(changes) => {
+                            for change in changes {
+                                let kind = match change.kind {
+                                    ChangeKind::Added => "A",
+                                    ChangeKind::Modified => "M",
+                                    ChangeKind::Deleted => "D",
+                                };
+                                println!("{} {}", kind, change.path);
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            exit_failed(&recording, &e);
+                        }
+                    }
+                }
+                MatrixboxCommands::Sign { image } => {
+                    use sentient_os::matrixbox::registry;
+                    
+                    match registry::image_signing_key().and_then(|key| registry::sign_image(image, &key)) {
+                        Ok(signature) => {
+                            println!("Signed {} (manifest {})", image.display(), signature.manifest_hash);
+                            println!("Signer key: {}", signature.public_key);
+                            println!("Nodes trust it once the key is saved in .matrixbox/trusted_keys/");
+                        }
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            exit_failed(&recording, &e);
+                        }
+                    }
+                }
+                MatrixboxCommands::VerifyImage { image } => {
+                    use sentient_os::matrixbox::registry;
+                    
+                    match registry::load_trusted_keys().and_then(|keys| registry::verify_image(image, &keys)) {
+                        Ok(true) => println!("Image signature: VALID"),
+                        Ok(false) => {
+                            eprintln!("Image signature: INVALID");
+                            exit_failed(&recording, &"image is not signed by a trusted key");
+                        }
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            exit_failed(&recording, &e);
+                        }
+                    }
+                }
+                MatrixboxCommands::Compose { command } => {
+                    use sentient_os::matrixbox::compose;
+                    
+                    let result = match command {
+                        ComposeCommands::Up { file } => compose::compose_up(file)
+                            .map(|ids| format!("Started {} service(s): {}", ids.len(), ids.join(", "))),
+                        ComposeCommands::Down => compose::compose_down()
+                            .map(|names| format!("Stopped {} service(s): {}", names.len(), names.join(", "))),
+                    };
+                    match result {
+                        Ok(summary) => println!("{}", summary),
+                        Err(e) => {
+                            eprintln!("{:#}", e);
+                            exit_failed(&recording, &e);
+                        }
+                    }
+                }
+                MatrixboxCommands::Ports { id } => {
+                    match sentient_os::matrixbox::network::forwarded_ports(&id) {
+                        Ok(ports) if ports.is_empty() => println!("No ports forwarded to container {}", id),
+                        Ok(ports) => {
+                            for port in ports {
+                                println!("{}/{} -> {}:{}", port.host_port, port.protocol,
+                                         port.container_ip, port.container_port);
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            exit_failed(&recording, &e);
+                        }
+                    }
+                }
             }
         }
         
         Commands::Unsecure(cmd) => {
             match cmd {
                 UnsecureCommands::Run { app } => {
+                    if let Err(e) = sentient_os::matrixbox::policy::ensure_unsecure_allowed() {
+                        eprintln!("{}", e);
+                        exit_failed(&recording, &e);
+                    }
                     println!("Running non-ZK app in unsecured container: {}", app);
                     // TODO: Implement unsecure container logic
                 }
@@ -432,25 +776,276 @@ fn main() {
             match cmd {
                 HealCommands::Container { id } => {
                     println!("Auto-recovering container: {}", id);
-                    // TODO: Implement container recovery logic
+                    match sentient_os::heal::heal_container(&id) {
+                        Ok(snapshot_id) => println!("Container {} healed from snapshot {}", id, snapshot_id),
+                        Err(e) if e.downcast_ref::<sentient_os::heal::NoContainerSnapshot>().is_some() => {
+                            println!("{}", e);
+                            print!("Recreate container {} from its package instead? Its data will start empty [y/N] ", id);
+                            let _ = std::io::Write::flush(&mut std::io::stdout());
+                            let mut answer = String::new();
+                            let _ = std::io::stdin().read_line(&mut answer);
+                            if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+                                eprintln!("Container {} left as is", id);
+                                exit_failed(&recording, &e);
+                            }
+                            
+                            match sentient_os::heal::recreate_container(&id) {
+                                Ok(new_id) => println!("Container {} recreated as {}", id, new_id),
+                                Err(e) => {
+                                    eprintln!("{}", e);
+                                    exit_failed(&recording, &e);
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            exit_failed(&recording, &e);
+                        }
+                    }
                 }
                 HealCommands::Boot {} => {
                     println!("Rebuilding kernel space from clean boot snapshot");
                     // TODO: Implement boot recovery logic
                 }
+                HealCommands::Snapshot { now: true, .. } => {
+                    match sentient_os::heal::take_scheduled_snapshot() {
+                        Ok(snapshot_id) => println!("Scheduled snapshot {} taken", snapshot_id),
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            exit_failed(&recording, &e);
+                        }
+                    }
+                }
+                HealCommands::Snapshot { container: Some(id), .. } => {
+                    match sentient_os::heal::snapshot::snapshot_container(&id) {
+                        Ok(snapshot_id) => println!("Snapshot {} of container {} taken", snapshot_id, id),
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            exit_failed(&recording, &e);
+                        }
+                    }
+                }
+                HealCommands::Snapshot { reason, incremental: false, .. } => {
+                    let params = serde_json::json!({ "reason": reason });
+                    let taken = sentient_os::daemon::call("take-snapshot", params).and_then(|result| match result {
+                        Some(id) => Ok(id.as_str().unwrap_or_default().to_string()),
+                        None => sentient_os::heal::take_snapshot(&reason),
+                    });
+                    match taken {
+                        Ok(snapshot_id) => println!("Snapshot {} taken", snapshot_id),
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            exit_failed(&recording, &e);
+                        }
+                    }
+                }
+                HealCommands::Snapshot { reason, incremental, base, .. } => {
+                    let options = if incremental {
+                        let base = match base {
+                            Some(base) => Some(base),
+                            None => match sentient_os::heal::get_latest_snapshot() {
+                                Ok(latest) => latest.map(|s| s.id),
+                                Err(e) => {
+                                    eprintln!("{}", e);
+                                    exit_failed(&recording, &e);
+                                }
+                            },
+                        };
+                        match base {
+                            Some(base) => sentient_os::heal::snapshot::SnapshotOptions::incremental(&base),
+                            None => {
+                                eprintln!("No snapshot to base an incremental snapshot on");
+                                exit_failed(&recording, &"no base snapshot");
+                            }
+                        }
+                    } else {
+                        sentient_os::heal::snapshot::SnapshotOptions::default()
+                    };
+                    match sentient_os::heal::take_snapshot_with(&reason, &options) {
+                        Ok(snapshot_id) => match options.mode.base() {
+                            Some(base) => println!("Snapshot {} taken (incremental on {})", snapshot_id, base),
+                            None => println!("Snapshot {} taken", snapshot_id),
+                        },
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            exit_failed(&recording, &e);
+                        }
+                    }
+                }
+                HealCommands::Compact { ids } => {
+                    match sentient_os::heal::snapshot::compact_chain(&ids) {
+                        Ok(snapshot_id) => println!("Compacted {} snapshot(s) into full snapshot {}", ids.len(), snapshot_id),
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            exit_failed(&recording, &e);
+                        }
+                    }
+                }
+                HealCommands::Prune { keep, max_age_days, min_keep, dry_run } => {
+                    let mut policy = match sentient_os::heal::snapshot::load_retention_policy() {
+                        Ok(policy) => policy,
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            exit_failed(&recording, &e);
+                        }
+                    };
+                    policy.max_count = keep.or(policy.max_count);
+                    policy.max_age_days = max_age_days.or(policy.max_age_days);
+                    policy.min_keep = min_keep.unwrap_or(policy.min_keep);
+                    if !policy.is_limited() {
+                        eprintln!("No retention limits given; pass --keep or --max-age-days, or configure snapshot_retention");
+                        exit_failed(&recording, &"no retention limits");
+                    }
+                    
+                    if dry_run {
+                        match sentient_os::heal::snapshot::plan_prune(&policy) {
+                            Ok(plan) => println!("{}", plan),
+                            Err(e) => {
+                                eprintln!("{}", e);
+                                exit_failed(&recording, &e);
+                            }
+                        }
+                    } else {
+                        match sentient_os::heal::snapshot::prune(policy) {
+                            Ok(report) => {
+                                for snapshot in &report.removed {
+                                    println!("Removed {} ({}, {} bytes)", snapshot.id, snapshot.reason, snapshot.bytes);
+                                }
+                                println!("Removed {} snapshot(s), kept {}", report.removed.len(), report.kept);
+                                if let Some(protected) = &report.protected {
+                                    println!("Kept panic fallback snapshot {}", protected);
+                                }
+                            }
+                            Err(e) => {
+                                eprintln!("{}", e);
+                                exit_failed(&recording, &e);
+                            }
+                        }
+                    }
+                }
+                HealCommands::Verify { id } => {
+                    match sentient_os::heal::verify_snapshot(&id) {
+                        Ok(verification) => {
+                            println!("{}", verification);
+                            if !verification.is_intact() {
+                                exit_failed(&recording, &"snapshot integrity check failed");
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            exit_failed(&recording, &e);
+                        }
+                    }
+                }
+                HealCommands::Rollback { component, snapshot, dry_run: true } => {
+                    match sentient_os::heal::plan_component_rollback(&component, &snapshot) {
+                        Ok(plan) => println!("{}", plan),
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            exit_failed(&recording, &e);
+                        }
+                    }
+                }
+                HealCommands::Rollback { component, snapshot, .. } => {
+                    match sentient_os::heal::recovery::rollback_component(&component, &snapshot) {
+                        Ok(()) => println!("Component {} rolled back to snapshot {}", component, snapshot),
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            exit_failed(&recording, &e);
+                        }
+                    }
+                }
+                HealCommands::Schedule(ScheduleCommands::Show {}) => {
+                    match sentient_os::heal::snapshot_schedule() {
+                        Ok(schedule) => {
+                            let format = |time: Option<u64>| time
+                                .and_then(|t| chrono::DateTime::from_timestamp(t as i64, 0))
+                                .map_or("-".to_string(), |t| t.format("%Y-%m-%d %H:%M:%S").to_string());
+                            if schedule.interval_secs == 0 {
+                                println!("Scheduled snapshots are disabled (auto_snapshot_interval_secs is 0)");
+                            } else {
+                                println!("Interval: {}s", schedule.interval_secs);
+                            }
+                            println!("Last run: {}", format(schedule.last_run));
+                            println!("Next run: {}", format(schedule.next_run));
+                        }
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            exit_failed(&recording, &e);
+                        }
+                    }
+                }
+                HealCommands::Ring(RingCommands::Status {}) => {
+                    match sentient_os::heal::snapshot::ring_status() {
+                        Ok(slots) => {
+                            let now = chrono::Utc::now().timestamp().max(0) as u64;
+                            for slot in slots {
+                                let head = if slot.is_head { " (head)" } else { "" };
+                                match slot.snapshot {
+                                    Some(snapshot) => println!("Slot {}: {}, taken {}{}", slot.index, snapshot.id,
+                                                               sentient_os::cli::table::format_age(now.saturating_sub(snapshot.timestamp)), head),
+                                    None => println!("Slot {}: empty", slot.index),
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            exit_failed(&recording, &e);
+                        }
+                    }
+                }
             }
         }
         
         Commands::Panic(cmd) => {
             match cmd {
-                PanicCommands::Recover {} => {
+                PanicCommands::Recover { force } => {
                     println!("Recovering from panic state using fallback");
-                    // TODO: Implement panic recovery logic
+                    if let Err(e) = sentient_os::panic::recover(*force) {
+                        eprintln!("{}", e);
+                        exit_failed(&recording, &e);
+                    }
                 }
-                PanicCommands::Report { output } => {
+                PanicCommands::Status {} => {
+                    match sentient_os::panic::status() {
+                        Ok(status) => sentient_os::cli::print_panic_status(status.as_ref()),
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            exit_failed(&recording, &e);
+                        }
+                    }
+                }
+                PanicCommands::Report { output, full_backtrace, severity_filter } => {
+                    let min_severity = match severity_filter.as_deref().map(str::parse).transpose() {
+                        Ok(min_severity) => min_severity,
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            exit_failed(&recording, &e);
+                        }
+                    };
                     let out_dir = output.as_deref().unwrap_or(std::path::Path::new("./"));
+                    let report_path = out_dir.join("crash_report.json");
                     println!("Generating crash report in: {:?}", out_dir);
-                    // TODO: Implement crash report generation logic
+                    match sentient_os::panic::generate_report(&report_path.to_string_lossy(), *full_backtrace, min_severity) {
+                        Ok(histogram) => sentient_os::cli::print_panic_histogram(&histogram),
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            exit_failed(&recording, &e);
+                        }
+                    }
+                }
+                PanicCommands::SendPending {} => {
+                    match sentient_os::panic::remote::send_pending() {
+                        Ok((sent, 0)) => println!("Sent {} crash report(s)", sent),
+                        Ok((sent, failed)) => {
+                            println!("Sent {} crash report(s); {} could not be sent and stay queued", sent, failed);
+                            exit_failed(&recording, &format!("{} crash report(s) not sent", failed));
+                        }
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            exit_failed(&recording, &e);
+                        }
+                    }
                 }
             }
         }
@@ -475,6 +1070,17 @@ fn main() {
             }
         }
         
+        Commands::Network(NetworkCommands::Tls(TlsCommands::Init { common_name, output })) => {
+            let output_dir = output.clone().unwrap_or_else(sentient_os::network::tls::default_dir);
+            match sentient_os::network::tls::generate_self_signed_cert(common_name, &output_dir) {
+                Ok(()) => println!("Wrote a self-signed certificate for {} to {:?}", common_name, output_dir),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit_failed(&recording, &e);
+                }
+            }
+        }
+        
         Commands::Intent(cmd) => {
             match cmd {
                 IntentCommands::Record {} => {
@@ -490,21 +1096,31 @@ fn main() {
         
         Commands::Package(cmd) => {
             match cmd {
-                PackageCommands::Install { name, version, ecosystem } => {
+                PackageCommands::Install { name, version, ecosystem, allow_denied_license } => {
                     println!("Installing package: {}", name);
-                    let eco = parse_ecosystem(ecosystem.as_deref());
+                    let eco = parse_ecosystem(ecosystem.as_deref()).unwrap_or(crate::package::Ecosystem::Native);
                     let ver_ref = version.as_deref();
                     
-                    match crate::package::install_package(&name, eco, ver_ref) {
+                    let params = serde_json::json!({
+                        "name": name,
+                        "ecosystem": eco,
+                        "version": ver_ref,
+                        "allow_denied_license": allow_denied_license,
+                    });
+                    let installed = sentient_os::daemon::call("install-package", params).and_then(|result| match result {
+                        Some(_) => Ok(()),
+                        None => sentient_os::package::install_package(&name, eco, ver_ref, *allow_denied_license),
+                    });
+                    match installed {
                         Ok(_) => println!("Package {} installed successfully", name),
                         Err(e) => eprintln!("Failed to install package: {}", e),
                     }
                 }
-                PackageCommands::Remove { name, ecosystem } => {
+                PackageCommands::Remove { name, ecosystem, purge } => {
                     println!("Removing package: {}", name);
                     let eco = parse_ecosystem(ecosystem.as_deref());
                     
-                    match crate::package::remove_package(&name, eco) {
+                    match crate::package::remove_package(&name, eco, *purge) {
                         Ok(_) => println!("Package {} removed successfully", name),
                         Err(e) => eprintln!("Failed to remove package: {}", e),
                     }
@@ -514,12 +1130,15 @@ fn main() {
                     
                     match crate::package::list_packages(eco) {
                         Ok(packages) => {
-                            println!("Installed packages:");
                             if packages.is_empty() {
-                                println!("  No packages installed");
+                                println!("No packages installed");
                             } else {
+                                let mut table = crate::cli::table::Table::new(&["NAME", "VERSION", "ECOSYSTEM"]);
                                 for pkg in packages {
-                                    println!("  {} ({}): {}", pkg.name, format!("{:?}", pkg.ecosystem).to_lowercase(), pkg.version);
+                                    table.row([pkg.name, pkg.version, format!("{:?}", pkg.ecosystem).to_lowercase()]);
+                                }
+                                if let Err(e) = table.print(&Default::default()) {
+                                    eprintln!("Failed to print packages: {}", e);
                                 }
                             }
                         }
@@ -568,14 +1187,27 @@ fn main() {
                         println!("Updating package: {}", pkg_name);
                         let eco = parse_ecosystem(ecosystem.as_deref());
                         
-                        match crate::package::update_package(&pkg_name, eco) {
+                        match sentient_os::package::update_package(&pkg_name, eco) {
                             Ok(_) => println!("Package {} updated successfully", pkg_name),
                             Err(e) => eprintln!("Failed to update package: {}", e),
                         }
                     } else {
                         println!("Updating all packages");
-                        // TODO: Implement update all packages
-                        eprintln!("Update all packages not implemented yet");
+                        let eco = parse_ecosystem(ecosystem.as_deref());
+                        
+                        match sentient_os::package::update_all(eco) {
+                            Ok(report) => {
+                                for updated in &report.updated {
+                                    println!("  updated {} ({} -> {})", updated.package, updated.from, updated.to);
+                                }
+                                for failed in &report.failed {
+                                    eprintln!("  failed  {}: {}", failed.package, failed.error);
+                                }
+                                println!("{} updated, {} skipped, {} failed",
+                                         report.updated.len(), report.skipped.len(), report.failed.len());
+                            }
+                            Err(e) => eprintln!("Failed to update packages: {}", e),
+                        }
                     }
                 }
             }
@@ -604,5 +1236,53 @@ fn main() {
             println!("Live hot-patching module: {}", module);
             // TODO: Implement hot-patch logic
         }
+        
+        Commands::Status {} => {
+            let status = sentient_os::daemon::call("status", serde_json::Value::Null).and_then(|result| match result {
+                Some(status) => Ok(serde_json::from_value::<sentient_os::status::SystemStatus>(status)?),
+                None => Ok(sentient_os::status::collect()),
+            });
+            match status {
+                Ok(status) => {
+                    let mut table = sentient_os::cli::table::Table::new(&["SUBSYSTEM", "STATUS", "DETAIL"]);
+                    for subsystem in status.subsystems {
+                        table.row([subsystem.name, subsystem.state.as_str().to_string(), subsystem.detail]);
+                    }
+                    if let Err(e) = table.print(&Default::default()) {
+                        eprintln!("Failed to print status: {}", e);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit_failed(&recording, &e);
+                }
+            }
+        }
+        
+        Commands::Daemon { command: Some(DaemonCommands::Stop {}), .. } => {
+            match sentient_os::daemon::call("stop", serde_json::Value::Null) {
+                Ok(Some(_)) => println!("Daemon stopped"),
+                Ok(None) => println!("No daemon is running"),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    exit_failed(&recording, &e);
+                }
+            }
+        }
+        
+        Commands::Daemon { zk, command: None } => {
+            if let Err(e) = sentient_os::daemon::run(zk.unwrap_or(true)) {
+                eprintln!("{}", e);
+                exit_failed(&recording, &e);
+            }
+        }
     }
+    
+    recording.finish(&Ok(()));
+}
+
+/// Record a failed command in the intent session and exit
+fn exit_failed(recording: &sentient_os::intent::CommandRecording, error: &dyn std::fmt::Display) -> ! {
+    recording.finish(&Err(anyhow::anyhow!("{}", error)));
+    std::process::exit(1);
 }
@@ -0,0 +1,305 @@
+// SentientOS Boot Image Builder
+// Stages a minimal root filesystem and packs it into a single tar image with a hash manifest
+
+use anyhow::{Result, Context};
+use tracing::{info, debug};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+
+use super::{BootConfig, BootMode};
+use crate::core::constants;
+
+// Constants
+const STAGING_DIR: &str = ".runtime/boot-staging";
+const MANIFEST_FILE: &str = "manifest.json";
+const BOOTLOADER_CANDIDATES: [&str; 2] = [".boot/zig/build/bootloader", ".boot/zig/bootloader"];
+const RUNTIME_BINARY: &str = "bin/sentientos";
+const TAR_BLOCK: usize = 512;
+
+/// Hashes of the files in a boot image, stored as `manifest.json` in it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageManifest {
+    /// Target architecture
+    pub arch: String,
+    
+    /// Boot mode the image starts in
+    pub mode: BootMode,
+    
+    /// When the image was built (RFC 3339)
+    pub created_at: String,
+    
+    /// BLAKE3 hash (hex) of every file, by path in the image
+    pub files: BTreeMap<String, String>,
+}
+
+/// Build a bootable image at `output`, returning its path
+///
+/// A staging directory receives the Zig bootloader, `boot.yaml` and the
+/// IoT boot files, plus a minimal root filesystem: the runtime binary in
+/// `bin`, the node's `.config` and its deployed `.zk` contracts. A
+/// manifest of file hashes is added and everything is packed into a
+/// single tar file.
+pub fn create_bootable_image(output: &str, config: &BootConfig) -> Result<PathBuf> {
+    info!("Building {:?} boot image for {} at {}", config.mode, config.arch, output);
+    
+    let root = constants::root_dir();
+    let staging = root.join(STAGING_DIR).join(chrono::Utc::now().format("%Y%m%d%H%M%S%f").to_string());
+    fs::create_dir_all(&staging)
+        .with_context(|| format!("Failed to create staging directory {:?}", staging))?;
+    
+    let result = stage(&staging, config).and_then(|manifest| {
+        let output = PathBuf::from(output);
+        if let Some(parent) = output.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        pack_tar(&staging, &output)?;
+        info!("Boot image built: {:?} ({} files)", output, manifest.files.len());
+        Ok(output)
+    });
+    
+    if let Err(e) = fs::remove_dir_all(&staging) {
+        debug!("Failed to remove staging directory {:?}: {}", staging, e);
+    }
+    result
+}
+
+/// The Zig bootloader to put in images, preferring a compiled one
+pub fn bootloader_artifact() -> Result<PathBuf> {
+    let root = constants::root_dir();
+    BOOTLOADER_CANDIDATES.iter()
+        .map(|candidate| root.join(candidate))
+        .find(|path| path.is_file())
+        .ok_or_else(|| anyhow::anyhow!(
+            "Zig bootloader artifacts are missing (looked for {}); build the bootloader or run `sentctl init` first",
+            BOOTLOADER_CANDIDATES.join(", ")))
+}
+
+/// Fill the staging directory and write its manifest
+fn stage(staging: &Path, config: &BootConfig) -> Result<ImageManifest> {
+    // Bootloader, boot.yaml and IoT boot files
+    super::prepare_bootable(&staging.to_string_lossy(), config)?;
+    
+    // Runtime binary
+    let binary = std::env::current_exe().context("Failed to locate the runtime binary")?;
+    let target = staging.join(RUNTIME_BINARY);
+    fs::create_dir_all(target.parent().unwrap())?;
+    fs::copy(&binary, &target)
+        .with_context(|| format!("Failed to copy runtime binary {:?}", binary))?;
+    
+    // Node configuration and deployed contracts
+    let root = constants::root_dir();
+    copy_tree(&root.join(".config"), &staging.join(".config"))?;
+    copy_tree(&root.join(".zk").join("contracts"), &staging.join(".zk").join("contracts"))?;
+    
+    let mut files = BTreeMap::new();
+    for (relative, path) in list_files(staging)? {
+        files.insert(relative, blake3::hash(&fs::read(&path)?).to_hex().to_string());
+    }
+    let manifest = ImageManifest {
+        arch: config.arch.clone(),
+        mode: config.mode,
+        created_at: chrono::Utc::now().to_rfc3339(),
+        files,
+    };
+    fs::write(staging.join(MANIFEST_FILE), serde_json::to_string_pretty(&manifest)?)?;
+    Ok(manifest)
+}
+
+/// Copy a directory tree if it exists
+fn copy_tree(from: &Path, to: &Path) -> Result<()> {
+    if !from.is_dir() {
+        debug!("Skipping missing {:?}", from);
+        return Ok(());
+    }
+    for (relative, path) in list_files(from)? {
+        let target = to.join(&relative);
+        fs::create_dir_all(target.parent().unwrap())?;
+        fs::copy(&path, &target).with_context(|| format!("Failed to copy {:?}", path))?;
+    }
+    Ok(())
+}
+
+/// Files under a directory by `/`-separated relative path, in path order
+fn list_files(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    fn collect(base: &Path, dir: &Path, files: &mut Vec<(String, PathBuf)>) -> Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                collect(base, &path, files)?;
+            } else {
+                let relative = path.strip_prefix(base)?.to_string_lossy().replace('\\', "/");
+                files.push((relative, path));
+            }
+        }
+        Ok(())
+    }
+    
+    let mut files = Vec::new();
+    collect(dir, dir, &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+/// Pack the files under `dir` into a ustar archive
+fn pack_tar(dir: &Path, output: &Path) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(output)
+        .with_context(|| format!("Failed to create image {:?}", output))?);
+    let mtime = chrono::Utc::now().timestamp() as u64;
+    
+    for (relative, path) in list_files(dir)? {
+        let data = fs::read(&path)?;
+        let mode = file_mode(&path)?;
+        writer.write_all(&tar_header(&relative, data.len() as u64, mode, mtime)?)?;
+        writer.write_all(&data)?;
+        let padding = (TAR_BLOCK - data.len() % TAR_BLOCK) % TAR_BLOCK;
+        writer.write_all(&vec![0u8; padding])?;
+    }
+    
+    // An archive ends with two empty blocks
+    writer.write_all(&[0u8; TAR_BLOCK * 2])?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// ustar header of a regular file
+fn tar_header(path: &str, size: u64, mode: u32, mtime: u64) -> Result<[u8; TAR_BLOCK]> {
+    let mut header = [0u8; TAR_BLOCK];
+    
+    // Names over 100 bytes are split into a prefix and a name at a `/`
+    let (prefix, name) = if path.len() <= 100 {
+        ("", path)
+    } else {
+        let split = path.char_indices()
+            .filter(|&(i, c)| c == '/' && i <= 155 && path.len() - i - 1 <= 100)
+            .map(|(i, _)| i)
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Path too long for the image: {}", path))?;
+        (&path[..split], &path[split + 1..])
+    };
+    
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    write_octal(&mut header[100..108], mode as u64);
+    write_octal(&mut header[108..116], 0);
+    write_octal(&mut header[116..124], 0);
+    write_octal(&mut header[124..136], size);
+    write_octal(&mut header[136..148], mtime);
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+    
+    // The checksum is computed with its own field set to spaces
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u32 = header.iter().map(|&b| b as u32).sum();
+    header[148..155].copy_from_slice(format!("{:06o}\0", checksum).as_bytes());
+    Ok(header)
+}
+
+/// Write a zero-padded, NUL-terminated octal number into a header field
+fn write_octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}\0", value, width = field.len() - 1);
+    field.copy_from_slice(digits.as_bytes());
+}
+
+/// Permission bits a file is archived with
+fn file_mode(path: &Path) -> Result<u32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        Ok(fs::metadata(path)?.permissions().mode() & 0o777)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Ok(0o644)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("boot-image-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    
+    /// Files of a ustar archive by path, checking every header's checksum
+    fn unpack(archive: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let field = |bytes: &[u8]| String::from_utf8_lossy(bytes.split(|&b| b == 0).next().unwrap()).to_string();
+        let octal = |bytes: &[u8]| u64::from_str_radix(field(bytes).trim(), 8).unwrap();
+        
+        let mut files = Vec::new();
+        let mut offset = 0;
+        while archive[offset..offset + TAR_BLOCK].iter().any(|&b| b != 0) {
+            let header = &archive[offset..offset + TAR_BLOCK];
+            let mut blank = header.to_vec();
+            blank[148..156].copy_from_slice(b"        ");
+            assert_eq!(blank.iter().map(|&b| b as u64).sum::<u64>(), octal(&header[148..156]));
+            
+            let (prefix, name) = (field(&header[345..500]), field(&header[..100]));
+            let path = if prefix.is_empty() { name } else { format!("{}/{}", prefix, name) };
+            let size = octal(&header[124..136]) as usize;
+            offset += TAR_BLOCK;
+            files.push((path, archive[offset..offset + size].to_vec()));
+            offset += size.div_ceil(TAR_BLOCK) * TAR_BLOCK;
+        }
+        assert_eq!(archive.len(), offset + TAR_BLOCK * 2);
+        files
+    }
+    
+    #[test]
+    fn packed_images_hold_every_file_in_path_order() {
+        let dir = scratch_dir("pack");
+        let long = format!("{}/{}", "nested".repeat(20), "f".repeat(90));
+        let files: Vec<(String, Vec<u8>)> = vec![
+            ("bin/sentientos".to_string(), vec![0x7f; 1300]),
+            ("boot.yaml".to_string(), b"mode: normal\n".to_vec()),
+            (".config/empty".to_string(), Vec::new()),
+            (long, b"long".to_vec()),
+        ];
+        for (path, content) in &files {
+            fs::create_dir_all(dir.join(path).parent().unwrap()).unwrap();
+            fs::write(dir.join(path), content).unwrap();
+        }
+        
+        let output = scratch_dir("pack-output").join("image.tar");
+        pack_tar(&dir, &output).unwrap();
+        let mut expected = files.clone();
+        expected.sort();
+        assert_eq!(unpack(&fs::read(&output).unwrap()), expected);
+    }
+    
+    #[test]
+    fn paths_that_cannot_be_split_are_refused() {
+        assert!(tar_header(&"x".repeat(101), 0, 0o644, 0).is_err());
+        assert!(tar_header(&format!("{}/name", "d".repeat(156)), 0, 0o644, 0).is_err());
+        
+        let header = tar_header("bin/sentientos", 10, 0o755, 0).unwrap();
+        assert_eq!(&header[100..108], b"0000755\0");
+        assert_eq!(&header[124..136], b"00000000012\0");
+        assert_eq!(&header[257..265], b"ustar\x0000");
+    }
+    
+    #[test]
+    fn trees_are_copied_when_present() {
+        let from = scratch_dir("tree");
+        fs::create_dir_all(from.join("contracts/v2")).unwrap();
+        fs::write(from.join("contracts/v2/token.yaml"), "name: token").unwrap();
+        fs::write(from.join("system.json"), "{}").unwrap();
+        
+        let to = scratch_dir("tree-copy").join("staged");
+        copy_tree(&from, &to).unwrap();
+        let copied: Vec<String> = list_files(&to).unwrap().into_iter().map(|(relative, _)| relative).collect();
+        assert_eq!(copied, ["contracts/v2/token.yaml", "system.json"]);
+        assert_eq!(fs::read_to_string(to.join("contracts/v2/token.yaml")).unwrap(), "name: token");
+        
+        copy_tree(&from.join("missing"), &to.join("missing")).unwrap();
+        assert!(!to.join("missing").exists());
+    }
+}
@@ -42,7 +42,7 @@ pub fn init() -> Result<()> {
     info!("Initializing IoT boot module");
     
     // Create IoT boot directories
-    let iot_dir = PathBuf::from(constants::ROOT_DIR).join(".boot").join("iot");
+    let iot_dir = constants::root_dir().join(".boot").join("iot");
     fs::create_dir_all(&iot_dir)?;
     
     // Create sensor configs directory
@@ -77,7 +77,7 @@ pub fn shutdown() -> Result<()> {
 pub fn verify_integrity() -> Result<bool> {
     info!("Verifying IoT components integrity");
     
-    let iot_dir = PathBuf::from(constants::ROOT_DIR).join(".boot").join("iot");
+    let iot_dir = constants::root_dir().join(".boot").join("iot");
     
     // Check if IoT boot directory exists
     if !iot_dir.exists() {
@@ -133,7 +133,7 @@ pub fn prepare_bootable(target_dir: &Path, config: &BootConfig) -> Result<()> {
     fs::write(iot_boot_dir.join("config.yaml"), iot_config_yaml)?;
     
     // Copy sensor configurations based on device type
-    let source_sensors_dir = PathBuf::from(constants::ROOT_DIR)
+    let source_sensors_dir = constants::root_dir()
         .join(".boot")
         .join("iot")
         .join("sensors");
@@ -3,8 +3,11 @@
 
 pub mod zig_interface;
 pub mod iot;
+pub mod image;
 
-use anyhow::Result;
+pub use image::create_bootable_image;
+
+use anyhow::{Result, Context};
 use tracing::{info, debug, warn, error};
 use std::path::PathBuf;
 use std::fs;
@@ -17,7 +20,7 @@ pub fn init() -> Result<()> {
     info!("Initializing SentientOS boot subsystem");
     
     // Create boot directories
-    let boot_dir = PathBuf::from(constants::ROOT_DIR).join(".boot");
+    let boot_dir = constants::root_dir().join(".boot");
     fs::create_dir_all(&boot_dir)?;
     
     let zig_dir = boot_dir.join("zig");
@@ -77,14 +80,10 @@ pub fn prepare_bootable(target_path: &str, config: &BootConfig) -> Result<()> {
     let target = PathBuf::from(target_path);
     fs::create_dir_all(&target)?;
     
-    // Copy boot components
-    let boot_dir = PathBuf::from(constants::ROOT_DIR).join(".boot");
-    
-    // Copy Zig boot loader
-    fs::copy(
-        boot_dir.join("zig").join("bootloader"),
-        target.join("bootloader")
-    )?;
+    // Copy Zig boot loader, preferring a compiled one
+    let bootloader = image::bootloader_artifact()?;
+    fs::copy(&bootloader, target.join("bootloader"))
+        .with_context(|| format!("Failed to copy Zig bootloader {:?}", bootloader))?;
     
     // Generate boot configuration
     let boot_config_path = target.join("boot.yaml");
@@ -133,6 +132,19 @@ pub enum BootMode {
     Zero,
 }
 
+impl std::str::FromStr for BootMode {
+    type Err = anyhow::Error;
+    
+    fn from_str(mode: &str) -> Result<Self> {
+        match mode.to_lowercase().as_str() {
+            "normal" => Ok(BootMode::Normal),
+            "recovery" => Ok(BootMode::Recovery),
+            "zero" => Ok(BootMode::Zero),
+            other => anyhow::bail!("Unknown boot mode: {} (expected normal, recovery or zero)", other),
+        }
+    }
+}
+
 /// IoT boot configuration
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct IotBootConfig {
@@ -171,6 +183,69 @@ pub enum IotNetworkMode {
     Cellular,
 }
 
+/// Hash of this node's boot chain record
+///
+/// Covers every file under `.boot` in path order, so any change to the
+/// boot components or their configuration changes the hash.
+pub fn boot_record_hash() -> Result<String> {
+    fn collect(dir: &std::path::Path, files: &mut Vec<PathBuf>) -> Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                collect(&path, files)?;
+            } else {
+                files.push(path);
+            }
+        }
+        Ok(())
+    }
+    
+    let boot_dir = constants::root_dir().join(".boot");
+    let mut files = Vec::new();
+    if boot_dir.exists() {
+        collect(&boot_dir, &mut files)?;
+    }
+    files.sort();
+    
+    let mut hasher = blake3::Hasher::new();
+    for path in &files {
+        hasher.update(path.strip_prefix(&boot_dir).unwrap_or(path).to_string_lossy().as_bytes());
+        hasher.update(&fs::read(path)?);
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Path of the boot configuration applied on this node
+fn boot_config_path() -> PathBuf {
+    constants::root_dir().join(".boot").join("boot.yaml")
+}
+
+/// Load this node's boot configuration, or the default if none is saved
+pub fn load_boot_config() -> Result<BootConfig> {
+    let path = boot_config_path();
+    if !path.exists() {
+        return Ok(default_boot_config());
+    }
+    
+    let data = fs::read_to_string(&path)?;
+    Ok(serde_yaml::from_str(&data)?)
+}
+
+/// Save this node's boot configuration
+pub fn save_boot_config(config: &BootConfig) -> Result<()> {
+    let path = boot_config_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    
+    fs::write(&path, serde_yaml::to_string(config)?)?;
+    info!("Boot configuration saved: {:?}", path);
+    
+    // The low-power policy takes effect without a restart
+    crate::core::workers::set_low_power(config.iot.low_power);
+    Ok(())
+}
+
 /// Create default boot configuration
 pub fn default_boot_config() -> BootConfig {
     BootConfig {
@@ -188,3 +263,18 @@ pub fn default_boot_config() -> BootConfig {
         debug: false,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn boot_modes_parse_from_their_names() {
+        assert_eq!("normal".parse::<BootMode>().unwrap(), BootMode::Normal);
+        assert_eq!("Recovery".parse::<BootMode>().unwrap(), BootMode::Recovery);
+        assert_eq!("ZERO".parse::<BootMode>().unwrap(), BootMode::Zero);
+        
+        let error = "safe".parse::<BootMode>().unwrap_err();
+        assert!(error.to_string().contains("Unknown boot mode: safe"), "{}", error);
+    }
+}
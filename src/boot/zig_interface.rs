@@ -21,7 +21,7 @@ pub fn init() -> Result<()> {
     info!("Initializing Zig interface");
     
     // Create Zig directories
-    let zig_dir = PathBuf::from(constants::ROOT_DIR).join(ZIG_BOOT_DIR);
+    let zig_dir = constants::root_dir().join(ZIG_BOOT_DIR);
     fs::create_dir_all(&zig_dir)?;
     
     // Check for Zig bootloader
@@ -53,7 +53,7 @@ pub fn shutdown() -> Result<()> {
 pub fn verify_integrity() -> Result<bool> {
     info!("Verifying Zig components integrity");
     
-    let zig_dir = PathBuf::from(constants::ROOT_DIR).join(ZIG_BOOT_DIR);
+    let zig_dir = constants::root_dir().join(ZIG_BOOT_DIR);
     let bootloader_path = zig_dir.join(ZIG_BOOTLOADER);
     
     // Check if bootloader exists
@@ -178,7 +178,7 @@ pub fn compile_bootloader(source: &Path, target_arch: &str) -> Result<PathBuf> {
     }
     
     // Create output directory
-    let output_dir = PathBuf::from(constants::ROOT_DIR)
+    let output_dir = constants::root_dir()
         .join(".boot")
         .join("zig")
         .join("build");
@@ -216,28 +216,28 @@ pub fn build(b: *std.build.Builder) void {
     // means any target is allowed, and the default is native. Other options
     // for restricting supported target set are available.
     const target = b.standardTargetOptions(.{});
-
+    
     // Standard release options allow the person running `zig build` to select
     // between Debug, ReleaseSafe, ReleaseFast, and ReleaseSmall.
     const mode = b.standardReleaseOptions();
-
+    
     // Bootloader executable
     const exe = b.addExecutable("bootloader", "src/main.zig");
     exe.setTarget(target);
     exe.setBuildMode(mode);
     exe.install();
-
+    
     // Runtime library
     const lib = b.addStaticLibrary("runtime", "src/runtime.zig");
     lib.setTarget(target);
     lib.setBuildMode(mode);
     lib.install();
-
+    
     // Tests
     const main_tests = b.addTest("src/main.zig");
     main_tests.setTarget(target);
     main_tests.setBuildMode(mode);
-
+    
     const test_step = b.step("test", "Run library tests");
     test_step.dependOn(&main_tests.step);
 }
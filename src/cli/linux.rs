@@ -8,7 +8,7 @@ use std::path::{Path, PathBuf};
 use tracing::{debug, error, info, warn};
 
 use crate::core::constants;
-use crate::linux::{compatibility, elf_loader};
+use crate::linux::{compatibility, elf_loader, NonZeroExit, OutputMode};
 
 /// Linux compatibility CLI subcommands
 #[derive(Subcommand)]
@@ -127,8 +127,10 @@ fn run_binary(binary_path: &str, args: &[String]) -> Result<()> {
     let args_str: Vec<&str> = args.iter().map(AsRef::as_ref).collect();
     
     println!("{} Running binary: {}", "INFO:".blue().bold(), path.display());
-    let output = match elf_loader::execute_elf(&path, &args_str) {
-        Ok(output) => output,
+    
+    // Stream the output so interactive binaries behave as in a shell
+    let result = match elf_loader::execute_elf(&path, &args_str, OutputMode::Stream) {
+        Ok(result) => result,
         Err(err) => {
             error!("Failed to execute binary: {}", err);
             println!("{} Execution failed: {}", "ERROR:".red().bold(), err);
@@ -136,10 +138,15 @@ fn run_binary(binary_path: &str, args: &[String]) -> Result<()> {
         }
     };
     
-    // Print output
-    println!("{}", output);
+    // The binary's exit code becomes ours
+    if !result.success() {
+        return Err(NonZeroExit {
+            program: path.display().to_string(),
+            code: result.exit_code,
+        }.into());
+    }
     
-    println!("{} Binary execution completed", "SUCCESS:".green().bold());
+    println!("{} Binary execution completed in {:.2}s", "SUCCESS:".green().bold(), result.duration.as_secs_f64());
     Ok(())
 }
 
@@ -288,7 +295,7 @@ fn inspect_binary(binary_path: &str) -> Result<()> {
 fn list_shared_libs() -> Result<()> {
     info!("Listing shared libraries");
     
-    let linux_lib_dir = PathBuf::from(constants::ROOT_DIR).join(".linux").join("lib");
+    let linux_lib_dir = constants::root_dir().join(".linux").join("lib");
     if !linux_lib_dir.exists() {
         println!("{} Linux lib directory not found", "WARNING:".yellow().bold());
         return Ok(());
@@ -348,7 +355,7 @@ fn install_shared_lib(lib_path: &str) -> Result<()> {
     })?;
     
     // Create .linux/lib directory if it doesn't exist
-    let linux_lib_dir = PathBuf::from(constants::ROOT_DIR).join(".linux").join("lib");
+    let linux_lib_dir = constants::root_dir().join(".linux").join("lib");
     std::fs::create_dir_all(&linux_lib_dir)?;
     
     // Copy the library to .linux/lib
@@ -364,7 +371,7 @@ fn install_shared_lib(lib_path: &str) -> Result<()> {
 fn show_status() -> Result<()> {
     info!("Checking Linux compatibility layer status");
     
-    let linux_dir = PathBuf::from(constants::ROOT_DIR).join(".linux");
+    let linux_dir = constants::root_dir().join(".linux");
     let linux_active = linux_dir.exists();
     
     println!("{} Linux Compatibility Status", "INFO:".blue().bold());
@@ -1,6 +1,8 @@
 // SentientOS CLI Module
 // Implements the sentctl command-line interface
 
+pub mod table;
+
 use anyhow::Result;
 use clap::{Parser, Subcommand};
 use tracing::{info, warn, error, debug};
@@ -10,15 +12,20 @@ use crate::matrixbox;
 use crate::zk;
 use crate::boot;
 use crate::core::constants;
+use crate::core::plan::Plan;
 use crate::linux;
 use crate::store;
+use table::{OutputOptions, Table};
+
+/// How often `matrixbox logs --follow` checks for new lines
+const LOG_FOLLOW_INTERVAL_MS: u64 = 500;
 
 /// Initialize the CLI module
 pub fn init() -> Result<()> {
     info!("Initializing CLI module");
     
     // Create CLI directories
-    let cli_dir = PathBuf::from(constants::ROOT_DIR).join(".cli");
+    let cli_dir = constants::root_dir().join(".cli");
     std::fs::create_dir_all(&cli_dir)?;
     
     info!("CLI module initialized successfully");
@@ -33,9 +40,53 @@ pub fn shutdown() -> Result<()> {
 }
 
 /// Parse and execute CLI commands
+///
+/// Each command runs in its own correlation scope so that the runtime trace
+/// records it produces can be matched against intent events on replay.
 pub fn execute_command(args: Vec<String>) -> Result<()> {
+    crate::intent::trace::begin_correlation()?;
+    let recording = crate::intent::CommandRecording::start(&args);
+    
+    let result = dispatch_command(args);
+    recording.finish(&result);
+    
+    crate::intent::trace::record_outcome(crate::intent::trace::TraceOutcome::ExitCode { code: exit_code(&result) });
+    crate::intent::trace::end_correlation();
+    
+    result
+}
+
+/// Exit code of a command result: 0 on success, the binary's status when a
+/// run binary failed, 1 otherwise
+pub(crate) fn exit_code(result: &Result<()>) -> i32 {
+    match result {
+        Ok(()) => 0,
+        Err(e) => e.downcast_ref::<linux::NonZeroExit>().map_or(1, |exit| exit.code),
+    }
+}
+
+/// Parse and dispatch CLI commands without intent tracing
+pub(crate) fn dispatch_command(args: Vec<String>) -> Result<()> {
     let cli = Cli::parse_from(args);
     
+    if cli.dry_run {
+        let plan = plan_command(&cli.command)?
+            .ok_or_else(|| anyhow::anyhow!("--dry-run is only supported by destructive commands"))?;
+        
+        if cli.json {
+            println!("{}", plan.to_json()?);
+        } else {
+            println!("{}", plan);
+        }
+        return Ok(());
+    }
+    
+    let output = OutputOptions {
+        wide: cli.wide,
+        plain: cli.plain,
+        no_pager: cli.no_pager,
+    };
+    
     match &cli.command {
         Commands::Init { zk_enabled } => {
             info!("Initializing system with ZK: {}", zk_enabled);
@@ -43,9 +94,404 @@ pub fn execute_command(args: Vec<String>) -> Result<()> {
             // This would typically be called during boot, not from CLI
             Ok(())
         }
-        Commands::ZkVerify {} => {
+        Commands::ZkVerify { refresh } => {
             info!("Verifying ZK proof chains across system");
-            // Implement full system ZK verification
+            let report = zk::verify::contract_verification_report()?;
+            let mut stale = 0;
+            for (name, status) in &report {
+                match status {
+                    zk::verify::ContractVerificationStatus::Verified => {
+                        println!("{}: verified", name);
+                        if *refresh {
+                            let renewed = zk::renew_proofs(name, true)?;
+                            println!("    renewed {} proof(s)", renewed);
+                        }
+                    }
+                    zk::verify::ContractVerificationStatus::Unverified => println!("{}: not verified", name),
+                    zk::verify::ContractVerificationStatus::NeedsReverification(reasons) => {
+                        stale += 1;
+                        println!("{}: needs re-verification", name);
+                        for reason in reasons {
+                            println!("    - {}", reason);
+                        }
+                    }
+                    zk::verify::ContractVerificationStatus::Invalid(error) => println!("{}: invalid ({})", name, error),
+                }
+                match zk::deploy::provenance(name)? {
+                    zk::deploy::ContractProvenance::Approved { proposal_id, approvals } => {
+                        println!("    deployed from proposal {} ({} approvals)", proposal_id, approvals);
+                    }
+                    zk::deploy::ContractProvenance::LocalEdit => println!("    local edit (no approval record)"),
+                }
+            }
+            if stale > 0 {
+                warn!("{} contract(s) need re-verification", stale);
+            }
+            Ok(())
+        }
+        Commands::Zk { command } => {
+            match command {
+                ZkCommands::Cache { command } => match command {
+                    ZkCacheCommands::Stats {} => {
+                        let stats = zk::cache_stats()?;
+                        println!("Entries: {} ({} bytes)", stats.entries, stats.bytes);
+                        println!("Hits: {}  Misses: {}  Hit rate: {:.1}%", stats.hits, stats.misses, stats.hit_rate() * 100.0);
+                        println!("Evictions: {}", stats.evictions);
+                    }
+                    ZkCacheCommands::Invalidate { contract } => {
+                        let removed = zk::cache::invalidate(contract)?;
+                        println!("Evicted {} cached result(s) of {}", removed, contract);
+                    }
+                    ZkCacheCommands::Prune {} => {
+                        let removed = zk::cache::ProofCache::open()?.prune()?;
+                        println!("Evicted {} expired result(s)", removed);
+                    }
+                    ZkCacheCommands::Clear {} => {
+                        let removed = zk::cache::clear()?;
+                        println!("Evicted {} cached result(s)", removed);
+                    }
+                },
+                ZkCommands::Deps { contract } => {
+                    info!("Resolving imports for contract: {}", contract);
+                    let path = if contract.ends_with(".yaml") {
+                        contract.clone()
+                    } else {
+                        format!(".zk/contracts/{}.yaml", contract)
+                    };
+                    let full_path = constants::root_dir().join(&path);
+                    let content = std::fs::read_to_string(&full_path)?;
+                    let parsed: zk::contracts::ZkContract = serde_yaml::from_str(&content)?;
+                    let tree = zk::imports::import_tree(&parsed)?;
+                    print!("{}", tree);
+                }
+                ZkCommands::Propose { contract, changelog, simulate } => {
+                    info!("Proposing contract for deployment: {}", contract);
+                    let calls = simulate.iter()
+                        .map(|spec| zk::simulate::parse_call(spec))
+                        .collect::<Result<Vec<_>>>()?;
+                    if !calls.is_empty() {
+                        zk::state::unlock(None)?;
+                    }
+                    let proposal = zk::deploy::propose(contract, changelog, &calls)?;
+                    println!("Created proposal {} for {} ({})", proposal.id, proposal.contract_name, proposal.contract_hash);
+                    for simulation in &proposal.simulations {
+                        print_simulation(simulation);
+                    }
+                }
+                ZkCommands::Approve { proposal_id } => {
+                    info!("Approving contract proposal: {}", proposal_id);
+                    zk::deploy::approve(proposal_id)?;
+                    println!("Approved proposal {}", proposal_id);
+                }
+                ZkCommands::Deploy { proposal_id } => {
+                    info!("Deploying contract proposal: {}", proposal_id);
+                    let record = zk::deploy::deploy(proposal_id)?;
+                    println!("Deployed proposal {} with {} approval(s)", record.proposal_id, record.approvals.len());
+                }
+                ZkCommands::Proposals {} => {
+                    for (proposal, approvals) in zk::deploy::list_proposals()? {
+                        println!("{}: {} by {} ({} approval(s)) - {}",
+                                 proposal.id, proposal.contract_name, proposal.author, approvals, proposal.changelog);
+                        for simulation in &proposal.simulations {
+                            print_simulation(simulation);
+                        }
+                    }
+                }
+                ZkCommands::Run { contract, method, args, simulate } => {
+                    let parsed = zk::registry::get(contract)?;
+                    // Arguments are JSON values; anything else is passed as a string
+                    let args: Vec<serde_json::Value> = args.iter()
+                        .map(|arg| serde_json::from_str(arg).unwrap_or_else(|_| serde_json::Value::String(arg.clone())))
+                        .collect();
+                    zk::state::unlock(None)?;
+                    
+                    if *simulate {
+                        let simulation = zk::simulate_contract_method(&parsed, method, &args)?;
+                        print_simulation(&simulation);
+                    } else {
+                        info!("Running contract method: {}.{}", contract, method);
+                        let result = zk::execute_contract_method(&parsed, method, &args)?;
+                        println!("{}.{} returned {}", contract, method, result.value);
+                        println!("Cost: {} steps, {} state bytes", result.cost.steps, result.cost.state_bytes);
+                        println!("State hash: {} -> {}", result.pre_state_hash, result.post_state_hash);
+                        println!("Proof: {}", result.proof_id);
+                    }
+                }
+                ZkCommands::Stats { contract, top } => {
+                    let stats = zk::metering::stats(contract, *top)?;
+                    if stats.methods.is_empty() {
+                        println!("No recorded invocations of {}", contract);
+                        return Ok(());
+                    }
+                    
+                    println!("{:<20} {:>8} {:>12} {:>10} {:>10} {:>10} {:>12}",
+                             "METHOD", "CALLS", "AVG STEPS", "P50", "P95", "P99", "AVG STATE B");
+                    for (method, m) in &stats.methods {
+                        println!("{:<20} {:>8} {:>12.1} {:>10} {:>10} {:>10} {:>12.1}",
+                                 method, m.invocations, m.avg_steps, m.p50_steps, m.p95_steps, m.p99_steps, m.avg_state_bytes);
+                    }
+                    
+                    println!();
+                    println!("Most expensive recent invocations:");
+                    for invocation in &stats.most_expensive {
+                        println!("  {} {} steps, {} state bytes at {} (proof {})",
+                                 invocation.method, invocation.cost.steps, invocation.cost.state_bytes,
+                                 invocation.timestamp, invocation.proof_id);
+                    }
+                }
+                ZkCommands::TestRun { contract } => {
+                    let results = zk::testing::run_tests(contract)?;
+                    if results.is_empty() {
+                        println!("No test cases for {} in .zk/tests", contract);
+                        return Ok(());
+                    }
+                    
+                    let mut failed = 0;
+                    for result in &results {
+                        match &result.failure {
+                            None => println!("ok    {}", result.name),
+                            Some(failure) => {
+                                failed += 1;
+                                println!("FAIL  {} ({})", result.name, result.path.display());
+                                println!("      {}", failure);
+                            }
+                        }
+                    }
+                    println!();
+                    println!("{} passed, {} failed", results.len() - failed, failed);
+                    if failed > 0 {
+                        anyhow::bail!("{} of {} test case(s) of {} failed", failed, results.len(), contract);
+                    }
+                }
+                ZkCommands::State { command } => match command {
+                    ZkStateCommands::Show { contract } => {
+                        let parsed = zk::registry::get(contract)?;
+                        let status = match zk::state::storage_status(contract)? {
+                            zk::state::StorageStatus::Absent => "not persisted yet (defaults)",
+                            zk::state::StorageStatus::Plaintext => "plaintext",
+                            zk::state::StorageStatus::Encrypted => "encrypted",
+                        };
+                        if parsed.state_encryption && status == "plaintext" {
+                            warn!("{} declares state_encryption; state is encrypted on its next write", contract);
+                        }
+                        
+                        // Reading encrypted state needs the unlocked master key
+                        zk::state::unlock(None)?;
+                        let state = zk::get_contract_state(contract)?;
+                        println!("Contract: {}", contract);
+                        println!("Storage: {}", status);
+                        println!("State hash: {}", zk::state::state_hash(&state)?);
+                        if let Some(version) = zk::state::state_version(contract)? {
+                            println!("Written by version: {}", version);
+                        }
+                        println!("{}", serde_json::to_string_pretty(&state)?);
+                    }
+                    ZkStateCommands::Reset { contract } => {
+                        if zk::reset_contract_state(contract)? {
+                            println!("Reset state of {}; its next call starts from the declared defaults", contract);
+                        } else {
+                            println!("{} has no persisted state", contract);
+                        }
+                    }
+                },
+                ZkCommands::ProtectStateKey {} => {
+                    zk::state::unlock(None)?;
+                    let passphrase = rpassword::prompt_password("New passphrase: ")?;
+                    if rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+                        anyhow::bail!("Passphrases do not match");
+                    }
+                    zk::state::protect_master_key(&passphrase)?;
+                    println!("Master key {} is now passphrase-protected", zk::state::master_key_path().display());
+                    println!("It is prompted for at daemon start, or read from $SENTIENT_STATE_PASSPHRASE");
+                }
+            }
+            Ok(())
+        }
+        Commands::Package { command } => {
+            match command {
+                PackageCommands::List { ecosystems: true, .. } => {
+                    let mut table = Table::new(&["ECOSYSTEM", "STATUS", "MISSING"]);
+                    for status in crate::package::tools::ecosystems() {
+                        let (availability, missing) = match &status.availability {
+                            crate::package::tools::Availability::Available => ("Available", "-".to_string()),
+                            crate::package::tools::Availability::Unavailable { missing } => ("Unavailable", missing.join(", ")),
+                        };
+                        table.row([
+                            format!("{:?}", status.ecosystem).to_lowercase(),
+                            availability.to_string(),
+                            missing,
+                        ]);
+                    }
+                    table.print(&output)?;
+                }
+                PackageCommands::List { at, .. } => {
+                    let registry: crate::package::PackageRegistry = match at {
+                        Some(snapshot_id) => {
+                            info!("Listing packages in snapshot: {}", snapshot_id);
+                            let content = crate::heal::read_from_snapshot(snapshot_id, "packages", "registry.json")?;
+                            print_snapshot_banner(snapshot_id)?;
+                            serde_json::from_slice(&content)?
+                        }
+                        None => {
+                            info!("Listing installed packages");
+                            crate::package::load_registry()?
+                        }
+                    };
+                    
+                    let mut packages: Vec<_> = registry.packages.values().collect();
+                    packages.sort_by(|a, b| a.name.cmp(&b.name));
+                    
+                    let mut table = Table::new(&["NAME", "VERSION", "ECOSYSTEM", "CONTAINER", "MIRROR", "PATH"]);
+                    for package in packages {
+                        table.row([
+                            package.name.clone(),
+                            package.version.clone(),
+                            format!("{:?}", package.ecosystem).to_lowercase(),
+                            package.container_id.clone().unwrap_or_else(|| "-".to_string()),
+                            package.mirror.clone().unwrap_or_else(|| "-".to_string()),
+                            package.path.clone(),
+                        ]);
+                    }
+                    table.print(&output)?;
+                }
+                PackageCommands::Update { name: None, show_diff, ecosystem } => {
+                    if *show_diff {
+                        anyhow::bail!("--show-diff needs a package name");
+                    }
+                    let ecosystem = ecosystem.as_deref().map(|eco| match eco.to_lowercase().as_str() {
+                        "native" => crate::package::Ecosystem::Native,
+                        "linux" => crate::package::Ecosystem::Linux,
+                        "npm" => crate::package::Ecosystem::Npm,
+                        "python" => crate::package::Ecosystem::Python,
+                        "java" => crate::package::Ecosystem::Java,
+                        "rust" => crate::package::Ecosystem::Rust,
+                        "go" => crate::package::Ecosystem::Go,
+                        other => crate::package::Ecosystem::Other(other.to_string()),
+                    });
+                    
+                    info!("Updating all packages");
+                    let report = crate::package::update_all(ecosystem)?;
+                    let mut table = Table::new(&["PACKAGE", "RESULT", "DETAIL"]);
+                    for updated in &report.updated {
+                        table.row([updated.package.clone(), "updated".to_string(), format!("{} -> {}", updated.from, updated.to)]);
+                    }
+                    for failed in &report.failed {
+                        table.row([failed.package.clone(), "FAILED".to_string(), failed.error.clone()]);
+                    }
+                    for skipped in &report.skipped {
+                        table.row([skipped.package.clone(), "skipped".to_string(), skipped.reason.clone()]);
+                    }
+                    table.print(&output)?;
+                    println!("{} updated, {} skipped, {} failed", report.updated.len(), report.skipped.len(), report.failed.len());
+                    
+                    if !report.failed.is_empty() {
+                        anyhow::bail!("{} package(s) failed to update", report.failed.len());
+                    }
+                }
+                PackageCommands::Update { name: Some(name), show_diff, .. } => {
+                    if *show_diff {
+                        let from = store::diff::installed_version(name)?
+                            .ok_or_else(|| anyhow::anyhow!("--show-diff needs a ZK-Store package installed with a recorded version: {}", name))?;
+                        let to = store::show_package_details(name)?
+                            .ok_or_else(|| anyhow::anyhow!("Package not found in index: {}", name))?
+                            .version;
+                        
+                        print!("{}", store::diff_versions(name, &from, &to)?.summary());
+                        print!("Proceed with the update? [y/N] ");
+                        std::io::Write::flush(&mut std::io::stdout())?;
+                        let mut answer = String::new();
+                        std::io::stdin().read_line(&mut answer)?;
+                        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+                            println!("Update cancelled");
+                            return Ok(());
+                        }
+                    }
+                    
+                    info!("Updating package: {}", name);
+                    crate::package::update_package(name, None)?;
+                    println!("Package {} updated", name);
+                }
+                PackageCommands::Mirror { command: MirrorCommands::Test { ecosystem } } => {
+                    let ecosystem = match ecosystem.to_lowercase().as_str() {
+                        "npm" => crate::package::Ecosystem::Npm,
+                        "python" => crate::package::Ecosystem::Python,
+                        "rust" => crate::package::Ecosystem::Rust,
+                        "go" => crate::package::Ecosystem::Go,
+                        "linux" => crate::package::Ecosystem::Linux,
+                        other => crate::package::Ecosystem::Other(other.to_string()),
+                    };
+                    
+                    let check = crate::package::mirror::test(&ecosystem)?;
+                    println!("Mirror:    {}", check.url);
+                    println!("Reachable: {}", if check.reachable { "yes" } else { "no" });
+                    match &check.probe {
+                        Some(Ok(())) => println!("Probe:     ok"),
+                        Some(Err(e)) => println!("Probe:     failed: {}", e),
+                        None => println!("Probe:     -"),
+                    }
+                    if !check.passed() {
+                        anyhow::bail!("{:?} mirror check failed", ecosystem);
+                    }
+                }
+                PackageCommands::ToolPath { tool, path, .. } => {
+                    crate::package::tools::set_tool_path(tool, path.as_deref())?;
+                    let resolved = crate::package::tools::resolve(tool)?;
+                    println!("{} -> {}", tool, resolved.display());
+                }
+                PackageCommands::Licenses { json, fail_on_violation } => {
+                    info!("Building package license report");
+                    let report = crate::package::license_report()?;
+                    
+                    if *json {
+                        println!("{}", serde_json::to_string_pretty(&report)?);
+                    } else {
+                        for (license, packages) in &report.by_license {
+                            println!("{} ({})", license, packages.len());
+                            for package in packages {
+                                println!("  {}", package);
+                            }
+                        }
+                        
+                        for package in report.unknown() {
+                            println!("UNKNOWN LICENSE: {} {}", package.package, package.version);
+                        }
+                        for package in report.violations() {
+                            println!("VIOLATION: {} {} is licensed under {} ({:?})",
+                                     package.package, package.version, package.license, package.status);
+                        }
+                    }
+                    
+                    let violations = report.violations().count();
+                    if *fail_on_violation && violations > 0 {
+                        anyhow::bail!("{} package(s) violate the license policy", violations);
+                    }
+                }
+            }
+            Ok(())
+        }
+        Commands::Config { command } => {
+            match command {
+                ConfigCommands::Get { key, at } => {
+                    let content = match at {
+                        Some(snapshot_id) => {
+                            let content = crate::heal::read_from_snapshot(snapshot_id, "config", "system.json")?;
+                            print_snapshot_banner(snapshot_id)?;
+                            content
+                        }
+                        None => std::fs::read(constants::root_dir().join(".config").join("system.json"))?,
+                    };
+                    
+                    let config: serde_json::Value = serde_json::from_slice(&content)?;
+                    let value = key.split('.')
+                        .try_fold(&config, |value, part| value.get(part))
+                        .ok_or_else(|| anyhow::anyhow!("Config key not found: {}", key))?;
+                    
+                    match value {
+                        serde_json::Value::String(s) => println!("{}", s),
+                        other => println!("{}", serde_json::to_string_pretty(other)?),
+                    }
+                }
+            }
             Ok(())
         }
         Commands::Rollback { target } => {
@@ -53,9 +499,17 @@ pub fn execute_command(args: Vec<String>) -> Result<()> {
             crate::heal::rollback_system(target)?;
             Ok(())
         }
-        Commands::IsoBuild { output } => {
+        Commands::IsoBuild { output, arch, mode } => {
             info!("Building bootable OS image to: {}", output);
-            boot::create_bootable_image(output)?;
+            let mut config = boot::load_boot_config()?;
+            if let Some(arch) = arch {
+                config.arch = arch.clone();
+            }
+            if let Some(mode) = mode {
+                config.mode = mode.parse()?;
+            }
+            let image = boot::create_bootable_image(output, &config)?;
+            println!("Built {:?} image for {}: {}", config.mode, config.arch, image.display());
             Ok(())
         }
         Commands::Boot { zero } => {
@@ -63,40 +517,241 @@ pub fn execute_command(args: Vec<String>) -> Result<()> {
             // This would typically not be called from CLI
             Ok(())
         }
-        Commands::TsoRun { container_path } => {
+        Commands::TsoRun { container_path, args } => {
             info!("Running TSO container: {}", container_path);
-            matrixbox::run_container(container_path)?;
+            let args: Vec<&str> = args.iter().map(String::as_str).collect();
+            matrixbox::run_container(container_path, &args)?;
             Ok(())
         }
         Commands::MatrixBox { command } => {
             match command {
-                MatrixBoxCommands::Ls {} => {
+                MatrixBoxCommands::Ls { at: Some(snapshot_id), .. } => {
+                    info!("Listing MatrixBox containers in snapshot: {}", snapshot_id);
+                    let content = crate::heal::read_from_snapshot(snapshot_id, "containers", "registry.json")?;
+                    print_snapshot_banner(snapshot_id)?;
+                    let mut table = Table::new(&["ID", "NAME"]);
+                    for (id, name) in matrixbox::registry::containers_in_registry_file(&content)? {
+                        table.row([id.to_string(), name]);
+                    }
+                    table.print(&output)?;
+                }
+                MatrixBoxCommands::Ls { at: None, sync: true } => {
+                    info!("Listing MatrixBox container sync status");
+                    let mut table = Table::new(&["ID", "NAME", "PEER", "LAST SYNCED", "PENDING", "CONFLICTS", "STATE"]);
+                    for container in matrixbox::list_containers()? {
+                        let status = match matrixbox::sync::status(&container.id)? {
+                            Some(status) => status,
+                            None => continue,
+                        };
+                        for peer in status.peers {
+                            let conflicts = status.conflicts.iter().filter(|c| c.peer == peer.peer).count();
+                            table.row([
+                                container.id.to_string(),
+                                container.name.clone(),
+                                peer.peer,
+                                peer.last_synced.map_or("never".to_string(), |t| t.to_string()),
+                                peer.pending.to_string(),
+                                conflicts.to_string(),
+                                peer.blocked.unwrap_or_else(|| "ready".to_string()),
+                            ]);
+                        }
+                    }
+                    table.print(&output)?;
+                }
+                MatrixBoxCommands::Ls { at: None, sync: false } => {
                     info!("Listing MatrixBox containers");
                     let containers = matrixbox::list_containers()?;
+                    let mut table = Table::new(&["ID", "NAME", "STATUS", "CREATED", "LIMITS", "COMMAND"]);
                     for container in containers {
-                        println!("{}: {}", container.id, container.name);
+                        let command = std::iter::once(container.entrypoint.as_str())
+                            .chain(container.args.iter().map(String::as_str))
+                            .collect::<Vec<_>>()
+                            .join(" ");
+                        let limits = matrixbox::limits::effective(container.resource_limits)?;
+                        table.row([
+                            container.id.to_string(),
+                            container.name,
+                            format!("{:?}", container.status).to_lowercase(),
+                            container.created_at,
+                            format_limits(limits.as_ref()),
+                            command,
+                        ]);
                     }
+                    table.print(&output)?;
                 }
-                MatrixBoxCommands::Rm { id } => {
+                MatrixBoxCommands::Rm { id, purge } => {
                     info!("Removing MatrixBox container: {}", id);
-                    matrixbox::remove_container(id)?;
+                    matrixbox::remove_container(id, *purge)?;
+                }
+                MatrixBoxCommands::Pause { id } => {
+                    info!("Pausing MatrixBox container: {}", id);
+                    matrixbox::runtime::pause_container(id)?;
+                }
+                MatrixBoxCommands::Resume { id } => {
+                    info!("Resuming MatrixBox container: {}", id);
+                    matrixbox::runtime::resume_container(id)?;
+                }
+                MatrixBoxCommands::Sync { id, peer } => {
+                    info!("Syncing data of container {}", id);
+                    let peer = matrixbox::sync::sync_with(id, peer.as_deref())?;
+                    println!("Synced container {} with {}", id, peer);
+                }
+                MatrixBoxCommands::Diff { id } => {
+                    let filesystem = matrixbox::container::container_filesystem(id)?
+                        .ok_or_else(|| anyhow::anyhow!("Container {} has no filesystem layer", id))?;
+                    for change in filesystem.diff()? {
+                        let kind = match change.kind {
+                            matrixbox::container::ChangeKind::Added => "A",
+                            matrixbox::container::ChangeKind::Modified => "M",
+                            matrixbox::container::ChangeKind::Deleted => "D",
+                        };
+                        println!("{} {}", kind, change.path);
+                    }
+                }
+                MatrixBoxCommands::Pack { dir, output, name, version } => {
+                    let config = matrixbox::container::ContainerConfig {
+                        name: name.clone().unwrap_or_default(),
+                        version: version.clone(),
+                        ..Default::default()
+                    };
+                    matrixbox::tso::create_tso_archive(dir, output, &config)?;
+                    let info = matrixbox::tso::get_tso_info(output)?;
+                    println!("Packed {} {} into {} ({} files)", info.name, info.version, output.display(), info.file_count);
+                }
+                MatrixBoxCommands::Sign { image } => {
+                    let signing_key = matrixbox::registry::image_signing_key()?;
+                    let signature = matrixbox::registry::sign_image(image, &signing_key)?;
+                    println!("Signed {} (manifest {})", image.display(), signature.manifest_hash);
+                    println!("Signer key: {}", signature.public_key);
+                    println!("Nodes trust it once the key is saved in .matrixbox/trusted_keys/");
+                }
+                MatrixBoxCommands::VerifyImage { image } => {
+                    let trusted_keys = matrixbox::registry::load_trusted_keys()?;
+                    if !matrixbox::registry::verify_image(image, &trusted_keys)? {
+                        anyhow::bail!("Image {} is not signed by a trusted key", image.display());
+                    }
+                    println!("Image signature: VALID");
+                }
+                MatrixBoxCommands::Ports { id } => {
+                    let ports = matrixbox::network::forwarded_ports(id)?;
+                    if ports.is_empty() {
+                        println!("No ports forwarded to container {}", id);
+                    }
+                    for port in ports {
+                        println!("{}/{} -> {}:{}", port.host_port, port.protocol, port.container_ip, port.container_port);
+                    }
+                }
+                MatrixBoxCommands::Logs { id, follow, tail } => {
+                    matrixbox::registry::get_container(id)?;
+                    
+                    // Start following before reading, so no line falls in between
+                    let mut follower = matrixbox::output::LogFollower::new(id);
+                    for line in matrixbox::get_logs(id, *tail)? {
+                        print_log_line(&line);
+                    }
+                    
+                    while *follow {
+                        std::thread::sleep(std::time::Duration::from_millis(LOG_FOLLOW_INTERVAL_MS));
+                        for line in follower.poll()? {
+                            print_log_line(&line);
+                        }
+                    }
                 }
+                MatrixBoxCommands::Kv { id, command } => match command {
+                    KvCommands::Get { key } => {
+                        match matrixbox::kv::with_store(id, |store| Ok(store.get(key.as_bytes())))? {
+                            Some(value) => println!("{}", String::from_utf8_lossy(&value)),
+                            None => anyhow::bail!("Key not found: {}", key),
+                        }
+                    }
+                    KvCommands::Put { key, value, ttl_ms } => {
+                        info!("Setting kv key {} of container {}", key, id);
+                        matrixbox::kv::with_store(id, |store| store.put(key.as_bytes(), value.as_bytes(), *ttl_ms))?;
+                    }
+                    KvCommands::Ls { prefix } => {
+                        let prefix = prefix.as_deref().unwrap_or("");
+                        let (entries, usage, digest) = matrixbox::kv::with_store(id, |store| {
+                            Ok((store.list(prefix.as_bytes()), store.usage(), store.digest()))
+                        })?;
+                        let mut table = Table::new(&["KEY", "BYTES"]);
+                        for (key, value) in entries {
+                            table.row([String::from_utf8_lossy(&key).to_string(), value.len().to_string()]);
+                        }
+                        table.print(&output)?;
+                        println!("{} key(s), {} of {} bytes used, digest {}",
+                                 usage.keys, usage.used_bytes, usage.quota_bytes, digest);
+                    }
+                    KvCommands::Del { key } => {
+                        info!("Deleting kv key {} of container {}", key, id);
+                        matrixbox::kv::with_store(id, |store| store.delete(key.as_bytes()))?;
+                    }
+                },
+                MatrixBoxCommands::Compose { command } => match command {
+                    ComposeCommands::Up { file } => {
+                        let ids = matrixbox::compose::compose_up(file)?;
+                        println!("Started {} service(s): {}", ids.len(), ids.join(", "));
+                    }
+                    ComposeCommands::Down => {
+                        let services = matrixbox::compose::compose_down()?;
+                        println!("Stopped {} service(s): {}", services.len(), services.join(", "));
+                    }
+                },
             }
             Ok(())
         }
         Commands::Contract { command } => {
             match command {
-                ContractCommands::Reload { path } => {
-                    info!("Reloading ZK contract: {}", path);
-                    let contract = zk::load_contract(path)?;
-                    // Implement hot reload logic
+                ContractCommands::Reload { name } => {
+                    info!("Reloading ZK contract: {}", name);
+                    // A bare name refers to the registered contract
+                    let path = if name.ends_with(".yaml") || name.contains('/') {
+                        name.clone()
+                    } else {
+                        format!(".zk/contracts/{}.yaml", name)
+                    };
+                    
+                    // Loading goes through the parser, which re-resolves imports from disk
+                    let reload = zk::reload_contract(&path)?;
+                    println!("Reloaded {}", reload.name);
+                    println!("Old hash: {}", reload.old_hash.as_deref().unwrap_or("-"));
+                    println!("New hash: {}", reload.new_hash);
                 }
                 ContractCommands::Verify { path } => {
                     info!("Verifying contract: {}", path);
-                    let contract = zk::load_contract(path)?;
+                    let contract = match zk::load_contract(path) {
+                        Ok(contract) => contract,
+                        Err(e) => match e.downcast_ref::<zk::parser::InvalidContract>() {
+                            Some(invalid) => {
+                                print_validation_issues(path, &invalid.errors, &output)?;
+                                anyhow::bail!("{} validation error(s) in {}", invalid.errors.len(), path);
+                            }
+                            None => return Err(e),
+                        },
+                    };
                     let result = zk::verify_contract(&contract)?;
                     println!("Contract verification: {}", if result { "PASSED" } else { "FAILED" });
                 }
+                ContractCommands::Validate { path } => {
+                    let issues = zk::validate_contract_file(path)?;
+                    if issues.is_empty() {
+                        println!("{}: no issues found", path);
+                        return Ok(());
+                    }
+                    
+                    print_validation_issues(path, &issues, &output)?;
+                    let errors = issues.iter().filter(|i| i.severity == zk::parser::Severity::Error).count();
+                    if errors > 0 {
+                        anyhow::bail!("{} validation error(s) in {}", errors, path);
+                    }
+                    println!("{} warning(s) in {}", issues.len(), path);
+                }
+                ContractCommands::Deps { name } => {
+                    info!("Resolving dependencies for contract: {}", name);
+                    let contract = zk::registry::get(name)?;
+                    let graph = zk::resolver::resolve_dependencies(&contract)?;
+                    println!("{}@{}", contract.name, contract.version);
+                    print_dependency_tree(&graph.tree, "");
+                }
             }
             Ok(())
         }
@@ -106,13 +761,75 @@ pub fn execute_command(args: Vec<String>) -> Result<()> {
         }
         Commands::Store { command } => {
             match command {
-                StoreCommands::Install { name } => {
-                    info!("Installing package: {}", name);
-                    store::install_package(&name)?;
+                StoreCommands::Advisory { command } => match command {
+                    AdvisoryCommands::Ls {} => {
+                        info!("Listing security advisories");
+                        let advisories = store::advisory::list_advisories()?;
+                        for advisory in &advisories {
+                            let state = store::advisory::advisory_state(&advisory.id)?;
+                            println!("{} [{}] {} {}: {} ({:?})",
+                                advisory.id, advisory.severity, advisory.package, advisory.affected, advisory.summary, state.status);
+                        }
+                        let ids: Vec<String> = advisories.into_iter().map(|a| a.id).collect();
+                        store::advisory::mark_seen(&ids)?;
+                    }
+                    AdvisoryCommands::Ack { id, note } => {
+                        info!("Acknowledging advisory: {}", id);
+                        store::advisory::acknowledge(id, note.as_deref())?;
+                        println!("Advisory {} acknowledged", id);
+                    }
+                },
+                StoreCommands::Repo { command } => match command {
+                    RepoCommands::Init { dir } => {
+                        let key = store::repo::init(dir)?;
+                        println!("Initialized repository in {}", dir.display());
+                        println!("Repository key: {}", key);
+                        println!("Clients trust the repository by registering it with this key");
+                    }
+                    RepoCommands::AddPackage { archive, dir, license } => {
+                        info!("Publishing {:?} to repository {:?}", archive, dir);
+                        let added = store::repo::add_package(dir, archive, license)?;
+                        if added.already_published {
+                            println!("{} {} is already published", added.package.name, added.package.version);
+                        } else {
+                            println!("Published {} {} ({})", added.package.name, added.package.version, added.package.hash);
+                        }
+                    }
+                    RepoCommands::Sign { dir } => {
+                        let generations = store::repo::sign(dir)?;
+                        println!("Re-signed index and {} change-log generation(s)", generations);
+                    }
+                    RepoCommands::Verify { dir } => {
+                        let problems = store::repo::verify(dir)?;
+                        for problem in &problems {
+                            println!("{}", problem);
+                        }
+                        if !problems.is_empty() {
+                            anyhow::bail!("Repository {} has {} problem(s)", dir.display(), problems.len());
+                        }
+                        println!("Repository {} is consistent", dir.display());
+                    }
+                },
+                StoreCommands::Install { name, from_peers, peer } => {
+                    if *from_peers || peer.is_some() {
+                        info!("Installing package from peers: {}", name);
+                        match store::install_from_peer(name, peer.as_deref())? {
+                            Some(served_by) => println!("Installed {} from an archive served by {}", name, served_by),
+                            None => println!("Installed {} from an archive already on this node", name),
+                        }
+                    } else {
+                        info!("Installing package: {}", name);
+                        let mut bar = table::ProgressBar::new(&format!("Downloading {}", name));
+                        let installed = store::install_package_with_progress(&name, |p| bar.update(p.downloaded, p.total));
+                        bar.finish();
+                        installed?;
+                    }
                 }
-                StoreCommands::Remove { name } => {
+                StoreCommands::Remove { name, purge, force } => {
                     info!("Removing package: {}", name);
-                    store::remove_package(&name)?;
+                    if let Some(trash_id) = store::remove_package(&name, *purge, *force)? {
+                        println!("Package {} moved to trash as {}", name, trash_id);
+                    }
                 }
                 StoreCommands::List {} => {
                     info!("Listing installed packages");
@@ -131,9 +848,11 @@ pub fn execute_command(args: Vec<String>) -> Result<()> {
                     if packages.is_empty() {
                         println!("No packages found matching: {}", query);
                     } else {
+                        let mut table = Table::new(&["NAME", "VERSION", "DESCRIPTION"]);
                         for package in packages {
-                            println!("{} ({}): {}", package.name, package.version, package.description);
+                            table.row([package.name, package.version, package.description]);
                         }
+                        table.print(&output)?;
                     }
                 }
                 StoreCommands::Info { name } => {
@@ -153,14 +872,196 @@ pub fn execute_command(args: Vec<String>) -> Result<()> {
                 }
                 StoreCommands::Update {} => {
                     info!("Updating package index");
-                    store::update_index()?;
-                    println!("Package index updated successfully");
+                    let update = store::update_index()?;
+                    let published = chrono::DateTime::from_timestamp(update.last_updated as i64, 0)
+                        .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+                        .unwrap_or_else(|| update.last_updated.to_string());
+                    println!("Package index updated: {} package(s), published {}", update.packages, published);
+                    println!("Signature verified with store publisher key {}", update.publisher_key);
                 }
                 StoreCommands::Verify { name } => {
                     info!("Verifying package integrity: {}", name);
                     let result = store::verify_package(&name)?;
                     println!("Package integrity: {}", if result { "VALID" } else { "INVALID" });
                 }
+                StoreCommands::Diff { name, from, to, json } => {
+                    let diff = store::diff_versions(name, from, to)?;
+                    if *json {
+                        println!("{}", serde_json::to_string_pretty(&diff)?);
+                    } else {
+                        crate::cli::table::emit(&diff.summary(), &output)?;
+                    }
+                }
+            }
+            Ok(())
+        }
+        Commands::Logs { command } => {
+            match command {
+                LogsCommands::Ship { command } => match command {
+                    LogsShipCommands::Status {} => {
+                        info!("Showing log shipping status");
+                        let status = crate::logs::ship::status()?;
+                        println!("Destination: {:?}", crate::logs::ship::destination());
+                        println!("Destination healthy: {}", status.destination_healthy);
+                        println!("Spool depth: {} batches ({} bytes)", status.spool_batches, status.spool_bytes);
+                        match status.last_flush {
+                            Some(ts) => println!("Last successful flush: {}", ts),
+                            None => println!("Last successful flush: never"),
+                        }
+                        if let Some(error) = status.last_error {
+                            println!("Last error: {}", error);
+                        }
+                        if status.dropped_batches > 0 {
+                            println!("Dropped batches (spool full): {}", status.dropped_batches);
+                        }
+                    }
+                    LogsShipCommands::Flush {} => {
+                        info!("Flushing log spool");
+                        crate::logs::ship::flush()?;
+                    }
+                    LogsShipCommands::Disable {} => {
+                        info!("Disabling log shipping");
+                        crate::logs::ship::disable()?;
+                    }
+                },
+            }
+            Ok(())
+        }
+        Commands::Metrics { command } => {
+            match command {
+                MetricsCommands::History { name, since } => {
+                    info!("Showing metric history: {}", name);
+                    let since = Some(crate::trash::parse_age(since)?);
+                    let history = crate::logs::metrics::history(name, since)?;
+                    if history.is_empty() {
+                        println!("No samples for {}", name);
+                        return Ok(());
+                    }
+                    
+                    let values: Vec<f64> = history.iter().map(|(_, value)| *value).collect();
+                    let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+                    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+                    let mean = values.iter().sum::<f64>() / values.len() as f64;
+                    
+                    println!("{}  {}", name, crate::logs::metrics::sparkline(&values, 60));
+                    println!("samples: {}  min: {:.2}  mean: {:.2}  max: {:.2}", values.len(), min, mean, max);
+                    println!();
+                    println!("{:<12} {:>12}", "TIMESTAMP", "VALUE");
+                    
+                    // Show at most 20 rows, averaging samples in between
+                    let bucket_size = (history.len() + 19) / 20;
+                    for bucket in history.chunks(bucket_size) {
+                        let value = bucket.iter().map(|(_, value)| value).sum::<f64>() / bucket.len() as f64;
+                        println!("{:<12} {:>12.2}", bucket[0].0, value);
+                    }
+                }
+                MetricsCommands::Findings {} => {
+                    let findings = crate::logs::metrics::findings();
+                    if findings.is_empty() {
+                        println!("No metric anomalies");
+                    }
+                    for finding in findings {
+                        println!("{} (since {})", finding.message, finding.raised_at);
+                    }
+                }
+            }
+            Ok(())
+        }
+        Commands::Maintenance { command } => {
+            match command {
+                MaintenanceCommands::Enter { run, max_duration } => {
+                    let max_duration = crate::trash::parse_age(max_duration)?;
+                    let state = match run {
+                        Some(operation) => {
+                            info!("Running maintenance operation: {}", operation);
+                            crate::maintenance::run(operation.parse()?, max_duration)?
+                        }
+                        None => {
+                            info!("Entering maintenance mode");
+                            crate::maintenance::enter(max_duration)?
+                        }
+                    };
+                    println!("Paused containers: {}", state.paused.len());
+                    for id in &state.paused {
+                        println!("  {}", id);
+                    }
+                    if !state.unpausable.is_empty() {
+                        println!("Containers that cannot be paused (stop them if needed):");
+                        for id in &state.unpausable {
+                            println!("  {}", id);
+                        }
+                    }
+                }
+                MaintenanceCommands::Exit {} => {
+                    info!("Exiting maintenance mode");
+                    crate::maintenance::exit()?;
+                }
+                MaintenanceCommands::Status {} => {
+                    let state = crate::maintenance::status()?;
+                    if state.active {
+                        println!("Maintenance mode: active since {} (deadline {})", state.entered_at, state.deadline);
+                        println!("Paused containers: {}", state.paused.join(", "));
+                        if !state.unpausable.is_empty() {
+                            println!("Unpausable containers: {}", state.unpausable.join(", "));
+                        }
+                    } else {
+                        println!("Maintenance mode: inactive");
+                    }
+                    if state.timed_out {
+                        println!("Last maintenance window exceeded its maximum duration");
+                    }
+                }
+            }
+            Ok(())
+        }
+        Commands::Trash { command } => {
+            match command {
+                TrashCommands::Ls {} => {
+                    info!("Listing trash entries");
+                    let entries = crate::trash::list_entries()?;
+                    if entries.is_empty() {
+                        println!("Trash is empty");
+                    }
+                    for entry in entries {
+                        println!("{}: {:?} {} (from {})", entry.id, entry.kind, entry.name, entry.original_path);
+                    }
+                }
+                TrashCommands::Restore { entry, rename } => {
+                    info!("Restoring trash entry: {}", entry);
+                    let name = crate::trash::restore_entry(entry, rename.as_deref())?;
+                    println!("Restored {} as {}", entry, name);
+                }
+                TrashCommands::Empty { older_than } => {
+                    info!("Emptying trash");
+                    let age = older_than.as_deref().map(crate::trash::parse_age).transpose()?;
+                    let purged = crate::trash::empty_trash(age)?;
+                    println!("Purged {} trash entries", purged);
+                }
+            }
+            Ok(())
+        }
+        Commands::Lock { command } => {
+            match command {
+                LockCommands::Ls {} => {
+                    info!("Listing resource locks");
+                    let locks = crate::core::lock::list_locks()?;
+                    if locks.is_empty() {
+                        println!("No locks held");
+                    }
+                    for lock in locks {
+                        let state = if lock.stale { " [STALE]" } else { "" };
+                        match lock.info {
+                            Some(info) => println!("{}: pid {} since {} ({}){}",
+                                lock.name, info.pid, info.acquired_at, info.purpose, state),
+                            None => println!("{}: unreadable lock file{}", lock.name, state),
+                        }
+                    }
+                }
+                LockCommands::Break { name, force } => {
+                    info!("Breaking lock: {}", name);
+                    crate::core::lock::break_lock(name, *force)?;
+                    println!("Lock {} broken", name);
+                }
             }
             Ok(())
         }
@@ -168,70 +1069,920 @@ pub fn execute_command(args: Vec<String>) -> Result<()> {
             match command {
                 HealCommands::Container { id } => {
                     info!("Healing container: {}", id);
-                    crate::heal::heal_container(id)?;
+                    match crate::heal::heal_container(id) {
+                        Ok(snapshot_id) => println!("Container {} healed from snapshot {}", id, snapshot_id),
+                        Err(e) if e.downcast_ref::<crate::heal::NoContainerSnapshot>().is_some() => {
+                            println!("{}", e);
+                            print!("Recreate container {} from its package instead? Its data will start empty [y/N] ", id);
+                            std::io::Write::flush(&mut std::io::stdout())?;
+                            let mut answer = String::new();
+                            std::io::stdin().read_line(&mut answer)?;
+                            if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+                                println!("Container {} left as is", id);
+                                return Ok(());
+                            }
+                            
+                            let new_id = crate::heal::recreate_container(id)?;
+                            println!("Container {} recreated as {}", id, new_id);
+                        }
+                        Err(e) => return Err(e),
+                    }
                 }
                 HealCommands::Boot {} => {
                     info!("Healing boot subsystem");
                     crate::heal::heal_boot()?;
                 }
+                HealCommands::List {} => {
+                    let mut table = Table::new(&["ID", "TAKEN", "REASON", "HASH"]);
+                    for snapshot in crate::heal::snapshot::list_snapshots()? {
+                        let taken = chrono::DateTime::from_timestamp(snapshot.timestamp as i64, 0)
+                            .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+                            .unwrap_or_else(|| snapshot.timestamp.to_string());
+                        table.row([snapshot.id, taken, snapshot.reason, snapshot.hash]);
+                    }
+                    table.print(&output)?;
+                }
+                HealCommands::Snapshot { now: true, .. } => {
+                    let snapshot_id = crate::heal::take_scheduled_snapshot()?;
+                    println!("Scheduled snapshot {} taken", snapshot_id);
+                }
+                HealCommands::Snapshot { container: Some(id), .. } => {
+                    let snapshot_id = crate::heal::snapshot::snapshot_container(id)?;
+                    println!("Snapshot {} of container {} taken", snapshot_id, id);
+                }
+                HealCommands::Snapshot { reason, incremental, base, .. } => {
+                    let options = if *incremental {
+                        let base = match base {
+                            Some(base) => base.clone(),
+                            None => crate::heal::get_latest_snapshot()?
+                                .map(|s| s.id)
+                                .ok_or_else(|| anyhow::anyhow!("No snapshot to base an incremental snapshot on"))?,
+                        };
+                        crate::heal::snapshot::SnapshotOptions::incremental(&base)
+                    } else {
+                        crate::heal::snapshot::SnapshotOptions::default()
+                    };
+                    let snapshot_id = crate::heal::take_snapshot_with(reason, &options)?;
+                    match options.mode.base() {
+                        Some(base) => println!("Snapshot {} taken (incremental on {})", snapshot_id, base),
+                        None => println!("Snapshot {} taken", snapshot_id),
+                    }
+                }
+                HealCommands::Compact { ids } => {
+                    let snapshot_id = crate::heal::snapshot::compact_chain(ids)?;
+                    println!("Compacted {} snapshot(s) into full snapshot {}", ids.len(), snapshot_id);
+                }
+                HealCommands::Schedule { command: ScheduleCommands::Show {} } => {
+                    let schedule = crate::heal::snapshot_schedule()?;
+                    let format = |time: Option<u64>| time
+                        .and_then(|t| chrono::DateTime::from_timestamp(t as i64, 0))
+                        .map_or("-".to_string(), |t| t.format("%Y-%m-%d %H:%M:%S").to_string());
+                    if schedule.interval_secs == 0 {
+                        println!("Scheduled snapshots are disabled (auto_snapshot_interval_secs is 0)");
+                    } else {
+                        println!("Interval: {}s", schedule.interval_secs);
+                    }
+                    println!("Last run: {}", format(schedule.last_run));
+                    println!("Next run: {}", format(schedule.next_run));
+                }
+                HealCommands::Ring { command: RingCommands::Status {} } => {
+                    let now = chrono::Utc::now().timestamp().max(0) as u64;
+                    let mut table = Table::new(&["SLOT", "SNAPSHOT", "AGE", "HEAD"]);
+                    for slot in crate::heal::snapshot::ring_status()? {
+                        let (id, age) = match &slot.snapshot {
+                            Some(snapshot) => (snapshot.id.clone(), table::format_age(now.saturating_sub(snapshot.timestamp))),
+                            None => ("-".to_string(), "-".to_string()),
+                        };
+                        table.row([slot.index.to_string(), id, age, if slot.is_head { "*".to_string() } else { String::new() }]);
+                    }
+                    table.print(&output)?;
+                }
+                HealCommands::Prune { keep, max_age_days, min_keep } => {
+                    let policy = prune_policy(*keep, *max_age_days, *min_keep)?;
+                    let report = crate::heal::snapshot::prune(policy)?;
+                    let mut table = Table::new(&["ID", "REASON", "BYTES"]);
+                    for snapshot in &report.removed {
+                        table.row([snapshot.id.clone(), snapshot.reason.clone(), snapshot.bytes.to_string()]);
+                    }
+                    table.print(&output)?;
+                    println!("Removed {} snapshot(s), kept {}", report.removed.len(), report.kept);
+                    if let Some(protected) = &report.protected {
+                        println!("Kept panic fallback snapshot {}", protected);
+                    }
+                }
+                HealCommands::Verify { id } => {
+                    let verification = crate::heal::verify_snapshot(id)?;
+                    println!("{}", verification);
+                    if !verification.is_intact() {
+                        anyhow::bail!("Snapshot {} failed integrity verification", id);
+                    }
+                }
+                HealCommands::Rollback { component, snapshot } => {
+                    crate::heal::recovery::rollback_component(component, snapshot)?;
+                    println!("Component {} rolled back to snapshot {}", component, snapshot);
+                }
             }
             Ok(())
         }
         Commands::Panic { command } => {
             match command {
-                PanicCommands::Recover {} => {
+                PanicCommands::Recover { force } => {
                     info!("Recovering from panic state");
-                    crate::panic::recover()?;
+                    crate::panic::recover(*force)?;
+                }
+                PanicCommands::Status {} => {
+                    print_panic_status(crate::panic::status()?.as_ref());
                 }
-                PanicCommands::Report { output } => {
+                PanicCommands::Report { output, full_backtrace, severity_filter } => {
                     info!("Generating crash report to: {}", output);
-                    crate::panic::generate_report(output)?;
+                    let min_severity = severity_filter.as_deref().map(str::parse).transpose()?;
+                    let histogram = crate::panic::generate_report(output, *full_backtrace, min_severity)?;
+                    print_panic_histogram(&histogram);
+                }
+                PanicCommands::SendPending {} => {
+                    let (sent, failed) = crate::panic::remote::send_pending()?;
+                    println!("Sent {} crash report(s)", sent);
+                    if failed > 0 {
+                        anyhow::bail!("{} crash report(s) could not be sent and stay queued", failed);
+                    }
                 }
             }
             Ok(())
         }
-        Commands::Gossip { command } => {
+        Commands::New { command } => {
             match command {
-                GossipCommands::Enable {} => {
-                    info!("Enabling gossip trace sync");
-                    crate::gossip::enable_sync()?;
-                }
-                GossipCommands::Pull { peer } => {
-                    info!("Pulling runtime trace from peer: {}", peer);
-                    crate::gossip::pull_from_peer(peer)?;
-                }
-                GossipCommands::VerifyTrace {} => {
-                    info!("Cross-validating trace integrity with peers");
-                    crate::gossip::verify_trace()?;
+                NewCommands::App { name, path } => {
+                    let app_dir = matrixbox::app::new_app(name, Path::new(path))?;
+                    println!("Created app {} at {}", name, app_dir.display());
+                    println!("Build it with: sentctl build --path {}", app_dir.display());
                 }
             }
             Ok(())
         }
-        Commands::Intent { command } => {
-            match command {
-                IntentCommands::Record {} => {
-                    info!("Starting intent recording session");
-                    crate::intent::start_recording()?;
+        Commands::Build { path, output } => {
+            info!("Building MatrixBox app in {}", path);
+            let archive = matrixbox::app::build_app(Path::new(path), output.as_deref().map(Path::new))?;
+            println!("Packed {}", archive.display());
+            Ok(())
+        }
+        Commands::Bench { filter, output, compare, threshold, list } => {
+            if *list {
+                for name in crate::bench::bench_names() {
+                    println!("{}", name);
                 }
-                IntentCommands::Stop {} => {
-                    info!("Stopping intent recording session");
-                    crate::intent::stop_recording()?;
+                return Ok(());
+            }
+            
+            info!("Running benchmarks");
+            let report = crate::bench::run(filter.as_deref())?;
+            crate::bench::save_report(&report, Path::new(output))?;
+            
+            println!("{:<28} {:>10} {:>10} {:>10} {:>16}", "BENCHMARK", "MEDIAN ms", "MIN ms", "MAX ms", "THROUGHPUT");
+            for result in &report.results {
+                let throughput = result.throughput.as_ref()
+                    .map(|t| format!("{:.1} {}/s", t.per_second, t.unit))
+                    .unwrap_or_else(|| "-".to_string());
+                println!("{:<28} {:>10.2} {:>10.2} {:>10.2} {:>16}",
+                         result.name, result.median_ms, result.min_ms, result.max_ms, throughput);
+            }
+            for skipped in &report.skipped {
+                println!("{:<28} skipped: {}", skipped.name, skipped.reason);
+            }
+            println!("Results written to {}", output);
+            
+            if let Some(baseline_path) = compare {
+                let baseline = crate::bench::load_report(Path::new(baseline_path))?;
+                let deltas = crate::bench::compare(&report, &baseline, *threshold)?;
+                
+                println!();
+                println!("{:<28} {:>10} {:>10} {:>9} {:>9}", "BENCHMARK", "BASE ms", "NOW ms", "CHANGE", "LIMIT");
+                for delta in &deltas {
+                    println!("{:<28} {:>10.2} {:>10.2} {:>8.1}% {:>8.1}%{}",
+                             delta.name, delta.baseline_ms, delta.current_ms, delta.change_pct, delta.threshold_pct,
+                             if delta.regression { "  REGRESSION" } else { "" });
                 }
-                IntentCommands::Replay { session } => {
-                    info!("Replaying intent session: {}", session);
-                    crate::intent::replay_session(session)?;
+                
+                let regressions = deltas.iter().filter(|d| d.regression).count();
+                if regressions > 0 {
+                    anyhow::bail!("{} benchmark(s) regressed beyond their threshold", regressions);
                 }
             }
             Ok(())
         }
-    }
-}
-
-/// CLI command definition using clap
+        Commands::Setup { defaults, answers } => {
+            if *defaults && answers.is_some() {
+                anyhow::bail!("--defaults and --answers cannot be combined");
+            }
+            
+            let mode = match answers {
+                Some(path) => crate::setup::Mode::Answers(path.clone()),
+                None if *defaults => crate::setup::Mode::Defaults,
+                None => crate::setup::Mode::Interactive,
+            };
+            
+            info!("Running setup");
+            let answers = crate::setup::run(mode)?;
+            crate::setup::print_summary(&answers);
+            Ok(())
+        }
+        Commands::Runtime { command } => {
+            match command {
+                RuntimeCommands::Workers {} => {
+                    let status = crate::core::workers::load_status()?;
+                    println!("Parallelism: {}{}", status.parallelism,
+                             if status.low_power { " (low power)" } else { "" });
+                    println!();
+                    
+                    let mut kinds = Table::new(&["KIND", "PRIORITY", "LIMIT", "QUEUED", "RUNNING", "COMPLETED", "FAILED"]);
+                    for kind in &status.kinds {
+                        kinds.row([
+                            kind.kind.clone(),
+                            format!("{:?}", kind.config.priority).to_lowercase(),
+                            kind.config.max_concurrent.to_string(),
+                            kind.queued.to_string(),
+                            kind.running.to_string(),
+                            kind.completed.to_string(),
+                            kind.failed.to_string(),
+                        ]);
+                    }
+                    print!("{}", kinds.render(&output));
+                    
+                    if !status.running.is_empty() {
+                        println!();
+                        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs();
+                        let mut running = Table::new(&["ID", "KIND", "TASK", "ELAPSED"]);
+                        for task in &status.running {
+                            running.row([task.id.to_string(), task.kind.clone(), task.name.clone(),
+                                         format!("{}s", now.saturating_sub(task.started_at))]);
+                        }
+                        print!("{}", running.render(&output));
+                    }
+                    
+                    if !status.recent.is_empty() {
+                        println!();
+                        let mut recent = Table::new(&["ID", "KIND", "TASK", "DURATION", "RESULT"]);
+                        for task in &status.recent {
+                            recent.row([
+                                task.id.to_string(),
+                                task.kind.clone(),
+                                task.name.clone(),
+                                format!("{:.1}s", task.elapsed_secs),
+                                task.error.clone().unwrap_or_else(|| "ok".to_string()),
+                            ]);
+                        }
+                        print!("{}", recent.render(&output));
+                    }
+                }
+            }
+            Ok(())
+        }
+        Commands::Config { command } => {
+            match command {
+                ConfigCommands::Export { output: path } => {
+                    let hash = crate::config::export(path)?;
+                    println!("Configuration exported to {}", path.display());
+                    println!("Bundle hash: {}", hash);
+                }
+                ConfigCommands::Import { bundle, sections, yes } => {
+                    let (bundle, hash) = crate::config::load(bundle)?;
+                    println!("Bundle from node {} (hash {})", bundle.node_id, hash);
+                    
+                    let diffs = crate::config::diff(&bundle, sections)?;
+                    let mut table = Table::new(&["SECTION", "FILE", "CHANGE", "KEYS"]);
+                    for diff in &diffs {
+                        table.row([
+                            diff.section.clone(),
+                            diff.path.clone(),
+                            format!("{:?}", diff.change).to_lowercase(),
+                            diff.changed_keys.join(", "),
+                        ]);
+                    }
+                    print!("{}", table.render(&output));
+                    
+                    if diffs.iter().all(|d| d.change == crate::config::FileChange::Unchanged) {
+                        println!("Configuration already matches the bundle");
+                        return Ok(());
+                    }
+                    
+                    if !*yes {
+                        print!("Apply these changes? [y/N] ");
+                        std::io::Write::flush(&mut std::io::stdout())?;
+                        let mut answer = String::new();
+                        std::io::stdin().read_line(&mut answer)?;
+                        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+                            println!("Import cancelled");
+                            return Ok(());
+                        }
+                    }
+                    
+                    let applied = crate::config::import(&bundle, &hash, sections)?;
+                    println!("Imported sections: {}", applied.join(", "));
+                }
+            }
+            Ok(())
+        }
+        Commands::Policy { command } => {
+            match command {
+                PolicyCommands::Exec { command } => match command {
+                    ExecPolicyCommands::Allow { target, note, expires } => {
+                        let expires_at = expires.as_deref().map(crate::matrixbox::policy::parse_expiry).transpose()?;
+                        let entry = crate::matrixbox::policy::allow(target, note, expires_at)?;
+                        println!("Allowed module {}", entry.hash);
+                        if !crate::matrixbox::policy::is_enforced() {
+                            println!("Note: the allow-list is not enforced; set exec_policy.enforce in security.json");
+                        }
+                    }
+                    ExecPolicyCommands::Ls {} => {
+                        println!("Enforced: {}", if crate::matrixbox::policy::is_enforced() { "yes" } else { "no" });
+                        let mut table = Table::new(&["HASH", "NOTE", "ADDED BY", "EXPIRES"]);
+                        for entry in crate::matrixbox::policy::list()? {
+                            let expires = match entry.expires_at {
+                                Some(t) => {
+                                    let date = chrono::DateTime::from_timestamp(t as i64, 0)
+                                        .map(|d| d.format("%Y-%m-%d").to_string())
+                                        .unwrap_or_else(|| t.to_string());
+                                    if entry.is_expired() { format!("{} (expired)", date) } else { date }
+                                }
+                                None => "never".to_string(),
+                            };
+                            table.row([entry.hash.clone(), entry.note.clone(), entry.added_by.clone(), expires]);
+                        }
+                        table.print(&output)?;
+                    }
+                    ExecPolicyCommands::Revoke { hash } => {
+                        crate::matrixbox::policy::revoke(hash)?;
+                        println!("Revoked module {}", hash);
+                    }
+                },
+            }
+            Ok(())
+        }
+        Commands::Status {} => {
+            let status = crate::status::collect();
+            if cli.json {
+                println!("{}", serde_json::to_string_pretty(&status)?);
+                return Ok(());
+            }
+            
+            let mut table = Table::new(&["SUBSYSTEM", "STATUS", "DETAIL"]);
+            for subsystem in &status.subsystems {
+                table.row([subsystem.name.clone(), subsystem.state.as_str().to_string(), subsystem.detail.clone()]);
+            }
+            table.print(&output)?;
+            Ok(())
+        }
+        Commands::Doctor {} => {
+            let checks = crate::doctor::run();
+            for check in &checks {
+                let mark = match check.status {
+                    crate::doctor::CheckStatus::Ok => "ok",
+                    crate::doctor::CheckStatus::Warning => "WARN",
+                    crate::doctor::CheckStatus::Error => "FAIL",
+                };
+                println!("[{:>4}] {}: {}", mark, check.name, check.detail);
+            }
+            
+            let failed = checks.iter().filter(|c| c.status == crate::doctor::CheckStatus::Error).count();
+            if failed > 0 {
+                anyhow::bail!("{} check(s) failed", failed);
+            }
+            Ok(())
+        }
+        Commands::Identity { command } => {
+            match command {
+                IdentityCommands::Show {} => {
+                    info!("Showing node identity");
+                    let identity = crate::core::identity::current()?;
+                    println!("ID: {}", identity.id);
+                    println!("Name: {}", identity.name);
+                    println!("Created: {}", identity.created_at);
+                    println!("Public key: {}", identity.public_key);
+                    println!("Key rotations: {}", identity.rotations.len());
+                    if !identity.legacy_ids.is_empty() {
+                        println!("Replaced IDs: {}", identity.legacy_ids.join(", "));
+                    }
+                }
+                IdentityCommands::Rename { name } => {
+                    info!("Renaming node to: {}", name);
+                    let identity = crate::core::identity::rename(name)?;
+                    println!("Node {} renamed to {}", identity.id, identity.name);
+                }
+                IdentityCommands::RotateKeys {} => {
+                    info!("Rotating node signing key");
+                    let identity = crate::core::identity::rotate_keys()?;
+                    println!("New public key: {}", identity.public_key);
+                }
+            }
+            Ok(())
+        }
+        Commands::Fleet { command } => {
+            match command {
+                FleetCommands::Advisories { id } => {
+                    info!("Showing fleet status for advisory: {}", id);
+                    let entries = store::advisory::fleet_status(id)?;
+                    println!("{:<18} {:<20} {:<12} {:<9} {:<13} {}", "NODE", "NAME", "VERSION", "AFFECTED", "STATUS", "REPORTED");
+                    for entry in entries {
+                        println!("{:<18} {:<20} {:<12} {:<9} {:<13} {}",
+                            entry.node_id,
+                            entry.name.unwrap_or_else(|| "-".to_string()),
+                            entry.installed_version.unwrap_or_else(|| "-".to_string()),
+                            if entry.affected { "yes" } else { "no" },
+                            format!("{:?}", entry.status).to_lowercase(),
+                            entry.reported_at);
+                    }
+                }
+                FleetCommands::Attestation {} => {
+                    println!("{:<18} {:<20} {:<8} {:<11} {:>9} {}", "NODE", "NAME", "TRUSTED", "LAST", "AGE", "REASON");
+                    for peer in crate::gossip::list_peers()? {
+                        let latest = crate::gossip::attest::latest(&peer.id)?;
+                        println!("{:<18} {:<20} {:<8} {:<11} {:>9} {}",
+                            peer.id,
+                            peer.display_name.unwrap_or_else(|| "-".to_string()),
+                            if crate::gossip::is_trusted_peer(&peer.id) { "yes" } else { "no" },
+                            latest.as_ref().map_or("never".to_string(), |r| format!("{:?}", r.outcome).to_lowercase()),
+                            latest.as_ref().map_or("-".to_string(), |r| format!("{}s", r.age_secs())),
+                            latest.and_then(|r| r.reason).unwrap_or_default());
+                    }
+                }
+                FleetCommands::Status { refresh } => {
+                    if let Some(peer) = refresh {
+                        crate::gossip::report::request_report(peer)?;
+                    }
+                    
+                    let summary = crate::gossip::fleet_summary()?;
+                    let mut table = Table::new(&["NODE", "NAME", "HEALTH", "VERSION", "PACKAGES", "CONTAINERS", "BOOT", "AGE"]);
+                    for entry in &summary.entries {
+                        let name = entry.peer.display_name.clone().unwrap_or_else(|| "-".to_string());
+                        match &entry.report {
+                            Some(report) => {
+                                let r = &report.report;
+                                table.row([
+                                    entry.peer.id.clone(),
+                                    name,
+                                    r.health.clone(),
+                                    r.version.clone(),
+                                    format!("{} ({})", r.package_count, &r.package_digest[..8.min(r.package_digest.len())]),
+                                    r.container_count.to_string(),
+                                    r.boot_record_hash.as_deref().map_or("-".to_string(), |h| h[..8.min(h.len())].to_string()),
+                                    format!("{}s{}", report.age_secs(), if entry.stale { " STALE" } else { "" }),
+                                ]);
+                            }
+                            None => {
+                                table.row([entry.peer.id.clone(), name, "-".to_string(), "-".to_string(), "-".to_string(),
+                                           "-".to_string(), "-".to_string(), "never".to_string()]);
+                            }
+                        }
+                    }
+                    table.print(&output)?;
+                    println!("{} fresh, {} stale, {} never reported, {} unhealthy",
+                             summary.fresh, summary.stale, summary.missing, summary.unhealthy);
+                }
+            }
+            Ok(())
+        }
+        Commands::Gossip { command } => {
+            match command {
+                GossipCommands::Enable {} => {
+                    info!("Enabling gossip trace sync");
+                    crate::gossip::enable_sync()?;
+                }
+                GossipCommands::Pull { peer } => {
+                    info!("Pulling runtime trace");
+                    let served_by = crate::gossip::verify::pull_from_peer(peer.as_deref())?;
+                    println!("Pulled runtime trace from {}", served_by);
+                }
+                GossipCommands::VerifyTrace {} => {
+                    info!("Cross-validating trace integrity with peers");
+                    crate::gossip::verify_trace()?;
+                }
+                GossipCommands::Status {} => {
+                    let mut table = Table::new(&["ID", "NAME", "ENDPOINT", "STATUS", "TRUSTED", "LAST SEEN",
+                                                 "RTT", "THROUGHPUT", "OK/FAILED"]);
+                    for peer in crate::gossip::list_peers()? {
+                        let trusted = crate::gossip::is_trusted_peer(&peer.id);
+                        table.row([
+                            peer.id,
+                            peer.display_name.unwrap_or_else(|| "-".to_string()),
+                            peer.endpoint,
+                            format!("{:?}", peer.status).to_lowercase(),
+                            if trusted { "yes" } else { "no" }.to_string(),
+                            peer.last_seen.to_string(),
+                            peer.stats.rtt_ms.map_or("-".to_string(), |ms| format!("{:.1}ms", ms)),
+                            peer.stats.throughput_bps.map_or("-".to_string(), |bps| format!("{:.0}KB/s", bps / 1024.0)),
+                            format!("{}/{}", peer.stats.successes, peer.stats.failures),
+                        ]);
+                    }
+                    table.print(&output)?;
+                }
+                GossipCommands::Attest { peer, history, limit } => {
+                    use crate::gossip::attest;
+                    let records = if *history {
+                        attest::history(peer, *limit)?
+                    } else {
+                        info!("Attesting peer: {}", peer);
+                        vec![attest::attest(peer)?]
+                    };
+                    
+                    if records.is_empty() {
+                        println!("No attestations recorded for {}", peer);
+                    }
+                    for record in records {
+                        println!("{}  {:<11} {}s ago  boot {}  trace {}{}",
+                                 record.recorded_at,
+                                 format!("{:?}", record.outcome).to_lowercase(),
+                                 record.age_secs(),
+                                 record.boot_record_hash.as_deref().map_or("-", |h| &h[..h.len().min(12)]),
+                                 record.trace_head.as_deref().map_or("-", |h| &h[..h.len().min(12)]),
+                                 record.reason.map(|r| format!("  ({})", r)).unwrap_or_default());
+                    }
+                }
+                GossipCommands::Conflicts { command: ConflictCommands::Ls {} } => {
+                    let conflicts = crate::gossip::conflict::list_conflicts()?;
+                    if conflicts.is_empty() {
+                        println!("No unresolved conflicts");
+                    }
+                    for conflict in conflicts {
+                        println!("{}  {}/{}  from {}  local {}  remote {}",
+                                 conflict.id, conflict.component, conflict.key, conflict.peer_id,
+                                 &conflict.local_hash[..12], &conflict.remote_hash[..12]);
+                    }
+                }
+                GossipCommands::Conflicts { command: ConflictCommands::Resolve { id, take, path } } => {
+                    use crate::gossip::conflict::Take;
+                    let take = match (take.as_str(), path) {
+                        ("local", None) => Take::Local,
+                        ("remote", None) => Take::Remote,
+                        ("merged-file", Some(path)) => Take::MergedFile(path.clone()),
+                        ("merged-file", None) => anyhow::bail!("--take merged-file requires a file path"),
+                        ("local", Some(_)) | ("remote", Some(_)) => anyhow::bail!("A file path is only used with --take merged-file"),
+                        (other, _) => anyhow::bail!("Unknown --take value: {} (use local, remote or merged-file)", other),
+                    };
+                    
+                    let outcome = crate::gossip::conflict::resolve(id, take)?;
+                    println!("Conflict {} resolved: kept {} ({})", id, outcome.outcome, &outcome.content_hash[..12]);
+                }
+                GossipCommands::Trust { peer } => {
+                    let key = crate::gossip::trust_gossip_key(peer)?;
+                    println!("Peer {} is now trusted with gossip key {}", peer, &key[..16]);
+                }
+            }
+            Ok(())
+        }
+        Commands::Intent { command } => {
+            match command {
+                IntentCommands::Record {} => {
+                    info!("Starting intent recording session");
+                    crate::intent::start_recording()?;
+                }
+                IntentCommands::Stop {} => {
+                    info!("Stopping intent recording session");
+                    crate::intent::stop_recording()?;
+                }
+                IntentCommands::Replay { session, execute, check } => {
+                    info!("Replaying intent session: {}", session);
+                    let options = crate::intent::ReplayOptions {
+                        execute: *execute || *check,
+                        check: *check,
+                    };
+                    
+                    if let Some(report) = crate::intent::replay_session_with(session, &options)? {
+                        println!("{}", report);
+                        if report.has_divergence() {
+                            anyhow::bail!("Replay of session {} diverged in {} event(s)", session, report.diverged_count());
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+        Commands::Purge { keep_data, export } => {
+            let artifacts = crate::purge::inventory(*keep_data)?;
+            if artifacts.is_empty() {
+                println!("Nothing to purge");
+                return Ok(());
+            }
+            
+            let mut table = Table::new(&["CATEGORY", "ITEM", "BYTES", "PATH"]);
+            for artifact in &artifacts {
+                table.row([
+                    artifact.category.name().to_string(),
+                    artifact.label.clone(),
+                    artifact.bytes.to_string(),
+                    artifact.path.as_ref().map(|p| p.display().to_string()).unwrap_or_else(|| "-".to_string()),
+                ]);
+            }
+            table.print(&output)?;
+            
+            let mut categories: Vec<crate::purge::Category> = artifacts.iter().map(|a| a.category).collect();
+            categories.dedup();
+            for category in categories {
+                let (count, bytes) = artifacts.iter()
+                    .filter(|a| a.category == category)
+                    .fold((0, 0), |(count, bytes), a| (count + 1, bytes + a.bytes));
+                println!("{:<16} {} item(s), {} bytes", category.name(), count, bytes);
+            }
+            if *keep_data {
+                println!("The root directory {} is kept", crate::core::constants::root_dir().display());
+            }
+            
+            // A stray -y must not wipe a machine; the phrase has to be typed
+            print!("Type '{}' to remove everything listed: ", crate::purge::CONFIRM_PHRASE);
+            std::io::Write::flush(&mut std::io::stdout())?;
+            let mut answer = String::new();
+            std::io::stdin().read_line(&mut answer)?;
+            if answer.trim() != crate::purge::CONFIRM_PHRASE {
+                println!("Purge cancelled");
+                return Ok(());
+            }
+            
+            if let Some(dir) = export {
+                crate::purge::export_final(dir)?;
+                println!("Exported final snapshot and config bundle to {}", dir.display());
+            }
+            
+            let report = crate::purge::execute(&artifacts);
+            println!("Removed {} of {} item(s)", report.removed, artifacts.len());
+            if !report.failed.is_empty() {
+                for (artifact, error) in &report.failed {
+                    println!("  failed: {} {}: {}", artifact.category.name(), artifact.label, error);
+                }
+                anyhow::bail!("Purge incomplete; fix the failures above and run `sentctl purge` again");
+            }
+            Ok(())
+        }
+        Commands::SelfUpdate { binary, check_only, max_duration } => {
+            use crate::update::Decision;
+            
+            let (report, decision) = crate::update::check_build(binary, false)?;
+            print_migration_report(&report, &output)?;
+            
+            if *check_only {
+                if !report.is_clean() {
+                    anyhow::bail!("Migration check of {} is not clean (would {:?})", binary.display(), decision);
+                }
+                println!("Migration check is clean");
+                return Ok(());
+            }
+            
+            match decision {
+                Decision::Abort => anyhow::bail!("Update aborted by policy"),
+                Decision::Defer => {
+                    crate::update::stage(binary)?;
+                    println!("Update staged and deferred to a maintenance window; apply it with \
+                              `sentctl maintenance enter --run self-update`");
+                }
+                Decision::Proceed => {
+                    crate::update::stage(binary)?;
+                    let result = crate::maintenance::run(crate::maintenance::MaintenanceOperation::SelfUpdate,
+                                                         crate::trash::parse_age(max_duration)?);
+                    if let Err(e) = result {
+                        crate::update::unstage()?;
+                        return Err(e.context("Self-update failed"));
+                    }
+                    println!("Update applied; restart SentientOS to run the new build");
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Print what a build's migration check found
+fn print_migration_report(report: &crate::update::migrate::MigrationReport, output: &OutputOptions) -> Result<()> {
+    println!("Build {} checked {} document(s) in {}ms", report.version, report.documents, report.check_ms);
+    if report.migrations.is_empty() {
+        println!("No migrations to run");
+    } else {
+        let mut table = Table::new(&["MIGRATION", "DOCUMENTS", "REVERSIBLE", "ESTIMATE", "DESCRIPTION"]);
+        for migration in &report.migrations {
+            table.row([
+                migration.id.clone(),
+                migration.documents.len().to_string(),
+                if migration.reversible { "yes" } else { "no" }.to_string(),
+                format!("{}ms", migration.estimated_ms),
+                migration.description.clone(),
+            ]);
+        }
+        table.print(output)?;
+    }
+    for failure in &report.failures {
+        println!("  failing document {}: {}", failure.path.display(), failure.error);
+    }
+    Ok(())
+}
+
+/// Print contract validation issues as `file:line` diagnostics
+fn print_validation_issues(path: &str, issues: &[zk::parser::ValidationIssue], output: &OutputOptions) -> Result<()> {
+    let mut table = Table::new(&["LOCATION", "SEVERITY", "FIELD", "MESSAGE"]);
+    for issue in issues {
+        let location = match issue.line {
+            Some(line) => format!("{}:{}", path, line),
+            None => path.to_string(),
+        };
+        let severity = match issue.severity {
+            zk::parser::Severity::Error => "error",
+            zk::parser::Severity::Warning => "warning",
+        };
+        let field = if issue.path.is_empty() { "-".to_string() } else { issue.path.clone() };
+        table.row([location, severity.to_string(), field, issue.message.clone()]);
+    }
+    table.print(output)
+}
+
+/// Print dependency tree nodes with box-drawing guides, highlighting unpinned picks
+fn print_dependency_tree(nodes: &[zk::resolver::DependencyNode], prefix: &str) {
+    use colored::Colorize;
+    
+    for (i, node) in nodes.iter().enumerate() {
+        let last = i == nodes.len() - 1;
+        let pin = format!("{}@{}", node.name, node.version);
+        if node.ambiguous.is_empty() {
+            println!("{}{} {}", prefix, if last { "└──" } else { "├──" }, pin);
+        } else {
+            println!("{}{} {} {}", prefix, if last { "└──" } else { "├──" }, pin.yellow().bold(),
+                     format!("(requested {}, available: {})", node.requirement, node.ambiguous.join(", ")).yellow());
+        }
+        
+        let child_prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+        print_dependency_tree(&node.children, &child_prefix);
+    }
+}
+
+/// Print a line of container output, stderr lines marked
+fn print_log_line(line: &matrixbox::LogLine) {
+    match line.stream {
+        matrixbox::output::LogStream::Stdout => println!("{} {}", line.timestamp, line.line),
+        matrixbox::output::LogStream::Stderr => println!("{} [stderr] {}", line.timestamp, line.line),
+    }
+}
+
+/// Resource limits of a container in one table cell
+fn format_limits(limits: Option<&matrixbox::ResourceLimits>) -> String {
+    let limits = match limits {
+        Some(limits) => limits,
+        None => return "-".to_string(),
+    };
+    
+    let mut parts = vec![
+        format!("mem={}MiB", limits.max_memory_bytes / (1024 * 1024)),
+        format!("cpu={}", limits.max_cpu_fraction),
+        format!("pids={}", limits.max_pids),
+    ];
+    if let Some(fuel) = limits.max_fuel {
+        parts.push(format!("fuel={}", fuel));
+    }
+    if let Some(seconds) = limits.max_exec_seconds {
+        parts.push(format!("time={}s", seconds));
+    }
+    parts.join(" ")
+}
+
+/// Print a simulation transcript with its state diff
+fn print_simulation(simulation: &zk::simulate::Simulation) {
+    let args: Vec<String> = simulation.args.iter().map(|a| a.to_string()).collect();
+    println!("Simulated {}.{}({}) against state {}",
+             simulation.contract, simulation.method, args.join(", "), simulation.pre_state_hash);
+    println!("  would return {}", simulation.value);
+    println!("  cost: {} steps, {} state bytes", simulation.cost.steps, simulation.cost.state_bytes);
+    
+    if simulation.changes.is_empty() {
+        println!("  no state changes");
+    }
+    for change in &simulation.changes {
+        let show = |value: &Option<serde_json::Value>| value.as_ref().map_or("(unset)".to_string(), |v| v.to_string());
+        println!("  {}: {} -> {}", change.key, show(&change.before), show(&change.after));
+    }
+    
+    for rule in &simulation.rules {
+        println!("  rule {}: {}", rule.rule, if rule.passed { "passed" } else { "FAILED" });
+    }
+    if simulation.rules_failed() {
+        println!("  the real call would fail rule verification");
+    }
+}
+
+/// Print how many panics of each kind a crash report holds, as a bar chart
+pub fn print_panic_histogram(histogram: &std::collections::BTreeMap<String, usize>) {
+    const BAR_WIDTH: usize = 40;
+    
+    if histogram.is_empty() {
+        println!("No panics recorded");
+        return;
+    }
+    let widest = histogram.keys().map(String::len).max().unwrap_or(0);
+    let most = histogram.values().copied().max().unwrap_or(1).max(1);
+    for (kind, count) in histogram {
+        let bar = (count * BAR_WIDTH).div_ceil(most);
+        println!("{:<width$}  {:>5}  {}", kind, count, "#".repeat(bar), width = widest);
+    }
+}
+
+/// Print the last panic and whether recovery is cooling down from a panic storm
+pub fn print_panic_status(status: Option<&crate::panic::PanicStatus>) {
+    let Some(status) = status else {
+        println!("No panics recorded");
+        return;
+    };
+    
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    println!("Panic: {} ({}, {})", status.reason,
+             if status.active { "active" } else { "recovered" },
+             table::format_age(now.saturating_sub(status.timestamp)));
+    if let Some(severity) = status.severity {
+        println!("Severity: {}", severity);
+    }
+    println!("Recovery attempted: {}", if status.recovery_attempted { "yes" } else { "no" });
+    if status.in_cooldown {
+        let until = chrono::DateTime::from_timestamp(status.cooldown_until as i64, 0)
+            .map(|t| t.to_rfc3339())
+            .unwrap_or_else(|| status.cooldown_until.to_string());
+        println!("Cooldown: recovery suspended after a panic storm until {} ({}s left)",
+                 until, status.cooldown_until.saturating_sub(now));
+    } else {
+        println!("Cooldown: none");
+    }
+}
+
+/// Print the banner marking output as read from a snapshot
+fn print_snapshot_banner(snapshot_id: &str) -> Result<()> {
+    match crate::heal::snapshot::get_snapshot(snapshot_id)? {
+        Some(snapshot) => {
+            let taken = chrono::DateTime::from_timestamp(snapshot.timestamp as i64, 0)
+                .map(|t| t.to_rfc3339())
+                .unwrap_or_else(|| snapshot.timestamp.to_string());
+            println!("=== Historical view: snapshot {} taken {} ({}) ===", snapshot.id, taken, snapshot.reason);
+        }
+        None => println!("=== Historical view: snapshot {} ===", snapshot_id),
+    }
+    Ok(())
+}
+
+/// Build the change plan of a destructive command without executing it
+///
+/// Returns `None` for commands that do not support planning.
+fn plan_command(command: &Commands) -> Result<Option<Plan>> {
+    let plan = match command {
+        Commands::Rollback { target } => crate::heal::plan_rollback(target)?,
+        Commands::MatrixBox { command: MatrixBoxCommands::Rm { id, purge } } => {
+            matrixbox::plan_remove_container(id, *purge)?
+        }
+        Commands::Store { command: StoreCommands::Remove { name, purge, force } } => {
+            store::plan_remove_package(name, *purge, *force)?
+        }
+        Commands::Trash { command: TrashCommands::Empty { older_than } } => {
+            let age = older_than.as_deref().map(crate::trash::parse_age).transpose()?;
+            crate::trash::plan_empty(age)?
+        }
+        Commands::Purge { keep_data, .. } => crate::purge::plan(*keep_data)?,
+        Commands::Heal { command: HealCommands::Prune { keep, max_age_days, min_keep } } => {
+            crate::heal::snapshot::plan_prune(&prune_policy(*keep, *max_age_days, *min_keep)?)?
+        }
+        Commands::Heal { command: HealCommands::Rollback { component, snapshot } } => {
+            crate::heal::plan_component_rollback(component, snapshot)?
+        }
+        _ => return Ok(None),
+    };
+    
+    Ok(Some(plan))
+}
+
+/// Snapshot retention policy for `heal prune`; flags override the configured policy
+fn prune_policy(keep: Option<usize>, max_age_days: Option<u64>, min_keep: Option<usize>) -> Result<crate::heal::snapshot::RetentionPolicy> {
+    let mut policy = crate::heal::snapshot::load_retention_policy()?;
+    policy.max_count = keep.or(policy.max_count);
+    policy.max_age_days = max_age_days.or(policy.max_age_days);
+    policy.min_keep = min_keep.unwrap_or(policy.min_keep);
+    if !policy.is_limited() {
+        anyhow::bail!("No retention limits given; pass --keep or --max-age-days, or configure snapshot_retention");
+    }
+    Ok(policy)
+}
+
+/// CLI command definition using clap
 #[derive(Parser)]
 #[clap(name = "sentctl")]
 #[clap(about = "SentientOS Command Line Interface", long_about = None)]
 struct Cli {
+    /// Show what a destructive command would change without changing anything
+    #[clap(long, global = true)]
+    dry_run: bool,
+    
+    /// Print dry-run plans and status as JSON
+    #[clap(long, global = true)]
+    json: bool,
+    
+    /// Do not truncate table columns to the terminal width
+    #[clap(long, global = true)]
+    wide: bool,
+    
+    /// Print tables as tab-separated rows without headers
+    #[clap(long, global = true)]
+    plain: bool,
+    
+    /// Do not page long output
+    #[clap(long, global = true)]
+    no_pager: bool,
+    
     #[clap(subcommand)]
     command: Commands,
 }
@@ -246,7 +1997,29 @@ enum Commands {
     },
     
     /// Verify full ZK proof chains across system
-    ZkVerify {},
+    ZkVerify {
+        /// Re-generate the stored proofs of verified contracts
+        #[clap(long)]
+        refresh: bool,
+    },
+    
+    /// ZK contract tooling
+    Zk {
+        #[clap(subcommand)]
+        command: ZkCommands,
+    },
+    
+    /// Installed package inspection
+    Package {
+        #[clap(subcommand)]
+        command: PackageCommands,
+    },
+    
+    /// System configuration inspection
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommands,
+    },
     
     /// Rollback to previous system state
     Rollback {
@@ -260,88 +2033,595 @@ enum Commands {
         /// Output path for the image
         #[clap(default_value = "sentientos.iso")]
         output: String,
+        
+        /// Target architecture, instead of the configured one
+        #[clap(long)]
+        arch: Option<String>,
+        
+        /// Boot mode of the image: normal, recovery or zero
+        #[clap(long)]
+        mode: Option<String>,
+    },
+    
+    /// Boot into system (normally not called directly)
+    Boot {
+        /// Boot into minimal zero-mode runtime
+        #[clap(long)]
+        zero: bool,
+    },
+    
+    /// Execute container inside MatrixBox runtime
+    TsoRun {
+        /// Path to the TSO container
+        container_path: String,
+        
+        /// Arguments passed to the container's entrypoint, after `--`
+        #[clap(last = true)]
+        args: Vec<String>,
+    },
+    
+    /// MatrixBox container operations
+    MatrixBox {
+        #[clap(subcommand)]
+        command: MatrixBoxCommands,
+    },
+    
+    /// Contract management
+    Contract {
+        #[clap(subcommand)]
+        command: ContractCommands,
+    },
+    
+    /// Log shipping
+    Logs {
+        #[clap(subcommand)]
+        command: LogsCommands,
+    },
+    
+    /// Metrics history and anomaly findings
+    Metrics {
+        #[clap(subcommand)]
+        command: MetricsCommands,
+    },
+    
+    /// Quiesce containers for snapshots and upgrades
+    Maintenance {
+        #[clap(subcommand)]
+        command: MaintenanceCommands,
+    },
+    
+    /// Removed packages and containers awaiting purge
+    Trash {
+        #[clap(subcommand)]
+        command: TrashCommands,
+    },
+    
+    /// Scaffold new projects
+    New {
+        #[clap(subcommand)]
+        command: NewCommands,
+    },
+    
+    /// Build a MatrixBox app for the WASM target and pack it into a .tso
+    Build {
+        /// App project directory
+        #[clap(long, default_value = ".")]
+        path: String,
+        
+        /// Output archive path (default: <path>/<name>.tso)
+        #[clap(long)]
+        output: Option<String>,
+    },
+    
+    /// Run the benchmark suite
+    Bench {
+        /// Only run benchmarks whose name contains this
+        #[clap(long)]
+        filter: Option<String>,
+        
+        /// Where to write the JSON results
+        #[clap(long, default_value = "bench-results.json")]
+        output: String,
+        
+        /// Baseline results to compare against
+        #[clap(long)]
+        compare: Option<String>,
+        
+        /// Allowed slowdown in percent (default from system.json, else 10)
+        #[clap(long)]
+        threshold: Option<f64>,
+        
+        /// List benchmark names and exit
+        #[clap(long)]
+        list: bool,
+    },
+    
+    /// First-run setup wizard; re-run to reconfigure single sections
+    Setup {
+        /// Accept every default without asking
+        #[clap(long)]
+        defaults: bool,
+        
+        /// Replay an answers file written by a previous setup
+        #[clap(long)]
+        answers: Option<PathBuf>,
+    },
+    
+    /// Show the state of each subsystem
+    Status {},
+    
+    /// Check the installation for problems
+    Doctor {},
+    
+    /// Runtime inspection
+    Runtime {
+        #[clap(subcommand)]
+        command: RuntimeCommands,
+    },
+    
+    /// Export and import node configuration bundles
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommands,
+    },
+    
+    /// Security policy management
+    Policy {
+        #[clap(subcommand)]
+        command: PolicyCommands,
+    },
+    
+    /// Node identity management
+    Identity {
+        #[clap(subcommand)]
+        command: IdentityCommands,
+    },
+    
+    /// Resource lock management
+    Lock {
+        #[clap(subcommand)]
+        command: LockCommands,
+    },
+    
+    /// Healing and recovery commands
+    Heal {
+        #[clap(subcommand)]
+        command: HealCommands,
+    },
+    
+    /// Panic recovery system
+    Panic {
+        #[clap(subcommand)]
+        command: PanicCommands,
+    },
+    
+    /// Fleet-wide views built from gossiped peer data
+    Fleet {
+        #[clap(subcommand)]
+        command: FleetCommands,
+    },
+    
+    /// Multi-device sync and gossip
+    Gossip {
+        #[clap(subcommand)]
+        command: GossipCommands,
+    },
+    
+    /// Developer intent recording and replay
+    Intent {
+        #[clap(subcommand)]
+        command: IntentCommands,
+    },
+    
+    /// Linux compatibility layer commands
+    Linux {
+        #[clap(subcommand)]
+        command: linux::LinuxCommands,
+    },
+    
+    /// ZK-Store package manager commands
+    Store {
+        #[clap(subcommand)]
+        command: StoreCommands,
+    },
+    
+    /// Remove SentientOS and everything it created from this machine
+    Purge {
+        /// Remove binaries, shims and desktop entries but keep the root directory
+        #[clap(long)]
+        keep_data: bool,
+        
+        /// Export a final snapshot and config bundle to this directory first
+        #[clap(long)]
+        export: Option<PathBuf>,
+    },
+    
+    /// Update this node to a new build after previewing its data migrations
+    SelfUpdate {
+        /// New sentient_os binary
+        binary: PathBuf,
+        
+        /// Only run the migration check; fail unless it is clean
+        #[clap(long)]
+        check_only: bool,
+        
+        /// Maximum duration of the maintenance window the update runs in
+        #[clap(long, default_value = "15m")]
+        max_duration: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum PackageCommands {
+    /// List installed packages
+    List {
+        /// List the packages recorded in a snapshot instead
+        #[clap(long)]
+        at: Option<String>,
+        
+        /// List package ecosystems and whether their tools are available instead
+        #[clap(long, conflicts_with = "at")]
+        ecosystems: bool,
+    },
+    
+    /// Reinstall a package at its latest version, or every outdated package
+    Update {
+        /// Package name; all installed packages when omitted
+        name: Option<String>,
+        
+        /// Show what changes and ask before updating (ZK-Store packages only)
+        #[clap(long)]
+        show_diff: bool,
+        
+        /// Only update packages of this ecosystem (native, linux, npm, python, java, rust, go)
+        #[clap(long)]
+        ecosystem: Option<String>,
+    },
+    
+    /// Use an ecosystem tool (npm, node, pip, ...) from a custom path
+    ToolPath {
+        /// Tool name
+        tool: String,
+        
+        /// Path of the executable
+        #[clap(required_unless_present = "unset")]
+        path: Option<PathBuf>,
+        
+        /// Search PATH for the tool again
+        #[clap(long, conflicts_with = "path")]
+        unset: bool,
+    },
+    
+    /// Report installed package licenses against .config/licenses.json
+    Licenses {
+        /// Print the report as JSON
+        #[clap(long)]
+        json: bool,
+        
+        /// Exit with an error if any package violates the license policy
+        #[clap(long)]
+        fail_on_violation: bool,
+    },
+    
+    /// Registry mirrors configured in .package/config.json
+    Mirror {
+        #[clap(subcommand)]
+        command: MirrorCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum MirrorCommands {
+    /// Check that an ecosystem's mirror is reachable and answers
+    Test {
+        /// Ecosystem (npm, python, rust, go)
+        ecosystem: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Print a system.json value by dotted key, e.g. subsystems.heal.enabled
+    Get {
+        /// Dotted config key
+        key: String,
+        
+        /// Read the value from a snapshot instead
+        #[clap(long)]
+        at: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum MatrixBoxCommands {
+    /// List all running MatrixBox containers
+    Ls {
+        /// List the containers registered in a snapshot instead
+        #[clap(long)]
+        at: Option<String>,
+        
+        /// Show data sync status with paired peers
+        #[clap(long, conflicts_with = "at")]
+        sync: bool,
+    },
+    
+    /// Remove container from MatrixBox registry
+    Rm {
+        /// Container ID to remove
+        id: String,
+        
+        /// Delete immediately instead of moving to the trash
+        #[clap(long)]
+        purge: bool,
+    },
+    
+    /// Pause a running container after its current host call completes
+    Pause {
+        /// Container ID to pause
+        id: String,
+    },
+    
+    /// Resume a paused container
+    Resume {
+        /// Container ID to resume
+        id: String,
+    },
+    
+    /// Sync a stopped or paused container's data with a paired peer
+    Sync {
+        /// Container ID
+        id: String,
+        
+        /// Peer to sync with instead of the best paired one
+        #[clap(long, alias = "with")]
+        peer: Option<String>,
+    },
+    
+    /// Start or stop a set of containers from a compose file
+    Compose {
+        #[clap(subcommand)]
+        command: ComposeCommands,
+    },
+    
+    /// Inspect or edit a container's key-value store
+    Kv {
+        /// Container ID
+        id: String,
+        
+        #[clap(subcommand)]
+        command: KvCommands,
+    },
+    
+    /// List files a container added (A), modified (M) or deleted (D)
+    Diff {
+        /// Container ID
+        id: String,
+    },
+    
+    /// Show the host ports forwarded to a container
+    Ports {
+        /// Container ID
+        id: String,
+    },
+    
+    /// Pack a container directory into a distributable .tso archive
+    Pack {
+        /// Container directory, with meta.yaml, main.wasm and permissions.zky
+        dir: PathBuf,
+        
+        /// Archive to write
+        #[clap(short, long)]
+        output: PathBuf,
+        
+        /// Container name; defaults to the directory name
+        #[clap(long)]
+        name: Option<String>,
+        
+        /// Container version; defaults to 1.0.0
+        #[clap(long)]
+        version: Option<String>,
+    },
+    
+    /// Sign a container image with this node's image key
+    Sign {
+        /// Image directory
+        image: PathBuf,
+    },
+    
+    /// Check that a container image is signed by a trusted key
+    VerifyImage {
+        /// Image directory
+        image: PathBuf,
+    },
+    
+    /// Show a container's stdout and stderr
+    Logs {
+        /// Container ID
+        id: String,
+        
+        /// Keep printing new lines as they are written
+        #[clap(long, short = 'f')]
+        follow: bool,
+        
+        /// Only show this many of the most recent lines
+        #[clap(long)]
+        tail: Option<usize>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ComposeCommands {
+    /// Start the services of a compose file in dependency order
+    Up {
+        /// Compose file
+        #[clap(default_value = "sentient-compose.yaml")]
+        file: PathBuf,
+    },
+    
+    /// Stop and remove the services that are up, in reverse order
+    Down,
+}
+
+#[derive(Subcommand)]
+enum KvCommands {
+    /// Print the value of a key
+    Get {
+        /// Key
+        key: String,
+    },
+    
+    /// Set a key
+    Put {
+        /// Key
+        key: String,
+        
+        /// Value
+        value: String,
+        
+        /// Expire the key after this many milliseconds
+        #[clap(long)]
+        ttl_ms: Option<u64>,
+    },
+    
+    /// List keys, with the store's usage and digest
+    Ls {
+        /// Only list keys starting with this prefix
+        #[clap(long)]
+        prefix: Option<String>,
+    },
+    
+    /// Delete a key
+    Del {
+        /// Key
+        key: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ZkCommands {
+    /// Print the resolved import tree of a contract
+    Deps {
+        /// Contract name in .zk/contracts, or path to a contract file
+        contract: String,
     },
     
-    /// Boot into system (normally not called directly)
-    Boot {
-        /// Boot into minimal zero-mode runtime
+    /// Create a signed deployment proposal and share it with trusted peers
+    Propose {
+        /// Path to the contract file
+        contract: String,
+        
+        /// Description of the change
+        #[clap(long, default_value = "")]
+        changelog: String,
+        
+        /// Simulate a call and attach the transcript, as `method` or `method:<JSON args array>` (repeatable)
         #[clap(long)]
-        zero: bool,
+        simulate: Vec<String>,
     },
     
-    /// Execute container inside MatrixBox runtime
-    TsoRun {
-        /// Path to the TSO container
-        container_path: String,
+    /// Sign an approval for a proposal (designated approvers only)
+    Approve {
+        /// Proposal ID
+        proposal_id: String,
     },
     
-    /// MatrixBox container operations
-    MatrixBox {
-        #[clap(subcommand)]
-        command: MatrixBoxCommands,
+    /// Install and hot-reload a proposal that has reached quorum
+    Deploy {
+        /// Proposal ID
+        proposal_id: String,
     },
     
-    /// Contract management
-    Contract {
-        #[clap(subcommand)]
-        command: ContractCommands,
-    },
+    /// List known proposals and their approvals
+    Proposals {},
     
-    /// Healing and recovery commands
-    Heal {
-        #[clap(subcommand)]
-        command: HealCommands,
+    /// Show execution cost statistics of a contract
+    Stats {
+        /// Contract name
+        contract: String,
+        
+        /// Number of most expensive invocations to list
+        #[clap(long, default_value = "5")]
+        top: usize,
     },
     
-    /// Panic recovery system
-    Panic {
-        #[clap(subcommand)]
-        command: PanicCommands,
+    /// Run a contract method, or preview its effects with --simulate
+    Run {
+        /// Contract name
+        contract: String,
+        
+        /// Method name
+        method: String,
+        
+        /// Arguments, as JSON values
+        args: Vec<String>,
+        
+        /// Show the result and state diff without committing anything
+        #[clap(long)]
+        simulate: bool,
     },
     
-    /// Multi-device sync and gossip
-    Gossip {
+    /// Show or reset a contract's persisted state
+    State {
         #[clap(subcommand)]
-        command: GossipCommands,
+        command: ZkStateCommands,
     },
     
-    /// Developer intent recording and replay
-    Intent {
-        #[clap(subcommand)]
-        command: IntentCommands,
+    /// Run a contract's test cases from .zk/tests
+    TestRun {
+        /// Contract name
+        #[clap(long)]
+        contract: String,
     },
     
-    /// Linux compatibility layer commands
-    Linux {
+    /// Protect the contract state master key with a passphrase
+    ProtectStateKey {},
+    
+    /// Inspect or evict cached verification results and proofs
+    Cache {
         #[clap(subcommand)]
-        command: linux::LinuxCommands,
+        command: ZkCacheCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum ZkStateCommands {
+    /// Show a contract's persisted state and how it is stored
+    Show {
+        /// Contract name
+        contract: String,
     },
     
-    /// ZK-Store package manager commands
-    Store {
-        #[clap(subcommand)]
-        command: StoreCommands,
+    /// Delete a contract's persisted state so its next call starts from the declared defaults
+    Reset {
+        /// Contract name
+        contract: String,
     },
 }
 
 #[derive(Subcommand)]
-enum MatrixBoxCommands {
-    /// List all running MatrixBox containers
-    Ls {},
+enum ZkCacheCommands {
+    /// Show hit rates and size of the proof cache
+    Stats {},
     
-    /// Remove container from MatrixBox registry
-    Rm {
-        /// Container ID to remove
-        id: String,
+    /// Evict every cached result of a contract
+    Invalidate {
+        /// Contract name
+        contract: String,
     },
+    
+    /// Evict expired results
+    Prune {},
+    
+    /// Evict every cached result
+    Clear {},
 }
 
 #[derive(Subcommand)]
 enum ContractCommands {
     /// Hot-reload ZK contract without reboot
     Reload {
-        /// Path to contract
-        path: String,
+        /// Contract name, or path to the contract file
+        name: String,
     },
     
     /// Verify contract validity and execution
@@ -349,6 +2629,142 @@ enum ContractCommands {
         /// Path to contract
         path: String,
     },
+    
+    /// Check a contract file for schema errors and warnings without loading it
+    Validate {
+        /// Path to contract
+        path: String,
+    },
+    
+    /// Show the resolved dependency tree of a contract
+    Deps {
+        /// Contract name
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum LogsCommands {
+    /// Log shipping to a peer or collector
+    Ship {
+        #[clap(subcommand)]
+        command: LogsShipCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum LogsShipCommands {
+    /// Show spool depth, last flush and destination health
+    Status {},
+    
+    /// Ship spooled records now
+    Flush {},
+    
+    /// Disable shipping, draining or discarding the spool per configuration
+    Disable {},
+}
+
+#[derive(Subcommand)]
+enum NewCommands {
+    /// Scaffold a WASM-targeted MatrixBox app
+    App {
+        /// App name
+        name: String,
+        
+        /// Directory to create the app in
+        #[clap(long, default_value = ".")]
+        path: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum MetricsCommands {
+    /// Show the retained history of a metric
+    History {
+        /// Metric name, e.g. heal.snapshot_duration_ms
+        name: String,
+        
+        /// How far back to look (e.g. 1h, 7d)
+        #[clap(long, default_value = "7d")]
+        since: String,
+    },
+    
+    /// List active metric anomalies
+    Findings {},
+}
+
+#[derive(Subcommand)]
+enum MaintenanceCommands {
+    /// Pause all containers, optionally run an operation and resume
+    Enter {
+        /// Operation to run before resuming: snapshot, self-update or hot-patch
+        #[clap(long)]
+        run: Option<String>,
+        
+        /// Maximum maintenance duration before the watchdog resumes containers
+        #[clap(long, default_value = "15m")]
+        max_duration: String,
+    },
+    
+    /// Resume paused containers and leave maintenance mode
+    Exit {},
+    
+    /// Show maintenance mode state
+    Status {},
+}
+
+#[derive(Subcommand)]
+enum TrashCommands {
+    /// List trash entries
+    Ls {},
+    
+    /// Restore a trash entry
+    Restore {
+        /// Trash entry ID
+        entry: String,
+        
+        /// Restore under a different name if the original is taken
+        #[clap(long)]
+        rename: Option<String>,
+    },
+    
+    /// Permanently delete trash entries
+    Empty {
+        /// Only delete entries older than this age (e.g. 7d, 12h)
+        #[clap(long)]
+        older_than: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum IdentityCommands {
+    /// Show this node's identity
+    Show {},
+    
+    /// Change this node's display name and announce it to peers
+    Rename {
+        /// New display name
+        name: String,
+    },
+    
+    /// Rotate the signing key, cross-signed by the current key
+    RotateKeys {},
+}
+
+#[derive(Subcommand)]
+enum LockCommands {
+    /// List resource locks and their owners
+    Ls {},
+    
+    /// Break a resource lock
+    Break {
+        /// Lock name
+        name: String,
+        
+        /// Break the lock even if its owner is still alive
+        #[clap(long)]
+        force: bool,
+    },
 }
 
 #[derive(Subcommand)]
@@ -361,19 +2777,128 @@ enum HealCommands {
     
     /// Rebuild kernel space from last clean .boot
     Boot {},
+    
+    /// List snapshots, newest first
+    List {},
+    
+    /// Take a snapshot now
+    Snapshot {
+        /// Why the snapshot is taken
+        #[clap(long, default_value = "manual")]
+        reason: String,
+        
+        /// Only store files changed since the base snapshot
+        #[clap(long)]
+        incremental: bool,
+        
+        /// Base of an incremental snapshot; defaults to the latest snapshot
+        #[clap(long, requires = "incremental")]
+        base: Option<String>,
+        
+        /// Only snapshot this container's data and registry entry
+        #[clap(long, conflicts_with = "incremental")]
+        container: Option<String>,
+        
+        /// Take the scheduled snapshot now and restart the schedule from it
+        #[clap(long, conflicts_with_all = ["incremental", "container"])]
+        now: bool,
+    },
+    
+    /// Merge a chain of incremental snapshots into one full snapshot
+    Compact {
+        /// Snapshot IDs of the chain, oldest first
+        #[clap(required = true)]
+        ids: Vec<String>,
+    },
+    
+    /// Scheduled snapshots
+    Schedule {
+        #[clap(subcommand)]
+        command: ScheduleCommands,
+    },
+    
+    /// Rolling snapshot ring buffer
+    Ring {
+        #[clap(subcommand)]
+        command: RingCommands,
+    },
+    
+    /// Delete old snapshots, keeping the panic fallback snapshot
+    Prune {
+        /// Keep at most this many snapshots
+        #[clap(long)]
+        keep: Option<usize>,
+        
+        /// Delete snapshots older than this many days
+        #[clap(long)]
+        max_age_days: Option<u64>,
+        
+        /// Always keep at least this many of the newest snapshots
+        #[clap(long)]
+        min_keep: Option<usize>,
+    },
+    
+    /// Check a snapshot against its recorded content hash and ZK proof
+    #[clap(alias = "verify-snapshot")]
+    Verify {
+        /// Snapshot ID
+        #[clap(required = true)]
+        id: String,
+    },
+    
+    /// Restore one component from a snapshot, pausing only its subsystem
+    Rollback {
+        /// Component to restore, e.g. zk or auth
+        #[clap(long)]
+        component: String,
+        
+        /// Snapshot ID to restore it from
+        #[clap(long)]
+        snapshot: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ScheduleCommands {
+    /// Show the snapshot interval and the last and next run times
+    Show {},
+}
+
+#[derive(Subcommand)]
+enum RingCommands {
+    /// Show which ring slots are occupied and how old their snapshots are
+    Status {},
 }
 
 #[derive(Subcommand)]
 enum PanicCommands {
     /// Recover from panic state using fallback
-    Recover {},
+    Recover {
+        /// Recover even from a critical panic or during a panic storm cooldown
+        #[clap(long)]
+        force: bool,
+    },
+    
+    /// Show the last panic and whether recovery is in a panic storm cooldown
+    Status {},
     
     /// Generate crash report from panic logs
     Report {
         /// Output path for report
         #[clap(default_value = "crash_report.json")]
         output: String,
+        
+        /// Include every backtrace frame instead of the innermost 20
+        #[clap(long)]
+        full_backtrace: bool,
+        
+        /// Only include panics at or above this severity (low, medium, high or critical)
+        #[clap(long)]
+        severity_filter: Option<String>,
     },
+    
+    /// Send crash reports that could not be sent to the remote endpoint
+    SendPending {},
 }
 
 #[derive(Subcommand)]
@@ -381,14 +2906,63 @@ enum GossipCommands {
     /// Enable trace sync between devices
     Enable {},
     
-    /// Pull runtime trace from peer device
+    /// Pull runtime trace from the best reachable peer
     Pull {
-        /// Peer ID to pull from
-        peer: String,
+        /// Peer to pull from instead of the best one
+        #[clap(long)]
+        peer: Option<String>,
     },
     
     /// Cross-validate trace integrity with peers
     VerifyTrace {},
+    
+    /// Show known peers and their status
+    Status {},
+    
+    /// Sync conflicts that need a manual pick
+    Conflicts {
+        #[clap(subcommand)]
+        command: ConflictCommands,
+    },
+    
+    /// Challenge a peer to attest its boot chain and trace head
+    Attest {
+        /// Peer ID
+        peer: String,
+        
+        /// Show recorded attestations instead of challenging
+        #[clap(long)]
+        history: bool,
+        
+        /// Number of history entries to show
+        #[clap(long, default_value = "20")]
+        limit: usize,
+    },
+    
+    /// Accept the new gossip key a known peer announced
+    Trust {
+        /// Peer ID
+        peer: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConflictCommands {
+    /// List unresolved conflicts
+    Ls {},
+    
+    /// Resolve a conflict and notify the peer that sent the conflicting state
+    Resolve {
+        /// Conflict ID
+        id: String,
+        
+        /// State to keep: local, remote or merged-file
+        #[clap(long)]
+        take: String,
+        
+        /// Hand-merged file, with --take merged-file
+        path: Option<PathBuf>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -403,21 +2977,181 @@ enum IntentCommands {
     Replay {
         /// Session ID to replay
         session: String,
+        
+        /// Re-execute recorded commands
+        #[clap(long)]
+        execute: bool,
+        
+        /// Compare replayed outcomes with the recording and fail on divergence
+        #[clap(long)]
+        check: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum FleetCommands {
+    /// Show which peers still run a version affected by an advisory
+    Advisories {
+        /// Advisory ID
+        id: String,
+    },
+    
+    /// Show the latest attestation of each peer and its freshness
+    Attestation {},
+    
+    /// One line per trusted peer from its latest self-report
+    Status {
+        /// Ask this peer for a fresh report first
+        #[clap(long)]
+        refresh: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AdvisoryCommands {
+    /// List security advisories and mark them seen
+    Ls {},
+    
+    /// Acknowledge an advisory
+    Ack {
+        /// Advisory ID
+        id: String,
+        
+        /// Note recorded with the acknowledgment
+        #[clap(long)]
+        note: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum RepoCommands {
+    /// Create an empty repository layout with a new repository key
+    Init {
+        /// Repository directory
+        dir: PathBuf,
+    },
+    
+    /// Validate a package archive and publish it into the repository
+    AddPackage {
+        /// TSO package archive
+        archive: PathBuf,
+        
+        /// Repository directory
+        #[clap(long, default_value = ".")]
+        dir: PathBuf,
+        
+        /// License recorded in the index entry
+        #[clap(long, default_value = "")]
+        license: String,
+    },
+    
+    /// Re-sign the index and change log with the repository key
+    Sign {
+        /// Repository directory
+        #[clap(long, default_value = ".")]
+        dir: PathBuf,
+    },
+    
+    /// Check archives, hashes, signatures and the change-log chain
+    Verify {
+        /// Repository directory
+        dir: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+enum ConfigCommands {
+    /// Export the node configuration as a signed bundle
+    Export {
+        /// Bundle file to write
+        #[clap(short, long)]
+        output: PathBuf,
+    },
+    
+    /// Import a configuration bundle
+    Import {
+        /// Bundle file to read
+        bundle: PathBuf,
+        
+        /// Comma-separated sections to apply (default: all in the bundle)
+        #[clap(long, value_delimiter = ',')]
+        sections: Vec<String>,
+        
+        /// Apply without asking for confirmation
+        #[clap(long)]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum PolicyCommands {
+    /// Execution allow-list of WASM module hashes
+    Exec {
+        #[clap(subcommand)]
+        command: ExecPolicyCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum ExecPolicyCommands {
+    /// Allow a module by blake3 hash, module file or container directory
+    Allow {
+        /// Module hash, .wasm file or container directory
+        target: String,
+        
+        /// Why the module is allowed
+        #[clap(long)]
+        note: String,
+        
+        /// Last day the entry is valid (YYYY-MM-DD)
+        #[clap(long)]
+        expires: Option<String>,
+    },
+    
+    /// List allowed modules
+    Ls {},
+    
+    /// Remove a module from the allow-list
+    Revoke {
+        /// Module hash
+        hash: String,
     },
 }
 
+#[derive(Subcommand)]
+enum RuntimeCommands {
+    /// Show background worker queues, running tasks and recent completions
+    Workers {},
+}
+
 #[derive(Subcommand)]
 enum StoreCommands {
     /// Install package from ZK-Store
     Install {
         /// Package name to install
         name: String,
+        
+        /// Fetch archives from the best reachable peer instead of the store
+        #[clap(long)]
+        from_peers: bool,
+        
+        /// Fetch archives from this peer instead of the best one; implies --from-peers
+        #[clap(long)]
+        peer: Option<String>,
     },
     
     /// Remove installed package
     Remove {
         /// Package name to remove
         name: String,
+        
+        /// Delete immediately instead of moving to the trash
+        #[clap(long)]
+        purge: bool,
+        
+        /// Remove even if installed packages depend on it
+        #[clap(long)]
+        force: bool,
     },
     
     /// List installed packages
@@ -443,4 +3177,32 @@ enum StoreCommands {
         /// Package name to verify
         name: String,
     },
+    
+    /// Show what changes between two versions of a package
+    Diff {
+        /// Package name
+        name: String,
+        
+        /// Old version
+        from: String,
+        
+        /// New version
+        to: String,
+        
+        /// Print the diff as JSON
+        #[clap(long)]
+        json: bool,
+    },
+    
+    /// Security advisories
+    Advisory {
+        #[clap(subcommand)]
+        command: AdvisoryCommands,
+    },
+    
+    /// Publish a package repository for a static file host
+    Repo {
+        #[clap(subcommand)]
+        command: RepoCommands,
+    },
 }
@@ -0,0 +1,370 @@
+// SentientOS CLI Output
+// Table rendering fitted to the terminal, with paging of long output and progress bars
+
+use anyhow::{Result, Context};
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+/// Width used when stdout is not a terminal and COLUMNS is unset
+const DEFAULT_WIDTH: usize = 120;
+
+/// Narrowest a column is shrunk to before the table overflows
+const MIN_COLUMN_WIDTH: usize = 6;
+
+/// Gap between columns
+const COLUMN_GAP: usize = 2;
+
+/// Width of a progress bar's bar, in characters
+const PROGRESS_WIDTH: usize = 30;
+
+/// Bytes between redraws of a progress bar whose total is unknown
+const PROGRESS_STEP: u64 = 1024 * 1024;
+
+/// How command output is rendered, from the global output flags
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OutputOptions {
+    /// Never truncate cells to the terminal width
+    pub wide: bool,
+    
+    /// Tab-separated rows without a header, for scripts
+    pub plain: bool,
+    
+    /// Never pipe through a pager
+    pub no_pager: bool,
+}
+
+/// A table of text cells
+///
+/// Columns keep the order they were declared in, in every mode, so
+/// `--plain` output can be parsed by position.
+pub struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    /// Create a table with the given column headers
+    pub fn new(headers: &[&'static str]) -> Self {
+        Self {
+            headers: headers.to_vec(),
+            rows: Vec::new(),
+        }
+    }
+    
+    /// Add a row; missing cells are left empty and extra cells dropped
+    pub fn row<I, S>(&mut self, cells: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString,
+    {
+        let mut row: Vec<String> = cells.into_iter().map(|c| c.to_string()).collect();
+        row.resize(self.headers.len(), String::new());
+        self.rows.push(row);
+    }
+    
+    /// Whether the table has no rows
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+    
+    /// Render the table to text
+    pub fn render(&self, options: &OutputOptions) -> String {
+        if options.plain {
+            return self.rows.iter()
+                .map(|row| row.iter().map(|c| c.replace(['\t', '\n'], " ")).collect::<Vec<_>>().join("\t") + "\n")
+                .collect();
+        }
+        
+        let widths = if options.wide {
+            self.natural_widths()
+        } else {
+            self.fit_widths(terminal_size().0)
+        };
+        
+        let mut out = String::new();
+        let header: Vec<String> = self.headers.iter().map(|h| h.to_string()).collect();
+        for row in std::iter::once(&header).chain(self.rows.iter()) {
+            let mut line = String::new();
+            for (cell, width) in row.iter().zip(&widths) {
+                let cell = truncate(cell, *width);
+                line.push_str(&cell);
+                line.push_str(&" ".repeat(width - cell.chars().count() + COLUMN_GAP));
+            }
+            
+            // Trailing empty cells would otherwise leave padding at the end of the line
+            out.push_str(line.trim_end());
+            out.push('\n');
+        }
+        out
+    }
+    
+    /// Render the table and print it, paging if needed
+    pub fn print(&self, options: &OutputOptions) -> Result<()> {
+        emit(&self.render(options), options)
+    }
+    
+    /// Widest cell of each column, header included
+    fn natural_widths(&self) -> Vec<usize> {
+        self.headers.iter()
+            .enumerate()
+            .map(|(i, header)| self.rows.iter()
+                .map(|row| row[i].chars().count())
+                .max()
+                .unwrap_or(0)
+                .max(header.chars().count()))
+            .collect()
+    }
+    
+    /// Column widths that fit `total` characters, shrinking the widest first
+    fn fit_widths(&self, total: usize) -> Vec<usize> {
+        let mut widths = self.natural_widths();
+        let gaps = COLUMN_GAP * widths.len().saturating_sub(1);
+        
+        while widths.iter().sum::<usize>() + gaps > total {
+            let widest = widths.iter()
+                .enumerate()
+                .filter(|(i, w)| **w > MIN_COLUMN_WIDTH.max(self.headers[*i].chars().count()))
+                .max_by_key(|(_, w)| **w)
+                .map(|(i, _)| i);
+            match widest {
+                Some(i) => widths[i] -= 1,
+                None => break,
+            }
+        }
+        widths
+    }
+}
+
+/// Print text, piping it through a pager when it does not fit the terminal
+///
+/// Uses $PAGER when set and falls back to a minimal built-in pager. Paging
+/// only happens when stdout is a terminal.
+pub fn emit(text: &str, options: &OutputOptions) -> Result<()> {
+    let height = terminal_size().1;
+    let paged = !options.no_pager && !options.plain
+        && io::stdout().is_terminal()
+        && text.lines().count() >= height;
+    
+    if !paged {
+        print!("{}", text);
+        return Ok(());
+    }
+    
+    if let Some(pager) = std::env::var("PAGER").ok().filter(|p| !p.trim().is_empty()) {
+        match run_pager(&pager, text) {
+            Ok(()) => return Ok(()),
+            Err(e) => tracing::debug!("Pager {} failed, using built-in pager: {}", pager, e),
+        }
+    }
+    
+    builtin_pager(text, height)
+}
+
+/// A progress bar drawn on stderr, only when stderr is a terminal
+pub struct ProgressBar {
+    /// Text in front of the bar
+    label: String,
+    
+    /// Whether the bar is drawn at all
+    visible: bool,
+    
+    /// What was last drawn: percent done, or bytes when the total is unknown
+    last: Option<u64>,
+}
+
+impl ProgressBar {
+    /// Create a bar; nothing is drawn until the first update
+    pub fn new(label: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            visible: io::stderr().is_terminal(),
+            last: None,
+        }
+    }
+    
+    /// Redraw the bar for `done` of `total` bytes
+    pub fn update(&mut self, done: u64, total: Option<u64>) {
+        if !self.visible {
+            return;
+        }
+        
+        let line = match total.filter(|t| *t > 0) {
+            Some(total) => {
+                let percent = (done.min(total) * 100) / total;
+                if self.last == Some(percent) {
+                    return;
+                }
+                self.last = Some(percent);
+                let filled = (percent as usize * PROGRESS_WIDTH) / 100;
+                format!("{} [{}{}] {:>3}% {}/{}", self.label, "#".repeat(filled),
+                        " ".repeat(PROGRESS_WIDTH - filled), percent, format_bytes(done), format_bytes(total))
+            }
+            None => {
+                if self.last.map_or(false, |last| done < last + PROGRESS_STEP) {
+                    return;
+                }
+                self.last = Some(done);
+                format!("{} {}", self.label, format_bytes(done))
+            }
+        };
+        
+        let line = truncate(&line, terminal_size().0.saturating_sub(1));
+        eprint!("\r{}\x1b[K", line);
+        let _ = io::stderr().flush();
+    }
+    
+    /// End the bar's line
+    pub fn finish(&mut self) {
+        if self.visible && self.last.is_some() {
+            eprintln!();
+        }
+        self.last = None;
+    }
+}
+
+/// Byte count in binary units
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Time elapsed since a moment, in its largest whole unit, e.g. `3h ago`
+pub fn format_age(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{}s ago", seconds),
+        60..=3_599 => format!("{}m ago", seconds / 60),
+        3_600..=86_399 => format!("{}h ago", seconds / 3_600),
+        _ => format!("{}d ago", seconds / 86_400),
+    }
+}
+
+/// Pipe text through an external pager command
+fn run_pager(pager: &str, text: &str) -> Result<()> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(pager)
+        .stdin(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to start pager: {}", pager))?;
+    
+    // The pager may exit early (e.g. `q` in less); a broken pipe is expected then
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    child.wait()?;
+    Ok(())
+}
+
+/// Show text one screen at a time
+fn builtin_pager(text: &str, height: usize) -> Result<()> {
+    let lines: Vec<&str> = text.lines().collect();
+    let page = height.saturating_sub(1).max(1);
+    let stdin = io::stdin();
+    let mut stdout = io::stdout().lock();
+    
+    for (i, chunk) in lines.chunks(page).enumerate() {
+        for line in chunk {
+            writeln!(stdout, "{}", line)?;
+        }
+        
+        let shown = (i * page + chunk.len()).min(lines.len());
+        if shown == lines.len() {
+            break;
+        }
+        
+        write!(stdout, "-- {}/{} lines (Enter for more, q to quit) --", shown, lines.len())?;
+        stdout.flush()?;
+        let mut answer = String::new();
+        if stdin.lock().read_line(&mut answer)? == 0 || answer.trim() == "q" {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Truncate a cell to `width` characters, marking the cut with an ellipsis
+fn truncate(cell: &str, width: usize) -> String {
+    if cell.chars().count() <= width {
+        return cell.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    cell.chars().take(width - 1).chain(std::iter::once('…')).collect()
+}
+
+/// Terminal width and height, from the terminal or COLUMNS/LINES
+fn terminal_size() -> (usize, usize) {
+    if let Some((terminal_size::Width(w), terminal_size::Height(h))) = terminal_size::terminal_size() {
+        return (w as usize, h as usize);
+    }
+    
+    let from_env = |name: &str| std::env::var(name).ok().and_then(|v| v.parse().ok());
+    (from_env("COLUMNS").unwrap_or(DEFAULT_WIDTH), from_env("LINES").unwrap_or(usize::MAX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn packages() -> Table {
+        let mut table = Table::new(&["NAME", "VERSION", "DESCRIPTION"]);
+        table.row(["left-pad", "1.3.0", "String padding\tfor everyone"]);
+        table.row(["a", "10.0.0-beta.1"]);
+        table
+    }
+    
+    #[test]
+    fn plain_output_is_tab_separated_without_a_header() {
+        let options = OutputOptions { plain: true, ..Default::default() };
+        assert_eq!(packages().render(&options), "left-pad\t1.3.0\tString padding for everyone\na\t10.0.0-beta.1\t\n");
+        assert!(Table::new(&["NAME"]).is_empty());
+    }
+    
+    #[test]
+    fn wide_output_aligns_columns_without_truncating() {
+        let options = OutputOptions { wide: true, ..Default::default() };
+        assert_eq!(packages().render(&options), concat!(
+            "NAME      VERSION        DESCRIPTION\n",
+            "left-pad  1.3.0          String padding\tfor everyone\n",
+            "a         10.0.0-beta.1\n",
+        ));
+    }
+    
+    #[test]
+    fn columns_shrink_widest_first_but_not_below_their_header() {
+        let table = packages();
+        assert_eq!(table.fit_widths(200), [8, 13, 27]);
+        assert_eq!(table.fit_widths(40), [8, 13, 15]);
+        assert_eq!(table.fit_widths(30), [8, 7, 11]);
+        assert_eq!(table.fit_widths(0), [6, 7, 11]);
+    }
+    
+    #[test]
+    fn cells_are_cut_with_an_ellipsis() {
+        assert_eq!(truncate("sentient", 8), "sentient");
+        assert_eq!(truncate("sentient", 5), "sent…");
+        assert_eq!(truncate("sentient", 0), "");
+    }
+    
+    #[test]
+    fn sizes_and_ages_use_their_largest_unit() {
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(5 * 1024 * 1024 * 1024 * 1024), "5120.0 GiB");
+        assert_eq!(format_age(59), "59s ago");
+        assert_eq!(format_age(3_599), "59m ago");
+        assert_eq!(format_age(7_200), "2h ago");
+        assert_eq!(format_age(86_400 * 3), "3d ago");
+    }
+}
@@ -12,7 +12,6 @@ use chrono::{DateTime, Utc};
 use crate::zk::contracts::ZkContract;
 use crate::zk::parser;
 use crate::zk::verification;
-use crate::zk::executor;
 use crate::core::constants;
 
 /// Register ZK subcommand to CLI
@@ -119,7 +118,7 @@ fn cmd_verify(contract_name: &str, proof_opt: Option<&String>) -> Result<()> {
     println!("\n{} {} {}\n", "🔐".green(), "Verifying ZK contract:".bold(), contract_name.cyan().bold());
     
     // Check if contract exists
-    let zk_dir = PathBuf::from(constants::ROOT_DIR).join(".zk");
+    let zk_dir = constants::root_dir().join(".zk");
     let contracts_dir = zk_dir.join("contracts");
     let contract_file = contracts_dir.join(format!("{}.yaml", contract_name));
     
@@ -207,7 +206,7 @@ fn cmd_verify(contract_name: &str, proof_opt: Option<&String>) -> Result<()> {
 fn cmd_list(verified_only: bool) -> Result<()> {
     println!("\n{} {}\n", "📋".green(), "ZK Contracts".bold());
     
-    let zk_dir = PathBuf::from(constants::ROOT_DIR).join(".zk");
+    let zk_dir = constants::root_dir().join(".zk");
     let contracts_dir = zk_dir.join("contracts");
     
     if !contracts_dir.exists() {
@@ -266,7 +265,7 @@ fn cmd_list(verified_only: bool) -> Result<()> {
 fn cmd_create(name: &str, template: &str) -> Result<()> {
     println!("\n{} {} {} ({})\n", "🔨".green(), "Creating ZK contract:".bold(), name.cyan().bold(), template);
     
-    let zk_dir = PathBuf::from(constants::ROOT_DIR).join(".zk");
+    let zk_dir = constants::root_dir().join(".zk");
     let contracts_dir = zk_dir.join("contracts");
     
     // Create the contracts directory if it doesn't exist
@@ -438,7 +437,7 @@ fn cmd_run(contract_name: &str, method_name: &str, args_json: &str) -> Result<()
     };
     
     // Load the contract
-    let zk_dir = PathBuf::from(constants::ROOT_DIR).join(".zk");
+    let zk_dir = constants::root_dir().join(".zk");
     let contracts_dir = zk_dir.join("contracts");
     let contract_file = contracts_dir.join(format!("{}.yaml", contract_name));
     
@@ -464,9 +463,11 @@ fn cmd_run(contract_name: &str, method_name: &str, args_json: &str) -> Result<()
     }
     
     // Execute the method
-    match executor::execute_contract_method(&contract, method_name, &args) {
+    match crate::zk::execute_contract_method(&contract, method_name, &args) {
         Ok(result) => {
-            println!("{} {}", "Result:".bold(), serde_json::to_string_pretty(&result)?);
+            println!("{} {}", "Result:".bold(), serde_json::to_string_pretty(&result.value)?);
+            println!("{} {} steps, {} state bytes (proof {})", "Cost:".bold(),
+                     result.cost.steps, result.cost.state_bytes, result.proof_id);
             
             // Generate and store a proof of execution
             let input_data = serde_json::to_string(&args)?;
@@ -0,0 +1,503 @@
+// SentientOS Configuration Bundles
+// Signed export and import of a node's declarative configuration
+
+use anyhow::{Result, Context};
+use tracing::{info, warn};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use crate::core::constants;
+
+// Constants
+const BUNDLE_FORMAT: &str = "sentient-config-bundle";
+const BUNDLE_SCHEMA_VERSION: u32 = 1;
+const IMPORT_SUFFIX: &str = "import-tmp";
+const BACKUP_SUFFIX: &str = "import-bak";
+
+/// A section of the configuration surface
+///
+/// Only declarative configuration belongs here. Identities, keys other than
+/// trusted public keys, secrets and data are never part of a section.
+struct Section {
+    /// Section name, as used with `--sections`
+    name: &'static str,
+    
+    /// Schema version of the section's files
+    version: u32,
+    
+    /// Files and directories, relative to the root directory
+    paths: &'static [&'static str],
+}
+
+// Exported sections
+const SECTIONS: &[Section] = &[
+    Section { name: "system", version: 1, paths: &[".config/system.json"] },
+    Section { name: "security", version: 1, paths: &[".config/security.json", ".config/licenses.json"] },
+    Section { name: "network", version: 1, paths: &[".network/config.json"] },
+    Section { name: "boot", version: 1, paths: &[".boot/boot.yaml", ".boot/iot/profiles"] },
+    Section { name: "store", version: 1, paths: &[".store/repositories.json", ".store/trusted_keys.json"] },
+    Section { name: "packages", version: 1, paths: &[".package/config.json"] },
+    Section { name: "trash", version: 1, paths: &[".trash/config.json"] },
+    Section { name: "policies", version: 1, paths: &[".auth/policies"] },
+];
+
+/// Configuration files of one section
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleSection {
+    /// Schema version of the section
+    pub version: u32,
+    
+    /// File contents by path relative to the root directory
+    pub files: BTreeMap<String, String>,
+}
+
+/// Signed configuration bundle
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigBundle {
+    /// Format marker
+    pub format: String,
+    
+    /// Bundle schema version
+    pub schema_version: u32,
+    
+    /// Node that exported the bundle
+    pub node_id: String,
+    
+    /// Public key (hex) of the exporting node
+    pub public_key: String,
+    
+    /// Export timestamp
+    pub exported_at: u64,
+    
+    /// Sections by name
+    pub sections: BTreeMap<String, BundleSection>,
+    
+    /// Signature (hex) over the bundle without this field
+    #[serde(default)]
+    pub signature: String,
+}
+
+/// How a file differs from the current configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileChange {
+    /// Same content
+    Unchanged,
+    
+    /// Content differs
+    Modified,
+    
+    /// Not present on this node
+    Added,
+}
+
+/// Difference of one bundled file against the current configuration
+#[derive(Debug, Clone)]
+pub struct FileDiff {
+    /// Section name
+    pub section: String,
+    
+    /// Path relative to the root directory
+    pub path: String,
+    
+    /// What changes
+    pub change: FileChange,
+    
+    /// Top-level keys whose values differ, for JSON and YAML files
+    pub changed_keys: Vec<String>,
+}
+
+/// Export the configuration as a signed bundle
+pub fn export(output: &Path) -> Result<String> {
+    info!("Exporting node configuration to {:?}", output);
+    
+    let root = constants::root_dir();
+    let mut sections = BTreeMap::new();
+    for section in SECTIONS {
+        let mut files = BTreeMap::new();
+        for path in section.paths {
+            collect(&root, &root.join(path), &mut files)?;
+        }
+        if !files.is_empty() {
+            sections.insert(section.name.to_string(), BundleSection { version: section.version, files });
+        }
+    }
+    
+    let identity = crate::core::identity::current()?;
+    let mut bundle = ConfigBundle {
+        format: BUNDLE_FORMAT.to_string(),
+        schema_version: BUNDLE_SCHEMA_VERSION,
+        node_id: identity.id,
+        public_key: identity.public_key,
+        exported_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        sections,
+        signature: String::new(),
+    };
+    bundle.signature = crate::core::identity::sign(&signing_payload(&bundle)?)?;
+    
+    let data = serde_json::to_vec_pretty(&bundle)?;
+    fs::write(output, &data).with_context(|| format!("Failed to write bundle {:?}", output))?;
+    
+    let hash = blake3::hash(&data).to_hex().to_string();
+    info!("Exported {} section(s), bundle hash {}", bundle.sections.len(), hash);
+    Ok(hash)
+}
+
+/// Read a bundle, checking its signature and schema versions
+pub fn load(path: &Path) -> Result<(ConfigBundle, String)> {
+    let data = fs::read(path).with_context(|| format!("Failed to read bundle {:?}", path))?;
+    let hash = blake3::hash(&data).to_hex().to_string();
+    let bundle: ConfigBundle = serde_json::from_slice(&data).context("Invalid config bundle")?;
+    
+    if bundle.format != BUNDLE_FORMAT {
+        anyhow::bail!("Not a config bundle: format {}", bundle.format);
+    }
+    if bundle.schema_version > BUNDLE_SCHEMA_VERSION {
+        anyhow::bail!("Bundle schema version {} is newer than the supported version {}; \
+                       upgrade this node before importing", bundle.schema_version, BUNDLE_SCHEMA_VERSION);
+    }
+    for (name, section) in &bundle.sections {
+        let spec = find_section(name)?;
+        if section.version > spec.version {
+            anyhow::bail!("Section {} has schema version {}, newer than the supported version {}; \
+                           upgrade this node before importing", name, section.version, spec.version);
+        }
+        if let Some(path) = section.files.keys().find(|p| !spec.paths.iter().any(|s| is_within(p, s))) {
+            anyhow::bail!("Section {} contains a file outside the section: {}", name, path);
+        }
+    }
+    
+    crate::core::identity::verify(&bundle.public_key, &signing_payload(&bundle)?, &bundle.signature)
+        .context("Config bundle signature is invalid")?;
+    
+    // A bundle claiming to be from a known peer must carry that peer's key
+    if let Some(known_key) = crate::gossip::peer_public_key(&bundle.node_id) {
+        if known_key != bundle.public_key {
+            anyhow::bail!("Bundle claims to be from peer {} but is signed with a different key", bundle.node_id);
+        }
+    }
+    
+    Ok((bundle, hash))
+}
+
+/// Difference of the selected sections against the current configuration
+pub fn diff(bundle: &ConfigBundle, sections: &[String]) -> Result<Vec<FileDiff>> {
+    let root = constants::root_dir();
+    let mut diffs = Vec::new();
+    
+    for name in selected(bundle, sections)? {
+        for (path, content) in &bundle.sections[&name].files {
+            let current = fs::read_to_string(root.join(path)).ok();
+            let (change, changed_keys) = match current {
+                None => (FileChange::Added, Vec::new()),
+                Some(current) if current == *content => (FileChange::Unchanged, Vec::new()),
+                Some(current) => (FileChange::Modified, changed_keys(path, &current, content)),
+            };
+            diffs.push(FileDiff { section: name.clone(), path: path.clone(), change, changed_keys });
+        }
+    }
+    Ok(diffs)
+}
+
+/// Apply the selected sections of a bundle
+///
+/// A heal snapshot is taken first. Every file is validated and staged
+/// before any is replaced, and replaced files are restored if a later one
+/// fails, so either all selected sections apply or none do.
+pub fn import(bundle: &ConfigBundle, hash: &str, sections: &[String]) -> Result<Vec<String>> {
+    let names = selected(bundle, sections)?;
+    info!("Importing config sections {} from node {}", names.join(", "), bundle.node_id);
+    
+    let root = constants::root_dir();
+    let files: Vec<(PathBuf, &String)> = names.iter()
+        .flat_map(|name| bundle.sections[name].files.iter())
+        .map(|(path, content)| (root.join(path), content))
+        .collect();
+    
+    for (path, content) in &files {
+        validate(path, content)?;
+    }
+    
+    let checkpoint = crate::heal::take_snapshot("pre config import")
+        .context("Failed to take pre-import checkpoint")?;
+    
+    // Stage everything next to its target first
+    for (path, content) in &files {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(with_suffix(path, IMPORT_SUFFIX), content)?;
+    }
+    
+    let mut replaced: Vec<&PathBuf> = Vec::new();
+    let result = files.iter().try_for_each(|(path, _)| -> Result<()> {
+        if path.exists() {
+            fs::copy(path, with_suffix(path, BACKUP_SUFFIX))?;
+        }
+        fs::rename(with_suffix(path, IMPORT_SUFFIX), path)?;
+        replaced.push(path);
+        Ok(())
+    });
+    
+    if let Err(e) = result {
+        warn!("Config import failed, restoring replaced files: {}", e);
+        for path in replaced {
+            let backup = with_suffix(path, BACKUP_SUFFIX);
+            if backup.exists() {
+                fs::rename(&backup, path).ok();
+            } else {
+                fs::remove_file(path).ok();
+            }
+        }
+        for (path, _) in &files {
+            fs::remove_file(with_suffix(path, IMPORT_SUFFIX)).ok();
+        }
+        return Err(e.context(format!("Config import rolled back; checkpoint {} is also available", checkpoint)));
+    }
+    
+    for (path, _) in &files {
+        fs::remove_file(with_suffix(path, BACKUP_SUFFIX)).ok();
+    }
+    
+    crate::logs::ship::ship_audit("config", &format!(
+        "Imported config bundle {} from node {}: sections {} (checkpoint {})",
+        hash, bundle.node_id, names.join(", "), checkpoint));
+    info!("Imported {} config file(s)", files.len());
+    Ok(names)
+}
+
+/// Names of all sections
+pub fn section_names() -> Vec<&'static str> {
+    SECTIONS.iter().map(|s| s.name).collect()
+}
+
+/// Sections to act on: the requested ones, or all in the bundle
+fn selected(bundle: &ConfigBundle, sections: &[String]) -> Result<Vec<String>> {
+    if sections.is_empty() {
+        return Ok(bundle.sections.keys().cloned().collect());
+    }
+    
+    for name in sections {
+        find_section(name)?;
+        if !bundle.sections.contains_key(name) {
+            anyhow::bail!("Section {} is not in the bundle", name);
+        }
+    }
+    Ok(sections.to_vec())
+}
+
+/// Look up a known section
+fn find_section(name: &str) -> Result<&'static Section> {
+    SECTIONS.iter()
+        .find(|s| s.name == name)
+        .ok_or_else(|| anyhow::anyhow!("Unknown config section: {} (known: {})", name, section_names().join(", ")))
+}
+
+/// Add a file, or every file under a directory, to a section
+fn collect(root: &Path, path: &Path, files: &mut BTreeMap<String, String>) -> Result<()> {
+    if path.is_dir() {
+        for entry in fs::read_dir(path)?.filter_map(Result::ok) {
+            collect(root, &entry.path(), files)?;
+        }
+    } else if path.is_file() {
+        let relative = path.strip_prefix(root)?.to_string_lossy().to_string();
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Config file is not text: {:?}", path))?;
+        files.insert(relative, content);
+    }
+    Ok(())
+}
+
+/// Whether a bundled path is one of a section's paths or under it
+fn is_within(path: &str, section_path: &str) -> bool {
+    !path.split('/').any(|c| c == "..")
+        && (path == section_path || path.starts_with(&format!("{}/", section_path)))
+}
+
+/// Check that a file parses in its format before it replaces anything
+fn validate(path: &Path, content: &str) -> Result<()> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => serde_json::from_str::<serde_json::Value>(content).map(drop)
+            .with_context(|| format!("Invalid JSON in bundled {:?}", path)),
+        Some("yaml") | Some("yml") => serde_yaml::from_str::<serde_yaml::Value>(content).map(drop)
+            .with_context(|| format!("Invalid YAML in bundled {:?}", path)),
+        _ => Ok(()),
+    }
+}
+
+/// Top-level keys whose values differ between two JSON or YAML documents
+fn changed_keys(path: &str, current: &str, incoming: &str) -> Vec<String> {
+    let parse = |text: &str| -> Option<serde_json::Value> {
+        if path.ends_with(".json") {
+            serde_json::from_str(text).ok()
+        } else {
+            serde_yaml::from_str(text).ok()
+        }
+    };
+    
+    match (parse(current), parse(incoming)) {
+        (Some(serde_json::Value::Object(a)), Some(serde_json::Value::Object(b))) => {
+            let mut keys: Vec<String> = a.keys().chain(b.keys())
+                .filter(|k| a.get(*k) != b.get(*k))
+                .cloned()
+                .collect();
+            keys.sort();
+            keys.dedup();
+            keys
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Bytes covered by the bundle signature
+fn signing_payload(bundle: &ConfigBundle) -> Result<Vec<u8>> {
+    let mut unsigned = bundle.clone();
+    unsigned.signature = String::new();
+    Ok(serde_json::to_vec(&unsigned)?)
+}
+
+/// A sibling path with an extra suffix
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", suffix));
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+    
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+    
+    /// Bundle of one policies file, signed with the key derived from `seed`
+    fn bundle(node_id: &str, file: &str, content: &str, seed: u8) -> ConfigBundle {
+        let key = SigningKey::from_bytes(&[seed; 32]);
+        let mut files = BTreeMap::new();
+        files.insert(file.to_string(), content.to_string());
+        let mut sections = BTreeMap::new();
+        sections.insert("policies".to_string(), BundleSection { version: 1, files });
+        
+        let mut bundle = ConfigBundle {
+            format: BUNDLE_FORMAT.to_string(),
+            schema_version: BUNDLE_SCHEMA_VERSION,
+            node_id: node_id.to_string(),
+            public_key: hex(key.verifying_key().as_bytes()),
+            exported_at: 0,
+            sections,
+            signature: String::new(),
+        };
+        bundle.signature = hex(&key.sign(&signing_payload(&bundle).unwrap()).to_bytes());
+        bundle
+    }
+    
+    /// Write a bundle where `load` can read it
+    fn write_bundle(name: &str, bundle: &ConfigBundle) -> PathBuf {
+        let dir = constants::root_dir().join("config-bundles");
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.json", name));
+        fs::write(&path, serde_json::to_vec(bundle).unwrap()).unwrap();
+        path
+    }
+    
+    #[test]
+    fn signed_bundles_load_and_tampered_ones_do_not() {
+        let signed = bundle("config-node", ".auth/policies/load.yaml", "allow: []\n", 1);
+        let path = write_bundle("signed", &signed);
+        let (loaded, hash) = load(&path).unwrap();
+        assert_eq!(loaded.sections["policies"].files[".auth/policies/load.yaml"], "allow: []\n");
+        assert_eq!(hash, blake3::hash(&fs::read(&path).unwrap()).to_hex().to_string());
+        
+        let mut tampered = signed.clone();
+        tampered.sections.get_mut("policies").unwrap().files
+            .insert(".auth/policies/load.yaml".to_string(), "allow: [all]\n".to_string());
+        assert!(load(&write_bundle("tampered", &tampered)).is_err());
+    }
+    
+    #[test]
+    fn bundles_from_known_peers_must_carry_their_key() {
+        let peer = SigningKey::from_bytes(&[2; 32]);
+        crate::gossip::add_peer("config-peer", "127.0.0.1:9", None).unwrap();
+        crate::gossip::update_peer_identity("config-peer", "config-peer", &hex(peer.verifying_key().as_bytes())).unwrap();
+        
+        let genuine = bundle("config-peer", ".auth/policies/peer.yaml", "allow: []\n", 2);
+        assert!(load(&write_bundle("genuine", &genuine)).is_ok());
+        let forged = bundle("config-peer", ".auth/policies/peer.yaml", "allow: []\n", 3);
+        assert!(load(&write_bundle("forged", &forged)).is_err());
+    }
+    
+    #[test]
+    fn unsupported_or_escaping_bundles_are_rejected() {
+        let mut wrong_format = bundle("config-node", ".auth/policies/a.yaml", "", 1);
+        wrong_format.format = "something-else".to_string();
+        assert!(load(&write_bundle("wrong-format", &wrong_format)).is_err());
+        
+        let mut newer = bundle("config-node", ".auth/policies/a.yaml", "", 1);
+        newer.schema_version = BUNDLE_SCHEMA_VERSION + 1;
+        assert!(load(&write_bundle("newer-schema", &newer)).is_err());
+        
+        let mut newer_section = bundle("config-node", ".auth/policies/a.yaml", "", 1);
+        newer_section.sections.get_mut("policies").unwrap().version = 2;
+        assert!(load(&write_bundle("newer-section", &newer_section)).is_err());
+        
+        let escaping = bundle("config-node", ".auth/policies/../../.identity/key", "", 1);
+        assert!(load(&write_bundle("escaping", &escaping)).is_err());
+        let outside = bundle("config-node", ".config/system.json", "{}", 1);
+        assert!(load(&write_bundle("outside", &outside)).is_err());
+    }
+    
+    #[test]
+    fn diffs_report_added_unchanged_and_modified_files() {
+        let dir = constants::root_dir().join(".auth").join("policies");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("diff-same.yaml"), "allow: []\n").unwrap();
+        fs::write(dir.join("diff-changed.yaml"), "allow: []\ndeny: [net]\nname: a\n").unwrap();
+        
+        let mut bundle = bundle("config-node", ".auth/policies/diff-new.yaml", "allow: []\n", 1);
+        let files = &mut bundle.sections.get_mut("policies").unwrap().files;
+        files.insert(".auth/policies/diff-same.yaml".to_string(), "allow: []\n".to_string());
+        files.insert(".auth/policies/diff-changed.yaml".to_string(), "allow: [fs]\nname: a\n".to_string());
+        
+        let diffs = diff(&bundle, &[]).unwrap();
+        let change = |path: &str| diffs.iter().find(|d| d.path.ends_with(path)).unwrap();
+        assert_eq!(change("diff-new.yaml").change, FileChange::Added);
+        assert_eq!(change("diff-same.yaml").change, FileChange::Unchanged);
+        assert_eq!(change("diff-changed.yaml").change, FileChange::Modified);
+        assert_eq!(change("diff-changed.yaml").changed_keys, ["allow", "deny"]);
+        assert!(diffs.iter().all(|d| d.section == "policies"));
+    }
+    
+    #[test]
+    fn sections_must_be_known_and_bundled() {
+        let bundle = bundle("config-node", ".auth/policies/a.yaml", "", 1);
+        assert_eq!(selected(&bundle, &[]).unwrap(), ["policies"]);
+        assert_eq!(selected(&bundle, &["policies".to_string()]).unwrap(), ["policies"]);
+        assert!(selected(&bundle, &["system".to_string()]).is_err());
+        assert!(selected(&bundle, &["bogus".to_string()]).is_err());
+    }
+    
+    #[test]
+    fn section_paths_cover_only_their_own_files() {
+        assert!(is_within(".boot/boot.yaml", ".boot/boot.yaml"));
+        assert!(is_within(".boot/iot/profiles/pi.yaml", ".boot/iot/profiles"));
+        assert!(!is_within(".boot/boot.yaml.bak", ".boot/boot.yaml"));
+        assert!(!is_within(".boot/iot/profiles/../../../etc/passwd", ".boot/iot/profiles"));
+    }
+    
+    #[test]
+    fn files_are_validated_by_format() {
+        assert!(validate(Path::new("a.json"), "{\"a\": 1}").is_ok());
+        assert!(validate(Path::new("a.json"), "{\"a\": ").is_err());
+        assert!(validate(Path::new("a.yaml"), "a: [1, 2]").is_ok());
+        assert!(validate(Path::new("a.yml"), "a: [1, 2").is_err());
+        assert!(validate(Path::new("a.txt"), "{").is_ok());
+        
+        assert_eq!(changed_keys("a.json", "{\"a\": 1, \"b\": 2}", "{\"b\": 3, \"c\": 4}"), ["a", "b", "c"]);
+        assert!(changed_keys("a.json", "[1]", "[2]").is_empty());
+        assert_eq!(with_suffix(Path::new("/x/system.json"), IMPORT_SUFFIX), Path::new("/x/system.json.import-tmp"));
+    }
+}
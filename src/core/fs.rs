@@ -1,10 +1,16 @@
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use anyhow::{Result, Context};
-use tracing::info;
+use serde::de::DeserializeOwned;
+use tracing::{info, warn};
 
 use crate::core::constants;
 
+// Numbers `write_atomic` temporary files, so threads writing the same file never share one
+static ATOMIC_WRITES: AtomicU64 = AtomicU64::new(0);
+
 /// Ensure all required SentientOS directories exist
 pub fn ensure_directories() -> Result<()> {
     info!("Ensuring core SentientOS directories exist");
@@ -19,6 +25,7 @@ pub fn ensure_directories() -> Result<()> {
     create_directory_if_not_exists(&format!("{}/zk.trace", lock_dir))?;
     create_directory_if_not_exists(&format!("{}/zk.remind", lock_dir))?;
     create_directory_if_not_exists(&format!("{}/zk.rollup", lock_dir))?;
+    create_directory_if_not_exists(&format!("{}/resources", lock_dir))?;
     
     // Auth directories
     let auth_dir = constants::AUTH_DIR;
@@ -90,7 +97,7 @@ pub fn ensure_directories() -> Result<()> {
 
 /// Create a directory if it doesn't exist
 pub fn create_directory_if_not_exists(dir: &str) -> Result<()> {
-    let path = PathBuf::from(constants::ROOT_DIR).join(dir);
+    let path = constants::root_dir().join(dir);
     if !path.exists() {
         info!("Creating directory: {:?}", path);
         fs::create_dir_all(&path)
@@ -101,13 +108,13 @@ pub fn create_directory_if_not_exists(dir: &str) -> Result<()> {
 
 /// Check if a file exists
 pub fn file_exists(path: &str) -> bool {
-    let full_path = PathBuf::from(constants::ROOT_DIR).join(path);
+    let full_path = constants::root_dir().join(path);
     full_path.exists() && full_path.is_file()
 }
 
 /// Write data to a file with ZK verification
 pub fn write_file_with_verification(path: &str, data: &[u8], enable_zk: bool) -> Result<()> {
-    let full_path = PathBuf::from(constants::ROOT_DIR).join(path);
+    let full_path = constants::root_dir().join(path);
     
     // Ensure parent directory exists
     if let Some(parent) = full_path.parent() {
@@ -123,7 +130,7 @@ pub fn write_file_with_verification(path: &str, data: &[u8], enable_zk: bool) ->
     if enable_zk {
         let hash = blake3::hash(data);
         let hash_path = format!("{}.zk.hash", path);
-        let hash_full_path = PathBuf::from(constants::ROOT_DIR).join(&hash_path);
+        let hash_full_path = constants::root_dir().join(&hash_path);
         
         fs::write(hash_full_path, hash.as_bytes())
             .with_context(|| format!("Failed to write ZK hash file for: {:?}", path))?;
@@ -138,7 +145,7 @@ pub fn write_file_with_verification(path: &str, data: &[u8], enable_zk: bool) ->
 
 /// Read a file with ZK verification
 pub fn read_file_with_verification(path: &str, verify_zk: bool) -> Result<Vec<u8>> {
-    let full_path = PathBuf::from(constants::ROOT_DIR).join(path);
+    let full_path = constants::root_dir().join(path);
     
     // Read the file
     let data = fs::read(&full_path)
@@ -149,7 +156,7 @@ pub fn read_file_with_verification(path: &str, verify_zk: bool) -> Result<Vec<u8
         let hash = blake3::hash(&data);
         
         let hash_path = format!("{}.zk.hash", path);
-        let hash_full_path = PathBuf::from(constants::ROOT_DIR).join(&hash_path);
+        let hash_full_path = constants::root_dir().join(&hash_path);
         
         if hash_full_path.exists() {
             let stored_hash = fs::read(&hash_full_path)
@@ -168,3 +175,201 @@ pub fn read_file_with_verification(path: &str, verify_zk: bool) -> Result<Vec<u8
     
     Ok(data)
 }
+
+/// Write a file so that a crash leaves either the old or the new contents
+///
+/// The data goes to a temporary file in the same directory, which is
+/// synced and renamed over `path`. The previous contents are kept next to
+/// it as `<name>.bak` for `read_json_or_backup`.
+pub fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let name = path.file_name()
+        .ok_or_else(|| anyhow::anyhow!("Not a file path: {:?}", path))?
+        .to_string_lossy();
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create parent directory for: {:?}", path))?;
+    
+    let temp_path = unique_temp_path(&dir, &name);
+    let written = (|| -> Result<()> {
+        let mut file = fs::File::create(&temp_path)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        Ok(())
+    })();
+    if let Err(e) = written {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e.context(format!("Failed to write file: {:?}", path)));
+    }
+    
+    // Files written here are never torn, so the current one is a good backup;
+    // it is swapped in whole too, as other threads may be backing it up as well
+    if path.is_file() {
+        let backup_temp_path = unique_temp_path(&dir, &name);
+        let backed_up = fs::copy(path, &backup_temp_path)
+            .and_then(|_| fs::rename(&backup_temp_path, backup_path(path)));
+        if let Err(e) = backed_up {
+            let _ = fs::remove_file(&backup_temp_path);
+            let _ = fs::remove_file(&temp_path);
+            return Err(e).with_context(|| format!("Failed to back up file: {:?}", path));
+        }
+    }
+    
+    fs::rename(&temp_path, path)
+        .with_context(|| format!("Failed to replace file: {:?}", path))?;
+    
+    // Make the rename itself durable
+    #[cfg(unix)]
+    if let Ok(dir) = fs::File::open(&dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
+
+/// Read a JSON file, falling back to its `.bak` copy if it fails to parse
+///
+/// An error is returned only when neither the file nor its backup can be
+/// read; the error of the primary file is reported.
+pub fn read_json_or_backup<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let primary = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read file: {:?}", path))
+        .and_then(|content| serde_json::from_str(&content)
+            .with_context(|| format!("Failed to parse file: {:?}", path)));
+    
+    let error = match primary {
+        Ok(value) => return Ok(value),
+        Err(e) => e,
+    };
+    
+    let backup = backup_path(path);
+    if !backup.is_file() {
+        return Err(error);
+    }
+    match fs::read_to_string(&backup).map_err(anyhow::Error::from)
+        .and_then(|content| Ok(serde_json::from_str(&content)?)) {
+        Ok(value) => {
+            warn!("{:#}; using backup {:?}", error, backup);
+            Ok(value)
+        }
+        Err(e) => {
+            warn!("Backup {:?} is unusable too: {}", backup, e);
+            Err(error)
+        }
+    }
+}
+
+/// Unique temporary file for `write_atomic` next to the file it replaces
+fn unique_temp_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!(".{}.tmp-{}-{}", name, std::process::id(), ATOMIC_WRITES.fetch_add(1, Ordering::Relaxed)))
+}
+
+/// Backup copy kept by `write_atomic`
+pub fn backup_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use std::thread;
+    
+    type Registry = BTreeMap<String, u32>;
+    
+    /// Fresh directory for one test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = constants::root_dir().join("fs-tests").join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    
+    fn registry(entries: &[(&str, u32)]) -> Vec<u8> {
+        let registry: Registry = entries.iter().map(|(k, v)| (k.to_string(), *v)).collect();
+        serde_json::to_vec_pretty(&registry).unwrap()
+    }
+    
+    fn leftover_temp_files(dir: &Path) -> Vec<String> {
+        fs::read_dir(dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|name| name.contains(".tmp-"))
+            .collect()
+    }
+    
+    #[test]
+    fn write_atomic_replaces_the_file_and_keeps_the_previous_one() {
+        let dir = test_dir("replace");
+        let path = dir.join("nested").join("registry.json");
+        
+        write_atomic(&path, &registry(&[("a", 1)])).unwrap();
+        assert!(!backup_path(&path).exists());
+        
+        write_atomic(&path, &registry(&[("a", 2)])).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), registry(&[("a", 2)]));
+        assert_eq!(fs::read(backup_path(&path)).unwrap(), registry(&[("a", 1)]));
+        assert!(leftover_temp_files(path.parent().unwrap()).is_empty());
+    }
+    
+    #[test]
+    fn truncated_registry_is_recovered_from_the_backup() {
+        let dir = test_dir("truncated");
+        let path = dir.join("registry.json");
+        write_atomic(&path, &registry(&[("a", 1)])).unwrap();
+        write_atomic(&path, &registry(&[("a", 1), ("b", 2)])).unwrap();
+        
+        // A plain write cut short by a crash
+        let full = fs::read(&path).unwrap();
+        fs::write(&path, &full[..full.len() / 2]).unwrap();
+        
+        let recovered: Registry = read_json_or_backup(&path).unwrap();
+        assert_eq!(recovered, serde_json::from_slice::<Registry>(&registry(&[("a", 1)])).unwrap());
+        
+        // The next write starts from the recovered contents and leaves a good file
+        write_atomic(&path, &registry(&[("a", 1), ("c", 3)])).unwrap();
+        let reread: Registry = read_json_or_backup(&path).unwrap();
+        assert_eq!(reread.get("c"), Some(&3));
+    }
+    
+    #[test]
+    fn errors_name_the_primary_file_when_the_backup_is_unusable() {
+        let dir = test_dir("unusable");
+        let path = dir.join("registry.json");
+        
+        let missing = read_json_or_backup::<Registry>(&path).unwrap_err();
+        assert!(format!("{:#}", missing).contains("Failed to read file"), "{:#}", missing);
+        
+        fs::write(&path, b"{\"a\": ").unwrap();
+        fs::write(backup_path(&path), b"not json either").unwrap();
+        let error = read_json_or_backup::<Registry>(&path).unwrap_err();
+        let message = format!("{:#}", error);
+        assert!(message.contains("Failed to parse file") && message.contains("registry.json\""), "{}", message);
+    }
+    
+    #[test]
+    fn concurrent_writers_never_share_a_temporary_file() {
+        let dir = test_dir("concurrent");
+        let path = Arc::new(dir.join("registry.json"));
+        
+        let writers: Vec<_> = (0..8u32).map(|writer| {
+            let path = Arc::clone(&path);
+            thread::spawn(move || {
+                for round in 0..25u32 {
+                    write_atomic(&path, &registry(&[("writer", writer), ("round", round)])).unwrap();
+                }
+            })
+        }).collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        
+        let last: Registry = serde_json::from_slice(&fs::read(&*path).unwrap()).unwrap();
+        assert_eq!(last.get("round"), Some(&24));
+        let _: Registry = serde_json::from_slice(&fs::read(backup_path(&path)).unwrap()).unwrap();
+        assert!(leftover_temp_files(&dir).is_empty());
+    }
+}
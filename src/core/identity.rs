@@ -0,0 +1,478 @@
+// SentientOS Node Identity
+// Single source of truth for this node's ID, display name and signing keys
+
+use anyhow::{Result, Context};
+use tracing::{info, debug, warn};
+use std::path::PathBuf;
+use std::fs;
+use std::sync::Mutex;
+use std::time::{SystemTime, Duration, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+use super::constants;
+
+// Constants
+const IDENTITY_FILE: &str = "identity.json";
+const KEYS_DIR: &str = "keys";
+const MAX_NAME_LEN: usize = 64;
+
+// Cached identity
+static IDENTITY: Mutex<Option<Identity>> = Mutex::new(None);
+
+/// Node identity record, stored in `.auth/identity.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Identity {
+    /// Stable node identifier
+    pub id: String,
+    
+    /// Human-friendly display name
+    pub name: String,
+    
+    /// Creation timestamp
+    pub created_at: u64,
+    
+    /// Current public key (hex)
+    pub public_key: String,
+    
+    /// File under `.auth/keys` holding the current signing key
+    pub key_ref: String,
+    
+    /// Key rotation history, oldest first
+    #[serde(default)]
+    pub rotations: Vec<KeyRotation>,
+    
+    /// Node IDs this identity replaced during migration
+    #[serde(default)]
+    pub legacy_ids: Vec<String>,
+    
+    /// Signature over the identity by the current key (hex)
+    pub signature: String,
+}
+
+/// A key rotation, cross-signed by the previous key so peers can follow it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyRotation {
+    /// Public key being retired (hex)
+    pub previous_key: String,
+    
+    /// Public key taking over (hex)
+    pub new_key: String,
+    
+    /// Rotation timestamp
+    pub rotated_at: u64,
+    
+    /// Signature of the previous key over the new key (hex)
+    pub cross_signature: String,
+}
+
+/// Initialize node identity, migrating legacy node IDs on first run
+pub fn init() -> Result<()> {
+    info!("Initializing node identity");
+    
+    fs::create_dir_all(keys_dir())
+        .context("Failed to create .auth/keys directory")?;
+    
+    let identity = if identity_path().exists() {
+        load()?
+    } else {
+        let identity = migrate()?;
+        save(&identity)?;
+        identity
+    };
+    
+    info!("Node identity: {} ({})", identity.id, identity.name);
+    *IDENTITY.lock().unwrap() = Some(identity);
+    Ok(())
+}
+
+/// Current node identity, initializing it if needed
+pub fn current() -> Result<Identity> {
+    if let Some(identity) = IDENTITY.lock().unwrap().as_ref() {
+        return Ok(identity.clone());
+    }
+    
+    init()?;
+    IDENTITY.lock().unwrap().clone()
+        .ok_or_else(|| anyhow::anyhow!("Node identity not initialized"))
+}
+
+/// This node's ID
+pub fn node_id() -> Result<String> {
+    Ok(current()?.id)
+}
+
+/// Change this node's display name and announce it to peers
+pub fn rename(name: &str) -> Result<Identity> {
+    let name = name.trim();
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        anyhow::bail!("Node name must be 1-{} characters", MAX_NAME_LEN);
+    }
+    
+    let mut identity = current()?;
+    let signing_key = load_signing_key(&identity.key_ref)?;
+    
+    identity.name = name.to_string();
+    identity.signature = sign_identity(&identity, &signing_key)?;
+    
+    save(&identity)?;
+    *IDENTITY.lock().unwrap() = Some(identity.clone());
+    info!("Node renamed to: {}", identity.name);
+    
+    announce(&identity);
+    Ok(identity)
+}
+
+/// Replace the signing key, cross-signing the new key with the old one
+pub fn rotate_keys() -> Result<Identity> {
+    let mut identity = current()?;
+    let old_key = load_signing_key(&identity.key_ref)?;
+    
+    let (new_key, key_ref) = generate_signing_key()?;
+    let new_public = to_hex(new_key.verifying_key().as_bytes());
+    
+    identity.rotations.push(KeyRotation {
+        previous_key: identity.public_key.clone(),
+        new_key: new_public.clone(),
+        rotated_at: now()?,
+        cross_signature: to_hex(&old_key.sign(new_public.as_bytes()).to_bytes()),
+    });
+    identity.public_key = new_public;
+    identity.key_ref = key_ref;
+    identity.signature = sign_identity(&identity, &new_key)?;
+    
+    save(&identity)?;
+    *IDENTITY.lock().unwrap() = Some(identity.clone());
+    info!("Rotated signing key for node {}", identity.id);
+    
+    announce(&identity);
+    Ok(identity)
+}
+
+/// Verify an identity announced by a peer
+///
+/// The identity must be signed by its current key. If a key is already known
+/// for the peer and differs, the rotation chain must lead from the known key
+/// to the current one.
+pub fn verify_identity(identity: &Identity, known_key: Option<&str>) -> Result<()> {
+    let payload = signing_payload(identity)?;
+    verify_signature(&identity.public_key, payload.as_bytes(), &identity.signature)
+        .context("Identity signature is invalid")?;
+    
+    let known_key = match known_key {
+        Some(key) if key != identity.public_key => key,
+        _ => return Ok(()),
+    };
+    
+    // Follow the rotation chain from the key we know to the announced one
+    let mut key = known_key.to_string();
+    for rotation in &identity.rotations {
+        if rotation.previous_key != key {
+            continue;
+        }
+        verify_signature(&rotation.previous_key, rotation.new_key.as_bytes(), &rotation.cross_signature)
+            .with_context(|| format!("Invalid cross-signature for rotation at {}", rotation.rotated_at))?;
+        key = rotation.new_key.clone();
+    }
+    
+    if key != identity.public_key {
+        anyhow::bail!("No valid rotation chain from known key to announced key for {}", identity.id);
+    }
+    
+    Ok(())
+}
+
+/// Sign a message with this node's current key, returning the hex signature
+pub fn sign(message: &[u8]) -> Result<String> {
+    let identity = current()?;
+    let signing_key = load_signing_key(&identity.key_ref)?;
+    Ok(to_hex(&signing_key.sign(message).to_bytes()))
+}
+
+/// Verify a hex signature made by the holder of a hex public key
+pub fn verify(public_key: &str, message: &[u8], signature: &str) -> Result<()> {
+    verify_signature(public_key, message, signature)
+}
+
+/// Send this node's signed identity to all known peers
+fn announce(identity: &Identity) {
+    let payload = match serde_json::to_vec(identity) {
+        Ok(payload) => payload,
+        Err(e) => {
+            warn!("Failed to serialize identity announcement: {}", e);
+            return;
+        }
+    };
+    
+    let peers = match crate::gossip::list_peers() {
+        Ok(peers) => peers,
+        Err(e) => {
+            warn!("Failed to list peers for identity announcement: {}", e);
+            return;
+        }
+    };
+    
+    for peer in peers {
+        if let Err(e) = crate::gossip::protocol::send_message(
+            &peer.endpoint,
+            crate::gossip::protocol::MessageType::IdentityAnnouncement,
+            &payload,
+        ) {
+            warn!("Failed to announce identity to {}: {}", peer.id, e);
+        }
+    }
+}
+
+/// Build the first identity, reconciling the legacy node ID sources
+///
+/// system.json is written at first boot, before gossip ever runs, so its ID
+/// wins; the gossip protocol state is used only when system.json has none.
+/// Whichever ID is not chosen is kept in `legacy_ids`.
+fn migrate() -> Result<Identity> {
+    let system_id = legacy_system_id();
+    let gossip_id = legacy_gossip_id();
+    
+    let (id, legacy_ids) = match (system_id, gossip_id) {
+        (Some(system), Some(gossip)) if system != gossip => (system, vec![gossip]),
+        (Some(system), _) => (system, Vec::new()),
+        (None, Some(gossip)) => (gossip, Vec::new()),
+        (None, None) => (generate_node_id(), Vec::new()),
+    };
+    
+    if !legacy_ids.is_empty() {
+        info!("Reconciled node IDs: using {} (replacing {})", id, legacy_ids.join(", "));
+    }
+    
+    let (signing_key, key_ref) = generate_signing_key()?;
+    let mut identity = Identity {
+        name: format!("node-{}", &id[..id.len().min(8)]),
+        id,
+        created_at: now()?,
+        public_key: to_hex(signing_key.verifying_key().as_bytes()),
+        key_ref,
+        rotations: Vec::new(),
+        legacy_ids,
+        signature: String::new(),
+    };
+    identity.signature = sign_identity(&identity, &signing_key)?;
+    
+    Ok(identity)
+}
+
+/// Node ID from system.json, if any
+fn legacy_system_id() -> Option<String> {
+    let path = constants::root_dir().join(".config").join("system.json");
+    let content = fs::read_to_string(path).ok()?;
+    let config: serde_json::Value = serde_json::from_str(&content).ok()?;
+    config.get("node_id")?.as_str().map(String::from)
+}
+
+/// Node ID from the gossip protocol state, if any
+fn legacy_gossip_id() -> Option<String> {
+    let path = constants::root_dir()
+        .join(constants::GOSSIP_DIR)
+        .join("protocol")
+        .join("state.json");
+    let content = fs::read_to_string(path).ok()?;
+    let state: serde_json::Value = serde_json::from_str(&content).ok()?;
+    state.get("node_id")?.as_str().map(String::from)
+}
+
+/// Canonical bytes covered by the identity signature
+fn signing_payload(identity: &Identity) -> Result<String> {
+    Ok(serde_json::to_string(&serde_json::json!({
+        "id": identity.id,
+        "name": identity.name,
+        "created_at": identity.created_at,
+        "public_key": identity.public_key,
+        "rotations": identity.rotations,
+    }))?)
+}
+
+/// Sign an identity with the given key
+fn sign_identity(identity: &Identity, signing_key: &SigningKey) -> Result<String> {
+    let payload = signing_payload(identity)?;
+    Ok(to_hex(&signing_key.sign(payload.as_bytes()).to_bytes()))
+}
+
+/// Verify a hex signature against a hex public key
+fn verify_signature(public_key: &str, message: &[u8], signature: &str) -> Result<()> {
+    let key_bytes: [u8; 32] = from_hex(public_key)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Invalid public key length"))?;
+    let signature_bytes: [u8; 64] = from_hex(signature)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Invalid signature length"))?;
+    
+    let key = VerifyingKey::from_bytes(&key_bytes)?;
+    key.verify(message, &Signature::from_bytes(&signature_bytes))?;
+    Ok(())
+}
+
+/// Generate and store a new signing key, returning it with its key reference
+fn generate_signing_key() -> Result<(SigningKey, String)> {
+    use rand::{thread_rng, Rng};
+    
+    let seed: [u8; 32] = thread_rng().gen();
+    let signing_key = SigningKey::from_bytes(&seed);
+    
+    let key_ref = format!("node-{}.key", SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos());
+    fs::write(keys_dir().join(&key_ref), to_hex(&seed))
+        .with_context(|| format!("Failed to write signing key: {}", key_ref))?;
+    
+    debug!("Generated signing key: {}", key_ref);
+    Ok((signing_key, key_ref))
+}
+
+/// Load a signing key from `.auth/keys`
+fn load_signing_key(key_ref: &str) -> Result<SigningKey> {
+    let content = fs::read_to_string(keys_dir().join(key_ref))
+        .with_context(|| format!("Failed to read signing key: {}", key_ref))?;
+    let seed: [u8; 32] = from_hex(content.trim())?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Invalid signing key: {}", key_ref))?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// Generate a fresh node ID
+fn generate_node_id() -> String {
+    use rand::{thread_rng, Rng};
+    
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_else(|_| Duration::from_secs(0))
+        .as_secs();
+    
+    let random_bytes: [u8; 8] = thread_rng().gen();
+    
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&timestamp.to_le_bytes());
+    hasher.update(&random_bytes);
+    
+    // Use first 16 chars of the hash, matching the legacy ID format
+    hasher.finalize().to_hex()[..16].to_string()
+}
+
+/// Load the identity from disk
+fn load() -> Result<Identity> {
+    let content = fs::read_to_string(identity_path())
+        .context("Failed to read node identity")?;
+    serde_json::from_str(&content).context("Failed to parse node identity")
+}
+
+/// Save the identity to disk
+fn save(identity: &Identity) -> Result<()> {
+    fs::write(identity_path(), serde_json::to_string_pretty(identity)?)
+        .context("Failed to write node identity")
+}
+
+/// Encode bytes as lowercase hex
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decode a hex string
+fn from_hex(hex: &str) -> Result<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        anyhow::bail!("Invalid hex string length");
+    }
+    
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).context("Invalid hex string"))
+        .collect()
+}
+
+/// Current time in seconds since the epoch
+fn now() -> Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+/// Path of the identity file
+fn identity_path() -> PathBuf {
+    constants::root_dir().join(constants::AUTH_DIR).join(IDENTITY_FILE)
+}
+
+/// Path of the keys directory
+fn keys_dir() -> PathBuf {
+    constants::root_dir().join(constants::AUTH_DIR).join(KEYS_DIR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Signing key derived from a fixed seed byte
+    fn key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+    
+    /// Public key of a signing key (hex)
+    fn public(key: &SigningKey) -> String {
+        to_hex(key.verifying_key().as_bytes())
+    }
+    
+    /// Identity that went through the given keys in order, signed by the last
+    fn identity_with(keys: &[SigningKey]) -> Identity {
+        let rotations = keys.windows(2).map(|pair| KeyRotation {
+            previous_key: public(&pair[0]),
+            new_key: public(&pair[1]),
+            rotated_at: 1_700_000_000,
+            cross_signature: to_hex(&pair[0].sign(public(&pair[1]).as_bytes()).to_bytes()),
+        }).collect();
+        
+        let current = keys.last().unwrap();
+        let mut identity = Identity {
+            id: "0123456789abcdef".to_string(),
+            name: "node-01234567".to_string(),
+            created_at: 1_700_000_000,
+            public_key: public(current),
+            key_ref: "node-test.key".to_string(),
+            rotations,
+            legacy_ids: Vec::new(),
+            signature: String::new(),
+        };
+        identity.signature = sign_identity(&identity, current).unwrap();
+        identity
+    }
+    
+    #[test]
+    fn signed_identities_verify_until_tampered_with() {
+        let mut identity = identity_with(&[key(1)]);
+        verify_identity(&identity, None).unwrap();
+        verify_identity(&identity, Some(&identity.public_key.clone())).unwrap();
+        
+        identity.name = "impostor".to_string();
+        assert!(verify_identity(&identity, None).is_err());
+    }
+    
+    #[test]
+    fn rotation_chains_lead_from_known_keys() {
+        let identity = identity_with(&[key(1), key(2), key(3)]);
+        verify_identity(&identity, Some(&public(&key(1)))).unwrap();
+        verify_identity(&identity, Some(&public(&key(2)))).unwrap();
+        
+        let error = verify_identity(&identity, Some(&public(&key(9)))).unwrap_err();
+        assert!(error.to_string().contains("No valid rotation chain"), "{}", error);
+    }
+    
+    #[test]
+    fn forged_rotations_are_rejected() {
+        let mut identity = identity_with(&[key(1), key(2)]);
+        identity.rotations[0].cross_signature = to_hex(&key(2).sign(identity.public_key.as_bytes()).to_bytes());
+        identity.signature = sign_identity(&identity, &key(2)).unwrap();
+        
+        verify_identity(&identity, None).unwrap();
+        assert!(verify_identity(&identity, Some(&public(&key(1)))).is_err());
+    }
+    
+    #[test]
+    fn hex_round_trips_and_rejects_garbage() {
+        let bytes = [0x00, 0x7f, 0xab, 0xff];
+        assert_eq!(to_hex(&bytes), "007fabff");
+        assert_eq!(from_hex("007fabff").unwrap(), bytes);
+        assert!(from_hex("abc").is_err());
+        assert!(from_hex("zz").is_err());
+        assert!(from_hex("é0").is_err());
+    }
+}
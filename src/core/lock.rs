@@ -0,0 +1,485 @@
+// SentientOS Lock Subsystem
+// Named advisory locks materialized under .lock/resources
+
+use anyhow::{Result, Context};
+use tracing::{debug, info, warn};
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::{self, ThreadId};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use crate::core::constants;
+
+/// Directory under LOCK_DIR holding resource locks
+const RESOURCES_DIR: &str = "resources";
+
+/// Default time to wait for a contended lock
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Interval between acquisition attempts when waiting for a lock
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Age after which an unreadable lock file is considered abandoned
+const UNREADABLE_LOCK_GRACE_SECS: u64 = 60;
+
+// Locks held by this process, by name
+static HELD: Mutex<BTreeMap<String, HeldLock>> = Mutex::new(BTreeMap::new());
+
+/// A lock this process holds
+struct HeldLock {
+    /// Thread that acquired it, the only one that may take it again
+    thread: ThreadId,
+    
+    /// Contents of the lock file, which its guards carry too
+    info: LockInfo,
+    
+    /// Guards out for it; the file is removed when the last is dropped
+    holds: usize,
+}
+
+/// Information stored in a lock file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LockInfo {
+    /// Lock name
+    pub name: String,
+    
+    /// PID of the owning process
+    pub pid: u32,
+    
+    /// Acquisition timestamp
+    pub acquired_at: u64,
+    
+    /// Why the lock was taken
+    pub purpose: String,
+}
+
+/// Lock state as reported to operators
+#[derive(Debug, Clone)]
+pub struct LockStatus {
+    /// Lock name
+    pub name: String,
+    
+    /// Lock file contents, if readable
+    pub info: Option<LockInfo>,
+    
+    /// Whether the owning process is no longer alive
+    pub stale: bool,
+}
+
+/// RAII guard releasing a lock when dropped
+#[derive(Debug)]
+pub struct LockGuard {
+    name: String,
+    path: PathBuf,
+    info: LockInfo,
+}
+
+impl LockGuard {
+    /// Name of the held lock
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        // The file stays until the last guard of a re-entered lock is dropped
+        {
+            let mut held = HELD.lock().unwrap();
+            match held.get_mut(&self.name) {
+                Some(lock) if lock.info == self.info && lock.holds > 1 => {
+                    lock.holds -= 1;
+                    debug!("Left re-entered lock: {}", self.name);
+                    return;
+                }
+                Some(lock) if lock.info == self.info => {
+                    held.remove(&self.name);
+                }
+                // Taken again by someone else after it was broken
+                _ => {}
+            }
+        }
+        
+        // Only remove the file if it still belongs to us; it may have been
+        // broken by an operator in the meantime
+        match read_lock_info(&self.path) {
+            Ok(Some(current)) if current == self.info => {
+                if let Err(e) = fs::remove_file(&self.path) {
+                    warn!("Failed to release lock {}: {:?}", self.name, e);
+                } else {
+                    debug!("Released lock: {}", self.name);
+                }
+            }
+            _ => warn!("Lock {} was broken while held", self.name),
+        }
+    }
+}
+
+/// Try to acquire a lock without waiting
+///
+/// Returns `None` if the lock is held by a live process, this one included.
+pub fn try_lock(name: &str, purpose: &str) -> Result<Option<LockGuard>> {
+    acquire(name, purpose, false)
+}
+
+/// Acquire a lock, waiting up to `timeout` for it to become available
+///
+/// The lock is re-entrant: a thread already holding it gets another guard
+/// at once, so a locked operation can call another that takes the same
+/// lock. Other threads of the process wait like other processes do.
+pub fn lock(name: &str, purpose: &str, timeout: Duration) -> Result<LockGuard> {
+    let deadline = Instant::now() + timeout;
+    
+    loop {
+        if let Some(guard) = acquire(name, purpose, true)? {
+            return Ok(guard);
+        }
+        
+        if Instant::now() >= deadline {
+            let holder = read_lock_info(&lock_path(name)).ok().flatten();
+            match holder {
+                Some(info) => anyhow::bail!(
+                    "Timed out waiting for lock {} (held by PID {} for: {})",
+                    name, info.pid, info.purpose
+                ),
+                None => anyhow::bail!("Timed out waiting for lock {}", name),
+            }
+        }
+        
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Acquire a lock if it is free or stale, or held by this thread and `reentrant`
+fn acquire(name: &str, purpose: &str, reentrant: bool) -> Result<Option<LockGuard>> {
+    validate_name(name)?;
+    
+    if reentrant {
+        let mut held = HELD.lock().unwrap();
+        if let Some(lock) = held.get_mut(name).filter(|lock| lock.thread == thread::current().id()) {
+            lock.holds += 1;
+            debug!("Re-entered lock: {} ({})", name, purpose);
+            return Ok(Some(LockGuard { name: name.to_string(), path: lock_path(name), info: lock.info.clone() }));
+        }
+    }
+    
+    let path = lock_path(name);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create lock directory: {:?}", parent))?;
+    }
+    
+    let info = LockInfo {
+        name: name.to_string(),
+        pid: std::process::id(),
+        acquired_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        purpose: purpose.to_string(),
+    };
+    
+    if create_lock_file(&path, &info)? {
+        debug!("Acquired lock: {} ({})", name, purpose);
+        return Ok(Some(hold(name, path, info)));
+    }
+    
+    // Lock is held; take it over if the owner is gone
+    if take_over_if_stale(name, &path)? && create_lock_file(&path, &info)? {
+        info!("Took over stale lock: {} ({})", name, purpose);
+        return Ok(Some(hold(name, path, info)));
+    }
+    
+    Ok(None)
+}
+
+/// Record a lock this thread has just acquired
+fn hold(name: &str, path: PathBuf, info: LockInfo) -> LockGuard {
+    let held = HeldLock { thread: thread::current().id(), info: info.clone(), holds: 1 };
+    HELD.lock().unwrap().insert(name.to_string(), held);
+    LockGuard { name: name.to_string(), path, info }
+}
+
+/// List all resource locks
+pub fn list_locks() -> Result<Vec<LockStatus>> {
+    let resources_dir = resources_dir();
+    if !resources_dir.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let mut locks = Vec::new();
+    for entry in fs::read_dir(&resources_dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("lock") {
+            continue;
+        }
+        
+        let name = match path.file_stem().and_then(|s| s.to_str()) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        
+        let info = read_lock_info(&path).ok().flatten();
+        let stale = is_stale(&path, info.as_ref());
+        locks.push(LockStatus { name, info, stale });
+    }
+    
+    locks.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(locks)
+}
+
+/// Break a lock
+///
+/// Stale locks can always be broken; locks held by a live process require `force`.
+pub fn break_lock(name: &str, force: bool) -> Result<()> {
+    validate_name(name)?;
+    
+    let path = lock_path(name);
+    if !path.exists() {
+        anyhow::bail!("Lock not found: {}", name);
+    }
+    
+    let info = read_lock_info(&path).ok().flatten();
+    if !is_stale(&path, info.as_ref()) && !force {
+        let pid = info.map(|i| i.pid.to_string()).unwrap_or_else(|| "unknown".to_string());
+        anyhow::bail!("Lock {} is held by live process {}; use --force to break it", name, pid);
+    }
+    
+    fs::remove_file(&path)
+        .with_context(|| format!("Failed to remove lock file: {:?}", path))?;
+    
+    warn!("Broke lock: {}", name);
+    Ok(())
+}
+
+/// Atomically create a lock file, returning false if it already exists
+fn create_lock_file(path: &Path, info: &LockInfo) -> Result<bool> {
+    let mut file = match OpenOptions::new().write(true).create_new(true).open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => return Ok(false),
+        Err(e) => return Err(e).with_context(|| format!("Failed to create lock file: {:?}", path)),
+    };
+    
+    file.write_all(serde_json::to_string_pretty(info)?.as_bytes())?;
+    file.sync_all()?;
+    Ok(true)
+}
+
+/// Remove a lock file if its owner is dead
+fn take_over_if_stale(name: &str, path: &Path) -> Result<bool> {
+    let observed = read_lock_info(path).ok().flatten();
+    if !is_stale(path, observed.as_ref()) {
+        return Ok(false);
+    }
+    remove_if_unchanged(name, path, observed.as_ref())
+}
+
+/// Remove a lock file judged stale, provided it still has the owner that was judged
+///
+/// The file is renamed aside before removal so that two processes racing to
+/// take over the same stale lock cannot both succeed. The moved file is read
+/// back: if the lock changed hands between the check and the rename, it
+/// belongs to a live owner and is put back.
+fn remove_if_unchanged(name: &str, path: &Path, observed: Option<&LockInfo>) -> Result<bool> {
+    static TAKEOVERS: AtomicU64 = AtomicU64::new(0);
+    let attempt = TAKEOVERS.fetch_add(1, Ordering::Relaxed);
+    let aside = path.with_extension(format!("lock.stale-{}-{}", std::process::id(), attempt));
+    if fs::rename(path, &aside).is_err() {
+        // Someone else got there first
+        return Ok(false);
+    }
+    
+    let moved = read_lock_info(&aside).ok().flatten();
+    if moved.as_ref() != observed {
+        // Unlike a rename, linking never replaces a lock created since
+        match fs::hard_link(&aside, path) {
+            Ok(()) => {
+                fs::remove_file(&aside).ok();
+                debug!("Lock {} changed hands while being taken over; put it back", name);
+            }
+            Err(e) => warn!("Lock {} changed hands while being taken over and could not be put back from {:?}: {}",
+                            name, aside, e),
+        }
+        return Ok(false);
+    }
+    
+    fs::remove_file(&aside).ok();
+    if let Some(info) = observed {
+        warn!("Removed stale lock {} held by dead PID {}", name, info.pid);
+    }
+    Ok(true)
+}
+
+/// Determine whether a lock is stale
+fn is_stale(path: &Path, info: Option<&LockInfo>) -> bool {
+    match info {
+        Some(info) => !process_alive(info.pid),
+        None => {
+            // Unreadable file: either being written right now or abandoned
+            let age = fs::metadata(path)
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.elapsed().ok())
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            age > UNREADABLE_LOCK_GRACE_SECS
+        }
+    }
+}
+
+/// Check whether a process is alive
+fn process_alive(pid: u32) -> bool {
+    pid == std::process::id() || Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// Read lock information from a lock file
+fn read_lock_info(path: &Path) -> Result<Option<LockInfo>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    
+    let content = fs::read_to_string(path)?;
+    Ok(serde_json::from_str(&content).ok())
+}
+
+/// Reject lock names that would escape the resources directory
+fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.contains('/') || name.contains("..") {
+        anyhow::bail!("Invalid lock name: {}", name);
+    }
+    Ok(())
+}
+
+/// Directory containing resource locks
+fn resources_dir() -> PathBuf {
+    constants::root_dir()
+        .join(constants::LOCK_DIR)
+        .join(RESOURCES_DIR)
+}
+
+/// Path of the lock file for a named lock
+fn lock_path(name: &str) -> PathBuf {
+    resources_dir().join(format!("{}.lock", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// PID that no process has, as `/proc` allows at most 2^22
+    const DEAD_PID: u32 = u32::MAX - 1;
+    
+    fn plant(name: &str, pid: u32) {
+        let path = lock_path(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let info = LockInfo { name: name.to_string(), pid, acquired_at: 0, purpose: "planted".to_string() };
+        fs::write(&path, serde_json::to_string(&info).unwrap()).unwrap();
+    }
+    
+    #[test]
+    fn stale_lock_is_taken_over() {
+        plant("test-stale", DEAD_PID);
+        
+        let guard = try_lock("test-stale", "take over").unwrap().expect("stale lock should be taken over");
+        let info = read_lock_info(&lock_path("test-stale")).unwrap().unwrap();
+        assert_eq!(info.pid, std::process::id());
+        assert_eq!(info.purpose, "take over");
+        
+        drop(guard);
+        assert!(!lock_path("test-stale").exists());
+    }
+    
+    #[test]
+    fn lock_taken_between_check_and_rename_is_put_back() {
+        let name = "test-replaced";
+        let path = lock_path(name);
+        let judged = LockInfo { name: name.to_string(), pid: DEAD_PID, acquired_at: 0, purpose: "planted".to_string() };
+        
+        // Judged stale while the dead PID held it, then taken by a live process
+        plant(name, std::process::id());
+        assert!(!remove_if_unchanged(name, &path, Some(&judged)).unwrap());
+        assert_eq!(read_lock_info(&path).unwrap().unwrap().pid, std::process::id());
+        let set_aside: Vec<_> = fs::read_dir(path.parent().unwrap()).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .filter(|file| file.starts_with(&format!("{}.lock.stale", name)))
+            .collect();
+        assert!(set_aside.is_empty(), "{:?}", set_aside);
+        
+        // Still held by the PID judged dead, it is removed
+        plant(name, DEAD_PID);
+        assert!(remove_if_unchanged(name, &path, Some(&judged)).unwrap());
+        assert!(!path.exists());
+    }
+    
+    #[test]
+    fn live_lock_is_not_taken_over() {
+        // This process is alive, but holds no guard for the lock
+        plant("test-live", std::process::id());
+        
+        assert!(try_lock("test-live", "contend").unwrap().is_none());
+        assert!(lock("test-live", "contend", Duration::from_millis(100)).is_err());
+        assert_eq!(read_lock_info(&lock_path("test-live")).unwrap().unwrap().purpose, "planted");
+    }
+    
+    #[test]
+    fn recent_unreadable_lock_is_not_stale() {
+        let path = lock_path("test-unreadable");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "{").unwrap();
+        
+        assert!(!is_stale(&path, None));
+        assert!(try_lock("test-unreadable", "contend").unwrap().is_none());
+    }
+    
+    #[test]
+    fn lock_is_reentrant_within_a_thread() {
+        let outer = lock("test-reentrant", "outer", Duration::from_millis(100)).unwrap();
+        let inner = lock("test-reentrant", "inner", Duration::from_millis(100)).unwrap();
+        
+        drop(inner);
+        assert!(lock_path("test-reentrant").exists(), "the outer guard still holds the lock");
+        drop(outer);
+        assert!(!lock_path("test-reentrant").exists());
+    }
+    
+    #[test]
+    fn try_lock_is_not_reentrant() {
+        let _guard = lock("test-try", "outer", Duration::from_millis(100)).unwrap();
+        assert!(try_lock("test-try", "inner").unwrap().is_none());
+    }
+    
+    #[test]
+    fn other_threads_wait_for_the_lock() {
+        let guard = lock("test-threads", "main", Duration::from_millis(100)).unwrap();
+        
+        let waited = thread::spawn(|| lock("test-threads", "other", Duration::from_millis(150)).map(|_| ()))
+            .join()
+            .unwrap();
+        assert!(waited.is_err());
+        
+        drop(guard);
+        let taken = thread::spawn(|| lock("test-threads", "other", Duration::from_millis(150)).map(|_| ()))
+            .join()
+            .unwrap();
+        assert!(taken.is_ok());
+    }
+    
+    #[test]
+    fn broken_lock_is_not_removed_by_its_old_guard() {
+        let guard = lock("test-broken", "first", Duration::from_millis(100)).unwrap();
+        break_lock("test-broken", true).unwrap();
+        plant("test-broken", std::process::id());
+        
+        drop(guard);
+        assert_eq!(read_lock_info(&lock_path("test-broken")).unwrap().unwrap().purpose, "planted");
+    }
+    
+    #[test]
+    fn names_cannot_escape_the_resources_dir() {
+        assert!(try_lock("../escape", "escape").is_err());
+        assert!(try_lock("a/b", "escape").is_err());
+        assert!(try_lock("", "escape").is_err());
+    }
+}
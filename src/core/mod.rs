@@ -3,15 +3,31 @@
 
 pub mod fs;
 pub mod error;
+pub mod lock;
+pub mod identity;
+pub mod plan;
+pub mod workers;
 
 /// Core system constants
 pub mod constants {
-    use std::path::Path;
-
-    /// Root directory of SentientOS
-    pub const ROOT_DIR: &str = "/home/umesh/Sentinent_os";
+    use std::path::{Path, PathBuf};
+    use std::sync::OnceLock;
+    
+    /// Environment variable naming the root directory
+    pub const ROOT_ENV: &str = "SENTIENT_ROOT";
+    
+    /// File under the home directory naming the root directory
+    pub const ROOT_FILE: &str = ".config/sentientos/root";
+    
+    /// Root directory under the home directory when nothing names one
+    pub const DEFAULT_ROOT_DIR: &str = ".sentientos";
+    
+    // Root directory, resolved on first use
+    static ROOT: OnceLock<PathBuf> = OnceLock::new();
     
     /// Core system directories
+    pub const CORE_DIR: &str = ".core";
+    pub const ZK_DIR: &str = ".zk";
     pub const RUNTIME_DIR: &str = ".runtime";
     pub const LOCK_DIR: &str = ".lock";
     pub const AUTH_DIR: &str = ".auth";
@@ -24,8 +40,112 @@ pub mod constants {
     pub const ZERO_DIR: &str = ".zero";
     pub const UNSECURE_DIR: &str = ".unsecure";
     
+    /// Root directory of SentientOS
+    ///
+    /// Resolved once per process from `SENTIENT_ROOT`, then the first line of
+    /// `~/.config/sentientos/root`, then `~/.sentientos`. Unit tests get a
+    /// fresh directory under the system temp dir instead.
+    pub fn root_dir() -> PathBuf {
+        ROOT.get_or_init(resolve_root).clone()
+    }
+    
     /// Get the absolute path to a SentientOS directory
     pub fn get_path(dir: &str) -> String {
-        Path::new(ROOT_DIR).join(dir).to_string_lossy().to_string()
+        root_dir().join(dir).to_string_lossy().to_string()
+    }
+    
+    fn resolve_root() -> PathBuf {
+        if cfg!(test) {
+            return std::env::temp_dir().join(format!("sentientos-test-{}", std::process::id()));
+        }
+        
+        let home = std::env::var_os("HOME").filter(|h| !h.is_empty()).map(PathBuf::from);
+        resolve_root_from(std::env::var_os(ROOT_ENV), home)
+    }
+    
+    /// Root named by the `SENTIENT_ROOT` value, the root file under `home`, or the default
+    fn resolve_root_from(env_root: Option<std::ffi::OsString>, home: Option<PathBuf>) -> PathBuf {
+        if let Some(root) = env_root.filter(|r| !r.is_empty()) {
+            return absolute(PathBuf::from(root));
+        }
+        
+        if let Some(home) = &home {
+            let configured = std::fs::read_to_string(home.join(ROOT_FILE)).ok()
+                .and_then(|content| content.lines().map(str::trim).find(|l| !l.is_empty()).map(String::from));
+            if let Some(root) = configured {
+                return match root.strip_prefix("~/") {
+                    Some(rest) => home.join(rest),
+                    None => absolute(PathBuf::from(root)),
+                };
+            }
+        }
+        
+        match home {
+            Some(home) => home.join(DEFAULT_ROOT_DIR),
+            None => Path::new("/var/lib/sentientos").to_path_buf(),
+        }
+    }
+    
+    /// Paths are compared by prefix elsewhere, so relative roots are anchored here
+    fn absolute(path: PathBuf) -> PathBuf {
+        if path.is_absolute() {
+            return path;
+        }
+        std::env::current_dir().map(|dir| dir.join(&path)).unwrap_or(path)
+    }
+    
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use std::fs;
+        
+        /// Home directory of its own under the test root
+        fn home(name: &str, root_file: Option<&str>) -> PathBuf {
+            let home = root_dir().join("homes").join(name);
+            let _ = fs::remove_dir_all(&home);
+            fs::create_dir_all(&home).unwrap();
+            if let Some(content) = root_file {
+                let path = home.join(ROOT_FILE);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, content).unwrap();
+            }
+            home
+        }
+        
+        #[test]
+        fn env_root_comes_first() {
+            let home = home("env", Some("/srv/from-file\n"));
+            assert_eq!(resolve_root_from(Some("/srv/from-env".into()), Some(home)), Path::new("/srv/from-env"));
+        }
+        
+        #[test]
+        fn relative_env_root_is_anchored_at_the_working_directory() {
+            let root = resolve_root_from(Some("instance-a".into()), None);
+            assert!(root.is_absolute());
+            assert_eq!(root, std::env::current_dir().unwrap().join("instance-a"));
+        }
+        
+        #[test]
+        fn root_file_is_used_without_the_env_root() {
+            let home_dir = home("file", Some("\n  /srv/from-file  \nignored\n"));
+            assert_eq!(resolve_root_from(Some("".into()), Some(home_dir)), Path::new("/srv/from-file"));
+            
+            let home_dir = home("tilde", Some("~/instances/b\n"));
+            assert_eq!(resolve_root_from(None, Some(home_dir.clone())), home_dir.join("instances/b"));
+        }
+        
+        #[test]
+        fn default_root_is_under_home() {
+            let home_dir = home("default", Some("\n\n"));
+            assert_eq!(resolve_root_from(None, Some(home_dir.clone())), home_dir.join(DEFAULT_ROOT_DIR));
+            assert_eq!(resolve_root_from(None, None), Path::new("/var/lib/sentientos"));
+        }
+        
+        #[test]
+        fn tests_run_in_a_temporary_root() {
+            let root = root_dir();
+            assert!(root.starts_with(std::env::temp_dir()));
+            assert_eq!(get_path(HEAL_DIR), root.join(HEAL_DIR).to_string_lossy());
+        }
     }
 }
@@ -0,0 +1,194 @@
+// SentientOS Change Plans
+// Structured descriptions of what a destructive operation is about to do
+
+use anyhow::Result;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+
+/// Version of the plan JSON format; bump when fields change meaning
+pub const PLAN_FORMAT_VERSION: u32 = 1;
+
+/// Ordered list of changes an operation will make
+///
+/// Destructive operations build a plan first and then execute its actions,
+/// so a `--dry-run` preview always matches what a real run would do.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Plan {
+    /// Plan format version
+    pub format_version: u32,
+    
+    /// Operation the plan was built for
+    pub operation: String,
+    
+    /// Actions in execution order
+    pub actions: Vec<PlannedAction>,
+}
+
+/// A single planned change
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum PlannedAction {
+    /// Stop a running container
+    StopContainer { id: String, name: String },
+    
+    /// Restart a subsystem after the change
+    RestartSubsystem { subsystem: String },
+    
+    /// Remove an entry from a registry
+    RemoveRegistryEntry { registry: String, entry: String },
+    
+    /// Move an item into the trash, with its data if any
+    MoveToTrash { item: String, path: Option<String>, bytes: u64 },
+    
+    /// Permanently delete files
+    DeleteFiles { path: String, bytes: u64 },
+    
+    /// Overwrite files with a copy from elsewhere
+    RestoreFiles { component: String, source: String, target: String, bytes: u64 },
+    
+    /// Run an external uninstaller
+    RunUninstaller { ecosystem: String, package: String },
+}
+
+impl Plan {
+    /// Create an empty plan for an operation
+    pub fn new(operation: &str) -> Self {
+        Self {
+            format_version: PLAN_FORMAT_VERSION,
+            operation: operation.to_string(),
+            actions: Vec::new(),
+        }
+    }
+    
+    /// Append an action
+    pub fn push(&mut self, action: PlannedAction) {
+        self.actions.push(action);
+    }
+    
+    /// Whether the plan makes no changes
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+    
+    /// Total bytes deleted, moved or overwritten by the plan
+    pub fn total_bytes(&self) -> u64 {
+        self.actions.iter().map(|action| match action {
+            PlannedAction::MoveToTrash { bytes, .. }
+            | PlannedAction::DeleteFiles { bytes, .. }
+            | PlannedAction::RestoreFiles { bytes, .. } => *bytes,
+            _ => 0,
+        }).sum()
+    }
+    
+    /// Stable JSON rendering, suitable for diffing plans across runs
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Plan for: {}", self.operation)?;
+        
+        if self.actions.is_empty() {
+            return write!(f, "  (no changes)");
+        }
+        
+        for (i, action) in self.actions.iter().enumerate() {
+            writeln!(f, "  {}. {}", i + 1, action)?;
+        }
+        
+        write!(f, "{} action(s), {} byte(s) affected", self.actions.len(), self.total_bytes())
+    }
+}
+
+impl fmt::Display for PlannedAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlannedAction::StopContainer { id, name } => write!(f, "stop container {} ({})", name, id),
+            PlannedAction::RestartSubsystem { subsystem } => write!(f, "restart {}", subsystem),
+            PlannedAction::RemoveRegistryEntry { registry, entry } => write!(f, "remove {} from {} registry", entry, registry),
+            PlannedAction::MoveToTrash { item, path: Some(path), bytes } => write!(f, "move {} to trash: {} ({} bytes)", item, path, bytes),
+            PlannedAction::MoveToTrash { item, path: None, .. } => write!(f, "record {} in trash (no data)", item),
+            PlannedAction::DeleteFiles { path, bytes } => write!(f, "delete {} ({} bytes)", path, bytes),
+            PlannedAction::RestoreFiles { component, source, target, bytes } => {
+                write!(f, "restore {} from {} to {} ({} bytes)", component, source, target, bytes)
+            }
+            PlannedAction::RunUninstaller { ecosystem, package } => write!(f, "uninstall {} with {} tooling", package, ecosystem),
+        }
+    }
+}
+
+/// Size of a file or directory tree in bytes, or 0 if it cannot be read
+pub fn path_size(path: &Path) -> u64 {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return 0,
+    };
+    
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    
+    fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|entry| path_size(&entry.path())).sum())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Plan touching files, an entry and a subsystem
+    fn sample_plan() -> Plan {
+        let mut plan = Plan::new("store remove demo");
+        plan.push(PlannedAction::RemoveRegistryEntry { registry: "store".to_string(), entry: "demo".to_string() });
+        plan.push(PlannedAction::MoveToTrash { item: "demo".to_string(), path: Some(".store/demo".to_string()), bytes: 40 });
+        plan.push(PlannedAction::DeleteFiles { path: ".store/cache/demo".to_string(), bytes: 2 });
+        plan.push(PlannedAction::RestartSubsystem { subsystem: "store".to_string() });
+        plan
+    }
+    
+    #[test]
+    fn plans_render_their_actions_in_order() {
+        let plan = sample_plan();
+        assert_eq!(plan.total_bytes(), 42);
+        assert_eq!(plan.to_string(), "Plan for: store remove demo\n\
+            \x20 1. remove demo from store registry\n\
+            \x20 2. move demo to trash: .store/demo (40 bytes)\n\
+            \x20 3. delete .store/cache/demo (2 bytes)\n\
+            \x20 4. restart store\n\
+            4 action(s), 42 byte(s) affected");
+        
+        let empty = Plan::new("trash empty");
+        assert!(empty.is_empty());
+        assert_eq!(empty.to_string(), "Plan for: trash empty\n  (no changes)");
+    }
+    
+    #[test]
+    fn plan_json_tags_each_action() {
+        let plan = sample_plan();
+        let json: serde_json::Value = serde_json::from_str(&plan.to_json().unwrap()).unwrap();
+        assert_eq!(json["format_version"], PLAN_FORMAT_VERSION);
+        assert_eq!(json["actions"][1]["action"], "move_to_trash");
+        assert_eq!(json["actions"][1]["bytes"], 40);
+        
+        let parsed: Plan = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.actions, plan.actions);
+    }
+    
+    #[test]
+    fn path_sizes_sum_directory_trees() {
+        let dir = super::super::constants::root_dir().join("plan_size");
+        fs::create_dir_all(dir.join("nested")).unwrap();
+        fs::write(dir.join("a"), [0u8; 10]).unwrap();
+        fs::write(dir.join("nested").join("b"), [0u8; 5]).unwrap();
+        
+        assert_eq!(path_size(&dir), 15);
+        assert_eq!(path_size(&dir.join("a")), 10);
+        assert_eq!(path_size(&dir.join("missing")), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
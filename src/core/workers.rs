@@ -0,0 +1,622 @@
+// SentientOS Worker Pool
+// Shared, bounded pool for background tasks with per-kind limits and priorities
+
+use anyhow::{Result, Context};
+use tracing::{info, debug, warn};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::PathBuf;
+use std::fs;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use super::constants;
+
+// Constants
+const STATUS_FILE: &str = "workers.json";
+const MAX_RECENT: usize = 50;
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
+
+/// Priority of a task kind; higher priorities are dispatched first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    /// Run when nothing else is waiting
+    Low,
+    
+    /// Default
+    Normal,
+    
+    /// Run ahead of everything else
+    High,
+}
+
+/// Limits of one task kind
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KindConfig {
+    /// Tasks of this kind running at once
+    pub max_concurrent: usize,
+    
+    /// Dispatch priority
+    pub priority: Priority,
+}
+
+impl Default for KindConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 1,
+            priority: Priority::Normal,
+        }
+    }
+}
+
+/// Pool settings, stored under `workers` in system.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorkerConfig {
+    /// Tasks running at once
+    pub parallelism: usize,
+    
+    /// Tasks running at once under the low-power policy
+    pub low_power_parallelism: usize,
+    
+    /// Limits by task kind; unlisted kinds use the defaults
+    pub kinds: BTreeMap<String, KindConfig>,
+}
+
+impl Default for WorkerConfig {
+    fn default() -> Self {
+        Self {
+            parallelism: 4,
+            low_power_parallelism: 1,
+            // Sweeps and prefetches can wait behind anything interactive
+            kinds: ["integrity_sweep", "prefetch"].into_iter()
+                .map(|kind| (kind.to_string(), KindConfig { priority: Priority::Low, ..Default::default() }))
+                .collect(),
+        }
+    }
+}
+
+/// Cooperative cancellation, set when the pool shuts down
+///
+/// Long tasks should check it between units of work and return early.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Whether the task should stop
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Work of a queued task
+type TaskFn = Box<dyn FnOnce(&CancelToken) -> Result<()> + Send>;
+
+/// A task waiting to run
+struct QueuedTask {
+    id: u64,
+    kind: String,
+    name: String,
+    priority: Priority,
+    task: TaskFn,
+}
+
+/// A task currently running
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunningTask {
+    /// Task ID
+    pub id: u64,
+    
+    /// Task kind
+    pub kind: String,
+    
+    /// Task name
+    pub name: String,
+    
+    /// When the task started
+    pub started_at: u64,
+}
+
+/// A finished task
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Completion {
+    /// Task ID
+    pub id: u64,
+    
+    /// Task kind
+    pub kind: String,
+    
+    /// Task name
+    pub name: String,
+    
+    /// Error, if the task failed
+    pub error: Option<String>,
+    
+    /// How long the task ran, in seconds
+    pub elapsed_secs: f64,
+    
+    /// When the task finished
+    pub finished_at: u64,
+}
+
+/// Queue state of one task kind
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KindStatus {
+    /// Task kind
+    pub kind: String,
+    
+    /// Tasks waiting
+    pub queued: usize,
+    
+    /// Tasks running
+    pub running: usize,
+    
+    /// Limits of the kind
+    pub config: KindConfig,
+    
+    /// Tasks completed since start
+    pub completed: u64,
+    
+    /// Tasks failed since start
+    pub failed: u64,
+}
+
+/// Snapshot of the pool
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolStatus {
+    /// Tasks allowed to run at once
+    pub parallelism: usize,
+    
+    /// Whether the low-power policy is shrinking the pool
+    pub low_power: bool,
+    
+    /// State by task kind
+    pub kinds: Vec<KindStatus>,
+    
+    /// Running tasks, longest running first
+    pub running: Vec<RunningTask>,
+    
+    /// Recently finished tasks, newest first
+    pub recent: Vec<Completion>,
+}
+
+/// Pool state shared with the worker threads
+#[derive(Default)]
+struct PoolState {
+    accepting: bool,
+    config: WorkerConfig,
+    low_power: bool,
+    threads: usize,
+    next_id: u64,
+    queue: Vec<QueuedTask>,
+    running: HashMap<u64, (String, String, u64)>,
+    counts: HashMap<String, (u64, u64)>,
+    recent: VecDeque<Completion>,
+}
+
+impl PoolState {
+    /// Tasks allowed to run at once under the current policy
+    fn parallelism(&self) -> usize {
+        let limit = if self.low_power { self.config.low_power_parallelism } else { self.config.parallelism };
+        limit.max(1)
+    }
+    
+    fn kind_config(&self, kind: &str) -> KindConfig {
+        self.config.kinds.get(kind).cloned().unwrap_or_default()
+    }
+    
+    fn running_of(&self, kind: &str) -> usize {
+        self.running.values().filter(|(k, _, _)| k == kind).count()
+    }
+    
+    /// Index of the next task allowed to run: highest priority, then oldest
+    fn next_dispatchable(&self) -> Option<usize> {
+        if self.running.len() >= self.parallelism() {
+            return None;
+        }
+        
+        self.queue.iter()
+            .enumerate()
+            .filter(|(_, t)| self.running_of(&t.kind) < self.kind_config(&t.kind).max_concurrent.max(1))
+            .max_by(|(ia, a), (ib, b)| a.priority.cmp(&b.priority).then(ib.cmp(ia)))
+            .map(|(i, _)| i)
+    }
+    
+    fn queued_of(&self, kind: &str) -> usize {
+        self.queue.iter().filter(|t| t.kind == kind).count()
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref POOL: Arc<(Mutex<PoolState>, Condvar)> = Arc::new((Mutex::new(PoolState::default()), Condvar::new()));
+    static ref CANCEL: Mutex<CancelToken> = Mutex::new(CancelToken::default());
+}
+
+/// Start the worker pool
+pub fn init() -> Result<()> {
+    info!("Initializing worker pool");
+    
+    let config = load_config()?;
+    let low_power = crate::boot::load_boot_config().map(|c| c.iot.low_power).unwrap_or(false);
+    
+    {
+        let (lock, _) = &**POOL;
+        let mut state = lock.lock().unwrap();
+        if state.accepting {
+            return Ok(());
+        }
+        state.accepting = true;
+        state.config = config;
+        state.low_power = low_power;
+    }
+    *CANCEL.lock().unwrap() = CancelToken::default();
+    ensure_threads();
+    publish();
+    
+    info!("Worker pool initialized (parallelism: {})", POOL.0.lock().unwrap().parallelism());
+    Ok(())
+}
+
+/// Stop the worker pool
+///
+/// Running tasks are asked to cancel and given a short grace period;
+/// tasks still queued are dropped.
+pub fn shutdown() -> Result<()> {
+    info!("Shutting down worker pool");
+    
+    let (lock, condvar) = &**POOL;
+    let dropped = {
+        let mut state = lock.lock().unwrap();
+        state.accepting = false;
+        state.threads = 0;
+        let dropped = state.queue.len();
+        state.queue.clear();
+        dropped
+    };
+    CANCEL.lock().unwrap().0.store(true, Ordering::SeqCst);
+    condvar.notify_all();
+    
+    if dropped > 0 {
+        warn!("Dropped {} queued background task(s) at shutdown", dropped);
+    }
+    
+    let deadline = Instant::now() + SHUTDOWN_GRACE;
+    let mut state = lock.lock().unwrap();
+    while !state.running.is_empty() && Instant::now() < deadline {
+        state = condvar.wait_timeout(state, Duration::from_millis(100)).unwrap().0;
+    }
+    if !state.running.is_empty() {
+        warn!("{} background task(s) still running after shutdown grace period", state.running.len());
+    }
+    drop(state);
+    publish();
+    
+    info!("Worker pool shutdown complete");
+    Ok(())
+}
+
+/// Queue a task of a kind, returning its ID
+pub fn submit<F>(kind: &str, name: &str, task: F) -> Result<u64>
+where
+    F: FnOnce(&CancelToken) -> Result<()> + Send + 'static,
+{
+    let (lock, condvar) = &**POOL;
+    let mut state = lock.lock().unwrap();
+    if !state.accepting {
+        anyhow::bail!("Worker pool is not running; cannot queue {} task {}", kind, name);
+    }
+    
+    state.next_id += 1;
+    let id = state.next_id;
+    let priority = state.kind_config(kind).priority;
+    state.queue.push(QueuedTask {
+        id,
+        kind: kind.to_string(),
+        name: name.to_string(),
+        priority,
+        task: Box::new(task),
+    });
+    record_depth(&state, kind);
+    drop(state);
+    
+    condvar.notify_all();
+    publish();
+    debug!("Queued {} task {} ({})", kind, name, id);
+    Ok(id)
+}
+
+/// Apply or lift the low-power policy; queued tasks are kept either way
+pub fn set_low_power(low_power: bool) {
+    let (lock, condvar) = &**POOL;
+    {
+        let mut state = lock.lock().unwrap();
+        if state.low_power == low_power {
+            return;
+        }
+        state.low_power = low_power;
+        info!("Worker pool parallelism now {} (low power: {})", state.parallelism(), low_power);
+    }
+    
+    ensure_threads();
+    condvar.notify_all();
+    publish();
+}
+
+/// Pool status last published by the daemon, for other processes
+pub fn load_status() -> Result<PoolStatus> {
+    let path = status_path();
+    let data = fs::read_to_string(&path)
+        .with_context(|| format!("No worker pool status at {:?}; is the daemon running?", path))?;
+    serde_json::from_str(&data).context("Invalid worker pool status")
+}
+
+/// Snapshot of queues, running tasks and recent completions
+pub fn status() -> PoolStatus {
+    let state = POOL.0.lock().unwrap();
+    
+    let mut kinds: Vec<String> = state.config.kinds.keys().cloned()
+        .chain(state.queue.iter().map(|t| t.kind.clone()))
+        .chain(state.running.values().map(|(k, _, _)| k.clone()))
+        .chain(state.counts.keys().cloned())
+        .collect();
+    kinds.sort();
+    kinds.dedup();
+    
+    let mut running: Vec<RunningTask> = state.running.iter()
+        .map(|(id, (kind, name, started))| RunningTask {
+            id: *id,
+            kind: kind.clone(),
+            name: name.clone(),
+            started_at: *started,
+        })
+        .collect();
+    running.sort_by_key(|t| t.started_at);
+    
+    PoolStatus {
+        parallelism: state.parallelism(),
+        low_power: state.low_power,
+        kinds: kinds.into_iter()
+            .map(|kind| {
+                let (completed, failed) = state.counts.get(&kind).copied().unwrap_or_default();
+                KindStatus {
+                    queued: state.queued_of(&kind),
+                    running: state.running_of(&kind),
+                    config: state.kind_config(&kind),
+                    completed,
+                    failed,
+                    kind,
+                }
+            })
+            .collect(),
+        running,
+        recent: state.recent.iter().rev().cloned().collect(),
+    }
+}
+
+/// Spawn worker threads up to the current parallelism
+///
+/// Threads are never stopped when the pool shrinks; surplus threads just
+/// stay idle because dispatch respects the current limit.
+fn ensure_threads() {
+    let (lock, _) = &**POOL;
+    let mut state = lock.lock().unwrap();
+    while state.threads < state.parallelism() {
+        state.threads += 1;
+        let index = state.threads;
+        thread::Builder::new()
+            .name(format!("sentient-worker-{}", index))
+            .spawn(worker_loop)
+            .map_err(|e| warn!("Failed to start worker thread: {}", e))
+            .ok();
+    }
+}
+
+/// Run tasks until the pool shuts down
+fn worker_loop() {
+    let (lock, condvar) = &**POOL;
+    loop {
+        let task = {
+            let mut state = lock.lock().unwrap();
+            loop {
+                if !state.accepting {
+                    return;
+                }
+                if let Some(index) = state.next_dispatchable() {
+                    let task = state.queue.remove(index);
+                    state.running.insert(task.id, (task.kind.clone(), task.name.clone(), now()));
+                    record_depth(&state, &task.kind);
+                    break task;
+                }
+                state = condvar.wait(state).unwrap();
+            }
+        };
+        publish();
+        
+        let token = CANCEL.lock().unwrap().clone();
+        let started = Instant::now();
+        // A panicking task fails on its own instead of taking the worker down
+        let work = task.task;
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| work(&token)))
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("task panicked")));
+        finish(task.id, &task.kind, &task.name, started.elapsed(), result);
+        condvar.notify_all();
+        publish();
+    }
+}
+
+/// Record the outcome of a task
+fn finish(id: u64, kind: &str, name: &str, elapsed: Duration, result: Result<()>) {
+    let error = result.err().map(|e| format!("{:#}", e));
+    match &error {
+        Some(e) => {
+            warn!("Background task {} ({}) failed: {}", name, kind, e);
+            crate::logs::metrics::increment(&format!("workers.{}.failed", kind));
+        }
+        None => crate::logs::metrics::increment(&format!("workers.{}.completed", kind)),
+    }
+    
+    let mut state = POOL.0.lock().unwrap();
+    state.running.remove(&id);
+    let counts = state.counts.entry(kind.to_string()).or_default();
+    if error.is_some() {
+        counts.1 += 1;
+    } else {
+        counts.0 += 1;
+    }
+    
+    state.recent.push_back(Completion {
+        id,
+        kind: kind.to_string(),
+        name: name.to_string(),
+        error,
+        elapsed_secs: elapsed.as_secs_f64(),
+        finished_at: now(),
+    });
+    while state.recent.len() > MAX_RECENT {
+        state.recent.pop_front();
+    }
+}
+
+/// Write the pool status for `sentctl runtime workers`
+fn publish() {
+    let path = status_path();
+    let result = path.parent().map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(&path, serde_json::to_string_pretty(&status()).unwrap_or_default()));
+    if let Err(e) = result {
+        debug!("Failed to publish worker pool status: {}", e);
+    }
+}
+
+/// Path of the published pool status
+fn status_path() -> PathBuf {
+    constants::root_dir().join(constants::RUNTIME_DIR).join(STATUS_FILE)
+}
+
+/// Publish the queue depth of a kind
+fn record_depth(state: &PoolState, kind: &str) {
+    crate::logs::metrics::record(&format!("workers.{}.queue_depth", kind), state.queued_of(kind) as f64);
+}
+
+/// Load the pool configuration from system.json
+fn load_config() -> Result<WorkerConfig> {
+    let path = constants::root_dir().join(".config").join("system.json");
+    if !path.exists() {
+        return Ok(WorkerConfig::default());
+    }
+    
+    let system_config: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    match system_config.get("workers") {
+        Some(value) => Ok(serde_json::from_value(value.clone())
+            .context("Invalid workers configuration in system.json")?),
+        None => Ok(WorkerConfig::default()),
+    }
+}
+
+/// Current time in seconds since epoch
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn queued(id: u64, kind: &str, priority: Priority) -> QueuedTask {
+        QueuedTask {
+            id,
+            kind: kind.to_string(),
+            name: format!("task {}", id),
+            priority,
+            task: Box::new(|_| Ok(())),
+        }
+    }
+    
+    /// Dispatch tasks from a state the way the workers do, without running them
+    fn dispatch_all(state: &mut PoolState) -> Vec<u64> {
+        let mut dispatched = Vec::new();
+        while let Some(index) = state.next_dispatchable() {
+            let task = state.queue.remove(index);
+            state.running.insert(task.id, (task.kind.clone(), task.name.clone(), 0));
+            dispatched.push(task.id);
+        }
+        dispatched
+    }
+    
+    #[test]
+    fn dispatch_prefers_priority_then_age_within_limits() {
+        let mut state = PoolState { config: WorkerConfig { parallelism: 3, ..Default::default() }, ..Default::default() };
+        state.queue = vec![
+            queued(1, "integrity_sweep", Priority::Low),
+            queued(2, "snapshot", Priority::Normal),
+            queued(3, "snapshot", Priority::Normal),
+            queued(4, "proof_verify", Priority::High),
+            queued(5, "attestation", Priority::Normal),
+        ];
+        
+        // One snapshot at a time, so the second waits behind the later attestation
+        assert_eq!(dispatch_all(&mut state), [4, 2, 5]);
+        
+        state.running.remove(&2);
+        state.running.remove(&5);
+        assert_eq!(dispatch_all(&mut state), [3, 1]);
+        assert!(state.queue.is_empty());
+    }
+    
+    #[test]
+    fn low_power_shrinks_the_pool() {
+        let mut state = PoolState::default();
+        assert_eq!(state.parallelism(), 4);
+        state.low_power = true;
+        assert_eq!(state.parallelism(), 1);
+        
+        state.queue = vec![queued(1, "a", Priority::Normal), queued(2, "b", Priority::Normal)];
+        assert_eq!(dispatch_all(&mut state), [1]);
+        
+        state.config.low_power_parallelism = 0;
+        state.running.clear();
+        assert_eq!(dispatch_all(&mut state), [2]);
+    }
+    
+    #[test]
+    fn tasks_run_fail_and_cancel_in_the_shared_pool() {
+        fn wait_for(condition: impl Fn(&PoolStatus) -> bool) {
+            let deadline = Instant::now() + Duration::from_secs(10);
+            while !condition(&status()) {
+                assert!(Instant::now() < deadline, "worker pool did not settle: {:?}", status());
+                thread::sleep(Duration::from_millis(10));
+            }
+        }
+        let counts = |status: &PoolStatus, kind: &str| status.kinds.iter()
+            .find(|k| k.kind == kind)
+            .map_or((0, 0), |k| (k.completed, k.failed));
+        
+        init().unwrap();
+        submit("workers_test", "succeeds", |_| Ok(())).unwrap();
+        submit("workers_test", "fails", |_| anyhow::bail!("disk full")).unwrap();
+        submit("workers_test", "panics", |_| panic!("worker test panic")).unwrap();
+        wait_for(|status| counts(status, "workers_test") == (1, 2));
+        
+        let recent = status().recent;
+        let failure = recent.iter().find(|c| c.name == "fails").unwrap();
+        assert_eq!(failure.error.as_deref(), Some("disk full"));
+        assert_eq!(recent.iter().find(|c| c.name == "panics").unwrap().error.as_deref(), Some("task panicked"));
+        assert_eq!(load_status().unwrap().parallelism, status().parallelism);
+        
+        // Shutdown cancels the running task and refuses new ones
+        let (started, started_rx) = std::sync::mpsc::channel();
+        submit("workers_test_cancel", "waits for cancel", move |token| {
+            started.send(()).unwrap();
+            while !token.is_cancelled() {
+                thread::sleep(Duration::from_millis(5));
+            }
+            anyhow::bail!("cancelled")
+        }).unwrap();
+        started_rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        shutdown().unwrap();
+        assert_eq!(counts(&status(), "workers_test_cancel"), (0, 1));
+        assert!(submit("workers_test", "too late", |_| Ok(())).is_err());
+    }
+}
@@ -0,0 +1,399 @@
+// SentientOS Daemon
+// Long-lived runtime that sentctl reaches through a control socket instead of initializing its own
+
+use anyhow::{Result, Context};
+use tracing::{info, debug, warn};
+use std::fs;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::core::constants;
+use crate::package::Ecosystem;
+
+// Constants
+const SOCKET_FILE: &str = "control.sock";
+const TOKEN_FILE: &str = "control.token";
+const TOKEN_LEN: usize = 32;
+
+/// How long one connection may take to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A call to the daemon, sent as one line of JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Request {
+    /// Contents of the token file
+    token: String,
+    
+    /// Method name: `status`, `run-container`, `install-package`, `take-snapshot` or `stop`
+    method: String,
+    
+    /// Method parameters
+    #[serde(default)]
+    params: Value,
+}
+
+/// The daemon's answer, sent as one line of JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Response {
+    /// Whether the call succeeded
+    ok: bool,
+    
+    /// Result of a successful call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    
+    /// Error of a failed call
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Parameters of `run-container`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunContainerParams {
+    /// Container image directory or TSO archive
+    pub path: String,
+    
+    /// Arguments the container runs with
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// Parameters of `install-package`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstallPackageParams {
+    /// Package name
+    pub name: String,
+    
+    /// Package ecosystem
+    pub ecosystem: Ecosystem,
+    
+    /// Package version
+    #[serde(default)]
+    pub version: Option<String>,
+    
+    /// Install even if the license is on the deny list
+    #[serde(default)]
+    pub allow_denied_license: bool,
+}
+
+/// Parameters of `take-snapshot`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TakeSnapshotParams {
+    /// Why the snapshot is taken
+    pub reason: String,
+}
+
+/// Initialize the runtime and serve control requests until told to stop
+///
+/// The socket is `.runtime/control.sock` and a fresh token is written to
+/// `.auth/control.token`, readable by the owner only; every request must
+/// carry it. Each connection is handled on a thread of its own, so a long
+/// `run-container` or `install-package` call does not hold up `status` or
+/// `stop`. A `stop` request ends the loop; calls in flight are finished and
+/// the runtime is shut down in order before returning.
+pub fn run(zk_enabled: bool) -> Result<()> {
+    let socket = socket_path();
+    if socket.exists() {
+        if UnixStream::connect(&socket).is_ok() {
+            anyhow::bail!("A daemon is already listening on {:?}", socket);
+        }
+        debug!("Removing stale control socket {:?}", socket);
+        fs::remove_file(&socket)?;
+    }
+    
+    crate::init(zk_enabled)?;
+    
+    let served = write_token().and_then(|token| serve(&socket, &token));
+    
+    for path in [socket, token_path()] {
+        if let Err(e) = fs::remove_file(&path) {
+            if e.kind() != ErrorKind::NotFound {
+                warn!("Failed to remove {:?}: {}", path, e);
+            }
+        }
+    }
+    
+    let shutdown = crate::shutdown();
+    served.and(shutdown)
+}
+
+/// Call a method of the running daemon
+///
+/// Returns `None` when no daemon is running, so the caller can do the work
+/// in-process instead. A daemon that refuses the call is an error.
+pub fn call(method: &str, params: Value) -> Result<Option<Value>> {
+    let socket = socket_path();
+    let mut stream = match UnixStream::connect(&socket) {
+        Ok(stream) => stream,
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused) => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to connect to the daemon at {:?}", socket)),
+    };
+    
+    let request = Request {
+        token: read_token()?,
+        method: method.to_string(),
+        params,
+    };
+    let mut line = serde_json::to_string(&request)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+    
+    let mut reply = String::new();
+    BufReader::new(stream).read_line(&mut reply)
+        .context("Failed to read the daemon's response")?;
+    let response: Response = serde_json::from_str(&reply)
+        .context("Invalid response from the daemon")?;
+    
+    if !response.ok {
+        anyhow::bail!("{}", response.error.unwrap_or_else(|| "Daemon call failed".to_string()));
+    }
+    Ok(Some(response.result.unwrap_or(Value::Null)))
+}
+
+/// Accept connections until a `stop` request
+///
+/// Returns once every connection accepted before the stop is answered;
+/// connections still queued are refused with "Daemon stopping".
+fn serve(socket: &Path, token: &str) -> Result<()> {
+    let listener = UnixListener::bind(socket)
+        .with_context(|| format!("Failed to bind control socket {:?}", socket))?;
+    fs::set_permissions(socket, fs::Permissions::from_mode(0o600))?;
+    info!("Daemon listening on {:?}", socket);
+    
+    let stopping = AtomicBool::new(false);
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            if stopping.load(Ordering::SeqCst) {
+                // New clients find no socket and work in-process; queued ones are told why
+                if let Err(e) = fs::remove_file(socket) {
+                    warn!("Failed to remove control socket {:?}: {}", socket, e);
+                }
+                refuse_queued(&listener, stream);
+                break;
+            }
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Failed to accept control connection: {}", e);
+                    continue;
+                }
+            };
+            
+            let stopping = &stopping;
+            let spawned = thread::Builder::new()
+                .name("daemon-request".to_string())
+                .spawn_scoped(scope, move || match handle(stream, token) {
+                    Ok(true) => {
+                        stopping.store(true, Ordering::SeqCst);
+                        
+                        // Wake the blocked accept so the loop sees the stop
+                        if let Err(e) = UnixStream::connect(socket) {
+                            warn!("Failed to wake the control socket: {}", e);
+                        }
+                    }
+                    Ok(false) => {}
+                    Err(e) => debug!("Control connection failed: {:#}", e),
+                });
+            if let Err(e) = spawned {
+                warn!("Failed to start a control request thread: {}", e);
+            }
+        }
+        info!("Daemon stopping");
+    });
+    
+    Ok(())
+}
+
+/// Answer a connection accepted after `stop`, and any queued behind it,
+/// with an error instead of closing them unanswered
+fn refuse_queued(listener: &UnixListener, first: std::io::Result<UnixStream>) {
+    if let Err(e) = listener.set_nonblocking(true) {
+        warn!("Failed to drain the control socket: {}", e);
+    }
+    for stream in std::iter::once(first).chain(listener.incoming()) {
+        match stream {
+            Ok(stream) => {
+                // The connection that woke the loop has already gone, so this can fail
+                if let Err(e) = respond(&stream, Err(anyhow::anyhow!("Daemon stopping"))) {
+                    debug!("Failed to refuse control connection: {:#}", e);
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+            Err(e) => {
+                warn!("Failed to accept control connection: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+/// Answer one request; returns whether it was `stop`
+fn handle(stream: UnixStream, token: &str) -> Result<bool> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line)?;
+    
+    let request: Request = match serde_json::from_str(&line) {
+        Ok(request) => request,
+        Err(e) => return respond(&stream, Err(anyhow::anyhow!("Invalid request: {}", e))).map(|_| false),
+    };
+    if ring::constant_time::verify_slices_are_equal(request.token.as_bytes(), token.as_bytes()).is_err() {
+        warn!("Refused {} request with an invalid token", request.method);
+        return respond(&stream, Err(anyhow::anyhow!("Invalid control token"))).map(|_| false);
+    }
+    
+    debug!("Control request: {}", request.method);
+    let stop = request.method == "stop";
+    respond(&stream, dispatch(&request.method, request.params))?;
+    Ok(stop)
+}
+
+/// Run a method
+fn dispatch(method: &str, params: Value) -> Result<Value> {
+    match method {
+        "status" => Ok(serde_json::to_value(crate::status::collect())?),
+        "run-container" => {
+            let params: RunContainerParams = serde_json::from_value(params)?;
+            let args: Vec<&str> = params.args.iter().map(String::as_str).collect();
+            Ok(Value::String(crate::matrixbox::run_container(&params.path, &args)?))
+        }
+        "install-package" => {
+            let params: InstallPackageParams = serde_json::from_value(params)?;
+            crate::package::install_package(&params.name, params.ecosystem, params.version.as_deref(), params.allow_denied_license)?;
+            Ok(Value::Null)
+        }
+        "take-snapshot" => {
+            let params: TakeSnapshotParams = serde_json::from_value(params)?;
+            Ok(Value::String(crate::heal::take_snapshot(&params.reason)?))
+        }
+        "stop" => Ok(Value::Null),
+        other => anyhow::bail!("Unknown daemon method: {}", other),
+    }
+}
+
+/// Send a method's outcome back
+fn respond(mut stream: &UnixStream, outcome: Result<Value>) -> Result<()> {
+    let response = match outcome {
+        Ok(result) => Response { ok: true, result: Some(result), error: None },
+        Err(e) => Response { ok: false, result: None, error: Some(format!("{:#}", e)) },
+    };
+    let mut line = serde_json::to_string(&response)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Write a new random token, readable by the owner only
+fn write_token() -> Result<String> {
+    let mut bytes = [0u8; TOKEN_LEN];
+    SystemRandom::new().fill(&mut bytes)
+        .map_err(|_| anyhow::anyhow!("Failed to generate control token"))?;
+    let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    
+    let path = token_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let _ = fs::remove_file(&path);
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&path)
+        .with_context(|| format!("Failed to create control token {:?}", path))?;
+    file.write_all(token.as_bytes())?;
+    Ok(token)
+}
+
+/// Read the token, refusing a file others can read
+fn read_token() -> Result<String> {
+    let path = token_path();
+    let metadata = fs::metadata(&path)
+        .with_context(|| format!("A daemon is running but its token {:?} cannot be read", path))?;
+    if metadata.permissions().mode() & 0o077 != 0 {
+        anyhow::bail!("Control token {:?} is readable by other users; restart the daemon", path);
+    }
+    Ok(fs::read_to_string(&path)?.trim().to_string())
+}
+
+/// Path of the control socket
+fn socket_path() -> PathBuf {
+    constants::root_dir().join(constants::RUNTIME_DIR).join(SOCKET_FILE)
+}
+
+/// Path of the control token
+fn token_path() -> PathBuf {
+    constants::root_dir().join(constants::AUTH_DIR).join(TOKEN_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn read_response(stream: UnixStream) -> Response {
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply).unwrap();
+        serde_json::from_str(&reply).unwrap()
+    }
+    
+    #[test]
+    fn connections_queued_at_stop_are_refused_with_an_error() {
+        let dir = constants::root_dir().join("daemon-tests");
+        fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("refuse.sock");
+        let _ = fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        
+        let clients: Vec<UnixStream> = (0..3).map(|_| UnixStream::connect(&socket).unwrap()).collect();
+        let first = listener.accept().map(|(stream, _)| stream);
+        refuse_queued(&listener, first);
+        
+        for client in clients {
+            let response = read_response(client);
+            assert!(!response.ok);
+            assert_eq!(response.error.as_deref(), Some("Daemon stopping"));
+        }
+    }
+    
+    #[test]
+    fn stop_answers_every_client() {
+        let dir = constants::root_dir().join("daemon-tests");
+        fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("stop.sock");
+        let _ = fs::remove_file(&socket);
+        let token = "test-token";
+        
+        thread::scope(|scope| {
+            let server = scope.spawn(|| serve(&socket, token));
+            let connect = || loop {
+                match UnixStream::connect(&socket) {
+                    Ok(stream) => return stream,
+                    Err(_) => thread::sleep(Duration::from_millis(10)),
+                }
+            };
+            
+            let mut stop = connect();
+            let request = Request { token: token.to_string(), method: "stop".to_string(), params: Value::Null };
+            stop.write_all(format!("{}\n", serde_json::to_string(&request).unwrap()).as_bytes()).unwrap();
+            assert!(read_response(stop).ok);
+            
+            // Late clients either find no socket or get an error, never a silent close
+            if let Ok(mut late) = UnixStream::connect(&socket) {
+                let request = Request { token: token.to_string(), method: "bogus".to_string(), params: Value::Null };
+                let _ = late.write_all(format!("{}\n", serde_json::to_string(&request).unwrap()).as_bytes());
+                assert!(!read_response(late).ok);
+            }
+            
+            server.join().unwrap().unwrap();
+        });
+        assert!(!socket.exists());
+    }
+}
@@ -0,0 +1,123 @@
+// SentientOS Doctor
+// Health checks of the local installation, with remediation hints
+
+use anyhow::Result;
+use tracing::info;
+use std::path::PathBuf;
+
+use crate::core::constants;
+
+/// Outcome of a check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    /// Nothing to do
+    Ok,
+    
+    /// Works, but needs attention
+    Warning,
+    
+    /// Broken; the detail says how to fix it
+    Error,
+}
+
+/// Result of one health check
+#[derive(Debug, Clone)]
+pub struct Check {
+    /// What was checked
+    pub name: String,
+    
+    /// Outcome
+    pub status: CheckStatus,
+    
+    /// Explanation, with a remediation hint when not Ok
+    pub detail: String,
+}
+
+impl Check {
+    fn new(name: &str, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self {
+            name: name.to_string(),
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Run every health check
+pub fn run() -> Vec<Check> {
+    info!("Running health checks");
+    
+    let mut checks = vec![check_root(), check_identity()];
+    checks.extend(check_contract_state());
+    checks.extend(check_package_tools());
+    checks
+}
+
+/// The SentientOS root directory exists
+fn check_root() -> Check {
+    let root = constants::root_dir();
+    if root.is_dir() {
+        Check::new("root", CheckStatus::Ok, format!("{}", root.display()))
+    } else {
+        Check::new("root", CheckStatus::Error,
+                   format!("{} does not exist; run `sentctl setup`", root.display()))
+    }
+}
+
+/// The node identity can be loaded
+fn check_identity() -> Check {
+    match crate::core::identity::current() {
+        Ok(identity) => Check::new("identity", CheckStatus::Ok, format!("{} ({})", identity.name, identity.id)),
+        Err(e) => Check::new("identity", CheckStatus::Error, format!("{}; run `sentctl setup`", e)),
+    }
+}
+
+/// Encrypted contract state can be decrypted with the master key
+fn check_contract_state() -> Vec<Check> {
+    let encrypted = match crate::zk::state::encrypted_contracts() {
+        Ok(encrypted) => encrypted,
+        Err(e) => return vec![Check::new("contract state", CheckStatus::Warning, format!("Failed to scan: {}", e))],
+    };
+    if encrypted.is_empty() {
+        return vec![Check::new("contract state", CheckStatus::Ok, "No encrypted contract state")];
+    }
+    
+    if !crate::zk::state::master_key_exists() {
+        return vec![Check::new("contract state", CheckStatus::Error, format!(
+            "{} contract(s) have encrypted state but the master key {} is missing; \
+             their state is unrecoverable without it. Restore the key from a backup, \
+             or delete their state.json to reset them: {}",
+            encrypted.len(), crate::zk::state::master_key_path().display(), encrypted.join(", ")))];
+    }
+    
+    if let Err(e) = crate::zk::state::unlock(None) {
+        return vec![Check::new("contract state", CheckStatus::Error, format!("Cannot unlock master key: {}", e))];
+    }
+    
+    encrypted.iter()
+        .map(|contract| {
+            let name = format!("contract state: {}", contract);
+            match crate::zk::state::check_readable(contract) {
+                Ok(()) => Check::new(&name, CheckStatus::Ok, "Encrypted, readable"),
+                Err(e) => Check::new(&name, CheckStatus::Error, e.to_string()),
+            }
+        })
+        .collect()
+}
+
+/// Tools of each package ecosystem are installed
+fn check_package_tools() -> Vec<Check> {
+    // Re-detect so tools installed since init are seen
+    crate::package::tools::detect();
+    
+    crate::package::tools::ecosystems()
+        .into_iter()
+        .map(|status| {
+            let name = format!("ecosystem: {:?}", status.ecosystem).to_lowercase();
+            match status.guidance() {
+                None => Check::new(&name, CheckStatus::Ok, "Available"),
+                Some(guidance) => Check::new(&name, CheckStatus::Warning, guidance),
+            }
+        })
+        .collect()
+}
@@ -14,7 +14,7 @@ pub fn init() -> Result<()> {
     info!("Initializing SentientOS filesystem structure");
     
     // Create the root directory if it doesn't exist
-    let root_dir = PathBuf::from(constants::ROOT_DIR);
+    let root_dir = constants::root_dir();
     fs::create_dir_all(&root_dir)?;
     
     // Create standard system directories
@@ -34,7 +34,7 @@ pub fn init() -> Result<()> {
 fn create_system_directories() -> Result<()> {
     debug!("Creating standard system directories");
     
-    let root_dir = PathBuf::from(constants::ROOT_DIR);
+    let root_dir = constants::root_dir();
     
     // Define the system directory structure
     let directories = [
@@ -91,38 +91,50 @@ fn create_system_directories() -> Result<()> {
 fn create_default_configs() -> Result<()> {
     debug!("Creating default configuration files");
     
-    let root_dir = PathBuf::from(constants::ROOT_DIR);
+    let root_dir = constants::root_dir();
     
     // System configuration
     let system_config = serde_json::json!({
         "version": env!("CARGO_PKG_VERSION"),
         "initialized_at": chrono::Utc::now().to_rfc3339(),
-        "node_id": generate_node_id(),
         "subsystems": {
             "heal": { "enabled": true, "snapshot_interval_minutes": 60 },
             "panic": { "enabled": true, "max_recovery_attempts": 3 },
-            "matrixbox": { "enabled": true, "max_containers": 50 },
+            "matrixbox": {
+                "enabled": true,
+                "max_containers": 50,
+                "default_limits": { "max_memory_bytes": 268435456, "max_cpu_fraction": 1.0, "max_pids": 64 },
+            },
             "zk": { "enabled": true },
             "gossip": { "enabled": true },
             "intent": { "enabled": true },
         }
     });
     
+    // Existing configs hold choices made by setup and must survive re-init
     let system_config_path = root_dir.join(".config").join("system.json");
-    fs::write(&system_config_path, serde_json::to_string_pretty(&system_config)?)?;
-    debug!("Created system config: {:?}", system_config_path);
+    if !system_config_path.exists() {
+        fs::write(&system_config_path, serde_json::to_string_pretty(&system_config)?)?;
+        debug!("Created system config: {:?}", system_config_path);
+    }
     
     // Security policy
     let security_policy = serde_json::json!({
         "default_container_policy": "restricted",
         "zk_verification_required": true,
         "peer_authentication_required": true,
-        "audit_logging_enabled": true
+        "audit_logging_enabled": true,
+        "contract_deployment": {
+            "approvers": [],
+            "quorum": 1
+        }
     });
     
     let security_policy_path = root_dir.join(".config").join("security.json");
-    fs::write(&security_policy_path, serde_json::to_string_pretty(&security_policy)?)?;
-    debug!("Created security policy: {:?}", security_policy_path);
+    if !security_policy_path.exists() {
+        fs::write(&security_policy_path, serde_json::to_string_pretty(&security_policy)?)?;
+        debug!("Created security policy: {:?}", security_policy_path);
+    }
     
     Ok(())
 }
@@ -134,7 +146,7 @@ fn setup_permissions() -> Result<()> {
     // In a real implementation, we would use proper file system permissions
     // For now, we'll just create a permissions manifest file
     
-    let root_dir = PathBuf::from(constants::ROOT_DIR);
+    let root_dir = constants::root_dir();
     
     // Define permission structure
     let permissions = serde_json::json!({
@@ -172,37 +184,11 @@ fn setup_permissions() -> Result<()> {
     Ok(())
 }
 
-/// Generate a unique node ID
-fn generate_node_id() -> String {
-    use rand::{thread_rng, Rng};
-    use blake3;
-    use std::time::{SystemTime, UNIX_EPOCH, Duration};
-    
-    let timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_else(|_| Duration::from_secs(0))
-        .as_secs();
-    
-    let mut rng = thread_rng();
-    let random_bytes: [u8; 8] = rng.gen();
-    
-    // Hash timestamp and random bytes for uniqueness
-    let mut hasher = blake3::Hasher::new();
-    hasher.update(&timestamp.to_le_bytes());
-    hasher.update(&random_bytes);
-    
-    let hash = hasher.finalize();
-    let node_id = hash.to_hex().to_string();
-    
-    // Use first 16 chars of the hash
-    node_id[..16].to_string()
-}
-
 /// Check if the filesystem structure is properly initialized
 pub fn check_structure() -> Result<bool> {
     debug!("Checking filesystem structure");
     
-    let root_dir = PathBuf::from(constants::ROOT_DIR);
+    let root_dir = constants::root_dir();
     
     // Check essential directories
     let essential_dirs = [
@@ -245,7 +231,7 @@ pub fn repair_structure() -> Result<()> {
     create_system_directories()?;
     
     // Recreate config files if missing
-    let root_dir = PathBuf::from(constants::ROOT_DIR);
+    let root_dir = constants::root_dir();
     let system_config_path = root_dir.join(".config").join("system.json");
     if !system_config_path.exists() {
         create_default_configs()?;
@@ -257,3 +243,33 @@ pub fn repair_structure() -> Result<()> {
     info!("Filesystem structure repaired");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn init_builds_the_tree_under_the_configured_root() {
+        let root_dir = constants::root_dir();
+        assert!(root_dir.starts_with(std::env::temp_dir()));
+        
+        init().unwrap();
+        assert!(check_structure().unwrap());
+        for dir in [".heal/snapshots", ".lock/resources", ".gossip/peers", "logs"] {
+            assert!(root_dir.join(dir).is_dir(), "{} was not created", dir);
+        }
+        for file in ["system.json", "security.json", "permissions.json"] {
+            let content = fs::read_to_string(root_dir.join(".config").join(file)).unwrap();
+            serde_json::from_str::<serde_json::Value>(&content).unwrap();
+        }
+        
+        // Choices recorded in the config survive a second init
+        let system_config_path = root_dir.join(".config").join("system.json");
+        let mut system_config: serde_json::Value = serde_json::from_str(&fs::read_to_string(&system_config_path).unwrap()).unwrap();
+        system_config["filesystem_test"] = serde_json::json!(true);
+        fs::write(&system_config_path, serde_json::to_string_pretty(&system_config).unwrap()).unwrap();
+        init().unwrap();
+        let system_config: serde_json::Value = serde_json::from_str(&fs::read_to_string(&system_config_path).unwrap()).unwrap();
+        assert_eq!(system_config["filesystem_test"], true);
+    }
+}
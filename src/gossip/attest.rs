@@ -0,0 +1,519 @@
+// SentientOS Peer Attestation
+// Challenge-response attestation of boot chain and trace head freshness
+
+use anyhow::{Result, Context};
+use tracing::{info, debug, warn};
+use std::path::PathBuf;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use crate::core::constants;
+use super::protocol::{self, MessageType};
+
+// Constants
+const ATTEST_DIR: &str = ".gossip/attest";
+const PENDING_DIR: &str = "pending";
+const HISTORY_EXTENSION: &str = "jsonl";
+const MAX_HISTORY: usize = 500;
+
+/// Attestation settings, stored under `attestation` in system.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AttestationConfig {
+    /// How often trusted peers are challenged, in minutes (0 disables)
+    pub interval_minutes: u64,
+    
+    /// How long a challenge waits for its response, in seconds
+    pub timeout_secs: u64,
+    
+    /// Maximum clock difference accepted in a response, in seconds
+    pub max_skew_secs: u64,
+    
+    /// Consecutive failures after which a peer is demoted
+    pub demote_after: usize,
+}
+
+impl Default for AttestationConfig {
+    fn default() -> Self {
+        Self {
+            interval_minutes: 15,
+            timeout_secs: 30,
+            max_skew_secs: 60,
+            demote_after: 3,
+        }
+    }
+}
+
+/// Challenge sent to a peer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Challenge {
+    /// Random nonce (hex)
+    nonce: String,
+    
+    /// Challenged peer
+    peer_id: String,
+    
+    /// When the challenge was sent
+    issued_at: u64,
+}
+
+/// Signed response to a challenge
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Response {
+    /// Nonce from the challenge
+    nonce: String,
+    
+    /// Hash of the responder's boot chain record
+    boot_record_hash: String,
+    
+    /// Head of the responder's runtime trace chain
+    trace_head: String,
+    
+    /// Responder's clock when signing
+    timestamp: u64,
+    
+    /// Signature by the responder's identity key (hex)
+    signature: String,
+}
+
+/// Result of an attestation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AttestationOutcome {
+    /// The response was signed by the peer's key and fresh
+    Passed,
+    
+    /// The response was invalid
+    Failed,
+    
+    /// The peer did not respond in time
+    NoResponse,
+}
+
+/// A recorded attestation of a peer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttestationRecord {
+    /// Peer ID
+    pub peer_id: String,
+    
+    /// Challenge nonce
+    pub nonce: String,
+    
+    /// When the challenge was sent
+    pub challenged_at: u64,
+    
+    /// When the result was recorded
+    pub recorded_at: u64,
+    
+    /// Result
+    pub outcome: AttestationOutcome,
+    
+    /// Why the attestation did not pass
+    pub reason: Option<String>,
+    
+    /// Attested boot chain record hash
+    pub boot_record_hash: Option<String>,
+    
+    /// Attested trace chain head
+    pub trace_head: Option<String>,
+}
+
+impl AttestationRecord {
+    /// Seconds since the record was made
+    pub fn age_secs(&self) -> u64 {
+        now().saturating_sub(self.recorded_at)
+    }
+}
+
+/// Send an attestation challenge to a peer and return its nonce
+pub fn challenge(peer_id: &str) -> Result<String> {
+    let peer = super::list_peers()?
+        .into_iter()
+        .find(|p| p.id == peer_id)
+        .ok_or_else(|| anyhow::anyhow!("Unknown peer: {}", peer_id))?;
+    
+    if super::peer_public_key(peer_id).is_none() {
+        anyhow::bail!("Peer {} has no verified identity key to attest against", peer_id);
+    }
+    
+    let nonce: [u8; 32] = rand::random();
+    let challenge = Challenge {
+        nonce: nonce.iter().map(|b| format!("{:02x}", b)).collect(),
+        peer_id: peer_id.to_string(),
+        issued_at: now(),
+    };
+    
+    let pending_dir = attest_dir().join(PENDING_DIR);
+    fs::create_dir_all(&pending_dir)?;
+    fs::write(pending_dir.join(format!("{}.json", challenge.nonce)), serde_json::to_string_pretty(&challenge)?)?;
+    
+    protocol::send_message(&peer.endpoint, MessageType::AttestChallenge, challenge.nonce.as_bytes())?;
+    debug!("Sent attestation challenge to {}", peer_id);
+    Ok(challenge.nonce)
+}
+
+/// Challenge a peer and wait for the result
+pub fn attest(peer_id: &str) -> Result<AttestationRecord> {
+    let config = load_config()?;
+    let nonce = challenge(peer_id)?;
+    
+    let deadline = SystemTime::now() + Duration::from_secs(config.timeout_secs);
+    while SystemTime::now() < deadline {
+        if let Some(record) = history(peer_id, 10)?.into_iter().find(|r| r.nonce == nonce) {
+            return Ok(record);
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+    
+    expire_pending()?;
+    history(peer_id, 10)?
+        .into_iter()
+        .find(|r| r.nonce == nonce)
+        .ok_or_else(|| anyhow::anyhow!("No attestation result recorded for {}", peer_id))
+}
+
+/// Challenge every trusted peer, recording unanswered earlier challenges
+///
+/// Run periodically by the healing rules; peers that fail repeatedly are demoted.
+pub fn attest_trusted_peers() -> Result<()> {
+    expire_pending()?;
+    
+    for peer in super::list_peers()? {
+        if !super::is_trusted_peer(&peer.id) {
+            continue;
+        }
+        if let Err(e) = challenge(&peer.id) {
+            warn!("Failed to challenge peer {}: {}", peer.id, e);
+        }
+    }
+    Ok(())
+}
+
+/// Answer a challenge from a peer
+pub fn handle_challenge(source_id: &str, payload: &[u8]) -> Result<()> {
+    let nonce = std::str::from_utf8(payload).context("Invalid attestation nonce")?;
+    if nonce.len() != 64 || !nonce.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!("Invalid attestation nonce from {}", source_id);
+    }
+    
+    // Only peers we know get an answer, and only at their registered endpoint
+    let peer = match super::list_peers()?.into_iter().find(|p| p.id == source_id) {
+        Some(peer) => peer,
+        None => {
+            warn!("Ignoring attestation challenge from unknown peer {}", source_id);
+            return Ok(());
+        }
+    };
+    
+    let boot_record_hash = crate::boot::boot_record_hash()?;
+    let trace_head = super::verify::local_trace_hash()?;
+    let timestamp = now();
+    let signature = crate::core::identity::sign(
+        signing_message(nonce, &boot_record_hash, &trace_head, timestamp).as_bytes())?;
+    
+    let response = Response {
+        nonce: nonce.to_string(),
+        boot_record_hash,
+        trace_head,
+        timestamp,
+        signature,
+    };
+    protocol::send_message(&peer.endpoint, MessageType::AttestResponse, &serde_json::to_vec(&response)?)?;
+    
+    debug!("Answered attestation challenge from {}", source_id);
+    Ok(())
+}
+
+/// Verify and record a peer's response to our challenge
+pub fn handle_response(source_id: &str, payload: &[u8]) -> Result<()> {
+    let response: Response = serde_json::from_slice(payload).context("Invalid attestation response")?;
+    if response.nonce.len() != 64 || !response.nonce.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!("Invalid attestation nonce from {}", source_id);
+    }
+    
+    let pending_path = attest_dir().join(PENDING_DIR).join(format!("{}.json", response.nonce));
+    let challenge: Challenge = match fs::read_to_string(&pending_path) {
+        Ok(data) => serde_json::from_str(&data)?,
+        Err(_) => {
+            warn!("Ignoring attestation response from {} for an unknown challenge", source_id);
+            return Ok(());
+        }
+    };
+    if challenge.peer_id != source_id {
+        warn!("Ignoring attestation response from {} to a challenge sent to {}", source_id, challenge.peer_id);
+        return Ok(());
+    }
+    fs::remove_file(&pending_path)?;
+    
+    let config = load_config()?;
+    let received_at = now();
+    let failure = match super::peer_public_key(source_id) {
+        None => Some("peer has no verified identity key".to_string()),
+        Some(key) => {
+            let message = signing_message(&response.nonce, &response.boot_record_hash, &response.trace_head, response.timestamp);
+            if crate::core::identity::verify(&key, message.as_bytes(), &response.signature).is_err() {
+                Some("invalid signature".to_string())
+            } else if received_at.saturating_sub(challenge.issued_at) > config.timeout_secs {
+                Some(format!("response arrived after {}s", received_at - challenge.issued_at))
+            } else if response.timestamp.abs_diff(received_at) > config.max_skew_secs {
+                Some(format!("response timestamp is {}s off", response.timestamp.abs_diff(received_at)))
+            } else {
+                None
+            }
+        }
+    };
+    
+    record(AttestationRecord {
+        peer_id: source_id.to_string(),
+        nonce: response.nonce,
+        challenged_at: challenge.issued_at,
+        recorded_at: received_at,
+        outcome: if failure.is_some() { AttestationOutcome::Failed } else { AttestationOutcome::Passed },
+        reason: failure,
+        boot_record_hash: Some(response.boot_record_hash),
+        trace_head: Some(response.trace_head),
+    }, &config)
+}
+
+/// Attestation history of a peer, newest first
+pub fn history(peer_id: &str, limit: usize) -> Result<Vec<AttestationRecord>> {
+    let path = history_path(peer_id);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let mut records: Vec<AttestationRecord> = fs::read_to_string(&path)?
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .filter_map(|l| serde_json::from_str(l).ok())
+        .collect();
+    records.reverse();
+    records.truncate(limit);
+    Ok(records)
+}
+
+/// Most recent attestation of a peer
+pub fn latest(peer_id: &str) -> Result<Option<AttestationRecord>> {
+    Ok(history(peer_id, 1)?.into_iter().next())
+}
+
+/// Record challenges that were not answered in time
+fn expire_pending() -> Result<()> {
+    let pending_dir = attest_dir().join(PENDING_DIR);
+    if !pending_dir.exists() {
+        return Ok(());
+    }
+    
+    let config = load_config()?;
+    for entry in fs::read_dir(&pending_dir)?.filter_map(Result::ok) {
+        let challenge: Challenge = match fs::read_to_string(entry.path()).ok().and_then(|d| serde_json::from_str(&d).ok()) {
+            Some(challenge) => challenge,
+            None => continue,
+        };
+        if now().saturating_sub(challenge.issued_at) <= config.timeout_secs {
+            continue;
+        }
+        
+        fs::remove_file(entry.path())?;
+        record(AttestationRecord {
+            peer_id: challenge.peer_id,
+            nonce: challenge.nonce,
+            challenged_at: challenge.issued_at,
+            recorded_at: now(),
+            outcome: AttestationOutcome::NoResponse,
+            reason: Some(format!("no response within {}s", config.timeout_secs)),
+            boot_record_hash: None,
+            trace_head: None,
+        }, &config)?;
+    }
+    Ok(())
+}
+
+/// Append a record and demote the peer after repeated failures
+fn record(record: AttestationRecord, config: &AttestationConfig) -> Result<()> {
+    match record.outcome {
+        AttestationOutcome::Passed => info!("Peer {} passed attestation", record.peer_id),
+        _ => warn!("Peer {} failed attestation: {}", record.peer_id, record.reason.as_deref().unwrap_or("unknown")),
+    }
+    crate::logs::metrics::increment(match record.outcome {
+        AttestationOutcome::Passed => "gossip.attest.passed",
+        AttestationOutcome::Failed => "gossip.attest.failed",
+        AttestationOutcome::NoResponse => "gossip.attest.no_response",
+    });
+    
+    let path = history_path(&record.peer_id);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    writeln!(file, "{}", serde_json::to_string(&record)?)?;
+    drop(file);
+    
+    let recent = history(&record.peer_id, MAX_HISTORY * 2 + 1)?;
+    if recent.len() > MAX_HISTORY * 2 {
+        let lines: Vec<String> = recent[..MAX_HISTORY].iter().rev().map(serde_json::to_string).collect::<Result<_, _>>()?;
+        fs::write(&path, lines.join("\n") + "\n")?;
+    }
+    
+    if record.outcome == AttestationOutcome::Passed {
+        return super::set_peer_demoted(&record.peer_id, false);
+    }
+    
+    let failures = recent.iter().take_while(|r| r.outcome != AttestationOutcome::Passed).count();
+    if failures >= config.demote_after && super::is_trusted_peer(&record.peer_id) {
+        warn!("Demoting peer {} after {} failed attestations", record.peer_id, failures);
+        crate::logs::ship::ship_audit("gossip.attest",
+            &format!("peer {} demoted after {} failed attestations", record.peer_id, failures));
+        super::set_peer_demoted(&record.peer_id, true)?;
+    }
+    Ok(())
+}
+
+/// Message covered by a response signature
+fn signing_message(nonce: &str, boot_record_hash: &str, trace_head: &str, timestamp: u64) -> String {
+    format!("attest\n{}\n{}\n{}\n{}", nonce, boot_record_hash, trace_head, timestamp)
+}
+
+/// Load the attestation configuration
+pub fn load_config() -> Result<AttestationConfig> {
+    let path = constants::root_dir().join(".config").join("system.json");
+    if !path.exists() {
+        return Ok(AttestationConfig::default());
+    }
+    
+    let system_config: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    match system_config.get("attestation") {
+        Some(value) => Ok(serde_json::from_value(value.clone())
+            .context("Invalid attestation configuration in system.json")?),
+        None => Ok(AttestationConfig::default()),
+    }
+}
+
+/// Directory of attestation state
+fn attest_dir() -> PathBuf {
+    constants::root_dir().join(ATTEST_DIR)
+}
+
+/// History file of a peer
+fn history_path(peer_id: &str) -> PathBuf {
+    attest_dir().join(format!("{}.{}", peer_id, HISTORY_EXTENSION))
+}
+
+/// Current time in seconds since epoch
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+    
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+    
+    /// Register a peer whose identity key is derived from `seed`
+    fn attested_peer(peer_id: &str, seed: u8) -> SigningKey {
+        let key = SigningKey::from_bytes(&[seed; 32]);
+        super::super::add_peer(peer_id, "127.0.0.1:9", None).unwrap();
+        super::super::update_peer_identity(peer_id, peer_id, &hex(key.verifying_key().as_bytes())).unwrap();
+        key
+    }
+    
+    /// Record a challenge to `peer_id` as if it had been sent `age` seconds ago
+    fn pending_challenge(peer_id: &str, nonce: u8, age: u64) -> String {
+        let challenge = Challenge {
+            nonce: hex(&[nonce; 32]),
+            peer_id: peer_id.to_string(),
+            issued_at: now() - age,
+        };
+        let pending_dir = attest_dir().join(PENDING_DIR);
+        fs::create_dir_all(&pending_dir).unwrap();
+        fs::write(pending_dir.join(format!("{}.json", challenge.nonce)), serde_json::to_string(&challenge).unwrap()).unwrap();
+        challenge.nonce
+    }
+    
+    fn response(key: &SigningKey, nonce: &str, timestamp: u64) -> Vec<u8> {
+        let message = signing_message(nonce, "boot-hash", "trace-head", timestamp);
+        serde_json::to_vec(&Response {
+            nonce: nonce.to_string(),
+            boot_record_hash: "boot-hash".to_string(),
+            trace_head: "trace-head".to_string(),
+            timestamp,
+            signature: hex(&key.sign(message.as_bytes()).to_bytes()),
+        }).unwrap()
+    }
+    
+    #[test]
+    fn signed_fresh_responses_pass() {
+        let key = attested_peer("attest-passing", 1);
+        let nonce = pending_challenge("attest-passing", 1, 0);
+        
+        handle_response("attest-passing", &response(&key, &nonce, now())).unwrap();
+        let record = latest("attest-passing").unwrap().unwrap();
+        assert_eq!(record.outcome, AttestationOutcome::Passed);
+        assert_eq!((record.nonce.as_str(), record.trace_head.as_deref()), (nonce.as_str(), Some("trace-head")));
+        assert!(!attest_dir().join(PENDING_DIR).join(format!("{}.json", nonce)).exists());
+        
+        // Replays and answers to challenges sent to someone else are ignored
+        handle_response("attest-passing", &response(&key, &nonce, now())).unwrap();
+        let other = pending_challenge("attest-someone-else", 2, 0);
+        handle_response("attest-passing", &response(&key, &other, now())).unwrap();
+        assert_eq!(history("attest-passing", 10).unwrap().len(), 1);
+    }
+    
+    #[test]
+    fn repeated_failures_demote_until_the_next_pass() {
+        let key = attested_peer("attest-failing", 3);
+        let forger = SigningKey::from_bytes(&[4; 32]);
+        
+        let nonce = pending_challenge("attest-failing", 3, 0);
+        handle_response("attest-failing", &response(&key, &nonce, now() - 3600)).unwrap();
+        assert!(latest("attest-failing").unwrap().unwrap().reason.unwrap().contains("off"));
+        let nonce = pending_challenge("attest-failing", 4, 0);
+        handle_response("attest-failing", &response(&forger, &nonce, now())).unwrap();
+        assert!(super::super::is_trusted_peer("attest-failing"));
+        
+        let nonce = pending_challenge("attest-failing", 5, 0);
+        handle_response("attest-failing", &response(&forger, &nonce, now())).unwrap();
+        let record = latest("attest-failing").unwrap().unwrap();
+        assert_eq!((record.outcome, record.reason.as_deref()), (AttestationOutcome::Failed, Some("invalid signature")));
+        assert!(!super::super::is_trusted_peer("attest-failing"));
+        
+        let nonce = pending_challenge("attest-failing", 6, 0);
+        handle_response("attest-failing", &response(&key, &nonce, now())).unwrap();
+        assert!(super::super::is_trusted_peer("attest-failing"));
+        let outcomes: Vec<_> = history("attest-failing", 2).unwrap().into_iter().map(|r| r.outcome).collect();
+        assert_eq!(outcomes, [AttestationOutcome::Passed, AttestationOutcome::Failed]);
+    }
+    
+    #[test]
+    fn unanswered_challenges_expire() {
+        attested_peer("attest-silent", 7);
+        let expired = pending_challenge("attest-silent", 7, 3600);
+        let waiting = pending_challenge("attest-silent", 8, 0);
+        
+        expire_pending().unwrap();
+        let records = history("attest-silent", 10).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!((records[0].nonce.as_str(), records[0].outcome), (expired.as_str(), AttestationOutcome::NoResponse));
+        assert!(attest_dir().join(PENDING_DIR).join(format!("{}.json", waiting)).exists());
+    }
+    
+    #[test]
+    fn malformed_challenges_and_responses_are_rejected() {
+        assert!(handle_challenge("attest-unknown", b"not-a-nonce").is_err());
+        assert!(handle_challenge("attest-unknown", hex(&[9; 32]).as_bytes()).is_ok());
+        assert!(handle_response("attest-unknown", b"{}").is_err());
+        
+        let key = SigningKey::from_bytes(&[9; 32]);
+        assert!(handle_response("attest-unknown", &response(&key, "abc", now())).is_err());
+        assert!(history("attest-unknown", 10).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,726 @@
+// SentientOS Gossip Conflict Resolution
+// Per-component resolvers for divergent state received from peers during sync
+
+use anyhow::{Result, Context};
+use tracing::{info, debug, warn};
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use serde::{Serialize, Deserialize};
+
+use crate::core::constants;
+use crate::core::identity;
+use crate::store::{self, PackageIndex};
+use crate::store::advisory::compare_versions;
+
+/// Deployed ZK contracts, keyed by file name
+pub const COMPONENT_CONTRACTS: &str = "contracts";
+
+/// Store package index
+pub const COMPONENT_STORE_INDEX: &str = "store-index";
+
+/// Gossip peer registry
+pub const COMPONENT_PEERS: &str = "peers";
+
+/// Archived runtime traces, keyed by file name
+pub const COMPONENT_TRACES: &str = "traces";
+
+/// Extension of unresolved conflict records
+const CONFLICT_EXT: &str = "conflict";
+
+/// Extension of the preserved remote state of a conflict
+const REMOTE_EXT: &str = "remote";
+
+// Resolvers by component; components without one need a manual pick
+lazy_static::lazy_static! {
+    static ref RESOLVERS: Arc<Mutex<HashMap<String, Arc<dyn ConflictResolver>>>> = {
+        let mut resolvers: HashMap<String, Arc<dyn ConflictResolver>> = HashMap::new();
+        resolvers.insert(COMPONENT_CONTRACTS.to_string(), Arc::new(ManualResolver));
+        resolvers.insert(COMPONENT_STORE_INDEX.to_string(), Arc::new(StoreIndexResolver));
+        resolvers.insert(COMPONENT_PEERS.to_string(), Arc::new(PeerRegistryResolver));
+        resolvers.insert(COMPONENT_TRACES.to_string(), Arc::new(AppendOnlyResolver));
+        Arc::new(Mutex::new(resolvers))
+    };
+}
+
+/// Outcome of running a resolver on divergent local and remote state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resolution {
+    /// Replace the local state with this content
+    Merged(Vec<u8>),
+    
+    /// Keep the local state and drop the remote one
+    KeepLocal,
+    
+    /// Keep both and wait for an operator to pick
+    Manual,
+}
+
+/// Resolves divergent state of one component
+pub trait ConflictResolver: Send + Sync {
+    /// Resolve local against remote state; both are the raw file contents
+    fn resolve(&self, local: &[u8], remote: &[u8]) -> Result<Resolution>;
+}
+
+/// Keeps both versions and requires a manual pick
+pub struct ManualResolver;
+
+impl ConflictResolver for ManualResolver {
+    fn resolve(&self, _local: &[u8], _remote: &[u8]) -> Result<Resolution> {
+        Ok(Resolution::Manual)
+    }
+}
+
+/// Merges store indexes per package entry
+///
+/// For each package the higher version wins, but only entries signed by a
+/// trusted store key can replace or add to the local index. Advisories are
+/// unioned by ID.
+pub struct StoreIndexResolver;
+
+impl ConflictResolver for StoreIndexResolver {
+    fn resolve(&self, local: &[u8], remote: &[u8]) -> Result<Resolution> {
+        let mut merged: PackageIndex = serde_json::from_slice(local)
+            .context("Failed to parse local store index")?;
+        let remote: PackageIndex = serde_json::from_slice(remote)
+            .context("Failed to parse remote store index")?;
+        
+        for (name, theirs) in remote.packages {
+            if !store::signature_is_valid(&theirs) {
+                debug!("Ignoring unsigned remote index entry: {} {}", name, theirs.version);
+                continue;
+            }
+            
+            let take = match merged.packages.get(&name) {
+                Some(ours) => !store::signature_is_valid(ours)
+                    || compare_versions(&theirs.version, &ours.version) == Ordering::Greater,
+                None => true,
+            };
+            if take {
+                merged.packages.insert(name, theirs);
+            }
+        }
+        
+        for advisory in remote.advisories {
+            if !merged.advisories.iter().any(|a| a.id == advisory.id) {
+                merged.advisories.push(advisory);
+            }
+        }
+        merged.last_updated = merged.last_updated.max(remote.last_updated);
+        
+        Ok(Resolution::Merged(serde_json::to_vec_pretty(&merged)?))
+    }
+}
+
+/// Union-merges peer registries
+///
+/// Peers known on both sides keep the more recently seen entry. Public keys
+/// are never taken from the remote registry, since they must come from a
+/// verified identity announcement.
+pub struct PeerRegistryResolver;
+
+impl ConflictResolver for PeerRegistryResolver {
+    fn resolve(&self, local: &[u8], remote: &[u8]) -> Result<Resolution> {
+        let mut merged: serde_json::Value = serde_json::from_slice(local)
+            .context("Failed to parse local peer registry")?;
+        let remote: serde_json::Value = serde_json::from_slice(remote)
+            .context("Failed to parse remote peer registry")?;
+        
+        let own_id = identity::node_id().ok();
+        let local_peers = merged.get_mut("peers").and_then(|p| p.as_object_mut())
+            .ok_or_else(|| anyhow::anyhow!("Local peer registry has no peers"))?;
+        let remote_peers = remote.get("peers").and_then(|p| p.as_object())
+            .ok_or_else(|| anyhow::anyhow!("Remote peer registry has no peers"))?;
+        
+        for (id, theirs) in remote_peers {
+            if own_id.as_deref() == Some(id.as_str()) {
+                continue;
+            }
+            
+            let last_seen = |peer: &serde_json::Value| peer.get("last_seen").and_then(|v| v.as_u64()).unwrap_or(0);
+            match local_peers.get_mut(id) {
+                Some(ours) => {
+                    if last_seen(theirs) > last_seen(ours) {
+                        for field in ["endpoint", "last_seen", "status"] {
+                            if let Some(value) = theirs.get(field) {
+                                ours[field] = value.clone();
+                            }
+                        }
+                    }
+                }
+                None => {
+                    let mut peer = theirs.clone();
+                    if let Some(peer) = peer.as_object_mut() {
+                        peer.insert("public_key".to_string(), serde_json::Value::Null);
+                    }
+                    local_peers.insert(id.clone(), peer);
+                }
+            }
+        }
+        
+        Ok(Resolution::Merged(serde_json::to_vec_pretty(&merged)?))
+    }
+}
+
+/// Never merges append-only trace archives
+///
+/// A remote archive that extends the local one is taken as is; diverged
+/// histories keep the local archive.
+pub struct AppendOnlyResolver;
+
+impl ConflictResolver for AppendOnlyResolver {
+    fn resolve(&self, local: &[u8], remote: &[u8]) -> Result<Resolution> {
+        if remote.starts_with(local) {
+            Ok(Resolution::Merged(remote.to_vec()))
+        } else {
+            Ok(Resolution::KeepLocal)
+        }
+    }
+}
+
+/// An unresolved or resolved conflict
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conflict {
+    /// Conflict ID
+    pub id: String,
+    
+    /// Component the state belongs to
+    pub component: String,
+    
+    /// Item within the component, e.g. a contract file name
+    pub key: String,
+    
+    /// Peer that sent the conflicting state
+    pub peer_id: String,
+    
+    /// Hash of the local state when the conflict was detected
+    pub local_hash: String,
+    
+    /// Hash of the remote state
+    pub remote_hash: String,
+    
+    /// Detection timestamp (seconds since epoch)
+    pub detected_at: u64,
+    
+    /// How the conflict was resolved, if it was
+    pub resolution: Option<ConflictOutcome>,
+}
+
+/// Which state an operator kept
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Take {
+    /// Keep the local state
+    Local,
+    
+    /// Replace with the remote state
+    Remote,
+    
+    /// Replace with a hand-merged file
+    MergedFile(PathBuf),
+}
+
+/// Resolution outcome, gossiped to the peer that sent the conflicting state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConflictOutcome {
+    /// Conflict ID on the resolving node
+    pub conflict_id: String,
+    
+    /// Component the state belongs to
+    pub component: String,
+    
+    /// Item within the component
+    pub key: String,
+    
+    /// Node that resolved the conflict
+    pub resolved_by: String,
+    
+    /// What was kept: local, remote or merged
+    pub outcome: String,
+    
+    /// Hash of the resulting state
+    pub content_hash: String,
+    
+    /// Resolution timestamp (seconds since epoch)
+    pub resolved_at: u64,
+    
+    /// Signature by the resolving node over the fields above
+    pub signature: String,
+}
+
+/// Register a resolver for a component, replacing any existing one
+pub fn register_resolver(component: &str, resolver: Arc<dyn ConflictResolver>) {
+    RESOLVERS.lock().unwrap().insert(component.to_string(), resolver);
+}
+
+/// Reconcile state received from a peer with the local copy
+///
+/// Returns the conflict ID if the state needs a manual pick.
+pub fn reconcile(component: &str, key: &str, peer_id: &str, remote: &[u8]) -> Result<Option<String>> {
+    let path = component_path(component, key)?;
+    
+    if !path.exists() {
+        write_state(&path, remote)?;
+        debug!("Took {}/{} from peer {}", component, key, peer_id);
+        return Ok(None);
+    }
+    
+    let local = fs::read(&path)?;
+    if local == remote {
+        return Ok(None);
+    }
+    
+    let resolver = RESOLVERS.lock().unwrap().get(component).cloned()
+        .unwrap_or_else(|| Arc::new(ManualResolver));
+    
+    match resolver.resolve(&local, remote)? {
+        Resolution::Merged(content) => {
+            if content != local {
+                write_state(&path, &content)?;
+            }
+            info!("Merged {}/{} with state from peer {}", component, key, peer_id);
+            Ok(None)
+        }
+        Resolution::KeepLocal => {
+            warn!("Kept local {}/{}; state from peer {} diverges", component, key, peer_id);
+            Ok(None)
+        }
+        Resolution::Manual => {
+            let conflict = record_conflict(component, key, peer_id, &local, remote)?;
+            warn!("Conflict {} on {}/{} with peer {} needs a manual pick", conflict.id, component, key, peer_id);
+            Ok(Some(conflict.id))
+        }
+    }
+}
+
+/// List unresolved conflicts, oldest first
+pub fn list_conflicts() -> Result<Vec<Conflict>> {
+    let dir = conflicts_dir();
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let mut conflicts = Vec::new();
+    for entry in fs::read_dir(&dir)?.filter_map(Result::ok) {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(CONFLICT_EXT) {
+            continue;
+        }
+        
+        match fs::read_to_string(&path).ok().and_then(|c| serde_json::from_str::<Conflict>(&c).ok()) {
+            Some(conflict) if conflict.resolution.is_none() => conflicts.push(conflict),
+            Some(_) => {}
+            None => warn!("Ignoring unreadable conflict record: {:?}", path),
+        }
+    }
+    
+    conflicts.sort_by(|a, b| a.detected_at.cmp(&b.detected_at).then_with(|| a.id.cmp(&b.id)));
+    Ok(conflicts)
+}
+
+/// Resolve a conflict and tell the peer that sent the conflicting state
+pub fn resolve(id: &str, take: Take) -> Result<ConflictOutcome> {
+    let mut conflict = load_conflict(id)?;
+    if conflict.resolution.is_some() {
+        anyhow::bail!("Conflict {} is already resolved", id);
+    }
+    
+    let path = component_path(&conflict.component, &conflict.key)?;
+    let remote_path = conflicts_dir().join(format!("{}.{}", id, REMOTE_EXT));
+    
+    let (outcome, content) = match &take {
+        Take::Local => ("local", fs::read(&path).unwrap_or_default()),
+        Take::Remote => ("remote", fs::read(&remote_path)
+            .with_context(|| format!("Remote state of conflict {} is missing", id))?),
+        Take::MergedFile(file) => ("merged", fs::read(file)
+            .with_context(|| format!("Failed to read merged file {:?}", file))?),
+    };
+    
+    if take != Take::Local {
+        write_state(&path, &content)?;
+    }
+    
+    let mut resolution = ConflictOutcome {
+        conflict_id: conflict.id.clone(),
+        component: conflict.component.clone(),
+        key: conflict.key.clone(),
+        resolved_by: identity::node_id()?,
+        outcome: outcome.to_string(),
+        content_hash: blake3::hash(&content).to_hex().to_string(),
+        resolved_at: now(),
+        signature: String::new(),
+    };
+    resolution.signature = identity::sign(outcome_payload(&resolution)?.as_bytes())?;
+    
+    conflict.resolution = Some(resolution.clone());
+    save_conflict(&conflict)?;
+    fs::remove_file(&remote_path).ok();
+    
+    info!("Resolved conflict {} on {}/{} by taking {}", id, conflict.component, conflict.key, outcome);
+    send_outcome(&conflict.peer_id, &resolution);
+    
+    Ok(resolution)
+}
+
+/// Handle a peer's resolution of a conflict caused by state we sent
+pub fn handle_peer_outcome(source_id: &str, payload: &[u8]) -> Result<()> {
+    if !super::is_trusted_peer(source_id) {
+        debug!("Ignoring conflict outcome from untrusted peer: {}", source_id);
+        return Ok(());
+    }
+    
+    let outcome: ConflictOutcome = serde_json::from_slice(payload)
+        .context("Failed to parse conflict outcome")?;
+    
+    if outcome.resolved_by != source_id {
+        anyhow::bail!("Conflict outcome from {} claims to be resolved by {}", source_id, outcome.resolved_by);
+    }
+    
+    let public_key = super::peer_public_key(source_id)
+        .ok_or_else(|| anyhow::anyhow!("No verified public key for peer {}", source_id))?;
+    identity::verify(&public_key, outcome_payload(&outcome)?.as_bytes(), &outcome.signature)
+        .with_context(|| format!("Invalid conflict outcome signature from {}", source_id))?;
+    
+    // Record the outcome so it is visible after the log rotates
+    let dir = outcomes_dir();
+    fs::create_dir_all(&dir)?;
+    let file_name = format!("{}-{}.json", source_id, outcome.conflict_id);
+    check_file_name(&file_name)?;
+    fs::write(dir.join(file_name), serde_json::to_string_pretty(&outcome)?)?;
+    
+    info!("Peer {} resolved conflict on {}/{} by taking {}", source_id, outcome.component, outcome.key, outcome.outcome);
+    
+    let path = component_path(&outcome.component, &outcome.key)?;
+    let local_hash = fs::read(&path).map(|c| blake3::hash(&c).to_hex().to_string()).unwrap_or_default();
+    if local_hash != outcome.content_hash {
+        warn!("Local {}/{} differs from the state peer {} settled on", outcome.component, outcome.key, source_id);
+    }
+    
+    Ok(())
+}
+
+/// File holding a component's state
+pub fn component_path(component: &str, key: &str) -> Result<PathBuf> {
+    let root = constants::root_dir();
+    
+    match component {
+        COMPONENT_CONTRACTS => {
+            check_file_name(key)?;
+            Ok(root.join(".zk").join("contracts").join(key))
+        }
+        COMPONENT_STORE_INDEX => Ok(root.join(".store").join("index.json")),
+        COMPONENT_PEERS => Ok(root.join(".gossip").join("peers").join("registry.json")),
+        COMPONENT_TRACES => {
+            check_file_name(key)?;
+            Ok(root.join(".gossip").join("archive").join(key))
+        }
+        other => anyhow::bail!("Unknown sync component: {}", other),
+    }
+}
+
+/// Store a conflict record and the remote state next to it
+fn record_conflict(component: &str, key: &str, peer_id: &str, local: &[u8], remote: &[u8]) -> Result<Conflict> {
+    let remote_hash = blake3::hash(remote).to_hex().to_string();
+    let detected_at = now();
+    
+    let conflict = Conflict {
+        id: format!("{}-{}", detected_at, &remote_hash[..12]),
+        component: component.to_string(),
+        key: key.to_string(),
+        peer_id: peer_id.to_string(),
+        local_hash: blake3::hash(local).to_hex().to_string(),
+        remote_hash,
+        detected_at,
+        resolution: None,
+    };
+    
+    let dir = conflicts_dir();
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join(format!("{}.{}", conflict.id, REMOTE_EXT)), remote)?;
+    save_conflict(&conflict)?;
+    
+    Ok(conflict)
+}
+
+/// Load a conflict record
+fn load_conflict(id: &str) -> Result<Conflict> {
+    check_file_name(id)?;
+    let path = conflicts_dir().join(format!("{}.{}", id, CONFLICT_EXT));
+    if !path.exists() {
+        anyhow::bail!("Conflict not found: {}", id);
+    }
+    
+    let data = fs::read_to_string(&path)?;
+    serde_json::from_str(&data).with_context(|| format!("Invalid conflict record: {}", id))
+}
+
+/// Save a conflict record
+fn save_conflict(conflict: &Conflict) -> Result<()> {
+    let path = conflicts_dir().join(format!("{}.{}", conflict.id, CONFLICT_EXT));
+    fs::write(&path, serde_json::to_string_pretty(conflict)?)?;
+    Ok(())
+}
+
+/// Replace a component's state file
+fn write_state(path: &Path, content: &[u8]) -> Result<()> {
+    crate::core::fs::write_atomic(path, content)
+}
+
+/// Send a resolution outcome to the peer that sent the conflicting state
+fn send_outcome(peer_id: &str, outcome: &ConflictOutcome) {
+    let endpoint = match super::list_peers() {
+        Ok(peers) => peers.into_iter().find(|p| p.id == peer_id).map(|p| p.endpoint),
+        Err(e) => {
+            warn!("Failed to list peers for conflict outcome: {}", e);
+            return;
+        }
+    };
+    
+    let Some(endpoint) = endpoint else {
+        warn!("Peer {} is no longer known; conflict outcome not sent", peer_id);
+        return;
+    };
+    
+    let result = serde_json::to_vec(outcome).map_err(anyhow::Error::from).and_then(|payload| {
+        super::protocol::send_message(&endpoint, super::protocol::MessageType::ConflictOutcome, &payload)
+    });
+    if let Err(e) = result {
+        warn!("Failed to send conflict outcome to {}: {}", peer_id, e);
+    }
+}
+
+/// Canonical bytes covered by an outcome signature
+fn outcome_payload(outcome: &ConflictOutcome) -> Result<String> {
+    Ok(serde_json::to_string(&serde_json::json!({
+        "conflict_id": outcome.conflict_id,
+        "component": outcome.component,
+        "key": outcome.key,
+        "resolved_by": outcome.resolved_by,
+        "outcome": outcome.outcome,
+        "content_hash": outcome.content_hash,
+        "resolved_at": outcome.resolved_at,
+    }))?)
+}
+
+/// Reject keys and IDs that could escape their directory
+fn check_file_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.starts_with('.') || name.contains('/') || name.contains('\\') {
+        anyhow::bail!("Invalid name: {}", name);
+    }
+    Ok(())
+}
+
+/// Directory of conflict records
+fn conflicts_dir() -> PathBuf {
+    constants::root_dir().join(".gossip").join("sync").join("conflicts")
+}
+
+/// Directory of outcomes received from peers
+fn outcomes_dir() -> PathBuf {
+    constants::root_dir().join(".gossip").join("sync").join("outcomes")
+}
+
+/// Current time in seconds since epoch
+fn now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+    use serde_json::json;
+    use crate::store::Package;
+    use crate::store::advisory::Advisory;
+    
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+    
+    /// Store key trusted in the test root, so entries it signs are valid
+    fn store_key() -> SigningKey {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        fs::create_dir_all(constants::root_dir().join(".store")).unwrap();
+        store::trust_key(&hex(key.verifying_key().as_bytes())).unwrap();
+        key
+    }
+    
+    fn package(name: &str, version: &str, signer: Option<&SigningKey>) -> Package {
+        let hash = blake3::hash(format!("{}-{}", name, version).as_bytes()).to_hex().to_string();
+        let message = format!("{}\n{}\n{}", name, version, hash);
+        Package {
+            name: name.to_string(),
+            version: version.to_string(),
+            description: String::new(),
+            author: "test".to_string(),
+            license: "MIT".to_string(),
+            dependencies: Vec::new(),
+            url: format!("https://example.org/{}-{}.pkg", name, version),
+            hash,
+            signature: signer.map(|key| hex(&key.sign(message.as_bytes()).to_bytes())).unwrap_or_default(),
+            zk_contract: None,
+            size: 0,
+        }
+    }
+    
+    fn advisory(id: &str) -> Advisory {
+        Advisory {
+            id: id.to_string(),
+            package: "a".to_string(),
+            affected: "<1.0.0".to_string(),
+            fixed_version: Some("1.0.0".to_string()),
+            severity: "high".to_string(),
+            summary: String::new(),
+        }
+    }
+    
+    fn index(packages: Vec<Package>, advisories: Vec<Advisory>, last_updated: u64) -> Vec<u8> {
+        let index = PackageIndex {
+            last_updated,
+            packages: packages.into_iter().map(|p| (p.name.clone(), p)).collect(),
+            advisories,
+        };
+        serde_json::to_vec(&index).unwrap()
+    }
+    
+    fn merged(resolution: Resolution) -> Vec<u8> {
+        match resolution {
+            Resolution::Merged(content) => content,
+            other => panic!("expected a merge, got {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn store_index_takes_higher_signed_versions() {
+        let key = store_key();
+        let local = index(vec![package("a", "1.0.0", Some(&key)), package("b", "2.0.0", Some(&key))], Vec::new(), 10);
+        let remote = index(vec![
+            package("a", "1.2.0", Some(&key)),
+            package("b", "1.5.0", Some(&key)),
+            package("c", "1.0.0", Some(&key)),
+            package("d", "1.0.0", None),
+        ], Vec::new(), 20);
+        
+        let merged: PackageIndex = serde_json::from_slice(&merged(StoreIndexResolver.resolve(&local, &remote).unwrap())).unwrap();
+        assert_eq!(merged.packages["a"].version, "1.2.0");
+        assert_eq!(merged.packages["b"].version, "2.0.0");
+        assert_eq!(merged.packages["c"].version, "1.0.0");
+        assert!(!merged.packages.contains_key("d"), "unsigned remote entries are ignored");
+        assert_eq!(merged.last_updated, 20);
+    }
+    
+    #[test]
+    fn store_index_replaces_unsigned_local_entries() {
+        let key = store_key();
+        let local = index(vec![package("a", "9.0.0", None)], Vec::new(), 10);
+        let remote = index(vec![package("a", "1.0.0", Some(&key))], Vec::new(), 5);
+        
+        let merged: PackageIndex = serde_json::from_slice(&merged(StoreIndexResolver.resolve(&local, &remote).unwrap())).unwrap();
+        assert_eq!(merged.packages["a"].version, "1.0.0");
+        assert!(store::signature_is_valid(&merged.packages["a"]));
+        assert_eq!(merged.last_updated, 10);
+    }
+    
+    #[test]
+    fn store_index_unions_advisories() {
+        let local = index(Vec::new(), vec![advisory("SA-1"), advisory("SA-2")], 0);
+        let remote = index(Vec::new(), vec![advisory("SA-2"), advisory("SA-3")], 0);
+        
+        let merged: PackageIndex = serde_json::from_slice(&merged(StoreIndexResolver.resolve(&local, &remote).unwrap())).unwrap();
+        let ids: Vec<&str> = merged.advisories.iter().map(|a| a.id.as_str()).collect();
+        assert_eq!(ids, ["SA-1", "SA-2", "SA-3"]);
+    }
+    
+    #[test]
+    fn peer_registry_union_merges_without_remote_keys() {
+        let local = json!({ "peers": {
+            "p1": { "id": "p1", "endpoint": "10.0.0.1:7000", "last_seen": 10, "status": "Online", "public_key": "aa" },
+            "p3": { "id": "p3", "endpoint": "10.0.0.3:7000", "last_seen": 50, "status": "Online", "public_key": "cc" },
+        }});
+        let remote = json!({ "peers": {
+            "p1": { "id": "p1", "endpoint": "10.0.0.9:7000", "last_seen": 20, "status": "Offline", "public_key": "ff" },
+            "p2": { "id": "p2", "endpoint": "10.0.0.2:7000", "last_seen": 30, "status": "Online", "public_key": "bb" },
+            "p3": { "id": "p3", "endpoint": "10.0.0.8:7000", "last_seen": 40, "status": "Offline", "public_key": "ee" },
+        }});
+        
+        let content = merged(PeerRegistryResolver.resolve(local.to_string().as_bytes(), remote.to_string().as_bytes()).unwrap());
+        let merged: serde_json::Value = serde_json::from_slice(&content).unwrap();
+        let peers = &merged["peers"];
+        
+        // Newer remote observations win, but never the key
+        assert_eq!(peers["p1"]["endpoint"], "10.0.0.9:7000");
+        assert_eq!(peers["p1"]["last_seen"], 20);
+        assert_eq!(peers["p1"]["public_key"], "aa");
+        
+        // Peers only the remote knows are added without a key
+        assert_eq!(peers["p2"]["endpoint"], "10.0.0.2:7000");
+        assert!(peers["p2"]["public_key"].is_null());
+        
+        // Older remote observations are ignored
+        assert_eq!(peers["p3"]["endpoint"], "10.0.0.3:7000");
+        assert_eq!(peers["p3"]["status"], "Online");
+    }
+    
+    #[test]
+    fn peer_registry_skips_this_node() {
+        let own_id = identity::node_id().unwrap();
+        let local = json!({ "peers": {} });
+        let remote = json!({ "peers": { (own_id.clone()): { "id": own_id, "endpoint": "10.0.0.1:7000", "last_seen": 1 } } });
+        
+        let content = merged(PeerRegistryResolver.resolve(local.to_string().as_bytes(), remote.to_string().as_bytes()).unwrap());
+        let merged: serde_json::Value = serde_json::from_slice(&content).unwrap();
+        assert!(merged["peers"].as_object().unwrap().is_empty());
+    }
+    
+    #[test]
+    fn traces_take_extensions_and_keep_diverged_history() {
+        assert_eq!(AppendOnlyResolver.resolve(b"one\n", b"one\ntwo\n").unwrap(), Resolution::Merged(b"one\ntwo\n".to_vec()));
+        assert_eq!(AppendOnlyResolver.resolve(b"one\ntwo\n", b"one\n").unwrap(), Resolution::KeepLocal);
+        assert_eq!(AppendOnlyResolver.resolve(b"one\ntwo\n", b"one\nsix\n").unwrap(), Resolution::KeepLocal);
+    }
+    
+    #[test]
+    fn reconcile_applies_trace_resolutions() {
+        let path = component_path(COMPONENT_TRACES, "reconcile.log").unwrap();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "one\n").unwrap();
+        
+        assert_eq!(reconcile(COMPONENT_TRACES, "reconcile.log", "p1", b"one\ntwo\n").unwrap(), None);
+        assert_eq!(fs::read(&path).unwrap(), b"one\ntwo\n");
+        
+        assert_eq!(reconcile(COMPONENT_TRACES, "reconcile.log", "p1", b"one\nsix\n").unwrap(), None);
+        assert_eq!(fs::read(&path).unwrap(), b"one\ntwo\n");
+    }
+    
+    #[test]
+    fn contracts_wait_for_a_manual_pick() {
+        assert_eq!(ManualResolver.resolve(b"local", b"remote").unwrap(), Resolution::Manual);
+        
+        let path = component_path(COMPONENT_CONTRACTS, "manual.yaml").unwrap();
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, "version: 1").unwrap();
+        
+        let id = reconcile(COMPONENT_CONTRACTS, "manual.yaml", "p1", b"version: 2").unwrap()
+            .expect("diverged contracts need a manual pick");
+        assert_eq!(fs::read(&path).unwrap(), b"version: 1", "local state is kept until resolved");
+        assert!(list_conflicts().unwrap().iter().any(|c| c.id == id && c.key == "manual.yaml"));
+        
+        let outcome = resolve(&id, Take::Remote).unwrap();
+        assert_eq!(outcome.outcome, "remote");
+        assert_eq!(outcome.content_hash, blake3::hash(b"version: 2").to_hex().to_string());
+        assert_eq!(fs::read(&path).unwrap(), b"version: 2");
+        assert!(!list_conflicts().unwrap().iter().any(|c| c.id == id));
+        assert!(resolve(&id, Take::Local).is_err(), "a conflict is resolved once");
+    }
+    
+    #[test]
+    fn component_keys_cannot_escape() {
+        assert!(component_path(COMPONENT_CONTRACTS, "../escape").is_err());
+        assert!(component_path(COMPONENT_TRACES, ".hidden").is_err());
+        assert!(component_path("unknown", "key").is_err());
+    }
+}
@@ -5,6 +5,10 @@ pub mod protocol;
 pub mod peers;
 pub mod sync;
 pub mod verify;
+pub mod conflict;
+pub mod attest;
+pub mod report;
+pub mod select;
 
 use anyhow::{Result, Context};
 use tracing::{info, debug, warn, error};
@@ -28,7 +32,7 @@ pub fn init() -> Result<()> {
     info!("Initializing SentientOS gossip system");
     
     // Create gossip system directories
-    let gossip_dir = PathBuf::from(constants::ROOT_DIR).join(".gossip");
+    let gossip_dir = constants::root_dir().join(".gossip");
     fs::create_dir_all(&gossip_dir)?;
     
     let peers_dir = gossip_dir.join("peers");
@@ -74,11 +78,12 @@ pub fn shutdown() -> Result<()> {
 }
 
 /// Add a new peer to the gossip network
-pub fn add_peer(peer_id: &str, endpoint: &str) -> Result<()> {
+///
+/// `gossip_key` is the public key from the peer's discovery message; peers
+/// added without one get theirs pinned when they are first discovered.
+pub fn add_peer(peer_id: &str, endpoint: &str, gossip_key: Option<&str>) -> Result<()> {
     info!("Adding peer to gossip network: {}", peer_id);
     
-    let mut registry = PEER_REGISTRY.lock().unwrap();
-    
     // Create the peer
     let peer = Peer {
         id: peer_id.to_string(),
@@ -86,10 +91,16 @@ pub fn add_peer(peer_id: &str, endpoint: &str) -> Result<()> {
         last_seen: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
         status: PeerStatus::Unknown,
         sync_status: HashMap::new(),
+        display_name: None,
+        public_key: None,
+        gossip_key: gossip_key.map(String::from),
+        pending_gossip_key: None,
+        demoted: false,
+        stats: select::PeerStats::default(),
     };
     
-    // Add to registry
-    registry.peers.insert(peer_id.to_string(), peer);
+    // Add to registry; the lock is released before saving, which takes it again
+    PEER_REGISTRY.lock().unwrap().peers.insert(peer_id.to_string(), peer);
     
     // Persist to disk
     save_peer_registry()?;
@@ -105,9 +116,7 @@ pub fn add_peer(peer_id: &str, endpoint: &str) -> Result<()> {
 pub fn remove_peer(peer_id: &str) -> Result<()> {
     info!("Removing peer from gossip network: {}", peer_id);
     
-    let mut registry = PEER_REGISTRY.lock().unwrap();
-    
-    if registry.peers.remove(peer_id).is_none() {
+    if PEER_REGISTRY.lock().unwrap().peers.remove(peer_id).is_none() {
         warn!("Attempted to remove unknown peer: {}", peer_id);
         return Ok(());
     }
@@ -133,6 +142,8 @@ pub fn list_peers() -> Result<Vec<PeerInfo>> {
             endpoint: peer.endpoint.clone(),
             last_seen: peer.last_seen,
             status: peer.status,
+            display_name: peer.display_name.clone(),
+            stats: peer.stats.clone(),
         });
     }
     
@@ -163,12 +174,17 @@ pub fn synchronize_with_peer(peer_id: &str) -> Result<()> {
 }
 
 /// Update peer status
+///
+/// `Online` means the peer was just heard from and refreshes `last_seen`;
+/// other statuses keep it, so a silent peer keeps ageing.
 pub fn update_peer_status(peer_id: &str, status: PeerStatus) -> Result<()> {
     let mut registry = PEER_REGISTRY.lock().unwrap();
     
     if let Some(peer) = registry.peers.get_mut(peer_id) {
         peer.status = status;
-        peer.last_seen = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        if status == PeerStatus::Online {
+            peer.last_seen = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        }
         
         // Persist changes
         save_peer_registry()?;
@@ -180,9 +196,136 @@ pub fn update_peer_status(peer_id: &str, status: PeerStatus) -> Result<()> {
     }
 }
 
+/// Record a peer's verified display name and public key
+pub fn update_peer_identity(peer_id: &str, display_name: &str, public_key: &str) -> Result<()> {
+    {
+        let mut registry = PEER_REGISTRY.lock().unwrap();
+        let peer = registry.peers.get_mut(peer_id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown peer: {}", peer_id))?;
+        
+        peer.display_name = Some(display_name.to_string());
+        peer.public_key = Some(public_key.to_string());
+    }
+    
+    save_peer_registry()?;
+    debug!("Updated identity for peer {}: {}", peer_id, display_name);
+    Ok(())
+}
+
+/// Record latency, throughput or outcome observations of a peer
+pub fn update_peer_stats(peer_id: &str, observe: impl FnOnce(&mut select::PeerStats)) -> Result<()> {
+    {
+        let mut registry = PEER_REGISTRY.lock().unwrap();
+        let peer = registry.peers.get_mut(peer_id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown peer: {}", peer_id))?;
+        
+        observe(&mut peer.stats);
+    }
+    
+    save_peer_registry()
+}
+
+/// Public key last verified for a peer, if any
+pub fn peer_public_key(peer_id: &str) -> Option<String> {
+    let registry = PEER_REGISTRY.lock().unwrap();
+    registry.peers.get(peer_id).and_then(|p| p.public_key.clone())
+}
+
+/// Gossip key pinned for a peer, which its messages must be signed with
+pub fn peer_gossip_key(peer_id: &str) -> Option<String> {
+    let registry = PEER_REGISTRY.lock().unwrap();
+    registry.peers.get(peer_id).and_then(|p| p.gossip_key.clone())
+}
+
+/// Pin the gossip key a known peer announced in discovery
+///
+/// The first key seen is pinned. A different key is kept as pending and
+/// rejected until `trust_gossip_key` accepts it.
+pub fn pin_gossip_key(peer_id: &str, key: &str) -> Result<()> {
+    let changed = {
+        let mut registry = PEER_REGISTRY.lock().unwrap();
+        let peer = registry.peers.get_mut(peer_id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown peer: {}", peer_id))?;
+        
+        match peer.gossip_key.as_deref() {
+            Some(pinned) if pinned == key => return Ok(()),
+            Some(_) => {
+                peer.pending_gossip_key = Some(key.to_string());
+                true
+            }
+            None => {
+                peer.gossip_key = Some(key.to_string());
+                false
+            }
+        }
+    };
+    
+    save_peer_registry()?;
+    if changed {
+        anyhow::bail!("Gossip key of peer {} changed; run `sentctl cli gossip trust {}` to accept it", peer_id, peer_id);
+    }
+    info!("Pinned gossip key for peer {}", peer_id);
+    Ok(())
+}
+
+/// Accept the changed gossip key a peer announced, returning it
+pub fn trust_gossip_key(peer_id: &str) -> Result<String> {
+    let key = {
+        let mut registry = PEER_REGISTRY.lock().unwrap();
+        let peer = registry.peers.get_mut(peer_id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown peer: {}", peer_id))?;
+        
+        let key = peer.pending_gossip_key.take()
+            .ok_or_else(|| anyhow::anyhow!("Peer {} has not announced a new gossip key", peer_id))?;
+        peer.gossip_key = Some(key.clone());
+        key
+    };
+    
+    save_peer_registry()?;
+    crate::logs::ship::ship_audit("gossip.trust", &format!("Trusted new gossip key {} for peer {}", key, peer_id));
+    Ok(key)
+}
+
+/// Whether a peer is trusted with signed fleet data
+///
+/// A peer is trusted once its signed identity has been verified, unless
+/// it was demoted for failing attestation.
+pub fn is_trusted_peer(peer_id: &str) -> bool {
+    let registry = PEER_REGISTRY.lock().unwrap();
+    registry.peers.get(peer_id).map_or(false, |p| p.public_key.is_some() && !p.demoted)
+}
+
+/// Latest report a peer included in its heartbeats
+pub fn peer_report(peer_id: &str) -> Result<Option<report::PeerReport>> {
+    report::peer_report(peer_id)
+}
+
+/// What every trusted peer reports about itself, with staleness
+pub fn fleet_summary() -> Result<report::FleetSummary> {
+    report::fleet_summary()
+}
+
+/// Demote a peer from trusted, or restore it
+pub fn set_peer_demoted(peer_id: &str, demoted: bool) -> Result<()> {
+    {
+        let mut registry = PEER_REGISTRY.lock().unwrap();
+        let peer = registry.peers.get_mut(peer_id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown peer: {}", peer_id))?;
+        
+        if peer.demoted == demoted {
+            return Ok(());
+        }
+        peer.demoted = demoted;
+    }
+    
+    save_peer_registry()?;
+    info!("Peer {} {}", peer_id, if demoted { "demoted" } else { "restored to trusted" });
+    Ok(())
+}
+
 /// Load peer registry from disk
 fn load_peer_registry() -> Result<()> {
-    let registry_path = PathBuf::from(constants::ROOT_DIR)
+    let registry_path = constants::root_dir()
         .join(".gossip")
         .join("peers")
         .join("registry.json");
@@ -193,11 +336,8 @@ fn load_peer_registry() -> Result<()> {
     }
     
     // Load the registry
-    let registry_json = fs::read_to_string(&registry_path)
-        .context("Failed to read peer registry")?;
-    
-    let loaded_registry: PeerRegistry = serde_json::from_str(&registry_json)
-        .context("Failed to parse peer registry JSON")?;
+    let loaded_registry: PeerRegistry = crate::core::fs::read_json_or_backup(&registry_path)
+        .context("Failed to load peer registry")?;
     
     // Update global registry
     let mut registry = PEER_REGISTRY.lock().unwrap();
@@ -209,16 +349,11 @@ fn load_peer_registry() -> Result<()> {
 
 /// Save peer registry to disk
 fn save_peer_registry() -> Result<()> {
-    let registry_path = PathBuf::from(constants::ROOT_DIR)
+    let registry_path = constants::root_dir()
         .join(".gossip")
         .join("peers")
         .join("registry.json");
     
-    // Ensure parent directory exists
-    if let Some(parent) = registry_path.parent() {
-        fs::create_dir_all(parent)?;
-    }
-    
     // Get registry
     let registry = PEER_REGISTRY.lock().unwrap();
     
@@ -227,7 +362,7 @@ fn save_peer_registry() -> Result<()> {
         .context("Failed to serialize peer registry")?;
     
     // Write to file
-    fs::write(&registry_path, registry_json)
+    crate::core::fs::write_atomic(&registry_path, registry_json.as_bytes())
         .context("Failed to write peer registry")?;
     
     debug!("Saved {} peers to registry", registry.peers.len());
@@ -267,6 +402,30 @@ struct Peer {
     
     /// Synchronization status for different components
     sync_status: HashMap<String, ComponentSyncStatus>,
+    
+    /// Display name from the peer's signed identity
+    #[serde(default)]
+    display_name: Option<String>,
+    
+    /// Public key from the peer's signed identity (hex)
+    #[serde(default)]
+    public_key: Option<String>,
+    
+    /// Gossip public key pinned at discovery (hex); messages must be signed with it
+    #[serde(default)]
+    gossip_key: Option<String>,
+    
+    /// Different gossip key the peer announced since, awaiting `gossip trust`
+    #[serde(default)]
+    pending_gossip_key: Option<String>,
+    
+    /// Demoted from trusted after repeated failed attestations
+    #[serde(default)]
+    demoted: bool,
+    
+    /// Latency and transfer observations, for peer selection
+    #[serde(default)]
+    stats: select::PeerStats,
 }
 
 /// Peer information for API responses
@@ -283,6 +442,12 @@ pub struct PeerInfo {
     
     /// Current peer status
     pub status: PeerStatus,
+    
+    /// Display name from the peer's signed identity
+    pub display_name: Option<String>,
+    
+    /// Latency and transfer observations
+    pub stats: select::PeerStats,
 }
 
 /// Peer status
@@ -332,3 +497,41 @@ pub fn discover_peers() -> Result<Vec<PeerInfo>> {
     // Return already known peers as a placeholder
     list_peers()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn first_gossip_key_is_pinned_and_changes_wait_for_trust() {
+        add_peer("gossip-key-peer", "127.0.0.1:29876", None).unwrap();
+        assert_eq!(peer_gossip_key("gossip-key-peer"), None);
+        
+        pin_gossip_key("gossip-key-peer", "aa").unwrap();
+        pin_gossip_key("gossip-key-peer", "aa").unwrap();
+        assert_eq!(peer_gossip_key("gossip-key-peer").as_deref(), Some("aa"));
+        assert!(trust_gossip_key("gossip-key-peer").is_err());
+        
+        // A changed key is held back, and the pinned one stays in force
+        let error = pin_gossip_key("gossip-key-peer", "bb").unwrap_err();
+        assert!(error.to_string().contains("gossip trust gossip-key-peer"), "{}", error);
+        assert_eq!(peer_gossip_key("gossip-key-peer").as_deref(), Some("aa"));
+        
+        assert_eq!(trust_gossip_key("gossip-key-peer").unwrap(), "bb");
+        assert_eq!(peer_gossip_key("gossip-key-peer").as_deref(), Some("bb"));
+        assert!(trust_gossip_key("gossip-key-peer").is_err());
+        
+        assert!(pin_gossip_key("gossip-unknown-peer", "aa").is_err());
+        assert!(trust_gossip_key("gossip-unknown-peer").is_err());
+    }
+    
+    #[test]
+    fn peers_added_from_discovery_keep_their_key() {
+        add_peer("gossip-discovered-peer", "127.0.0.1:29876", Some("cc")).unwrap();
+        assert_eq!(peer_gossip_key("gossip-discovered-peer").as_deref(), Some("cc"));
+        assert!(pin_gossip_key("gossip-discovered-peer", "dd").is_err());
+        
+        remove_peer("gossip-discovered-peer").unwrap();
+        assert_eq!(peer_gossip_key("gossip-discovered-peer"), None);
+    }
+}
@@ -4,7 +4,7 @@ use std::path::PathBuf;
 use std::fs;
 use std::sync::{Arc, Mutex};
 use std::collections::HashMap;
-use std::time::{SystemTime, Duration, UNIX_EPOCH};
+use std::time::{SystemTime, Duration, Instant, UNIX_EPOCH};
 use serde::{Serialize, Deserialize};
 use std::thread;
 use std::net::{UdpSocket, ToSocketAddrs};
@@ -12,15 +12,16 @@ use std::net::{UdpSocket, ToSocketAddrs};
 use crate::core::constants;
 use super::{PeerStatus, PeerInfo};
 
-// Peer activity timeouts
-const PEER_OFFLINE_THRESHOLD: u64 = 120; // seconds
-const HEARTBEAT_INTERVAL: u64 = 30; // seconds
+// Peer activity timeouts; heartbeat timing is in the protocol state
 const DISCOVERY_INTERVAL: u64 = 300; // seconds
 
 // Global peer tracker
 lazy_static::lazy_static! {
     static ref PEER_HEARTBEAT_THREAD: Arc<Mutex<Option<std::thread::JoinHandle<()>>>> = 
         Arc::new(Mutex::new(None));
+    
+    // When the last unacknowledged heartbeat was sent to each peer
+    static ref HEARTBEATS_SENT: Mutex<HashMap<String, Instant>> = Mutex::new(HashMap::new());
 }
 
 /// Initialize the peers subsystem
@@ -28,7 +29,7 @@ pub fn init() -> Result<()> {
     info!("Initializing gossip peers subsystem");
     
     // Create peers directory
-    let peers_dir = PathBuf::from(constants::ROOT_DIR)
+    let peers_dir = constants::root_dir()
         .join(".gossip")
         .join("peers");
     
@@ -79,6 +80,9 @@ fn start_heartbeat_thread() -> Result<()> {
 }
 
 /// Main heartbeat loop
+///
+/// Heartbeats and discovery pings are only sent while the protocol is
+/// enabled; peers are reaped either way, so silent ones still age out.
 fn heartbeat_loop() {
     let mut last_heartbeat = 0;
     let mut last_discovery = 0;
@@ -88,17 +92,23 @@ fn heartbeat_loop() {
             .duration_since(UNIX_EPOCH)
             .unwrap_or_else(|_| Duration::from_secs(0))
             .as_secs();
+        let config = super::protocol::liveness_config();
+        let enabled = super::protocol::is_enabled();
         
         // Check if it's time to send heartbeats
-        if now - last_heartbeat >= HEARTBEAT_INTERVAL {
-            if let Err(e) = send_heartbeats() {
-                error!("Error sending heartbeats: {}", e);
+        if enabled && now.saturating_sub(last_heartbeat) >= config.heartbeat_interval_secs {
+            match send_heartbeats() {
+                Ok(()) => crate::panic::crash::heartbeat("gossip", "heartbeats sent"),
+                Err(e) => {
+                    error!("Error sending heartbeats: {}", e);
+                    crate::panic::crash::heartbeat("gossip", "heartbeat failed");
+                }
             }
             last_heartbeat = now;
         }
         
         // Check if it's time to send discovery
-        if now - last_discovery >= DISCOVERY_INTERVAL {
+        if enabled && now.saturating_sub(last_discovery) >= DISCOVERY_INTERVAL {
             if let Err(e) = super::protocol::send_discovery_ping() {
                 error!("Error sending discovery ping: {}", e);
             }
@@ -106,7 +116,7 @@ fn heartbeat_loop() {
         }
         
         // Update peer status based on last seen time
-        if let Err(e) = update_peer_statuses() {
+        if let Err(e) = update_peer_statuses(&config, now) {
             error!("Error updating peer statuses: {}", e);
         }
         
@@ -125,18 +135,17 @@ fn send_heartbeats() -> Result<()> {
     let mut success_count = 0;
     let mut failure_count = 0;
     
+    // Trusted peers get our self-report; others an empty heartbeat
+    let report = super::report::heartbeat_payload();
+    
+    // Offline and failed peers are included, so their ack brings them back
     for peer in &peers {
-        // Skip offline peers
-        if peer.status == PeerStatus::Offline {
-            continue;
-        }
-        
-        // Create empty heartbeat payload
-        let payload = vec![];
+        let payload = if super::is_trusted_peer(&peer.id) { report.clone() } else { Vec::new() };
         
         // Send heartbeat message
         match super::protocol::send_message(&peer.endpoint, super::protocol::MessageType::Heartbeat, &payload) {
             Ok(_) => {
+                HEARTBEATS_SENT.lock().unwrap().insert(peer.id.clone(), Instant::now());
                 success_count += 1;
             },
             Err(e) => {
@@ -150,27 +159,49 @@ fn send_heartbeats() -> Result<()> {
     Ok(())
 }
 
-/// Update peer statuses based on last seen time
-fn update_peer_statuses() -> Result<()> {
-    // Get list of peers
-    let peers = super::list_peers()?;
+/// Record the round trip of the heartbeat a peer acknowledged
+pub fn heartbeat_acked(peer_id: &str) -> Result<()> {
+    let sent = match HEARTBEATS_SENT.lock().unwrap().remove(peer_id) {
+        Some(sent) => sent,
+        None => {
+            debug!("Unsolicited heartbeat ack from {}", peer_id);
+            return Ok(());
+        }
+    };
     
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_else(|_| Duration::from_secs(0))
-        .as_secs();
+    // An acknowledged heartbeat proves the peer is alive
+    super::update_peer_status(peer_id, PeerStatus::Online)?;
     
-    for peer in &peers {
-        // Skip peers that are already offline
-        if peer.status == PeerStatus::Offline {
-            continue;
-        }
+    let rtt = sent.elapsed();
+    if rtt > Duration::from_secs(super::protocol::liveness_config().offline_after_secs()) {
+        return Ok(());
+    }
+    super::update_peer_stats(peer_id, |stats| stats.observe_rtt(rtt))?;
+    debug!("Heartbeat round trip to {}: {:?}", peer_id, rtt);
+    Ok(())
+}
+
+/// Mark peers silent for too long offline, then in error
+///
+/// A peer goes offline after `offline_after_intervals` heartbeat intervals
+/// without being heard from and into error after `error_after_intervals`.
+/// Only changes are written; hearing from the peer again brings it back
+/// online.
+fn update_peer_statuses(config: &super::protocol::LivenessConfig, now: u64) -> Result<()> {
+    for peer in super::list_peers()? {
+        let silent = now.saturating_sub(peer.last_seen);
+        let target = match config.silent_status(silent) {
+            Some(target) => target,
+            None => continue,
+        };
         
-        // Check if peer is offline based on last seen time
-        if now - peer.last_seen > PEER_OFFLINE_THRESHOLD {
-            // Mark peer as offline
-            super::update_peer_status(&peer.id, PeerStatus::Offline)?;
-            debug!("Peer {} marked as offline", peer.id);
+        let worse = match target {
+            PeerStatus::Error => peer.status != PeerStatus::Error,
+            _ => !matches!(peer.status, PeerStatus::Offline | PeerStatus::Error),
+        };
+        if worse {
+            super::update_peer_status(&peer.id, target)?;
+            warn!("Peer {} marked {:?}: not heard from for {}s", peer.id, target, silent);
         }
     }
     
@@ -224,7 +255,7 @@ pub fn check_peer_reachability(peer_id: &str) -> Result<bool> {
 
 /// Load peer information
 pub fn load_peer_info(peer_id: &str) -> Result<PeerDetails> {
-    let peer_file = PathBuf::from(constants::ROOT_DIR)
+    let peer_file = constants::root_dir()
         .join(".gossip")
         .join("peers")
         .join(format!("{}.json", peer_id));
@@ -245,7 +276,7 @@ pub fn load_peer_info(peer_id: &str) -> Result<PeerDetails> {
 
 /// Save peer information
 pub fn save_peer_info(peer_id: &str, details: &PeerDetails) -> Result<()> {
-    let peer_file = PathBuf::from(constants::ROOT_DIR)
+    let peer_file = constants::root_dir()
         .join(".gossip")
         .join("peers")
         .join(format!("{}.json", peer_id));
@@ -309,3 +340,81 @@ pub struct SyncEvent {
     /// Description
     pub description: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::protocol::LivenessConfig;
+    
+    /// Long ago; peers added by other tests were seen after it, so the
+    /// reaper leaves them alone when `now` is close to it. Each test has
+    /// its own, after the times the tests before it reap at.
+    const EPOCH: u64 = 1_000_000;
+    
+    /// Add a peer last heard from at `last_seen`
+    fn silent_peer(id: &str, last_seen: u64) {
+        super::super::add_peer(id, "127.0.0.1:9", None).unwrap();
+        super::super::PEER_REGISTRY.lock().unwrap().peers.get_mut(id).unwrap().last_seen = last_seen;
+    }
+    
+    fn peer(id: &str) -> PeerInfo {
+        super::super::list_peers().unwrap().into_iter().find(|p| p.id == id).unwrap()
+    }
+    
+    #[test]
+    fn silent_peers_go_offline_then_error() {
+        let config = LivenessConfig { heartbeat_interval_secs: 2, offline_after_intervals: 3, error_after_intervals: 10 };
+        let epoch = 2 * EPOCH;
+        silent_peer("peers-test-silent", epoch);
+        
+        update_peer_statuses(&config, epoch + 6).unwrap();
+        assert_eq!(peer("peers-test-silent").status, PeerStatus::Unknown);
+        
+        update_peer_statuses(&config, epoch + 7).unwrap();
+        assert_eq!(peer("peers-test-silent").status, PeerStatus::Offline);
+        
+        // Being marked offline does not reset the silence
+        assert_eq!(peer("peers-test-silent").last_seen, epoch);
+        update_peer_statuses(&config, epoch + 20).unwrap();
+        assert_eq!(peer("peers-test-silent").status, PeerStatus::Offline);
+        
+        update_peer_statuses(&config, epoch + 21).unwrap();
+        assert_eq!(peer("peers-test-silent").status, PeerStatus::Error);
+        update_peer_statuses(&config, epoch + 100).unwrap();
+        assert_eq!(peer("peers-test-silent").status, PeerStatus::Error);
+    }
+    
+    #[test]
+    fn acknowledged_heartbeats_bring_peers_back_online() {
+        let config = LivenessConfig::default();
+        silent_peer("peers-test-returning", EPOCH);
+        update_peer_statuses(&config, EPOCH + config.error_after_secs() + 1).unwrap();
+        assert_eq!(peer("peers-test-returning").status, PeerStatus::Error);
+        
+        // Acks only count for heartbeats that were sent
+        heartbeat_acked("peers-test-returning").unwrap();
+        assert_eq!(peer("peers-test-returning").status, PeerStatus::Error);
+        
+        HEARTBEATS_SENT.lock().unwrap().insert("peers-test-returning".to_string(), Instant::now());
+        heartbeat_acked("peers-test-returning").unwrap();
+        let returned = peer("peers-test-returning");
+        assert_eq!(returned.status, PeerStatus::Online);
+        assert!(returned.last_seen > EPOCH);
+        assert!(returned.stats.rtt_ms.is_some());
+    }
+    
+    #[test]
+    fn liveness_thresholds_are_whole_heartbeat_intervals() {
+        let config = LivenessConfig::default();
+        assert_eq!((config.offline_after_secs(), config.error_after_secs()), (90, 300));
+        assert_eq!(config.silent_status(90), None);
+        assert_eq!(config.silent_status(91), Some(PeerStatus::Offline));
+        assert_eq!(config.silent_status(300), Some(PeerStatus::Offline));
+        assert_eq!(config.silent_status(301), Some(PeerStatus::Error));
+        
+        // State files from before the liveness section keep the defaults
+        assert_eq!(serde_json::from_str::<LivenessConfig>("{}").unwrap(), config);
+        let partial: LivenessConfig = serde_json::from_str(r#"{ "heartbeat_interval_secs": 5 }"#).unwrap();
+        assert_eq!((partial.offline_after_secs(), partial.error_after_secs()), (15, 50));
+    }
+}
@@ -1,13 +1,14 @@
 use anyhow::{Result, Context};
 use tracing::{info, debug, warn, error};
 use std::path::PathBuf;
+use std::collections::HashMap;
 use std::fs;
 use std::net::{SocketAddr, UdpSocket};
 use std::time::{SystemTime, Duration, UNIX_EPOCH};
-use std::sync::{Arc, Mutex};
+use std::sync::{mpsc, Arc, Mutex};
 use std::thread;
 use serde::{Serialize, Deserialize};
-use blake3;
+use ed25519_dalek::{Signer, SigningKey};
 
 use crate::core::constants;
 
@@ -15,12 +16,92 @@ const PROTOCOL_VERSION: u8 = 1;
 const MAX_MESSAGE_SIZE: usize = 65507; // Max UDP packet size
 const DEFAULT_PORT: u16 = 29876;
 const DISCOVERY_PORT: u16 = 29877;
-const HEARTBEAT_INTERVAL: u64 = 30; // seconds
+const GOSSIP_KEYS_DIR: &str = "gossip";
+const GOSSIP_KEY_FILE: &str = "node.key";
+
+// This node's gossip signing key, loaded once
+static GOSSIP_KEY: Mutex<Option<SigningKey>> = Mutex::new(None);
 
 // Global protocol state
 lazy_static::lazy_static! {
     static ref PROTOCOL_STATE: Arc<Mutex<ProtocolState>> = 
         Arc::new(Mutex::new(ProtocolState::new()));
+    
+    // Requests waiting for their response, by request ID
+    static ref PENDING_REQUESTS: Mutex<HashMap<String, PendingRequest>> = Mutex::new(HashMap::new());
+}
+
+/// Request/response settings, stored under `gossip_requests` in system.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RequestConfig {
+    /// How long a request waits for its response, in seconds
+    pub timeout_secs: u64,
+}
+
+impl Default for RequestConfig {
+    fn default() -> Self {
+        Self { timeout_secs: 5 }
+    }
+}
+
+/// Heartbeat and peer liveness settings, stored under `liveness` in the protocol state
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LivenessConfig {
+    /// How often heartbeats are sent to every peer, in seconds
+    pub heartbeat_interval_secs: u64,
+    
+    /// Heartbeat intervals a peer may stay silent before it is marked offline
+    pub offline_after_intervals: u64,
+    
+    /// Heartbeat intervals a peer may stay silent before it is marked in error
+    pub error_after_intervals: u64,
+}
+
+impl Default for LivenessConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_interval_secs: 30,
+            offline_after_intervals: 3,
+            error_after_intervals: 10,
+        }
+    }
+}
+
+impl LivenessConfig {
+    /// Seconds of silence after which a peer is offline
+    pub fn offline_after_secs(&self) -> u64 {
+        self.heartbeat_interval_secs.saturating_mul(self.offline_after_intervals)
+    }
+    
+    /// Seconds of silence after which a peer is in error
+    pub fn error_after_secs(&self) -> u64 {
+        self.heartbeat_interval_secs.saturating_mul(self.error_after_intervals)
+    }
+    
+    /// Status a peer silent for `silent_secs` has fallen to, if any
+    pub fn silent_status(&self, silent_secs: u64) -> Option<super::PeerStatus> {
+        if silent_secs > self.error_after_secs() {
+            Some(super::PeerStatus::Error)
+        } else if silent_secs > self.offline_after_secs() {
+            Some(super::PeerStatus::Offline)
+        } else {
+            None
+        }
+    }
+}
+
+/// A request waiting for its response
+struct PendingRequest {
+    /// Peer the request was sent to; responses from anyone else are ignored
+    peer_id: String,
+    
+    /// Message type the response must have
+    response_type: MessageType,
+    
+    /// Where the listener hands the response payload over
+    sender: mpsc::Sender<Vec<u8>>,
 }
 
 /// Initialize the gossip protocol subsystem
@@ -28,16 +109,29 @@ pub fn init() -> Result<()> {
     info!("Initializing gossip protocol subsystem");
     
     // Create protocol directories
-    let protocol_dir = PathBuf::from(constants::ROOT_DIR)
+    let protocol_dir = constants::root_dir()
         .join(".gossip")
         .join("protocol");
     
     fs::create_dir_all(&protocol_dir)?;
     
+    // Messages are signed with a key of the node's own, generated on first start
+    gossip_key()?;
+    
     // Initialize the protocol state
     let mut state = PROTOCOL_STATE.lock().unwrap();
     *state = load_protocol_state()?;
     
+    // Node identity is owned by core; the persisted copy is informational only
+    state.node_id = crate::core::identity::node_id()?;
+    
+    // Advertise capabilities added since the state was persisted
+    for capability in ProtocolState::new().capabilities {
+        if !state.capabilities.contains(&capability) {
+            state.capabilities.push(capability);
+        }
+    }
+    
     // Start the background listener thread if enabled
     if state.enabled {
         start_listener_thread()?;
@@ -77,6 +171,16 @@ pub fn enable() -> Result<()> {
     Ok(())
 }
 
+/// Whether the gossip protocol is enabled
+pub fn is_enabled() -> bool {
+    PROTOCOL_STATE.lock().unwrap().enabled
+}
+
+/// Heartbeat and liveness settings from the protocol state
+pub fn liveness_config() -> LivenessConfig {
+    PROTOCOL_STATE.lock().unwrap().liveness
+}
+
 /// Disable the gossip protocol
 pub fn disable() -> Result<()> {
     let mut state = PROTOCOL_STATE.lock().unwrap();
@@ -90,16 +194,6 @@ pub fn disable() -> Result<()> {
     Ok(())
 }
 
-/// Set the node identifier
-pub fn set_node_id(node_id: &str) -> Result<()> {
-    let mut state = PROTOCOL_STATE.lock().unwrap();
-    state.node_id = node_id.to_string();
-    
-    save_protocol_state(&*state)?;
-    info!("Node ID set to: {}", node_id);
-    Ok(())
-}
-
 /// Send a gossip message to a specific peer
 pub fn send_message(peer_endpoint: &str, message_type: MessageType, payload: &[u8]) -> Result<()> {
     let state = PROTOCOL_STATE.lock().unwrap();
@@ -112,19 +206,8 @@ pub fn send_message(peer_endpoint: &str, message_type: MessageType, payload: &[u
     let peer_addr: SocketAddr = peer_endpoint.parse()
         .with_context(|| format!("Invalid peer endpoint: {}", peer_endpoint))?;
     
-    // Create message
-    let message = Message {
-        version: PROTOCOL_VERSION,
-        source_id: state.node_id.clone(),
-        message_type,
-        timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
-        payload: payload.to_vec(),
-        signature: String::new(), // TODO: Implement proper signatures
-    };
-    
-    // Serialize message
-    let message_bytes = bincode::serialize(&message)
-        .context("Failed to serialize gossip message")?;
+    // Create and serialize message
+    let message_bytes = encode_message(&state.node_id, message_type, payload)?;
     
     // Check message size
     if message_bytes.len() > MAX_MESSAGE_SIZE {
@@ -136,13 +219,53 @@ pub fn send_message(peer_endpoint: &str, message_type: MessageType, payload: &[u
     let socket = UdpSocket::bind("0.0.0.0:0")
         .context("Failed to create UDP socket for sending")?;
     
-    socket.send_to(&message_bytes, peer_addr)
-        .with_context(|| format!("Failed to send gossip message to {}", peer_endpoint))?;
+    if let Err(e) = socket.send_to(&message_bytes, peer_addr) {
+        crate::logs::metrics::increment("gossip.send_failures");
+        return Err(e).with_context(|| format!("Failed to send gossip message to {}", peer_endpoint));
+    }
+    crate::logs::metrics::increment("gossip.messages_sent");
     
-    debug!("Sent gossip message to {}: {:?}", peer_endpoint, message.message_type);
+    debug!("Sent gossip message to {}: {:?}", peer_endpoint, message_type);
     Ok(())
 }
 
+/// Serialize a message as it is sent on the wire
+pub fn encode_message(source_id: &str, message_type: MessageType, payload: &[u8]) -> Result<Vec<u8>> {
+    let mut message = Message {
+        version: PROTOCOL_VERSION,
+        source_id: source_id.to_string(),
+        message_type,
+        timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        payload: payload.to_vec(),
+        signature: String::new(),
+    };
+    message.signature = to_hex(&gossip_key()?.sign(&signed_body(&message)?).to_bytes());
+    
+    bincode::serialize(&message).context("Failed to serialize gossip message")
+}
+
+/// Deserialize a wire message into its source, type and payload
+pub fn decode_message(message_data: &[u8]) -> Result<(String, MessageType, Vec<u8>)> {
+    let message: Message = bincode::deserialize(message_data)
+        .context("Failed to deserialize gossip message")?;
+    
+    if message.version != PROTOCOL_VERSION {
+        anyhow::bail!("Unsupported protocol version: {}", message.version);
+    }
+    
+    Ok((message.source_id, message.message_type, message.payload))
+}
+
+/// This node's gossip public key (hex), carried in discovery messages
+pub fn public_key() -> Result<String> {
+    Ok(to_hex(gossip_key()?.verifying_key().as_bytes()))
+}
+
+/// Capabilities this node advertises
+pub fn capabilities() -> Vec<String> {
+    PROTOCOL_STATE.lock().unwrap().capabilities.clone()
+}
+
 /// Send a discovery ping to find peers
 pub fn send_discovery_ping() -> Result<()> {
     // Create discovery message
@@ -157,6 +280,7 @@ pub fn send_discovery_ping() -> Result<()> {
         node_id: state.node_id.clone(),
         capabilities: state.capabilities.clone(),
         version: state.version.clone(),
+        public_key: public_key()?,
     };
     
     let payload = bincode::serialize(&discovery_info)
@@ -269,12 +393,34 @@ fn handle_message(message_data: &[u8], src: SocketAddr) -> Result<()> {
         return Ok(());
     }
     
+    // Only messages signed with the key pinned for the sender are processed
+    if let Err(e) = authenticate(&message) {
+        crate::logs::metrics::increment("gossip.unauthenticated_messages");
+        warn!("Dropping unauthenticated {:?} from {} ({}): {:#}", message.message_type, message.source_id, src, e);
+        return Ok(());
+    }
+    
     // Process message based on type
     match message.message_type {
         MessageType::Heartbeat => {
             debug!("Received heartbeat from {}", message.source_id);
             // Update peer last seen time
             super::update_peer_status(&message.source_id, super::PeerStatus::Online)?;
+            if !message.payload.is_empty() {
+                super::report::handle_report(&message.source_id, &message.payload)?;
+            }
+            
+            // Acknowledge to the registered endpoint, so the sender can time the round trip
+            let endpoint = super::list_peers()?.into_iter()
+                .find(|p| p.id == message.source_id)
+                .map(|p| p.endpoint);
+            if let Some(endpoint) = endpoint {
+                send_message(&endpoint, MessageType::HeartbeatAck, &[])?;
+            }
+        },
+        MessageType::HeartbeatAck => {
+            debug!("Received heartbeat ack from {}", message.source_id);
+            super::peers::heartbeat_acked(&message.source_id)?;
         },
         MessageType::SyncRequest => {
             debug!("Received sync request from {}", message.source_id);
@@ -291,8 +437,295 @@ fn handle_message(message_data: &[u8], src: SocketAddr) -> Result<()> {
             // Pass to sync module
             super::sync::handle_state_update(&message.source_id, &message.payload)?;
         },
+        MessageType::LogBatch => {
+            debug!("Received log batch from {}", message.source_id);
+            store_log_batch(&message.source_id, &message.payload)?;
+        },
+        MessageType::IdentityAnnouncement => {
+            debug!("Received identity announcement from {}", message.source_id);
+            handle_identity_announcement(&message.source_id, &message.payload)?;
+        },
+        MessageType::AdvisoryReport => {
+            debug!("Received advisory report from {}", message.source_id);
+            crate::store::advisory::handle_peer_report(&message.source_id, &message.payload)?;
+        },
+        MessageType::ContractProposal => {
+            debug!("Received contract proposal from {}", message.source_id);
+            crate::zk::deploy::handle_peer_proposal(&message.source_id, &message.payload)?;
+        },
+        MessageType::ContractApproval => {
+            debug!("Received contract approval from {}", message.source_id);
+            crate::zk::deploy::handle_peer_approval(&message.source_id, &message.payload)?;
+        },
+        MessageType::ConflictOutcome => {
+            debug!("Received conflict outcome from {}", message.source_id);
+            super::conflict::handle_peer_outcome(&message.source_id, &message.payload)?;
+        },
+        MessageType::AttestChallenge => {
+            debug!("Received attestation challenge from {}", message.source_id);
+            super::attest::handle_challenge(&message.source_id, &message.payload)?;
+        },
+        MessageType::AttestResponse => {
+            debug!("Received attestation response from {}", message.source_id);
+            super::attest::handle_response(&message.source_id, &message.payload)?;
+        },
+        MessageType::ReportRequest => {
+            debug!("Received report request from {}", message.source_id);
+            super::report::handle_request(&message.source_id)?;
+        },
+        MessageType::PeerReport => {
+            debug!("Received report from {}", message.source_id);
+            super::report::handle_report(&message.source_id, &message.payload)?;
+        },
+        MessageType::ExecAllowList => {
+            debug!("Received execution allow-list from {}", message.source_id);
+            crate::matrixbox::policy::handle_peer_allowlist(&message.source_id, &message.payload)?;
+        },
+        MessageType::DataSync => {
+            debug!("Received data sync frame from {}", message.source_id);
+            crate::matrixbox::sync::handle_frame(&message.source_id, &message.payload)?;
+        },
+        MessageType::TraceHashRequest => {
+            debug!("Received trace hash request from {}", message.source_id);
+            handle_trace_hash_request(&message.source_id, src, &message.payload)?;
+        },
+        MessageType::ListTraceFilesRequest => {
+            debug!("Received trace file list request from {}", message.source_id);
+            handle_list_trace_files_request(&message.source_id, src, &message.payload)?;
+        },
+        MessageType::GetTraceFileRequest => {
+            debug!("Received trace file request from {}", message.source_id);
+            handle_get_trace_file_request(&message.source_id, src, &message.payload)?;
+        },
+        MessageType::GetPackageArchiveRequest => {
+            debug!("Received package archive request from {}", message.source_id);
+            handle_get_package_archive_request(&message.source_id, src, &message.payload)?;
+        },
+        MessageType::TraceHashResponse
+        | MessageType::ListTraceFilesResponse
+        | MessageType::GetTraceFileResponse
+        | MessageType::GetPackageArchiveResponse => {
+            debug!("Received {:?} from {}", message.message_type, message.source_id);
+            route_response(&message.source_id, message.message_type, message.payload)?;
+        },
+    }
+    
+    Ok(())
+}
+
+/// Check a message's signature against the gossip key pinned for its sender
+fn authenticate(message: &Message) -> Result<()> {
+    let key = super::peer_gossip_key(&message.source_id)
+        .ok_or_else(|| anyhow::anyhow!("No gossip key is pinned for peer {}", message.source_id))?;
+    crate::core::identity::verify(&key, &signed_body(message)?, &message.signature)
+        .context("Message signature is invalid")
+}
+
+/// Bytes a message signature covers: every field but the signature
+fn signed_body(message: &Message) -> Result<Vec<u8>> {
+    bincode::serialize(&(message.version, &message.source_id, message.message_type, message.timestamp, &message.payload))
+        .context("Failed to serialize gossip message body")
+}
+
+/// This node's gossip signing key, generated under `.auth/keys/gossip` on first use
+fn gossip_key() -> Result<SigningKey> {
+    let mut cached = GOSSIP_KEY.lock().unwrap();
+    if let Some(key) = cached.as_ref() {
+        return Ok(key.clone());
+    }
+    
+    let path = constants::root_dir()
+        .join(constants::AUTH_DIR)
+        .join("keys")
+        .join(GOSSIP_KEYS_DIR)
+        .join(GOSSIP_KEY_FILE);
+    let key = if path.exists() {
+        let seed: [u8; 32] = from_hex(fs::read_to_string(&path)?.trim())?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid gossip signing key {:?}", path))?;
+        SigningKey::from_bytes(&seed)
+    } else {
+        use rand::{thread_rng, Rng};
+        
+        let seed: [u8; 32] = thread_rng().gen();
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, to_hex(&seed)).with_context(|| format!("Failed to write gossip signing key {:?}", path))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        }
+        info!("Generated gossip signing key {:?}", path);
+        SigningKey::from_bytes(&seed)
+    };
+    
+    *cached = Some(key.clone());
+    Ok(key)
+}
+
+/// Hand a response to the request waiting for it
+fn route_response(source_id: &str, message_type: MessageType, payload: Vec<u8>) -> Result<()> {
+    let header: ResponseHeader = serde_json::from_slice(&payload)
+        .context("Failed to parse response request ID")?;
+    
+    let mut pending = PENDING_REQUESTS.lock().unwrap();
+    let matches = match pending.get(&header.request_id) {
+        Some(request) => request.peer_id == source_id && request.response_type == message_type,
+        None => {
+            debug!("Dropping {:?} from {} for unknown or timed-out request {}", message_type, source_id, header.request_id);
+            return Ok(());
+        }
+    };
+    if !matches {
+        warn!("Ignoring {:?} from {} for request {} sent elsewhere", message_type, source_id, header.request_id);
+        return Ok(());
+    }
+    
+    // The caller may have just timed out and dropped its receiver
+    let request = pending.remove(&header.request_id).unwrap();
+    let _ = request.sender.send(payload);
+    Ok(())
+}
+
+/// Reply to a peer's request with its local trace hash
+fn handle_trace_hash_request(source_id: &str, src: SocketAddr, payload: &[u8]) -> Result<()> {
+    let request: TraceHashRequestMsg = serde_json::from_slice(payload)
+        .context("Failed to parse trace hash request")?;
+    
+    let response = match super::verify::local_trace_hash() {
+        Ok(hash) => TraceHashResponseMsg { request_id: request.request_id, hash, error: None },
+        Err(e) => TraceHashResponseMsg { request_id: request.request_id, hash: String::new(), error: Some(format!("{:#}", e)) },
+    };
+    reply(source_id, src, MessageType::TraceHashResponse, &serde_json::to_vec(&response)?)
+}
+
+/// Reply to a peer's request with the list of local trace files
+fn handle_list_trace_files_request(source_id: &str, src: SocketAddr, payload: &[u8]) -> Result<()> {
+    let request: ListTraceFilesRequestMsg = serde_json::from_slice(payload)
+        .context("Failed to parse trace file list request")?;
+    
+    let response = match super::verify::local_trace_files() {
+        Ok(files) => ListTraceFilesResponseMsg {
+            request_id: request.request_id,
+            files: files.into_iter()
+                .map(|f| TraceFile { name: f.name, size: f.size, hash: f.hash })
+                .collect(),
+            error: None,
+        },
+        Err(e) => ListTraceFilesResponseMsg { request_id: request.request_id, files: Vec::new(), error: Some(format!("{:#}", e)) },
+    };
+    reply(source_id, src, MessageType::ListTraceFilesResponse, &serde_json::to_vec(&response)?)
+}
+
+/// Reply to a peer's request with the content of a local trace file
+fn handle_get_trace_file_request(source_id: &str, src: SocketAddr, payload: &[u8]) -> Result<()> {
+    let request: GetTraceFileRequestMsg = serde_json::from_slice(payload)
+        .context("Failed to parse trace file request")?;
+    
+    // Only files in the listing are served, so the name cannot escape the trace directory
+    let content = super::verify::local_trace_files().and_then(|files| {
+        if !files.iter().any(|f| f.name == request.filename) {
+            anyhow::bail!("No trace file named {}", request.filename);
+        }
+        Ok(fs::read(constants::root_dir().join(".runtime").join(&request.filename))?)
+    });
+    let mut response = GetTraceFileResponseMsg {
+        request_id: request.request_id,
+        filename: request.filename,
+        content: String::new(),
+        error: None,
+    };
+    match content {
+        Ok(content) => response.content = to_hex(&content),
+        Err(e) => response.error = Some(format!("{:#}", e)),
+    }
+    
+    let endpoint = reply_endpoint(source_id, src)?;
+    if let Err(e) = send_message(&endpoint, MessageType::GetTraceFileResponse, &serde_json::to_vec(&response)?) {
+        // Most likely too large for one message; tell the peer instead of letting it time out
+        response.content = String::new();
+        response.error = Some(format!("{:#}", e));
+        send_message(&endpoint, MessageType::GetTraceFileResponse, &serde_json::to_vec(&response)?)?;
+    }
+    Ok(())
+}
+
+/// Reply to a peer's request with a package archive this node holds
+fn handle_get_package_archive_request(source_id: &str, src: SocketAddr, payload: &[u8]) -> Result<()> {
+    let request: GetPackageArchiveRequestMsg = serde_json::from_slice(payload)
+        .context("Failed to parse package archive request")?;
+    
+    let mut response = GetPackageArchiveResponseMsg {
+        request_id: request.request_id,
+        name: request.name,
+        version: request.version,
+        content: String::new(),
+        error: None,
+    };
+    match crate::store::local_archive(&response.name, &response.version) {
+        Ok(content) => response.content = to_hex(&content),
+        Err(e) => response.error = Some(format!("{:#}", e)),
+    }
+    
+    let endpoint = reply_endpoint(source_id, src)?;
+    if let Err(e) = send_message(&endpoint, MessageType::GetPackageArchiveResponse, &serde_json::to_vec(&response)?) {
+        // Most likely too large for one message; tell the peer instead of letting it time out
+        response.content = String::new();
+        response.error = Some(format!("{:#}", e));
+        send_message(&endpoint, MessageType::GetPackageArchiveResponse, &serde_json::to_vec(&response)?)?;
+    }
+    Ok(())
+}
+
+/// Send a response to the peer that made a request
+fn reply(source_id: &str, src: SocketAddr, message_type: MessageType, payload: &[u8]) -> Result<()> {
+    send_message(&reply_endpoint(source_id, src)?, message_type, payload)
+}
+
+/// Where a peer listens: its registered endpoint, or the gossip port on its address
+///
+/// Requests arrive from an ephemeral port, so the source address itself is
+/// not where the peer receives.
+fn reply_endpoint(source_id: &str, src: SocketAddr) -> Result<String> {
+    let registered = super::list_peers()?.into_iter()
+        .find(|p| p.id == source_id)
+        .map(|p| p.endpoint);
+    Ok(registered.unwrap_or_else(|| format!("{}:{}", src.ip(), DEFAULT_PORT)))
+}
+
+/// Verify a peer's identity announcement and update its registry entry
+fn handle_identity_announcement(source_id: &str, payload: &[u8]) -> Result<()> {
+    let identity: crate::core::identity::Identity = serde_json::from_slice(payload)
+        .context("Failed to parse identity announcement")?;
+    
+    if identity.id != source_id {
+        warn!("Identity announcement for {} sent by {}, ignoring", identity.id, source_id);
+        return Ok(());
     }
     
+    let known_key = super::peer_public_key(source_id);
+    crate::core::identity::verify_identity(&identity, known_key.as_deref())?;
+    
+    super::update_peer_identity(source_id, &identity.name, &identity.public_key)?;
+    info!("Peer {} is now known as {}", source_id, identity.name);
+    Ok(())
+}
+
+/// Append a log batch shipped by a peer to its received log file
+fn store_log_batch(source_id: &str, payload: &[u8]) -> Result<()> {
+    let received_dir = constants::root_dir()
+        .join(".logs")
+        .join("received");
+    fs::create_dir_all(&received_dir)?;
+    
+    let log_path = received_dir.join(format!("{}.jsonl", source_id));
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .with_context(|| format!("Failed to open received log file: {:?}", log_path))?;
+    
+    std::io::Write::write_all(&mut file, payload)?;
     Ok(())
 }
 
@@ -304,6 +737,11 @@ fn handle_discovery(message_data: &[u8], src: SocketAddr) -> Result<()> {
     
     debug!("Received discovery from node: {}", discovery_info.node_id);
     
+    if from_hex(&discovery_info.public_key).map_or(true, |key| key.len() != 32) {
+        warn!("Ignoring discovery from {} ({}) without a valid gossip key", discovery_info.node_id, src);
+        return Ok(());
+    }
+    
     // Don't respond to own discovery messages
     let state = PROTOCOL_STATE.lock().unwrap();
     if discovery_info.node_id == state.node_id {
@@ -320,9 +758,15 @@ fn handle_discovery(message_data: &[u8], src: SocketAddr) -> Result<()> {
     
     if !known {
         // Add new peer
-        super::add_peer(&discovery_info.node_id, &endpoint)?;
+        super::add_peer(&discovery_info.node_id, &endpoint, Some(&discovery_info.public_key))?;
         info!("Discovered new peer: {}", discovery_info.node_id);
     } else {
+        // A changed key is held back until `gossip trust` accepts it
+        if let Err(e) = super::pin_gossip_key(&discovery_info.node_id, &discovery_info.public_key) {
+            warn!("{:#}", e);
+            return Ok(());
+        }
+        
         // Update existing peer status
         super::update_peer_status(&discovery_info.node_id, super::PeerStatus::Online)?;
         debug!("Updated existing peer from discovery: {}", discovery_info.node_id);
@@ -333,7 +777,7 @@ fn handle_discovery(message_data: &[u8], src: SocketAddr) -> Result<()> {
 
 /// Load protocol state from disk
 fn load_protocol_state() -> Result<ProtocolState> {
-    let state_path = PathBuf::from(constants::ROOT_DIR)
+    let state_path = constants::root_dir()
         .join(".gossip")
         .join("protocol")
         .join("state.json");
@@ -355,7 +799,7 @@ fn load_protocol_state() -> Result<ProtocolState> {
 
 /// Save protocol state to disk
 fn save_protocol_state(state: &ProtocolState) -> Result<()> {
-    let state_path = PathBuf::from(constants::ROOT_DIR)
+    let state_path = constants::root_dir()
         .join(".gossip")
         .join("protocol")
         .join("state.json");
@@ -393,48 +837,30 @@ struct ProtocolState {
     
     /// Last heartbeat timestamp
     last_heartbeat: u64,
+    
+    /// Heartbeat and liveness settings
+    #[serde(default)]
+    liveness: LivenessConfig,
 }
 
 impl ProtocolState {
     /// Create new default protocol state
     fn new() -> Self {
         Self {
-            node_id: generate_node_id(),
+            node_id: String::new(),
             enabled: true,
             capabilities: vec![
                 "sync".to_string(),
                 "discovery".to_string(),
+                crate::matrixbox::sync::CAPABILITY.to_string(),
             ],
             version: env!("CARGO_PKG_VERSION").to_string(),
             last_heartbeat: 0,
+            liveness: LivenessConfig::default(),
         }
     }
 }
 
-/// Generate a unique node ID
-fn generate_node_id() -> String {
-    use rand::{thread_rng, Rng};
-    
-    let timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_else(|_| Duration::from_secs(0))
-        .as_secs();
-    
-    let mut rng = thread_rng();
-    let random_bytes: [u8; 8] = rng.gen();
-    
-    // Hash timestamp and random bytes for uniqueness
-    let mut hasher = blake3::Hasher::new();
-    hasher.update(&timestamp.to_le_bytes());
-    hasher.update(&random_bytes);
-    
-    let hash = hasher.finalize();
-    let node_id = hash.to_hex().to_string();
-    
-    // Use first 16 chars of the hash
-    node_id[..16].to_string()
-}
-
 /// Get trace hash from a peer
 pub fn get_trace_hash(peer_id: &str, peer_endpoint: &str) -> Result<String> {
     debug!("Getting trace hash from peer: {}", peer_id);
@@ -444,22 +870,17 @@ pub fn get_trace_hash(peer_id: &str, peer_endpoint: &str) -> Result<String> {
         request_id: generate_request_id(),
     };
     
-    // Serialize request
+    // Send the request and wait for the peer's answer
     let payload = serde_json::to_vec(&request_msg)?;
+    let response = request(peer_id, peer_endpoint, &request_msg.request_id,
+                           MessageType::TraceHashRequest, MessageType::TraceHashResponse, &payload)?;
+    let response: TraceHashResponseMsg = serde_json::from_slice(&response)
+        .context("Failed to parse trace hash response")?;
     
-    // Send request
-    send_message(peer_endpoint, MessageType::TraceHashRequest, &payload)?;
-    
-    // TODO: In a real implementation, we would wait for the response asynchronously
-    // For now, we'll simulate a response with a dummy hash
-    
-    // Compute a deterministic hash for the simulation
-    let mut hasher = blake3::Hasher::new();
-    hasher.update(peer_id.as_bytes());
-    hasher.update(b"trace-hash-simulation");
-    let hash = hasher.finalize();
-    
-    Ok(hash.to_hex().to_string())
+    if let Some(error) = response.error {
+        anyhow::bail!("Peer {} could not compute its trace hash: {}", peer_id, error);
+    }
+    Ok(response.hash)
 }
 
 /// List trace files from a peer
@@ -471,35 +892,23 @@ pub fn list_trace_files(peer_id: &str, peer_endpoint: &str) -> Result<Vec<super:
         request_id: generate_request_id(),
     };
     
-    // Serialize request
+    // Send the request and wait for the peer's answer
     let payload = serde_json::to_vec(&request_msg)?;
+    let response = request(peer_id, peer_endpoint, &request_msg.request_id,
+                           MessageType::ListTraceFilesRequest, MessageType::ListTraceFilesResponse, &payload)?;
+    let response: ListTraceFilesResponseMsg = serde_json::from_slice(&response)
+        .context("Failed to parse trace file list response")?;
     
-    // Send request
-    send_message(peer_endpoint, MessageType::ListTraceFilesRequest, &payload)?;
-    
-    // TODO: In a real implementation, we would wait for the response asynchronously
-    // For now, we'll simulate a response with dummy files
-    
-    let file_count = 3; // Simulate 3 trace files
-    let mut files = Vec::with_capacity(file_count);
-    
-    for i in 0..file_count {
-        let filename = format!("trace-{}.trace", i);
-        
-        // Create deterministic hash for the file
-        let mut hasher = blake3::Hasher::new();
-        hasher.update(peer_id.as_bytes());
-        hasher.update(filename.as_bytes());
-        let hash = hasher.finalize();
-        
-        files.push(super::verify::TraceFileInfo {
-            name: filename,
-            size: 1024 * (i + 1) as u64, // Simulate different file sizes
-            hash: hash.to_hex().to_string(),
-        });
+    if let Some(error) = response.error {
+        anyhow::bail!("Peer {} could not list its trace files: {}", peer_id, error);
     }
-    
-    Ok(files)
+    // Names become paths under the pull directory
+    if let Some(bad) = response.files.iter().find(|f| f.name.contains(['/', '\\']) || f.name.starts_with('.')) {
+        anyhow::bail!("Peer {} listed an invalid trace file name: {:?}", peer_id, bad.name);
+    }
+    Ok(response.files.into_iter()
+        .map(|f| super::verify::TraceFileInfo { name: f.name, size: f.size, hash: f.hash })
+        .collect())
 }
 
 /// Get a trace file from a peer
@@ -512,26 +921,95 @@ pub fn get_trace_file(peer_id: &str, peer_endpoint: &str, filename: &str) -> Res
         filename: filename.to_string(),
     };
     
-    // Serialize request
+    // Send the request and wait for the peer's answer
     let payload = serde_json::to_vec(&request_msg)?;
+    let response = request(peer_id, peer_endpoint, &request_msg.request_id,
+                           MessageType::GetTraceFileRequest, MessageType::GetTraceFileResponse, &payload)?;
+    let response: GetTraceFileResponseMsg = serde_json::from_slice(&response)
+        .context("Failed to parse trace file response")?;
     
-    // Send request
-    send_message(peer_endpoint, MessageType::GetTraceFileRequest, &payload)?;
+    if let Some(error) = response.error {
+        anyhow::bail!("Peer {} could not send trace file {}: {}", peer_id, filename, error);
+    }
+    if response.filename != filename {
+        anyhow::bail!("Peer {} sent trace file {} instead of {}", peer_id, response.filename, filename);
+    }
+    from_hex(&response.content)
+}
+
+/// Get the archive of a package version from a peer
+///
+/// The caller checks the archive against its own index.
+pub fn get_package_archive(peer_id: &str, peer_endpoint: &str, name: &str, version: &str) -> Result<Vec<u8>> {
+    debug!("Getting archive of {} {} from peer: {}", name, version, peer_id);
+    
+    // Create request message
+    let request_msg = GetPackageArchiveRequestMsg {
+        request_id: generate_request_id(),
+        name: name.to_string(),
+        version: version.to_string(),
+    };
     
-    // TODO: In a real implementation, we would wait for the response asynchronously
-    // For now, we'll simulate a response with dummy file content
+    // Send the request and wait for the peer's answer
+    let payload = serde_json::to_vec(&request_msg)?;
+    let response = request(peer_id, peer_endpoint, &request_msg.request_id,
+                           MessageType::GetPackageArchiveRequest, MessageType::GetPackageArchiveResponse, &payload)?;
+    let response: GetPackageArchiveResponseMsg = serde_json::from_slice(&response)
+        .context("Failed to parse package archive response")?;
     
-    // Create deterministic content for the simulation
-    let mut content = Vec::new();
-    let content_size = 1024; // 1KB simulated content
+    if let Some(error) = response.error {
+        anyhow::bail!("Peer {} could not send the archive of {} {}: {}", peer_id, name, version, error);
+    }
+    if response.name != name || response.version != version {
+        anyhow::bail!("Peer {} sent the archive of {} {} instead of {} {}",
+                      peer_id, response.name, response.version, name, version);
+    }
+    from_hex(&response.content)
+}
+
+/// Send a request and block until the listener routes back its response
+///
+/// Fails if no response with the request's ID arrives from the peer within
+/// the configured timeout.
+fn request(
+    peer_id: &str,
+    peer_endpoint: &str,
+    request_id: &str,
+    request_type: MessageType,
+    response_type: MessageType,
+    payload: &[u8],
+) -> Result<Vec<u8>> {
+    let timeout = Duration::from_secs(load_request_config()?.timeout_secs);
+    let (sender, receiver) = mpsc::channel();
+    PENDING_REQUESTS.lock().unwrap().insert(request_id.to_string(), PendingRequest {
+        peer_id: peer_id.to_string(),
+        response_type,
+        sender,
+    });
     
-    // Fill with deterministic pattern based on filename and peer_id
-    for i in 0..content_size {
-        let byte = (i as u8) ^ (peer_id.as_bytes()[i % peer_id.len()]);
-        content.push(byte);
+    let response = send_message(peer_endpoint, request_type, payload).and_then(|_| {
+        receiver.recv_timeout(timeout).map_err(|_| {
+            crate::logs::metrics::increment("gossip.request_timeouts");
+            anyhow::anyhow!("No {:?} from peer {} within {}s", response_type, peer_id, timeout.as_secs())
+        })
+    });
+    PENDING_REQUESTS.lock().unwrap().remove(request_id);
+    response
+}
+
+/// Load the request/response configuration
+pub fn load_request_config() -> Result<RequestConfig> {
+    let path = constants::root_dir().join(".config").join("system.json");
+    if !path.exists() {
+        return Ok(RequestConfig::default());
     }
     
-    Ok(content)
+    let system_config: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    match system_config.get("gossip_requests") {
+        Some(value) => Ok(serde_json::from_value(value.clone())
+            .context("Invalid gossip_requests configuration in system.json")?),
+        None => Ok(RequestConfig::default()),
+    }
 }
 
 /// Generate a unique request ID
@@ -567,7 +1045,7 @@ struct Message {
     /// Message payload
     payload: Vec<u8>,
     
-    /// Message signature
+    /// Ed25519 signature over the other fields by the sender's gossip key (hex)
     signature: String,
 }
 
@@ -603,6 +1081,51 @@ pub enum MessageType {
     
     /// Get trace file response
     GetTraceFileResponse,
+    
+    /// Batch of shipped log records (JSON lines)
+    LogBatch,
+    
+    /// Signed node identity (display name and key rotations)
+    IdentityAnnouncement,
+    
+    /// Signed security advisory states and installed package versions
+    AdvisoryReport,
+    
+    /// Signed proposal to deploy a contract version
+    ContractProposal,
+    
+    /// Signed approval of a contract proposal
+    ContractApproval,
+    
+    /// Signed outcome of a manually resolved sync conflict
+    ConflictOutcome,
+    
+    /// Attestation challenge carrying a random nonce
+    AttestChallenge,
+    
+    /// Signed attestation of boot chain and trace head for a nonce
+    AttestResponse,
+    
+    /// Signed execution allow-list of WASM module hashes
+    ExecAllowList,
+    
+    /// Request for a fresh self-report
+    ReportRequest,
+    
+    /// Signed self-report sent on request
+    PeerReport,
+    
+    /// Frame of a container data sync message
+    DataSync,
+    
+    /// Acknowledgment of a heartbeat, for round-trip timing
+    HeartbeatAck,
+    
+    /// Get package archive request
+    GetPackageArchiveRequest,
+    
+    /// Get package archive response
+    GetPackageArchiveResponse,
 }
 
 /// Discovery information
@@ -616,6 +1139,9 @@ struct DiscoveryInfo {
     
     /// Software version
     version: String,
+    
+    /// Gossip public key the node signs its messages with (hex)
+    public_key: String,
 }
 
 /// Trace hash request message
@@ -625,6 +1151,13 @@ struct TraceHashRequestMsg {
     request_id: String,
 }
 
+/// Request identifier shared by every response message
+#[derive(Debug, Clone, Deserialize)]
+struct ResponseHeader {
+    /// Request identifier (matches the request)
+    request_id: String,
+}
+
 /// Trace hash response message
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct TraceHashResponseMsg {
@@ -633,6 +1166,10 @@ struct TraceHashResponseMsg {
     
     /// Trace hash
     hash: String,
+    
+    /// Why the request could not be served
+    #[serde(default)]
+    error: Option<String>,
 }
 
 /// List trace files request message
@@ -650,6 +1187,10 @@ struct ListTraceFilesResponseMsg {
     
     /// List of trace files
     files: Vec<TraceFile>,
+    
+    /// Why the request could not be served
+    #[serde(default)]
+    error: Option<String>,
 }
 
 /// Trace file information
@@ -684,6 +1225,243 @@ struct GetTraceFileResponseMsg {
     /// File name
     filename: String,
     
-    /// File content (base64 encoded)
+    /// File content (hex)
     content: String,
+    
+    /// Why the request could not be served
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Get package archive request message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GetPackageArchiveRequestMsg {
+    /// Request identifier
+    request_id: String,
+    
+    /// Package name
+    name: String,
+    
+    /// Package version
+    version: String,
+}
+
+/// Get package archive response message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct GetPackageArchiveResponseMsg {
+    /// Request identifier (matches the request)
+    request_id: String,
+    
+    /// Package name
+    name: String,
+    
+    /// Package version
+    version: String,
+    
+    /// Archive content (hex)
+    content: String,
+    
+    /// Why the request could not be served
+    #[serde(default)]
+    error: Option<String>,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        anyhow::bail!("Invalid hex string length");
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).context("Invalid hex string"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Once;
+    
+    /// This node's ID in tests
+    const NODE: &str = "protocol-test-node";
+    
+    /// Fixed node ID, a short request timeout and a trace file to hash
+    fn setup() {
+        static SETUP: Once = Once::new();
+        SETUP.call_once(|| {
+            PROTOCOL_STATE.lock().unwrap().node_id = NODE.to_string();
+            
+            let config_dir = constants::root_dir().join(".config");
+            fs::create_dir_all(&config_dir).unwrap();
+            let path = config_dir.join("system.json");
+            let mut system_config: serde_json::Value = fs::read_to_string(&path).ok()
+                .and_then(|json| serde_json::from_str(&json).ok())
+                .unwrap_or_else(|| serde_json::json!({}));
+            system_config["gossip_requests"] = serde_json::json!({ "timeout_secs": 1 });
+            fs::write(&path, system_config.to_string()).unwrap();
+            
+            let runtime_dir = constants::root_dir().join(".runtime");
+            fs::create_dir_all(&runtime_dir).unwrap();
+            fs::write(runtime_dir.join("protocol-test.trace"), b"trace line\n").unwrap();
+        });
+    }
+    
+    /// Socket on an ephemeral loopback port, standing in for a node's listener
+    fn listener() -> UdpSocket {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        socket
+    }
+    
+    /// Receive one message on a socket and handle it as the listener loop does
+    fn serve_one(socket: UdpSocket) -> thread::JoinHandle<MessageType> {
+        thread::spawn(move || {
+            let mut buffer = vec![0u8; MAX_MESSAGE_SIZE];
+            let (size, src) = socket.recv_from(&mut buffer).unwrap();
+            let (_, message_type, _) = decode_message(&buffer[..size]).unwrap();
+            handle_message(&buffer[..size], src).unwrap();
+            message_type
+        })
+    }
+    
+    fn pending(request_id: &str, peer_id: &str, response_type: MessageType) -> mpsc::Receiver<Vec<u8>> {
+        let (sender, receiver) = mpsc::channel();
+        PENDING_REQUESTS.lock().unwrap().insert(request_id.to_string(), PendingRequest {
+            peer_id: peer_id.to_string(),
+            response_type,
+            sender,
+        });
+        receiver
+    }
+    
+    fn hash_response(request_id: &str) -> Vec<u8> {
+        serde_json::to_vec(&TraceHashResponseMsg {
+            request_id: request_id.to_string(),
+            hash: "abc".to_string(),
+            error: None,
+        }).unwrap()
+    }
+    
+    #[test]
+    fn trace_hash_round_trips_between_listeners() {
+        setup();
+        
+        // One process has one identity, so both listeners belong to this node:
+        // the request goes out to the responder's port and the answer comes
+        // back to the port the node is registered at
+        let requester = listener();
+        let responder = listener();
+        let responder_endpoint = responder.local_addr().unwrap().to_string();
+        super::super::add_peer(NODE, &requester.local_addr().unwrap().to_string(), Some(&public_key().unwrap())).unwrap();
+        
+        let requester = serve_one(requester);
+        let responder = serve_one(responder);
+        let hash = get_trace_hash(NODE, &responder_endpoint).unwrap();
+        
+        assert_eq!(responder.join().unwrap(), MessageType::TraceHashRequest);
+        assert_eq!(requester.join().unwrap(), MessageType::TraceHashResponse);
+        assert_eq!(hash, super::super::verify::local_trace_hash().unwrap());
+        assert!(!hash.is_empty());
+    }
+    
+    #[test]
+    fn responses_reach_only_the_request_that_awaits_them() {
+        let receiver = pending("routing-1", "peer-a", MessageType::TraceHashResponse);
+        
+        route_response("peer-b", MessageType::TraceHashResponse, hash_response("routing-1")).unwrap();
+        route_response("peer-a", MessageType::ListTraceFilesResponse, hash_response("routing-1")).unwrap();
+        route_response("peer-a", MessageType::TraceHashResponse, hash_response("routing-2")).unwrap();
+        assert!(receiver.try_recv().is_err());
+        assert!(PENDING_REQUESTS.lock().unwrap().contains_key("routing-1"));
+        
+        route_response("peer-a", MessageType::TraceHashResponse, hash_response("routing-1")).unwrap();
+        assert_eq!(receiver.try_recv().unwrap(), hash_response("routing-1"));
+        assert!(!PENDING_REQUESTS.lock().unwrap().contains_key("routing-1"));
+        
+        // A duplicate finds nobody waiting and is dropped
+        route_response("peer-a", MessageType::TraceHashResponse, hash_response("routing-1")).unwrap();
+        assert!(route_response("peer-a", MessageType::TraceHashResponse, b"not json".to_vec()).is_err());
+    }
+    
+    #[test]
+    fn unanswered_requests_time_out_and_late_answers_are_dropped() {
+        setup();
+        let silent = listener();
+        let endpoint = silent.local_addr().unwrap().to_string();
+        super::super::add_peer("protocol-silent-peer", &endpoint, Some(&public_key().unwrap())).unwrap();
+        
+        let error = get_trace_hash("protocol-silent-peer", &endpoint).unwrap_err();
+        assert!(error.to_string().contains("No TraceHashResponse"), "{}", error);
+        
+        let mut buffer = vec![0u8; MAX_MESSAGE_SIZE];
+        let (size, src) = silent.recv_from(&mut buffer).unwrap();
+        let (_, _, payload) = decode_message(&buffer[..size]).unwrap();
+        let request: TraceHashRequestMsg = serde_json::from_slice(&payload).unwrap();
+        assert!(!PENDING_REQUESTS.lock().unwrap().contains_key(&request.request_id));
+        
+        let late = encode_message("protocol-silent-peer", MessageType::TraceHashResponse, &hash_response(&request.request_id)).unwrap();
+        handle_message(&late, src).unwrap();
+    }
+    
+    #[test]
+    fn responses_from_unpinned_senders_are_not_routed() {
+        let receiver = pending("unpinned-1", "protocol-unpinned-peer", MessageType::TraceHashResponse);
+        let forged = encode_message("protocol-unpinned-peer", MessageType::TraceHashResponse, &hash_response("unpinned-1")).unwrap();
+        handle_message(&forged, "127.0.0.1:9".parse().unwrap()).unwrap();
+        
+        assert!(receiver.try_recv().is_err());
+        PENDING_REQUESTS.lock().unwrap().remove("unpinned-1");
+    }
+    
+    #[test]
+    fn messages_verify_only_against_the_pinned_key() {
+        let encoded = encode_message("protocol-signing-peer", MessageType::Heartbeat, b"alive").unwrap();
+        let message: Message = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(message.signature.len(), 128);
+        assert!(authenticate(&message).is_err());
+        
+        super::super::add_peer("protocol-signing-peer", "127.0.0.1:9", Some(&public_key().unwrap())).unwrap();
+        authenticate(&message).unwrap();
+        
+        let tampered = Message { payload: b"dead".to_vec(), ..message.clone() };
+        assert!(authenticate(&tampered).is_err());
+        let replayed = Message { timestamp: message.timestamp + 1, ..message.clone() };
+        assert!(authenticate(&replayed).is_err());
+        let unsigned = Message { signature: String::new(), ..message.clone() };
+        assert!(authenticate(&unsigned).is_err());
+        
+        // Signed by this node, but claiming to come from a peer pinned to another key
+        super::super::add_peer("protocol-other-peer", "127.0.0.1:9", Some(&to_hex(&[1; 32]))).unwrap();
+        let impostor = Message { source_id: "protocol-other-peer".to_string(), ..message };
+        assert!(authenticate(&impostor).is_err());
+        
+        // The key is generated once and kept
+        assert_eq!(public_key().unwrap(), public_key().unwrap());
+    }
+    
+    #[test]
+    fn discovery_without_a_valid_key_adds_no_peer() {
+        for public_key in ["", "not hex", &to_hex(&[2; 16])] {
+            let info = DiscoveryInfo {
+                node_id: "protocol-keyless-peer".to_string(),
+                capabilities: Vec::new(),
+                version: "test".to_string(),
+                public_key: public_key.to_string(),
+            };
+            handle_discovery(&bincode::serialize(&info).unwrap(), "127.0.0.1:9".parse().unwrap()).unwrap();
+        }
+        assert!(!super::super::list_peers().unwrap().iter().any(|p| p.id == "protocol-keyless-peer"));
+        
+        let info = DiscoveryInfo {
+            node_id: "protocol-discovered-peer".to_string(),
+            capabilities: Vec::new(),
+            version: "test".to_string(),
+            public_key: to_hex(&[3; 32]),
+        };
+        handle_discovery(&bincode::serialize(&info).unwrap(), "127.0.0.1:9".parse().unwrap()).unwrap();
+        assert_eq!(super::super::peer_gossip_key("protocol-discovered-peer"), Some(to_hex(&[3; 32])));
+    }
 }
@@ -0,0 +1,467 @@
+// SentientOS Peer Reports
+// Compact signed self-reports carried in heartbeats and cached per peer
+
+use anyhow::{Result, Context};
+use tracing::{debug, warn};
+use std::path::PathBuf;
+use std::fs;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use crate::core::constants;
+use super::protocol::{self, MessageType};
+
+// Constants
+const REPORTS_DIR: &str = "reports";
+
+// Last report built by this node, reused until it is older than the interval
+static OWN_REPORT: Mutex<Option<SelfReport>> = Mutex::new(None);
+
+/// Peer report settings, stored under `fleet_reports` in system.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReportConfig {
+    /// Seconds a built self-report is reused before it is rebuilt
+    pub refresh_secs: u64,
+    
+    /// Age in seconds after which a peer's report is shown as stale
+    pub stale_after_secs: u64,
+    
+    /// Seconds an on-demand report request waits for the answer
+    pub request_timeout_secs: u64,
+}
+
+impl Default for ReportConfig {
+    fn default() -> Self {
+        Self {
+            refresh_secs: 60,
+            stale_after_secs: 300,
+            request_timeout_secs: 10,
+        }
+    }
+}
+
+/// What a node reports about itself
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SelfReport {
+    /// Reporting node
+    pub node_id: String,
+    
+    /// Number of installed packages, across ecosystems and the store
+    pub package_count: usize,
+    
+    /// Hash over the sorted installed package versions
+    pub package_digest: String,
+    
+    /// Number of registered containers
+    pub container_count: usize,
+    
+    /// Health status (healthy, degraded, critical)
+    pub health: String,
+    
+    /// SentientOS version
+    pub version: String,
+    
+    /// Boot chain record hash, as attested
+    pub boot_record_hash: Option<String>,
+    
+    /// Capabilities the node advertises
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    
+    /// When the report was built
+    pub generated_at: u64,
+    
+    /// Signature by the node's identity key
+    pub signature: String,
+}
+
+/// A peer's latest report as stored locally
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerReport {
+    /// The verified report
+    pub report: SelfReport,
+    
+    /// When it was received
+    pub received_at: u64,
+}
+
+impl PeerReport {
+    /// Seconds since the report was received
+    pub fn age_secs(&self) -> u64 {
+        now().saturating_sub(self.received_at)
+    }
+    
+    /// Whether the report is older than the stale threshold
+    pub fn is_stale(&self, config: &ReportConfig) -> bool {
+        self.age_secs() > config.stale_after_secs
+    }
+}
+
+/// One peer in the fleet summary
+#[derive(Debug, Clone)]
+pub struct FleetEntry {
+    /// Peer
+    pub peer: super::PeerInfo,
+    
+    /// Latest report, if any was received
+    pub report: Option<PeerReport>,
+    
+    /// Whether the report is stale
+    pub stale: bool,
+}
+
+/// Overview of what trusted peers report
+#[derive(Debug, Clone)]
+pub struct FleetSummary {
+    /// Trusted peers with their reports
+    pub entries: Vec<FleetEntry>,
+    
+    /// Peers with a fresh report
+    pub fresh: usize,
+    
+    /// Peers whose report is stale
+    pub stale: usize,
+    
+    /// Peers that never reported
+    pub missing: usize,
+    
+    /// Peers with a fresh report that is not healthy
+    pub unhealthy: usize,
+}
+
+/// Latest stored report of a peer
+pub fn peer_report(peer_id: &str) -> Result<Option<PeerReport>> {
+    let path = report_path(peer_id)?;
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&fs::read_to_string(&path)?)
+        .with_context(|| format!("Invalid stored report of {}", peer_id))?))
+}
+
+/// Reports of every trusted peer, with staleness
+pub fn fleet_summary() -> Result<FleetSummary> {
+    let config = load_config()?;
+    let mut summary = FleetSummary { entries: Vec::new(), fresh: 0, stale: 0, missing: 0, unhealthy: 0 };
+    
+    for peer in super::list_peers()? {
+        if !super::is_trusted_peer(&peer.id) {
+            continue;
+        }
+        
+        let report = peer_report(&peer.id)?;
+        let stale = report.as_ref().map_or(false, |r| r.is_stale(&config));
+        match &report {
+            None => summary.missing += 1,
+            Some(_) if stale => summary.stale += 1,
+            Some(r) => {
+                summary.fresh += 1;
+                if r.report.health != "healthy" {
+                    summary.unhealthy += 1;
+                }
+            }
+        }
+        summary.entries.push(FleetEntry { peer, report, stale });
+    }
+    Ok(summary)
+}
+
+/// Ask a peer for a fresh report and wait for it
+pub fn request_report(peer_id: &str) -> Result<PeerReport> {
+    let config = load_config()?;
+    let peer = super::list_peers()?
+        .into_iter()
+        .find(|p| p.id == peer_id)
+        .ok_or_else(|| anyhow::anyhow!("Unknown peer: {}", peer_id))?;
+    if !super::is_trusted_peer(peer_id) {
+        anyhow::bail!("Peer {} is not trusted", peer_id);
+    }
+    
+    let requested_at = now();
+    protocol::send_message(&peer.endpoint, MessageType::ReportRequest, &[])?;
+    
+    let deadline = SystemTime::now() + Duration::from_secs(config.request_timeout_secs);
+    while SystemTime::now() < deadline {
+        if let Some(report) = peer_report(peer_id)?.filter(|r| r.received_at >= requested_at) {
+            return Ok(report);
+        }
+        std::thread::sleep(Duration::from_millis(200));
+    }
+    anyhow::bail!("Peer {} did not report within {}s", peer_id, config.request_timeout_secs)
+}
+
+/// Heartbeat payload carrying this node's report; empty if it can't be built
+pub fn heartbeat_payload() -> Vec<u8> {
+    match own_report(false).and_then(|r| Ok(serde_json::to_vec(&r)?)) {
+        Ok(payload) => payload,
+        Err(e) => {
+            warn!("Failed to build self-report for heartbeats: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Verify and store a report received from a trusted peer
+pub fn handle_report(source_id: &str, payload: &[u8]) -> Result<()> {
+    if !super::is_trusted_peer(source_id) {
+        debug!("Ignoring report from untrusted peer: {}", source_id);
+        return Ok(());
+    }
+    
+    let report: SelfReport = serde_json::from_slice(payload).context("Invalid peer report")?;
+    if report.node_id != source_id {
+        warn!("Report for {} sent by {}, ignoring", report.node_id, source_id);
+        return Ok(());
+    }
+    
+    let key = super::peer_public_key(source_id)
+        .ok_or_else(|| anyhow::anyhow!("No verified public key for {}", source_id))?;
+    crate::core::identity::verify(&key, signing_payload(&report)?.as_bytes(), &report.signature)
+        .with_context(|| format!("Invalid report signature from {}", source_id))?;
+    
+    // Heartbeats can arrive out of order; keep the newest report
+    if let Some(existing) = peer_report(source_id)? {
+        if existing.report.generated_at > report.generated_at {
+            return Ok(());
+        }
+    }
+    
+    let path = report_path(source_id)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(&PeerReport { report, received_at: now() })?)?;
+    debug!("Stored report from {}", source_id);
+    Ok(())
+}
+
+/// Answer a peer's request for a fresh report
+pub fn handle_request(source_id: &str) -> Result<()> {
+    if !super::is_trusted_peer(source_id) {
+        debug!("Ignoring report request from untrusted peer: {}", source_id);
+        return Ok(());
+    }
+    
+    let peer = match super::list_peers()?.into_iter().find(|p| p.id == source_id) {
+        Some(peer) => peer,
+        None => return Ok(()),
+    };
+    let report = own_report(true)?;
+    protocol::send_message(&peer.endpoint, MessageType::PeerReport, &serde_json::to_vec(&report)?)?;
+    debug!("Sent report to {}", source_id);
+    Ok(())
+}
+
+/// This node's signed report, rebuilt when forced or older than the interval
+fn own_report(force: bool) -> Result<SelfReport> {
+    let config = load_config()?;
+    if !force {
+        if let Some(report) = OWN_REPORT.lock().unwrap().as_ref() {
+            if now().saturating_sub(report.generated_at) < config.refresh_secs {
+                return Ok(report.clone());
+            }
+        }
+    }
+    
+    let report = build_report()?;
+    *OWN_REPORT.lock().unwrap() = Some(report.clone());
+    Ok(report)
+}
+
+/// Build and sign this node's report
+fn build_report() -> Result<SelfReport> {
+    let mut packages: Vec<String> = crate::package::list_packages(None)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|p| format!("{:?}:{}@{}", p.ecosystem, p.name, p.version).to_lowercase())
+        .collect();
+    packages.extend(crate::store::list_installed_packages()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|name| format!("store:{}", name)));
+    packages.sort();
+    packages.dedup();
+    
+    let health = match crate::heal::check_health() {
+        Ok(status) => format!("{:?}", status).to_lowercase(),
+        Err(e) => {
+            warn!("Health check for self-report failed: {}", e);
+            "unknown".to_string()
+        }
+    };
+    
+    let mut report = SelfReport {
+        node_id: crate::core::identity::node_id()?,
+        package_count: packages.len(),
+        package_digest: blake3::hash(packages.join("\n").as_bytes()).to_hex().to_string(),
+        container_count: crate::matrixbox::registry::list_containers().map(|c| c.len()).unwrap_or(0),
+        health,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        boot_record_hash: crate::boot::boot_record_hash().ok(),
+        capabilities: protocol::capabilities(),
+        generated_at: now(),
+        signature: String::new(),
+    };
+    report.signature = crate::core::identity::sign(signing_payload(&report)?.as_bytes())?;
+    Ok(report)
+}
+
+/// Canonical bytes covered by a report signature
+fn signing_payload(report: &SelfReport) -> Result<String> {
+    let mut payload = serde_json::json!({
+        "node_id": report.node_id,
+        "package_count": report.package_count,
+        "package_digest": report.package_digest,
+        "container_count": report.container_count,
+        "health": report.health,
+        "version": report.version,
+        "boot_record_hash": report.boot_record_hash,
+        "generated_at": report.generated_at,
+    });
+    // Only covered when present, so reports of older nodes keep verifying
+    if !report.capabilities.is_empty() {
+        payload["capabilities"] = serde_json::json!(report.capabilities);
+    }
+    Ok(serde_json::to_string(&payload)?)
+}
+
+/// Path of a peer's stored report
+fn report_path(peer_id: &str) -> Result<PathBuf> {
+    if peer_id.is_empty() || !peer_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        anyhow::bail!("Invalid peer ID: {}", peer_id);
+    }
+    Ok(constants::root_dir().join(constants::GOSSIP_DIR).join(REPORTS_DIR)
+        .join(format!("{}.json", peer_id)))
+}
+
+/// Load the report configuration
+pub fn load_config() -> Result<ReportConfig> {
+    let path = constants::root_dir().join(".config").join("system.json");
+    if !path.exists() {
+        return Ok(ReportConfig::default());
+    }
+    
+    let system_config: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    match system_config.get("fleet_reports") {
+        Some(value) => Ok(serde_json::from_value(value.clone())
+            .context("Invalid fleet_reports configuration in system.json")?),
+        None => Ok(ReportConfig::default()),
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+    
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+    
+    /// Register a trusted peer whose identity key is derived from `seed`
+    fn trusted_peer(peer_id: &str, seed: u8) -> SigningKey {
+        let key = SigningKey::from_bytes(&[seed; 32]);
+        super::super::add_peer(peer_id, "127.0.0.1:9", None).unwrap();
+        super::super::update_peer_identity(peer_id, peer_id, &hex(key.verifying_key().as_bytes())).unwrap();
+        key
+    }
+    
+    fn report(node_id: &str, key: &SigningKey, generated_at: u64, health: &str) -> SelfReport {
+        let mut report = SelfReport {
+            node_id: node_id.to_string(),
+            package_count: 2,
+            package_digest: "digest".to_string(),
+            container_count: 1,
+            health: health.to_string(),
+            version: "1.0.0".to_string(),
+            boot_record_hash: None,
+            capabilities: vec!["reports".to_string()],
+            generated_at,
+            signature: String::new(),
+        };
+        report.signature = hex(&key.sign(signing_payload(&report).unwrap().as_bytes()).to_bytes());
+        report
+    }
+    
+    fn payload(report: &SelfReport) -> Vec<u8> {
+        serde_json::to_vec(report).unwrap()
+    }
+    
+    #[test]
+    fn signed_reports_from_trusted_peers_are_stored() {
+        let key = trusted_peer("report-trusted", 11);
+        assert!(peer_report("report-trusted").unwrap().is_none());
+        
+        handle_report("report-trusted", &payload(&report("report-trusted", &key, 100, "healthy"))).unwrap();
+        let stored = peer_report("report-trusted").unwrap().unwrap();
+        assert_eq!(stored.report.generated_at, 100);
+        assert_eq!(stored.report.capabilities, ["reports"]);
+        assert!(!stored.is_stale(&ReportConfig::default()));
+        
+        let entry = fleet_summary().unwrap().entries.into_iter()
+            .find(|e| e.peer.id == "report-trusted").unwrap();
+        assert_eq!(entry.report.unwrap().report.health, "healthy");
+    }
+    
+    #[test]
+    fn older_reports_do_not_replace_newer_ones() {
+        let key = trusted_peer("report-order", 12);
+        handle_report("report-order", &payload(&report("report-order", &key, 200, "healthy"))).unwrap();
+        handle_report("report-order", &payload(&report("report-order", &key, 150, "critical"))).unwrap();
+        
+        let stored = peer_report("report-order").unwrap().unwrap();
+        assert_eq!(stored.report.generated_at, 200);
+        assert_eq!(stored.report.health, "healthy");
+    }
+    
+    #[test]
+    fn forged_misdirected_and_untrusted_reports_are_not_stored() {
+        trusted_peer("report-forged", 13);
+        let forger = SigningKey::from_bytes(&[14; 32]);
+        assert!(handle_report("report-forged", &payload(&report("report-forged", &forger, 100, "healthy"))).is_err());
+        assert!(peer_report("report-forged").unwrap().is_none());
+        
+        let key = trusted_peer("report-relay", 15);
+        handle_report("report-relay", &payload(&report("report-other", &key, 100, "healthy"))).unwrap();
+        assert!(peer_report("report-relay").unwrap().is_none());
+        assert!(peer_report("report-other").unwrap().is_none());
+        
+        let stranger = SigningKey::from_bytes(&[16; 32]);
+        handle_report("report-stranger", &payload(&report("report-stranger", &stranger, 100, "healthy"))).unwrap();
+        assert!(peer_report("report-stranger").unwrap().is_none());
+    }
+    
+    #[test]
+    fn capabilities_are_only_signed_when_present() {
+        let key = SigningKey::from_bytes(&[17; 32]);
+        let mut legacy = report("report-legacy", &key, 100, "healthy");
+        legacy.capabilities.clear();
+        assert!(!signing_payload(&legacy).unwrap().contains("capabilities"));
+        
+        let current = report("report-legacy", &key, 100, "healthy");
+        assert!(signing_payload(&current).unwrap().contains("\"capabilities\":[\"reports\"]"));
+    }
+    
+    #[test]
+    fn peer_ids_cannot_escape_the_reports_directory() {
+        assert!(report_path("peer-1_a").is_ok());
+        assert!(report_path("").is_err());
+        assert!(report_path("../identity").is_err());
+        assert!(peer_report("a/b").is_err());
+    }
+    
+    #[test]
+    fn reports_go_stale_after_the_threshold() {
+        let key = SigningKey::from_bytes(&[18; 32]);
+        let old = PeerReport { report: report("report-age", &key, 1, "healthy"), received_at: now() - 301 };
+        assert!(old.age_secs() >= 301);
+        assert!(old.is_stale(&ReportConfig::default()));
+        assert!(!old.is_stale(&ReportConfig { stale_after_secs: 600, ..ReportConfig::default() }));
+    }
+}
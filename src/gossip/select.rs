@@ -0,0 +1,367 @@
+// SentientOS Gossip Peer Selection
+// Picks which peer serves a transfer, from trust and observed latency
+
+use anyhow::Result;
+use tracing::{info, debug, warn};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use super::{PeerInfo, PeerStatus};
+
+// Constants
+const EWMA_WEIGHT: f64 = 0.3;
+const UNMEASURED_RTT_MS: f64 = 1000.0;
+const FAILURE_PENALTY: f64 = 4.0;
+const FAILURE_MEMORY_SECS: u64 = 600;
+
+/// Latency, throughput and outcome observations of a peer
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PeerStats {
+    /// Smoothed heartbeat round-trip time, in milliseconds
+    pub rtt_ms: Option<f64>,
+    
+    /// Smoothed throughput of past transfers, in bytes per second
+    pub throughput_bps: Option<f64>,
+    
+    /// Transfers the peer served
+    pub successes: u64,
+    
+    /// Transfers that failed with the peer
+    pub failures: u64,
+    
+    /// Failures since the last success
+    pub consecutive_failures: u32,
+    
+    /// When the last transfer failed
+    pub last_failure: Option<u64>,
+}
+
+impl PeerStats {
+    /// Record a heartbeat round trip
+    pub fn observe_rtt(&mut self, rtt: Duration) {
+        self.rtt_ms = Some(smooth(self.rtt_ms, rtt.as_secs_f64() * 1000.0));
+    }
+    
+    /// Record a transfer the peer served
+    pub fn observe_success(&mut self, bytes: u64, elapsed: Duration) {
+        self.successes += 1;
+        self.consecutive_failures = 0;
+        if bytes > 0 && !elapsed.is_zero() {
+            self.throughput_bps = Some(smooth(self.throughput_bps, bytes as f64 / elapsed.as_secs_f64()));
+        }
+    }
+    
+    /// Record a transfer that failed
+    pub fn observe_failure(&mut self) {
+        self.failures += 1;
+        self.consecutive_failures += 1;
+        self.last_failure = Some(now());
+    }
+    
+    /// Latency used for ranking, inflated by recent failures
+    pub fn score(&self, now: u64) -> f64 {
+        let latency = self.rtt_ms.unwrap_or(UNMEASURED_RTT_MS);
+        let recently_failed = self.consecutive_failures > 0
+            && self.last_failure.map_or(false, |t| now.saturating_sub(t) < FAILURE_MEMORY_SECS);
+        if recently_failed {
+            latency * FAILURE_PENALTY * self.consecutive_failures as f64
+        } else {
+            latency
+        }
+    }
+}
+
+/// A peer that could serve a transfer
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    /// Peer ID
+    pub id: String,
+    
+    /// Network endpoint
+    pub endpoint: String,
+    
+    /// Whether the peer is trusted
+    pub trusted: bool,
+    
+    /// Observations of the peer
+    pub stats: PeerStats,
+}
+
+impl Candidate {
+    fn from_peer(peer: PeerInfo) -> Self {
+        Self {
+            trusted: super::is_trusted_peer(&peer.id),
+            id: peer.id,
+            endpoint: peer.endpoint,
+            stats: peer.stats,
+        }
+    }
+}
+
+/// Order candidates best first
+///
+/// Trusted peers come before others; within each group the lowest recent
+/// latency wins, then the higher throughput, then the peer ID.
+pub fn rank(candidates: &mut [Candidate], now: u64) {
+    candidates.sort_by(|a, b| {
+        b.trusted.cmp(&a.trusted)
+            .then_with(|| a.stats.score(now).total_cmp(&b.stats.score(now)))
+            .then_with(|| b.stats.throughput_bps.unwrap_or(0.0).total_cmp(&a.stats.throughput_bps.unwrap_or(0.0)))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+}
+
+/// Reachable peers, optionally restricted to the given IDs
+pub fn candidates(only: Option<&[String]>) -> Result<Vec<Candidate>> {
+    Ok(super::list_peers()?
+        .into_iter()
+        .filter(|p| !matches!(p.status, PeerStatus::Offline | PeerStatus::Error))
+        .filter(|p| only.map_or(true, |ids| ids.contains(&p.id)))
+        .map(Candidate::from_peer)
+        .collect())
+}
+
+/// Run a transfer against the best candidate, failing over down the ranking
+///
+/// `attempt` returns its result and the bytes it moved. Every outcome is
+/// recorded in the peer's stats. A `forced` peer is used alone, whatever
+/// its rank or status.
+pub fn with_failover<T>(
+    what: &str,
+    mut candidates: Vec<Candidate>,
+    forced: Option<&str>,
+    mut attempt: impl FnMut(&Candidate) -> Result<(T, u64)>,
+) -> Result<T> {
+    if let Some(peer_id) = forced {
+        let peer = super::list_peers()?
+            .into_iter()
+            .find(|p| p.id == peer_id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown peer: {}", peer_id))?;
+        candidates = vec![Candidate::from_peer(peer)];
+    } else {
+        rank(&mut candidates, now());
+    }
+    if candidates.is_empty() {
+        anyhow::bail!("No peer available for {}", what);
+    }
+    
+    let mut errors = Vec::new();
+    for candidate in &candidates {
+        debug!("Trying {} with peer {}", what, candidate.id);
+        let started = Instant::now();
+        match attempt(candidate) {
+            Ok((value, bytes)) => {
+                let elapsed = started.elapsed();
+                record(&candidate.id, |stats| stats.observe_success(bytes, elapsed));
+                info!("{} served by peer {}", what, candidate.id);
+                return Ok(value);
+            }
+            Err(e) => {
+                record(&candidate.id, PeerStats::observe_failure);
+                warn!("{} failed with peer {}: {:#}", what, candidate.id, e);
+                errors.push(format!("{}: {:#}", candidate.id, e));
+            }
+        }
+    }
+    anyhow::bail!("{} failed with every peer ({})", what, errors.join("; "))
+}
+
+/// Record an observation, logging rather than failing the transfer
+fn record(peer_id: &str, observe: impl FnOnce(&mut PeerStats)) {
+    if let Err(e) = super::update_peer_stats(peer_id, observe) {
+        debug!("Failed to record stats of peer {}: {}", peer_id, e);
+    }
+}
+
+fn smooth(previous: Option<f64>, sample: f64) -> f64 {
+    match previous {
+        Some(previous) => previous + EWMA_WEIGHT * (sample - previous),
+        None => sample,
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use std::thread;
+    
+    const NOW: u64 = 1_000_000;
+    
+    fn candidate(id: &str, trusted: bool, rtt_ms: Option<f64>) -> Candidate {
+        Candidate {
+            id: id.to_string(),
+            endpoint: format!("{}:7000", id),
+            trusted,
+            stats: PeerStats { rtt_ms, ..PeerStats::default() },
+        }
+    }
+    
+    fn ranked(mut candidates: Vec<Candidate>) -> Vec<String> {
+        rank(&mut candidates, NOW);
+        candidates.into_iter().map(|c| c.id).collect()
+    }
+    
+    #[test]
+    fn trusted_peers_rank_before_faster_untrusted_ones() {
+        let order = ranked(vec![
+            candidate("fast-normal", false, Some(5.0)),
+            candidate("slow-trusted", true, Some(400.0)),
+            candidate("fast-trusted", true, Some(20.0)),
+        ]);
+        assert_eq!(order, ["fast-trusted", "slow-trusted", "fast-normal"]);
+    }
+    
+    #[test]
+    fn unmeasured_peers_rank_after_measured_ones() {
+        let order = ranked(vec![
+            candidate("unmeasured", false, None),
+            candidate("measured", false, Some(UNMEASURED_RTT_MS - 1.0)),
+        ]);
+        assert_eq!(order, ["measured", "unmeasured"]);
+    }
+    
+    #[test]
+    fn recent_failures_push_a_peer_down_until_forgotten() {
+        let mut failing = candidate("failing", false, Some(10.0));
+        failing.stats.consecutive_failures = 3;
+        failing.stats.last_failure = Some(NOW - 1);
+        let steady = candidate("steady", false, Some(100.0));
+        assert_eq!(ranked(vec![failing.clone(), steady.clone()]), ["steady", "failing"]);
+        
+        failing.stats.last_failure = Some(NOW - FAILURE_MEMORY_SECS);
+        assert_eq!(ranked(vec![steady, failing]), ["failing", "steady"]);
+    }
+    
+    #[test]
+    fn ties_are_broken_by_throughput_then_id() {
+        let mut quick = candidate("b-quick", false, Some(50.0));
+        quick.stats.throughput_bps = Some(1e6);
+        let order = ranked(vec![
+            candidate("c-plain", false, Some(50.0)),
+            candidate("a-plain", false, Some(50.0)),
+            quick,
+        ]);
+        assert_eq!(order, ["b-quick", "a-plain", "c-plain"]);
+    }
+    
+    #[test]
+    fn observations_are_smoothed_and_successes_reset_failures() {
+        let mut stats = PeerStats::default();
+        stats.observe_rtt(Duration::from_millis(100));
+        assert_eq!(stats.rtt_ms, Some(100.0));
+        stats.observe_rtt(Duration::from_millis(200));
+        assert!((stats.rtt_ms.unwrap() - 130.0).abs() < 1e-9);
+        
+        stats.observe_failure();
+        stats.observe_failure();
+        assert_eq!((stats.failures, stats.consecutive_failures), (2, 2));
+        stats.observe_success(1000, Duration::from_millis(500));
+        assert_eq!((stats.successes, stats.consecutive_failures), (1, 0));
+        assert_eq!(stats.throughput_bps, Some(2000.0));
+        
+        // Nothing moved, so throughput has no sample
+        stats.observe_success(0, Duration::from_millis(500));
+        assert_eq!(stats.throughput_bps, Some(2000.0));
+    }
+    
+    /// A peer served by a thread of the test process
+    struct TestPeer {
+        id: String,
+        connections: Arc<AtomicUsize>,
+        failing: Arc<AtomicBool>,
+    }
+    
+    impl TestPeer {
+        /// Serve `<id>` to every connection after `delay`, or hang up at once while failing
+        fn spawn(id: &str, delay: Duration, rtt: Duration) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let endpoint = listener.local_addr().unwrap().to_string();
+            let connections = Arc::new(AtomicUsize::new(0));
+            let failing = Arc::new(AtomicBool::new(false));
+            
+            let (served, fail, payload) = (connections.clone(), failing.clone(), id.to_string());
+            thread::spawn(move || {
+                for mut stream in listener.incoming().flatten() {
+                    served.fetch_add(1, Ordering::SeqCst);
+                    if fail.load(Ordering::SeqCst) {
+                        continue;
+                    }
+                    thread::sleep(delay);
+                    let _ = stream.write_all(payload.as_bytes());
+                }
+            });
+            
+            super::super::add_peer(id, &endpoint, None).unwrap();
+            super::super::update_peer_stats(id, |stats| stats.observe_rtt(rtt)).unwrap();
+            Self { id: id.to_string(), connections, failing }
+        }
+        
+        fn stats(&self) -> PeerStats {
+            super::super::list_peers().unwrap().into_iter().find(|p| p.id == self.id).unwrap().stats
+        }
+    }
+    
+    /// Fetch a peer's payload, failing when it hangs up without one
+    fn fetch(peer: &Candidate) -> Result<(String, u64)> {
+        let mut content = String::new();
+        TcpStream::connect(&peer.endpoint)?.read_to_string(&mut content)?;
+        if content.is_empty() {
+            anyhow::bail!("peer closed the connection");
+        }
+        let bytes = content.len() as u64;
+        Ok((content, bytes))
+    }
+    
+    #[test]
+    fn slower_peer_is_only_used_after_the_faster_one_fails() {
+        let fast = TestPeer::spawn("select-test-fast", Duration::ZERO, Duration::from_millis(5));
+        let slow = TestPeer::spawn("select-test-slow", Duration::from_millis(50), Duration::from_millis(200));
+        let ids = vec![slow.id.clone(), fast.id.clone()];
+        
+        // Both healthy: the faster peer serves and the slower one is never contacted
+        let served = with_failover("test pull", candidates(Some(&ids)).unwrap(), None, fetch).unwrap();
+        assert_eq!(served, fast.id);
+        assert_eq!(slow.connections.load(Ordering::SeqCst), 0);
+        
+        // The faster peer fails: it is tried first, then the slower one serves
+        fast.failing.store(true, Ordering::SeqCst);
+        let tried = Mutex::new(Vec::new());
+        let served = with_failover("test pull", candidates(Some(&ids)).unwrap(), None, |peer| {
+            tried.lock().unwrap().push(peer.id.clone());
+            fetch(peer)
+        }).unwrap();
+        assert_eq!(served, slow.id);
+        assert_eq!(*tried.lock().unwrap(), [fast.id.clone(), slow.id.clone()]);
+        
+        // Both outcomes were recorded
+        let fast_stats = fast.stats();
+        assert_eq!((fast_stats.successes, fast_stats.failures, fast_stats.consecutive_failures), (1, 1, 1));
+        let slow_stats = slow.stats();
+        assert_eq!((slow_stats.successes, slow_stats.failures), (1, 0));
+        assert!(slow_stats.throughput_bps.is_some());
+        
+        // Every peer failing is an error naming each of them
+        slow.failing.store(true, Ordering::SeqCst);
+        let err = with_failover("test pull", candidates(Some(&ids)).unwrap(), None, fetch).unwrap_err().to_string();
+        assert!(err.contains(&fast.id) && err.contains(&slow.id), "{}", err);
+        
+        // A forced peer is used alone, whatever its rank
+        slow.failing.store(false, Ordering::SeqCst);
+        let connections = fast.connections.load(Ordering::SeqCst);
+        let served = with_failover("test pull", candidates(Some(&ids)).unwrap(), Some(&slow.id), fetch).unwrap();
+        assert_eq!(served, slow.id);
+        assert_eq!(fast.connections.load(Ordering::SeqCst), connections);
+        
+        assert!(with_failover("test pull", Vec::new(), Some("select-test-unknown"), fetch).is_err());
+    }
+}
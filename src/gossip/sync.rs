@@ -12,7 +12,7 @@ pub fn init() -> Result<()> {
     info!("Initializing gossip sync subsystem");
     
     // Create sync directories
-    let sync_dir = PathBuf::from(constants::ROOT_DIR)
+    let sync_dir = constants::root_dir()
         .join(".gossip")
         .join("sync");
     
@@ -99,10 +99,22 @@ pub fn handle_sync_response(peer_id: &str, payload: &[u8]) -> Result<()> {
 }
 
 /// Handle a state update from a peer
+///
+/// Divergent state is passed to the component's conflict resolver.
 pub fn handle_state_update(peer_id: &str, payload: &[u8]) -> Result<()> {
     debug!("Received state update from peer {}", peer_id);
     
-    // Will implement the actual state update handling later
+    if !super::is_trusted_peer(peer_id) {
+        debug!("Ignoring state update from untrusted peer: {}", peer_id);
+        return Ok(());
+    }
+    
+    let update: StateUpdate = serde_json::from_slice(payload)
+        .context("Failed to deserialize state update")?;
+    
+    if let Some(conflict_id) = super::conflict::reconcile(&update.component, &update.key, peer_id, &update.content)? {
+        debug!("State update from peer {} left conflict {}", peer_id, conflict_id);
+    }
     
     Ok(())
 }
@@ -116,3 +128,16 @@ struct SyncRequest {
     /// Request timestamp
     timestamp: u64,
 }
+
+/// State update structure
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StateUpdate {
+    /// Component the state belongs to
+    component: String,
+    
+    /// Item within the component
+    key: String,
+    
+    /// Full state content
+    content: Vec<u8>,
+}
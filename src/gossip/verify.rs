@@ -18,7 +18,7 @@ use super::peers;
 pub fn init() -> Result<()> {
     info!("Initializing trace verification system");
     
-    let verify_dir = PathBuf::from(constants::ROOT_DIR)
+    let verify_dir = constants::root_dir()
         .join(".gossip")
         .join("verify");
     fs::create_dir_all(&verify_dir)?;
@@ -32,7 +32,7 @@ pub fn shutdown() -> Result<()> {
     info!("Shutting down trace verification system");
     
     // Update cached hashes before shutdown
-    let cache_dir = PathBuf::from(constants::ROOT_DIR)
+    let cache_dir = constants::root_dir()
         .join(".gossip")
         .join("hash_cache");
     
@@ -53,7 +53,7 @@ pub fn verify_trace() -> Result<VerificationResult> {
     info!("Verifying trace integrity with peers");
     
     // Get local trace hash
-    let local_hash = compute_local_trace_hash()?;
+    let local_hash = local_trace_hash()?;
     
     // Collect trace hashes from peers
     let peer_hashes = collect_peer_trace_hashes()?;
@@ -111,12 +111,12 @@ pub fn verify_trace() -> Result<VerificationResult> {
     Ok(result)
 }
 
-/// Compute hash of local trace
-fn compute_local_trace_hash() -> Result<String> {
+/// Compute hash of local trace, the head of its trace chain
+pub fn local_trace_hash() -> Result<String> {
     debug!("Computing local trace hash");
     
     // Get the runtime trace directory
-    let runtime_dir = PathBuf::from(constants::ROOT_DIR).join(".runtime");
+    let runtime_dir = constants::root_dir().join(".runtime");
     
     // Use blake3 to hash directory contents
     let mut hasher = blake3::Hasher::new();
@@ -152,6 +152,31 @@ fn compute_local_trace_hash() -> Result<String> {
     Ok(hash_hex)
 }
 
+/// Trace files of the local runtime in hashing order, as served to peers
+pub fn local_trace_files() -> Result<Vec<TraceFileInfo>> {
+    let runtime_dir = constants::root_dir().join(".runtime");
+    let mut files = Vec::new();
+    for entry in fs::read_dir(&runtime_dir)
+        .with_context(|| format!("Failed to read runtime directory: {:?}", runtime_dir))?
+    {
+        let path = entry?.path();
+        if !path.is_file() || path.extension().and_then(|ext| ext.to_str()) != Some("trace") {
+            continue;
+        }
+        
+        let content = fs::read(&path)
+            .with_context(|| format!("Failed to read trace file: {:?}", path))?;
+        files.push(TraceFileInfo {
+            name: path.file_name().unwrap().to_string_lossy().to_string(),
+            size: content.len() as u64,
+            hash: blake3::hash(&content).to_hex().to_string(),
+        });
+    }
+    
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(files)
+}
+
 /// Collect trace hashes from peers
 fn collect_peer_trace_hashes() -> Result<HashMap<String, String>> {
     debug!("Collecting trace hashes from peers");
@@ -160,7 +185,7 @@ fn collect_peer_trace_hashes() -> Result<HashMap<String, String>> {
     let mut peer_hashes = HashMap::new();
     
     // Load cached hashes for backup if no peers are available
-    let cache_dir = PathBuf::from(constants::ROOT_DIR)
+    let cache_dir = constants::root_dir()
         .join(".gossip")
         .join("hash_cache");
     let cached_hashes = load_cached_peer_hashes(&cache_dir)?;
@@ -214,7 +239,7 @@ fn record_verification_result(
     peer_hashes: &HashMap<String, String>,
     status: &VerificationStatus,
 ) -> Result<()> {
-    let verify_dir = PathBuf::from(constants::ROOT_DIR)
+    let verify_dir = constants::root_dir()
         .join(".gossip")
         .join("verify");
     
@@ -234,28 +259,30 @@ fn record_verification_result(
     Ok(())
 }
 
-/// Pull runtime trace from a peer
-pub fn pull_from_peer(peer_id: &str) -> Result<()> {
+/// Pull runtime trace from the best reachable peer, or from `peer_id` if given
+///
+/// Peers are tried in selection order until one serves a verified trace.
+/// Returns the ID of the peer that served it.
+pub fn pull_from_peer(peer_id: Option<&str>) -> Result<String> {
+    let candidates = super::select::candidates(None)?;
+    super::select::with_failover("trace pull", candidates, peer_id, |peer| {
+        let bytes = pull_trace(&peer.id, &peer.endpoint)?;
+        Ok((peer.id.clone(), bytes))
+    })
+}
+
+/// Pull and verify a peer's runtime trace, returning the bytes transferred
+fn pull_trace(peer_id: &str, endpoint: &str) -> Result<u64> {
     info!("Pulling runtime trace from peer: {}", peer_id);
     
-    // Get peer info
-    let peers = super::list_peers()?;
-    let peer = peers.iter()
-        .find(|p| p.id == peer_id)
-        .ok_or_else(|| anyhow::anyhow!("Unknown peer: {}", peer_id))?;
-    
-    if peer.status != super::PeerStatus::Online {
-        return Err(anyhow::anyhow!("Peer is not online: {}", peer_id));
-    }
-    
     // Get peer's trace hash
-    let peer_hash = protocol::get_trace_hash(peer_id, &peer.endpoint)?;
+    let peer_hash = protocol::get_trace_hash(peer_id, endpoint)?;
     
     // Get list of trace files from peer
-    let trace_files = protocol::list_trace_files(peer_id, &peer.endpoint)?;
+    let trace_files = protocol::list_trace_files(peer_id, endpoint)?;
     
     // Create directory for pulled trace
-    let pull_dir = PathBuf::from(constants::ROOT_DIR)
+    let pull_dir = constants::root_dir()
         .join(".gossip")
         .join("pull")
         .join(peer_id)
@@ -264,25 +291,23 @@ pub fn pull_from_peer(peer_id: &str) -> Result<()> {
     fs::create_dir_all(&pull_dir)?;
     
     // Pull each trace file
-    for file_info in trace_files {
+    let mut bytes = 0;
+    for file_info in &trace_files {
         info!("Pulling trace file: {}", file_info.name);
         
-        let content = protocol::get_trace_file(peer_id, &peer.endpoint, &file_info.name)?;
+        let content = protocol::get_trace_file(peer_id, endpoint, &file_info.name)?;
+        bytes += content.len() as u64;
         
         let file_path = pull_dir.join(&file_info.name);
         fs::write(&file_path, content)?;
     }
     
-    // Verify the pulled trace
+    // Verify the pulled trace, hashing the files in the order the peer does
+    let mut names: Vec<&str> = trace_files.iter().map(|f| f.name.as_str()).collect();
+    names.sort();
     let mut hasher = blake3::Hasher::new();
-    for entry in fs::read_dir(&pull_dir)? {
-        let entry = entry?;
-        let path = entry.path();
-        
-        if path.is_file() {
-            let content = fs::read(&path)?;
-            hasher.update(&content);
-        }
+    for name in names {
+        hasher.update(&fs::read(pull_dir.join(name))?);
     }
     
     let hash = hasher.finalize();
@@ -305,7 +330,7 @@ pub fn pull_from_peer(peer_id: &str) -> Result<()> {
     fs::write(&record_path, serde_json::to_string_pretty(&record)?)?;
     
     info!("Successfully pulled trace from peer: {}", peer_id);
-    Ok(())
+    Ok(bytes)
 }
 
 /// Enable trace sync with peers
@@ -313,13 +338,13 @@ pub fn enable_sync() -> Result<()> {
     info!("Enabling trace synchronization with peers");
     
     // Create sync config file
-    let config_path = PathBuf::from(constants::ROOT_DIR)
+    let config_path = constants::root_dir()
         .join(".gossip")
         .join("sync")
         .join("config.json");
     
     // Create hash cache directory
-    let cache_dir = PathBuf::from(constants::ROOT_DIR)
+    let cache_dir = constants::root_dir()
         .join(".gossip")
         .join("hash_cache");
     fs::create_dir_all(&cache_dir)?;
@@ -494,7 +519,7 @@ fn load_cached_peer_hashes(cache_dir: &Path) -> Result<HashMap<String, String>>
     }
     
     // Read config to get max cache age
-    let config_path = PathBuf::from(constants::ROOT_DIR)
+    let config_path = constants::root_dir()
         .join(".gossip")
         .join("sync")
         .join("config.json");
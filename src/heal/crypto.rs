@@ -0,0 +1,343 @@
+// SentientOS Snapshot Encryption
+// AES-256-GCM sealing of snapshot files and the keys they are sealed with
+
+use anyhow::{Result, Context};
+use tracing::info;
+use std::fs;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use serde::{Serialize, Deserialize};
+use ring::aead::{Aad, BoundKey, Nonce, NonceSequence, OpeningKey, SealingKey, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::error::Unspecified;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::core::constants;
+
+// Constants
+const KEY_PARAMS_FILE: &str = ".heal/keyparams.json";
+const PBKDF2_ITERATIONS: u32 = 600_000;
+const SALT_LEN: usize = 16;
+const MIN_PASSPHRASE_LEN: usize = 8;
+const DEFAULT_KEY_ENV: &str = "SENTIENT_SNAPSHOT_KEY";
+const PASSPHRASE_ENV: &str = "SENTIENT_SNAPSHOT_PASSPHRASE";
+
+// Resolved snapshot key, held for the life of the process
+static SNAPSHOT_KEY: Mutex<Option<(KeySource, [u8; 32])>> = Mutex::new(None);
+
+/// Where the snapshot encryption key comes from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum KeySource {
+    /// 64 hex characters in an environment variable
+    Env {
+        /// Variable name
+        var: String,
+    },
+    
+    /// A file holding 32 raw bytes or 64 hex characters
+    File {
+        /// Key file path
+        path: PathBuf,
+    },
+    
+    /// A random key, sealed with one derived from a passphrase and kept in
+    /// `.heal/keyparams.json`; the passphrase is read from
+    /// $SENTIENT_SNAPSHOT_PASSPHRASE or prompted for
+    Passphrase,
+}
+
+impl Default for KeySource {
+    fn default() -> Self {
+        KeySource::Env { var: DEFAULT_KEY_ENV.to_string() }
+    }
+}
+
+/// Key derivation parameters, stored in `.heal/keyparams.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KeyParams {
+    /// PBKDF2 salt (hex)
+    salt: String,
+    
+    /// PBKDF2-HMAC-SHA256 iterations
+    iterations: u32,
+    
+    /// Snapshot key sealed with the passphrase key (hex), for `KeySource::Passphrase`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    wrapped_key: Option<String>,
+}
+
+/// Nonce sequence yielding a single nonce, for sealing one file
+struct SingleNonce(Option<Nonce>);
+
+impl NonceSequence for SingleNonce {
+    fn advance(&mut self) -> std::result::Result<Nonce, Unspecified> {
+        self.0.take().ok_or(Unspecified)
+    }
+}
+
+/// Derive a snapshot key from a passphrase with PBKDF2-HMAC-SHA256
+///
+/// The salt is generated on first use and kept in `.heal/keyparams.json`,
+/// so the same passphrase always yields the same key on this node.
+pub fn derive_snapshot_key(passphrase: &[u8]) -> Result<[u8; 32]> {
+    let params = load_or_create_params()?;
+    derive(passphrase, &params)
+}
+
+/// The snapshot key from `source`, resolved once per process
+pub fn snapshot_key(source: &KeySource) -> Result<[u8; 32]> {
+    if let Some((cached, key)) = &*SNAPSHOT_KEY.lock().unwrap() {
+        if cached == source {
+            return Ok(*key);
+        }
+    }
+    
+    let key = match source {
+        KeySource::Env { var } => {
+            let value = std::env::var(var)
+                .with_context(|| format!("Snapshot key variable ${} is not set", var))?;
+            to_key(&from_hex(value.trim())?)?
+        }
+        KeySource::File { path } => {
+            let data = fs::read(path)
+                .with_context(|| format!("Failed to read snapshot key file {:?}", path))?;
+            if data.len() == 32 {
+                to_key(&data)?
+            } else {
+                to_key(&from_hex(String::from_utf8_lossy(&data).trim())?)?
+            }
+        }
+        KeySource::Passphrase => passphrase_key()?,
+    };
+    
+    *SNAPSHOT_KEY.lock().unwrap() = Some((source.clone(), key));
+    Ok(key)
+}
+
+/// Encrypt data with AES-256-GCM, prefixing the random 12-byte nonce
+pub fn seal(key: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new().fill(&mut nonce)
+        .map_err(|_| anyhow::anyhow!("Failed to generate nonce"))?;
+    
+    let unbound = UnboundKey::new(&AES_256_GCM, key)
+        .map_err(|_| anyhow::anyhow!("Invalid snapshot key"))?;
+    let mut sealing_key = SealingKey::new(unbound, SingleNonce(Some(Nonce::assume_unique_for_key(nonce))));
+    
+    let mut in_out = plaintext.to_vec();
+    sealing_key.seal_in_place_append_tag(Aad::empty(), &mut in_out)
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt snapshot data"))?;
+    
+    let mut sealed = nonce.to_vec();
+    sealed.extend_from_slice(&in_out);
+    Ok(sealed)
+}
+
+/// Decrypt data sealed with `seal`
+pub fn open(key: &[u8; 32], sealed: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < NONCE_LEN + AES_256_GCM.tag_len() {
+        anyhow::bail!("Encrypted snapshot data is truncated");
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    
+    let unbound = UnboundKey::new(&AES_256_GCM, key)
+        .map_err(|_| anyhow::anyhow!("Invalid snapshot key"))?;
+    let nonce = Nonce::try_assume_unique_for_key(nonce)
+        .map_err(|_| anyhow::anyhow!("Invalid snapshot nonce"))?;
+    let mut opening_key = OpeningKey::new(unbound, SingleNonce(Some(nonce)));
+    
+    let mut in_out = ciphertext.to_vec();
+    let plaintext = opening_key.open_in_place(Aad::empty(), &mut in_out)
+        .map_err(|_| anyhow::anyhow!(
+            "Snapshot data cannot be decrypted; it was encrypted with a different key or has been modified"))?;
+    Ok(plaintext.to_vec())
+}
+
+/// Unseal the passphrase-protected snapshot key, creating it on first use
+fn passphrase_key() -> Result<[u8; 32]> {
+    let mut params = load_or_create_params()?;
+    let passphrase = match std::env::var(PASSPHRASE_ENV) {
+        Ok(passphrase) => passphrase,
+        Err(_) => rpassword::prompt_password("Snapshot passphrase: ")
+            .context("Failed to read snapshot passphrase")?,
+    };
+    let wrapping_key = derive(passphrase.as_bytes(), &params)?;
+    
+    match &params.wrapped_key {
+        Some(wrapped) => {
+            let key = open(&wrapping_key, &from_hex(wrapped)?)
+                .map_err(|_| anyhow::anyhow!("Wrong snapshot passphrase"))?;
+            to_key(&key)
+        }
+        None => {
+            if passphrase.len() < MIN_PASSPHRASE_LEN {
+                anyhow::bail!("Snapshot passphrase must be at least {} characters", MIN_PASSPHRASE_LEN);
+            }
+            
+            let mut key = [0u8; 32];
+            SystemRandom::new().fill(&mut key)
+                .map_err(|_| anyhow::anyhow!("Failed to generate snapshot key"))?;
+            params.wrapped_key = Some(to_hex(&seal(&wrapping_key, &key)?));
+            save_params(&params)?;
+            info!("Created passphrase-protected snapshot key");
+            Ok(key)
+        }
+    }
+}
+
+/// Run PBKDF2 over a passphrase with the stored parameters
+fn derive(passphrase: &[u8], params: &KeyParams) -> Result<[u8; 32]> {
+    let iterations = NonZeroU32::new(params.iterations)
+        .ok_or_else(|| anyhow::anyhow!("Invalid PBKDF2 iteration count in {}", KEY_PARAMS_FILE))?;
+    
+    let mut key = [0u8; 32];
+    ring::pbkdf2::derive(ring::pbkdf2::PBKDF2_HMAC_SHA256, iterations, &from_hex(&params.salt)?, passphrase, &mut key);
+    Ok(key)
+}
+
+/// Read the key parameters, generating a salt if there are none yet
+fn load_or_create_params() -> Result<KeyParams> {
+    let path = params_path();
+    if path.exists() {
+        return serde_json::from_str(&fs::read_to_string(&path)?)
+            .with_context(|| format!("Invalid snapshot key parameters {:?}", path));
+    }
+    
+    let mut salt = [0u8; SALT_LEN];
+    SystemRandom::new().fill(&mut salt)
+        .map_err(|_| anyhow::anyhow!("Failed to generate salt"))?;
+    let params = KeyParams {
+        salt: to_hex(&salt),
+        iterations: PBKDF2_ITERATIONS,
+        wrapped_key: None,
+    };
+    save_params(&params)?;
+    Ok(params)
+}
+
+/// Write the key parameters, readable by the owner only
+fn save_params(params: &KeyParams) -> Result<()> {
+    let path = params_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    crate::core::fs::write_atomic(&path, serde_json::to_string_pretty(params)?.as_bytes())?;
+    
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+/// Path of the key parameters
+fn params_path() -> PathBuf {
+    constants::root_dir().join(KEY_PARAMS_FILE)
+}
+
+/// Convert bytes to a key
+fn to_key(bytes: &[u8]) -> Result<[u8; 32]> {
+    bytes.try_into().map_err(|_| anyhow::anyhow!("Snapshot key must be 32 bytes"))
+}
+
+/// Encode bytes as hex
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decode a hex string
+fn from_hex(hex: &str) -> Result<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        anyhow::bail!("Invalid hex string length");
+    }
+    
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).context("Invalid hex string"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const KEY: [u8; 32] = [7; 32];
+    
+    #[test]
+    fn sealed_data_opens_only_with_its_key() {
+        let sealed = seal(&KEY, b"zk proving key").unwrap();
+        assert_eq!(sealed.len(), NONCE_LEN + b"zk proving key".len() + AES_256_GCM.tag_len());
+        assert_eq!(open(&KEY, &sealed).unwrap(), b"zk proving key");
+        
+        // Every seal draws a fresh nonce
+        assert_ne!(seal(&KEY, b"zk proving key").unwrap(), sealed);
+        
+        assert!(open(&[8; 32], &sealed).is_err());
+        let mut tampered = sealed.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        assert!(open(&KEY, &tampered).is_err());
+        assert!(open(&KEY, &sealed[..NONCE_LEN + 4]).is_err());
+        
+        assert_eq!(open(&KEY, &seal(&KEY, b"").unwrap()).unwrap(), b"");
+    }
+    
+    #[test]
+    fn keys_are_read_from_variables_and_files() {
+        let hex = to_hex(&KEY);
+        assert_eq!(hex.len(), 64);
+        assert_eq!(from_hex(&hex).unwrap(), KEY);
+        assert!(from_hex("abc").is_err() && from_hex("zz").is_err() && from_hex("éé").is_err());
+        
+        std::env::set_var("SENTIENT_TEST_SNAPSHOT_KEY", format!("{}\n", hex));
+        let env = KeySource::Env { var: "SENTIENT_TEST_SNAPSHOT_KEY".to_string() };
+        assert_eq!(snapshot_key(&env).unwrap(), KEY);
+        assert!(snapshot_key(&KeySource::Env { var: "SENTIENT_TEST_SNAPSHOT_KEY_UNSET".to_string() }).is_err());
+        
+        let dir = constants::root_dir().join("crypto-tests");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("raw.key"), [9u8; 32]).unwrap();
+        fs::write(dir.join("hex.key"), to_hex(&[10u8; 32])).unwrap();
+        fs::write(dir.join("short.key"), to_hex(&[11u8; 8])).unwrap();
+        assert_eq!(snapshot_key(&KeySource::File { path: dir.join("raw.key") }).unwrap(), [9; 32]);
+        assert_eq!(snapshot_key(&KeySource::File { path: dir.join("hex.key") }).unwrap(), [10; 32]);
+        assert!(snapshot_key(&KeySource::File { path: dir.join("short.key") }).is_err());
+        assert!(snapshot_key(&KeySource::File { path: dir.join("missing.key") }).is_err());
+        
+        assert_eq!(serde_json::to_string(&KeySource::default()).unwrap(),
+                   r#"{"type":"env","var":"SENTIENT_SNAPSHOT_KEY"}"#);
+        assert_eq!(serde_json::from_str::<KeySource>(r#"{"type":"passphrase"}"#).unwrap(), KeySource::Passphrase);
+    }
+    
+    #[test]
+    fn derived_keys_depend_on_passphrase_and_salt() {
+        let params = KeyParams { salt: to_hex(&[1; SALT_LEN]), iterations: 1000, wrapped_key: None };
+        let key = derive(b"correct horse", &params).unwrap();
+        assert_eq!(derive(b"correct horse", &params).unwrap(), key);
+        assert_ne!(derive(b"battery staple", &params).unwrap(), key);
+        
+        let salted = KeyParams { salt: to_hex(&[2; SALT_LEN]), ..params.clone() };
+        assert_ne!(derive(b"correct horse", &salted).unwrap(), key);
+        assert!(derive(b"correct horse", &KeyParams { iterations: 0, ..params }).is_err());
+    }
+    
+    #[test]
+    fn passphrase_keys_are_wrapped_once_and_unwrapped_after() {
+        // Cheap parameters, so the test does not run the full PBKDF2 count
+        save_params(&KeyParams { salt: to_hex(&[3; SALT_LEN]), iterations: 1000, wrapped_key: None }).unwrap();
+        
+        std::env::set_var(PASSPHRASE_ENV, "short");
+        assert!(passphrase_key().is_err());
+        
+        std::env::set_var(PASSPHRASE_ENV, "a long enough passphrase");
+        let key = passphrase_key().unwrap();
+        assert!(load_or_create_params().unwrap().wrapped_key.is_some());
+        assert_eq!(passphrase_key().unwrap(), key);
+        assert_ne!(derive_snapshot_key(b"a long enough passphrase").unwrap(), key);
+        
+        std::env::set_var(PASSPHRASE_ENV, "a different passphrase");
+        assert!(passphrase_key().unwrap_err().to_string().contains("Wrong snapshot passphrase"));
+        std::env::remove_var(PASSPHRASE_ENV);
+    }
+}
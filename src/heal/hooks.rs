@@ -0,0 +1,158 @@
+// SentientOS Heal Hooks
+// Callbacks other subsystems register to react to snapshots, recoveries and health changes
+
+use tracing::debug;
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::HealthStatus;
+
+/// A callback run when a heal event fires
+pub type HealHook = Box<dyn Fn(&HealEventPayload) + Send + Sync>;
+
+// Registered hooks, by the event they run on
+lazy_static::lazy_static! {
+    static ref HOOKS: RwLock<HashMap<HealEvent, Vec<HealHook>>> = RwLock::new(HashMap::new());
+}
+
+/// Something the heal system did or observed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HealEvent {
+    /// A snapshot was written, normal or emergency
+    SnapshotCreated,
+    
+    /// A recovery from a snapshot is about to change live state
+    RecoveryStarted,
+    
+    /// A recovery from a snapshot finished and the system is usable
+    RecoveryCompleted,
+    
+    /// A health check found a status different from the previous one
+    HealthChanged(HealthStatus),
+}
+
+/// What a hook is told about the event it runs on
+#[derive(Debug, Clone)]
+pub struct HealEventPayload {
+    /// Event that fired
+    pub event: HealEvent,
+    
+    /// When the event fired (seconds since the epoch)
+    pub timestamp: u64,
+    
+    /// Snapshot created or recovered from
+    pub snapshot_id: Option<String>,
+    
+    /// Reason the snapshot was taken
+    pub reason: Option<String>,
+    
+    /// Health status before a health change
+    pub previous_status: Option<HealthStatus>,
+}
+
+impl HealEventPayload {
+    /// A payload for `event` with nothing else set
+    pub fn new(event: HealEvent) -> Self {
+        Self {
+            event,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            snapshot_id: None,
+            reason: None,
+            previous_status: None,
+        }
+    }
+}
+
+/// Register a callback to run whenever `event` fires
+///
+/// `HealthChanged` hooks run only on a change to the status they were
+/// registered with. Hooks run on the thread that fired the event, in
+/// registration order, and must not register further hooks.
+pub fn register(event: HealEvent, callback: HealHook) {
+    HOOKS.write().unwrap().entry(event).or_default().push(callback);
+}
+
+/// Run the hooks registered for a payload's event
+pub(crate) fn fire(payload: &HealEventPayload) {
+    let hooks = HOOKS.read().unwrap();
+    if let Some(callbacks) = hooks.get(&payload.event) {
+        debug!("Running {} hook(s) for {:?}", callbacks.len(), payload.event);
+        for callback in callbacks {
+            callback(payload);
+        }
+    }
+}
+
+/// Fire `SnapshotCreated` for a new snapshot
+pub(crate) fn snapshot_created(snapshot_id: &str, reason: &str) {
+    fire(&HealEventPayload {
+        snapshot_id: Some(snapshot_id.to_string()),
+        reason: Some(reason.to_string()),
+        ..HealEventPayload::new(HealEvent::SnapshotCreated)
+    });
+}
+
+/// Fire `RecoveryStarted` or `RecoveryCompleted` for a snapshot
+pub(crate) fn recovery(event: HealEvent, snapshot_id: &str) {
+    fire(&HealEventPayload {
+        snapshot_id: Some(snapshot_id.to_string()),
+        ..HealEventPayload::new(event)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    
+    /// Register a hook on `event` noting `label` and the snapshot of payloads from this test
+    fn record(event: HealEvent, label: &'static str, seen: &Arc<Mutex<Vec<String>>>) {
+        let seen = seen.clone();
+        register(event, Box::new(move |payload| {
+            if let Some(id) = payload.snapshot_id.as_deref().filter(|id| id.starts_with("hooks-test")) {
+                seen.lock().unwrap().push(format!("{} {}", label, id));
+            }
+        }));
+    }
+    
+    #[test]
+    fn hooks_run_in_registration_order_for_their_event_only() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        record(HealEvent::SnapshotCreated, "first", &seen);
+        record(HealEvent::SnapshotCreated, "second", &seen);
+        record(HealEvent::RecoveryCompleted, "completed", &seen);
+        record(HealEvent::HealthChanged(HealthStatus::Critical), "critical", &seen);
+        
+        snapshot_created("hooks-test-snapshot", "test");
+        recovery(HealEvent::RecoveryStarted, "hooks-test-started");
+        recovery(HealEvent::RecoveryCompleted, "hooks-test-completed");
+        for status in [HealthStatus::Degraded, HealthStatus::Critical] {
+            fire(&HealEventPayload {
+                snapshot_id: Some(format!("hooks-test-{:?}", status)),
+                ..HealEventPayload::new(HealEvent::HealthChanged(status))
+            });
+        }
+        
+        assert_eq!(*seen.lock().unwrap(), [
+            "first hooks-test-snapshot",
+            "second hooks-test-snapshot",
+            "completed hooks-test-completed",
+            "critical hooks-test-Critical",
+        ]);
+    }
+    
+    #[test]
+    fn payloads_carry_the_snapshot_and_reason() {
+        let reasons = Arc::new(Mutex::new(Vec::new()));
+        let recorded = reasons.clone();
+        register(HealEvent::SnapshotCreated, Box::new(move |payload| {
+            if payload.snapshot_id.as_deref() == Some("hooks-payload") {
+                recorded.lock().unwrap().push((payload.reason.clone(), payload.previous_status, payload.timestamp > 0));
+            }
+        }));
+        
+        snapshot_created("hooks-payload", "panic");
+        assert_eq!(*reasons.lock().unwrap(), [(Some("panic".to_string()), None, true)]);
+    }
+}
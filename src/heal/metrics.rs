@@ -0,0 +1,113 @@
+// SentientOS Heal Metrics
+// Snapshot freshness, recovery and health figures in the Prometheus text format
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use tracing::warn;
+
+use super::HealthStatus;
+
+/// The heal subsystem's metrics as a Prometheus text exposition page
+///
+/// Covers snapshot counts by reason, the size and time of the latest
+/// snapshot, recovery attempts and the last known health status. Metrics
+/// whose source can't be read are left out and the failure is logged.
+pub fn snapshot_metrics() -> String {
+    let mut page = String::new();
+    
+    match super::list_snapshots() {
+        Ok(snapshots) => {
+            let mut by_reason: BTreeMap<&str, usize> = BTreeMap::new();
+            for snapshot in &snapshots {
+                *by_reason.entry(snapshot.reason.as_str()).or_default() += 1;
+            }
+            // Pruning removes snapshots, so this is a gauge despite its name
+            header(&mut page, "sentientos_snapshots_total", "gauge", "Snapshots on disk, by the reason they were taken");
+            for (reason, count) in by_reason {
+                let _ = writeln!(page, "sentientos_snapshots_total{{reason=\"{}\"}} {}", escape_label(reason), count);
+            }
+            
+            if let Some(latest) = snapshots.iter().max_by_key(|s| s.timestamp) {
+                header(&mut page, "sentientos_snapshot_size_bytes", "gauge", "Size of the latest snapshot in bytes");
+                let _ = writeln!(page, "sentientos_snapshot_size_bytes {}", crate::core::plan::path_size(&latest.path));
+                header(&mut page, "sentientos_last_snapshot_timestamp_seconds", "gauge", "When the latest snapshot was taken");
+                let _ = writeln!(page, "sentientos_last_snapshot_timestamp_seconds {}", latest.timestamp);
+            }
+        }
+        Err(e) => warn!("Failed to list snapshots for metrics: {:#}", e),
+    }
+    
+    match super::recovery::recovery_attempts() {
+        Ok(attempts) => {
+            header(&mut page, "sentientos_recovery_attempts_total", "counter", "Recoveries started from a snapshot");
+            let _ = writeln!(page, "sentientos_recovery_attempts_total {}", attempts);
+        }
+        Err(e) => warn!("Failed to count recovery attempts for metrics: {:#}", e),
+    }
+    
+    match super::latest_health() {
+        Ok(health) => {
+            header(&mut page, "sentientos_health_status", "gauge", "System health: 0 healthy, 1 degraded, 2 critical");
+            let _ = writeln!(page, "sentientos_health_status {}", health_value(health));
+        }
+        Err(e) => warn!("Failed to check health for metrics: {:#}", e),
+    }
+    
+    page
+}
+
+/// Gauge value of a health status
+fn health_value(health: HealthStatus) -> u8 {
+    match health {
+        HealthStatus::Healthy => 0,
+        HealthStatus::Degraded => 1,
+        HealthStatus::Critical => 2,
+    }
+}
+
+/// Write the HELP and TYPE lines of a metric
+fn header(page: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(page, "# HELP {} {}", name, help);
+    let _ = writeln!(page, "# TYPE {} {}", name, kind);
+}
+
+/// Escape a label value for the text format
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn pages_follow_the_text_format() {
+        assert_eq!(escape_label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+        assert_eq!([HealthStatus::Healthy, HealthStatus::Degraded, HealthStatus::Critical].map(health_value), [0, 1, 2]);
+        
+        let mut page = String::new();
+        header(&mut page, "sentientos_test", "gauge", "A test metric");
+        assert_eq!(page, "# HELP sentientos_test A test metric\n# TYPE sentientos_test gauge\n");
+    }
+    
+    #[test]
+    fn recovery_logs_count_as_attempts() {
+        // A known status, so rendering the page does not run a health check
+        super::super::LAST_HEALTH.lock().unwrap().get_or_insert(HealthStatus::Degraded);
+        
+        let logs_dir = crate::core::constants::root_dir().join(".heal").join("logs");
+        std::fs::create_dir_all(&logs_dir).unwrap();
+        let before = super::super::recovery::recovery_attempts().unwrap();
+        std::fs::write(logs_dir.join("recovery-metrics-test-1.log"), "").unwrap();
+        std::fs::write(logs_dir.join("recovery-metrics-test-2.log"), "").unwrap();
+        std::fs::write(logs_dir.join("rollback-metrics-test.log"), "").unwrap();
+        let after = super::super::recovery::recovery_attempts().unwrap();
+        assert!(after >= before + 2);
+        
+        let page = snapshot_metrics();
+        assert!(page.contains("# TYPE sentientos_snapshots_total gauge\n"));
+        assert!(page.contains("# TYPE sentientos_recovery_attempts_total counter\n"));
+        assert!(page.lines().any(|line| line.starts_with("sentientos_recovery_attempts_total ")));
+        assert!(page.lines().any(|line| line.starts_with("sentientos_health_status ")));
+    }
+}
@@ -0,0 +1,138 @@
+// SentientOS Heal Metrics Server
+// Minimal HTTP endpoint serving the heal metrics to Prometheus
+
+use anyhow::{Result, Context};
+use tracing::{info, debug, warn};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use super::HealthStatus;
+
+// Constants
+pub const DEFAULT_METRICS_PORT: u16 = 29902;
+const ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(200);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Whether the server keeps accepting connections
+static SERVER_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// Serve the metrics page on `port`, in a background thread
+///
+/// `GET /metrics` returns `heal::metrics::snapshot_metrics`; `GET /health`
+/// returns the health status, with 503 when it is critical. Does nothing
+/// if the server is already running.
+pub fn start(port: u16) -> Result<()> {
+    if SERVER_RUNNING.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+    
+    let listener = match TcpListener::bind(("0.0.0.0", port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            SERVER_RUNNING.store(false, Ordering::SeqCst);
+            return Err(e).with_context(|| format!("Failed to bind the metrics server to port {}", port));
+        }
+    };
+    listener.set_nonblocking(true)?;
+    
+    thread::spawn(move || {
+        while SERVER_RUNNING.load(Ordering::SeqCst) {
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    debug!("Metrics request from {}", peer);
+                    if let Err(e) = handle(stream) {
+                        debug!("Failed to answer metrics request from {}: {}", peer, e);
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => thread::sleep(ACCEPT_POLL_INTERVAL),
+                Err(e) => {
+                    warn!("Metrics server failed to accept a connection: {}", e);
+                    thread::sleep(ACCEPT_POLL_INTERVAL);
+                }
+            }
+        }
+        debug!("Metrics server stopped");
+    });
+    
+    info!("Serving heal metrics on port {}", port);
+    Ok(())
+}
+
+/// Stop the metrics server
+pub fn stop() {
+    SERVER_RUNNING.store(false, Ordering::SeqCst);
+}
+
+/// Answer one HTTP request
+fn handle(stream: TcpStream) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+    
+    let (status, content_type, body) = match (method, path) {
+        ("GET", "/metrics") => ("200 OK", "text/plain; version=0.0.4", super::metrics::snapshot_metrics()),
+        ("GET", "/health") => health_response(super::latest_health()),
+        ("GET", _) => ("404 Not Found", "text/plain", "not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "text/plain", "method not allowed\n".to_string()),
+    };
+    
+    let mut stream = stream;
+    write!(stream, "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+           status, content_type, body.len(), body)?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Status line, content type and body answering `GET /health`
+fn health_response(health: Result<HealthStatus>) -> (&'static str, &'static str, String) {
+    match health {
+        Ok(HealthStatus::Critical) => ("503 Service Unavailable", "text/plain", "critical\n".to_string()),
+        Ok(health) => ("200 OK", "text/plain", format!("{:?}\n", health).to_lowercase()),
+        Err(e) => ("500 Internal Server Error", "text/plain", format!("{:#}\n", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    
+    /// Send a raw request through `handle` and return the response
+    fn request(raw: &str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(raw.as_bytes()).unwrap();
+        
+        let (stream, _) = listener.accept().unwrap();
+        handle(stream).unwrap();
+        
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        response
+    }
+    
+    #[test]
+    fn requests_are_answered_by_path_and_method() {
+        let response = request("GET /missing HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(response.ends_with("Content-Length: 10\r\nConnection: close\r\n\r\nnot found\n"));
+        
+        assert!(request("POST /metrics HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+        assert!(request("\r\n").starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    }
+    
+    #[test]
+    fn health_is_unavailable_only_when_critical() {
+        assert_eq!(health_response(Ok(HealthStatus::Critical)), ("503 Service Unavailable", "text/plain", "critical\n".to_string()));
+        assert_eq!(health_response(Ok(HealthStatus::Degraded)), ("200 OK", "text/plain", "degraded\n".to_string()));
+        assert_eq!(health_response(Ok(HealthStatus::Healthy)).2, "healthy\n");
+        assert_eq!(health_response(Err(anyhow::anyhow!("no root"))).0, "500 Internal Server Error");
+    }
+}
@@ -4,22 +4,45 @@
 pub mod snapshot;
 pub mod recovery;
 pub mod verification;
+pub mod crypto;
+pub mod metrics;
+pub mod metrics_server;
+pub mod hooks;
+
+pub use verification::{verify_snapshot, SnapshotVerification};
 
 use anyhow::{Result, Context};
 use tracing::{info, debug, warn, error};
 use std::path::{Path, PathBuf};
 use std::fs;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use blake3;
+use serde::{Serialize, Deserialize};
+use thiserror::Error;
 
 use crate::core::constants;
+use crate::core::plan::{Plan, PlannedAction};
+
+// Constants
+const SCHEDULE_FILE: &str = ".heal/schedule.json";
+const SCHEDULED_REASON: &str = "scheduled";
+const INTEGRITY_SWEEP_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+// Whether the periodic healing rules keep running
+static RULES_RUNNING: AtomicBool = AtomicBool::new(false);
+
+// Outcome of the last health check
+static LAST_HEALTH: Mutex<Option<HealthStatus>> = Mutex::new(None);
 
 /// Initialize the healing system
 pub fn init() -> Result<()> {
     info!("Initializing SentientOS healing system");
     
     // Create healing system directories
-    let heal_dir = PathBuf::from(constants::ROOT_DIR).join(".heal");
+    let heal_dir = constants::root_dir().join(".heal");
     fs::create_dir_all(&heal_dir)?;
     
     let snapshots_dir = heal_dir.join("snapshots");
@@ -36,6 +59,16 @@ pub fn init() -> Result<()> {
     recovery::init()?;
     verification::init()?;
     
+    start_rules();
+    
+    // A metrics endpoint that can't bind is not worth failing startup over
+    let config = snapshot::load_config()?;
+    if config.metrics_enabled {
+        if let Err(e) = metrics_server::start(config.metrics_port) {
+            warn!("Heal metrics are not served: {:#}", e);
+        }
+    }
+    
     info!("SentientOS healing system initialized successfully");
     Ok(())
 }
@@ -44,6 +77,9 @@ pub fn init() -> Result<()> {
 pub fn shutdown() -> Result<()> {
     info!("Shutting down SentientOS healing system");
     
+    RULES_RUNNING.store(false, Ordering::SeqCst);
+    metrics_server::stop();
+    
     // Take a final snapshot before shutdown
     let snapshot_id = take_snapshot("shutdown")?;
     info!("Created shutdown snapshot: {}", snapshot_id);
@@ -57,6 +93,122 @@ pub fn shutdown() -> Result<()> {
     Ok(())
 }
 
+/// Start the periodic healing rules
+///
+/// Scheduled snapshots are taken when due, installed packages are swept for
+/// integrity every few hours, and trusted peers are re-attested on the
+/// configured interval so a peer that stops answering, or answers with a
+/// bad signature, is demoted.
+fn start_rules() {
+    if RULES_RUNNING.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    
+    thread::spawn(|| {
+        let mut last_attestation = SystemTime::now();
+        let mut last_sweep = SystemTime::now();
+        while RULES_RUNNING.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_secs(1));
+            
+            if let Err(e) = check_snapshot_schedule() {
+                warn!("Failed to run the snapshot schedule: {:#}", e);
+            }
+            
+            if last_sweep.elapsed().unwrap_or_default() >= INTEGRITY_SWEEP_INTERVAL {
+                last_sweep = SystemTime::now();
+                if let Err(e) = crate::core::workers::submit("integrity_sweep", "sweep installed packages",
+                                                             crate::store::integrity_sweep) {
+                    warn!("Failed to queue the package integrity sweep: {}", e);
+                }
+            }
+            
+            let interval = match crate::gossip::attest::load_config() {
+                Ok(config) if config.interval_minutes > 0 => Duration::from_secs(config.interval_minutes * 60),
+                _ => continue,
+            };
+            if last_attestation.elapsed().unwrap_or_default() < interval {
+                continue;
+            }
+            
+            // Attestation waits on peers, so it runs on the worker pool
+            last_attestation = SystemTime::now();
+            if let Err(e) = crate::core::workers::submit("attestation", "attest trusted peers",
+                                                         |_| crate::gossip::attest::attest_trusted_peers()) {
+                warn!("Failed to queue periodic peer attestation: {}", e);
+            }
+        }
+    });
+    debug!("Started healing rules");
+}
+
+/// Queue a scheduled snapshot if one is due
+///
+/// The due time is kept in `.heal/schedule.json`, so a window missed while
+/// the system was down is caught up on the first check after boot.
+fn check_snapshot_schedule() -> Result<()> {
+    let interval = snapshot::load_config()?.auto_snapshot_interval_secs;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut schedule = load_schedule()?;
+    if schedule.apply_interval(interval, now) {
+        save_schedule(&schedule)?;
+    }
+    if !schedule.is_due(now) {
+        return Ok(());
+    }
+    
+    // Advance first, so a failing snapshot is retried on the next interval rather than every second
+    schedule.next_run = Some(now + interval);
+    save_schedule(&schedule)?;
+    
+    // Snapshots take a while, so they run on the worker pool
+    crate::core::workers::submit("snapshot", "scheduled snapshot", |_| take_scheduled_snapshot().map(|_| ()))?;
+    Ok(())
+}
+
+/// Take a scheduled snapshot now and count the next interval from it
+pub fn take_scheduled_snapshot() -> Result<String> {
+    let snapshot_id = take_snapshot(SCHEDULED_REASON)?;
+    
+    let interval = snapshot::load_config()?.auto_snapshot_interval_secs;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    save_schedule(&SnapshotSchedule {
+        interval_secs: interval,
+        last_run: Some(now),
+        next_run: if interval > 0 { Some(now + interval) } else { None },
+    })?;
+    
+    Ok(snapshot_id)
+}
+
+/// The snapshot schedule, with the interval currently configured
+pub fn snapshot_schedule() -> Result<SnapshotSchedule> {
+    let interval = snapshot::load_config()?.auto_snapshot_interval_secs;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    
+    // The interval may not have been picked up by the healing rules yet
+    let mut schedule = load_schedule()?;
+    schedule.apply_interval(interval, now);
+    Ok(schedule)
+}
+
+/// Load the snapshot schedule from `.heal/schedule.json`
+fn load_schedule() -> Result<SnapshotSchedule> {
+    let path = constants::root_dir().join(SCHEDULE_FILE);
+    if !path.exists() {
+        return Ok(SnapshotSchedule::default());
+    }
+    
+    serde_json::from_str(&fs::read_to_string(&path)?)
+        .with_context(|| format!("Invalid snapshot schedule {:?}", path))
+}
+
+/// Save the snapshot schedule to `.heal/schedule.json`
+fn save_schedule(schedule: &SnapshotSchedule) -> Result<()> {
+    let path = constants::root_dir().join(SCHEDULE_FILE);
+    crate::core::fs::write_atomic(&path, serde_json::to_string_pretty(schedule)?.as_bytes())
+        .context("Failed to write snapshot schedule")
+}
+
 /// Check system health
 pub fn check_health() -> Result<HealthStatus> {
     info!("Checking SentientOS system health");
@@ -70,8 +222,14 @@ pub fn check_health() -> Result<HealthStatus> {
     // Verify ZK contract state
     let zk_status = verification::verify_zk_contract_state()?;
     
+    // Metric anomalies degrade health without making it critical
+    let findings = crate::logs::metrics::findings();
+    for finding in &findings {
+        warn!("Health finding: {}", finding.message);
+    }
+    
     // Determine overall health status
-    let status = if core_status && container_status && zk_status {
+    let status = if core_status && container_status && zk_status && findings.is_empty() {
         HealthStatus::Healthy
     } else if !core_status {
         HealthStatus::Critical
@@ -80,14 +238,134 @@ pub fn check_health() -> Result<HealthStatus> {
     };
     
     info!("System health status: {:?}", status);
+    let previous = LAST_HEALTH.lock().unwrap().replace(status);
+    if previous != Some(status) {
+        hooks::fire(&hooks::HealEventPayload {
+            previous_status: previous,
+            ..hooks::HealEventPayload::new(hooks::HealEvent::HealthChanged(status))
+        });
+    }
     Ok(status)
 }
 
+/// Status of the last health check, checking now if there was none
+pub fn latest_health() -> Result<HealthStatus> {
+    let last = *LAST_HEALTH.lock().unwrap();
+    match last {
+        Some(status) => Ok(status),
+        None => check_health(),
+    }
+}
+
 /// Take a system snapshot
 pub fn take_snapshot(reason: &str) -> Result<String> {
+    take_snapshot_with(reason, &snapshot::SnapshotOptions::default())
+}
+
+/// Take a system snapshot with the given options, e.g. an incremental one
+pub fn take_snapshot_with(reason: &str, options: &snapshot::SnapshotOptions) -> Result<String> {
     info!("Taking system snapshot: {}", reason);
+    let started = std::time::Instant::now();
+    
+    let snapshot_id = new_snapshot_id(reason)?;
+    
+    // Create the snapshot
+    snapshot::create_snapshot(&snapshot_id, reason, options)?;
+    crate::logs::metrics::record("heal.snapshot_duration_ms", started.elapsed().as_millis() as f64);
+    info!("Snapshot created: {}", snapshot_id);
+    hooks::snapshot_created(&snapshot_id, reason);
+    
+    // Checking the proof waits on the verifier, so it is deferred to the
+    // worker pool; processes without a pool leave it to `heal verify`
+    let deferred_id = snapshot_id.clone();
+    if let Err(e) = crate::core::workers::submit("proof_verify", &format!("verify proof of {}", snapshot_id),
+                                                 move |_| verify_new_snapshot(&deferred_id)) {
+        debug!("Not verifying the proof of snapshot {} in the background: {}", snapshot_id, e);
+    }
     
-    // Generate snapshot ID
+    // Keep the configured retention; the new snapshot is the newest, so it stays
+    match snapshot::load_retention_policy() {
+        Ok(policy) if policy.is_limited() => {
+            if let Err(e) = snapshot::prune(policy) {
+                warn!("Failed to prune snapshots: {:#}", e);
+            }
+        }
+        Ok(_) => {}
+        Err(e) => warn!("Failed to load snapshot retention policy: {:#}", e),
+    }
+    
+    Ok(snapshot_id)
+}
+
+/// Check the proof of a freshly taken snapshot, alerting if it is invalid
+fn verify_new_snapshot(snapshot_id: &str) -> Result<()> {
+    match snapshot::verify_snapshot_proof(snapshot_id)? {
+        Some(false) => {
+            error!("ALERT: the proof of snapshot {} does not verify", snapshot_id);
+            anyhow::bail!("Invalid proof of snapshot {}", snapshot_id);
+        }
+        Some(true) => debug!("Proof of snapshot {} verified", snapshot_id),
+        None => {}
+    }
+    Ok(())
+}
+
+/// Read a single file from a snapshot without touching live state
+///
+/// `component` is a snapshot component such as `packages` or `config`, and
+/// `relative_path` is the file's path inside that component's copy.
+pub fn read_from_snapshot(snapshot_id: &str, component: &str, relative_path: &str) -> Result<Vec<u8>> {
+    if snapshot_id.is_empty() || snapshot_id.contains(|c| c == '/' || c == '\\') || snapshot_id.starts_with('.') {
+        anyhow::bail!("Invalid snapshot ID: {}", snapshot_id);
+    }
+    if component.is_empty() || !component.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        anyhow::bail!("Invalid snapshot component: {}", component);
+    }
+    
+    // Only plain relative paths, so reads can't leave the snapshot
+    let relative = Path::new(relative_path);
+    if relative_path.is_empty() || !relative.components().all(|c| matches!(c, std::path::Component::Normal(_))) {
+        anyhow::bail!("Invalid path inside snapshot: {}", relative_path);
+    }
+    
+    let snapshot_dir = constants::root_dir()
+        .join(".heal")
+        .join("snapshots")
+        .join(snapshot_id);
+    
+    if !snapshot_dir.join("metadata.json").exists() {
+        anyhow::bail!("Snapshot not found: {}", snapshot_id);
+    }
+    
+    let bundle_path = format!("{}/{}", component, relative_path);
+    if let Some(data) = snapshot::read_bundle_entry(&snapshot_dir, &bundle_path)? {
+        return Ok(data);
+    }
+    
+    // Incremental snapshots may hold the file further down their base chain
+    match snapshot::read_file(snapshot_id, &bundle_path)
+        .with_context(|| format!("Failed to read {} from snapshot {}", bundle_path, snapshot_id))? {
+        Some(data) => Ok(data),
+        None => anyhow::bail!("{} was not captured in snapshot {}", bundle_path, snapshot_id),
+    }
+}
+
+/// Take a minimal, compressed snapshot when the disk is nearly full
+///
+/// Used by the panic path when a normal snapshot fails for lack of space.
+/// Snapshots other than `protected` may be deleted to make room.
+pub fn take_emergency_snapshot(reason: &str, protected: Option<&str>) -> Result<(String, snapshot::EmergencyOutcome)> {
+    warn!("Taking emergency snapshot: {}", reason);
+    
+    let snapshot_id = new_snapshot_id(reason)?;
+    let outcome = snapshot::create_emergency_snapshot(&snapshot_id, reason, protected)?;
+    hooks::snapshot_created(&snapshot_id, reason);
+    
+    Ok((snapshot_id, outcome))
+}
+
+/// Generate a snapshot ID
+fn new_snapshot_id(reason: &str) -> Result<String> {
     let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?
         .as_secs();
     
@@ -97,21 +375,129 @@ pub fn take_snapshot(reason: &str) -> Result<String> {
         format!("{:04x}", rng.gen::<u16>())
     };
     
-    let snapshot_id = format!("{}-{}-{}", timestamp, reason, random_suffix);
+    Ok(format!("{}-{}-{}", timestamp, reason, random_suffix))
+}
+
+/// A container to heal has no snapshot that passes verification
+#[derive(Debug, Clone, Error)]
+#[error("No usable snapshot of container {container}")]
+pub struct NoContainerSnapshot {
+    /// Container ID
+    pub container: String,
+}
+
+/// A snapshot to recover from no longer matches its recorded content hash
+#[derive(Debug, Clone, Error)]
+#[error("Snapshot {snapshot_id} is corrupted; mismatched files: {}", file_list(.files))]
+pub struct SnapshotCorrupted {
+    /// Snapshot ID
+    pub snapshot_id: String,
     
-    // Create the snapshot
-    snapshot::create_snapshot(&snapshot_id, reason)?;
+    /// Files added, removed or changed since the snapshot was taken, where known
+    pub files: Vec<String>,
+}
+
+/// Mismatched files for an error message
+fn file_list(files: &[String]) -> String {
+    if files.is_empty() {
+        "none identified".to_string()
+    } else {
+        files.join(", ")
+    }
+}
+
+/// Fail with `SnapshotCorrupted` unless a snapshot passes verification
+///
+/// Run before a recovery touches live state, so a corrupted or tampered
+/// snapshot is never restored.
+pub fn ensure_snapshot_intact(snapshot_id: &str) -> Result<()> {
+    let verification = verify_snapshot(snapshot_id)?;
+    if !verification.is_intact() {
+        error!("Refusing to recover from snapshot {}: verification failed", snapshot_id);
+        return Err(SnapshotCorrupted {
+            snapshot_id: snapshot_id.to_string(),
+            files: verification.mismatched_files,
+        }.into());
+    }
+    Ok(())
+}
+
+/// Heal a container from its last good snapshot
+///
+/// The container is stopped, its data and registry entry are restored from
+/// the newest container snapshot whose hash still matches, and it is
+/// started again. Returns the ID of the snapshot used; fails with
+/// `NoContainerSnapshot` if there is none.
+pub fn heal_container(id: &str) -> Result<String> {
+    info!("Healing container: {}", id);
+    let id = id.to_string();
+    
+    let good = snapshot::list_container_snapshots(&id)?.into_iter()
+        .find(|s| match snapshot::verify_container_snapshot(s) {
+            Ok(true) => true,
+            Ok(false) => {
+                warn!("Container snapshot {} of {} failed verification; trying an older one", s.id, id);
+                false
+            }
+            Err(e) => {
+                warn!("Failed to verify container snapshot {} of {}: {:#}", s.id, id, e);
+                false
+            }
+        });
+    let good = match good {
+        Some(snapshot) => snapshot,
+        None => return Err(NoContainerSnapshot { container: id }.into()),
+    };
     
-    info!("Snapshot created: {}", snapshot_id);
-    Ok(snapshot_id)
+    // A container that needs healing has usually stopped already
+    if let Err(e) = crate::matrixbox::stop_container(&id) {
+        debug!("Container {} was not running: {:#}", id, e);
+    }
+    
+    snapshot::restore_container_snapshot(&good)?;
+    
+    crate::matrixbox::container::create_container_filesystem(&good.entry.image, &id)?;
+    crate::matrixbox::runtime::start_container(&id)
+        .with_context(|| format!("Container {} was restored but failed to start", id))?;
+    
+    info!("Container {} healed from snapshot {}", id, good.id);
+    Ok(good.id)
+}
+
+/// Replace a container with a fresh one from its image, e.g. its package
+///
+/// For containers without a usable snapshot. The old container is removed
+/// and a new one is run with the same arguments; its data starts empty.
+/// Returns the new container's ID.
+pub fn recreate_container(id: &str) -> Result<String> {
+    info!("Recreating container: {}", id);
+    let id = id.to_string();
+    
+    let container = crate::matrixbox::registry::get_container(&id)?;
+    let image = container.path.clone()
+        .ok_or_else(|| anyhow::anyhow!("Container has no path"))?;
+    if !image.exists() {
+        anyhow::bail!("Image {:?} of container {} is gone; reinstall its package first", image, id);
+    }
+    let args = crate::matrixbox::registry::get_container_args(&id)?;
+    
+    // Purging keeps the image, which the new container runs from
+    crate::matrixbox::remove_container(&id, true)?;
+    
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let new_id = crate::matrixbox::run_container(&image.to_string_lossy(), &args)?;
+    
+    crate::logs::ship::ship_audit("heal.container", &format!("Container {} recreated as {}", id, new_id));
+    Ok(new_id)
 }
 
-/// Recover from a snapshot
+/// Recover from a snapshot, or from the newest intact one for `last-known-good`
 pub fn recover_from_snapshot(snapshot_id: &str) -> Result<()> {
+    let snapshot_id = &resolve_rollback_target(snapshot_id)?;
     info!("Recovering from snapshot: {}", snapshot_id);
     
     // Verify the snapshot exists
-    let snapshot_path = PathBuf::from(constants::ROOT_DIR)
+    let snapshot_path = constants::root_dir()
         .join(".heal")
         .join("snapshots")
         .join(snapshot_id);
@@ -119,19 +505,44 @@ pub fn recover_from_snapshot(snapshot_id: &str) -> Result<()> {
     if !snapshot_path.exists() {
         anyhow::bail!("Snapshot not found: {}", snapshot_id);
     }
+    if let Err(e) = ensure_snapshot_intact(snapshot_id) {
+        if e.is::<SnapshotCorrupted>() {
+            let event = crate::panic::PanicEvent::CorruptedState { path: snapshot_path.clone() };
+            if let Err(record_error) = crate::panic::record_panic(event) {
+                error!("Failed to record corrupted snapshot {}: {:#}", snapshot_id, record_error);
+            }
+        }
+        return Err(e);
+    }
+    
+    let plan = plan_recovery(snapshot_id)?;
+    let components: Vec<String> = plan.actions.iter()
+        .filter_map(|action| match action {
+            PlannedAction::RestoreFiles { component, .. } => Some(component.clone()),
+            _ => None,
+        })
+        .collect();
+    
+    hooks::recovery(hooks::HealEvent::RecoveryStarted, snapshot_id);
     
     // Stop running containers
     info!("Stopping running containers for recovery");
     crate::matrixbox::shutdown()?;
     
     // Perform recovery
-    recovery::recover_from_snapshot(snapshot_id)?;
+    recovery::recover_components(snapshot_id, &components)?;
     
     // Restart container runtime
     info!("Restarting container runtime");
     crate::matrixbox::init()?;
     
-    // Verify recovery
+    confirm_recovery()?;
+    hooks::recovery(hooks::HealEvent::RecoveryCompleted, snapshot_id);
+    Ok(())
+}
+
+/// Check that the system is healthy enough after a recovery
+fn confirm_recovery() -> Result<()> {
     let health = check_health()?;
     
     if health == HealthStatus::Healthy || health == HealthStatus::Degraded {
@@ -143,6 +554,147 @@ pub fn recover_from_snapshot(snapshot_id: &str) -> Result<()> {
     }
 }
 
+/// Plan a recovery from a snapshot without changing anything
+pub fn plan_recovery(snapshot_id: &str) -> Result<Plan> {
+    let snapshot_path = constants::root_dir()
+        .join(".heal")
+        .join("snapshots")
+        .join(snapshot_id);
+    
+    if !snapshot_path.exists() {
+        anyhow::bail!("Snapshot not found: {}", snapshot_id);
+    }
+    
+    let mut plan = Plan::new(&format!("recover {}", snapshot_id));
+    
+    // Recovery stops the whole container runtime
+    plan.actions.extend(stop_running_containers()?);
+    plan.actions.extend(restore_actions(&snapshot_path, recovery::COMPONENTS.iter().copied()));
+    plan.push(PlannedAction::RestartSubsystem { subsystem: "matrixbox".to_string() });
+    Ok(plan)
+}
+
+/// Roll the system back to a snapshot, or to the latest one for `last-known-good`
+///
+/// The snapshot's components are rolled back one at a time in recovery
+/// order, each pausing only the subsystem that uses it.
+pub fn rollback_system(target: &str) -> Result<()> {
+    let snapshot_id = resolve_rollback_target(target)?;
+    info!("Rolling back to snapshot: {}", snapshot_id);
+    
+    for action in plan_recovery(&snapshot_id)?.actions {
+        if let PlannedAction::RestoreFiles { component, .. } = action {
+            recovery::rollback_component(&component, &snapshot_id)?;
+        }
+    }
+    
+    confirm_recovery()
+}
+
+/// Plan a rollback without changing anything
+pub fn plan_rollback(target: &str) -> Result<Plan> {
+    let snapshot_id = resolve_rollback_target(target)?;
+    let mut plan = Plan::new(&format!("rollback {} ({})", target, snapshot_id));
+    
+    for action in plan_recovery(&snapshot_id)?.actions {
+        if let PlannedAction::RestoreFiles { component, .. } = action {
+            for action in plan_component_rollback(&component, &snapshot_id)?.actions {
+                // Containers stopped for one component stay stopped for the next
+                if !plan.actions.contains(&action) {
+                    plan.push(action);
+                }
+            }
+        }
+    }
+    Ok(plan)
+}
+
+/// Plan rolling one component back to a snapshot without changing anything
+pub fn plan_component_rollback(component: &str, snapshot_id: &str) -> Result<Plan> {
+    let snapshot_path = constants::root_dir()
+        .join(".heal")
+        .join("snapshots")
+        .join(snapshot_id);
+    
+    if !snapshot_path.exists() {
+        anyhow::bail!("Snapshot not found: {}", snapshot_id);
+    }
+    if recovery::component_target(component).is_none() {
+        anyhow::bail!("Unknown component: {}", component);
+    }
+    
+    let mut plan = Plan::new(&format!("rollback {} to {}", component, snapshot_id));
+    let subsystem = recovery::component_subsystem(component);
+    if subsystem == Some("matrixbox") {
+        plan.actions.extend(stop_running_containers()?);
+    }
+    plan.actions.extend(restore_actions(&snapshot_path, [component]));
+    if let Some(subsystem) = subsystem {
+        plan.push(PlannedAction::RestartSubsystem { subsystem: subsystem.to_string() });
+    }
+    Ok(plan)
+}
+
+/// Planned stops of the containers that are running
+fn stop_running_containers() -> Result<Vec<PlannedAction>> {
+    let mut actions = Vec::new();
+    for container in crate::matrixbox::list_containers()? {
+        if crate::matrixbox::runtime::is_container_running(&container.id)? {
+            actions.push(PlannedAction::StopContainer { id: container.id.clone(), name: container.name.clone() });
+        }
+    }
+    Ok(actions)
+}
+
+/// Planned restores of the given components a snapshot has
+fn restore_actions<'a>(snapshot_path: &Path, components: impl IntoIterator<Item = &'a str>) -> Vec<PlannedAction> {
+    let mut actions = Vec::new();
+    for component in components {
+        let source = snapshot_path.join(component);
+        if !source.exists() {
+            continue;
+        }
+        
+        let target = match recovery::component_target(component) {
+            Some(target) => target,
+            None => continue,
+        };
+        
+        actions.push(PlannedAction::RestoreFiles {
+            component: component.to_string(),
+            source: source.to_string_lossy().to_string(),
+            target: target.to_string_lossy().to_string(),
+            bytes: crate::core::plan::path_size(&source),
+        });
+    }
+    actions
+}
+
+/// Resolve a rollback target to a snapshot ID
+///
+/// With the snapshot ring buffer enabled, `last-known-good` is the newest
+/// ring entry that still matches its content hash.
+fn resolve_rollback_target(target: &str) -> Result<String> {
+    if target != "last-known-good" {
+        return Ok(target.to_string());
+    }
+    
+    if snapshot::load_config()?.ring_buffer.is_some() {
+        for id in snapshot::ring_entries()? {
+            match verify_snapshot(&id) {
+                Ok(verification) if verification.is_intact() => return Ok(id),
+                Ok(_) => warn!("Skipping ring snapshot {}: it failed verification", id),
+                Err(e) => warn!("Skipping ring snapshot {}: {:#}", id, e),
+            }
+        }
+        anyhow::bail!("No intact snapshot in the ring buffer to roll back to");
+    }
+    
+    get_latest_snapshot()?
+        .map(|snapshot| snapshot.id)
+        .ok_or_else(|| anyhow::anyhow!("No snapshots available to roll back to"))
+}
+
 /// List available snapshots
 pub fn list_snapshots() -> Result<Vec<SnapshotInfo>> {
     info!("Listing available snapshots");
@@ -150,10 +702,17 @@ pub fn list_snapshots() -> Result<Vec<SnapshotInfo>> {
     snapshot::list_snapshots()
 }
 
-/// Get the latest snapshot
+/// Get the latest snapshot, the ring head when the ring buffer is enabled
 pub fn get_latest_snapshot() -> Result<Option<SnapshotInfo>> {
     info!("Getting latest snapshot");
     
+    if snapshot::load_config()?.ring_buffer.is_some() {
+        if let Some(head) = snapshot::ring_entries()?.into_iter().next() {
+            info!("Latest snapshot: {} (ring head)", head);
+            return snapshot::get_snapshot(&head);
+        }
+    }
+    
     let snapshots = snapshot::list_snapshots()?;
     
     if snapshots.is_empty() {
@@ -175,7 +734,7 @@ pub fn get_latest_snapshot() -> Result<Option<SnapshotInfo>> {
 }
 
 /// System health status
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum HealthStatus {
     /// System is healthy
     Healthy,
@@ -187,6 +746,47 @@ pub enum HealthStatus {
     Critical,
 }
 
+/// When scheduled snapshots run, stored in `.heal/schedule.json`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SnapshotSchedule {
+    /// Interval between scheduled snapshots in seconds; 0 when disabled
+    pub interval_secs: u64,
+    
+    /// When the last scheduled snapshot was taken (seconds since epoch)
+    pub last_run: Option<u64>,
+    
+    /// When the next scheduled snapshot is due (seconds since epoch)
+    pub next_run: Option<u64>,
+}
+
+impl SnapshotSchedule {
+    /// Bring the schedule in line with the configured interval, returning
+    /// whether it changed
+    ///
+    /// A new or changed interval counts from the last scheduled snapshot,
+    /// or from `now` if there has been none.
+    fn apply_interval(&mut self, interval: u64, now: u64) -> bool {
+        if interval == 0 {
+            let changed = self.interval_secs != 0 || self.next_run.is_some();
+            self.interval_secs = 0;
+            self.next_run = None;
+            return changed;
+        }
+        
+        if self.interval_secs == interval && self.next_run.is_some() {
+            return false;
+        }
+        self.interval_secs = interval;
+        self.next_run = Some(self.last_run.unwrap_or(now) + interval);
+        true
+    }
+    
+    /// Whether a scheduled snapshot is due at `now`
+    fn is_due(&self, now: u64) -> bool {
+        matches!(self.next_run, Some(next) if next <= now)
+    }
+}
+
 /// Snapshot information
 #[derive(Debug, Clone)]
 pub struct SnapshotInfo {
@@ -204,4 +804,114 @@ pub struct SnapshotInfo {
     
     /// Content hash of the snapshot
     pub hash: String,
+    
+    /// Snapshot an incremental snapshot is based on
+    pub base: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn containers_without_usable_snapshots_are_not_healed() {
+        let error = heal_container("heal-test-unsnapshotted").unwrap_err();
+        let missing = error.downcast_ref::<NoContainerSnapshot>().unwrap();
+        assert_eq!(missing.container, "heal-test-unsnapshotted");
+        assert_eq!(error.to_string(), "No usable snapshot of container heal-test-unsnapshotted");
+        
+        assert!(heal_container("../escape").unwrap_err().downcast_ref::<NoContainerSnapshot>().is_none());
+    }
+    
+    #[test]
+    fn corrupted_snapshots_are_refused_before_anything_is_paused() {
+        let contract = constants::root_dir().join(constants::ZK_DIR).join("contracts").join("heal_corrupted.yaml");
+        fs::create_dir_all(contract.parent().unwrap()).unwrap();
+        fs::write(&contract, "original").unwrap();
+        snapshot::create_snapshot("heal-corrupted", "test", &snapshot::SnapshotOptions::default()).unwrap();
+        ensure_snapshot_intact("heal-corrupted").unwrap();
+        
+        let snapshot_dir = constants::root_dir().join(".heal").join("snapshots").join("heal-corrupted");
+        fs::write(snapshot_dir.join("zk/contracts/heal_corrupted.yaml"), "tampered").unwrap();
+        fs::write(snapshot_dir.join("zk/contracts/heal_added.yaml"), "added").unwrap();
+        
+        let error = ensure_snapshot_intact("heal-corrupted").unwrap_err();
+        let corrupted = error.downcast_ref::<SnapshotCorrupted>().unwrap();
+        assert_eq!(corrupted.files, ["zk/contracts/heal_added.yaml", "zk/contracts/heal_corrupted.yaml"]);
+        assert_eq!(error.to_string(), "Snapshot heal-corrupted is corrupted; mismatched files: zk/contracts/heal_added.yaml, zk/contracts/heal_corrupted.yaml");
+        
+        // Rolling back from it fails without touching the live files
+        let error = recovery::rollback_component("zk", "heal-corrupted").unwrap_err();
+        assert!(error.is::<SnapshotCorrupted>());
+        assert_eq!(fs::read_to_string(&contract).unwrap(), "original");
+        
+        assert_eq!(file_list(&[]), "none identified");
+    }
+    
+    #[test]
+    fn component_rollbacks_restart_only_their_subsystem() {
+        let snapshot = constants::root_dir().join(".heal").join("snapshots").join("rollback-plan");
+        for component in ["zk", "core"] {
+            fs::create_dir_all(snapshot.join(component)).unwrap();
+            fs::write(snapshot.join(component).join("state.json"), "{}").unwrap();
+        }
+        
+        let plan = plan_component_rollback("zk", "rollback-plan").unwrap();
+        assert_eq!(plan.actions.len(), 2);
+        match &plan.actions[0] {
+            PlannedAction::RestoreFiles { component, source, target, bytes } => {
+                assert_eq!(component, "zk");
+                assert_eq!(Path::new(source), snapshot.join("zk"));
+                assert_eq!(Path::new(target), constants::root_dir().join(constants::ZK_DIR));
+                assert_eq!(*bytes, 2);
+            }
+            other => panic!("unexpected action: {:?}", other),
+        }
+        assert_eq!(plan.actions[1], PlannedAction::RestartSubsystem { subsystem: "zk".to_string() });
+        
+        // Core files are restored in place, with nothing to restart
+        let plan = plan_component_rollback("core", "rollback-plan").unwrap();
+        assert!(matches!(plan.actions.as_slice(), [PlannedAction::RestoreFiles { .. }]));
+        
+        // A component the snapshot lacks leaves only the restart
+        let plan = plan_component_rollback("auth", "rollback-plan").unwrap();
+        assert_eq!(plan.actions, [PlannedAction::RestartSubsystem { subsystem: "auth".to_string() }]);
+        
+        assert!(plan_component_rollback("bogus", "rollback-plan").is_err());
+        assert!(plan_component_rollback("zk", "rollback-missing").is_err());
+    }
+    
+    #[test]
+    fn schedules_follow_the_configured_interval() {
+        let mut schedule = SnapshotSchedule::default();
+        assert!(!schedule.apply_interval(0, 1000));
+        assert!(!schedule.is_due(u64::MAX));
+        
+        // A first interval counts from now
+        assert!(schedule.apply_interval(60, 1000));
+        assert_eq!(schedule.next_run, Some(1060));
+        assert!(!schedule.apply_interval(60, 1030));
+        assert!(!schedule.is_due(1059) && schedule.is_due(1060));
+        
+        // A changed one counts from the last scheduled snapshot, so a window
+        // missed while the system was down is due at once
+        schedule.last_run = Some(500);
+        assert!(schedule.apply_interval(120, 2000));
+        assert_eq!((schedule.interval_secs, schedule.next_run), (120, Some(620)));
+        assert!(schedule.is_due(2000));
+        
+        assert!(schedule.apply_interval(0, 2000));
+        assert_eq!((schedule.interval_secs, schedule.next_run, schedule.last_run), (0, None, Some(500)));
+        assert!(!schedule.is_due(2000));
+    }
+    
+    #[test]
+    fn schedules_are_kept_across_restarts() {
+        assert!(load_schedule().is_ok());
+        let schedule = SnapshotSchedule { interval_secs: 300, last_run: Some(1000), next_run: Some(1300) };
+        save_schedule(&schedule).unwrap();
+        
+        let loaded = load_schedule().unwrap();
+        assert_eq!((loaded.interval_secs, loaded.last_run, loaded.next_run), (300, Some(1000), Some(1300)));
+    }
 }
@@ -4,12 +4,16 @@ use std::path::{Path, PathBuf};
 use std::fs;
 
 use crate::core::constants;
+use super::snapshot::Storage;
+
+/// Components restored from a snapshot, in recovery order
+pub const COMPONENTS: &[&str] = &["core", "zk", "auth", "containers", "runtime", "linux"];
 
 /// Initialize the recovery system
 pub fn init() -> Result<()> {
     info!("Initializing recovery system");
     
-    let recovery_dir = PathBuf::from(constants::ROOT_DIR)
+    let recovery_dir = constants::root_dir()
         .join(".heal")
         .join("recovery");
     
@@ -35,7 +39,22 @@ pub fn recover_from_snapshot(snapshot_id: &str) -> Result<()> {
     info!("Recovering from snapshot: {}", snapshot_id);
     
     // Verify snapshot exists
-    let snapshot_dir = PathBuf::from(constants::ROOT_DIR)
+    let snapshot_dir = constants::root_dir()
+        .join(".heal")
+        .join("snapshots")
+        .join(snapshot_id);
+    
+    if !snapshot_dir.exists() {
+        return Err(anyhow::anyhow!("Snapshot not found: {}", snapshot_id));
+    }
+    
+    let components: Vec<String> = COMPONENTS.iter().map(|c| c.to_string()).collect();
+    recover_components(snapshot_id, &components)
+}
+
+/// Recover the given components from a snapshot, in the order given
+pub fn recover_components(snapshot_id: &str, components: &[String]) -> Result<()> {
+    let snapshot_dir = constants::root_dir()
         .join(".heal")
         .join("snapshots")
         .join(snapshot_id);
@@ -44,27 +63,166 @@ pub fn recover_from_snapshot(snapshot_id: &str) -> Result<()> {
         return Err(anyhow::anyhow!("Snapshot not found: {}", snapshot_id));
     }
     
+    // Emergency snapshots keep their files in a compressed bundle
+    super::snapshot::unpack_emergency(&snapshot_dir)?;
+    
+    // Incremental snapshots are assembled from their base chain first
+    let staged = if super::snapshot::is_incremental(&snapshot_dir) {
+        let staging = constants::root_dir()
+            .join(".heal")
+            .join("recovery")
+            .join(snapshot_id);
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+        super::snapshot::materialize(snapshot_id, &staging)
+            .with_context(|| format!("Failed to assemble incremental snapshot {}", snapshot_id))?;
+        Some(staging)
+    } else {
+        None
+    };
+    let source_dir = staged.as_deref().unwrap_or(&snapshot_dir);
+    
+    // Assembled trees hold plain files; otherwise follow the snapshot's storage
+    let storage = match staged {
+        Some(_) => Storage::default(),
+        None => Storage::of(&snapshot_dir)?,
+    };
+    
     // Create a recovery log
     let recovery_log = create_recovery_log(snapshot_id)?;
     
     // Perform component recovery in order
-    recover_component("core", &snapshot_dir, &recovery_log)?;
-    recover_component("zk", &snapshot_dir, &recovery_log)?;
-    recover_component("auth", &snapshot_dir, &recovery_log)?;
-    recover_component("containers", &snapshot_dir, &recovery_log)?;
-    recover_component("runtime", &snapshot_dir, &recovery_log)?;
-    recover_component("linux", &snapshot_dir, &recovery_log)?;
+    let recovered = components.iter()
+        .try_for_each(|component| recover_component(component, source_dir, &recovery_log, &storage));
+    
+    if let Some(staging) = &staged {
+        if let Err(e) = fs::remove_dir_all(staging) {
+            debug!("Failed to remove recovery staging {:?}: {}", staging, e);
+        }
+    }
+    recovered?;
     
     info!("Recovery complete from snapshot: {}", snapshot_id);
     Ok(())
 }
 
+/// System path a snapshot component is restored to
+pub fn component_target(component: &str) -> Option<PathBuf> {
+    let root = constants::root_dir();
+    match component {
+        "core" => Some(root.join(constants::CORE_DIR)),
+        "zk" => Some(root.join(constants::ZK_DIR)),
+        "containers" => Some(root.join(constants::CONTAINER_DIR)),
+        "runtime" => Some(root.join(constants::RUNTIME_DIR)),
+        "auth" => Some(root.join(constants::AUTH_DIR)),
+        "linux" => Some(root.join(".linux")),
+        "kv" => Some(root.join(".matrixbox").join("data")),
+        _ => None,
+    }
+}
+
+/// Subsystem using a component's files, paused while it is rolled back
+///
+/// `core` and `runtime` have none that can be restarted on its own; their
+/// files are restored in place.
+pub fn component_subsystem(component: &str) -> Option<&'static str> {
+    match component {
+        "zk" => Some("zk"),
+        "auth" => Some("auth"),
+        "containers" | "kv" => Some("matrixbox"),
+        "linux" => Some("linux"),
+        _ => None,
+    }
+}
+
+/// Restore one component from a snapshot without a full system recovery
+///
+/// Only the subsystem using the component is paused: it is shut down
+/// before the files are restored and initialized again afterwards, even
+/// if the restore fails. Containers keep running unless the component is
+/// theirs.
+pub fn rollback_component(component: &str, snapshot_id: &str) -> Result<()> {
+    if component_target(component).is_none() {
+        anyhow::bail!("Unknown component: {}", component);
+    }
+    info!("Rolling back component {} to snapshot {}", component, snapshot_id);
+    super::ensure_snapshot_intact(snapshot_id)?;
+    
+    let subsystem = component_subsystem(component);
+    if let Some(subsystem) = subsystem {
+        info!("Pausing {} subsystem", subsystem);
+        shutdown_subsystem(subsystem)
+            .with_context(|| format!("Failed to shut down the {} subsystem", subsystem))?;
+    }
+    
+    let restored = recover_components(snapshot_id, &[component.to_string()]);
+    let resumed = match subsystem {
+        Some(subsystem) => {
+            info!("Resuming {} subsystem", subsystem);
+            init_subsystem(subsystem)
+                .with_context(|| format!("Failed to restart the {} subsystem", subsystem))
+        }
+        None => Ok(()),
+    };
+    restored?;
+    resumed?;
+    
+    crate::logs::ship::ship_audit("heal.rollback", &format!(
+        "Component {} rolled back to snapshot {}", component, snapshot_id));
+    info!("Component {} rolled back to snapshot {}", component, snapshot_id);
+    Ok(())
+}
+
+/// Shut down a subsystem named by `component_subsystem`
+fn shutdown_subsystem(subsystem: &str) -> Result<()> {
+    match subsystem {
+        "zk" => crate::zk::shutdown(),
+        "auth" => crate::auth::shutdown(),
+        "matrixbox" => crate::matrixbox::shutdown(),
+        "linux" => crate::linux::shutdown(),
+        _ => Ok(()),
+    }
+}
+
+/// Initialize a subsystem named by `component_subsystem`
+fn init_subsystem(subsystem: &str) -> Result<()> {
+    match subsystem {
+        "zk" => crate::zk::init(),
+        "auth" => crate::auth::init(),
+        "matrixbox" => crate::matrixbox::init(),
+        "linux" => crate::linux::init(),
+        _ => Ok(()),
+    }
+}
+
+/// Number of recoveries started, counted by their logs in `.heal/logs`
+pub fn recovery_attempts() -> Result<usize> {
+    let logs_dir = constants::root_dir()
+        .join(".heal")
+        .join("logs");
+    
+    if !logs_dir.exists() {
+        return Ok(0);
+    }
+    
+    let mut attempts = 0;
+    for entry in fs::read_dir(&logs_dir)? {
+        let name = entry?.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with("recovery-") && name.ends_with(".log") {
+            attempts += 1;
+        }
+    }
+    Ok(attempts)
+}
+
 /// Create a recovery log file
 fn create_recovery_log(snapshot_id: &str) -> Result<PathBuf> {
     let timestamp = chrono::Utc::now().format("%Y%m%d-%H%M%S");
     let log_name = format!("recovery-{}-{}.log", snapshot_id, timestamp);
     
-    let log_path = PathBuf::from(constants::ROOT_DIR)
+    let log_path = constants::root_dir()
         .join(".heal")
         .join("logs")
         .join(log_name);
@@ -83,7 +241,7 @@ fn create_recovery_log(snapshot_id: &str) -> Result<PathBuf> {
 }
 
 /// Recover a specific component
-fn recover_component(component: &str, snapshot_dir: &Path, recovery_log: &Path) -> Result<()> {
+fn recover_component(component: &str, snapshot_dir: &Path, recovery_log: &Path, storage: &Storage) -> Result<()> {
     debug!("Recovering component: {}", component);
     
     // Source path in snapshot
@@ -96,14 +254,9 @@ fn recover_component(component: &str, snapshot_dir: &Path, recovery_log: &Path)
     }
     
     // Target path in system
-    let target_path = match component {
-        "core" => PathBuf::from(constants::ROOT_DIR).join(constants::CORE_DIR),
-        "zk" => PathBuf::from(constants::ROOT_DIR).join(constants::ZK_DIR),
-        "containers" => PathBuf::from(constants::ROOT_DIR).join(constants::CONTAINER_DIR),
-        "runtime" => PathBuf::from(constants::ROOT_DIR).join(constants::RUNTIME_DIR),
-        "auth" => PathBuf::from(constants::ROOT_DIR).join(constants::AUTH_DIR),
-        "linux" => PathBuf::from(constants::ROOT_DIR).join(".linux"),
-        _ => {
+    let target_path = match component_target(component) {
+        Some(path) => path,
+        None => {
             warn!("Unknown component: {}", component);
             log_recovery_event(recovery_log, component, "ERROR", "Unknown component")?;
             return Err(anyhow::anyhow!("Unknown component: {}", component));
@@ -114,7 +267,7 @@ fn recover_component(component: &str, snapshot_dir: &Path, recovery_log: &Path)
     fs::create_dir_all(&target_path)?;
     
     // Restore files from snapshot
-    restore_files(&component_source, &target_path, recovery_log, component)?;
+    restore_files(&component_source, &target_path, recovery_log, component, storage)?;
     
     log_recovery_event(recovery_log, component, "SUCCESS", "Component recovered")?;
     debug!("Component recovery complete: {}", component);
@@ -136,7 +289,7 @@ fn log_recovery_event(log_path: &Path, component: &str, status: &str, message: &
 }
 
 /// Restore files from a snapshot to the target system
-fn restore_files(source: &Path, target: &Path, log_path: &Path, component: &str) -> Result<()> {
+fn restore_files(source: &Path, target: &Path, log_path: &Path, component: &str, storage: &Storage) -> Result<()> {
     debug!("Restoring files from {:?} to {:?}", source, target);
     
     // Check if source exists
@@ -159,30 +312,30 @@ fn restore_files(source: &Path, target: &Path, log_path: &Path, component: &str)
         "core" => {
             // For core, we restore config and state files
             restore_specific_files(source, target, &["config.yaml", "state.json"], 
-                                 log_path, component, &mut success_count, &mut error_count)?;
+                                 log_path, component, storage, &mut success_count, &mut error_count)?;
         },
         "zk" => {
             // For ZK, restore contracts and keys directories
             restore_directory(source.join("contracts"), target.join("contracts"), 
-                           log_path, component, &mut success_count, &mut error_count)?;
+                           log_path, component, storage, &mut success_count, &mut error_count)?;
             
             restore_directory(source.join("keys"), target.join("keys"), 
-                           log_path, component, &mut success_count, &mut error_count)?;
+                           log_path, component, storage, &mut success_count, &mut error_count)?;
         },
         "containers" => {
             // For containers, just restore the registry file, not actual containers
             restore_specific_files(source, target, &["registry.json"], 
-                                 log_path, component, &mut success_count, &mut error_count)?;
+                                 log_path, component, storage, &mut success_count, &mut error_count)?;
         },
         "runtime" => {
             // For runtime, restore state but not logs
             restore_specific_files(source, target, &["state.json"], 
-                                 log_path, component, &mut success_count, &mut error_count)?;
+                                 log_path, component, storage, &mut success_count, &mut error_count)?;
         },
         "auth" => {
             // For auth, restore config and public keys only
             restore_specific_files(source, target, &["config.yaml"], 
-                                 log_path, component, &mut success_count, &mut error_count)?;
+                                 log_path, component, storage, &mut success_count, &mut error_count)?;
             
             // Public keys are in a directory
             let src_keys = source.join("keys");
@@ -192,18 +345,18 @@ fn restore_files(source: &Path, target: &Path, log_path: &Path, component: &str)
                 // Only restore public keys
                 restore_directory_with_filter(src_keys, tgt_keys, |name| {
                     name.contains("public") || name.ends_with(".pub")
-                }, log_path, component, &mut success_count, &mut error_count)?;
+                }, log_path, component, storage, &mut success_count, &mut error_count)?;
             }
         },
         "linux" => {
             // For Linux compatibility, restore etc directory
             restore_directory(source.join("etc"), target.join("etc"), 
-                           log_path, component, &mut success_count, &mut error_count)?;
+                           log_path, component, storage, &mut success_count, &mut error_count)?;
         },
         _ => {
             // For unknown components, just try to restore everything
             restore_directory(source.clone(), target.clone(), 
-                           log_path, component, &mut success_count, &mut error_count)?;
+                           log_path, component, storage, &mut success_count, &mut error_count)?;
         }
     }
     
@@ -227,7 +380,7 @@ fn backup_target_before_restore(target: &Path, component: &str) -> Result<()> {
     
     // Create backup directory
     let timestamp = chrono::Utc::now().format("%Y%m%d%H%M%S").to_string();
-    let backup_dir = PathBuf::from(constants::ROOT_DIR)
+    let backup_dir = constants::root_dir()
         .join(".heal")
         .join("backups")
         .join(format!("{}-{}", component, timestamp));
@@ -275,12 +428,16 @@ fn restore_specific_files(
     files: &[&str],
     log_path: &Path,
     component: &str,
+    storage: &Storage,
     success_count: &mut usize,
     error_count: &mut usize
 ) -> Result<()> {
     for file in files {
-        let src_file = source.join(file);
+        let mut src_file = source.join(file);
         let tgt_file = target.join(file);
+        if !src_file.exists() {
+            src_file = storage.stored_path(&src_file);
+        }
         
         // Skip if source file doesn't exist
         if !src_file.exists() {
@@ -292,7 +449,7 @@ fn restore_specific_files(
             fs::create_dir_all(parent)?;
         }
         
-        match fs::copy(&src_file, &tgt_file) {
+        match restore_file(&src_file, &tgt_file, storage) {
             Ok(_) => {
                 *success_count += 1;
                 log_recovery_event(log_path, component, "RESTORED", &format!("File {}", file))?;
@@ -307,12 +464,28 @@ fn restore_specific_files(
     Ok(())
 }
 
+/// Copy a snapshot file into place
+///
+/// Compressed and encrypted files are restored to their original name and
+/// content.
+fn restore_file(source: &Path, target: &Path, storage: &Storage) -> Result<()> {
+    let target_str = target.to_string_lossy();
+    match storage.original_name(&target_str) {
+        Some(original) => storage.extract(source, Path::new(original)),
+        None => {
+            fs::copy(source, target)?;
+            Ok(())
+        }
+    }
+}
+
 /// Restore an entire directory recursively
 fn restore_directory(
     source: &Path, 
     target: &Path,
     log_path: &Path,
     component: &str,
+    storage: &Storage,
     success_count: &mut usize,
     error_count: &mut usize
 ) -> Result<()> {
@@ -332,10 +505,10 @@ fn restore_directory(
         
         if path.is_dir() {
             // Recursively restore directory
-            restore_directory(&path, &target_path, log_path, component, success_count, error_count)?;
+            restore_directory(&path, &target_path, log_path, component, storage, success_count, error_count)?;
         } else {
             // Restore file
-            match fs::copy(&path, &target_path) {
+            match restore_file(&path, &target_path, storage) {
                 Ok(_) => {
                     *success_count += 1;
                     log_recovery_event(log_path, component, "RESTORED", 
@@ -361,6 +534,7 @@ fn restore_directory_with_filter<F>(
     filter: F,
     log_path: &Path,
     component: &str,
+    storage: &Storage,
     success_count: &mut usize,
     error_count: &mut usize
 ) -> Result<()>
@@ -382,18 +556,23 @@ where
         let file_name_str = file_name.to_string_lossy().to_string();
         let target_path = target.join(&file_name);
         
-        // Apply filter
-        if !filter(&file_name_str) {
+        // Apply filter, to the original name of stored files
+        let original_name = if path.is_file() {
+            storage.original_name(&file_name_str).unwrap_or(&file_name_str)
+        } else {
+            &file_name_str
+        };
+        if !filter(original_name) {
             continue;
         }
         
         if path.is_dir() {
             // Recursively restore directory
             restore_directory_with_filter(&path, &target_path, &filter, 
-                                       log_path, component, success_count, error_count)?;
+                                       log_path, component, storage, success_count, error_count)?;
         } else {
             // Restore file
-            match fs::copy(&path, &target_path) {
+            match restore_file(&path, &target_path, storage) {
                 Ok(_) => {
                     *success_count += 1;
                     log_recovery_event(log_path, component, "RESTORED", 
@@ -410,3 +589,25 @@ where
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn components_map_to_their_directory_and_subsystem() {
+        let root = constants::root_dir();
+        assert_eq!(component_target("zk"), Some(root.join(constants::ZK_DIR)));
+        assert_eq!(component_target("kv"), Some(root.join(".matrixbox").join("data")));
+        assert_eq!(component_target("bogus"), None);
+        
+        let subsystems: Vec<_> = ["zk", "auth", "containers", "kv", "linux", "core", "runtime"].iter()
+            .map(|component| component_subsystem(component))
+            .collect();
+        assert_eq!(subsystems, [Some("zk"), Some("auth"), Some("matrixbox"), Some("matrixbox"), Some("linux"), None, None]);
+        
+        // Unknown components are refused before any subsystem is paused
+        let error = rollback_component("bogus", "any-snapshot").unwrap_err();
+        assert_eq!(error.to_string(), "Unknown component: bogus");
+    }
+}
@@ -1,6 +1,7 @@
 use anyhow::{Result, Context};
 use tracing::{info, debug, warn};
 use std::path::{Path, PathBuf};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs::{self, File};
 use std::io::{Read, Write};
 use std::time::{SystemTime, UNIX_EPOCH};
@@ -8,6 +9,486 @@ use serde::{Serialize, Deserialize};
 
 use super::SnapshotInfo;
 use crate::core::constants;
+use crate::core::lock;
+use crate::core::plan::{Plan, PlannedAction};
+use crate::matrixbox::container::ContainerId;
+use crate::matrixbox::{kv, registry};
+use super::crypto::KeySource;
+
+/// Lock serializing snapshot creation
+const SNAPSHOT_LOCK: &str = "heal-snapshot";
+
+/// Disk space held back for emergency snapshots
+pub const RESERVED_HEADROOM_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Ballast file holding the reserved headroom
+const RESERVE_FILE: &str = "reserve";
+
+/// Compressed bundle written by emergency snapshots
+const EMERGENCY_BUNDLE: &str = "emergency.zst";
+
+/// Manifest of an incremental snapshot
+const DELTA_FILE: &str = "delta.json";
+
+/// Snapshot settings, in the heal directory
+const CONFIG_FILE: &str = "config.json";
+
+/// Suffix of files stored compressed in a snapshot
+pub const COMPRESSED_SUFFIX: &str = ".zst";
+
+/// Suffix of files stored encrypted in a snapshot, after any `.zst`
+pub const ENCRYPTED_SUFFIX: &str = ".enc";
+
+/// Per-container snapshots, under the snapshots directory
+const CONTAINER_SNAPSHOTS_DIR: &str = "containers";
+
+/// Copy of the container's data directory in a container snapshot
+const CONTAINER_DATA_DIR: &str = "data";
+
+/// Staging area `compact_chain` assembles full snapshots in
+const COMPACT_DIR: &str = "compact";
+
+/// Ring buffer slots, in the snapshots directory
+const RING_FILE: &str = "ring.json";
+
+/// Where snapshots evicted from the ring are moved before they are deleted
+const RING_EVICTED_DIR: &str = "ring-evicted";
+
+/// ZK operation proving a snapshot's content hash
+pub const SNAPSHOT_PROOF_OPERATION: &str = "snapshot_integrity";
+
+/// zstd level used for emergency bundles; slow, but the bundle is small
+const EMERGENCY_COMPRESSION_LEVEL: i32 = 19;
+
+/// Components captured by emergency snapshots: configs, registries and
+/// contract state, without container data or logs
+pub const EMERGENCY_COMPONENTS: &[&str] = &["core", "config", "zk", "containers", "packages", "auth"];
+
+/// How a snapshot was taken
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SnapshotMode {
+    /// All components, copied as-is
+    Normal,
+    
+    /// Minimal component set in a compressed bundle
+    Emergency,
+    
+    /// Only files that changed since the base snapshot; the others are
+    /// read from the base chain
+    Incremental {
+        /// Snapshot this one is based on
+        base_snapshot_id: String,
+    },
+}
+
+impl Default for SnapshotMode {
+    fn default() -> Self {
+        SnapshotMode::Normal
+    }
+}
+
+impl SnapshotMode {
+    /// Snapshot an incremental snapshot is based on
+    pub fn base(&self) -> Option<&str> {
+        match self {
+            SnapshotMode::Incremental { base_snapshot_id } => Some(base_snapshot_id),
+            _ => None,
+        }
+    }
+}
+
+/// Snapshot settings, loaded from `.heal/config.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SnapshotConfig {
+    /// Whether normal and incremental snapshots store files zstd-compressed
+    pub compression_enabled: bool,
+    
+    /// zstd level, from 1 (fastest) to 19 (smallest)
+    pub compression_level: u8,
+    
+    /// Whether snapshots store files encrypted with AES-256-GCM
+    pub encryption_enabled: bool,
+    
+    /// Where the encryption key comes from, for writing and reading
+    /// encrypted snapshots
+    pub key_source: KeySource,
+    
+    /// Seconds between scheduled snapshots; 0 disables them
+    pub auto_snapshot_interval_secs: u64,
+    
+    /// Whether heal metrics are served over HTTP for Prometheus
+    pub metrics_enabled: bool,
+    
+    /// Port the metrics are served on
+    pub metrics_port: u16,
+    
+    /// Keep snapshots in a fixed ring of slots instead of accumulating
+    /// them; incremental snapshots can't be taken in this mode
+    pub ring_buffer: Option<RingBufferPolicy>,
+}
+
+impl Default for SnapshotConfig {
+    fn default() -> Self {
+        Self {
+            compression_enabled: false,
+            compression_level: 3,
+            encryption_enabled: false,
+            key_source: KeySource::default(),
+            auto_snapshot_interval_secs: 0,
+            metrics_enabled: false,
+            metrics_port: super::metrics_server::DEFAULT_METRICS_PORT,
+            ring_buffer: None,
+        }
+    }
+}
+
+impl SnapshotConfig {
+    /// zstd level to store files at, if compression is enabled
+    pub fn compression(&self) -> Option<i32> {
+        if self.compression_enabled { Some(self.compression_level as i32) } else { None }
+    }
+    
+    /// How new snapshots store their files, resolving the key if encryption is enabled
+    pub fn storage(&self) -> Result<Storage> {
+        let key = if self.encryption_enabled {
+            Some(super::crypto::snapshot_key(&self.key_source)?)
+        } else {
+            None
+        };
+        Ok(Storage { compression: self.compression(), key })
+    }
+}
+
+/// How a snapshot's files are stored
+///
+/// Files are compressed first and then encrypted, gaining a `.zst` and then
+/// an `.enc` suffix. Files without the suffixes, such as manifests, are
+/// stored as-is.
+#[derive(Clone, Copy, Default)]
+pub struct Storage {
+    /// zstd level files are compressed at; when reading, only whether it is set matters
+    pub compression: Option<i32>,
+    
+    /// Key files are encrypted with
+    pub key: Option<[u8; 32]>,
+}
+
+impl Storage {
+    /// Storage of an existing snapshot, per its metadata
+    ///
+    /// Encrypted snapshots are read with the key from the configured source.
+    pub fn of(snapshot_dir: &Path) -> Result<Self> {
+        let metadata = read_metadata(snapshot_dir)?;
+        let key = if metadata.encrypted {
+            Some(super::crypto::snapshot_key(&load_config()?.key_source)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            compression: metadata.compressed.then_some(zstd::DEFAULT_COMPRESSION_LEVEL),
+            key,
+        })
+    }
+    
+    /// Name a stored file had before it was compressed or encrypted, or
+    /// `None` for files stored as-is
+    pub fn original_name<'a>(&self, stored: &'a str) -> Option<&'a str> {
+        let mut name = stored;
+        if self.key.is_some() {
+            name = name.strip_suffix(ENCRYPTED_SUFFIX)?;
+        }
+        if self.compression.is_some() {
+            name = name.strip_suffix(COMPRESSED_SUFFIX)?;
+        }
+        (name.len() != stored.len()).then_some(name)
+    }
+    
+    /// Path a file is stored at
+    pub fn stored_path(&self, path: &Path) -> PathBuf {
+        let mut name = path.as_os_str().to_os_string();
+        if self.compression.is_some() {
+            name.push(COMPRESSED_SUFFIX);
+        }
+        if self.key.is_some() {
+            name.push(ENCRYPTED_SUFFIX);
+        }
+        PathBuf::from(name)
+    }
+    
+    /// Original content of a stored file
+    pub fn read(&self, stored: &Path) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.open(stored)?.read_to_end(&mut data)
+            .with_context(|| format!("Failed to read {:?}", stored))?;
+        Ok(data)
+    }
+    
+    /// Write the original content of a stored file to `dst`
+    pub fn extract(&self, stored: &Path, dst: &Path) -> Result<()> {
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        
+        std::io::copy(&mut self.open(stored)?, &mut File::create(dst)?)
+            .with_context(|| format!("Failed to extract {:?}", stored))?;
+        Ok(())
+    }
+    
+    /// Reader over the original content of a stored file
+    fn open(&self, stored: &Path) -> Result<Box<dyn Read>> {
+        let mut file = File::open(stored)?;
+        if self.original_name(&stored.to_string_lossy()).is_none() {
+            return Ok(Box::new(file));
+        }
+        
+        let decrypted: Box<dyn Read> = match &self.key {
+            Some(key) => {
+                let mut sealed = Vec::new();
+                file.read_to_end(&mut sealed)?;
+                let plaintext = super::crypto::open(key, &sealed)
+                    .with_context(|| format!("Failed to decrypt {:?}", stored))?;
+                Box::new(std::io::Cursor::new(plaintext))
+            }
+            None => Box::new(file),
+        };
+        if self.compression.is_some() {
+            Ok(Box::new(zstd::stream::Decoder::new(decrypted)
+                .with_context(|| format!("Failed to decompress {:?}", stored))?))
+        } else {
+            Ok(decrypted)
+        }
+    }
+    
+    /// Write content into a snapshot as `dst`, under its stored name
+    fn store(&self, content: &mut dyn Read, dst: &Path) -> Result<()> {
+        let target = self.stored_path(dst);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        
+        let file = match (&self.key, self.compression) {
+            (Some(key), compression) => {
+                let mut data = Vec::new();
+                content.read_to_end(&mut data)?;
+                if let Some(level) = compression {
+                    data = zstd::encode_all(data.as_slice(), level)?;
+                }
+                let mut file = File::create(&target)?;
+                file.write_all(&super::crypto::seal(key, &data)?)?;
+                file
+            }
+            (None, Some(level)) => {
+                let mut encoder = zstd::stream::Encoder::new(File::create(&target)?, level)?;
+                std::io::copy(content, &mut encoder)?;
+                encoder.finish()?
+            }
+            (None, None) => {
+                let mut file = File::create(&target)?;
+                std::io::copy(content, &mut file)?;
+                file
+            }
+        };
+        file.sync_all()?;
+        Ok(())
+    }
+    
+    /// Whether files are stored the same way as in `other`
+    fn same_as(&self, other: &Storage) -> bool {
+        self.compression.is_some() == other.compression.is_some() && self.key == other.key
+    }
+}
+
+/// How `create_snapshot` takes a snapshot
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotOptions {
+    /// Normal or incremental; emergency snapshots are taken with
+    /// `create_emergency_snapshot`
+    pub mode: SnapshotMode,
+}
+
+impl SnapshotOptions {
+    /// Options for an incremental snapshot on top of `base_snapshot_id`
+    pub fn incremental(base_snapshot_id: &str) -> Self {
+        Self {
+            mode: SnapshotMode::Incremental { base_snapshot_id: base_snapshot_id.to_string() },
+        }
+    }
+}
+
+/// Files of an incremental snapshot, stored as `delta.json`
+#[derive(Debug, Serialize, Deserialize)]
+struct DeltaManifest {
+    /// Snapshot the unchanged files are read from
+    base_snapshot_id: String,
+    
+    /// Files left out because the base has them unchanged, by path in the snapshot
+    unchanged: BTreeSet<String>,
+    
+    /// BLAKE3 hash (hex) of every file of the full tree, by path in the snapshot
+    files: BTreeMap<String, String>,
+}
+
+/// A snapshot in a chain of incremental snapshots
+struct ChainLink {
+    /// Snapshot directory
+    dir: PathBuf,
+    
+    /// How its files are stored
+    storage: Storage,
+    
+    /// Manifest, for incremental snapshots
+    delta: Option<DeltaManifest>,
+}
+
+/// Result of an emergency snapshot
+#[derive(Debug, Clone)]
+pub struct EmergencyOutcome {
+    /// Size of the compressed bundle in bytes
+    pub bytes: u64,
+    
+    /// Snapshots deleted to make room, oldest first
+    pub sacrificed: Vec<String>,
+}
+
+/// A file inside an emergency bundle
+#[derive(Debug, Serialize, Deserialize)]
+struct BundleEntry {
+    /// Path relative to the snapshot directory, e.g. `zk/contracts/x.zky`
+    path: String,
+    
+    /// File contents
+    data: Vec<u8>,
+}
+
+/// Which snapshots `prune` keeps, stored under `snapshot_retention` in system.json
+///
+/// The snapshot `.panic/fallback.zk` points at is always kept.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Keep at most this many snapshots, newest first
+    pub max_count: Option<usize>,
+    
+    /// Delete snapshots taken more than this many days ago
+    pub max_age_days: Option<u64>,
+    
+    /// Always keep at least this many of the newest snapshots, however old
+    pub min_keep: usize,
+}
+
+/// Retention policy taken by `prune_snapshots`
+pub type SnapshotRetentionPolicy = RetentionPolicy;
+
+impl RetentionPolicy {
+    /// Whether the policy limits anything
+    pub fn is_limited(&self) -> bool {
+        self.max_count.is_some() || self.max_age_days.is_some()
+    }
+}
+
+/// Rolling snapshot mode, set under `ring_buffer` in `.heal/config.json`
+///
+/// Snapshots go into slots 0..capacity in turn, each new one replacing the
+/// oldest; the slots are recorded in `.heal/snapshots/ring.json`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RingBufferPolicy {
+    /// Number of slots
+    pub capacity: usize,
+}
+
+/// Ring buffer slots, stored as `ring.json`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct RingState {
+    /// Slot holding the newest snapshot
+    head: Option<usize>,
+    
+    /// Snapshot ID in each slot
+    slots: Vec<Option<String>>,
+}
+
+/// A slot of the snapshot ring
+#[derive(Debug, Clone)]
+pub struct RingSlot {
+    /// Slot number
+    pub index: usize,
+    
+    /// Snapshot in the slot, if any
+    pub snapshot: Option<SnapshotInfo>,
+    
+    /// Whether the slot holds the newest snapshot
+    pub is_head: bool,
+}
+
+/// A snapshot removed by `prune`
+#[derive(Debug, Clone, Serialize)]
+pub struct PrunedSnapshot {
+    /// Snapshot ID
+    pub id: String,
+    
+    /// When it was taken
+    pub timestamp: u64,
+    
+    /// Why it was taken
+    pub reason: String,
+    
+    /// Bytes freed
+    pub bytes: u64,
+}
+
+/// Outcome of `prune`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PruneReport {
+    /// Snapshots removed, newest first
+    pub removed: Vec<PrunedSnapshot>,
+    
+    /// Snapshots left in place
+    pub kept: usize,
+    
+    /// Snapshot kept because panic recovery falls back to it
+    pub protected: Option<String>,
+}
+
+/// A snapshot of one container, from `snapshot_container`
+///
+/// Stored as `metadata.json` in `.heal/snapshots/containers/<id>/<snapshot>/`,
+/// next to a copy of the container's `.matrixbox/data/<id>` directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerSnapshot {
+    /// Snapshot ID
+    pub id: String,
+    
+    /// Container the snapshot is of
+    pub container_id: String,
+    
+    /// When it was taken (seconds since epoch)
+    pub timestamp: u64,
+    
+    /// The container's registry entry
+    pub entry: ContainerEntry,
+    
+    /// Hash of the captured data
+    pub content_hash: String,
+    
+    /// Snapshot directory
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+/// A container's registry entry, as captured in a container snapshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerEntry {
+    /// Container name
+    pub name: String,
+    
+    /// Image directory the container runs from
+    pub image: PathBuf,
+    
+    /// Arguments the container was run with
+    #[serde(default)]
+    pub args: Vec<String>,
+}
 
 /// Snapshot metadata
 #[derive(Debug, Serialize, Deserialize)]
@@ -26,19 +507,66 @@ struct SnapshotMetadata {
     
     /// Hash of the snapshot contents
     content_hash: String,
+    
+    /// How the snapshot was taken
+    #[serde(default)]
+    mode: SnapshotMode,
+    
+    /// Digest of each container's kv store at snapshot time
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    kv_digests: BTreeMap<String, String>,
+    
+    /// Whether captured files are stored zstd-compressed with a `.zst` suffix
+    #[serde(default)]
+    compressed: bool,
+    
+    /// Whether captured files, or the emergency bundle, are stored
+    /// encrypted; files get an `.enc` suffix
+    #[serde(default)]
+    encrypted: bool,
+    
+    /// Groth16 proof (hex) of the content hash, when ZK is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    proof: Option<String>,
+    
+    /// BLAKE3 hash (hex) of each captured file, by original relative path,
+    /// to name the files that changed when the content hash no longer matches
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    file_hashes: BTreeMap<String, String>,
+}
+
+/// A snapshot's recorded content hash next to the hash of its files now
+#[derive(Debug, Clone)]
+pub struct SnapshotHashes {
+    /// Content hash recorded in `metadata.json`
+    pub stored: String,
+    
+    /// Content hash of the snapshot directory as it is now
+    pub computed: String,
+    
+    /// Proof of the recorded hash, if one was generated
+    pub proof: Option<Vec<u8>>,
+    
+    /// Files added, removed or changed since the snapshot was taken; empty
+    /// when the snapshot predates per-file hashes
+    pub mismatched_files: Vec<String>,
 }
 
 /// Initialize the snapshot system
 pub fn init() -> Result<()> {
     info!("Initializing snapshot system");
     
-    let snapshot_dir = PathBuf::from(constants::ROOT_DIR)
+    let snapshot_dir = constants::root_dir()
         .join(".heal")
         .join("snapshots");
     
     fs::create_dir_all(&snapshot_dir)
         .context("Failed to create snapshot directory")?;
     
+    if let Err(e) = maintain_headroom() {
+        warn!("Failed to reserve emergency snapshot headroom: {}", e);
+    }
+    
     info!("Snapshot system initialized");
     Ok(())
 }
@@ -54,11 +582,33 @@ pub fn shutdown() -> Result<()> {
 }
 
 /// Create a new system snapshot
-pub fn create_snapshot(id: &str, reason: &str) -> Result<()> {
+///
+/// In incremental mode, files whose BLAKE3 hash matches the base snapshot's
+/// are dropped from the copy and listed in `delta.json` instead, so only
+/// changed files take up space. With compression or encryption enabled in
+/// `.heal/config.json`, files are stored zstd-compressed and/or
+/// AES-256-GCM encrypted.
+pub fn create_snapshot(id: &str, reason: &str, options: &SnapshotOptions) -> Result<()> {
     info!("Creating snapshot: {} - {}", id, reason);
     
+    let _lock = lock::lock(SNAPSHOT_LOCK, &format!("snapshot {}", id), lock::DEFAULT_TIMEOUT)?;
+    let config = load_config()?;
+    let storage = config.storage()?;
+    
+    // Hashes of the base's full tree, to compare the new copy against
+    let base = match &options.mode {
+        SnapshotMode::Normal => None,
+        SnapshotMode::Emergency => anyhow::bail!("Emergency snapshots are taken with create_emergency_snapshot"),
+        SnapshotMode::Incremental { .. } if config.ring_buffer.is_some() => {
+            anyhow::bail!("Incremental snapshots can't be taken while the snapshot ring buffer is enabled")
+        }
+        SnapshotMode::Incremental { base_snapshot_id } => {
+            Some((base_snapshot_id.as_str(), tree_hashes(base_snapshot_id)?))
+        }
+    };
+    
     // Create snapshot directory
-    let snapshot_dir = PathBuf::from(constants::ROOT_DIR)
+    let snapshot_dir = constants::root_dir()
         .join(".heal")
         .join("snapshots")
         .join(id);
@@ -80,16 +630,36 @@ pub fn create_snapshot(id: &str, reason: &str) -> Result<()> {
         "runtime",
         "auth",
         "linux",
+        "config",
+        "packages",
+        "kv",
     ];
     
-    // Take snapshots of each component
+    // Fold kv write-ahead logs into compact data logs before copying them
+    let kv_digests = crate::matrixbox::kv::checkpoint_all().unwrap_or_else(|e| {
+        warn!("Failed to checkpoint kv stores: {}", e);
+        BTreeMap::new()
+    });
+    
+    // Take snapshots of each component, removing the partial snapshot on
+    // failure so it doesn't eat into space an emergency snapshot may need
     for component in &components {
-        snapshot_component(component, &snapshot_dir)
-            .with_context(|| format!("Failed to snapshot component: {}", component))?;
+        if let Err(e) = snapshot_component(component, &snapshot_dir, &storage) {
+            let _ = fs::remove_dir_all(&snapshot_dir);
+            return Err(e).with_context(|| format!("Failed to snapshot component: {}", component));
+        }
+    }
+    
+    if let Some((base_id, base_files)) = &base {
+        if let Err(e) = write_delta(&snapshot_dir, base_id, base_files, &storage) {
+            let _ = fs::remove_dir_all(&snapshot_dir);
+            return Err(e).context("Failed to write incremental snapshot manifest");
+        }
     }
     
     // Calculate content hash
-    let content_hash = calculate_snapshot_hash(&snapshot_dir)?;
+    let content_hash = calculate_snapshot_hash(&snapshot_dir, &storage)?;
+    let file_hashes = calculate_file_hashes(&snapshot_dir, &storage)?;
     
     // Create metadata
     let metadata = SnapshotMetadata {
@@ -98,6 +668,12 @@ pub fn create_snapshot(id: &str, reason: &str) -> Result<()> {
         reason: reason.to_string(),
         components: components.iter().map(|s| s.to_string()).collect(),
         content_hash: content_hash.clone(),
+        mode: options.mode.clone(),
+        kv_digests,
+        compressed: storage.compression.is_some(),
+        encrypted: storage.key.is_some(),
+        proof: prove_content_hash(&content_hash),
+        file_hashes,
     };
     
     // Save metadata
@@ -108,12 +684,256 @@ pub fn create_snapshot(id: &str, reason: &str) -> Result<()> {
     fs::write(&metadata_path, metadata_json)
         .context("Failed to write snapshot metadata")?;
     
+    if let Some(policy) = &config.ring_buffer {
+        ring_insert(policy, id).context("Failed to add the snapshot to the ring buffer")?;
+    }
+    
+    // Top the headroom back up if an emergency snapshot consumed it
+    if let Err(e) = maintain_headroom() {
+        debug!("Emergency headroom not restored: {}", e);
+    }
+    
     info!("Snapshot created successfully: {}", id);
     Ok(())
 }
 
+/// Create an emergency snapshot of the minimal component set
+///
+/// The bundle is compressed in memory and must fit in the reserved
+/// headroom, which is released to write it. If it doesn't fit, the trash is
+/// purged and then the oldest snapshots other than `protected` are deleted
+/// until it does.
+pub fn create_emergency_snapshot(id: &str, reason: &str, protected: Option<&str>) -> Result<EmergencyOutcome> {
+    warn!("Creating emergency snapshot: {} - {}", id, reason);
+    
+    let _lock = lock::lock(SNAPSHOT_LOCK, &format!("emergency snapshot {}", id), lock::DEFAULT_TIMEOUT)?;
+    
+    // Build and compress the bundle before touching the disk
+    let mut entries = Vec::new();
+    for component in EMERGENCY_COMPONENTS {
+        collect_emergency_entries(component, &mut entries)?;
+    }
+    
+    let mut bundle = zstd::encode_all(&bincode::serialize(&entries)?[..], EMERGENCY_COMPRESSION_LEVEL)
+        .context("Failed to compress emergency snapshot")?;
+    let key = load_config()?.storage()?.key;
+    if let Some(key) = &key {
+        bundle = super::crypto::seal(key, &bundle)?;
+    }
+    let needed = bundle.len() as u64;
+    
+    let reserve_path = heal_dir().join(RESERVE_FILE);
+    let mut available = fs::metadata(&reserve_path).map(|m| m.len()).unwrap_or(0);
+    let mut sacrificed = Vec::new();
+    
+    if needed > available {
+        warn!("Emergency snapshot needs {} bytes but only {} are reserved", needed, available);
+        available += crate::trash::free_space(needed - available).unwrap_or(0);
+    }
+    
+    if needed > available {
+        // Oldest first, never the fallback snapshot or a base of incremental ones
+        let snapshots = list_snapshots()?;
+        let mut candidates = snapshots.clone();
+        candidates.reverse();
+        
+        for candidate in candidates {
+            if available >= needed {
+                break;
+            }
+            if Some(candidate.id.as_str()) == protected || is_base(&snapshots, &candidate.id) {
+                continue;
+            }
+            
+            let bytes = crate::core::plan::path_size(&candidate.path);
+            delete_snapshot(&candidate.id)?;
+            warn!("Sacrificed snapshot {} ({} bytes, taken {}, reason: {}) for emergency snapshot {}",
+                  candidate.id, bytes, candidate.timestamp, candidate.reason, id);
+            
+            available += bytes;
+            sacrificed.push(candidate.id);
+        }
+    }
+    
+    if needed > available {
+        anyhow::bail!("Emergency snapshot does not fit: needs {} bytes, {} available", needed, available);
+    }
+    
+    // Release the headroom, then write into the space it held
+    if reserve_path.exists() {
+        fs::remove_file(&reserve_path).context("Failed to release reserved headroom")?;
+    }
+    
+    let snapshot_dir = snapshots_dir().join(id);
+    fs::create_dir_all(&snapshot_dir)
+        .with_context(|| format!("Failed to create snapshot directory: {}", id))?;
+    fs::write(snapshot_dir.join(EMERGENCY_BUNDLE), &bundle)
+        .context("Failed to write emergency snapshot bundle")?;
+    
+    let metadata = SnapshotMetadata {
+        id: id.to_string(),
+        timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        reason: reason.to_string(),
+        components: EMERGENCY_COMPONENTS.iter().map(|s| s.to_string()).collect(),
+        content_hash: blake3::hash(&bundle).to_hex().to_string(),
+        mode: SnapshotMode::Emergency,
+        kv_digests: BTreeMap::new(),
+        compressed: false,
+        encrypted: key.is_some(),
+        // No proof: generating one would hold up recovery
+        proof: None,
+        file_hashes: BTreeMap::new(),
+    };
+    fs::write(snapshot_dir.join("metadata.json"), serde_json::to_string_pretty(&metadata)?)
+        .context("Failed to write snapshot metadata")?;
+    
+    warn!("Emergency snapshot created: {} ({} bytes, {} snapshot(s) sacrificed)", id, needed, sacrificed.len());
+    Ok(EmergencyOutcome { bytes: needed, sacrificed })
+}
+
+/// Unpack an emergency snapshot's bundle into component directories
+///
+/// Does nothing for normal snapshots or bundles already unpacked. Files of
+/// encrypted bundles are unpacked encrypted.
+pub fn unpack_emergency(snapshot_dir: &Path) -> Result<()> {
+    let bundle_path = snapshot_dir.join(EMERGENCY_BUNDLE);
+    if !bundle_path.exists() || snapshot_dir.join(EMERGENCY_COMPONENTS[0]).exists() {
+        return Ok(());
+    }
+    
+    info!("Unpacking emergency snapshot: {:?}", snapshot_dir);
+    
+    let storage = Storage::of(snapshot_dir)?;
+    for entry in load_bundle(&bundle_path, &storage)? {
+        storage.store(&mut entry.data.as_slice(), &snapshot_dir.join(&entry.path))?;
+    }
+    
+    Ok(())
+}
+
+/// Read a single file from an emergency snapshot's bundle without unpacking it
+///
+/// Returns `None` if the snapshot has no bundle or the bundle lacks the file.
+pub fn read_bundle_entry(snapshot_dir: &Path, relative_path: &str) -> Result<Option<Vec<u8>>> {
+    let bundle_path = snapshot_dir.join(EMERGENCY_BUNDLE);
+    if !bundle_path.exists() {
+        return Ok(None);
+    }
+    
+    let entries = load_bundle(&bundle_path, &Storage::of(snapshot_dir)?)?;
+    Ok(entries.into_iter().find(|entry| entry.path == relative_path).map(|entry| entry.data))
+}
+
+/// Decrypt and decompress an emergency snapshot's bundle
+fn load_bundle(bundle_path: &Path, storage: &Storage) -> Result<Vec<BundleEntry>> {
+    let mut bundle = fs::read(bundle_path)?;
+    if let Some(key) = &storage.key {
+        bundle = super::crypto::open(key, &bundle)
+            .context("Failed to decrypt emergency snapshot")?;
+    }
+    let bundle = zstd::decode_all(bundle.as_slice())
+        .context("Failed to decompress emergency snapshot")?;
+    Ok(bincode::deserialize(&bundle)?)
+}
+
+/// Recreate the headroom reserve file if it is missing or short
+pub fn maintain_headroom() -> Result<()> {
+    let reserve_path = heal_dir().join(RESERVE_FILE);
+    if fs::metadata(&reserve_path).map(|m| m.len()).unwrap_or(0) >= RESERVED_HEADROOM_BYTES {
+        return Ok(());
+    }
+    
+    // Write real zeroes so the space is actually allocated
+    let mut file = File::create(&reserve_path)?;
+    let chunk = vec![0u8; 1024 * 1024];
+    let mut written = 0;
+    while written < RESERVED_HEADROOM_BYTES {
+        let len = chunk.len().min((RESERVED_HEADROOM_BYTES - written) as usize);
+        if let Err(e) = file.write_all(&chunk[..len]) {
+            drop(file);
+            let _ = fs::remove_file(&reserve_path);
+            return Err(e).context("Not enough space to reserve emergency headroom");
+        }
+        written += len as u64;
+    }
+    file.sync_all()?;
+    
+    debug!("Reserved {} bytes of emergency snapshot headroom", RESERVED_HEADROOM_BYTES);
+    Ok(())
+}
+
+/// Whether an error was caused by the disk or quota being full
+pub fn is_out_of_space(error: &anyhow::Error) -> bool {
+    const ENOSPC: i32 = 28;
+    const EDQUOT: i32 = 122;
+    
+    error.chain().any(|cause| {
+        cause.downcast_ref::<std::io::Error>()
+            .and_then(|e| e.raw_os_error())
+            .map_or(false, |code| code == ENOSPC || code == EDQUOT)
+    })
+}
+
+/// Collect the files an emergency snapshot keeps for a component
+fn collect_emergency_entries(component: &str, entries: &mut Vec<BundleEntry>) -> Result<()> {
+    let root = constants::root_dir();
+    
+    let sources: Vec<(PathBuf, &str)> = match component {
+        "core" => vec![
+            (root.join(constants::CORE_DIR).join("config.yaml"), "core/config.yaml"),
+            (root.join(constants::CORE_DIR).join("state.json"), "core/state.json"),
+        ],
+        "zk" => vec![
+            (root.join(constants::ZK_DIR).join("contracts"), "zk/contracts"),
+            (root.join(constants::ZK_DIR).join("keys"), "zk/keys"),
+            (root.join(constants::ZK_DIR).join("runtime"), "zk/runtime"),
+        ],
+        "config" => vec![
+            (root.join(".config").join("system.json"), "config/system.json"),
+            (root.join(".config").join("security.json"), "config/security.json"),
+        ],
+        "containers" => vec![
+            (root.join(constants::CONTAINER_DIR).join("registry").join("registry.json"), "containers/registry.json"),
+        ],
+        "packages" => vec![
+            (root.join(".package").join("registry.json"), "packages/registry.json"),
+        ],
+        "auth" => vec![
+            (root.join(constants::AUTH_DIR).join("config.yaml"), "auth/config.yaml"),
+            (root.join(constants::AUTH_DIR).join("keys"), "auth/keys"),
+        ],
+        _ => anyhow::bail!("Unknown emergency component: {}", component),
+    };
+    
+    for (source, relative) in sources {
+        collect_path(&source, relative, component == "auth", entries)?;
+    }
+    
+    Ok(())
+}
+
+/// Add a file or directory tree to a bundle
+fn collect_path(source: &Path, relative: &str, public_only: bool, entries: &mut Vec<BundleEntry>) -> Result<()> {
+    if source.is_dir() {
+        for entry in fs::read_dir(source)?.filter_map(Result::ok) {
+            let name = entry.file_name().to_string_lossy().to_string();
+            collect_path(&entry.path(), &format!("{}/{}", relative, name), public_only, entries)?;
+        }
+    } else if source.is_file() {
+        // Same rule as normal snapshots: never capture private keys
+        let name = source.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        if public_only && relative.contains("/keys/") && !(name.contains("public") || name.ends_with(".pub")) {
+            return Ok(());
+        }
+        
+        entries.push(BundleEntry { path: relative.to_string(), data: fs::read(source)? });
+    }
+    
+    Ok(())
+}
+
 /// Take a snapshot of a specific component
-fn snapshot_component(component: &str, snapshot_dir: &Path) -> Result<()> {
+fn snapshot_component(component: &str, snapshot_dir: &Path, storage: &Storage) -> Result<()> {
     debug!("Snapshotting component: {}", component);
     
     let component_dir = snapshot_dir.join(component);
@@ -122,12 +942,15 @@ fn snapshot_component(component: &str, snapshot_dir: &Path) -> Result<()> {
     
     // Determine the source path based on the component
     let source_path = match component {
-        "core" => PathBuf::from(constants::ROOT_DIR).join(constants::CORE_DIR),
-        "zk" => PathBuf::from(constants::ROOT_DIR).join(constants::ZK_DIR),
-        "containers" => PathBuf::from(constants::ROOT_DIR).join(constants::CONTAINER_DIR),
-        "runtime" => PathBuf::from(constants::ROOT_DIR).join(constants::RUNTIME_DIR),
-        "auth" => PathBuf::from(constants::ROOT_DIR).join(constants::AUTH_DIR),
-        "linux" => PathBuf::from(constants::ROOT_DIR).join(".linux"),
+        "core" => constants::root_dir().join(constants::CORE_DIR),
+        "zk" => constants::root_dir().join(constants::ZK_DIR),
+        "containers" => constants::root_dir().join(constants::CONTAINER_DIR),
+        "runtime" => constants::root_dir().join(constants::RUNTIME_DIR),
+        "auth" => constants::root_dir().join(constants::AUTH_DIR),
+        "linux" => constants::root_dir().join(".linux"),
+        "config" => constants::root_dir().join(".config"),
+        "packages" => constants::root_dir().join(".package"),
+        "kv" => constants::root_dir().join(".matrixbox").join("data"),
         _ => anyhow::bail!("Unknown component: {}", component),
     };
     
@@ -146,46 +969,62 @@ fn snapshot_component(component: &str, snapshot_dir: &Path) -> Result<()> {
             // Core configuration
             let config_path = source_path.join("config.yaml");
             if config_path.exists() {
-                copy_file(&config_path, &component_dir.join("config.yaml"))?;
+                store_file(&config_path, &component_dir.join("config.yaml"), storage)?;
             }
             
             // Core state
             let state_path = source_path.join("state.json");
             if state_path.exists() {
-                copy_file(&state_path, &component_dir.join("state.json"))?;
+                store_file(&state_path, &component_dir.join("state.json"), storage)?;
             }
         },
         "zk" => {
             // ZK contracts
             let contracts_path = source_path.join("contracts");
             if contracts_path.exists() {
-                copy_directory(&contracts_path, &component_dir.join("contracts"))?;
+                store_directory(&contracts_path, &component_dir.join("contracts"), storage)?;
             }
             
             // ZK verification keys
             let keys_path = source_path.join("keys");
             if keys_path.exists() {
-                copy_directory(&keys_path, &component_dir.join("keys"))?;
+                store_directory(&keys_path, &component_dir.join("keys"), storage)?;
+            }
+            
+            // Contract state, as stored; encrypted state stays encrypted and
+            // the master key is never captured
+            let runtime_path = source_path.join("runtime");
+            if runtime_path.exists() {
+                store_directory(&runtime_path, &component_dir.join("runtime"), storage)?;
             }
         },
         "containers" => {
             // Container registry
             let registry_path = source_path.join("registry");
             if registry_path.exists() {
-                copy_directory(&registry_path, &component_dir.join("registry"))?;
+                store_directory(&registry_path, &component_dir.join("registry"), storage)?;
             }
             
             // Active container state (but not the actual containers)
             let registry_file = registry_path.join("registry.json");
             if registry_file.exists() {
-                copy_file(&registry_file, &component_dir.join("registry.json"))?;
+                store_file(&registry_file, &component_dir.join("registry.json"), storage)?;
+            }
+            
+            // Memory and globals of running containers, restorable with
+            // `matrixbox::runtime::restore_container`
+            let checkpoint_dir = component_dir.join("checkpoints");
+            let checkpointed = crate::matrixbox::runtime::checkpoint_all(&checkpoint_dir);
+            if !checkpointed.is_empty() {
+                debug!("Checkpointed {} running container(s)", checkpointed.len());
+                store_in_place(&checkpoint_dir, storage)?;
             }
         },
         "runtime" => {
             // Runtime state
             let state_path = source_path.join("state.json");
             if state_path.exists() {
-                copy_file(&state_path, &component_dir.join("state.json"))?;
+                store_file(&state_path, &component_dir.join("state.json"), storage)?;
             }
             
             // Runtime logs (last 10 only)
@@ -213,7 +1052,7 @@ fn snapshot_component(component: &str, snapshot_dir: &Path) -> Result<()> {
                 
                 for (i, log) in sorted_logs.iter().take(10).enumerate() {
                     let dest = logs_dest.join(format!("log_{}.log", i));
-                    copy_file(&log.path(), &dest)?;
+                    store_file(&log.path(), &dest, storage)?;
                 }
             }
         },
@@ -221,7 +1060,7 @@ fn snapshot_component(component: &str, snapshot_dir: &Path) -> Result<()> {
             // Auth configuration
             let config_path = source_path.join("config.yaml");
             if config_path.exists() {
-                copy_file(&config_path, &component_dir.join("config.yaml"))?;
+                store_file(&config_path, &component_dir.join("config.yaml"), storage)?;
             }
             
             // Auth keys (excluding private keys)
@@ -238,7 +1077,7 @@ fn snapshot_component(component: &str, snapshot_dir: &Path) -> Result<()> {
                         
                         // Only copy public keys or non-sensitive data
                         if name_str.contains("public") || name_str.ends_with(".pub") {
-                            copy_file(&entry.path(), &keys_dest.join(file_name))?;
+                            store_file(&entry.path(), &keys_dest.join(file_name), storage)?;
                         }
                     }
                 }
@@ -248,22 +1087,62 @@ fn snapshot_component(component: &str, snapshot_dir: &Path) -> Result<()> {
             // Linux compatibility layer configuration
             let etc_path = source_path.join("etc");
             if etc_path.exists() {
-                copy_directory(&etc_path, &component_dir.join("etc"))?;
+                store_directory(&etc_path, &component_dir.join("etc"), storage)?;
             }
         },
-        _ => {}
-    }
-    
-    debug!("Component snapshot complete: {}", component);
+        "config" => {
+            // System configuration and security policy
+            for file in ["system.json", "security.json"] {
+                let path = source_path.join(file);
+                if path.exists() {
+                    store_file(&path, &component_dir.join(file), storage)?;
+                }
+            }
+        },
+        "packages" => {
+            // Package registry, for inspecting what was installed
+            let registry_path = source_path.join("registry.json");
+            if registry_path.exists() {
+                store_file(&registry_path, &component_dir.join("registry.json"), storage)?;
+            }
+        },
+        "kv" => {
+            // Container kv stores, without the rest of their data
+            for entry in fs::read_dir(&source_path)?.filter_map(Result::ok) {
+                let kv_path = entry.path().join("kv");
+                if kv_path.is_dir() {
+                    store_directory(&kv_path, &component_dir.join(entry.file_name()).join("kv"), storage)?;
+                }
+            }
+        },
+        _ => {}
+    }
+    
+    debug!("Component snapshot complete: {}", component);
     Ok(())
 }
 
+/// Copy a directory tree into a snapshot location and hash the copy
+///
+/// This is the per-component work of a normal snapshot, usable on trees
+/// outside the root, e.g. by the benchmark suite.
+pub fn snapshot_tree(src: &Path, dst: &Path) -> Result<String> {
+    copy_directory(src, dst)?;
+    calculate_snapshot_hash(dst, &Storage::default())
+}
+
 /// Calculate a hash of the snapshot contents
-fn calculate_snapshot_hash(snapshot_dir: &Path) -> Result<String> {
+///
+/// Compressed and encrypted files are hashed by their original content
+/// under their original name, so the hash doesn't depend on the storage
+/// settings. Paths are hashed relative to the snapshot and in name order,
+/// so the hash can be recomputed after the snapshot was moved; the
+/// top-level `metadata.json`, which records the hash, is left out.
+fn calculate_snapshot_hash(snapshot_dir: &Path, storage: &Storage) -> Result<String> {
     let mut hasher = blake3::Hasher::new();
     
     // Hash all files in the snapshot directory recursively
-    hash_directory_recursive(snapshot_dir, &mut hasher)?;
+    hash_directory_recursive(snapshot_dir, snapshot_dir, storage, &mut hasher)?;
     
     // Finalize hash
     let hash = hasher.finalize();
@@ -271,40 +1150,85 @@ fn calculate_snapshot_hash(snapshot_dir: &Path) -> Result<String> {
 }
 
 /// Hash a directory recursively
-fn hash_directory_recursive(dir: &Path, hasher: &mut blake3::Hasher) -> Result<()> {
+fn hash_directory_recursive(base: &Path, dir: &Path, storage: &Storage, hasher: &mut blake3::Hasher) -> Result<()> {
     if !dir.exists() {
         return Ok(());
     }
     
-    for entry in fs::read_dir(dir)? {
-        let entry = entry?;
-        let path = entry.path();
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    paths.sort();
+    
+    for path in paths {
+        let relative = path.strip_prefix(base)?.to_string_lossy().replace('\\', "/");
+        if relative == "metadata.json" {
+            continue;
+        }
         
-        // Hash the path itself
-        hasher.update(path.to_string_lossy().as_bytes());
+        // Hash the path itself, under its original name
+        let original = if path.is_file() { storage.original_name(&relative) } else { None };
+        hasher.update(original.unwrap_or(&relative).as_bytes());
         
         if path.is_dir() {
             // Recursively hash subdirectories
-            hash_directory_recursive(&path, hasher)?;
+            hash_directory_recursive(base, &path, storage, hasher)?;
         } else if path.is_file() {
             // Hash file contents
-            let mut file = File::open(&path)?;
-            let mut buffer = [0; 8192];
-            
-            loop {
-                let bytes_read = file.read(&mut buffer)?;
-                if bytes_read == 0 {
-                    break;
-                }
-                
-                hasher.update(&buffer[..bytes_read]);
-            }
+            std::io::copy(&mut storage.open(&path)?, hasher)?;
         }
     }
     
     Ok(())
 }
 
+/// Hash each file of a snapshot directory by its original relative path
+///
+/// Like the content hash, this reads files decompressed and decrypted and
+/// leaves out the top-level `metadata.json`.
+fn calculate_file_hashes(snapshot_dir: &Path, storage: &Storage) -> Result<BTreeMap<String, String>> {
+    fn collect(base: &Path, dir: &Path, storage: &Storage, hashes: &mut BTreeMap<String, String>) -> Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let relative = path.strip_prefix(base)?.to_string_lossy().replace('\\', "/");
+            if path.is_dir() {
+                collect(base, &path, storage, hashes)?;
+            } else if path.is_file() && relative != "metadata.json" {
+                let mut hasher = blake3::Hasher::new();
+                std::io::copy(&mut storage.open(&path)?, &mut hasher)?;
+                let name = storage.original_name(&relative).unwrap_or(&relative).to_string();
+                hashes.insert(name, hasher.finalize().to_hex().to_string());
+            }
+        }
+        Ok(())
+    }
+    
+    let mut hashes = BTreeMap::new();
+    if snapshot_dir.exists() {
+        collect(snapshot_dir, snapshot_dir, storage, &mut hashes)?;
+    }
+    Ok(hashes)
+}
+
+/// Paths whose hash differs between two per-file hash maps, in path order
+fn mismatched_files(stored: &BTreeMap<String, String>, computed: &BTreeMap<String, String>) -> Vec<String> {
+    let mut paths: Vec<String> = stored.iter()
+        .filter(|(path, hash)| computed.get(*path) != Some(*hash))
+        .map(|(path, _)| path.clone())
+        .collect();
+    paths.extend(computed.keys().filter(|path| !stored.contains_key(*path)).cloned());
+    paths.sort();
+    paths
+}
+
+fn heal_dir() -> PathBuf {
+    constants::root_dir().join(".heal")
+}
+
+fn snapshots_dir() -> PathBuf {
+    heal_dir().join("snapshots")
+}
+
 /// Copy a file
 fn copy_file(src: &Path, dst: &Path) -> Result<()> {
     debug!("Copying file: {:?} -> {:?}", src, dst);
@@ -318,6 +1242,49 @@ fn copy_file(src: &Path, dst: &Path) -> Result<()> {
     Ok(())
 }
 
+/// Copy a file into a snapshot, compressing and encrypting it as `storage` says
+fn store_file(src: &Path, dst: &Path, storage: &Storage) -> Result<()> {
+    if storage.same_as(&Storage::default()) {
+        return copy_file(src, dst);
+    }
+    
+    debug!("Storing file: {:?} -> {:?}", src, storage.stored_path(dst));
+    storage.store(&mut File::open(src)?, dst)
+}
+
+/// Copy a directory into a snapshot, storing its files as `storage` says
+fn store_directory(src: &Path, dst: &Path, storage: &Storage) -> Result<()> {
+    fs::create_dir_all(dst)?;
+    for entry in fs::read_dir(src)? {
+        let path = entry?.path();
+        let dest_path = dst.join(path.file_name().unwrap_or_default());
+        if path.is_dir() {
+            store_directory(&path, &dest_path, storage)?;
+        } else {
+            store_file(&path, &dest_path, storage)?;
+        }
+    }
+    Ok(())
+}
+
+/// Store the plain files under a snapshot directory in place, as `storage` says
+fn store_in_place(dir: &Path, storage: &Storage) -> Result<()> {
+    if storage.same_as(&Storage::default()) {
+        return Ok(());
+    }
+    
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            store_in_place(&path, storage)?;
+        } else {
+            storage.store(&mut File::open(&path)?, &path)?;
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
 /// Copy a directory recursively
 fn copy_directory(src: &Path, dst: &Path) -> Result<()> {
     debug!("Copying directory: {:?} -> {:?}", src, dst);
@@ -345,7 +1312,7 @@ fn copy_directory(src: &Path, dst: &Path) -> Result<()> {
 
 /// List all available snapshots
 pub fn list_snapshots() -> Result<Vec<SnapshotInfo>> {
-    let snapshot_base = PathBuf::from(constants::ROOT_DIR)
+    let snapshot_base = constants::root_dir()
         .join(".heal")
         .join("snapshots");
     
@@ -367,6 +1334,7 @@ pub fn list_snapshots() -> Result<Vec<SnapshotInfo>> {
                 let metadata: SnapshotMetadata = serde_json::from_str(&metadata_json)?;
                 
                 snapshots.push(SnapshotInfo {
+                    base: metadata.mode.base().map(String::from),
                     id: metadata.id,
                     timestamp: metadata.timestamp,
                     reason: metadata.reason,
@@ -397,10 +1365,13 @@ pub fn get_snapshot(id: &str) -> Result<Option<SnapshotInfo>> {
 }
 
 /// Delete a snapshot
+///
+/// A snapshot incremental snapshots are based on can't be deleted until
+/// they are compacted with `compact_chain`.
 pub fn delete_snapshot(id: &str) -> Result<()> {
     info!("Deleting snapshot: {}", id);
     
-    let snapshot_path = PathBuf::from(constants::ROOT_DIR)
+    let snapshot_path = constants::root_dir()
         .join(".heal")
         .join("snapshots")
         .join(id);
@@ -409,6 +1380,15 @@ pub fn delete_snapshot(id: &str) -> Result<()> {
         anyhow::bail!("Snapshot not found: {}", id);
     }
     
+    let dependents: Vec<String> = list_snapshots()?.into_iter()
+        .filter(|s| s.base.as_deref() == Some(id))
+        .map(|s| s.id)
+        .collect();
+    if !dependents.is_empty() {
+        anyhow::bail!("Snapshot {} is the base of incremental snapshot(s) {}; compact them first",
+                      id, dependents.join(", "));
+    }
+    
     // Remove the snapshot directory
     fs::remove_dir_all(&snapshot_path)
         .with_context(|| format!("Failed to delete snapshot: {}", id))?;
@@ -416,3 +1396,1191 @@ pub fn delete_snapshot(id: &str) -> Result<()> {
     info!("Snapshot deleted: {}", id);
     Ok(())
 }
+
+/// Delete the snapshots a retention policy does not keep
+///
+/// The newest `max_count` snapshots younger than `max_age_days` are kept,
+/// along with the panic fallback snapshot, which is never deleted and does
+/// not count toward `max_count`. The newest `min_keep` snapshots are kept
+/// whatever their age. Snapshots that kept incremental snapshots are based
+/// on are kept too, without counting.
+pub fn prune(policy: RetentionPolicy) -> Result<PruneReport> {
+    let _lock = lock::lock(SNAPSHOT_LOCK, "prune snapshots", lock::DEFAULT_TIMEOUT)?;
+    
+    let protected = crate::panic::fallback_snapshot()?;
+    let (doomed, kept) = select_for_pruning(&policy, protected.as_deref())?;
+    
+    let mut report = PruneReport { protected, kept, ..Default::default() };
+    for (snapshot, expired) in doomed {
+        let bytes = crate::core::plan::path_size(&snapshot.path);
+        delete_snapshot(&snapshot.id)?;
+        info!("Pruned snapshot {} ({} bytes, taken {}, reason: {}, {})", snapshot.id, bytes, snapshot.timestamp,
+              snapshot.reason, if expired { "too old" } else { "over the count limit" });
+        report.removed.push(PrunedSnapshot { id: snapshot.id, timestamp: snapshot.timestamp, reason: snapshot.reason, bytes });
+    }
+    
+    if !report.removed.is_empty() {
+        crate::logs::ship::ship_audit("heal.prune", &format!(
+            "Pruned {} snapshot(s): {}", report.removed.len(),
+            report.removed.iter().map(|s| s.id.as_str()).collect::<Vec<_>>().join(", ")));
+    }
+    Ok(report)
+}
+
+/// Delete the snapshots a retention policy does not keep, returning their IDs
+///
+/// Same as `prune`, for callers that only need to know what was removed.
+pub fn prune_snapshots(policy: &SnapshotRetentionPolicy) -> Result<Vec<String>> {
+    let report = prune(policy.clone())?;
+    Ok(report.removed.into_iter().map(|snapshot| snapshot.id).collect())
+}
+
+/// Plan the deletions `prune` would make with a retention policy
+pub fn plan_prune(policy: &RetentionPolicy) -> Result<Plan> {
+    let protected = crate::panic::fallback_snapshot()?;
+    let (doomed, _) = select_for_pruning(policy, protected.as_deref())?;
+    
+    let mut plan = Plan::new("prune snapshots");
+    for (snapshot, _) in doomed {
+        plan.push(PlannedAction::DeleteFiles {
+            path: snapshot.path.to_string_lossy().to_string(),
+            bytes: crate::core::plan::path_size(&snapshot.path),
+        });
+    }
+    Ok(plan)
+}
+
+/// Snapshots a retention policy deletes, newest first and each with whether
+/// it is past the age limit, and the number of snapshots it keeps
+///
+/// Incremental snapshots come before their bases, so deleting in order
+/// never removes a base that is still referenced.
+fn select_for_pruning(policy: &RetentionPolicy, protected: Option<&str>) -> Result<(Vec<(SnapshotInfo, bool)>, usize)> {
+    if policy.max_count == Some(0) {
+        anyhow::bail!("Snapshot retention must keep at least one snapshot");
+    }
+    
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    Ok(split_for_pruning(policy, protected, &list_snapshots()?, now))
+}
+
+/// Split snapshots, newest first, into those a retention policy deletes at
+/// `now` and the number it keeps
+fn split_for_pruning(policy: &RetentionPolicy, protected: Option<&str>, snapshots: &[SnapshotInfo], now: u64) -> (Vec<(SnapshotInfo, bool)>, usize) {
+    let max_age = policy.max_age_days.map(|days| days * 24 * 60 * 60);
+    
+    let mut counted = 0;
+    let mut kept = 0;
+    let mut keep = BTreeSet::new();
+    let mut doomed = Vec::new();
+    for snapshot in snapshots {
+        if protected == Some(snapshot.id.as_str()) {
+            kept += 1;
+            keep.insert(snapshot.id.as_str());
+            continue;
+        }
+        
+        let expired = max_age.map_or(false, |age| now.saturating_sub(snapshot.timestamp) > age);
+        let surplus = policy.max_count.map_or(false, |count| counted >= count);
+        if counted < policy.min_keep || (!expired && !surplus) {
+            counted += 1;
+            kept += 1;
+            keep.insert(snapshot.id.as_str());
+            continue;
+        }
+        doomed.push((snapshot.clone(), expired));
+    }
+    
+    // Kept incremental snapshots need their whole base chain
+    let bases: BTreeMap<&str, &str> = snapshots.iter()
+        .filter_map(|s| s.base.as_deref().map(|base| (s.id.as_str(), base)))
+        .collect();
+    let mut needed = BTreeSet::new();
+    for id in keep {
+        let mut current = id;
+        while let Some(&base) = bases.get(current) {
+            if !needed.insert(base) {
+                break;
+            }
+            current = base;
+        }
+    }
+    
+    let doomed = doomed.into_iter()
+        .filter(|(snapshot, _)| {
+            if needed.contains(snapshot.id.as_str()) {
+                debug!("Keeping snapshot {}: incremental snapshots are based on it", snapshot.id);
+                kept += 1;
+                return false;
+            }
+            true
+        })
+        .collect();
+    (doomed, kept)
+}
+
+/// Put a new snapshot in the slot after the ring head, evicting the snapshot there
+///
+/// The evicted snapshot is first renamed out of the snapshots directory,
+/// then `ring.json` is replaced atomically, and only then is the evicted
+/// copy deleted, so a crash never leaves the ring pointing at a half-deleted
+/// snapshot. The panic fallback snapshot is dropped from the ring but kept.
+/// If the capacity changed, the newest snapshots are laid out again and the
+/// ones that no longer fit are evicted too.
+fn ring_insert(policy: &RingBufferPolicy, id: &str) -> Result<()> {
+    let mut state = load_ring()?;
+    let mut evicted = Vec::new();
+    if state.slots.len() != policy.capacity {
+        // Newest first, keeping room for the new snapshot
+        let mut entries = ring_order(&state);
+        let keep = entries.len().min(policy.capacity - 1);
+        evicted.extend(entries.split_off(keep));
+        entries.reverse();
+        
+        let mut slots: Vec<Option<String>> = entries.into_iter().map(Some).collect();
+        slots.resize(policy.capacity, None);
+        state = RingState { head: keep.checked_sub(1), slots };
+    }
+    
+    let slot = state.head.map_or(0, |head| (head + 1) % policy.capacity);
+    if let Some(old) = state.slots[slot].replace(id.to_string()) {
+        evicted.push(old);
+    }
+    state.head = Some(slot);
+    
+    let protected = crate::panic::fallback_snapshot().unwrap_or_default();
+    let trash = heal_dir().join(RING_EVICTED_DIR);
+    fs::create_dir_all(&trash)?;
+    let mut moved = Vec::new();
+    for old in &evicted {
+        let dir = snapshots_dir().join(old);
+        if protected.as_deref() == Some(old.as_str()) {
+            info!("Snapshot {} leaves the ring but is kept as the panic fallback", old);
+        } else if dir.exists() {
+            fs::rename(&dir, trash.join(old))
+                .with_context(|| format!("Failed to move evicted snapshot {}", old))?;
+            moved.push(old.clone());
+        }
+    }
+    
+    save_ring(&state)?;
+    debug!("Snapshot {} is in ring slot {}", id, slot);
+    
+    for old in moved {
+        if let Err(e) = fs::remove_dir_all(trash.join(&old)) {
+            warn!("Failed to delete evicted snapshot {}: {}", old, e);
+        } else {
+            info!("Evicted snapshot {} from the ring", old);
+        }
+    }
+    Ok(())
+}
+
+/// Snapshot IDs in the ring, newest first, skipping ones deleted since
+pub fn ring_entries() -> Result<Vec<String>> {
+    let state = load_ring()?;
+    Ok(ring_order(&state).into_iter()
+        .filter(|id| snapshots_dir().join(id).join("metadata.json").exists())
+        .collect())
+}
+
+/// The slots of the snapshot ring, in slot order
+///
+/// Fails if the ring buffer is not enabled. Slots not filled yet, or whose
+/// snapshot was deleted since, have no snapshot.
+pub fn ring_status() -> Result<Vec<RingSlot>> {
+    let capacity = match load_config()?.ring_buffer {
+        Some(policy) => policy.capacity,
+        None => anyhow::bail!("The snapshot ring buffer is not enabled; set ring_buffer in .heal/config.json"),
+    };
+    let state = load_ring()?;
+    let snapshots = list_snapshots()?;
+    Ok((0..capacity.max(state.slots.len()))
+        .map(|index| RingSlot {
+            index,
+            snapshot: state.slots.get(index).cloned().flatten()
+                .and_then(|id| snapshots.iter().find(|s| s.id == id).cloned()),
+            is_head: state.head == Some(index),
+        })
+        .collect())
+}
+
+/// Snapshot IDs of a ring, from the head backwards
+fn ring_order(state: &RingState) -> Vec<String> {
+    let len = state.slots.len();
+    let head = match state.head {
+        Some(head) if head < len => head,
+        _ => return Vec::new(),
+    };
+    (0..len)
+        .filter_map(|back| state.slots[(head + len - back) % len].clone())
+        .collect()
+}
+
+/// The ring slots, empty if no snapshot was taken in ring mode yet
+fn load_ring() -> Result<RingState> {
+    let path = snapshots_dir().join(RING_FILE);
+    if !path.exists() {
+        return Ok(RingState::default());
+    }
+    serde_json::from_str(&fs::read_to_string(&path)?)
+        .with_context(|| format!("Invalid snapshot ring {:?}", path))
+}
+
+/// Replace the ring slots atomically
+fn save_ring(state: &RingState) -> Result<()> {
+    crate::core::fs::write_atomic(&snapshots_dir().join(RING_FILE), serde_json::to_string_pretty(state)?.as_bytes())
+}
+
+/// Load the snapshot settings from `.heal/config.json`
+pub fn load_config() -> Result<SnapshotConfig> {
+    let path = heal_dir().join(CONFIG_FILE);
+    if !path.exists() {
+        return Ok(SnapshotConfig::default());
+    }
+    
+    let config: SnapshotConfig = serde_json::from_str(&fs::read_to_string(&path)?)
+        .with_context(|| format!("Invalid snapshot configuration {:?}", path))?;
+    if !(1..=19).contains(&config.compression_level) {
+        anyhow::bail!("Snapshot compression_level must be between 1 and 19, got {}", config.compression_level);
+    }
+    if matches!(config.ring_buffer, Some(ring) if ring.capacity == 0) {
+        anyhow::bail!("Snapshot ring_buffer capacity must be at least 1");
+    }
+    Ok(config)
+}
+
+/// Load the retention policy from system.json
+pub fn load_retention_policy() -> Result<RetentionPolicy> {
+    let path = constants::root_dir().join(".config").join("system.json");
+    if !path.exists() {
+        return Ok(RetentionPolicy::default());
+    }
+    
+    let system_config: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    match system_config.get("snapshot_retention") {
+        Some(value) => Ok(serde_json::from_value(value.clone())
+            .context("Invalid snapshot_retention configuration in system.json")?),
+        None => Ok(RetentionPolicy::default()),
+    }
+}
+
+/// Copy a snapshot out of the root directory
+pub fn export_snapshot(id: &str, dest: &Path) -> Result<()> {
+    let snapshot_path = snapshots_dir().join(id);
+    if !snapshot_path.exists() {
+        anyhow::bail!("Snapshot not found: {}", id);
+    }
+    
+    copy_directory(&snapshot_path, dest)
+        .with_context(|| format!("Failed to export snapshot {} to {:?}", id, dest))?;
+    info!("Snapshot {} exported to {:?}", id, dest);
+    Ok(())
+}
+
+/// Read a file of a snapshot's full tree, following the base chain
+///
+/// `relative_path` is the file's path inside the snapshot, e.g.
+/// `packages/registry.json`; stored copies are decrypted and decompressed.
+/// Returns `None` if the snapshot's tree lacks the file.
+pub fn read_file(snapshot_id: &str, relative_path: &str) -> Result<Option<Vec<u8>>> {
+    match locate(&load_chain(snapshot_id)?, relative_path) {
+        Some((path, storage)) => Ok(Some(storage.read(&path)?)),
+        None => Ok(None),
+    }
+}
+
+/// Whether a snapshot directory holds an incremental snapshot
+pub fn is_incremental(snapshot_dir: &Path) -> bool {
+    snapshot_dir.join(DELTA_FILE).is_file()
+}
+
+/// Write the full file tree of an incremental snapshot into `dest`, as plain files
+///
+/// Files the snapshot left out are copied from the snapshots of its base
+/// chain that hold them.
+pub fn materialize(snapshot_id: &str, dest: &Path) -> Result<()> {
+    write_tree(snapshot_id, dest, &Storage::default())
+}
+
+/// Merge a chain of incremental snapshots into a single full snapshot
+///
+/// `ids` lists the chain oldest first, each snapshot after the first being
+/// based on the one before it. The last snapshot is rewritten as a full
+/// snapshot holding its whole tree and the others are deleted; snapshots
+/// outside the chain must not be based on them. Returns the ID of the
+/// merged snapshot.
+pub fn compact_chain(ids: &[String]) -> Result<String> {
+    let tip = ids.last().ok_or_else(|| anyhow::anyhow!("No snapshots to compact"))?;
+    
+    let _lock = lock::lock(SNAPSHOT_LOCK, &format!("compact {}", tip), lock::DEFAULT_TIMEOUT)?;
+    
+    let snapshots = list_snapshots()?;
+    let find = |id: &str| snapshots.iter().find(|s| s.id == id)
+        .ok_or_else(|| anyhow::anyhow!("Snapshot not found: {}", id));
+    for pair in ids.windows(2) {
+        if find(&pair[1])?.base.as_deref() != Some(pair[0].as_str()) {
+            anyhow::bail!("Snapshot {} is not based on {}; list the chain oldest first", pair[1], pair[0]);
+        }
+    }
+    for id in &ids[..ids.len() - 1] {
+        if let Some(outside) = snapshots.iter().find(|s| s.base.as_deref() == Some(id.as_str()) && !ids.contains(&s.id)) {
+            anyhow::bail!("Snapshot {} is also the base of {}, which is not in the chain", id, outside.id);
+        }
+    }
+    
+    let tip_dir = snapshots_dir().join(tip);
+    let mut metadata = read_metadata(&tip_dir)?;
+    
+    if is_incremental(&tip_dir) {
+        info!("Compacting snapshot chain {} into a full snapshot", ids.join(" -> "));
+        
+        // Assemble the full tree next to the snapshots, then swap it in
+        let staging = heal_dir().join(COMPACT_DIR).join(tip);
+        if staging.exists() {
+            fs::remove_dir_all(&staging)?;
+        }
+        let storage = load_config()?.storage()?;
+        let assembled = write_tree(tip, &staging, &storage).and_then(|_| {
+            metadata.content_hash = calculate_snapshot_hash(&staging, &storage)?;
+            metadata.proof = prove_content_hash(&metadata.content_hash);
+            metadata.file_hashes = calculate_file_hashes(&staging, &storage)?;
+            metadata.mode = SnapshotMode::Normal;
+            metadata.compressed = storage.compression.is_some();
+            metadata.encrypted = storage.key.is_some();
+            fs::write(staging.join("metadata.json"), serde_json::to_string_pretty(&metadata)?)?;
+            Ok(())
+        });
+        if let Err(e) = assembled {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(e).with_context(|| format!("Failed to compact snapshot {}", tip));
+        }
+        
+        fs::remove_dir_all(&tip_dir)
+            .with_context(|| format!("Failed to replace snapshot {}", tip))?;
+        fs::rename(&staging, &tip_dir)
+            .with_context(|| format!("Failed to replace snapshot {}", tip))?;
+    }
+    
+    // Newest first, so each one's dependent is already gone
+    for id in ids[..ids.len() - 1].iter().rev() {
+        delete_snapshot(id)?;
+    }
+    
+    crate::logs::ship::ship_audit("heal.compact", &format!("Compacted snapshot chain {} into {}", ids.join(" -> "), tip));
+    Ok(tip.clone())
+}
+
+/// Snapshot one container's data directory and registry entry
+///
+/// The container's kv store is compacted first so the copy holds its
+/// current state. Returns the snapshot ID.
+pub fn snapshot_container(container_id: &ContainerId) -> Result<String> {
+    info!("Snapshotting container: {}", container_id);
+    
+    let container = registry::get_container(container_id)?;
+    let entry = ContainerEntry {
+        name: container.name.clone(),
+        image: container.path.clone()
+            .ok_or_else(|| anyhow::anyhow!("Container has no path"))?,
+        args: registry::get_container_args(container_id)?,
+    };
+    
+    let _lock = lock::lock(SNAPSHOT_LOCK, &format!("container snapshot {}", container_id), lock::DEFAULT_TIMEOUT)?;
+    
+    let data_dir = kv::data_dir(container_id)?;
+    if kv::kv_dir(container_id)?.is_dir() {
+        if let Err(e) = kv::with_store(container_id, |store| store.compact()) {
+            warn!("Failed to compact kv store of container {} before snapshot: {:#}", container_id, e);
+        }
+    }
+    
+    let id = super::new_snapshot_id("container")?;
+    let snapshot_dir = container_snapshots_dir(container_id)?.join(&id);
+    let captured = snapshot_dir.join(CONTAINER_DATA_DIR);
+    let copied = if data_dir.exists() {
+        copy_directory(&data_dir, &captured)
+    } else {
+        fs::create_dir_all(&captured).map_err(Into::into)
+    };
+    
+    let snapshot = copied.and_then(|_| {
+        let snapshot = ContainerSnapshot {
+            id: id.clone(),
+            container_id: container_id.clone(),
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            entry,
+            content_hash: calculate_snapshot_hash(&captured, &Storage::default())?,
+            path: snapshot_dir.clone(),
+        };
+        crate::core::fs::write_atomic(&snapshot_dir.join("metadata.json"),
+                                      serde_json::to_string_pretty(&snapshot)?.as_bytes())?;
+        Ok(snapshot)
+    });
+    if let Err(e) = snapshot {
+        let _ = fs::remove_dir_all(&snapshot_dir);
+        return Err(e).with_context(|| format!("Failed to snapshot container {}", container_id));
+    }
+    
+    info!("Container snapshot created: {}", id);
+    Ok(id)
+}
+
+/// Snapshots of a container, newest first
+pub fn list_container_snapshots(container_id: &ContainerId) -> Result<Vec<ContainerSnapshot>> {
+    let dir = container_snapshots_dir(container_id)?;
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let mut snapshots = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        let metadata_path = path.join("metadata.json");
+        if !metadata_path.is_file() {
+            continue;
+        }
+        
+        let mut snapshot: ContainerSnapshot = serde_json::from_str(&fs::read_to_string(&metadata_path)?)
+            .with_context(|| format!("Invalid container snapshot metadata {:?}", metadata_path))?;
+        snapshot.path = path;
+        snapshots.push(snapshot);
+    }
+    
+    snapshots.sort_by(|a, b| b.timestamp.cmp(&a.timestamp).then_with(|| b.id.cmp(&a.id)));
+    Ok(snapshots)
+}
+
+/// The content hash a snapshot recorded and the hash of its files now
+///
+/// Emergency snapshots are hashed by their stored bundle; the files
+/// unpacked from it don't count.
+pub fn snapshot_hashes(snapshot_id: &str) -> Result<SnapshotHashes> {
+    let snapshot_dir = snapshots_dir().join(snapshot_id);
+    if !snapshot_dir.join("metadata.json").exists() {
+        anyhow::bail!("Snapshot not found: {}", snapshot_id);
+    }
+    let metadata = read_metadata(&snapshot_dir)?;
+    
+    let (computed, mismatched_files) = if metadata.mode == SnapshotMode::Emergency {
+        let bundle = fs::read(snapshot_dir.join(EMERGENCY_BUNDLE))
+            .with_context(|| format!("Failed to read the bundle of emergency snapshot {}", snapshot_id))?;
+        let computed = blake3::hash(&bundle).to_hex().to_string();
+        let mismatched = if computed == metadata.content_hash { Vec::new() } else { vec![EMERGENCY_BUNDLE.to_string()] };
+        (computed, mismatched)
+    } else {
+        let storage = Storage::of(&snapshot_dir)?;
+        let computed = calculate_snapshot_hash(&snapshot_dir, &storage)?;
+        let mismatched = if computed == metadata.content_hash || metadata.file_hashes.is_empty() {
+            Vec::new()
+        } else {
+            mismatched_files(&metadata.file_hashes, &calculate_file_hashes(&snapshot_dir, &storage)?)
+        };
+        (computed, mismatched)
+    };
+    let proof = match &metadata.proof {
+        Some(proof) => Some(from_hex(proof)
+            .with_context(|| format!("Invalid proof in the metadata of snapshot {}", snapshot_id))?),
+        None => None,
+    };
+    
+    Ok(SnapshotHashes { stored: metadata.content_hash, computed, proof, mismatched_files })
+}
+
+/// Check the ZK proof of a snapshot's recorded content hash
+///
+/// The snapshot's files are not hashed again. Returns `None` if the
+/// snapshot has no proof.
+pub fn verify_snapshot_proof(snapshot_id: &str) -> Result<Option<bool>> {
+    let snapshot_dir = snapshots_dir().join(snapshot_id);
+    if !snapshot_dir.join("metadata.json").exists() {
+        anyhow::bail!("Snapshot not found: {}", snapshot_id);
+    }
+    let metadata = read_metadata(&snapshot_dir)?;
+    
+    match &metadata.proof {
+        Some(proof) => {
+            let proof = from_hex(proof)
+                .with_context(|| format!("Invalid proof in the metadata of snapshot {}", snapshot_id))?;
+            Ok(Some(crate::zk::verify_proof(metadata.content_hash.as_bytes(), &proof, SNAPSHOT_PROOF_OPERATION)?))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Whether a container snapshot's data still matches its hash
+pub fn verify_container_snapshot(snapshot: &ContainerSnapshot) -> Result<bool> {
+    let captured = snapshot.path.join(CONTAINER_DATA_DIR);
+    if !captured.is_dir() {
+        return Ok(false);
+    }
+    Ok(calculate_snapshot_hash(&captured, &Storage::default())? == snapshot.content_hash)
+}
+
+/// Put a container's data directory and registry entry back as captured
+///
+/// The container must not be running. Its data directory is replaced
+/// whole, and the registry entry is re-created if it is gone.
+pub fn restore_container_snapshot(snapshot: &ContainerSnapshot) -> Result<()> {
+    let container_id = &snapshot.container_id;
+    info!("Restoring container {} from snapshot {}", container_id, snapshot.id);
+    
+    let _lock = lock::lock(SNAPSHOT_LOCK, &format!("container restore {}", container_id), lock::DEFAULT_TIMEOUT)?;
+    
+    // The store is reopened from the restored files on next use
+    kv::close(container_id);
+    
+    // Stage the copy next to the live directory, then swap it in
+    let data_dir = kv::data_dir(container_id)?;
+    let staging = data_dir.with_extension("restore");
+    let replaced = data_dir.with_extension("replaced");
+    for leftover in [&staging, &replaced] {
+        if leftover.exists() {
+            fs::remove_dir_all(leftover)?;
+        }
+    }
+    copy_directory(&snapshot.path.join(CONTAINER_DATA_DIR), &staging)
+        .with_context(|| format!("Failed to stage data of container {}", container_id))?;
+    
+    if data_dir.exists() {
+        fs::rename(&data_dir, &replaced)?;
+    }
+    if let Err(e) = fs::rename(&staging, &data_dir) {
+        if replaced.exists() {
+            let _ = fs::rename(&replaced, &data_dir);
+        }
+        return Err(e).with_context(|| format!("Failed to restore data of container {}", container_id));
+    }
+    if replaced.exists() {
+        fs::remove_dir_all(&replaced)?;
+    }
+    
+    if registry::get_container(container_id).is_err() {
+        let mut container = crate::matrixbox::container::load_container(&snapshot.entry.image.to_string_lossy())
+            .with_context(|| format!("Failed to load image of container {}", container_id))?;
+        container.name = snapshot.entry.name.clone();
+        registry::reinstate_container(container_id, &container, &snapshot.entry.args)?;
+    }
+    
+    crate::logs::ship::ship_audit("heal.container", &format!(
+        "Container {} restored from snapshot {}", container_id, snapshot.id));
+    Ok(())
+}
+
+/// Directory holding a container's snapshots
+fn container_snapshots_dir(container_id: &ContainerId) -> Result<PathBuf> {
+    // Validates the ID for use in a path
+    kv::data_dir(container_id)?;
+    Ok(snapshots_dir().join(CONTAINER_SNAPSHOTS_DIR).join(container_id))
+}
+
+/// Write the full tree of an incremental snapshot, storing files as `storage` says
+fn write_tree(snapshot_id: &str, dest: &Path, storage: &Storage) -> Result<()> {
+    let chain = load_chain(snapshot_id)?;
+    let delta = chain[0].delta.as_ref()
+        .ok_or_else(|| anyhow::anyhow!("Snapshot {} is not incremental", snapshot_id))?;
+    
+    for relative in delta.files.keys() {
+        let (source, source_storage) = locate(&chain, relative)
+            .ok_or_else(|| anyhow::anyhow!("{} of snapshot {} is missing from its base chain", relative, snapshot_id))?;
+        let target = dest.join(relative);
+        if source_storage.same_as(storage) {
+            copy_file(&source, &storage.stored_path(&target))?;
+        } else {
+            storage.store(&mut source_storage.open(&source)?, &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Hash each file of a changed-files-only copy against the base, dropping unchanged ones
+fn write_delta(snapshot_dir: &Path, base_id: &str, base_files: &BTreeMap<String, String>, storage: &Storage) -> Result<()> {
+    let mut delta = DeltaManifest {
+        base_snapshot_id: base_id.to_string(),
+        unchanged: BTreeSet::new(),
+        files: BTreeMap::new(),
+    };
+    
+    for (relative, path, file_storage) in list_tree(snapshot_dir, storage)? {
+        let hash = hash_file(&path, &file_storage)?;
+        if base_files.get(&relative) == Some(&hash) {
+            fs::remove_file(&path)?;
+            delta.unchanged.insert(relative.clone());
+        }
+        delta.files.insert(relative, hash);
+    }
+    
+    debug!("Incremental snapshot keeps {} of {} file(s), {} unchanged since {}",
+           delta.files.len() - delta.unchanged.len(), delta.files.len(), delta.unchanged.len(), base_id);
+    fs::write(snapshot_dir.join(DELTA_FILE), serde_json::to_string_pretty(&delta)?)?;
+    Ok(())
+}
+
+/// BLAKE3 hashes of every file in a snapshot's full tree, by original content
+fn tree_hashes(snapshot_id: &str) -> Result<BTreeMap<String, String>> {
+    let mut chain = load_chain(snapshot_id)?;
+    let link = chain.remove(0);
+    if let Some(delta) = link.delta {
+        return Ok(delta.files);
+    }
+    
+    if link.dir.join(EMERGENCY_BUNDLE).exists() {
+        anyhow::bail!("Snapshot {} is an emergency snapshot and can't be a base", snapshot_id);
+    }
+    
+    let mut hashes = BTreeMap::new();
+    for (relative, path, file_storage) in list_tree(&link.dir, &link.storage)? {
+        hashes.insert(relative, hash_file(&path, &file_storage)?);
+    }
+    Ok(hashes)
+}
+
+/// A snapshot and the snapshots it is based on, newest first
+fn load_chain(snapshot_id: &str) -> Result<Vec<ChainLink>> {
+    let mut chain: Vec<ChainLink> = Vec::new();
+    let mut seen = BTreeSet::new();
+    let mut id = snapshot_id.to_string();
+    
+    loop {
+        let dir = snapshots_dir().join(&id);
+        if !dir.join("metadata.json").exists() {
+            if chain.is_empty() {
+                anyhow::bail!("Snapshot not found: {}", id);
+            }
+            anyhow::bail!("Snapshot {} in the base chain of {} is missing", id, snapshot_id);
+        }
+        if !seen.insert(id.clone()) {
+            anyhow::bail!("Snapshot {} has a cyclic base chain", snapshot_id);
+        }
+        
+        let delta: Option<DeltaManifest> = if is_incremental(&dir) {
+            Some(serde_json::from_str(&fs::read_to_string(dir.join(DELTA_FILE))?)
+                .with_context(|| format!("Invalid manifest of incremental snapshot {}", id))?)
+        } else {
+            None
+        };
+        let base = delta.as_ref().map(|d| d.base_snapshot_id.clone());
+        let storage = Storage::of(&dir)?;
+        chain.push(ChainLink { dir, storage, delta });
+        
+        match base {
+            Some(base) => id = base,
+            None => return Ok(chain),
+        }
+    }
+}
+
+/// Snapshot copy of a file and how it is stored, searching down the chain
+/// while the file is unchanged
+fn locate(chain: &[ChainLink], relative_path: &str) -> Option<(PathBuf, Storage)> {
+    for link in chain {
+        let path = link.dir.join(relative_path);
+        let stored = link.storage.stored_path(&path);
+        if stored != path && stored.is_file() {
+            return Some((stored, link.storage));
+        }
+        if path.is_file() {
+            return Some((path, Storage::default()));
+        }
+        match &link.delta {
+            Some(delta) if delta.unchanged.contains(relative_path) => continue,
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Whether incremental snapshots are based on a snapshot
+fn is_base(snapshots: &[SnapshotInfo], id: &str) -> bool {
+    snapshots.iter().any(|s| s.base.as_deref() == Some(id))
+}
+
+/// Metadata of a snapshot directory
+fn read_metadata(snapshot_dir: &Path) -> Result<SnapshotMetadata> {
+    let path = snapshot_dir.join("metadata.json");
+    serde_json::from_str(&fs::read_to_string(&path)?)
+        .with_context(|| format!("Invalid snapshot metadata {:?}", path))
+}
+
+/// Captured files of a snapshot directory, without its own bookkeeping
+///
+/// Each file is listed by its original `/`-separated path in the snapshot,
+/// along with where and how it is stored.
+fn list_tree(snapshot_dir: &Path, storage: &Storage) -> Result<Vec<(String, PathBuf, Storage)>> {
+    fn collect(base: &Path, dir: &Path, storage: &Storage, files: &mut Vec<(String, PathBuf, Storage)>) -> Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                collect(base, &path, storage, files)?;
+            } else if path.is_file() {
+                let relative = path.strip_prefix(base)?.to_string_lossy().replace('\\', "/");
+                if matches!(relative.as_str(), "metadata.json" | DELTA_FILE | EMERGENCY_BUNDLE) {
+                    continue;
+                }
+                match storage.original_name(&relative) {
+                    Some(original) => files.push((original.to_string(), path, *storage)),
+                    None => files.push((relative, path, Storage::default())),
+                }
+            }
+        }
+        Ok(())
+    }
+    
+    let mut files = Vec::new();
+    collect(snapshot_dir, snapshot_dir, storage, &mut files)?;
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(files)
+}
+
+/// BLAKE3 hash (hex) of a stored file's original content
+fn hash_file(path: &Path, storage: &Storage) -> Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    std::io::copy(&mut storage.open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Prove a content hash, if ZK is enabled in system.json
+///
+/// A failed proof is logged and leaves the snapshot without one.
+fn prove_content_hash(content_hash: &str) -> Option<String> {
+    if !zk_enabled() {
+        return None;
+    }
+    match crate::zk::generate_proof(content_hash.as_bytes(), SNAPSHOT_PROOF_OPERATION) {
+        Ok(proof) => Some(to_hex(&proof)),
+        Err(e) => {
+            warn!("Failed to prove snapshot content hash: {:#}", e);
+            None
+        }
+    }
+}
+
+/// Whether the ZK subsystem is enabled in system.json
+fn zk_enabled() -> bool {
+    let path = constants::root_dir().join(".config").join("system.json");
+    fs::read_to_string(&path).ok()
+        .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+        .and_then(|config| config["subsystems"]["zk"]["enabled"].as_bool())
+        .unwrap_or(false)
+}
+
+/// Encode bytes as hex
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decode a hex string
+fn from_hex(hex: &str) -> Result<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        anyhow::bail!("Invalid hex string length");
+    }
+    
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).context("Invalid hex string"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    
+    // Emergency snapshots release the shared headroom, so tests take them one at a time
+    static EMERGENCY: Mutex<()> = Mutex::new(());
+    
+    /// Take an emergency snapshot with the full headroom reserved
+    fn emergency_snapshot(id: &str) -> EmergencyOutcome {
+        let _serial = EMERGENCY.lock().unwrap_or_else(|e| e.into_inner());
+        {
+            let _lock = lock::lock(SNAPSHOT_LOCK, "test headroom", lock::DEFAULT_TIMEOUT).unwrap();
+            fs::create_dir_all(heal_dir()).unwrap();
+            maintain_headroom().unwrap();
+        }
+        create_emergency_snapshot(id, "test", None).unwrap()
+    }
+    
+    const NOW: u64 = 1_700_000_000;
+    
+    /// Listed snapshot taken `age_days` before `NOW`
+    fn listed(id: &str, age_days: u64, base: Option<&str>) -> SnapshotInfo {
+        SnapshotInfo {
+            id: id.to_string(),
+            timestamp: NOW - age_days * 24 * 60 * 60,
+            reason: "test".to_string(),
+            path: PathBuf::from(id),
+            hash: String::new(),
+            base: base.map(String::from),
+        }
+    }
+    
+    /// IDs a policy deletes from the snapshots, with whether each is too old, and how many it keeps
+    fn pruned(policy: &RetentionPolicy, protected: Option<&str>, snapshots: &[SnapshotInfo]) -> (Vec<(String, bool)>, usize) {
+        let (doomed, kept) = split_for_pruning(policy, protected, snapshots, NOW);
+        (doomed.into_iter().map(|(snapshot, expired)| (snapshot.id, expired)).collect(), kept)
+    }
+    
+    /// Write a file under the root directory, creating its parents
+    fn write_root_file(relative: &str, content: &str) {
+        let path = constants::root_dir().join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+    
+    #[test]
+    fn emergency_snapshots_bundle_the_minimal_set_without_private_keys() {
+        write_root_file(".zk/contracts/emergency_contract.yaml", "name: emergency_contract\n");
+        write_root_file(".auth/keys/emergency.pub", "public");
+        write_root_file(".auth/keys/emergency.key", "private");
+        
+        let outcome = emergency_snapshot("emergency-bundle");
+        assert!(outcome.sacrificed.is_empty());
+        assert!(outcome.bytes <= RESERVED_HEADROOM_BYTES);
+        
+        let snapshot_dir = snapshots_dir().join("emergency-bundle");
+        assert_eq!(read_metadata(&snapshot_dir).unwrap().mode, SnapshotMode::Emergency);
+        assert_eq!(read_bundle_entry(&snapshot_dir, "auth/keys/emergency.pub").unwrap().unwrap(), b"public");
+        assert_eq!(read_bundle_entry(&snapshot_dir, "auth/keys/emergency.key").unwrap(), None);
+        
+        unpack_emergency(&snapshot_dir).unwrap();
+        let contract = fs::read_to_string(snapshot_dir.join("zk/contracts/emergency_contract.yaml")).unwrap();
+        assert_eq!(contract, "name: emergency_contract\n");
+        assert!(!snapshot_dir.join("auth/keys/emergency.key").exists());
+    }
+    
+    #[test]
+    fn only_full_disks_count_as_out_of_space() {
+        let full = anyhow::Error::from(std::io::Error::from_raw_os_error(28)).context("Failed to write snapshot");
+        assert!(is_out_of_space(&full));
+        let quota = anyhow::Error::from(std::io::Error::from_raw_os_error(122));
+        assert!(is_out_of_space(&quota));
+        
+        let denied = anyhow::Error::from(std::io::Error::from_raw_os_error(13));
+        assert!(!is_out_of_space(&denied));
+        assert!(!is_out_of_space(&anyhow::anyhow!("disk full")));
+    }
+    
+    #[test]
+    fn snapshots_capture_the_package_registry_for_inspection() {
+        let registry = r#"{"packages":{"inspected":{"version":"1.2.3"}}}"#;
+        write_root_file(".package/registry.json", registry);
+        
+        create_snapshot("inspect-packages", "test", &SnapshotOptions::default()).unwrap();
+        let captured = read_file("inspect-packages", "packages/registry.json").unwrap().unwrap();
+        assert_eq!(captured, registry.as_bytes());
+        assert_eq!(read_file("inspect-packages", "packages/missing.json").unwrap(), None);
+        
+        assert!(emergency_snapshot("inspect-packages-emergency").sacrificed.is_empty());
+        let bundled = read_bundle_entry(&snapshots_dir().join("inspect-packages-emergency"), "packages/registry.json").unwrap();
+        assert_eq!(bundled.unwrap(), registry.as_bytes());
+    }
+    
+    #[test]
+    fn snapshot_hashes_survive_moving_and_ignore_metadata() {
+        let dir = heal_dir().join("hash-test");
+        let _ = fs::remove_dir_all(&dir);
+        let original = dir.join("original");
+        fs::create_dir_all(original.join("zk")).unwrap();
+        fs::write(original.join("zk").join("b.yaml"), "b").unwrap();
+        fs::write(original.join("zk").join("a.yaml"), "a").unwrap();
+        fs::write(original.join("zk").join("metadata.json"), "nested").unwrap();
+        fs::write(original.join("metadata.json"), "{}").unwrap();
+        let hash = calculate_snapshot_hash(&original, &Storage::default()).unwrap();
+        
+        let moved = dir.join("moved");
+        fs::rename(&original, &moved).unwrap();
+        assert_eq!(calculate_snapshot_hash(&moved, &Storage::default()).unwrap(), hash);
+        
+        // Only the top-level metadata.json, which records the hash, is left out
+        fs::write(moved.join("metadata.json"), r#"{"content_hash": "x"}"#).unwrap();
+        assert_eq!(calculate_snapshot_hash(&moved, &Storage::default()).unwrap(), hash);
+        fs::write(moved.join("zk").join("metadata.json"), "changed").unwrap();
+        assert_ne!(calculate_snapshot_hash(&moved, &Storage::default()).unwrap(), hash);
+        fs::write(moved.join("zk").join("metadata.json"), "nested").unwrap();
+        
+        fs::rename(moved.join("zk").join("a.yaml"), moved.join("zk").join("c.yaml")).unwrap();
+        assert_ne!(calculate_snapshot_hash(&moved, &Storage::default()).unwrap(), hash);
+    }
+    
+    #[test]
+    fn tampered_snapshots_no_longer_match_their_hash() {
+        write_root_file(".zk/contracts/verify_tampered.yaml", "original");
+        create_snapshot("verify-tampered", "test", &SnapshotOptions::default()).unwrap();
+        
+        let hashes = snapshot_hashes("verify-tampered").unwrap();
+        assert_eq!(hashes.computed, hashes.stored);
+        assert!(hashes.mismatched_files.is_empty());
+        
+        let tampered = "zk/contracts/verify_tampered.yaml";
+        fs::write(snapshots_dir().join("verify-tampered").join(tampered), "forged").unwrap();
+        let hashes = snapshot_hashes("verify-tampered").unwrap();
+        assert_ne!(hashes.computed, hashes.stored);
+        assert_eq!(hashes.mismatched_files, [tampered]);
+        
+        assert!(snapshot_hashes("verify-missing").is_err());
+        assert!(verify_snapshot_proof("verify-missing").is_err());
+    }
+    
+    #[test]
+    fn retention_keeps_the_newest_and_the_fallback() {
+        let snapshots = [listed("s4", 0, None), listed("s3", 1, None), listed("fallback", 2, None), listed("s2", 3, None), listed("s1", 40, None)];
+        let count = RetentionPolicy { max_count: Some(2), ..Default::default() };
+        assert_eq!(pruned(&count, Some("fallback"), &snapshots), (vec![("s2".to_string(), false), ("s1".to_string(), false)], 3));
+        
+        // Without a fallback every snapshot counts
+        assert_eq!(pruned(&count, None, &snapshots).0.len(), 3);
+        
+        let age = RetentionPolicy { max_age_days: Some(30), ..Default::default() };
+        assert_eq!(pruned(&age, Some("fallback"), &snapshots), (vec![("s1".to_string(), true)], 4));
+        
+        // An old fallback survives any policy
+        let snapshots = [listed("s2", 0, None), listed("fallback", 90, None)];
+        let both = RetentionPolicy { max_count: Some(1), max_age_days: Some(1), ..Default::default() };
+        assert_eq!(pruned(&both, Some("fallback"), &snapshots), (Vec::new(), 2));
+    }
+    
+    #[test]
+    fn retention_keeps_the_bases_of_kept_snapshots() {
+        let snapshots = [listed("tip", 0, Some("middle")), listed("middle", 1, Some("base")), listed("base", 2, None), listed("old", 3, None)];
+        let count = RetentionPolicy { max_count: Some(1), ..Default::default() };
+        assert_eq!(pruned(&count, None, &snapshots), (vec![("old".to_string(), false)], 3));
+        
+        // A base is only needed while something kept is based on it
+        let snapshots = [listed("new", 0, None), listed("tip", 2, Some("base")), listed("base", 3, None)];
+        let age = RetentionPolicy { max_age_days: Some(1), ..Default::default() };
+        assert_eq!(pruned(&age, None, &snapshots), (vec![("tip".to_string(), true), ("base".to_string(), true)], 1));
+    }
+    
+    #[test]
+    fn retention_keeps_the_newest_min_keep_whatever_their_age() {
+        let snapshots = [listed("s4", 50, None), listed("s3", 60, None), listed("fallback", 70, None), listed("s2", 80, None), listed("s1", 90, None)];
+        let age = RetentionPolicy { max_age_days: Some(30), min_keep: 2, ..Default::default() };
+        assert_eq!(pruned(&age, Some("fallback"), &snapshots), (vec![("s2".to_string(), true), ("s1".to_string(), true)], 3));
+        
+        // min_keep wins over a lower max_count
+        let count = RetentionPolicy { max_count: Some(1), min_keep: 3, ..Default::default() };
+        assert_eq!(pruned(&count, None, &snapshots), (vec![("s2".to_string(), false), ("s1".to_string(), false)], 3));
+        
+        // More than there are keeps them all
+        let all = RetentionPolicy { max_age_days: Some(1), min_keep: 10, ..Default::default() };
+        assert_eq!(pruned(&all, None, &snapshots), (Vec::new(), 5));
+        
+        let policy: RetentionPolicy = serde_json::from_str(r#"{"max_count": 5}"#).unwrap();
+        assert_eq!(policy.min_keep, 0);
+    }
+    
+    #[test]
+    fn incremental_snapshots_store_only_changed_files() {
+        write_root_file(".zk/contracts/incremental_same.yaml", "same");
+        write_root_file(".zk/contracts/incremental_changed.yaml", "before");
+        create_snapshot("incremental-base", "test", &SnapshotOptions::default()).unwrap();
+        write_root_file(".zk/contracts/incremental_changed.yaml", "after");
+        write_root_file(".zk/contracts/incremental_added.yaml", "added");
+        create_snapshot("incremental-middle", "test", &SnapshotOptions::incremental("incremental-base")).unwrap();
+        create_snapshot("incremental-tip", "test", &SnapshotOptions::incremental("incremental-middle")).unwrap();
+        
+        let (same, changed, added) = ("zk/contracts/incremental_same.yaml", "zk/contracts/incremental_changed.yaml", "zk/contracts/incremental_added.yaml");
+        let middle = snapshots_dir().join("incremental-middle");
+        assert!(is_incremental(&middle) && !is_incremental(&snapshots_dir().join("incremental-base")));
+        assert!(!middle.join(same).exists());
+        assert!(middle.join(changed).exists() && middle.join(added).exists());
+        assert!(!snapshots_dir().join("incremental-tip").join(changed).exists());
+        
+        // Reads follow the chain down to the snapshot holding the file
+        assert_eq!(read_file("incremental-tip", same).unwrap().unwrap(), b"same");
+        assert_eq!(read_file("incremental-tip", changed).unwrap().unwrap(), b"after");
+        assert_eq!(read_file("incremental-base", changed).unwrap().unwrap(), b"before");
+        assert_eq!(read_file("incremental-base", added).unwrap(), None);
+        
+        let dest = heal_dir().join("materialized-tip");
+        materialize("incremental-tip", &dest).unwrap();
+        assert_eq!(fs::read_to_string(dest.join(same)).unwrap(), "same");
+        assert_eq!(fs::read_to_string(dest.join(added)).unwrap(), "added");
+        assert!(materialize("incremental-base", &dest).is_err());
+        
+        let error = delete_snapshot("incremental-base").unwrap_err();
+        assert!(error.to_string().contains("incremental-middle"), "{}", error);
+        let chain = ["incremental-middle".to_string(), "incremental-base".to_string()];
+        assert!(compact_chain(&chain).is_err());
+        
+        // Compacting leaves the tip as a full snapshot with the same contents
+        let chain = ["incremental-base".to_string(), "incremental-middle".to_string(), "incremental-tip".to_string()];
+        assert_eq!(compact_chain(&chain).unwrap(), "incremental-tip");
+        let tip = snapshots_dir().join("incremental-tip");
+        assert!(!is_incremental(&tip) && tip.join(same).exists());
+        assert_eq!(read_metadata(&tip).unwrap().mode, SnapshotMode::Normal);
+        assert!(!snapshots_dir().join("incremental-base").exists() && !middle.exists());
+        assert_eq!(read_file("incremental-tip", changed).unwrap().unwrap(), b"after");
+    }
+    
+    #[test]
+    fn compressed_files_read_back_under_their_original_names() {
+        let dir = heal_dir().join("compression-test");
+        let _ = fs::remove_dir_all(&dir);
+        let source = dir.join("source");
+        fs::create_dir_all(source.join("contracts")).unwrap();
+        let contract = "name: compressed\n".repeat(200);
+        fs::write(source.join("contracts").join("token.yaml"), &contract).unwrap();
+        fs::write(source.join("archive.zst"), "not really zstd").unwrap();
+        
+        let compressed = Storage { compression: Some(3), key: None };
+        let plain = Storage::default();
+        store_directory(&source, &dir.join("compressed"), &compressed).unwrap();
+        store_directory(&source, &dir.join("plain"), &plain).unwrap();
+        
+        let stored = dir.join("compressed").join("contracts").join("token.yaml.zst");
+        assert!(fs::metadata(&stored).unwrap().len() < contract.len() as u64);
+        assert_eq!(compressed.read(&stored).unwrap(), contract.as_bytes());
+        assert_eq!(compressed.original_name("token.yaml.zst"), Some("token.yaml"));
+        assert_eq!(compressed.original_name("metadata.json"), None);
+        
+        // A .zst file in an uncompressed snapshot is its own content
+        assert_eq!(plain.original_name("archive.zst"), None);
+        assert_eq!(plain.read(&dir.join("plain").join("archive.zst")).unwrap(), b"not really zstd");
+        
+        // Hashes cover original content under original names, however it is stored
+        assert_eq!(calculate_file_hashes(&dir.join("compressed"), &compressed).unwrap(),
+                   calculate_file_hashes(&dir.join("plain"), &plain).unwrap());
+        assert_eq!(calculate_snapshot_hash(&dir.join("compressed"), &compressed).unwrap(),
+                   calculate_snapshot_hash(&dir.join("plain"), &plain).unwrap());
+        
+        compressed.extract(&stored, &dir.join("extracted.yaml")).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("extracted.yaml")).unwrap(), contract);
+    }
+    
+    #[test]
+    fn encrypted_files_need_the_key_to_read() {
+        let dir = heal_dir().join("encryption-test");
+        let _ = fs::remove_dir_all(&dir);
+        let source = dir.join("source");
+        fs::create_dir_all(source.join("auth")).unwrap();
+        let secret = "credential: hunter2\n".repeat(50);
+        fs::write(source.join("auth").join("token.yaml"), &secret).unwrap();
+        
+        let sealed = Storage { compression: Some(3), key: Some([5; 32]) };
+        let plain = Storage::default();
+        store_directory(&source, &dir.join("sealed"), &sealed).unwrap();
+        store_directory(&source, &dir.join("plain"), &plain).unwrap();
+        
+        // Compressed, then encrypted, so nothing of the content shows
+        let stored = dir.join("sealed").join("auth").join("token.yaml.zst.enc");
+        let raw = fs::read(&stored).unwrap();
+        assert!(raw.len() < secret.len() && !raw.windows(7).any(|w| w == b"hunter2"));
+        assert_eq!(sealed.original_name("token.yaml.zst.enc"), Some("token.yaml"));
+        assert_eq!(sealed.original_name("token.yaml.zst"), None);
+        assert_eq!(sealed.read(&stored).unwrap(), secret.as_bytes());
+        
+        let wrong = Storage { key: Some([6; 32]), ..sealed };
+        assert!(wrong.read(&stored).is_err());
+        assert!(!wrong.same_as(&sealed));
+        
+        assert_eq!(calculate_file_hashes(&dir.join("sealed"), &sealed).unwrap(),
+                   calculate_file_hashes(&dir.join("plain"), &plain).unwrap());
+    }
+    
+    #[test]
+    fn compression_is_off_until_configured() {
+        let config = SnapshotConfig::default();
+        assert_eq!((config.compression(), config.compression_level), (None, 3));
+        
+        let config: SnapshotConfig = serde_json::from_str(r#"{"compression_enabled": true, "compression_level": 19}"#).unwrap();
+        assert_eq!(config.compression(), Some(19));
+        assert_eq!(config.storage().unwrap().stored_path(Path::new("a/b.json")), PathBuf::from("a/b.json.zst"));
+    }
+    
+    #[test]
+    fn container_snapshots_bring_back_data_and_registration() {
+        let image = constants::root_dir().join("snapshot-tests").join("container-image");
+        fs::create_dir_all(&image).unwrap();
+        let mut container = crate::matrixbox::app::default_manifest("snapshotted");
+        container.path = Some(image.clone());
+        crate::matrixbox::container::save_container(&container).unwrap();
+        fs::write(image.join("main.wasm"), b"\0asm\x01\0\0\0").unwrap();
+        
+        let id = registry::register_container(&container, &["--verbose"]).unwrap();
+        let state = kv::data_dir(&id).unwrap().join("files").join("state.txt");
+        fs::create_dir_all(state.parent().unwrap()).unwrap();
+        fs::write(&state, "v1").unwrap();
+        let first = snapshot_container(&id).unwrap();
+        fs::write(&state, "v2").unwrap();
+        let second = snapshot_container(&id).unwrap();
+        
+        // Newest first; both were taken within the same second, so age one
+        let first_metadata = container_snapshots_dir(&id).unwrap().join(&first).join("metadata.json");
+        let mut aged: ContainerSnapshot = serde_json::from_str(&fs::read_to_string(&first_metadata).unwrap()).unwrap();
+        aged.timestamp -= 60;
+        fs::write(&first_metadata, serde_json::to_string(&aged).unwrap()).unwrap();
+        let snapshots = list_container_snapshots(&id).unwrap();
+        assert_eq!(snapshots.iter().map(|s| s.id.as_str()).collect::<Vec<_>>(), [second.as_str(), first.as_str()]);
+        assert_eq!((snapshots[1].entry.name.as_str(), snapshots[1].entry.args.clone()), ("snapshotted", vec!["--verbose".to_string()]));
+        
+        assert!(snapshots.iter().all(|s| verify_container_snapshot(s).unwrap()));
+        fs::write(snapshots[0].path.join(CONTAINER_DATA_DIR).join("files").join("state.txt"), "tampered").unwrap();
+        assert!(!verify_container_snapshot(&snapshots[0]).unwrap());
+        
+        // A lost registry entry is put back under the same ID
+        registry::unregister_container(&id).unwrap();
+        restore_container_snapshot(&snapshots[1]).unwrap();
+        assert_eq!(fs::read_to_string(&state).unwrap(), "v1");
+        assert_eq!(registry::get_container(&id).unwrap().name, "snapshotted");
+        assert_eq!(registry::get_container_args(&id).unwrap(), ["--verbose"]);
+        registry::unregister_container(&id).unwrap();
+        
+        assert!(list_container_snapshots(&"never-snapshotted".to_string()).unwrap().is_empty());
+        assert!(list_container_snapshots(&"../escape".to_string()).is_err());
+    }
+    
+    #[test]
+    fn retention_must_keep_a_snapshot_when_limited() {
+        assert!(!RetentionPolicy::default().is_limited());
+        assert!(RetentionPolicy { max_age_days: Some(7), ..Default::default() }.is_limited());
+        
+        let none = RetentionPolicy { max_count: Some(0), ..Default::default() };
+        assert!(select_for_pruning(&none, None).is_err());
+        assert!(plan_prune(&none).is_err());
+        assert!(prune(none).is_err());
+        
+        // A dry run without limits plans no deletions
+        assert!(plan_prune(&RetentionPolicy::default()).unwrap().is_empty());
+    }
+    
+    #[test]
+    fn ring_slots_replace_the_oldest_and_follow_capacity_changes() {
+        for id in ["ring-1", "ring-2", "ring-3", "ring-4", "ring-5"] {
+            create_snapshot(id, "test", &SnapshotOptions::default()).unwrap();
+        }
+        let two = RingBufferPolicy { capacity: 2 };
+        ring_insert(&two, "ring-1").unwrap();
+        ring_insert(&two, "ring-2").unwrap();
+        assert_eq!(ring_entries().unwrap(), ["ring-2", "ring-1"]);
+        
+        // The third snapshot takes the oldest one's slot and deletes it
+        ring_insert(&two, "ring-3").unwrap();
+        assert_eq!(ring_entries().unwrap(), ["ring-3", "ring-2"]);
+        let state = load_ring().unwrap();
+        assert_eq!(state.head, Some(0));
+        assert_eq!(state.slots, [Some("ring-3".to_string()), Some("ring-2".to_string())]);
+        assert!(!snapshots_dir().join("ring-1").exists());
+        assert!(!heal_dir().join(RING_EVICTED_DIR).join("ring-1").exists());
+        
+        // Growing keeps every snapshot, oldest in slot 0
+        ring_insert(&RingBufferPolicy { capacity: 3 }, "ring-4").unwrap();
+        assert_eq!(ring_entries().unwrap(), ["ring-4", "ring-3", "ring-2"]);
+        assert_eq!(load_ring().unwrap().head, Some(2));
+        
+        // Shrinking evicts the snapshots that no longer fit
+        ring_insert(&RingBufferPolicy { capacity: 1 }, "ring-5").unwrap();
+        assert_eq!(ring_entries().unwrap(), ["ring-5"]);
+        for id in ["ring-2", "ring-3", "ring-4"] {
+            assert!(!snapshots_dir().join(id).exists());
+        }
+        
+        // Snapshots deleted behind the ring's back are skipped
+        fs::remove_dir_all(snapshots_dir().join("ring-5")).unwrap();
+        assert!(ring_entries().unwrap().is_empty());
+        assert_eq!(load_ring().unwrap().slots, [Some("ring-5".to_string())]);
+        
+        let stale = RingState { head: Some(4), slots: vec![Some("ring-1".to_string())] };
+        assert!(ring_order(&stale).is_empty());
+    }
+}
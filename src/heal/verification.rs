@@ -5,13 +5,59 @@ use std::fs;
 use blake3;
 
 use crate::core::constants;
+use super::snapshot;
+
+/// Result of checking a snapshot against its recorded content hash
+#[derive(Debug, Clone)]
+pub struct SnapshotVerification {
+    /// Snapshot ID
+    pub snapshot_id: String,
+    
+    /// Content hash recorded when the snapshot was taken
+    pub stored_hash: String,
+    
+    /// Content hash of the snapshot as it is now
+    pub computed_hash: String,
+    
+    /// Whether the ZK proof of the recorded hash checks out, or `None` if
+    /// the snapshot has no proof
+    pub proof_verified: Option<bool>,
+    
+    /// Files that changed since the snapshot was taken, where known
+    pub mismatched_files: Vec<String>,
+}
+
+impl SnapshotVerification {
+    /// Whether the snapshot is unchanged and its proof, if any, is valid
+    pub fn is_intact(&self) -> bool {
+        self.stored_hash == self.computed_hash && self.proof_verified != Some(false)
+    }
+}
+
+impl std::fmt::Display for SnapshotVerification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Snapshot:      {}", self.snapshot_id)?;
+        writeln!(f, "Stored hash:   {}", self.stored_hash)?;
+        writeln!(f, "Computed hash: {}", self.computed_hash)?;
+        let proof = match self.proof_verified {
+            Some(true) => "valid",
+            Some(false) => "INVALID",
+            None => "none",
+        };
+        writeln!(f, "ZK proof:      {}", proof)?;
+        for file in &self.mismatched_files {
+            writeln!(f, "Mismatch:      {}", file)?;
+        }
+        write!(f, "Result:        {}", if self.is_intact() { "intact" } else { "TAMPERED" })
+    }
+}
 
 /// Initialize the verification system
 pub fn init() -> Result<()> {
     info!("Initializing verification system");
     
     // Ensure verification directory exists
-    let verify_dir = PathBuf::from(constants::ROOT_DIR)
+    let verify_dir = constants::root_dir()
         .join(".heal")
         .join("verification");
     
@@ -44,7 +90,7 @@ pub fn verify_core_components() -> Result<bool> {
     all_valid &= verify_directory_exists(constants::CONTAINER_DIR)?;
     
     // Check core configuration files
-    let core_config = PathBuf::from(constants::ROOT_DIR)
+    let core_config = constants::root_dir()
         .join(constants::CORE_DIR)
         .join("config.yaml");
     
@@ -58,10 +104,56 @@ pub fn verify_core_components() -> Result<bool> {
     all_valid &= verify_file_integrity(constants::ZK_DIR, "registry.json")?;
     all_valid &= verify_file_integrity(constants::CONTAINER_DIR, "registry.json")?;
     
+    // Snapshots must still match their recorded content hashes
+    for info in super::list_snapshots()? {
+        match verify_snapshot(&info.id) {
+            Ok(integrity) if integrity.is_intact() => {
+                debug!("Snapshot integrity verified: {}", info.id);
+            }
+            Ok(integrity) => {
+                warn!("Snapshot integrity check failed: {}", info.id);
+                warn!("  Expected: {}", integrity.stored_hash);
+                warn!("  Actual: {}", integrity.computed_hash);
+                if integrity.proof_verified == Some(false) {
+                    warn!("  ZK proof of the content hash is invalid");
+                }
+                all_valid = false;
+            }
+            Err(e) => {
+                warn!("Failed to verify snapshot {}: {:#}", info.id, e);
+                all_valid = false;
+            }
+        }
+    }
+    
     info!("Core component verification complete: {}", all_valid);
     Ok(all_valid)
 }
 
+/// Check a snapshot against the content hash recorded in its metadata
+///
+/// The snapshot's files are hashed again and compared with the recorded
+/// hash. Snapshots taken with ZK enabled also carry a Groth16 proof of
+/// that hash, which is verified with `zk::verify_proof`.
+pub fn verify_snapshot(snapshot_id: &str) -> Result<SnapshotVerification> {
+    info!("Verifying snapshot integrity: {}", snapshot_id);
+    
+    let hashes = snapshot::snapshot_hashes(snapshot_id)?;
+    let proof_verified = match &hashes.proof {
+        Some(proof) => Some(crate::zk::verify_proof(hashes.stored.as_bytes(), proof, snapshot::SNAPSHOT_PROOF_OPERATION)
+            .with_context(|| format!("Failed to verify the proof of snapshot {}", snapshot_id))?),
+        None => None,
+    };
+    
+    Ok(SnapshotVerification {
+        snapshot_id: snapshot_id.to_string(),
+        stored_hash: hashes.stored,
+        computed_hash: hashes.computed,
+        proof_verified,
+        mismatched_files: hashes.mismatched_files,
+    })
+}
+
 /// Verify container state
 pub fn verify_container_state() -> Result<bool> {
     info!("Verifying container state");
@@ -69,7 +161,7 @@ pub fn verify_container_state() -> Result<bool> {
     let mut all_valid = true;
     
     // Check container registry
-    let registry_path = PathBuf::from(constants::ROOT_DIR)
+    let registry_path = constants::root_dir()
         .join(constants::CONTAINER_DIR)
         .join("registry.json");
     
@@ -94,7 +186,7 @@ pub fn verify_container_state() -> Result<bool> {
     }
     
     // Check container directories
-    let containers_dir = PathBuf::from(constants::ROOT_DIR)
+    let containers_dir = constants::root_dir()
         .join(constants::CONTAINER_DIR)
         .join("instances");
     
@@ -114,7 +206,7 @@ pub fn verify_zk_contract_state() -> Result<bool> {
     let mut all_valid = true;
     
     // Check ZK contracts directory
-    let contracts_dir = PathBuf::from(constants::ROOT_DIR)
+    let contracts_dir = constants::root_dir()
         .join(constants::ZK_DIR)
         .join("contracts");
     
@@ -152,7 +244,7 @@ pub fn verify_zk_contract_state() -> Result<bool> {
     }
     
     // Check ZK verification keys
-    let keys_dir = PathBuf::from(constants::ROOT_DIR)
+    let keys_dir = constants::root_dir()
         .join(constants::ZK_DIR)
         .join("keys");
     
@@ -167,7 +259,7 @@ pub fn verify_zk_contract_state() -> Result<bool> {
 
 /// Verify a directory exists
 fn verify_directory_exists(dir_name: &str) -> Result<bool> {
-    let dir_path = PathBuf::from(constants::ROOT_DIR).join(dir_name);
+    let dir_path = constants::root_dir().join(dir_name);
     
     if dir_path.exists() && dir_path.is_dir() {
         debug!("Directory exists: {:?}", dir_path);
@@ -180,11 +272,11 @@ fn verify_directory_exists(dir_name: &str) -> Result<bool> {
 
 /// Verify file integrity using stored hash
 fn verify_file_integrity(dir_name: &str, file_name: &str) -> Result<bool> {
-    let file_path = PathBuf::from(constants::ROOT_DIR)
+    let file_path = constants::root_dir()
         .join(dir_name)
         .join(file_name);
     
-    let hash_path = PathBuf::from(constants::ROOT_DIR)
+    let hash_path = constants::root_dir()
         .join(".heal")
         .join("verification")
         .join(format!("{}_{}.hash", dir_name.replace("/", "_"), file_name));
@@ -237,11 +329,11 @@ fn compute_file_hash(file_path: &Path) -> Result<String> {
 
 /// Update stored hash for a file
 pub fn update_file_hash(dir_name: &str, file_name: &str) -> Result<()> {
-    let file_path = PathBuf::from(constants::ROOT_DIR)
+    let file_path = constants::root_dir()
         .join(dir_name)
         .join(file_name);
     
-    let hash_path = PathBuf::from(constants::ROOT_DIR)
+    let hash_path = constants::root_dir()
         .join(".heal")
         .join("verification")
         .join(format!("{}_{}.hash", dir_name.replace("/", "_"), file_name));
@@ -263,3 +355,34 @@ pub fn update_file_hash(dir_name: &str, file_name: &str) -> Result<()> {
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn verification(computed_hash: &str, proof_verified: Option<bool>) -> SnapshotVerification {
+        SnapshotVerification {
+            snapshot_id: "verified".to_string(),
+            stored_hash: "aaaa".to_string(),
+            computed_hash: computed_hash.to_string(),
+            proof_verified,
+            mismatched_files: Vec::new(),
+        }
+    }
+    
+    #[test]
+    fn snapshots_are_intact_only_with_matching_hashes_and_no_bad_proof() {
+        assert!(verification("aaaa", None).is_intact());
+        assert!(verification("aaaa", Some(true)).is_intact());
+        assert!(!verification("aaaa", Some(false)).is_intact());
+        assert!(!verification("bbbb", Some(true)).is_intact());
+        
+        let mut tampered = verification("bbbb", None);
+        tampered.mismatched_files.push("zk/registry.json".to_string());
+        let report = tampered.to_string();
+        assert!(report.contains("ZK proof:      none\n"));
+        assert!(report.contains("Mismatch:      zk/registry.json\n"));
+        assert!(report.ends_with("Result:        TAMPERED"));
+        assert!(verification("aaaa", Some(true)).to_string().ends_with("Result:        intact"));
+    }
+}
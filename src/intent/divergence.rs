@@ -0,0 +1,164 @@
+// SentientOS Intent Replay Divergence Detection
+// Compares replayed event outcomes against the originally recorded ones
+
+use std::fmt;
+use serde::{Serialize, Deserialize};
+
+use super::trace::{TraceOutcome, TraceRecord};
+
+/// Result of comparing a single replayed event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventComparison {
+    /// Replayed outcomes match the recorded ones
+    Matched,
+    
+    /// Replayed outcomes differ from the recorded ones
+    Diverged(Vec<String>),
+    
+    /// Outcomes could not be compared
+    Uncomparable(String),
+}
+
+/// Comparison entry for one event in the divergence report
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventReport {
+    /// Event timestamp
+    pub timestamp: u64,
+    
+    /// Event type
+    pub event_type: String,
+    
+    /// Event details
+    pub details: String,
+    
+    /// Comparison result
+    pub comparison: EventComparison,
+}
+
+/// Divergence report for a replayed session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DivergenceReport {
+    /// Session ID that was replayed
+    pub session_id: String,
+    
+    /// Per-event comparison results
+    pub events: Vec<EventReport>,
+}
+
+impl DivergenceReport {
+    /// Create an empty report for a session
+    pub fn new(session_id: &str) -> Self {
+        Self {
+            session_id: session_id.to_string(),
+            events: Vec::new(),
+        }
+    }
+    
+    /// Number of events whose outcomes matched
+    pub fn matched_count(&self) -> usize {
+        self.events.iter().filter(|e| e.comparison == EventComparison::Matched).count()
+    }
+    
+    /// Number of events whose outcomes diverged
+    pub fn diverged_count(&self) -> usize {
+        self.events.iter().filter(|e| matches!(e.comparison, EventComparison::Diverged(_))).count()
+    }
+    
+    /// Number of events that could not be compared
+    pub fn uncomparable_count(&self) -> usize {
+        self.events.iter().filter(|e| matches!(e.comparison, EventComparison::Uncomparable(_))).count()
+    }
+    
+    /// Whether any event diverged
+    pub fn has_divergence(&self) -> bool {
+        self.diverged_count() > 0
+    }
+}
+
+impl fmt::Display for DivergenceReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Replay divergence report for session: {}", self.session_id)?;
+        
+        for event in &self.events {
+            match &event.comparison {
+                EventComparison::Matched => {
+                    writeln!(f, "  [MATCH]     {}: {}", event.event_type, event.details)?;
+                }
+                EventComparison::Diverged(differences) => {
+                    writeln!(f, "  [DIVERGED]  {}: {}", event.event_type, event.details)?;
+                    for difference in differences {
+                        writeln!(f, "      - {}", difference)?;
+                    }
+                }
+                EventComparison::Uncomparable(reason) => {
+                    writeln!(f, "  [SKIPPED]   {}: {} ({})", event.event_type, event.details, reason)?;
+                }
+            }
+        }
+        
+        write!(f, "Matched: {}, Diverged: {}, Not comparable: {}",
+            self.matched_count(), self.diverged_count(), self.uncomparable_count())
+    }
+}
+
+/// Compare the trace records of an original event against those of its replay
+pub fn compare_outcomes(original: &[TraceRecord], replayed: &[TraceRecord]) -> EventComparison {
+    if original.is_empty() {
+        return EventComparison::Uncomparable("no outcomes recorded for original event".to_string());
+    }
+    
+    let original = normalize(original);
+    let replayed = normalize(replayed);
+    
+    let mut differences = Vec::new();
+    
+    compare_field("exit code", &original.exit_codes, &replayed.exit_codes, &mut differences);
+    compare_field("proof post-state hash", &original.post_state_hashes, &replayed.post_state_hashes, &mut differences);
+    compare_field("created container", &original.containers, &replayed.containers, &mut differences);
+    
+    if differences.is_empty() {
+        EventComparison::Matched
+    } else {
+        EventComparison::Diverged(differences)
+    }
+}
+
+/// Outcomes of an event reduced to the values that are comparable across runs
+struct NormalizedOutcomes {
+    exit_codes: Vec<String>,
+    post_state_hashes: Vec<String>,
+    containers: Vec<String>,
+}
+
+/// Normalize trace records for comparison
+///
+/// Proof IDs and container IDs are generated per run, so proofs are compared
+/// by post-state hash and containers by their order of creation.
+fn normalize(records: &[TraceRecord]) -> NormalizedOutcomes {
+    let mut outcomes = NormalizedOutcomes {
+        exit_codes: Vec::new(),
+        post_state_hashes: Vec::new(),
+        containers: Vec::new(),
+    };
+    
+    for record in records {
+        match &record.outcome {
+            TraceOutcome::ExitCode { code } => outcomes.exit_codes.push(code.to_string()),
+            TraceOutcome::Proof { post_state_hash, .. } => outcomes.post_state_hashes.push(post_state_hash.clone()),
+            TraceOutcome::ContainerCreated { .. } => {
+                let index = outcomes.containers.len();
+                outcomes.containers.push(format!("container#{}", index));
+            }
+        }
+    }
+    
+    outcomes
+}
+
+/// Compare one outcome field and record any differences
+fn compare_field(name: &str, original: &[String], replayed: &[String], differences: &mut Vec<String>) {
+    if original != replayed {
+        differences.push(format!("{}: recorded {:?}, replayed {:?}", name, original, replayed));
+    }
+}
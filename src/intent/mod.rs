@@ -1,17 +1,21 @@
 // SentientOS Intent System
 // Provides developer intent logging & replay
 
+pub mod trace;
+pub mod divergence;
+
 use anyhow::{Result, Context};
 use tracing::{info, debug, warn, error};
 use std::path::{Path, PathBuf};
 use std::fs;
-use std::time::{SystemTime, UNIX_EPOCH, Duration};
+use std::time::{SystemTime, UNIX_EPOCH, Duration, Instant};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
 
 use crate::core::constants;
+use divergence::{DivergenceReport, EventComparison, EventReport};
 
 // Whether recording is active
 static RECORDING_ACTIVE: AtomicBool = AtomicBool::new(false);
@@ -24,7 +28,7 @@ pub fn init() -> Result<()> {
     info!("Initializing SentientOS intent system");
     
     // Create intent system directories
-    let intent_dir = PathBuf::from(constants::ROOT_DIR).join(".intent");
+    let intent_dir = constants::root_dir().join(".intent");
     fs::create_dir_all(&intent_dir)?;
     
     let sessions_dir = intent_dir.join("sessions");
@@ -66,7 +70,7 @@ pub fn start_recording() -> Result<String> {
     let session_id = format!("session-{}", timestamp);
     
     // Create session directory
-    let session_dir = PathBuf::from(constants::ROOT_DIR)
+    let session_dir = constants::root_dir()
         .join(".intent")
         .join("sessions")
         .join(&session_id);
@@ -83,7 +87,7 @@ pub fn start_recording() -> Result<String> {
     
     // Write metadata
     let metadata_path = session_dir.join("metadata.json");
-    fs::write(&metadata_path, serde_json::to_string_pretty(&metadata)?)?;
+    crate::core::fs::write_atomic(&metadata_path, serde_json::to_string_pretty(&metadata)?.as_bytes())?;
     
     // Set current session
     *CURRENT_SESSION.lock().unwrap() = Some(session_id.clone());
@@ -110,20 +114,19 @@ pub fn stop_recording() -> Result<()> {
     info!("Stopping developer intent recording session: {}", session_id);
     
     // Update session metadata
-    let session_dir = PathBuf::from(constants::ROOT_DIR)
+    let session_dir = constants::root_dir()
         .join(".intent")
         .join("sessions")
         .join(&session_id);
     
     let metadata_path = session_dir.join("metadata.json");
-    let metadata_str = fs::read_to_string(&metadata_path)?;
-    let mut metadata: SessionMetadata = serde_json::from_str(&metadata_str)?;
+    let mut metadata: SessionMetadata = crate::core::fs::read_json_or_backup(&metadata_path)?;
     
     let now: DateTime<Utc> = SystemTime::now().into();
     metadata.completed_at = Some(now.to_rfc3339());
     
     // Write updated metadata
-    fs::write(&metadata_path, serde_json::to_string_pretty(&metadata)?)?;
+    crate::core::fs::write_atomic(&metadata_path, serde_json::to_string_pretty(&metadata)?.as_bytes())?;
     
     // Clear current session
     *CURRENT_SESSION.lock().unwrap() = None;
@@ -137,6 +140,27 @@ pub fn stop_recording() -> Result<()> {
 
 /// Record an intent event
 pub fn record_event(event_type: &str, details: &str) -> Result<()> {
+    record_event_with(event_type, details, EventContext::default())
+}
+
+/// Optional context recorded along with an intent event
+#[derive(Debug, Clone, Default)]
+pub struct EventContext {
+    /// Working directory the event happened in
+    pub working_dir: Option<String>,
+    
+    /// How the event ended, e.g. `success` or `error: ...`
+    pub outcome: Option<String>,
+    
+    /// How long it took, in milliseconds
+    pub duration_ms: Option<u64>,
+    
+    /// Argv of a command event, kept whole so it can be replayed exactly
+    pub args: Option<Vec<String>>,
+}
+
+/// Record an intent event with context
+pub fn record_event_with(event_type: &str, details: &str, context: EventContext) -> Result<()> {
     if !RECORDING_ACTIVE.load(Ordering::SeqCst) {
         // No recording in progress, just ignore
         return Ok(());
@@ -158,33 +182,107 @@ pub fn record_event(event_type: &str, details: &str) -> Result<()> {
         timestamp,
         event_type: event_type.to_string(),
         details: details.to_string(),
+        correlation_id: trace::current_correlation(),
+        working_dir: context.working_dir,
+        outcome: context.outcome,
+        duration_ms: context.duration_ms,
+        args: context.args,
     };
     
-    // Write event to session directory
-    let session_dir = PathBuf::from(constants::ROOT_DIR)
+    let session_dir = constants::root_dir()
         .join(".intent")
         .join("sessions")
         .join(&session_id);
+    let metadata_path = session_dir.join("metadata.json");
+    let mut metadata: SessionMetadata = crate::core::fs::read_json_or_backup(&metadata_path)?;
     
-    let event_path = session_dir.join(format!("event-{}.json", timestamp));
+    // Events in the same second are told apart, and ordered, by their sequence number
+    let event_path = session_dir.join(format!("event-{}-{:06}.json", timestamp, metadata.events_count));
     fs::write(&event_path, serde_json::to_string_pretty(&event)?)?;
     
     // Update metadata event count
-    let metadata_path = session_dir.join("metadata.json");
-    let metadata_str = fs::read_to_string(&metadata_path)?;
-    let mut metadata: SessionMetadata = serde_json::from_str(&metadata_str)?;
     metadata.events_count += 1;
-    fs::write(&metadata_path, serde_json::to_string_pretty(&metadata)?)?;
+    crate::core::fs::write_atomic(&metadata_path, serde_json::to_string_pretty(&metadata)?.as_bytes())?;
     
     Ok(())
 }
 
+/// A CLI command recorded as `cli_command` events when it starts and ends
+///
+/// Recording never fails the command: without an active session nothing is
+/// written, and write errors are only logged.
+pub struct CommandRecording {
+    /// Full argv
+    args: Vec<String>,
+    
+    /// Full argv, space separated
+    details: String,
+    
+    /// When the command started
+    started: Instant,
+}
+
+impl CommandRecording {
+    /// Record the start of a command with its argv and working directory
+    pub fn start(args: &[String]) -> Self {
+        let details = args.join(" ");
+        let context = EventContext {
+            working_dir: std::env::current_dir().ok().map(|dir| dir.to_string_lossy().to_string()),
+            args: Some(args.to_vec()),
+            ..Default::default()
+        };
+        if let Err(e) = record_event_with("cli_command", &details, context) {
+            warn!("Failed to record start of command: {:#}", e);
+        }
+        Self { args: args.to_vec(), details, started: Instant::now() }
+    }
+    
+    /// Record how the command ended and how long it took
+    pub fn finish(&self, result: &Result<()>) {
+        let outcome = match result {
+            Ok(()) => "success".to_string(),
+            Err(e) => format!("error: {:#}", e),
+        };
+        let context = EventContext {
+            working_dir: None,
+            outcome: Some(outcome),
+            duration_ms: Some(self.started.elapsed().as_millis() as u64),
+            args: Some(self.args.clone()),
+        };
+        if let Err(e) = record_event_with("cli_command", &self.details, context) {
+            warn!("Failed to record end of command: {:#}", e);
+        }
+    }
+}
+
+/// Options controlling how a session is replayed
+#[derive(Debug, Clone, Default)]
+pub struct ReplayOptions {
+    /// Re-execute recorded CLI commands instead of only logging them
+    pub execute: bool,
+    
+    /// Compare replayed outcomes against the recorded runtime trace
+    pub check: bool,
+}
+
 /// Replay a recorded session
 pub fn replay_session(session_id: &str) -> Result<()> {
+    replay_session_with(session_id, &ReplayOptions::default())?;
+    Ok(())
+}
+
+/// Replay a recorded session with options
+///
+/// Returns a divergence report when `check` is enabled.
+pub fn replay_session_with(session_id: &str, options: &ReplayOptions) -> Result<Option<DivergenceReport>> {
     info!("Replaying intent session: {}", session_id);
     
+    if options.execute && RECORDING_ACTIVE.load(Ordering::SeqCst) {
+        anyhow::bail!("Cannot replay with execution while a recording session is active");
+    }
+    
     // Get session directory
-    let session_dir = PathBuf::from(constants::ROOT_DIR)
+    let session_dir = constants::root_dir()
         .join(".intent")
         .join("sessions")
         .join(session_id);
@@ -195,37 +293,120 @@ pub fn replay_session(session_id: &str) -> Result<()> {
     
     // Read session metadata
     let metadata_path = session_dir.join("metadata.json");
-    let metadata_str = fs::read_to_string(&metadata_path)?;
-    let metadata: SessionMetadata = serde_json::from_str(&metadata_str)?;
+    let metadata: SessionMetadata = crate::core::fs::read_json_or_backup(&metadata_path)?;
     
     info!("Replaying session: {} (events: {})", session_id, metadata.events_count);
     
-    // Collect all events
-    let mut events = Vec::new();
+    // Collect all events, in file name order so sequence numbers break ties
+    let mut paths = Vec::new();
     for entry in fs::read_dir(&session_dir)? {
         let entry = entry?;
         let path = entry.path();
         
         if path.is_file() && path.file_name().and_then(|n| n.to_str()).map_or(false, |n| n.starts_with("event-")) {
-            let content = fs::read_to_string(&path)?;
-            let event: IntentEvent = serde_json::from_str(&content)?;
-            events.push(event);
+            paths.push(path);
         }
     }
+    paths.sort();
+    
+    let mut events = Vec::new();
+    for path in paths {
+        let content = fs::read_to_string(&path)?;
+        let event: IntentEvent = serde_json::from_str(&content)?;
+        events.push(event);
+    }
     
     // Sort events by timestamp
     events.sort_by_key(|e| e.timestamp);
     
+    let mut report = DivergenceReport::new(session_id);
+    
     // Replay events
     for event in events {
         info!("[REPLAY] {}: {}", event.event_type, event.details);
         
-        // In a real implementation, we would actually execute the intent
-        // For now, we just log it
+        if !options.execute {
+            continue;
+        }
+        
+        let comparison = match replay_event(&event)? {
+            Some(replayed) => {
+                match &event.correlation_id {
+                    Some(original_id) => {
+                        let original = trace::records_for(original_id)?;
+                        divergence::compare_outcomes(&original, &replayed)
+                    }
+                    None => EventComparison::Uncomparable("event has no correlation id".to_string()),
+                }
+            }
+            None => EventComparison::Uncomparable("event type is not replayable".to_string()),
+        };
+        
+        if let EventComparison::Diverged(_) = &comparison {
+            warn!("Replayed event diverged: {}: {}", event.event_type, event.details);
+        }
+        
+        report.events.push(EventReport {
+            timestamp: event.timestamp,
+            event_type: event.event_type.clone(),
+            details: event.details.clone(),
+            comparison,
+        });
     }
     
     info!("Completed replaying session: {}", session_id);
-    Ok(())
+    
+    if !options.check {
+        return Ok(None);
+    }
+    
+    // Keep the report next to other replay artifacts
+    let report_path = constants::root_dir()
+        .join(".intent")
+        .join("replay")
+        .join(format!("{}-divergence.json", session_id));
+    fs::write(&report_path, serde_json::to_string_pretty(&report)?)
+        .with_context(|| format!("Failed to write divergence report: {:?}", report_path))?;
+    
+    Ok(Some(report))
+}
+
+/// Execute a recorded event and return the trace records it produced
+///
+/// Returns `None` for events that cannot be re-executed.
+fn replay_event(event: &IntentEvent) -> Result<Option<Vec<trace::TraceRecord>>> {
+    // A command is replayed from its start event; its end event only records the outcome
+    if event.event_type != "cli_command" || event.outcome.is_some() {
+        return Ok(None);
+    }
+    
+    // Sessions recorded before the argv was stored only have it space separated
+    let args: Vec<String> = match &event.args {
+        Some(args) => args.clone(),
+        None => event.details.split_whitespace().map(String::from).collect(),
+    };
+    
+    // Replaying intent commands would recurse into the intent system itself
+    if args.iter().any(|a| a == "intent") {
+        return Ok(None);
+    }
+    
+    // Replay runs inside the replaying command's own scope, which is put back afterwards
+    let outer_correlation = trace::current_correlation();
+    let correlation_id = trace::begin_correlation()?;
+    let result = crate::cli::dispatch_command(args);
+    trace::record_outcome(trace::TraceOutcome::ExitCode { code: crate::cli::exit_code(&result) });
+    trace::end_correlation();
+    trace::restore_correlation(outer_correlation);
+    
+    if let Err(e) = result {
+        debug!("Replayed command failed: {:?}", e);
+    }
+    
+    // The replay's trace is only needed for this comparison
+    let records = trace::records_for(&correlation_id)?;
+    trace::discard(&correlation_id)?;
+    Ok(Some(records))
 }
 
 /// Session metadata
@@ -255,13 +436,33 @@ struct IntentEvent {
     
     /// Event details
     details: String,
+    
+    /// Correlation ID linking the event to runtime trace records
+    #[serde(default)]
+    correlation_id: Option<String>,
+    
+    /// Working directory the event happened in
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    working_dir: Option<String>,
+    
+    /// How the event ended, e.g. `success` or `error: ...`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    outcome: Option<String>,
+    
+    /// How long it took, in milliseconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    duration_ms: Option<u64>,
+    
+    /// Argv of a command event, as a JSON array
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    args: Option<Vec<String>>,
 }
 
 /// List all recorded sessions
 pub fn list_sessions() -> Result<Vec<SessionMetadata>> {
     info!("Listing intent sessions");
     
-    let sessions_dir = PathBuf::from(constants::ROOT_DIR)
+    let sessions_dir = constants::root_dir()
         .join(".intent")
         .join("sessions");
     
@@ -271,8 +472,7 @@ pub fn list_sessions() -> Result<Vec<SessionMetadata>> {
         if entry.path().is_dir() {
             let metadata_path = entry.path().join("metadata.json");
             if metadata_path.exists() {
-                let metadata_str = fs::read_to_string(&metadata_path)?;
-                let metadata: SessionMetadata = serde_json::from_str(&metadata_str)?;
+                let metadata: SessionMetadata = crate::core::fs::read_json_or_backup(&metadata_path)?;
                 sessions.push(metadata);
             }
         }
@@ -281,3 +481,65 @@ pub fn list_sessions() -> Result<Vec<SessionMetadata>> {
     info!("Found {} intent sessions", sessions.len());
     Ok(sessions)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn session_events(session_id: &str) -> Vec<(String, IntentEvent)> {
+        let session_dir = constants::root_dir().join(".intent").join("sessions").join(session_id);
+        let mut events: Vec<(String, IntentEvent)> = fs::read_dir(session_dir).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.file_name().unwrap().to_string_lossy().starts_with("event-"))
+            .map(|path| {
+                let name = path.file_name().unwrap().to_string_lossy().to_string();
+                (name, serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap())
+            })
+            .collect();
+        events.sort_by(|a, b| a.0.cmp(&b.0));
+        events
+    }
+    
+    #[test]
+    fn commands_are_recorded_when_they_start_and_end() {
+        let args: Vec<String> = ["sentctl", "matrixbox", "ls", "--all"].iter().map(|s| s.to_string()).collect();
+        let session_id = start_recording().unwrap();
+        let recording = CommandRecording::start(&args);
+        recording.finish(&Err(anyhow::anyhow!("boom")));
+        stop_recording().unwrap();
+        
+        let events = session_events(&session_id);
+        let events: Vec<&(String, IntentEvent)> = events.iter().filter(|(_, e)| e.details == "sentctl matrixbox ls --all").collect();
+        assert_eq!(events.len(), 2);
+        
+        // Sequence numbers keep the two apart even within one second
+        let (start_file, start) = events[0];
+        let (end_file, end) = events[1];
+        assert!(start_file.ends_with("-000000.json") && end_file.ends_with("-000001.json"), "{} {}", start_file, end_file);
+        
+        assert_eq!(start.event_type, "cli_command");
+        assert_eq!(start.args.as_ref(), Some(&args));
+        assert!(start.working_dir.is_some());
+        assert_eq!((start.outcome.as_deref(), start.duration_ms), (None, None));
+        
+        assert_eq!(end.outcome.as_deref(), Some("error: boom"));
+        assert!(end.duration_ms.is_some() && end.working_dir.is_none());
+        
+        // Only the start of a command is re-executed
+        assert!(replay_event(end).unwrap().is_none());
+    }
+    
+    #[test]
+    fn events_from_before_command_context_still_load() {
+        let event: IntentEvent = serde_json::from_str(
+            r#"{"timestamp": 1700000000, "event_type": "cli_command", "details": "sentctl intent replay s1"}"#).unwrap();
+        assert!(event.working_dir.is_none() && event.outcome.is_none() && event.duration_ms.is_none());
+        assert!(event.args.is_none() && event.correlation_id.is_none());
+        
+        // Intent commands are never replayed, even from space-separated details
+        assert!(replay_event(&event).unwrap().is_none());
+        
+        let serialized = serde_json::to_value(&event).unwrap();
+        assert!(serialized.get("outcome").is_none() && serialized.get("args").is_none());
+    }
+}
@@ -0,0 +1,224 @@
+// SentientOS Intent Runtime Trace
+// Correlates intent events with the runtime trace records they produce
+
+use anyhow::{Result, Context};
+use tracing::{debug, warn};
+use std::path::{Path, PathBuf};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use crate::core::constants;
+
+// Constants
+/// Trace files kept; the oldest are pruned as new scopes begin
+const MAX_TRACE_FILES: usize = 1000;
+
+// Correlation ID of the operation currently being executed
+static CURRENT_CORRELATION: Mutex<Option<String>> = Mutex::new(None);
+
+/// Outcome recorded in the runtime trace for a correlated operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TraceOutcome {
+    /// Exit code of a CLI command (0 on success)
+    ExitCode { code: i32 },
+    
+    /// A ZK proof was generated
+    Proof { proof_id: String, post_state_hash: String },
+    
+    /// A container was created
+    ContainerCreated { container_id: String },
+}
+
+/// A single runtime trace record
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceRecord {
+    /// Correlation ID linking the record to an intent event
+    pub correlation_id: String,
+    
+    /// Record timestamp
+    pub timestamp: u64,
+    
+    /// Recorded outcome
+    pub outcome: TraceOutcome,
+}
+
+/// Start a new correlation scope and return its ID
+pub fn begin_correlation() -> Result<String> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+    let correlation_id = format!("corr-{}", timestamp);
+    
+    *CURRENT_CORRELATION.lock().unwrap() = Some(correlation_id.clone());
+    crate::panic::crash::note_correlation(&correlation_id);
+    
+    if let Err(e) = prune_traces(&constants::root_dir().join(constants::RUNTIME_DIR), MAX_TRACE_FILES) {
+        warn!("Failed to prune runtime trace files: {:?}", e);
+    }
+    
+    debug!("Started correlation scope: {}", correlation_id);
+    Ok(correlation_id)
+}
+
+/// End the current correlation scope
+pub fn end_correlation() {
+    if let Some(id) = CURRENT_CORRELATION.lock().unwrap().take() {
+        debug!("Ended correlation scope: {}", id);
+    }
+}
+
+/// Make a scope current again once a nested one has ended
+pub fn restore_correlation(correlation_id: Option<String>) {
+    *CURRENT_CORRELATION.lock().unwrap() = correlation_id;
+}
+
+/// Get the ID of the current correlation scope, if any
+pub fn current_correlation() -> Option<String> {
+    CURRENT_CORRELATION.lock().unwrap().clone()
+}
+
+/// Record an outcome against the current correlation scope
+///
+/// Outcomes produced outside of a correlation scope are not traced.
+pub fn record_outcome(outcome: TraceOutcome) {
+    let correlation_id = match current_correlation() {
+        Some(id) => id,
+        None => return,
+    };
+    
+    if let Err(e) = append_record(&correlation_id, outcome) {
+        warn!("Failed to write runtime trace record for {}: {:?}", correlation_id, e);
+    }
+}
+
+/// Read all trace records for a correlation ID, in recording order
+pub fn records_for(correlation_id: &str) -> Result<Vec<TraceRecord>> {
+    let trace_path = trace_path(correlation_id);
+    if !trace_path.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let content = fs::read_to_string(&trace_path)
+        .with_context(|| format!("Failed to read trace file: {:?}", trace_path))?;
+    
+    let mut records = Vec::new();
+    for line in content.lines().filter(|l| !l.trim().is_empty()) {
+        let record: TraceRecord = serde_json::from_str(line)
+            .with_context(|| format!("Invalid trace record in {:?}", trace_path))?;
+        records.push(record);
+    }
+    
+    Ok(records)
+}
+
+/// Delete the trace file of a correlation ID once its records are no longer needed
+pub fn discard(correlation_id: &str) -> Result<()> {
+    let trace_path = trace_path(correlation_id);
+    match fs::remove_file(&trace_path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("Failed to delete trace file: {:?}", trace_path))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Delete all but the `keep` newest trace files in a directory
+///
+/// Age is read from the correlation ID, which embeds its start time; other
+/// files are left alone. Returns how many files were deleted.
+fn prune_traces(dir: &Path, keep: usize) -> Result<usize> {
+    if !dir.exists() {
+        return Ok(0);
+    }
+    
+    let mut traces: Vec<(u128, PathBuf)> = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter_map(|path| {
+            let name = path.file_name()?.to_str()?;
+            let started = name.strip_suffix(".trace")?.strip_prefix("corr-")?.parse().ok()?;
+            Some((started, path))
+        })
+        .collect();
+    if traces.len() <= keep {
+        return Ok(0);
+    }
+    
+    traces.sort();
+    let expired = traces.len() - keep;
+    for (_, path) in &traces[..expired] {
+        fs::remove_file(path).with_context(|| format!("Failed to delete trace file: {:?}", path))?;
+    }
+    debug!("Pruned {} runtime trace file(s)", expired);
+    Ok(expired)
+}
+
+/// Append a record to the trace file of a correlation ID
+fn append_record(correlation_id: &str, outcome: TraceOutcome) -> Result<()> {
+    let record = TraceRecord {
+        correlation_id: correlation_id.to_string(),
+        timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        outcome,
+    };
+    
+    let trace_path = trace_path(correlation_id);
+    if let Some(parent) = trace_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&trace_path)
+        .with_context(|| format!("Failed to open trace file: {:?}", trace_path))?;
+    
+    writeln!(file, "{}", serde_json::to_string(&record)?)?;
+    Ok(())
+}
+
+/// Path of the runtime trace file for a correlation ID
+fn trace_path(correlation_id: &str) -> PathBuf {
+    constants::root_dir()
+        .join(constants::RUNTIME_DIR)
+        .join(format!("{}.trace", correlation_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn only_the_newest_traces_are_kept() {
+        let dir = constants::root_dir().join("trace-retention");
+        fs::create_dir_all(&dir).unwrap();
+        for started in [30, 10, 50, 20, 40] {
+            fs::write(dir.join(format!("corr-{}.trace", started)), "").unwrap();
+        }
+        fs::write(dir.join("daemon.sock.trace"), "").unwrap();
+        fs::write(dir.join("corr-5.notes"), "").unwrap();
+        
+        assert_eq!(prune_traces(&dir, 2).unwrap(), 3);
+        let mut left: Vec<String> = fs::read_dir(&dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        left.sort();
+        assert_eq!(left, ["corr-40.trace", "corr-5.notes", "corr-50.trace", "daemon.sock.trace"]);
+        
+        assert_eq!(prune_traces(&dir, 2).unwrap(), 0);
+        assert_eq!(prune_traces(&dir.join("missing"), 2).unwrap(), 0);
+    }
+    
+    #[test]
+    fn discarded_traces_are_deleted() {
+        let correlation_id = "corr-discard-test";
+        append_record(correlation_id, TraceOutcome::ExitCode { code: 0 }).unwrap();
+        assert_eq!(records_for(correlation_id).unwrap().len(), 1);
+        
+        discard(correlation_id).unwrap();
+        assert!(!trace_path(correlation_id).exists());
+        assert!(records_for(correlation_id).unwrap().is_empty());
+        discard(correlation_id).unwrap();
+    }
+}
@@ -17,6 +17,17 @@ pub mod cli;
 pub mod network;
 pub mod store;
 pub mod package;
+pub mod trash;
+pub mod logs;
+pub mod maintenance;
+pub mod bench;
+pub mod setup;
+pub mod doctor;
+pub mod config;
+pub mod purge;
+pub mod update;
+pub mod status;
+pub mod daemon;
 
 /// Version of Sentinent OS
 pub const VERSION: &str = "0.1.0";
@@ -35,12 +46,21 @@ pub fn init(zk_enabled: bool) -> anyhow::Result<()> {
     // Initialize core directories
     core::fs::ensure_directories()?;
     
+    // Initialize node identity before anything that reports or gossips it
+    core::identity::init()?;
+    
     // Initialize the boot subsystem for hardware setup
     boot::init()?;
     
     // Initialize panic system early for fault tolerance
     panic::init()?;
     
+    // Initialize log shipping so early subsystem logs are captured
+    logs::init()?;
+    
+    // Start the shared worker pool before subsystems queue background work
+    core::workers::init()?;
+    
     // Initialize the runtime
     runtime::init(zk_enabled)?;
     
@@ -78,6 +98,12 @@ pub fn init(zk_enabled: bool) -> anyhow::Result<()> {
     // Initialize package manager
     package::init()?;
     
+    // Initialize trash and purge expired entries
+    trash::init()?;
+    
+    // Initialize maintenance mode after the container runtime
+    maintenance::init()?;
+    
     // Initialize CLI interface
     cli::init()?;
     
@@ -99,6 +125,11 @@ pub fn shutdown() -> anyhow::Result<()> {
     
     // Shutdown components in reverse order of initialization
     cli::shutdown()?;
+    
+    // Cancel background work before the subsystems it uses go away
+    core::workers::shutdown()?;
+    
+    maintenance::shutdown()?;
     package::shutdown()?;
     store::shutdown()?;
     intent::shutdown()?;
@@ -110,6 +141,7 @@ pub fn shutdown() -> anyhow::Result<()> {
     matrixbox::shutdown()?;
     auth::shutdown()?;
     runtime::shutdown()?;
+    logs::shutdown()?;
     panic::shutdown()?;
     boot::shutdown()?; // Shutdown boot subsystem last
     
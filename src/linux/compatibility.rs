@@ -24,7 +24,7 @@ pub fn init() -> Result<()> {
     info!("Initializing Linux compatibility layer");
     
     // Create necessary directories
-    let linux_dir = PathBuf::from(constants::ROOT_DIR).join(".linux");
+    let linux_dir = constants::root_dir().join(".linux");
     fs::create_dir_all(&linux_dir)?;
     
     let bin_dir = linux_dir.join("bin");
@@ -89,11 +89,7 @@ pub fn run_elf(path: &Path, args: &[&str]) -> Result<String> {
     let process_id = generate_process_id();
     
     // Create environment variables
-    let mut envs = HashMap::new();
-    envs.insert("PATH".to_string(), "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin".to_string());
-    envs.insert("HOME".to_string(), "/home/sentinent".to_string());
-    envs.insert("USER".to_string(), "sentinent".to_string());
-    envs.insert("TERM".to_string(), "xterm-256color".to_string());
+    let envs = linux_env();
     
     // Setup process
     let mut command = Command::new(path);
@@ -144,7 +140,7 @@ pub fn run_elf_in_container(path: &Path, args: &[&str], container_name: &str) ->
         Ok(container) => container,
         Err(_) => {
             // Create a new container
-            let container_path = PathBuf::from(constants::ROOT_DIR)
+            let container_path = constants::root_dir()
                 .join(".matrixbox")
                 .join("containers")
                 .join(container_name);
@@ -201,11 +197,7 @@ pub fn run_elf_in_container(path: &Path, args: &[&str], container_name: &str) ->
     let process_id = generate_process_id();
     
     // Create environment variables
-    let mut envs = HashMap::new();
-    envs.insert("PATH".to_string(), "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin".to_string());
-    envs.insert("HOME".to_string(), "/home/sentinent".to_string());
-    envs.insert("USER".to_string(), "sentinent".to_string());
-    envs.insert("TERM".to_string(), "xterm-256color".to_string());
+    let mut envs = linux_env();
     envs.insert("CONTAINER".to_string(), container_name.to_string());
     
     // Setup process
@@ -333,6 +325,16 @@ fn is_elf_binary(path: &Path) -> Result<bool> {
     Ok(magic[0] == 0x7F && magic[1] == b'E' && magic[2] == b'L' && magic[3] == b'F')
 }
 
+/// Environment Linux binaries run with
+pub(super) fn linux_env() -> HashMap<String, String> {
+    let mut envs = HashMap::new();
+    envs.insert("PATH".to_string(), "/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin".to_string());
+    envs.insert("HOME".to_string(), "/home/sentinent".to_string());
+    envs.insert("USER".to_string(), "sentinent".to_string());
+    envs.insert("TERM".to_string(), "xterm-256color".to_string());
+    envs
+}
+
 /// Generate a unique process ID
 fn generate_process_id() -> String {
     use rand::{thread_rng, Rng};
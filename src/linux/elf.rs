@@ -106,7 +106,7 @@ pub fn init() -> Result<()> {
     info!("Initializing ELF execution system");
     
     // Create directories for ELF execution
-    let elf_dir = PathBuf::from(crate::core::constants::ROOT_DIR)
+    let elf_dir = crate::core::constants::root_dir()
         .join(".linux")
         .join("elf");
     std::fs::create_dir_all(&elf_dir)?
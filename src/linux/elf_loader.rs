@@ -7,12 +7,14 @@ use std::path::{Path, PathBuf};
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom};
 use std::collections::HashMap;
+use std::process::Command;
 use goblin::elf::{Elf, ProgramHeader, SectionHeader, header};
 use goblin::Object;
 use scroll::Pread;
 
 use crate::core::constants;
 use super::compatibility;
+use super::exec::{self, ExecutionResult, OutputMode};
 
 /// ELF file header and information
 #[derive(Debug)]
@@ -143,7 +145,7 @@ pub fn init() -> Result<()> {
     info!("Initializing ELF binary loader");
     
     // Create necessary directories
-    let linux_dir = PathBuf::from(constants::ROOT_DIR).join(".linux");
+    let linux_dir = constants::root_dir().join(".linux");
     let loader_dir = linux_dir.join("loader");
     std::fs::create_dir_all(&loader_dir)?;
     
@@ -313,7 +315,7 @@ pub fn print_elf_info(info: &ElfInfo) {
 
 /// Get the executable loader for a specific architecture
 fn get_loader_for_arch(arch: ElfArchitecture) -> Result<PathBuf> {
-    let linux_dir = PathBuf::from(constants::ROOT_DIR).join(".linux");
+    let linux_dir = constants::root_dir().join(".linux");
     let loader_dir = linux_dir.join("loader");
     
     let loader_name = match arch {
@@ -334,47 +336,33 @@ fn get_loader_for_arch(arch: ElfArchitecture) -> Result<PathBuf> {
     Ok(loader_path)
 }
 
-/// Execute an ELF binary
-pub fn execute_elf(path: &Path, args: &[&str]) -> Result<String> {
+/// Execute an ELF binary and wait for it to exit
+pub fn execute_elf(path: &Path, args: &[&str], mode: OutputMode) -> Result<ExecutionResult> {
     info!("Executing ELF binary: {:?}", path);
     
     // Analyze the ELF binary
     let elf_info = analyze_elf(path)?;
     
-    // Choose execution method based on ELF type
-    if elf_info.is_dynamic {
-        // For dynamically linked binaries
-        if let Some(interpreter) = &elf_info.interpreter {
-            // Use the specified interpreter
+    // Dynamically linked binaries start through their interpreter, or our
+    // loader when the interpreter is missing; everything else runs directly
+    let mut command = match &elf_info.interpreter {
+        Some(interpreter) if elf_info.is_dynamic => {
             let interpreter_path = PathBuf::from(interpreter);
-            
-            if interpreter_path.exists() {
-                // Execute using the interpreter
-                let mut full_args = Vec::new();
-                full_args.push(interpreter.as_str());
-                full_args.push(path.to_str().unwrap_or(""));
-                full_args.extend(args.iter());
-                
-                compatibility::run_elf(&interpreter_path, &full_args.iter().copied().collect::<Vec<_>>())
+            let loader = if interpreter_path.exists() {
+                interpreter_path
             } else {
-                // Try to use our loader
-                let loader = get_loader_for_arch(elf_info.arch)?;
-                
-                let mut full_args = Vec::new();
-                full_args.push(loader.to_str().unwrap_or(""));
-                full_args.push(path.to_str().unwrap_or(""));
-                full_args.extend(args.iter());
-                
-                compatibility::run_elf(&loader, &full_args.iter().copied().collect::<Vec<_>>())
-            }
-        } else {
-            // No interpreter specified, try direct execution
-            compatibility::run_elf(path, args)
+                get_loader_for_arch(elf_info.arch)?
+            };
+            
+            let mut command = Command::new(loader);
+            command.arg(path);
+            command
         }
-    } else {
-        // For statically linked binaries, execute directly
-        compatibility::run_elf(path, args)
-    }
+        _ => Command::new(path),
+    };
+    command.args(args).envs(compatibility::linux_env());
+    
+    exec::run(command, mode)
 }
 
 /// Execute an ELF binary inside a container
@@ -430,7 +418,7 @@ pub fn load_shared_libraries(elf_info: &ElfInfo) -> Result<Vec<PathBuf>> {
     let search_paths = vec![
         PathBuf::from("/lib"),
         PathBuf::from("/usr/lib"),
-        PathBuf::from(constants::ROOT_DIR).join(".linux").join("lib"),
+        constants::root_dir().join(".linux").join("lib"),
     ];
     
     // Try to find and load each required library
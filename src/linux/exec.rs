@@ -0,0 +1,230 @@
+// SentientOS Linux Binary Execution
+// Runs a binary to completion and hands its exit code and output back to the caller
+
+use anyhow::{Result, Context};
+use tracing::{info, debug};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+use crate::core::constants;
+
+// Constants
+const EXEC_DIR: &str = ".linux/var/exec";
+const READ_CHUNK: usize = 64 * 1024;
+
+/// Output kept in memory per stream; anything beyond is spilled to a file
+pub const SPILL_THRESHOLD: usize = 4 * 1024 * 1024;
+
+/// Where a binary's output goes while it runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Collect stdout and stderr into the result
+    Capture,
+    
+    /// Pass the caller's terminal through; nothing is collected
+    Stream,
+}
+
+/// Outcome of a binary run to completion
+#[derive(Debug, Clone)]
+pub struct ExecutionResult {
+    /// Execution ID, naming the spill directory under `.linux/var/exec`
+    pub id: String,
+    
+    /// Exit code; 128 plus the signal number for a binary killed by a signal
+    pub exit_code: i32,
+    
+    /// Captured stdout, up to the spill threshold
+    pub stdout: Vec<u8>,
+    
+    /// Captured stderr, up to the spill threshold
+    pub stderr: Vec<u8>,
+    
+    /// File with the complete stdout, when it exceeded the spill threshold
+    pub stdout_file: Option<PathBuf>,
+    
+    /// File with the complete stderr, when it exceeded the spill threshold
+    pub stderr_file: Option<PathBuf>,
+    
+    /// Time from spawn to exit
+    pub duration: Duration,
+}
+
+impl ExecutionResult {
+    /// Whether the binary exited with status 0
+    pub fn success(&self) -> bool {
+        self.exit_code == 0
+    }
+}
+
+/// A binary ran but exited with a non-zero status
+#[derive(Debug, Clone, Error)]
+#[error("{program} exited with status {code}")]
+pub struct NonZeroExit {
+    /// Binary that was run
+    pub program: String,
+    
+    /// Its exit code
+    pub code: i32,
+}
+
+/// Output read from one stream
+struct Collected {
+    head: Vec<u8>,
+    file: Option<PathBuf>,
+}
+
+/// Run a command to completion
+///
+/// In `Capture` mode stdin is closed and each output stream is kept in
+/// memory up to `SPILL_THRESHOLD`; a stream that grows beyond it is written
+/// in full to `.linux/var/exec/<id>/stdout` or `stderr`. In `Stream` mode
+/// the binary shares the caller's stdin, stdout and stderr.
+pub fn run(mut command: Command, mode: OutputMode) -> Result<ExecutionResult> {
+    let id = generate_exec_id();
+    let program = command.get_program().to_string_lossy().to_string();
+    debug!("Execution {}: {:?}", id, command);
+    
+    match mode {
+        OutputMode::Capture => command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()),
+        OutputMode::Stream => command.stdin(Stdio::inherit()).stdout(Stdio::inherit()).stderr(Stdio::inherit()),
+    };
+    
+    let started = Instant::now();
+    let mut child = command.spawn()
+        .with_context(|| format!("Failed to start {}", program))?;
+    
+    let stdout = child.stdout.take().map(|pipe| spawn_collector(pipe, id.clone(), "stdout"));
+    let stderr = child.stderr.take().map(|pipe| spawn_collector(pipe, id.clone(), "stderr"));
+    
+    let status = child.wait()
+        .with_context(|| format!("Failed to wait for {}", program))?;
+    let duration = started.elapsed();
+    
+    let stdout = join_collector(stdout)?;
+    let stderr = join_collector(stderr)?;
+    let exit_code = status.code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(-1);
+    
+    info!("Execution {} of {} finished with exit code {} in {:?}", id, program, exit_code, duration);
+    Ok(ExecutionResult {
+        id,
+        exit_code,
+        stdout: stdout.head,
+        stderr: stderr.head,
+        stdout_file: stdout.file,
+        stderr_file: stderr.file,
+        duration,
+    })
+}
+
+/// Read a pipe on its own thread so a full stdout cannot block stderr
+fn spawn_collector<R: Read + Send + 'static>(pipe: R, id: String, name: &'static str) -> thread::JoinHandle<io::Result<Collected>> {
+    thread::spawn(move || collect(pipe, &id, name))
+}
+
+/// Wait for a collector; a stream that was not piped collected nothing
+fn join_collector(handle: Option<thread::JoinHandle<io::Result<Collected>>>) -> Result<Collected> {
+    match handle {
+        Some(handle) => handle.join()
+            .map_err(|_| anyhow::anyhow!("Output reader thread panicked"))?
+            .context("Failed to read the binary's output"),
+        None => Ok(Collected { head: Vec::new(), file: None }),
+    }
+}
+
+/// Read a stream to the end, spilling to a file past the threshold
+fn collect<R: Read>(mut pipe: R, id: &str, name: &str) -> io::Result<Collected> {
+    let mut head = Vec::new();
+    let mut spill: Option<(PathBuf, File)> = None;
+    let mut chunk = vec![0u8; READ_CHUNK];
+    
+    loop {
+        let n = match pipe.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        let data = &chunk[..n];
+        
+        if spill.is_none() && head.len() + n > SPILL_THRESHOLD {
+            let dir = constants::root_dir().join(EXEC_DIR).join(id);
+            fs::create_dir_all(&dir)?;
+            let path = dir.join(name);
+            let mut file = File::create(&path)?;
+            file.write_all(&head)?;
+            debug!("Execution {} {} exceeded {} bytes, spilling to {:?}", id, name, SPILL_THRESHOLD, path);
+            spill = Some((path, file));
+        }
+        
+        let room = SPILL_THRESHOLD - head.len();
+        head.extend_from_slice(&data[..n.min(room)]);
+        if let Some((_, file)) = spill.as_mut() {
+            file.write_all(data)?;
+        }
+    }
+    
+    if let Some((_, file)) = spill.as_mut() {
+        file.flush()?;
+    }
+    Ok(Collected { head, file: spill.map(|(path, _)| path) })
+}
+
+/// Generate an execution ID
+fn generate_exec_id() -> String {
+    use rand::{thread_rng, Rng};
+    let random: u32 = thread_rng().gen();
+    
+    format!("exec-{}-{:08x}", chrono::Utc::now().timestamp(), random)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn shell(script: &str) -> Command {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script);
+        command
+    }
+    
+    #[test]
+    fn output_and_exit_codes_are_returned() {
+        let result = run(shell("printf out; printf err >&2; exit 3"), OutputMode::Capture).unwrap();
+        assert_eq!((result.exit_code, result.success()), (3, false));
+        assert_eq!((result.stdout.as_slice(), result.stderr.as_slice()), (&b"out"[..], &b"err"[..]));
+        assert_eq!((result.stdout_file, result.stderr_file), (None, None));
+        
+        // Killed by a signal
+        assert_eq!(run(shell("kill -9 $$"), OutputMode::Capture).unwrap().exit_code, 128 + 9);
+        
+        // Streamed output is not collected
+        let streamed = run(shell("exit 0"), OutputMode::Stream).unwrap();
+        assert!(streamed.success() && streamed.stdout.is_empty());
+        
+        assert!(run(Command::new("/nonexistent/exec-test"), OutputMode::Capture).is_err());
+    }
+    
+    #[test]
+    fn large_output_spills_to_a_file() {
+        let length = SPILL_THRESHOLD + READ_CHUNK + 7;
+        let collected = collect(io::repeat(b'x').take(length as u64), "exec-test-spill", "stdout").unwrap();
+        assert_eq!(collected.head.len(), SPILL_THRESHOLD);
+        let file = collected.file.unwrap();
+        assert_eq!(file, constants::root_dir().join(EXEC_DIR).join("exec-test-spill").join("stdout"));
+        assert_eq!(fs::metadata(&file).unwrap().len(), length as u64);
+        
+        let small = collect(&b"small"[..], "exec-test-small", "stdout").unwrap();
+        assert_eq!((small.head.as_slice(), small.file), (&b"small"[..], None));
+        
+        fs::remove_dir_all(file.parent().unwrap()).unwrap();
+    }
+}
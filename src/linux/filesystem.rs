@@ -28,7 +28,7 @@ pub fn shutdown() -> Result<()> {
 
 /// Create the Linux filesystem structure
 fn create_linux_filesystem() -> Result<()> {
-    let linux_root = PathBuf::from(constants::ROOT_DIR).join(".linux");
+    let linux_root = constants::root_dir().join(".linux");
     
     // Create standard Linux directories
     let directories = [
@@ -88,7 +88,7 @@ fn create_linux_filesystem() -> Result<()> {
 
 /// Write a file to the /etc directory
 fn write_etc_file(name: &str, content: &str) -> Result<()> {
-    let path = PathBuf::from(constants::ROOT_DIR)
+    let path = constants::root_dir()
         .join(".linux")
         .join("etc")
         .join(name);
@@ -104,10 +104,10 @@ fn write_etc_file(name: &str, content: &str) -> Result<()> {
 pub fn translate_to_linux_path(path: &str) -> String {
     if path.starts_with("/") {
         // Absolute path, translate to Linux path
-        format!("{}.linux{}", constants::ROOT_DIR, path)
+        format!("{}.linux{}", constants::root_dir().display(), path)
     } else if path.starts_with(".linux/") || path.starts_with(".linux\\") {
         // Already a Linux path
-        format!("{}{}", constants::ROOT_DIR, path)
+        format!("{}{}", constants::root_dir().display(), path)
     } else {
         // Relative path, leave as-is
         path.to_string()
@@ -116,7 +116,7 @@ pub fn translate_to_linux_path(path: &str) -> String {
 
 /// Translate a Linux path to a SentientOS path
 pub fn translate_from_linux_path(path: &str) -> String {
-    let linux_prefix = format!("{}.linux", constants::ROOT_DIR);
+    let linux_prefix = format!("{}.linux", constants::root_dir().display());
     
     if path.starts_with(&linux_prefix) {
         // Linux path, translate to SentientOS path
@@ -129,7 +129,7 @@ pub fn translate_from_linux_path(path: &str) -> String {
 
 /// Check if a path is within the Linux filesystem
 pub fn is_linux_path(path: &str) -> bool {
-    let linux_prefix = format!("{}.linux", constants::ROOT_DIR);
+    let linux_prefix = format!("{}.linux", constants::root_dir().display());
     path.starts_with(&linux_prefix) || path.starts_with("/.linux/") || path.starts_with(".linux/")
 }
 
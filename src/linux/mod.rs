@@ -11,21 +11,24 @@ pub mod elf;
 pub mod posix;
 pub mod filesystem;
 pub mod elf_loader;
+pub mod exec;
 pub mod compatibility;
 pub mod cli;
 
 use anyhow::Result;
 use tracing::{info, warn};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use crate::core::constants;
 
+pub use exec::{ExecutionResult, NonZeroExit, OutputMode};
+
 /// Initialize the Linux compatibility layer
 pub fn init() -> Result<()> {
     info!("Initializing Linux compatibility layer");
     
     // Create Linux compatibility directories
-    let linux_dir = PathBuf::from(constants::ROOT_DIR).join(".linux");
+    let linux_dir = constants::root_dir().join(".linux");
     std::fs::create_dir_all(&linux_dir)?;
     
     let bin_dir = linux_dir.join("bin");
@@ -70,8 +73,8 @@ pub fn shutdown() -> Result<()> {
     Ok(())
 }
 
-/// Execute a Linux ELF binary
-pub fn execute_binary(binary_path: &str, args: Vec<String>) -> Result<i32> {
+/// Execute a Linux ELF binary and wait for it to exit
+pub fn execute_binary(binary_path: &str, args: Vec<String>, mode: OutputMode) -> Result<ExecutionResult> {
     info!("Executing Linux binary: {} with args: {:?}", binary_path, args);
     
     // Translate path if needed
@@ -82,17 +85,12 @@ pub fn execute_binary(binary_path: &str, args: Vec<String>) -> Result<i32> {
         return Err(anyhow::anyhow!("Binary not found: {}", binary_path));
     }
     
-    // Load the ELF binary
-    let elf_binary = elf::load_binary(&translated_path)?;
-    
-    // Create execution context
-    let mut context = elf::create_execution_context(elf_binary, args)?;
-    
     // Execute the binary
-    let exit_code = elf::execute(&mut context)?;
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let result = elf_loader::execute_elf(Path::new(&translated_path), &args, mode)?;
     
-    info!("Linux binary execution completed with exit code: {}", exit_code);
-    Ok(exit_code)
+    info!("Linux binary execution completed with exit code: {}", result.exit_code);
+    Ok(result)
 }
 
 /// Check if a file is a valid Linux binary
@@ -84,7 +84,7 @@ pub fn init() -> Result<()> {
     info!("Initializing POSIX compatibility layer");
     
     // Create necessary directories
-    let posix_dir = PathBuf::from(crate::core::constants::ROOT_DIR)
+    let posix_dir = crate::core::constants::root_dir()
         .join(".linux")
         .join("posix");
     
@@ -0,0 +1,586 @@
+// SentientOS Metrics History
+// Retains periodic metric snapshots in ring buffers and flags anomalies
+
+use anyhow::{Result, Context};
+use tracing::{info, debug, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use crate::core::constants;
+
+// Constants
+const METRICS_DIR: &str = "metrics";
+const RING_EXTENSION: &str = "ring";
+const FINDINGS_FILE: &str = "findings.json";
+const SPARK_CHARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+// Global metrics state
+lazy_static::lazy_static! {
+    static ref METRICS_STATE: Arc<Mutex<MetricsState>> = Arc::new(Mutex::new(MetricsState::default()));
+}
+
+/// Metrics retention configuration, stored under `metrics` in system.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    /// Seconds between metric snapshots
+    pub resolution_secs: u64,
+    
+    /// How long snapshots are kept, in seconds
+    pub retention_secs: u64,
+    
+    /// Anomaly detection thresholds
+    pub anomaly: AnomalyConfig,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            resolution_secs: 60,
+            retention_secs: 7 * 24 * 60 * 60,
+            anomaly: AnomalyConfig::default(),
+        }
+    }
+}
+
+/// Anomaly detection thresholds
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnomalyConfig {
+    /// Number of trailing samples used as the baseline
+    pub window: usize,
+    
+    /// Samples required before the deviation check runs
+    pub min_samples: usize,
+    
+    /// Standard deviations from the trailing mean that count as anomalous
+    pub max_stddevs: f64,
+    
+    /// Consecutive non-decreasing samples that count as a trend
+    pub growth_samples: usize,
+    
+    /// Growth over a trend, as a ratio of its first value, that is flagged
+    pub growth_ratio: f64,
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        Self {
+            window: 30,
+            min_samples: 10,
+            max_stddevs: 3.0,
+            growth_samples: 10,
+            growth_ratio: 2.0,
+        }
+    }
+}
+
+/// A single metric snapshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sample {
+    /// Snapshot time (seconds since epoch)
+    pub timestamp: u64,
+    
+    /// Values of the family's metrics at that time
+    pub values: BTreeMap<String, f64>,
+}
+
+/// Fixed-capacity history of one metric family
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct FamilyRing {
+    /// Maximum number of samples kept
+    capacity: usize,
+    
+    /// Slot the next sample overwrites once the ring is full
+    head: usize,
+    
+    /// Sample slots
+    samples: Vec<Sample>,
+}
+
+impl FamilyRing {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            head: 0,
+            samples: Vec::new(),
+        }
+    }
+    
+    /// Append a sample, overwriting the oldest once full
+    fn push(&mut self, sample: Sample) {
+        if self.samples.len() < self.capacity {
+            self.samples.push(sample);
+        } else {
+            self.samples[self.head] = sample;
+            self.head = (self.head + 1) % self.capacity;
+        }
+    }
+    
+    /// Samples from oldest to newest
+    fn iter(&self) -> impl DoubleEndedIterator<Item = &Sample> {
+        let (newer, older) = self.samples.split_at(self.head);
+        older.iter().chain(newer.iter())
+    }
+    
+    /// Rebuild with a different capacity, keeping the newest samples
+    fn resize(&mut self, capacity: usize) {
+        let mut resized = FamilyRing::new(capacity);
+        let skip = self.samples.len().saturating_sub(resized.capacity);
+        for sample in self.iter().skip(skip) {
+            resized.push(sample.clone());
+        }
+        *self = resized;
+    }
+}
+
+/// An anomaly raised against a metric
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Finding {
+    /// Metric the finding is about
+    pub metric: String,
+    
+    /// Human-readable description
+    pub message: String,
+    
+    /// When the finding was raised (seconds since epoch)
+    pub raised_at: u64,
+}
+
+/// In-memory metrics state
+#[derive(Default)]
+struct MetricsState {
+    /// Active configuration
+    config: MetricsConfig,
+    
+    /// Latest value of each gauge
+    gauges: HashMap<String, f64>,
+    
+    /// Counter increments since the last snapshot
+    counters: HashMap<String, f64>,
+    
+    /// Loaded family histories
+    rings: HashMap<String, FamilyRing>,
+    
+    /// Active findings by metric
+    findings: BTreeMap<String, Finding>,
+    
+    /// Time of the last snapshot
+    last_tick: u64,
+    
+    /// Whether the snapshot thread should keep running
+    running: bool,
+}
+
+/// Initialize metrics retention from system.json
+pub fn init() -> Result<()> {
+    info!("Initializing metrics history");
+    
+    fs::create_dir_all(metrics_dir())
+        .context("Failed to create metrics directory")?;
+    
+    let config = load_config()?;
+    let resolution = Duration::from_secs(config.resolution_secs.max(1));
+    
+    {
+        let mut state = METRICS_STATE.lock().unwrap();
+        state.findings = load_findings().unwrap_or_default();
+        state.config = config;
+        state.running = true;
+    }
+    
+    thread::spawn(move || loop {
+        thread::sleep(resolution);
+        
+        if !METRICS_STATE.lock().unwrap().running {
+            break;
+        }
+        
+        if let Err(e) = tick() {
+            warn!("Failed to record metrics snapshot: {}", e);
+        }
+    });
+    
+    info!("Metrics history initialized (resolution: {}s)", resolution.as_secs());
+    Ok(())
+}
+
+/// Re-read the metrics configuration from system.json
+///
+/// Retention and anomaly settings apply from the next snapshot; a finer
+/// resolution than the one started with needs a restart.
+pub fn reload_config() -> Result<()> {
+    let config = load_config()?;
+    METRICS_STATE.lock().unwrap().config = config;
+    
+    debug!("Metrics configuration reloaded");
+    Ok(())
+}
+
+/// Shutdown metrics retention
+pub fn shutdown() -> Result<()> {
+    info!("Shutting down metrics history");
+    
+    METRICS_STATE.lock().unwrap().running = false;
+    
+    info!("Metrics history shutdown complete");
+    Ok(())
+}
+
+/// Set a gauge to its current value
+pub fn record(name: &str, value: f64) {
+    METRICS_STATE.lock().unwrap().gauges.insert(name.to_string(), value);
+}
+
+/// Increment a counter; counters report their increase per snapshot
+pub fn increment(name: &str) {
+    *METRICS_STATE.lock().unwrap().counters.entry(name.to_string()).or_insert(0.0) += 1.0;
+}
+
+/// Take a snapshot of all metrics and run anomaly detection
+///
+/// Runs on every scheduler tick but only records once per resolution
+/// interval; detection only looks at the trailing window of each metric.
+pub fn tick() -> Result<()> {
+    super::throttle::publish_metrics();
+    
+    let mut state = METRICS_STATE.lock().unwrap();
+    
+    let now = now();
+    if now < state.last_tick + state.config.resolution_secs {
+        return Ok(());
+    }
+    state.last_tick = now;
+    
+    // Group current values by family
+    let mut families: BTreeMap<String, BTreeMap<String, f64>> = BTreeMap::new();
+    for (name, value) in state.gauges.iter() {
+        families.entry(family_of(name).to_string()).or_default().insert(name.clone(), *value);
+    }
+    for (name, value) in state.counters.drain().collect::<Vec<_>>() {
+        families.entry(family_of(&name).to_string()).or_default().insert(name, value);
+    }
+    
+    let capacity = ring_capacity(&state.config);
+    let anomaly = state.config.anomaly.clone();
+    
+    for (family, values) in families {
+        if !state.rings.contains_key(&family) {
+            let ring = load_ring(&family)?.unwrap_or_else(|| FamilyRing::new(capacity));
+            state.rings.insert(family.clone(), ring);
+        }
+        
+        let ring = state.rings.get_mut(&family).unwrap();
+        if ring.capacity != capacity {
+            ring.resize(capacity);
+        }
+        
+        let names: Vec<String> = values.keys().cloned().collect();
+        ring.push(Sample { timestamp: now, values });
+        save_ring(&family, ring)?;
+        
+        let mut raised = Vec::new();
+        for name in names {
+            raised.push((name.clone(), detect(ring, &name, &anomaly)));
+        }
+        
+        for (name, message) in raised {
+            match message {
+                Some(message) => {
+                    if !state.findings.contains_key(&name) {
+                        warn!("Metric anomaly: {}", message);
+                    }
+                    state.findings.insert(name.clone(), Finding { metric: name, message, raised_at: now });
+                }
+                None => {
+                    state.findings.remove(&name);
+                }
+            }
+        }
+    }
+    
+    save_findings(&state.findings)?;
+    drop(state);
+    
+    crate::panic::crash::heartbeat("metrics", "snapshot recorded");
+    debug!("Recorded metrics snapshot at {}", now);
+    Ok(())
+}
+
+/// Active anomaly findings
+pub fn findings() -> Vec<Finding> {
+    METRICS_STATE.lock().unwrap().findings.values().cloned().collect()
+}
+
+/// Raise a finding that isn't backed by a metric's samples
+///
+/// It stays until cleared, since anomaly detection never revisits it.
+pub fn raise_finding(metric: &str, message: &str) -> Result<()> {
+    warn!("Health finding: {}", message);
+    let mut state = METRICS_STATE.lock().unwrap();
+    state.findings.insert(metric.to_string(), Finding {
+        metric: metric.to_string(),
+        message: message.to_string(),
+        raised_at: now(),
+    });
+    
+    // May run before metrics are initialized
+    fs::create_dir_all(metrics_dir())?;
+    save_findings(&state.findings)
+}
+
+/// Clear a finding raised with `raise_finding`
+pub fn clear_finding(metric: &str) -> Result<()> {
+    let mut state = METRICS_STATE.lock().unwrap();
+    if state.findings.remove(metric).is_some() {
+        save_findings(&state.findings)?;
+    }
+    Ok(())
+}
+
+/// History of one metric since a point in time, oldest first
+pub fn history(name: &str, since: Option<Duration>) -> Result<Vec<(u64, f64)>> {
+    let family = family_of(name);
+    let cutoff = since.map(|age| now().saturating_sub(age.as_secs())).unwrap_or(0);
+    
+    let state = METRICS_STATE.lock().unwrap();
+    let loaded;
+    let ring = match state.rings.get(family) {
+        Some(ring) => ring,
+        None => {
+            loaded = load_ring(family)?
+                .ok_or_else(|| anyhow::anyhow!("No history for metric: {}", name))?;
+            &loaded
+        }
+    };
+    
+    Ok(ring.iter()
+        .filter(|sample| sample.timestamp >= cutoff)
+        .filter_map(|sample| sample.values.get(name).map(|value| (sample.timestamp, *value)))
+        .collect())
+}
+
+/// Render values as a sparkline of at most `width` characters
+pub fn sparkline(values: &[f64], width: usize) -> String {
+    if values.is_empty() || width == 0 {
+        return String::new();
+    }
+    
+    // Average values into at most `width` buckets
+    let bucket_size = (values.len() + width - 1) / width;
+    let buckets: Vec<f64> = values.chunks(bucket_size)
+        .map(|chunk| chunk.iter().sum::<f64>() / chunk.len() as f64)
+        .collect();
+    
+    let min = buckets.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = buckets.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let range = max - min;
+    
+    buckets.iter()
+        .map(|value| {
+            if range <= f64::EPSILON {
+                SPARK_CHARS[0]
+            } else {
+                let level = ((value - min) / range * (SPARK_CHARS.len() - 1) as f64).round() as usize;
+                SPARK_CHARS[level.min(SPARK_CHARS.len() - 1)]
+            }
+        })
+        .collect()
+}
+
+/// Check the newest sample of a metric against its trailing window
+fn detect(ring: &FamilyRing, name: &str, config: &AnomalyConfig) -> Option<String> {
+    let lookback = config.window.max(config.growth_samples) + 1;
+    let mut recent: Vec<f64> = ring.iter().rev()
+        .filter_map(|sample| sample.values.get(name).copied())
+        .take(lookback)
+        .collect();
+    recent.reverse();
+    
+    let (&current, previous) = recent.split_last()?;
+    
+    // Deviation from the trailing window
+    let window = &previous[previous.len().saturating_sub(config.window)..];
+    if window.len() >= config.min_samples.max(2) {
+        let mean = window.iter().sum::<f64>() / window.len() as f64;
+        let variance = window.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / window.len() as f64;
+        let stddev = variance.sqrt();
+        
+        if stddev > f64::EPSILON && (current - mean).abs() > config.max_stddevs * stddev {
+            return Some(if mean.abs() > f64::EPSILON {
+                format!("{} is {:.1}x baseline ({:.2} vs {:.2})", name, current / mean, current, mean)
+            } else {
+                format!("{} deviates from baseline ({:.2} vs {:.2})", name, current, mean)
+            });
+        }
+    }
+    
+    // Sustained monotonic growth
+    if config.growth_samples >= 2 && recent.len() >= config.growth_samples {
+        let run = &recent[recent.len() - config.growth_samples..];
+        let first = run[0];
+        let monotonic = run.windows(2).all(|pair| pair[1] >= pair[0]);
+        
+        if monotonic && current > first {
+            let grown = if first > f64::EPSILON { current / first >= config.growth_ratio } else { current > 0.0 };
+            if grown {
+                return Some(format!("{} trending up ({:.2} -> {:.2} over {} samples)", name, first, current, run.len()));
+            }
+        }
+    }
+    
+    None
+}
+
+/// Family a metric belongs to, the part of its name before the first dot
+fn family_of(name: &str) -> &str {
+    name.split('.').next().unwrap_or(name)
+}
+
+/// Number of samples needed to cover the retention period
+fn ring_capacity(config: &MetricsConfig) -> usize {
+    (config.retention_secs / config.resolution_secs.max(1)).max(1) as usize
+}
+
+/// Load a family history from disk
+fn load_ring(family: &str) -> Result<Option<FamilyRing>> {
+    let path = ring_path(family);
+    if !path.exists() {
+        return Ok(None);
+    }
+    
+    let bytes = fs::read(&path)
+        .with_context(|| format!("Failed to read metrics history: {}", family))?;
+    Ok(Some(bincode::deserialize(&bytes)?))
+}
+
+/// Persist a family history
+fn save_ring(family: &str, ring: &FamilyRing) -> Result<()> {
+    fs::write(ring_path(family), bincode::serialize(ring)?)
+        .with_context(|| format!("Failed to write metrics history: {}", family))
+}
+
+/// Load persisted findings
+fn load_findings() -> Result<BTreeMap<String, Finding>> {
+    let path = metrics_dir().join(FINDINGS_FILE);
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    
+    let content = fs::read_to_string(&path)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// Persist findings
+fn save_findings(findings: &BTreeMap<String, Finding>) -> Result<()> {
+    fs::write(metrics_dir().join(FINDINGS_FILE), serde_json::to_string_pretty(findings)?)?;
+    Ok(())
+}
+
+/// Load the metrics configuration from system.json
+fn load_config() -> Result<MetricsConfig> {
+    let path = constants::root_dir().join(".config").join("system.json");
+    if !path.exists() {
+        return Ok(MetricsConfig::default());
+    }
+    
+    let system_config: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    match system_config.get("metrics") {
+        Some(value) => Ok(serde_json::from_value(value.clone())
+            .context("Invalid metrics configuration in system.json")?),
+        None => Ok(MetricsConfig::default()),
+    }
+}
+
+/// Current time in seconds since the epoch
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn metrics_dir() -> PathBuf {
+    constants::root_dir().join(".logs").join(METRICS_DIR)
+}
+
+fn ring_path(family: &str) -> PathBuf {
+    metrics_dir().join(format!("{}.{}", family, RING_EXTENSION))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Sample of a single metric
+    fn sample(timestamp: u64, name: &str, value: f64) -> Sample {
+        Sample { timestamp, values: BTreeMap::from([(name.to_string(), value)]) }
+    }
+    
+    /// Ring holding one metric's values in order
+    fn ring_of(name: &str, values: &[f64]) -> FamilyRing {
+        let mut ring = FamilyRing::new(values.len());
+        for (i, value) in values.iter().enumerate() {
+            ring.push(sample(i as u64, name, *value));
+        }
+        ring
+    }
+    
+    #[test]
+    fn rings_overwrite_the_oldest_samples() {
+        let mut ring = FamilyRing::new(3);
+        for timestamp in 0..5 {
+            ring.push(sample(timestamp, "cpu.load", timestamp as f64));
+        }
+        let timestamps: Vec<u64> = ring.iter().map(|s| s.timestamp).collect();
+        assert_eq!(timestamps, [2, 3, 4]);
+        
+        ring.resize(2);
+        let timestamps: Vec<u64> = ring.iter().map(|s| s.timestamp).collect();
+        assert_eq!(timestamps, [3, 4]);
+        
+        ring.resize(4);
+        ring.push(sample(5, "cpu.load", 5.0));
+        let timestamps: Vec<u64> = ring.iter().map(|s| s.timestamp).collect();
+        assert_eq!(timestamps, [3, 4, 5]);
+    }
+    
+    #[test]
+    fn spikes_and_sustained_growth_are_flagged() {
+        let config = AnomalyConfig::default();
+        
+        let mut steady: Vec<f64> = (0..20).map(|i| 10.0 + (i % 2) as f64).collect();
+        assert_eq!(detect(&ring_of("mem.used", &steady), "mem.used", &config), None);
+        
+        steady.push(50.0);
+        let message = detect(&ring_of("mem.used", &steady), "mem.used", &config).unwrap();
+        assert!(message.contains("baseline"), "{}", message);
+        
+        let growing: Vec<f64> = (1..=10).map(|i| i as f64).collect();
+        let message = detect(&ring_of("disk.used", &growing), "disk.used", &config).unwrap();
+        assert!(message.contains("trending up"), "{}", message);
+        
+        assert_eq!(detect(&ring_of("disk.used", &growing), "cpu.load", &config), None);
+    }
+    
+    #[test]
+    fn sparklines_scale_to_their_range() {
+        assert_eq!(sparkline(&[0.0, 7.0], 10), "▁█");
+        assert_eq!(sparkline(&[3.0, 3.0, 3.0], 10), "▁▁▁");
+        assert_eq!(sparkline(&[0.0, 0.0, 7.0, 7.0], 2), "▁█");
+        assert_eq!(sparkline(&[], 10), "");
+    }
+    
+    #[test]
+    fn families_and_capacities_follow_the_config() {
+        assert_eq!(family_of("mem.used"), "mem");
+        assert_eq!(family_of("uptime"), "uptime");
+        
+        let config = MetricsConfig { resolution_secs: 60, retention_secs: 3600, ..Default::default() };
+        assert_eq!(ring_capacity(&config), 60);
+        let config = MetricsConfig { resolution_secs: 0, retention_secs: 0, ..Default::default() };
+        assert_eq!(ring_capacity(&config), 1);
+    }
+}
@@ -0,0 +1,47 @@
+// SentientOS Logs
+// Handles off-device shipping of logs and audit records, metrics history and flood protection
+
+pub mod ship;
+pub mod metrics;
+pub mod throttle;
+
+use anyhow::Result;
+use tracing::info;
+use std::path::PathBuf;
+
+use crate::core::constants;
+
+/// Initialize the logs subsystem
+pub fn init() -> Result<()> {
+    info!("Initializing logs subsystem");
+    
+    let logs_dir = constants::root_dir().join(".logs");
+    std::fs::create_dir_all(&logs_dir)?;
+    
+    throttle::init()?;
+    ship::init()?;
+    metrics::init()?;
+    
+    info!("Logs subsystem initialized successfully");
+    Ok(())
+}
+
+/// Re-apply the throttling, shipping and metrics settings from system.json
+pub fn reload_config() -> Result<()> {
+    throttle::init()?;
+    ship::reload_config()?;
+    metrics::reload_config()?;
+    Ok(())
+}
+
+/// Shutdown the logs subsystem
+pub fn shutdown() -> Result<()> {
+    info!("Shutting down logs subsystem");
+    
+    metrics::shutdown()?;
+    ship::shutdown()?;
+    throttle::shutdown()?;
+    
+    info!("Logs subsystem shutdown complete");
+    Ok(())
+}
@@ -0,0 +1,711 @@
+// SentientOS Log Shipping
+// Batches structured log events and audit records and ships them off-device
+
+use anyhow::{Result, Context};
+use tracing::{info, debug, warn, Event, Subscriber};
+use tracing::field::{Field, Visit};
+use tracing_subscriber::layer::{Context as LayerContext, Layer};
+use std::path::PathBuf;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use crate::core::constants;
+
+// Constants
+const LOGS_DIR: &str = ".logs";
+const SPOOL_DIR: &str = "spool";
+const STATUS_FILE: &str = "ship_status.json";
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Largest log payload sent in one gossip message, leaving room for the envelope
+const MAX_PEER_PAYLOAD: usize = 60 * 1024;
+
+/// Keys whose values are masked before records leave the device
+const SENSITIVE_KEYS: &[&str] = &["password", "passwd", "secret", "token", "api_key", "apikey", "private_key", "authorization"];
+
+// Global shipping state
+lazy_static::lazy_static! {
+    static ref SHIP_STATE: Arc<Mutex<ShipState>> = Arc::new(Mutex::new(ShipState::default()));
+}
+
+/// Log shipping configuration, stored under `log_shipping` in system.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShippingConfig {
+    /// Whether log shipping is enabled
+    pub enabled: bool,
+    
+    /// Where records are shipped
+    pub destination: Destination,
+    
+    /// Number of records per batch
+    pub batch_size: usize,
+    
+    /// Seconds between flush attempts
+    pub flush_interval_secs: u64,
+    
+    /// Hard cap on spooled data in bytes; oldest batches are dropped beyond it
+    pub max_spool_bytes: u64,
+    
+    /// Bandwidth budget per flush in bytes
+    pub max_bytes_per_flush: u64,
+    
+    /// What to do with spooled records when shipping is disabled
+    pub on_disable: DisablePolicy,
+}
+
+impl Default for ShippingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            destination: Destination::Tcp { address: "127.0.0.1:29950".to_string() },
+            batch_size: 100,
+            flush_interval_secs: 30,
+            max_spool_bytes: 10 * 1024 * 1024,
+            max_bytes_per_flush: 256 * 1024,
+            on_disable: DisablePolicy::Drain,
+        }
+    }
+}
+
+/// Log shipping destination
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Destination {
+    /// A registered gossip peer
+    Peer { peer_id: String },
+    
+    /// A plain TCP collector accepting JSON lines
+    Tcp { address: String },
+    
+    /// An HTTP collector accepting POSTed JSON lines
+    Http { url: String },
+}
+
+/// Spool handling when shipping is disabled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisablePolicy {
+    /// Ship spooled records one last time before stopping
+    Drain,
+    
+    /// Delete spooled records
+    Discard,
+}
+
+/// Kind of shipped record
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordKind {
+    /// Structured log event
+    Log,
+    
+    /// Audit record
+    Audit,
+}
+
+/// A record as shipped off-device
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShippedRecord {
+    /// Node that produced the record
+    pub node_id: String,
+    
+    /// Record timestamp
+    pub timestamp: u64,
+    
+    /// Record kind
+    pub kind: RecordKind,
+    
+    /// Log level
+    pub level: String,
+    
+    /// Log target (module path)
+    pub target: String,
+    
+    /// Message, with secrets masked
+    pub message: String,
+    
+    /// Correlation ID of the operation that produced the record
+    pub correlation_id: Option<String>,
+}
+
+/// Shipping status as reported to operators
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShipStatus {
+    /// Number of spooled batches awaiting shipment
+    pub spool_batches: usize,
+    
+    /// Bytes of spooled data awaiting shipment
+    pub spool_bytes: u64,
+    
+    /// Timestamp of the last successful flush
+    pub last_flush: Option<u64>,
+    
+    /// Whether the last delivery attempt succeeded
+    pub destination_healthy: bool,
+    
+    /// Error from the last failed delivery attempt
+    pub last_error: Option<String>,
+    
+    /// Batches dropped because the spool cap was reached
+    pub dropped_batches: u64,
+}
+
+/// In-memory shipping state
+#[derive(Debug, Default)]
+struct ShipState {
+    config: ShippingConfig,
+    node_id: String,
+    buffer: Vec<ShippedRecord>,
+    running: bool,
+}
+
+/// Tracing layer feeding log events into the shipping buffer
+pub struct ShippingLayer;
+
+impl<S: Subscriber> Layer<S> for ShippingLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: LayerContext<'_, S>) {
+        let metadata = event.metadata();
+        
+        // Never ship our own events; logging from inside the shipper would recurse
+        if metadata.target().starts_with(module_path!()) {
+            return;
+        }
+        
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        
+        enqueue(RecordKind::Log, &metadata.level().to_string(), metadata.target(), &visitor.message);
+    }
+}
+
+/// Collects an event's message and fields into a single line
+#[derive(Default)]
+pub(super) struct MessageVisitor {
+    pub(super) message: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message.insert_str(0, &format!("{:?}", value));
+        } else {
+            self.message.push_str(&format!(" {}={:?}", field.name(), value));
+        }
+    }
+}
+
+/// Initialize log shipping from system.json
+pub fn init() -> Result<()> {
+    info!("Initializing log shipping");
+    
+    fs::create_dir_all(spool_dir())
+        .context("Failed to create log spool directory")?;
+    
+    let config = load_config()?;
+    let enabled = config.enabled;
+    
+    {
+        let mut state = SHIP_STATE.lock().unwrap();
+        state.config = config;
+        state.node_id = crate::core::identity::node_id().unwrap_or_else(|_| "unknown".to_string());
+    }
+    
+    if enabled {
+        start_flush_thread();
+    }
+    
+    info!("Log shipping initialized (enabled: {})", enabled);
+    Ok(())
+}
+
+/// Re-read the shipping configuration from system.json
+///
+/// Shipping that was switched on starts its flush thread; shipping that
+/// was switched off stops after spooling what is buffered.
+pub fn reload_config() -> Result<()> {
+    let config = load_config()?;
+    let enabled = config.enabled;
+    
+    let running = {
+        let mut state = SHIP_STATE.lock().unwrap();
+        state.config = config;
+        state.running
+    };
+    
+    if enabled && !running {
+        start_flush_thread();
+    } else if !enabled && running {
+        SHIP_STATE.lock().unwrap().running = false;
+        spool_buffer()?;
+    }
+    
+    info!("Log shipping configuration reloaded (enabled: {})", enabled);
+    Ok(())
+}
+
+/// Shutdown log shipping, spooling any buffered records
+pub fn shutdown() -> Result<()> {
+    info!("Shutting down log shipping");
+    
+    let enabled = {
+        let mut state = SHIP_STATE.lock().unwrap();
+        state.running = false;
+        state.config.enabled
+    };
+    
+    if enabled {
+        spool_buffer()?;
+    }
+    
+    info!("Log shipping shutdown complete");
+    Ok(())
+}
+
+/// Queue an audit record for shipping
+pub fn ship_audit(target: &str, message: &str) {
+    enqueue(RecordKind::Audit, "AUDIT", target, message);
+}
+
+/// Disable log shipping, draining or discarding the spool per configuration
+pub fn disable() -> Result<()> {
+    let policy = {
+        let mut state = SHIP_STATE.lock().unwrap();
+        state.running = false;
+        state.config.on_disable
+    };
+    
+    match policy {
+        DisablePolicy::Drain => {
+            spool_buffer()?;
+            flush()?;
+        }
+        DisablePolicy::Discard => {
+            SHIP_STATE.lock().unwrap().buffer.clear();
+            for batch in spooled_batches()? {
+                fs::remove_file(&batch).ok();
+            }
+        }
+    }
+    
+    let mut state = SHIP_STATE.lock().unwrap();
+    state.config.enabled = false;
+    save_config(&state.config)?;
+    
+    info!("Log shipping disabled");
+    Ok(())
+}
+
+/// Ship spooled batches to the destination, within the bandwidth budget
+pub fn flush() -> Result<()> {
+    let (config, node_id) = {
+        let state = SHIP_STATE.lock().unwrap();
+        (state.config.clone(), state.node_id.clone())
+    };
+    
+    let mut status = load_status()?;
+    let mut sent_bytes = 0u64;
+    
+    for batch_path in spooled_batches()? {
+        let batch = fs::read(&batch_path)
+            .with_context(|| format!("Failed to read spooled batch: {:?}", batch_path))?;
+        
+        if sent_bytes > 0 && sent_bytes + batch.len() as u64 > config.max_bytes_per_flush {
+            debug!("Log shipping bandwidth budget reached for this flush");
+            break;
+        }
+        
+        match deliver(&config.destination, &node_id, &batch) {
+            Ok(()) => {
+                fs::remove_file(&batch_path).ok();
+                sent_bytes += batch.len() as u64;
+                status.last_flush = Some(now());
+                status.destination_healthy = true;
+                status.last_error = None;
+            }
+            Err(e) => {
+                // Leave the batch spooled and retry on the next flush
+                status.destination_healthy = false;
+                status.last_error = Some(e.to_string());
+                break;
+            }
+        }
+    }
+    
+    save_status(&status)
+}
+
+/// Get the current shipping status
+pub fn status() -> Result<ShipStatus> {
+    let mut status = load_status()?;
+    
+    let batches = spooled_batches()?;
+    status.spool_batches = batches.len();
+    status.spool_bytes = batches.iter()
+        .filter_map(|b| fs::metadata(b).ok())
+        .map(|m| m.len())
+        .sum();
+    
+    Ok(status)
+}
+
+/// Get the configured destination
+pub fn destination() -> Destination {
+    SHIP_STATE.lock().unwrap().config.destination.clone()
+}
+
+/// Mask values of sensitive keys in a message
+pub fn mask_secrets(message: &str) -> String {
+    let mut masked = Vec::new();
+    let mut mask_next = false;
+    
+    for word in message.split(' ') {
+        if mask_next {
+            masked.push("****".to_string());
+            mask_next = false;
+            continue;
+        }
+        
+        let lower = word.to_lowercase();
+        match SENSITIVE_KEYS.iter().find(|k| lower.trim_start_matches('"').starts_with(*k)) {
+            Some(_) => {
+                match word.find(|c| c == '=' || c == ':') {
+                    Some(pos) if pos + 1 < word.len() => masked.push(format!("{}****", &word[..=pos])),
+                    Some(_) => {
+                        // "key: value" form, value is the next word
+                        masked.push(word.to_string());
+                        mask_next = true;
+                    }
+                    None => masked.push(word.to_string()),
+                }
+            }
+            None => masked.push(word.to_string()),
+        }
+    }
+    
+    masked.join(" ")
+}
+
+/// Add a record to the buffer, spooling a batch once it is full
+fn enqueue(kind: RecordKind, level: &str, target: &str, message: &str) {
+    let batch_full = {
+        let mut state = match SHIP_STATE.lock() {
+            Ok(state) => state,
+            Err(_) => return,
+        };
+        
+        if !state.config.enabled {
+            return;
+        }
+        
+        let record = ShippedRecord {
+            node_id: state.node_id.clone(),
+            timestamp: now(),
+            kind,
+            level: level.to_string(),
+            target: target.to_string(),
+            message: mask_secrets(message),
+            correlation_id: crate::intent::trace::current_correlation(),
+        };
+        state.buffer.push(record);
+        state.buffer.len() >= state.config.batch_size
+    };
+    
+    if batch_full {
+        spool_buffer().ok();
+    }
+}
+
+/// Write buffered records to a spool batch and enforce the spool cap
+fn spool_buffer() -> Result<()> {
+    let (records, max_spool_bytes) = {
+        let mut state = SHIP_STATE.lock().unwrap();
+        (std::mem::take(&mut state.buffer), state.config.max_spool_bytes)
+    };
+    
+    if records.is_empty() {
+        return Ok(());
+    }
+    
+    let mut batch = String::new();
+    for record in &records {
+        batch.push_str(&serde_json::to_string(record)?);
+        batch.push('\n');
+    }
+    
+    let batch_path = spool_dir().join(format!("batch-{}.jsonl",
+        SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos()));
+    fs::write(&batch_path, batch)
+        .with_context(|| format!("Failed to write spool batch: {:?}", batch_path))?;
+    
+    // Drop the oldest batches once the spool exceeds its cap
+    let mut batches = spooled_batches()?;
+    let mut total: u64 = batches.iter().filter_map(|b| fs::metadata(b).ok()).map(|m| m.len()).sum();
+    let mut dropped = 0;
+    while total > max_spool_bytes && batches.len() > 1 {
+        let oldest = batches.remove(0);
+        total -= fs::metadata(&oldest).map(|m| m.len()).unwrap_or(0);
+        fs::remove_file(&oldest).ok();
+        dropped += 1;
+    }
+    
+    if dropped > 0 {
+        let mut status = load_status()?;
+        status.dropped_batches += dropped;
+        save_status(&status)?;
+    }
+    
+    Ok(())
+}
+
+/// Start the background thread that periodically spools and flushes
+fn start_flush_thread() {
+    let interval = {
+        let mut state = SHIP_STATE.lock().unwrap();
+        state.running = true;
+        Duration::from_secs(state.config.flush_interval_secs.max(1))
+    };
+    
+    thread::spawn(move || {
+        while SHIP_STATE.lock().unwrap().running {
+            thread::sleep(interval);
+            
+            if let Err(e) = spool_buffer().and_then(|_| flush()) {
+                warn!("Log shipping flush failed: {}", e);
+            }
+        }
+    });
+    
+    debug!("Started log shipping thread");
+}
+
+/// Deliver one batch of JSON lines to the destination
+fn deliver(destination: &Destination, node_id: &str, batch: &[u8]) -> Result<()> {
+    match destination {
+        Destination::Peer { peer_id } => {
+            let peer = crate::gossip::list_peers()?
+                .into_iter()
+                .find(|p| &p.id == peer_id)
+                .ok_or_else(|| anyhow::anyhow!("Log shipping peer not registered: {}", peer_id))?;
+            
+            if peer.status == crate::gossip::PeerStatus::Offline {
+                anyhow::bail!("Log shipping peer is offline: {}", peer_id);
+            }
+            
+            // Split on record boundaries so each chunk fits in one message
+            let mut chunk = Vec::new();
+            for line in batch.split_inclusive(|b| *b == b'\n') {
+                if !chunk.is_empty() && chunk.len() + line.len() > MAX_PEER_PAYLOAD {
+                    crate::gossip::protocol::send_message(&peer.endpoint, crate::gossip::protocol::MessageType::LogBatch, &chunk)?;
+                    chunk.clear();
+                }
+                chunk.extend_from_slice(line);
+            }
+            if !chunk.is_empty() {
+                crate::gossip::protocol::send_message(&peer.endpoint, crate::gossip::protocol::MessageType::LogBatch, &chunk)?;
+            }
+            Ok(())
+        }
+        Destination::Tcp { address } => {
+            let mut stream = connect(address)?;
+            stream.write_all(batch)?;
+            stream.flush()?;
+            Ok(())
+        }
+        Destination::Http { url } => {
+            let rest = url.strip_prefix("http://")
+                .ok_or_else(|| anyhow::anyhow!("Only http:// collector URLs are supported: {}", url))?;
+            let (host, path) = match rest.find('/') {
+                Some(pos) => (&rest[..pos], &rest[pos..]),
+                None => (rest, "/"),
+            };
+            let address = if host.contains(':') { host.to_string() } else { format!("{}:80", host) };
+            
+            let mut stream = connect(&address)?;
+            let request = format!(
+                "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/x-ndjson\r\nX-Sentient-Node: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                path, host, node_id, batch.len()
+            );
+            stream.write_all(request.as_bytes())?;
+            stream.write_all(batch)?;
+            
+            let mut response = String::new();
+            stream.read_to_string(&mut response).ok();
+            let status_code = response.split_whitespace().nth(1).unwrap_or("");
+            if !status_code.starts_with('2') {
+                anyhow::bail!("Collector rejected log batch: {}", response.lines().next().unwrap_or("no response"));
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Connect to a collector with timeouts
+fn connect(address: &str) -> Result<TcpStream> {
+    let addr = std::net::ToSocketAddrs::to_socket_addrs(address)?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Could not resolve collector address: {}", address))?;
+    
+    let stream = TcpStream::connect_timeout(&addr, NETWORK_TIMEOUT)
+        .with_context(|| format!("Failed to connect to collector: {}", address))?;
+    stream.set_write_timeout(Some(NETWORK_TIMEOUT))?;
+    stream.set_read_timeout(Some(NETWORK_TIMEOUT))?;
+    Ok(stream)
+}
+
+/// Spooled batch files, oldest first
+fn spooled_batches() -> Result<Vec<PathBuf>> {
+    let spool_dir = spool_dir();
+    if !spool_dir.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let mut batches: Vec<PathBuf> = fs::read_dir(&spool_dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().and_then(|e| e.to_str()) == Some("jsonl"))
+        .collect();
+    batches.sort();
+    Ok(batches)
+}
+
+/// Load the shipping configuration from system.json
+fn load_config() -> Result<ShippingConfig> {
+    let system_config = read_system_config()?;
+    match system_config.get("log_shipping") {
+        Some(value) => Ok(serde_json::from_value(value.clone())
+            .context("Invalid log_shipping configuration in system.json")?),
+        None => Ok(ShippingConfig::default()),
+    }
+}
+
+/// Save the shipping configuration into system.json
+fn save_config(config: &ShippingConfig) -> Result<()> {
+    let mut system_config = read_system_config()?;
+    system_config["log_shipping"] = serde_json::to_value(config)?;
+    fs::write(system_config_path(), serde_json::to_string_pretty(&system_config)?)?;
+    Ok(())
+}
+
+/// Read system.json, or an empty object if it does not exist
+fn read_system_config() -> Result<serde_json::Value> {
+    let path = system_config_path();
+    if !path.exists() {
+        return Ok(serde_json::json!({}));
+    }
+    
+    let content = fs::read_to_string(&path)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// Load persisted shipping status
+fn load_status() -> Result<ShipStatus> {
+    let path = logs_dir().join(STATUS_FILE);
+    if !path.exists() {
+        return Ok(ShipStatus::default());
+    }
+    
+    let content = fs::read_to_string(&path)?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// Persist shipping status
+fn save_status(status: &ShipStatus) -> Result<()> {
+    fs::write(logs_dir().join(STATUS_FILE), serde_json::to_string_pretty(status)?)?;
+    Ok(())
+}
+
+/// Current time in seconds since the epoch
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn system_config_path() -> PathBuf {
+    constants::root_dir().join(".config").join("system.json")
+}
+
+fn logs_dir() -> PathBuf {
+    constants::root_dir().join(LOGS_DIR)
+}
+
+fn spool_dir() -> PathBuf {
+    logs_dir().join(SPOOL_DIR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    
+    const BATCH: &[u8] = b"{\"message\":\"one\"}\n{\"message\":\"two\"}\n";
+    
+    /// Accept one connection, answer it with `reply` once a full request
+    /// arrived, and return what was received
+    fn collector(reply: &'static str) -> (String, thread::JoinHandle<Vec<u8>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut received = Vec::new();
+            let mut buf = [0u8; 4096];
+            loop {
+                let n = stream.read(&mut buf).unwrap();
+                received.extend_from_slice(&buf[..n]);
+                if n == 0 || received.ends_with(BATCH) {
+                    break;
+                }
+            }
+            stream.write_all(reply.as_bytes()).unwrap();
+            received
+        });
+        (address, handle)
+    }
+    
+    #[test]
+    fn secrets_are_masked() {
+        assert_eq!(mask_secrets("login password=hunter2 user=bob"), "login password=**** user=bob");
+        assert_eq!(mask_secrets("Token: abc123 accepted"), "Token: **** accepted");
+        assert_eq!(mask_secrets("\"api_key\":\"k-1\""), "\"api_key\":****");
+        assert_eq!(mask_secrets("nothing to hide"), "nothing to hide");
+    }
+    
+    #[test]
+    fn tcp_collectors_receive_the_batch_as_is() {
+        let (address, handle) = collector("");
+        deliver(&Destination::Tcp { address }, "node-1", BATCH).unwrap();
+        assert_eq!(handle.join().unwrap(), BATCH);
+    }
+    
+    #[test]
+    fn http_collectors_receive_a_post_and_must_accept_it() {
+        let (address, handle) = collector("HTTP/1.1 204 No Content\r\n\r\n");
+        let url = format!("http://{}/ingest", address);
+        deliver(&Destination::Http { url }, "node-1", BATCH).unwrap();
+        
+        let request = String::from_utf8(handle.join().unwrap()).unwrap();
+        assert!(request.starts_with("POST /ingest HTTP/1.1\r\n"), "{}", request);
+        assert!(request.contains("X-Sentient-Node: node-1\r\n"), "{}", request);
+        assert!(request.contains(&format!("Content-Length: {}\r\n", BATCH.len())), "{}", request);
+        
+        let (address, handle) = collector("HTTP/1.1 503 Service Unavailable\r\n\r\n");
+        let error = deliver(&Destination::Http { url: format!("http://{}", address) }, "node-1", BATCH).unwrap_err();
+        assert!(error.to_string().contains("503"), "{}", error);
+        handle.join().unwrap();
+        
+        assert!(deliver(&Destination::Http { url: "https://example.com".to_string() }, "node-1", BATCH).is_err());
+    }
+    
+    #[test]
+    fn destinations_are_tagged_by_type() {
+        let destination: Destination = serde_json::from_str(r#"{"type":"peer","peer_id":"node-2"}"#).unwrap();
+        assert!(matches!(destination, Destination::Peer { peer_id } if peer_id == "node-2"));
+        
+        let config: ShippingConfig = serde_json::from_value(serde_json::to_value(ShippingConfig::default()).unwrap()).unwrap();
+        assert!(!config.enabled);
+        assert_eq!(config.on_disable, DisablePolicy::Drain);
+        assert_eq!(serde_json::to_value(DisablePolicy::Discard).unwrap(), "discard");
+    }
+}
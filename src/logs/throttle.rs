@@ -0,0 +1,553 @@
+// SentientOS Log Throttling
+// Flood protection for noisy subsystems: collapses repeats and raises the level
+
+use anyhow::{Result, Context};
+use tracing::{info, warn, Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context as LayerContext, Layer};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{Serialize, Deserialize};
+
+use crate::core::constants;
+use super::ship::MessageVisitor;
+
+/// Environment variable disabling throttling, for debugging
+const NO_THROTTLE_ENV: &str = "SENTIENT_LOG_NO_THROTTLE";
+
+/// Interval at which queued notices are logged and ended floods reported
+const DRAIN_INTERVAL: Duration = Duration::from_secs(1);
+
+// Whether the notice drain thread keeps running
+static DRAINING: AtomicBool = AtomicBool::new(false);
+
+// Global throttling state
+lazy_static::lazy_static! {
+    static ref THROTTLE_STATE: Arc<Mutex<ThrottleState>> = Arc::new(Mutex::new(ThrottleState::new(ThrottleConfig::default())));
+}
+
+/// Log throttling configuration, stored under `log_throttle` in system.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThrottleConfig {
+    /// Whether throttling is active
+    pub enabled: bool,
+    
+    /// Limit of subsystems without their own entry
+    pub default: ThrottleLimit,
+    
+    /// Limits by subsystem (`gossip`, `matrixbox`, ...)
+    pub subsystems: HashMap<String, ThrottleLimit>,
+    
+    /// Seconds a flooding subsystem stays raised to WARN
+    pub raise_secs: u64,
+}
+
+impl Default for ThrottleConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            default: ThrottleLimit::default(),
+            subsystems: HashMap::new(),
+            raise_secs: 60,
+        }
+    }
+}
+
+/// Token bucket limit of one subsystem
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThrottleLimit {
+    /// Events allowed in a burst
+    pub burst: u32,
+    
+    /// Events per second the bucket refills with
+    pub per_second: f64,
+}
+
+impl Default for ThrottleLimit {
+    fn default() -> Self {
+        Self {
+            burst: 200,
+            per_second: 20.0,
+        }
+    }
+}
+
+/// Throttling counters of one subsystem
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SubsystemStats {
+    /// Events suppressed since startup
+    pub suppressed: u64,
+    
+    /// Times the subsystem started flooding
+    pub floods: u64,
+    
+    /// Whether the subsystem is raised to WARN right now
+    pub raised: bool,
+}
+
+/// Throttling state of one subsystem
+#[derive(Debug)]
+struct Bucket {
+    limit: ThrottleLimit,
+    tokens: f64,
+    refilled_at: Instant,
+    
+    /// Last event passed through, as (level, message)
+    last: Option<(Level, String)>,
+    
+    /// Suppressed repeats of `last` not reported yet
+    repeats: u64,
+    
+    /// End of the raised level, while flooding
+    raised_until: Option<Instant>,
+    
+    /// Events suppressed during the current flood
+    flood_suppressed: u64,
+    
+    stats: SubsystemStats,
+}
+
+impl Bucket {
+    fn new(limit: ThrottleLimit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst as f64,
+            refilled_at: now,
+            last: None,
+            repeats: 0,
+            raised_until: None,
+            flood_suppressed: 0,
+            stats: SubsystemStats::default(),
+        }
+    }
+    
+    fn take_token(&mut self, now: Instant) -> bool {
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.per_second).min(self.limit.burst as f64);
+        self.refilled_at = now;
+        
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+    
+    fn suppress(&mut self) {
+        self.flood_suppressed += 1;
+        self.stats.suppressed += 1;
+    }
+}
+
+/// A record the throttle emits about its own decisions
+#[derive(Debug)]
+enum Notice {
+    /// A subsystem exceeded its limit
+    FloodStarted { subsystem: String, raise_secs: u64 },
+    
+    /// Identical messages were collapsed
+    Repeated { subsystem: String, level: Level, message: String, count: u64 },
+    
+    /// A subsystem's flood ended
+    FloodEnded { subsystem: String, suppressed: u64 },
+}
+
+/// In-memory throttling state
+#[derive(Debug)]
+struct ThrottleState {
+    config: ThrottleConfig,
+    disabled_by_env: bool,
+    buckets: HashMap<String, Bucket>,
+    
+    /// Notices waiting for the drain thread
+    pending: Vec<Notice>,
+}
+
+impl ThrottleState {
+    fn new(config: ThrottleConfig) -> Self {
+        Self {
+            config,
+            disabled_by_env: std::env::var(NO_THROTTLE_ENV).map(|v| v == "1").unwrap_or(false),
+            buckets: HashMap::new(),
+            pending: Vec::new(),
+        }
+    }
+    
+    fn active(&self) -> bool {
+        self.config.enabled && !self.disabled_by_env
+    }
+    
+    /// Decide whether an event passes, collecting notices to emit
+    fn admit(&mut self, subsystem: &str, level: Level, message: String, now: Instant, notices: &mut Vec<Notice>) -> bool {
+        self.expire(now, notices);
+        
+        let limit = self.config.subsystems.get(subsystem).copied().unwrap_or(self.config.default);
+        let raise_secs = self.config.raise_secs;
+        let bucket = self.buckets.entry(subsystem.to_string()).or_insert_with(|| Bucket::new(limit, now));
+        
+        let identical = bucket.last.as_ref().map(|(l, m)| *l == level && *m == message).unwrap_or(false);
+        
+        if bucket.take_token(now) {
+            flush_repeats(subsystem, bucket, notices);
+            bucket.last = Some((level, message));
+            return true;
+        }
+        
+        // Over the limit: start a flood if one isn't running
+        if bucket.raised_until.is_none() {
+            bucket.stats.floods += 1;
+            bucket.stats.raised = true;
+            notices.push(Notice::FloodStarted { subsystem: subsystem.to_string(), raise_secs });
+        }
+        bucket.raised_until = Some(now + Duration::from_secs(raise_secs));
+        
+        if identical {
+            bucket.repeats += 1;
+            bucket.suppress();
+            return false;
+        }
+        
+        // Levels are ordered by verbosity, so INFO, DEBUG and TRACE are above WARN
+        if level > Level::WARN {
+            bucket.suppress();
+            return false;
+        }
+        
+        flush_repeats(subsystem, bucket, notices);
+        bucket.last = Some((level, message));
+        true
+    }
+    
+    /// End floods whose raised period is over
+    fn expire(&mut self, now: Instant, notices: &mut Vec<Notice>) {
+        for (subsystem, bucket) in self.buckets.iter_mut() {
+            if bucket.raised_until.map(|t| now >= t).unwrap_or(false) {
+                end_flood(subsystem, bucket, notices);
+            }
+        }
+    }
+    
+    /// Report every pending repeat and end every flood
+    fn flush_all(&mut self, notices: &mut Vec<Notice>) {
+        for (subsystem, bucket) in self.buckets.iter_mut() {
+            if bucket.raised_until.is_some() {
+                end_flood(subsystem, bucket, notices);
+            } else {
+                flush_repeats(subsystem, bucket, notices);
+            }
+        }
+    }
+}
+
+/// Tracing layer suppressing floods before any other layer sees them
+///
+/// Add it to the registry before the fmt and shipping layers; an event it
+/// rejects is dropped for the whole subscriber.
+pub struct ThrottleLayer;
+
+impl<S: Subscriber> Layer<S> for ThrottleLayer {
+    fn event_enabled(&self, event: &Event<'_>, _ctx: LayerContext<'_, S>) -> bool {
+        let metadata = event.metadata();
+        
+        // Our own notices always pass; throttling them would recurse
+        if metadata.target().starts_with(module_path!()) {
+            return true;
+        }
+        
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        
+        let mut state = THROTTLE_STATE.lock().unwrap();
+        if !state.active() {
+            return true;
+        }
+        let mut notices = Vec::new();
+        let admitted = state.admit(subsystem_of(metadata.target()), *metadata.level(), visitor.message, Instant::now(), &mut notices);
+        
+        // tracing drops events logged while it dispatches one, so the
+        // notices wait for the drain thread
+        state.pending.extend(notices);
+        admitted
+    }
+}
+
+/// Load the throttling configuration from system.json
+pub fn init() -> Result<()> {
+    info!("Initializing log throttling");
+    
+    let config = load_config()?;
+    let active = {
+        let mut state = THROTTLE_STATE.lock().unwrap();
+        state.config = config;
+        state.buckets.clear();
+        state.active()
+    };
+    
+    if !DRAINING.swap(true, Ordering::SeqCst) {
+        thread::spawn(|| {
+            while DRAINING.load(Ordering::SeqCst) {
+                thread::sleep(DRAIN_INTERVAL);
+                emit_pending();
+            }
+        });
+    }
+    
+    info!("Log throttling initialized (active: {})", active);
+    Ok(())
+}
+
+/// Report pending repeats and ended floods
+pub fn shutdown() -> Result<()> {
+    info!("Shutting down log throttling");
+    
+    DRAINING.store(false, Ordering::SeqCst);
+    let notices = {
+        let mut state = THROTTLE_STATE.lock().unwrap();
+        let mut notices = std::mem::take(&mut state.pending);
+        state.flush_all(&mut notices);
+        notices
+    };
+    emit(notices);
+    
+    info!("Log throttling shutdown complete");
+    Ok(())
+}
+
+/// Throttling counters by subsystem
+pub fn stats() -> HashMap<String, SubsystemStats> {
+    THROTTLE_STATE.lock().unwrap().buckets.iter()
+        .map(|(subsystem, bucket)| (subsystem.clone(), bucket.stats.clone()))
+        .collect()
+}
+
+/// Publish throttling counters as metrics
+///
+/// Called by the metrics snapshot before it takes its own lock, since the
+/// throttle cannot record metrics from inside the tracing layer.
+pub fn publish_metrics() {
+    for (subsystem, stats) in stats() {
+        super::metrics::record(&format!("logs.throttle.{}.suppressed", subsystem), stats.suppressed as f64);
+        super::metrics::record(&format!("logs.throttle.{}.floods", subsystem), stats.floods as f64);
+    }
+}
+
+/// Subsystem of a target: the module below the crate root
+fn subsystem_of(target: &str) -> &str {
+    let mut parts = target.split("::");
+    let first = parts.next().unwrap_or(target);
+    parts.next().unwrap_or(first)
+}
+
+/// Queue a "repeated N times" notice for a bucket's pending repeats
+fn flush_repeats(subsystem: &str, bucket: &mut Bucket, notices: &mut Vec<Notice>) {
+    if bucket.repeats == 0 {
+        return;
+    }
+    
+    if let Some((level, message)) = &bucket.last {
+        notices.push(Notice::Repeated {
+            subsystem: subsystem.to_string(),
+            level: *level,
+            message: message.clone(),
+            count: bucket.repeats,
+        });
+    }
+    bucket.repeats = 0;
+}
+
+/// End a bucket's flood, restoring its level
+fn end_flood(subsystem: &str, bucket: &mut Bucket, notices: &mut Vec<Notice>) {
+    flush_repeats(subsystem, bucket, notices);
+    notices.push(Notice::FloodEnded { subsystem: subsystem.to_string(), suppressed: bucket.flood_suppressed });
+    bucket.raised_until = None;
+    bucket.flood_suppressed = 0;
+    bucket.stats.raised = false;
+}
+
+/// Log the notices the layer queued and report floods that are over
+///
+/// Must not be called from within a tracing layer.
+fn emit_pending() {
+    let notices = {
+        let mut state = THROTTLE_STATE.lock().unwrap();
+        let mut notices = std::mem::take(&mut state.pending);
+        state.expire(Instant::now(), &mut notices);
+        notices
+    };
+    emit(notices);
+}
+
+/// Log the throttle's notices
+fn emit(notices: Vec<Notice>) {
+    for notice in notices {
+        match notice {
+            Notice::FloodStarted { subsystem, raise_secs } => {
+                warn!("Log flood from {}: collapsing repeats and raising level to WARN for {}s", subsystem, raise_secs);
+            }
+            Notice::Repeated { subsystem, level, message, count } => {
+                info!("[{}] {} message repeated {} times: {}", subsystem, level, count, message);
+            }
+            Notice::FloodEnded { subsystem, suppressed } => {
+                warn!("Log flood from {} ended: {} events suppressed", subsystem, suppressed);
+            }
+        }
+    }
+}
+
+/// Load the throttling configuration from system.json
+fn load_config() -> Result<ThrottleConfig> {
+    let path = constants::root_dir().join(".config").join("system.json");
+    if !path.exists() {
+        return Ok(ThrottleConfig::default());
+    }
+    
+    let system_config: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    match system_config.get("log_throttle") {
+        Some(value) => Ok(serde_json::from_value(value.clone())
+            .context("Invalid log_throttle configuration in system.json")?),
+        None => Ok(ThrottleConfig::default()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::prelude::*;
+    
+    const LIMIT: ThrottleLimit = ThrottleLimit { burst: 3, per_second: 0.0 };
+    
+    fn state() -> ThrottleState {
+        let mut config = ThrottleConfig { raise_secs: 10, ..ThrottleConfig::default() };
+        config.subsystems.insert("noisy".to_string(), LIMIT);
+        ThrottleState { config, disabled_by_env: false, buckets: HashMap::new(), pending: Vec::new() }
+    }
+    
+    fn repeats(notices: &[Notice]) -> Vec<u64> {
+        notices.iter()
+            .filter_map(|n| match n {
+                Notice::Repeated { count, .. } => Some(*count),
+                _ => None,
+            })
+            .collect()
+    }
+    
+    #[test]
+    fn burst_of_identical_events_collapses_into_one_count() {
+        let mut state = state();
+        let now = Instant::now();
+        let mut notices = Vec::new();
+        
+        let admitted = (0..100)
+            .filter(|_| state.admit("noisy", Level::ERROR, "retry failed".to_string(), now, &mut notices))
+            .count();
+        assert_eq!(admitted, 3);
+        assert!(matches!(notices.as_slice(), [Notice::FloodStarted { raise_secs: 10, .. }]));
+        
+        // The next distinct event reports the collapsed repeats first
+        assert!(state.admit("noisy", Level::ERROR, "gave up".to_string(), now, &mut notices));
+        assert_eq!(repeats(&notices), [97]);
+        assert_eq!(state.buckets["noisy"].stats.suppressed, 97);
+        assert_eq!(state.buckets["noisy"].stats.floods, 1);
+    }
+    
+    #[test]
+    fn flooding_subsystem_is_raised_to_warn() {
+        let mut state = state();
+        let now = Instant::now();
+        let mut notices = Vec::new();
+        for i in 0..3 {
+            assert!(state.admit("noisy", Level::INFO, format!("event {}", i), now, &mut notices));
+        }
+        
+        assert!(!state.admit("noisy", Level::INFO, "chatter".to_string(), now, &mut notices));
+        assert!(!state.admit("noisy", Level::DEBUG, "more chatter".to_string(), now, &mut notices));
+        assert!(state.admit("noisy", Level::WARN, "something is wrong".to_string(), now, &mut notices));
+        assert!(state.buckets["noisy"].stats.raised);
+        
+        // Other subsystems keep their own budget
+        assert!(state.admit("quiet", Level::INFO, "chatter".to_string(), now, &mut notices));
+    }
+    
+    #[test]
+    fn flood_ends_after_the_raised_period() {
+        let mut state = state();
+        let now = Instant::now();
+        let mut notices = Vec::new();
+        for _ in 0..10 {
+            state.admit("noisy", Level::INFO, "tick".to_string(), now, &mut notices);
+        }
+        notices.clear();
+        
+        let later = now + Duration::from_secs(10);
+        state.expire(later, &mut notices);
+        assert_eq!(repeats(&notices), [7]);
+        assert!(matches!(notices.last(), Some(Notice::FloodEnded { suppressed: 7, .. })));
+        assert!(!state.buckets["noisy"].stats.raised);
+        
+        // Pending repeats are reported once only
+        notices.clear();
+        state.flush_all(&mut notices);
+        assert!(repeats(&notices).is_empty());
+    }
+    
+    #[test]
+    fn disabled_throttle_is_inactive() {
+        let mut state = state();
+        assert!(state.active());
+        
+        state.disabled_by_env = true;
+        assert!(!state.active());
+        
+        state.disabled_by_env = false;
+        state.config.enabled = false;
+        assert!(!state.active());
+    }
+    
+    #[test]
+    fn subsystem_is_the_module_below_the_crate() {
+        assert_eq!(subsystem_of("sentient_os::gossip::protocol"), "gossip");
+        assert_eq!(subsystem_of("sentient_os::heal"), "heal");
+        assert_eq!(subsystem_of("sentctl"), "sentctl");
+    }
+    
+    /// Records the messages that reach it, after the throttle
+    struct Capture(Arc<Mutex<Vec<String>>>);
+    
+    impl<S: Subscriber> Layer<S> for Capture {
+        fn on_event(&self, event: &Event<'_>, _ctx: LayerContext<'_, S>) {
+            let mut visitor = MessageVisitor::default();
+            event.record(&mut visitor);
+            self.0.lock().unwrap().push(visitor.message);
+        }
+    }
+    
+    #[test]
+    fn layer_collapses_a_burst_before_other_layers() {
+        THROTTLE_STATE.lock().unwrap().config.subsystems.insert("floodtest".to_string(), LIMIT);
+        
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = tracing_subscriber::registry()
+            .with(ThrottleLayer)
+            .with(Capture(captured.clone()));
+        tracing::subscriber::with_default(subscriber, || {
+            for _ in 0..50 {
+                tracing::error!(target: "sentient_os::floodtest", "disk write failed");
+            }
+            tracing::error!(target: "sentient_os::floodtest", "giving up");
+            emit_pending();
+        });
+        
+        let captured = captured.lock().unwrap();
+        assert_eq!(captured.iter().filter(|m| *m == "disk write failed").count(), 3);
+        assert!(captured.contains(&"giving up".to_string()));
+        assert!(captured.iter().any(|m| m.starts_with("Log flood from floodtest")));
+        assert!(captured.contains(&"[floodtest] ERROR message repeated 47 times: disk write failed".to_string()));
+    }
+}
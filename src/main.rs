@@ -12,35 +12,71 @@ mod intent;
 mod heal;
 mod panic;
 mod store;
+mod trash;
+mod logs;
+mod maintenance;
+mod bench;
+mod setup;
+mod doctor;
+mod config;
+mod purge;
+mod update;
+mod status;
+mod network;
 
 use anyhow::{Result, Context};
 use std::env;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 use tracing::{info, warn, error, debug, Level};
 
+// Constants
+const SIGNAL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const FORCE_EXIT_WINDOW: Duration = Duration::from_secs(5);
+const FORCED_EXIT_CODE: i32 = 130;
+
+// SIGINT/SIGTERM deliveries so far, counted by the signal handler
+static TERMINATION_SIGNALS: AtomicUsize = AtomicUsize::new(0);
+
+// Set by the signal handler on SIGHUP, cleared once the reload ran
+static RELOAD_REQUESTED: AtomicBool = AtomicBool::new(false);
+
 /// Main entry point for SentientOS
 fn main() -> Result<()> {
+    // A staged build previews its migrations for the updater; stdout carries only
+    // the report and nothing is initialized, so the check stays read-only
+    let args: Vec<String> = env::args().collect();
+    if args.len() > 1 && args[1] == "--migration-check" {
+        return migration_check(&args[2..]);
+    }
+    
     // Initialize tracing
     tracing_subscriber::registry()
         .with(tracing_subscriber::EnvFilter::new(
             std::env::var("SENTIENT_LOG").unwrap_or_else(|_| "info".into()),
         ))
+        .with(logs::throttle::ThrottleLayer)
         .with(tracing_subscriber::fmt::layer())
+        .with(logs::ship::ShippingLayer)
         .init();
-
+    
     info!("Starting SentientOS");
     info!("Version: {}", env!("CARGO_PKG_VERSION"));
-
+    
     // Initialize core subsystems
     core::init()?;
     
-    // Parse command-line arguments
-    let args: Vec<String> = env::args().collect();
-    
     if args.len() > 1 && args[1] == "cli" {
         // CLI mode - handle command directly
         debug!("Running in CLI mode");
-        cli::execute_command(args[2..].to_vec())?;
+        if let Err(e) = cli::execute_command(args[2..].to_vec()) {
+            // A binary run through `linux run` passes its exit status on
+            if let Some(exit) = e.downcast_ref::<linux::NonZeroExit>() {
+                std::process::exit(exit.code);
+            }
+            return Err(e);
+        }
     } else if args.len() > 1 && args[1] == "init" {
         // Initialization mode - bootstrap full system
         info!("Running in initialization mode");
@@ -50,11 +86,24 @@ fn main() -> Result<()> {
         info!("Running in interactive mode");
         start_runtime()?;
     }
-
+    
     info!("SentientOS terminated successfully");
     Ok(())
 }
 
+/// Print the migration report of this build against a root directory
+fn migration_check(args: &[String]) -> Result<()> {
+    let root = match args {
+        [flag, root] if flag == "--root" => std::path::PathBuf::from(root),
+        [] => core::constants::root_dir(),
+        _ => anyhow::bail!("Usage: sentient_os --migration-check [--root <path>]"),
+    };
+    
+    let report = update::migrate::check(&root)?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
 /// Bootstrap the entire system
 fn bootstrap_system() -> Result<()> {
     info!("Bootstrapping system...");
@@ -69,6 +118,7 @@ fn bootstrap_system() -> Result<()> {
     intent::init().context("Failed to initialize Intent")?;
     heal::init().context("Failed to initialize Heal")?;
     panic::init().context("Failed to initialize Panic")?;
+    logs::init().context("Failed to initialize Logs")?;
     store::init().context("Failed to initialize ZK-Store")?;
     
     info!("System bootstrap complete");
@@ -79,39 +129,132 @@ fn bootstrap_system() -> Result<()> {
 fn start_runtime() -> Result<()> {
     info!("Starting SentientOS runtime...");
     
+    // Start the shared worker pool before subsystems queue background work
+    core::workers::init()?;
+    
     // Initialize minimal set of subsystems
     cli::init()?;
     zk::init()?;
+    
+    // Unlock the contract state master key, prompting if it is protected
+    zk::state::unlock(None)?;
+    
     matrixbox::init()?;
     linux::init()?;
     heal::init()?;
     panic::init()?;
+    logs::init()?;
     store::init()?;
     
-    // Start interactive shell or service listener here
-    // This would typically block until termination
+    install_signal_handlers()?;
     println!("SentientOS is running. Press Ctrl+C to exit.");
     
-    // Wait for termination signal
+    // Subsystems run on their own threads; this one waits for signals
     wait_for_termination();
     
+    // A second Ctrl+C while shutting down forces the exit
+    watch_forced_exit();
+    
     // Perform clean shutdown
     shutdown()
 }
 
-/// Wait for termination signal
+/// Route SIGINT, SIGTERM and SIGHUP to the run loop
+fn install_signal_handlers() -> Result<()> {
+    for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle_signal as usize;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal, &action, std::ptr::null_mut()) != 0 {
+                anyhow::bail!("Failed to install handler of signal {}: {}", signal, std::io::Error::last_os_error());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Signal handler; only notes the signal for the run loop
+extern "C" fn handle_signal(signal: libc::c_int) {
+    if signal == libc::SIGHUP {
+        RELOAD_REQUESTED.store(true, Ordering::SeqCst);
+    } else {
+        TERMINATION_SIGNALS.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Run until SIGINT or SIGTERM arrives, reloading configuration on SIGHUP
 fn wait_for_termination() {
-    // In a real implementation, this would wait for a signal
-    // For this prototype, we'll just sleep for a moment
-    std::thread::sleep(std::time::Duration::from_secs(1));
+    while TERMINATION_SIGNALS.load(Ordering::SeqCst) == 0 {
+        std::thread::sleep(SIGNAL_POLL_INTERVAL);
+        
+        if RELOAD_REQUESTED.swap(false, Ordering::SeqCst) {
+            info!("SIGHUP received, reloading configuration");
+            if let Err(e) = reload_config() {
+                error!("Failed to reload configuration: {:#}", e);
+            }
+        }
+    }
+    info!("Termination signal received");
+}
+
+/// Reload system.json and the network configuration in place
+fn reload_config() -> Result<()> {
+    let path = core::constants::root_dir().join(".config").join("system.json");
+    let content = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {:?}", path))?;
+    serde_json::from_str::<serde_json::Value>(&content)
+        .with_context(|| format!("Invalid system configuration {:?}", path))?;
+    
+    // Other subsystems read system.json on every use
+    logs::reload_config()?;
+    network::reload_config()?;
+    
+    info!("Configuration reloaded");
+    Ok(())
+}
+
+/// Force the exit if two termination signals arrive within `FORCE_EXIT_WINDOW`
+///
+/// The forced exit is recorded as a panic first, when that still works.
+fn watch_forced_exit() {
+    std::thread::spawn(|| {
+        let mut seen = TERMINATION_SIGNALS.load(Ordering::SeqCst);
+        let mut last = Instant::now();
+        loop {
+            std::thread::sleep(SIGNAL_POLL_INTERVAL);
+            
+            let count = TERMINATION_SIGNALS.load(Ordering::SeqCst);
+            if count == seen {
+                continue;
+            }
+            if last.elapsed() < FORCE_EXIT_WINDOW {
+                warn!("Second termination signal within {}s, forcing exit", FORCE_EXIT_WINDOW.as_secs());
+                let event = panic::PanicEvent::SubsystemFailure {
+                    subsystem: "shutdown".to_string(),
+                    error: "Termination signal repeated during shutdown".to_string(),
+                };
+                if let Err(e) = panic::record_panic(event) {
+                    error!("Failed to record forced shutdown: {:#}", e);
+                }
+                std::process::exit(FORCED_EXIT_CODE);
+            }
+            seen = count;
+            last = Instant::now();
+        }
+    });
 }
 
 /// Clean shutdown of all subsystems
 fn shutdown() -> Result<()> {
     info!("Shutting down SentientOS...");
     
+    // Cancel background work before the subsystems it uses go away
+    core::workers::shutdown().ok();
+    
     // Shutdown in reverse order of initialization
     store::shutdown().ok();
+    logs::shutdown().ok();
     panic::shutdown().ok();
     heal::shutdown().ok();
     intent::shutdown().ok();
@@ -126,3 +269,32 @@ fn shutdown() -> Result<()> {
     info!("Shutdown complete");
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn signals_are_noted_for_the_run_loop() {
+        install_signal_handlers().unwrap();
+        
+        // SIGHUP asks for a reload and does not end the loop
+        unsafe { libc::raise(libc::SIGHUP) };
+        assert!(RELOAD_REQUESTED.load(Ordering::SeqCst));
+        assert_eq!(TERMINATION_SIGNALS.load(Ordering::SeqCst), 0);
+        
+        // The loop runs the pending reload and keeps going until terminated
+        let run_loop = std::thread::spawn(wait_for_termination);
+        let started = Instant::now();
+        while RELOAD_REQUESTED.load(Ordering::SeqCst) {
+            assert!(started.elapsed() < FORCE_EXIT_WINDOW, "reload was not picked up");
+            std::thread::sleep(SIGNAL_POLL_INTERVAL);
+        }
+        assert!(!run_loop.is_finished());
+        
+        unsafe { libc::raise(libc::SIGTERM) };
+        unsafe { libc::raise(libc::SIGINT) };
+        assert_eq!(TERMINATION_SIGNALS.load(Ordering::SeqCst), 2);
+        run_loop.join().unwrap();
+    }
+}
@@ -0,0 +1,301 @@
+// SentientOS Maintenance Mode
+// Quiesces containers around disruptive operations such as snapshots
+
+use anyhow::{Result, Context};
+use tracing::{info, warn, error};
+use std::path::PathBuf;
+use std::fs;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use crate::core::constants;
+use crate::matrixbox::container::ContainerId;
+use crate::matrixbox::{registry, runtime};
+
+// Constants
+const STATE_FILE: &str = "maintenance.json";
+const WATCHDOG_POLL: Duration = Duration::from_secs(1);
+
+/// Operation performed while in maintenance mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum MaintenanceOperation {
+    /// Take a system snapshot
+    Snapshot,
+    
+    /// Update the system in place
+    SelfUpdate,
+    
+    /// Apply a hot patch
+    HotPatch,
+}
+
+impl FromStr for MaintenanceOperation {
+    type Err = anyhow::Error;
+    
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "snapshot" => Ok(Self::Snapshot),
+            "self-update" => Ok(Self::SelfUpdate),
+            "hot-patch" => Ok(Self::HotPatch),
+            _ => anyhow::bail!("Unknown maintenance operation: {} (expected snapshot, self-update or hot-patch)", s),
+        }
+    }
+}
+
+/// Persisted maintenance mode state
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MaintenanceState {
+    /// Whether maintenance mode is active
+    pub active: bool,
+    
+    /// When maintenance mode was entered
+    pub entered_at: u64,
+    
+    /// Deadline after which the watchdog resumes containers
+    pub deadline: u64,
+    
+    /// Containers paused on entry, in pause order
+    pub paused: Vec<ContainerId>,
+    
+    /// Running containers that declare they cannot be paused
+    pub unpausable: Vec<ContainerId>,
+    
+    /// Whether the last maintenance window was ended by the watchdog
+    pub timed_out: bool,
+}
+
+/// Initialize maintenance mode, clearing state left by an interrupted window
+pub fn init() -> Result<()> {
+    info!("Initializing maintenance mode");
+    
+    let state = status()?;
+    if state.active {
+        // Containers do not survive a restart, so there is nothing left to resume
+        warn!("Maintenance mode was active at startup; clearing stale state");
+        save_state(&MaintenanceState { timed_out: state.timed_out, ..Default::default() })?;
+    }
+    
+    info!("Maintenance mode initialized successfully");
+    Ok(())
+}
+
+/// Shutdown maintenance mode, resuming containers if a window is open
+pub fn shutdown() -> Result<()> {
+    info!("Shutting down maintenance mode");
+    
+    if status()?.active {
+        exit()?;
+    }
+    
+    info!("Maintenance mode shutdown complete");
+    Ok(())
+}
+
+/// Enter maintenance mode, pausing running containers in manifest order
+///
+/// Containers that declare themselves unpausable keep running and are
+/// listed in the returned state so the operator can stop them instead.
+pub fn enter(max_duration: Duration) -> Result<MaintenanceState> {
+    if status()?.active {
+        anyhow::bail!("Maintenance mode is already active");
+    }
+    
+    info!("Entering maintenance mode (max duration: {}s)", max_duration.as_secs());
+    
+    let mut containers = Vec::new();
+    for id in runtime::running_container_ids() {
+        let container = registry::get_container(&id)?;
+        containers.push((container.metadata.pause_order, container.metadata.pausable, id));
+    }
+    containers.sort();
+    
+    let now = now()?;
+    let mut state = MaintenanceState {
+        active: true,
+        entered_at: now,
+        deadline: now + max_duration.as_secs(),
+        ..Default::default()
+    };
+    
+    for (_, pausable, id) in containers {
+        if !pausable {
+            warn!("Container cannot be paused, leaving it running: {}", id);
+            state.unpausable.push(id);
+            continue;
+        }
+        
+        if let Err(e) = runtime::pause_container(&id) {
+            // Undo what we did so far rather than leave a half-paused system
+            error!("Failed to pause container {}: {}", id, e);
+            resume_all(&state.paused);
+            return Err(e).with_context(|| format!("Failed to enter maintenance mode while pausing {}", id));
+        }
+        state.paused.push(id);
+    }
+    
+    save_state(&state)?;
+    start_watchdog(state.entered_at);
+    
+    info!("Maintenance mode active: {} paused, {} unpausable", state.paused.len(), state.unpausable.len());
+    Ok(state)
+}
+
+/// Exit maintenance mode, resuming paused containers in reverse order
+pub fn exit() -> Result<()> {
+    let state = status()?;
+    if !state.active {
+        anyhow::bail!("Maintenance mode is not active");
+    }
+    
+    info!("Exiting maintenance mode");
+    resume_all(&state.paused);
+    
+    save_state(&MaintenanceState { timed_out: state.timed_out, ..Default::default() })?;
+    info!("Maintenance mode exited");
+    Ok(())
+}
+
+/// Enter maintenance mode, run an operation, and exit again
+pub fn run(operation: MaintenanceOperation, max_duration: Duration) -> Result<MaintenanceState> {
+    let state = enter(max_duration)?;
+    
+    let result = perform(operation);
+    
+    // Always resume, even if the operation failed
+    if status()?.active {
+        exit()?;
+    }
+    
+    result.map(|_| state)
+}
+
+/// Current maintenance mode state
+pub fn status() -> Result<MaintenanceState> {
+    let path = state_path();
+    if !path.exists() {
+        return Ok(MaintenanceState::default());
+    }
+    
+    let content = fs::read_to_string(&path)
+        .context("Failed to read maintenance state")?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// Perform a maintenance operation
+fn perform(operation: MaintenanceOperation) -> Result<()> {
+    info!("Performing maintenance operation: {:?}", operation);
+    
+    match operation {
+        MaintenanceOperation::Snapshot => {
+            let snapshot_id = crate::heal::take_snapshot("maintenance")?;
+            info!("Maintenance snapshot created: {}", snapshot_id);
+            Ok(())
+        }
+        MaintenanceOperation::SelfUpdate => crate::update::apply_staged(),
+        MaintenanceOperation::HotPatch => {
+            anyhow::bail!("Maintenance operation {:?} is not supported yet", operation)
+        }
+    }
+}
+
+/// Resume containers in reverse pause order, logging failures
+fn resume_all(paused: &[ContainerId]) {
+    for id in paused.iter().rev() {
+        if let Err(e) = runtime::resume_container(id) {
+            warn!("Failed to resume container {}: {}", id, e);
+        }
+    }
+}
+
+/// Start the watchdog enforcing the maintenance deadline
+fn start_watchdog(entered_at: u64) {
+    thread::spawn(move || loop {
+        thread::sleep(WATCHDOG_POLL);
+        
+        let state = match status() {
+            Ok(state) => state,
+            Err(e) => {
+                warn!("Maintenance watchdog failed to read state: {}", e);
+                continue;
+            }
+        };
+        
+        // A later window has its own watchdog
+        if !state.active || state.entered_at != entered_at {
+            return;
+        }
+        
+        if now().unwrap_or(0) < state.deadline {
+            continue;
+        }
+        
+        error!("ALERT: maintenance window exceeded its maximum duration; resuming containers");
+        if let Err(e) = save_state(&MaintenanceState { timed_out: true, ..state }) {
+            warn!("Failed to record maintenance timeout: {}", e);
+        }
+        if let Err(e) = exit() {
+            error!("Maintenance watchdog failed to exit maintenance mode: {}", e);
+        }
+        return;
+    });
+}
+
+/// Save maintenance state
+fn save_state(state: &MaintenanceState) -> Result<()> {
+    let path = state_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    
+    fs::write(&path, serde_json::to_string_pretty(state)?)
+        .context("Failed to write maintenance state")
+}
+
+/// Current time in seconds since the epoch
+fn now() -> Result<u64> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+/// Path of the maintenance state file
+fn state_path() -> PathBuf {
+    constants::root_dir().join(constants::RUNTIME_DIR).join(STATE_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn operations_parse_from_their_cli_names() {
+        assert_eq!("snapshot".parse::<MaintenanceOperation>().unwrap(), MaintenanceOperation::Snapshot);
+        assert_eq!("self-update".parse::<MaintenanceOperation>().unwrap(), MaintenanceOperation::SelfUpdate);
+        assert_eq!("hot-patch".parse::<MaintenanceOperation>().unwrap(), MaintenanceOperation::HotPatch);
+        assert!("reboot".parse::<MaintenanceOperation>().is_err());
+        
+        assert_eq!(serde_json::to_string(&MaintenanceOperation::SelfUpdate).unwrap(), "\"self-update\"");
+    }
+    
+    #[test]
+    fn stale_windows_are_cleared_at_startup() {
+        save_state(&MaintenanceState {
+            active: true,
+            entered_at: 1,
+            deadline: 2,
+            timed_out: true,
+            ..Default::default()
+        }).unwrap();
+        assert!(status().unwrap().active);
+        
+        init().unwrap();
+        let state = status().unwrap();
+        assert!(!state.active);
+        assert!(state.timed_out);
+        assert_eq!(state.deadline, 0);
+        
+        let error = exit().unwrap_err();
+        assert!(error.to_string().contains("not active"), "{}", error);
+    }
+}
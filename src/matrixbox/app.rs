@@ -0,0 +1,249 @@
+// SentientOS MatrixBox App Tooling
+// Scaffolds WASM-targeted app projects and packs their builds into TSO archives
+
+use anyhow::{Result, Context};
+use tracing::info;
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::process::Command;
+
+use super::container::{Container, ContainerConfig, ContainerMetadata, ContainerPermissions, NetworkPermissions};
+use super::tso;
+
+/// Rust target apps are compiled for
+pub const WASM_TARGET: &str = "wasm32-wasi";
+
+/// App manifest file name
+pub const MANIFEST_FILE: &str = "tso.yaml";
+
+// The bindings crate is vendored into each new app
+const ABI_CARGO_TOML: &str = include_str!("../../sdk/sentient-abi/Cargo.toml");
+const ABI_LIB_RS: &str = include_str!("../../sdk/sentient-abi/src/lib.rs");
+
+const CARGO_TOML_TEMPLATE: &str = r#"[package]
+name = "{{name}}"
+version = "0.1.0"
+edition = "2021"
+
+[lib]
+crate-type = ["cdylib"]
+
+[dependencies]
+sentient-abi = { path = "sentient-abi" }
+
+[profile.release]
+opt-level = "s"
+lto = true
+"#;
+
+const CARGO_CONFIG_TEMPLATE: &str = r#"[build]
+target = "wasm32-wasi"
+"#;
+
+const LIB_RS_TEMPLATE: &str = r#"// {{name}} - SentientOS MatrixBox application
+
+use sentient_abi as sentient;
+
+/// Entry point called by MatrixBox when the container starts
+#[no_mangle]
+pub extern "C" fn _start() {
+    let message = format!("{{name}} started in container {}", sentient::container_id());
+    sentient::info(&message);
+}
+
+/// Health probe; returns 0 when the app is healthy
+#[no_mangle]
+pub extern "C" fn health() -> i32 {
+    0
+}
+"#;
+
+const GITIGNORE_TEMPLATE: &str = "/target\n*.tso\n";
+
+/// Scaffold a new WASM app project under `parent`
+pub fn new_app(name: &str, parent: &Path) -> Result<PathBuf> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        anyhow::bail!("Invalid app name: {} (use letters, digits, '-' and '_')", name);
+    }
+    
+    let app_dir = parent.join(name);
+    if app_dir.exists() {
+        anyhow::bail!("App directory already exists: {:?}", app_dir);
+    }
+    
+    info!("Creating MatrixBox app: {} in {:?}", name, app_dir);
+    
+    let files = [
+        ("Cargo.toml", CARGO_TOML_TEMPLATE.replace("{{name}}", name)),
+        (".cargo/config.toml", CARGO_CONFIG_TEMPLATE.to_string()),
+        ("src/lib.rs", LIB_RS_TEMPLATE.replace("{{name}}", name)),
+        (".gitignore", GITIGNORE_TEMPLATE.to_string()),
+        ("sentient-abi/Cargo.toml", ABI_CARGO_TOML.to_string()),
+        ("sentient-abi/src/lib.rs", ABI_LIB_RS.to_string()),
+        (MANIFEST_FILE, serde_yaml::to_string(&default_manifest(name))?),
+    ];
+    
+    for (relative, content) in files {
+        let path = app_dir.join(relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, content)
+            .with_context(|| format!("Failed to write {:?}", path))?;
+    }
+    
+    info!("Created MatrixBox app: {}", name);
+    Ok(app_dir)
+}
+
+/// Build an app for the WASM target and pack it into a TSO archive
+///
+/// Returns the path of the written archive, `<app>/<name>.tso` unless
+/// `output` is given.
+pub fn build_app(app_dir: &Path, output: Option<&Path>) -> Result<PathBuf> {
+    let manifest_path = app_dir.join(MANIFEST_FILE);
+    let manifest = fs::read_to_string(&manifest_path)
+        .with_context(|| format!("Failed to read app manifest: {:?}", manifest_path))?;
+    let mut container: Container = serde_yaml::from_str(&manifest)
+        .with_context(|| format!("Failed to parse app manifest: {:?}", manifest_path))?;
+    
+    let crate_name = crate_name(app_dir)?;
+    info!("Building MatrixBox app: {} ({})", container.name, crate_name);
+    
+    let status = Command::new("cargo")
+        .args(["build", "--release", "--target", WASM_TARGET])
+        .current_dir(app_dir)
+        .status()
+        .context("Failed to run cargo")?;
+    
+    if !status.success() {
+        anyhow::bail!("cargo build failed for {}", container.name);
+    }
+    
+    let wasm_path = app_dir.join("target").join(WASM_TARGET).join("release")
+        .join(format!("{}.wasm", crate_name.replace('-', "_")));
+    if !wasm_path.exists() {
+        anyhow::bail!("Build did not produce {:?}; is crate-type set to cdylib?", wasm_path);
+    }
+    
+    // Stage the container layout the archive creator expects
+    let stage_dir = app_dir.join("target").join("tso").join(&container.name);
+    fs::create_dir_all(&stage_dir)?;
+    fs::copy(&wasm_path, stage_dir.join("main.wasm"))
+        .context("Failed to stage main.wasm")?;
+    
+    container.metadata.hash_tree_root = blake3::hash(&fs::read(&wasm_path)?).to_hex().to_string();
+    container.path = Some(stage_dir.clone());
+    super::container::save_container(&container)?;
+    
+    let output = output.map(Path::to_path_buf)
+        .unwrap_or_else(|| app_dir.join(format!("{}.tso", container.name)));
+    let config = ContainerConfig {
+        name: container.name.clone(),
+        version: Some(container.version.clone()),
+        ..Default::default()
+    };
+    tso::create_tso_archive(&stage_dir, &output, &config)?;
+    
+    info!("Built MatrixBox app: {:?}", output);
+    Ok(output)
+}
+
+/// Manifest written for new apps
+pub fn default_manifest(name: &str) -> Container {
+    Container {
+        id: None,
+        name: name.to_string(),
+        version: "0.1.0".to_string(),
+        author: None,
+        description: None,
+        path: None,
+        metadata: ContainerMetadata {
+            created_at: chrono::Utc::now().to_rfc3339(),
+            entrypoint: "_start".to_string(),
+            environment: Vec::new(),
+            dependencies: Vec::new(),
+            hash_tree_root: String::new(),
+            pausable: true,
+            pause_order: 0,
+            sync: None,
+            resource_limits: None,
+            ports: Vec::new(),
+        },
+        permissions: ContainerPermissions {
+            filesystem: vec![format!(".container/{}", name)],
+            network: NetworkPermissions {
+                outbound: false,
+                inbound: false,
+                allowed_hosts: Vec::new(),
+            },
+            memory_limit: 1024 * 1024 * 64, // 64MB
+            cpu_limit: 50,
+            kv_quota_bytes: super::kv::DEFAULT_QUOTA_BYTES,
+            capabilities: Vec::new(),
+        },
+    }
+}
+
+/// Package name from an app's Cargo.toml
+fn crate_name(app_dir: &Path) -> Result<String> {
+    let cargo_toml = fs::read_to_string(app_dir.join("Cargo.toml"))
+        .context("Failed to read Cargo.toml")?;
+    
+    let mut in_package = false;
+    for line in cargo_toml.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_package = line == "[package]";
+        } else if in_package {
+            if let Some(value) = line.strip_prefix("name").map(str::trim).and_then(|rest| rest.strip_prefix('=')) {
+                return Ok(value.trim().trim_matches('"').to_string());
+            }
+        }
+    }
+    
+    anyhow::bail!("No package name in {:?}", app_dir.join("Cargo.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::constants;
+    
+    #[test]
+    fn new_apps_are_scaffolded_with_their_name() {
+        let parent = constants::root_dir().join("app_scaffold");
+        let app_dir = new_app("hello-app", &parent).unwrap();
+        
+        assert_eq!(crate_name(&app_dir).unwrap(), "hello-app");
+        let lib_rs = fs::read_to_string(app_dir.join("src/lib.rs")).unwrap();
+        assert!(lib_rs.contains("hello-app started in container"));
+        assert!(!lib_rs.contains("{{name}}"));
+        assert!(app_dir.join("sentient-abi/src/lib.rs").exists());
+        
+        let manifest: Container = serde_yaml::from_str(&fs::read_to_string(app_dir.join(MANIFEST_FILE)).unwrap()).unwrap();
+        assert_eq!(manifest.name, "hello-app");
+        assert_eq!(manifest.metadata.entrypoint, "_start");
+        
+        assert!(new_app("hello-app", &parent).is_err());
+    }
+    
+    #[test]
+    fn app_names_must_be_plain() {
+        let parent = constants::root_dir().join("app_names");
+        for name in ["", "../escape", "has space"] {
+            assert!(new_app(name, &parent).is_err(), "{:?}", name);
+        }
+        assert!(!parent.exists());
+    }
+    
+    #[test]
+    fn crate_names_come_from_the_package_section() {
+        let app_dir = constants::root_dir().join("app_crate_name");
+        fs::create_dir_all(&app_dir).unwrap();
+        fs::write(app_dir.join("Cargo.toml"), "[dependencies]\nname = \"wrong\"\n\n[package]\nversion = \"0.1.0\"\nname = \"right\"\n").unwrap();
+        assert_eq!(crate_name(&app_dir).unwrap(), "right");
+        
+        fs::write(app_dir.join("Cargo.toml"), "[workspace]\n").unwrap();
+        assert!(crate_name(&app_dir).is_err());
+    }
+}
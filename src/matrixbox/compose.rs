@@ -0,0 +1,371 @@
+// SentientOS MatrixBox Compositions
+// Starts related containers from a sentient-compose.yaml in dependency order
+
+use anyhow::{Result, Context};
+use tracing::{info, debug, warn};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use serde::{Serialize, Deserialize};
+
+use super::container::{self, ContainerConfig, ContainerId, ContainerStatus};
+use super::{registry, runtime};
+use crate::core::constants;
+
+// Constants
+const STATE_FILE: &str = ".matrixbox/compose/state.json";
+const HEALTH_FUNCTION: &str = "health";
+const HEALTH_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const DEFAULT_HEALTH_TIMEOUT_SECS: u64 = 30;
+
+/// A set of containers started together, from `sentient-compose.yaml`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompositionSpec {
+    /// Services by name
+    pub services: BTreeMap<String, ServiceSpec>,
+}
+
+/// A container in a composition
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceSpec {
+    /// Container image directory or TSO archive, relative to the compose file
+    pub image: PathBuf,
+    
+    /// Settings overriding the image's; the name defaults to the service name
+    #[serde(flatten)]
+    pub config: ContainerConfig,
+    
+    /// Services that must be healthy before this one starts
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    
+    /// Arguments the container runs with
+    #[serde(default)]
+    pub args: Vec<String>,
+    
+    /// How long the service may take to become healthy, in seconds
+    #[serde(default)]
+    pub health_timeout_secs: Option<u64>,
+}
+
+/// Containers of the composition that is up, in start order
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct CompositionState {
+    /// Compose file the composition was started from
+    file: Option<PathBuf>,
+    
+    /// Service names and their container IDs
+    services: Vec<(String, ContainerId)>,
+}
+
+impl CompositionSpec {
+    /// Parse a compose file; image paths are resolved against its directory
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read compose file {:?}", path))?;
+        let mut spec: CompositionSpec = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse compose file {:?}", path))?;
+        
+        let base = path.parent().unwrap_or_else(|| Path::new("."));
+        for service in spec.services.values_mut() {
+            if service.image.is_relative() {
+                service.image = base.join(&service.image);
+            }
+        }
+        Ok(spec)
+    }
+    
+    /// Service names with every service after the ones it depends on
+    ///
+    /// Services without a dependency between them keep name order. An
+    /// unknown dependency or a dependency cycle is an error.
+    pub fn start_order(&self) -> Result<Vec<String>> {
+        for (name, service) in &self.services {
+            for dependency in &service.depends_on {
+                if !self.services.contains_key(dependency) {
+                    anyhow::bail!("Service {} depends on unknown service {}", name, dependency);
+                }
+            }
+        }
+        
+        let mut order = Vec::new();
+        let mut started = BTreeSet::new();
+        while order.len() < self.services.len() {
+            let ready: Vec<&String> = self.services.iter()
+                .filter(|(name, service)| !started.contains(*name)
+                    && service.depends_on.iter().all(|d| started.contains(d)))
+                .map(|(name, _)| name)
+                .collect();
+            if ready.is_empty() {
+                let blocked: Vec<&str> = self.services.keys()
+                    .filter(|name| !started.contains(*name))
+                    .map(String::as_str)
+                    .collect();
+                anyhow::bail!("Dependency cycle between services: {}", blocked.join(", "));
+            }
+            for name in ready {
+                started.insert(name.clone());
+                order.push(name.clone());
+            }
+        }
+        Ok(order)
+    }
+}
+
+/// Start a composition's services in dependency order
+///
+/// Each service is started in the runtime and must report healthy before
+/// the services depending on it start: modules exporting `health` are
+/// polled until it returns 0, others are healthy once running. If a
+/// service fails, the ones already started are stopped and removed again.
+/// Returns the container IDs in start order.
+pub fn launch_composition(spec: &CompositionSpec) -> Result<Vec<ContainerId>> {
+    launch(spec, None)
+}
+
+/// Start the composition in a compose file
+pub fn compose_up(path: &Path) -> Result<Vec<ContainerId>> {
+    let spec = CompositionSpec::load(path)?;
+    launch(&spec, Some(path))
+}
+
+/// Stop and remove the containers of the composition that is up, in reverse start order
+///
+/// Returns the names of the services taken down.
+pub fn compose_down() -> Result<Vec<String>> {
+    let state = match load_state()? {
+        Some(state) => state,
+        None => anyhow::bail!("No composition is up"),
+    };
+    
+    let mut stopped = Vec::new();
+    for (name, id) in state.services.iter().rev() {
+        tear_down(name, id);
+        stopped.push(name.clone());
+    }
+    
+    fs::remove_file(state_path())?;
+    info!("Composition down: {} service(s) stopped", stopped.len());
+    Ok(stopped)
+}
+
+/// Start a composition, recording it as the one that is up
+fn launch(spec: &CompositionSpec, file: Option<&Path>) -> Result<Vec<ContainerId>> {
+    if let Some(state) = load_state()? {
+        anyhow::bail!("A composition of {} service(s) is already up; take it down first", state.services.len());
+    }
+    
+    let order = spec.start_order()?;
+    info!("Starting composition: {}", order.join(" -> "));
+    
+    let mut started: Vec<(String, ContainerId)> = Vec::new();
+    for name in &order {
+        match start_service(name, &spec.services[name]) {
+            Ok(id) => started.push((name.clone(), id)),
+            Err(e) => {
+                warn!("Service {} failed to start; stopping the {} started before it", name, started.len());
+                for (name, id) in started.iter().rev() {
+                    tear_down(name, id);
+                }
+                return Err(e.context(format!("Failed to start service {}", name)));
+            }
+        }
+    }
+    
+    save_state(&CompositionState {
+        file: file.map(Path::to_path_buf),
+        services: started.clone(),
+    })?;
+    
+    crate::logs::ship::ship_audit("matrixbox.compose", &format!(
+        "Composition up: {}", started.iter().map(|(n, id)| format!("{}={}", n, id)).collect::<Vec<_>>().join(", ")));
+    Ok(started.into_iter().map(|(_, id)| id).collect())
+}
+
+/// Start one service and wait for it to become healthy
+fn start_service(name: &str, service: &ServiceSpec) -> Result<ContainerId> {
+    let mut container = super::load_image(&service.image.to_string_lossy())?;
+    
+    // The service's settings override the image's
+    let config = &service.config;
+    container.name = if config.name.is_empty() { name.to_string() } else { config.name.clone() };
+    if let Some(description) = &config.description {
+        container.description = Some(description.clone());
+    }
+    if let Some(version) = &config.version {
+        container.version = version.clone();
+    }
+    if let Some(author) = &config.author {
+        container.author = Some(author.clone());
+    }
+    if config.resource_limits.is_some() {
+        container.metadata.resource_limits = config.resource_limits;
+    }
+    if !config.ports.is_empty() {
+        container.metadata.ports = config.ports.clone();
+    }
+    
+    let args: Vec<&str> = service.args.iter().map(String::as_str).collect();
+    let id = registry::register_container(&container, &args)?;
+    info!("Starting service {} as container {}", name, id);
+    
+    let image_path = container.path.clone()
+        .ok_or_else(|| anyhow::anyhow!("Container has no path"))?;
+    let started = container::create_container_filesystem(&image_path, &id)
+        .and_then(|_| runtime::start_container(&id))
+        .and_then(|_| run_entry_points(&id))
+        .and_then(|_| wait_healthy(&id, service.health_timeout_secs.unwrap_or(DEFAULT_HEALTH_TIMEOUT_SECS)));
+    
+    if let Err(e) = started {
+        tear_down(name, &id);
+        return Err(e);
+    }
+    Ok(id)
+}
+
+/// Run a service's WASI initialization and entry point, where exported
+fn run_entry_points(id: &ContainerId) -> Result<()> {
+    for function in ["_initialize", "_start"] {
+        if runtime::has_function(id, function)? {
+            debug!("Calling {} of container {}", function, id);
+            runtime::execute_function(id, function, &[])?;
+        }
+    }
+    Ok(())
+}
+
+/// Poll a container's health probe until it passes, marking it `Healthy`
+fn wait_healthy(id: &ContainerId, timeout_secs: u64) -> Result<()> {
+    let deadline = Instant::now() + Duration::from_secs(timeout_secs);
+    let probed = runtime::has_function(id, HEALTH_FUNCTION)?;
+    
+    loop {
+        match registry::get_container_status(id)? {
+            ContainerStatus::Failed(message) => anyhow::bail!("Container {} failed: {}", id, message),
+            ContainerStatus::OomKilled => anyhow::bail!("Container {} was killed for exceeding its memory limit", id),
+            ContainerStatus::ResourceExceeded(limit) => anyhow::bail!("Container {} was killed for exceeding its {} limit", id, limit),
+            ContainerStatus::Exited(code) => anyhow::bail!("Container {} exited with code {}", id, code),
+            _ => {}
+        }
+        
+        let healthy = !probed || match runtime::execute_function(id, HEALTH_FUNCTION, &[]) {
+            Ok(results) => matches!(results.first(), Some(wasmer::Value::I32(0))),
+            Err(e) => {
+                debug!("Health probe of container {} failed: {:#}", id, e);
+                false
+            }
+        };
+        if healthy {
+            registry::update_container_status(id, ContainerStatus::Healthy)?;
+            info!("Container {} is healthy", id);
+            return Ok(());
+        }
+        
+        if Instant::now() >= deadline {
+            anyhow::bail!("Container {} did not become healthy within {}s", id, timeout_secs);
+        }
+        std::thread::sleep(HEALTH_POLL_INTERVAL);
+    }
+}
+
+/// Stop and remove a service's container and its layer; failures are logged
+fn tear_down(name: &str, id: &ContainerId) {
+    super::kv::close(id);
+    if let Err(e) = super::remove_container(id, true) {
+        warn!("Failed to remove service {} ({}): {:#}", name, id, e);
+    }
+}
+
+/// The composition that is up, if any
+fn load_state() -> Result<Option<CompositionState>> {
+    let path = state_path();
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&fs::read_to_string(&path)?)
+        .with_context(|| format!("Invalid composition state {:?}", path))?))
+}
+
+/// Record the composition that is up
+fn save_state(state: &CompositionState) -> Result<()> {
+    let path = state_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(state)?)?;
+    Ok(())
+}
+
+/// Path of the composition state
+fn state_path() -> PathBuf {
+    constants::root_dir().join(STATE_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::container::Transport;
+    
+    fn spec(services: &[(&str, &[&str])]) -> CompositionSpec {
+        let services = services.iter().map(|(name, depends_on)| {
+            let service = ServiceSpec {
+                image: PathBuf::from(name),
+                config: ContainerConfig::default(),
+                depends_on: depends_on.iter().map(|d| d.to_string()).collect(),
+                args: Vec::new(),
+                health_timeout_secs: None,
+            };
+            (name.to_string(), service)
+        }).collect();
+        CompositionSpec { services }
+    }
+    
+    #[test]
+    fn compose_files_resolve_images_next_to_them() {
+        let dir = std::env::temp_dir().join(format!("compose-load-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("sentient-compose.yaml");
+        fs::write(&path, r#"
+services:
+  db:
+    image: images/db
+    ports:
+      - { host_port: 15432, container_port: 5432, protocol: tcp }
+  web:
+    image: /srv/images/web.tso
+    name: frontend
+    depends_on: [db]
+    args: ["--port", "80"]
+    health_timeout_secs: 5
+"#).unwrap();
+
+        let spec = CompositionSpec::load(&path).unwrap();
+        let (db, web) = (&spec.services["db"], &spec.services["web"]);
+        assert_eq!(db.image, dir.join("images/db"));
+        assert_eq!((db.config.ports[0].host_port, db.config.ports[0].protocol), (15432, Transport::Tcp));
+        assert!(db.config.name.is_empty() && db.depends_on.is_empty() && db.health_timeout_secs.is_none());
+        
+        assert_eq!(web.image, PathBuf::from("/srv/images/web.tso"));
+        assert_eq!((web.config.name.as_str(), web.health_timeout_secs), ("frontend", Some(5)));
+        assert_eq!((web.depends_on.clone(), web.args.clone()), (vec!["db".to_string()], vec!["--port".to_string(), "80".to_string()]));
+        
+        fs::write(&path, "services:\n  web: {}\n").unwrap();
+        assert!(CompositionSpec::load(&path).is_err());
+        assert!(CompositionSpec::load(&dir.join("missing.yaml")).is_err());
+    }
+    
+    #[test]
+    fn services_start_after_their_dependencies() {
+        let order = spec(&[("web", &["api", "cache"]), ("api", &["db"]), ("db", &[]), ("cache", &[]), ("admin", &[])])
+            .start_order().unwrap();
+        assert_eq!(order, ["admin", "cache", "db", "api", "web"]);
+        assert!(spec(&[]).start_order().unwrap().is_empty());
+        
+        let error = spec(&[("web", &["db"])]).start_order().unwrap_err();
+        assert_eq!(error.to_string(), "Service web depends on unknown service db");
+        
+        let error = spec(&[("a", &["b"]), ("b", &["a"]), ("c", &[]), ("d", &["a"])]).start_order().unwrap_err();
+        assert_eq!(error.to_string(), "Dependency cycle between services: a, b, d");
+    }
+}
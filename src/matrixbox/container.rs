@@ -6,6 +6,11 @@ use std::fs;
 
 use crate::core::constants;
 
+// Constants
+const FILESYSTEMS_DIR: &str = ".matrixbox/fs";
+const FILESYSTEM_FILE: &str = "layer.json";
+const WHITEOUT_PREFIX: &str = ".wh.";
+
 /// Container ID type
 pub type ContainerId = String;
 
@@ -56,6 +61,108 @@ pub struct ContainerMetadata {
     
     /// Container hash tree root
     pub hash_tree_root: String,
+    
+    /// Whether the container can be paused for maintenance
+    #[serde(default = "default_pausable")]
+    pub pausable: bool,
+    
+    /// Order in which containers are paused for maintenance (lower first, resumed last)
+    #[serde(default)]
+    pub pause_order: i32,
+    
+    /// Data synced with paired peers, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync: Option<super::sync::SyncConfig>,
+    
+    /// Hard memory, CPU and process limits, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resource_limits: Option<ResourceLimits>,
+    
+    /// Host ports forwarded into the container
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ports: Vec<NetworkConfig>,
+}
+
+/// Hard resource limits of a container
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ResourceLimits {
+    /// Memory the container's WASM instance may grow to, in bytes
+    pub max_memory_bytes: u64,
+    
+    /// Share of one CPU the container may use (1.0 is a full core)
+    pub max_cpu_fraction: f32,
+    
+    /// Threads and processes the container may run
+    pub max_pids: u32,
+    
+    /// Instructions one call into the container may execute, if limited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_fuel: Option<u64>,
+    
+    /// Wall-clock seconds one call into the container may take, if limited
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_exec_seconds: Option<u64>,
+}
+
+/// Transport protocol of a forwarded port
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    /// TCP
+    Tcp,
+    
+    /// UDP
+    Udp,
+}
+
+impl std::fmt::Display for Transport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Transport::Tcp => write!(f, "tcp"),
+            Transport::Udp => write!(f, "udp"),
+        }
+    }
+}
+
+/// A host port forwarded into a container
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkConfig {
+    /// Port on the host
+    pub host_port: u16,
+    
+    /// Port the container listens on
+    pub container_port: u16,
+    
+    /// Transport protocol
+    pub protocol: Transport,
+}
+
+/// Settings a container is created with
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContainerConfig {
+    /// Container name
+    pub name: String,
+    
+    /// Container description
+    pub description: Option<String>,
+    
+    /// Container version
+    pub version: Option<String>,
+    
+    /// Container author
+    pub author: Option<String>,
+    
+    /// Hard memory, CPU and process limits, if any
+    pub resource_limits: Option<ResourceLimits>,
+    
+    /// Host ports forwarded into the container
+    pub ports: Vec<NetworkConfig>,
+}
+
+/// Containers are pausable unless their metadata says otherwise
+fn default_pausable() -> bool {
+    true
 }
 
 /// Container permissions
@@ -72,6 +179,19 @@ pub struct ContainerPermissions {
     
     /// CPU limit (percentage)
     pub cpu_limit: u8,
+    
+    /// Size quota of the container's kv store in bytes
+    #[serde(default = "default_kv_quota")]
+    pub kv_quota_bytes: u64,
+    
+    /// Capabilities granted beyond the defaults, e.g. `data-sync`
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+}
+
+/// Containers get the default kv quota unless their permissions set one
+fn default_kv_quota() -> u64 {
+    super::kv::DEFAULT_QUOTA_BYTES
 }
 
 /// Network permissions
@@ -101,6 +221,20 @@ pub struct ContainerInfo {
     
     /// Container creation time
     pub created_at: String,
+    
+    /// WASM entrypoint
+    pub entrypoint: String,
+    
+    /// Arguments the container was run with
+    pub args: Vec<String>,
+    
+    /// Host ports forwarded into the container, while it is attached
+    #[serde(default)]
+    pub ports: Vec<super::network::PortMapping>,
+    
+    /// Hard limits the container runs under, if any
+    #[serde(default)]
+    pub resource_limits: Option<ResourceLimits>,
 }
 
 /// Container status
@@ -120,6 +254,18 @@ pub enum ContainerStatus {
     
     /// Container has failed
     Failed(String), // Error message
+    
+    /// Container was killed for exceeding its memory limit
+    OomKilled,
+    
+    /// Container was killed for exceeding its fuel or time limit
+    ResourceExceeded(String), // Limit that tripped
+    
+    /// Container is running and its health probe passes
+    Healthy,
+    
+    /// Container was running when a panic was recorded and may hold inconsistent state
+    Suspect,
 }
 
 /// Load a MatrixBox container from disk
@@ -154,6 +300,9 @@ pub fn load_container(container_path: &str) -> Result<Container> {
         anyhow::bail!("Container permissions.zky not found: {:?}", permissions_path);
     }
     
+    // Images must carry a trusted signature when ZK verification is on
+    super::registry::check_image(&path)?;
+    
     // Load and parse container metadata
     let meta_content = fs::read_to_string(&meta_path)
         .with_context(|| format!("Failed to read meta.yaml: {:?}", meta_path))?;
@@ -195,7 +344,7 @@ pub fn create_container(name: &str, entrypoint: &str) -> Result<Container> {
     info!("Creating new MatrixBox container: {}", name);
     
     // Generate container directory path
-    let container_dir = PathBuf::from(constants::ROOT_DIR)
+    let container_dir = constants::root_dir()
         .join(constants::CONTAINER_DIR)
         .join(name);
     
@@ -215,6 +364,11 @@ pub fn create_container(name: &str, entrypoint: &str) -> Result<Container> {
         environment: Vec::new(),
         dependencies: Vec::new(),
         hash_tree_root: "0000000000000000000000000000000000000000000000000000000000000000".to_string(),
+        pausable: true,
+        pause_order: 0,
+        sync: None,
+        resource_limits: None,
+        ports: Vec::new(),
     };
     
     // Create default container permissions
@@ -229,6 +383,8 @@ pub fn create_container(name: &str, entrypoint: &str) -> Result<Container> {
         },
         memory_limit: 1024 * 1024 * 100, // 100MB
         cpu_limit: 50, // 50% CPU
+        kv_quota_bytes: default_kv_quota(),
+        capabilities: Vec::new(),
     };
     
     // Write container files
@@ -297,6 +453,378 @@ pub fn generate_container_id() -> ContainerId {
     format!("{:016x}", rng.gen::<u64>())
 }
 
+/// How a container's filesystem layers are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FilesystemMode {
+    /// Mounted with overlayfs at `merged_dir`
+    Overlay,
+    
+    /// Combined by the runtime: files are copied to `upper_dir` before their first write
+    CopyOnWrite,
+}
+
+/// A container's filesystem: its shared image under its own writable layer
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContainerFilesystem {
+    /// Container the filesystem belongs to
+    pub container_id: ContainerId,
+    
+    /// Image layer, shared and never written
+    pub lower_dir: PathBuf,
+    
+    /// Changes made by this container
+    pub upper_dir: PathBuf,
+    
+    /// Scratch directory overlayfs needs next to `upper_dir`
+    pub work_dir: PathBuf,
+    
+    /// Combined view when mounted with overlayfs
+    pub merged_dir: PathBuf,
+    
+    /// How the layers are combined
+    pub mode: FilesystemMode,
+}
+
+/// How a file in a container's writable layer differs from its image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChangeKind {
+    /// Not in the image
+    Added,
+    
+    /// In the image with other contents
+    Modified,
+    
+    /// In the image but deleted by the container
+    Deleted,
+}
+
+/// A file changed by a container
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileChange {
+    /// Path relative to the container's root
+    pub path: String,
+    
+    /// What changed
+    pub kind: ChangeKind,
+}
+
+impl ContainerFilesystem {
+    /// Directory the container sees as its own: the overlay mount, or the
+    /// writable layer when copying on write
+    pub fn root(&self) -> &Path {
+        match self.mode {
+            FilesystemMode::Overlay => &self.merged_dir,
+            FilesystemMode::CopyOnWrite => &self.upper_dir,
+        }
+    }
+    
+    /// Where to read a file from, `None` if it does not exist or was deleted
+    pub fn read_path(&self, relative: &str) -> Result<Option<PathBuf>> {
+        let relative = checked_relative(relative)?;
+        if self.mode == FilesystemMode::Overlay {
+            let path = self.merged_dir.join(relative);
+            return Ok(path.exists().then_some(path));
+        }
+        
+        let upper = self.upper_dir.join(relative);
+        if upper.exists() {
+            return Ok(Some(upper));
+        }
+        if self.whiteout_path(relative).exists() {
+            return Ok(None);
+        }
+        let lower = self.lower_dir.join(relative);
+        Ok(lower.exists().then_some(lower))
+    }
+    
+    /// Where to write a file, copying it out of the image first if needed
+    pub fn write_path(&self, relative: &str) -> Result<PathBuf> {
+        let relative = checked_relative(relative)?;
+        if self.mode == FilesystemMode::Overlay {
+            return Ok(self.merged_dir.join(relative));
+        }
+        
+        let upper = self.upper_dir.join(relative);
+        if let Some(parent) = upper.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        
+        // A deleted file is written from scratch rather than copied back
+        let whiteout = self.whiteout_path(relative);
+        if whiteout.exists() {
+            fs::remove_file(&whiteout)?;
+        } else if !upper.exists() {
+            let lower = self.lower_dir.join(relative);
+            if lower.is_file() {
+                fs::copy(&lower, &upper)
+                    .with_context(|| format!("Failed to copy {:?} into the container layer", lower))?;
+            }
+        }
+        Ok(upper)
+    }
+    
+    /// Delete a file from the container's view, leaving the image untouched
+    pub fn remove_file(&self, relative: &str) -> Result<()> {
+        let relative = checked_relative(relative)?;
+        if self.mode == FilesystemMode::Overlay {
+            return fs::remove_file(self.merged_dir.join(relative)).map_err(Into::into);
+        }
+        
+        let upper = self.upper_dir.join(relative);
+        if upper.exists() {
+            fs::remove_file(&upper)?;
+        }
+        
+        // Hide the image's copy behind a whiteout marker
+        if self.lower_dir.join(relative).exists() {
+            let whiteout = self.whiteout_path(relative);
+            if let Some(parent) = whiteout.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&whiteout, [])?;
+        }
+        Ok(())
+    }
+    
+    /// Copy every image file that has no copy yet into the writable layer
+    ///
+    /// WASI guests open files directly, so writes cannot be intercepted to
+    /// copy them up one at a time. Without overlayfs, the layer is filled
+    /// before a guest gets it as its root. Unchanged copies are not reported
+    /// by `diff`.
+    pub fn materialize(&self) -> Result<()> {
+        if self.mode == FilesystemMode::Overlay {
+            return Ok(());
+        }
+        
+        for relative in list_files(&self.lower_dir)? {
+            let upper = self.upper_dir.join(&relative);
+            if upper.exists() || self.whiteout_path(Path::new(&relative)).exists() {
+                continue;
+            }
+            if let Some(parent) = upper.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::copy(self.lower_dir.join(&relative), &upper)?;
+        }
+        Ok(())
+    }
+    
+    /// Files the container added, modified or deleted, by path
+    pub fn diff(&self) -> Result<Vec<FileChange>> {
+        let mut changes = Vec::new();
+        
+        for relative in list_files(&self.upper_dir)? {
+            let path = Path::new(&relative);
+            let name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            
+            // Whiteouts: the shim's `.wh.` markers, or overlayfs' 0:0 character devices
+            if let Some(deleted) = name.strip_prefix(WHITEOUT_PREFIX) {
+                let deleted = path.with_file_name(deleted);
+                changes.push(FileChange { path: deleted.to_string_lossy().to_string(), kind: ChangeKind::Deleted });
+                continue;
+            }
+            if is_overlay_whiteout(&self.upper_dir.join(path)) {
+                changes.push(FileChange { path: relative, kind: ChangeKind::Deleted });
+                continue;
+            }
+            
+            let lower = self.lower_dir.join(path);
+            if !lower.is_file() {
+                changes.push(FileChange { path: relative, kind: ChangeKind::Added });
+            } else if !same_contents(&lower, &self.upper_dir.join(path))? {
+                changes.push(FileChange { path: relative, kind: ChangeKind::Modified });
+            }
+        }
+        
+        changes.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(changes)
+    }
+    
+    /// Unmount the overlay, if mounted; the writable layer is kept
+    pub fn unmount(&self) -> Result<()> {
+        if self.mode == FilesystemMode::Overlay {
+            unmount_overlay(&self.merged_dir)?;
+        }
+        Ok(())
+    }
+    
+    /// Marker hiding a deleted image file in the copy-on-write layer
+    fn whiteout_path(&self, relative: &Path) -> PathBuf {
+        let name = relative.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+        self.upper_dir.join(relative).with_file_name(format!("{}{}", WHITEOUT_PREFIX, name))
+    }
+}
+
+/// Set up a container's filesystem over an image
+///
+/// The writable layer lives in `.matrixbox/fs/<container_id>/`. On Linux
+/// the layers are mounted with overlayfs; where that is unavailable (other
+/// platforms, or no privilege to mount) the runtime copies files on write.
+pub fn create_container_filesystem(image_path: &Path, container_id: &ContainerId) -> Result<ContainerFilesystem> {
+    let lower_dir = image_path.canonicalize()
+        .with_context(|| format!("Container image not found: {:?}", image_path))?;
+    let layer_dir = filesystem_dir(container_id);
+    
+    let mut filesystem = ContainerFilesystem {
+        container_id: container_id.clone(),
+        lower_dir,
+        upper_dir: layer_dir.join("upper"),
+        work_dir: layer_dir.join("work"),
+        merged_dir: layer_dir.join("merged"),
+        mode: FilesystemMode::CopyOnWrite,
+    };
+    fs::create_dir_all(&filesystem.upper_dir)?;
+    fs::create_dir_all(&filesystem.work_dir)?;
+    fs::create_dir_all(&filesystem.merged_dir)?;
+    
+    match mount_overlay(&filesystem) {
+        Ok(()) => filesystem.mode = FilesystemMode::Overlay,
+        Err(e) => warn!("Overlayfs unavailable for container {} ({}); copying files on write", container_id, e),
+    }
+    
+    fs::write(layer_dir.join(FILESYSTEM_FILE), serde_json::to_string_pretty(&filesystem)?)?;
+    info!("Created {:?} filesystem for container {} over {:?}", filesystem.mode, container_id, filesystem.lower_dir);
+    Ok(filesystem)
+}
+
+/// A container's filesystem, if one was created for it
+pub fn container_filesystem(container_id: &ContainerId) -> Result<Option<ContainerFilesystem>> {
+    let path = filesystem_dir(container_id).join(FILESYSTEM_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    
+    let filesystem = serde_json::from_str(&fs::read_to_string(&path)?)
+        .with_context(|| format!("Invalid container filesystem record {:?}", path))?;
+    Ok(Some(filesystem))
+}
+
+/// Unmount a container's writable layer, leaving it on disk
+pub fn unmount_container_filesystem(container_id: &ContainerId) -> Result<()> {
+    if let Some(filesystem) = container_filesystem(container_id)? {
+        filesystem.unmount()?;
+    }
+    Ok(())
+}
+
+/// Unmount and delete a container's writable layer; the image is left intact
+pub fn remove_container_filesystem(container_id: &ContainerId) -> Result<()> {
+    unmount_container_filesystem(container_id)?;
+    
+    let layer_dir = filesystem_dir(container_id);
+    if layer_dir.exists() {
+        fs::remove_dir_all(&layer_dir)
+            .with_context(|| format!("Failed to delete container layer {:?}", layer_dir))?;
+    }
+    Ok(())
+}
+
+/// Directory holding a container's writable layer
+pub fn filesystem_dir(container_id: &ContainerId) -> PathBuf {
+    constants::root_dir().join(FILESYSTEMS_DIR).join(container_id)
+}
+
+/// Reject paths that would leave the container's root
+fn checked_relative(relative: &str) -> Result<&Path> {
+    let path = Path::new(relative.trim_start_matches('/'));
+    if path.components().any(|c| !matches!(c, std::path::Component::Normal(_))) {
+        anyhow::bail!("Invalid container path: {}", relative);
+    }
+    Ok(path)
+}
+
+/// Every file below a directory, as relative paths
+fn list_files(dir: &Path) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    
+    while let Some(current) = pending.pop() {
+        if !current.exists() {
+            continue;
+        }
+        for entry in fs::read_dir(&current)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                pending.push(path);
+            } else if let Ok(relative) = path.strip_prefix(dir) {
+                files.push(relative.to_string_lossy().to_string());
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Whether two files have the same contents
+fn same_contents(a: &Path, b: &Path) -> Result<bool> {
+    if fs::metadata(a)?.len() != fs::metadata(b)?.len() {
+        return Ok(false);
+    }
+    Ok(fs::read(a)? == fs::read(b)?)
+}
+
+/// Whether a path is an overlayfs whiteout (a 0:0 character device)
+#[cfg(target_os = "linux")]
+fn is_overlay_whiteout(path: &Path) -> bool {
+    use std::os::unix::fs::{FileTypeExt, MetadataExt};
+    fs::symlink_metadata(path).map_or(false, |m| m.file_type().is_char_device() && m.rdev() == 0)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_overlay_whiteout(_path: &Path) -> bool {
+    false
+}
+
+/// Mount a container's layers with overlayfs
+#[cfg(target_os = "linux")]
+fn mount_overlay(filesystem: &ContainerFilesystem) -> Result<()> {
+    use std::ffi::CString;
+    
+    // overlayfs separates options with commas and lower layers with colons
+    let dirs = [&filesystem.lower_dir, &filesystem.upper_dir, &filesystem.work_dir];
+    if dirs.iter().any(|d| d.to_string_lossy().contains([',', ':'])) {
+        anyhow::bail!("layer paths contain ',' or ':'");
+    }
+    
+    let options = format!("lowerdir={},upperdir={},workdir={}",
+                          filesystem.lower_dir.display(), filesystem.upper_dir.display(), filesystem.work_dir.display());
+    let source = CString::new("overlay")?;
+    let target = CString::new(filesystem.merged_dir.to_string_lossy().as_bytes())?;
+    let options = CString::new(options)?;
+    
+    let result = unsafe {
+        libc::mount(source.as_ptr(), target.as_ptr(), source.as_ptr(), 0, options.as_ptr() as *const libc::c_void)
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error()).context("mount failed");
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn mount_overlay(_filesystem: &ContainerFilesystem) -> Result<()> {
+    anyhow::bail!("overlayfs requires Linux")
+}
+
+/// Unmount an overlay; a directory that is not mounted is left alone
+#[cfg(target_os = "linux")]
+fn unmount_overlay(merged_dir: &Path) -> Result<()> {
+    let target = std::ffi::CString::new(merged_dir.to_string_lossy().as_bytes())?;
+    if unsafe { libc::umount2(target.as_ptr(), libc::MNT_DETACH) } != 0 {
+        let error = std::io::Error::last_os_error();
+        if error.raw_os_error() != Some(libc::EINVAL) && error.raw_os_error() != Some(libc::ENOENT) {
+            return Err(error).with_context(|| format!("Failed to unmount {:?}", merged_dir));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unmount_overlay(_merged_dir: &Path) -> Result<()> {
+    Ok(())
+}
+
 /// Example TSO container structure
 pub fn example_container_files() -> Vec<(String, String)> {
     vec![
@@ -311,6 +839,8 @@ environment:
 dependencies:
   - std.wasm
 hash_tree_root: '0000000000000000000000000000000000000000000000000000000000000000'
+pausable: true
+pause_order: 0
 "#.to_string()
         ),
         (
@@ -334,3 +864,110 @@ cpu_limit: 50  # 50% CPU
         ),
     ]
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// A copy-on-write filesystem over a fresh image holding `files`
+    fn layered(name: &str, files: &[(&str, &str)]) -> ContainerFilesystem {
+        let dir = std::env::temp_dir().join(format!("matrixbox-layers-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, content) in files {
+            let path = dir.join("image").join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        fs::create_dir_all(dir.join("image")).unwrap();
+        
+        ContainerFilesystem {
+            container_id: name.to_string(),
+            lower_dir: dir.join("image"),
+            upper_dir: dir.join("upper"),
+            work_dir: dir.join("work"),
+            merged_dir: dir.join("merged"),
+            mode: FilesystemMode::CopyOnWrite,
+        }
+    }
+    
+    fn change(path: &str, kind: ChangeKind) -> FileChange {
+        FileChange { path: path.to_string(), kind }
+    }
+    
+    #[test]
+    fn writes_copy_files_out_of_the_image_and_deletes_hide_them() {
+        let filesystem = layered("cow", &[("etc/app.conf", "original"), ("data/seed.txt", "seed")]);
+        assert_eq!(filesystem.root(), filesystem.upper_dir.as_path());
+        assert_eq!(filesystem.read_path("/etc/app.conf").unwrap(), Some(filesystem.lower_dir.join("etc/app.conf")));
+        assert_eq!(filesystem.read_path("missing").unwrap(), None);
+        
+        // The first write starts from the image's copy
+        let path = filesystem.write_path("etc/app.conf").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "original");
+        fs::write(&path, "changed").unwrap();
+        assert_eq!(filesystem.read_path("etc/app.conf").unwrap(), Some(path));
+        assert_eq!(fs::read_to_string(filesystem.lower_dir.join("etc/app.conf")).unwrap(), "original");
+        
+        filesystem.remove_file("data/seed.txt").unwrap();
+        assert_eq!(filesystem.read_path("data/seed.txt").unwrap(), None);
+        assert!(filesystem.lower_dir.join("data/seed.txt").exists());
+        
+        fs::write(filesystem.write_path("logs/run.log").unwrap(), "started").unwrap();
+        assert_eq!(filesystem.diff().unwrap(), vec![
+            change("data/seed.txt", ChangeKind::Deleted),
+            change("etc/app.conf", ChangeKind::Modified),
+            change("logs/run.log", ChangeKind::Added),
+        ]);
+        
+        // A deleted file written again starts empty instead of from the image
+        let path = filesystem.write_path("data/seed.txt").unwrap();
+        assert!(!path.exists());
+        fs::write(&path, "replanted").unwrap();
+        assert_eq!(filesystem.read_path("data/seed.txt").unwrap(), Some(path));
+        assert!(filesystem.diff().unwrap().contains(&change("data/seed.txt", ChangeKind::Modified)));
+    }
+    
+    #[test]
+    fn materialized_copies_are_not_reported_as_changes() {
+        let filesystem = layered("materialize", &[("a.txt", "a"), ("nested/b.txt", "b"), ("nested/c.txt", "c")]);
+        filesystem.remove_file("nested/c.txt").unwrap();
+        fs::write(filesystem.write_path("a.txt").unwrap(), "edited").unwrap();
+        
+        filesystem.materialize().unwrap();
+        assert_eq!(fs::read_to_string(filesystem.upper_dir.join("nested/b.txt")).unwrap(), "b");
+        assert_eq!(fs::read_to_string(filesystem.upper_dir.join("a.txt")).unwrap(), "edited");
+        assert!(!filesystem.upper_dir.join("nested/c.txt").exists());
+        assert_eq!(filesystem.diff().unwrap(), vec![
+            change("a.txt", ChangeKind::Modified),
+            change("nested/c.txt", ChangeKind::Deleted),
+        ]);
+    }
+    
+    #[test]
+    fn paths_cannot_leave_the_container_root() {
+        let filesystem = layered("escape", &[]);
+        for path in ["../outside", "etc/../../outside", "./etc"] {
+            assert!(filesystem.read_path(path).is_err(), "{}", path);
+            assert!(filesystem.write_path(path).is_err(), "{}", path);
+            assert!(filesystem.remove_file(path).is_err(), "{}", path);
+        }
+    }
+    
+    #[test]
+    fn removing_a_filesystem_deletes_only_its_layer() {
+        let id = format!("layer-test-{}", std::process::id());
+        let mut filesystem = layered("record", &[("app.wasm", "image")]);
+        filesystem.container_id = id.clone();
+        filesystem.upper_dir = filesystem_dir(&id).join("upper");
+        fs::write(filesystem.write_path("app.wasm").unwrap(), "patched").unwrap();
+        fs::write(filesystem_dir(&id).join(FILESYSTEM_FILE), serde_json::to_string(&filesystem).unwrap()).unwrap();
+        
+        let loaded = container_filesystem(&id).unwrap().unwrap();
+        assert_eq!((loaded.upper_dir, loaded.mode), (filesystem.upper_dir.clone(), FilesystemMode::CopyOnWrite));
+        
+        remove_container_filesystem(&id).unwrap();
+        assert!(!filesystem_dir(&id).exists());
+        assert!(container_filesystem(&id).unwrap().is_none());
+        assert_eq!(fs::read_to_string(filesystem.lower_dir.join("app.wasm")).unwrap(), "image");
+    }
+}
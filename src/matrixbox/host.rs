@@ -0,0 +1,227 @@
+// SentientOS MatrixBox Host ABI
+// Host functions imported by containers from the `sentient` module
+
+use anyhow::Result;
+use tracing::{debug, info, warn, error};
+use std::time::{SystemTime, UNIX_EPOCH};
+use wasmer::{Function, FunctionEnv, FunctionEnvMut, Imports, Instance, Memory, RuntimeError, Store};
+
+use super::container::ContainerId;
+use super::kv::{self, KvOp, QuotaExceeded};
+
+/// Import module name of the host ABI
+pub const ABI_MODULE: &str = "sentient";
+
+/// Largest single log message accepted from a container
+const MAX_LOG_BYTES: i32 = 64 * 1024;
+
+/// Largest kv transaction batch accepted from a container
+const MAX_KV_BATCH_BYTES: i32 = 4 * 1024 * 1024;
+
+/// kv return code: the key does not exist
+pub const KV_NOT_FOUND: i32 = -1;
+
+/// kv return code: the operation failed; details are logged
+pub const KV_ERROR: i32 = -2;
+
+/// kv return code: the write would exceed the container's kv quota
+pub const KV_QUOTA_EXCEEDED: i32 = -3;
+
+/// Per-instance state available to host functions
+pub struct HostEnv {
+    /// Container the instance belongs to
+    container_id: ContainerId,
+    
+    /// Exported linear memory, set once the instance exists
+    memory: Option<Memory>,
+}
+
+/// Add the host ABI to an import object
+pub fn register(store: &mut Store, imports: &mut Imports, container_id: &ContainerId) -> FunctionEnv<HostEnv> {
+    let env = FunctionEnv::new(store, HostEnv {
+        container_id: container_id.clone(),
+        memory: None,
+    });
+    
+    imports.define(ABI_MODULE, "log", Function::new_typed_with_env(store, &env, host_log));
+    imports.define(ABI_MODULE, "now_ms", Function::new_typed(store, host_now_ms));
+    imports.define(ABI_MODULE, "container_id", Function::new_typed_with_env(store, &env, host_container_id));
+    imports.define(ABI_MODULE, "kv_get", Function::new_typed_with_env(store, &env, host_kv_get));
+    imports.define(ABI_MODULE, "kv_put", Function::new_typed_with_env(store, &env, host_kv_put));
+    imports.define(ABI_MODULE, "kv_del", Function::new_typed_with_env(store, &env, host_kv_del));
+    imports.define(ABI_MODULE, "kv_list", Function::new_typed_with_env(store, &env, host_kv_list));
+    imports.define(ABI_MODULE, "kv_txn", Function::new_typed_with_env(store, &env, host_kv_txn));
+    
+    env
+}
+
+/// Give host functions access to the instance memory
+pub fn attach(store: &mut Store, env: &FunctionEnv<HostEnv>, instance: &Instance) -> Result<()> {
+    let memory = instance.exports.get_memory("memory")?.clone();
+    env.as_mut(store).memory = Some(memory);
+    Ok(())
+}
+
+/// sentient.log(level, ptr, len)
+fn host_log(env: FunctionEnvMut<HostEnv>, level: i32, ptr: i32, len: i32) -> Result<(), RuntimeError> {
+    if !(0..=MAX_LOG_BYTES).contains(&len) {
+        return Err(RuntimeError::new(format!("Invalid log length: {}", len)));
+    }
+    
+    let data = env.data();
+    let memory = data.memory.as_ref()
+        .ok_or_else(|| RuntimeError::new("Instance memory not attached"))?;
+    
+    let mut buf = vec![0u8; len as usize];
+    memory.view(&env).read(ptr as u32 as u64, &mut buf)
+        .map_err(|e| RuntimeError::new(e.to_string()))?;
+    let message = String::from_utf8_lossy(&buf);
+    
+    match level {
+        0 => debug!("[{}] {}", data.container_id, message),
+        1 => info!("[{}] {}", data.container_id, message),
+        2 => warn!("[{}] {}", data.container_id, message),
+        _ => error!("[{}] {}", data.container_id, message),
+    }
+    Ok(())
+}
+
+/// sentient.now_ms() -> i64
+fn host_now_ms() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as i64).unwrap_or(0)
+}
+
+/// sentient.container_id(ptr, cap) -> len
+///
+/// Writes the ID if it fits and always returns its full length, so the
+/// guest can retry with a larger buffer.
+fn host_container_id(env: FunctionEnvMut<HostEnv>, ptr: i32, cap: i32) -> Result<i32, RuntimeError> {
+    let data = env.data();
+    let id = data.container_id.as_bytes();
+    
+    if id.len() as i32 <= cap {
+        let memory = data.memory.as_ref()
+            .ok_or_else(|| RuntimeError::new("Instance memory not attached"))?;
+        memory.view(&env).write(ptr as u32 as u64, id)
+            .map_err(|e| RuntimeError::new(e.to_string()))?;
+    }
+    
+    Ok(id.len() as i32)
+}
+
+/// sentient.kv_get(key_ptr, key_len, val_ptr, val_cap) -> len
+///
+/// Writes the value if it fits and returns its full length, or
+/// `KV_NOT_FOUND`.
+fn host_kv_get(env: FunctionEnvMut<HostEnv>, key_ptr: i32, key_len: i32, val_ptr: i32, val_cap: i32) -> Result<i32, RuntimeError> {
+    let key = read_guest(&env, key_ptr, key_len, kv::MAX_KEY_BYTES as i32)?;
+    let value = match kv::open(&env.data().container_id) {
+        Ok(store) => store.lock().unwrap().get(&key),
+        Err(e) => return Ok(kv_error(&env, e)),
+    };
+    
+    match value {
+        Some(value) => {
+            if value.len() as i32 <= val_cap {
+                write_guest(&env, val_ptr, &value)?;
+            }
+            Ok(value.len() as i32)
+        }
+        None => Ok(KV_NOT_FOUND),
+    }
+}
+
+/// sentient.kv_put(key_ptr, key_len, val_ptr, val_len, ttl_ms) -> status
+///
+/// A `ttl_ms` of 0 or less keeps the key until it is deleted.
+fn host_kv_put(env: FunctionEnvMut<HostEnv>, key_ptr: i32, key_len: i32, val_ptr: i32, val_len: i32, ttl_ms: i64) -> Result<i32, RuntimeError> {
+    let key = read_guest(&env, key_ptr, key_len, kv::MAX_KEY_BYTES as i32)?;
+    let value = read_guest(&env, val_ptr, val_len, kv::MAX_VALUE_BYTES as i32)?;
+    let ttl_ms = if ttl_ms > 0 { Some(ttl_ms as u64) } else { None };
+    
+    Ok(kv_commit(&env, vec![KvOp::Put { key, value, ttl_ms }]))
+}
+
+/// sentient.kv_del(key_ptr, key_len) -> status
+fn host_kv_del(env: FunctionEnvMut<HostEnv>, key_ptr: i32, key_len: i32) -> Result<i32, RuntimeError> {
+    let key = read_guest(&env, key_ptr, key_len, kv::MAX_KEY_BYTES as i32)?;
+    Ok(kv_commit(&env, vec![KvOp::Delete { key }]))
+}
+
+/// sentient.kv_list(prefix_ptr, prefix_len, out_ptr, out_cap) -> len
+///
+/// Writes the matching keys, each as a little-endian u32 length followed by
+/// the key, if they fit; always returns the full length.
+fn host_kv_list(env: FunctionEnvMut<HostEnv>, prefix_ptr: i32, prefix_len: i32, out_ptr: i32, out_cap: i32) -> Result<i32, RuntimeError> {
+    let prefix = read_guest(&env, prefix_ptr, prefix_len, kv::MAX_KEY_BYTES as i32)?;
+    let entries = match kv::open(&env.data().container_id) {
+        Ok(store) => store.lock().unwrap().list(&prefix),
+        Err(e) => return Ok(kv_error(&env, e)),
+    };
+    
+    let mut out = Vec::new();
+    for (key, _) in entries {
+        out.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        out.extend_from_slice(&key);
+    }
+    
+    if out.len() > i32::MAX as usize {
+        return Ok(KV_ERROR);
+    }
+    if out.len() as i32 <= out_cap {
+        write_guest(&env, out_ptr, &out)?;
+    }
+    Ok(out.len() as i32)
+}
+
+/// sentient.kv_txn(batch_ptr, batch_len) -> status
+///
+/// Applies every operation of the batch or none; see `kv::decode_batch`
+/// for the encoding.
+fn host_kv_txn(env: FunctionEnvMut<HostEnv>, batch_ptr: i32, batch_len: i32) -> Result<i32, RuntimeError> {
+    let batch = read_guest(&env, batch_ptr, batch_len, MAX_KV_BATCH_BYTES)?;
+    match kv::decode_batch(&batch) {
+        Ok(ops) => Ok(kv_commit(&env, ops)),
+        Err(e) => Ok(kv_error(&env, e)),
+    }
+}
+
+/// Commit operations to the container's store, mapping failures to codes
+fn kv_commit(env: &FunctionEnvMut<HostEnv>, ops: Vec<KvOp>) -> i32 {
+    match kv::open(&env.data().container_id).and_then(|store| store.lock().unwrap().commit(ops)) {
+        Ok(()) => 0,
+        Err(e) => kv_error(env, e),
+    }
+}
+
+/// Log a kv failure and return its code
+fn kv_error(env: &FunctionEnvMut<HostEnv>, error: anyhow::Error) -> i32 {
+    if error.downcast_ref::<QuotaExceeded>().is_some() {
+        debug!("[{}] {}", env.data().container_id, error);
+        return KV_QUOTA_EXCEEDED;
+    }
+    warn!("[{}] kv operation failed: {}", env.data().container_id, error);
+    KV_ERROR
+}
+
+/// Copy bytes out of guest memory
+fn read_guest(env: &FunctionEnvMut<HostEnv>, ptr: i32, len: i32, max: i32) -> Result<Vec<u8>, RuntimeError> {
+    if !(0..=max).contains(&len) {
+        return Err(RuntimeError::new(format!("Invalid length: {}", len)));
+    }
+    
+    let memory = env.data().memory.as_ref()
+        .ok_or_else(|| RuntimeError::new("Instance memory not attached"))?;
+    let mut buf = vec![0u8; len as usize];
+    memory.view(env).read(ptr as u32 as u64, &mut buf)
+        .map_err(|e| RuntimeError::new(e.to_string()))?;
+    Ok(buf)
+}
+
+/// Copy bytes into guest memory
+fn write_guest(env: &FunctionEnvMut<HostEnv>, ptr: i32, data: &[u8]) -> Result<(), RuntimeError> {
+    let memory = env.data().memory.as_ref()
+        .ok_or_else(|| RuntimeError::new("Instance memory not attached"))?;
+    memory.view(env).write(ptr as u32 as u64, data)
+        .map_err(|e| RuntimeError::new(e.to_string()))
+}
@@ -0,0 +1,865 @@
+// SentientOS MatrixBox Key-Value Store
+// Per-container log-structured kv engine with transactions, TTLs and quotas
+
+use anyhow::{Result, Context};
+use tracing::{info, debug, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+use crate::core::{constants, lock};
+use super::container::ContainerId;
+
+// Constants
+const DATA_DIR: &str = ".matrixbox/data";
+const KV_DIR: &str = "kv";
+const LOG_FILE: &str = "data.log";
+const WAL_FILE: &str = "wal.log";
+const COMPACT_SUFFIX: &str = "compact";
+
+/// Quota of containers that don't declare one
+pub const DEFAULT_QUOTA_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Largest key accepted
+pub const MAX_KEY_BYTES: usize = 1024;
+
+/// Largest value accepted
+pub const MAX_VALUE_BYTES: usize = 1024 * 1024;
+
+/// The data log is compacted once it is this many times the live size
+const COMPACT_RATIO: u64 = 4;
+
+/// Data logs below this size are never compacted
+const COMPACT_MIN_BYTES: u64 = 64 * 1024;
+
+/// Frame header: payload length (u32) and blake3 of the payload
+const FRAME_HEADER_BYTES: usize = 4 + 32;
+
+// Stores opened by this process, one per container
+lazy_static::lazy_static! {
+    static ref OPEN_STORES: Arc<Mutex<HashMap<ContainerId, Arc<Mutex<KvStore>>>>> =
+        Arc::new(Mutex::new(HashMap::new()));
+}
+
+/// A write would take a container's kv store over its quota
+#[derive(Debug, Clone, Error)]
+#[error("kv quota of container {container} exceeded: {needed} bytes needed, quota is {quota}")]
+pub struct QuotaExceeded {
+    /// Container ID
+    pub container: ContainerId,
+    
+    /// Live bytes the store would hold after the write
+    pub needed: u64,
+    
+    /// Quota in bytes
+    pub quota: u64,
+}
+
+/// One operation of a transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KvOp {
+    /// Set a key, optionally expiring after `ttl_ms`
+    Put { key: Vec<u8>, value: Vec<u8>, ttl_ms: Option<u64> },
+    
+    /// Remove a key
+    Delete { key: Vec<u8> },
+}
+
+/// A write as recorded in the logs; expiry is absolute
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum LogWrite {
+    Put { key: Vec<u8>, value: Vec<u8>, expires_at_ms: Option<u64> },
+    Delete { key: Vec<u8> },
+}
+
+/// A committed transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LogRecord {
+    seq: u64,
+    writes: Vec<LogWrite>,
+}
+
+/// A live value
+#[derive(Debug, Clone)]
+struct Entry {
+    value: Vec<u8>,
+    expires_at_ms: Option<u64>,
+}
+
+impl Entry {
+    fn is_expired(&self, now_ms: u64) -> bool {
+        self.expires_at_ms.map(|t| t <= now_ms).unwrap_or(false)
+    }
+}
+
+/// Usage of a container's kv store
+#[derive(Debug, Clone, Copy)]
+pub struct KvUsage {
+    /// Live keys
+    pub keys: usize,
+    
+    /// Live bytes (keys plus values)
+    pub used_bytes: u64,
+    
+    /// Quota in bytes
+    pub quota_bytes: u64,
+    
+    /// Size of the data log on disk
+    pub log_bytes: u64,
+}
+
+/// A container's kv store
+///
+/// Every transaction is appended to the write-ahead log and synced before
+/// it is applied to the data log, so a crash at any point either loses the
+/// whole transaction or replays it on the next open. The data log is an
+/// append-only sequence of checksummed records; a torn record at its tail
+/// is cut off when the store is opened.
+pub struct KvStore {
+    container_id: ContainerId,
+    dir: PathBuf,
+    quota: u64,
+    entries: BTreeMap<Vec<u8>, Entry>,
+    used: u64,
+    seq: u64,
+    log_bytes: u64,
+    _lock: lock::LockGuard,
+}
+
+impl KvStore {
+    /// Open a store directory, recovering from an interrupted commit
+    pub fn open(container_id: &str, dir: &Path, quota: u64) -> Result<Self> {
+        let guard = lock::try_lock(&format!("kv-{}", container_id), "kv store")?
+            .ok_or_else(|| anyhow::anyhow!("kv store of container {} is open in another process", container_id))?;
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create kv directory: {:?}", dir))?;
+        
+        let mut store = Self {
+            container_id: container_id.to_string(),
+            dir: dir.to_path_buf(),
+            quota,
+            entries: BTreeMap::new(),
+            used: 0,
+            seq: 0,
+            log_bytes: 0,
+            _lock: guard,
+        };
+        
+        let log_path = store.dir.join(LOG_FILE);
+        let (records, valid_len) = read_log(&log_path)?;
+        for record in &records {
+            store.apply(record);
+        }
+        if log_path.exists() && fs::metadata(&log_path)?.len() > valid_len {
+            warn!("Truncating torn tail of kv log for container {}", container_id);
+            OpenOptions::new().write(true).open(&log_path)?.set_len(valid_len)?;
+        }
+        store.log_bytes = valid_len;
+        
+        // Redo committed transactions that never reached the data log
+        let wal_path = store.dir.join(WAL_FILE);
+        let (pending, _) = read_log(&wal_path)?;
+        let applied_seq = store.seq;
+        for record in pending.into_iter().filter(|r| r.seq > applied_seq) {
+            debug!("Replaying kv transaction {} for container {}", record.seq, container_id);
+            store.append_log(&record)?;
+            store.apply(&record);
+        }
+        if wal_path.exists() {
+            File::create(&wal_path)?.sync_all()?;
+        }
+        
+        store.purge_expired();
+        Ok(store)
+    }
+    
+    /// Value of a key; expired keys are dropped on access
+    pub fn get(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        if self.entries.get(key).map(|e| e.is_expired(now_ms())).unwrap_or(false) {
+            self.remove_entry(key);
+            return None;
+        }
+        self.entries.get(key).map(|e| e.value.clone())
+    }
+    
+    /// Live keys and values starting with a prefix, in key order
+    pub fn list(&mut self, prefix: &[u8]) -> Vec<(Vec<u8>, Vec<u8>)> {
+        self.purge_expired();
+        self.entries.range(prefix.to_vec()..)
+            .take_while(|(k, _)| k.starts_with(prefix))
+            .map(|(k, e)| (k.clone(), e.value.clone()))
+            .collect()
+    }
+    
+    /// Set one key
+    pub fn put(&mut self, key: &[u8], value: &[u8], ttl_ms: Option<u64>) -> Result<()> {
+        self.commit(vec![KvOp::Put { key: key.to_vec(), value: value.to_vec(), ttl_ms }])
+    }
+    
+    /// Remove one key
+    pub fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.commit(vec![KvOp::Delete { key: key.to_vec() }])
+    }
+    
+    /// Apply a batch of operations atomically
+    ///
+    /// Fails with [`QuotaExceeded`] if the store would hold more live bytes
+    /// than its quota afterwards; nothing is written in that case.
+    pub fn commit(&mut self, ops: Vec<KvOp>) -> Result<()> {
+        if ops.is_empty() {
+            return Ok(());
+        }
+        self.purge_expired();
+        
+        let now_ms = now_ms();
+        let writes: Vec<LogWrite> = ops.into_iter()
+            .map(|op| {
+                let key = match &op {
+                    KvOp::Put { key, .. } | KvOp::Delete { key } => key,
+                };
+                if key.is_empty() || key.len() > MAX_KEY_BYTES {
+                    anyhow::bail!("kv key must be 1 to {} bytes", MAX_KEY_BYTES);
+                }
+                Ok(match op {
+                    KvOp::Put { value, .. } if value.len() > MAX_VALUE_BYTES => {
+                        anyhow::bail!("kv value exceeds {} bytes", MAX_VALUE_BYTES)
+                    }
+                    KvOp::Put { key, value, ttl_ms } => LogWrite::Put {
+                        key,
+                        value,
+                        expires_at_ms: ttl_ms.map(|ttl| now_ms.saturating_add(ttl)),
+                    },
+                    KvOp::Delete { key } => LogWrite::Delete { key },
+                })
+            })
+            .collect::<Result<_>>()?;
+        
+        let needed = self.usage_after(&writes);
+        if needed > self.quota {
+            return Err(QuotaExceeded { container: self.container_id.clone(), needed, quota: self.quota }.into());
+        }
+        
+        let record = LogRecord { seq: self.seq + 1, writes };
+        
+        // Write-ahead: once this is synced the transaction survives a crash
+        let wal_path = self.dir.join(WAL_FILE);
+        let mut wal = OpenOptions::new().create(true).append(true).open(&wal_path)?;
+        wal.write_all(&frame(&encode_record(&record)))?;
+        wal.sync_data()?;
+        
+        self.append_log(&record)?;
+        self.apply(&record);
+        wal.set_len(0)?;
+        
+        if self.log_bytes > COMPACT_MIN_BYTES && self.log_bytes > self.used.saturating_mul(COMPACT_RATIO) {
+            self.compact()?;
+        }
+        Ok(())
+    }
+    
+    /// Rewrite the data log with only live entries
+    pub fn compact(&mut self) -> Result<()> {
+        self.purge_expired();
+        
+        let record = LogRecord {
+            seq: self.seq,
+            writes: self.entries.iter()
+                .map(|(k, e)| LogWrite::Put { key: k.clone(), value: e.value.clone(), expires_at_ms: e.expires_at_ms })
+                .collect(),
+        };
+        let data = frame(&encode_record(&record));
+        
+        let log_path = self.dir.join(LOG_FILE);
+        let compact_path = self.dir.join(format!("{}.{}", LOG_FILE, COMPACT_SUFFIX));
+        let mut file = File::create(&compact_path)?;
+        file.write_all(&data)?;
+        file.sync_all()?;
+        fs::rename(&compact_path, &log_path)?;
+        
+        debug!("Compacted kv log of container {}: {} -> {} bytes", self.container_id, self.log_bytes, data.len());
+        self.log_bytes = data.len() as u64;
+        Ok(())
+    }
+    
+    /// Hash over the live entries, independent of log layout
+    pub fn digest(&mut self) -> String {
+        self.purge_expired();
+        
+        let mut hasher = blake3::Hasher::new();
+        for (key, entry) in &self.entries {
+            hasher.update(&(key.len() as u64).to_le_bytes());
+            hasher.update(key);
+            hasher.update(&(entry.value.len() as u64).to_le_bytes());
+            hasher.update(&entry.value);
+            hasher.update(&entry.expires_at_ms.unwrap_or(0).to_le_bytes());
+        }
+        hasher.finalize().to_hex().to_string()
+    }
+    
+    /// Current usage
+    pub fn usage(&mut self) -> KvUsage {
+        self.purge_expired();
+        KvUsage {
+            keys: self.entries.len(),
+            used_bytes: self.used,
+            quota_bytes: self.quota,
+            log_bytes: self.log_bytes,
+        }
+    }
+    
+    /// Append a record to the data log and sync it
+    fn append_log(&mut self, record: &LogRecord) -> Result<()> {
+        let data = frame(&encode_record(record));
+        let mut file = OpenOptions::new().create(true).append(true).open(self.dir.join(LOG_FILE))?;
+        file.write_all(&data)?;
+        file.sync_data()?;
+        self.log_bytes += data.len() as u64;
+        Ok(())
+    }
+    
+    /// Apply a record to the in-memory index
+    fn apply(&mut self, record: &LogRecord) {
+        for write in &record.writes {
+            match write {
+                LogWrite::Put { key, value, expires_at_ms } => {
+                    self.remove_entry(key);
+                    self.used += (key.len() + value.len()) as u64;
+                    self.entries.insert(key.clone(), Entry { value: value.clone(), expires_at_ms: *expires_at_ms });
+                }
+                LogWrite::Delete { key } => self.remove_entry(key),
+            }
+        }
+        self.seq = self.seq.max(record.seq);
+    }
+    
+    /// Live bytes after applying writes, without applying them
+    fn usage_after(&self, writes: &[LogWrite]) -> u64 {
+        let mut sizes: HashMap<&[u8], Option<u64>> = HashMap::new();
+        for write in writes {
+            match write {
+                LogWrite::Put { key, value, .. } => sizes.insert(key.as_slice(), Some((key.len() + value.len()) as u64)),
+                LogWrite::Delete { key } => sizes.insert(key.as_slice(), None),
+            };
+        }
+        
+        sizes.into_iter().fold(self.used, |used, (key, size)| {
+            let old = self.entries.get(key).map(|e| (key.len() + e.value.len()) as u64).unwrap_or(0);
+            used - old + size.unwrap_or(0)
+        })
+    }
+    
+    /// Drop expired entries from the index; the log forgets them on compaction
+    fn purge_expired(&mut self) {
+        let now_ms = now_ms();
+        let expired: Vec<Vec<u8>> = self.entries.iter()
+            .filter(|(_, e)| e.is_expired(now_ms))
+            .map(|(k, _)| k.clone())
+            .collect();
+        for key in expired {
+            self.remove_entry(&key);
+        }
+    }
+    
+    fn remove_entry(&mut self, key: &[u8]) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.used -= (key.len() + entry.value.len()) as u64;
+        }
+    }
+}
+
+/// Open a container's kv store, or return it if this process has it open
+///
+/// The quota comes from the container's `kv_quota_bytes` permission.
+pub fn open(container_id: &ContainerId) -> Result<Arc<Mutex<KvStore>>> {
+    let mut stores = OPEN_STORES.lock().unwrap();
+    if let Some(store) = stores.get(container_id) {
+        return Ok(store.clone());
+    }
+    
+    let quota = super::registry::get_container(container_id)
+        .map(|c| c.permissions.kv_quota_bytes)
+        .unwrap_or(DEFAULT_QUOTA_BYTES);
+    let store = Arc::new(Mutex::new(KvStore::open(container_id, &kv_dir(container_id)?, quota)?));
+    stores.insert(container_id.clone(), store.clone());
+    Ok(store)
+}
+
+/// Run a function against a container's store, closing it afterwards if
+/// it wasn't open before
+pub fn with_store<T>(container_id: &ContainerId, f: impl FnOnce(&mut KvStore) -> Result<T>) -> Result<T> {
+    let was_open = OPEN_STORES.lock().unwrap().contains_key(container_id);
+    let store = open(container_id)?;
+    let result = f(&mut store.lock().unwrap());
+    drop(store);
+    
+    if !was_open {
+        close(container_id);
+    }
+    result
+}
+
+/// Close a container's store, releasing its lock
+pub fn close(container_id: &ContainerId) {
+    if OPEN_STORES.lock().unwrap().remove(container_id).is_some() {
+        debug!("Closed kv store of container {}", container_id);
+    }
+}
+
+/// Compact every store and return their digests, for checkpoints
+///
+/// Stores opened by another process are skipped with a warning.
+pub fn checkpoint_all() -> Result<BTreeMap<ContainerId, String>> {
+    let data_dir = constants::root_dir().join(DATA_DIR);
+    let mut digests = BTreeMap::new();
+    if !data_dir.exists() {
+        return Ok(digests);
+    }
+    
+    for entry in fs::read_dir(&data_dir)?.filter_map(Result::ok) {
+        if !entry.path().join(KV_DIR).is_dir() {
+            continue;
+        }
+        let id = entry.file_name().to_string_lossy().to_string();
+        match with_store(&id, |store| {
+            store.compact()?;
+            Ok(store.digest())
+        }) {
+            Ok(digest) => {
+                digests.insert(id, digest);
+            }
+            Err(e) => warn!("Skipping kv store of container {} in checkpoint: {}", id, e),
+        }
+    }
+    
+    info!("Checkpointed {} kv store(s)", digests.len());
+    Ok(digests)
+}
+
+/// Directory of a container's persistent data, `.matrixbox/data/<id>`
+pub fn data_dir(container_id: &str) -> Result<PathBuf> {
+    if container_id.is_empty() || !container_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        anyhow::bail!("Invalid container ID: {}", container_id);
+    }
+    Ok(constants::root_dir().join(DATA_DIR).join(container_id))
+}
+
+/// Directory of a container's kv store
+pub fn kv_dir(container_id: &str) -> Result<PathBuf> {
+    Ok(data_dir(container_id)?.join(KV_DIR))
+}
+
+/// Decode a transaction batch passed by a guest
+///
+/// The batch is a sequence of operations, integers little-endian:
+/// `0u8, key_len u32, key, value_len u32, value, ttl_ms u64 (0 = none)` for
+/// a put and `1u8, key_len u32, key` for a delete.
+pub fn decode_batch(bytes: &[u8]) -> Result<Vec<KvOp>> {
+    let mut reader = bytes;
+    let mut ops = Vec::new();
+    
+    while !reader.is_empty() {
+        let op = read_u8(&mut reader)?;
+        let key = read_bytes(&mut reader)?;
+        ops.push(match op {
+            0 => {
+                let value = read_bytes(&mut reader)?;
+                let ttl_ms = read_u64(&mut reader)?;
+                KvOp::Put { key, value, ttl_ms: if ttl_ms == 0 { None } else { Some(ttl_ms) } }
+            }
+            1 => KvOp::Delete { key },
+            _ => anyhow::bail!("Unknown kv batch operation: {}", op),
+        });
+    }
+    Ok(ops)
+}
+
+/// Encode a record's payload
+fn encode_record(record: &LogRecord) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(&record.seq.to_le_bytes());
+    for write in &record.writes {
+        match write {
+            LogWrite::Put { key, value, expires_at_ms } => {
+                out.push(0);
+                write_bytes(&mut out, key);
+                write_bytes(&mut out, value);
+                out.extend_from_slice(&expires_at_ms.unwrap_or(0).to_le_bytes());
+            }
+            LogWrite::Delete { key } => {
+                out.push(1);
+                write_bytes(&mut out, key);
+            }
+        }
+    }
+    out
+}
+
+/// Decode a record's payload
+fn decode_record(mut payload: &[u8]) -> Result<LogRecord> {
+    let seq = read_u64(&mut payload)?;
+    let mut writes = Vec::new();
+    
+    while !payload.is_empty() {
+        let op = read_u8(&mut payload)?;
+        let key = read_bytes(&mut payload)?;
+        writes.push(match op {
+            0 => {
+                let value = read_bytes(&mut payload)?;
+                let expires_at_ms = read_u64(&mut payload)?;
+                LogWrite::Put { key, value, expires_at_ms: if expires_at_ms == 0 { None } else { Some(expires_at_ms) } }
+            }
+            1 => LogWrite::Delete { key },
+            _ => anyhow::bail!("Unknown kv log operation: {}", op),
+        });
+    }
+    Ok(LogRecord { seq, writes })
+}
+
+/// Frame a payload with its length and checksum
+fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(FRAME_HEADER_BYTES + payload.len());
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(blake3::hash(payload).as_bytes());
+    out.extend_from_slice(payload);
+    out
+}
+
+/// Read the valid records of a log and the length they span
+///
+/// Reading stops at the first truncated or corrupt frame.
+fn read_log(path: &Path) -> Result<(Vec<LogRecord>, u64)> {
+    if !path.exists() {
+        return Ok((Vec::new(), 0));
+    }
+    
+    let mut data = Vec::new();
+    File::open(path)?.read_to_end(&mut data)?;
+    
+    let mut records = Vec::new();
+    let mut offset = 0;
+    while data.len() - offset >= FRAME_HEADER_BYTES {
+        let len = u32::from_le_bytes(data[offset..offset + 4].try_into()?) as usize;
+        let start = offset + FRAME_HEADER_BYTES;
+        if data.len() - start < len {
+            break;
+        }
+        
+        let payload = &data[start..start + len];
+        if blake3::hash(payload).as_bytes()[..] != data[offset + 4..start] {
+            break;
+        }
+        match decode_record(payload) {
+            Ok(record) => records.push(record),
+            Err(_) => break,
+        }
+        offset = start + len;
+    }
+    Ok((records, offset as u64))
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn read_u8(reader: &mut &[u8]) -> Result<u8> {
+    let (&byte, rest) = reader.split_first().ok_or_else(|| anyhow::anyhow!("Truncated kv data"))?;
+    *reader = rest;
+    Ok(byte)
+}
+
+fn read_u64(reader: &mut &[u8]) -> Result<u64> {
+    if reader.len() < 8 {
+        anyhow::bail!("Truncated kv data");
+    }
+    let (bytes, rest) = reader.split_at(8);
+    *reader = rest;
+    Ok(u64::from_le_bytes(bytes.try_into()?))
+}
+
+fn read_bytes(reader: &mut &[u8]) -> Result<Vec<u8>> {
+    if reader.len() < 4 {
+        anyhow::bail!("Truncated kv data");
+    }
+    let (len, rest) = reader.split_at(4);
+    let len = u32::from_le_bytes(len.try_into()?) as usize;
+    if rest.len() < len {
+        anyhow::bail!("Truncated kv data");
+    }
+    let (bytes, rest) = rest.split_at(len);
+    *reader = rest;
+    Ok(bytes.to_vec())
+}
+
+/// Current time in milliseconds since the epoch
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Deterministic xorshift generator, so a failing sequence can be replayed
+    struct Rng(u64);
+    
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+        
+        fn below(&mut self, n: u64) -> u64 {
+            self.next() % n
+        }
+        
+        fn bytes(&mut self, max: u64) -> Vec<u8> {
+            (0..self.below(max + 1)).map(|_| self.next() as u8).collect()
+        }
+    }
+    
+    /// Where a simulated crash interrupts a commit
+    #[derive(Debug, Clone, Copy)]
+    enum Crash {
+        /// Halfway through writing the WAL record: the transaction is lost
+        TornWal,
+        
+        /// After the WAL record is synced, before the data log is touched
+        AfterWal,
+        
+        /// After the WAL record, halfway through the data log record
+        TornLog,
+    }
+    
+    fn open_store(id: &str, quota: u64) -> KvStore {
+        KvStore::open(id, &kv_dir(id).unwrap(), quota).unwrap()
+    }
+    
+    fn fresh_store(id: &str, quota: u64) -> KvStore {
+        let dir = kv_dir(id).unwrap();
+        if dir.exists() {
+            fs::remove_dir_all(&dir).unwrap();
+        }
+        open_store(id, quota)
+    }
+    
+    /// Write a transaction the way `commit` does, stopping at `crash`
+    ///
+    /// Returns whether the transaction counts as committed.
+    fn crash_mid_commit(store: KvStore, writes: Vec<LogWrite>, crash: Crash) -> bool {
+        let framed = frame(&encode_record(&LogRecord { seq: store.seq + 1, writes }));
+        let append = |file: &str, data: &[u8]| {
+            let mut file = OpenOptions::new().create(true).append(true).open(store.dir.join(file)).unwrap();
+            file.write_all(data).unwrap();
+        };
+        
+        match crash {
+            Crash::TornWal => append(WAL_FILE, &framed[..framed.len() / 2]),
+            Crash::AfterWal => append(WAL_FILE, &framed),
+            Crash::TornLog => {
+                append(WAL_FILE, &framed);
+                append(LOG_FILE, &framed[..framed.len() / 2]);
+            }
+        }
+        !matches!(crash, Crash::TornWal)
+    }
+    
+    fn model_usage(model: &BTreeMap<Vec<u8>, Vec<u8>>) -> u64 {
+        model.iter().map(|(k, v)| (k.len() + v.len()) as u64).sum()
+    }
+    
+    fn apply_to_model(model: &mut BTreeMap<Vec<u8>, Vec<u8>>, ops: &[KvOp]) {
+        for op in ops {
+            match op {
+                KvOp::Put { key, value, .. } => {
+                    model.insert(key.clone(), value.clone());
+                }
+                KvOp::Delete { key } => {
+                    model.remove(key);
+                }
+            }
+        }
+    }
+    
+    fn random_ops(rng: &mut Rng) -> Vec<KvOp> {
+        (0..1 + rng.below(4))
+            .map(|_| {
+                let key = format!("key-{}", rng.below(8)).into_bytes();
+                if rng.below(4) == 0 {
+                    KvOp::Delete { key }
+                } else {
+                    KvOp::Put { key, value: rng.bytes(64), ttl_ms: None }
+                }
+            })
+            .collect()
+    }
+    
+    fn to_writes(ops: &[KvOp]) -> Vec<LogWrite> {
+        ops.iter()
+            .map(|op| match op {
+                KvOp::Put { key, value, .. } => LogWrite::Put { key: key.clone(), value: value.clone(), expires_at_ms: None },
+                KvOp::Delete { key } => LogWrite::Delete { key: key.clone() },
+            })
+            .collect()
+    }
+    
+    fn assert_matches_model(store: &mut KvStore, model: &BTreeMap<Vec<u8>, Vec<u8>>, context: &str) {
+        let entries: BTreeMap<Vec<u8>, Vec<u8>> = store.list(b"").into_iter().collect();
+        assert_eq!(&entries, model, "{}", context);
+        assert_eq!(store.usage().used_bytes, model_usage(model), "{}", context);
+    }
+    
+    #[test]
+    fn random_operations_match_a_model_across_crashes() {
+        const QUOTA: u64 = 400;
+        
+        for seed in 1..=8u64 {
+            let id = format!("kv-model-{}", seed);
+            let mut rng = Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+            let mut store = fresh_store(&id, QUOTA);
+            let mut model = BTreeMap::new();
+            
+            for step in 0..150 {
+                let context = format!("seed {} step {}", seed, step);
+                let ops = random_ops(&mut rng);
+                
+                match rng.below(10) {
+                    // Crash partway through a commit, then recover
+                    0 => {
+                        let crash = [Crash::TornWal, Crash::AfterWal, Crash::TornLog][rng.below(3) as usize];
+                        let mut next = model.clone();
+                        apply_to_model(&mut next, &ops);
+                        if model_usage(&next) <= QUOTA && crash_mid_commit(store, to_writes(&ops), crash) {
+                            model = next;
+                        }
+                        store = open_store(&id, QUOTA);
+                    }
+                    // Clean reopen
+                    1 => {
+                        drop(store);
+                        store = open_store(&id, QUOTA);
+                    }
+                    // Compaction
+                    2 => store.compact().unwrap(),
+                    _ => {
+                        let mut next = model.clone();
+                        apply_to_model(&mut next, &ops);
+                        match store.commit(ops) {
+                            Ok(()) => {
+                                assert!(model_usage(&next) <= QUOTA, "{}: commit over quota succeeded", context);
+                                model = next;
+                            }
+                            Err(e) => {
+                                let exceeded = e.downcast_ref::<QuotaExceeded>()
+                                    .unwrap_or_else(|| panic!("{}: unexpected error {:#}", context, e));
+                                assert_eq!(exceeded.needed, model_usage(&next), "{}", context);
+                            }
+                        }
+                    }
+                }
+                
+                assert_matches_model(&mut store, &model, &context);
+            }
+            
+            // Everything survives a final reopen
+            let digest = store.digest();
+            drop(store);
+            let mut store = open_store(&id, QUOTA);
+            assert_matches_model(&mut store, &model, &format!("seed {} after reopen", seed));
+            assert_eq!(store.digest(), digest);
+        }
+    }
+    
+    #[test]
+    fn transactions_are_all_or_nothing_under_quota() {
+        let mut store = fresh_store("kv-quota", 32);
+        store.put(b"a", &[0; 10], None).unwrap();
+        
+        let batch = vec![
+            KvOp::Put { key: b"b".to_vec(), value: vec![0; 10], ttl_ms: None },
+            KvOp::Put { key: b"c".to_vec(), value: vec![0; 20], ttl_ms: None },
+        ];
+        let error = store.commit(batch).unwrap_err();
+        let exceeded = error.downcast_ref::<QuotaExceeded>().expect("typed quota error");
+        assert_eq!((exceeded.needed, exceeded.quota), (11 + 11 + 21, 32));
+        assert_eq!(store.get(b"b"), None, "no part of a rejected batch is applied");
+        
+        // Replacing and deleting in the same batch frees room first
+        store.commit(vec![
+            KvOp::Delete { key: b"a".to_vec() },
+            KvOp::Put { key: b"c".to_vec(), value: vec![0; 20], ttl_ms: None },
+        ]).unwrap();
+        assert_eq!(store.usage().used_bytes, 21);
+    }
+    
+    #[test]
+    fn expired_keys_disappear_and_free_quota() {
+        let mut store = fresh_store("kv-ttl", 1024);
+        store.put(b"short", b"value", Some(1)).unwrap();
+        store.put(b"long", b"value", Some(60_000)).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        
+        assert_eq!(store.get(b"short"), None);
+        assert_eq!(store.get(b"long"), Some(b"value".to_vec()));
+        assert_eq!(store.usage().used_bytes, 9);
+        
+        // Expiry is absolute, so it survives a reopen
+        drop(store);
+        let mut store = open_store("kv-ttl", 1024);
+        assert_eq!(store.get(b"long"), Some(b"value".to_vec()));
+        assert_eq!(store.get(b"short"), None);
+    }
+    
+    #[test]
+    fn list_returns_keys_with_a_prefix_in_order() {
+        let mut store = fresh_store("kv-prefix", 1024);
+        for key in ["user/2", "user/1", "session/1", "user", "users"] {
+            store.put(key.as_bytes(), b"x", None).unwrap();
+        }
+        
+        let keys: Vec<Vec<u8>> = store.list(b"user/").into_iter().map(|(k, _)| k).collect();
+        assert_eq!(keys, [b"user/1".to_vec(), b"user/2".to_vec()]);
+        assert_eq!(store.list(b"").len(), 5);
+        assert!(store.list(b"none").is_empty());
+    }
+    
+    #[test]
+    fn store_is_exclusive_to_one_opener() {
+        let store = fresh_store("kv-exclusive", 1024);
+        assert!(KvStore::open("kv-exclusive", &kv_dir("kv-exclusive").unwrap(), 1024).is_err());
+        drop(store);
+        assert!(KvStore::open("kv-exclusive", &kv_dir("kv-exclusive").unwrap(), 1024).is_ok());
+    }
+    
+    #[test]
+    fn guest_batches_decode() {
+        let mut batch = vec![0u8];
+        write_bytes(&mut batch, b"key");
+        write_bytes(&mut batch, b"value");
+        batch.extend_from_slice(&500u64.to_le_bytes());
+        batch.push(1);
+        write_bytes(&mut batch, b"gone");
+        
+        assert_eq!(decode_batch(&batch).unwrap(), [
+            KvOp::Put { key: b"key".to_vec(), value: b"value".to_vec(), ttl_ms: Some(500) },
+            KvOp::Delete { key: b"gone".to_vec() },
+        ]);
+        assert!(decode_batch(&batch[..batch.len() - 1]).is_err());
+        assert!(decode_batch(&[7]).is_err());
+    }
+    
+    #[test]
+    fn invalid_keys_and_values_are_rejected() {
+        let mut store = fresh_store("kv-limits", DEFAULT_QUOTA_BYTES);
+        assert!(store.put(b"", b"value", None).is_err());
+        assert!(store.put(&vec![b'k'; MAX_KEY_BYTES + 1], b"value", None).is_err());
+        assert!(store.put(b"key", &vec![0; MAX_VALUE_BYTES + 1], None).is_err());
+        assert!(data_dir("../escape").is_err());
+    }
+}
@@ -0,0 +1,645 @@
+// SentientOS MatrixBox Resource Limits
+// Hard memory, CPU, process, fuel and time limits of containers
+
+use anyhow::{Result, Context};
+use tracing::{info, debug, warn, error};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::ptr::NonNull;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use thiserror::Error;
+use wasmer::{BaseTunables, CompilerConfig, Cranelift, Engine, Instance, MemoryType, NativeEngineExt, Pages, Store, TableType, Target, Tunables};
+use wasmer::vm::{MemoryError, MemoryStyle, TableStyle, VMMemory, VMMemoryDefinition, VMTable, VMTableDefinition};
+use wasmer::wasmparser::Operator;
+use wasmer_middlewares::Metering;
+use wasmer_middlewares::metering::{get_remaining_points, set_remaining_points, MeteringPoints};
+
+use crate::core::constants;
+
+use super::container::{ContainerId, ContainerStatus, ResourceLimits};
+use super::registry;
+
+// Constants
+const CGROUP_FS: &str = "/sys/fs/cgroup";
+const CGROUP_PARENT: &str = "sentientos";
+const CPU_PERIOD_US: u64 = 100_000;
+const WASM_PAGE_SIZE: u64 = 65_536;
+const OOM_SNAPSHOT_REASON: &str = "oom-kill";
+
+/// A call into a container ran out of fuel or time and the container was killed
+#[derive(Debug, Clone, Error)]
+#[error("Container {container} was killed for exceeding its {limit} limit of {value}")]
+pub struct ResourceExceeded {
+    /// Container that was killed
+    pub container: ContainerId,
+    
+    /// Limit that tripped: `max_fuel` or `max_exec_seconds`
+    pub limit: &'static str,
+    
+    /// Configured value of that limit
+    pub value: u64,
+}
+
+/// How a container's CPU and process limits are enforced
+///
+/// Memory is always bounded by the container's WASM memory limit as well.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Enforcement {
+    /// Through the container's cgroup v2 directory
+    Cgroup(PathBuf),
+    
+    /// Cgroups are unavailable; the runtime's address space is capped instead
+    Rlimit,
+}
+
+/// Check that limits can be enforced
+pub fn validate(limits: &ResourceLimits) -> Result<()> {
+    if limits.max_memory_bytes < WASM_PAGE_SIZE {
+        anyhow::bail!("max_memory_bytes must be at least one WASM page ({} bytes)", WASM_PAGE_SIZE);
+    }
+    if !limits.max_cpu_fraction.is_finite() || limits.max_cpu_fraction <= 0.0 {
+        anyhow::bail!("max_cpu_fraction must be greater than zero");
+    }
+    if limits.max_pids == 0 {
+        anyhow::bail!("max_pids must be greater than zero");
+    }
+    if limits.max_fuel == Some(0) {
+        anyhow::bail!("max_fuel must be greater than zero");
+    }
+    if limits.max_exec_seconds == Some(0) {
+        anyhow::bail!("max_exec_seconds must be greater than zero");
+    }
+    Ok(())
+}
+
+/// Limits of containers that set none, from `subsystems.matrixbox.default_limits` in system.json
+pub fn default_limits() -> Result<Option<ResourceLimits>> {
+    let path = constants::root_dir().join(".config").join("system.json");
+    if !path.exists() {
+        return Ok(None);
+    }
+    
+    let system_config: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    match system_config.pointer("/subsystems/matrixbox/default_limits") {
+        Some(value) if !value.is_null() => Ok(Some(serde_json::from_value(value.clone())
+            .context("Invalid matrixbox default_limits in system.json")?)),
+        _ => Ok(None),
+    }
+}
+
+/// Limits a container runs under
+///
+/// A container without limits gets the defaults from system.json; one with
+/// limits keeps them and only takes the fuel and time limits it leaves
+/// unset from the defaults.
+pub fn effective(limits: Option<ResourceLimits>) -> Result<Option<ResourceLimits>> {
+    Ok(with_defaults(limits, default_limits()?))
+}
+
+/// A container's limits completed from the defaults, as `effective` describes
+fn with_defaults(limits: Option<ResourceLimits>, defaults: Option<ResourceLimits>) -> Option<ResourceLimits> {
+    match (limits, defaults) {
+        (Some(mut limits), Some(defaults)) => {
+            limits.max_fuel = limits.max_fuel.or(defaults.max_fuel);
+            limits.max_exec_seconds = limits.max_exec_seconds.or(defaults.max_exec_seconds);
+            Some(limits)
+        }
+        (limits, defaults) => limits.or(defaults),
+    }
+}
+
+/// Apply a container's limits, through cgroups v2 when available
+///
+/// Containers run on runtime threads rather than in processes of their own,
+/// so their cgroup is threaded: the runtime process joins the parent
+/// `sentientos` cgroup and a thread joins a container's cgroup while it runs
+/// the container (see `attach`). Memory is not a threaded controller, so the
+/// cgroup carries the CPU and pids limits and memory is bounded by the WASM
+/// memory limit of the container's store.
+///
+/// Without cgroups, the runtime's address space is capped with
+/// `setrlimit(RLIMIT_AS)`. That covers the whole process, so it is a coarse
+/// fallback and is not lifted when the container stops.
+pub fn apply(id: &ContainerId, limits: &ResourceLimits) -> Result<Enforcement> {
+    validate(limits)?;
+    
+    if Path::new(CGROUP_FS).join("cgroup.controllers").exists() {
+        match apply_cgroup(id, limits) {
+            Ok(dir) => {
+                info!("Applied resource limits to container {} via {:?}", id, dir);
+                return Ok(Enforcement::Cgroup(dir));
+            }
+            Err(e) => warn!("Failed to apply cgroup limits to container {}: {:#}; falling back to rlimit", id, e),
+        }
+    }
+    
+    apply_rlimit(limits.max_memory_bytes)
+        .with_context(|| format!("Failed to limit memory of container {}", id))?;
+    info!("Applied memory rlimit for container {}", id);
+    Ok(Enforcement::Rlimit)
+}
+
+/// Move the current thread into a container's cgroup until the guard is dropped
+///
+/// Does nothing if the container has no cgroup.
+pub fn attach(id: &ContainerId) -> Result<ThreadAttachment> {
+    let dir = cgroup_dir(id);
+    if !dir.exists() {
+        return Ok(ThreadAttachment { attached: false });
+    }
+    
+    fs::write(dir.join("cgroup.threads"), current_thread_id().to_string())
+        .with_context(|| format!("Failed to move thread into {:?}", dir))?;
+    Ok(ThreadAttachment { attached: true })
+}
+
+/// A thread running inside a container's cgroup
+pub struct ThreadAttachment {
+    /// Whether the thread was moved and must be moved back
+    attached: bool,
+}
+
+impl Drop for ThreadAttachment {
+    fn drop(&mut self) {
+        if !self.attached {
+            return;
+        }
+        let parent = Path::new(CGROUP_FS).join(CGROUP_PARENT);
+        if let Err(e) = fs::write(parent.join("cgroup.threads"), current_thread_id().to_string()) {
+            warn!("Failed to move thread out of container cgroup: {}", e);
+        }
+    }
+}
+
+/// Remove a container's cgroup
+pub fn release(id: &ContainerId) {
+    let dir = cgroup_dir(id);
+    if dir.exists() {
+        // rmdir of a cgroup only succeeds once no thread is left in it
+        match fs::remove_dir(&dir) {
+            Ok(()) => debug!("Removed cgroup of container {}", id),
+            Err(e) => warn!("Failed to remove cgroup {:?}: {}", dir, e),
+        }
+    }
+}
+
+/// Wasmer store whose memories cannot grow past the container's limit
+///
+/// With a fuel or time limit the module is also metered, counting every
+/// instruction as one point; `CallBudget` sets the points of each call.
+pub fn store(limits: Option<&ResourceLimits>) -> Store {
+    let limits = match limits {
+        Some(limits) => limits,
+        None => return Store::default(),
+    };
+    
+    let base = BaseTunables::for_target(&Target::default());
+    let pages = Pages((limits.max_memory_bytes / WASM_PAGE_SIZE).min(u32::MAX as u64) as u32);
+    let mut engine: Engine = if metered(limits) {
+        let mut compiler = Cranelift::default();
+        compiler.push_middleware(Arc::new(Metering::new(u64::MAX, |_: &Operator| -> u64 { 1 })));
+        compiler.into()
+    } else {
+        Engine::default()
+    };
+    engine.set_tunables(LimitingTunables { base, limit: pages });
+    Store::new(engine)
+}
+
+/// Fuel and time limits of one call into a container
+///
+/// Both are enforced through the instance's metering points: the call
+/// starts with `max_fuel` points, and once it has run for
+/// `max_exec_seconds` a watchdog thread zeroes the points, so the instance
+/// traps at its next metering check the way an epoch deadline would. Keep
+/// the budget alive until the call returns.
+pub struct CallBudget {
+    /// Fuel the call started with, if limited
+    fuel: Option<u64>,
+    
+    /// Time limit of the call and the watchdog enforcing it
+    deadline: Option<(u64, Watchdog)>,
+    
+    /// Whether the store is metered at all
+    metered: bool,
+}
+
+impl CallBudget {
+    /// Refuel an instance for a call and start its deadline
+    ///
+    /// The store and instance must stay in place until the budget is dropped.
+    pub fn start(store: &mut Store, instance: &Instance, limits: Option<&ResourceLimits>) -> Result<Self> {
+        let limits = match limits.filter(|limits| metered(limits)) {
+            Some(limits) => limits,
+            None => return Ok(CallBudget { fuel: None, deadline: None, metered: false }),
+        };
+        
+        set_remaining_points(store, instance, limits.max_fuel.unwrap_or(u64::MAX));
+        let deadline = match limits.max_exec_seconds {
+            Some(seconds) => Some((seconds, Watchdog::spawn(store, instance, Duration::from_secs(seconds))?)),
+            None => None,
+        };
+        Ok(CallBudget { fuel: limits.max_fuel, deadline, metered: true })
+    }
+    
+    /// Limit a failed call ran into, if it failed for running out of fuel or time
+    pub fn exceeded(&self, id: &ContainerId, store: &mut Store, instance: &Instance) -> Option<ResourceExceeded> {
+        if !self.metered {
+            return None;
+        }
+        
+        let tripped = match (&self.deadline, self.fuel) {
+            (Some((seconds, watchdog)), _) if watchdog.expired.load(Ordering::SeqCst) => ("max_exec_seconds", *seconds),
+            (_, Some(fuel)) if matches!(get_remaining_points(store, instance), MeteringPoints::Exhausted) => ("max_fuel", fuel),
+            _ => return None,
+        };
+        Some(ResourceExceeded { container: id.clone(), limit: tripped.0, value: tripped.1 })
+    }
+}
+
+/// Thread cutting a call off once its time limit passes
+struct Watchdog {
+    /// Dropped to tell the thread the call returned
+    done: Option<mpsc::Sender<()>>,
+    
+    /// The watchdog thread
+    thread: Option<thread::JoinHandle<()>>,
+    
+    /// Whether the time limit passed
+    expired: Arc<AtomicBool>,
+}
+
+/// Store of a running call, handed to its watchdog
+struct StorePtr(*mut Store);
+
+// The watchdog only touches the store while the calling thread is blocked
+// in the call, and is joined before the call's budget goes away
+unsafe impl Send for StorePtr {}
+
+impl StorePtr {
+    fn get(&self) -> *mut Store {
+        self.0
+    }
+}
+
+impl Watchdog {
+    /// Start the watchdog of a call with the given time limit
+    fn spawn(store: &mut Store, instance: &Instance, limit: Duration) -> Result<Self> {
+        let (done, wait) = mpsc::channel::<()>();
+        let expired = Arc::new(AtomicBool::new(false));
+        let store = StorePtr(store);
+        let instance = instance.clone();
+        let flag = expired.clone();
+        
+        let thread = thread::Builder::new()
+            .name("matrixbox-deadline".to_string())
+            .spawn(move || {
+                if let Err(RecvTimeoutError::Timeout) = wait.recv_timeout(limit) {
+                    flag.store(true, Ordering::SeqCst);
+                    // SAFETY: the budget outlives the call and joins this thread
+                    // when dropped, so the store is still in place; zeroing the
+                    // points only writes the instance's metering globals, which
+                    // live at fixed addresses for as long as the instance does
+                    let store = unsafe { &mut *store.get() };
+                    set_remaining_points(store, &instance, 0);
+                }
+            })
+            .context("Failed to start the container deadline thread")?;
+        
+        Ok(Watchdog { done: Some(done), thread: Some(thread), expired })
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        drop(self.done.take());
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                warn!("Container deadline thread panicked");
+            }
+        }
+    }
+}
+
+/// Whether limits need the module metered
+fn metered(limits: &ResourceLimits) -> bool {
+    limits.max_fuel.is_some() || limits.max_exec_seconds.is_some()
+}
+
+/// Whether a failed call ran out of memory: its memory is within a page of the limit
+pub fn memory_exhausted(memory_bytes: u64, limits: &ResourceLimits) -> bool {
+    memory_bytes.saturating_add(WASM_PAGE_SIZE) > limits.max_memory_bytes
+}
+
+/// Record that a container was killed for exceeding its memory limit
+///
+/// Takes an `oom-kill` snapshot, marks the container `OomKilled` and
+/// removes its cgroup. The caller drops the instance.
+pub fn oom_kill(id: &ContainerId, memory_bytes: u64, limits: &ResourceLimits) {
+    error!("Container {} exceeded its memory limit ({} of {} bytes); killing it",
+           id, memory_bytes, limits.max_memory_bytes);
+    crate::logs::metrics::increment("matrixbox.oom_kills");
+    
+    if let Err(e) = crate::heal::take_snapshot(OOM_SNAPSHOT_REASON) {
+        warn!("Failed to take snapshot after OOM kill of {}: {}", id, e);
+    }
+    if let Err(e) = registry::update_container_status(id, ContainerStatus::OomKilled) {
+        warn!("Failed to mark container {} as OOM-killed: {}", id, e);
+    }
+    release(id);
+    
+    crate::logs::ship::ship_audit("matrixbox.oom_kill", &format!(
+        "Killed container {} at {} bytes (limit {})", id, memory_bytes, limits.max_memory_bytes));
+}
+
+/// Record that a container was killed for exceeding its fuel or time limit
+///
+/// Marks the container `ResourceExceeded` and removes its cgroup. The
+/// caller drops the instance.
+pub fn resource_kill(exceeded: &ResourceExceeded) {
+    let id = &exceeded.container;
+    error!("Container {} exceeded its {} limit of {}; killing it", id, exceeded.limit, exceeded.value);
+    crate::logs::metrics::increment("matrixbox.resource_kills");
+    
+    if let Err(e) = registry::update_container_status(id, ContainerStatus::ResourceExceeded(exceeded.limit.to_string())) {
+        warn!("Failed to mark container {} as over its {} limit: {}", id, exceeded.limit, e);
+    }
+    release(id);
+    
+    crate::logs::ship::ship_audit("matrixbox.resource_kill", &format!(
+        "Killed container {} for exceeding {} ({})", id, exceeded.limit, exceeded.value));
+}
+
+/// Create a container's threaded cgroup and write its limits
+fn apply_cgroup(id: &ContainerId, limits: &ResourceLimits) -> Result<PathBuf> {
+    let root = Path::new(CGROUP_FS);
+    let parent = root.join(CGROUP_PARENT);
+    let dir = cgroup_dir(id);
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create cgroup {:?}", dir))?;
+    
+    // Delegate the controllers down to the container's cgroup; the root may
+    // already have them enabled
+    if let Err(e) = fs::write(root.join("cgroup.subtree_control"), "+cpu +pids") {
+        debug!("Could not enable cpu and pids controllers at the cgroup root: {}", e);
+    }
+    fs::write(dir.join("cgroup.type"), "threaded")
+        .with_context(|| format!("Failed to make {:?} threaded", dir))?;
+    fs::write(parent.join("cgroup.subtree_control"), "+cpu +pids")
+        .with_context(|| format!("Failed to enable controllers in {:?}", parent))?;
+    
+    let quota = ((limits.max_cpu_fraction as f64) * CPU_PERIOD_US as f64).round().max(1_000.0) as u64;
+    fs::write(dir.join("cpu.max"), format!("{} {}", quota, CPU_PERIOD_US))
+        .with_context(|| format!("Failed to write cpu.max of {:?}", dir))?;
+    fs::write(dir.join("pids.max"), limits.max_pids.to_string())
+        .with_context(|| format!("Failed to write pids.max of {:?}", dir))?;
+    
+    // The runtime process has to be in the threaded domain for its threads to move
+    fs::write(parent.join("cgroup.procs"), std::process::id().to_string())
+        .with_context(|| format!("Failed to move the runtime into {:?}", parent))?;
+    Ok(dir)
+}
+
+/// Lower the address space rlimit to the current size plus the container's memory
+///
+/// The limit is only ever lowered, never raised.
+fn apply_rlimit(max_memory_bytes: u64) -> Result<()> {
+    let status = fs::read_to_string("/proc/self/status").context("Failed to read /proc/self/status")?;
+    let current_kb: u64 = status.lines()
+        .find_map(|line| line.strip_prefix("VmSize:"))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("No VmSize in /proc/self/status"))?;
+    let wanted = (current_kb * 1024).saturating_add(max_memory_bytes);
+    
+    let mut limit = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    if unsafe { libc::getrlimit(libc::RLIMIT_AS, &mut limit) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("getrlimit(RLIMIT_AS) failed");
+    }
+    if limit.rlim_cur != libc::RLIM_INFINITY && limit.rlim_cur <= wanted {
+        return Ok(());
+    }
+    
+    limit.rlim_cur = wanted.min(limit.rlim_max);
+    if unsafe { libc::setrlimit(libc::RLIMIT_AS, &limit) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("setrlimit(RLIMIT_AS) failed");
+    }
+    Ok(())
+}
+
+/// cgroup directory of a container
+fn cgroup_dir(id: &ContainerId) -> PathBuf {
+    Path::new(CGROUP_FS).join(CGROUP_PARENT).join(id)
+}
+
+/// Kernel ID of the calling thread
+fn current_thread_id() -> i64 {
+    unsafe { libc::syscall(libc::SYS_gettid) }
+}
+
+/// Tunables bounding every memory of a store to a number of pages
+struct LimitingTunables<T: Tunables> {
+    /// Tunables doing the actual work
+    base: T,
+    
+    /// Largest size a memory may have
+    limit: Pages,
+}
+
+impl<T: Tunables> LimitingTunables<T> {
+    /// Cap the memory's maximum at the limit; unbounded memories get the limit
+    fn adjust_memory(&self, requested: &MemoryType) -> MemoryType {
+        let mut adjusted = requested.clone();
+        adjusted.maximum = Some(requested.maximum.map_or(self.limit, |maximum| maximum.min(self.limit)));
+        adjusted
+    }
+    
+    /// Refuse memories that start out larger than the limit
+    fn validate_memory(&self, ty: &MemoryType) -> Result<(), MemoryError> {
+        if ty.minimum > self.limit {
+            return Err(MemoryError::Generic(format!(
+                "Initial memory of {} pages exceeds the container limit of {} pages", ty.minimum.0, self.limit.0)));
+        }
+        Ok(())
+    }
+}
+
+impl<T: Tunables> Tunables for LimitingTunables<T> {
+    fn memory_style(&self, memory: &MemoryType) -> MemoryStyle {
+        // Reserve only what the memory uses rather than the whole 32-bit range
+        // up front, so an address space rlimit leaves room for it
+        match self.base.memory_style(&self.adjust_memory(memory)) {
+            MemoryStyle::Static { .. } => MemoryStyle::Dynamic { offset_guard_size: WASM_PAGE_SIZE },
+            style => style,
+        }
+    }
+    
+    fn table_style(&self, table: &TableType) -> TableStyle {
+        self.base.table_style(table)
+    }
+    
+    fn create_host_memory(&self, ty: &MemoryType, style: &MemoryStyle) -> Result<VMMemory, MemoryError> {
+        let adjusted = self.adjust_memory(ty);
+        self.validate_memory(&adjusted)?;
+        self.base.create_host_memory(&adjusted, style)
+    }
+    
+    unsafe fn create_vm_memory(
+        &self,
+        ty: &MemoryType,
+        style: &MemoryStyle,
+        vm_definition_location: NonNull<VMMemoryDefinition>,
+    ) -> Result<VMMemory, MemoryError> {
+        let adjusted = self.adjust_memory(ty);
+        self.validate_memory(&adjusted)?;
+        self.base.create_vm_memory(&adjusted, style, vm_definition_location)
+    }
+    
+    fn create_host_table(&self, ty: &TableType, style: &TableStyle) -> Result<VMTable, String> {
+        self.base.create_host_table(ty, style)
+    }
+    
+    unsafe fn create_vm_table(
+        &self,
+        ty: &TableType,
+        style: &TableStyle,
+        vm_definition_location: NonNull<VMTableDefinition>,
+    ) -> Result<VMTable, String> {
+        self.base.create_vm_table(ty, style, vm_definition_location)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use super::super::{container, runtime};
+    
+    /// Module exporting `memory`, `spin`, which loops forever, and `seven`,
+    /// which returns 7
+    const BUSY_WASM: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x02, 0x60, 0x00, 0x00, 0x60, 0x00,
+        0x01, 0x7f, 0x03, 0x03, 0x02, 0x00, 0x01, 0x05, 0x03, 0x01, 0x00, 0x01, 0x07, 0x19, 0x03, 0x06,
+        0x6d, 0x65, 0x6d, 0x6f, 0x72, 0x79, 0x02, 0x00, 0x04, 0x73, 0x70, 0x69, 0x6e, 0x00, 0x00, 0x05,
+        0x73, 0x65, 0x76, 0x65, 0x6e, 0x00, 0x01, 0x0a, 0x0e, 0x02, 0x07, 0x00, 0x03, 0x40, 0x0c, 0x00,
+        0x0b, 0x0b, 0x04, 0x00, 0x41, 0x07, 0x0b,
+    ];
+    
+    /// Limits that only cap fuel and time; the others are far above what
+    /// the tests use, as the rlimit fallback caps the whole test process
+    fn limits(max_fuel: Option<u64>, max_exec_seconds: Option<u64>) -> ResourceLimits {
+        ResourceLimits {
+            max_memory_bytes: 1 << 40,
+            max_cpu_fraction: 1.0,
+            max_pids: 4096,
+            max_fuel,
+            max_exec_seconds,
+        }
+    }
+    
+    /// Create, register and start a container running the busy module
+    fn busy_container(name: &str, limits: ResourceLimits) -> ContainerId {
+        let mut container = container::create_container(name, "spin").unwrap();
+        fs::write(container.path.as_ref().unwrap().join("main.wasm"), BUSY_WASM).unwrap();
+        container.metadata.resource_limits = Some(limits);
+        let id = registry::register_container(&container, &[]).unwrap();
+        runtime::start_container(&id).unwrap();
+        id
+    }
+    
+    /// Call `spin` and return the limit that stopped it
+    fn spin(id: &ContainerId) -> ResourceExceeded {
+        let error = runtime::execute_function(id, "spin", &[]).unwrap_err();
+        error.downcast_ref::<ResourceExceeded>().unwrap_or_else(|| panic!("not a limit kill: {:#}", error)).clone()
+    }
+    
+    fn assert_killed(id: &ContainerId, exceeded: &ResourceExceeded, limit: &str, value: u64) {
+        assert_eq!((&exceeded.container, exceeded.limit, exceeded.value), (id, limit, value));
+        assert!(!runtime::is_container_running(id).unwrap());
+        assert!(!cgroup_dir(id).exists());
+        match registry::get_container_status(id).unwrap() {
+            ContainerStatus::ResourceExceeded(tripped) => assert_eq!(tripped, limit),
+            status => panic!("container left {:?}", status),
+        }
+    }
+    
+    #[test]
+    fn busy_loops_are_killed_at_the_time_limit() {
+        let id = busy_container("limits-time", limits(None, Some(1)));
+        
+        let started = Instant::now();
+        let exceeded = spin(&id);
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_secs(1) && elapsed < Duration::from_secs(10), "{:?}", elapsed);
+        assert_killed(&id, &exceeded, "max_exec_seconds", 1);
+        assert!(runtime::execute_function(&id, "seven", &[]).is_err());
+    }
+    
+    #[test]
+    fn busy_loops_are_killed_when_out_of_fuel() {
+        let id = busy_container("limits-fuel", limits(Some(100_000), Some(60)));
+        
+        let started = Instant::now();
+        let exceeded = spin(&id);
+        assert!(started.elapsed() < Duration::from_secs(30), "{:?}", started.elapsed());
+        assert_killed(&id, &exceeded, "max_fuel", 100_000);
+    }
+    
+    #[test]
+    fn every_call_gets_the_full_budget() {
+        let id = busy_container("limits-refuel", limits(Some(10), Some(1)));
+        
+        // Far more instructions in total than one call may run
+        for _ in 0..100 {
+            assert_eq!(runtime::execute_function(&id, "seven", &[]).unwrap()[0].i32(), Some(7));
+        }
+        assert!(matches!(registry::get_container_status(&id).unwrap(), ContainerStatus::Running));
+        runtime::stop_container(&id).unwrap();
+    }
+    
+    #[test]
+    fn zero_fuel_and_time_limits_are_refused() {
+        assert!(validate(&limits(Some(1), Some(1))).is_ok());
+        let error = validate(&limits(Some(0), None)).unwrap_err();
+        assert!(error.to_string().contains("max_fuel"), "{}", error);
+        let error = validate(&limits(None, Some(0))).unwrap_err();
+        assert!(error.to_string().contains("max_exec_seconds"), "{}", error);
+    }
+    
+    #[test]
+    fn memory_cpu_and_process_limits_are_validated() {
+        assert!(validate(&ResourceLimits { max_memory_bytes: WASM_PAGE_SIZE, ..limits(None, None) }).is_ok());
+        for invalid in [
+            ResourceLimits { max_memory_bytes: WASM_PAGE_SIZE - 1, ..limits(None, None) },
+            ResourceLimits { max_cpu_fraction: 0.0, ..limits(None, None) },
+            ResourceLimits { max_cpu_fraction: f32::NAN, ..limits(None, None) },
+            ResourceLimits { max_pids: 0, ..limits(None, None) },
+        ] {
+            assert!(validate(&invalid).is_err(), "{:?}", invalid);
+        }
+    }
+    
+    #[test]
+    fn memory_is_exhausted_within_a_page_of_the_limit() {
+        let limits = ResourceLimits { max_memory_bytes: 4 * WASM_PAGE_SIZE, ..limits(None, None) };
+        assert!(!memory_exhausted(2 * WASM_PAGE_SIZE, &limits));
+        assert!(memory_exhausted(3 * WASM_PAGE_SIZE + 1, &limits));
+        assert!(memory_exhausted(4 * WASM_PAGE_SIZE, &limits));
+        assert_eq!(cgroup_dir(&"c1".to_string()), Path::new("/sys/fs/cgroup/sentientos/c1"));
+    }
+    
+    #[test]
+    fn defaults_only_fill_unset_fuel_and_time_limits() {
+        let defaults = ResourceLimits { max_memory_bytes: 1 << 20, ..limits(Some(500), Some(5)) };
+        
+        assert_eq!(with_defaults(None, Some(defaults)), Some(defaults));
+        assert_eq!(with_defaults(Some(limits(None, None)), None), Some(limits(None, None)));
+        assert_eq!(with_defaults(None, None), None);
+        
+        // A container's own limits win; only the missing fuel and time come from the defaults
+        assert_eq!(with_defaults(Some(limits(None, None)), Some(defaults)), Some(limits(Some(500), Some(5))));
+        assert_eq!(with_defaults(Some(limits(Some(7), None)), Some(defaults)), Some(limits(Some(7), Some(5))));
+    }
+}
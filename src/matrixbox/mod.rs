@@ -6,25 +6,47 @@ pub mod runtime;
 pub mod registry;
 pub mod wasm;
 pub mod tso;
+pub mod host;
+pub mod app;
+pub mod policy;
+pub mod kv;
+pub mod sync;
+pub mod limits;
+pub mod output;
+pub mod network;
+pub mod compose;
 
-use anyhow::Result;
+pub use container::{ContainerConfig, NetworkConfig, ResourceLimits, Transport};
+pub use output::{get_logs, LogLine};
+pub use network::PortMapping;
+pub use compose::{launch_composition, CompositionSpec};
+
+use anyhow::{Result, Context};
 use tracing::{info, warn};
 use std::path::PathBuf;
+use std::sync::Once;
 
 use crate::core::constants;
+use crate::core::plan::{Plan, PlannedAction};
+
+// Guards registering the panic hook more than once
+static PANIC_HOOK: Once = Once::new();
 
 /// Initialize the MatrixBox container runtime
 pub fn init() -> Result<()> {
     info!("Initializing MatrixBox container runtime");
     
     // Create container directory if it doesn't exist
-    let container_dir = PathBuf::from(constants::ROOT_DIR).join(constants::CONTAINER_DIR);
+    let container_dir = constants::root_dir().join(constants::CONTAINER_DIR);
     std::fs::create_dir_all(&container_dir)?;
     
     // Create TSO archive directory
-    let tso_dir = PathBuf::from(constants::ROOT_DIR).join(".matrixbox").join("tso");
+    let tso_dir = constants::root_dir().join(".matrixbox").join("tso");
     std::fs::create_dir_all(&tso_dir)?;
     
+    // Trusted image keys must be known before the registry loads any image
+    registry::load_image_trust()?;
+    
     // Initialize container registry
     registry::init()?;
     
@@ -34,6 +56,14 @@ pub fn init() -> Result<()> {
     // Initialize container runtime
     runtime::init()?;
     
+    // Containers running through a panic can't be trusted until checked
+    PANIC_HOOK.call_once(|| {
+        crate::panic::hooks::register(Box::new(|_| match registry::mark_running_suspect() {
+            Ok(marked) => warn!("Marked {} running container(s) as suspect", marked),
+            Err(e) => warn!("Failed to mark running containers as suspect: {}", e),
+        }));
+    });
+    
     info!("MatrixBox container runtime initialized successfully");
     Ok(())
 }
@@ -52,8 +82,8 @@ pub fn shutdown() -> Result<()> {
 }
 
 /// Run a MatrixBox container
-pub fn run_container(container_path: &str) -> Result<container::ContainerId> {
-    info!("Running MatrixBox container: {}", container_path);
+pub fn run_container(container_path: &str, args: &[&str]) -> Result<container::ContainerId> {
+    info!("Running MatrixBox container: {} {:?}", container_path, args);
     
     // Check if this is a TSO archive
     let path = PathBuf::from(container_path);
@@ -61,31 +91,50 @@ pub fn run_container(container_path: &str) -> Result<container::ContainerId> {
         .map(|ext| ext == "tso")
         .unwrap_or(false);
     
-    let container = if is_tso {
+    let mut container = load_image(container_path)?;
+    
+    // Register the container
+    let id = registry::register_container(&container, args)?;
+    crate::intent::trace::record_outcome(crate::intent::trace::TraceOutcome::ContainerCreated {
+        container_id: id.clone(),
+    });
+    
+    // Writes go to the container's own layer, never to the shared image
+    let image_path = container.path.clone()
+        .ok_or_else(|| anyhow::anyhow!("Container has no path"))?;
+    container::create_container_filesystem(&image_path, &id)?;
+    
+    // Start the container with WASM runtime, under its registered ID
+    container.id = Some(id.clone());
+    wasm::run_container(&container, args)?;
+    
+    info!("MatrixBox container started: {}", id);
+    Ok(id)
+}
+
+/// Load a container image from a directory or a TSO archive
+pub fn load_image(container_path: &str) -> Result<container::Container> {
+    // Check if this is a TSO archive
+    let path = PathBuf::from(container_path);
+    let is_tso = path.extension()
+        .map(|ext| ext == "tso")
+        .unwrap_or(false);
+    
+    if is_tso {
         info!("Loading TSO container archive: {}", container_path);
         
         // Extract TSO to temporary directory
-        let temp_dir = PathBuf::from(constants::ROOT_DIR)
+        let temp_dir = constants::root_dir()
             .join(".matrixbox")
             .join("extracted")
             .join(format!("{}", chrono::Utc::now().timestamp()));
         
         std::fs::create_dir_all(&temp_dir)?;
-        tso::extract_tso_archive(&path, &temp_dir)?
+        tso::extract_tso_archive(&path, &temp_dir)
     } else {
         // Load the container normally
-        container::load_container(container_path)?
-    };
-    
-    // Register the container
-    let id = registry::register_container(&container)?;
-    
-    // Start the container with WASM runtime
-    let args = Vec::new();
-    wasm::run_container(&container, &args.iter().map(|s| s.as_str()).collect::<Vec<_>>())?;
-    
-    info!("MatrixBox container started: {}", id);
-    Ok(id)
+        container::load_container(container_path)
+    }
 }
 
 /// Stop a running MatrixBox container
@@ -98,6 +147,14 @@ pub fn stop_container(id: &container::ContainerId) -> Result<()> {
     // Stop the container runtime
     runtime::stop_container(id)?;
     
+    // Release the kv store for other processes
+    kv::close(id);
+    
+    // The writable layer stays for `diff` and a later removal
+    if let Some(filesystem) = container::container_filesystem(id)? {
+        filesystem.unmount()?;
+    }
+    
     info!("MatrixBox container stopped: {}", id);
     Ok(())
 }
@@ -114,17 +171,137 @@ pub fn list_containers() -> Result<Vec<container::ContainerInfo>> {
 }
 
 /// Remove a MatrixBox container
-pub fn remove_container(id: &container::ContainerId) -> Result<()> {
+///
+/// Unless `purge` is set, the container is recorded in the trash and its data
+/// is moved there if it lives in a SentientOS-managed directory. For
+/// containers with a filesystem layer the data is that layer, which is only
+/// deleted when purging; their image is shared and stays in place.
+pub fn remove_container(id: &container::ContainerId, purge: bool) -> Result<()> {
     info!("Removing MatrixBox container: {}", id);
     
-    // Ensure container is stopped
+    let plan = plan_remove_container(id, purge)?;
+    let container = registry::get_container(id)?;
+    let layer_dir = container::filesystem_dir(id);
+    
+    for action in &plan.actions {
+        match action {
+            PlannedAction::StopContainer { .. } => {
+                runtime::stop_container(id)?;
+            }
+            PlannedAction::RemoveRegistryEntry { .. } => {
+                registry::unregister_container(id)?;
+            }
+            PlannedAction::DeleteFiles { .. } => {
+                container::remove_container_filesystem(id)?;
+            }
+            PlannedAction::MoveToTrash { path, .. } => {
+                // A layer is restored to where it was, as it belongs to the container ID
+                let original_path = if path.as_deref().map(std::path::Path::new) == Some(layer_dir.as_path()) {
+                    container::unmount_container_filesystem(id)?;
+                    layer_dir.clone()
+                } else {
+                    container.path.clone().unwrap_or_default()
+                };
+                crate::trash::move_to_trash(
+                    crate::trash::TrashKind::Container,
+                    &container.name,
+                    path.as_ref().map(std::path::Path::new),
+                    &original_path.to_string_lossy(),
+                    serde_json::to_value(&container)?,
+                )?;
+            }
+            other => anyhow::bail!("Unexpected action in container removal plan: {}", other),
+        }
+    }
+    
+    info!("MatrixBox container removed: {}", id);
+    Ok(())
+}
+
+/// Plan the removal of a container without changing anything
+///
+/// Container data is only moved when it lives in a SentientOS-managed
+/// directory or is a writable layer. Purging deletes a writable layer and
+/// otherwise leaves the data in place and only unregisters. The
+/// registry entry is removed last, so a failed step leaves the container
+/// registered.
+pub fn plan_remove_container(id: &container::ContainerId, purge: bool) -> Result<Plan> {
+    let container = registry::get_container(id)?;
+    let mut plan = Plan::new(&format!("matrixbox rm {}", id));
+    
     if runtime::is_container_running(id)? {
-        runtime::stop_container(id)?;
+        plan.push(PlannedAction::StopContainer { id: id.clone(), name: container.name.clone() });
+    }
+    
+    let layer_dir = container::filesystem_dir(id);
+    let layered = layer_dir.exists();
+    if purge {
+        if layered {
+            plan.push(PlannedAction::DeleteFiles {
+                path: layer_dir.to_string_lossy().to_string(),
+                bytes: crate::core::plan::path_size(&layer_dir),
+            });
+        }
+    } else {
+        let original_path = container.path.clone().unwrap_or_default();
+        let data = if layered {
+            Some(layer_dir)
+        } else if is_managed_path(&original_path) {
+            Some(original_path)
+        } else {
+            None
+        };
+        let bytes = data.as_deref().map_or(0, crate::core::plan::path_size);
+        let path = data.map(|data| data.to_string_lossy().to_string());
+        
+        plan.push(PlannedAction::MoveToTrash { item: container.name.clone(), path, bytes });
     }
     
-    // Unregister the container
-    registry::unregister_container(id)?;
+    plan.push(PlannedAction::RemoveRegistryEntry { registry: "matrixbox".to_string(), entry: id.clone() });
     
-    info!("MatrixBox container removed: {}", id);
+    Ok(plan)
+}
+
+/// Restore a container from a trash entry under `target_name`
+pub fn restore_from_trash(
+    tombstone: &crate::trash::Tombstone,
+    data_path: Option<&std::path::Path>,
+    target_name: &str,
+) -> Result<()> {
+    if list_containers()?.iter().any(|c| c.name == target_name) {
+        anyhow::bail!("Container {} is already registered; restore it under another name with --rename", target_name);
+    }
+    
+    let original_path = PathBuf::from(&tombstone.original_path);
+    if let Some(data_path) = data_path {
+        if original_path.exists() {
+            anyhow::bail!("Container path {:?} is occupied; cannot restore data", original_path);
+        }
+        std::fs::rename(data_path, &original_path)
+            .with_context(|| format!("Failed to restore container data to {:?}", original_path))?;
+    }
+    
+    // A container whose writable layer was trashed gets its ID back, as the layer is stored under it
+    let entry: container::Container = serde_json::from_value(tombstone.registry_entry.clone())
+        .context("Invalid container registry entry in trash")?;
+    if let Some(id) = entry.id.clone().filter(|id| container::filesystem_dir(id) == original_path) {
+        let mut container = entry;
+        container.name = target_name.to_string();
+        registry::reinstate_container(&id, &container, &[])?;
+        info!("Container {} restored from trash as {}", target_name, id);
+        return Ok(());
+    }
+    
+    let mut container = container::load_container(&tombstone.original_path)?;
+    container.name = target_name.to_string();
+    
+    let id = registry::register_container(&container, &[])?;
+    info!("Container {} restored from trash as {}", target_name, id);
     Ok(())
 }
+
+/// Whether a container path lives in a SentientOS-managed directory
+fn is_managed_path(path: &std::path::Path) -> bool {
+    let root = constants::root_dir();
+    path.starts_with(root.join(constants::CONTAINER_DIR)) || path.starts_with(root.join(".matrixbox"))
+}
@@ -0,0 +1,853 @@
+// SentientOS MatrixBox Container Networking
+// Private container addresses, host port forwarding and the WASI socket calls behind them
+
+use anyhow::{Result, Context};
+use tracing::{info, debug, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Mutex;
+use serde::{Serialize, Deserialize};
+use wasmer::{Function, FunctionEnv, FunctionEnvMut, Imports, Instance, Memory, RuntimeError, Store, TypedFunction};
+
+use super::container::{ContainerId, NetworkConfig, Transport};
+use crate::core::constants;
+use crate::core::lock;
+
+// Constants
+const LEASES_FILE: &str = ".matrixbox/network/leases.json";
+const LEASE_LOCK: &str = "matrixbox-network";
+const POOL_PREFIX: [u8; 2] = [10, 88];
+const DEVICE_PREFIX: &str = "sbx";
+const WASI_MODULE: &str = "wasi_snapshot_preview1";
+const LISTEN_FDS_ENV: &str = "SENTIENT_LISTEN_FDS";
+const FIRST_SOCKET_FD: u32 = 1024;
+const MAX_IOVECS: u32 = 1024;
+const UDP_DATAGRAM_BYTES: usize = 65_536;
+
+// WASI errno values
+const ERRNO_SUCCESS: i32 = 0;
+const ERRNO_AGAIN: i32 = 6;
+const ERRNO_BADF: i32 = 8;
+const ERRNO_INVAL: i32 = 28;
+const ERRNO_IO: i32 = 29;
+const ERRNO_NOTCONN: i32 = 53;
+const ERRNO_NOTSOCK: i32 = 57;
+
+// WASI flags
+const FDFLAGS_NONBLOCK: i32 = 0x4;
+const SDFLAGS_RD: i32 = 0x1;
+const SDFLAGS_WR: i32 = 0x2;
+
+// Linux TUN ioctls and flags
+#[cfg(target_os = "linux")]
+const TUNSETIFF: libc::c_ulong = 0x4004_54ca;
+#[cfg(target_os = "linux")]
+const TUNSETPERSIST: libc::c_ulong = 0x4004_54cb;
+#[cfg(target_os = "linux")]
+const IFF_TUN: libc::c_short = 0x0001;
+#[cfg(target_os = "linux")]
+const IFF_NO_PI: libc::c_short = 0x1000;
+
+// Sockets of attached containers, by container
+lazy_static::lazy_static! {
+    static ref SOCKETS: Mutex<HashMap<ContainerId, ContainerSockets>> = Mutex::new(HashMap::new());
+}
+
+/// How a container's ports reach it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NetworkMode {
+    /// The container's address is on its own TUN device and the host port
+    /// is forwarded with a DNAT rule
+    Tun,
+    
+    /// The runtime listens on the host port and relays each connection
+    /// through a socketpair
+    Socketpair,
+}
+
+/// A container's network attachment, in the leases file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkLease {
+    /// Private address from 10.88.0.0/16
+    pub ip: Ipv4Addr,
+    
+    /// TUN device holding the address, in `Tun` mode
+    pub device: Option<String>,
+    
+    /// How the ports reach the container
+    pub mode: NetworkMode,
+    
+    /// Forwarded ports
+    pub ports: Vec<NetworkConfig>,
+}
+
+/// A forwarded port as listed for a container
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortMapping {
+    /// Port on the host
+    pub host_port: u16,
+    
+    /// Port inside the container
+    pub container_port: u16,
+    
+    /// Transport protocol
+    pub protocol: Transport,
+    
+    /// Container's private address
+    pub container_ip: Ipv4Addr,
+}
+
+/// Open sockets of a container, by guest file descriptor
+struct ContainerSockets {
+    /// Whether accepted connections are relayed through socketpairs
+    mode: NetworkMode,
+    
+    /// Listening sockets, one per forwarded port
+    listeners: BTreeMap<u32, Listener>,
+    
+    /// Accepted connections
+    streams: HashMap<u32, GuestStream>,
+    
+    /// Next descriptor to hand out
+    next_fd: u32,
+}
+
+/// A socket a forwarded port is received on
+enum Listener {
+    /// TCP listener, accepted from with `sock_accept`
+    Tcp(TcpListener),
+    
+    /// UDP socket, read and written directly; replies go to the last sender
+    Udp(UdpSocket, Option<SocketAddr>),
+}
+
+/// A connection handed to a container
+enum GuestStream {
+    /// The accepted connection itself
+    Tcp(TcpStream),
+    
+    /// The container's end of a socketpair relayed to the connection
+    Pair(UnixStream),
+}
+
+impl GuestStream {
+    fn shutdown(&self, how: std::net::Shutdown) -> io::Result<()> {
+        match self {
+            GuestStream::Tcp(s) => s.shutdown(how),
+            GuestStream::Pair(s) => s.shutdown(how),
+        }
+    }
+    
+    fn try_clone(&self) -> io::Result<GuestStream> {
+        Ok(match self {
+            GuestStream::Tcp(s) => GuestStream::Tcp(s.try_clone()?),
+            GuestStream::Pair(s) => GuestStream::Pair(s.try_clone()?),
+        })
+    }
+}
+
+impl Read for GuestStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            GuestStream::Tcp(s) => s.read(buf),
+            GuestStream::Pair(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for GuestStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            GuestStream::Tcp(s) => s.write(buf),
+            GuestStream::Pair(s) => s.write(buf),
+        }
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Give a container its address and forward its ports
+///
+/// With privileges to create TUN devices and iptables available, the
+/// address is put on a TUN device of its own and DNAT rules send the host
+/// ports to it. Otherwise the runtime binds the host ports itself. Either
+/// way the container receives the sockets through its WASI socket calls;
+/// the descriptors are listed in `SENTIENT_LISTEN_FDS` (see `listen_fds_env`).
+pub fn attach(id: &ContainerId, ports: &[NetworkConfig]) -> Result<NetworkLease> {
+    let _lock = lock::lock(LEASE_LOCK, &format!("attach {}", id), lock::DEFAULT_TIMEOUT)?;
+    let mut leases = load_leases()?;
+    if let Some(lease) = leases.get(id) {
+        anyhow::bail!("Container {} is already attached at {}", id, lease.ip);
+    }
+    
+    let ip = allocate(&leases)?;
+    let device = format!("{}{}", DEVICE_PREFIX, &id[..id.len().min(12)]);
+    
+    let lease = match setup_tun(&device, ip, ports) {
+        Ok(()) => NetworkLease { ip, device: Some(device), mode: NetworkMode::Tun, ports: ports.to_vec() },
+        Err(e) => {
+            debug!("TUN networking unavailable for container {} ({:#}); relaying through socketpairs", id, e);
+            NetworkLease { ip, device: None, mode: NetworkMode::Socketpair, ports: ports.to_vec() }
+        }
+    };
+    
+    if let Err(e) = bind_listeners(id, &lease) {
+        teardown(&lease);
+        return Err(e);
+    }
+    
+    leases.insert(id.clone(), lease.clone());
+    save_leases(&leases)?;
+    info!("Attached container {} at {} ({:?}, {} port(s))", id, ip, lease.mode, ports.len());
+    Ok(lease)
+}
+
+/// Close a container's sockets, remove its forwarding and release its address
+pub fn detach(id: &ContainerId) -> Result<()> {
+    SOCKETS.lock().unwrap().remove(id);
+    
+    let _lock = lock::lock(LEASE_LOCK, &format!("detach {}", id), lock::DEFAULT_TIMEOUT)?;
+    let mut leases = load_leases()?;
+    if let Some(lease) = leases.remove(id) {
+        teardown(&lease);
+        save_leases(&leases)?;
+        info!("Detached container {} from {}", id, lease.ip);
+    }
+    Ok(())
+}
+
+/// Ports forwarded to a container, empty if it is not attached
+pub fn forwarded_ports(id: &ContainerId) -> Result<Vec<PortMapping>> {
+    let leases = load_leases()?;
+    Ok(leases.get(id).map(|lease| {
+        lease.ports.iter().map(|port| PortMapping {
+            host_port: port.host_port,
+            container_port: port.container_port,
+            protocol: port.protocol,
+            container_ip: lease.ip,
+        }).collect()
+    }).unwrap_or_default())
+}
+
+/// `SENTIENT_LISTEN_FDS` value announcing a container's listening descriptors
+///
+/// Formatted as `fd=protocol:container_port` entries separated by commas,
+/// e.g. `1024=tcp:8080,1025=udp:5353`.
+pub fn listen_fds_env(ports: &[NetworkConfig]) -> (&'static str, String) {
+    let value = ports.iter()
+        .enumerate()
+        .map(|(i, port)| format!("{}={}:{}", FIRST_SOCKET_FD + i as u32, port.protocol, port.container_port))
+        .collect::<Vec<_>>()
+        .join(",");
+    (LISTEN_FDS_ENV, value)
+}
+
+/// Per-instance state of the intercepted WASI calls
+pub struct NetEnv {
+    /// Container the instance belongs to
+    container_id: ContainerId,
+    
+    /// Exported linear memory, set once the instance exists
+    memory: Option<Memory>,
+    
+    /// WASI `fd_read` for descriptors that are not sockets
+    fd_read: Option<Function>,
+    
+    /// WASI `fd_write` for descriptors that are not sockets
+    fd_write: Option<Function>,
+    
+    /// WASI `fd_close` for descriptors that are not sockets
+    fd_close: Option<Function>,
+}
+
+/// Replace the WASI socket calls of an import object with the runtime's
+///
+/// `sock_accept`, `sock_recv`, `sock_send` and `sock_shutdown` serve the
+/// container's forwarded ports, and `fd_read`, `fd_write` and `fd_close`
+/// handle socket descriptors before passing any other to WASI.
+pub fn register(store: &mut Store, imports: &mut Imports, container_id: &ContainerId) -> FunctionEnv<NetEnv> {
+    let original = |name: &str| match imports.get_export(WASI_MODULE, name) {
+        Some(wasmer::Extern::Function(function)) => Some(function),
+        _ => None,
+    };
+    let env = FunctionEnv::new(store, NetEnv {
+        container_id: container_id.clone(),
+        memory: None,
+        fd_read: original("fd_read"),
+        fd_write: original("fd_write"),
+        fd_close: original("fd_close"),
+    });
+    
+    imports.define(WASI_MODULE, "sock_accept", Function::new_typed_with_env(store, &env, sock_accept));
+    imports.define(WASI_MODULE, "sock_recv", Function::new_typed_with_env(store, &env, sock_recv));
+    imports.define(WASI_MODULE, "sock_send", Function::new_typed_with_env(store, &env, sock_send));
+    imports.define(WASI_MODULE, "sock_shutdown", Function::new_typed_with_env(store, &env, sock_shutdown));
+    imports.define(WASI_MODULE, "fd_read", Function::new_typed_with_env(store, &env, fd_read));
+    imports.define(WASI_MODULE, "fd_write", Function::new_typed_with_env(store, &env, fd_write));
+    imports.define(WASI_MODULE, "fd_close", Function::new_typed_with_env(store, &env, fd_close));
+    
+    env
+}
+
+/// Give the intercepted calls access to the instance memory
+pub fn attach_memory(store: &mut Store, env: &FunctionEnv<NetEnv>, instance: &Instance) -> Result<()> {
+    let memory = instance.exports.get_memory("memory")?.clone();
+    env.as_mut(store).memory = Some(memory);
+    Ok(())
+}
+
+/// sock_accept(fd, flags, ro_fd) -> errno
+fn sock_accept(env: FunctionEnvMut<NetEnv>, fd: i32, flags: i32, ro_fd: i32) -> Result<i32, RuntimeError> {
+    let id = env.data().container_id.clone();
+    
+    // Accept on a clone so a blocking accept does not hold the socket table
+    let (listener, mode) = {
+        let sockets = SOCKETS.lock().unwrap();
+        let container = match sockets.get(&id) {
+            Some(container) => container,
+            None => return Ok(ERRNO_BADF),
+        };
+        match container.listeners.get(&(fd as u32)) {
+            Some(Listener::Tcp(listener)) => (listener.try_clone().map_err(runtime_error)?, container.mode),
+            Some(Listener::Udp(..)) => return Ok(ERRNO_NOTSOCK),
+            None => return Ok(ERRNO_BADF),
+        }
+    };
+    
+    listener.set_nonblocking(flags & FDFLAGS_NONBLOCK != 0).map_err(runtime_error)?;
+    let (stream, peer) = match listener.accept() {
+        Ok(accepted) => accepted,
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(ERRNO_AGAIN),
+        Err(e) => {
+            warn!("[{}] accept failed: {}", id, e);
+            return Ok(ERRNO_IO);
+        }
+    };
+    stream.set_nonblocking(false).map_err(runtime_error)?;
+    debug!("[{}] accepted connection from {}", id, peer);
+    
+    let guest = match mode {
+        NetworkMode::Tun => GuestStream::Tcp(stream),
+        NetworkMode::Socketpair => GuestStream::Pair(relay(stream).map_err(runtime_error)?),
+    };
+    
+    let new_fd = {
+        let mut sockets = SOCKETS.lock().unwrap();
+        let container = match sockets.get_mut(&id) {
+            Some(container) => container,
+            None => return Ok(ERRNO_BADF),
+        };
+        let new_fd = container.next_fd;
+        container.next_fd += 1;
+        container.streams.insert(new_fd, guest);
+        new_fd
+    };
+    
+    write_guest(&env, ro_fd, &new_fd.to_le_bytes())?;
+    Ok(ERRNO_SUCCESS)
+}
+
+/// sock_recv(fd, ri_data, ri_data_len, ri_flags, ro_datalen, ro_flags) -> errno
+fn sock_recv(
+    env: FunctionEnvMut<NetEnv>,
+    fd: i32,
+    ri_data: i32,
+    ri_data_len: i32,
+    _ri_flags: i32,
+    ro_datalen: i32,
+    ro_flags: i32,
+) -> Result<i32, RuntimeError> {
+    let errno = socket_read(&env, fd, ri_data, ri_data_len, ro_datalen)?;
+    if errno == ERRNO_SUCCESS {
+        write_guest(&env, ro_flags, &0u16.to_le_bytes())?;
+    }
+    Ok(errno)
+}
+
+/// sock_send(fd, si_data, si_data_len, si_flags, so_datalen) -> errno
+fn sock_send(
+    env: FunctionEnvMut<NetEnv>,
+    fd: i32,
+    si_data: i32,
+    si_data_len: i32,
+    _si_flags: i32,
+    so_datalen: i32,
+) -> Result<i32, RuntimeError> {
+    socket_write(&env, fd, si_data, si_data_len, so_datalen)
+}
+
+/// sock_shutdown(fd, how) -> errno
+fn sock_shutdown(env: FunctionEnvMut<NetEnv>, fd: i32, how: i32) -> Result<i32, RuntimeError> {
+    let how = match (how & SDFLAGS_RD != 0, how & SDFLAGS_WR != 0) {
+        (true, true) => std::net::Shutdown::Both,
+        (true, false) => std::net::Shutdown::Read,
+        (false, true) => std::net::Shutdown::Write,
+        (false, false) => return Ok(ERRNO_INVAL),
+    };
+    
+    let sockets = SOCKETS.lock().unwrap();
+    match sockets.get(&env.data().container_id).and_then(|c| c.streams.get(&(fd as u32))) {
+        Some(stream) => Ok(match stream.shutdown(how) {
+            Ok(()) => ERRNO_SUCCESS,
+            Err(_) => ERRNO_NOTCONN,
+        }),
+        None => Ok(ERRNO_BADF),
+    }
+}
+
+/// fd_read(fd, iovs, iovs_len, nread) -> errno, for sockets or passed to WASI
+fn fd_read(mut env: FunctionEnvMut<NetEnv>, fd: i32, iovs: i32, iovs_len: i32, nread: i32) -> Result<i32, RuntimeError> {
+    if is_socket(&env, fd) {
+        return socket_read(&env, fd, iovs, iovs_len, nread);
+    }
+    let function = env.data().fd_read.clone()
+        .ok_or_else(|| RuntimeError::new("WASI fd_read is not available"))?;
+    let typed: TypedFunction<(i32, i32, i32, i32), i32> = function.typed(&env).map_err(runtime_error)?;
+    typed.call(&mut env, fd, iovs, iovs_len, nread)
+}
+
+/// fd_write(fd, iovs, iovs_len, nwritten) -> errno, for sockets or passed to WASI
+fn fd_write(mut env: FunctionEnvMut<NetEnv>, fd: i32, iovs: i32, iovs_len: i32, nwritten: i32) -> Result<i32, RuntimeError> {
+    if is_socket(&env, fd) {
+        return socket_write(&env, fd, iovs, iovs_len, nwritten);
+    }
+    let function = env.data().fd_write.clone()
+        .ok_or_else(|| RuntimeError::new("WASI fd_write is not available"))?;
+    let typed: TypedFunction<(i32, i32, i32, i32), i32> = function.typed(&env).map_err(runtime_error)?;
+    typed.call(&mut env, fd, iovs, iovs_len, nwritten)
+}
+
+/// fd_close(fd) -> errno, for sockets or passed to WASI
+fn fd_close(mut env: FunctionEnvMut<NetEnv>, fd: i32) -> Result<i32, RuntimeError> {
+    if is_socket(&env, fd) {
+        let mut sockets = SOCKETS.lock().unwrap();
+        if let Some(container) = sockets.get_mut(&env.data().container_id) {
+            // Listeners stay open for the container's lifetime
+            container.streams.remove(&(fd as u32));
+        }
+        return Ok(ERRNO_SUCCESS);
+    }
+    let function = env.data().fd_close.clone()
+        .ok_or_else(|| RuntimeError::new("WASI fd_close is not available"))?;
+    let typed: TypedFunction<i32, i32> = function.typed(&env).map_err(runtime_error)?;
+    typed.call(&mut env, fd)
+}
+
+/// Read from a socket into guest iovecs, storing the byte count at `out`
+fn socket_read(env: &FunctionEnvMut<NetEnv>, fd: i32, iovs: i32, iovs_len: i32, out: i32) -> Result<i32, RuntimeError> {
+    let buffers = read_iovecs(env, iovs, iovs_len)?;
+    let capacity: usize = buffers.iter().map(|(_, len)| *len as usize).sum();
+    let mut data = vec![0u8; capacity];
+    
+    // Read from a clone so a blocking read does not hold the socket table
+    let socket = {
+        let sockets = SOCKETS.lock().unwrap();
+        let container = match sockets.get(&env.data().container_id) {
+            Some(container) => container,
+            None => return Ok(ERRNO_BADF),
+        };
+        match (container.listeners.get(&(fd as u32)), container.streams.get(&(fd as u32))) {
+            (Some(Listener::Udp(socket, _)), _) => socket.try_clone().map(Ok),
+            (_, Some(stream)) => stream.try_clone().map(Err),
+            _ => return Ok(ERRNO_BADF),
+        }
+    };
+    
+    let read = match socket {
+        Ok(Ok(socket)) => {
+            let mut datagram = vec![0u8; UDP_DATAGRAM_BYTES];
+            socket.recv_from(&mut datagram).map(|(len, peer)| {
+                // Replies go to whoever sent last
+                let mut sockets = SOCKETS.lock().unwrap();
+                if let Some(Listener::Udp(_, last_peer)) = sockets.get_mut(&env.data().container_id)
+                    .and_then(|c| c.listeners.get_mut(&(fd as u32))) {
+                    *last_peer = Some(peer);
+                }
+                let len = len.min(capacity);
+                data[..len].copy_from_slice(&datagram[..len]);
+                len
+            })
+        }
+        Ok(Err(mut stream)) => stream.read(&mut data),
+        Err(e) => Err(e),
+    };
+    
+    let read = match read {
+        Ok(read) => read,
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(ERRNO_AGAIN),
+        Err(_) => return Ok(ERRNO_IO),
+    };
+    
+    // Scatter into the guest's buffers
+    let mut offset = 0;
+    for (ptr, len) in buffers {
+        if offset >= read {
+            break;
+        }
+        let end = (offset + len as usize).min(read);
+        write_guest(env, ptr as i32, &data[offset..end])?;
+        offset = end;
+    }
+    write_guest(env, out, &(read as u32).to_le_bytes())?;
+    Ok(ERRNO_SUCCESS)
+}
+
+/// Write guest iovecs to a socket, storing the byte count at `out`
+fn socket_write(env: &FunctionEnvMut<NetEnv>, fd: i32, iovs: i32, iovs_len: i32, out: i32) -> Result<i32, RuntimeError> {
+    let mut data = Vec::new();
+    for (ptr, len) in read_iovecs(env, iovs, iovs_len)? {
+        data.extend(read_guest(env, ptr, len)?);
+    }
+    
+    let written = {
+        let sockets = SOCKETS.lock().unwrap();
+        let container = match sockets.get(&env.data().container_id) {
+            Some(container) => container,
+            None => return Ok(ERRNO_BADF),
+        };
+        if let Some(Listener::Udp(socket, last_peer)) = container.listeners.get(&(fd as u32)) {
+            match last_peer {
+                Some(peer) => socket.send_to(&data, *peer),
+                None => return Ok(ERRNO_NOTCONN),
+            }
+        } else {
+            let stream = match container.streams.get(&(fd as u32)) {
+                Some(stream) => stream.try_clone(),
+                None => return Ok(ERRNO_BADF),
+            };
+            drop(sockets);
+            stream.and_then(|mut stream| stream.write(&data))
+        }
+    };
+    
+    let written = match written {
+        Ok(written) => written,
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(ERRNO_AGAIN),
+        Err(_) => return Ok(ERRNO_IO),
+    };
+    write_guest(env, out, &(written as u32).to_le_bytes())?;
+    Ok(ERRNO_SUCCESS)
+}
+
+/// Whether a descriptor is one of the container's sockets
+fn is_socket(env: &FunctionEnvMut<NetEnv>, fd: i32) -> bool {
+    let fd = fd as u32;
+    if fd < FIRST_SOCKET_FD {
+        return false;
+    }
+    let sockets = SOCKETS.lock().unwrap();
+    sockets.get(&env.data().container_id)
+        .map_or(false, |c| c.listeners.contains_key(&fd) || c.streams.contains_key(&fd))
+}
+
+/// Read a guest iovec array as (pointer, length) pairs
+fn read_iovecs(env: &FunctionEnvMut<NetEnv>, iovs: i32, iovs_len: i32) -> Result<Vec<(u32, u32)>, RuntimeError> {
+    if iovs_len < 0 || iovs_len as u32 > MAX_IOVECS {
+        return Err(RuntimeError::new(format!("Invalid iovec count: {}", iovs_len)));
+    }
+    let raw = read_guest(env, iovs as u32, iovs_len as u32 * 8)?;
+    Ok(raw.chunks_exact(8)
+        .map(|iovec| (
+            u32::from_le_bytes([iovec[0], iovec[1], iovec[2], iovec[3]]),
+            u32::from_le_bytes([iovec[4], iovec[5], iovec[6], iovec[7]]),
+        ))
+        .collect())
+}
+
+/// Copy bytes out of guest memory
+fn read_guest(env: &FunctionEnvMut<NetEnv>, ptr: u32, len: u32) -> Result<Vec<u8>, RuntimeError> {
+    let memory = env.data().memory.as_ref()
+        .ok_or_else(|| RuntimeError::new("Instance memory not attached"))?;
+    let mut buf = vec![0u8; len as usize];
+    memory.view(env).read(ptr as u64, &mut buf)
+        .map_err(|e| RuntimeError::new(e.to_string()))?;
+    Ok(buf)
+}
+
+/// Copy bytes into guest memory
+fn write_guest(env: &FunctionEnvMut<NetEnv>, ptr: i32, data: &[u8]) -> Result<(), RuntimeError> {
+    let memory = env.data().memory.as_ref()
+        .ok_or_else(|| RuntimeError::new("Instance memory not attached"))?;
+    memory.view(env).write(ptr as u32 as u64, data)
+        .map_err(|e| RuntimeError::new(e.to_string()))
+}
+
+/// Runtime error from an I/O or type error
+fn runtime_error(error: impl std::fmt::Display) -> RuntimeError {
+    RuntimeError::new(error.to_string())
+}
+
+/// Relay a connection through a socketpair, returning the container's end
+fn relay(stream: TcpStream) -> io::Result<UnixStream> {
+    let (container_end, relay_end) = UnixStream::pair()?;
+    
+    let mut inbound = (stream.try_clone()?, relay_end.try_clone()?);
+    let mut outbound = (relay_end, stream);
+    std::thread::spawn(move || {
+        let _ = io::copy(&mut inbound.0, &mut inbound.1);
+        let _ = inbound.1.shutdown(std::net::Shutdown::Write);
+    });
+    std::thread::spawn(move || {
+        let _ = io::copy(&mut outbound.0, &mut outbound.1);
+        let _ = outbound.1.shutdown(std::net::Shutdown::Write);
+    });
+    Ok(container_end)
+}
+
+/// Bind the sockets a container's ports arrive on
+fn bind_listeners(id: &ContainerId, lease: &NetworkLease) -> Result<()> {
+    let mut listeners = BTreeMap::new();
+    
+    for (i, port) in lease.ports.iter().enumerate() {
+        // Behind DNAT the container's own address receives the traffic;
+        // otherwise the host port is bound directly
+        let address = match lease.mode {
+            NetworkMode::Tun => SocketAddr::from((lease.ip, port.container_port)),
+            NetworkMode::Socketpair => SocketAddr::from(([0, 0, 0, 0], port.host_port)),
+        };
+        let listener = match port.protocol {
+            Transport::Tcp => Listener::Tcp(TcpListener::bind(address)
+                .with_context(|| format!("Failed to listen on {}", address))?),
+            Transport::Udp => Listener::Udp(UdpSocket::bind(address)
+                .with_context(|| format!("Failed to bind {}", address))?, None),
+        };
+        listeners.insert(FIRST_SOCKET_FD + i as u32, listener);
+    }
+    
+    let next_fd = FIRST_SOCKET_FD + lease.ports.len() as u32;
+    SOCKETS.lock().unwrap().insert(id.clone(), ContainerSockets {
+        mode: lease.mode,
+        listeners,
+        streams: HashMap::new(),
+        next_fd,
+    });
+    Ok(())
+}
+
+/// Lowest free address of the pool; .0.1 is left for a gateway
+fn allocate(leases: &BTreeMap<ContainerId, NetworkLease>) -> Result<Ipv4Addr> {
+    for host in 2..=0xfffe_u32 {
+        let ip = Ipv4Addr::new(POOL_PREFIX[0], POOL_PREFIX[1], (host >> 8) as u8, host as u8);
+        if host & 0xff != 0 && host & 0xff != 0xff && !leases.values().any(|lease| lease.ip == ip) {
+            return Ok(ip);
+        }
+    }
+    anyhow::bail!("No free container addresses left in {}.{}.0.0/16", POOL_PREFIX[0], POOL_PREFIX[1])
+}
+
+/// Create a container's TUN device with its address and forward its ports to it
+fn setup_tun(device: &str, ip: Ipv4Addr, ports: &[NetworkConfig]) -> Result<()> {
+    create_tun(device)?;
+    
+    let result = run("ip", &["addr", "add", &format!("{}/32", ip), "dev", device])
+        .and_then(|_| run("ip", &["link", "set", device, "up"]))
+        .and_then(|_| {
+            for port in ports {
+                for args in dnat_rules("-A", ip, port) {
+                    run("iptables", &args.iter().map(String::as_str).collect::<Vec<_>>())?;
+                }
+            }
+            Ok(())
+        });
+    
+    if result.is_err() {
+        teardown(&NetworkLease {
+            ip,
+            device: Some(device.to_string()),
+            mode: NetworkMode::Tun,
+            ports: ports.to_vec(),
+        });
+    }
+    result
+}
+
+/// Remove a lease's forwarding rules and TUN device; failures are logged
+fn teardown(lease: &NetworkLease) {
+    let device = match (&lease.device, lease.mode) {
+        (Some(device), NetworkMode::Tun) => device,
+        _ => return,
+    };
+    
+    for port in &lease.ports {
+        for args in dnat_rules("-D", lease.ip, port) {
+            if let Err(e) = run("iptables", &args.iter().map(String::as_str).collect::<Vec<_>>()) {
+                debug!("Failed to remove forwarding of port {}: {:#}", port.host_port, e);
+            }
+        }
+    }
+    if let Err(e) = run("ip", &["link", "delete", device]) {
+        warn!("Failed to delete network device {}: {:#}", device, e);
+    }
+}
+
+/// iptables arguments forwarding a host port to a container, for external
+/// and for local clients
+fn dnat_rules(action: &str, ip: Ipv4Addr, port: &NetworkConfig) -> Vec<Vec<String>> {
+    let destination = format!("{}:{}", ip, port.container_port);
+    ["PREROUTING", "OUTPUT"].iter().map(|chain| {
+        let mut args = vec!["-t", "nat", action, chain, "-p"].into_iter().map(String::from).collect::<Vec<_>>();
+        args.push(port.protocol.to_string());
+        args.extend(["--dport".to_string(), port.host_port.to_string()]);
+        if *chain == "OUTPUT" {
+            args.extend(["-m", "addrtype", "--dst-type", "LOCAL"].iter().map(|s| s.to_string()));
+        }
+        args.extend(["-j".to_string(), "DNAT".to_string(), "--to-destination".to_string(), destination.clone()]);
+        args
+    }).collect()
+}
+
+/// Create a persistent TUN device
+#[cfg(target_os = "linux")]
+fn create_tun(device: &str) -> Result<()> {
+    #[repr(C)]
+    struct IfReq {
+        name: [u8; libc::IFNAMSIZ],
+        flags: libc::c_short,
+        _pad: [u8; 22],
+    }
+    
+    if device.len() >= libc::IFNAMSIZ {
+        anyhow::bail!("Device name too long: {}", device);
+    }
+    let mut request = IfReq { name: [0; libc::IFNAMSIZ], flags: IFF_TUN | IFF_NO_PI, _pad: [0; 22] };
+    request.name[..device.len()].copy_from_slice(device.as_bytes());
+    
+    let file = fs::OpenOptions::new().read(true).write(true).open("/dev/net/tun")
+        .context("Failed to open /dev/net/tun")?;
+    let fd = std::os::unix::io::AsRawFd::as_raw_fd(&file);
+    unsafe {
+        if libc::ioctl(fd, TUNSETIFF, &mut request) < 0 {
+            return Err(io::Error::last_os_error()).context("TUNSETIFF failed");
+        }
+        if libc::ioctl(fd, TUNSETPERSIST, 1) < 0 {
+            return Err(io::Error::last_os_error()).context("TUNSETPERSIST failed");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn create_tun(_device: &str) -> Result<()> {
+    anyhow::bail!("TUN devices require Linux")
+}
+
+/// Run a network tool, failing with its stderr
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    let output = Command::new(program).args(args).output()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !output.status.success() {
+        anyhow::bail!("{} {} failed: {}", program, args.join(" "), String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(())
+}
+
+/// Load the network leases
+fn load_leases() -> Result<BTreeMap<ContainerId, NetworkLease>> {
+    let path = leases_path();
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    serde_json::from_str(&fs::read_to_string(&path)?)
+        .with_context(|| format!("Invalid network leases in {:?}", path))
+}
+
+/// Save the network leases
+fn save_leases(leases: &BTreeMap<ContainerId, NetworkLease>) -> Result<()> {
+    let path = leases_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(leases)?)?;
+    Ok(())
+}
+
+/// Path of the network leases
+fn leases_path() -> PathBuf {
+    constants::root_dir().join(LEASES_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn port(host_port: u16, container_port: u16, protocol: Transport) -> NetworkConfig {
+        NetworkConfig { host_port, container_port, protocol }
+    }
+    
+    fn lease(ip: Ipv4Addr, ports: Vec<NetworkConfig>) -> NetworkLease {
+        NetworkLease { ip, device: None, mode: NetworkMode::Socketpair, ports }
+    }
+    
+    #[test]
+    fn addresses_are_the_lowest_free_host_of_the_pool() {
+        let mut leases = BTreeMap::new();
+        assert_eq!(allocate(&leases).unwrap(), Ipv4Addr::new(10, 88, 0, 2));
+        
+        leases.insert("a".to_string(), lease(Ipv4Addr::new(10, 88, 0, 2), Vec::new()));
+        leases.insert("b".to_string(), lease(Ipv4Addr::new(10, 88, 0, 4), Vec::new()));
+        assert_eq!(allocate(&leases).unwrap(), Ipv4Addr::new(10, 88, 0, 3));
+        
+        // Network and broadcast addresses of each /24 are never handed out
+        for host in 3..=254 {
+            leases.insert(format!("c{}", host), lease(Ipv4Addr::new(10, 88, 0, host), Vec::new()));
+        }
+        assert_eq!(allocate(&leases).unwrap(), Ipv4Addr::new(10, 88, 1, 1));
+    }
+    
+    #[test]
+    fn forwarding_rules_cover_external_and_local_clients() {
+        let rules = dnat_rules("-A", Ipv4Addr::new(10, 88, 0, 7), &port(8080, 80, Transport::Udp));
+        let rules: Vec<String> = rules.iter().map(|args| args.join(" ")).collect();
+        assert_eq!(rules, [
+            "-t nat -A PREROUTING -p udp --dport 8080 -j DNAT --to-destination 10.88.0.7:80",
+            "-t nat -A OUTPUT -p udp --dport 8080 -m addrtype --dst-type LOCAL -j DNAT --to-destination 10.88.0.7:80",
+        ]);
+    }
+    
+    #[test]
+    fn listening_descriptors_are_announced_in_port_order() {
+        let ports = [port(8080, 80, Transport::Tcp), port(5353, 53, Transport::Udp)];
+        assert_eq!(listen_fds_env(&ports), ("SENTIENT_LISTEN_FDS", "1024=tcp:80,1025=udp:53".to_string()));
+        assert_eq!(listen_fds_env(&[]).1, "");
+    }
+    
+    #[test]
+    fn host_ports_are_bound_for_relayed_containers() {
+        let id = format!("network-test-{}", std::process::id());
+        let ports = vec![port(0, 80, Transport::Tcp), port(0, 53, Transport::Udp)];
+        bind_listeners(&id, &lease(Ipv4Addr::new(10, 88, 0, 2), ports)).unwrap();
+        
+        let sockets = SOCKETS.lock().unwrap().remove(&id).unwrap();
+        assert_eq!(sockets.mode, NetworkMode::Socketpair);
+        assert!(matches!(sockets.listeners.get(&1024), Some(Listener::Tcp(_))));
+        assert!(matches!(sockets.listeners.get(&1025), Some(Listener::Udp(_, None))));
+        assert_eq!(sockets.next_fd, 1026);
+    }
+    
+    #[test]
+    fn relayed_connections_carry_both_directions() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut container = relay(listener.accept().unwrap().0).unwrap();
+        
+        client.write_all(b"ping").unwrap();
+        let mut request = [0; 4];
+        container.read_exact(&mut request).unwrap();
+        assert_eq!(&request, b"ping");
+        
+        // Closing the container's end closes the client's connection
+        container.write_all(b"pong").unwrap();
+        container.shutdown(std::net::Shutdown::Both).unwrap();
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).unwrap();
+        assert_eq!(reply, "pong");
+    }
+}
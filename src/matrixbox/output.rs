@@ -0,0 +1,441 @@
+// SentientOS MatrixBox Container Output
+// Captures container stdout/stderr into rotated, timestamped log files
+
+use anyhow::{Result, Context};
+use tracing::warn;
+use std::fs::{self, File, OpenOptions};
+use std::future::Future;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{Context as TaskContext, Poll};
+use serde::{Serialize, Deserialize};
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+use wasmer_wasix::virtual_fs::{FsError, VirtualFile};
+
+use super::container::ContainerId;
+use crate::core::constants;
+
+// Constants
+const LOGS_DIR: &str = ".matrixbox/logs";
+const LOG_FILE: &str = "output.log";
+const MAX_LINE_BYTES: usize = 64 * 1024;
+
+/// Container log configuration, under `container_logs` in system.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// Size a log file may reach before it is rotated, in bytes
+    pub max_file_bytes: u64,
+    
+    /// Rotated files kept per container, besides the current one
+    pub max_rotated_files: usize,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            max_file_bytes: 10 * 1024 * 1024,
+            max_rotated_files: 3,
+        }
+    }
+}
+
+/// Output stream a line was written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogStream {
+    /// Standard output
+    Stdout,
+    
+    /// Standard error
+    Stderr,
+}
+
+/// A line of container output
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogLine {
+    /// When the line was written (RFC 3339)
+    pub timestamp: String,
+    
+    /// Stream it was written to
+    pub stream: LogStream,
+    
+    /// Line text, without the newline
+    pub line: String,
+}
+
+/// Writer appending a container's output to its log, one line at a time
+///
+/// Used as the WASI stdout or stderr of a container. Lines are timestamped
+/// when they are completed, and a partial line is written out when the
+/// writer is dropped.
+#[derive(Debug)]
+pub struct ContainerOutput {
+    /// Container writing
+    container_id: ContainerId,
+    
+    /// Stream this writer captures
+    stream: LogStream,
+    
+    /// Bytes of the current, unfinished line
+    partial: Vec<u8>,
+    
+    /// Rotation settings
+    config: LogConfig,
+}
+
+impl ContainerOutput {
+    /// Writer for one of a container's streams
+    pub fn new(container_id: &ContainerId, stream: LogStream) -> Result<Self> {
+        fs::create_dir_all(log_dir(container_id))?;
+        Ok(Self {
+            container_id: container_id.clone(),
+            stream,
+            partial: Vec::new(),
+            config: load_config()?,
+        })
+    }
+    
+    /// Append the buffered partial line to the log
+    fn flush_line(&mut self) -> io::Result<()> {
+        let line = LogLine {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            stream: self.stream,
+            line: String::from_utf8_lossy(&self.partial).trim_end_matches('\r').to_string(),
+        };
+        self.partial.clear();
+        append(&self.container_id, &line, &self.config).map_err(io::Error::other)
+    }
+}
+
+impl Write for ContainerOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for &byte in buf {
+            if byte == b'\n' {
+                self.flush_line()?;
+            } else {
+                self.partial.push(byte);
+                // Unterminated output is split rather than buffered without bound
+                if self.partial.len() >= MAX_LINE_BYTES {
+                    self.flush_line()?;
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// WASI writes go straight to the log file, which never blocks for long
+impl AsyncWrite for ContainerOutput {
+    fn poll_write(self: Pin<&mut Self>, _cx: &mut TaskContext<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Poll::Ready(self.get_mut().write(buf))
+    }
+    
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+    
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut TaskContext<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncRead for ContainerOutput {
+    fn poll_read(self: Pin<&mut Self>, _cx: &mut TaskContext<'_>, _buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Err(io::Error::new(io::ErrorKind::PermissionDenied, "container output is write-only")))
+    }
+}
+
+impl AsyncSeek for ContainerOutput {
+    fn start_seek(self: Pin<&mut Self>, _position: SeekFrom) -> io::Result<()> {
+        Err(io::Error::new(io::ErrorKind::Unsupported, "container output cannot seek"))
+    }
+    
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut TaskContext<'_>) -> Poll<io::Result<u64>> {
+        Poll::Ready(Ok(0))
+    }
+}
+
+impl VirtualFile for ContainerOutput {
+    fn last_accessed(&self) -> u64 {
+        0
+    }
+    
+    fn last_modified(&self) -> u64 {
+        0
+    }
+    
+    fn created_time(&self) -> u64 {
+        0
+    }
+    
+    fn size(&self) -> u64 {
+        0
+    }
+    
+    fn set_len(&mut self, _new_size: u64) -> Result<(), FsError> {
+        Err(FsError::PermissionDenied)
+    }
+    
+    fn unlink(&mut self) -> Pin<Box<dyn Future<Output = Result<(), FsError>> + Send + 'static>> {
+        Box::pin(async { Ok(()) })
+    }
+    
+    fn poll_read_ready(self: Pin<&mut Self>, _cx: &mut TaskContext<'_>) -> Poll<io::Result<usize>> {
+        Poll::Ready(Ok(0))
+    }
+    
+    fn poll_write_ready(self: Pin<&mut Self>, _cx: &mut TaskContext<'_>) -> Poll<io::Result<usize>> {
+        Poll::Ready(Ok(MAX_LINE_BYTES))
+    }
+}
+
+impl Drop for ContainerOutput {
+    fn drop(&mut self) {
+        if !self.partial.is_empty() {
+            if let Err(e) = self.flush_line() {
+                warn!("Failed to write output of container {}: {}", self.container_id, e);
+            }
+        }
+    }
+}
+
+/// A container's logged output, oldest first
+///
+/// With `tail`, only that many of the most recent lines are returned.
+pub fn get_logs(id: &ContainerId, tail: Option<usize>) -> Result<Vec<LogLine>> {
+    let config = load_config()?;
+    let mut lines = Vec::new();
+    
+    // Rotated files hold older output, highest number oldest
+    for index in (0..=config.max_rotated_files).rev() {
+        let path = log_path(id, index);
+        if path.exists() {
+            lines.extend(read_lines(&path, 0)?.0);
+        }
+    }
+    
+    if let Some(tail) = tail {
+        let skip = lines.len().saturating_sub(tail);
+        lines.drain(..skip);
+    }
+    Ok(lines)
+}
+
+/// Reads lines appended to a container's log after it was created
+pub struct LogFollower {
+    /// Container followed
+    container_id: ContainerId,
+    
+    /// Bytes of the current log file already read
+    offset: u64,
+}
+
+impl LogFollower {
+    /// Follow a container's log from its current end
+    pub fn new(id: &ContainerId) -> Self {
+        let offset = fs::metadata(log_path(id, 0)).map(|m| m.len()).unwrap_or(0);
+        Self { container_id: id.clone(), offset }
+    }
+    
+    /// Lines appended since the last poll
+    ///
+    /// If the log was rotated in between, the rest of the rotated file is
+    /// read before the new one.
+    pub fn poll(&mut self) -> Result<Vec<LogLine>> {
+        let current = log_path(&self.container_id, 0);
+        let length = fs::metadata(&current).map(|m| m.len()).unwrap_or(0);
+        let mut lines = Vec::new();
+        
+        if length < self.offset {
+            let rotated = log_path(&self.container_id, 1);
+            if rotated.exists() {
+                lines.extend(read_lines(&rotated, self.offset)?.0);
+            }
+            self.offset = 0;
+        }
+        
+        if current.exists() {
+            let (new_lines, offset) = read_lines(&current, self.offset)?;
+            lines.extend(new_lines);
+            self.offset = offset;
+        }
+        Ok(lines)
+    }
+}
+
+/// Load the container log configuration
+pub fn load_config() -> Result<LogConfig> {
+    let path = constants::root_dir().join(".config").join("system.json");
+    if !path.exists() {
+        return Ok(LogConfig::default());
+    }
+    
+    let system_config: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    match system_config.get("container_logs") {
+        Some(value) => Ok(serde_json::from_value(value.clone())
+            .context("Invalid container_logs configuration in system.json")?),
+        None => Ok(LogConfig::default()),
+    }
+}
+
+/// Append a line to a container's log, rotating the log first if it is full
+fn append(id: &ContainerId, line: &LogLine, config: &LogConfig) -> Result<()> {
+    let path = log_path(id, 0);
+    if fs::metadata(&path).map_or(false, |m| m.len() >= config.max_file_bytes) {
+        rotate(id, config)?;
+    }
+    
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)
+        .with_context(|| format!("Failed to open container log {:?}", path))?;
+    writeln!(file, "{}", serde_json::to_string(line)?)?;
+    Ok(())
+}
+
+/// Shift a container's log files up by one, dropping the oldest
+fn rotate(id: &ContainerId, config: &LogConfig) -> Result<()> {
+    if config.max_rotated_files == 0 {
+        fs::remove_file(log_path(id, 0))?;
+        return Ok(());
+    }
+    
+    let oldest = log_path(id, config.max_rotated_files);
+    if oldest.exists() {
+        fs::remove_file(&oldest)?;
+    }
+    for index in (0..config.max_rotated_files).rev() {
+        let from = log_path(id, index);
+        if from.exists() {
+            fs::rename(&from, log_path(id, index + 1))?;
+        }
+    }
+    Ok(())
+}
+
+/// Complete lines of a log file from a byte offset, and the offset after them
+fn read_lines(path: &PathBuf, offset: u64) -> Result<(Vec<LogLine>, u64)> {
+    let mut file = File::open(path).with_context(|| format!("Failed to open container log {:?}", path))?;
+    file.seek(SeekFrom::Start(offset))?;
+    
+    let mut reader = BufReader::new(file);
+    let mut lines = Vec::new();
+    let mut offset = offset;
+    let mut buffer = String::new();
+    
+    loop {
+        buffer.clear();
+        let read = reader.read_line(&mut buffer)?;
+        // A line still being written is picked up by the next read
+        if read == 0 || !buffer.ends_with('\n') {
+            break;
+        }
+        offset += read as u64;
+        match serde_json::from_str(buffer.trim_end()) {
+            Ok(line) => lines.push(line),
+            Err(e) => warn!("Skipping malformed line in {:?}: {}", path, e),
+        }
+    }
+    Ok((lines, offset))
+}
+
+/// Directory holding a container's logs
+fn log_dir(id: &ContainerId) -> PathBuf {
+    constants::root_dir().join(LOGS_DIR).join(id)
+}
+
+/// Log file of a container: the current one at 0, rotated ones numbered up
+fn log_path(id: &ContainerId, index: usize) -> PathBuf {
+    match index {
+        0 => log_dir(id).join(LOG_FILE),
+        index => log_dir(id).join(format!("{}.{}", LOG_FILE, index)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn container(name: &str) -> ContainerId {
+        let id = format!("output-test-{}-{}", name, std::process::id());
+        let _ = fs::remove_dir_all(log_dir(&id));
+        fs::create_dir_all(log_dir(&id)).unwrap();
+        id
+    }
+    
+    fn writer(id: &ContainerId, stream: LogStream, config: LogConfig) -> ContainerOutput {
+        ContainerOutput { container_id: id.clone(), stream, partial: Vec::new(), config }
+    }
+    
+    fn texts(lines: &[LogLine]) -> Vec<&str> {
+        lines.iter().map(|l| l.line.as_str()).collect()
+    }
+    
+    #[test]
+    fn output_is_logged_line_by_line() {
+        let id = container("lines");
+        let mut stdout = writer(&id, LogStream::Stdout, LogConfig::default());
+        let mut stderr = writer(&id, LogStream::Stderr, LogConfig::default());
+        
+        stdout.write_all(b"hello\r\nwor").unwrap();
+        stderr.write_all(b"failed\n").unwrap();
+        stdout.write_all(b"ld\n\npartial").unwrap();
+        assert_eq!(texts(&get_logs(&id, None).unwrap()), ["hello", "failed", "world", ""]);
+        
+        // The unfinished line is written when the writer goes away
+        drop(stdout);
+        let lines = get_logs(&id, None).unwrap();
+        assert_eq!(texts(&lines), ["hello", "failed", "world", "", "partial"]);
+        assert_eq!(lines[1].stream, LogStream::Stderr);
+        assert!(chrono::DateTime::parse_from_rfc3339(&lines[0].timestamp).is_ok());
+        assert_eq!(texts(&get_logs(&id, Some(2)).unwrap()), ["", "partial"]);
+        assert_eq!(get_logs(&id, Some(10)).unwrap().len(), 5);
+        
+        // Runaway lines are split instead of buffered
+        stderr.write_all(&vec![b'x'; MAX_LINE_BYTES + 10]).unwrap();
+        drop(stderr);
+        let lengths: Vec<usize> = get_logs(&id, Some(2)).unwrap().iter().map(|l| l.line.len()).collect();
+        assert_eq!(lengths, [MAX_LINE_BYTES, 10]);
+    }
+    
+    #[test]
+    fn full_logs_are_rotated_and_read_back_in_order() {
+        let id = container("rotate");
+        let config = LogConfig { max_file_bytes: 1, max_rotated_files: 2 };
+        let mut output = writer(&id, LogStream::Stdout, config.clone());
+        output.write_all(b"one\ntwo\nthree\nfour\n").unwrap();
+        
+        // Every line fills a file, so only the newest three survive
+        assert!(!log_path(&id, 3).exists());
+        assert_eq!(texts(&read_lines(&log_path(&id, 2), 0).unwrap().0), ["two"]);
+        assert_eq!(texts(&get_logs(&id, None).unwrap()), ["two", "three", "four"]);
+        
+        let id = container("no-rotated");
+        let mut output = writer(&id, LogStream::Stdout, LogConfig { max_file_bytes: 1, max_rotated_files: 0 });
+        output.write_all(b"old\nnew\n").unwrap();
+        assert_eq!(texts(&get_logs(&id, None).unwrap()), ["new"]);
+    }
+    
+    #[test]
+    fn followers_see_new_lines_across_rotations() {
+        let id = container("follow");
+        let mut output = writer(&id, LogStream::Stdout, LogConfig::default());
+        output.write_all(b"before\n").unwrap();
+        
+        let mut follower = LogFollower::new(&id);
+        assert!(follower.poll().unwrap().is_empty());
+        output.write_all(b"first\nhalf").unwrap();
+        assert_eq!(texts(&follower.poll().unwrap()), ["first"]);
+        
+        // Lines left in a file that was rotated away are still delivered
+        output.write_all(b" done\n").unwrap();
+        output.config.max_file_bytes = 1;
+        output.write_all(b"rotated\n").unwrap();
+        assert_eq!(texts(&follower.poll().unwrap()), ["half done", "rotated"]);
+        assert!(follower.poll().unwrap().is_empty());
+    }
+}
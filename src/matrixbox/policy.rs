@@ -0,0 +1,438 @@
+// SentientOS MatrixBox Execution Policy
+// Hash-pinned allow-list of WASM modules that may be executed
+
+use anyhow::{Result, Context};
+use tracing::{info, debug, warn};
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use crate::core::{constants, identity};
+use crate::gossip::protocol::MessageType;
+
+// Constants
+const POLICIES_DIR: &str = "policies";
+const ALLOWLIST_FILE: &str = "exec-allowlist.json";
+
+/// Execution policy, stored under `exec_policy` in security.json
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExecPolicy {
+    /// Refuse modules that are not on the allow-list
+    #[serde(default)]
+    pub enforce: bool,
+    
+    /// Peers whose signed allow-lists are accepted, besides this node
+    #[serde(default)]
+    pub signers: Vec<String>,
+}
+
+/// A module allowed to execute
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AllowEntry {
+    /// blake3 hash of the module bytes
+    pub hash: String,
+    
+    /// Why the module is allowed
+    pub note: String,
+    
+    /// Node that added the entry
+    pub added_by: String,
+    
+    /// When the entry was added
+    pub added_at: u64,
+    
+    /// When the entry stops allowing the module, if ever
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+}
+
+impl AllowEntry {
+    /// Whether the entry has expired
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.map(|t| t <= now()).unwrap_or(false)
+    }
+}
+
+/// Signed execution allow-list
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AllowList {
+    /// Allowed modules
+    pub entries: Vec<AllowEntry>,
+    
+    /// When the list was last changed
+    pub updated_at: u64,
+    
+    /// Node that signed the list
+    pub signer: String,
+    
+    /// Signature over the list without this field
+    #[serde(default)]
+    pub signature: String,
+}
+
+/// Whether the allow-list is enforced
+pub fn is_enforced() -> bool {
+    match load_policy() {
+        Ok(policy) => policy.enforce,
+        Err(e) => {
+            // A broken policy must not silently turn enforcement off
+            warn!("Failed to load execution policy, enforcing it: {}", e);
+            true
+        }
+    }
+}
+
+/// Refuse a module that is not allowed to execute
+///
+/// Does nothing unless the policy is enforced. The error names the
+/// module's hash so it can be added with `sentctl policy exec allow`.
+pub fn check_module(wasm_bytes: &[u8], wasm_path: &Path) -> Result<()> {
+    if !is_enforced() {
+        return Ok(());
+    }
+    
+    let hash = blake3::hash(wasm_bytes).to_hex().to_string();
+    let list = load_allowlist().context("Execution allow-list is unusable; refusing to run modules")?;
+    
+    match list.entries.iter().find(|e| e.hash == hash) {
+        Some(entry) if entry.is_expired() => {
+            anyhow::bail!("Module {:?} (blake3 {}) was allowed until {} and the entry has expired; \
+                           renew it with `sentctl policy exec allow {} --note ...`",
+                          wasm_path, hash, entry.expires_at.unwrap_or_default(), hash)
+        }
+        Some(entry) => {
+            debug!("Module {:?} allowed: {}", wasm_path, entry.note);
+            Ok(())
+        }
+        None => {
+            anyhow::bail!("Module {:?} is not on the execution allow-list (blake3 {}); \
+                           add it with `sentctl policy exec allow {} --note ...`", wasm_path, hash, hash)
+        }
+    }
+}
+
+/// Refuse unsecure-mode runs while the allow-list is enforced
+pub fn ensure_unsecure_allowed() -> Result<()> {
+    if is_enforced() {
+        anyhow::bail!("Unsecure mode is unavailable while the execution allow-list policy is enforced");
+    }
+    Ok(())
+}
+
+/// Allow a module, given its hash or a module file or container directory
+///
+/// An existing entry for the same hash is replaced, which renews it.
+pub fn allow(target: &str, note: &str, expires_at: Option<u64>) -> Result<AllowEntry> {
+    let hash = resolve_hash(target)?;
+    if let Some(expires_at) = expires_at {
+        if expires_at <= now() {
+            anyhow::bail!("Expiry must be in the future");
+        }
+    }
+    
+    let entry = AllowEntry {
+        hash: hash.clone(),
+        note: note.to_string(),
+        added_by: identity::node_id()?,
+        added_at: now(),
+        expires_at,
+    };
+    
+    let mut list = load_allowlist()?;
+    list.entries.retain(|e| e.hash != hash);
+    list.entries.push(entry.clone());
+    save_signed(&mut list)?;
+    
+    crate::logs::ship::ship_audit("policy", &format!(
+        "Allowed module {} for execution: {}{}", hash, note,
+        expires_at.map(|t| format!(" (expires {})", t)).unwrap_or_default()));
+    info!("Allowed module {} for execution", hash);
+    Ok(entry)
+}
+
+/// Remove a module from the allow-list
+pub fn revoke(hash: &str) -> Result<AllowEntry> {
+    let mut list = load_allowlist()?;
+    let position = list.entries.iter()
+        .position(|e| e.hash == hash)
+        .ok_or_else(|| anyhow::anyhow!("Module {} is not on the execution allow-list", hash))?;
+    let entry = list.entries.remove(position);
+    save_signed(&mut list)?;
+    
+    crate::logs::ship::ship_audit("policy", &format!("Revoked module {} from execution allow-list", hash));
+    info!("Revoked module {} from execution allow-list", hash);
+    Ok(entry)
+}
+
+/// Entries of the verified allow-list
+pub fn list() -> Result<Vec<AllowEntry>> {
+    let mut entries = load_allowlist()?.entries;
+    entries.sort_by_key(|e| e.added_at);
+    Ok(entries)
+}
+
+/// Store an allow-list gossiped by a trusted peer, if it is newer
+pub fn handle_peer_allowlist(source_id: &str, payload: &[u8]) -> Result<()> {
+    if !crate::gossip::is_trusted_peer(source_id) {
+        debug!("Ignoring execution allow-list from untrusted peer: {}", source_id);
+        return Ok(());
+    }
+    
+    let list: AllowList = serde_json::from_slice(payload)
+        .context("Failed to parse execution allow-list")?;
+    verify(&list, &load_policy()?)
+        .with_context(|| format!("Rejected execution allow-list from {}", source_id))?;
+    
+    let current = load_allowlist().unwrap_or_default();
+    if list.updated_at <= current.updated_at {
+        debug!("Execution allow-list from {} is not newer than ours", source_id);
+        return Ok(());
+    }
+    
+    write_allowlist(&list)?;
+    crate::logs::ship::ship_audit("policy", &format!(
+        "Accepted execution allow-list signed by {} from {} ({} entries)",
+        list.signer, source_id, list.entries.len()));
+    info!("Updated execution allow-list from {}", source_id);
+    Ok(())
+}
+
+/// Parse an expiry date (YYYY-MM-DD, end of day UTC) into a timestamp
+pub fn parse_expiry(date: &str) -> Result<u64> {
+    let date = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .with_context(|| format!("Invalid expiry date (expected YYYY-MM-DD): {}", date))?;
+    let end_of_day = date.and_hms_opt(23, 59, 59)
+        .ok_or_else(|| anyhow::anyhow!("Invalid expiry date"))?;
+    Ok(end_of_day.and_utc().timestamp().max(0) as u64)
+}
+
+/// Load the execution policy from security.json
+pub fn load_policy() -> Result<ExecPolicy> {
+    let path = constants::root_dir().join(".config").join("security.json");
+    if !path.exists() {
+        return Ok(ExecPolicy::default());
+    }
+    
+    let security: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    match security.get("exec_policy") {
+        Some(value) => Ok(serde_json::from_value(value.clone())
+            .context("Invalid exec_policy in security.json")?),
+        None => Ok(ExecPolicy::default()),
+    }
+}
+
+/// Path of the allow-list
+pub fn allowlist_path() -> PathBuf {
+    constants::root_dir().join(constants::AUTH_DIR).join(POLICIES_DIR).join(ALLOWLIST_FILE)
+}
+
+/// Load and verify the allow-list; a missing list is empty
+fn load_allowlist() -> Result<AllowList> {
+    let path = allowlist_path();
+    if !path.exists() {
+        return Ok(AllowList::default());
+    }
+    
+    let list: AllowList = serde_json::from_str(&fs::read_to_string(&path)?)
+        .context("Failed to parse execution allow-list")?;
+    verify(&list, &load_policy()?).context("Execution allow-list failed verification")?;
+    Ok(list)
+}
+
+/// Check that a list is signed by this node or an accepted signer
+fn verify(list: &AllowList, policy: &ExecPolicy) -> Result<()> {
+    let own = identity::current()?;
+    let public_key = if list.signer == own.id {
+        own.public_key
+    } else if policy.signers.contains(&list.signer) {
+        crate::gossip::peer_public_key(&list.signer)
+            .ok_or_else(|| anyhow::anyhow!("No verified public key for signer {}", list.signer))?
+    } else {
+        anyhow::bail!("Signer {} is not an accepted allow-list signer", list.signer);
+    };
+    
+    identity::verify(&public_key, &signing_payload(list)?, &list.signature)
+        .context("Execution allow-list signature is invalid")
+}
+
+/// Sign a changed list as this node, store it and send it to peers
+fn save_signed(list: &mut AllowList) -> Result<()> {
+    list.updated_at = now().max(list.updated_at + 1);
+    list.signer = identity::node_id()?;
+    list.signature = identity::sign(&signing_payload(list)?)?;
+    
+    write_allowlist(list)?;
+    broadcast(list);
+    Ok(())
+}
+
+/// Write the allow-list
+fn write_allowlist(list: &AllowList) -> Result<()> {
+    let path = allowlist_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(list)?)
+        .context("Failed to write execution allow-list")
+}
+
+/// Bytes covered by the list signature
+fn signing_payload(list: &AllowList) -> Result<Vec<u8>> {
+    let mut unsigned = list.clone();
+    unsigned.signature = String::new();
+    Ok(serde_json::to_vec(&unsigned)?)
+}
+
+/// Hash of a target given as a hash, a module file or a container directory
+fn resolve_hash(target: &str) -> Result<String> {
+    let path = Path::new(target);
+    if path.exists() {
+        let module = if path.is_dir() { path.join("main.wasm") } else { path.to_path_buf() };
+        let bytes = fs::read(&module).with_context(|| format!("Failed to read module {:?}", module))?;
+        return Ok(blake3::hash(&bytes).to_hex().to_string());
+    }
+    
+    if target.len() == 64 && target.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(target.to_lowercase());
+    }
+    anyhow::bail!("Not a module file or a blake3 hash: {}", target)
+}
+
+/// Send the allow-list to trusted peers
+fn broadcast(list: &AllowList) {
+    let payload = match serde_json::to_vec(list) {
+        Ok(payload) => payload,
+        Err(e) => {
+            warn!("Failed to serialize execution allow-list: {}", e);
+            return;
+        }
+    };
+    
+    let peers = match crate::gossip::list_peers() {
+        Ok(peers) => peers,
+        Err(e) => {
+            warn!("Failed to list peers for execution allow-list: {}", e);
+            return;
+        }
+    };
+    
+    for peer in peers.iter().filter(|p| crate::gossip::is_trusted_peer(&p.id)) {
+        if let Err(e) = crate::gossip::protocol::send_message(&peer.endpoint, MessageType::ExecAllowList, &payload) {
+            warn!("Failed to send execution allow-list to {}: {}", peer.id, e);
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+    
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+    
+    fn entry(hash: &str, expires_at: Option<u64>) -> AllowEntry {
+        AllowEntry {
+            hash: hash.to_string(),
+            note: "test".to_string(),
+            added_by: "policy-node".to_string(),
+            added_at: 1,
+            expires_at,
+        }
+    }
+    
+    /// List of one entry signed as `signer` with the key derived from `seed`
+    fn peer_list(signer: &str, seed: u8) -> AllowList {
+        let key = SigningKey::from_bytes(&[seed; 32]);
+        let mut list = AllowList {
+            entries: vec![entry(&"c".repeat(64), None)],
+            updated_at: 5,
+            signer: signer.to_string(),
+            signature: String::new(),
+        };
+        list.signature = hex(&key.sign(&signing_payload(&list).unwrap()).to_bytes());
+        list
+    }
+    
+    #[test]
+    fn allowed_modules_are_listed_renewed_and_revoked() {
+        let dir = constants::root_dir().join("policy-modules");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("main.wasm"), b"\0asm policy").unwrap();
+        let module_hash = blake3::hash(b"\0asm policy").to_hex().to_string();
+        let other_hash = "A".repeat(64);
+        
+        let allowed = allow(dir.to_str().unwrap(), "first", None).unwrap();
+        assert_eq!(allowed.hash, module_hash);
+        assert_eq!(allow(&other_hash, "other", None).unwrap().hash, "a".repeat(64));
+        let expiry = now() + 3600;
+        let renewed = allow(dir.join("main.wasm").to_str().unwrap(), "renewed", Some(expiry)).unwrap();
+        assert_eq!(renewed.expires_at, Some(expiry));
+        
+        let entries = list().unwrap();
+        let ours: Vec<_> = entries.iter().filter(|e| e.hash == module_hash).collect();
+        assert_eq!(ours.len(), 1);
+        assert_eq!(ours[0].note, "renewed");
+        assert!(entries.iter().any(|e| e.hash == "a".repeat(64)));
+        
+        assert!(allow(&module_hash, "late", Some(now() - 1)).is_err());
+        assert_eq!(revoke(&module_hash).unwrap().note, "renewed");
+        assert!(revoke(&module_hash).is_err());
+        assert!(!list().unwrap().iter().any(|e| e.hash == module_hash));
+    }
+    
+    #[test]
+    fn own_lists_verify_and_tampered_ones_do_not() {
+        let own = identity::current().unwrap();
+        let mut list = AllowList {
+            entries: vec![entry(&"b".repeat(64), None)],
+            updated_at: 3,
+            signer: own.id.clone(),
+            signature: String::new(),
+        };
+        list.signature = identity::sign(&signing_payload(&list).unwrap()).unwrap();
+        assert!(verify(&list, &ExecPolicy::default()).is_ok());
+        
+        list.entries.push(entry(&"d".repeat(64), None));
+        assert!(verify(&list, &ExecPolicy::default()).is_err());
+    }
+    
+    #[test]
+    fn peer_lists_need_an_accepted_signer_with_a_known_key() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        crate::gossip::add_peer("policy-signer", "127.0.0.1:9", None).unwrap();
+        crate::gossip::update_peer_identity("policy-signer", "policy-signer", &hex(key.verifying_key().as_bytes())).unwrap();
+        let accepting = ExecPolicy { enforce: true, signers: vec!["policy-signer".to_string(), "policy-unknown".to_string()] };
+        
+        assert!(verify(&peer_list("policy-signer", 7), &accepting).is_ok());
+        assert!(verify(&peer_list("policy-signer", 7), &ExecPolicy::default()).is_err());
+        assert!(verify(&peer_list("policy-signer", 8), &accepting).is_err());
+        assert!(verify(&peer_list("policy-unknown", 7), &accepting).is_err());
+    }
+    
+    #[test]
+    fn targets_resolve_to_module_hashes() {
+        let file = constants::root_dir().join("policy-target.wasm");
+        fs::write(&file, b"\0asm target").unwrap();
+        assert_eq!(resolve_hash(file.to_str().unwrap()).unwrap(), blake3::hash(b"\0asm target").to_hex().to_string());
+        assert_eq!(resolve_hash(&"F".repeat(64)).unwrap(), "f".repeat(64));
+        assert!(resolve_hash("not-a-hash").is_err());
+        assert!(resolve_hash(&"g".repeat(64)).is_err());
+    }
+    
+    #[test]
+    fn expiry_dates_end_at_the_end_of_the_day() {
+        assert_eq!(parse_expiry("1970-01-02").unwrap(), 86400 + 86399);
+        assert!(parse_expiry("02/01/1970").is_err());
+        
+        assert!(entry("x", Some(now() - 1)).is_expired());
+        assert!(!entry("x", Some(now() + 60)).is_expired());
+        assert!(!entry("x", None).is_expired());
+    }
+}
@@ -1,19 +1,40 @@
 use anyhow::{Result, Context};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::{Arc, Mutex};
-use tracing::{info, warn};
+use tracing::{info, debug, warn};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use serde::{Serialize, Deserialize};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 
 use super::container::{Container, ContainerId, ContainerInfo, ContainerStatus, generate_container_id};
 use crate::core::constants;
 
+// Constants
+const SIGNATURE_FILE: &str = "signature.json";
+const TRUSTED_KEYS_DIR: &str = ".matrixbox/trusted_keys";
+const IMAGE_SIGNING_KEY_FILE: &str = "matrixbox-image.key";
+
 // In-memory container registry
 lazy_static::lazy_static! {
     static ref CONTAINER_REGISTRY: Arc<Mutex<Registry>> = Arc::new(Mutex::new(Registry::new()));
 }
 
+// Keys images must be signed with, loaded by matrixbox::init
+lazy_static::lazy_static! {
+    static ref IMAGE_TRUST: Mutex<ImageTrust> = Mutex::new(ImageTrust::default());
+}
+
+/// Trusted image keys and whether unsigned images are refused
+#[derive(Debug, Clone, Default)]
+struct ImageTrust {
+    /// Keys an image may be signed with
+    keys: Vec<VerifyingKey>,
+    
+    /// Whether images without a trusted signature are refused
+    enforce: bool,
+}
+
 /// Container Registry
 #[derive(Debug, Default)]
 struct Registry {
@@ -22,6 +43,9 @@ struct Registry {
     
     /// Map of container ID to container status
     status: HashMap<ContainerId, ContainerStatus>,
+    
+    /// Map of container ID to the arguments it was run with
+    args: HashMap<ContainerId, Vec<String>>,
 }
 
 impl Registry {
@@ -30,6 +54,7 @@ impl Registry {
         Self {
             containers: HashMap::new(),
             status: HashMap::new(),
+            args: HashMap::new(),
         }
     }
 }
@@ -39,6 +64,10 @@ impl Registry {
 struct RegistryData {
     /// Container IDs and their respective paths
     containers: HashMap<ContainerId, String>,
+    
+    /// Arguments of containers that were run with any
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    args: HashMap<ContainerId, Vec<String>>,
 }
 
 /// Initialize the MatrixBox registry
@@ -46,7 +75,7 @@ pub fn init() -> Result<()> {
     info!("Initializing MatrixBox registry");
     
     // Create registry directory if it doesn't exist
-    let registry_dir = PathBuf::from(constants::ROOT_DIR)
+    let registry_dir = constants::root_dir()
         .join(constants::CONTAINER_DIR)
         .join("registry");
     
@@ -68,7 +97,7 @@ pub fn shutdown() -> Result<()> {
     info!("Shutting down MatrixBox registry");
     
     // Save registry data
-    let registry_dir = PathBuf::from(constants::ROOT_DIR)
+    let registry_dir = constants::root_dir()
         .join(constants::CONTAINER_DIR)
         .join("registry");
     
@@ -79,6 +108,25 @@ pub fn shutdown() -> Result<()> {
     Ok(())
 }
 
+/// Container IDs and names from a saved registry file, such as a snapshot's copy
+pub fn containers_in_registry_file(content: &[u8]) -> Result<Vec<(ContainerId, String)>> {
+    let data: RegistryData = serde_json::from_slice(content)
+        .context("Failed to parse registry data")?;
+    
+    let mut containers: Vec<(ContainerId, String)> = data.containers.into_iter()
+        .map(|(id, path)| {
+            // Container names are their directory names, as in load_container
+            let name = PathBuf::from(&path).file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or(path);
+            (id, name)
+        })
+        .collect();
+    containers.sort();
+    
+    Ok(containers)
+}
+
 /// Load registry data from file
 fn load_registry(file_path: &PathBuf) -> Result<()> {
     info!("Loading MatrixBox registry from: {:?}", file_path);
@@ -101,6 +149,9 @@ fn load_registry(file_path: &PathBuf) -> Result<()> {
                     container.id = Some(id.clone());
                     registry.containers.insert(id.clone(), container);
                     registry.status.insert(id.clone(), ContainerStatus::Created);
+                    if let Some(args) = data.args.get(&id) {
+                        registry.args.insert(id.clone(), args.clone());
+                    }
                     info!("Loaded container: {} from registry", id);
                 },
                 Err(err) => {
@@ -123,11 +174,15 @@ fn save_registry(file_path: &PathBuf) -> Result<()> {
     
     let mut data = RegistryData {
         containers: HashMap::new(),
+        args: HashMap::new(),
     };
     
     for (id, container) in &registry.containers {
         if let Some(path) = &container.path {
             data.containers.insert(id.clone(), path.to_string_lossy().to_string());
+            if let Some(args) = registry.args.get(id).filter(|args| !args.is_empty()) {
+                data.args.insert(id.clone(), args.clone());
+            }
         }
     }
     
@@ -142,8 +197,8 @@ fn save_registry(file_path: &PathBuf) -> Result<()> {
     Ok(())
 }
 
-/// Register a container in the registry
-pub fn register_container(container: &Container) -> Result<ContainerId> {
+/// Register a container in the registry, with the arguments it runs with
+pub fn register_container(container: &Container, args: &[&str]) -> Result<ContainerId> {
     let id = generate_container_id();
     info!("Registering container: {} with ID: {}", container.name, id);
     
@@ -156,6 +211,7 @@ pub fn register_container(container: &Container) -> Result<ContainerId> {
     // Add to registry
     registry.containers.insert(id.clone(), container);
     registry.status.insert(id.clone(), ContainerStatus::Created);
+    registry.args.insert(id.clone(), args.iter().map(|arg| arg.to_string()).collect());
     
     info!("Container registered: {}", id);
     Ok(id)
@@ -169,6 +225,7 @@ pub fn unregister_container(id: &ContainerId) -> Result<()> {
     
     if registry.containers.remove(id).is_some() {
         registry.status.remove(id);
+        registry.args.remove(id);
         info!("Container unregistered: {}", id);
         Ok(())
     } else {
@@ -176,6 +233,27 @@ pub fn unregister_container(id: &ContainerId) -> Result<()> {
     }
 }
 
+/// Put a container back in the registry under an ID it had before
+///
+/// Used when healing a container whose registry entry was lost.
+pub fn reinstate_container(id: &ContainerId, container: &Container, args: &[String]) -> Result<()> {
+    info!("Reinstating container: {} with ID: {}", container.name, id);
+    
+    let mut registry = CONTAINER_REGISTRY.lock().unwrap();
+    
+    if registry.containers.contains_key(id) {
+        anyhow::bail!("Container already registered: {}", id);
+    }
+    
+    let mut container = container.clone();
+    container.id = Some(id.clone());
+    
+    registry.containers.insert(id.clone(), container);
+    registry.status.insert(id.clone(), ContainerStatus::Created);
+    registry.args.insert(id.clone(), args.to_vec());
+    Ok(())
+}
+
 /// Get a container by ID
 pub fn get_container(id: &ContainerId) -> Result<Container> {
     let registry = CONTAINER_REGISTRY.lock().unwrap();
@@ -187,6 +265,16 @@ pub fn get_container(id: &ContainerId) -> Result<Container> {
     }
 }
 
+/// Get the arguments a container runs with
+pub fn get_container_args(id: &ContainerId) -> Result<Vec<String>> {
+    let registry = CONTAINER_REGISTRY.lock().unwrap();
+    
+    if !registry.containers.contains_key(id) {
+        anyhow::bail!("Container not found: {}", id);
+    }
+    Ok(registry.args.get(id).cloned().unwrap_or_default())
+}
+
 /// Update a container's status
 pub fn update_container_status(id: &ContainerId, status: ContainerStatus) -> Result<()> {
     let mut registry = CONTAINER_REGISTRY.lock().unwrap();
@@ -200,6 +288,26 @@ pub fn update_container_status(id: &ContainerId, status: ContainerStatus) -> Res
     }
 }
 
+/// Mark every running container as suspect, returning how many were marked
+///
+/// Called from the panic hook, possibly on a thread that holds the
+/// registry; the registry is left alone then instead of deadlocking.
+pub fn mark_running_suspect() -> Result<usize> {
+    let mut registry = match CONTAINER_REGISTRY.try_lock() {
+        Ok(registry) => registry,
+        Err(_) => anyhow::bail!("Container registry is busy"),
+    };
+    
+    let mut marked = 0;
+    for status in registry.status.values_mut() {
+        if matches!(status, ContainerStatus::Running | ContainerStatus::Healthy) {
+            *status = ContainerStatus::Suspect;
+            marked += 1;
+        }
+    }
+    Ok(marked)
+}
+
 /// Get a container's status
 pub fn get_container_status(id: &ContainerId) -> Result<ContainerStatus> {
     let registry = CONTAINER_REGISTRY.lock().unwrap();
@@ -225,8 +333,330 @@ pub fn list_containers() -> Result<Vec<ContainerInfo>> {
             name: container.name.clone(),
             status,
             created_at: container.metadata.created_at.clone(),
+            entrypoint: container.metadata.entrypoint.clone(),
+            args: registry.args.get(id).cloned().unwrap_or_default(),
+            ports: super::network::forwarded_ports(id)?,
+            resource_limits: container.metadata.resource_limits,
         });
     }
     
     Ok(containers)
 }
+
+/// Signature over a container image, stored as `signature.json` in the image
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageSignature {
+    /// BLAKE3 hash (hex) of the image manifest
+    pub manifest_hash: String,
+    
+    /// Ed25519 public key (hex) of the signer
+    pub public_key: String,
+    
+    /// Ed25519 signature (hex) over the manifest hash
+    pub signature: String,
+    
+    /// When the image was signed (RFC 3339)
+    pub signed_at: String,
+}
+
+/// Sign a container image, writing `signature.json` into it
+///
+/// The image manifest lists the BLAKE3 hash of every file in the image
+/// except the signature itself, so changing, adding or removing any file
+/// invalidates the signature.
+pub fn sign_image(image_dir: &Path, signing_key: &SigningKey) -> Result<ImageSignature> {
+    let manifest_hash = image_manifest_hash(image_dir)?;
+    let signature = ImageSignature {
+        public_key: to_hex(signing_key.verifying_key().as_bytes()),
+        signature: to_hex(&signing_key.sign(manifest_hash.as_bytes()).to_bytes()),
+        manifest_hash,
+        signed_at: chrono::Utc::now().to_rfc3339(),
+    };
+    
+    let path = image_dir.join(SIGNATURE_FILE);
+    fs::write(&path, serde_json::to_string_pretty(&signature)?)
+        .with_context(|| format!("Failed to write image signature {:?}", path))?;
+    
+    info!("Signed image {:?} with key {}", image_dir, signature.public_key);
+    Ok(signature)
+}
+
+/// Whether an image is signed by one of `trusted_keys` and unchanged since
+///
+/// An unsigned image, a signer outside `trusted_keys`, a bad signature or
+/// files that no longer match the signed manifest all give `false`.
+pub fn verify_image(image_dir: &Path, trusted_keys: &[VerifyingKey]) -> Result<bool> {
+    let path = image_dir.join(SIGNATURE_FILE);
+    if !path.exists() {
+        debug!("Image {:?} is not signed", image_dir);
+        return Ok(false);
+    }
+    let signature: ImageSignature = serde_json::from_str(&fs::read_to_string(&path)?)
+        .with_context(|| format!("Invalid image signature {:?}", path))?;
+    
+    let key = match trusted_keys.iter().find(|key| to_hex(key.as_bytes()) == signature.public_key) {
+        Some(key) => key,
+        None => {
+            debug!("Image {:?} is signed by untrusted key {}", image_dir, signature.public_key);
+            return Ok(false);
+        }
+    };
+    
+    let signature_bytes: [u8; 64] = match from_hex(&signature.signature)?.try_into() {
+        Ok(bytes) => bytes,
+        Err(_) => return Ok(false),
+    };
+    if key.verify(signature.manifest_hash.as_bytes(), &Signature::from_bytes(&signature_bytes)).is_err() {
+        debug!("Image {:?} has a bad signature", image_dir);
+        return Ok(false);
+    }
+    
+    if image_manifest_hash(image_dir)? != signature.manifest_hash {
+        debug!("Image {:?} changed since it was signed", image_dir);
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Load the trusted image keys and whether unsigned images are refused
+///
+/// Keys are hex Ed25519 public keys, one per file in
+/// `.matrixbox/trusted_keys/`. Images are required to be signed when
+/// `zk_verify` is set in the package manager configuration.
+pub fn load_image_trust() -> Result<()> {
+    let keys = load_trusted_keys()?;
+    let enforce = match crate::package::load_config() {
+        Ok(config) => config.zk_verify,
+        Err(e) => {
+            debug!("Package configuration unavailable ({}); image signatures are not enforced", e);
+            false
+        }
+    };
+    
+    info!("Loaded {} trusted image key(s); signatures {}", keys.len(),
+          if enforce { "required" } else { "optional" });
+    *IMAGE_TRUST.lock().unwrap() = ImageTrust { keys, enforce };
+    Ok(())
+}
+
+/// Refuse an image that is not signed by a trusted key, when signatures are required
+pub fn check_image(image_dir: &Path) -> Result<()> {
+    let trust = IMAGE_TRUST.lock().unwrap().clone();
+    if !trust.enforce {
+        return Ok(());
+    }
+    if !verify_image(image_dir, &trust.keys)? {
+        anyhow::bail!("Refusing to load image {:?}: it is not signed by a trusted key", image_dir);
+    }
+    Ok(())
+}
+
+/// Trusted image keys from `.matrixbox/trusted_keys/`
+pub fn load_trusted_keys() -> Result<Vec<VerifyingKey>> {
+    let dir = constants::root_dir().join(TRUSTED_KEYS_DIR);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let mut keys = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        let key_bytes: Option<[u8; 32]> = from_hex(fs::read_to_string(&path)?.trim())
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok());
+        match key_bytes.map(|bytes| VerifyingKey::from_bytes(&bytes)) {
+            Some(Ok(key)) => keys.push(key),
+            _ => warn!("Ignoring invalid trusted key {:?}", path),
+        }
+    }
+    Ok(keys)
+}
+
+/// This node's image signing key, generated on first use
+pub fn image_signing_key() -> Result<SigningKey> {
+    use rand::{thread_rng, Rng};
+    
+    let path = constants::root_dir().join(constants::AUTH_DIR).join("keys").join(IMAGE_SIGNING_KEY_FILE);
+    if path.exists() {
+        let seed: [u8; 32] = from_hex(fs::read_to_string(&path)?.trim())?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid image signing key {:?}", path))?;
+        return Ok(SigningKey::from_bytes(&seed));
+    }
+    
+    let seed: [u8; 32] = thread_rng().gen();
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(&path, to_hex(&seed)).with_context(|| format!("Failed to write image signing key {:?}", path))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    }
+    info!("Generated image signing key {:?}", path);
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// BLAKE3 hash (hex) of an image's manifest of file hashes
+fn image_manifest_hash(image_dir: &Path) -> Result<String> {
+    if !image_dir.is_dir() {
+        anyhow::bail!("Image not found: {:?}", image_dir);
+    }
+    
+    let mut manifest = BTreeMap::new();
+    collect_manifest(image_dir, image_dir, &mut manifest)?;
+    Ok(blake3::hash(serde_json::to_string(&manifest)?.as_bytes()).to_hex().to_string())
+}
+
+/// Add the hashes of the files under `dir` to a manifest, by path relative to the image
+fn collect_manifest(image_dir: &Path, dir: &Path, manifest: &mut BTreeMap<String, String>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let relative = path.strip_prefix(image_dir)?.to_string_lossy().replace('\\', "/");
+        if relative == SIGNATURE_FILE {
+            continue;
+        }
+        if path.is_dir() {
+            collect_manifest(image_dir, &path, manifest)?;
+        } else {
+            let data = fs::read(&path).with_context(|| format!("Failed to read {:?}", path))?;
+            manifest.insert(relative, blake3::hash(&data).to_hex().to_string());
+        }
+    }
+    Ok(())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        anyhow::bail!("Invalid hex string length");
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).context("Invalid hex string"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn saved_registries_list_containers_by_directory_name() {
+        let saved = r#"{"containers":{"c2":"/srv/containers/web","c1":"/srv/containers/db/"},"args":{"c2":["--port","80"]}}"#;
+        let containers = containers_in_registry_file(saved.as_bytes()).unwrap();
+        assert_eq!(containers, [
+            ("c1".to_string(), "db".to_string()),
+            ("c2".to_string(), "web".to_string()),
+        ]);
+        
+        assert!(containers_in_registry_file(b"{}").is_err());
+    }
+    
+    /// Container image on disk that `load_container` accepts
+    fn image(name: &str) -> Container {
+        let dir = constants::root_dir().join("registry-tests").join(name);
+        fs::create_dir_all(&dir).unwrap();
+        let mut container = super::super::app::default_manifest(name);
+        container.path = Some(dir.clone());
+        super::super::container::save_container(&container).unwrap();
+        fs::write(dir.join("main.wasm"), b"\0asm\x01\0\0\0").unwrap();
+        container
+    }
+    
+    #[test]
+    fn run_arguments_are_listed_and_survive_a_reload() {
+        let with_args = register_container(&image("registry-args"), &["--port", "80"]).unwrap();
+        let without_args = register_container(&image("registry-no-args"), &[]).unwrap();
+        
+        assert_eq!(get_container_args(&with_args).unwrap(), ["--port", "80"]);
+        assert!(get_container_args(&without_args).unwrap().is_empty());
+        let listed = list_containers().unwrap().into_iter().find(|info| info.id == with_args).unwrap();
+        assert_eq!((listed.entrypoint.as_str(), listed.args), ("_start", vec!["--port".to_string(), "80".to_string()]));
+        
+        // Only containers run with arguments have them saved
+        let file = constants::root_dir().join("registry-tests").join("registry.json");
+        save_registry(&file).unwrap();
+        let data: RegistryData = serde_json::from_slice(&fs::read(&file).unwrap()).unwrap();
+        assert!(data.args.contains_key(&with_args) && !data.args.contains_key(&without_args));
+        
+        unregister_container(&with_args).unwrap();
+        unregister_container(&without_args).unwrap();
+        assert!(get_container_args(&with_args).is_err());
+        
+        load_registry(&file).unwrap();
+        assert_eq!(get_container_args(&with_args).unwrap(), ["--port", "80"]);
+        assert!(get_container_args(&without_args).unwrap().is_empty());
+        unregister_container(&with_args).unwrap();
+        unregister_container(&without_args).unwrap();
+    }
+    
+    /// Unsigned image directory holding a few files
+    fn unsigned_image(name: &str) -> PathBuf {
+        let dir = constants::root_dir().join("registry-tests").join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("assets")).unwrap();
+        fs::write(dir.join("main.wasm"), b"\0asm\x01\0\0\0").unwrap();
+        fs::write(dir.join("assets").join("index.html"), "<h1>hello</h1>").unwrap();
+        dir
+    }
+    
+    #[test]
+    fn signed_images_verify_until_a_file_changes() {
+        let dir = unsigned_image("signed-image");
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let other = SigningKey::from_bytes(&[8; 32]);
+        let trusted = [other.verifying_key(), key.verifying_key()];
+        assert!(!verify_image(&dir, &trusted).unwrap());
+        
+        let signature = sign_image(&dir, &key).unwrap();
+        assert_eq!(signature.public_key, to_hex(key.verifying_key().as_bytes()));
+        assert!(verify_image(&dir, &trusted).unwrap());
+        assert!(!verify_image(&dir, &[other.verifying_key()]).unwrap());
+        
+        // Signing again covers the same files: the signature is not part of its manifest
+        assert_eq!(sign_image(&dir, &key).unwrap().manifest_hash, signature.manifest_hash);
+        
+        fs::write(dir.join("assets").join("index.html"), "<h1>changed</h1>").unwrap();
+        assert!(!verify_image(&dir, &trusted).unwrap());
+        fs::write(dir.join("assets").join("index.html"), "<h1>hello</h1>").unwrap();
+        assert!(verify_image(&dir, &trusted).unwrap());
+        
+        fs::write(dir.join("extra.wasm"), b"added").unwrap();
+        assert!(!verify_image(&dir, &trusted).unwrap());
+        fs::remove_file(dir.join("extra.wasm")).unwrap();
+        assert!(verify_image(&dir, &trusted).unwrap());
+        
+        // A signature made by another key over the same manifest is refused
+        let forged = ImageSignature {
+            signature: to_hex(&other.sign(signature.manifest_hash.as_bytes()).to_bytes()),
+            ..signature
+        };
+        fs::write(dir.join(SIGNATURE_FILE), serde_json::to_string(&forged).unwrap()).unwrap();
+        assert!(!verify_image(&dir, &trusted).unwrap());
+        
+        assert!(sign_image(&dir.join("missing"), &key).is_err());
+    }
+    
+    #[test]
+    fn trusted_keys_are_read_as_hex_and_bad_ones_skipped() {
+        let dir = constants::root_dir().join(TRUSTED_KEYS_DIR);
+        fs::create_dir_all(&dir).unwrap();
+        let key = SigningKey::from_bytes(&[9; 32]).verifying_key();
+        fs::write(dir.join("registry-test-valid"), format!("{}\n", to_hex(key.as_bytes()))).unwrap();
+        fs::write(dir.join("registry-test-invalid"), "not a key").unwrap();
+        
+        let keys = load_trusted_keys().unwrap();
+        assert!(keys.contains(&key));
+        fs::remove_file(dir.join("registry-test-valid")).unwrap();
+        fs::remove_file(dir.join("registry-test-invalid")).unwrap();
+        
+        // Unsigned images load while signatures are not required
+        assert!(check_image(&unsigned_image("unenforced-image")).is_ok());
+    }
+}
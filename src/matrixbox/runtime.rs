@@ -2,16 +2,24 @@ use anyhow::{Result, Context};
 use tracing::{info, warn, error};
 use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-use wasmer::{Store, Module, Instance, Memory, MemoryType};
+use wasmer::{Store, Module, Instance, Memory, MemoryType, Mutability, Pages};
 use wasmer::AsStoreRef;
-use wasmer_wasi::{WasiEnv, WasiState};
-use std::path::PathBuf;
+use wasmer_wasix::{WasiEnv, WasiFunctionEnv};
+use std::path::{Path, PathBuf};
 use std::fs;
+use serde::{Serialize, Deserialize};
 
-use super::container::{Container, ContainerId, ContainerStatus};
+use super::container::{Container, ContainerId, ContainerStatus, ResourceLimits};
+use super::limits;
+use super::network;
 use super::registry;
 use crate::core::constants;
 
+// Constants
+const CHECKPOINT_FILE: &str = "checkpoint.json";
+const CHECKPOINT_MEMORY_FILE: &str = "memory.bin";
+const WASM_PAGE_SIZE: u64 = 65_536;
+
 // Map of container ID to running instance
 lazy_static::lazy_static! {
     static ref RUNNING_CONTAINERS: Arc<Mutex<HashMap<ContainerId, RunningContainer>>> = 
@@ -33,10 +41,16 @@ struct RunningContainer {
     instance: Instance,
     
     /// WASI environment
-    wasi_env: WasiEnv,
+    wasi_env: WasiFunctionEnv,
     
     /// Memory snapshot for ZK verification
     memory_snapshots: Vec<Vec<u8>>,
+    
+    /// Whether new calls into the instance are held back
+    paused: bool,
+    
+    /// Hard limits the container runs under
+    resource_limits: Option<ResourceLimits>,
 }
 
 /// Initialize the MatrixBox runtime
@@ -44,7 +58,7 @@ pub fn init() -> Result<()> {
     info!("Initializing MatrixBox runtime");
     
     // Create runtime directories
-    let runtime_dir = PathBuf::from(constants::ROOT_DIR)
+    let runtime_dir = constants::root_dir()
         .join(constants::CONTAINER_DIR)
         .join("runtime");
     
@@ -91,6 +105,23 @@ pub fn start_container(id: &ContainerId) -> Result<()> {
         }
     }
     
+    let running_container = instantiate(id, &container)?;
+    
+    // Add to running containers
+    {
+        let mut running_containers = RUNNING_CONTAINERS.lock().unwrap();
+        running_containers.insert(id.clone(), running_container);
+    }
+    
+    // Update container status
+    registry::update_container_status(id, ContainerStatus::Running)?;
+    
+    info!("Container started: {}", id);
+    Ok(())
+}
+
+/// Compile and instantiate a container's module under its limits
+fn instantiate(id: &ContainerId, container: &Container) -> Result<RunningContainer> {
     // Get the container path
     let container_path = container.path.as_ref()
         .ok_or_else(|| anyhow::anyhow!("Container has no path"))?;
@@ -99,9 +130,10 @@ pub fn start_container(id: &ContainerId) -> Result<()> {
     let wasm_path = container_path.join("main.wasm");
     let wasm_bytes = fs::read(&wasm_path)
         .with_context(|| format!("Failed to read WASM file: {:?}", wasm_path))?;
+    super::policy::check_module(&wasm_bytes, &wasm_path)?;
     
-    // Create WASI environment
-    let mut wasi_state = WasiState::new("sentientos-matrixbox");
+    // Configure the WASI environment
+    let mut wasi_state = WasiEnv::builder("sentientos-matrixbox");
     
     // Set environment variables
     for env_var in &container.metadata.environment {
@@ -112,58 +144,368 @@ pub fn start_container(id: &ContainerId) -> Result<()> {
         }
     }
     
-    // Add standard directories
-    wasi_state = wasi_state
-        .preopen_dir(container_path, "/")?
-        .preopen_dir(PathBuf::from(constants::ROOT_DIR).join(".runtime"), "/runtime")?;
+    // Add standard directories and container-specific permissions
+    for preopen in preopened_dirs(container, container_path) {
+        wasi_state = wasi_state.map_dir(&preopen.guest_path, preopen.host_path)?;
+    }
     
-    // Add container-specific permissions
-    for path in &container.permissions.filesystem {
-        let fs_path = PathBuf::from(constants::ROOT_DIR).join(path);
-        if fs_path.exists() {
-            let mount_point = format!("/{}", path);
-            wasi_state = wasi_state.preopen_dir(fs_path, mount_point)?;
-        }
+    // Tell the container which descriptors its forwarded ports arrive on
+    let ports = &container.metadata.ports;
+    if !ports.is_empty() {
+        let (key, value) = network::listen_fds_env(ports);
+        wasi_state = wasi_state.env(key, value);
     }
     
-    // Create the WASI environment
-    let wasi_env = wasi_state.finalize()?;
+    // Limit CPU and processes before anything runs; memory, fuel and time are bounded by the store
+    let resource_limits = limits::effective(container.metadata.resource_limits)?;
+    if let Some(resource_limits) = &resource_limits {
+        limits::apply(id, resource_limits)?;
+    }
     
     // Create the Wasmer store and compile module
-    let mut store = Store::default();
+    let mut store = limits::store(resource_limits.as_ref());
     let module = Module::new(&store, wasm_bytes)
         .context("Failed to compile WASM module")?;
     
-    // Create import object for WASI
-    let import_object = wasi_env.import_object(&mut store, &module)?;
+    // Create the WASI environment
+    let mut wasi_env = wasi_state.finalize(&mut store)?;
+    
+    // Create import object for WASI, with socket calls served by the runtime
+    let mut import_object = wasi_env.import_object(&mut store, &module)?;
+    let host_env = module.imports()
+        .any(|import| import.module() == super::host::ABI_MODULE)
+        .then(|| super::host::register(&mut store, &mut import_object, id));
+    let net_env = if ports.is_empty() {
+        None
+    } else {
+        let net_env = network::register(&mut store, &mut import_object, id);
+        network::attach(id, ports)?;
+        Some(net_env)
+    };
     
     // Instantiate module
     let instance = Instance::new(&mut store, &module, &import_object)
-        .context("Failed to instantiate WASM module")?;
+        .context("Failed to instantiate WASM module")
+        .and_then(|instance| {
+            wasi_env.initialize(&mut store, instance.clone())?;
+            if let Some(host_env) = &host_env {
+                super::host::attach(&mut store, host_env, &instance)?;
+            }
+            if let Some(net_env) = &net_env {
+                network::attach_memory(&mut store, net_env, &instance)?;
+            }
+            Ok(instance)
+        });
+    let instance = match instance {
+        Ok(instance) => instance,
+        Err(e) => {
+            if net_env.is_some() {
+                if let Err(detach_error) = network::detach(id) {
+                    warn!("Failed to detach network of container {}: {:#}", id, detach_error);
+                }
+            }
+            return Err(e);
+        }
+    };
     
     // Create running container
-    let running_container = RunningContainer {
+    Ok(RunningContainer {
         id: id.clone(),
         store,
         module,
         instance,
         wasi_env,
         memory_snapshots: Vec::new(),
-    };
+        paused: false,
+        resource_limits,
+    })
+}
+
+/// Directories preopened for a container, in file descriptor order
+fn preopened_dirs(container: &Container, container_path: &Path) -> Vec<CheckpointFd> {
+    let mut dirs = vec![
+        (container_path.to_path_buf(), "/".to_string()),
+        (constants::root_dir().join(".runtime"), "/runtime".to_string()),
+    ];
+    for path in &container.permissions.filesystem {
+        let fs_path = constants::root_dir().join(path);
+        if fs_path.exists() {
+            dirs.push((fs_path, format!("/{}", path)));
+        }
+    }
     
-    // Add to running containers
-    {
-        let mut running_containers = RUNNING_CONTAINERS.lock().unwrap();
-        running_containers.insert(id.clone(), running_container);
+    // WASI numbers preopens after stdin, stdout and stderr
+    dirs.into_iter()
+        .enumerate()
+        .map(|(i, (host_path, guest_path))| CheckpointFd { fd: 3 + i as u32, guest_path, host_path })
+        .collect()
+}
+
+/// Saved state of a running container, in `checkpoint.json`
+///
+/// Checkpoints are taken between calls: a call holds the runtime lock for
+/// its whole duration, so when a checkpoint gets the lock no guest code is
+/// running and the WASM stack is empty. Execution resumes at that boundary,
+/// with the next call into the instance. The linear memory is stored next
+/// to this file in `memory.bin`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    /// Container the checkpoint was taken of
+    pub container_id: ContainerId,
+    
+    /// Container name
+    pub container_name: String,
+    
+    /// Container directory the module is loaded from again on restore
+    pub container_path: PathBuf,
+    
+    /// When the checkpoint was taken (RFC 3339)
+    pub created_at: String,
+    
+    /// BLAKE3 hash (hex) of the module, which must not change before a restore
+    pub module_hash: String,
+    
+    /// Size of the linear memory, in bytes
+    pub memory_bytes: u64,
+    
+    /// BLAKE3 hash (hex) of `memory.bin`
+    pub memory_hash: String,
+    
+    /// Exported mutable globals by name
+    pub globals: Vec<CheckpointGlobal>,
+    
+    /// Open file descriptors: the preopened directories
+    pub fds: Vec<CheckpointFd>,
+    
+    /// Whether the container was paused
+    pub paused: bool,
+}
+
+/// An exported mutable global in a checkpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckpointGlobal {
+    /// Export name
+    pub name: String,
+    
+    /// Value
+    pub value: CheckpointValue,
+}
+
+/// A WASM value in a checkpoint; floats are kept as their bits
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "lowercase")]
+pub enum CheckpointValue {
+    /// 32-bit integer
+    I32(i32),
+    
+    /// 64-bit integer
+    I64(i64),
+    
+    /// 32-bit float
+    F32(u32),
+    
+    /// 64-bit float
+    F64(u64),
+}
+
+impl CheckpointValue {
+    /// Checkpoint value of a WASM value, `None` for reference and vector types
+    fn from_value(value: &wasmer::Value) -> Option<Self> {
+        match value {
+            wasmer::Value::I32(v) => Some(Self::I32(*v)),
+            wasmer::Value::I64(v) => Some(Self::I64(*v)),
+            wasmer::Value::F32(v) => Some(Self::F32(v.to_bits())),
+            wasmer::Value::F64(v) => Some(Self::F64(v.to_bits())),
+            _ => None,
+        }
     }
     
-    // Update container status
-    registry::update_container_status(id, ContainerStatus::Running)?;
+    /// WASM value of a checkpoint value
+    fn to_value(self) -> wasmer::Value {
+        match self {
+            Self::I32(v) => wasmer::Value::I32(v),
+            Self::I64(v) => wasmer::Value::I64(v),
+            Self::F32(v) => wasmer::Value::F32(f32::from_bits(v)),
+            Self::F64(v) => wasmer::Value::F64(f64::from_bits(v)),
+        }
+    }
+}
+
+/// A file descriptor open in a checkpointed container
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckpointFd {
+    /// Descriptor number as the guest sees it
+    pub fd: u32,
     
-    info!("Container started: {}", id);
+    /// Path the guest opened it at
+    pub guest_path: String,
+    
+    /// Host directory behind it
+    pub host_path: PathBuf,
+}
+
+/// Save a running container's memory, globals and descriptors to `checkpoint_dir`
+///
+/// Files the guest opened itself are not captured; only the preopened
+/// directories are, and they are opened again on restore.
+pub fn checkpoint_container(id: &ContainerId, checkpoint_dir: &Path) -> Result<()> {
+    info!("Checkpointing container {} to {:?}", id, checkpoint_dir);
+    
+    let container = registry::get_container(id)?;
+    let container_path = container.path.clone()
+        .ok_or_else(|| anyhow::anyhow!("Container has no path"))?;
+    let module_hash = hash_file(&container_path.join("main.wasm"))?;
+    
+    let mut running_containers = RUNNING_CONTAINERS.lock().unwrap();
+    let running = running_containers.get_mut(id)
+        .ok_or_else(|| anyhow::anyhow!("Container is not running: {}", id))?;
+    
+    let memory = running.instance
+        .exports
+        .get_memory("memory")
+        .map_err(|_| anyhow::anyhow!("Memory not exported by WASM module"))?;
+    let memory_data = memory.view(&running.store.as_store_ref()).data().to_vec();
+    
+    let mut globals = Vec::new();
+    for (name, global) in running.instance.exports.iter().globals() {
+        if global.ty(&running.store).mutability != Mutability::Var {
+            continue;
+        }
+        match CheckpointValue::from_value(&global.get(&mut running.store)) {
+            Some(value) => globals.push(CheckpointGlobal { name: name.clone(), value }),
+            None => warn!("Global {} of container {} has an unsupported type; not checkpointed", name, id),
+        }
+    }
+    
+    let checkpoint = Checkpoint {
+        container_id: id.clone(),
+        container_name: container.name.clone(),
+        container_path: container_path.clone(),
+        created_at: chrono::Utc::now().to_rfc3339(),
+        module_hash,
+        memory_bytes: memory_data.len() as u64,
+        memory_hash: blake3::hash(&memory_data).to_hex().to_string(),
+        globals,
+        fds: preopened_dirs(&container, &container_path),
+        paused: running.paused,
+    };
+    
+    fs::create_dir_all(checkpoint_dir)
+        .with_context(|| format!("Failed to create checkpoint directory {:?}", checkpoint_dir))?;
+    fs::write(checkpoint_dir.join(CHECKPOINT_MEMORY_FILE), &memory_data)?;
+    fs::write(checkpoint_dir.join(CHECKPOINT_FILE), serde_json::to_string_pretty(&checkpoint)?)?;
+    
+    info!("Checkpointed container {}: {} bytes of memory, {} globals",
+          id, checkpoint.memory_bytes, checkpoint.globals.len());
     Ok(())
 }
 
+/// Start a new container from a checkpoint, returning its ID
+///
+/// The module is instantiated again from the checkpointed container's
+/// directory, its memory and globals are overwritten with the saved ones,
+/// and it resumes with the next call, as the original would have.
+pub fn restore_container(checkpoint_dir: &Path) -> Result<ContainerId> {
+    info!("Restoring container from checkpoint {:?}", checkpoint_dir);
+    
+    let checkpoint: Checkpoint = serde_json::from_str(&fs::read_to_string(checkpoint_dir.join(CHECKPOINT_FILE))?)
+        .with_context(|| format!("Invalid checkpoint in {:?}", checkpoint_dir))?;
+    let memory_data = fs::read(checkpoint_dir.join(CHECKPOINT_MEMORY_FILE))?;
+    if memory_data.len() as u64 != checkpoint.memory_bytes
+        || blake3::hash(&memory_data).to_hex().to_string() != checkpoint.memory_hash {
+        anyhow::bail!("Checkpoint memory in {:?} is corrupted", checkpoint_dir);
+    }
+    
+    let container = super::container::load_container(&checkpoint.container_path.to_string_lossy())?;
+    if hash_file(&checkpoint.container_path.join("main.wasm"))? != checkpoint.module_hash {
+        anyhow::bail!("Module of container {} changed since the checkpoint was taken", checkpoint.container_name);
+    }
+    
+    // The instance needs the ID; a restore that fails leaves no registration behind
+    let id = registry::register_container(&container, &[])?;
+    let running = match instantiate_checkpoint(&id, &container, &checkpoint, &memory_data) {
+        Ok(running) => running,
+        Err(e) => {
+            if let Err(unregister_error) = registry::unregister_container(&id) {
+                warn!("Failed to unregister container {}: {:#}", id, unregister_error);
+            }
+            return Err(e);
+        }
+    };
+    
+    RUNNING_CONTAINERS.lock().unwrap().insert(id.clone(), running);
+    
+    let status = if checkpoint.paused { ContainerStatus::Paused } else { ContainerStatus::Running };
+    registry::update_container_status(&id, status)?;
+    
+    crate::logs::ship::ship_audit("matrixbox.restore", &format!(
+        "Restored container {} as {} from checkpoint of {}", checkpoint.container_name, id, checkpoint.container_id));
+    info!("Restored container {} as {}", checkpoint.container_name, id);
+    Ok(id)
+}
+
+/// Instantiate a checkpointed container's module and load the saved memory and globals into it
+fn instantiate_checkpoint(
+    id: &ContainerId,
+    container: &Container,
+    checkpoint: &Checkpoint,
+    memory_data: &[u8],
+) -> Result<RunningContainer> {
+    let mut running = instantiate(id, container)?;
+    
+    let fds = preopened_dirs(container, &checkpoint.container_path);
+    if fds != checkpoint.fds {
+        warn!("Preopened directories of {} differ from the checkpoint; descriptors may be renumbered",
+              checkpoint.container_name);
+    }
+    
+    // Grow the fresh memory to the saved size, then overwrite it
+    let memory = running.instance
+        .exports
+        .get_memory("memory")
+        .map_err(|_| anyhow::anyhow!("Memory not exported by WASM module"))?
+        .clone();
+    let current = memory.view(&running.store.as_store_ref()).data_size();
+    if checkpoint.memory_bytes > current {
+        let delta = (checkpoint.memory_bytes - current) / WASM_PAGE_SIZE;
+        memory.grow(&mut running.store, Pages(delta as u32))
+            .with_context(|| format!("Failed to grow memory to {} bytes", checkpoint.memory_bytes))?;
+    }
+    memory.view(&running.store.as_store_ref()).write(0, memory_data)
+        .context("Failed to restore linear memory")?;
+    
+    for saved in &checkpoint.globals {
+        let global = running.instance.exports.get_global(&saved.name)
+            .with_context(|| format!("Checkpointed global {} is not exported", saved.name))?
+            .clone();
+        global.set(&mut running.store, saved.value.to_value())
+            .map_err(|e| anyhow::anyhow!("Failed to restore global {}: {}", saved.name, e))?;
+    }
+    
+    running.paused = checkpoint.paused;
+    Ok(running)
+}
+
+/// Checkpoint every running container into `dir/<container_id>`
+///
+/// Returns the IDs checkpointed; containers that fail are logged and skipped.
+pub fn checkpoint_all(dir: &Path) -> Vec<ContainerId> {
+    let mut checkpointed = Vec::new();
+    for id in running_container_ids() {
+        match checkpoint_container(&id, &dir.join(&id)) {
+            Ok(()) => checkpointed.push(id),
+            Err(e) => warn!("Failed to checkpoint container {}: {:#}", id, e),
+        }
+    }
+    checkpointed
+}
+
+/// BLAKE3 hash (hex) of a file
+fn hash_file(path: &Path) -> Result<String> {
+    let data = fs::read(path).with_context(|| format!("Failed to read {:?}", path))?;
+    Ok(blake3::hash(&data).to_hex().to_string())
+}
+
 /// Stop a container
 pub fn stop_container(id: &ContainerId) -> Result<()> {
     info!("Stopping container: {}", id);
@@ -179,6 +521,11 @@ fn stop_container_internal(
 ) -> Result<()> {
     // Remove container from running containers
     if running_containers.remove(id).is_some() {
+        limits::release(id);
+        if let Err(e) = network::detach(id) {
+            warn!("Failed to detach network of container {}: {:#}", id, e);
+        }
+        
         // Update container status
         registry::update_container_status(id, ContainerStatus::Exited(0))?;
         
@@ -189,12 +536,77 @@ fn stop_container_internal(
     }
 }
 
+/// Pause a running container
+///
+/// Calls into an instance hold the runtime lock, so acquiring it here lets
+/// any in-flight call complete first. After that no new calls are scheduled
+/// until the container is resumed.
+pub fn pause_container(id: &ContainerId) -> Result<()> {
+    let container = registry::get_container(id)?;
+    if !container.metadata.pausable {
+        anyhow::bail!("Container cannot be paused: {}", id);
+    }
+    
+    {
+        let mut running_containers = RUNNING_CONTAINERS.lock().unwrap();
+        let running = running_containers.get_mut(id)
+            .ok_or_else(|| anyhow::anyhow!("Container is not running: {}", id))?;
+        
+        if running.paused {
+            return Ok(());
+        }
+        running.paused = true;
+    }
+    
+    registry::update_container_status(id, ContainerStatus::Paused)?;
+    info!("Container paused: {}", id);
+    Ok(())
+}
+
+/// Resume a paused container
+pub fn resume_container(id: &ContainerId) -> Result<()> {
+    {
+        let mut running_containers = RUNNING_CONTAINERS.lock().unwrap();
+        let running = running_containers.get_mut(id)
+            .ok_or_else(|| anyhow::anyhow!("Container is not running: {}", id))?;
+        
+        if !running.paused {
+            return Ok(());
+        }
+        running.paused = false;
+    }
+    
+    registry::update_container_status(id, ContainerStatus::Running)?;
+    info!("Container resumed: {}", id);
+    Ok(())
+}
+
+/// Check if a container is paused
+pub fn is_container_paused(id: &ContainerId) -> Result<bool> {
+    let running_containers = RUNNING_CONTAINERS.lock().unwrap();
+    Ok(running_containers.get(id).map_or(false, |c| c.paused))
+}
+
+/// IDs of all running containers, paused or not
+pub fn running_container_ids() -> Vec<ContainerId> {
+    let running_containers = RUNNING_CONTAINERS.lock().unwrap();
+    running_containers.keys().cloned().collect()
+}
+
 /// Check if a container is running
 pub fn is_container_running(id: &ContainerId) -> Result<bool> {
     let running_containers = RUNNING_CONTAINERS.lock().unwrap();
     Ok(running_containers.contains_key(id))
 }
 
+/// Whether a running container's module exports a function
+pub fn has_function(id: &ContainerId, function_name: &str) -> Result<bool> {
+    let running_containers = RUNNING_CONTAINERS.lock().unwrap();
+    let container = running_containers.get(id)
+        .ok_or_else(|| anyhow::anyhow!("Container is not running: {}", id))?;
+    Ok(container.instance.exports.get_function(function_name).is_ok())
+}
+
 /// Take a memory snapshot for ZK verification
 pub fn take_memory_snapshot(id: &ContainerId) -> Result<()> {
     info!("Taking memory snapshot for container: {}", id);
@@ -262,6 +674,10 @@ pub fn execute_function(id: &ContainerId, function_name: &str, args: &[wasmer::V
     let mut running_containers = RUNNING_CONTAINERS.lock().unwrap();
     
     if let Some(container) = running_containers.get_mut(id) {
+        if container.paused {
+            anyhow::bail!("Container is paused: {}", id);
+        }
+        
         // Get the function from the instance
         let function = container.instance
             .exports
@@ -271,9 +687,42 @@ pub fn execute_function(id: &ContainerId, function_name: &str, args: &[wasmer::V
         // Take pre-execution memory snapshot for verification
         take_memory_snapshot_internal(container)?;
         
-        // Call the function
-        let results = function.call(&mut container.store.as_store_ref(), args)
-            .with_context(|| format!("Failed to execute function '{}'", function_name))?;
+        // Call the function, inside the container's cgroup if it has one
+        let budget = limits::CallBudget::start(&mut container.store, &container.instance, container.resource_limits.as_ref())?;
+        let attachment = limits::attach(id)?;
+        let call = function.call(&mut container.store.as_store_ref(), args);
+        drop(attachment);
+        let exceeded = match &call {
+            Err(_) => budget.exceeded(id, &mut container.store, &container.instance),
+            Ok(_) => None,
+        };
+        drop(budget);
+        
+        let results = match call {
+            Ok(results) => results,
+            Err(e) => {
+                // A call that ran out of fuel or time kills the container
+                if let Some(exceeded) = exceeded {
+                    running_containers.remove(id);
+                    drop(running_containers);
+                    limits::resource_kill(&exceeded);
+                    return Err(exceeded.into());
+                }
+                
+                // A trap with the memory grown to its limit is an out-of-memory kill
+                if let Some(resource_limits) = container.resource_limits {
+                    let memory_bytes = memory_bytes(container);
+                    if limits::memory_exhausted(memory_bytes, &resource_limits) {
+                        running_containers.remove(id);
+                        // The OOM snapshot checkpoints running containers, which takes this lock
+                        drop(running_containers);
+                        limits::oom_kill(id, memory_bytes, &resource_limits);
+                        anyhow::bail!("Container {} was killed for exceeding its memory limit", id);
+                    }
+                }
+                return Err(e).with_context(|| format!("Failed to execute function '{}'", function_name));
+            }
+        };
         
         // Take post-execution memory snapshot for verification
         take_memory_snapshot_internal(container)?;
@@ -285,6 +734,15 @@ pub fn execute_function(id: &ContainerId, function_name: &str, args: &[wasmer::V
     }
 }
 
+/// Current size of a container's exported memory, 0 if it exports none
+fn memory_bytes(container: &RunningContainer) -> u64 {
+    container.instance
+        .exports
+        .get_memory("memory")
+        .map(|memory| memory.view(&container.store.as_store_ref()).data_size())
+        .unwrap_or(0)
+}
+
 /// Internal function to take memory snapshot
 fn take_memory_snapshot_internal(container: &mut RunningContainer) -> Result<()> {
     // Get the memory from the instance
@@ -302,3 +760,194 @@ fn take_memory_snapshot_internal(container: &mut RunningContainer) -> Result<()>
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::container;
+    
+    /// Module whose `bump` increments the mutable global `count`, stores its
+    /// low byte at `COUNT_OFFSET` in the exported memory and returns it
+    const COUNTER_WASM: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, 0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7f, 0x03,
+        0x02, 0x01, 0x00, 0x05, 0x03, 0x01, 0x00, 0x01, 0x06, 0x06, 0x01, 0x7f, 0x01, 0x41, 0x00, 0x0b,
+        0x07, 0x19, 0x03, 0x06, 0x6d, 0x65, 0x6d, 0x6f, 0x72, 0x79, 0x02, 0x00, 0x05, 0x63, 0x6f, 0x75,
+        0x6e, 0x74, 0x03, 0x00, 0x04, 0x62, 0x75, 0x6d, 0x70, 0x00, 0x00, 0x0a, 0x14, 0x01, 0x12, 0x00,
+        0x23, 0x00, 0x41, 0x01, 0x6a, 0x24, 0x00, 0x41, 0x10, 0x23, 0x00, 0x3a, 0x00, 0x00, 0x23, 0x00,
+        0x0b,
+    ];
+    const COUNT_OFFSET: usize = 16;
+    
+    /// The burn app, which logs through the host ABI, keeps its calculation
+    /// at `BURN_RESULT_OFFSET` and counts its runs in the global `runs`
+    const BURN_APP_WAT: &str = include_str!("../../tso_burn_output/burn_app.wat");
+    const BURN_RESULT_OFFSET: usize = 200;
+    
+    /// Create, register and start a container running the counter module
+    fn counter_container(name: &str) -> ContainerId {
+        let container = container::create_container(name, "bump").unwrap();
+        fs::write(container.path.as_ref().unwrap().join("main.wasm"), COUNTER_WASM).unwrap();
+        let id = registry::register_container(&container, &[]).unwrap();
+        start_container(&id).unwrap();
+        id
+    }
+    
+    fn bump(id: &ContainerId) -> i32 {
+        execute_function(id, "bump", &[]).unwrap()[0].i32().unwrap()
+    }
+    
+    fn checkpoint_dir(name: &str) -> PathBuf {
+        constants::root_dir().join("checkpoint-tests").join(name)
+    }
+    
+    fn read_checkpoint(dir: &Path) -> Checkpoint {
+        serde_json::from_str(&fs::read_to_string(dir.join(CHECKPOINT_FILE)).unwrap()).unwrap()
+    }
+    
+    #[test]
+    fn checkpoint_and_restore_round_trip_memory_and_globals() {
+        let id = counter_container("checkpoint-roundtrip");
+        assert_eq!((bump(&id), bump(&id)), (1, 2));
+        
+        let dir = checkpoint_dir("roundtrip");
+        checkpoint_container(&id, &dir).unwrap();
+        stop_container(&id).unwrap();
+        
+        let checkpoint = read_checkpoint(&dir);
+        assert_eq!(checkpoint.memory_bytes, WASM_PAGE_SIZE);
+        assert_eq!(checkpoint.globals.len(), 1);
+        assert_eq!((checkpoint.globals[0].name.as_str(), checkpoint.globals[0].value), ("count", CheckpointValue::I32(2)));
+        assert_eq!((checkpoint.fds[0].fd, checkpoint.fds[0].guest_path.as_str()), (3, "/"));
+        assert!(!checkpoint.paused);
+        assert_eq!(fs::read(dir.join(CHECKPOINT_MEMORY_FILE)).unwrap()[COUNT_OFFSET], 2);
+        
+        // The restored instance carries on where the original stopped
+        let restored = restore_container(&dir).unwrap();
+        assert_ne!(restored, id);
+        assert!(is_container_running(&restored).unwrap());
+        assert_eq!(bump(&restored), 3);
+        stop_container(&restored).unwrap();
+    }
+    
+    #[test]
+    fn burn_app_resumes_from_its_checkpoint() {
+        let container = container::create_container("checkpoint-burn-app", "main").unwrap();
+        let wasm = wasmer::wat2wasm(BURN_APP_WAT.as_bytes()).unwrap();
+        fs::write(container.path.as_ref().unwrap().join("main.wasm"), &wasm).unwrap();
+        let id = registry::register_container(&container, &[]).unwrap();
+        start_container(&id).unwrap();
+        let run = |id: &ContainerId| execute_function(id, "main", &[]).unwrap()[0].i32().unwrap();
+        assert_eq!((run(&id), run(&id)), (1, 2));
+        
+        let dir = checkpoint_dir("burn-app");
+        checkpoint_container(&id, &dir).unwrap();
+        stop_container(&id).unwrap();
+        
+        let checkpoint = read_checkpoint(&dir);
+        assert_eq!(checkpoint.globals.len(), 1);
+        assert_eq!((checkpoint.globals[0].name.as_str(), checkpoint.globals[0].value), ("runs", CheckpointValue::I32(2)));
+        let memory = fs::read(dir.join(CHECKPOINT_MEMORY_FILE)).unwrap();
+        assert_eq!(memory[BURN_RESULT_OFFSET..BURN_RESULT_OFFSET + 4], 427i32.to_le_bytes());
+        
+        let restored = restore_container(&dir).unwrap();
+        assert_eq!(run(&restored), 3);
+        stop_container(&restored).unwrap();
+    }
+    
+    #[test]
+    fn failed_restores_leave_no_container_registered() {
+        let id = counter_container("checkpoint-unregistered");
+        bump(&id);
+        let dir = checkpoint_dir("unregistered");
+        checkpoint_container(&id, &dir).unwrap();
+        stop_container(&id).unwrap();
+        
+        // A global the module does not export fails the restore after instantiation
+        let mut checkpoint = read_checkpoint(&dir);
+        checkpoint.globals[0].name = "missing".to_string();
+        fs::write(dir.join(CHECKPOINT_FILE), serde_json::to_string(&checkpoint).unwrap()).unwrap();
+        
+        let error = restore_container(&dir).unwrap_err();
+        assert!(error.to_string().contains("not exported"), "{:#}", error);
+        let registered: Vec<ContainerId> = registry::list_containers().unwrap().into_iter()
+            .filter(|info| info.name == "checkpoint-unregistered")
+            .map(|info| info.id)
+            .collect();
+        assert_eq!(registered, [id]);
+    }
+    
+    #[test]
+    fn paused_containers_are_restored_paused() {
+        let id = counter_container("checkpoint-paused");
+        bump(&id);
+        pause_container(&id).unwrap();
+        
+        let dir = checkpoint_dir("paused");
+        checkpoint_container(&id, &dir).unwrap();
+        stop_container(&id).unwrap();
+        
+        let restored = restore_container(&dir).unwrap();
+        assert!(is_container_paused(&restored).unwrap());
+        assert!(execute_function(&restored, "bump", &[]).is_err());
+        resume_container(&restored).unwrap();
+        assert_eq!(bump(&restored), 2);
+        stop_container(&restored).unwrap();
+    }
+    
+    #[test]
+    fn restore_refuses_corrupted_memory_and_changed_modules() {
+        let id = counter_container("checkpoint-refused");
+        bump(&id);
+        let dir = checkpoint_dir("refused");
+        checkpoint_container(&id, &dir).unwrap();
+        stop_container(&id).unwrap();
+        
+        let memory_path = dir.join(CHECKPOINT_MEMORY_FILE);
+        let memory = fs::read(&memory_path).unwrap();
+        let mut corrupted = memory.clone();
+        corrupted[COUNT_OFFSET] = 0x7f;
+        fs::write(&memory_path, &corrupted).unwrap();
+        let error = restore_container(&dir).unwrap_err();
+        assert!(error.to_string().contains("corrupted"), "{:#}", error);
+        fs::write(&memory_path, &memory).unwrap();
+        
+        // Append an empty custom section named "x"
+        let module_path = read_checkpoint(&dir).container_path.join("main.wasm");
+        let mut changed = COUNTER_WASM.to_vec();
+        changed.extend_from_slice(&[0x00, 0x02, 0x01, b'x']);
+        fs::write(&module_path, &changed).unwrap();
+        let error = restore_container(&dir).unwrap_err();
+        assert!(error.to_string().contains("changed since the checkpoint"), "{:#}", error);
+    }
+    
+    #[test]
+    fn checkpoints_need_a_running_container() {
+        let container = container::create_container("checkpoint-stopped", "bump").unwrap();
+        fs::write(container.path.as_ref().unwrap().join("main.wasm"), COUNTER_WASM).unwrap();
+        let id = registry::register_container(&container, &[]).unwrap();
+        
+        let dir = checkpoint_dir("stopped");
+        assert!(checkpoint_container(&id, &dir).is_err());
+        assert!(!dir.join(CHECKPOINT_FILE).exists());
+    }
+    
+    #[test]
+    fn checkpoint_values_keep_float_bits() {
+        let values = [
+            wasmer::Value::I32(-7),
+            wasmer::Value::I64(i64::MIN),
+            wasmer::Value::F32(f32::from_bits(0x7fc0_0001)),
+            wasmer::Value::F64(-0.0),
+        ];
+        for value in values {
+            let saved = CheckpointValue::from_value(&value).unwrap();
+            let json = serde_json::to_string(&saved).unwrap();
+            let loaded: CheckpointValue = serde_json::from_str(&json).unwrap();
+            assert_eq!(loaded, saved, "{}", json);
+            
+            let restored = loaded.to_value();
+            assert_eq!(CheckpointValue::from_value(&restored), Some(saved));
+        }
+        assert_eq!(serde_json::to_string(&CheckpointValue::I32(5)).unwrap(), r#"{"type":"i32","value":5}"#);
+    }
+}
@@ -0,0 +1,859 @@
+// SentientOS MatrixBox Data Sync
+// Differential sync of container files and kv entries between paired peers
+
+use anyhow::{Result, Context};
+use tracing::{info, debug, warn};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path, PathBuf};
+use std::fs;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use crate::core::{constants, lock};
+use crate::gossip::protocol::{self, MessageType};
+use super::container::{Container, ContainerId, ContainerStatus};
+
+// Constants
+const SYNC_DIR: &str = ".matrixbox/sync";
+const STATE_FILE: &str = "state.json";
+const FILE_PREFIX: &str = "files/";
+const KV_PREFIX: &str = "kv/";
+const CONFLICT_MARKER: &str = ".sync-conflict-";
+const FRAME_DATA_BYTES: usize = 56 * 1024;
+const TRANSFER_TIMEOUT_SECS: u64 = 120;
+const MAX_TRANSFERS: usize = 64;
+const SYNC_TIMEOUT_SECS: u64 = 30;
+
+/// Capability peers advertise and containers are granted for data sync
+pub const CAPABILITY: &str = "data-sync";
+
+// Partly received transfers, by sending peer and transfer ID
+static TRANSFERS: Mutex<BTreeMap<(String, String), Transfer>> = Mutex::new(BTreeMap::new());
+
+/// Data a container shares with its paired peers, declared in meta.yaml
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    /// Files or directories to sync, relative to the container directory
+    pub paths: Vec<String>,
+    
+    /// Whether the container's kv store is synced
+    pub kv: bool,
+    
+    /// Peers the container's data is paired with
+    pub peers: Vec<String>,
+}
+
+/// Version of one synced entry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Leaf {
+    /// Entry path: `files/<relative path>` or `kv/<hex key>`
+    pub path: String,
+    
+    /// Content hash, `None` once the entry is deleted
+    pub hash: Option<String>,
+    
+    /// When the entry last changed (milliseconds since the epoch)
+    pub modified_at: u64,
+}
+
+/// A concurrent edit that was resolved by last-writer-wins
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conflict {
+    /// Entry that was edited on both sides
+    pub path: String,
+    
+    /// Peer the entry was synced with
+    pub peer: String,
+    
+    /// Where the losing local version was kept, if it still existed
+    pub copy: Option<String>,
+    
+    /// When the conflict was resolved
+    pub resolved_at: u64,
+}
+
+/// Sync status of a container with one paired peer
+#[derive(Debug, Clone)]
+pub struct PeerSyncStatus {
+    /// Paired peer
+    pub peer: String,
+    
+    /// Why sync with the peer can't run, if it can't
+    pub blocked: Option<String>,
+    
+    /// When the last sync completed
+    pub last_synced: Option<u64>,
+    
+    /// Local entries changed since the last sync
+    pub pending: usize,
+}
+
+/// Sync status of a container
+#[derive(Debug, Clone)]
+pub struct SyncStatus {
+    /// Status per paired peer
+    pub peers: Vec<PeerSyncStatus>,
+    
+    /// Conflicts resolved so far
+    pub conflicts: Vec<Conflict>,
+}
+
+/// Sync bookkeeping of a container, in `.matrixbox/sync/<id>/state.json`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct SyncState {
+    /// Local entries as of the last scan, including deletions
+    index: BTreeMap<String, Leaf>,
+    
+    /// Progress with each peer
+    peers: BTreeMap<String, PeerState>,
+    
+    /// Conflicts resolved so far
+    conflicts: Vec<Conflict>,
+}
+
+/// Progress of syncing with one peer
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct PeerState {
+    /// When the last sync completed
+    last_synced: Option<u64>,
+    
+    /// Hash of every entry as both sides had it after the last sync
+    base: BTreeMap<String, Option<String>>,
+}
+
+/// Sync message, sent as one or more frames
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum SyncMessage {
+    /// Sync request with the initiator's Merkle root and entry versions
+    Offer { container: String, root: String, leaves: Vec<Leaf> },
+    
+    /// Entries the receiver lacks and the entries the sender wants back
+    Entries { container: String, entries: Vec<EntryData>, wanted: Vec<String> },
+}
+
+/// An entry's version with its content; no content for deletions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EntryData {
+    leaf: Leaf,
+    content: Option<Vec<u8>>,
+}
+
+/// Gossip payload carrying part of a sync message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Frame {
+    transfer: String,
+    index: u32,
+    count: u32,
+    data: Vec<u8>,
+}
+
+/// A sync message being reassembled
+struct Transfer {
+    parts: Vec<Option<Vec<u8>>>,
+    started_at: u64,
+}
+
+/// Sync a container's data with the best paired peer, or with `peer_id` if given
+///
+/// Paired peers are tried in selection order until one completes the
+/// exchange. Returns the ID of the peer synced with.
+pub fn sync_with(container_id: &ContainerId, peer_id: Option<&str>) -> Result<String> {
+    let container = super::registry::get_container(container_id)?;
+    let config = container.metadata.sync.clone()
+        .ok_or_else(|| anyhow::anyhow!("Container {} declares no syncable data", container.name))?;
+    check_quiescent(container_id)?;
+    
+    let candidates: Vec<_> = crate::gossip::select::candidates(Some(&config.peers))?
+        .into_iter()
+        .filter(|c| check_enabled(&container, &c.id).is_ok())
+        .collect();
+    if candidates.is_empty() && peer_id.is_none() {
+        anyhow::bail!("No paired peer of container {} is reachable and enabled for data sync", container.name);
+    }
+    
+    crate::gossip::select::with_failover("data sync", candidates, peer_id, |peer| {
+        sync_once(container_id, &container, &peer.id)?;
+        // Entries flow both ways between the daemons, so only the outcome is recorded
+        Ok((peer.id.clone(), 0))
+    })
+}
+
+/// Sync a container's data with one peer and wait for it
+///
+/// Sends the container's entry versions; the peer answers with what changed
+/// on its side and what it wants, and the exchange completes between the
+/// two daemons.
+fn sync_once(container_id: &ContainerId, container: &Container, peer_id: &str) -> Result<()> {
+    let config = check_enabled(container, peer_id)?;
+    
+    let leaves = with_state(container_id, |state| {
+        scan(container_id, container, &config, state)?;
+        Ok(state.index.values().cloned().collect::<Vec<_>>())
+    })?;
+    let root = merkle_root(&leaves);
+    let started = now();
+    
+    send(peer_id, &SyncMessage::Offer { container: container.name.clone(), root, leaves })?;
+    info!("Offered data of container {} to {}", container.name, peer_id);
+    
+    let deadline = SystemTime::now() + Duration::from_secs(SYNC_TIMEOUT_SECS);
+    while SystemTime::now() < deadline {
+        let last_synced = with_state(container_id, |state| {
+            Ok(state.peers.get(peer_id).and_then(|p| p.last_synced))
+        })?;
+        if last_synced.map_or(false, |t| t >= started) {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(250));
+    }
+    anyhow::bail!("Sync of {} with {} did not complete within {}s", container.name, peer_id, SYNC_TIMEOUT_SECS)
+}
+
+/// Sync status of a container, `None` if it declares no syncable data
+///
+/// Pending counts come from a fresh scan while the container is stopped or
+/// paused, and from the last scan while it runs.
+pub fn status(container_id: &ContainerId) -> Result<Option<SyncStatus>> {
+    let container = super::registry::get_container(container_id)?;
+    let config = match &container.metadata.sync {
+        Some(config) => config.clone(),
+        None => return Ok(None),
+    };
+    
+    with_state(container_id, |state| {
+        if check_quiescent(container_id).is_ok() {
+            if let Err(e) = scan(container_id, &container, &config, state) {
+                debug!("Using the last scan of container {}: {}", container.name, e);
+            }
+        }
+        
+        let peers = config.peers.iter()
+            .map(|peer| {
+                let progress = state.peers.get(peer).cloned().unwrap_or_default();
+                PeerSyncStatus {
+                    peer: peer.clone(),
+                    blocked: check_enabled(&container, peer).err().map(|e| e.to_string()),
+                    last_synced: progress.last_synced,
+                    pending: state.index.values()
+                        .filter(|leaf| progress.base.get(&leaf.path).cloned().flatten() != leaf.hash)
+                        .count(),
+                }
+            })
+            .collect();
+        Ok(Some(SyncStatus { peers, conflicts: state.conflicts.clone() }))
+    })
+}
+
+/// Reassemble a sync frame from a peer and handle the completed message
+pub fn handle_frame(source_id: &str, payload: &[u8]) -> Result<()> {
+    if !crate::gossip::is_trusted_peer(source_id) {
+        debug!("Ignoring data sync frame from untrusted peer: {}", source_id);
+        return Ok(());
+    }
+    
+    let frame: Frame = bincode::deserialize(payload).context("Invalid data sync frame")?;
+    if frame.count == 0 || frame.index >= frame.count {
+        anyhow::bail!("Invalid data sync frame {}/{} from {}", frame.index, frame.count, source_id);
+    }
+    
+    let message = {
+        let mut transfers = TRANSFERS.lock().unwrap();
+        let cutoff = now().saturating_sub(TRANSFER_TIMEOUT_SECS);
+        transfers.retain(|_, t| t.started_at >= cutoff);
+        
+        let key = (source_id.to_string(), frame.transfer.clone());
+        if !transfers.contains_key(&key) && transfers.len() >= MAX_TRANSFERS {
+            anyhow::bail!("Too many data sync transfers in progress; dropping frame from {}", source_id);
+        }
+        let transfer = transfers.entry(key.clone()).or_insert_with(|| Transfer {
+            parts: vec![None; frame.count as usize],
+            started_at: now(),
+        });
+        if transfer.parts.len() != frame.count as usize {
+            anyhow::bail!("Data sync frame count changed mid-transfer from {}", source_id);
+        }
+        transfer.parts[frame.index as usize] = Some(frame.data);
+        
+        if transfer.parts.iter().any(Option::is_none) {
+            return Ok(());
+        }
+        let bytes: Vec<u8> = transfers.remove(&key).unwrap().parts.into_iter().flatten().flatten().collect();
+        bincode::deserialize::<SyncMessage>(&bytes).context("Invalid data sync message")?
+    };
+    
+    match message {
+        SyncMessage::Offer { container, root, leaves } => handle_offer(source_id, &container, &root, leaves),
+        SyncMessage::Entries { container, entries, wanted } => handle_entries(source_id, &container, entries, wanted),
+    }
+}
+
+/// Compare an offer with local data, send back what the peer lacks and ask for what we lack
+fn handle_offer(peer_id: &str, name: &str, root: &str, leaves: Vec<Leaf>) -> Result<()> {
+    let (id, container) = find_container(name)?;
+    let config = check_enabled(&container, peer_id)?;
+    check_quiescent(&id)?;
+    let local_node = crate::core::identity::node_id()?;
+    
+    let (entries, wanted) = with_state(&id, |state| {
+        scan(&id, &container, &config, state)?;
+        let local: Vec<Leaf> = state.index.values().cloned().collect();
+        if merkle_root(&local) == root {
+            let progress = state.peers.entry(peer_id.to_string()).or_default();
+            progress.base = live_hashes(&local);
+            progress.last_synced = Some(now());
+            return Ok((Vec::new(), Vec::new()));
+        }
+        
+        let remote: BTreeMap<String, Leaf> = leaves.into_iter().map(|l| (l.path.clone(), l)).collect();
+        let paths: BTreeSet<String> = state.index.keys().chain(remote.keys()).cloned().collect();
+        let mut send = Vec::new();
+        let mut wanted = Vec::new();
+        let mut apply = Vec::new();
+        
+        for path in paths {
+            let ours = state.index.get(&path).cloned().unwrap_or_else(|| absent(&path));
+            let theirs = remote.get(&path).cloned().unwrap_or_else(|| absent(&path));
+            let progress = state.peers.entry(peer_id.to_string()).or_default();
+            let base = progress.base.get(&path).cloned().flatten();
+            
+            if ours.hash == theirs.hash {
+                progress.base.insert(path, ours.hash);
+                continue;
+            }
+            
+            // Last writer wins when both sides changed the entry
+            let take_theirs = match (ours.hash != base, theirs.hash != base) {
+                (false, _) => true,
+                (true, false) => false,
+                (true, true) => (theirs.modified_at, peer_id) > (ours.modified_at, local_node.as_str()),
+            };
+            if !take_theirs {
+                send.push(ours);
+            } else if theirs.hash.is_some() {
+                wanted.push(path);
+            } else {
+                apply.push(EntryData { leaf: theirs, content: None });
+            }
+        }
+        
+        for entry in apply {
+            apply_entry(&id, &container, state, peer_id, entry)?;
+        }
+        let entries = send.into_iter()
+            .map(|leaf| read_entry(&id, &container, leaf))
+            .collect::<Result<Vec<_>>>()?;
+        
+        let progress = state.peers.entry(peer_id.to_string()).or_default();
+        for entry in &entries {
+            progress.base.insert(entry.leaf.path.clone(), entry.leaf.hash.clone());
+        }
+        if wanted.is_empty() {
+            progress.last_synced = Some(now());
+        }
+        Ok((entries, wanted))
+    })?;
+    
+    info!("Sync of container {} with {}: sending {} entries, requesting {}", name, peer_id, entries.len(), wanted.len());
+    send(peer_id, &SyncMessage::Entries { container: name.to_string(), entries, wanted })
+}
+
+/// Apply entries from a peer and send back the entries it asked for
+fn handle_entries(peer_id: &str, name: &str, entries: Vec<EntryData>, wanted: Vec<String>) -> Result<()> {
+    let (id, container) = find_container(name)?;
+    check_enabled(&container, peer_id)?;
+    check_quiescent(&id)?;
+    
+    let reply = with_state(&id, |state| {
+        let received = entries.len();
+        for entry in entries {
+            apply_entry(&id, &container, state, peer_id, entry)?;
+        }
+        
+        let reply = wanted.iter()
+            .filter_map(|path| state.index.get(path).cloned())
+            .map(|leaf| read_entry(&id, &container, leaf))
+            .collect::<Result<Vec<_>>>()?;
+        
+        let progress = state.peers.entry(peer_id.to_string()).or_default();
+        for entry in &reply {
+            progress.base.insert(entry.leaf.path.clone(), entry.leaf.hash.clone());
+        }
+        progress.last_synced = Some(now());
+        debug!("Applied {} entries of container {} from {}", received, name, peer_id);
+        Ok(reply)
+    })?;
+    
+    if !wanted.is_empty() {
+        send(peer_id, &SyncMessage::Entries { container: name.to_string(), entries: reply, wanted: Vec::new() })?;
+    }
+    info!("Synced container {} with {}", name, peer_id);
+    Ok(())
+}
+
+/// Write an entry received from a peer
+///
+/// If the local entry changed since the last sync with the peer, the edits
+/// were concurrent: the local version is kept as a conflict copy.
+fn apply_entry(id: &ContainerId, container: &Container, state: &mut SyncState, peer_id: &str, entry: EntryData) -> Result<()> {
+    let path = entry.leaf.path.clone();
+    let local = state.index.get(&path).and_then(|l| l.hash.clone());
+    let base = state.peers.get(peer_id).and_then(|p| p.base.get(&path).cloned()).flatten();
+    
+    if local != entry.leaf.hash {
+        if let Some(content) = &entry.content {
+            if entry.leaf.hash.as_deref() != Some(blake3::hash(content).to_hex().as_str()) {
+                anyhow::bail!("Entry {} from {} does not match its hash", path, peer_id);
+            }
+        }
+        
+        if local.is_some() && local != base {
+            let copy = keep_conflict_copy(id, container, &path)?;
+            warn!("Concurrent edit of {} in container {}; kept local version as {}", path, container.name, copy);
+            state.conflicts.push(Conflict { path: path.clone(), peer: peer_id.to_string(), copy: Some(copy), resolved_at: now() });
+        } else if local.is_none() && base.is_some() && entry.leaf.hash.is_some() {
+            state.conflicts.push(Conflict { path: path.clone(), peer: peer_id.to_string(), copy: None, resolved_at: now() });
+        }
+        
+        write_entry(id, container, &path, entry.content.as_deref())?;
+    }
+    
+    state.index.insert(path.clone(), entry.leaf.clone());
+    state.peers.entry(peer_id.to_string()).or_default().base.insert(path, entry.leaf.hash);
+    Ok(())
+}
+
+/// Refresh the local index from the container's files and kv store
+fn scan(id: &ContainerId, container: &Container, config: &SyncConfig, state: &mut SyncState) -> Result<()> {
+    let mut current: BTreeMap<String, (String, u64)> = BTreeMap::new();
+    
+    let dir = container_dir(container)?;
+    for path in &config.paths {
+        let relative = safe_relative(path)?;
+        collect_files(&dir, &dir.join(relative), &mut current)?;
+    }
+    if config.kv {
+        let modified_at = now_ms();
+        super::kv::with_store(id, |store| {
+            for (key, value) in store.list(b"") {
+                if !contains(&key, CONFLICT_MARKER.as_bytes()) {
+                    current.insert(format!("{}{}", KV_PREFIX, to_hex(&key)), (hash(&value), modified_at));
+                }
+            }
+            Ok(())
+        })?;
+    }
+    
+    for (path, (hash, modified_at)) in &current {
+        if state.index.get(path).and_then(|l| l.hash.as_ref()) != Some(hash) {
+            state.index.insert(path.clone(), Leaf { path: path.clone(), hash: Some(hash.clone()), modified_at: *modified_at });
+        }
+    }
+    let now_ms = now_ms();
+    for leaf in state.index.values_mut() {
+        if leaf.hash.is_some() && !current.contains_key(&leaf.path) {
+            leaf.hash = None;
+            leaf.modified_at = now_ms;
+        }
+    }
+    Ok(())
+}
+
+/// Add the files below a path to the scan, skipping conflict copies
+fn collect_files(root: &Path, path: &Path, out: &mut BTreeMap<String, (String, u64)>) -> Result<()> {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {:?}", path)),
+    };
+    
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path)?.filter_map(Result::ok) {
+            collect_files(root, &entry.path(), out)?;
+        }
+    } else if metadata.is_file() {
+        let relative = path.strip_prefix(root)?.to_string_lossy().to_string();
+        if relative.contains(CONFLICT_MARKER) {
+            return Ok(());
+        }
+        let modified_at = metadata.modified().ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_else(now_ms);
+        out.insert(format!("{}{}", FILE_PREFIX, relative), (hash(&fs::read(path)?), modified_at));
+    }
+    Ok(())
+}
+
+/// Read an entry's current content for sending
+fn read_entry(id: &ContainerId, container: &Container, leaf: Leaf) -> Result<EntryData> {
+    let content = match &leaf.hash {
+        None => None,
+        Some(_) => Some(match entry_location(container, &leaf.path)? {
+            Location::File(path) => fs::read(&path).with_context(|| format!("Failed to read {:?}", path))?,
+            Location::Kv(key) => super::kv::with_store(id, |store| Ok(store.get(&key)))?
+                .ok_or_else(|| anyhow::anyhow!("kv entry {} disappeared during sync", leaf.path))?,
+        }),
+    };
+    Ok(EntryData { leaf, content })
+}
+
+/// Write or delete an entry
+fn write_entry(id: &ContainerId, container: &Container, path: &str, content: Option<&[u8]>) -> Result<()> {
+    match (entry_location(container, path)?, content) {
+        (Location::File(file), Some(content)) => {
+            if let Some(parent) = file.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            // Replace atomically so a failed sync never leaves a torn file
+            let staged = PathBuf::from(format!("{}.sync-tmp", file.display()));
+            fs::write(&staged, content)?;
+            fs::rename(&staged, &file).with_context(|| format!("Failed to write {:?}", file))
+        }
+        (Location::File(file), None) => match fs::remove_file(&file) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e).with_context(|| format!("Failed to remove {:?}", file)),
+            _ => Ok(()),
+        },
+        (Location::Kv(key), Some(content)) => super::kv::with_store(id, |store| store.put(&key, content, None)),
+        (Location::Kv(key), None) => super::kv::with_store(id, |store| store.delete(&key)),
+    }
+}
+
+/// Keep the local version of an entry under a conflict name
+fn keep_conflict_copy(id: &ContainerId, container: &Container, path: &str) -> Result<String> {
+    let suffix = format!("{}{}", CONFLICT_MARKER, now());
+    match entry_location(container, path)? {
+        Location::File(file) => {
+            let copy = PathBuf::from(format!("{}{}", file.display(), suffix));
+            fs::copy(&file, &copy).with_context(|| format!("Failed to keep conflict copy of {:?}", file))?;
+            Ok(format!("{}{}", path, suffix))
+        }
+        Location::Kv(key) => {
+            let mut copy = key.clone();
+            copy.extend_from_slice(suffix.as_bytes());
+            super::kv::with_store(id, |store| match store.get(&key) {
+                Some(value) => store.put(&copy, &value, None),
+                None => Ok(()),
+            })?;
+            Ok(format!("{}{}", KV_PREFIX, to_hex(&copy)))
+        }
+    }
+}
+
+/// Where an entry lives
+enum Location {
+    File(PathBuf),
+    Kv(Vec<u8>),
+}
+
+/// Resolve an entry path, refusing anything outside the container's synced data
+fn entry_location(container: &Container, path: &str) -> Result<Location> {
+    if let Some(key) = path.strip_prefix(KV_PREFIX) {
+        return Ok(Location::Kv(from_hex(key).with_context(|| format!("Invalid kv entry path: {}", path))?));
+    }
+    let relative = path.strip_prefix(FILE_PREFIX)
+        .ok_or_else(|| anyhow::anyhow!("Invalid entry path: {}", path))?;
+    let relative = safe_relative(relative)?;
+    
+    let config = container.metadata.sync.clone().unwrap_or_default();
+    let declared = config.paths.iter()
+        .filter_map(|p| safe_relative(p).ok())
+        .any(|p| relative.starts_with(p));
+    if !declared {
+        anyhow::bail!("Entry {} is outside the syncable paths of container {}", path, container.name);
+    }
+    Ok(Location::File(container_dir(container)?.join(relative)))
+}
+
+/// Check that data sync between a container and a peer is enabled
+fn check_enabled(container: &Container, peer_id: &str) -> Result<SyncConfig> {
+    let config = container.metadata.sync.clone()
+        .ok_or_else(|| anyhow::anyhow!("Container {} declares no syncable data", container.name))?;
+    if !container.permissions.capabilities.iter().any(|c| c == CAPABILITY) {
+        anyhow::bail!("Container {} is not granted the {} capability", container.name, CAPABILITY);
+    }
+    if !config.peers.iter().any(|p| p == peer_id) {
+        anyhow::bail!("Container {} is not paired with {}", container.name, peer_id);
+    }
+    if !crate::gossip::is_trusted_peer(peer_id) {
+        anyhow::bail!("Peer {} is not trusted", peer_id);
+    }
+    if !protocol::capabilities().iter().any(|c| c == CAPABILITY) {
+        anyhow::bail!("This node does not advertise {}", CAPABILITY);
+    }
+    let advertised = crate::gossip::peer_report(peer_id)?
+        .map_or(false, |r| r.report.capabilities.iter().any(|c| c == CAPABILITY));
+    if !advertised {
+        anyhow::bail!("Peer {} does not advertise {}", peer_id, CAPABILITY);
+    }
+    Ok(config)
+}
+
+/// Sync only runs while the container can't change its data
+fn check_quiescent(id: &ContainerId) -> Result<()> {
+    if matches!(super::registry::get_container_status(id)?, ContainerStatus::Running | ContainerStatus::Healthy) {
+        anyhow::bail!("Container {} is running; stop or pause it to sync its data", id);
+    }
+    Ok(())
+}
+
+/// Registered container with a name
+fn find_container(name: &str) -> Result<(ContainerId, Container)> {
+    let info = super::registry::list_containers()?
+        .into_iter()
+        .find(|c| c.name == name)
+        .ok_or_else(|| anyhow::anyhow!("No container named {} to sync", name))?;
+    Ok((info.id.clone(), super::registry::get_container(&info.id)?))
+}
+
+/// Split a message into frames and send them to a peer
+fn send(peer_id: &str, message: &SyncMessage) -> Result<()> {
+    let peer = crate::gossip::list_peers()?
+        .into_iter()
+        .find(|p| p.id == peer_id)
+        .ok_or_else(|| anyhow::anyhow!("Unknown peer: {}", peer_id))?;
+    
+    let bytes = bincode::serialize(message).context("Failed to serialize data sync message")?;
+    let transfer = blake3::hash(&[&bytes[..], &now_ms().to_le_bytes()].concat()).to_hex()[..16].to_string();
+    let chunks: Vec<&[u8]> = bytes.chunks(FRAME_DATA_BYTES).collect();
+    
+    for (index, chunk) in chunks.iter().enumerate() {
+        let frame = Frame {
+            transfer: transfer.clone(),
+            index: index as u32,
+            count: chunks.len() as u32,
+            data: chunk.to_vec(),
+        };
+        protocol::send_message(&peer.endpoint, MessageType::DataSync, &bincode::serialize(&frame)?)?;
+    }
+    debug!("Sent data sync transfer {} to {} in {} frame(s)", transfer, peer_id, chunks.len());
+    Ok(())
+}
+
+/// Merkle root over the live entries, so equal data gives equal roots on both sides
+fn merkle_root(leaves: &[Leaf]) -> String {
+    let mut level: Vec<blake3::Hash> = leaves.iter()
+        .filter_map(|leaf| leaf.hash.as_ref().map(|h| blake3::hash(format!("{}\0{}", leaf.path, h).as_bytes())))
+        .collect();
+    if level.is_empty() {
+        return blake3::hash(b"").to_hex().to_string();
+    }
+    
+    while level.len() > 1 {
+        level = level.chunks(2)
+            .map(|pair| match pair {
+                [left, right] => blake3::hash(&[left.as_bytes().as_slice(), right.as_bytes().as_slice()].concat()),
+                [single] => *single,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    level[0].to_hex().to_string()
+}
+
+/// Hashes of live entries, as a sync base
+fn live_hashes(leaves: &[Leaf]) -> BTreeMap<String, Option<String>> {
+    leaves.iter()
+        .filter(|l| l.hash.is_some())
+        .map(|l| (l.path.clone(), l.hash.clone()))
+        .collect()
+}
+
+/// An entry one side never had
+fn absent(path: &str) -> Leaf {
+    Leaf { path: path.to_string(), hash: None, modified_at: 0 }
+}
+
+/// Load a container's sync state, change it and save it, under the container's sync lock
+fn with_state<T>(id: &ContainerId, f: impl FnOnce(&mut SyncState) -> Result<T>) -> Result<T> {
+    let _lock = lock::lock(&format!("matrixbox-sync-{}", id), "data sync", lock::DEFAULT_TIMEOUT)?;
+    
+    let dir = constants::root_dir().join(SYNC_DIR).join(safe_relative(id)?);
+    let path = dir.join(STATE_FILE);
+    let mut state: SyncState = if path.exists() {
+        serde_json::from_str(&fs::read_to_string(&path)?)
+            .with_context(|| format!("Invalid sync state of container {}", id))?
+    } else {
+        SyncState::default()
+    };
+    
+    let result = f(&mut state)?;
+    fs::create_dir_all(&dir)?;
+    fs::write(&path, serde_json::to_string_pretty(&state)?)?;
+    Ok(result)
+}
+
+fn container_dir(container: &Container) -> Result<PathBuf> {
+    container.path.clone()
+        .ok_or_else(|| anyhow::anyhow!("Container {} has no directory", container.name))
+}
+
+/// A relative path without `..` or root components
+fn safe_relative(path: &str) -> Result<&Path> {
+    let relative = Path::new(path);
+    if path.is_empty() || !relative.components().all(|c| matches!(c, Component::Normal(_))) {
+        anyhow::bail!("Invalid sync path: {}", path);
+    }
+    Ok(relative)
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        anyhow::bail!("Invalid hex string length");
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).context("Invalid hex string"))
+        .collect()
+}
+
+fn hash(content: &[u8]) -> String {
+    blake3::hash(content).to_hex().to_string()
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Container syncing its `data` directory, in a fresh directory under the root
+    fn container(name: &str) -> Container {
+        let dir = constants::root_dir().join("sync-containers").join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("data")).unwrap();
+        
+        let mut container = super::super::app::default_manifest(name);
+        container.path = Some(dir);
+        container.metadata.sync = Some(SyncConfig { paths: vec!["data".to_string()], kv: false, peers: vec!["sync-peer".to_string()] });
+        container
+    }
+    
+    fn leaf(path: &str, content: Option<&[u8]>, modified_at: u64) -> Leaf {
+        Leaf { path: path.to_string(), hash: content.map(hash), modified_at }
+    }
+    
+    #[test]
+    fn scans_track_new_changed_and_deleted_files() {
+        let container = container("sync-scan");
+        let dir = container.path.clone().unwrap();
+        let config = container.metadata.sync.clone().unwrap();
+        fs::write(dir.join("data/a.txt"), b"one").unwrap();
+        fs::write(dir.join("data/b.txt.sync-conflict-1"), b"copy").unwrap();
+        fs::write(dir.join("outside.txt"), b"not synced").unwrap();
+        
+        let mut state = SyncState::default();
+        scan(&"sync-scan".to_string(), &container, &config, &mut state).unwrap();
+        assert_eq!(state.index.keys().collect::<Vec<_>>(), ["files/data/a.txt"]);
+        assert_eq!(state.index["files/data/a.txt"].hash, Some(hash(b"one")));
+        
+        fs::write(dir.join("data/a.txt"), b"two").unwrap();
+        scan(&"sync-scan".to_string(), &container, &config, &mut state).unwrap();
+        assert_eq!(state.index["files/data/a.txt"].hash, Some(hash(b"two")));
+        
+        fs::remove_file(dir.join("data/a.txt")).unwrap();
+        scan(&"sync-scan".to_string(), &container, &config, &mut state).unwrap();
+        assert_eq!(state.index["files/data/a.txt"].hash, None);
+    }
+    
+    #[test]
+    fn concurrent_edits_keep_the_local_version_as_a_copy() {
+        let container = container("sync-conflict");
+        let dir = container.path.clone().unwrap();
+        let id = "sync-conflict".to_string();
+        fs::write(dir.join("data/notes.txt"), b"local edit").unwrap();
+        
+        let mut state = SyncState::default();
+        state.index.insert("files/data/notes.txt".to_string(), leaf("files/data/notes.txt", Some(b"local edit"), 10));
+        state.peers.entry("sync-peer".to_string()).or_default()
+            .base.insert("files/data/notes.txt".to_string(), Some(hash(b"original")));
+        
+        let incoming = EntryData { leaf: leaf("files/data/notes.txt", Some(b"remote edit"), 20), content: Some(b"remote edit".to_vec()) };
+        apply_entry(&id, &container, &mut state, "sync-peer", incoming).unwrap();
+        
+        assert_eq!(fs::read(dir.join("data/notes.txt")).unwrap(), b"remote edit");
+        assert_eq!(state.conflicts.len(), 1);
+        let copy = state.conflicts[0].copy.clone().unwrap();
+        assert!(copy.starts_with("files/data/notes.txt.sync-conflict-"));
+        assert_eq!(fs::read(dir.join(copy.strip_prefix(FILE_PREFIX).unwrap())).unwrap(), b"local edit");
+        assert_eq!(state.peers["sync-peer"].base["files/data/notes.txt"], Some(hash(b"remote edit")));
+    }
+    
+    #[test]
+    fn entries_apply_deletions_and_reject_bad_content() {
+        let container = container("sync-apply");
+        let dir = container.path.clone().unwrap();
+        let id = "sync-apply".to_string();
+        let mut state = SyncState::default();
+        
+        let forged = EntryData { leaf: leaf("files/data/x.txt", Some(b"claimed"), 1), content: Some(b"actual".to_vec()) };
+        assert!(apply_entry(&id, &container, &mut state, "sync-peer", forged).is_err());
+        assert!(!dir.join("data/x.txt").exists());
+        
+        let added = EntryData { leaf: leaf("files/data/sub/x.txt", Some(b"new"), 1), content: Some(b"new".to_vec()) };
+        apply_entry(&id, &container, &mut state, "sync-peer", added).unwrap();
+        assert_eq!(fs::read(dir.join("data/sub/x.txt")).unwrap(), b"new");
+        
+        let deleted = EntryData { leaf: leaf("files/data/sub/x.txt", None, 2), content: None };
+        apply_entry(&id, &container, &mut state, "sync-peer", deleted).unwrap();
+        assert!(!dir.join("data/sub/x.txt").exists());
+        assert!(state.conflicts.is_empty());
+    }
+    
+    #[test]
+    fn entry_paths_stay_within_the_synced_data() {
+        let container = container("sync-paths");
+        assert!(matches!(entry_location(&container, "files/data/a/b.txt").unwrap(), Location::File(_)));
+        assert!(matches!(entry_location(&container, "kv/6b6579").unwrap(), Location::Kv(ref key) if key == b"key"));
+        
+        for path in ["files/other.txt", "files/data/../meta.yaml", "files//etc/passwd", "kv/6b6", "data/a.txt"] {
+            assert!(entry_location(&container, path).is_err(), "{}", path);
+        }
+    }
+    
+    #[test]
+    fn merkle_roots_depend_only_on_live_entries() {
+        let a = leaf("files/a", Some(b"a"), 1);
+        let b = leaf("files/b", Some(b"b"), 2);
+        let c = leaf("files/c", Some(b"c"), 3);
+        let root = merkle_root(&[a.clone(), b.clone(), c.clone()]);
+        
+        let later = [leaf("files/a", Some(b"a"), 9), b.clone(), leaf("files/gone", None, 4), c.clone()];
+        assert_eq!(merkle_root(&later), root);
+        assert_ne!(merkle_root(&[a.clone(), leaf("files/b", Some(b"B"), 2), c]), root);
+        assert_eq!(merkle_root(&[]), merkle_root(&[leaf("files/gone", None, 4)]));
+        
+        assert_eq!(live_hashes(&later).len(), 3);
+    }
+    
+    #[test]
+    fn frames_from_untrusted_peers_or_with_bad_counts_are_refused() {
+        let frame = |index, count| bincode::serialize(&Frame { transfer: "t1".to_string(), index, count, data: vec![1] }).unwrap();
+        handle_frame("sync-stranger", &frame(5, 1)).unwrap();
+        
+        crate::gossip::add_peer("sync-frames", "127.0.0.1:9", None).unwrap();
+        crate::gossip::update_peer_identity("sync-frames", "sync-frames", &"ab".repeat(32)).unwrap();
+        assert!(handle_frame("sync-frames", &frame(1, 1)).is_err());
+        assert!(handle_frame("sync-frames", &frame(0, 0)).is_err());
+        assert!(handle_frame("sync-frames", b"garbage").is_err());
+        
+        // The first of two frames waits for the second
+        handle_frame("sync-frames", &frame(0, 2)).unwrap();
+        assert!(handle_frame("sync-frames", &frame(0, 3)).is_err());
+    }
+}
@@ -10,89 +10,97 @@ use serde::{Serialize, Deserialize};
 use blake3;
 
 use crate::core::constants;
-use super::container::{Container, ContainerId};
+use super::container::{Container, ContainerConfig, ContainerId, ContainerMetadata};
 
 // TSO file magic number and version
 const TSO_MAGIC: [u8; 4] = [b'T', b'S', b'O', b'1'];
+const REQUIRED_FILES: [&str; 3] = ["meta.yaml", "main.wasm", "permissions.zky"];
+const DEFAULT_VERSION: &str = "1.0.0";
 
-/// Create a TSO archive from a container directory
-pub fn create_tso_archive(container: &Container, output_path: &Path) -> Result<()> {
-    info!("Creating TSO archive for container: {}", container.name);
-    
-    let container_path = container.path.as_ref()
-        .ok_or_else(|| anyhow::anyhow!("Container has no path"))?;
+/// Pack a container directory into a TSO archive
+///
+/// Every file under `src_dir` is stored, by `/`-separated relative path and
+/// with its BLAKE3 hash in the manifest. The archive is named and versioned
+/// from `config`; its resource limits and ports, if set, are written into
+/// the packed `meta.yaml`, which leaves the source directory untouched but
+/// invalidates any image signature over it.
+pub fn create_tso_archive(src_dir: &Path, output: &Path, config: &ContainerConfig) -> Result<()> {
+    info!("Creating TSO archive from {:?}", src_dir);
+    
+    for required in REQUIRED_FILES {
+        if !src_dir.join(required).is_file() {
+            anyhow::bail!("Container directory {:?} is missing {}", src_dir, required);
+        }
+    }
     
-    // Ensure the container has all required files
-    let meta_path = container_path.join("meta.yaml");
-    let wasm_path = container_path.join("main.wasm");
-    let permissions_path = container_path.join("permissions.zky");
+    let name = if config.name.is_empty() {
+        src_dir.file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| anyhow::anyhow!("Cannot name the archive of {:?}; pass a name", src_dir))?
+            .to_string()
+    } else {
+        config.name.clone()
+    };
     
-    if !meta_path.exists() || !wasm_path.exists() || !permissions_path.exists() {
-        return Err(anyhow::anyhow!("Container is missing required files"));
+    // Contents in path order, with the settings applied to the metadata
+    let mut contents = Vec::new();
+    for (relative, path) in list_files(src_dir)? {
+        let mut content = fs::read(&path).with_context(|| format!("Failed to read {:?}", path))?;
+        if relative == "meta.yaml" && (config.resource_limits.is_some() || !config.ports.is_empty()) {
+            let mut metadata: ContainerMetadata = serde_yaml::from_slice(&content)
+                .with_context(|| format!("Failed to parse {:?}", path))?;
+            if config.resource_limits.is_some() {
+                metadata.resource_limits = config.resource_limits;
+            }
+            if !config.ports.is_empty() {
+                metadata.ports = config.ports.clone();
+            }
+            content = serde_yaml::to_string(&metadata)?.into_bytes();
+        }
+        contents.push((relative, content));
     }
     
-    // Create TSO manifest
-    let manifest = TsoManifest {
-        name: container.name.clone(),
-        version: container.version.clone(),
+    let wasm = &contents.iter().find(|(name, _)| name == "main.wasm").unwrap().1;
+    let mut manifest = TsoManifest {
+        name,
+        version: config.version.clone().unwrap_or_else(|| DEFAULT_VERSION.to_string()),
         created_at: chrono::Utc::now().to_rfc3339(),
-        wasm_size: fs::metadata(&wasm_path)?.len(),
-        wasm_hash: calculate_file_hash(&wasm_path)?,
+        wasm_size: wasm.len() as u64,
+        wasm_hash: blake3::hash(wasm).to_hex().to_string(),
         iot_optimized: true,
-        files: vec![
-            TsoFileEntry {
-                name: "meta.yaml".to_string(),
-                size: fs::metadata(&meta_path)?.len(),
-                offset: 0, // Will be filled in later
-                hash: calculate_file_hash(&meta_path)?,
-            },
-            TsoFileEntry {
-                name: "main.wasm".to_string(),
-                size: fs::metadata(&wasm_path)?.len(),
-                offset: 0, // Will be filled in later
-                hash: calculate_file_hash(&wasm_path)?,
-            },
-            TsoFileEntry {
-                name: "permissions.zky".to_string(),
-                size: fs::metadata(&permissions_path)?.len(),
-                offset: 0, // Will be filled in later
-                hash: calculate_file_hash(&permissions_path)?,
-            },
-        ],
+        files: contents.iter()
+            .map(|(name, content)| TsoFileEntry {
+                name: name.clone(),
+                size: content.len() as u64,
+                offset: 0,
+                hash: blake3::hash(content).to_hex().to_string(),
+            })
+            .collect(),
     };
     
-    // Create TSO file
-    let mut file = File::create(output_path)
-        .with_context(|| format!("Failed to create TSO file: {:?}", output_path))?;
+    // Offsets count from the start of the archive; they do not change the
+    // manifest's encoded size, so it is serialized once to measure it
+    let mut offset = (TSO_MAGIC.len() + std::mem::size_of::<u32>() + bincode::serialize(&manifest)?.len()) as u64;
+    for entry in &mut manifest.files {
+        entry.offset = offset;
+        offset += entry.size;
+    }
+    let manifest_bytes = bincode::serialize(&manifest)?;
     
-    // Write TSO header
+    if let Some(parent) = output.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = File::create(output)
+        .with_context(|| format!("Failed to create TSO file: {:?}", output))?;
     file.write_all(&TSO_MAGIC)?;
-    
-    // Serialize and write the manifest
-    let manifest_bytes = bincode::serialize(&manifest)?;
-    let manifest_len = manifest_bytes.len() as u32;
-    file.write_all(&manifest_len.to_le_bytes())?;
+    file.write_all(&(manifest_bytes.len() as u32).to_le_bytes())?;
     file.write_all(&manifest_bytes)?;
+    for (_, content) in &contents {
+        file.write_all(content)?;
+    }
+    file.sync_all()?;
     
-    // Calculate initial offset for file data
-    let header_size = TSO_MAGIC.len() + std::mem::size_of::<u32>() + manifest_bytes.len();
-    let mut current_offset = header_size;
-    
-    // Write meta.yaml
-    let meta_content = fs::read(&meta_path)?;
-    file.write_all(&meta_content)?;
-    current_offset += meta_content.len();
-    
-    // Write main.wasm
-    let wasm_content = fs::read(&wasm_path)?;
-    file.write_all(&wasm_content)?;
-    current_offset += wasm_content.len();
-    
-    // Write permissions.zky
-    let permissions_content = fs::read(&permissions_path)?;
-    file.write_all(&permissions_content)?;
-    
-    info!("Successfully created TSO archive: {:?}", output_path);
+    info!("Created TSO archive {:?}: {} {} ({} files)", output, manifest.name, manifest.version, manifest.files.len());
     Ok(())
 }
 
@@ -131,7 +139,16 @@ pub fn extract_tso_archive(archive_path: &Path, target_dir: &Path) -> Result<Con
     
     // Extract files
     for file_entry in &manifest.files {
-        let target_path = target_dir.join(&file_entry.name);
+        // Names are relative paths inside the archive and must stay inside the target
+        let relative = Path::new(&file_entry.name);
+        if file_entry.name.is_empty()
+            || !relative.components().all(|c| matches!(c, std::path::Component::Normal(_))) {
+            return Err(anyhow::anyhow!("Invalid file name in TSO archive: {:?}", file_entry.name));
+        }
+        let target_path = target_dir.join(relative);
+        if let Some(parent) = target_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
         
         // Read file content
         let mut content = vec![0u8; file_entry.size as usize];
@@ -148,9 +165,11 @@ pub fn extract_tso_archive(archive_path: &Path, target_dir: &Path) -> Result<Con
         current_offset += file_entry.size as usize;
     }
     
-    // Load the extracted container
+    // Load the extracted container, named and versioned as packed
     let container_path = target_dir.to_str().unwrap();
-    let container = super::container::load_container(container_path)?;
+    let mut container = super::container::load_container(container_path)?;
+    container.name = manifest.name;
+    container.version = manifest.version;
     
     info!("Successfully extracted TSO archive: {:?}", archive_path);
     Ok(container)
@@ -208,19 +227,25 @@ pub fn get_tso_info(path: &Path) -> Result<TsoInfo> {
     Ok(info)
 }
 
-/// Calculate the Blake3 hash of a file
-fn calculate_file_hash(path: &Path) -> Result<String> {
-    // Open the file
-    let mut file = File::open(path)?;
-    
-    // Read the file content
-    let mut content = Vec::new();
-    file.read_to_end(&mut content)?;
-    
-    // Calculate hash
-    let hash = blake3::hash(&content);
+/// Files under a directory by `/`-separated relative path, in path order
+fn list_files(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    fn collect(base: &Path, dir: &Path, files: &mut Vec<(String, PathBuf)>) -> Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                collect(base, &path, files)?;
+            } else {
+                let relative = path.strip_prefix(base)?.to_string_lossy().replace('\\', "/");
+                files.push((relative, path));
+            }
+        }
+        Ok(())
+    }
     
-    Ok(hash.to_hex().to_string())
+    let mut files = Vec::new();
+    collect(dir, dir, &mut files)?;
+    files.sort();
+    Ok(files)
 }
 
 /// TSO manifest structure
@@ -286,21 +311,155 @@ pub struct TsoInfo {
     pub file_count: usize,
 }
 
-/// TSO file structure:
-/// ```
-/// +----------------+
-/// | Magic (4 bytes) |
-/// +----------------+
-/// | Manifest Size  |
-/// | (4 bytes)      |
-/// +----------------+
-/// | Manifest       |
-/// | (bincode)      |
-/// +----------------+
-/// | File 1 Data    |
-/// +----------------+
-/// | File 2 Data    |
-/// +----------------+
-/// | ...            |
-/// +----------------+
-/// ```
+// TSO file structure:
+// +----------------+
+// | Magic (4 bytes) |
+// +----------------+
+// | Manifest Size  |
+// | (4 bytes)      |
+// +----------------+
+// | Manifest       |
+// | (bincode)      |
+// +----------------+
+// | File 1 Data    |
+// +----------------+
+// | File 2 Data    |
+// +----------------+
+// | ...            |
+// +----------------+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::container::{self, ResourceLimits};
+    
+    /// A container directory with nested and binary files besides the required ones
+    fn source_dir(name: &str) -> PathBuf {
+        let container = container::create_container(name, "main").unwrap();
+        let dir = container.path.unwrap();
+        fs::write(dir.join("main.wasm"), b"\0asm\x01\0\0\0").unwrap();
+        fs::create_dir_all(dir.join("assets/data")).unwrap();
+        fs::write(dir.join("assets/data/blob.bin"), (0..=255u8).rev().collect::<Vec<_>>()).unwrap();
+        fs::write(dir.join("assets/readme.txt"), "packed by the tso tests\n").unwrap();
+        dir
+    }
+    
+    fn archive_path(name: &str) -> PathBuf {
+        constants::root_dir().join("tso-tests").join(format!("{}.tso", name))
+    }
+    
+    fn config(name: &str, version: &str) -> ContainerConfig {
+        ContainerConfig {
+            name: name.to_string(),
+            version: Some(version.to_string()),
+            ..Default::default()
+        }
+    }
+    
+    fn read_manifest(archive: &Path) -> (TsoManifest, Vec<u8>) {
+        let bytes = fs::read(archive).unwrap();
+        assert_eq!(bytes[..4], TSO_MAGIC);
+        let size = u32::from_le_bytes(bytes[4..8].try_into().unwrap()) as usize;
+        (bincode::deserialize(&bytes[8..8 + size]).unwrap(), bytes)
+    }
+    
+    #[test]
+    fn packed_directories_extract_byte_identical() {
+        let src = source_dir("tso-roundtrip");
+        let archive = archive_path("roundtrip");
+        create_tso_archive(&src, &archive, &config("foo", "1.0")).unwrap();
+        assert!(is_valid_tso_archive(&archive).unwrap());
+        
+        let target = constants::root_dir().join("tso-tests/roundtrip");
+        let extracted = extract_tso_archive(&archive, &target).unwrap();
+        assert_eq!((extracted.name.as_str(), extracted.version.as_str()), ("foo", "1.0"));
+        
+        let packed = list_files(&src).unwrap();
+        let unpacked = list_files(&target).unwrap();
+        assert_eq!(
+            packed.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+            unpacked.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+        );
+        assert!(packed.iter().any(|(name, _)| name == "assets/data/blob.bin"));
+        for ((name, from), (_, to)) in packed.iter().zip(&unpacked) {
+            assert_eq!(fs::read(from).unwrap(), fs::read(to).unwrap(), "{} differs", name);
+        }
+        
+        // Every manifest entry hashes and locates the file it names
+        let (manifest, bytes) = read_manifest(&archive);
+        assert_eq!(manifest.files.len(), packed.len());
+        for (entry, (name, path)) in manifest.files.iter().zip(&unpacked) {
+            let content = fs::read(path).unwrap();
+            assert_eq!(&entry.name, name);
+            assert_eq!(entry.size, content.len() as u64);
+            assert_eq!(entry.hash, blake3::hash(&content).to_hex().to_string());
+            assert_eq!(&bytes[entry.offset as usize..(entry.offset + entry.size) as usize], &content[..]);
+        }
+        assert_eq!(manifest.wasm_hash, blake3::hash(b"\0asm\x01\0\0\0").to_hex().to_string());
+        
+        let info = get_tso_info(&archive).unwrap();
+        assert_eq!((info.name.as_str(), info.version.as_str(), info.file_count, info.wasm_size), ("foo", "1.0", packed.len(), 8));
+    }
+    
+    #[test]
+    fn archives_default_to_the_directory_name_and_version() {
+        let src = source_dir("tso-unnamed");
+        let archive = archive_path("unnamed");
+        create_tso_archive(&src, &archive, &ContainerConfig::default()).unwrap();
+        
+        let info = get_tso_info(&archive).unwrap();
+        assert_eq!((info.name.as_str(), info.version.as_str()), ("tso-unnamed", DEFAULT_VERSION));
+    }
+    
+    #[test]
+    fn limits_are_packed_without_touching_the_source() {
+        let src = source_dir("tso-limits");
+        let original = fs::read(src.join("meta.yaml")).unwrap();
+        let limits = ResourceLimits {
+            max_memory_bytes: 32 * 1024 * 1024,
+            max_cpu_fraction: 0.5,
+            max_pids: 4,
+            max_fuel: Some(1_000_000),
+            max_exec_seconds: None,
+        };
+        let archive = archive_path("limits");
+        create_tso_archive(&src, &archive, &ContainerConfig { resource_limits: Some(limits), ..config("limited", "2.0") }).unwrap();
+        assert_eq!(fs::read(src.join("meta.yaml")).unwrap(), original);
+        
+        let target = constants::root_dir().join("tso-tests/limits");
+        extract_tso_archive(&archive, &target).unwrap();
+        let metadata: ContainerMetadata = serde_yaml::from_slice(&fs::read(target.join("meta.yaml")).unwrap()).unwrap();
+        assert_eq!(metadata.resource_limits, Some(limits));
+        
+        // The manifest describes the packed metadata, not the source file
+        let (manifest, _) = read_manifest(&archive);
+        let meta = manifest.files.iter().find(|entry| entry.name == "meta.yaml").unwrap();
+        assert_eq!(meta.hash, blake3::hash(&fs::read(target.join("meta.yaml")).unwrap()).to_hex().to_string());
+    }
+    
+    #[test]
+    fn directories_missing_a_required_file_are_not_packed() {
+        let src = source_dir("tso-incomplete");
+        fs::remove_file(src.join("permissions.zky")).unwrap();
+        let archive = archive_path("incomplete");
+        
+        let err = create_tso_archive(&src, &archive, &config("incomplete", "1.0")).unwrap_err();
+        assert!(err.to_string().contains("permissions.zky"), "{}", err);
+        assert!(!archive.exists());
+    }
+    
+    #[test]
+    fn tampered_archives_fail_hash_verification() {
+        let src = source_dir("tso-tampered");
+        let archive = archive_path("tampered");
+        create_tso_archive(&src, &archive, &config("tampered", "1.0")).unwrap();
+        
+        let (manifest, mut bytes) = read_manifest(&archive);
+        let blob = manifest.files.iter().find(|entry| entry.name == "assets/data/blob.bin").unwrap();
+        bytes[blob.offset as usize] ^= 0xff;
+        fs::write(&archive, bytes).unwrap();
+        
+        let err = extract_tso_archive(&archive, &constants::root_dir().join("tso-tests/tampered")).unwrap_err();
+        assert!(err.to_string().contains("assets/data/blob.bin"), "{}", err);
+    }
+}
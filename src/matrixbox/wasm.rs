@@ -8,13 +8,15 @@ use std::fs;
 use std::sync::{Arc, Mutex};
 use std::collections::HashMap;
 use wasmer::{Instance, Module, Store, Value, Function, imports};
-use wasmer_wasi::{WasiState, WasiEnv};
+use wasmer_wasix::WasiEnv;
 use serde::{Serialize, Deserialize};
 
 use crate::core::constants;
 use crate::zk;
 
-use super::container::{Container, ContainerStatus, ContainerId};
+use super::container::{Container, ContainerStatus, ContainerId, ResourceLimits};
+use super::limits::{self, ResourceExceeded};
+use super::output::{ContainerOutput, LogStream};
 
 // Global registry for running WASM instances
 lazy_static::lazy_static! {
@@ -27,7 +29,7 @@ pub fn init() -> Result<()> {
     info!("Initializing MatrixBox WASM runtime");
     
     // Create necessary directories
-    let wasm_dir = PathBuf::from(constants::ROOT_DIR).join(".matrixbox").join("wasm");
+    let wasm_dir = constants::root_dir().join(".matrixbox").join("wasm");
     fs::create_dir_all(&wasm_dir)?;
     
     // Clear any stale instance info
@@ -86,16 +88,23 @@ pub fn run_container(container: &Container, args: &[&str]) -> Result<ContainerId
         .with_context(|| format!("Failed to read WASM file: {:?}", wasm_path))?;
     
     debug!("Loaded WASM module: {} bytes", wasm_bytes.len());
+    super::policy::check_module(&wasm_bytes, &wasm_path)?;
+    
+    // Limit CPU and processes; memory, fuel and time are bounded by the store
+    let resource_limits = limits::effective(container.metadata.resource_limits)?;
+    if let Some(resource_limits) = &resource_limits {
+        limits::apply(&container_id, resource_limits)?;
+    }
     
     // Create a wasmer store
-    let mut store = Store::default();
+    let mut store = limits::store(resource_limits.as_ref());
     
     // Compile the WASM module
     let module = Module::new(&store, &wasm_bytes)
         .with_context(|| "Failed to compile WASM module")?;
     
     // Create WASI environment
-    let mut wasi_env_builder = WasiState::new(container.name.clone());
+    let mut wasi_env_builder = WasiEnv::builder(container.name.clone());
     
     // Add container-specific environment variables
     for env_var in &container.metadata.environment {
@@ -104,11 +113,22 @@ pub fn run_container(container: &Container, args: &[&str]) -> Result<ContainerId
         }
     }
     
+    // The container's own directory is served from its filesystem layer
+    let filesystem = super::container::container_filesystem(&container_id)?;
+    if let Some(filesystem) = &filesystem {
+        filesystem.materialize()?;
+    }
+    
     // Apply filesystem permissions
     for path in &container.permissions.filesystem {
-        let fs_path = PathBuf::from(constants::ROOT_DIR).join(path);
+        let mut fs_path = constants::root_dir().join(path);
+        if let Some(filesystem) = &filesystem {
+            if let Ok(relative) = fs_path.strip_prefix(container_path) {
+                fs_path = filesystem.root().join(relative);
+            }
+        }
         if fs_path.exists() {
-            wasi_env_builder = wasi_env_builder.preopen_dir(fs_path, path)?;
+            wasi_env_builder = wasi_env_builder.map_dir(path, fs_path)?;
         } else {
             warn!("Container requested access to non-existent path: {}", path);
         }
@@ -119,14 +139,55 @@ pub fn run_container(container: &Container, args: &[&str]) -> Result<ContainerId
         wasi_env_builder = wasi_env_builder.arg(arg);
     }
     
-    let wasi_env = wasi_env_builder.finalize()?;
+    // Keep the container's output for `matrixbox logs`
+    wasi_env_builder = wasi_env_builder
+        .stdout(Box::new(ContainerOutput::new(&container_id, LogStream::Stdout)?))
+        .stderr(Box::new(ContainerOutput::new(&container_id, LogStream::Stderr)?));
+    
+    // Tell the container which descriptors its forwarded ports arrive on
+    let ports = &container.metadata.ports;
+    if !ports.is_empty() {
+        let (key, value) = super::network::listen_fds_env(ports);
+        wasi_env_builder = wasi_env_builder.env(key, value);
+    }
+    
+    let mut wasi_env = wasi_env_builder.finalize(&mut store)?;
     
-    // Get import object from WASI
-    let import_object = wasi_env.import_object(&mut store, &module)?;
+    // Get import object from WASI and add the sentient.* host ABI
+    let mut import_object = wasi_env.import_object(&mut store, &module)?;
+    let host_env = super::host::register(&mut store, &mut import_object, &container_id);
+    
+    // Socket calls are served by the runtime from the container's forwarded ports
+    let net_env = if ports.is_empty() {
+        None
+    } else {
+        let net_env = super::network::register(&mut store, &mut import_object, &container_id);
+        super::network::attach(&container_id, ports)?;
+        Some(net_env)
+    };
     
     // Instantiate the module with imports
     let instance = Instance::new(&mut store, &module, &import_object)
-        .with_context(|| "Failed to instantiate WASM module")?;
+        .with_context(|| "Failed to instantiate WASM module")
+        .and_then(|instance| {
+            wasi_env.initialize(&mut store, instance.clone())?;
+            super::host::attach(&mut store, &host_env, &instance)?;
+            if let Some(net_env) = &net_env {
+                super::network::attach_memory(&mut store, net_env, &instance)?;
+            }
+            Ok(instance)
+        });
+    let instance = match instance {
+        Ok(instance) => instance,
+        Err(e) => {
+            if net_env.is_some() {
+                if let Err(detach_error) = super::network::detach(&container_id) {
+                    warn!("Failed to detach network of container {}: {:#}", container_id, detach_error);
+                }
+            }
+            return Err(e);
+        }
+    };
     
     // Get the WASM memory export
     let memory = instance.exports.get_memory("memory")?;
@@ -144,6 +205,21 @@ pub fn run_container(container: &Container, args: &[&str]) -> Result<ContainerId
     let mut instances = WASM_INSTANCES.lock().unwrap();
     instances.insert(container_id.clone(), instance_info);
     
+    // Entry points run inside the container's cgroup and share one fuel and time budget
+    let _attached = limits::attach(&container_id)?;
+    let budget = limits::CallBudget::start(&mut store, &instance, resource_limits.as_ref())?;
+    
+    // WASI reactors (cdylib apps) must be initialized before any export is called
+    if let Ok(initialize) = instance.exports.get_function("_initialize") {
+        debug!("Calling _initialize function");
+        if let Err(e) = initialize.call(&mut store, &[]) {
+            let exceeded = budget.exceeded(&container_id, &mut store, &instance);
+            let memory_bytes = memory.view(&store).data_size();
+            return Err(entry_failed(&mut instances, &container_id, resource_limits.as_ref(), exceeded, memory_bytes,
+                                    format!("WASM initialization failed: {}", e)));
+        }
+    }
+    
     // Call the _start function (WASI entry point)
     if let Ok(start) = instance.exports.get_function("_start") {
         debug!("Calling _start function");
@@ -153,11 +229,10 @@ pub fn run_container(container: &Container, args: &[&str]) -> Result<ContainerId
             },
             Err(e) => {
                 error!("Error in WASM execution: {}", e);
-                // Update status to failed
-                if let Some(instance_info) = instances.get_mut(&container_id) {
-                    instance_info.status = WasmInstanceStatus::Failed(e.to_string());
-                }
-                return Err(anyhow::anyhow!("WASM execution failed: {}", e));
+                let exceeded = budget.exceeded(&container_id, &mut store, &instance);
+                let memory_bytes = memory.view(&store).data_size();
+                return Err(entry_failed(&mut instances, &container_id, resource_limits.as_ref(), exceeded, memory_bytes,
+                                        format!("WASM execution failed: {}", e)));
             }
         }
     } else {
@@ -170,11 +245,10 @@ pub fn run_container(container: &Container, args: &[&str]) -> Result<ContainerId
                 },
                 Err(e) => {
                     error!("Error in WASM execution: {}", e);
-                    // Update status to failed
-                    if let Some(instance_info) = instances.get_mut(&container_id) {
-                        instance_info.status = WasmInstanceStatus::Failed(e.to_string());
-                    }
-                    return Err(anyhow::anyhow!("WASM execution failed: {}", e));
+                    let exceeded = budget.exceeded(&container_id, &mut store, &instance);
+                    let memory_bytes = memory.view(&store).data_size();
+                    return Err(entry_failed(&mut instances, &container_id, resource_limits.as_ref(), exceeded, memory_bytes,
+                                            format!("WASM execution failed: {}", e)));
                 }
             }
         } else {
@@ -191,6 +265,42 @@ pub fn run_container(container: &Container, args: &[&str]) -> Result<ContainerId
     Ok(container_id)
 }
 
+/// Record a failed entry point call, as a kill if it ran out of fuel, time or memory
+fn entry_failed(
+    instances: &mut HashMap<ContainerId, WasmInstanceInfo>,
+    container_id: &ContainerId,
+    resource_limits: Option<&ResourceLimits>,
+    exceeded: Option<ResourceExceeded>,
+    memory_bytes: u64,
+    message: String,
+) -> anyhow::Error {
+    if let Some(exceeded) = exceeded {
+        limits::resource_kill(&exceeded);
+        if let Some(instance_info) = instances.get_mut(container_id) {
+            instance_info.status = WasmInstanceStatus::ResourceExceeded(exceeded.limit.to_string());
+        }
+        return exceeded.into();
+    }
+    
+    let oom = resource_limits.filter(|l| limits::memory_exhausted(memory_bytes, l));
+    if let Some(resource_limits) = oom {
+        limits::oom_kill(container_id, memory_bytes, resource_limits);
+    }
+    
+    // Update status to failed
+    if let Some(instance_info) = instances.get_mut(container_id) {
+        instance_info.status = match oom {
+            Some(_) => WasmInstanceStatus::OomKilled,
+            None => WasmInstanceStatus::Failed(message.clone()),
+        };
+    }
+    
+    match oom {
+        Some(_) => anyhow::anyhow!("Container {} was killed for exceeding its memory limit", container_id),
+        None => anyhow::anyhow!(message),
+    }
+}
+
 /// Stop a running container
 pub fn stop_container(container_id: &str) -> Result<()> {
     info!("Stopping container: {}", container_id);
@@ -200,6 +310,10 @@ pub fn stop_container(container_id: &str) -> Result<()> {
     if let Some(instance_info) = instances.get_mut(container_id) {
         // Update status to stopped
         instance_info.status = WasmInstanceStatus::Exited(0);
+        limits::release(&container_id.to_string());
+        if let Err(e) = super::network::detach(&container_id.to_string()) {
+            warn!("Failed to detach network of container {}: {:#}", container_id, e);
+        }
         
         info!("Container stopped: {}", container_id);
         Ok(())
@@ -219,6 +333,8 @@ pub fn get_container_status(container_id: &str) -> Result<ContainerStatus> {
             WasmInstanceStatus::Paused => ContainerStatus::Paused,
             WasmInstanceStatus::Exited(code) => ContainerStatus::Exited(*code),
             WasmInstanceStatus::Failed(msg) => ContainerStatus::Failed(msg.clone()),
+            WasmInstanceStatus::OomKilled => ContainerStatus::OomKilled,
+            WasmInstanceStatus::ResourceExceeded(limit) => ContainerStatus::ResourceExceeded(limit.clone()),
         };
         
         Ok(status)
@@ -269,4 +385,10 @@ pub enum WasmInstanceStatus {
     
     /// Instance has failed
     Failed(String),
+    
+    /// Instance was killed for exceeding its memory limit
+    OomKilled,
+    
+    /// Instance was killed for exceeding its fuel or time limit
+    ResourceExceeded(String),
 }
@@ -1,19 +1,29 @@
 // SentientOS Network Subsystem
 // Provides network communication facilities for SentientOS components
 
+pub mod tls;
+
 use anyhow::{Result, Context};
 use tracing::{info, debug, warn, error};
 use std::path::{Path, PathBuf};
 use std::fs;
-use std::net::{IpAddr, SocketAddr, TcpListener, TcpStream, UdpSocket};
-use std::sync::{Arc, Mutex};
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex, OnceLock};
 use std::collections::HashMap;
 use std::time::{SystemTime, Duration, UNIX_EPOCH};
 use serde::{Serialize, Deserialize};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::Runtime;
+use tokio::sync::{oneshot, Mutex as AsyncMutex};
+use tokio::task::JoinHandle;
+use tokio_rustls::{TlsAcceptor, TlsConnector};
 
 use crate::core::constants;
 use crate::gossip;
 
+use tls::{PeerStream, TlsConfig};
+
 // Constants
 const DEFAULT_PORT: u16 = 29900;
 const DISCOVERY_PORT: u16 = 29901;
@@ -24,12 +34,15 @@ lazy_static::lazy_static! {
         Arc::new(Mutex::new(NetworkState::new()));
 }
 
+// Runtime the listener and peer connections run on, started on first use
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
 /// Initialize the network subsystem
 pub fn init() -> Result<()> {
     info!("Initializing SentientOS network subsystem");
     
     // Create network system directories
-    let network_dir = PathBuf::from(constants::ROOT_DIR).join(".network");
+    let network_dir = constants::root_dir().join(".network");
     fs::create_dir_all(&network_dir)?;
     
     // Load network configuration
@@ -45,6 +58,7 @@ pub fn init() -> Result<()> {
             max_connections: 100,
             connection_timeout_seconds: 30,
             tls_enabled: false,
+            tls: TlsConfig::default(),
             allowed_ips: Vec::new(),
         };
         
@@ -55,29 +69,33 @@ pub fn init() -> Result<()> {
         config
     };
     
-    // Initialize the network state
-    let mut state = NETWORK_STATE.lock().unwrap();
-    state.config = network_config;
-    
-    // Initialize connection tracking
-    state.connections = HashMap::new();
+    // Initialize the network state and connection tracking
+    let discovery_enabled = {
+        let mut state = NETWORK_STATE.lock().unwrap();
+        state.config = network_config;
+        state.connections = HashMap::new();
+        state.streams = HashMap::new();
+        state.config.discovery_enabled
+    };
     
-    // Try to start the network service if auto-start is enabled
-    if state.config.discovery_enabled {
+    // Try to start the network service if auto-start is enabled; starting
+    // takes the state lock itself
+    let status = if discovery_enabled {
         match start_network_services() {
             Ok(_) => {
-                state.status = NetworkStatus::Online;
                 info!("Network services started successfully");
+                NetworkStatus::Online
             },
             Err(e) => {
-                state.status = NetworkStatus::Error;
-                warn!("Failed to start network services: {}", e);
+                warn!("Failed to start network services: {:#}", e);
+                NetworkStatus::Error
             }
         }
     } else {
-        state.status = NetworkStatus::Offline;
         info!("Network services not started (discovery disabled in config)");
-    }
+        NetworkStatus::Offline
+    };
+    NETWORK_STATE.lock().unwrap().status = status;
     
     info!("SentientOS network subsystem initialized successfully");
     Ok(())
@@ -87,33 +105,58 @@ pub fn init() -> Result<()> {
 pub fn shutdown() -> Result<()> {
     info!("Shutting down SentientOS network subsystem");
     
-    let mut state = NETWORK_STATE.lock().unwrap();
+    stop_network_services()?;
+    let streams: Vec<_> = {
+        let mut state = NETWORK_STATE.lock().unwrap();
+        state.connections.clear();
+        state.streams.drain().collect()
+    };
     
     // Close any open connections
-    for (addr, conn) in state.connections.drain() {
+    for (addr, stream) in streams {
         debug!("Closing connection to {}", addr);
-        // In a real implementation, we would close the connection
+        close_stream(stream);
     }
     
-    // Update state
-    state.status = NetworkStatus::Offline;
-    
     info!("SentientOS network subsystem shutdown complete");
     Ok(())
 }
 
 /// Start network services (listeners and discovery)
+///
+/// Peers are accepted by a task on the network runtime. With
+/// `tls_enabled`, the certificate and key are loaded here, so a bad one
+/// fails the start rather than every handshake, and each peer completes a
+/// TLS handshake before it is tracked.
 pub fn start_network_services() -> Result<()> {
     info!("Starting network services");
     
     // Get network configuration
-    let state = NETWORK_STATE.lock().unwrap();
+    let mut state = NETWORK_STATE.lock().unwrap();
+    if state.listener.is_some() {
+        debug!("Network services are already running");
+        return Ok(());
+    }
     let bind_addr = format!("{}:{}", state.config.bind_address, state.config.port);
     
-    // TODO: In a real implementation, we would start listeners in separate threads
-    // For now, we'll just create a placeholder
+    let acceptor = if state.config.tls_enabled {
+        let server_config = tls::server_config(&state.config.tls)
+            .context("Failed to load the TLS certificate of the listener")?;
+        Some(TlsAcceptor::from(server_config))
+    } else {
+        None
+    };
+    
+    let runtime = runtime()?;
+    let listener = runtime.block_on(TcpListener::bind(&bind_addr))
+        .with_context(|| format!("Failed to listen on {}", bind_addr))?;
+    let address = listener.local_addr()?;
+    let encrypted = acceptor.is_some();
+    let (stop, stopped) = oneshot::channel();
+    let task = runtime.spawn(accept_loop(listener, acceptor, stopped));
+    state.listener = Some(Listener { address, stop, task });
     
-    debug!("Would start TCP listener on {}", bind_addr);
+    info!("Listening for peers on {}{}", address, if encrypted { " (TLS)" } else { "" });
     debug!("Would start UDP discovery on port {}", DISCOVERY_PORT);
     
     Ok(())
@@ -123,7 +166,11 @@ pub fn start_network_services() -> Result<()> {
 pub fn stop_network_services() -> Result<()> {
     info!("Stopping network services");
     
-    // TODO: In a real implementation, we would stop listeners and cleanup resources
+    // Waiting for the accept task must not hold the state lock its handshakes take
+    let listener = NETWORK_STATE.lock().unwrap().listener.take();
+    if let Some(listener) = listener {
+        listener.stop();
+    }
     
     // Update state
     let mut state = NETWORK_STATE.lock().unwrap();
@@ -132,6 +179,145 @@ pub fn stop_network_services() -> Result<()> {
     Ok(())
 }
 
+/// Accept peers until told to stop
+async fn accept_loop(listener: TcpListener, acceptor: Option<TlsAcceptor>, mut stop: oneshot::Receiver<()>) {
+    loop {
+        let (socket, addr) = tokio::select! {
+            _ = &mut stop => break,
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!("Failed to accept peer connection: {}", e);
+                    continue;
+                }
+            },
+        };
+        
+        // The slot is taken before the handshake so peers still completing
+        // one count against max_connections
+        let (slot, timeout) = match reserve(addr) {
+            Ok(reserved) => reserved,
+            Err(e) => {
+                debug!("Refused peer connection: {:#}", e);
+                continue;
+            }
+        };
+        
+        // Handshakes run as tasks of their own so a slow peer cannot hold up others
+        let acceptor = acceptor.clone();
+        tokio::spawn(async move {
+            if let Err(e) = admit(socket, addr, acceptor, timeout, slot).await {
+                debug!("Refused peer connection: {:#}", e);
+            }
+        });
+    }
+    debug!("Network listener stopped");
+}
+
+/// Check a peer against the allow list and connection limit, and hold a
+/// connection slot for it until it is tracked or refused
+fn reserve(addr: SocketAddr) -> Result<(PendingSlot, Option<Duration>)> {
+    let mut state = NETWORK_STATE.lock().unwrap();
+    let allowed_ips = &state.config.allowed_ips;
+    if !allowed_ips.is_empty() && !allowed_ips.iter().any(|ip| ip.parse::<IpAddr>().map_or(false, |ip| ip == addr.ip())) {
+        anyhow::bail!("{} is not in allowed_ips", addr.ip());
+    }
+    if state.connections.len() + state.pending >= state.config.max_connections {
+        anyhow::bail!("Connection limit of {} reached; refusing {}", state.config.max_connections, addr);
+    }
+    
+    state.pending += 1;
+    Ok((PendingSlot, connection_timeout(&state.config)))
+}
+
+/// Complete the handshake of a reserved peer, then track it
+async fn admit(
+    socket: TcpStream,
+    addr: SocketAddr,
+    acceptor: Option<TlsAcceptor>,
+    timeout: Option<Duration>,
+    _slot: PendingSlot,
+) -> Result<()> {
+    let stream = match acceptor {
+        Some(acceptor) => with_timeout(timeout, tls::accept(&acceptor, socket)).await
+            .with_context(|| format!("TLS handshake with {} failed", addr))?,
+        None => PeerStream::Plain(socket),
+    };
+    track(addr, stream).await?;
+    
+    info!("Accepted peer connection from {}", addr);
+    Ok(())
+}
+
+/// Record an established connection
+async fn track(addr: SocketAddr, stream: PeerStream) -> Result<()> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let connection = Connection {
+        address: addr.to_string(),
+        connected_at: now,
+        last_activity: now,
+        status: ConnectionStatus::Connected,
+    };
+    
+    let replaced = {
+        let mut state = NETWORK_STATE.lock().unwrap();
+        state.connections.insert(addr.to_string(), connection);
+        state.streams.insert(addr.to_string(), Arc::new(AsyncMutex::new(stream)))
+    };
+    if let Some(replaced) = replaced {
+        replaced.lock().await.close().await;
+    }
+    Ok(())
+}
+
+/// Open a connection to a peer, encrypted when a connector is given
+async fn open(addr: SocketAddr, connector: Option<TlsConnector>, timeout: Option<Duration>) -> Result<PeerStream> {
+    let socket = with_timeout(timeout, async { Ok(TcpStream::connect(addr).await?) }).await
+        .with_context(|| format!("Failed to connect to {}", addr))?;
+    match connector {
+        Some(connector) => with_timeout(timeout, tls::connect(&connector, addr, socket)).await,
+        None => Ok(PeerStream::Plain(socket)),
+    }
+}
+
+/// Close a stream that has been taken out of the state
+fn close_stream(stream: Arc<AsyncMutex<PeerStream>>) {
+    // Streams only exist once the runtime has started
+    if let Some(runtime) = RUNTIME.get() {
+        runtime.block_on(async { stream.lock().await.close().await });
+    }
+}
+
+/// The network runtime, started on first use
+fn runtime() -> Result<&'static Runtime> {
+    if let Some(runtime) = RUNTIME.get() {
+        return Ok(runtime);
+    }
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .thread_name("network")
+        .enable_all()
+        .build()
+        .context("Failed to start the network runtime")?;
+    Ok(RUNTIME.get_or_init(|| runtime))
+}
+
+/// Fail a connection step that takes longer than the connection timeout
+async fn with_timeout<T>(timeout: Option<Duration>, step: impl Future<Output = Result<T>>) -> Result<T> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, step).await
+            .map_err(|_| anyhow::anyhow!("Timed out after {}s", timeout.as_secs()))?,
+        None => step.await,
+    }
+}
+
+/// Timeout of peer connection steps, none when set to 0
+fn connection_timeout(config: &NetworkConfig) -> Option<Duration> {
+    match config.connection_timeout_seconds {
+        0 => None,
+        seconds => Some(Duration::from_secs(seconds as u64)),
+    }
+}
+
 /// Get the current network status
 pub fn get_status() -> Result<NetworkStatusInfo> {
     let state = NETWORK_STATE.lock().unwrap();
@@ -152,21 +338,24 @@ pub fn connect_to_peer(peer_addr: &str) -> Result<()> {
     let addr: SocketAddr = peer_addr.parse()
         .with_context(|| format!("Invalid peer address: {}", peer_addr))?;
     
-    // TODO: In a real implementation, we would establish a connection
-    // For now, we'll just create a placeholder
-    
-    // Track connection in state
-    let mut state = NETWORK_STATE.lock().unwrap();
-    let connection = Connection {
-        address: addr.to_string(),
-        connected_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
-        status: ConnectionStatus::Connected,
+    let (timeout, connector) = {
+        let state = NETWORK_STATE.lock().unwrap();
+        let connector = if state.config.tls_enabled {
+            Some(TlsConnector::from(tls::client_config(&state.config.tls)?))
+        } else {
+            None
+        };
+        (connection_timeout(&state.config), connector)
     };
     
-    state.connections.insert(addr.to_string(), connection);
+    // Establish the connection, encrypted when TLS is enabled, and track it in state
+    runtime()?.block_on(async {
+        let stream = open(addr, connector, timeout).await?;
+        track(addr, stream).await
+    })?;
     
     // Register the peer with gossip subsystem
-    match gossip::add_peer(&addr.to_string(), peer_addr) {
+    match gossip::add_peer(&addr.to_string(), peer_addr, None) {
         Ok(_) => debug!("Peer registered with gossip system: {}", peer_addr),
         Err(e) => warn!("Failed to register peer with gossip system: {}", e),
     }
@@ -179,9 +368,15 @@ pub fn connect_to_peer(peer_addr: &str) -> Result<()> {
 pub fn disconnect_from_peer(peer_addr: &str) -> Result<()> {
     info!("Disconnecting from peer: {}", peer_addr);
     
-    let mut state = NETWORK_STATE.lock().unwrap();
+    let (connection, stream) = {
+        let mut state = NETWORK_STATE.lock().unwrap();
+        (state.connections.remove(peer_addr), state.streams.remove(peer_addr))
+    };
+    if let Some(stream) = stream {
+        close_stream(stream);
+    }
     
-    if let Some(conn) = state.connections.remove(peer_addr) {
+    if connection.is_some() {
         debug!("Connection to {} removed", peer_addr);
         
         // Unregister from gossip system
@@ -202,31 +397,72 @@ pub fn list_connections() -> Result<Vec<ConnectionInfo>> {
     
     let mut connections = Vec::new();
     for (_, conn) in &state.connections {
-        connections.push(ConnectionInfo {
-            address: conn.address.clone(),
-            connected_at: conn.connected_at,
-            status: conn.status,
-        });
+        connections.push(conn.info());
     }
     
     Ok(connections)
 }
 
+/// Get information about the connection to a specific peer
+pub fn get_connection(peer_addr: &str) -> Result<ConnectionInfo> {
+    let state = NETWORK_STATE.lock().unwrap();
+    
+    state.connections.get(peer_addr)
+        .map(Connection::info)
+        .ok_or_else(|| anyhow::anyhow!("No active connection to {}", peer_addr))
+}
+
 /// Send data to a specific peer
 pub fn send_data(peer_addr: &str, data: &[u8]) -> Result<usize> {
     debug!("Sending {} bytes to {}", data.len(), peer_addr);
     
     // Check if we have an active connection
-    let state = NETWORK_STATE.lock().unwrap();
+    let (stream, timeout) = {
+        let mut state = NETWORK_STATE.lock().unwrap();
+        
+        let connection = match state.connections.get_mut(peer_addr) {
+            Some(connection) => connection,
+            None => return Err(anyhow::anyhow!("No active connection to {}", peer_addr)),
+        };
+        connection.last_activity = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        
+        let stream = state.streams.get(peer_addr).cloned()
+            .ok_or_else(|| anyhow::anyhow!("No active connection to {}", peer_addr))?;
+        (stream, connection_timeout(&state.config))
+    };
     
-    if !state.connections.contains_key(peer_addr) {
-        return Err(anyhow::anyhow!("No active connection to {}", peer_addr));
+    // The state lock is released while sending, so a slow peer holds up only its own stream
+    runtime()?.block_on(with_timeout(timeout, async {
+        Ok(stream.lock().await.send(data).await?)
+    }))
+    .with_context(|| format!("Failed to send data to {}", peer_addr))?;
+    
+    Ok(data.len())
+}
+
+/// Re-read `.network/config.json` without restarting the network subsystem
+///
+/// Limits, the allow list and the TLS settings of new outgoing connections
+/// apply at once; a changed bind address, port or listener TLS setting
+/// only takes effect when the services are restarted.
+pub fn reload_config() -> Result<()> {
+    let config_path = constants::root_dir().join(".network").join("config.json");
+    if !config_path.exists() {
+        debug!("No network configuration to reload");
+        return Ok(());
     }
+    let config = load_network_config(&config_path)?;
     
-    // TODO: In a real implementation, we would send data over the connection
-    // For now, we'll just return the data length as if it was sent
+    let mut state = NETWORK_STATE.lock().unwrap();
+    if state.status == NetworkStatus::Online
+        && (config.bind_address != state.config.bind_address || config.port != state.config.port) {
+        warn!("Network bind address changed to {}:{}; restart the network services to apply it",
+              config.bind_address, config.port);
+    }
+    state.config = config;
     
-    Ok(data.len())
+    info!("Network configuration reloaded");
+    Ok(())
 }
 
 /// Load network configuration from file
@@ -250,6 +486,15 @@ struct NetworkState {
     
     /// Active connections
     connections: HashMap<String, Connection>,
+    
+    /// Streams of the active connections
+    streams: HashMap<String, Arc<AsyncMutex<PeerStream>>>,
+    
+    /// Peers accepted but still completing their handshake
+    pending: usize,
+    
+    /// Listener accepting peers, while the services run
+    listener: Option<Listener>,
 }
 
 impl NetworkState {
@@ -262,14 +507,56 @@ impl NetworkState {
                 max_connections: 100,
                 connection_timeout_seconds: 30,
                 tls_enabled: false,
+                tls: TlsConfig::default(),
                 allowed_ips: Vec::new(),
             },
             status: NetworkStatus::Initializing,
             connections: HashMap::new(),
+            streams: HashMap::new(),
+            pending: 0,
+            listener: None,
+        }
+    }
+}
+
+/// Task accepting peers
+struct Listener {
+    /// Address the listener is bound to
+    address: SocketAddr,
+    
+    /// Sent to end the accept loop
+    stop: oneshot::Sender<()>,
+    
+    /// The accept task
+    task: JoinHandle<()>,
+}
+
+impl Listener {
+    /// End the accept loop and wait for it
+    fn stop(self) {
+        debug!("Stopping the network listener on {}", self.address);
+        let _ = self.stop.send(());
+        
+        // The task runs on the runtime, which exists as long as a listener does
+        if let Some(runtime) = RUNTIME.get() {
+            if let Err(e) = runtime.block_on(self.task) {
+                warn!("Network listener task failed: {}", e);
+            }
         }
     }
 }
 
+/// A connection slot reserved by a peer that is still completing its handshake
+///
+/// Released when dropped, once the peer is tracked or refused.
+struct PendingSlot;
+
+impl Drop for PendingSlot {
+    fn drop(&mut self) {
+        NETWORK_STATE.lock().unwrap().pending -= 1;
+    }
+}
+
 /// Network configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct NetworkConfig {
@@ -291,6 +578,10 @@ struct NetworkConfig {
     /// Whether TLS is enabled
     tls_enabled: bool,
     
+    /// Certificates used when TLS is enabled
+    #[serde(default)]
+    tls: TlsConfig,
+    
     /// List of allowed IP addresses (empty for all)
     allowed_ips: Vec<String>,
 }
@@ -336,25 +627,46 @@ struct Connection {
     /// When the connection was established
     connected_at: u64,
     
+    /// When data was last sent over the connection
+    last_activity: u64,
+    
     /// Current status
     status: ConnectionStatus,
 }
 
+impl Connection {
+    /// Connection information for API responses
+    fn info(&self) -> ConnectionInfo {
+        ConnectionInfo {
+            address: self.address.clone(),
+            connected_at: self.connected_at,
+            last_activity: self.last_activity,
+            status: self.status,
+        }
+    }
+}
+
 /// Connection status
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum ConnectionStatus {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConnectionStatus {
     /// Connecting
     Connecting,
     
     /// Connected and ready
     Connected,
     
+    /// Being closed
+    Disconnecting,
+    
+    /// No activity within the connection timeout
+    TimedOut,
+    
     /// Error state
     Error,
 }
 
 /// Connection information for API responses
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConnectionInfo {
     /// Remote address
     pub address: String,
@@ -362,6 +674,9 @@ pub struct ConnectionInfo {
     /// When the connection was established
     pub connected_at: u64,
     
+    /// When data was last sent over the connection (seconds since epoch)
+    pub last_activity: u64,
+    
     /// Current status
     pub status: ConnectionStatus,
 }
@@ -412,7 +727,7 @@ pub fn configure(config: NetworkConfigOptions) -> Result<()> {
     }
     
     // Save configuration to disk
-    let network_dir = PathBuf::from(constants::ROOT_DIR).join(".network");
+    let network_dir = constants::root_dir().join(".network");
     let config_path = network_dir.join("config.json");
     
     let config_json = serde_json::to_string_pretty(&state.config)?;
@@ -443,3 +758,96 @@ pub struct NetworkConfigOptions {
     /// Whether TLS is enabled
     pub tls_enabled: Option<bool>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    
+    #[test]
+    fn connection_info_round_trips_through_json() {
+        let statuses = [
+            ConnectionStatus::Connecting,
+            ConnectionStatus::Connected,
+            ConnectionStatus::Disconnecting,
+            ConnectionStatus::TimedOut,
+            ConnectionStatus::Error,
+        ];
+        for status in statuses {
+            let info = ConnectionInfo {
+                address: "10.0.0.7:29900".to_string(),
+                connected_at: 1_700_000_000,
+                last_activity: 1_700_000_042,
+                status,
+            };
+            let json = serde_json::to_string(&info).unwrap();
+            let parsed: ConnectionInfo = serde_json::from_str(&json).unwrap();
+            
+            assert_eq!(parsed.address, info.address);
+            assert_eq!((parsed.connected_at, parsed.last_activity), (info.connected_at, info.last_activity));
+            assert_eq!(parsed.status, status);
+        }
+        
+        let json = serde_json::json!({
+            "address": "10.0.0.7:29900",
+            "connected_at": 1,
+            "last_activity": 2,
+            "status": "TimedOut",
+        });
+        assert_eq!(serde_json::from_value::<ConnectionInfo>(json).unwrap().status, ConnectionStatus::TimedOut);
+    }
+    
+    #[test]
+    fn get_connection_follows_a_peer_from_connect_to_disconnect() {
+        let peer = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = peer.local_addr().unwrap().to_string();
+        assert!(get_connection(&addr).is_err());
+        
+        connect_to_peer(&addr).unwrap();
+        let (mut accepted, _) = peer.accept().unwrap();
+        let info = get_connection(&addr).unwrap();
+        assert_eq!(info.address, addr);
+        assert_eq!(info.status, ConnectionStatus::Connected);
+        assert!(info.last_activity >= info.connected_at);
+        
+        assert_eq!(send_data(&addr, b"ping").unwrap(), 4);
+        let mut received = [0u8; 4];
+        accepted.read_exact(&mut received).unwrap();
+        assert_eq!(&received, b"ping");
+        assert!(get_connection(&addr).unwrap().last_activity >= info.last_activity);
+        assert!(list_connections().unwrap().iter().any(|c| c.address == addr));
+        
+        disconnect_from_peer(&addr).unwrap();
+        assert!(get_connection(&addr).is_err());
+        assert!(send_data(&addr, b"ping").is_err());
+    }
+    
+    #[test]
+    fn reloading_applies_the_configuration_file_in_place() {
+        let config_path = constants::root_dir().join(".network").join("config.json");
+        fs::create_dir_all(config_path.parent().unwrap()).unwrap();
+        let config = serde_json::json!({
+            "bind_address": "127.0.0.1",
+            "port": 31999,
+            "discovery_enabled": false,
+            "max_connections": 100,
+            "connection_timeout_seconds": 7,
+            "tls_enabled": false,
+            "allowed_ips": [],
+        });
+        fs::write(&config_path, config.to_string()).unwrap();
+        reload_config().unwrap();
+        {
+            let state = NETWORK_STATE.lock().unwrap();
+            assert_eq!((state.config.port, state.config.connection_timeout_seconds), (31999, 7));
+        }
+        
+        // A broken file leaves the running configuration alone
+        fs::write(&config_path, "{").unwrap();
+        assert!(reload_config().is_err());
+        assert_eq!(NETWORK_STATE.lock().unwrap().config.port, 31999);
+        
+        fs::remove_file(&config_path).unwrap();
+        reload_config().unwrap();
+    }
+}
@@ -0,0 +1,324 @@
+// SentientOS Network TLS
+// Certificates and rustls endpoints of peer connections
+
+use anyhow::{Result, Context};
+use tracing::{info, debug};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Write};
+use std::net::SocketAddr;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use serde::{Serialize, Deserialize};
+use rustls::{Certificate, ClientConfig, PrivateKey, RootCertStore, ServerConfig, ServerName};
+use rustls::server::AllowAnyAuthenticatedClient;
+use rustls_pemfile::Item;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio_rustls::{client, server, TlsAcceptor, TlsConnector};
+
+use crate::core::constants;
+
+// Constants
+const TLS_DIR: &str = ".network/tls";
+const CERT_FILE: &str = "cert.pem";
+const KEY_FILE: &str = "key.pem";
+
+/// Certificate files of the node, under `tls` in `.network/config.json`
+///
+/// Relative paths are resolved against the SentientOS root.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    /// PEM certificate chain the node presents to peers
+    pub cert_pem_path: PathBuf,
+    
+    /// PEM private key of the certificate
+    pub key_pem_path: PathBuf,
+    
+    /// PEM CA certificates that peers' certificates must be signed by
+    ///
+    /// Without it, incoming peers are not asked for a certificate and
+    /// outgoing connections cannot be made, as the peer cannot be verified.
+    pub ca_cert_path: Option<PathBuf>,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            cert_pem_path: Path::new(TLS_DIR).join(CERT_FILE),
+            key_pem_path: Path::new(TLS_DIR).join(KEY_FILE),
+            ca_cert_path: None,
+        }
+    }
+}
+
+/// A peer connection, encrypted when TLS is enabled
+pub enum PeerStream {
+    /// Plain TCP
+    Plain(TcpStream),
+    
+    /// TLS connection a peer opened to this node
+    Server(Box<server::TlsStream<TcpStream>>),
+    
+    /// TLS connection this node opened to a peer
+    Client(Box<client::TlsStream<TcpStream>>),
+}
+
+impl PeerStream {
+    /// Whether the connection is encrypted
+    pub fn is_tls(&self) -> bool {
+        !matches!(self, PeerStream::Plain(_))
+    }
+    
+    /// Write all of `data` and flush it
+    pub async fn send(&mut self, data: &[u8]) -> io::Result<()> {
+        match self {
+            PeerStream::Plain(socket) => {
+                socket.write_all(data).await?;
+                socket.flush().await
+            }
+            PeerStream::Server(stream) => {
+                stream.write_all(data).await?;
+                stream.flush().await
+            }
+            PeerStream::Client(stream) => {
+                stream.write_all(data).await?;
+                stream.flush().await
+            }
+        }
+    }
+    
+    /// Close the connection; a TLS peer is sent a close_notify first
+    pub async fn close(&mut self) {
+        let closed = match self {
+            PeerStream::Plain(socket) => socket.shutdown().await,
+            PeerStream::Server(stream) => stream.shutdown().await,
+            PeerStream::Client(stream) => stream.shutdown().await,
+        };
+        if let Err(e) = closed {
+            debug!("Failed to close peer connection cleanly: {}", e);
+        }
+    }
+}
+
+/// rustls configuration of the node's listener
+///
+/// With a CA configured, peers must present a certificate signed by it.
+pub fn server_config(config: &TlsConfig) -> Result<Arc<ServerConfig>> {
+    let certs = load_certs(&resolve(&config.cert_pem_path))?;
+    let key = load_key(&resolve(&config.key_pem_path))?;
+    
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = match &config.ca_cert_path {
+        Some(ca_cert_path) => {
+            let roots = load_roots(&resolve(ca_cert_path))?;
+            builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+        }
+        None => builder.with_no_client_auth(),
+    };
+    let server_config = builder.with_single_cert(certs, key)
+        .context("The TLS certificate does not match its key")?;
+    Ok(Arc::new(server_config))
+}
+
+/// rustls configuration of connections to peers
+///
+/// Peers are verified against the configured CA, and the node presents its
+/// own certificate for listeners that ask for one.
+pub fn client_config(config: &TlsConfig) -> Result<Arc<ClientConfig>> {
+    let ca_cert_path = config.ca_cert_path.as_ref()
+        .ok_or_else(|| anyhow::anyhow!("TLS connections to peers need tls.ca_cert_path in .network/config.json to verify them"))?;
+    let roots = load_roots(&resolve(ca_cert_path))?;
+    let certs = load_certs(&resolve(&config.cert_pem_path))?;
+    let key = load_key(&resolve(&config.key_pem_path))?;
+    
+    let client_config = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots)
+        .with_client_auth_cert(certs, key)
+        .context("The TLS certificate does not match its key")?;
+    Ok(Arc::new(client_config))
+}
+
+/// Complete the TLS handshake of a connection a peer opened
+pub async fn accept(acceptor: &TlsAcceptor, socket: TcpStream) -> Result<PeerStream> {
+    let stream = acceptor.accept(socket).await.context("TLS handshake failed")?;
+    Ok(PeerStream::Server(Box::new(stream)))
+}
+
+/// Complete the TLS handshake of a connection to a peer
+///
+/// Peers are addressed by IP, so their certificate must name that address.
+pub async fn connect(connector: &TlsConnector, addr: SocketAddr, socket: TcpStream) -> Result<PeerStream> {
+    let stream = connector.connect(ServerName::IpAddress(addr.ip()), socket).await
+        .with_context(|| format!("TLS handshake with {} failed", addr))?;
+    Ok(PeerStream::Client(Box::new(stream)))
+}
+
+/// Generate a self-signed certificate and key for a development node
+///
+/// Writes `cert.pem` and `key.pem` to the directory; the key is readable
+/// by the owner only. The common name is also the certificate's subject
+/// alternative name, so give the IP address peers connect to. Existing
+/// files are never overwritten.
+pub fn generate_self_signed_cert(common_name: &str, output_dir: &Path) -> Result<()> {
+    let cert_path = output_dir.join(CERT_FILE);
+    let key_path = output_dir.join(KEY_FILE);
+    for path in [&cert_path, &key_path] {
+        if path.exists() {
+            anyhow::bail!("{:?} already exists; remove it to generate a new certificate", path);
+        }
+    }
+    
+    let mut params = rcgen::CertificateParams::new(vec![common_name.to_string()]);
+    params.distinguished_name.push(rcgen::DnType::CommonName, common_name);
+    let cert = rcgen::Certificate::from_params(params)
+        .context("Failed to generate the certificate")?;
+    let cert_pem = cert.serialize_pem().context("Failed to encode the certificate")?;
+    let key_pem = cert.serialize_private_key_pem();
+    
+    fs::create_dir_all(output_dir)?;
+    let mut key_file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&key_path)
+        .with_context(|| format!("Failed to create {:?}", key_path))?;
+    key_file.write_all(key_pem.as_bytes())?;
+    crate::core::fs::write_atomic(&cert_path, cert_pem.as_bytes())?;
+    
+    info!("Generated self-signed certificate for {} in {:?}", common_name, output_dir);
+    Ok(())
+}
+
+/// Directory `tls init` writes to, and where the default configuration looks
+pub fn default_dir() -> PathBuf {
+    constants::root_dir().join(TLS_DIR)
+}
+
+/// Resolve a configured path against the SentientOS root
+fn resolve(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        constants::root_dir().join(path)
+    }
+}
+
+/// Read the certificates of a PEM file
+fn load_certs(path: &Path) -> Result<Vec<Certificate>> {
+    let file = File::open(path).with_context(|| format!("Failed to open certificate {:?}", path))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .with_context(|| format!("Failed to read certificate {:?}", path))?;
+    if certs.is_empty() {
+        anyhow::bail!("No certificate found in {:?}", path);
+    }
+    
+    debug!("Loaded {} certificate(s) from {:?}", certs.len(), path);
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+/// Read the first private key of a PEM file
+fn load_key(path: &Path) -> Result<PrivateKey> {
+    let file = File::open(path).with_context(|| format!("Failed to open private key {:?}", path))?;
+    let items = rustls_pemfile::read_all(&mut BufReader::new(file))
+        .with_context(|| format!("Failed to read private key {:?}", path))?;
+    
+    items.into_iter()
+        .find_map(|item| match item {
+            Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| anyhow::anyhow!("No private key found in {:?}", path))
+}
+
+/// Read the CA certificates of a PEM file
+fn load_roots(path: &Path) -> Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots.add(&cert).with_context(|| format!("Invalid CA certificate in {:?}", path))?;
+    }
+    Ok(roots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+    
+    /// Self-signed certificate for 127.0.0.1 in a fresh directory
+    fn certificate(name: &str) -> (PathBuf, TlsConfig) {
+        let dir = std::env::temp_dir().join(format!("sentient-tls-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        generate_self_signed_cert("127.0.0.1", &dir).unwrap();
+        let config = TlsConfig {
+            cert_pem_path: dir.join(CERT_FILE),
+            key_pem_path: dir.join(KEY_FILE),
+            ca_cert_path: Some(dir.join(CERT_FILE)),
+        };
+        (dir, config)
+    }
+    
+    #[test]
+    fn generated_certificates_load_and_are_never_overwritten() {
+        let (dir, config) = certificate("generate");
+        assert_eq!(fs::metadata(dir.join(KEY_FILE)).unwrap().permissions().mode() & 0o777, 0o600);
+        assert_eq!(load_certs(&config.cert_pem_path).unwrap().len(), 1);
+        load_key(&config.key_pem_path).unwrap();
+        
+        let cert = fs::read(dir.join(CERT_FILE)).unwrap();
+        assert!(generate_self_signed_cert("127.0.0.1", &dir).is_err());
+        assert_eq!(fs::read(dir.join(CERT_FILE)).unwrap(), cert);
+        
+        // A key is never taken for a certificate, nor the other way round
+        assert!(load_certs(&config.key_pem_path).is_err());
+        assert!(load_key(&config.cert_pem_path).is_err());
+        
+        // Outgoing connections need a CA to verify peers; listeners don't
+        let no_ca = TlsConfig { ca_cert_path: None, ..config.clone() };
+        server_config(&no_ca).unwrap();
+        assert!(client_config(&no_ca).is_err());
+        
+        // Relative paths are under the root
+        assert_eq!(resolve(Path::new(TLS_DIR)), default_dir());
+        assert_eq!(resolve(&dir), dir);
+        
+        fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[test]
+    fn peers_trusting_the_ca_exchange_data_both_ways_authenticated() {
+        let (dir, config) = certificate("handshake");
+        let acceptor = TlsAcceptor::from(server_config(&config).unwrap());
+        let connector = TlsConnector::from(client_config(&config).unwrap());
+        
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        let received = runtime.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let server = tokio::spawn(async move {
+                let (socket, _) = listener.accept().await.unwrap();
+                let mut stream = match accept(&acceptor, socket).await.unwrap() {
+                    PeerStream::Server(stream) => stream,
+                    _ => unreachable!(),
+                };
+                let mut received = Vec::new();
+                stream.read_to_end(&mut received).await.unwrap();
+                received
+            });
+            
+            let socket = TcpStream::connect(addr).await.unwrap();
+            let mut stream = connect(&connector, addr, socket).await.unwrap();
+            assert!(stream.is_tls());
+            stream.send(b"hello over tls").await.unwrap();
+            stream.close().await;
+            server.await.unwrap()
+        });
+        assert_eq!(received, b"hello over tls");
+        
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -17,13 +17,13 @@ pub fn install_package(name: &str, version: Option<&str>) -> Result<()> {
     let go_check = Command::new("which")
         .arg("go")
         .output()?;
-        
+    
     if !go_check.status.success() {
         return Err(anyhow::anyhow!("go not found, please install Go"));
     }
     
     // Create Go packages directory
-    let go_dir = PathBuf::from(constants::ROOT_DIR).join("packages").join("go");
+    let go_dir = constants::root_dir().join("packages").join("go");
     fs::create_dir_all(&go_dir)?;
     
     // Set custom GOPATH to install within SentientOS packages directory
@@ -63,7 +63,7 @@ pub fn remove_package(name: &str) -> Result<()> {
     // Go doesn't have a built-in uninstall command,
     // so we'll manually remove the binary
     
-    let go_dir = PathBuf::from(constants::ROOT_DIR).join("packages").join("go");
+    let go_dir = constants::root_dir().join("packages").join("go");
     
     // Extract binary name from package path
     let binary_name = name.split('/').last().unwrap_or(name);
@@ -98,7 +98,7 @@ pub fn remove_package(name: &str) -> Result<()> {
 pub fn run_package(name: &str, args: &[&str]) -> Result<()> {
     info!("Running Go package: {}", name);
     
-    let go_dir = PathBuf::from(constants::ROOT_DIR).join("packages").join("go");
+    let go_dir = constants::root_dir().join("packages").join("go");
     
     // Extract binary name from package path
     let binary_name = name.split('/').last().unwrap_or(name);
@@ -142,7 +142,7 @@ pub fn search_packages(query: &str) -> Result<Vec<String>> {
     let go_check = Command::new("which")
         .arg("go")
         .output()?;
-        
+    
     if !go_check.status.success() {
         return Err(anyhow::anyhow!("go not found, please install Go"));
     }
@@ -163,7 +163,7 @@ pub fn search_packages(query: &str) -> Result<Vec<String>> {
             let search_cmd = Command::new("go-search")
                 .arg(query)
                 .output();
-                
+            
             if let Ok(search_output) = search_cmd {
                 if search_output.status.success() {
                     let stdout = String::from_utf8_lossy(&search_output.stdout);
@@ -12,17 +12,11 @@ use crate::core::constants;
 pub fn install_package(name: &str, version: Option<&str>) -> Result<()> {
     info!("Installing Java package: {}", name);
     
-    // Check if Java is installed
-    let java_check = Command::new("which")
-        .arg("java")
-        .output()?;
-        
-    if !java_check.status.success() {
-        return Err(anyhow::anyhow!("Java not found, please install JDK"));
-    }
+    // Resolve java, failing with guidance if it is missing
+    super::tools::resolve("java")?;
     
     // Create packages directory
-    let java_dir = PathBuf::from(constants::ROOT_DIR).join("packages").join("java");
+    let java_dir = constants::root_dir().join("packages").join("java");
     fs::create_dir_all(&java_dir)?;
     
     // Determine if the package uses Maven format (groupId:artifactId)
@@ -38,14 +32,8 @@ pub fn install_package(name: &str, version: Option<&str>) -> Result<()> {
 
 /// Install a Maven package
 fn install_maven_package(java_dir: &PathBuf, name: &str, version: Option<&str>) -> Result<()> {
-    // Check if Maven is installed
-    let maven_check = Command::new("which")
-        .arg("mvn")
-        .output()?;
-        
-    if !maven_check.status.success() {
-        return Err(anyhow::anyhow!("Maven not found, please install Maven"));
-    }
+    // Resolve mvn, failing with guidance if it is missing
+    let mvn = super::tools::resolve("mvn")?;
     
     // Create a temporary pom file
     let pom_dir = java_dir.join("maven");
@@ -88,7 +76,7 @@ fn install_maven_package(java_dir: &PathBuf, name: &str, version: Option<&str>)
     fs::write(&pom_path, pom_content)?;
     
     // Run Maven to download the dependency
-    let mut cmd = Command::new("mvn");
+    let mut cmd = Command::new(&mvn);
     cmd.current_dir(&pom_dir);
     cmd.args(["dependency:copy-dependencies", "-DoutputDirectory=./lib"]);
     
@@ -124,7 +112,7 @@ fn install_jar_package(java_dir: &PathBuf, name: &str, version: Option<&str>) ->
 pub fn remove_package(name: &str) -> Result<()> {
     info!("Removing Java package: {}", name);
     
-    let java_dir = PathBuf::from(constants::ROOT_DIR).join("packages").join("java");
+    let java_dir = constants::root_dir().join("packages").join("java");
     
     if name.contains(":") {
         // Maven package
@@ -170,16 +158,10 @@ pub fn remove_package(name: &str) -> Result<()> {
 pub fn run_package(name: &str, args: &[&str]) -> Result<()> {
     info!("Running Java package: {}", name);
     
-    // Check if Java is installed
-    let java_check = Command::new("which")
-        .arg("java")
-        .output()?;
-        
-    if !java_check.status.success() {
-        return Err(anyhow::anyhow!("Java not found, please install JDK"));
-    }
+    // Resolve java, failing with guidance if it is missing
+    let java = super::tools::resolve("java")?;
     
-    let java_dir = PathBuf::from(constants::ROOT_DIR).join("packages").join("java");
+    let java_dir = constants::root_dir().join("packages").join("java");
     
     if name.contains(":") {
         // Maven package
@@ -208,7 +190,7 @@ pub fn run_package(name: &str, args: &[&str]) -> Result<()> {
         
         if let Some(path) = jar_path {
             // Run the JAR file
-            let mut cmd = Command::new("java");
+            let mut cmd = Command::new(&java);
             cmd.arg("-jar");
             cmd.arg(path);
             cmd.args(args);
@@ -240,7 +222,7 @@ pub fn run_package(name: &str, args: &[&str]) -> Result<()> {
         
         if let Some(path) = jar_path {
             // Run the JAR file
-            let mut cmd = Command::new("java");
+            let mut cmd = Command::new(&java);
             cmd.arg("-jar");
             cmd.arg(path);
             cmd.args(args);
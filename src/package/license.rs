@@ -0,0 +1,391 @@
+// SentientOS Package Manager - License Compliance
+// Aggregates installed package licenses and checks them against the license policy
+
+use anyhow::{Result, Context};
+use tracing::warn;
+use std::fs;
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+
+use super::{Ecosystem, InstalledPackage};
+use crate::core::constants;
+use crate::store;
+
+/// Group name for packages whose license could not be determined
+pub const UNKNOWN_LICENSE: &str = "UNKNOWN";
+
+/// License policy, stored in `.config/licenses.json`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LicensePolicy {
+    /// SPDX identifiers that are allowed; empty allows anything not denied
+    #[serde(default)]
+    pub allow: Vec<String>,
+    
+    /// SPDX identifiers that are never allowed
+    #[serde(default)]
+    pub deny: Vec<String>,
+    
+    /// Refuse to install packages whose license is denied
+    #[serde(default)]
+    pub block_on_install: bool,
+}
+
+/// Compliance status of a single package license
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LicenseStatus {
+    /// Allowed by the policy
+    Allowed,
+    
+    /// On the deny list
+    Denied,
+    
+    /// Not on a non-empty allow list
+    NotAllowed,
+    
+    /// License could not be determined
+    Unknown,
+}
+
+impl LicenseStatus {
+    /// Whether this status is a policy violation
+    pub fn is_violation(self) -> bool {
+        matches!(self, LicenseStatus::Denied | LicenseStatus::NotAllowed)
+    }
+}
+
+/// License information for an installed package
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageLicense {
+    /// Registry key of the package
+    pub package: String,
+    
+    /// Package version
+    pub version: String,
+    
+    /// Package ecosystem
+    pub ecosystem: Ecosystem,
+    
+    /// License as declared by the package, if any
+    pub declared: Option<String>,
+    
+    /// SPDX identifier, or the declared license if it could not be normalized
+    pub license: String,
+    
+    /// Policy status
+    pub status: LicenseStatus,
+}
+
+/// License compliance report over all installed packages
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LicenseReport {
+    /// Report timestamp (seconds since epoch)
+    pub generated_at: u64,
+    
+    /// Package keys grouped by license
+    pub by_license: BTreeMap<String, Vec<String>>,
+    
+    /// Per-package details
+    pub packages: Vec<PackageLicense>,
+}
+
+impl LicenseReport {
+    /// Packages that violate the license policy
+    pub fn violations(&self) -> impl Iterator<Item = &PackageLicense> {
+        self.packages.iter().filter(|p| p.status.is_violation())
+    }
+    
+    /// Packages whose license could not be determined
+    pub fn unknown(&self) -> impl Iterator<Item = &PackageLicense> {
+        self.packages.iter().filter(|p| p.status == LicenseStatus::Unknown)
+    }
+}
+
+/// Build a license report for every installed package
+pub fn license_report() -> Result<LicenseReport> {
+    let registry = super::load_registry()?;
+    let policy = load_policy()?;
+    
+    let mut keys: Vec<&String> = registry.packages.keys().collect();
+    keys.sort();
+    
+    let mut by_license: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut packages = Vec::new();
+    
+    for key in keys {
+        let package = &registry.packages[key];
+        let declared = declared_license(package);
+        let license = declared.as_deref()
+            .map(|raw| normalize_spdx(raw).unwrap_or_else(|| raw.trim().to_string()))
+            .unwrap_or_else(|| UNKNOWN_LICENSE.to_string());
+        let status = policy.status(&license);
+        
+        by_license.entry(license.clone()).or_default().push(key.clone());
+        packages.push(PackageLicense {
+            package: key.clone(),
+            version: package.version.clone(),
+            ecosystem: package.ecosystem.clone(),
+            declared,
+            license,
+            status,
+        });
+    }
+    
+    Ok(LicenseReport {
+        generated_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        by_license,
+        packages,
+    })
+}
+
+/// Check a package license at install time
+///
+/// Fails if the policy blocks installs and the license is denied, unless
+/// `allow_denied` is set, in which case the override is audited.
+pub fn check_install(package: &str, declared: Option<&str>, allow_denied: bool) -> Result<()> {
+    let policy = load_policy()?;
+    if !policy.block_on_install {
+        return Ok(());
+    }
+    
+    let license = match declared {
+        Some(raw) => normalize_spdx(raw).unwrap_or_else(|| raw.trim().to_string()),
+        None => return Ok(()),
+    };
+    
+    if policy.status(&license) != LicenseStatus::Denied {
+        return Ok(());
+    }
+    
+    if allow_denied {
+        let message = format!("Installed {} despite denied license {}", package, license);
+        warn!("{}", message);
+        crate::logs::ship::ship_audit("package.license", &message);
+        return Ok(());
+    }
+    
+    anyhow::bail!("Package {} is licensed under {}, which is on the license deny list", package, license)
+}
+
+/// License declared by a package, if its ecosystem exposes one
+pub fn declared_license(package: &InstalledPackage) -> Option<String> {
+    let license = match package.ecosystem {
+        Ecosystem::Native => store::show_package_details(&package.name).ok()
+            .flatten()
+            .map(|p| p.license),
+        Ecosystem::Npm => npm_license(package),
+        Ecosystem::Python => python_license(&package.name),
+        _ => None,
+    };
+    
+    license.filter(|l| !l.trim().is_empty())
+}
+
+/// Load the license policy, defaulting to an empty policy
+pub fn load_policy() -> Result<LicensePolicy> {
+    let path = constants::root_dir().join(".config").join("licenses.json");
+    if !path.exists() {
+        return Ok(LicensePolicy::default());
+    }
+    
+    let data = fs::read_to_string(&path)?;
+    serde_json::from_str(&data).context("Invalid license policy in licenses.json")
+}
+
+impl LicensePolicy {
+    /// Status of a normalized license under this policy
+    pub fn status(&self, license: &str) -> LicenseStatus {
+        if license == UNKNOWN_LICENSE {
+            return LicenseStatus::Unknown;
+        }
+        
+        // Expressions like "MIT OR Apache-2.0" are checked term by term
+        let terms: Vec<&str> = license.split(['(', ')', ' '])
+            .filter(|t| !t.is_empty() && !matches!(*t, "OR" | "AND" | "WITH"))
+            .collect();
+        
+        let listed = |list: &[String], term: &str| list.iter().any(|l| l.eq_ignore_ascii_case(term));
+        
+        if terms.iter().any(|t| listed(&self.deny, t)) {
+            LicenseStatus::Denied
+        } else if !self.allow.is_empty() && !terms.iter().any(|t| listed(&self.allow, t)) {
+            LicenseStatus::NotAllowed
+        } else {
+            LicenseStatus::Allowed
+        }
+    }
+}
+
+/// Normalize a free-form license name to an SPDX identifier where known
+pub fn normalize_spdx(raw: &str) -> Option<String> {
+    let key: String = raw.trim()
+        .trim_start_matches("License :: OSI Approved :: ")
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '.' || *c == '+')
+        .collect();
+    
+    let spdx = match key.as_str() {
+        "mit" | "mitlicense" | "themitlicense" | "expat" => "MIT",
+        "apache2.0" | "apache2" | "apachelicense2.0" | "apachesoftwarelicense" | "apachelicenseversion2.0" | "asl2.0" => "Apache-2.0",
+        "bsd" | "bsdlicense" | "bsd3clause" | "newbsd" | "bsd3" => "BSD-3-Clause",
+        "bsd2clause" | "simplifiedbsd" | "freebsd" | "bsd2" => "BSD-2-Clause",
+        "isc" | "isclicense" | "isclicenseiscl" => "ISC",
+        "gpl" | "gpl3" | "gplv3" | "gpl3.0" | "gnugeneralpubliclicensev3gplv3" | "gpl3.0only" => "GPL-3.0-only",
+        "gpl3+" | "gplv3+" | "gpl3.0orlater" | "gnugeneralpubliclicensev3orlatergplv3+" => "GPL-3.0-or-later",
+        "gpl2" | "gplv2" | "gpl2.0" | "gnugeneralpubliclicensev2gplv2" | "gpl2.0only" => "GPL-2.0-only",
+        "gpl2+" | "gplv2+" | "gpl2.0orlater" | "gnugeneralpubliclicensev2orlatergplv2+" => "GPL-2.0-or-later",
+        "lgpl" | "lgpl3" | "lgplv3" | "lgpl3.0" | "gnulessergeneralpubliclicensev3lgplv3" => "LGPL-3.0-only",
+        "lgpl2.1" | "lgplv2.1" | "gnulessergeneralpubliclicensev2lgplv2" => "LGPL-2.1-only",
+        "agpl" | "agpl3" | "agplv3" | "agpl3.0" | "gnuafferogeneralpubliclicensev3" => "AGPL-3.0-only",
+        "mpl2.0" | "mpl2" | "mozillapubliclicense2.0mpl2.0" => "MPL-2.0",
+        "unlicense" | "theunlicense" => "Unlicense",
+        "cc00" | "cc01.0" | "cc0" => "CC0-1.0",
+        "psf" | "psfl" | "pythonsoftwarefoundationlicense" | "psf2.0" => "PSF-2.0",
+        "zlib" | "zlibliibpnglicense" => "Zlib",
+        "bsl1.0" | "boostsoftwarelicense" => "BSL-1.0",
+        _ => {
+            // Keep anything that already looks like an SPDX identifier or expression
+            let trimmed = raw.trim();
+            let is_expression = trimmed.contains(" OR ") || trimmed.contains(" AND ") || trimmed.contains(" WITH ");
+            let looks_spdx = !trimmed.is_empty()
+                && (is_expression || !trimmed.contains(' '))
+                && trimmed.chars().all(|c| c.is_ascii_alphanumeric() || "-.+() ".contains(c));
+            return looks_spdx.then(|| trimmed.to_string());
+        }
+    };
+    
+    Some(spdx.to_string())
+}
+
+/// License field from an installed npm package's package.json
+fn npm_license(package: &InstalledPackage) -> Option<String> {
+    let manifest = super::npm::read_manifest(&super::npm::package_dir(package).ok()?).ok()?;
+    manifest_license(&manifest)
+}
+
+/// License declared in an npm `package.json`
+pub(super) fn manifest_license(manifest: &serde_json::Value) -> Option<String> {
+    // Older packages use {"type": "..."} or a "licenses" array
+    match manifest.get("license") {
+        Some(serde_json::Value::String(license)) => Some(license.clone()),
+        Some(license) => license.get("type").and_then(|t| t.as_str()).map(str::to_string),
+        None => manifest.get("licenses")
+            .and_then(|l| l.get(0))
+            .and_then(|l| l.get("type"))
+            .and_then(|t| t.as_str())
+            .map(str::to_string),
+    }
+}
+
+/// License from a Python package's METADATA in the package venv
+fn python_license(name: &str) -> Option<String> {
+    let lib_dir = constants::root_dir()
+        .join("packages").join("python").join("venv").join("lib");
+    let wanted = name.to_lowercase().replace('-', "_");
+    
+    for python_dir in fs::read_dir(&lib_dir).ok()?.flatten() {
+        let site_packages = python_dir.path().join("site-packages");
+        let Ok(entries) = fs::read_dir(&site_packages) else { continue };
+        
+        for entry in entries.flatten() {
+            let file_name = entry.file_name().to_string_lossy().to_lowercase();
+            let Some(stem) = file_name.strip_suffix(".dist-info") else { continue };
+            let dist_name = stem.split('-').next().unwrap_or(stem).replace('-', "_");
+            if dist_name != wanted {
+                continue;
+            }
+            
+            let metadata = fs::read_to_string(entry.path().join("METADATA")).ok()?;
+            return metadata_license(&metadata);
+        }
+    }
+    
+    None
+}
+
+/// License from core metadata, preferring License-Expression over classifiers
+fn metadata_license(metadata: &str) -> Option<String> {
+    let headers: Vec<(&str, &str)> = metadata.lines()
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.split_once(": "))
+        .collect();
+    
+    let header = |name: &str| headers.iter()
+        .find(|(key, value)| key.eq_ignore_ascii_case(name) && !value.trim().is_empty() && *value != "UNKNOWN")
+        .map(|(_, value)| value.trim().to_string());
+    
+    header("License-Expression")
+        .or_else(|| headers.iter()
+            .find(|(key, value)| *key == "Classifier" && value.starts_with("License :: "))
+            .map(|(_, value)| value.rsplit(" :: ").next().unwrap_or(value).to_string()))
+        .or_else(|| header("License").filter(|l| !l.contains('\n') && l.len() <= 64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn policy(allow: &[&str], deny: &[&str]) -> LicensePolicy {
+        LicensePolicy {
+            allow: allow.iter().map(|l| l.to_string()).collect(),
+            deny: deny.iter().map(|l| l.to_string()).collect(),
+            block_on_install: true,
+        }
+    }
+    
+    #[test]
+    fn free_form_names_normalize_to_spdx() {
+        assert_eq!(normalize_spdx("The MIT License").as_deref(), Some("MIT"));
+        assert_eq!(normalize_spdx("Apache License, Version 2.0").as_deref(), Some("Apache-2.0"));
+        assert_eq!(normalize_spdx("License :: OSI Approved :: BSD License").as_deref(), Some("BSD-3-Clause"));
+        assert_eq!(normalize_spdx("GPLv3+").as_deref(), Some("GPL-3.0-or-later"));
+        
+        // Identifiers and expressions pass through, prose does not
+        assert_eq!(normalize_spdx("MIT OR Apache-2.0").as_deref(), Some("MIT OR Apache-2.0"));
+        assert_eq!(normalize_spdx("EUPL-1.2").as_deref(), Some("EUPL-1.2"));
+        assert_eq!(normalize_spdx("see the LICENSE file"), None);
+        assert_eq!(normalize_spdx("  "), None);
+    }
+    
+    #[test]
+    fn policies_check_expressions_term_by_term() {
+        let open = policy(&[], &["GPL-3.0-only"]);
+        assert_eq!(open.status("MIT"), LicenseStatus::Allowed);
+        assert_eq!(open.status("gpl-3.0-only"), LicenseStatus::Denied);
+        assert_eq!(open.status("(MIT OR GPL-3.0-only)"), LicenseStatus::Denied);
+        assert_eq!(open.status(UNKNOWN_LICENSE), LicenseStatus::Unknown);
+        
+        let strict = policy(&["MIT", "Apache-2.0"], &[]);
+        assert_eq!(strict.status("Apache-2.0 WITH LLVM-exception"), LicenseStatus::Allowed);
+        assert_eq!(strict.status("ISC"), LicenseStatus::NotAllowed);
+        assert!(strict.status("ISC").is_violation());
+        assert!(!strict.status(UNKNOWN_LICENSE).is_violation());
+    }
+    
+    #[test]
+    fn npm_manifests_declare_licenses_in_any_historic_form() {
+        let license = |manifest: serde_json::Value| manifest_license(&manifest);
+        
+        assert_eq!(license(serde_json::json!({"license": "ISC"})).as_deref(), Some("ISC"));
+        assert_eq!(license(serde_json::json!({"license": {"type": "MIT"}})).as_deref(), Some("MIT"));
+        assert_eq!(license(serde_json::json!({"licenses": [{"type": "BSD"}]})).as_deref(), Some("BSD"));
+        assert_eq!(license(serde_json::json!({"name": "left-pad"})), None);
+    }
+    
+    #[test]
+    fn python_metadata_prefers_expressions_over_classifiers() {
+        let metadata = "Metadata-Version: 2.4\nName: demo\nLicense: UNKNOWN\n\
+            Classifier: License :: OSI Approved :: MIT License\nLicense-Expression: Apache-2.0\n\nLicense: body text";
+        assert_eq!(metadata_license(metadata).as_deref(), Some("Apache-2.0"));
+        
+        let classified = "Name: demo\nClassifier: License :: OSI Approved :: MIT License\n";
+        assert_eq!(metadata_license(classified).as_deref(), Some("MIT License"));
+        
+        assert_eq!(metadata_license("Name: demo\nLicense: BSD\n").as_deref(), Some("BSD"));
+        assert_eq!(metadata_license("Name: demo\nLicense: UNKNOWN\n"), None);
+    }
+}
@@ -1,216 +1,209 @@
 // SentientOS Package Manager - Linux Package Handler
-// Handles apt, dnf, pacman, and other Linux package managers
+// Handles apt, dnf, pacman, zypper and apk through a detected backend
 
 use anyhow::{Result, Context};
-use tracing::{info, debug, warn, error};
+use tracing::{info, debug};
+use std::fmt;
 use std::process::Command;
-use std::path::PathBuf;
+use serde::{Serialize, Deserialize};
+use thiserror::Error;
 
-/// Detect the system package manager
-pub fn detect_package_manager() -> Result<&'static str> {
-    let package_managers = [
-        ("apt", "/usr/bin/apt"),
-        ("dnf", "/usr/bin/dnf"),
-        ("yum", "/usr/bin/yum"),
-        ("pacman", "/usr/bin/pacman"),
-        ("zypper", "/usr/bin/zypper"),
-        ("apk", "/sbin/apk"),
-        ("pkg", "/usr/sbin/pkg"),
+/// A Linux distribution's package manager
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LinuxBackend {
+    /// Debian, Ubuntu and derivatives
+    Apt,
+    
+    /// Fedora, RHEL and derivatives
+    Dnf,
+    
+    /// Arch Linux and derivatives
+    Pacman,
+    
+    /// openSUSE and SLES
+    Zypper,
+    
+    /// Alpine Linux
+    Apk,
+}
+
+/// No supported package manager was found on this machine
+#[derive(Debug, Clone, Error)]
+#[error("Unsupported Linux distribution: none of {} was found; set linux_backend in .package/config.json to override", .probed.join(", "))]
+pub struct UnsupportedDistro {
+    /// Binaries looked for
+    pub probed: Vec<String>,
+}
+
+impl LinuxBackend {
+    /// Every backend, in detection order
+    pub const ALL: [LinuxBackend; 5] = [
+        LinuxBackend::Apt,
+        LinuxBackend::Dnf,
+        LinuxBackend::Pacman,
+        LinuxBackend::Zypper,
+        LinuxBackend::Apk,
     ];
     
-    for (manager, path) in package_managers {
-        if std::path::Path::new(path).exists() {
-            return Ok(manager);
+    /// Binary the backend runs
+    pub fn binary(&self) -> &'static str {
+        match self {
+            LinuxBackend::Apt => "apt-get",
+            LinuxBackend::Dnf => "dnf",
+            LinuxBackend::Pacman => "pacman",
+            LinuxBackend::Zypper => "zypper",
+            LinuxBackend::Apk => "apk",
         }
     }
     
-    Err(anyhow::anyhow!("No supported package manager found"))
-}
-
-/// Install a Linux package
-pub fn install_package(name: &str, version: Option<&str>) -> Result<()> {
-    let pkg_manager = detect_package_manager()?;
-    info!("Installing Linux package {} using {}", name, pkg_manager);
+    /// Command line installing a package, optionally at a version
+    pub fn install_command(&self, name: &str, version: Option<&str>) -> Command {
+        let target = match version {
+            // dnf takes name-version, the others name=version
+            Some(version) if *self == LinuxBackend::Dnf => format!("{}-{}", name, version),
+            Some(version) => format!("{}={}", name, version),
+            None => name.to_string(),
+        };
+        
+        let mut cmd = self.command();
+        match self {
+            LinuxBackend::Apt => cmd.args(["install", "-y", "-q", &target]),
+            LinuxBackend::Dnf => cmd.args(["install", "-y", "-q", &target]),
+            LinuxBackend::Pacman => cmd.args(["-S", "--noconfirm", "--needed", &target]),
+            LinuxBackend::Zypper => cmd.args(["--non-interactive", "install", &target]),
+            LinuxBackend::Apk => cmd.args(["add", "-q", &target]),
+        };
+        cmd
+    }
     
-    match pkg_manager {
-        "apt" => {
-            let mut cmd = Command::new("apt");
-            cmd.args(["install", "-y"]);
-            
-            if let Some(ver) = version {
-                cmd.arg(&format!("{}={}", name, ver));
-            } else {
-                cmd.arg(name);
-            }
-            
-            // Use matrixbox container to isolate the installation
-            debug!("Running apt in MatrixBox container");
-            let output = cmd.output()?;
-            
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(anyhow::anyhow!("Failed to install package: {}\n{}", name, stderr));
-            }
-        },
-        "dnf" | "yum" => {
-            let mut cmd = Command::new(pkg_manager);
-            cmd.args(["install", "-y"]);
-            
-            if let Some(ver) = version {
-                cmd.arg(&format!("{}-{}", name, ver));
-            } else {
-                cmd.arg(name);
-            }
-            
-            let output = cmd.output()?;
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(anyhow::anyhow!("Failed to install package: {}\n{}", name, stderr));
-            }
-        },
-        "pacman" => {
-            let mut cmd = Command::new("pacman");
-            cmd.args(["-S", "--noconfirm"]);
-            
-            if let Some(ver) = version {
-                cmd.arg(&format!("{}={}", name, ver));
-            } else {
-                cmd.arg(name);
-            }
-            
-            let output = cmd.output()?;
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(anyhow::anyhow!("Failed to install package: {}\n{}", name, stderr));
-            }
-        },
-        "zypper" => {
-            let mut cmd = Command::new("zypper");
-            cmd.args(["install", "-y"]);
-            
-            if let Some(ver) = version {
-                cmd.arg(&format!("{}={}", name, ver));
-            } else {
-                cmd.arg(name);
-            }
-            
-            let output = cmd.output()?;
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(anyhow::anyhow!("Failed to install package: {}\n{}", name, stderr));
-            }
-        },
-        "apk" => {
-            let mut cmd = Command::new("apk");
-            cmd.args(["add"]);
-            
-            if let Some(ver) = version {
-                cmd.arg(&format!("{}={}", name, ver));
-            } else {
-                cmd.arg(name);
-            }
-            
-            let output = cmd.output()?;
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(anyhow::anyhow!("Failed to install package: {}\n{}", name, stderr));
-            }
-        },
-        "pkg" => {
-            let mut cmd = Command::new("pkg");
-            cmd.args(["install", "-y"]);
-            
-            if let Some(ver) = version {
-                cmd.arg(&format!("{}-{}", name, ver));
+    /// Command line removing a package
+    pub fn remove_command(&self, name: &str) -> Command {
+        let mut cmd = self.command();
+        match self {
+            LinuxBackend::Apt => cmd.args(["remove", "-y", "-q", name]),
+            LinuxBackend::Dnf => cmd.args(["remove", "-y", "-q", name]),
+            LinuxBackend::Pacman => cmd.args(["-R", "--noconfirm", name]),
+            LinuxBackend::Zypper => cmd.args(["--non-interactive", "remove", name]),
+            LinuxBackend::Apk => cmd.args(["del", "-q", name]),
+        };
+        cmd
+    }
+    
+    /// Command line searching packages
+    pub fn search_command(&self, query: &str) -> Command {
+        // apt-get has no search; apt-cache ships with it
+        let mut cmd = match self {
+            LinuxBackend::Apt => Command::new("apt-cache"),
+            _ => self.command(),
+        };
+        match self {
+            LinuxBackend::Apt => cmd.args(["search", "--names-only", query]),
+            LinuxBackend::Dnf => cmd.args(["search", "-q", query]),
+            LinuxBackend::Pacman => cmd.args(["-Ss", query]),
+            LinuxBackend::Zypper => cmd.args(["--non-interactive", "search", query]),
+            LinuxBackend::Apk => cmd.args(["search", query]),
+        };
+        cmd
+    }
+    
+    /// Package names in the output of the search command
+    pub fn parse_search(&self, output: &str) -> Vec<String> {
+        let names = output.lines().filter_map(|line| match self {
+            // `name - description`
+            LinuxBackend::Apt => line.split(" - ").next().map(str::trim),
+            // `name.arch : summary`, under `=== Name Matched: ... ===` headings
+            LinuxBackend::Dnf => line.split_once(" : ")
+                .map(|(package, _)| package.trim())
+                .map(|package| package.rsplit_once('.').map_or(package, |(name, _)| name)),
+            // `repo/name version`, followed by an indented description
+            LinuxBackend::Pacman => if line.starts_with(char::is_whitespace) {
+                None
             } else {
-                cmd.arg(name);
-            }
-            
-            let output = cmd.output()?;
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(anyhow::anyhow!("Failed to install package: {}\n{}", name, stderr));
+                line.split_whitespace().next().and_then(|package| package.rsplit('/').next())
+            },
+            // Table rows `S | Name | Summary | Type`
+            LinuxBackend::Zypper => line.split('|').nth(1)
+                .map(str::trim)
+                .filter(|name| *name != "Name"),
+            // `name-version-rN`
+            LinuxBackend::Apk => {
+                let mut parts = line.trim().rsplitn(3, '-');
+                match (parts.next(), parts.next(), parts.next()) {
+                    (Some(_), Some(_), Some(name)) => Some(name),
+                    _ => None,
+                }
             }
-        },
-        _ => {
-            return Err(anyhow::anyhow!("Unsupported package manager: {}", pkg_manager));
+        });
+        
+        let mut results: Vec<String> = names
+            .filter(|name| !name.is_empty() && !name.contains(char::is_whitespace))
+            .map(|name| format!("{} (linux)", name))
+            .collect();
+        results.dedup();
+        results
+    }
+    
+    /// Base command of the backend, set up to never prompt
+    fn command(&self) -> Command {
+        let mut cmd = Command::new(self.binary());
+        if *self == LinuxBackend::Apt {
+            cmd.env("DEBIAN_FRONTEND", "noninteractive");
         }
+        cmd
+    }
+}
+
+impl fmt::Display for LinuxBackend {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            LinuxBackend::Apt => "apt",
+            LinuxBackend::Dnf => "dnf",
+            LinuxBackend::Pacman => "pacman",
+            LinuxBackend::Zypper => "zypper",
+            LinuxBackend::Apk => "apk",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Probe for the binaries of the supported package managers
+pub fn detect_backend() -> Option<LinuxBackend> {
+    LinuxBackend::ALL.iter()
+        .copied()
+        .find(|backend| super::tools::find_executable(backend.binary()).is_some())
+}
+
+/// Backend to use: the one in the package config, or a detected one
+///
+/// Fails with `UnsupportedDistro` if none is configured or found.
+pub fn backend() -> Result<LinuxBackend> {
+    if let Some(backend) = super::load_config().ok().and_then(|config| config.linux_backend) {
+        return Ok(backend);
     }
     
+    detect_backend().ok_or_else(|| UnsupportedDistro {
+        probed: LinuxBackend::ALL.iter().map(|b| b.binary().to_string()).collect(),
+    }.into())
+}
+
+/// Install a Linux package
+pub fn install_package(name: &str, version: Option<&str>) -> Result<()> {
+    let backend = backend()?;
+    info!("Installing Linux package {} using {}", name, backend);
+    
+    run(backend, backend.install_command(name, version), "install", name)?;
+    
     info!("Linux package {} installed successfully", name);
     Ok(())
 }
 
 /// Remove a Linux package
 pub fn remove_package(name: &str) -> Result<()> {
-    let pkg_manager = detect_package_manager()?;
-    info!("Removing Linux package {} using {}", name, pkg_manager);
+    let backend = backend()?;
+    info!("Removing Linux package {} using {}", name, backend);
     
-    match pkg_manager {
-        "apt" => {
-            let mut cmd = Command::new("apt");
-            cmd.args(["remove", "-y", name]);
-            
-            let output = cmd.output()?;
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(anyhow::anyhow!("Failed to remove package: {}\n{}", name, stderr));
-            }
-        },
-        "dnf" | "yum" => {
-            let mut cmd = Command::new(pkg_manager);
-            cmd.args(["remove", "-y", name]);
-            
-            let output = cmd.output()?;
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(anyhow::anyhow!("Failed to remove package: {}\n{}", name, stderr));
-            }
-        },
-        "pacman" => {
-            let mut cmd = Command::new("pacman");
-            cmd.args(["-R", "--noconfirm", name]);
-            
-            let output = cmd.output()?;
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(anyhow::anyhow!("Failed to remove package: {}\n{}", name, stderr));
-            }
-        },
-        "zypper" => {
-            let mut cmd = Command::new("zypper");
-            cmd.args(["remove", "-y", name]);
-            
-            let output = cmd.output()?;
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(anyhow::anyhow!("Failed to remove package: {}\n{}", name, stderr));
-            }
-        },
-        "apk" => {
-            let mut cmd = Command::new("apk");
-            cmd.args(["del", name]);
-            
-            let output = cmd.output()?;
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(anyhow::anyhow!("Failed to remove package: {}\n{}", name, stderr));
-            }
-        },
-        "pkg" => {
-            let mut cmd = Command::new("pkg");
-            cmd.args(["remove", "-y", name]);
-            
-            let output = cmd.output()?;
-            if !output.status.success() {
-                let stderr = String::from_utf8_lossy(&output.stderr);
-                return Err(anyhow::anyhow!("Failed to remove package: {}\n{}", name, stderr));
-            }
-        },
-        _ => {
-            return Err(anyhow::anyhow!("Unsupported package manager: {}", pkg_manager));
-        }
-    }
+    run(backend, backend.remove_command(name), "remove", name)?;
     
     info!("Linux package {} removed successfully", name);
     Ok(())
@@ -246,94 +239,90 @@ pub fn run_package(name: &str, args: &[&str]) -> Result<()> {
 
 /// Search for Linux packages
 pub fn search_packages(query: &str) -> Result<Vec<String>> {
-    let pkg_manager = detect_package_manager().unwrap_or("apt");
-    info!("Searching for Linux packages matching {} using {}", query, pkg_manager);
+    let backend = backend()?;
+    info!("Searching for Linux packages matching {} using {}", query, backend);
     
-    let mut results = Vec::new();
+    let output = backend.search_command(query).output()
+        .with_context(|| format!("Failed to run {} search", backend))?;
     
-    match pkg_manager {
-        "apt" => {
-            let cmd = Command::new("apt")
-                .args(["search", query])
-                .output()?;
-                
-            if cmd.status.success() {
-                let output = String::from_utf8_lossy(&cmd.stdout);
-                for line in output.lines() {
-                    if line.contains("/") {
-                        let parts: Vec<&str> = line.split("/").collect();
-                        if !parts.is_empty() {
-                            let pkg_name = parts[0].trim();
-                            if !pkg_name.is_empty() {
-                                results.push(format!("{} (linux)", pkg_name));
-                            }
-                        }
-                    }
-                }
-            }
-        },
-        "dnf" | "yum" => {
-            let cmd = Command::new(pkg_manager)
-                .args(["search", query])
-                .output()?;
-                
-            if cmd.status.success() {
-                let output = String::from_utf8_lossy(&cmd.stdout);
-                for line in output.lines() {
-                    if line.contains(" : ") {
-                        let parts: Vec<&str> = line.split(" : ").collect();
-                        if !parts.is_empty() {
-                            let pkg_name = parts[0].trim();
-                            if !pkg_name.is_empty() {
-                                results.push(format!("{} (linux)", pkg_name));
-                            }
-                        }
-                    }
-                }
-            }
-        },
-        "pacman" => {
-            let cmd = Command::new("pacman")
-                .args(["-Ss", query])
-                .output()?;
-                
-            if cmd.status.success() {
-                let output = String::from_utf8_lossy(&cmd.stdout);
-                for line in output.lines() {
-                    if line.starts_with(" ") || line.is_empty() {
-                        continue;
-                    }
-                    
-                    let parts: Vec<&str> = line.split(" ").collect();
-                    if parts.len() >= 2 {
-                        let pkg_name = parts[0].split("/").last().unwrap_or("").trim();
-                        if !pkg_name.is_empty() {
-                            results.push(format!("{} (linux)", pkg_name));
-                        }
-                    }
-                }
-            }
-        },
-        _ => {
-            warn!("Package search not fully implemented for {}", pkg_manager);
-            // Generic implementation
-            let cmd = Command::new(pkg_manager)
-                .args(["search", query])
-                .output()?;
-                
-            if cmd.status.success() {
-                let output = String::from_utf8_lossy(&cmd.stdout);
-                for line in output.lines() {
-                    if !line.is_empty() && !line.starts_with(" ") {
-                        let pkg_name = line.split(" ").next().unwrap_or("").trim();
-                        if !pkg_name.is_empty() {
-                            results.push(format!("{} (linux)", pkg_name));
-                        }
-                    }
-                }
-            }
-        }
+    // Most backends exit non-zero when nothing matches
+    if !output.status.success() {
+        debug!("{} search exited with {:?}", backend, output.status.code());
+        return Ok(Vec::new());
+    }
+    
+    Ok(backend.parse_search(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Run a backend command, failing with its stderr
+fn run(backend: LinuxBackend, mut cmd: Command, action: &str, name: &str) -> Result<()> {
+    let output = cmd.output()
+        .with_context(|| format!("Failed to run {}", backend.binary()))?;
+    
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(anyhow::anyhow!("Failed to {} package: {}\n{}", action, name, stderr));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Program and arguments of a command line
+    fn command_line(cmd: &Command) -> String {
+        let mut line = vec![cmd.get_program().to_string_lossy().to_string()];
+        line.extend(cmd.get_args().map(|arg| arg.to_string_lossy().to_string()));
+        line.join(" ")
+    }
+    
+    #[test]
+    fn commands_never_prompt_and_pin_versions() {
+        let installs: Vec<String> = LinuxBackend::ALL.iter().map(|b| command_line(&b.install_command("curl", Some("8.5")))).collect();
+        assert_eq!(installs, [
+            "apt-get install -y -q curl=8.5",
+            "dnf install -y -q curl-8.5",
+            "pacman -S --noconfirm --needed curl=8.5",
+            "zypper --non-interactive install curl=8.5",
+            "apk add -q curl=8.5",
+        ]);
+        assert_eq!(command_line(&LinuxBackend::Dnf.install_command("curl", None)), "dnf install -y -q curl");
+        assert_eq!(command_line(&LinuxBackend::Pacman.remove_command("curl")), "pacman -R --noconfirm curl");
+        assert_eq!(command_line(&LinuxBackend::Apt.search_command("curl")), "apt-cache search --names-only curl");
+        
+        let apt = LinuxBackend::Apt.install_command("curl", None);
+        let envs: Vec<_> = apt.get_envs().collect();
+        assert_eq!(envs, [(std::ffi::OsStr::new("DEBIAN_FRONTEND"), Some(std::ffi::OsStr::new("noninteractive")))]);
+        assert_eq!(LinuxBackend::Dnf.install_command("curl", None).get_envs().count(), 0);
+    }
+    
+    #[test]
+    fn search_output_is_parsed_per_backend() {
+        let parsed = |backend: LinuxBackend, output: &str| backend.parse_search(output);
+        assert_eq!(parsed(LinuxBackend::Apt, "curl - command line tool\ncurlftpfs - FTP filesystem\n"),
+                   ["curl (linux)", "curlftpfs (linux)"]);
+        assert_eq!(parsed(LinuxBackend::Dnf, "=== Name Matched: curl ===\ncurl.x86_64 : A utility\ncurl.i686 : A utility\n"),
+                   ["curl (linux)"]);
+        assert_eq!(parsed(LinuxBackend::Pacman, "core/curl 8.5.0-1 [installed]\n    URL retrieval utility\nextra/curlie 1.7.2-1\n"),
+                   ["curl (linux)", "curlie (linux)"]);
+        assert_eq!(parsed(LinuxBackend::Zypper, "S | Name | Summary | Type\n--+------+---------+--------\ni | curl | A tool  | package\n"),
+                   ["curl (linux)"]);
+        assert_eq!(parsed(LinuxBackend::Apk, "curl-8.5.0-r0\nlibcurl-8.5.0-r0\nbroken\n"),
+                   ["curl (linux)", "libcurl (linux)"]);
+        assert!(parsed(LinuxBackend::Apt, "").is_empty());
     }
     
-    Ok(results)
+    #[test]
+    fn backends_are_named_like_their_config_values() {
+        for backend in LinuxBackend::ALL {
+            let configured: LinuxBackend = serde_json::from_str(&format!("\"{}\"", backend)).unwrap();
+            assert_eq!(configured, backend);
+        }
+        assert!(serde_json::from_str::<LinuxBackend>("\"yum\"").is_err());
+        
+        let error = UnsupportedDistro { probed: vec!["apt-get".to_string(), "dnf".to_string()] };
+        assert_eq!(error.to_string(),
+                   "Unsupported Linux distribution: none of apt-get, dnf was found; set linux_backend in .package/config.json to override");
+    }
 }
@@ -11,6 +11,8 @@ use std::process::Command;
 use serde::{Serialize, Deserialize};
 
 use crate::core::constants;
+use crate::core::lock;
+use crate::core::plan::{Plan, PlannedAction};
 use crate::zk;
 use crate::matrixbox;
 use crate::store;
@@ -19,11 +21,19 @@ pub mod linux;
 pub mod npm;
 pub mod python;
 pub mod java;
+pub mod license;
+pub mod mirror;
+pub mod tools;
+pub mod update;
+
+pub use license::license_report;
+pub use update::{update_all, UpdateReport};
 
 // Constants
 const PACKAGE_DIR: &str = ".package";
 const REGISTRY_FILE: &str = "registry.json";
 const CONFIG_FILE: &str = "config.json";
+const REGISTRY_LOCK: &str = "package-registry";
 
 /// Package ecosystem types
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -76,6 +86,10 @@ pub struct InstalledPackage {
     
     /// Configuration options
     pub config: HashMap<String, String>,
+    
+    /// Mirror the package was installed from, if not the public registry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mirror: Option<String>,
 }
 
 /// Package registry to track installed packages across ecosystems
@@ -102,6 +116,19 @@ pub struct PackageConfig {
     
     /// Global environment variables
     pub env_vars: HashMap<String, String>,
+    
+    /// Custom paths of ecosystem tools (e.g. node in a non-standard prefix), by tool name
+    #[serde(default)]
+    pub tool_paths: HashMap<String, String>,
+    
+    /// Registry mirrors by ecosystem, for fleets without public registry access
+    #[serde(default)]
+    pub mirrors: HashMap<String, mirror::MirrorConfig>,
+    
+    /// System package manager; detected at init when unset, and can be
+    /// changed to override the detection
+    #[serde(default)]
+    pub linux_backend: Option<linux::LinuxBackend>,
 }
 
 /// Initialize the package manager
@@ -109,7 +136,7 @@ pub fn init() -> Result<()> {
     info!("Initializing Universal Package Manager");
     
     // Create package directories
-    let package_dir = PathBuf::from(constants::ROOT_DIR).join(PACKAGE_DIR);
+    let package_dir = constants::root_dir().join(PACKAGE_DIR);
     fs::create_dir_all(&package_dir)?;
     
     // Initialize registry if it doesn't exist
@@ -124,7 +151,7 @@ pub fn init() -> Result<()> {
         };
         
         let registry_json = serde_json::to_string_pretty(&empty_registry)?;
-        fs::write(&registry_path, registry_json)?;
+        crate::core::fs::write_atomic(&registry_path, registry_json.as_bytes())?;
     }
     
     // Initialize config if it doesn't exist
@@ -132,17 +159,20 @@ pub fn init() -> Result<()> {
     if !config_path.exists() {
         let default_config = PackageConfig {
             ecosystem_paths: [
-                ("Native".to_string(), format!("{}/packages", constants::ROOT_DIR)),
+                ("Native".to_string(), format!("{}/packages", constants::root_dir().display())),
                 ("Linux".to_string(), "/usr/bin".to_string()),
-                ("Npm".to_string(), format!("{}/packages/npm", constants::ROOT_DIR)),
-                ("Python".to_string(), format!("{}/packages/python", constants::ROOT_DIR)),
-                ("Java".to_string(), format!("{}/packages/java", constants::ROOT_DIR)),
-                ("Rust".to_string(), format!("{}/packages/rust", constants::ROOT_DIR)),
-                ("Go".to_string(), format!("{}/packages/go", constants::ROOT_DIR)),
+                ("Npm".to_string(), format!("{}/packages/npm", constants::root_dir().display())),
+                ("Python".to_string(), format!("{}/packages/python", constants::root_dir().display())),
+                ("Java".to_string(), format!("{}/packages/java", constants::root_dir().display())),
+                ("Rust".to_string(), format!("{}/packages/rust", constants::root_dir().display())),
+                ("Go".to_string(), format!("{}/packages/go", constants::root_dir().display())),
             ].iter().cloned().collect(),
             zk_verify: true,
             isolate: true,
             env_vars: HashMap::new(),
+            tool_paths: HashMap::new(),
+            mirrors: HashMap::new(),
+            linux_backend: None,
         };
         
         let config_json = serde_json::to_string_pretty(&default_config)?;
@@ -150,18 +180,34 @@ pub fn init() -> Result<()> {
     }
     
     // Ensure ecosystem directories exist
-    let config = load_config()?;
-    for (_, path) in config.ecosystem_paths {
+    let mut config = load_config()?;
+    for path in config.ecosystem_paths.values() {
         fs::create_dir_all(path)?;
     }
     
+    // Record the system package manager; a configured one is kept
+    match config.linux_backend {
+        Some(backend) => debug!("Using configured Linux package manager: {}", backend),
+        None => match linux::detect_backend() {
+            Some(backend) => {
+                info!("Detected Linux package manager: {}", backend);
+                config.linux_backend = Some(backend);
+                save_config(&config)?;
+            }
+            None => warn!("No supported Linux package manager found"),
+        },
+    }
+    
+    // Detect ecosystem tools up front; backends re-check on a miss
+    tools::detect();
+    
     info!("Universal Package Manager initialized successfully");
     Ok(())
 }
 
 /// Load package manager configuration
 pub fn load_config() -> Result<PackageConfig> {
-    let package_dir = PathBuf::from(constants::ROOT_DIR).join(PACKAGE_DIR);
+    let package_dir = constants::root_dir().join(PACKAGE_DIR);
     let config_path = package_dir.join(CONFIG_FILE);
     
     if !config_path.exists() {
@@ -174,39 +220,49 @@ pub fn load_config() -> Result<PackageConfig> {
     Ok(config)
 }
 
+/// Save package manager configuration
+pub fn save_config(config: &PackageConfig) -> Result<()> {
+    let package_dir = constants::root_dir().join(PACKAGE_DIR);
+    let config_path = package_dir.join(CONFIG_FILE);
+    
+    let config_json = serde_json::to_string_pretty(config)?;
+    fs::write(&config_path, config_json)?;
+    
+    Ok(())
+}
+
 /// Load package registry
 pub fn load_registry() -> Result<PackageRegistry> {
-    let package_dir = PathBuf::from(constants::ROOT_DIR).join(PACKAGE_DIR);
+    let package_dir = constants::root_dir().join(PACKAGE_DIR);
     let registry_path = package_dir.join(REGISTRY_FILE);
     
     if !registry_path.exists() {
         return Err(anyhow::anyhow!("Package registry not initialized"));
     }
     
-    let registry_data = fs::read_to_string(&registry_path)?;
-    let registry: PackageRegistry = serde_json::from_str(&registry_data)?;
-    
-    Ok(registry)
+    crate::core::fs::read_json_or_backup(&registry_path)
 }
 
 /// Save package registry
 fn save_registry(registry: &PackageRegistry) -> Result<()> {
-    let package_dir = PathBuf::from(constants::ROOT_DIR).join(PACKAGE_DIR);
+    let package_dir = constants::root_dir().join(PACKAGE_DIR);
     let registry_path = package_dir.join(REGISTRY_FILE);
     
     let registry_json = serde_json::to_string_pretty(&registry)?;
-    fs::write(&registry_path, registry_json)?;
-    
-    Ok(())
+    crate::core::fs::write_atomic(&registry_path, registry_json.as_bytes())
 }
 
 /// Install a package from any supported ecosystem
-pub fn install_package(name: &str, ecosystem: Ecosystem, version: Option<&str>) -> Result<()> {
+///
+/// Packages whose license is denied by the license policy are refused when
+/// the policy blocks installs, unless `allow_denied_license` is set.
+pub fn install_package(name: &str, ecosystem: Ecosystem, version: Option<&str>, allow_denied_license: bool) -> Result<()> {
     info!("Installing package: {} from {:?} ecosystem", name, ecosystem);
     
+    let _lock = lock::lock(REGISTRY_LOCK, &format!("install {}", name), lock::DEFAULT_TIMEOUT)?;
+    
     // Check if already installed
     let mut registry = load_registry()?;
-    let config = load_config()?;
     
     let full_name = match &ecosystem {
         Ecosystem::Native => name.to_string(),
@@ -231,7 +287,113 @@ pub fn install_package(name: &str, ecosystem: Ecosystem, version: Option<&str>)
         }
     }
     
-    // Install based on ecosystem
+    // Fail early with guidance rather than on a bare spawn error
+    tools::ensure_available(&ecosystem)?;
+    
+    // A broken mirror setting must fail here, not fall back to the public registry
+    let mirror = mirror::mirror_config(&ecosystem)?.map(|m| m.url);
+    
+    // Every step is recorded so a failure part-way leaves nothing behind
+    let mut transaction = InstallTransaction::new(&full_name);
+    if let Err(e) = perform_install(&mut transaction, &mut registry, name, &ecosystem, version, mirror, allow_denied_license) {
+        return Err(transaction.roll_back(e));
+    }
+    
+    info!("Package {} installed successfully", full_name);
+    Ok(())
+}
+
+/// Install a package and register it, recording each step in `transaction`
+fn perform_install(
+    transaction: &mut InstallTransaction,
+    registry: &mut PackageRegistry,
+    name: &str,
+    ecosystem: &Ecosystem,
+    version: Option<&str>,
+    mirror: Option<String>,
+    allow_denied_license: bool,
+) -> Result<()> {
+    let config = load_config()?;
+    let full_name = transaction.package.clone();
+    
+    // The ecosystem handler creates its package directory on first use
+    if let Some(dir) = ecosystem_dir(ecosystem).filter(|dir| !dir.exists()) {
+        transaction.record(InstallStep::CreatedDirectory(dir));
+    }
+    
+    if let Ecosystem::Native = ecosystem {
+        // The store index declares the license before anything is downloaded
+        let declared = store::show_package_details(name)?.map(|p| p.license);
+        license::check_install(&full_name, declared.as_deref(), allow_denied_license)?;
+    }
+    
+    // An upgrade that fails from here on puts the installed version back
+    let previous = registry.packages.get(&full_name).cloned();
+    if let Some(previous) = &previous {
+        transaction.record(InstallStep::Replacing { key: full_name.clone(), previous: previous.clone() });
+    }
+    install_with_tooling(name, ecosystem, version)?;
+    if !transaction.is_upgrade() {
+        transaction.record(InstallStep::EcosystemInstalled { name: name.to_string(), ecosystem: ecosystem.clone() });
+    }
+    
+    // Add to registry
+    let version_str = match version {
+        Some(v) => v.to_string(),
+        None => "latest".to_string(),
+    };
+    
+    let ecosystem_path = match ecosystem {
+        Ecosystem::Native => config.ecosystem_paths.get("Native"),
+        Ecosystem::Linux => config.ecosystem_paths.get("Linux"),
+        Ecosystem::Npm => config.ecosystem_paths.get("Npm"),
+        Ecosystem::Python => config.ecosystem_paths.get("Python"),
+        Ecosystem::Java => config.ecosystem_paths.get("Java"),
+        Ecosystem::Rust => config.ecosystem_paths.get("Rust"),
+        Ecosystem::Go => config.ecosystem_paths.get("Go"),
+        Ecosystem::Other(eco) => config.ecosystem_paths.get(eco),
+    };
+    
+    let path = ecosystem_path
+        .cloned()
+        .unwrap_or_else(|| format!("{}/packages", constants::root_dir().display()));
+    
+    // An upgrade keeps the container and configuration the old version was set up with
+    let (container_id, package_config) = previous
+        .map(|previous| (previous.container_id, previous.config))
+        .unwrap_or_default();
+    
+    let installed_pkg = InstalledPackage {
+        name: name.to_string(),
+        version: version_str,
+        ecosystem: ecosystem.clone(),
+        path,
+        container_id,
+        installed_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        config: package_config,
+        mirror,
+    };
+    
+    // Foreign ecosystems only expose the license once the package is on disk
+    if matches!(installed_pkg.ecosystem, Ecosystem::Npm | Ecosystem::Python) {
+        let declared = license::declared_license(&installed_pkg);
+        license::check_install(&full_name, declared.as_deref(), allow_denied_license)?;
+    }
+    
+    registry.packages.insert(full_name, installed_pkg);
+    registry.last_updated = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    
+    save_registry(registry).context("Failed to save the package registry")
+}
+
+/// Install a package with its ecosystem's tooling, leaving the registry alone
+fn install_with_tooling(name: &str, ecosystem: &Ecosystem, version: Option<&str>) -> Result<()> {
     match ecosystem {
         Ecosystem::Native => {
             // Use existing ZK-Store for native packages
@@ -251,13 +413,18 @@ pub fn install_package(name: &str, ecosystem: Ecosystem, version: Option<&str>)
         },
         Ecosystem::Rust => {
             // Use cargo to install Rust packages
-            let mut cmd = Command::new("cargo");
+            let mut cmd = tools::command("cargo")?;
             cmd.arg("install");
             cmd.arg(name);
             if let Some(ver) = version {
                 cmd.args(["--version", ver]);
             }
             
+            let session = mirror::session(&Ecosystem::Rust)?;
+            if let Some(session) = &session {
+                session.apply(&mut cmd)?;
+            }
+            
             let output = cmd.output()?;
             if !output.status.success() {
                 return Err(anyhow::anyhow!("Failed to install Rust package: {}", name));
@@ -265,7 +432,7 @@ pub fn install_package(name: &str, ecosystem: Ecosystem, version: Option<&str>)
         },
         Ecosystem::Go => {
             // Use go get to install Go packages
-            let mut cmd = Command::new("go");
+            let mut cmd = tools::command("go")?;
             cmd.arg("install");
             
             let package_spec = if let Some(ver) = version {
@@ -276,93 +443,151 @@ pub fn install_package(name: &str, ecosystem: Ecosystem, version: Option<&str>)
             
             cmd.arg(&package_spec);
             
+            let session = mirror::session(&Ecosystem::Go)?;
+            if let Some(session) = &session {
+                session.apply(&mut cmd)?;
+            }
+            
             let output = cmd.output()?;
             if !output.status.success() {
                 return Err(anyhow::anyhow!("Failed to install Go package: {}", name));
             }
         },
         Ecosystem::Other(eco) => {
+            // Tests stand a fake ecosystem in for the real ones
+            #[cfg(test)]
+            if eco.starts_with(tests::FAKE_PREFIX) {
+                return tests::fake_install(eco, name, version);
+            }
             return Err(anyhow::anyhow!("Unsupported ecosystem: {}", eco));
         }
     }
+    Ok(())
+}
+
+/// A step of a package install, undone if a later step fails
+#[derive(Debug)]
+enum InstallStep {
+    /// Directory the install created
+    CreatedDirectory(PathBuf),
+    
+    /// Version already installed, which an upgrade replaces
+    Replacing {
+        /// Registry key of the package
+        key: String,
+        
+        /// Registry entry of the installed version
+        previous: InstalledPackage,
+    },
+    
+    /// Package newly installed by its ecosystem's tooling
+    EcosystemInstalled {
+        /// Package name
+        name: String,
+        
+        /// Ecosystem it was installed from
+        ecosystem: Ecosystem,
+    },
+}
+
+impl InstallStep {
+    /// Undo the step
+    fn undo(&self) -> Result<()> {
+        match self {
+            InstallStep::CreatedDirectory(dir) => {
+                if dir.exists() {
+                    fs::remove_dir_all(dir)
+                        .with_context(|| format!("Failed to remove {:?}", dir))?;
+                }
+                Ok(())
+            }
+            InstallStep::Replacing { key, previous } => {
+                // Reinstall the old version over whatever the failed upgrade left
+                let version = Some(previous.version.as_str()).filter(|v| *v != "latest");
+                install_with_tooling(&previous.name, &previous.ecosystem, version)?;
+                
+                let mut registry = load_registry()?;
+                registry.packages.insert(key.clone(), previous.clone());
+                save_registry(&registry)
+            }
+            InstallStep::EcosystemInstalled { name, ecosystem } => {
+                // Only recorded when nothing was installed before, so nothing is lost
+                uninstall(name, ecosystem, true).map(|_| ())
+            }
+        }
+    }
+}
+
+/// Steps an install has performed, for unwinding it if it fails
+struct InstallTransaction {
+    /// Registry key of the package being installed
+    package: String,
     
-    // Add to registry
-    let version_str = match version {
-        Some(v) => v.to_string(),
-        None => "latest".to_string(),
-    };
-    
-    let ecosystem_path = match ecosystem {
-        Ecosystem::Native => config.ecosystem_paths.get("Native"),
-        Ecosystem::Linux => config.ecosystem_paths.get("Linux"),
-        Ecosystem::Npm => config.ecosystem_paths.get("Npm"),
-        Ecosystem::Python => config.ecosystem_paths.get("Python"),
-        Ecosystem::Java => config.ecosystem_paths.get("Java"),
-        Ecosystem::Rust => config.ecosystem_paths.get("Rust"),
-        Ecosystem::Go => config.ecosystem_paths.get("Go"),
-        Ecosystem::Other(eco) => config.ecosystem_paths.get(&eco),
-    };
-    
-    let path = ecosystem_path
-        .cloned()
-        .unwrap_or_else(|| format!("{}/packages", constants::ROOT_DIR));
-    
-    let installed_pkg = InstalledPackage {
-        name: name.to_string(),
-        version: version_str,
-        ecosystem: ecosystem.clone(),
-        path,
-        container_id: None,
-        installed_at: std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap_or_default()
-            .as_secs(),
-        config: HashMap::new(),
-    };
+    /// Steps performed, oldest first
+    steps: Vec<InstallStep>,
+}
+
+impl InstallTransaction {
+    /// Start an install of `package`
+    fn new(package: &str) -> Self {
+        Self { package: package.to_string(), steps: Vec::new() }
+    }
     
-    registry.packages.insert(full_name.clone(), installed_pkg);
-    registry.last_updated = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs();
+    /// Record a step that was performed
+    fn record(&mut self, step: InstallStep) {
+        debug!("Install of {}: {:?}", self.package, step);
+        self.steps.push(step);
+    }
     
-    save_registry(&registry)?;
+    /// Whether the install replaces a version that was already installed
+    fn is_upgrade(&self) -> bool {
+        self.steps.iter().any(|step| matches!(step, InstallStep::Replacing { .. }))
+    }
     
-    info!("Package {} installed successfully", full_name);
-    Ok(())
+    /// Undo the recorded steps, newest first, after the install failed with `error`
+    ///
+    /// Steps that can't be undone are logged and chained onto the error.
+    fn roll_back(self, error: anyhow::Error) -> anyhow::Error {
+        warn!("Install of {} failed, undoing {} step(s): {:#}", self.package, self.steps.len(), error);
+        
+        let mut failures = Vec::new();
+        for step in self.steps.iter().rev() {
+            if let Err(e) = step.undo() {
+                error!("Failed to undo {:?} for {}: {:#}", step, self.package, e);
+                failures.push(format!("{:#}", e));
+            }
+        }
+        
+        if failures.is_empty() {
+            error
+        } else {
+            error.context(format!("Install of {} failed and could not be fully undone: {}", self.package, failures.join("; ")))
+        }
+    }
+}
+
+/// Directory an ecosystem's handler keeps its packages in, if it has its own
+fn ecosystem_dir(ecosystem: &Ecosystem) -> Option<PathBuf> {
+    let packages = constants::root_dir().join("packages");
+    match ecosystem {
+        Ecosystem::Npm => Some(packages.join("npm")),
+        Ecosystem::Python => Some(packages.join("python")),
+        Ecosystem::Java => Some(packages.join("java")),
+        #[cfg(test)]
+        Ecosystem::Other(eco) if eco.starts_with(tests::FAKE_PREFIX) => Some(packages.join(eco)),
+        _ => None,
+    }
 }
 
 /// Remove an installed package
-pub fn remove_package(name: &str, ecosystem: Option<Ecosystem>) -> Result<()> {
+///
+/// Native packages are moved to the trash unless `purge` is set. Packages
+/// from other ecosystems are uninstalled by their own tooling.
+pub fn remove_package(name: &str, ecosystem: Option<Ecosystem>, purge: bool) -> Result<()> {
+    let _lock = lock::lock(REGISTRY_LOCK, &format!("remove {}", name), lock::DEFAULT_TIMEOUT)?;
     let mut registry = load_registry()?;
     
-    // If ecosystem is specified, create full name
-    let package_key = if let Some(eco) = ecosystem {
-        match eco {
-            Ecosystem::Native => name.to_string(),
-            Ecosystem::Linux => format!("linux:{}", name),
-            Ecosystem::Npm => format!("npm:{}", name),
-            Ecosystem::Python => format!("python:{}", name),
-            Ecosystem::Java => format!("java:{}", name),
-            Ecosystem::Rust => format!("rust:{}", name),
-            Ecosystem::Go => format!("go:{}", name),
-            Ecosystem::Other(eco_name) => format!("{}:{}", eco_name, name),
-        }
-    } else {
-        // Try to find by name only
-        let matches: Vec<_> = registry.packages.keys()
-            .filter(|k| k.ends_with(&format!(":{}", name)) || *k == name)
-            .cloned()
-            .collect();
-            
-        if matches.is_empty() {
-            return Err(anyhow::anyhow!("Package not found: {}", name));
-        } else if matches.len() > 1 {
-            return Err(anyhow::anyhow!("Multiple packages found with name {}, please specify ecosystem", name));
-        }
-        
-        matches[0].clone()
-    };
+    let package_key = resolve_package_key(&registry, name, ecosystem)?;
     
     // Check if package exists
     if !registry.packages.contains_key(&package_key) {
@@ -371,10 +596,30 @@ pub fn remove_package(name: &str, ecosystem: Option<Ecosystem>) -> Result<()> {
     
     let package = registry.packages.remove(&package_key).unwrap();
     
-    // Uninstall based on ecosystem
-    match package.ecosystem {
+    if let Some(trash_id) = uninstall(name, &package.ecosystem, purge)? {
+        // Keep the registry entry so a restore can re-register it
+        crate::trash::set_registry_entry(&trash_id, serde_json::to_value(&package)?)?;
+    }
+    
+    // Update registry
+    registry.last_updated = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    
+    save_registry(&registry)?;
+    
+    info!("Package {} removed successfully", package_key);
+    Ok(())
+}
+
+/// Uninstall a package with its ecosystem's tooling, leaving the registry alone
+///
+/// Returns the trash entry of a native package moved to the trash.
+fn uninstall(name: &str, ecosystem: &Ecosystem, purge: bool) -> Result<Option<String>> {
+    match ecosystem {
         Ecosystem::Native => {
-            store::remove_package(name)?;
+            return store::remove_package(name, purge, false);
         },
         Ecosystem::Linux => {
             linux::remove_package(name)?;
@@ -390,7 +635,7 @@ pub fn remove_package(name: &str, ecosystem: Option<Ecosystem>) -> Result<()> {
         },
         Ecosystem::Rust => {
             // Use cargo to uninstall Rust packages
-            let mut cmd = Command::new("cargo");
+            let mut cmd = tools::command("cargo")?;
             cmd.args(["uninstall", name]);
             
             let output = cmd.output()?;
@@ -400,27 +645,97 @@ pub fn remove_package(name: &str, ecosystem: Option<Ecosystem>) -> Result<()> {
         },
         Ecosystem::Go => {
             // Go doesn't have a direct uninstall command, just remove the binary
-            let go_bin = std::env::var("GOBIN").unwrap_or_else(|_| format!("{}/go/bin", std::env::var("HOME").unwrap_or_default()));
-            let bin_path = PathBuf::from(go_bin).join(name);
+            let bin_path = go_binary_path(name);
             if bin_path.exists() {
                 fs::remove_file(bin_path)?;
             }
         },
         Ecosystem::Other(eco) => {
+            #[cfg(test)]
+            if eco.starts_with(tests::FAKE_PREFIX) {
+                tests::fake_uninstall(eco, name)?;
+                return Ok(None);
+            }
             warn!("No uninstall handler for ecosystem {}, just removing from registry", eco);
         }
     }
+    Ok(None)
+}
+
+/// Plan the removal of an installed package without changing anything
+pub fn plan_remove_package(name: &str, ecosystem: Option<Ecosystem>, purge: bool) -> Result<Plan> {
+    let registry = load_registry()?;
+    let package_key = resolve_package_key(&registry, name, ecosystem)?;
     
-    // Update registry
-    registry.last_updated = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs();
+    let package = registry.packages.get(&package_key)
+        .ok_or_else(|| anyhow::anyhow!("Package not installed: {}", package_key))?;
     
-    save_registry(&registry)?;
+    let mut plan = match &package.ecosystem {
+        Ecosystem::Native => store::plan_remove_package(name, purge, false)?,
+        Ecosystem::Go => {
+            let mut plan = Plan::new("");
+            let bin_path = go_binary_path(name);
+            if bin_path.exists() {
+                plan.push(PlannedAction::DeleteFiles {
+                    path: bin_path.to_string_lossy().to_string(),
+                    bytes: crate::core::plan::path_size(&bin_path),
+                });
+            }
+            plan
+        },
+        Ecosystem::Other(_) => Plan::new(""),
+        other => {
+            let mut plan = Plan::new("");
+            plan.push(PlannedAction::RunUninstaller {
+                ecosystem: format!("{:?}", other).to_lowercase(),
+                package: name.to_string(),
+            });
+            plan
+        },
+    };
     
-    info!("Package {} removed successfully", package_key);
-    Ok(())
+    plan.operation = format!("package remove {}", package_key);
+    plan.push(PlannedAction::RemoveRegistryEntry { registry: "package".to_string(), entry: package_key });
+    Ok(plan)
+}
+
+/// Path of an installed Go binary
+fn go_binary_path(name: &str) -> PathBuf {
+    let go_bin = std::env::var("GOBIN").unwrap_or_else(|_| format!("{}/go/bin", std::env::var("HOME").unwrap_or_default()));
+    PathBuf::from(go_bin).join(name)
+}
+
+/// Resolve a package name to its registry key
+fn resolve_package_key(registry: &PackageRegistry, name: &str, ecosystem: Option<Ecosystem>) -> Result<String> {
+    // If ecosystem is specified, create full name
+    let package_key = if let Some(eco) = ecosystem {
+        match eco {
+            Ecosystem::Native => name.to_string(),
+            Ecosystem::Linux => format!("linux:{}", name),
+            Ecosystem::Npm => format!("npm:{}", name),
+            Ecosystem::Python => format!("python:{}", name),
+            Ecosystem::Java => format!("java:{}", name),
+            Ecosystem::Rust => format!("rust:{}", name),
+            Ecosystem::Go => format!("go:{}", name),
+            Ecosystem::Other(eco_name) => format!("{}:{}", eco_name, name),
+        }
+    } else {
+        // Try to find by name only
+        let matches: Vec<_> = registry.packages.keys()
+            .filter(|k| k.ends_with(&format!(":{}", name)) || *k == name)
+            .cloned()
+            .collect();
+        
+        if matches.is_empty() {
+            return Err(anyhow::anyhow!("Package not found: {}", name));
+        } else if matches.len() > 1 {
+            return Err(anyhow::anyhow!("Multiple packages found with name {}, please specify ecosystem", name));
+        }
+        
+        matches[0].clone()
+    };
+    
+    Ok(package_key)
 }
 
 /// List installed packages, optionally filtered by ecosystem
@@ -464,7 +779,7 @@ pub fn run_package(name: &str, ecosystem: Option<Ecosystem>, args: &[&str]) -> R
             .filter(|(k, _)| k.ends_with(&format!(":{}", name)) || *k == name)
             .map(|(_, v)| v)
             .collect();
-            
+        
         if matches.is_empty() {
             None
         } else if matches.len() > 1 {
@@ -480,8 +795,9 @@ pub fn run_package(name: &str, ecosystem: Option<Ecosystem>, args: &[&str]) -> R
             Ecosystem::Native => {
                 // Run in MatrixBox container if isolate is enabled
                 if config.isolate {
-                    let container_id = pkg.container_id.clone().unwrap_or_else(|| name.to_string());
-                    matrixbox::run_container(&container_id, args)?;
+                    // The store installs native packages as containers in their package directory
+                    let container_path = store::package_path(name);
+                    matrixbox::run_container(&container_path.to_string_lossy(), args)?;
                 } else {
                     // Run directly
                     let bin_path = PathBuf::from(&pkg.path).join(name);
@@ -496,7 +812,7 @@ pub fn run_package(name: &str, ecosystem: Option<Ecosystem>, args: &[&str]) -> R
                 linux::run_package(name, args)?;
             },
             Ecosystem::Npm => {
-                npm::run_package(name, args)?;
+                npm::run_package(&pkg, args)?;
             },
             Ecosystem::Python => {
                 python::run_package(name, args)?;
@@ -581,7 +897,12 @@ pub fn search_packages(query: &str, ecosystem: Option<Ecosystem>) -> Result<Vec<
                 results.push(format!("{} (native) - {}", pkg.name, pkg.description));
             }
             
-            results.extend(linux::search_packages(query)?);
+            // Machines without a supported package manager just have no Linux results
+            match linux::search_packages(query) {
+                Ok(found) => results.extend(found),
+                Err(e) if e.is::<linux::UnsupportedDistro>() => debug!("Skipping Linux packages: {}", e),
+                Err(e) => return Err(e),
+            }
             results.extend(npm::search_packages(query)?);
             results.extend(python::search_packages(query)?);
             results.extend(java::search_packages(query)?);
@@ -618,7 +939,7 @@ pub fn create_app(name: &str, packages: &[&str], icon: Option<&str>, desktop_ent
     };
     
     // Create app directory
-    let app_dir = PathBuf::from(constants::ROOT_DIR).join("apps").join(name);
+    let app_dir = constants::root_dir().join("apps").join(name);
     fs::create_dir_all(&app_dir)?;
     
     // Create app metadata
@@ -709,7 +1030,7 @@ pub fn update_package(name: &str, ecosystem: Option<Ecosystem>) -> Result<()> {
             .filter(|(k, _)| k.ends_with(&format!(":{}", name)) || *k == name)
             .map(|(_, v)| v)
             .collect();
-            
+        
         if matches.is_empty() {
             None
         } else if matches.len() > 1 {
@@ -721,8 +1042,8 @@ pub fn update_package(name: &str, ecosystem: Option<Ecosystem>) -> Result<()> {
     
     if let Some(pkg) = package {
         // Remove and reinstall the package
-        remove_package(name, Some(pkg.ecosystem.clone()))?;
-        install_package(name, pkg.ecosystem, None)?;
+        remove_package(name, Some(pkg.ecosystem.clone()), true)?;
+        install_package(name, pkg.ecosystem, None, false)?;
         
         info!("Package {} updated successfully", name);
         Ok(())
@@ -730,3 +1051,228 @@ pub fn update_package(name: &str, ecosystem: Option<Ecosystem>) -> Result<()> {
         Err(anyhow::anyhow!("Package not found: {}", name))
     }
 }
+
+/// Restore a native package from a trash entry under `target_name`
+pub fn restore_from_trash(
+    tombstone: &crate::trash::Tombstone,
+    data_path: Option<&Path>,
+    target_name: &str,
+) -> Result<()> {
+    let _lock = lock::lock(REGISTRY_LOCK, &format!("restore {}", target_name), lock::DEFAULT_TIMEOUT)?;
+    let mut registry = load_registry()?;
+    
+    let package_dir = store::package_path(target_name);
+    if package_dir.exists() || registry.packages.contains_key(target_name) {
+        anyhow::bail!("Package {} is already installed; restore it under another name with --rename", target_name);
+    }
+    
+    if let Some(data_path) = data_path {
+        fs::rename(data_path, &package_dir)
+            .with_context(|| format!("Failed to restore package data to {:?}", package_dir))?;
+    }
+    
+    if !tombstone.registry_entry.is_null() {
+        let mut package: InstalledPackage = serde_json::from_value(tombstone.registry_entry.clone())
+            .context("Invalid package registry entry in tombstone")?;
+        package.name = target_name.to_string();
+        package.path = package_dir.to_string_lossy().to_string();
+        
+        registry.packages.insert(target_name.to_string(), package);
+        registry.last_updated = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        
+        save_registry(&registry)?;
+    }
+    
+    info!("Package {} restored from trash", target_name);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Mutex, Once};
+    
+    /// Ecosystems named with this prefix are installed by `fake_install`
+    pub(super) const FAKE_PREFIX: &str = "fake-";
+    
+    static SETUP: Once = Once::new();
+    
+    // Serializes changes to the config the package tests share
+    static CONFIG: Mutex<()> = Mutex::new(());
+    
+    /// Create the registry and config, once per process
+    pub(super) fn setup() {
+        SETUP.call_once(|| {
+            update_config(|_| {});
+            if !constants::root_dir().join(PACKAGE_DIR).join(REGISTRY_FILE).exists() {
+                save_registry(&PackageRegistry { last_updated: 0, packages: HashMap::new() }).unwrap();
+            }
+        });
+    }
+    
+    /// Change the shared package config, creating it if no test has yet
+    ///
+    /// Tests only change the settings they rely on, and the config is
+    /// replaced whole, as other tests may be reading it.
+    pub(super) fn update_config(change: impl FnOnce(&mut PackageConfig)) {
+        let _guard = CONFIG.lock().unwrap();
+        let mut config = load_config().unwrap_or_else(|_| PackageConfig {
+            ecosystem_paths: HashMap::new(),
+            zk_verify: false,
+            isolate: false,
+            env_vars: HashMap::new(),
+            tool_paths: HashMap::new(),
+            mirrors: HashMap::new(),
+            linux_backend: None,
+        });
+        change(&mut config);
+        let config_path = constants::root_dir().join(PACKAGE_DIR).join(CONFIG_FILE);
+        crate::core::fs::write_atomic(&config_path, serde_json::to_string_pretty(&config).unwrap().as_bytes()).unwrap();
+    }
+    
+    /// File a fake ecosystem installs a package as, holding its version
+    fn fake_package(eco: &str, name: &str) -> PathBuf {
+        ecosystem_dir(&Ecosystem::Other(eco.to_string())).unwrap().join(name)
+    }
+    
+    /// Install a fake package; version `broken` fails once its file is written
+    pub(super) fn fake_install(eco: &str, name: &str, version: Option<&str>) -> Result<()> {
+        let path = fake_package(eco, name);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, version.unwrap_or("latest"))?;
+        if version == Some("broken") {
+            anyhow::bail!("Fake install of {} failed", name);
+        }
+        Ok(())
+    }
+    
+    /// Uninstall a fake package; a package named `stuck` can't be uninstalled
+    pub(super) fn fake_uninstall(eco: &str, name: &str) -> Result<()> {
+        if name == "stuck" {
+            anyhow::bail!("Fake uninstall of {} failed", name);
+        }
+        let path = fake_package(eco, name);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+    
+    fn fake(case: &str) -> Ecosystem {
+        Ecosystem::Other(format!("{}{}", FAKE_PREFIX, case))
+    }
+    
+    /// Hold the registry for the rest of a test
+    pub(super) fn registry_lock() -> lock::LockGuard {
+        setup();
+        lock::lock(REGISTRY_LOCK, "package tests", lock::DEFAULT_TIMEOUT).unwrap()
+    }
+    
+    /// Install the way `install_package` does once an ecosystem is available
+    fn try_install(name: &str, ecosystem: &Ecosystem, version: Option<&str>) -> Result<()> {
+        let mut registry = load_registry()?;
+        let key = resolve_package_key(&registry, name, Some(ecosystem.clone()))?;
+        let mut transaction = InstallTransaction::new(&key);
+        perform_install(&mut transaction, &mut registry, name, ecosystem, version, None, false)
+            .map_err(|e| transaction.roll_back(e))
+    }
+    
+    fn registry_bytes() -> Vec<u8> {
+        fs::read(constants::root_dir().join(PACKAGE_DIR).join(REGISTRY_FILE)).unwrap()
+    }
+    
+    /// Make registry saves fail until the returned backup blocker is removed
+    fn block_registry_saves() -> PathBuf {
+        // A backup can't be renamed over a directory that isn't empty
+        let blocker = constants::root_dir().join(PACKAGE_DIR).join(format!("{}.bak", REGISTRY_FILE));
+        let _ = fs::remove_file(&blocker);
+        fs::create_dir_all(blocker.join("blocked")).unwrap();
+        blocker
+    }
+    
+    fn unblock_registry_saves(blocker: PathBuf) {
+        fs::remove_dir_all(blocker).unwrap();
+    }
+    
+    #[test]
+    fn fake_installs_are_registered() {
+        let _lock = registry_lock();
+        let eco = fake("ok");
+        try_install("tool", &eco, Some("1.0")).unwrap();
+        
+        assert_eq!(fs::read_to_string(fake_package("fake-ok", "tool")).unwrap(), "1.0");
+        let registry = load_registry().unwrap();
+        let installed = &registry.packages["fake-ok:tool"];
+        assert_eq!((installed.version.as_str(), &installed.ecosystem), ("1.0", &eco));
+    }
+    
+    #[test]
+    fn failed_ecosystem_installs_leave_nothing_behind() {
+        let _lock = registry_lock();
+        let before = registry_bytes();
+        let eco = fake("broken");
+        
+        let err = try_install("tool", &eco, Some("broken")).unwrap_err();
+        assert!(format!("{:#}", err).contains("Fake install of tool failed"), "{:#}", err);
+        
+        // The directory the install created goes, with the half-installed package in it
+        assert!(!ecosystem_dir(&eco).unwrap().exists());
+        assert_eq!(registry_bytes(), before);
+    }
+    
+    #[test]
+    fn registry_save_failures_uninstall_the_package() {
+        let _lock = registry_lock();
+        let before = registry_bytes();
+        let eco = fake("unsaved");
+        
+        let blocker = block_registry_saves();
+        let result = try_install("tool", &eco, Some("1.0"));
+        unblock_registry_saves(blocker);
+        
+        let err = result.unwrap_err();
+        assert!(format!("{:#}", err).starts_with("Failed to save the package registry"), "{:#}", err);
+        assert!(!fake_package("fake-unsaved", "tool").exists());
+        assert!(!ecosystem_dir(&eco).unwrap().exists());
+        assert_eq!(registry_bytes(), before);
+        
+        // Nothing half-written is left next to the registry
+        let leftovers: Vec<_> = fs::read_dir(constants::root_dir().join(PACKAGE_DIR)).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.contains(".tmp-"))
+            .collect();
+        assert!(leftovers.is_empty(), "{:?}", leftovers);
+    }
+    
+    #[test]
+    fn failed_uninstalls_are_chained_onto_the_save_failure() {
+        let _lock = registry_lock();
+        let before = registry_bytes();
+        
+        let blocker = block_registry_saves();
+        let result = try_install("stuck", &fake("stuck"), None);
+        unblock_registry_saves(blocker);
+        
+        let err = format!("{:#}", result.unwrap_err());
+        assert!(err.contains("Install of fake-stuck:stuck failed and could not be fully undone"), "{}", err);
+        assert!(err.contains("Fake uninstall of stuck failed"), "{}", err);
+        assert!(err.contains("Failed to save the package registry"), "{}", err);
+        assert_eq!(registry_bytes(), before);
+    }
+    
+    #[test]
+    fn failed_upgrades_put_the_previous_version_back() {
+        let _lock = registry_lock();
+        let eco = fake("upgrade");
+        try_install("tool", &eco, Some("1.0")).unwrap();
+        
+        let err = try_install("tool", &eco, Some("broken")).unwrap_err();
+        assert!(format!("{:#}", err).contains("Fake install of tool failed"), "{:#}", err);
+        
+        assert_eq!(fs::read_to_string(fake_package("fake-upgrade", "tool")).unwrap(), "1.0");
+        assert_eq!(load_registry().unwrap().packages["fake-upgrade:tool"].version, "1.0");
+    }
+}
@@ -3,29 +3,34 @@
 
 use anyhow::{Result, Context};
 use tracing::{info, debug, warn, error};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
 use std::process::Command;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use crate::core::constants;
+use crate::core::lock;
+
+use super::{Ecosystem, InstalledPackage};
+
+// Constants
+const MANIFEST_FILE: &str = "package.json";
+const LOCK_FILE: &str = "package-lock.json";
+const PROJECTS_DIR: &str = "projects";
+const MODULES_DIR: &str = "node_modules";
 
 /// Install an npm package
 pub fn install_package(name: &str, version: Option<&str>) -> Result<()> {
     info!("Installing npm package: {}", name);
     
-    // Check if npm is installed
-    let npm_check = Command::new("which")
-        .arg("npm")
-        .output()?;
-        
-    if !npm_check.status.success() {
-        return Err(anyhow::anyhow!("npm not found, please install Node.js"));
-    }
+    // Resolve npm, failing with guidance if it is missing
+    let npm = super::tools::resolve("npm")?;
     
     // Create package directory
-    let npm_dir = PathBuf::from(constants::ROOT_DIR).join("packages").join("npm");
+    let npm_dir = constants::root_dir().join("packages").join("npm");
     std::fs::create_dir_all(&npm_dir)?;
     
     // Run npm install
-    let mut cmd = Command::new("npm");
+    let mut cmd = Command::new(&npm);
     cmd.current_dir(&npm_dir);
     cmd.arg("install");
     
@@ -38,6 +43,12 @@ pub fn install_package(name: &str, version: Option<&str>) -> Result<()> {
         cmd.arg(name);
     }
     
+    // Kept alive until npm exits, as it holds the mirror credentials file
+    let mirror = super::mirror::session(&super::Ecosystem::Npm)?;
+    if let Some(mirror) = &mirror {
+        mirror.apply(&mut cmd)?;
+    }
+    
     let output = cmd.output()?;
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -52,17 +63,11 @@ pub fn install_package(name: &str, version: Option<&str>) -> Result<()> {
 pub fn remove_package(name: &str) -> Result<()> {
     info!("Removing npm package: {}", name);
     
-    // Check if npm is installed
-    let npm_check = Command::new("which")
-        .arg("npm")
-        .output()?;
-        
-    if !npm_check.status.success() {
-        return Err(anyhow::anyhow!("npm not found, please install Node.js"));
-    }
+    // Resolve npm, failing with guidance if it is missing
+    let npm = super::tools::resolve("npm")?;
     
     // Run npm uninstall
-    let mut cmd = Command::new("npm");
+    let mut cmd = Command::new(&npm);
     cmd.args(["uninstall", "--global", name]);
     
     let output = cmd.output()?;
@@ -75,67 +80,268 @@ pub fn remove_package(name: &str) -> Result<()> {
     Ok(())
 }
 
+/// Install the dependencies of a `package.json` into a prefix of their own
+///
+/// The manifest, and its `package-lock.json` if there is one next to it,
+/// are copied to `projects/<name>` under the configured Npm path and
+/// `npm install` runs there. Every package the resulting lock file
+/// resolves is recorded in the registry at its locked version, pointing at
+/// its directory in the prefix; nested copies of a package at other
+/// versions are left to npm. Packages whose license the policy denies fail
+/// the install, and a prefix created for it is removed again. Returns the
+/// registry keys recorded.
+pub fn install_from_manifest(manifest_path: &Path) -> Result<Vec<String>> {
+    info!("Installing npm dependencies from {:?}", manifest_path);
+    
+    let manifest = read_json(manifest_path)?;
+    let project = match manifest.get("name").and_then(|n| n.as_str()) {
+        Some(name) => name.to_string(),
+        None => manifest_path.parent()
+            .and_then(|dir| dir.file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .ok_or_else(|| anyhow::anyhow!("{:?} has no name and no directory to name it after", manifest_path))?,
+    };
+    validate_name(&project)?;
+    
+    let _lock = lock::lock(super::REGISTRY_LOCK, &format!("install {}", manifest_path.display()), lock::DEFAULT_TIMEOUT)?;
+    
+    let prefix = ecosystem_root()?.join(PROJECTS_DIR).join(&project);
+    let created = !prefix.exists();
+    fs::create_dir_all(&prefix)
+        .with_context(|| format!("Failed to create npm prefix {:?}", prefix))?;
+    
+    let installed = install_into(manifest_path, &prefix);
+    if installed.is_err() && created {
+        if let Err(e) = fs::remove_dir_all(&prefix) {
+            error!("Failed to remove npm prefix {:?}: {}", prefix, e);
+        }
+    }
+    let installed = installed?;
+    
+    info!("Installed {} npm package(s) for {}", installed.len(), project);
+    Ok(installed)
+}
+
+/// Run `npm install` in a prefix and register what it resolved
+fn install_into(manifest_path: &Path, prefix: &Path) -> Result<Vec<String>> {
+    fs::copy(manifest_path, prefix.join(MANIFEST_FILE))
+        .with_context(|| format!("Failed to copy {:?}", manifest_path))?;
+    let lock_path = manifest_path.with_file_name(LOCK_FILE);
+    if lock_path.is_file() {
+        fs::copy(&lock_path, prefix.join(LOCK_FILE))
+            .with_context(|| format!("Failed to copy {:?}", lock_path))?;
+    }
+    
+    let mut cmd = super::tools::command("npm")?;
+    cmd.current_dir(prefix);
+    cmd.arg("install").arg("--prefix").arg(prefix);
+    
+    // Kept alive until npm exits, as it holds the mirror credentials file
+    let mirror = super::mirror::session(&Ecosystem::Npm)?;
+    if let Some(mirror) = &mirror {
+        mirror.apply(&mut cmd)?;
+    }
+    
+    let output = cmd.output().context("Failed to run npm install")?;
+    if !output.status.success() {
+        anyhow::bail!("npm install failed in {:?}\n{}", prefix, String::from_utf8_lossy(&output.stderr));
+    }
+    
+    let lock_file = read_json(&prefix.join(LOCK_FILE))
+        .context("npm install left no readable package-lock.json")?;
+    let resolved = resolved_packages(&lock_file);
+    
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let mirror_url = super::mirror::mirror_config(&Ecosystem::Npm)?.map(|m| m.url);
+    let mut registry = super::load_registry()?;
+    let mut keys = Vec::new();
+    for (name, (version, relative)) in resolved {
+        let dir = prefix.join(&relative);
+        let key = format!("npm:{}", name);
+        let declared = read_manifest(&dir).ok().and_then(|m| super::license::manifest_license(&m));
+        super::license::check_install(&key, declared.as_deref(), false)?;
+        
+        if let Some(previous) = registry.packages.get(&key) {
+            debug!("Replacing {} {} at {} in the registry", key, previous.version, previous.path);
+        }
+        registry.packages.insert(key.clone(), InstalledPackage {
+            name,
+            version,
+            ecosystem: Ecosystem::Npm,
+            path: dir.to_string_lossy().to_string(),
+            container_id: None,
+            installed_at: now,
+            config: HashMap::from([("manifest".to_string(), manifest_path.to_string_lossy().to_string())]),
+            mirror: mirror_url.clone(),
+        });
+        keys.push(key);
+    }
+    
+    registry.last_updated = now;
+    super::save_registry(&registry).context("Failed to save the package registry")?;
+    Ok(keys)
+}
+
+/// Packages a lock file resolves, by name, with their version and directory
+/// relative to the prefix
+///
+/// Reads the `packages` map of lockfile v2 and v3, or the nested
+/// `dependencies` of v1. Where a package is locked at several depths, the
+/// shallowest copy is kept.
+fn resolved_packages(lock_file: &serde_json::Value) -> BTreeMap<String, (String, String)> {
+    let mut found: Vec<(String, String, String)> = Vec::new();
+    
+    if let Some(packages) = lock_file.get("packages").and_then(|p| p.as_object()) {
+        for (path, entry) in packages {
+            // The root project is "", workspace members have no node_modules
+            let name = match path.rfind(MODULES_DIR) {
+                Some(at) => &path[at + MODULES_DIR.len() + 1..],
+                None => continue,
+            };
+            if entry.get("link").and_then(|l| l.as_bool()) == Some(true) {
+                continue;
+            }
+            if let Some(version) = entry.get("version").and_then(|v| v.as_str()) {
+                found.push((name.to_string(), version.to_string(), path.clone()));
+            }
+        }
+    } else if let Some(dependencies) = lock_file.get("dependencies") {
+        fn walk(dependencies: &serde_json::Value, parent: &str, found: &mut Vec<(String, String, String)>) {
+            let Some(dependencies) = dependencies.as_object() else { return };
+            for (name, entry) in dependencies {
+                let path = format!("{}{}/{}", parent, MODULES_DIR, name);
+                if let Some(version) = entry.get("version").and_then(|v| v.as_str()) {
+                    found.push((name.clone(), version.to_string(), path.clone()));
+                }
+                if let Some(nested) = entry.get("dependencies") {
+                    walk(nested, &format!("{}/", path), found);
+                }
+            }
+        }
+        walk(dependencies, "", &mut found);
+    }
+    
+    found.sort_by_key(|(_, _, path)| path.matches(MODULES_DIR).count());
+    let mut resolved = BTreeMap::new();
+    for (name, version, path) in found {
+        resolved.entry(name).or_insert((version, path));
+    }
+    resolved
+}
+
 /// Run an npm package with arguments
-pub fn run_package(name: &str, args: &[&str]) -> Result<()> {
-    info!("Running npm package: {}", name);
+///
+/// The executable is the package's `bin` entry from its package.json: a
+/// lone entry, or the one named after the package when there are several.
+pub fn run_package(package: &InstalledPackage, args: &[&str]) -> Result<()> {
+    info!("Running npm package: {}", package.name);
     
-    // First try npx
-    let mut cmd = Command::new("npx");
-    cmd.arg(name);
-    cmd.args(args);
+    let dir = package_dir(package)?;
+    let manifest = read_manifest(&dir)?;
+    let script = bin_entry(&package.name, &manifest)?;
     
-    // Run in a subshell to handle shebang scripts properly
-    let mut child = cmd.spawn()?;
-    let status = child.wait()?;
+    let mut cmd = super::tools::command("node")?;
+    cmd.arg(dir.join(&script));
+    cmd.args(args);
     
+    let status = cmd.spawn()
+        .with_context(|| format!("Failed to run npm package {}", package.name))?
+        .wait()?;
     if !status.success() {
-        warn!("npx command failed, trying node_modules/.bin directory");
-        
-        // Try looking in the standard binary paths
-        let home = std::env::var("HOME").unwrap_or_default();
-        let bin_paths = [
-            format!("{}/node_modules/.bin/{}", home, name),
-            format!("/usr/local/bin/{}", name),
-            format!("/usr/bin/{}", name),
-        ];
-        
-        for path in bin_paths {
-            if std::path::Path::new(&path).exists() {
-                let mut cmd = Command::new(&path);
-                cmd.args(args);
-                
-                let mut child = cmd.spawn()?;
-                child.wait()?;
-                return Ok(());
+        warn!("npm package {} exited with {}", package.name, status);
+    }
+    Ok(())
+}
+
+/// Script a package's `bin` entry points at, relative to the package directory
+fn bin_entry(name: &str, manifest: &serde_json::Value) -> Result<String> {
+    // A scoped package's command is named after the part after the scope
+    let command = name.rsplit('/').next().unwrap_or(name);
+    match manifest.get("bin") {
+        Some(serde_json::Value::String(script)) => Ok(script.clone()),
+        Some(serde_json::Value::Object(bins)) => {
+            let chosen = if bins.len() == 1 { bins.values().next() } else { bins.get(command) };
+            match chosen.and_then(|script| script.as_str()) {
+                Some(script) => Ok(script.to_string()),
+                None => anyhow::bail!("npm package {} has several executables ({}); none is named {}",
+                                      name, bins.keys().cloned().collect::<Vec<_>>().join(", "), command),
             }
         }
-        
-        return Err(anyhow::anyhow!("Failed to run npm package: {}", name));
+        _ => anyhow::bail!("npm package {} declares no executable in its package.json", name),
+    }
+}
+
+/// Directory an installed npm package lives in
+///
+/// Packages installed from a manifest record their own directory; others
+/// were installed globally and live under `npm root --global`.
+pub fn package_dir(package: &InstalledPackage) -> Result<PathBuf> {
+    let own = PathBuf::from(&package.path);
+    if own.join(MANIFEST_FILE).is_file() {
+        return Ok(own);
     }
     
+    let output = super::tools::command("npm")?.args(["root", "--global"]).output()?;
+    if !output.status.success() {
+        anyhow::bail!("Failed to locate global npm packages: {}", String::from_utf8_lossy(&output.stderr));
+    }
+    Ok(PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()).join(&package.name))
+}
+
+/// The package.json of a package directory
+pub fn read_manifest(dir: &Path) -> Result<serde_json::Value> {
+    read_json(&dir.join(MANIFEST_FILE))
+}
+
+/// Parse a package.json or package-lock.json
+fn read_json(path: &Path) -> Result<serde_json::Value> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {:?}", path))?;
+    serde_json::from_str(&content)
+        .with_context(|| format!("Invalid JSON in {:?}", path))
+}
+
+/// Check an npm package name, allowing scoped names like `@scope/pkg`
+///
+/// Names end up in paths, so anything but a plain name or a single
+/// scope-and-name pair is refused.
+fn validate_name(name: &str) -> Result<()> {
+    let parts = match name.strip_prefix('@') {
+        Some(scoped) => scoped.split_once('/').map(|(scope, package)| vec![scope, package]).unwrap_or_default(),
+        None => vec![name],
+    };
+    let valid = !parts.is_empty() && parts.iter().all(|part| !part.is_empty() && !part.starts_with('.')
+        && !part.contains(|c: char| c == '/' || c == '\\' || c.is_whitespace()));
+    if !valid {
+        anyhow::bail!("Invalid npm package name: {}", name);
+    }
     Ok(())
 }
 
+/// The configured Npm ecosystem path
+fn ecosystem_root() -> Result<PathBuf> {
+    Ok(super::load_config()?.ecosystem_paths.get("Npm")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| constants::root_dir().join("packages").join("npm")))
+}
+
 /// Search for npm packages
 pub fn search_packages(query: &str) -> Result<Vec<String>> {
     info!("Searching for npm packages matching: {}", query);
     
-    // Check if npm is installed
-    let npm_check = Command::new("which")
-        .arg("npm")
-        .output()?;
-        
-    if !npm_check.status.success() {
-        return Err(anyhow::anyhow!("npm not found, please install Node.js"));
-    }
+    // Resolve npm, failing with guidance if it is missing
+    let npm = super::tools::resolve("npm")?;
     
     let mut results = Vec::new();
     
     // Run npm search
-    let cmd = Command::new("npm")
+    let cmd = Command::new(&npm)
         .args(["search", query, "--no-description", "--parseable"])
         .output()?;
-        
+    
     if cmd.status.success() {
         let output = String::from_utf8_lossy(&cmd.stdout);
         for line in output.lines() {
@@ -161,3 +367,169 @@ pub fn search_packages(query: &str) -> Result<Vec<String>> {
     
     Ok(results)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+    use std::sync::Once;
+    use super::super::tests as package_tests;
+    
+    static SETUP: Once = Once::new();
+    
+    /// Vendored project whose `node_modules` the fake npm "installs"
+    fn fixture() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("src/package/fixtures/npm-project")
+    }
+    
+    /// Put a fake npm in place of the real one, once per process
+    ///
+    /// It copies the fixture's `node_modules` into the prefix, and its lock
+    /// file when the project has none; projects named `fixture-broken` fail
+    /// half-way.
+    fn setup() {
+        SETUP.call_once(|| {
+            package_tests::setup();
+            let npm = constants::root_dir().join("npm-tests").join("npm");
+            fs::create_dir_all(npm.parent().unwrap()).unwrap();
+            fs::write(&npm, format!(r#"#!/bin/sh
+[ "$1" = install ] || exit 1
+if grep -q '"fixture-broken"' package.json; then
+    mkdir -p node_modules/left-pad
+    echo "npm ERR! fake failure" >&2
+    exit 1
+fi
+cp -R "{fixture}/node_modules" .
+[ -f package-lock.json ] || cp "{fixture}/package-lock.json" .
+"#, fixture = fixture().display())).unwrap();
+            fs::set_permissions(&npm, fs::Permissions::from_mode(0o755)).unwrap();
+            package_tests::update_config(|config| {
+                config.tool_paths.insert("npm".to_string(), npm.to_string_lossy().to_string());
+            });
+        });
+    }
+    
+    /// Copy of the fixture's package.json under another project name
+    fn project(name: &str) -> PathBuf {
+        let dir = constants::root_dir().join("npm-tests").join(name);
+        fs::create_dir_all(&dir).unwrap();
+        let mut manifest = read_manifest(&fixture()).unwrap();
+        manifest["name"] = serde_json::json!(name);
+        let path = dir.join(MANIFEST_FILE);
+        fs::write(&path, serde_json::to_string_pretty(&manifest).unwrap()).unwrap();
+        path
+    }
+    
+    fn registry_bytes() -> Vec<u8> {
+        fs::read(constants::root_dir().join(super::super::PACKAGE_DIR).join(super::super::REGISTRY_FILE)).unwrap()
+    }
+    
+    #[test]
+    fn manifest_installs_record_the_locked_versions() {
+        setup();
+        let _lock = package_tests::registry_lock();
+        let manifest = fixture().join(MANIFEST_FILE);
+        
+        let keys = install_from_manifest(&manifest).unwrap();
+        assert_eq!(keys, ["npm:@scope/cli", "npm:left-pad"]);
+        
+        // The top-level copy is recorded, not the older one nested under @scope/cli
+        let prefix = ecosystem_root().unwrap().join(PROJECTS_DIR).join("fixture-app");
+        let registry = super::super::load_registry().unwrap();
+        let left_pad = &registry.packages["npm:left-pad"];
+        assert_eq!(left_pad.version, "1.3.0");
+        assert_eq!(PathBuf::from(&left_pad.path), prefix.join("node_modules/left-pad"));
+        assert_eq!(left_pad.config["manifest"], manifest.to_string_lossy());
+        assert_eq!(registry.packages["npm:@scope/cli"].version, "2.1.0");
+        assert!(!registry.packages.contains_key("npm:local-lib"));
+    }
+    
+    #[test]
+    fn lock_files_written_by_npm_are_read() {
+        setup();
+        let _lock = package_tests::registry_lock();
+        let manifest = project("fixture-unlocked");
+        
+        assert_eq!(install_from_manifest(&manifest).unwrap(), ["npm:@scope/cli", "npm:left-pad"]);
+        let prefix = ecosystem_root().unwrap().join(PROJECTS_DIR).join("fixture-unlocked");
+        assert!(prefix.join(LOCK_FILE).is_file());
+        assert_eq!(PathBuf::from(&super::super::load_registry().unwrap().packages["npm:left-pad"].path),
+                   prefix.join("node_modules/left-pad"));
+    }
+    
+    #[test]
+    fn scoped_packages_are_found_and_run_from_their_bin_entry() {
+        setup();
+        let _lock = package_tests::registry_lock();
+        install_from_manifest(&fixture().join(MANIFEST_FILE)).unwrap();
+        let registry = super::super::load_registry().unwrap();
+        
+        // Scoped names survive the `npm:` key and lookups by bare name
+        assert_eq!(super::super::resolve_package_key(&registry, "@scope/cli", None).unwrap(), "npm:@scope/cli");
+        let package = &registry.packages["npm:@scope/cli"];
+        assert_eq!(package.name, "@scope/cli");
+        
+        let dir = package_dir(package).unwrap();
+        let script = bin_entry(&package.name, &read_manifest(&dir).unwrap()).unwrap();
+        assert_eq!(script, "bin/cli.js");
+        assert!(dir.join(script).is_file());
+    }
+    
+    #[test]
+    fn failed_installs_remove_their_prefix_and_leave_the_registry_alone() {
+        setup();
+        let _lock = package_tests::registry_lock();
+        let before = registry_bytes();
+        let manifest = project("fixture-broken");
+        
+        let err = install_from_manifest(&manifest).unwrap_err();
+        assert!(err.to_string().contains("npm ERR! fake failure"), "{}", err);
+        assert!(!ecosystem_root().unwrap().join(PROJECTS_DIR).join("fixture-broken").exists());
+        assert_eq!(registry_bytes(), before);
+    }
+    
+    #[test]
+    fn lockfile_v1_dependencies_are_walked_shallowest_first() {
+        let lock_file = serde_json::json!({
+            "lockfileVersion": 1,
+            "dependencies": {
+                "@scope/cli": {
+                    "version": "2.1.0",
+                    "dependencies": { "left-pad": { "version": "1.1.3" } }
+                },
+                "left-pad": { "version": "1.3.0" }
+            }
+        });
+        
+        let resolved = resolved_packages(&lock_file);
+        assert_eq!(resolved["left-pad"], ("1.3.0".to_string(), "node_modules/left-pad".to_string()));
+        assert_eq!(resolved["@scope/cli"], ("2.1.0".to_string(), "node_modules/@scope/cli".to_string()));
+        assert_eq!(resolved.len(), 2);
+    }
+    
+    #[test]
+    fn names_may_be_scoped_but_not_paths() {
+        for valid in ["left-pad", "@scope/cli", "@types/node"] {
+            assert!(validate_name(valid).is_ok(), "{}", valid);
+        }
+        for invalid in ["", "@scope", "@scope/", "@/cli", "@scope/cli/extra", "../escape", ".hidden", "a/b", "has space"] {
+            assert!(validate_name(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+    
+    #[test]
+    fn bin_entries_follow_the_package_json() {
+        let only = serde_json::json!({ "bin": "cli.js" });
+        assert_eq!(bin_entry("tool", &only).unwrap(), "cli.js");
+        
+        let single = serde_json::json!({ "bin": { "other-name": "bin/run.js" } });
+        assert_eq!(bin_entry("tool", &single).unwrap(), "bin/run.js");
+        
+        let several = serde_json::json!({ "bin": { "cli": "bin/cli.js", "cli-admin": "bin/admin.js" } });
+        assert_eq!(bin_entry("@scope/cli", &several).unwrap(), "bin/cli.js");
+        let err = bin_entry("@scope/tool", &several).unwrap_err();
+        assert!(err.to_string().contains("none is named tool"), "{}", err);
+        
+        assert!(bin_entry("tool", &serde_json::json!({ "main": "index.js" })).is_err());
+    }
+}
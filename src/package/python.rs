@@ -11,26 +11,21 @@ use crate::core::constants;
 pub fn install_package(name: &str, version: Option<&str>) -> Result<()> {
     info!("Installing Python package: {}", name);
     
-    // Check if pip is installed
-    let pip_check = Command::new("which")
-        .arg("pip")
-        .output()?;
-        
-    if !pip_check.status.success() {
-        return Err(anyhow::anyhow!("pip not found, please install Python and pip"));
-    }
+    // Resolve python and pip, failing with guidance if either is missing
+    let python = super::tools::resolve("python")?;
+    super::tools::resolve("pip")?;
     
     // Create virtual environment directory if it doesn't exist
-    let venv_dir = PathBuf::from(constants::ROOT_DIR).join("packages").join("python").join("venv");
+    let venv_dir = constants::root_dir().join("packages").join("python").join("venv");
     if !venv_dir.exists() {
         info!("Creating Python virtual environment");
         std::fs::create_dir_all(venv_dir.parent().unwrap())?;
         
         // Create virtual environment
-        let venv_cmd = Command::new("python")
+        let venv_cmd = Command::new(&python)
             .args(["-m", "venv", &venv_dir.to_string_lossy()])
             .output()?;
-            
+        
         if !venv_cmd.status.success() {
             return Err(anyhow::anyhow!("Failed to create Python virtual environment"));
         }
@@ -49,6 +44,12 @@ pub fn install_package(name: &str, version: Option<&str>) -> Result<()> {
         cmd.arg(name);
     }
     
+    // Kept alive until pip exits, as it holds the mirror credentials file
+    let mirror = super::mirror::session(&super::Ecosystem::Python)?;
+    if let Some(mirror) = &mirror {
+        mirror.apply(&mut cmd)?;
+    }
+    
     let output = cmd.output()?;
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
@@ -63,17 +64,11 @@ pub fn install_package(name: &str, version: Option<&str>) -> Result<()> {
 pub fn remove_package(name: &str) -> Result<()> {
     info!("Removing Python package: {}", name);
     
-    // Check if pip is installed
-    let pip_check = Command::new("which")
-        .arg("pip")
-        .output()?;
-        
-    if !pip_check.status.success() {
-        return Err(anyhow::anyhow!("pip not found, please install Python and pip"));
-    }
+    // Resolve pip, failing with guidance if it is missing
+    super::tools::resolve("pip")?;
     
     // Determine pip executable path
-    let venv_dir = PathBuf::from(constants::ROOT_DIR).join("packages").join("python").join("venv");
+    let venv_dir = constants::root_dir().join("packages").join("python").join("venv");
     let pip_path = venv_dir.join("bin").join("pip");
     
     if !pip_path.exists() {
@@ -99,7 +94,7 @@ pub fn run_package(name: &str, args: &[&str]) -> Result<()> {
     info!("Running Python package: {}", name);
     
     // Determine python executable path
-    let venv_dir = PathBuf::from(constants::ROOT_DIR).join("packages").join("python").join("venv");
+    let venv_dir = constants::root_dir().join("packages").join("python").join("venv");
     let python_path = venv_dir.join("bin").join("python");
     
     if !python_path.exists() {
@@ -142,23 +137,17 @@ pub fn run_package(name: &str, args: &[&str]) -> Result<()> {
 pub fn search_packages(query: &str) -> Result<Vec<String>> {
     info!("Searching for Python packages matching: {}", query);
     
-    // Check if pip is installed
-    let pip_check = Command::new("which")
-        .arg("pip")
-        .output()?;
-        
-    if !pip_check.status.success() {
-        return Err(anyhow::anyhow!("pip not found, please install Python and pip"));
-    }
+    // Resolve pip, failing with guidance if it is missing
+    let pip = super::tools::resolve("pip")?;
     
     let mut results = Vec::new();
     
     // Run pip search (note: this functionality was removed in newer pip versions)
     // Instead, we'll use pip index
-    let cmd = Command::new("pip")
+    let cmd = Command::new(&pip)
         .args(["index", "versions", query])
         .output();
-        
+    
     match cmd {
         Ok(output) => {
             if output.status.success() {
@@ -16,7 +16,7 @@ pub fn install_package(name: &str, version: Option<&str>) -> Result<()> {
     let cargo_check = Command::new("which")
         .arg("cargo")
         .output()?;
-        
+    
     if !cargo_check.status.success() {
         return Err(anyhow::anyhow!("cargo not found, please install Rust toolchain"));
     }
@@ -30,7 +30,7 @@ pub fn install_package(name: &str, version: Option<&str>) -> Result<()> {
     }
     
     // Set custom install location within SentientOS package directory
-    let cargo_dir = PathBuf::from(constants::ROOT_DIR).join("packages").join("rust");
+    let cargo_dir = constants::root_dir().join("packages").join("rust");
     fs::create_dir_all(&cargo_dir)?;
     
     cmd.args(["--root", cargo_dir.to_str().unwrap()]);
@@ -54,13 +54,13 @@ pub fn remove_package(name: &str) -> Result<()> {
     let cargo_check = Command::new("which")
         .arg("cargo")
         .output()?;
-        
+    
     if !cargo_check.status.success() {
         return Err(anyhow::anyhow!("cargo not found, please install Rust toolchain"));
     }
     
     // Remove the package using cargo uninstall
-    let cargo_dir = PathBuf::from(constants::ROOT_DIR).join("packages").join("rust");
+    let cargo_dir = constants::root_dir().join("packages").join("rust");
     
     let mut cmd = Command::new("cargo");
     cmd.arg("uninstall");
@@ -81,7 +81,7 @@ pub fn remove_package(name: &str) -> Result<()> {
 pub fn run_package(name: &str, args: &[&str]) -> Result<()> {
     info!("Running Rust package: {}", name);
     
-    let cargo_dir = PathBuf::from(constants::ROOT_DIR).join("packages").join("rust");
+    let cargo_dir = constants::root_dir().join("packages").join("rust");
     let bin_path = cargo_dir.join("bin").join(name);
     
     if !bin_path.exists() {
@@ -110,7 +110,7 @@ pub fn search_packages(query: &str) -> Result<Vec<String>> {
     let cargo_check = Command::new("which")
         .arg("cargo")
         .output()?;
-        
+    
     if !cargo_check.status.success() {
         return Err(anyhow::anyhow!("cargo not found, please install Rust toolchain"));
     }
@@ -0,0 +1,353 @@
+// SentientOS Package Manager - Ecosystem Tools
+// Detection of the external tools each ecosystem backend shells out to
+
+use anyhow::{Result, Context};
+use tracing::{info, debug, warn};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+use super::{Ecosystem, load_config, save_config};
+
+/// An external tool a backend needs
+struct ToolSpec {
+    /// Tool name, as used in `tool_paths`
+    name: &'static str,
+    
+    /// Executable names searched on PATH, in order
+    candidates: &'static [&'static str],
+    
+    /// What to install to get the tool
+    provided_by: &'static str,
+}
+
+// Tools used by the ecosystem backends
+const TOOLS: &[ToolSpec] = &[
+    ToolSpec { name: "node", candidates: &["node"], provided_by: "Node.js" },
+    ToolSpec { name: "npm", candidates: &["npm"], provided_by: "Node.js (npm ships with it)" },
+    ToolSpec { name: "python", candidates: &["python3", "python"], provided_by: "Python 3" },
+    ToolSpec { name: "pip", candidates: &["pip3", "pip"], provided_by: "pip (`python3 -m ensurepip` or your distribution's python3-pip)" },
+    ToolSpec { name: "java", candidates: &["java"], provided_by: "a Java runtime (JRE or JDK)" },
+    ToolSpec { name: "mvn", candidates: &["mvn"], provided_by: "Apache Maven" },
+    ToolSpec { name: "cargo", candidates: &["cargo"], provided_by: "Rust (https://rustup.rs)" },
+    ToolSpec { name: "go", candidates: &["go"], provided_by: "Go (https://go.dev/dl)" },
+];
+
+// Detected tool paths; None means the tool was not found
+static DETECTED: Mutex<Option<HashMap<&'static str, Option<PathBuf>>>> = Mutex::new(None);
+
+/// Whether an ecosystem can be used on this machine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Availability {
+    /// Every tool the backend needs was found
+    Available,
+    
+    /// Tools the backend needs are missing
+    Unavailable {
+        /// Missing tools
+        missing: Vec<String>,
+    },
+}
+
+/// Availability of one ecosystem
+#[derive(Debug, Clone)]
+pub struct EcosystemStatus {
+    /// Ecosystem
+    pub ecosystem: Ecosystem,
+    
+    /// Whether it can be used
+    pub availability: Availability,
+}
+
+impl EcosystemStatus {
+    /// Whether the ecosystem can be used
+    pub fn is_available(&self) -> bool {
+        self.availability == Availability::Available
+    }
+    
+    /// How to make the ecosystem usable, if it is not
+    pub fn guidance(&self) -> Option<String> {
+        match &self.availability {
+            Availability::Available => None,
+            Availability::Unavailable { missing } => Some(guidance(&self.ecosystem, missing)),
+        }
+    }
+}
+
+/// Detect every tool, replacing earlier results
+pub fn detect() -> HashMap<&'static str, Option<PathBuf>> {
+    let tool_paths = configured_paths();
+    let detected: HashMap<_, _> = TOOLS.iter()
+        .map(|spec| (spec.name, locate(spec, &tool_paths)))
+        .collect();
+    
+    let missing: Vec<&str> = detected.iter().filter(|(_, p)| p.is_none()).map(|(t, _)| *t).collect();
+    if missing.is_empty() {
+        info!("All ecosystem tools found");
+    } else {
+        info!("Ecosystem tools not found: {}", missing.join(", "));
+    }
+    
+    *DETECTED.lock().unwrap() = Some(detected.clone());
+    detected
+}
+
+/// Path of a tool, or an error naming it and how to get it
+///
+/// A tool missing from the cache is looked for again, so tools installed
+/// while the daemon runs are picked up.
+pub fn resolve(tool: &str) -> Result<PathBuf> {
+    let spec = find_spec(tool)?;
+    
+    if let Some(Some(path)) = DETECTED.lock().unwrap().as_ref().and_then(|d| d.get(spec.name)) {
+        return Ok(path.clone());
+    }
+    
+    let path = locate(spec, &configured_paths());
+    DETECTED.lock().unwrap().get_or_insert_with(HashMap::new).insert(spec.name, path.clone());
+    
+    path.ok_or_else(|| anyhow::anyhow!(
+        "`{}` was not found on PATH. Install {}, or if it is installed elsewhere run \
+         `sentctl package tool-path {} <path>`", spec.name, spec.provided_by, spec.name))
+}
+
+/// Build a command running a tool
+pub fn command(tool: &str) -> Result<Command> {
+    Ok(Command::new(resolve(tool)?))
+}
+
+/// Tools an ecosystem's backend needs
+pub fn required_tools(ecosystem: &Ecosystem) -> &'static [&'static str] {
+    match ecosystem {
+        Ecosystem::Npm => &["node", "npm"],
+        Ecosystem::Python => &["python", "pip"],
+        Ecosystem::Java => &["java", "mvn"],
+        Ecosystem::Rust => &["cargo"],
+        Ecosystem::Go => &["go"],
+        Ecosystem::Native | Ecosystem::Linux | Ecosystem::Other(_) => &[],
+    }
+}
+
+/// Availability of an ecosystem
+pub fn ecosystem_status(ecosystem: &Ecosystem) -> EcosystemStatus {
+    let missing: Vec<String> = match ecosystem {
+        // The system package manager is detected, or set in the package config
+        Ecosystem::Linux => match super::linux::backend() {
+            Ok(_) => Vec::new(),
+            Err(_) => vec!["apt, dnf, pacman, zypper or apk".to_string()],
+        },
+        Ecosystem::Other(eco) => vec![format!("a backend for {}", eco)],
+        _ => required_tools(ecosystem).iter()
+            .filter(|tool| resolve(tool).is_err())
+            .map(|tool| tool.to_string())
+            .collect(),
+    };
+    
+    EcosystemStatus {
+        ecosystem: ecosystem.clone(),
+        availability: if missing.is_empty() {
+            Availability::Available
+        } else {
+            Availability::Unavailable { missing }
+        },
+    }
+}
+
+/// Availability of every built-in ecosystem
+pub fn ecosystems() -> Vec<EcosystemStatus> {
+    [Ecosystem::Native, Ecosystem::Linux, Ecosystem::Npm, Ecosystem::Python,
+     Ecosystem::Java, Ecosystem::Rust, Ecosystem::Go]
+        .iter()
+        .map(ecosystem_status)
+        .collect()
+}
+
+/// Fail with guidance if an ecosystem cannot be used
+pub fn ensure_available(ecosystem: &Ecosystem) -> Result<()> {
+    match ecosystem_status(ecosystem).guidance() {
+        Some(guidance) => Err(anyhow::anyhow!(guidance)),
+        None => Ok(()),
+    }
+}
+
+/// Use a tool at a custom path, or go back to searching PATH with `None`
+///
+/// The path must be an executable that runs; it is stored under
+/// `tool_paths` in the package manager config.
+pub fn set_tool_path(tool: &str, path: Option<&Path>) -> Result<()> {
+    let spec = find_spec(tool)?;
+    let mut config = load_config()?;
+    
+    match path {
+        Some(path) => {
+            if !path.is_absolute() {
+                anyhow::bail!("Tool path must be absolute: {}", path.display());
+            }
+            validate_executable(path)?;
+            
+            // Running it catches wrong architectures and broken installs
+            Command::new(path)
+                .arg(if spec.name == "java" { "-version" } else { "--version" })
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .with_context(|| format!("{} cannot be run", path.display()))?;
+            
+            info!("Using {} for {}", path.display(), spec.name);
+            config.tool_paths.insert(spec.name.to_string(), path.to_string_lossy().to_string());
+        }
+        None => {
+            info!("Searching PATH for {}", spec.name);
+            config.tool_paths.remove(spec.name);
+        }
+    }
+    
+    save_config(&config)?;
+    detect();
+    Ok(())
+}
+
+/// Configured tool paths, if the package manager is initialized
+fn configured_paths() -> HashMap<String, String> {
+    load_config().map(|c| c.tool_paths).unwrap_or_default()
+}
+
+/// Find a tool at its configured path, then on PATH
+fn locate(spec: &ToolSpec, tool_paths: &HashMap<String, String>) -> Option<PathBuf> {
+    if let Some(configured) = tool_paths.get(spec.name) {
+        let path = PathBuf::from(configured);
+        if validate_executable(&path).is_ok() {
+            return Some(path);
+        }
+        warn!("Configured path of {} is not usable: {}", spec.name, configured);
+        return None;
+    }
+    
+    let path = spec.candidates.iter().find_map(|candidate| find_executable(candidate))?;
+    debug!("Found {} at {}", spec.name, path.display());
+    Some(path)
+}
+
+/// Find an executable by name on PATH
+pub fn find_executable(name: &str) -> Option<PathBuf> {
+    let search_path = std::env::var_os("PATH")?;
+    std::env::split_paths(&search_path)
+        .map(|dir| dir.join(name))
+        .find(|path| validate_executable(path).is_ok())
+}
+
+/// Check that a path is an executable file
+fn validate_executable(path: &Path) -> Result<()> {
+    let metadata = std::fs::metadata(path)
+        .with_context(|| format!("{} does not exist", path.display()))?;
+    if !metadata.is_file() {
+        anyhow::bail!("{} is not a file", path.display());
+    }
+    
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o111 == 0 {
+            anyhow::bail!("{} is not executable", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Look up a known tool
+fn find_spec(tool: &str) -> Result<&'static ToolSpec> {
+    TOOLS.iter()
+        .find(|spec| spec.name == tool)
+        .ok_or_else(|| anyhow::anyhow!("Unknown tool: {} (known: {})",
+            tool, TOOLS.iter().map(|s| s.name).collect::<Vec<_>>().join(", ")))
+}
+
+/// How to make an unavailable ecosystem usable
+fn guidance(ecosystem: &Ecosystem, missing: &[String]) -> String {
+    let name = format!("{:?}", ecosystem).to_lowercase();
+    let mut lines = vec![format!("The {} ecosystem is unavailable: missing {}", name, missing.join(", "))];
+    
+    for tool in missing {
+        if let Ok(spec) = find_spec(tool) {
+            lines.push(format!("  - install {} to get `{}`, or if it is installed elsewhere run \
+                                `sentctl package tool-path {} <path>`", spec.provided_by, spec.name, spec.name));
+        }
+    }
+    lines.push("  - native packages from the ZK-Store need no external tools; \
+                look for an alternative with `sentctl store search <name>`".to_string());
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Fresh directory for one test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sentient-tools-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    
+    #[cfg(unix)]
+    fn write_script(path: &Path, mode: u32) {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::write(path, "#!/bin/sh\nexit 0\n").unwrap();
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).unwrap();
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn only_executable_files_are_usable_tools() {
+        let dir = test_dir("validate");
+        write_script(&dir.join("node"), 0o755);
+        write_script(&dir.join("readme"), 0o644);
+        
+        assert!(validate_executable(&dir.join("node")).is_ok());
+        assert!(validate_executable(&dir.join("readme")).unwrap_err().to_string().contains("not executable"));
+        assert!(validate_executable(&dir).unwrap_err().to_string().contains("not a file"));
+        assert!(validate_executable(&dir.join("missing")).unwrap_err().to_string().contains("does not exist"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn configured_paths_take_precedence_over_path() {
+        let dir = test_dir("configured");
+        write_script(&dir.join("custom-node"), 0o755);
+        let spec = find_spec("node").unwrap();
+        
+        let configured = HashMap::from([("node".to_string(), dir.join("custom-node").to_string_lossy().to_string())]);
+        assert_eq!(locate(spec, &configured), Some(dir.join("custom-node")));
+        
+        // A broken configured path is reported rather than silently replaced by PATH
+        let broken = HashMap::from([("node".to_string(), dir.join("gone").to_string_lossy().to_string())]);
+        assert_eq!(locate(spec, &broken), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[test]
+    fn guidance_names_each_missing_tool_and_how_to_get_it() {
+        let text = guidance(&Ecosystem::Python, &["pip".to_string()]);
+        assert!(text.starts_with("The python ecosystem is unavailable: missing pip"));
+        assert!(text.contains("install pip (`python3 -m ensurepip`"));
+        assert!(text.contains("sentctl package tool-path pip <path>"));
+        assert!(text.contains("sentctl store search"));
+        
+        assert!(find_spec("gradle").is_err_and(|e| e.to_string().contains("known: node, npm")));
+        assert!(resolve("gradle").is_err());
+    }
+    
+    #[test]
+    fn ecosystems_without_backends_are_unavailable() {
+        assert_eq!(required_tools(&Ecosystem::Java), ["java", "mvn"]);
+        assert!(required_tools(&Ecosystem::Native).is_empty());
+        assert!(ecosystem_status(&Ecosystem::Native).is_available());
+        
+        let status = ecosystem_status(&Ecosystem::Other("haskell".to_string()));
+        assert_eq!(status.availability, Availability::Unavailable { missing: vec!["a backend for haskell".to_string()] });
+        assert!(ensure_available(&Ecosystem::Other("haskell".to_string())).is_err());
+    }
+}
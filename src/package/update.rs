@@ -0,0 +1,246 @@
+// SentientOS Package Manager - Bulk Updates
+// Checks every installed package for a newer version and reinstalls the changed ones
+
+use anyhow::{Result, Context};
+use tracing::{info, warn};
+use std::process::Command;
+use serde::{Serialize, Deserialize};
+
+use crate::core::constants;
+use crate::store;
+use super::{Ecosystem, InstalledPackage};
+use super::linux::LinuxBackend;
+
+/// A package moved to a newer version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdatedPackage {
+    /// Registry key, such as `npm:left-pad`
+    pub package: String,
+    
+    /// Version recorded before the update
+    pub from: String,
+    
+    /// Version installed now
+    pub to: String,
+}
+
+/// A package left as it was
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedPackage {
+    /// Registry key
+    pub package: String,
+    
+    /// Why it was not updated
+    pub reason: String,
+}
+
+/// A package whose check or reinstall failed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedPackage {
+    /// Registry key
+    pub package: String,
+    
+    /// What went wrong
+    pub error: String,
+}
+
+/// Outcome of `update_all`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateReport {
+    /// Packages moved to a newer version
+    pub updated: Vec<UpdatedPackage>,
+    
+    /// Packages already current or that cannot be checked
+    pub skipped: Vec<SkippedPackage>,
+    
+    /// Packages that failed; the others were still processed
+    pub failed: Vec<FailedPackage>,
+}
+
+/// Update every installed package, optionally of one ecosystem
+///
+/// Each package's ecosystem is asked for its latest version and only
+/// packages whose version changed are reinstalled, pinned to that version
+/// so the registry records what is actually installed. A failing package is
+/// reported and the run continues.
+pub fn update_all(ecosystem: Option<Ecosystem>) -> Result<UpdateReport> {
+    info!("Updating all packages{}", ecosystem.as_ref().map_or(String::new(), |e| format!(" of {:?}", e)));
+    
+    let mut packages: Vec<(String, InstalledPackage)> = super::load_registry()?
+        .packages
+        .into_iter()
+        .filter(|(_, p)| ecosystem.as_ref().map_or(true, |e| &p.ecosystem == e))
+        .collect();
+    packages.sort_by(|a, b| a.0.cmp(&b.0));
+    
+    let mut report = UpdateReport::default();
+    for (key, package) in packages {
+        let latest = match latest_version(&package.name, &package.ecosystem) {
+            Ok(Some(latest)) => latest,
+            Ok(None) => {
+                report.skipped.push(SkippedPackage {
+                    package: key,
+                    reason: format!("{:?} packages cannot be checked for updates", package.ecosystem),
+                });
+                continue;
+            }
+            Err(e) => {
+                warn!("Failed to check {} for updates: {:#}", key, e);
+                report.failed.push(FailedPackage { package: key, error: format!("{:#}", e) });
+                continue;
+            }
+        };
+        
+        if latest == package.version {
+            report.skipped.push(SkippedPackage { package: key, reason: "up to date".to_string() });
+            continue;
+        }
+        
+        info!("Updating {} from {} to {}", key, package.version, latest);
+        match reinstall(&package, &latest) {
+            Ok(()) => report.updated.push(UpdatedPackage { package: key, from: package.version, to: latest }),
+            Err(e) => {
+                warn!("Failed to update {}: {:#}", key, e);
+                report.failed.push(FailedPackage { package: key, error: format!("{:#}", e) });
+            }
+        }
+    }
+    
+    crate::logs::ship::ship_audit("package.update_all", &format!(
+        "{} updated, {} skipped, {} failed", report.updated.len(), report.skipped.len(), report.failed.len()));
+    Ok(report)
+}
+
+/// Install a package at a new version
+///
+/// The install snapshots the old registry entry first: the new entry keeps
+/// its container and configuration, and a failed install puts the old
+/// version and entry back.
+fn reinstall(package: &InstalledPackage, version: &str) -> Result<()> {
+    super::install_package(&package.name, package.ecosystem.clone(), Some(version), false)
+}
+
+/// Latest version an ecosystem offers, or `None` if it cannot be checked
+pub fn latest_version(name: &str, ecosystem: &Ecosystem) -> Result<Option<String>> {
+    match ecosystem {
+        Ecosystem::Native => Ok(store::show_package_details(name)?.map(|p| p.version)),
+        Ecosystem::Npm => {
+            let output = query(super::tools::command("npm")?.args(["view", name, "version"]), ecosystem)?;
+            Ok(non_empty(output.trim()))
+        }
+        Ecosystem::Python => {
+            // Prefer the managed virtual environment's pip, which installs use
+            let venv_pip = constants::root_dir().join("packages").join("python").join("venv").join("bin").join("pip");
+            let mut cmd = if venv_pip.exists() { Command::new(venv_pip) } else { super::tools::command("pip")? };
+            let output = query(cmd.args(["index", "versions", name]), ecosystem)?;
+            Ok(parse_pip_versions(&output))
+        }
+        Ecosystem::Rust => {
+            let output = query(super::tools::command("cargo")?.args(["search", name, "--limit", "1"]), ecosystem)?;
+            Ok(parse_cargo_search(&output, name))
+        }
+        Ecosystem::Go => {
+            let module = format!("{}@latest", name);
+            let output = query(super::tools::command("go")?.args(["list", "-m", &module]), ecosystem)?;
+            Ok(parse_go_list(&output))
+        }
+        Ecosystem::Linux => match super::linux::backend()? {
+            LinuxBackend::Apt => {
+                let output = query(Command::new("apt-cache").args(["policy", name]), ecosystem)?;
+                Ok(parse_apt_policy(&output))
+            }
+            _ => Ok(None),
+        },
+        Ecosystem::Java | Ecosystem::Other(_) => Ok(None),
+    }
+}
+
+/// Version from `pip index versions`, whose first line reads `name (1.2.3)`
+fn parse_pip_versions(output: &str) -> Option<String> {
+    output.lines()
+        .next()
+        .and_then(|line| line.split_once('('))
+        .and_then(|(_, rest)| rest.split_once(')'))
+        .and_then(|(version, _)| non_empty(version.trim()))
+}
+
+/// Version of a crate from `cargo search`, whose lines read `name = "1.2.3"    # description`
+fn parse_cargo_search(output: &str, name: &str) -> Option<String> {
+    output.lines()
+        .filter_map(|line| line.split_once('='))
+        .find(|(crate_name, _)| crate_name.trim() == name)
+        .and_then(|(_, rest)| rest.split('"').nth(1))
+        .and_then(non_empty)
+}
+
+/// Version from `go list -m`, whose output reads `module v1.2.3`
+fn parse_go_list(output: &str) -> Option<String> {
+    output.split_whitespace().nth(1).and_then(non_empty)
+}
+
+/// Candidate version from `apt-cache policy`
+fn parse_apt_policy(output: &str) -> Option<String> {
+    output.lines()
+        .find_map(|line| line.trim().strip_prefix("Candidate:"))
+        .map(str::trim)
+        .filter(|candidate| *candidate != "(none)")
+        .and_then(non_empty)
+}
+
+/// Run a lookup through the ecosystem's mirror, returning its stdout
+fn query(cmd: &mut Command, ecosystem: &Ecosystem) -> Result<String> {
+    // Kept alive until the tool exits, as it holds the mirror credentials
+    let mirror = super::mirror::session(ecosystem)?;
+    if let Some(mirror) = &mirror {
+        mirror.apply(cmd)?;
+    }
+    
+    let output = cmd.output().context("Failed to run version lookup")?;
+    if !output.status.success() {
+        anyhow::bail!("Version lookup failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn non_empty(version: &str) -> Option<String> {
+    if version.is_empty() { None } else { Some(version.to_string()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn tool_output_is_parsed_into_versions() {
+        assert_eq!(parse_pip_versions("requests (2.31.0)\nAvailable versions: 2.31.0, 2.30.0\n"), Some("2.31.0".to_string()));
+        assert_eq!(parse_pip_versions("requests ()\n"), None);
+        assert_eq!(parse_pip_versions(""), None);
+        
+        let search = "serde_json = \"1.0.108\"    # A JSON serialization file format\nserde = \"1.0.193\"    # A serialization framework\n";
+        assert_eq!(parse_cargo_search(search, "serde"), Some("1.0.193".to_string()));
+        assert_eq!(parse_cargo_search(search, "tokio"), None);
+        
+        assert_eq!(parse_go_list("golang.org/x/text v0.14.0\n"), Some("v0.14.0".to_string()));
+        assert_eq!(parse_go_list("golang.org/x/text"), None);
+        
+        let policy = "curl:\n  Installed: 7.88.1-10\n  Candidate: 7.88.1-10+deb12u5\n  Version table:\n";
+        assert_eq!(parse_apt_policy(policy), Some("7.88.1-10+deb12u5".to_string()));
+        assert_eq!(parse_apt_policy("curl:\n  Installed: (none)\n  Candidate: (none)\n"), None);
+        assert_eq!(parse_apt_policy(""), None);
+    }
+    
+    #[test]
+    fn ecosystems_without_lookups_report_no_version() {
+        assert!(latest_version("junit", &Ecosystem::Java).unwrap().is_none());
+        assert!(latest_version("thing", &Ecosystem::Other("custom".to_string())).unwrap().is_none());
+    }
+    
+    #[test]
+    fn failed_lookups_carry_the_tool_error() {
+        let output = query(Command::new("sh").args(["-c", "echo 1.2.3"]), &Ecosystem::Java).unwrap();
+        assert_eq!(output.trim(), "1.2.3");
+        
+        let err = query(Command::new("sh").args(["-c", "echo 'no such package' >&2; exit 1"]), &Ecosystem::Java).unwrap_err();
+        assert_eq!(err.to_string(), "Version lookup failed: no such package");
+    }
+}
@@ -0,0 +1,423 @@
+// SentientOS Crash Capture
+// Async-signal-safe record of hard crashes in a pre-mapped region
+
+use anyhow::{Result, Context};
+use tracing::{info, debug, warn};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::OpenOptions;
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::ptr;
+use std::sync::{Mutex, OnceLock};
+use std::sync::atomic::{AtomicPtr, AtomicU32, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use crate::core::{constants, lock};
+
+// Constants
+const REGION_FILE: &str = "crash.region";
+const REGION_LOCK: &str = "panic-crash-region";
+const REGION_MAGIC: u64 = u64::from_le_bytes(*b"SNTCRSH1");
+const CONTEXT_SLOT_BYTES: usize = 4096;
+const MAX_CORRELATIONS: usize = 16;
+
+// Region states
+const STATE_EMPTY: u32 = 0;
+const STATE_CRASHED: u32 = 1;
+
+/// Signals captured, in the order of `PREVIOUS_ACTIONS`
+const SIGNALS: [libc::c_int; 3] = [libc::SIGSEGV, libc::SIGABRT, libc::SIGBUS];
+
+/// Layout of the crash region file
+///
+/// The signal handler only writes the fixed-size crash fields. The context
+/// is kept current by normal code in two slots: the inactive slot is
+/// rewritten and then made active, so the handler never sees a torn one.
+#[repr(C)]
+struct CrashRegion {
+    magic: u64,
+    state: AtomicU32,
+    active_slot: AtomicU32,
+    signal: i32,
+    pid: i32,
+    fault_addr: u64,
+    crashed_at: u64,
+    context_len: [u32; 2],
+    context: [[u8; CONTEXT_SLOT_BYTES]; 2],
+}
+
+// Mapped region, null until init
+static REGION: AtomicPtr<CrashRegion> = AtomicPtr::new(ptr::null_mut());
+
+// Actions our handlers replaced, handed the signal after capture
+static PREVIOUS_ACTIONS: OnceLock<[libc::sigaction; 3]> = OnceLock::new();
+
+// Lock keeping other processes out of the region
+static REGION_GUARD: Mutex<Option<lock::LockGuard>> = Mutex::new(None);
+
+// Context mirrored into the region
+static CONTEXT: Mutex<CrashContext> = Mutex::new(CrashContext {
+    correlations: VecDeque::new(),
+    heartbeats: BTreeMap::new(),
+});
+
+/// What the process was doing, as last written to the region
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CrashContext {
+    /// Most recent correlation IDs, oldest first
+    pub correlations: VecDeque<String>,
+    
+    /// Latest heartbeat of each subsystem
+    pub heartbeats: BTreeMap<String, Heartbeat>,
+}
+
+/// A subsystem's latest sign of life
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Heartbeat {
+    /// When it was recorded
+    pub at: u64,
+    
+    /// What the subsystem reported
+    pub state: String,
+}
+
+/// A crash captured by the signal handler during an earlier run
+#[derive(Debug, Clone)]
+pub struct CapturedCrash {
+    /// Signal number
+    pub signal: i32,
+    
+    /// Crashed process
+    pub pid: i32,
+    
+    /// Faulting address, 0 for signals without one
+    pub fault_addr: u64,
+    
+    /// When the signal arrived
+    pub crashed_at: u64,
+    
+    /// Context at the time, if the region held a readable one
+    pub context: Option<CrashContext>,
+}
+
+impl CapturedCrash {
+    /// Name of the signal
+    pub fn signal_name(&self) -> &'static str {
+        match self.signal {
+            libc::SIGSEGV => "SIGSEGV",
+            libc::SIGABRT => "SIGABRT",
+            libc::SIGBUS => "SIGBUS",
+            _ => "unknown signal",
+        }
+    }
+}
+
+/// Map the crash region and install the signal handlers
+///
+/// Returns the crash left in the region by the previous run, if any, and
+/// clears it. Only one process captures crashes; others get `None` and run
+/// without capture.
+pub fn init() -> Result<Option<CapturedCrash>> {
+    if !REGION.load(Ordering::Acquire).is_null() {
+        return Ok(None);
+    }
+    
+    let guard = match lock::try_lock(REGION_LOCK, "crash capture")? {
+        Some(guard) => guard,
+        None => {
+            warn!("Crash region is held by another process; crash capture disabled");
+            return Ok(None);
+        }
+    };
+    
+    let path = region_path();
+    let size = std::mem::size_of::<CrashRegion>();
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)
+        .with_context(|| format!("Failed to open crash region {:?}", path))?;
+    file.set_len(size as u64)?;
+    
+    // The mapping outlives the file handle
+    let mapped = unsafe {
+        libc::mmap(ptr::null_mut(), size, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_SHARED, file.as_raw_fd(), 0)
+    };
+    if mapped == libc::MAP_FAILED {
+        anyhow::bail!("Failed to map crash region {:?}: {}", path, std::io::Error::last_os_error());
+    }
+    let region = mapped as *mut CrashRegion;
+    
+    let captured = unsafe { read_captured(region) };
+    unsafe {
+        ptr::write_bytes(region as *mut u8, 0, size);
+        (*region).magic = REGION_MAGIC;
+    }
+    
+    REGION.store(region, Ordering::Release);
+    *REGION_GUARD.lock().unwrap() = Some(guard);
+    write_context(&mut CONTEXT.lock().unwrap());
+    install_handlers()?;
+    
+    info!("Crash capture armed ({})", path.display());
+    Ok(captured)
+}
+
+/// Restore the previous signal handlers and unmap the region
+pub fn shutdown() {
+    let region = REGION.load(Ordering::Acquire);
+    if region.is_null() {
+        return;
+    }
+    
+    if let Some(previous) = PREVIOUS_ACTIONS.get() {
+        for (signal, action) in SIGNALS.iter().zip(previous) {
+            unsafe { libc::sigaction(*signal, action, ptr::null_mut()) };
+        }
+    }
+    
+    // Context writers hold this lock while they use the mapping
+    let _context = CONTEXT.lock().unwrap();
+    REGION.store(ptr::null_mut(), Ordering::Release);
+    unsafe { libc::munmap(region as *mut libc::c_void, std::mem::size_of::<CrashRegion>()) };
+    REGION_GUARD.lock().unwrap().take();
+    debug!("Crash capture disarmed");
+}
+
+/// Remember a correlation ID as recent activity
+pub fn note_correlation(correlation_id: &str) {
+    let mut context = CONTEXT.lock().unwrap();
+    context.correlations.push_back(correlation_id.to_string());
+    while context.correlations.len() > MAX_CORRELATIONS {
+        context.correlations.pop_front();
+    }
+    write_context(&mut context);
+}
+
+/// Record a subsystem's sign of life
+pub fn heartbeat(subsystem: &str, state: &str) {
+    let mut context = CONTEXT.lock().unwrap();
+    context.heartbeats.insert(subsystem.to_string(), Heartbeat { at: now(), state: state.to_string() });
+    write_context(&mut context);
+}
+
+/// Path of the crash region
+pub fn region_path() -> PathBuf {
+    constants::root_dir().join(constants::PANIC_DIR).join(REGION_FILE)
+}
+
+/// Serialize the context into the inactive slot and make it active
+fn write_context(context: &mut CrashContext) {
+    let region = REGION.load(Ordering::Acquire);
+    if region.is_null() {
+        return;
+    }
+    
+    let mut bytes = match serde_json::to_vec(&*context) {
+        Ok(bytes) => bytes,
+        Err(_) => return,
+    };
+    // Drop the oldest correlations until the context fits its slot
+    while bytes.len() > CONTEXT_SLOT_BYTES && context.correlations.pop_front().is_some() {
+        bytes = serde_json::to_vec(&*context).unwrap_or_default();
+    }
+    if bytes.len() > CONTEXT_SLOT_BYTES {
+        debug!("Crash context too large for the region ({} bytes)", bytes.len());
+        return;
+    }
+    
+    unsafe {
+        let slot = 1 - (*region).active_slot.load(Ordering::Acquire).min(1) as usize;
+        ptr::copy_nonoverlapping(bytes.as_ptr(), (*region).context[slot].as_mut_ptr(), bytes.len());
+        (*region).context_len[slot] = bytes.len() as u32;
+        (*region).active_slot.store(slot as u32, Ordering::Release);
+    }
+}
+
+/// Read a crash left in the region, if it holds one
+unsafe fn read_captured(region: *const CrashRegion) -> Option<CapturedCrash> {
+    if (*region).magic != REGION_MAGIC || (*region).state.load(Ordering::Acquire) != STATE_CRASHED {
+        return None;
+    }
+    
+    let slot = (*region).active_slot.load(Ordering::Acquire).min(1) as usize;
+    let len = ((*region).context_len[slot] as usize).min(CONTEXT_SLOT_BYTES);
+    Some(CapturedCrash {
+        signal: (*region).signal,
+        pid: (*region).pid,
+        fault_addr: (*region).fault_addr,
+        crashed_at: (*region).crashed_at,
+        context: serde_json::from_slice(&(&(*region).context[slot])[..len]).ok(),
+    })
+}
+
+/// Install the handler for every captured signal
+fn install_handlers() -> Result<()> {
+    // Read the current actions first, so the handler can always chain to them
+    let mut previous: [libc::sigaction; 3] = unsafe { std::mem::zeroed() };
+    for (signal, action) in SIGNALS.iter().zip(previous.iter_mut()) {
+        if unsafe { libc::sigaction(*signal, ptr::null(), action) } != 0 {
+            anyhow::bail!("Failed to read handler of signal {}: {}", signal, std::io::Error::last_os_error());
+        }
+    }
+    PREVIOUS_ACTIONS.get_or_init(|| previous);
+    
+    for signal in SIGNALS {
+        unsafe {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle_signal as *const () as libc::sighandler_t;
+            action.sa_flags = libc::SA_SIGINFO | libc::SA_ONSTACK;
+            libc::sigemptyset(&mut action.sa_mask);
+            if libc::sigaction(signal, &action, ptr::null_mut()) != 0 {
+                anyhow::bail!("Failed to install handler of signal {}: {}", signal, std::io::Error::last_os_error());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Signal handler; only plain stores and async-signal-safe calls
+extern "C" fn handle_signal(signal: libc::c_int, info: *mut libc::siginfo_t, _ucontext: *mut libc::c_void) {
+    let region = REGION.load(Ordering::Acquire);
+    unsafe {
+        if !region.is_null() && (*region).state.load(Ordering::Acquire) == STATE_EMPTY {
+            let mut time = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+            libc::clock_gettime(libc::CLOCK_REALTIME, &mut time);
+            
+            ptr::write_volatile(&mut (*region).signal, signal);
+            ptr::write_volatile(&mut (*region).pid, libc::getpid());
+            ptr::write_volatile(&mut (*region).fault_addr, if info.is_null() { 0 } else { (*info).si_addr() as u64 });
+            ptr::write_volatile(&mut (*region).crashed_at, time.tv_sec.max(0) as u64);
+            (*region).state.store(STATE_CRASHED, Ordering::Release);
+        }
+        
+        let sent = info.is_null() || (*info).si_code <= 0;
+        let previous = SIGNALS.iter().position(|s| *s == signal)
+            .and_then(|index| PREVIOUS_ACTIONS.get().map(|actions| &actions[index]));
+        
+        match previous {
+            // A genuine fault recurs when the instruction is retried, now
+            // reaching the previous handler (e.g. the stack overflow report)
+            Some(action) if !sent && signal != libc::SIGABRT => {
+                libc::sigaction(signal, action, ptr::null_mut());
+            }
+            // Sent or raised signals don't recur on their own
+            _ => {
+                libc::signal(signal, libc::SIG_DFL);
+                libc::raise(signal);
+            }
+        }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::{Duration, Instant};
+    use crate::logs::metrics;
+    
+    /// Address the faulting child writes to
+    const FAULT_ADDR: usize = 0x10;
+    
+    /// How long a child gets to crash before the test gives up on it
+    const CHILD_DEADLINE: Duration = Duration::from_secs(10);
+    
+    /// Fork a child that arms crash capture, records some context and runs `crash`
+    ///
+    /// Returns the child's pid and the signal it died from. The child never
+    /// returns into the test harness.
+    fn crash_in_child(tag: &str, crash: fn()) -> (i32, i32) {
+        assert!(REGION.load(Ordering::Acquire).is_null(), "crash capture is armed in the test process");
+        fs::create_dir_all(region_path().parent().unwrap()).unwrap();
+        
+        let pid = unsafe { libc::fork() };
+        assert!(pid >= 0, "fork failed: {}", std::io::Error::last_os_error());
+        if pid == 0 {
+            let armed = init().is_ok() && !REGION.load(Ordering::Acquire).is_null();
+            if armed {
+                note_correlation(&format!("corr-{}", tag));
+                heartbeat("gossip", &format!("{} peers", tag));
+                crash();
+            }
+            unsafe { libc::_exit(if armed { 0 } else { 1 }) };
+        }
+        
+        // A child stuck on a lock another test thread held at fork time is killed
+        let deadline = Instant::now() + CHILD_DEADLINE;
+        let mut status = 0;
+        loop {
+            match unsafe { libc::waitpid(pid, &mut status, libc::WNOHANG) } {
+                0 if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(10)),
+                0 => {
+                    unsafe { libc::kill(pid, libc::SIGKILL) };
+                    unsafe { libc::waitpid(pid, &mut status, 0) };
+                    panic!("crashing child {} did not die", pid);
+                }
+                _ => break,
+            }
+        }
+        
+        assert!(libc::WIFSIGNALED(status), "child exited with status {} instead of crashing", libc::WEXITSTATUS(status));
+        (pid, libc::WTERMSIG(status))
+    }
+    
+    /// "Reboot": arm crash capture in this process and turn what it finds into a record
+    fn reboot() -> CapturedCrash {
+        let captured = init().unwrap().expect("crash region holds the child's crash");
+        shutdown();
+        super::super::record_crash(&captured).unwrap();
+        captured
+    }
+    
+    /// Panic record written for a captured crash
+    fn record_of(crash: &CapturedCrash) -> super::super::PanicRecord {
+        let path = constants::root_dir().join(constants::PANIC_DIR).join(format!("panic-{}.json", crash.crashed_at));
+        serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap()
+    }
+    
+    fn segfault() {
+        unsafe { ptr::write_volatile(ptr::null_mut::<u8>().wrapping_add(FAULT_ADDR), 1) };
+    }
+    
+    fn abort() {
+        std::process::abort();
+    }
+    
+    #[test]
+    fn crash_of_a_child_is_recorded_after_reboot() {
+        let _records = super::super::tests::lock_records();
+        for (tag, crash, signal) in [("segv", segfault as fn(), libc::SIGSEGV), ("abrt", abort as fn(), libc::SIGABRT)] {
+            let (pid, died_from) = crash_in_child(tag, crash);
+            assert_eq!(died_from, signal, "the signal must still kill the child after capture");
+            
+            let captured = reboot();
+            assert_eq!(captured.signal, signal);
+            assert_eq!(captured.pid, pid);
+            if signal == libc::SIGSEGV {
+                assert_eq!(captured.fault_addr, FAULT_ADDR as u64);
+            }
+            let context = captured.context.as_ref().expect("context is readable");
+            assert_eq!(context.correlations.back().map(String::as_str), Some(format!("corr-{}", tag).as_str()));
+            assert_eq!(context.heartbeats["gossip"].state, format!("{} peers", tag));
+            
+            let record = record_of(&captured);
+            assert_eq!(record.reason, format!("crash-sig{}", tag));
+            assert!(record.details.contains(&format!("Process {} received {}", pid, captured.signal_name())));
+            assert!(record.details.contains(&format!("corr-{}", tag)));
+            assert!(record.details.contains(&format!("Heartbeat gossip: {} peers", tag)));
+            assert!(metrics::findings().iter().any(|f| f.metric == super::super::CRASH_FINDING));
+        }
+        
+        // The crash was consumed by the reboot
+        assert!(init().unwrap().is_none());
+        shutdown();
+    }
+}
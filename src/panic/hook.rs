@@ -0,0 +1,179 @@
+// SentientOS Panic Hook
+// Records Rust panics through the panic system before the thread unwinds
+
+use tracing::{debug, error};
+use std::cell::Cell;
+use std::panic::{self, PanicHookInfo};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+type Hook = Box<dyn Fn(&PanicHookInfo<'_>) + Send + Sync + 'static>;
+
+// Whether our hook is installed
+static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+// Hook ours replaced, called after recording and restored on uninstall
+static PREVIOUS_HOOK: Mutex<Option<Hook>> = Mutex::new(None);
+
+thread_local! {
+    // Set while this thread records a panic, so a panic in the recording
+    // itself is not recorded again
+    static RECORDING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Install the panic hook, keeping the current one to chain to
+pub fn install() {
+    if INSTALLED.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    
+    *PREVIOUS_HOOK.lock().unwrap() = Some(panic::take_hook());
+    panic::set_hook(Box::new(handle_panic));
+    debug!("Panic hook installed");
+}
+
+/// Put back the hook that was current before `install`
+pub fn uninstall() {
+    if !INSTALLED.swap(false, Ordering::AcqRel) {
+        return;
+    }
+    
+    let _ = panic::take_hook();
+    if let Some(previous) = PREVIOUS_HOOK.lock().unwrap().take() {
+        panic::set_hook(previous);
+    }
+    debug!("Panic hook removed");
+}
+
+/// Run `f` with panics on this thread left unrecorded
+pub(super) fn unrecorded<R>(f: impl FnOnce() -> R) -> R {
+    let was_recording = RECORDING.with(|recording| recording.replace(true));
+    let result = f();
+    RECORDING.with(|recording| recording.set(was_recording));
+    result
+}
+
+/// Record a panic, then hand it to the previous hook
+fn handle_panic(info: &PanicHookInfo<'_>) {
+    if !RECORDING.with(|recording| recording.replace(true)) {
+        let thread = std::thread::current();
+        let event = super::PanicEvent::SubsystemFailure {
+            subsystem: thread.name().unwrap_or("runtime").to_string(),
+            error: describe(info),
+        };
+        if let Err(e) = super::record_panic(event) {
+            error!("Failed to record Rust panic: {:#}", e);
+        }
+        RECORDING.with(|recording| recording.set(false));
+    }
+    
+    // A poisoned lock only means another thread panicked inside the hook
+    let previous = PREVIOUS_HOOK.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(previous) = previous.as_ref() {
+        previous(info);
+    }
+}
+
+/// Message, location and thread of a panic; the backtrace is captured by `record_panic`
+fn describe(info: &PanicHookInfo<'_>) -> String {
+    let payload = info.payload();
+    let message = payload.downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("non-string panic payload");
+    let location = info.location()
+        .map(|l| format!("{}:{}:{}", l.file(), l.line(), l.column()))
+        .unwrap_or_else(|| "unknown location".to_string());
+    let thread = std::thread::current();
+    
+    format!("Thread '{}' panicked at {}: {}", thread.name().unwrap_or("<unnamed>"), location, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+    use super::super::tests::{lock_records, records};
+    
+    /// Records whose details mention `message`
+    fn recorded(message: &str) -> Vec<super::super::PanicRecord> {
+        records().into_iter().filter(|r| r.details.contains(message)).collect()
+    }
+    
+    /// Panic with `message` in a named thread and catch it by joining
+    fn panic_in_thread(name: &str, message: &'static str) {
+        let panicked = thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || panic!("{}", message))
+            .unwrap()
+            .join();
+        assert!(panicked.is_err());
+    }
+    
+    #[test]
+    fn caught_panic_in_a_thread_is_recorded_and_chained() {
+        let _records = lock_records();
+        
+        // Stand-in for the hook that was current before ours
+        let original = panic::take_hook();
+        let chained = Arc::new(AtomicUsize::new(0));
+        let calls = chained.clone();
+        panic::set_hook(Box::new(move |_| {
+            calls.fetch_add(1, Ordering::SeqCst);
+        }));
+        
+        install();
+        panic_in_thread("hook-test-worker", "hook test: recorded panic");
+        uninstall();
+        
+        // After uninstalling, panics reach the previous hook without being recorded
+        panic_in_thread("hook-test-worker", "hook test: unrecorded panic");
+        panic::set_hook(original);
+        assert_eq!(chained.load(Ordering::SeqCst), 2);
+        assert!(recorded("hook test: unrecorded panic").is_empty());
+        
+        let records = recorded("hook test: recorded panic");
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.reason, "subsystem_failure");
+        assert!(record.details.contains("Thread 'hook-test-worker' panicked at src/panic/hook.rs:"), "{}", record.details);
+        assert!(matches!(&record.event, Some(super::super::PanicEvent::SubsystemFailure { subsystem, .. }) if subsystem == "hook-test-worker"));
+        assert!(record.backtrace.as_deref().map_or(false, |b| !b.is_empty()));
+        
+        let status = super::super::read_status().unwrap().expect("status is written");
+        assert!(status.active);
+        assert_eq!(status.reason, "subsystem_failure");
+    }
+    
+    #[test]
+    fn panics_while_recording_are_not_recorded() {
+        let _records = lock_records();
+        let original = panic::take_hook();
+        panic::set_hook(Box::new(|_| {}));
+        install();
+        
+        let caught = unrecorded(|| std::panic::catch_unwind(|| panic!("hook test: panic inside recording")));
+        assert!(caught.is_err());
+        
+        uninstall();
+        panic::set_hook(original);
+        assert!(recorded("hook test: panic inside recording").is_empty());
+    }
+    
+    #[test]
+    fn install_and_uninstall_are_idempotent() {
+        let _records = lock_records();
+        let original = panic::take_hook();
+        panic::set_hook(Box::new(|_| {}));
+        
+        install();
+        install();
+        uninstall();
+        uninstall();
+        assert!(!INSTALLED.load(Ordering::SeqCst));
+        assert!(PREVIOUS_HOOK.lock().unwrap().is_none());
+        panic::set_hook(original);
+    }
+}
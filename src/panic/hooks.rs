@@ -0,0 +1,94 @@
+// SentientOS Panic Hooks
+// Cleanup other subsystems register to run as soon as a panic is recorded
+
+use tracing::{debug, error};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::RwLock;
+
+use super::PanicEvent;
+
+/// A callback run when a panic is recorded
+pub type PanicHook = Box<dyn Fn(&PanicEvent) + Send + Sync>;
+
+// Registered hooks, in registration order
+static HOOKS: RwLock<Vec<PanicHook>> = RwLock::new(Vec::new());
+
+/// Register a callback to run whenever a panic is recorded
+///
+/// Hooks run on the recording thread, in registration order, before the
+/// panic snapshot is taken. A hook that panics is logged and skipped; it
+/// must not register further hooks.
+pub fn register(hook: PanicHook) {
+    HOOKS.write().unwrap_or_else(|e| e.into_inner()).push(hook);
+}
+
+/// Run every registered hook for a panic event
+pub(super) fn run(event: &PanicEvent) {
+    let hooks = HOOKS.read().unwrap_or_else(|e| e.into_inner());
+    if hooks.is_empty() {
+        return;
+    }
+    
+    debug!("Running {} panic hook(s) for {}", hooks.len(), event.kind());
+    for (index, hook) in hooks.iter().enumerate() {
+        // A panic inside a hook is not itself recorded as a panic
+        let outcome = super::hook::unrecorded(|| panic::catch_unwind(AssertUnwindSafe(|| hook(event))));
+        if outcome.is_err() {
+            error!("Panic hook {} panicked while handling {}", index, event.kind());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use super::super::tests::{lock_records, records};
+    
+    // What this module's hooks saw; they ignore other tests' panics
+    static CALLS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    
+    fn event(error: &str) -> PanicEvent {
+        PanicEvent::SubsystemFailure { subsystem: "hooks-test".to_string(), error: error.to_string() }
+    }
+    
+    /// The error of a panic raised by this module's tests
+    fn hooks_test_error(event: &PanicEvent) -> Option<&str> {
+        match event {
+            PanicEvent::SubsystemFailure { subsystem, error } if subsystem == "hooks-test" => Some(error),
+            _ => None,
+        }
+    }
+    
+    #[test]
+    fn hooks_run_in_order_before_the_record_and_survive_a_panicking_hook() {
+        let _records = lock_records();
+        register(Box::new(|event| {
+            if let Some(error) = hooks_test_error(event) {
+                let saved = records().iter().any(|r| r.details.contains(error));
+                CALLS.lock().unwrap().push(format!("first: {} (record saved: {})", error, saved));
+            }
+        }));
+        register(Box::new(|event| {
+            if hooks_test_error(event).is_some() {
+                panic!("hooks test: a hook panicked");
+            }
+        }));
+        register(Box::new(|event| {
+            if let Some(error) = hooks_test_error(event) {
+                CALLS.lock().unwrap().push(format!("last: {}", error));
+            }
+        }));
+        
+        run(&event("run directly"));
+        assert_eq!(*CALLS.lock().unwrap(), ["first: run directly (record saved: false)", "last: run directly"]);
+        
+        CALLS.lock().unwrap().clear();
+        super::super::record_panic(event("recorded by record_panic")).unwrap();
+        assert_eq!(*CALLS.lock().unwrap(), ["first: recorded by record_panic (record saved: false)", "last: recorded by record_panic"]);
+        assert!(records().iter().any(|r| r.details.contains("recorded by record_panic")));
+        
+        // The hook's own panic is not recorded as another panic
+        assert!(!records().iter().any(|r| r.details.contains("hooks test: a hook panicked")));
+    }
+}
@@ -3,21 +3,130 @@
 
 use anyhow::{Result, Context};
 use tracing::{info, debug, warn, error};
+use std::backtrace::Backtrace;
 use std::path::{Path, PathBuf};
+use std::collections::BTreeMap;
 use std::fs;
+use std::sync::Once;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::time::{SystemTime, UNIX_EPOCH};
 use serde::{Serialize, Deserialize};
 use serde_json;
 
 use crate::core::constants;
 use crate::heal;
+use crate::heal::snapshot::SnapshotMode;
+
+pub mod crash;
+pub mod hook;
+pub mod hooks;
+pub mod remote;
+
+// Constants
+const CRASH_FINDING: &str = "panic.crash";
+const PANIC_SNAPSHOT_PREFIX: &str = "panic-";
+const REPORT_BACKTRACE_FRAMES: usize = 20;
+const CONFIG_FILE: &str = ".panic/config.json";
+const PANIC_WINDOW_SECS: u64 = 60;
+const PANIC_WINDOW_SLOTS: usize = u8::MAX as usize + 1;
+
+// Guards registering the fallback hook more than once
+static FALLBACK_HOOK: Once = Once::new();
+
+// Recent panics, for storm detection
+static PANIC_STATE: PanicState = PanicState::new();
+
+// Guards noting the previous runs' panics more than once
+static RECENT_RECORDS: Once = Once::new();
+
+/// Panic system settings, stored in `.panic/config.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PanicConfig {
+    /// Endpoint crash reports are POSTed to as JSON
+    pub remote_report_url: Option<String>,
+    
+    /// Whether a crash report is sent each time a panic is recorded
+    pub report_on_panic: bool,
+    
+    /// Whether sent reports include backtraces
+    pub include_backtrace: bool,
+    
+    /// Panics within a minute above which recovery is suspended; 0 never suspends it
+    pub max_panics_per_minute: u8,
+    
+    /// How long recovery stays suspended after a panic storm (seconds)
+    pub cooldown_secs: u64,
+    
+    /// Severity of each event kind, e.g. `container_oom`; kinds not listed are `high`
+    pub severity_rules: BTreeMap<String, PanicSeverity>,
+}
+
+impl Default for PanicConfig {
+    fn default() -> Self {
+        Self {
+            remote_report_url: None,
+            report_on_panic: false,
+            include_backtrace: false,
+            max_panics_per_minute: 5,
+            cooldown_secs: 300,
+            severity_rules: BTreeMap::from([
+                ("container_oom".to_string(), PanicSeverity::Medium),
+                ("zk_verification_failure".to_string(), PanicSeverity::High),
+                ("corrupted_state".to_string(), PanicSeverity::Critical),
+            ]),
+        }
+    }
+}
+
+impl PanicConfig {
+    /// Severity of an event under the configured rules
+    pub fn severity_of(&self, event: &PanicEvent) -> PanicSeverity {
+        self.severity_rules.get(event.kind()).copied().unwrap_or(PanicSeverity::High)
+    }
+}
+
+/// Times of the most recent panics, updated without locking
+///
+/// Holds one more slot than the largest `max_panics_per_minute`, so a
+/// rate above any threshold can be seen.
+struct PanicState {
+    /// Panic timestamps (seconds since the epoch) in a ring; 0 marks an empty slot
+    recent: [AtomicU64; PANIC_WINDOW_SLOTS],
+    
+    /// Slot the next panic goes into
+    next: AtomicUsize,
+}
+
+impl PanicState {
+    const fn new() -> Self {
+        Self {
+            recent: [const { AtomicU64::new(0) }; PANIC_WINDOW_SLOTS],
+            next: AtomicUsize::new(0),
+        }
+    }
+    
+    /// Note a panic at `timestamp`, replacing the oldest one noted
+    fn note(&self, timestamp: u64) {
+        let slot = self.next.fetch_add(1, Ordering::Relaxed) % PANIC_WINDOW_SLOTS;
+        self.recent[slot].store(timestamp, Ordering::Relaxed);
+    }
+    
+    /// Panics noted after `since`
+    fn count_after(&self, since: u64) -> usize {
+        self.recent.iter()
+            .map(|slot| slot.load(Ordering::Relaxed))
+            .filter(|&timestamp| timestamp != 0 && timestamp > since)
+            .count()
+    }
+}
 
 /// Initialize the panic system
 pub fn init() -> Result<()> {
     info!("Initializing SentientOS panic system");
     
     // Create panic system directories
-    let panic_dir = PathBuf::from(constants::ROOT_DIR).join(".panic");
+    let panic_dir = constants::root_dir().join(".panic");
     fs::create_dir_all(&panic_dir)?;
     
     // Create initial fallback.zk file with last known good state
@@ -30,7 +139,7 @@ pub fn init() -> Result<()> {
         };
         
         let fallback_content = serde_json::to_string_pretty(&initial_state)?;
-        fs::write(&fallback_path, fallback_content)?;
+        crate::core::fs::write_atomic(&fallback_path, fallback_content.as_bytes())?;
     }
     
     // Create trace recovery directory
@@ -41,6 +150,39 @@ pub fn init() -> Result<()> {
     let report_dir = panic_dir.join("log.send");
     fs::create_dir_all(&report_dir)?;
     
+    // Arm crash capture, converting a crash left by the previous run
+    match crash::init() {
+        Ok(Some(captured)) => record_crash(&captured)?,
+        Ok(None) => {}
+        Err(e) => warn!("Crash capture unavailable: {}", e),
+    }
+    
+    // Panics recorded by the previous runs count toward a storm, so a crash
+    // loop across restarts is throttled too
+    RECENT_RECORDS.call_once(|| {
+        if let Err(e) = note_recent_records(&panic_dir) {
+            warn!("Failed to read recent panic records: {}", e);
+        }
+    });
+    
+    // Record Rust panics as they happen
+    hook::install();
+    
+    // Reports that could not be sent before the last shutdown go out now
+    remote::send_pending_in_background();
+    
+    // Point fallback.zk at each panic snapshot as heal writes it
+    FALLBACK_HOOK.call_once(|| {
+        heal::hooks::register(heal::hooks::HealEvent::SnapshotCreated, Box::new(|payload| {
+            let is_panic = payload.reason.as_deref().map_or(false, |r| r.starts_with(PANIC_SNAPSHOT_PREFIX));
+            if let (true, Some(snapshot_id)) = (is_panic, payload.snapshot_id.as_deref()) {
+                if let Err(e) = update_fallback_state("panic", Some(snapshot_id)) {
+                    warn!("Failed to update fallback state for snapshot {}: {}", snapshot_id, e);
+                }
+            }
+        }));
+    });
+    
     info!("SentientOS panic system initialized successfully");
     Ok(())
 }
@@ -52,61 +194,261 @@ pub fn shutdown() -> Result<()> {
     // Update fallback.zk with current known good state
     update_fallback_state("shutdown", None)?;
     
+    hook::uninstall();
+    crash::shutdown();
+    
     info!("SentientOS panic system shutdown complete");
     Ok(())
 }
 
 /// Record a panic event
-pub fn record_panic(reason: &str, details: &str) -> Result<()> {
-    error!("SYSTEM PANIC: {}", reason);
+///
+/// Events of `medium` severity or below are recovered from right away;
+/// anything higher waits for `recover`.
+pub fn record_panic(event: PanicEvent) -> Result<()> {
+    let config = load_config()?;
+    let severity = config.severity_of(&event);
+    error!("SYSTEM PANIC ({}): {}", severity, event);
+    let reason = event.kind();
+    
+    // Stack of whoever recorded the panic, for the crash report
+    let backtrace = Backtrace::force_capture().to_string();
     
     // Record panic timestamp
     let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    PANIC_STATE.note(timestamp);
+    
+    // Let subsystems clean up before their state is snapshotted
+    hooks::run(&event);
+    
+    // Take a snapshot for potential recovery
+    let snapshot = take_panic_snapshot(reason);
     
     // Create panic record
     let panic_record = PanicRecord {
         timestamp,
         reason: reason.to_string(),
-        details: details.to_string(),
+        details: event.to_string(),
+        event: Some(event),
+        severity: Some(severity),
+        backtrace: Some(backtrace),
+        snapshot_id: snapshot.as_ref().map(|s| s.id.clone()),
+        snapshot_mode: snapshot.as_ref().map(|s| s.mode.clone()),
+        sacrificed_snapshots: snapshot.map(|s| s.sacrificed).unwrap_or_default(),
     };
     
     // Save panic record
-    let panic_dir = PathBuf::from(constants::ROOT_DIR).join(".panic");
-    let panic_file = panic_dir.join(format!("panic-{}.json", timestamp));
-    let panic_content = serde_json::to_string_pretty(&panic_record)?;
-    fs::write(&panic_file, panic_content)?;
+    save_record(&panic_record)?;
     
-    // Update current panic status
+    // Update current panic status; a cooldown in progress carries over, and
+    // so does a higher severity of a panic not recovered from yet
+    let panic_dir = constants::root_dir().join(".panic");
     let status_file = panic_dir.join("status.json");
+    let previous = read_status().ok().flatten();
+    let cooldown_until = previous.as_ref().map_or(0, |s| s.cooldown_until);
+    let unrecovered = previous.filter(|s| s.active).and_then(|s| s.severity);
     let status = PanicStatus {
         active: true,
         timestamp,
         reason: reason.to_string(),
+        severity: Some(unrecovered.map_or(severity, |s| s.max(severity))),
         recovery_attempted: false,
+        in_cooldown: cooldown_until > timestamp,
+        cooldown_until,
     };
     let status_content = serde_json::to_string_pretty(&status)?;
-    fs::write(&status_file, status_content)?;
+    crate::core::fs::write_atomic(&status_file, status_content.as_bytes())?;
     
-    // Take a snapshot for potential recovery
-    match heal::take_snapshot(&format!("panic-{}", reason)) {
+    // The record is already safe on disk, so a report that fails is only logged
+    if let Err(e) = report_remotely(&config) {
+        warn!("Failed to queue the crash report: {:#}", e);
+    }
+    
+    if status.severity <= Some(PanicSeverity::Medium) {
+        info!("Recovering from {} panic automatically", severity);
+        if let Err(e) = recover(false) {
+            warn!("Automatic recovery failed: {:#}", e);
+        }
+    }
+    
+    Ok(())
+}
+
+/// Load the panic system settings
+pub fn load_config() -> Result<PanicConfig> {
+    let path = constants::root_dir().join(CONFIG_FILE);
+    if !path.exists() {
+        return Ok(PanicConfig::default());
+    }
+    serde_json::from_str(&fs::read_to_string(&path)?)
+        .with_context(|| format!("Invalid panic configuration {:?}", path))
+}
+
+/// Send a crash report to the configured endpoint, if reporting on panic is on
+fn report_remotely(config: &PanicConfig) -> Result<()> {
+    let url = match (&config.remote_report_url, config.report_on_panic) {
+        (Some(url), true) => url,
+        _ => return Ok(()),
+    };
+    
+    let report = build_report(config, config.include_backtrace.then_some(REPORT_BACKTRACE_FRAMES), None)?;
+    remote::submit(url, &serde_json::to_vec(&report)?)
+}
+
+/// Turn a crash captured by the signal handler into a panic record
+///
+/// The process is long gone, so there is no snapshot; the record carries
+/// what the region held and a health finding stays until `recover`.
+fn record_crash(crash: &crash::CapturedCrash) -> Result<()> {
+    error!("Previous run crashed with {} (pid {})", crash.signal_name(), crash.pid);
+    
+    let mut details = format!("Process {} received {} (signal {}) at address {:#x}",
+                              crash.pid, crash.signal_name(), crash.signal, crash.fault_addr);
+    match &crash.context {
+        Some(context) => {
+            let correlations: Vec<&str> = context.correlations.iter().map(String::as_str).collect();
+            details.push_str(&format!("\nRecent correlations: {}",
+                                      if correlations.is_empty() { "none".to_string() } else { correlations.join(", ") }));
+            for (subsystem, heartbeat) in &context.heartbeats {
+                details.push_str(&format!("\nHeartbeat {}: {} ({}s before the crash)",
+                                          subsystem, heartbeat.state, crash.crashed_at.saturating_sub(heartbeat.at)));
+            }
+        }
+        None => details.push_str("\nNo readable context in the crash region"),
+    }
+    
+    save_record(&PanicRecord {
+        timestamp: crash.crashed_at,
+        reason: format!("crash-{}", crash.signal_name().to_lowercase()),
+        details,
+        event: None,
+        severity: Some(PanicSeverity::High),
+        backtrace: None,
+        snapshot_id: None,
+        snapshot_mode: None,
+        sacrificed_snapshots: Vec::new(),
+    })?;
+    
+    crate::logs::metrics::raise_finding(CRASH_FINDING, &format!(
+        "Previous run crashed with {}; see the panic record of {}", crash.signal_name(), crash.crashed_at))?;
+    Ok(())
+}
+
+/// Write a panic record
+fn save_record(record: &PanicRecord) -> Result<()> {
+    let panic_dir = constants::root_dir().join(".panic");
+    let panic_file = panic_dir.join(format!("panic-{}.json", record.timestamp));
+    fs::write(&panic_file, serde_json::to_string_pretty(record)?)?;
+    Ok(())
+}
+
+/// Take the panic snapshot, falling back to emergency mode when out of space
+fn take_panic_snapshot(reason: &str) -> Option<PanicSnapshot> {
+    let snapshot_reason = format!("{}{}", PANIC_SNAPSHOT_PREFIX, reason);
+    
+    let error = match heal::take_snapshot(&snapshot_reason) {
         Ok(snapshot_id) => {
             info!("Created panic snapshot: {}", snapshot_id);
-            update_fallback_state("panic", Some(&snapshot_id))?;
+            return Some(PanicSnapshot { id: snapshot_id, mode: SnapshotMode::Normal, sacrificed: Vec::new() });
+        }
+        Err(e) => e,
+    };
+    
+    if !heal::snapshot::is_out_of_space(&error) {
+        error!("Failed to create panic snapshot: {:?}", error);
+        return None;
+    }
+    
+    warn!("Panic snapshot failed for lack of space, switching to emergency mode");
+    
+    // Never sacrifice the snapshot the system would fall back to
+    let protected = read_fallback_state().ok().and_then(|state| state.heal_snapshot_id);
+    
+    match heal::take_emergency_snapshot(&snapshot_reason, protected.as_deref()) {
+        Ok((snapshot_id, outcome)) => {
+            info!("Created emergency panic snapshot: {}", snapshot_id);
+            Some(PanicSnapshot { id: snapshot_id, mode: SnapshotMode::Emergency, sacrificed: outcome.sacrificed })
         }
         Err(e) => {
-            error!("Failed to create panic snapshot: {:?}", e);
+            error!("Failed to create emergency panic snapshot: {:?}", e);
+            None
         }
     }
+}
+
+/// Read the current fallback state
+fn read_fallback_state() -> Result<FallbackState> {
+    let fallback_path = constants::root_dir().join(".panic").join("fallback.zk");
+    crate::core::fs::read_json_or_backup(&fallback_path)
+}
+
+/// Snapshot panic recovery falls back to, if any
+pub fn fallback_snapshot() -> Result<Option<String>> {
+    let fallback_path = constants::root_dir().join(".panic").join("fallback.zk");
+    if !fallback_path.exists() {
+        return Ok(None);
+    }
+    Ok(read_fallback_state()?.heal_snapshot_id)
+}
+
+/// Reason of the active panic, if the system is in one
+pub fn active_panic() -> Result<Option<String>> {
+    Ok(read_status()?.filter(|status| status.active).map(|status| status.reason))
+}
+
+/// Status of the last panic, with `in_cooldown` as of now; `None` if none was recorded
+pub fn status() -> Result<Option<PanicStatus>> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    Ok(read_status()?.map(|mut status| {
+        status.in_cooldown = status.cooldown_until > now;
+        status
+    }))
+}
+
+/// Read the panic status file, if present
+fn read_status() -> Result<Option<PanicStatus>> {
+    let status_file = constants::root_dir().join(".panic").join("status.json");
+    if !status_file.exists() {
+        return Ok(None);
+    }
     
+    let status = crate::core::fs::read_json_or_backup(&status_file)
+        .context("Invalid panic status")?;
+    Ok(Some(status))
+}
+
+/// Note the panics recorded within the last minute by earlier runs
+fn note_recent_records(panic_dir: &Path) -> Result<()> {
+    let since = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs().saturating_sub(PANIC_WINDOW_SECS);
+    for entry in fs::read_dir(panic_dir)?.filter_map(|e| e.ok()) {
+        // Records are named `panic-<timestamp>.json`
+        let timestamp = entry.file_name().to_str()
+            .and_then(|name| name.strip_prefix("panic-")?.strip_suffix(".json")?.parse::<u64>().ok());
+        if let Some(timestamp) = timestamp.filter(|&t| t > since) {
+            PANIC_STATE.note(timestamp);
+        }
+    }
     Ok(())
 }
 
 /// Recover from a panic state
-pub fn recover() -> Result<()> {
+///
+/// Recovery is skipped while the system is cooling down from a panic
+/// storm: more than `max_panics_per_minute` panics within a minute start
+/// a cooldown of `cooldown_secs`. Only panics after a cooldown ends count
+/// toward the next one.
+///
+/// A `critical` panic is never recovered from without `force`; the panic
+/// stays active until an operator forces recovery. `force` also ignores a
+/// cooldown.
+pub fn recover(force: bool) -> Result<()> {
     info!("Recovering from panic state");
     
+    // Recovering acknowledges a crash captured at startup
+    crate::logs::metrics::clear_finding(CRASH_FINDING)?;
+    
     // Check if system is actually in a panic state
-    let panic_dir = PathBuf::from(constants::ROOT_DIR).join(".panic");
+    let panic_dir = constants::root_dir().join(".panic");
     let status_file = panic_dir.join("status.json");
     
     if !status_file.exists() {
@@ -115,18 +457,43 @@ pub fn recover() -> Result<()> {
     }
     
     // Read panic status
-    let status_content = fs::read_to_string(&status_file)?;
-    let mut status: PanicStatus = serde_json::from_str(&status_content)?;
+    let mut status: PanicStatus = crate::core::fs::read_json_or_backup(&status_file)?;
     
     if !status.active {
         info!("No active panic state found");
         return Ok(());
     }
     
+    // A critical panic stays active until an operator forces recovery
+    if status.severity == Some(PanicSeverity::Critical) && !force {
+        anyhow::bail!("Refusing to recover from critical panic {}; inspect the system, then run `panic recover --force`",
+                      status.reason);
+    }
+    
+    // Recovering in a panic storm only feeds it
+    let config = load_config()?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    if force {
+        info!("Forced recovery; ignoring severity and cooldown");
+        status.cooldown_until = status.cooldown_until.min(now);
+    } else if status.cooldown_until > now {
+        warn!("panic storm detected; recovery skipped for another {}s", status.cooldown_until - now);
+        return Ok(());
+    }
+    let recent = PANIC_STATE.count_after(now.saturating_sub(PANIC_WINDOW_SECS).max(status.cooldown_until));
+    if !force && config.max_panics_per_minute > 0 && recent > config.max_panics_per_minute as usize {
+        warn!("panic storm detected: {} panics in the last minute (limit {}); recovery skipped for {}s",
+              recent, config.max_panics_per_minute, config.cooldown_secs);
+        status.in_cooldown = true;
+        status.cooldown_until = now + config.cooldown_secs;
+        let status_content = serde_json::to_string_pretty(&status)?;
+        crate::core::fs::write_atomic(&status_file, status_content.as_bytes())?;
+        return Ok(());
+    }
+    status.in_cooldown = false;
+    
     // Get fallback state
-    let fallback_path = panic_dir.join("fallback.zk");
-    let fallback_content = fs::read_to_string(&fallback_path)?;
-    let fallback: FallbackState = serde_json::from_str(&fallback_content)?;
+    let fallback: FallbackState = read_fallback_state()?;
     
     // Attempt recovery from snapshot if available
     if let Some(snapshot_id) = &fallback.heal_snapshot_id {
@@ -140,7 +507,7 @@ pub fn recover() -> Result<()> {
                 status.recovery_attempted = true;
                 status.active = false;
                 let status_content = serde_json::to_string_pretty(&status)?;
-                fs::write(&status_file, status_content)?;
+                crate::core::fs::write_atomic(&status_file, status_content.as_bytes())?;
                 
                 return Ok(());
             }
@@ -157,17 +524,39 @@ pub fn recover() -> Result<()> {
     // Update panic status
     status.recovery_attempted = true;
     let status_content = serde_json::to_string_pretty(&status)?;
-    fs::write(&status_file, status_content)?;
+    crate::core::fs::write_atomic(&status_file, status_content.as_bytes())?;
     
     Ok(())
 }
 
-/// Generate a crash report
-pub fn generate_report(output_path: &str) -> Result<()> {
+/// Generate a crash report, with the panic records grouped by kind
+///
+/// Backtraces are written as arrays of frames, cut to the innermost 20
+/// unless `full_backtrace` is set. With `min_severity`, only records at or
+/// above it are included. Returns the number of records of each kind.
+pub fn generate_report(output_path: &str, full_backtrace: bool, min_severity: Option<PanicSeverity>) -> Result<BTreeMap<String, usize>> {
     info!("Generating crash report: {}", output_path);
     
+    let config = load_config()?;
+    let frame_limit = if full_backtrace { usize::MAX } else { REPORT_BACKTRACE_FRAMES };
+    let crash_report = build_report(&config, Some(frame_limit), min_severity)?;
+    let histogram = crash_report.panic_records_by_kind.iter()
+        .map(|(kind, records)| (kind.clone(), records.len()))
+        .collect();
+    
+    // Write crash report
+    let report_content = serde_json::to_string_pretty(&crash_report)?;
+    fs::write(output_path, report_content)?;
+    
+    info!("Crash report generated successfully: {}", output_path);
+    Ok(histogram)
+}
+
+/// Crash report of the panic records at or above `min_severity`, with
+/// backtraces cut to `frame_limit` frames or left out
+fn build_report(config: &PanicConfig, frame_limit: Option<usize>, min_severity: Option<PanicSeverity>) -> Result<CrashReport> {
     // Get panic directory
-    let panic_dir = PathBuf::from(constants::ROOT_DIR).join(".panic");
+    let panic_dir = constants::root_dir().join(".panic");
     
     // Collect all panic records
     let mut panic_records = Vec::new();
@@ -180,7 +569,15 @@ pub fn generate_report(output_path: &str) -> Result<()> {
                 if file_name.starts_with("panic-") && file_name.ends_with(".json") {
                     let content = fs::read_to_string(&path)?;
                     match serde_json::from_str::<PanicRecord>(&content) {
-                        Ok(record) => panic_records.push(record),
+                        Ok(mut record) => {
+                            // Records from before severities get one from the current rules
+                            if record.severity.is_none() {
+                                record.severity = record.event.as_ref().map(|event| config.severity_of(event));
+                            }
+                            if min_severity.map_or(true, |min| record.severity >= Some(min)) {
+                                panic_records.push(record);
+                            }
+                        }
                         Err(_) => continue, // Skip invalid records
                     }
                 }
@@ -196,24 +593,53 @@ pub fn generate_report(output_path: &str) -> Result<()> {
         containers_running: 0, // This would be fetched from matrixbox
     };
     
-    // Create crash report
-    let crash_report = CrashReport {
+    // Group the records by kind, oldest first; records from before typed
+    // events are grouped by their reason
+    panic_records.sort_by_key(|record| record.timestamp);
+    let mut panic_records_by_kind: BTreeMap<String, Vec<ReportedPanic>> = BTreeMap::new();
+    for mut record in panic_records {
+        let kind = record.event.as_ref().map_or_else(|| record.reason.clone(), |event| event.kind().to_string());
+        let backtrace = match (record.backtrace.take(), frame_limit) {
+            (Some(backtrace), Some(limit)) => backtrace_frames(&backtrace, limit),
+            _ => Vec::new(),
+        };
+        panic_records_by_kind.entry(kind).or_default().push(ReportedPanic { record, backtrace });
+    }
+    
+    Ok(CrashReport {
         generated_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
         system_info,
-        panic_records,
-    };
-    
-    // Write crash report
-    let report_content = serde_json::to_string_pretty(&crash_report)?;
-    fs::write(output_path, report_content)?;
+        panic_records_by_kind,
+    })
+}
+
+/// Frames of a captured backtrace, one line each, at most `limit` of them
+///
+/// A frame's `at file:line` lines are joined onto its symbol line; when
+/// frames are cut, a last entry says how many.
+fn backtrace_frames(backtrace: &str, limit: usize) -> Vec<String> {
+    let mut frames: Vec<String> = Vec::new();
+    for line in backtrace.lines().map(str::trim_start).filter(|l| !l.is_empty()) {
+        match frames.last_mut() {
+            Some(frame) if line.starts_with("at ") => {
+                frame.push(' ');
+                frame.push_str(line);
+            }
+            _ => frames.push(line.to_string()),
+        }
+    }
     
-    info!("Crash report generated successfully: {}", output_path);
-    Ok(())
+    if frames.len() > limit {
+        let cut = frames.len() - limit;
+        frames.truncate(limit);
+        frames.push(format!("... {} more frame(s); use --full-backtrace to see them", cut));
+    }
+    frames
 }
 
 /// Update fallback state
 fn update_fallback_state(status: &str, snapshot_id: Option<&str>) -> Result<()> {
-    let panic_dir = PathBuf::from(constants::ROOT_DIR).join(".panic");
+    let panic_dir = constants::root_dir().join(".panic");
     let fallback_path = panic_dir.join("fallback.zk");
     
     let fallback_state = FallbackState {
@@ -223,11 +649,121 @@ fn update_fallback_state(status: &str, snapshot_id: Option<&str>) -> Result<()>
     };
     
     let fallback_content = serde_json::to_string_pretty(&fallback_state)?;
-    fs::write(&fallback_path, fallback_content)?;
+    crate::core::fs::write_atomic(&fallback_path, fallback_content.as_bytes())?;
     
     Ok(())
 }
 
+/// What went wrong, recorded with each panic
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PanicEvent {
+    /// A subsystem failed in a way it can't recover from itself
+    SubsystemFailure {
+        /// Subsystem, or the thread that panicked
+        subsystem: String,
+        
+        /// What failed
+        error: String,
+    },
+    
+    /// A container was killed for running out of memory
+    ContainerOom {
+        /// Container ID
+        container_id: String,
+    },
+    
+    /// A ZK contract or proof did not verify
+    ZkVerificationFailure {
+        /// Contract name
+        contract: String,
+        
+        /// Hash of the proof or contract that failed
+        proof_hash: String,
+    },
+    
+    /// This node lost contact with its peers
+    NetworkPartition {
+        /// Peers still reachable
+        peer_count: usize,
+    },
+    
+    /// State on disk is corrupted
+    CorruptedState {
+        /// File or directory found corrupted
+        path: PathBuf,
+    },
+}
+
+impl PanicEvent {
+    /// The event's `kind` discriminator, e.g. `subsystem_failure`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            PanicEvent::SubsystemFailure { .. } => "subsystem_failure",
+            PanicEvent::ContainerOom { .. } => "container_oom",
+            PanicEvent::ZkVerificationFailure { .. } => "zk_verification_failure",
+            PanicEvent::NetworkPartition { .. } => "network_partition",
+            PanicEvent::CorruptedState { .. } => "corrupted_state",
+        }
+    }
+}
+
+impl std::fmt::Display for PanicEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PanicEvent::SubsystemFailure { subsystem, error } => write!(f, "{} failed: {}", subsystem, error),
+            PanicEvent::ContainerOom { container_id } => write!(f, "Container {} ran out of memory", container_id),
+            PanicEvent::ZkVerificationFailure { contract, proof_hash } => {
+                write!(f, "ZK verification of {} failed (hash {})", contract, proof_hash)
+            }
+            PanicEvent::NetworkPartition { peer_count } => write!(f, "Network partition: {} peer(s) reachable", peer_count),
+            PanicEvent::CorruptedState { path } => write!(f, "Corrupted state at {:?}", path),
+        }
+    }
+}
+
+/// How serious a panic is, from least to most
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PanicSeverity {
+    /// Recovered from automatically
+    Low,
+    
+    /// Recovered from automatically
+    Medium,
+    
+    /// Recovered from by `panic recover`
+    High,
+    
+    /// Recovered from only by `panic recover --force`
+    Critical,
+}
+
+impl std::fmt::Display for PanicSeverity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            PanicSeverity::Low => "low",
+            PanicSeverity::Medium => "medium",
+            PanicSeverity::High => "high",
+            PanicSeverity::Critical => "critical",
+        })
+    }
+}
+
+impl std::str::FromStr for PanicSeverity {
+    type Err = anyhow::Error;
+    
+    fn from_str(severity: &str) -> Result<Self> {
+        match severity.to_lowercase().as_str() {
+            "low" => Ok(PanicSeverity::Low),
+            "medium" => Ok(PanicSeverity::Medium),
+            "high" => Ok(PanicSeverity::High),
+            "critical" => Ok(PanicSeverity::Critical),
+            other => anyhow::bail!("Unknown panic severity: {} (expected low, medium, high or critical)", other),
+        }
+    }
+}
+
 /// Fallback state
 #[derive(Debug, Serialize, Deserialize)]
 struct FallbackState {
@@ -247,27 +783,76 @@ struct PanicRecord {
     /// Timestamp when the panic occurred
     timestamp: u64,
     
-    /// Reason for the panic
+    /// Reason for the panic: the event kind, or a free-form reason in
+    /// records from before typed events
     reason: String,
     
     /// Detailed information about the panic
     details: String,
+    
+    /// What went wrong; absent for crashes and older records
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    event: Option<PanicEvent>,
+    
+    /// How serious the panic was; absent in older records
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    severity: Option<PanicSeverity>,
+    
+    /// Stack of the thread that recorded the panic; absent for crashes and older records
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    backtrace: Option<String>,
+    
+    /// Snapshot taken for recovery, if any
+    #[serde(default)]
+    snapshot_id: Option<String>,
+    
+    /// Whether the snapshot was a normal or emergency one
+    #[serde(default)]
+    snapshot_mode: Option<SnapshotMode>,
+    
+    /// Snapshots deleted to make room for an emergency snapshot
+    #[serde(default)]
+    sacrificed_snapshots: Vec<String>,
+}
+
+/// Snapshot taken while recording a panic
+struct PanicSnapshot {
+    /// Snapshot ID
+    id: String,
+    
+    /// How the snapshot was taken
+    mode: SnapshotMode,
+    
+    /// Snapshots deleted to make room
+    sacrificed: Vec<String>,
 }
 
 /// Panic status
-#[derive(Debug, Serialize, Deserialize)]
-struct PanicStatus {
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PanicStatus {
     /// Whether a panic is currently active
-    active: bool,
+    pub active: bool,
     
     /// Timestamp of the panic
-    timestamp: u64,
+    pub timestamp: u64,
     
     /// Reason for the panic
-    reason: String,
+    pub reason: String,
+    
+    /// Highest severity among the panics not recovered from; absent in older status files
+    #[serde(default)]
+    pub severity: Option<PanicSeverity>,
     
     /// Whether recovery has been attempted
-    recovery_attempted: bool,
+    pub recovery_attempted: bool,
+    
+    /// Whether recovery is suspended after a panic storm
+    #[serde(default)]
+    pub in_cooldown: bool,
+    
+    /// When the suspension ends (seconds since the epoch); 0 if there never was one
+    #[serde(default)]
+    pub cooldown_until: u64,
 }
 
 /// System information
@@ -295,6 +880,354 @@ struct CrashReport {
     /// System information
     system_info: SystemInfo,
     
-    /// Panic records
-    panic_records: Vec<PanicRecord>,
+    /// Panic records by kind, oldest first
+    panic_records_by_kind: BTreeMap<String, Vec<ReportedPanic>>,
+}
+
+/// Panic record as written into a crash report
+#[derive(Debug, Serialize, Deserialize)]
+struct ReportedPanic {
+    /// The record, without its raw backtrace
+    #[serde(flatten)]
+    record: PanicRecord,
+    
+    /// Backtrace frames, innermost first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    backtrace: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Mutex, MutexGuard};
+    
+    // Held by tests that write panic records or the status, which other tests would see
+    static RECORDS: Mutex<()> = Mutex::new(());
+    
+    /// Serialize a test that writes panic records; their names only have second resolution
+    ///
+    /// Storm detection is turned off, as tests record panics faster than any limit.
+    pub(super) fn lock_records() -> MutexGuard<'static, ()> {
+        let guard = RECORDS.lock().unwrap_or_else(|e| e.into_inner());
+        fs::create_dir_all(constants::root_dir().join(constants::PANIC_DIR)).unwrap();
+        save_config(&PanicConfig { max_panics_per_minute: 0, ..PanicConfig::default() });
+        guard
+    }
+    
+    fn save_config(config: &PanicConfig) {
+        fs::write(constants::root_dir().join(CONFIG_FILE), serde_json::to_string_pretty(config).unwrap()).unwrap();
+    }
+    
+    /// Forget the panic status, so earlier tests' panics don't carry over,
+    /// and leave recovery without a snapshot to restore
+    fn clear_status() {
+        let _ = fs::remove_file(constants::root_dir().join(constants::PANIC_DIR).join("status.json"));
+        update_fallback_state("test", None).unwrap();
+    }
+    
+    fn status() -> PanicStatus {
+        read_status().unwrap().expect("a panic was recorded")
+    }
+    
+    fn record_at(timestamp: u64, reason: &str, event: Option<PanicEvent>, severity: Option<PanicSeverity>) {
+        save_record(&PanicRecord {
+            timestamp,
+            reason: reason.to_string(),
+            details: format!("severity test: {}", reason),
+            event,
+            severity,
+            backtrace: None,
+            snapshot_id: None,
+            snapshot_mode: None,
+            sacrificed_snapshots: Vec::new(),
+        }).unwrap();
+    }
+    
+    /// Write a crash report and read it back
+    fn report(full_backtrace: bool, min_severity: Option<PanicSeverity>) -> CrashReport {
+        let path = constants::root_dir().join(constants::PANIC_DIR).join("report-test.json");
+        generate_report(path.to_str().unwrap(), full_backtrace, min_severity).unwrap();
+        serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap()
+    }
+    
+    /// The reported panic whose details mention `message`
+    fn reported<'a>(report: &'a CrashReport, kind: &str, message: &str) -> Option<&'a ReportedPanic> {
+        report.panic_records_by_kind.get(kind)?.iter().find(|r| r.record.details.contains(message))
+    }
+    
+    #[test]
+    fn recorded_backtraces_round_trip_through_reports() {
+        let _records = lock_records();
+        clear_status();
+        record_panic(PanicEvent::SubsystemFailure {
+            subsystem: "report-test".to_string(),
+            error: "backtrace round trip".to_string(),
+        }).unwrap();
+        
+        let record = records().into_iter().find(|r| r.details.contains("backtrace round trip")).unwrap();
+        let raw = record.backtrace.expect("record_panic captures a backtrace");
+        assert!(raw.contains("record_panic"), "{}", raw);
+        
+        let full = report(true, None);
+        let frames = &reported(&full, "subsystem_failure", "backtrace round trip").unwrap().backtrace;
+        assert_eq!(frames, &backtrace_frames(&raw, usize::MAX));
+        assert!(frames.len() > REPORT_BACKTRACE_FRAMES, "{:#?}", frames);
+        assert!(frames.iter().all(|f| f == f.trim_start() && !f.starts_with("at ")), "{:#?}", frames);
+        
+        // By default the innermost frames are kept, with a note of how many were cut
+        let trimmed = report(false, None);
+        let reported = reported(&trimmed, "subsystem_failure", "backtrace round trip").unwrap();
+        assert_eq!(reported.backtrace[..REPORT_BACKTRACE_FRAMES], frames[..REPORT_BACKTRACE_FRAMES]);
+        assert_eq!(reported.backtrace[REPORT_BACKTRACE_FRAMES],
+                   format!("... {} more frame(s); use --full-backtrace to see them", frames.len() - REPORT_BACKTRACE_FRAMES));
+        assert_eq!(reported.backtrace.len(), REPORT_BACKTRACE_FRAMES + 1);
+        assert!(reported.record.backtrace.is_none());
+    }
+    
+    #[test]
+    fn events_are_tagged_with_their_kind_and_reported_by_it() {
+        let event = PanicEvent::ZkVerificationFailure { contract: "kind-test".to_string(), proof_hash: "ab12".to_string() };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json, serde_json::json!({ "kind": "zk_verification_failure", "contract": "kind-test", "proof_hash": "ab12" }));
+        assert_eq!(json["kind"], event.kind());
+        assert_eq!(serde_json::from_value::<PanicEvent>(json).unwrap(), event);
+        assert_eq!(event.to_string(), "ZK verification of kind-test failed (hash ab12)");
+        
+        let partition = serde_json::to_value(PanicEvent::NetworkPartition { peer_count: 2 }).unwrap();
+        assert_eq!(partition, serde_json::json!({ "kind": "network_partition", "peer_count": 2 }));
+        assert!(serde_json::from_str::<PanicEvent>(r#"{"kind": "meltdown"}"#).is_err());
+        
+        // Typed records are grouped by their event, older ones by their reason
+        let _records = lock_records();
+        record_at(1_000_201, "zk_verification_failure", Some(event), None);
+        record_at(1_000_202, "kind-test-legacy", None, Some(PanicSeverity::High));
+        record_at(1_000_203, "kind-test-legacy", None, Some(PanicSeverity::High));
+        let path = constants::root_dir().join(constants::PANIC_DIR).join("report-test.json");
+        let histogram = generate_report(path.to_str().unwrap(), false, None).unwrap();
+        assert_eq!(histogram.get("kind-test-legacy"), Some(&2));
+        assert!(histogram["zk_verification_failure"] >= 1);
+        
+        let report: CrashReport = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(histogram.len(), report.panic_records_by_kind.len());
+        assert!(reported(&report, "zk_verification_failure", "severity test: zk_verification_failure").is_some());
+        let legacy = &report.panic_records_by_kind["kind-test-legacy"];
+        assert_eq!(legacy.iter().map(|r| r.record.timestamp).collect::<Vec<_>>(), [1_000_202, 1_000_203]);
+        
+        for timestamp in 1_000_201..=1_000_203 {
+            fs::remove_file(constants::root_dir().join(constants::PANIC_DIR).join(format!("panic-{}.json", timestamp))).unwrap();
+        }
+    }
+    
+    #[test]
+    fn backtrace_frames_join_locations_and_cut_to_the_limit() {
+        let raw = concat!(
+            "   0: std::backtrace::Backtrace::force_capture\n",
+            "             at /rustc/library/std/src/backtrace.rs:312:13\n",
+            "   1: sentientos::panic::record_panic\n",
+            "             at ./src/panic/mod.rs:216:21\n",
+            "\n",
+            "   2: main\n",
+        );
+        let frames = [
+            "0: std::backtrace::Backtrace::force_capture at /rustc/library/std/src/backtrace.rs:312:13",
+            "1: sentientos::panic::record_panic at ./src/panic/mod.rs:216:21",
+            "2: main",
+        ];
+        
+        assert_eq!(backtrace_frames(raw, 3), frames);
+        assert_eq!(backtrace_frames(raw, 2), [frames[0], frames[1], "... 1 more frame(s); use --full-backtrace to see them"]);
+        assert!(backtrace_frames("", REPORT_BACKTRACE_FRAMES).is_empty());
+    }
+    
+    #[test]
+    fn records_without_a_backtrace_are_reported_without_one() {
+        let _records = lock_records();
+        let record = PanicRecord {
+            timestamp: 1_000_001,
+            reason: "crash-sigsegv".to_string(),
+            details: "report test: crash without a backtrace".to_string(),
+            event: None,
+            severity: Some(PanicSeverity::High),
+            backtrace: None,
+            snapshot_id: None,
+            snapshot_mode: None,
+            sacrificed_snapshots: Vec::new(),
+        };
+        save_record(&record).unwrap();
+        
+        let path = constants::root_dir().join(constants::PANIC_DIR).join("report-test.json");
+        generate_report(path.to_str().unwrap(), true, None).unwrap();
+        let report: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        let reported = report["panic_records_by_kind"]["crash-sigsegv"].as_array().unwrap().iter()
+            .find(|r| r["details"] == "report test: crash without a backtrace")
+            .unwrap();
+        assert!(reported.get("backtrace").is_none(), "{}", reported);
+        
+        fs::remove_file(constants::root_dir().join(constants::PANIC_DIR).join("panic-1000001.json")).unwrap();
+    }
+    
+    #[test]
+    fn severities_follow_the_rules_and_default_to_high() {
+        let config = PanicConfig::default();
+        assert_eq!(config.severity_of(&PanicEvent::ContainerOom { container_id: "c".to_string() }), PanicSeverity::Medium);
+        assert_eq!(config.severity_of(&PanicEvent::CorruptedState { path: PathBuf::from("/x") }), PanicSeverity::Critical);
+        assert_eq!(config.severity_of(&PanicEvent::NetworkPartition { peer_count: 0 }), PanicSeverity::High);
+        
+        let config = PanicConfig {
+            severity_rules: BTreeMap::from([("network_partition".to_string(), PanicSeverity::Low)]),
+            ..PanicConfig::default()
+        };
+        assert_eq!(config.severity_of(&PanicEvent::NetworkPartition { peer_count: 0 }), PanicSeverity::Low);
+        assert_eq!(config.severity_of(&PanicEvent::ContainerOom { container_id: "c".to_string() }), PanicSeverity::High);
+        
+        for severity in [PanicSeverity::Low, PanicSeverity::Medium, PanicSeverity::High, PanicSeverity::Critical] {
+            assert_eq!(severity.to_string().parse::<PanicSeverity>().unwrap(), severity);
+        }
+        assert_eq!("CRITICAL".parse::<PanicSeverity>().unwrap(), PanicSeverity::Critical);
+        assert!("fatal".parse::<PanicSeverity>().is_err());
+    }
+    
+    #[test]
+    fn critical_panics_are_only_recovered_when_forced() {
+        let _records = lock_records();
+        clear_status();
+        record_panic(PanicEvent::CorruptedState { path: PathBuf::from("/severity-test/critical") }).unwrap();
+        let recorded = status();
+        assert!(recorded.active);
+        assert_eq!(recorded.severity, Some(PanicSeverity::Critical));
+        assert!(!recorded.recovery_attempted);
+        
+        let err = recover(false).unwrap_err();
+        assert!(err.to_string().contains("Refusing to recover from critical panic corrupted_state"), "{:#}", err);
+        assert!(!status().recovery_attempted);
+        
+        recover(true).unwrap();
+        let recovered = status();
+        assert!(recovered.recovery_attempted);
+        assert_eq!(recovered.severity, Some(PanicSeverity::Critical));
+    }
+    
+    #[test]
+    fn medium_panics_are_recovered_automatically() {
+        let _records = lock_records();
+        clear_status();
+        record_panic(PanicEvent::ContainerOom { container_id: "severity-test-medium".to_string() }).unwrap();
+        let recorded = status();
+        assert_eq!(recorded.severity, Some(PanicSeverity::Medium));
+        assert!(recorded.recovery_attempted);
+        
+        // High panics wait for an operator
+        clear_status();
+        record_panic(PanicEvent::NetworkPartition { peer_count: 0 }).unwrap();
+        let recorded = status();
+        assert_eq!(recorded.severity, Some(PanicSeverity::High));
+        assert!(!recorded.recovery_attempted);
+    }
+    
+    #[test]
+    fn unrecovered_critical_panics_block_automatic_recovery() {
+        let _records = lock_records();
+        clear_status();
+        record_panic(PanicEvent::CorruptedState { path: PathBuf::from("/severity-test/carried") }).unwrap();
+        record_panic(PanicEvent::ContainerOom { container_id: "severity-test-carried".to_string() }).unwrap();
+        
+        let status = status();
+        assert!(status.active);
+        assert_eq!(status.reason, "container_oom");
+        assert_eq!(status.severity, Some(PanicSeverity::Critical));
+        assert!(!status.recovery_attempted);
+    }
+    
+    #[test]
+    fn reports_only_include_records_at_or_above_the_minimum_severity() {
+        let _records = lock_records();
+        // Records from before severities take theirs from the rules
+        record_at(1_000_101, "container_oom", Some(PanicEvent::ContainerOom { container_id: "c".to_string() }), None);
+        record_at(1_000_102, "severity-test-low", None, Some(PanicSeverity::Low));
+        record_at(1_000_103, "severity-test-high", None, Some(PanicSeverity::High));
+        record_at(1_000_104, "corrupted_state", Some(PanicEvent::CorruptedState { path: PathBuf::from("/x") }), None);
+        let kinds = ["container_oom", "severity-test-low", "severity-test-high", "corrupted_state"];
+        let included = |min_severity| {
+            let report = report(false, min_severity);
+            kinds.into_iter()
+                .filter(|kind| reported(&report, kind, &format!("severity test: {}", kind)).is_some())
+                .collect::<Vec<_>>()
+        };
+        
+        assert_eq!(included(None), kinds);
+        assert_eq!(included(Some(PanicSeverity::Medium)), ["container_oom", "severity-test-high", "corrupted_state"]);
+        assert_eq!(included(Some(PanicSeverity::High)), ["severity-test-high", "corrupted_state"]);
+        assert_eq!(included(Some(PanicSeverity::Critical)), ["corrupted_state"]);
+        assert_eq!(reported(&report(false, None), "container_oom", "severity test: container_oom").unwrap().record.severity,
+                   Some(PanicSeverity::Medium));
+        
+        for timestamp in 1_000_101..=1_000_104 {
+            fs::remove_file(constants::root_dir().join(constants::PANIC_DIR).join(format!("panic-{}.json", timestamp))).unwrap();
+        }
+    }
+    
+    #[test]
+    fn panic_windows_count_recent_panics_and_drop_the_oldest() {
+        let state = PanicState::new();
+        state.note(100);
+        state.note(150);
+        assert_eq!((state.count_after(0), state.count_after(100), state.count_after(150)), (2, 1, 0));
+        
+        // A full window replaces its oldest entries
+        for timestamp in 200..200 + PANIC_WINDOW_SLOTS as u64 {
+            state.note(timestamp);
+        }
+        assert_eq!(state.count_after(0), PANIC_WINDOW_SLOTS);
+        assert_eq!(state.count_after(199), PANIC_WINDOW_SLOTS);
+    }
+    
+    #[test]
+    fn panic_storms_pause_recovery_until_the_cooldown_ends() {
+        let _records = lock_records();
+        clear_status();
+        save_config(&PanicConfig { max_panics_per_minute: 2, cooldown_secs: 600, ..PanicConfig::default() });
+        for peer_count in 0..3 {
+            record_panic(PanicEvent::NetworkPartition { peer_count }).unwrap();
+        }
+        
+        recover(false).unwrap();
+        let storm = status();
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        assert!(storm.in_cooldown && !storm.recovery_attempted);
+        assert!(storm.cooldown_until > now && storm.cooldown_until <= now + 600);
+        assert!(super::status().unwrap().unwrap().in_cooldown);
+        
+        // Still cooling down
+        recover(false).unwrap();
+        assert!(!status().recovery_attempted);
+        
+        // Forcing ends the cooldown, and panics before it no longer count
+        recover(true).unwrap();
+        let forced = status();
+        assert!(forced.recovery_attempted && !forced.in_cooldown);
+        assert!(forced.cooldown_until <= now + 1);
+        assert!(!super::status().unwrap().unwrap().in_cooldown);
+        
+        // Once a cooldown is over, recovery resumes by itself
+        clear_status();
+        save_config(&PanicConfig { max_panics_per_minute: 2, cooldown_secs: 0, ..PanicConfig::default() });
+        for peer_count in 0..3 {
+            record_panic(PanicEvent::NetworkPartition { peer_count }).unwrap();
+        }
+        recover(false).unwrap();
+        assert!(!status().recovery_attempted);
+        recover(false).unwrap();
+        assert!(status().recovery_attempted);
+        
+        save_config(&PanicConfig { max_panics_per_minute: 0, ..PanicConfig::default() });
+    }
+    
+    /// Every panic record under `.panic`
+    pub(super) fn records() -> Vec<PanicRecord> {
+        fs::read_dir(constants::root_dir().join(constants::PANIC_DIR)).unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().starts_with("panic-"))
+            .filter_map(|e| serde_json::from_str(&fs::read_to_string(e.path()).ok()?).ok())
+            .collect()
+    }
 }
@@ -0,0 +1,227 @@
+// SentientOS Remote Crash Reports
+// Sends crash reports to the configured endpoint, keeping the ones that could not be sent
+
+use anyhow::{Result, Context};
+use tracing::{info, debug, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::core::constants;
+
+// Constants
+const UNSENT_DIR: &str = ".panic/log.send/unsent";
+const SEND_ATTEMPTS: u32 = 3;
+const FIRST_RETRY_DELAY: Duration = Duration::from_secs(1);
+const SEND_TIMEOUT: Duration = Duration::from_secs(15);
+
+// Held while a queued report is sent, so two threads never send the same one
+static SENDING: Mutex<()> = Mutex::new(());
+
+/// Queue a report and send it from a background thread
+///
+/// The report is written to `.panic/log.send/unsent` first and removed
+/// once the endpoint accepts it, so a report the process dies before
+/// sending is still sent at the next boot.
+pub(super) fn submit(url: &str, report: &[u8]) -> Result<()> {
+    let path = enqueue(report)?;
+    let url = url.to_string();
+    
+    thread::Builder::new()
+        .name("panic-report".to_string())
+        .spawn(move || {
+            if let Err(e) = send_queued(&url, &path) {
+                warn!("Crash report kept in {:?} for the next boot: {:#}", path, e);
+            }
+        })
+        .context("Failed to start the crash report thread")?;
+    Ok(())
+}
+
+/// Send every queued report to the configured endpoint
+///
+/// Returns how many were sent and how many are still queued.
+pub fn send_pending() -> Result<(usize, usize)> {
+    let config = super::load_config()?;
+    let url = config.remote_report_url
+        .ok_or_else(|| anyhow::anyhow!("No remote_report_url is configured in .panic/config.json"))?;
+    
+    let mut sent = 0;
+    let mut failed = 0;
+    for path in queued()? {
+        match send_queued(&url, &path) {
+            Ok(()) => sent += 1,
+            Err(e) => {
+                warn!("Failed to send crash report {:?}: {:#}", path, e);
+                failed += 1;
+            }
+        }
+    }
+    Ok((sent, failed))
+}
+
+/// Send queued reports from a background thread, if an endpoint is configured
+pub(super) fn send_pending_in_background() {
+    let configured = matches!(super::load_config(), Ok(config) if config.remote_report_url.is_some());
+    if !configured || queued().map_or(true, |q| q.is_empty()) {
+        return;
+    }
+    
+    let spawned = thread::Builder::new()
+        .name("panic-report".to_string())
+        .spawn(|| match send_pending() {
+            Ok((sent, 0)) => info!("Sent {} crash report(s) left from earlier runs", sent),
+            Ok((sent, failed)) => warn!("Sent {} crash report(s); {} still queued", sent, failed),
+            Err(e) => warn!("Failed to send queued crash reports: {:#}", e),
+        });
+    if let Err(e) = spawned {
+        warn!("Failed to start the crash report thread: {}", e);
+    }
+}
+
+/// Send a queued report, retrying with exponential backoff, and remove it once sent
+fn send_queued(url: &str, path: &Path) -> Result<()> {
+    let _sending = SENDING.lock().unwrap_or_else(|e| e.into_inner());
+    if !path.exists() {
+        // Sent by another thread meanwhile
+        return Ok(());
+    }
+    let body = fs::read(path)?;
+    
+    let mut delay = FIRST_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        match post(url, &body) {
+            Ok(()) => break,
+            Err(e) if attempt >= SEND_ATTEMPTS => {
+                return Err(e.context(format!("Gave up after {} attempts", SEND_ATTEMPTS)));
+            }
+            Err(e) => {
+                debug!("Crash report attempt {} failed, retrying in {:?}: {:#}", attempt, delay, e);
+                thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+        }
+    }
+    
+    fs::remove_file(path)?;
+    info!("Crash report sent to {}", url);
+    Ok(())
+}
+
+/// POST a report as JSON
+fn post(url: &str, body: &[u8]) -> Result<()> {
+    ureq::AgentBuilder::new()
+        .timeout(SEND_TIMEOUT)
+        .build()
+        .post(url)
+        .set("Content-Type", "application/json")
+        .send_bytes(body)
+        .map_err(|e| match e {
+            ureq::Error::Status(code, response) => anyhow::anyhow!("{} answered {} {}", url, code, response.status_text()),
+            e => anyhow::anyhow!("Failed to reach {}: {}", url, e),
+        })?;
+    Ok(())
+}
+
+/// Write a report into the queue
+fn enqueue(report: &[u8]) -> Result<PathBuf> {
+    let dir = unsent_dir();
+    fs::create_dir_all(&dir)?;
+    
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+    let path = dir.join(format!("report-{}-{:09}-{}.json", now.as_secs(), now.subsec_nanos(), std::process::id()));
+    crate::core::fs::write_atomic(&path, report)?;
+    Ok(path)
+}
+
+/// Queued reports, oldest first
+fn queued() -> Result<Vec<PathBuf>> {
+    let dir = unsent_dir();
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let mut reports: Vec<PathBuf> = fs::read_dir(&dir)?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().and_then(|e| e.to_str()) == Some("json"))
+        .collect();
+    reports.sort();
+    Ok(reports)
+}
+
+/// Directory of reports waiting to be sent
+fn unsent_dir() -> PathBuf {
+    constants::root_dir().join(UNSENT_DIR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    
+    /// Endpoint answering each request with the next status, returning the bodies it got
+    fn endpoint(statuses: &'static [u16]) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/reports", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            statuses.iter().map(|status| {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut length = 0;
+                let mut json = false;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let header = line.trim().to_lowercase();
+                    if header.is_empty() {
+                        break;
+                    }
+                    if let Some(value) = header.strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                    json |= header == "content-type: application/json";
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                write!(reader.get_mut(), "HTTP/1.1 {} Test\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status).unwrap();
+                assert!(json, "reports are sent as JSON");
+                String::from_utf8(body).unwrap()
+            }).collect()
+        });
+        (url, handle)
+    }
+    
+    #[test]
+    fn reports_are_retried_until_accepted_then_unqueued() {
+        let path = enqueue(br#"{"test": "retried"}"#).unwrap();
+        assert!(queued().unwrap().contains(&path));
+        
+        let (url, server) = endpoint(&[503, 200]);
+        send_queued(&url, &path).unwrap();
+        assert_eq!(server.join().unwrap(), [r#"{"test": "retried"}"#, r#"{"test": "retried"}"#]);
+        assert!(!path.exists());
+        
+        // Already sent by another thread
+        send_queued(&url, &path).unwrap();
+    }
+    
+    #[test]
+    fn reports_stay_queued_when_the_endpoint_is_unreachable() {
+        let url = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/reports", listener.local_addr().unwrap())
+        };
+        let path = enqueue(br#"{"test": "unreachable"}"#).unwrap();
+        
+        let error = send_queued(&url, &path).unwrap_err();
+        assert!(format!("{:#}", error).contains(&format!("Gave up after {} attempts", SEND_ATTEMPTS)), "{:#}", error);
+        assert!(path.exists());
+        
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,397 @@
+// SentientOS Purge
+// Removes everything SentientOS has created on this machine
+
+use anyhow::{Result, Context};
+use tracing::{info, warn};
+use std::path::{Path, PathBuf};
+use std::fs;
+use serde::Serialize;
+
+use crate::core::constants;
+use crate::core::plan::{self, Plan, PlannedAction};
+use crate::package::Ecosystem;
+
+// Constants
+const DESKTOP_PREFIX: &str = "sentientos-";
+const BINARIES: &[&str] = &["sentctl", "sentient_os"];
+
+/// Phrase that must be typed to confirm a purge
+pub const CONFIRM_PHRASE: &str = "purge sentientos";
+
+/// Kind of artifact, in removal order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+    /// System packages installed through the host package manager
+    SystemPackages,
+    
+    /// Desktop entries of SentientOS applications
+    DesktopEntries,
+    
+    /// Shims in bin directories that point into the root directory
+    Shims,
+    
+    /// SentientOS executables
+    Binaries,
+    
+    /// Contents of the root directory
+    RootData,
+}
+
+impl Category {
+    /// Display name
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::SystemPackages => "system packages",
+            Category::DesktopEntries => "desktop entries",
+            Category::Shims => "bin shims",
+            Category::Binaries => "binaries",
+            Category::RootData => "root data",
+        }
+    }
+}
+
+/// Something SentientOS created
+#[derive(Debug, Clone, Serialize)]
+pub struct Artifact {
+    /// Kind of artifact
+    pub category: Category,
+    
+    /// What the artifact is (subsystem, package or file name)
+    pub label: String,
+    
+    /// Location on disk, for files
+    pub path: Option<PathBuf>,
+    
+    /// Size in bytes, 0 for packages
+    pub bytes: u64,
+}
+
+/// Outcome of a purge
+#[derive(Debug, Default)]
+pub struct PurgeReport {
+    /// Artifacts removed
+    pub removed: usize,
+    
+    /// Artifacts that could not be removed, with the reason
+    pub failed: Vec<(Artifact, String)>,
+}
+
+/// Everything a purge would remove, in removal order
+///
+/// With `keep_data` only binaries, shims and desktop entries are listed; the
+/// root directory and installed system packages stay.
+pub fn inventory(keep_data: bool) -> Result<Vec<Artifact>> {
+    let mut artifacts = Vec::new();
+    
+    if !keep_data {
+        artifacts.extend(system_packages());
+    }
+    artifacts.extend(desktop_entries());
+    artifacts.extend(shims());
+    artifacts.extend(binaries());
+    if !keep_data {
+        artifacts.extend(root_data()?);
+    }
+    
+    artifacts.sort_by_key(|a| a.category);
+    Ok(artifacts)
+}
+
+/// Change plan of a purge, for `--dry-run`
+pub fn plan(keep_data: bool) -> Result<Plan> {
+    let mut plan = Plan::new(if keep_data { "purge (keep data)" } else { "purge" });
+    for artifact in inventory(keep_data)? {
+        match &artifact.path {
+            Some(path) => plan.push(PlannedAction::DeleteFiles {
+                path: path.to_string_lossy().to_string(),
+                bytes: artifact.bytes,
+            }),
+            None => plan.push(PlannedAction::RunUninstaller {
+                ecosystem: "linux".to_string(),
+                package: artifact.label.clone(),
+            }),
+        }
+    }
+    Ok(plan)
+}
+
+/// Export a final snapshot and config bundle into a directory outside the root
+pub fn export_final(dir: &Path) -> Result<()> {
+    if dir.starts_with(constants::root_dir()) {
+        anyhow::bail!("Export directory {:?} is inside the root directory, which is about to be removed", dir);
+    }
+    fs::create_dir_all(dir)?;
+    
+    let snapshot_id = crate::heal::take_snapshot("pre purge")?;
+    crate::heal::snapshot::export_snapshot(&snapshot_id, &dir.join(&snapshot_id))?;
+    crate::config::export(&dir.join("config-bundle.json"))?;
+    
+    info!("Exported snapshot {} and config bundle to {:?}", snapshot_id, dir);
+    Ok(())
+}
+
+/// Remove the listed artifacts in order
+///
+/// Artifacts that are already gone count as removed and failures don't stop
+/// the purge, so an interrupted or partly failed purge can simply be re-run.
+pub fn execute(artifacts: &[Artifact]) -> PurgeReport {
+    let mut report = PurgeReport::default();
+    
+    for artifact in artifacts {
+        if artifact.category == Category::RootData {
+            // The command's trace record must not recreate the root afterwards
+            crate::intent::trace::end_correlation();
+        }
+        
+        match remove(artifact) {
+            Ok(()) => report.removed += 1,
+            Err(e) => {
+                warn!("Failed to remove {} {}: {}", artifact.category.name(), artifact.label, e);
+                report.failed.push((artifact.clone(), format!("{:#}", e)));
+            }
+        }
+    }
+    
+    if artifacts.iter().any(|a| a.category == Category::RootData) {
+        // Only succeeds once every entry is gone
+        if let Err(e) = fs::remove_dir(constants::root_dir()) {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Root directory {} was not removed: {}", constants::root_dir().display(), e);
+            }
+        }
+    }
+    
+    report
+}
+
+/// Remove one artifact; one that is already gone is not an error
+fn remove(artifact: &Artifact) -> Result<()> {
+    let path = match &artifact.path {
+        Some(path) => path,
+        None => {
+            // Removing through the package manager also drops the registry entry
+            return crate::package::remove_package(&artifact.label, Some(Ecosystem::Linux), true)
+                .with_context(|| format!("Failed to uninstall system package {}", artifact.label));
+        }
+    };
+    
+    let result = match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Ok(_) => fs::remove_file(path),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => Err(e),
+    };
+    result.with_context(|| format!("Failed to remove {:?}", path))
+}
+
+/// System packages recorded in the package registry
+fn system_packages() -> Vec<Artifact> {
+    let registry = match crate::package::load_registry() {
+        Ok(registry) => registry,
+        Err(_) => return Vec::new(),
+    };
+    
+    let mut packages: Vec<Artifact> = registry.packages.values()
+        .filter(|p| p.ecosystem == Ecosystem::Linux)
+        .map(|p| Artifact { category: Category::SystemPackages, label: p.name.clone(), path: None, bytes: 0 })
+        .collect();
+    packages.sort_by(|a, b| a.label.cmp(&b.label));
+    packages
+}
+
+/// Desktop entries written for SentientOS applications
+fn desktop_entries() -> Vec<Artifact> {
+    let home = match std::env::var("HOME") {
+        Ok(home) if !home.is_empty() => home,
+        _ => return Vec::new(),
+    };
+    
+    let dir = PathBuf::from(home).join(".local/share/applications");
+    files_in(&dir)
+        .into_iter()
+        .filter(|p| file_name(p).starts_with(DESKTOP_PREFIX) && file_name(p).ends_with(".desktop"))
+        .map(|p| file_artifact(Category::DesktopEntries, p))
+        .collect()
+}
+
+/// Links in bin directories resolving into the root directory
+fn shims() -> Vec<Artifact> {
+    let mut dirs = vec![PathBuf::from("/usr/local/bin")];
+    if let Ok(home) = std::env::var("HOME") {
+        dirs.push(PathBuf::from(home).join(".local/bin"));
+    }
+    
+    dirs.iter()
+        .flat_map(|dir| files_in(dir))
+        .filter(|p| fs::read_link(p).map(|target| target.starts_with(constants::root_dir())).unwrap_or(false))
+        .map(|p| file_artifact(Category::Shims, p))
+        .collect()
+}
+
+/// SentientOS executables next to the running one
+fn binaries() -> Vec<Artifact> {
+    let dir = match std::env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf)) {
+        Some(dir) => dir,
+        None => return Vec::new(),
+    };
+    
+    BINARIES.iter()
+        .map(|name| dir.join(name))
+        .filter(|p| p.is_file())
+        .map(|p| file_artifact(Category::Binaries, p))
+        .collect()
+}
+
+/// Entries of the root directory, labelled by subsystem
+fn root_data() -> Result<Vec<Artifact>> {
+    let root = constants::root_dir();
+    if !root.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let mut entries: Vec<Artifact> = fs::read_dir(&root)
+        .with_context(|| format!("Failed to read root directory {}", root.display()))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| {
+            let path = entry.path();
+            Artifact {
+                category: Category::RootData,
+                label: subsystem_of(&file_name(&path)),
+                bytes: plan::path_size(&path),
+                path: Some(path),
+            }
+        })
+        .collect();
+    
+    // Identity and keys go last, so a re-run still knows which node this was
+    entries.sort_by_key(|a| (a.path.as_deref().map_or(false, |p| p.ends_with(constants::AUTH_DIR)), a.label.clone()));
+    Ok(entries)
+}
+
+/// Subsystem owning a root directory entry
+fn subsystem_of(name: &str) -> String {
+    let subsystem = match name {
+        constants::RUNTIME_DIR => "runtime",
+        constants::LOCK_DIR => "locks",
+        constants::AUTH_DIR => "identity and policies",
+        constants::BROWSER_DIR => "browser",
+        constants::CONTAINER_DIR | ".matrixbox" => "matrixbox",
+        constants::HEAL_DIR => "heal snapshots",
+        constants::GOSSIP_DIR => "gossip",
+        constants::INTENT_DIR => "intent",
+        constants::PANIC_DIR => "panic",
+        constants::ZERO_DIR => "zero",
+        constants::UNSECURE_DIR => "unsecure",
+        ".config" => "configuration",
+        ".package" | "packages" => "packages",
+        ".store" => "store",
+        ".trash" => "trash",
+        ".zk" => "zk",
+        ".boot" => "boot",
+        ".network" => "network",
+        ".logs" => "logs",
+        "apps" => "apps",
+        other => other,
+    };
+    format!("{} ({})", subsystem, name)
+}
+
+fn file_artifact(category: Category, path: PathBuf) -> Artifact {
+    Artifact {
+        category,
+        label: file_name(&path),
+        bytes: plan::path_size(&path),
+        path: Some(path),
+    }
+}
+
+fn files_in(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .map(|entries| entries.filter_map(|e| e.ok()).map(|e| e.path()).collect())
+        .unwrap_or_default()
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Fresh scratch directory outside the root
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sentient-purge-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    
+    #[test]
+    fn execute_removes_files_directories_and_links() {
+        let dir = scratch("execute");
+        fs::write(dir.join("sentctl"), b"binary").unwrap();
+        fs::create_dir_all(dir.join("apps/editor")).unwrap();
+        fs::write(dir.join("apps/editor/main.wasm"), b"wasm").unwrap();
+        fs::write(dir.join("target"), b"kept").unwrap();
+        std::os::unix::fs::symlink(dir.join("target"), dir.join("shim")).unwrap();
+        
+        let artifacts: Vec<Artifact> = ["sentctl", "apps", "shim", "already-gone"].iter()
+            .map(|name| file_artifact(Category::Shims, dir.join(name)))
+            .collect();
+        let report = execute(&artifacts);
+        assert_eq!(report.removed, 4);
+        assert!(report.failed.is_empty());
+        assert!(!dir.join("sentctl").exists() && !dir.join("apps").exists());
+        assert!(fs::symlink_metadata(dir.join("shim")).is_err());
+        assert_eq!(fs::read(dir.join("target")).unwrap(), b"kept");
+        
+        // A re-run finds nothing left to fail on
+        assert_eq!(execute(&artifacts).removed, 4);
+    }
+    
+    #[test]
+    fn failures_are_reported_without_stopping_the_purge() {
+        let dir = scratch("failures");
+        fs::write(dir.join("file"), b"not a directory").unwrap();
+        fs::write(dir.join("later"), b"x").unwrap();
+        
+        let artifacts = vec![
+            file_artifact(Category::DesktopEntries, dir.join("file").join("child")),
+            file_artifact(Category::Binaries, dir.join("later")),
+        ];
+        let report = execute(&artifacts);
+        assert_eq!(report.removed, 1);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0.category, Category::DesktopEntries);
+        assert!(!dir.join("later").exists());
+    }
+    
+    #[test]
+    fn root_data_is_labelled_and_keeps_identity_last() {
+        let root = constants::root_dir();
+        fs::create_dir_all(root.join(constants::AUTH_DIR)).unwrap();
+        fs::create_dir_all(root.join(".trash")).unwrap();
+        
+        let entries = root_data().unwrap();
+        let last = entries.last().unwrap();
+        assert!(last.path.as_ref().unwrap().ends_with(constants::AUTH_DIR));
+        assert_eq!(last.label, format!("identity and policies ({})", constants::AUTH_DIR));
+        assert!(entries.iter().all(|a| a.category == Category::RootData));
+        assert!(entries.iter().any(|a| a.label == "trash (.trash)"));
+        
+        assert_eq!(subsystem_of("packages"), "packages (packages)");
+        assert_eq!(subsystem_of("custom"), "custom (custom)");
+    }
+    
+    #[test]
+    fn keeping_data_leaves_the_root_and_packages() {
+        let artifacts = inventory(true).unwrap();
+        assert!(artifacts.iter().all(|a| a.category != Category::RootData && a.category != Category::SystemPackages));
+        assert!(artifacts.windows(2).all(|w| w[0].category <= w[1].category));
+        
+        assert!(Category::SystemPackages < Category::RootData);
+        assert!(export_final(&constants::root_dir().join("exports")).is_err());
+    }
+}
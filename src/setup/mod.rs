@@ -0,0 +1,468 @@
+// SentientOS Setup Wizard
+// First-run configuration, interactive or replayed from an answers file
+
+use anyhow::{Result, Context};
+use tracing::info;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::fs;
+use serde::{Serialize, Deserialize};
+
+use crate::core::constants;
+
+/// Answers file written after every successful setup
+const ANSWERS_FILE: &str = "setup-answers.yaml";
+
+/// Sections of the wizard, in the order they are asked
+const SECTIONS: &[&str] = &["identity", "subsystems", "boot", "store", "peer"];
+
+/// How the wizard gets its answers
+pub enum Mode {
+    /// Ask on the terminal
+    Interactive,
+    
+    /// Accept every default without asking
+    Defaults,
+    
+    /// Replay an answers file
+    Answers(PathBuf),
+}
+
+/// Boot profile, mapped onto the boot configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BootProfile {
+    /// Battery powered sensor nodes
+    UltraLowPower,
+    
+    /// General purpose nodes
+    Standard,
+    
+    /// Edge processors with hardware acceleration
+    HighPerformance,
+}
+
+impl BootProfile {
+    const ALL: [BootProfile; 3] = [BootProfile::UltraLowPower, BootProfile::Standard, BootProfile::HighPerformance];
+    
+    fn name(self) -> &'static str {
+        match self {
+            BootProfile::UltraLowPower => "ultra_low_power",
+            BootProfile::Standard => "standard",
+            BootProfile::HighPerformance => "high_performance",
+        }
+    }
+    
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|p| p.name() == name)
+    }
+}
+
+/// Initial package store repository
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoreAnswers {
+    /// Repository name
+    pub name: String,
+    
+    /// Index URL
+    pub url: String,
+    
+    /// Signing key to trust (hex)
+    pub key: Option<String>,
+}
+
+/// Peer to pair with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PeerAnswers {
+    /// Peer node ID
+    pub id: String,
+    
+    /// Peer gossip endpoint
+    pub endpoint: String,
+}
+
+/// Everything the wizard asks, as stored in the answers file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SetupAnswers {
+    /// Root directory; fixed when SentientOS is built
+    pub root: String,
+    
+    /// Node display name; the generated name is kept when unset
+    pub node_name: Option<String>,
+    
+    /// Enforce ZK proofs
+    pub zk_enabled: bool,
+    
+    /// Take part in gossip synchronization
+    pub gossip_enabled: bool,
+    
+    /// Boot profile
+    pub boot_profile: BootProfile,
+    
+    /// Initial store repository
+    pub store: Option<StoreAnswers>,
+    
+    /// Peer to pair with
+    pub peer: Option<PeerAnswers>,
+}
+
+impl Default for SetupAnswers {
+    fn default() -> Self {
+        Self {
+            root: constants::root_dir().to_string_lossy().to_string(),
+            node_name: None,
+            zk_enabled: true,
+            gossip_enabled: true,
+            boot_profile: BootProfile::Standard,
+            store: None,
+            peer: None,
+        }
+    }
+}
+
+/// Path of the answers file written by the last setup
+pub fn answers_path() -> PathBuf {
+    constants::root_dir().join(".config").join(ANSWERS_FILE)
+}
+
+/// Run the wizard, initialize the system and apply the answers
+pub fn run(mode: Mode) -> Result<SetupAnswers> {
+    let previous = load_answers(&answers_path()).ok();
+    
+    let (answers, sections) = match mode {
+        Mode::Defaults => (SetupAnswers::default(), SECTIONS.to_vec()),
+        Mode::Answers(path) => (load_answers(&path)?, SECTIONS.to_vec()),
+        Mode::Interactive => ask(previous)?,
+    };
+    
+    let root = constants::root_dir();
+    if Path::new(&answers.root) != root {
+        anyhow::bail!("Root directory {} differs from {}; set {} or ~/{} to use it",
+                      answers.root, root.display(), constants::ROOT_ENV, constants::ROOT_FILE);
+    }
+    
+    crate::init(answers.zk_enabled)?;
+    let applied = apply(&answers, &sections);
+    let shutdown = crate::shutdown();
+    applied?;
+    shutdown?;
+    
+    Ok(answers)
+}
+
+/// Read an answers file
+pub fn load_answers(path: &Path) -> Result<SetupAnswers> {
+    let data = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read answers file {:?}", path))?;
+    serde_yaml::from_str(&data).with_context(|| format!("Invalid answers file {:?}", path))
+}
+
+/// Print what setup configured
+pub fn print_summary(answers: &SetupAnswers) {
+    println!("SentientOS setup complete");
+    println!("  Root:        {}", answers.root);
+    println!("  Node name:   {}", answers.node_name.as_deref().unwrap_or("(generated)"));
+    println!("  ZK proofs:   {}", if answers.zk_enabled { "enabled" } else { "disabled" });
+    println!("  Gossip:      {}", if answers.gossip_enabled { "enabled" } else { "disabled" });
+    println!("  Boot:        {}", answers.boot_profile.name());
+    match &answers.store {
+        Some(store) => println!("  Store:       {} ({}){}", store.name, store.url,
+                                if store.key.is_some() { ", key trusted" } else { "" }),
+        None => println!("  Store:       none"),
+    }
+    match &answers.peer {
+        Some(peer) => println!("  Peer:        {} at {}", peer.id, peer.endpoint),
+        None => println!("  Peer:        none"),
+    }
+    
+    let unavailable: Vec<String> = crate::package::tools::ecosystems()
+        .into_iter()
+        .filter(|s| !s.is_available())
+        .map(|s| format!("{:?}", s.ecosystem).to_lowercase())
+        .collect();
+    if unavailable.is_empty() {
+        println!("  Ecosystems:  all available");
+    } else {
+        println!("  Ecosystems:  unavailable: {} (see `sentctl doctor`)", unavailable.join(", "));
+    }
+    println!("Answers saved to {:?}; replay with `sentctl setup --answers <file>`", answers_path());
+}
+
+/// Ask the questions, offering to reconfigure single sections after a previous setup
+fn ask(previous: Option<SetupAnswers>) -> Result<(SetupAnswers, Vec<&'static str>)> {
+    let sections = match &previous {
+        Some(_) => {
+            println!("SentientOS is already set up. Sections: {}", SECTIONS.join(", "));
+            let chosen = prompt("Reconfigure which sections (comma separated)", "all")?;
+            if chosen == "all" {
+                SECTIONS.to_vec()
+            } else {
+                let chosen: Vec<&str> = chosen.split(',').map(str::trim).collect();
+                if let Some(unknown) = chosen.iter().find(|c| !SECTIONS.contains(c)) {
+                    anyhow::bail!("Unknown setup section: {}", unknown);
+                }
+                SECTIONS.iter().copied().filter(|s| chosen.contains(s)).collect()
+            }
+        }
+        None => SECTIONS.to_vec(),
+    };
+    
+    let mut answers = previous.unwrap_or_default();
+    
+    // The root is resolved before setup runs, so only confirm it on first setup
+    if sections.len() == SECTIONS.len() {
+        let resolved = constants::root_dir();
+        loop {
+            let root = prompt("Root directory", &answers.root)?;
+            if Path::new(&root) == resolved {
+                answers.root = root;
+                break;
+            }
+            println!("This run uses {}; set {} or ~/{} to choose another root",
+                     resolved.display(), constants::ROOT_ENV, constants::ROOT_FILE);
+        }
+    }
+    
+    if sections.contains(&"identity") {
+        let name = prompt("Node name (empty keeps the generated name)",
+                          answers.node_name.as_deref().unwrap_or(""))?;
+        answers.node_name = Some(name).filter(|n| !n.is_empty());
+    }
+    
+    if sections.contains(&"subsystems") {
+        answers.zk_enabled = confirm("Enable ZK proof enforcement", answers.zk_enabled)?;
+        answers.gossip_enabled = confirm("Enable gossip synchronization", answers.gossip_enabled)?;
+    }
+    
+    if sections.contains(&"boot") {
+        let names: Vec<&str> = BootProfile::ALL.iter().map(|p| p.name()).collect();
+        loop {
+            let profile = prompt(&format!("Boot profile ({})", names.join(", ")), answers.boot_profile.name())?;
+            match BootProfile::from_name(&profile) {
+                Some(profile) => {
+                    answers.boot_profile = profile;
+                    break;
+                }
+                None => println!("Unknown boot profile: {}", profile),
+            }
+        }
+    }
+    
+    if sections.contains(&"store") {
+        answers.store = if confirm("Add a package store repository", answers.store.is_some())? {
+            let current = answers.store.take();
+            let name = prompt("Repository name", current.as_ref().map_or("main", |s| s.name.as_str()))?;
+            let url = prompt("Repository index URL", current.as_ref().map_or("", |s| s.url.as_str()))?;
+            let key = prompt("Repository signing key (hex, empty for none)",
+                             current.as_ref().and_then(|s| s.key.as_deref()).unwrap_or(""))?;
+            Some(StoreAnswers { name, url, key: Some(key).filter(|k| !k.is_empty()) })
+        } else {
+            None
+        };
+    }
+    
+    if sections.contains(&"peer") {
+        answers.peer = if answers.gossip_enabled && confirm("Pair with a peer", answers.peer.is_some())? {
+            let current = answers.peer.take();
+            let id = prompt("Peer node ID", current.as_ref().map_or("", |p| p.id.as_str()))?;
+            let endpoint = prompt("Peer endpoint (host:port)", current.as_ref().map_or("", |p| p.endpoint.as_str()))?;
+            Some(PeerAnswers { id, endpoint })
+        } else {
+            None
+        };
+    }
+    
+    Ok((answers, sections))
+}
+
+/// Apply the given sections of the answers to the initialized system
+fn apply(answers: &SetupAnswers, sections: &[&str]) -> Result<()> {
+    if sections.contains(&"identity") {
+        if let Some(name) = &answers.node_name {
+            crate::core::identity::rename(name)?;
+        }
+    }
+    
+    if sections.contains(&"subsystems") {
+        update_system_config(|config| {
+            config["subsystems"]["zk"]["enabled"] = serde_json::json!(answers.zk_enabled);
+            config["subsystems"]["gossip"]["enabled"] = serde_json::json!(answers.gossip_enabled);
+        })?;
+    }
+    
+    if sections.contains(&"boot") {
+        crate::boot::save_boot_config(&boot_config(answers))?;
+    }
+    
+    if sections.contains(&"store") {
+        if let Some(store) = &answers.store {
+            if store.url.is_empty() {
+                anyhow::bail!("Store repository {} has no URL", store.name);
+            }
+            crate::store::add_repository(crate::store::Repository {
+                name: store.name.clone(),
+                url: store.url.clone(),
+                key: store.key.clone(),
+            })?;
+        }
+    }
+    
+    if sections.contains(&"peer") && answers.gossip_enabled {
+        if let Some(peer) = &answers.peer {
+            if peer.id.is_empty() || peer.endpoint.is_empty() {
+                anyhow::bail!("Peer needs both an ID and an endpoint");
+            }
+            crate::gossip::add_peer(&peer.id, &peer.endpoint, None)?;
+        }
+    }
+    
+    update_system_config(|config| {
+        config["setup"] = serde_json::json!({
+            "completed_at": chrono::Utc::now().to_rfc3339(),
+            "boot_profile": answers.boot_profile.name(),
+        });
+    })?;
+    
+    let path = answers_path();
+    fs::write(&path, serde_yaml::to_string(answers)?)
+        .with_context(|| format!("Failed to write answers file {:?}", path))?;
+    
+    info!("Setup applied: {}", sections.join(", "));
+    Ok(())
+}
+
+/// Boot configuration for the chosen profile
+fn boot_config(answers: &SetupAnswers) -> crate::boot::BootConfig {
+    let mut config = crate::boot::load_boot_config().unwrap_or_else(|_| crate::boot::default_boot_config());
+    config.zk_enabled = answers.zk_enabled;
+    
+    match answers.boot_profile {
+        BootProfile::UltraLowPower => {
+            config.iot.device_type = "battery_sensor".to_string();
+            config.iot.low_power = true;
+            config.iot.hw_acceleration = false;
+            config.memory_limit = 64;
+        }
+        BootProfile::Standard => {
+            config.iot.device_type = "standard".to_string();
+            config.iot.low_power = false;
+            config.iot.hw_acceleration = true;
+            config.memory_limit = 128;
+        }
+        BootProfile::HighPerformance => {
+            config.iot.device_type = "edge_processor".to_string();
+            config.iot.low_power = false;
+            config.iot.hw_acceleration = true;
+            config.memory_limit = 512;
+        }
+    }
+    
+    config
+}
+
+/// Modify system.json in place
+fn update_system_config(update: impl FnOnce(&mut serde_json::Value)) -> Result<()> {
+    let path = constants::root_dir().join(".config").join("system.json");
+    let mut config: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?)
+        .context("Invalid system.json")?;
+    
+    update(&mut config);
+    
+    fs::write(&path, serde_json::to_string_pretty(&config)?)?;
+    Ok(())
+}
+
+/// Ask for a line of text, returning `default` for an empty answer
+fn prompt(question: &str, default: &str) -> Result<String> {
+    if default.is_empty() {
+        print!("{}: ", question);
+    } else {
+        print!("{} [{}]: ", question, default);
+    }
+    io::stdout().flush()?;
+    
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        anyhow::bail!("Setup aborted: no more input");
+    }
+    
+    let line = line.trim();
+    Ok(if line.is_empty() { default.to_string() } else { line.to_string() })
+}
+
+/// Ask a yes/no question
+fn confirm(question: &str, default: bool) -> Result<bool> {
+    loop {
+        let answer = prompt(&format!("{} (y/n)", question), if default { "y" } else { "n" })?;
+        match answer.to_lowercase().as_str() {
+            "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => println!("Please answer y or n"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn write_answers(name: &str, yaml: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("sentient-setup-{}-{}.yaml", name, std::process::id()));
+        fs::write(&path, yaml).unwrap();
+        path
+    }
+    
+    #[test]
+    fn answers_files_fall_back_to_defaults() {
+        let path = write_answers("partial", "node_name: edge-1\nboot_profile: high_performance\npeer:\n  id: node-2\n  endpoint: 10.0.0.2:7000\n");
+        let answers = load_answers(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        
+        assert_eq!(answers.root, constants::root_dir().to_string_lossy());
+        assert_eq!(answers.node_name.as_deref(), Some("edge-1"));
+        assert!(answers.zk_enabled && answers.gossip_enabled);
+        assert_eq!(answers.boot_profile, BootProfile::HighPerformance);
+        assert!(answers.store.is_none());
+        assert_eq!(answers.peer.unwrap().endpoint, "10.0.0.2:7000");
+        
+        let path = write_answers("invalid", "boot_profile: turbo\n");
+        assert!(load_answers(&path).is_err());
+        fs::remove_file(&path).unwrap();
+        assert!(load_answers(&path).is_err());
+    }
+    
+    #[test]
+    fn saved_answers_replay_unchanged() {
+        let answers = SetupAnswers {
+            node_name: Some("sensor".to_string()),
+            zk_enabled: false,
+            boot_profile: BootProfile::UltraLowPower,
+            store: Some(StoreAnswers { name: "main".to_string(), url: "https://example.org/index.json".to_string(), key: None }),
+            ..Default::default()
+        };
+        let path = write_answers("replay", &serde_yaml::to_string(&answers).unwrap());
+        let replayed = load_answers(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        
+        assert_eq!(serde_yaml::to_string(&replayed).unwrap(), serde_yaml::to_string(&answers).unwrap());
+    }
+    
+    #[test]
+    fn boot_profiles_map_onto_the_boot_configuration() {
+        for profile in BootProfile::ALL {
+            assert_eq!(BootProfile::from_name(profile.name()), Some(profile));
+        }
+        assert_eq!(BootProfile::from_name("turbo"), None);
+        
+        let config = |boot_profile, zk_enabled| boot_config(&SetupAnswers { boot_profile, zk_enabled, ..Default::default() });
+        
+        let low_power = config(BootProfile::UltraLowPower, false);
+        assert!(low_power.iot.low_power && !low_power.iot.hw_acceleration && !low_power.zk_enabled);
+        assert_eq!((low_power.iot.device_type.as_str(), low_power.memory_limit), ("battery_sensor", 64));
+        
+        let edge = config(BootProfile::HighPerformance, true);
+        assert!(!edge.iot.low_power && edge.iot.hw_acceleration && edge.zk_enabled);
+        assert_eq!((edge.iot.device_type.as_str(), edge.memory_limit), ("edge_processor", 512));
+    }
+}
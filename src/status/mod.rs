@@ -0,0 +1,249 @@
+// SentientOS Status
+// At-a-glance state of each subsystem, for `sentctl status`
+
+use anyhow::Result;
+use tracing::{info, warn};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use crate::gossip::PeerStatus;
+use crate::heal::HealthStatus;
+use crate::matrixbox::container::ContainerStatus;
+use crate::network::NetworkStatus;
+use crate::zk::verify::ContractVerificationStatus;
+
+/// Check of one subsystem, returning its state and a detail line
+type Probe = fn() -> Result<(SubsystemState, String)>;
+
+/// State of a subsystem
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubsystemState {
+    /// Working as expected
+    Healthy,
+    
+    /// Working, but needs attention
+    Degraded,
+    
+    /// Not running or not connected
+    Offline,
+    
+    /// Its state could not be determined
+    Error,
+}
+
+impl SubsystemState {
+    /// Lowercase name, as printed in the status table
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubsystemState::Healthy => "healthy",
+            SubsystemState::Degraded => "degraded",
+            SubsystemState::Offline => "offline",
+            SubsystemState::Error => "error",
+        }
+    }
+}
+
+/// State of one subsystem
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubsystemStatus {
+    /// Subsystem name
+    pub name: String,
+    
+    /// State
+    pub state: SubsystemState,
+    
+    /// Short explanation of the state
+    pub detail: String,
+}
+
+/// State of the whole system, as printed by `sentctl status --json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemStatus {
+    /// Node ID, if the identity could be loaded
+    pub node_id: Option<String>,
+    
+    /// Version of this build
+    pub version: String,
+    
+    /// When the status was collected
+    pub collected_at: u64,
+    
+    /// One entry per subsystem
+    pub subsystems: Vec<SubsystemStatus>,
+}
+
+impl SystemStatus {
+    /// Whether every subsystem is healthy
+    pub fn is_healthy(&self) -> bool {
+        self.subsystems.iter().all(|s| s.state == SubsystemState::Healthy)
+    }
+}
+
+/// Collect the state of every subsystem
+///
+/// A subsystem whose check fails is reported in the `error` state; the
+/// others are still collected.
+pub fn collect() -> SystemStatus {
+    info!("Collecting system status");
+    
+    let probes: [(&str, Probe); 6] = [
+        ("health", health),
+        ("network", network),
+        ("gossip", gossip),
+        ("matrixbox", matrixbox),
+        ("zk", zk),
+        ("panic", panic),
+    ];
+    
+    SystemStatus {
+        node_id: crate::core::identity::current().ok().map(|identity| identity.id),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        collected_at: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+        subsystems: run_probes(&probes),
+    }
+}
+
+/// Run every probe in order, turning a failed one into the `error` state
+fn run_probes(probes: &[(&str, Probe)]) -> Vec<SubsystemStatus> {
+    probes.iter()
+        .map(|(name, probe)| {
+            let (state, detail) = probe().unwrap_or_else(|e| {
+                warn!("Failed to get status of {}: {}", name, e);
+                (SubsystemState::Error, format!("{:#}", e))
+            });
+            SubsystemStatus { name: name.to_string(), state, detail }
+        })
+        .collect()
+}
+
+fn health() -> Result<(SubsystemState, String)> {
+    let findings = crate::logs::metrics::findings();
+    Ok(match crate::heal::check_health()? {
+        HealthStatus::Healthy => (SubsystemState::Healthy, "all components verified".to_string()),
+        HealthStatus::Degraded => (SubsystemState::Degraded, format!("{} finding(s)", findings.len())),
+        HealthStatus::Critical => (SubsystemState::Degraded, "core components failed verification".to_string()),
+    })
+}
+
+fn network() -> Result<(SubsystemState, String)> {
+    let status = crate::network::get_status()?;
+    let state = match status.status {
+        NetworkStatus::Online => SubsystemState::Healthy,
+        NetworkStatus::Initializing => SubsystemState::Degraded,
+        NetworkStatus::Offline => SubsystemState::Offline,
+        NetworkStatus::Error => SubsystemState::Error,
+    };
+    Ok((state, format!("{} connection(s), discovery {}, TLS {}",
+                       status.connections_count,
+                       if status.discovery_enabled { "on" } else { "off" },
+                       if status.tls_enabled { "on" } else { "off" })))
+}
+
+fn gossip() -> Result<(SubsystemState, String)> {
+    let peers = crate::gossip::list_peers()?;
+    if peers.is_empty() {
+        return Ok((SubsystemState::Offline, "no peers".to_string()));
+    }
+    
+    let reachable = peers.iter().filter(|p| !matches!(p.status, PeerStatus::Offline | PeerStatus::Error)).count();
+    let trusted = peers.iter().filter(|p| crate::gossip::is_trusted_peer(&p.id)).count();
+    let state = match reachable {
+        0 => SubsystemState::Offline,
+        n if n < peers.len() => SubsystemState::Degraded,
+        _ => SubsystemState::Healthy,
+    };
+    Ok((state, format!("{}/{} peer(s) reachable, {} trusted", reachable, peers.len(), trusted)))
+}
+
+fn matrixbox() -> Result<(SubsystemState, String)> {
+    let containers = crate::matrixbox::list_containers()?;
+    let running = containers.iter().filter(|c| matches!(c.status, ContainerStatus::Running | ContainerStatus::Healthy)).count();
+    let failed = containers.iter().filter(|c| matches!(c.status, ContainerStatus::Failed(_) | ContainerStatus::OomKilled | ContainerStatus::ResourceExceeded(_))).count();
+    let suspect = containers.iter().filter(|c| c.status == ContainerStatus::Suspect).count();
+    
+    let state = if failed > 0 || suspect > 0 { SubsystemState::Degraded } else { SubsystemState::Healthy };
+    Ok((state, format!("{} container(s), {} running, {} failed, {} suspect", containers.len(), running, failed, suspect)))
+}
+
+fn zk() -> Result<(SubsystemState, String)> {
+    let proofs: usize = crate::zk::metering::envelope_counts()?.values().sum();
+    let report = crate::zk::verify::contract_verification_report()?;
+    let stale = report.values()
+        .filter(|s| matches!(s, ContractVerificationStatus::NeedsReverification(_) | ContractVerificationStatus::Invalid(_)))
+        .count();
+    
+    let state = if stale > 0 { SubsystemState::Degraded } else { SubsystemState::Healthy };
+    Ok((state, format!("{} contract(s), {} need reverification, {} proof(s)", report.len(), stale, proofs)))
+}
+
+fn panic() -> Result<(SubsystemState, String)> {
+    Ok(match crate::panic::active_panic()? {
+        Some(reason) => (SubsystemState::Degraded, format!("panic active: {}", reason)),
+        None => (SubsystemState::Healthy, "no active panic".to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    
+    fn healthy() -> Result<(SubsystemState, String)> {
+        Ok((SubsystemState::Healthy, "fine".to_string()))
+    }
+    
+    fn failing() -> Result<(SubsystemState, String)> {
+        Err(anyhow::anyhow!("socket refused")).context("Failed to reach the daemon")
+    }
+    
+    fn degraded() -> Result<(SubsystemState, String)> {
+        Ok((SubsystemState::Degraded, "1 finding(s)".to_string()))
+    }
+    
+    fn status(subsystems: Vec<SubsystemStatus>) -> SystemStatus {
+        SystemStatus { node_id: None, version: "0.0.0".to_string(), collected_at: 0, subsystems }
+    }
+    
+    #[test]
+    fn failed_probe_is_reported_as_error_and_the_rest_still_run() {
+        let subsystems = run_probes(&[("first", healthy), ("broken", failing), ("last", degraded)]);
+        
+        let summary: Vec<(&str, SubsystemState)> = subsystems.iter().map(|s| (s.name.as_str(), s.state)).collect();
+        assert_eq!(summary, [
+            ("first", SubsystemState::Healthy),
+            ("broken", SubsystemState::Error),
+            ("last", SubsystemState::Degraded),
+        ]);
+        assert_eq!(subsystems[1].detail, "Failed to reach the daemon: socket refused");
+    }
+    
+    #[test]
+    fn system_is_healthy_only_if_every_subsystem_is() {
+        assert!(status(run_probes(&[("a", healthy), ("b", healthy)])).is_healthy());
+        assert!(!status(run_probes(&[("a", healthy), ("b", degraded)])).is_healthy());
+        assert!(!status(run_probes(&[("a", failing)])).is_healthy());
+    }
+    
+    #[test]
+    fn json_uses_lowercase_states_and_round_trips() {
+        let original = status(run_probes(&[("zk", degraded), ("panic", failing)]));
+        let json = serde_json::to_value(&original).unwrap();
+        assert_eq!(json["subsystems"][0]["state"], "degraded");
+        assert_eq!(json["subsystems"][1]["state"], SubsystemState::Error.as_str());
+        
+        // sentctl reads the daemon's answer back into the same struct
+        let parsed: SystemStatus = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.subsystems.len(), 2);
+        assert_eq!(parsed.subsystems[0].state, SubsystemState::Degraded);
+        assert_eq!(parsed.subsystems[1].detail, original.subsystems[1].detail);
+    }
+    
+    #[test]
+    fn collect_reports_every_subsystem_on_an_uninitialized_node() {
+        let status = collect();
+        let names: Vec<&str> = status.subsystems.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["health", "network", "gossip", "matrixbox", "zk", "panic"]);
+        assert_eq!(status.version, env!("CARGO_PKG_VERSION"));
+    }
+}
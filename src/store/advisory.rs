@@ -0,0 +1,534 @@
+// SentientOS Store Security Advisories
+// Tracks per-advisory acknowledgment state and shares it with trusted peers
+
+use anyhow::{Result, Context};
+use tracing::{info, debug, warn};
+use std::path::PathBuf;
+use std::fs;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use crate::core::constants;
+
+// Constants
+const STATE_FILE: &str = "advisory_state.json";
+const PEER_REPORTS_DIR: &str = "advisories";
+
+/// Security advisory published in the package index
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Advisory {
+    /// Advisory identifier
+    pub id: String,
+    
+    /// Affected package name
+    pub package: String,
+    
+    /// Affected version range, e.g. `>=1.0.0, <1.4.2`
+    pub affected: String,
+    
+    /// First version containing the fix
+    pub fixed_version: Option<String>,
+    
+    /// Severity (low, medium, high, critical)
+    pub severity: String,
+    
+    /// Short description
+    pub summary: String,
+}
+
+/// Local handling status of an advisory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AdvisoryStatus {
+    /// Not yet shown to an operator
+    Unseen,
+    
+    /// Shown to an operator
+    Seen,
+    
+    /// Acknowledged by an operator
+    Acknowledged,
+    
+    /// Installed package is outside the affected range
+    Remediated,
+}
+
+/// Local state of an advisory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdvisoryState {
+    /// Handling status
+    pub status: AdvisoryStatus,
+    
+    /// Package version that remediated the advisory
+    pub remediation_version: Option<String>,
+    
+    /// Operator note recorded with the acknowledgment
+    pub note: Option<String>,
+    
+    /// Last status change timestamp
+    pub updated_at: u64,
+}
+
+/// Signed advisory report gossiped between trusted peers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AdvisoryReport {
+    /// Reporting node
+    pub node_id: String,
+    
+    /// Report timestamp
+    pub timestamp: u64,
+    
+    /// Advisory states by advisory ID
+    pub states: BTreeMap<String, AdvisoryState>,
+    
+    /// Installed packages and versions
+    pub installed: BTreeMap<String, String>,
+    
+    /// Signature by the reporting node's identity key (hex)
+    pub signature: String,
+}
+
+/// One node's standing for an advisory in the fleet view
+#[derive(Debug, Clone)]
+pub struct FleetEntry {
+    /// Node ID
+    pub node_id: String,
+    
+    /// Node display name, if known
+    pub name: Option<String>,
+    
+    /// Installed version of the affected package
+    pub installed_version: Option<String>,
+    
+    /// Whether the installed version is in the affected range
+    pub affected: bool,
+    
+    /// Reported advisory status
+    pub status: AdvisoryStatus,
+    
+    /// When the node's data was reported
+    pub reported_at: u64,
+}
+
+/// List advisories from the package index
+pub fn list_advisories() -> Result<Vec<Advisory>> {
+    Ok(super::load_index()?.advisories)
+}
+
+/// Get an advisory by ID
+pub fn get_advisory(id: &str) -> Result<Advisory> {
+    list_advisories()?
+        .into_iter()
+        .find(|a| a.id == id)
+        .ok_or_else(|| anyhow::anyhow!("Advisory not found: {}", id))
+}
+
+/// Local state of an advisory
+pub fn advisory_state(id: &str) -> Result<AdvisoryState> {
+    Ok(load_states()?.remove(id).unwrap_or_else(|| AdvisoryState {
+        status: AdvisoryStatus::Unseen,
+        remediation_version: None,
+        note: None,
+        updated_at: 0,
+    }))
+}
+
+/// Mark advisories as seen by an operator
+pub fn mark_seen(ids: &[String]) -> Result<()> {
+    let mut states = load_states()?;
+    let mut changed = false;
+    
+    for id in ids {
+        let unseen = states.get(id).map_or(true, |s| s.status == AdvisoryStatus::Unseen);
+        if unseen {
+            states.insert(id.clone(), new_state(AdvisoryStatus::Seen, None, None)?);
+            changed = true;
+        }
+    }
+    
+    if changed {
+        save_states(&states)?;
+    }
+    Ok(())
+}
+
+/// Acknowledge an advisory and share the acknowledgment with trusted peers
+pub fn acknowledge(id: &str, note: Option<&str>) -> Result<()> {
+    get_advisory(id)?;
+    
+    let mut states = load_states()?;
+    if states.get(id).map_or(false, |s| s.status == AdvisoryStatus::Remediated) {
+        info!("Advisory {} is already remediated", id);
+        return Ok(());
+    }
+    
+    states.insert(id.to_string(), new_state(AdvisoryStatus::Acknowledged, None, note.map(String::from))?);
+    save_states(&states)?;
+    
+    info!("Acknowledged advisory: {}", id);
+    broadcast_report();
+    Ok(())
+}
+
+/// Flip advisories to remediated when the installed package left the affected range
+///
+/// Called whenever installed package versions may have changed.
+pub fn refresh_remediation() -> Result<()> {
+    let advisories = list_advisories()?;
+    if advisories.is_empty() {
+        return Ok(());
+    }
+    
+    let installed = installed_versions()?;
+    let mut states = load_states()?;
+    let mut changed = false;
+    
+    for advisory in &advisories {
+        let version = match installed.get(&advisory.package) {
+            Some(version) => version,
+            None => continue,
+        };
+        
+        let already = states.get(&advisory.id).map_or(false, |s| s.status == AdvisoryStatus::Remediated);
+        if already || version_in_range(version, &advisory.affected)? {
+            continue;
+        }
+        
+        // Versions below the affected range were never vulnerable, so they do not remediate
+        let past_fix = advisory.fixed_version.as_deref()
+            .map_or(true, |fixed| compare_versions(version, fixed) != Ordering::Less);
+        if !past_fix {
+            continue;
+        }
+        
+        let note = states.get(&advisory.id).and_then(|s| s.note.clone());
+        states.insert(advisory.id.clone(), new_state(AdvisoryStatus::Remediated, Some(version.clone()), note)?);
+        info!("Advisory {} remediated by {} {}", advisory.id, advisory.package, version);
+        changed = true;
+    }
+    
+    if changed {
+        save_states(&states)?;
+        broadcast_report();
+    }
+    Ok(())
+}
+
+/// Per-node status of an advisory across this node and its trusted peers
+pub fn fleet_status(id: &str) -> Result<Vec<FleetEntry>> {
+    let advisory = get_advisory(id)?;
+    let mut entries = Vec::new();
+    
+    let local = build_report()?;
+    let local_name = crate::core::identity::current().ok().map(|i| i.name);
+    entries.push(fleet_entry(&advisory, &local, local_name)?);
+    
+    for peer in crate::gossip::list_peers()? {
+        if let Some(report) = load_peer_report(&peer.id)? {
+            entries.push(fleet_entry(&advisory, &report, peer.display_name.clone())?);
+        }
+    }
+    
+    Ok(entries)
+}
+
+/// Handle an advisory report gossiped by a peer
+pub fn handle_peer_report(source_id: &str, payload: &[u8]) -> Result<()> {
+    let public_key = match crate::gossip::peer_public_key(source_id).filter(|_| crate::gossip::is_trusted_peer(source_id)) {
+        Some(key) => key,
+        None => {
+            debug!("Ignoring advisory report from untrusted peer: {}", source_id);
+            return Ok(());
+        }
+    };
+    
+    let report: AdvisoryReport = serde_json::from_slice(payload)
+        .context("Failed to parse advisory report")?;
+    
+    if report.node_id != source_id {
+        warn!("Advisory report for {} sent by {}, ignoring", report.node_id, source_id);
+        return Ok(());
+    }
+    
+    crate::core::identity::verify(&public_key, signing_payload(&report)?.as_bytes(), &report.signature)
+        .with_context(|| format!("Invalid advisory report signature from {}", source_id))?;
+    
+    let reports_dir = peer_reports_dir();
+    fs::create_dir_all(&reports_dir)?;
+    fs::write(reports_dir.join(format!("{}.json", source_id)), serde_json::to_string_pretty(&report)?)?;
+    
+    debug!("Stored advisory report from {}", source_id);
+    Ok(())
+}
+
+/// Whether a version falls in a range like `>=1.0.0, <1.4.2`
+pub fn version_in_range(version: &str, range: &str) -> Result<bool> {
+    for constraint in range.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+        let (op, bound) = if let Some(bound) = constraint.strip_prefix(">=") {
+            (">=", bound)
+        } else if let Some(bound) = constraint.strip_prefix("<=") {
+            ("<=", bound)
+        } else if let Some(bound) = constraint.strip_prefix('>') {
+            (">", bound)
+        } else if let Some(bound) = constraint.strip_prefix('<') {
+            ("<", bound)
+        } else if let Some(bound) = constraint.strip_prefix('=') {
+            ("=", bound)
+        } else {
+            ("=", constraint)
+        };
+        
+        let ordering = compare_versions(version, bound.trim());
+        let satisfied = match op {
+            ">=" => ordering != Ordering::Less,
+            "<=" => ordering != Ordering::Greater,
+            ">" => ordering == Ordering::Greater,
+            "<" => ordering == Ordering::Less,
+            _ => ordering == Ordering::Equal,
+        };
+        
+        if !satisfied {
+            return Ok(false);
+        }
+    }
+    
+    Ok(true)
+}
+
+/// Compare dotted versions, numerically where both parts are numbers
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let mut a_parts = a.split(|c| c == '.' || c == '-');
+    let mut b_parts = b.split(|c| c == '.' || c == '-');
+    
+    loop {
+        match (a_parts.next(), b_parts.next()) {
+            (None, None) => return Ordering::Equal,
+            (Some(_), None) => return Ordering::Greater,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(x), Some(y)) => {
+                let ordering = match (x.parse::<u64>(), y.parse::<u64>()) {
+                    (Ok(x), Ok(y)) => x.cmp(&y),
+                    _ => x.cmp(y),
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+        }
+    }
+}
+
+/// Build a fleet entry from a node's report
+fn fleet_entry(advisory: &Advisory, report: &AdvisoryReport, name: Option<String>) -> Result<FleetEntry> {
+    let installed_version = report.installed.get(&advisory.package).cloned();
+    let affected = match &installed_version {
+        Some(version) => version_in_range(version, &advisory.affected)?,
+        None => false,
+    };
+    
+    let status = report.states.get(&advisory.id)
+        .map(|s| s.status)
+        .unwrap_or(AdvisoryStatus::Unseen);
+    
+    Ok(FleetEntry {
+        node_id: report.node_id.clone(),
+        name,
+        installed_version,
+        affected,
+        status,
+        reported_at: report.timestamp,
+    })
+}
+
+/// Send this node's signed advisory report to trusted peers
+fn broadcast_report() {
+    let payload = match build_report().and_then(|report| Ok(serde_json::to_vec(&report)?)) {
+        Ok(payload) => payload,
+        Err(e) => {
+            warn!("Failed to build advisory report: {}", e);
+            return;
+        }
+    };
+    
+    let peers = match crate::gossip::list_peers() {
+        Ok(peers) => peers,
+        Err(e) => {
+            warn!("Failed to list peers for advisory report: {}", e);
+            return;
+        }
+    };
+    
+    for peer in peers.iter().filter(|p| crate::gossip::is_trusted_peer(&p.id)) {
+        if let Err(e) = crate::gossip::protocol::send_message(
+            &peer.endpoint,
+            crate::gossip::protocol::MessageType::AdvisoryReport,
+            &payload,
+        ) {
+            warn!("Failed to send advisory report to {}: {}", peer.id, e);
+        }
+    }
+}
+
+/// Build and sign this node's advisory report
+fn build_report() -> Result<AdvisoryReport> {
+    let mut report = AdvisoryReport {
+        node_id: crate::core::identity::node_id()?,
+        timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        states: load_states()?,
+        installed: installed_versions()?,
+        signature: String::new(),
+    };
+    report.signature = crate::core::identity::sign(signing_payload(&report)?.as_bytes())?;
+    Ok(report)
+}
+
+/// Canonical bytes covered by a report signature
+fn signing_payload(report: &AdvisoryReport) -> Result<String> {
+    Ok(serde_json::to_string(&serde_json::json!({
+        "node_id": report.node_id,
+        "timestamp": report.timestamp,
+        "states": report.states,
+        "installed": report.installed,
+    }))?)
+}
+
+/// Installed native package versions, keyed by package name
+fn installed_versions() -> Result<BTreeMap<String, String>> {
+    let mut installed = BTreeMap::new();
+    
+    for package in crate::package::list_packages(Some(crate::package::Ecosystem::Native))? {
+        installed.insert(package.name, package.version);
+    }
+    
+    // Store packages installed outside the package manager take their index version
+    let index = super::load_index()?;
+    for name in super::list_installed_packages()? {
+        if let Some(package) = index.packages.get(&name) {
+            installed.entry(name).or_insert_with(|| package.version.clone());
+        }
+    }
+    
+    Ok(installed)
+}
+
+/// Latest stored report from a peer
+fn load_peer_report(peer_id: &str) -> Result<Option<AdvisoryReport>> {
+    let path = peer_reports_dir().join(format!("{}.json", peer_id));
+    if !path.exists() {
+        return Ok(None);
+    }
+    
+    let content = fs::read_to_string(&path)?;
+    Ok(Some(serde_json::from_str(&content)?))
+}
+
+/// Create a state record stamped with the current time
+fn new_state(status: AdvisoryStatus, remediation_version: Option<String>, note: Option<String>) -> Result<AdvisoryState> {
+    Ok(AdvisoryState {
+        status,
+        remediation_version,
+        note,
+        updated_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+    })
+}
+
+/// Load local advisory states
+fn load_states() -> Result<BTreeMap<String, AdvisoryState>> {
+    let path = state_path();
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    
+    let content = fs::read_to_string(&path)
+        .context("Failed to read advisory state")?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// Save local advisory states
+fn save_states(states: &BTreeMap<String, AdvisoryState>) -> Result<()> {
+    fs::write(state_path(), serde_json::to_string_pretty(states)?)
+        .context("Failed to write advisory state")
+}
+
+/// Path of the local advisory state file
+fn state_path() -> PathBuf {
+    constants::root_dir().join(".store").join(STATE_FILE)
+}
+
+/// Directory of advisory reports received from peers
+fn peer_reports_dir() -> PathBuf {
+    constants::root_dir().join(constants::GOSSIP_DIR).join(PEER_REPORTS_DIR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Advisory for `openssl` fixed in 1.4.2
+    fn advisory() -> Advisory {
+        Advisory {
+            id: "SOS-2024-001".to_string(),
+            package: "openssl".to_string(),
+            affected: ">=1.0.0, <1.4.2".to_string(),
+            fixed_version: Some("1.4.2".to_string()),
+            severity: "high".to_string(),
+            summary: "Example".to_string(),
+        }
+    }
+    
+    #[test]
+    fn versions_compare_numerically() {
+        assert_eq!(compare_versions("1.10.0", "1.9.9"), Ordering::Greater);
+        assert_eq!(compare_versions("1.4", "1.4.0"), Ordering::Less);
+        assert_eq!(compare_versions("2.0.0", "2.0.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.0.0-beta", "1.0.0-alpha"), Ordering::Greater);
+    }
+    
+    #[test]
+    fn ranges_require_every_constraint() {
+        let range = &advisory().affected;
+        assert!(version_in_range("1.0.0", range).unwrap());
+        assert!(version_in_range("1.4.1", range).unwrap());
+        assert!(!version_in_range("1.4.2", range).unwrap());
+        assert!(!version_in_range("0.9.9", range).unwrap());
+        
+        assert!(version_in_range("3.1", "=3.1").unwrap());
+        assert!(version_in_range("3.1", "3.1").unwrap());
+        assert!(!version_in_range("3.2", "<=3.1").unwrap());
+        assert!(version_in_range("3.2", ">3.1").unwrap());
+        assert!(version_in_range("anything", "").unwrap());
+    }
+    
+    #[test]
+    fn fleet_entries_reflect_the_reported_version_and_status() {
+        let mut report = AdvisoryReport {
+            node_id: "node-a".to_string(),
+            timestamp: 1_700_000_000,
+            states: BTreeMap::new(),
+            installed: BTreeMap::from([("openssl".to_string(), "1.3.0".to_string())]),
+            signature: String::new(),
+        };
+        
+        let entry = fleet_entry(&advisory(), &report, Some("alpha".to_string())).unwrap();
+        assert!(entry.affected);
+        assert_eq!(entry.status, AdvisoryStatus::Unseen);
+        assert_eq!(entry.installed_version.as_deref(), Some("1.3.0"));
+        assert_eq!(entry.reported_at, 1_700_000_000);
+        
+        report.installed.insert("openssl".to_string(), "1.5.0".to_string());
+        report.states.insert("SOS-2024-001".to_string(), AdvisoryState {
+            status: AdvisoryStatus::Remediated,
+            remediation_version: Some("1.5.0".to_string()),
+            note: None,
+            updated_at: 1_700_000_000,
+        });
+        let entry = fleet_entry(&advisory(), &report, None).unwrap();
+        assert!(!entry.affected);
+        assert_eq!(entry.status, AdvisoryStatus::Remediated);
+        
+        report.installed.clear();
+        assert!(!fleet_entry(&advisory(), &report, None).unwrap().affected);
+    }
+}
@@ -0,0 +1,299 @@
+// SentientOS ZK-Store Dependency Resolution
+// Orders package installs so dependencies come first, and guards removal of required packages
+
+use anyhow::{Result, Context};
+use tracing::debug;
+use std::collections::HashSet;
+
+use super::{advisory, diff, Package, PackageIndex};
+
+// Constants
+const CONSTRAINT_OPERATORS: [char; 3] = ['>', '<', '='];
+
+/// A parsed package dependency like `name>=1.2.0`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencySpec {
+    /// Package name
+    pub name: String,
+    
+    /// Version range the dependency must fall in, e.g. `>=1.2.0, <2`;
+    /// `None` accepts any version
+    pub constraint: Option<String>,
+}
+
+impl DependencySpec {
+    /// Parse `name`, `name>=1.2.0` or `name>=1.0, <2.0`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        let (name, constraint) = match spec.find(CONSTRAINT_OPERATORS) {
+            Some(pos) => (spec[..pos].trim(), Some(spec[pos..].trim())),
+            None => (spec, None),
+        };
+        
+        if name.is_empty() || name.contains(['/', '\\', ' ', ',']) || name.starts_with('.') {
+            anyhow::bail!("Invalid dependency name in {:?}", spec);
+        }
+        
+        Ok(Self {
+            name: name.to_string(),
+            constraint: constraint.map(String::from),
+        })
+    }
+    
+    /// Whether a version satisfies the constraint
+    pub fn accepts(&self, version: &str) -> Result<bool> {
+        match &self.constraint {
+            Some(constraint) => advisory::version_in_range(version, constraint),
+            None => Ok(true),
+        }
+    }
+}
+
+/// Packages to install for `package_name`, dependencies before dependents
+///
+/// The package itself always comes last. Dependencies are only included
+/// when they are not installed or their installed version falls outside
+/// the dependent's constraint; the index must then hold a version inside
+/// it. A dependency cycle is an error naming the chain.
+pub fn install_order(index: &PackageIndex, package_name: &str) -> Result<Vec<Package>> {
+    let mut resolver = Resolver {
+        index,
+        stack: Vec::new(),
+        visited: HashSet::new(),
+        order: Vec::new(),
+    };
+    resolver.visit(package_name, true)?;
+    Ok(resolver.order)
+}
+
+/// Installed packages whose index entries depend on `package_name`
+pub fn installed_dependents(index: &PackageIndex, package_name: &str) -> Result<Vec<String>> {
+    let mut dependents = Vec::new();
+    
+    for installed in super::list_installed_packages()? {
+        if installed == package_name {
+            continue;
+        }
+        let package = match index.packages.get(&installed) {
+            Some(package) => package,
+            None => continue,
+        };
+        for requirement in &package.dependencies {
+            if DependencySpec::parse(requirement)?.name == package_name {
+                dependents.push(installed.clone());
+                break;
+            }
+        }
+    }
+    
+    dependents.sort();
+    Ok(dependents)
+}
+
+/// Depth-first walk over the dependency graph in the index
+struct Resolver<'a> {
+    /// Index dependencies are looked up in
+    index: &'a PackageIndex,
+    
+    /// Packages being visited, root first
+    stack: Vec<String>,
+    
+    /// Packages already visited
+    visited: HashSet<String>,
+    
+    /// Packages to install, dependencies first
+    order: Vec<Package>,
+}
+
+impl<'a> Resolver<'a> {
+    /// Visit a package's dependencies, then queue it if `install` is set
+    fn visit(&mut self, name: &str, install: bool) -> Result<()> {
+        let index = self.index;
+        let package = index.packages.get(name)
+            .ok_or_else(|| anyhow::anyhow!("Package not found: {}", name))?;
+        self.stack.push(name.to_string());
+        
+        for requirement in &package.dependencies {
+            let spec = DependencySpec::parse(requirement)
+                .with_context(|| format!("Invalid dependency of package {}", name))?;
+            if self.stack.contains(&spec.name) {
+                anyhow::bail!("Dependency cycle detected: {} -> {}", self.stack.join(" -> "), spec.name);
+            }
+            
+            let installed = diff::installed_version(&spec.name)?
+                .filter(|_| super::package_path(&spec.name).exists());
+            let satisfied = match &installed {
+                Some(version) => spec.accepts(version)?,
+                None => false,
+            };
+            
+            if !satisfied {
+                let available = index.packages.get(&spec.name)
+                    .ok_or_else(|| anyhow::anyhow!("Package {} requires {}, which is not in the index", name, requirement))?;
+                if !spec.accepts(&available.version)? {
+                    anyhow::bail!("Package {} requires {}, but the index only has version {}",
+                                  name, requirement, available.version);
+                }
+                debug!("Dependency {} of {} resolved to version {}", requirement, name, available.version);
+            }
+            
+            if self.visited.contains(&spec.name) {
+                // Visited for a dependent its installed version satisfied
+                if !satisfied && !self.order.iter().any(|p| p.name == spec.name) {
+                    self.order.push(index.packages[&spec.name].clone());
+                }
+            } else if index.packages.contains_key(&spec.name) {
+                // Installed dependencies are still walked to catch cycles through them
+                self.visit(&spec.name, !satisfied)?;
+            }
+        }
+        
+        self.stack.pop();
+        self.visited.insert(name.to_string());
+        if install {
+            self.order.push(package.clone());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::fs;
+    
+    fn package(name: &str, version: &str, dependencies: &[&str]) -> Package {
+        Package {
+            name: name.to_string(),
+            version: version.to_string(),
+            description: String::new(),
+            author: String::new(),
+            license: String::new(),
+            dependencies: dependencies.iter().map(|d| d.to_string()).collect(),
+            url: String::new(),
+            hash: String::new(),
+            signature: String::new(),
+            zk_contract: None,
+            size: 0,
+        }
+    }
+    
+    fn index(packages: Vec<Package>) -> PackageIndex {
+        PackageIndex {
+            last_updated: 0,
+            packages: packages.into_iter().map(|p| (p.name.clone(), p)).collect::<HashMap<_, _>>(),
+            advisories: Vec::new(),
+        }
+    }
+    
+    /// Mark a package as installed the way an install does
+    fn install(package: &Package) {
+        let package_dir = super::super::package_path(&package.name);
+        fs::create_dir_all(&package_dir).unwrap();
+        diff::record_installed(package, &package_dir).unwrap();
+    }
+    
+    fn names(order: &[Package]) -> Vec<&str> {
+        order.iter().map(|p| p.name.as_str()).collect()
+    }
+    
+    #[test]
+    fn specs_parse_names_and_constraints() {
+        assert_eq!(DependencySpec::parse("libfoo").unwrap(), DependencySpec { name: "libfoo".to_string(), constraint: None });
+        assert_eq!(DependencySpec::parse(" libfoo >= 1.2.0 ").unwrap(),
+                   DependencySpec { name: "libfoo".to_string(), constraint: Some(">= 1.2.0".to_string()) });
+        
+        let range = DependencySpec::parse("libfoo>=1.0, <2.0").unwrap();
+        assert!(range.accepts("1.4.2").unwrap());
+        assert!(!range.accepts("2.0.0").unwrap());
+        assert!(!range.accepts("0.9.0").unwrap());
+        
+        for bad in ["", ">=1.0", "../libfoo", ".hidden", "lib foo>=1.0"] {
+            assert!(DependencySpec::parse(bad).is_err(), "{:?} parsed", bad);
+        }
+    }
+    
+    #[test]
+    fn three_level_chain_installs_dependencies_first() {
+        let index = index(vec![
+            package("deps-chain-app", "1.0.0", &["deps-chain-lib>=1.2.0"]),
+            package("deps-chain-lib", "1.3.0", &["deps-chain-core"]),
+            package("deps-chain-core", "0.4.0", &[]),
+        ]);
+        
+        let order = install_order(&index, "deps-chain-app").unwrap();
+        assert_eq!(names(&order), ["deps-chain-core", "deps-chain-lib", "deps-chain-app"]);
+    }
+    
+    #[test]
+    fn shared_dependencies_are_installed_once() {
+        let index = index(vec![
+            package("deps-diamond-app", "1.0.0", &["deps-diamond-left", "deps-diamond-right"]),
+            package("deps-diamond-left", "1.0.0", &["deps-diamond-base"]),
+            package("deps-diamond-right", "1.0.0", &["deps-diamond-base>=1.0.0"]),
+            package("deps-diamond-base", "1.1.0", &[]),
+        ]);
+        
+        let order = install_order(&index, "deps-diamond-app").unwrap();
+        assert_eq!(names(&order), ["deps-diamond-base", "deps-diamond-left", "deps-diamond-right", "deps-diamond-app"]);
+    }
+    
+    #[test]
+    fn cycles_are_reported_with_the_chain() {
+        let index = index(vec![
+            package("deps-cycle-a", "1.0.0", &["deps-cycle-b"]),
+            package("deps-cycle-b", "1.0.0", &["deps-cycle-c>=1.0.0"]),
+            package("deps-cycle-c", "1.0.0", &["deps-cycle-a"]),
+        ]);
+        
+        let error = install_order(&index, "deps-cycle-a").unwrap_err().to_string();
+        assert!(error.contains("deps-cycle-a -> deps-cycle-b -> deps-cycle-c -> deps-cycle-a"), "{}", error);
+    }
+    
+    #[test]
+    fn constraints_the_index_cannot_meet_are_refused() {
+        let missing = index(vec![package("deps-unmet-app", "1.0.0", &["deps-unmet-gone"])]);
+        assert!(install_order(&missing, "deps-unmet-app").unwrap_err().to_string().contains("not in the index"));
+        
+        let too_old = index(vec![
+            package("deps-unmet-app", "1.0.0", &["deps-unmet-lib>=2.0.0"]),
+            package("deps-unmet-lib", "1.9.0", &[]),
+        ]);
+        let error = install_order(&too_old, "deps-unmet-app").unwrap_err().to_string();
+        assert!(error.contains("only has version 1.9.0"), "{}", error);
+    }
+    
+    #[test]
+    fn installed_dependencies_are_kept_unless_outside_the_constraint() {
+        install(&package("deps-installed-core", "1.0.0", &[]));
+        
+        let satisfied = index(vec![
+            package("deps-installed-app", "1.0.0", &["deps-installed-core>=1.0.0"]),
+            package("deps-installed-core", "2.1.0", &[]),
+        ]);
+        assert_eq!(names(&install_order(&satisfied, "deps-installed-app").unwrap()), ["deps-installed-app"]);
+        
+        let outdated = index(vec![
+            package("deps-installed-app", "1.0.0", &["deps-installed-core>=2.0.0"]),
+            package("deps-installed-core", "2.1.0", &[]),
+        ]);
+        let order = install_order(&outdated, "deps-installed-app").unwrap();
+        assert_eq!(names(&order), ["deps-installed-core", "deps-installed-app"]);
+        assert_eq!(order[0].version, "2.1.0");
+    }
+    
+    #[test]
+    fn installed_dependents_block_removal() {
+        let app = package("deps-removal-app", "1.0.0", &["deps-removal-lib>=1.0.0"]);
+        let lib = package("deps-removal-lib", "1.0.0", &[]);
+        let unrelated = package("deps-removal-other", "1.0.0", &[]);
+        install(&app);
+        install(&lib);
+        install(&unrelated);
+        let index = index(vec![app, lib, unrelated]);
+        
+        assert_eq!(installed_dependents(&index, "deps-removal-lib").unwrap(), ["deps-removal-app"]);
+        assert!(installed_dependents(&index, "deps-removal-app").unwrap().is_empty());
+    }
+}
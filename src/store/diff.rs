@@ -0,0 +1,576 @@
+// SentientOS ZK-Store Package Diff
+// File manifests of package versions and what changes between two of them
+
+use anyhow::{Result, Context};
+use tracing::{info, debug};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+
+use crate::core::constants;
+use super::{Package, STORE_DIR, REMOTE_INDEX_URL};
+
+// Constants
+const MANIFESTS_DIR: &str = "manifests";
+const INSTALLED_FILE: &str = "installed";
+const PERMISSIONS_FILE: &str = "permissions.zky";
+const FETCH_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A file in a package
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileEntry {
+    /// Size in bytes
+    pub size: u64,
+    
+    /// BLAKE3 hash (hex)
+    pub hash: String,
+}
+
+/// What a package needs from the host
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Requirements {
+    /// Capabilities, e.g. `network:outbound` or `host:api.example.com`
+    pub capabilities: BTreeSet<String>,
+    
+    /// Filesystem paths mounted into the container
+    pub mounts: BTreeSet<String>,
+}
+
+/// File manifest and metadata of one package version
+///
+/// Repositories serve manifests at `manifests/<name>/<version>.json` next to
+/// their index, so versions can be compared without downloading archives.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageManifest {
+    /// Package name
+    pub name: String,
+    
+    /// Package version
+    pub version: String,
+    
+    /// Files by path relative to the package root
+    pub files: BTreeMap<String, FileEntry>,
+    
+    /// Declared requirements
+    #[serde(default)]
+    pub requirements: Requirements,
+    
+    /// ZK contracts the package is bound to
+    #[serde(default)]
+    pub contracts: BTreeSet<String>,
+    
+    /// Package license
+    pub license: String,
+    
+    /// Package author
+    pub author: String,
+    
+    /// Installation size in bytes
+    pub size: u64,
+}
+
+/// A file whose content changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileModification {
+    /// Path relative to the package root
+    pub path: String,
+    
+    /// Entry in the old version
+    pub from: FileEntry,
+    
+    /// Entry in the new version
+    pub to: FileEntry,
+}
+
+/// Items added and removed between two versions
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SetChange {
+    /// Present only in the new version
+    pub added: Vec<String>,
+    
+    /// Present only in the old version
+    pub removed: Vec<String>,
+}
+
+impl SetChange {
+    fn between(from: &BTreeSet<String>, to: &BTreeSet<String>) -> Self {
+        Self {
+            added: to.difference(from).cloned().collect(),
+            removed: from.difference(to).cloned().collect(),
+        }
+    }
+    
+    /// Whether nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// A metadata field that changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetadataChange {
+    /// Field name
+    pub field: String,
+    
+    /// Old value
+    pub from: String,
+    
+    /// New value
+    pub to: String,
+}
+
+/// What changes between two versions of a package
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageDiff {
+    /// Package name
+    pub name: String,
+    
+    /// Old version
+    pub from_version: String,
+    
+    /// New version
+    pub to_version: String,
+    
+    /// Files only in the new version
+    pub added: BTreeMap<String, FileEntry>,
+    
+    /// Files only in the old version
+    pub removed: BTreeMap<String, FileEntry>,
+    
+    /// Files whose content changed
+    pub modified: Vec<FileModification>,
+    
+    /// Capability changes
+    pub capabilities: SetChange,
+    
+    /// Mount changes
+    pub mounts: SetChange,
+    
+    /// Contract binding changes
+    pub contracts: SetChange,
+    
+    /// License, author and size changes
+    pub metadata: Vec<MetadataChange>,
+}
+
+impl PackageDiff {
+    /// Whether the versions have identical content and metadata
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+            && self.capabilities.is_empty() && self.mounts.is_empty() && self.contracts.is_empty()
+            && self.metadata.is_empty()
+    }
+    
+    /// Human-readable summary
+    pub fn summary(&self) -> String {
+        let mut lines = vec![format!("{} {} -> {}", self.name, self.from_version, self.to_version)];
+        if self.is_empty() {
+            lines.push("  no changes".to_string());
+            return lines.join("\n") + "\n";
+        }
+        
+        lines.push(format!("  files: {} added, {} removed, {} modified",
+                           self.added.len(), self.removed.len(), self.modified.len()));
+        for (path, entry) in &self.added {
+            lines.push(format!("    + {} ({} bytes)", path, entry.size));
+        }
+        for (path, entry) in &self.removed {
+            lines.push(format!("    - {} ({} bytes)", path, entry.size));
+        }
+        for m in &self.modified {
+            lines.push(format!("    ~ {} ({} -> {} bytes)", m.path, m.from.size, m.to.size));
+        }
+        
+        for (label, change) in [("capabilities", &self.capabilities), ("mounts", &self.mounts), ("contracts", &self.contracts)] {
+            if !change.is_empty() {
+                lines.push(format!("  {}:", label));
+                lines.extend(change.added.iter().map(|c| format!("    + {}", c)));
+                lines.extend(change.removed.iter().map(|c| format!("    - {}", c)));
+            }
+        }
+        
+        for change in &self.metadata {
+            lines.push(format!("  {}: {} -> {}", change.field, change.from, change.to));
+        }
+        lines.join("\n") + "\n"
+    }
+}
+
+/// Compare the manifests of two versions of a package
+///
+/// Manifests come from the local cache, which holds the installed version's
+/// manifest, or else from the configured repositories; archives are never
+/// downloaded.
+pub fn diff_versions(name: &str, from_version: &str, to_version: &str) -> Result<PackageDiff> {
+    info!("Comparing package {} {} with {}", name, from_version, to_version);
+    
+    let from = load_manifest(name, from_version)?;
+    let to = load_manifest(name, to_version)?;
+    
+    let mut added = BTreeMap::new();
+    let mut modified = Vec::new();
+    for (path, entry) in &to.files {
+        match from.files.get(path) {
+            None => {
+                added.insert(path.clone(), entry.clone());
+            }
+            Some(old) if old.hash != entry.hash => modified.push(FileModification {
+                path: path.clone(),
+                from: old.clone(),
+                to: entry.clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    let removed = from.files.iter()
+        .filter(|(path, _)| !to.files.contains_key(*path))
+        .map(|(path, entry)| (path.clone(), entry.clone()))
+        .collect();
+    
+    let metadata = [
+        ("license", from.license.clone(), to.license.clone()),
+        ("author", from.author.clone(), to.author.clone()),
+        ("size", from.size.to_string(), to.size.to_string()),
+    ]
+    .into_iter()
+    .filter(|(_, old, new)| old != new)
+    .map(|(field, old, new)| MetadataChange { field: field.to_string(), from: old, to: new })
+    .collect();
+    
+    Ok(PackageDiff {
+        name: name.to_string(),
+        from_version: from_version.to_string(),
+        to_version: to_version.to_string(),
+        added,
+        removed,
+        modified,
+        capabilities: SetChange::between(&from.requirements.capabilities, &to.requirements.capabilities),
+        mounts: SetChange::between(&from.requirements.mounts, &to.requirements.mounts),
+        contracts: SetChange::between(&from.contracts, &to.contracts),
+        metadata,
+    })
+}
+
+/// Manifest of a package version
+pub fn load_manifest(name: &str, version: &str) -> Result<PackageManifest> {
+    let cached = manifest_path(name, version)?;
+    if cached.exists() {
+        let data = fs::read_to_string(&cached)?;
+        return serde_json::from_str(&data).with_context(|| format!("Invalid cached manifest {:?}", cached));
+    }
+    
+    let manifest = fetch_manifest(name, version)?;
+    cache_manifest(&manifest)?;
+    Ok(manifest)
+}
+
+/// Record the manifest of a freshly installed package
+pub fn record_installed(package: &Package, package_dir: &Path) -> Result<()> {
+    let manifest = build_manifest(package, package_dir)?;
+    cache_manifest(&manifest)?;
+    fs::write(manifests_dir(&package.name)?.join(INSTALLED_FILE), &package.version)?;
+    Ok(())
+}
+
+/// Installed version of a package, if recorded at install time
+pub fn installed_version(name: &str) -> Result<Option<String>> {
+    let path = manifests_dir(name)?.join(INSTALLED_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(fs::read_to_string(&path)?.trim().to_string()))
+}
+
+/// Files of an installed package that differ from its install-time manifest
+///
+/// Missing and modified files are reported; files added since, such as
+/// prefetched archives, are not. Returns `None` if no manifest was recorded.
+pub fn changed_files(name: &str) -> Result<Option<Vec<String>>> {
+    let version = match installed_version(name)? {
+        Some(version) => version,
+        None => return Ok(None),
+    };
+    if !manifest_path(name, &version)?.exists() {
+        return Ok(None);
+    }
+    let manifest = load_manifest(name, &version)?;
+    
+    let package_dir = super::package_path(name);
+    let mut current = BTreeMap::new();
+    if package_dir.exists() {
+        collect_files(&package_dir, &package_dir, &mut current)?;
+    }
+    
+    Ok(Some(manifest.files.iter()
+        .filter(|(path, entry)| current.get(*path) != Some(*entry))
+        .map(|(path, _)| path.clone())
+        .collect()))
+}
+
+/// Build the manifest of a package directory
+pub(super) fn build_manifest(package: &Package, package_dir: &Path) -> Result<PackageManifest> {
+    let mut files = BTreeMap::new();
+    collect_files(package_dir, package_dir, &mut files)?;
+    
+    let mut requirements = Requirements::default();
+    let permissions_path = package_dir.join(PERMISSIONS_FILE);
+    if permissions_path.exists() {
+        let content = fs::read_to_string(&permissions_path)?;
+        let permissions: crate::matrixbox::container::ContainerPermissions = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse {:?}", permissions_path))?;
+        
+        requirements.mounts = permissions.filesystem.into_iter().collect();
+        if permissions.network.outbound {
+            requirements.capabilities.insert("network:outbound".to_string());
+        }
+        if permissions.network.inbound {
+            requirements.capabilities.insert("network:inbound".to_string());
+        }
+        for host in permissions.network.allowed_hosts {
+            requirements.capabilities.insert(format!("host:{}", host));
+        }
+        requirements.capabilities.insert(format!("memory_limit:{}", permissions.memory_limit));
+        requirements.capabilities.insert(format!("cpu_limit:{}", permissions.cpu_limit));
+        requirements.capabilities.extend(permissions.capabilities);
+    }
+    
+    Ok(PackageManifest {
+        name: package.name.clone(),
+        version: package.version.clone(),
+        files,
+        requirements,
+        contracts: package.zk_contract.iter().cloned().collect(),
+        license: package.license.clone(),
+        author: package.author.clone(),
+        size: package.size,
+    })
+}
+
+/// Hash every file under a directory
+fn collect_files(root: &Path, dir: &Path, files: &mut BTreeMap<String, FileEntry>) -> Result<()> {
+    for entry in fs::read_dir(dir)?.filter_map(Result::ok) {
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(root, &path, files)?;
+        } else if path.is_file() {
+            let data = fs::read(&path)?;
+            let relative = path.strip_prefix(root)?.to_string_lossy().to_string();
+            files.insert(relative, FileEntry {
+                size: data.len() as u64,
+                hash: blake3::hash(&data).to_hex().to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Fetch a manifest from the first repository that has it
+fn fetch_manifest(name: &str, version: &str) -> Result<PackageManifest> {
+    let mut index_urls: Vec<String> = super::list_repositories()?.into_iter().map(|r| r.url).collect();
+    index_urls.push(REMOTE_INDEX_URL.to_string());
+    
+    let mut errors = Vec::new();
+    for index_url in index_urls {
+        let base = index_url.rsplit_once('/').map_or(index_url.as_str(), |(base, _)| base);
+        let url = format!("{}/{}/{}/{}.json", base, MANIFESTS_DIR, name, version);
+        debug!("Fetching manifest {}", url);
+        
+        match fetch(&url).and_then(|body| serde_json::from_slice::<PackageManifest>(&body).map_err(Into::into)) {
+            Ok(manifest) if manifest.name == name && manifest.version == version => return Ok(manifest),
+            Ok(_) => errors.push(format!("{}: manifest is for a different package or version", url)),
+            Err(e) => errors.push(format!("{}: {}", url, e)),
+        }
+    }
+    
+    anyhow::bail!("Manifest of {} {} is not available locally or from any repository:\n  {}",
+                  name, version, errors.join("\n  "))
+}
+
+/// Fetch a URL; http:// and local paths (file:// or absolute) are supported
+fn fetch(url: &str) -> Result<Vec<u8>> {
+    if let Some(path) = url.strip_prefix("file://").or_else(|| url.starts_with('/').then_some(url)) {
+        return fs::read(path).with_context(|| format!("Failed to read {}", path));
+    }
+    
+    let rest = url.strip_prefix("http://")
+        .ok_or_else(|| anyhow::anyhow!("Only http://, file:// and local repositories are supported: {}", url))?;
+    let (host, path) = match rest.find('/') {
+        Some(pos) => (&rest[..pos], &rest[pos..]),
+        None => (rest, "/"),
+    };
+    let address = if host.contains(':') { host.to_string() } else { format!("{}:80", host) };
+    let addr = std::net::ToSocketAddrs::to_socket_addrs(&address)?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("Could not resolve repository address: {}", address))?;
+    
+    let mut stream = TcpStream::connect_timeout(&addr, FETCH_TIMEOUT)?;
+    stream.set_read_timeout(Some(FETCH_TIMEOUT))?;
+    stream.write_all(format!("GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", path, host).as_bytes())?;
+    
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response)?;
+    
+    let header_end = response.windows(4).position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| anyhow::anyhow!("Malformed response"))?;
+    let head = String::from_utf8_lossy(&response[..header_end]);
+    let status = head.split_whitespace().nth(1).unwrap_or("");
+    if status != "200" {
+        anyhow::bail!("{}", head.lines().next().unwrap_or("no response"));
+    }
+    Ok(response[header_end + 4..].to_vec())
+}
+
+/// Write a manifest to the local cache
+fn cache_manifest(manifest: &PackageManifest) -> Result<()> {
+    let path = manifest_path(&manifest.name, &manifest.version)?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(manifest)?)?;
+    Ok(())
+}
+
+/// Cached manifest of a package version
+fn manifest_path(name: &str, version: &str) -> Result<PathBuf> {
+    check_component(version, "version")?;
+    Ok(manifests_dir(name)?.join(format!("{}.json", version)))
+}
+
+/// Manifest cache directory of a package
+fn manifests_dir(name: &str) -> Result<PathBuf> {
+    check_component(name, "package name")?;
+    Ok(constants::root_dir().join(STORE_DIR).join(MANIFESTS_DIR).join(name))
+}
+
+/// Names and versions become path components; refuse anything that escapes
+pub(super) fn check_component(value: &str, what: &str) -> Result<()> {
+    if value.is_empty() || value.contains(['/', '\\']) || value.starts_with('.') {
+        anyhow::bail!("Invalid {}: {}", what, value);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn package(name: &str, version: &str) -> Package {
+        Package {
+            name: name.to_string(),
+            version: version.to_string(),
+            description: String::new(),
+            author: "alice".to_string(),
+            license: "MIT".to_string(),
+            dependencies: Vec::new(),
+            url: String::new(),
+            hash: String::new(),
+            signature: String::new(),
+            zk_contract: Some("storage".to_string()),
+            size: 100,
+        }
+    }
+    
+    /// Write a package directory with the given files
+    fn package_dir(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = super::super::package_path(name);
+        let _ = fs::remove_dir_all(&dir);
+        for (path, content) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
+    
+    #[test]
+    fn versions_differ_in_files_requirements_and_metadata() {
+        let name = "diff-versions";
+        let dir = package_dir(name, &[("main.wasm", "v1"), ("lib/util.wasm", "util"), ("README", "old")]);
+        fs::write(dir.join(PERMISSIONS_FILE), "filesystem: [/data]\nnetwork: { outbound: false, inbound: false, allowed_hosts: [] }\nmemory_limit: 1024\ncpu_limit: 50\n").unwrap();
+        cache_manifest(&build_manifest(&package(name, "1.0.0"), &dir).unwrap()).unwrap();
+        
+        let dir = package_dir(name, &[("main.wasm", "v2"), ("lib/util.wasm", "util"), ("CHANGELOG", "new")]);
+        fs::write(dir.join(PERMISSIONS_FILE), "filesystem: [/data]\nnetwork: { outbound: true, inbound: false, allowed_hosts: [api.example.com] }\nmemory_limit: 1024\ncpu_limit: 50\n").unwrap();
+        let mut newer = package(name, "1.1.0");
+        newer.license = "Apache-2.0".to_string();
+        newer.zk_contract = None;
+        cache_manifest(&build_manifest(&newer, &dir).unwrap()).unwrap();
+        
+        let diff = diff_versions(name, "1.0.0", "1.1.0").unwrap();
+        assert_eq!(diff.added.keys().collect::<Vec<_>>(), ["CHANGELOG"]);
+        assert_eq!(diff.removed.keys().collect::<Vec<_>>(), ["README"]);
+        let modified: Vec<&str> = diff.modified.iter().map(|m| m.path.as_str()).collect();
+        assert_eq!(modified, ["main.wasm", PERMISSIONS_FILE]);
+        assert_eq!(diff.capabilities.added, ["host:api.example.com", "network:outbound"]);
+        assert!(diff.capabilities.removed.is_empty() && diff.mounts.is_empty());
+        assert_eq!(diff.contracts.removed, ["storage"]);
+        let fields: Vec<&str> = diff.metadata.iter().map(|m| m.field.as_str()).collect();
+        assert_eq!(fields, ["license"]);
+        
+        let summary = diff.summary();
+        assert!(summary.starts_with("diff-versions 1.0.0 -> 1.1.0\n  files: 1 added, 1 removed, 2 modified\n"));
+        assert!(summary.contains("    + network:outbound\n") && summary.contains("  license: MIT -> Apache-2.0\n"));
+        
+        let same = diff_versions(name, "1.1.0", "1.1.0").unwrap();
+        assert!(same.is_empty());
+        assert!(same.summary().ends_with("  no changes\n"));
+    }
+    
+    #[test]
+    fn installed_packages_report_files_changed_since_install() {
+        let name = "diff-installed";
+        assert_eq!(changed_files(name).unwrap(), None);
+        
+        let dir = package_dir(name, &[("main.wasm", "code"), ("data/seed.json", "{}")]);
+        record_installed(&package(name, "2.0.0"), &dir).unwrap();
+        assert_eq!(installed_version(name).unwrap().as_deref(), Some("2.0.0"));
+        assert_eq!(changed_files(name).unwrap(), Some(Vec::new()));
+        
+        fs::write(dir.join("main.wasm"), "patched").unwrap();
+        fs::remove_file(dir.join("data/seed.json")).unwrap();
+        fs::write(dir.join("prefetched.tar"), "archive").unwrap();
+        assert_eq!(changed_files(name).unwrap(), Some(vec!["data/seed.json".to_string(), "main.wasm".to_string()]));
+    }
+    
+    #[test]
+    fn manifests_are_fetched_over_http_and_from_local_paths() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/manifests/app/1.0.0.json", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            for body in [Some("{\"ok\":true}"), None] {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0u8; 1024];
+                let _ = stream.read(&mut request).unwrap();
+                let response = match body {
+                    Some(body) => format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body),
+                    None => "HTTP/1.1 404 Not Found\r\n\r\n".to_string(),
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        
+        assert_eq!(fetch(&url).unwrap(), b"{\"ok\":true}");
+        assert_eq!(fetch(&url).unwrap_err().to_string(), "HTTP/1.1 404 Not Found");
+        server.join().unwrap();
+        
+        let local = std::env::temp_dir().join(format!("sentient-diff-fetch-{}.json", std::process::id()));
+        fs::write(&local, "{}").unwrap();
+        assert_eq!(fetch(&format!("file://{}", local.display())).unwrap(), b"{}");
+        assert_eq!(fetch(&local.to_string_lossy()).unwrap(), b"{}");
+        fs::remove_file(&local).unwrap();
+        assert!(fetch("https://store.example.com/index.json").is_err());
+    }
+    
+    #[test]
+    fn names_and_versions_cannot_escape_the_cache() {
+        for bad in ["", "../etc", ".hidden", "a/b", "a\\b"] {
+            assert!(check_component(bad, "version").is_err(), "{:?} was accepted", bad);
+        }
+        assert!(check_component("1.0.0-rc.1", "version").is_ok());
+        assert!(load_manifest("app", "../../secrets").is_err());
+    }
+}
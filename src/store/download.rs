@@ -0,0 +1,357 @@
+// SentientOS ZK-Store Package Download
+// Resumable HTTP(S) download of package archives, verified against their index hash
+
+use anyhow::{Result, Context};
+use tracing::{info, debug, warn};
+use std::path::{Path, PathBuf};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::time::Duration;
+
+use super::Package;
+
+// Constants
+const PART_EXTENSION: &str = "part";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// How far a download has come, reported after every chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DownloadProgress {
+    /// Bytes on disk, including any resumed from an earlier attempt
+    pub downloaded: u64,
+    
+    /// Size of the whole archive, if the server reported it
+    pub total: Option<u64>,
+}
+
+/// Download a package's archive into `dir`, returning the archive's path
+///
+/// The archive is streamed to a `.part` file next to its final path. If a
+/// `.part` file is left from an earlier attempt, only the missing bytes are
+/// requested. A network failure leaves the `.part` file so the next attempt
+/// resumes; an archive whose BLAKE3 hash does not match `package.hash` is
+/// deleted.
+pub fn download_package(
+    package: &Package,
+    dir: &Path,
+    mut progress: impl FnMut(DownloadProgress),
+) -> Result<PathBuf> {
+    if !package.url.starts_with("https://") && !package.url.starts_with("http://") {
+        anyhow::bail!("Package {} has an unsupported URL: {}", package.name, package.url);
+    }
+    fs::create_dir_all(dir)?;
+    
+    let path = dir.join(archive_name(package));
+    let part_path = path.with_extension(match path.extension() {
+        Some(ext) => format!("{}.{}", ext.to_string_lossy(), PART_EXTENSION),
+        None => PART_EXTENSION.to_string(),
+    });
+    
+    // A finished archive from an earlier install is reused if it is intact
+    if path.exists() {
+        if hash_file(&path)? == package.hash.to_lowercase() {
+            debug!("Using downloaded archive {:?}", path);
+            return Ok(path);
+        }
+        warn!("Discarding archive {:?}: its hash does not match the index", path);
+        fs::remove_file(&path)?;
+    }
+    
+    let hash = fetch(&package.url, &part_path, &mut progress)
+        .with_context(|| format!("Failed to download {} from {}; run the install again to resume",
+                                 package.name, package.url))?;
+    
+    if hash != package.hash.to_lowercase() {
+        fs::remove_file(&part_path)?;
+        anyhow::bail!("Download of {} {} is corrupted: expected hash {}, got {}; the partial file was deleted",
+                      package.name, package.version, package.hash, hash);
+    }
+    
+    fs::rename(&part_path, &path)
+        .with_context(|| format!("Failed to move {:?} into place", part_path))?;
+    info!("Downloaded {} {} to {:?}", package.name, package.version, path);
+    Ok(path)
+}
+
+/// Path of a package's archive in `dir`
+pub fn archive_path(package: &Package, dir: &Path) -> PathBuf {
+    dir.join(archive_name(package))
+}
+
+/// Read a package's archive from `dir`, if it is there and intact
+pub fn read_archive(package: &Package, dir: &Path) -> Result<Option<Vec<u8>>> {
+    let path = archive_path(package, dir);
+    if !path.exists() {
+        return Ok(None);
+    }
+    
+    let content = fs::read(&path).with_context(|| format!("Failed to read archive {:?}", path))?;
+    if blake3::hash(&content).to_hex().to_string() != package.hash.to_lowercase() {
+        debug!("Archive {:?} does not match the index hash", path);
+        return Ok(None);
+    }
+    Ok(Some(content))
+}
+
+/// Store an archive obtained elsewhere, e.g. from a peer, in `dir`
+///
+/// The archive is refused unless its BLAKE3 hash matches `package.hash`, so
+/// it is as trustworthy as the index whoever served it.
+pub fn store_archive(package: &Package, dir: &Path, content: &[u8]) -> Result<PathBuf> {
+    let hash = blake3::hash(content).to_hex().to_string();
+    if hash != package.hash.to_lowercase() {
+        anyhow::bail!("Archive of {} {} is corrupted: expected hash {}, got {}",
+                      package.name, package.version, package.hash, hash);
+    }
+    
+    fs::create_dir_all(dir)?;
+    let path = archive_path(package, dir);
+    crate::core::fs::write_atomic(&path, content)?;
+    debug!("Stored archive of {} {} at {:?}", package.name, package.version, path);
+    Ok(path)
+}
+
+/// Stream a URL into `part_path`, resuming from its current length
+///
+/// Returns the BLAKE3 hash (hex) of the complete file.
+fn fetch(url: &str, part_path: &Path, progress: &mut impl FnMut(DownloadProgress)) -> Result<String> {
+    // Bytes already on disk are hashed first, so the hash covers the whole file
+    let mut hasher = blake3::Hasher::new();
+    let mut offset = 0;
+    if part_path.exists() {
+        let mut part = File::open(part_path)?;
+        offset = std::io::copy(&mut part, &mut hasher)?;
+    }
+    
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(CONNECT_TIMEOUT)
+        .timeout_read(READ_TIMEOUT)
+        .build();
+    let mut request = agent.get(url);
+    if offset > 0 {
+        debug!("Resuming download of {} at byte {}", url, offset);
+        request = request.set("Range", &format!("bytes={}-", offset));
+    }
+    
+    let response = match request.call() {
+        Ok(response) => response,
+        // The partial file already holds everything the server has
+        Err(ureq::Error::Status(416, _)) if offset > 0 => {
+            progress(DownloadProgress { downloaded: offset, total: Some(offset) });
+            return Ok(hasher.finalize().to_hex().to_string());
+        }
+        Err(ureq::Error::Status(code, response)) => {
+            anyhow::bail!("Server answered {} {}", code, response.status_text());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    
+    // A server ignoring the range sends the whole file; start over
+    let resumed = offset > 0 && response.status() == 206;
+    if offset > 0 && !resumed {
+        debug!("Server does not support resuming {}; downloading from the start", url);
+        offset = 0;
+        hasher = blake3::Hasher::new();
+    }
+    
+    let length: Option<u64> = response.header("Content-Length").and_then(|v| v.parse().ok());
+    let total = match response.header("Content-Range").and_then(|v| v.rsplit_once('/')) {
+        Some((_, total)) if resumed => total.parse().ok(),
+        _ => length.map(|length| length + offset),
+    };
+    
+    let mut part = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(part_path)
+        .with_context(|| format!("Failed to open {:?}", part_path))?;
+    
+    let mut reader = response.into_reader();
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut downloaded = offset;
+    progress(DownloadProgress { downloaded, total });
+    
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        part.write_all(&buffer[..read])?;
+        hasher.update(&buffer[..read]);
+        downloaded += read as u64;
+        progress(DownloadProgress { downloaded, total });
+    }
+    part.sync_all()?;
+    
+    if let Some(total) = total {
+        if downloaded < total {
+            anyhow::bail!("Connection closed after {} of {} bytes", downloaded, total);
+        }
+    }
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// File name of a package's archive: the last segment of its URL
+fn archive_name(package: &Package) -> String {
+    let url = package.url.split(['?', '#']).next().unwrap_or("");
+    let path = url.split_once("://")
+        .and_then(|(_, rest)| rest.split_once('/'))
+        .map_or("", |(_, path)| path);
+    match path.rsplit('/').next() {
+        Some(name) if !name.is_empty() && !name.starts_with('.') => name.to_string(),
+        _ => format!("{}-{}.pkg", package.name, package.version),
+    }
+}
+
+/// BLAKE3 hash (hex) of a file
+fn hash_file(path: &Path) -> Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+    
+    fn package(url: &str, content: &[u8]) -> Package {
+        Package {
+            name: "demo".to_string(),
+            version: "1.0".to_string(),
+            description: String::new(),
+            author: String::new(),
+            license: String::new(),
+            dependencies: Vec::new(),
+            url: url.to_string(),
+            hash: blake3::hash(content).to_hex().to_string(),
+            signature: String::new(),
+            zk_contract: None,
+            size: content.len() as u64,
+        }
+    }
+    
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sentient-download-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+    
+    /// Serve one request for `body`, answering ranges only if `ranges` is set;
+    /// the thread returns the request's Range header
+    fn serve(body: Vec<u8>, ranges: bool) -> (String, thread::JoinHandle<Option<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut range = None;
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.split_once(':') {
+                    if name.eq_ignore_ascii_case("range") {
+                        range = Some(value.trim().to_string());
+                    }
+                }
+            }
+            
+            let start = range.as_deref()
+                .filter(|_| ranges)
+                .and_then(|range| range.strip_prefix("bytes=")?.strip_suffix('-')?.parse::<usize>().ok());
+            let head = match start {
+                Some(start) => format!("HTTP/1.1 206 Partial Content\r\nContent-Length: {}\r\nContent-Range: bytes {}-{}/{}\r\n",
+                                       body.len() - start, start, body.len() - 1, body.len()),
+                None => format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n", body.len()),
+            };
+            stream.write_all(format!("{}Connection: close\r\n\r\n", head).as_bytes()).unwrap();
+            stream.write_all(&body[start.unwrap_or(0)..]).unwrap();
+            range
+        });
+        (base, server)
+    }
+    
+    fn body() -> Vec<u8> {
+        (0..200_000u32).map(|i| (i % 251) as u8).collect()
+    }
+    
+    #[test]
+    fn partial_downloads_resume_where_they_stopped() {
+        let dir = scratch_dir("resume");
+        let body = body();
+        let (base, server) = serve(body.clone(), true);
+        let package = package(&format!("{}/pkgs/demo-1.0.tar.gz?token=1", base), &body);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("demo-1.0.tar.gz.part"), &body[..1000]).unwrap();
+        
+        let mut reports = Vec::new();
+        let path = download_package(&package, &dir, |progress| reports.push(progress)).unwrap();
+        assert_eq!(server.join().unwrap().as_deref(), Some("bytes=1000-"));
+        assert_eq!(path, dir.join("demo-1.0.tar.gz"));
+        assert_eq!(fs::read(&path).unwrap(), body);
+        assert!(!dir.join("demo-1.0.tar.gz.part").exists());
+        
+        let total = Some(body.len() as u64);
+        assert_eq!(reports.first(), Some(&DownloadProgress { downloaded: 1000, total }));
+        assert_eq!(reports.last(), Some(&DownloadProgress { downloaded: body.len() as u64, total }));
+        
+        // The finished archive is reused without asking the server again
+        assert_eq!(download_package(&package, &dir, |_| {}).unwrap(), path);
+    }
+    
+    #[test]
+    fn servers_without_ranges_send_the_whole_archive_again() {
+        let dir = scratch_dir("no-ranges");
+        let body = body();
+        let (base, server) = serve(body.clone(), false);
+        let package = package(&format!("{}/demo.pkg", base), &body);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("demo.pkg.part"), b"stale bytes").unwrap();
+        
+        let path = download_package(&package, &dir, |_| {}).unwrap();
+        assert_eq!(server.join().unwrap().as_deref(), Some("bytes=11-"));
+        assert_eq!(fs::read(path).unwrap(), body);
+    }
+    
+    #[test]
+    fn unsupported_and_corrupted_downloads_are_refused() {
+        let dir = scratch_dir("corrupt");
+        let error = download_package(&package("ftp://mirror/demo.pkg", b""), &dir, |_| {}).unwrap_err();
+        assert!(error.to_string().contains("unsupported URL"), "{}", error);
+        
+        let (base, server) = serve(b"tampered".to_vec(), true);
+        let package = package(&format!("{}/demo.pkg", base), b"original");
+        
+        let error = download_package(&package, &dir, |_| {}).unwrap_err();
+        server.join().unwrap();
+        assert!(error.to_string().contains("is corrupted"), "{}", error);
+        assert!(!dir.join("demo.pkg").exists() && !dir.join("demo.pkg.part").exists());
+    }
+    
+    #[test]
+    fn archives_are_named_after_their_url_and_checked_against_the_index() {
+        assert_eq!(archive_name(&package("https://host/a/b/pkg.tgz?x=1#top", b"")), "pkg.tgz");
+        assert_eq!(archive_name(&package("https://host/", b"")), "demo-1.0.pkg");
+        assert_eq!(archive_name(&package("https://host/dir/.hidden", b"")), "demo-1.0.pkg");
+        assert_eq!(archive_name(&package("https://host", b"")), "demo-1.0.pkg");
+        
+        let dir = scratch_dir("archives");
+        let package = package("https://host/demo.pkg", b"archive");
+        assert!(store_archive(&package, &dir, b"other").is_err());
+        assert_eq!(read_archive(&package, &dir).unwrap(), None);
+        
+        store_archive(&package, &dir, b"archive").unwrap();
+        assert_eq!(read_archive(&package, &dir).unwrap().as_deref(), Some(&b"archive"[..]));
+        fs::write(archive_path(&package, &dir), b"changed").unwrap();
+        assert_eq!(read_archive(&package, &dir).unwrap(), None);
+    }
+}
@@ -7,17 +7,37 @@ use std::path::{Path, PathBuf};
 use std::fs::{self, File};
 use std::io::{Read, Write};
 use std::collections::HashMap;
+use std::time::Duration;
 use serde::{Serialize, Deserialize};
 
 use crate::core::constants;
+use crate::core::lock;
+use crate::core::plan::{Plan, PlannedAction};
+use crate::core::workers::CancelToken;
 use crate::zk;
 use crate::matrixbox;
 
+pub mod advisory;
+pub mod deps;
+pub mod diff;
+pub mod download;
+pub mod repo;
+
+pub use diff::{diff_versions, PackageDiff};
+pub use download::DownloadProgress;
+
 // Constants
 const STORE_DIR: &str = ".store";
 const PACKAGES_DIR: &str = "packages";
 const INDEX_FILE: &str = "index.json";
+const PREVIOUS_INDEX_FILE: &str = "index.previous.json";
+const SIGNATURE_EXTENSION: &str = "sig";
 const REMOTE_INDEX_URL: &str = "https://store.sentientos.org/index.json";
+const PUBLISHER_KEY_FILE: &str = "keys/store_publisher.pub";
+const INDEX_FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+const INDEX_LOCK: &str = "store-index";
+const TRUSTED_KEYS_FILE: &str = "trusted_keys.json";
+const REPOSITORIES_FILE: &str = "repositories.json";
 
 /// Package metadata
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -56,6 +76,19 @@ pub struct Package {
     pub size: u64,
 }
 
+/// Package repository the index is fetched from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Repository {
+    /// Repository name
+    pub name: String,
+    
+    /// Index URL
+    pub url: String,
+    
+    /// Public key (hex) the repository signs packages with
+    pub key: Option<String>,
+}
+
 /// Package index
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PackageIndex {
@@ -64,6 +97,23 @@ pub struct PackageIndex {
     
     /// Packages in index
     pub packages: HashMap<String, Package>,
+    
+    /// Security advisories for packages in the index
+    #[serde(default)]
+    pub advisories: Vec<advisory::Advisory>,
+}
+
+/// Outcome of an index update
+#[derive(Debug, Clone)]
+pub struct IndexUpdate {
+    /// When the publisher generated the index
+    pub last_updated: u64,
+    
+    /// Number of packages in the index
+    pub packages: usize,
+    
+    /// Publisher key (hex) the index signature was verified with
+    pub publisher_key: String,
 }
 
 /// Initialize the store module
@@ -71,7 +121,7 @@ pub fn init() -> Result<()> {
     info!("Initializing ZK-Store package manager");
     
     // Create store directories
-    let store_dir = PathBuf::from(constants::ROOT_DIR).join(STORE_DIR);
+    let store_dir = constants::root_dir().join(STORE_DIR);
     let packages_dir = store_dir.join(PACKAGES_DIR);
     
     fs::create_dir_all(&store_dir)?;
@@ -86,10 +136,11 @@ pub fn init() -> Result<()> {
                 .unwrap_or_default()
                 .as_secs(),
             packages: HashMap::new(),
+            advisories: Vec::new(),
         };
         
         let index_json = serde_json::to_string_pretty(&empty_index)?;
-        fs::write(&index_path, index_json)?;
+        crate::core::fs::write_atomic(&index_path, index_json.as_bytes())?;
     }
     
     info!("ZK-Store package manager initialized successfully");
@@ -107,41 +158,117 @@ pub fn shutdown() -> Result<()> {
 }
 
 /// Update package index from remote source
-pub fn update_index() -> Result<()> {
-    info!("Updating package index from remote source");
+///
+/// The index and its detached ed25519 signature (`index.json.sig`, hex) are
+/// fetched from the store and the signature is checked against the pinned
+/// publisher key in `.auth/keys/store_publisher.pub`. An unsigned or
+/// badly-signed index is refused and the local one kept. The index it
+/// replaces is kept as `index.previous.json` for `rollback_index`.
+pub fn update_index() -> Result<IndexUpdate> {
+    update_index_from(REMOTE_INDEX_URL)
+}
+
+/// Update the package index from `url`, its signature being next to it
+fn update_index_from(url: &str) -> Result<IndexUpdate> {
+    info!("Updating package index from {}", url);
     
-    // In a real implementation, this would make an HTTP request
-    // to the remote index URL and update the local index
+    let publisher_key = publisher_key()?;
+    let index_bytes = fetch_index(url)?;
+    let signature_url = format!("{}.{}", url, SIGNATURE_EXTENSION);
+    let signature = fetch_index(&signature_url)
+        .context("Refusing the package index: its signature could not be fetched")?;
+    let signature = String::from_utf8_lossy(&signature).trim().to_string();
     
-    // For now, we'll just update the timestamp
-    let store_dir = PathBuf::from(constants::ROOT_DIR).join(STORE_DIR);
-    let index_path = store_dir.join(INDEX_FILE);
+    crate::core::identity::verify(&publisher_key, &index_bytes, &signature)
+        .context("Refusing the package index: its signature does not match the store publisher key")?;
+    let index: PackageIndex = serde_json::from_slice(&index_bytes)
+        .context("Refusing the package index: it is signed but not a valid index")?;
     
-    let mut index: PackageIndex = if index_path.exists() {
-        let index_data = fs::read_to_string(&index_path)?;
-        serde_json::from_str(&index_data)?
-    } else {
-        PackageIndex {
-            last_updated: 0,
-            packages: HashMap::new(),
+    let _lock = lock::lock(INDEX_LOCK, "update index", lock::DEFAULT_TIMEOUT)?;
+    
+    let store_dir = constants::root_dir().join(STORE_DIR);
+    let index_path = store_dir.join(INDEX_FILE);
+    let previous_path = store_dir.join(PREVIOUS_INDEX_FILE);
+    if index_path.exists() {
+        crate::core::fs::write_atomic(&previous_path, &fs::read(&index_path)?)?;
+        match fs::read(signature_path(&index_path)) {
+            Ok(previous_signature) => crate::core::fs::write_atomic(&signature_path(&previous_path), &previous_signature)?,
+            Err(_) => remove_if_exists(&signature_path(&previous_path))?,
         }
-    };
+    }
     
-    index.last_updated = std::time::SystemTime::now()
-        .duration_since(std::time::UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_secs();
+    crate::core::fs::write_atomic(&index_path, &index_bytes)?;
+    crate::core::fs::write_atomic(&signature_path(&index_path), signature.as_bytes())?;
+    drop(_lock);
     
-    let index_json = serde_json::to_string_pretty(&index)?;
-    fs::write(&index_path, index_json)?;
+    // New advisories may already be covered by installed versions
+    advisory::refresh_remediation()?;
     
-    info!("Package index updated successfully");
-    Ok(())
+    // Upgrades then find their archives downloaded; processes without a
+    // worker pool download them at install time instead
+    if let Err(e) = crate::core::workers::submit("prefetch", "prefetch package updates", prefetch_updates) {
+        debug!("Not prefetching package updates: {}", e);
+    }
+    
+    info!("Package index updated: {} package(s), verified with publisher key {}", index.packages.len(), publisher_key);
+    Ok(IndexUpdate {
+        last_updated: index.last_updated,
+        packages: index.packages.len(),
+        publisher_key,
+    })
+}
+
+/// Put back the index that the last update replaced
+///
+/// The current index becomes the previous one, so a second rollback undoes
+/// the first. Returns the restored index.
+pub fn rollback_index() -> Result<PackageIndex> {
+    let _lock = lock::lock(INDEX_LOCK, "roll back index", lock::DEFAULT_TIMEOUT)?;
+    
+    let store_dir = constants::root_dir().join(STORE_DIR);
+    let index_path = store_dir.join(INDEX_FILE);
+    let previous_path = store_dir.join(PREVIOUS_INDEX_FILE);
+    if !previous_path.exists() {
+        anyhow::bail!("No previous package index to roll back to");
+    }
+    
+    let previous = fs::read(&previous_path)?;
+    let restored: PackageIndex = serde_json::from_slice(&previous)
+        .with_context(|| format!("Previous package index {:?} is invalid", previous_path))?;
+    let previous_signature = fs::read(signature_path(&previous_path)).ok();
+    
+    let current = fs::read(&index_path).ok();
+    let current_signature = fs::read(signature_path(&index_path)).ok();
+    
+    crate::core::fs::write_atomic(&index_path, &previous)?;
+    match previous_signature {
+        Some(signature) => crate::core::fs::write_atomic(&signature_path(&index_path), &signature)?,
+        None => remove_if_exists(&signature_path(&index_path))?,
+    }
+    if let Some(current) = current {
+        crate::core::fs::write_atomic(&previous_path, &current)?;
+        match current_signature {
+            Some(signature) => crate::core::fs::write_atomic(&signature_path(&previous_path), &signature)?,
+            None => remove_if_exists(&signature_path(&previous_path))?,
+        }
+    }
+    drop(_lock);
+    
+    advisory::refresh_remediation()?;
+    
+    warn!("Package index rolled back to the one from {}", restored.last_updated);
+    Ok(restored)
+}
+
+/// Load the local package index
+pub fn load_index() -> Result<PackageIndex> {
+    let index_path = constants::root_dir().join(STORE_DIR).join(INDEX_FILE);
+    crate::core::fs::read_json_or_backup(&index_path).context("Failed to read package index")
 }
 
 /// Search for packages in the index
 pub fn search_packages(query: &str) -> Result<Vec<Package>> {
-    let store_dir = PathBuf::from(constants::ROOT_DIR).join(STORE_DIR);
+    let store_dir = constants::root_dir().join(STORE_DIR);
     let index_path = store_dir.join(INDEX_FILE);
     
     if !index_path.exists() {
@@ -166,29 +293,107 @@ pub fn search_packages(query: &str) -> Result<Vec<Package>> {
 
 /// Install package with zero-knowledge verification
 pub fn install_package(package_name: &str) -> Result<()> {
+    install_package_with_progress(package_name, |_| {})
+}
+
+/// Install package, reporting download progress to `progress`
+///
+/// Missing dependencies, and installed ones outside a dependent's version
+/// constraint, are installed first.
+pub fn install_package_with_progress(package_name: &str, mut progress: impl FnMut(DownloadProgress)) -> Result<()> {
     info!("Installing package: {}", package_name);
     
-    // 1. Find package in index
-    let store_dir = PathBuf::from(constants::ROOT_DIR).join(STORE_DIR);
-    let index_path = store_dir.join(INDEX_FILE);
-    let packages_dir = store_dir.join(PACKAGES_DIR);
+    // 1. Find package and the dependencies it still needs in index
+    let index = load_index()?;
+    let order = deps::install_order(&index, package_name)?;
     
-    let index_data = fs::read_to_string(&index_path)?;
-    let index: PackageIndex = serde_json::from_str(&index_data)?;
+    for package in &order {
+        if package.name != package_name {
+            info!("Installing dependency {} v{} of {}", package.name, package.version, package_name);
+        }
+        install_resolved(package, &mut progress)
+            .with_context(|| format!("Failed to install {}", package.name))?;
+    }
     
+    info!("Package {} installed successfully", package_name);
+    Ok(())
+}
+
+/// Install a package with archives served by peers instead of the store
+///
+/// Archives of the package, and of the dependencies it still needs, that
+/// are not already on this node are fetched from the best peer, failing
+/// over down the ranking; `peer_id` overrides the selection. Every archive
+/// must match the hash in the local index. Returns the peer that served
+/// the package's own archive, if it had to be fetched.
+pub fn install_from_peer(package_name: &str, peer_id: Option<&str>) -> Result<Option<String>> {
+    info!("Installing package from peers: {}", package_name);
+    
+    let index = load_index()?;
+    let order = deps::install_order(&index, package_name)?;
+    
+    let mut served_by = None;
+    for package in &order {
+        let package_dir = package_path(&package.name);
+        let peer = match download::read_archive(package, &package_dir)? {
+            Some(_) => {
+                debug!("Archive of {} {} is already on this node", package.name, package.version);
+                None
+            }
+            None => Some(fetch_from_peer(package, &package_dir, peer_id)?),
+        };
+        if package.name == package_name {
+            served_by = peer;
+        }
+        
+        install_resolved(package, |_| {})
+            .with_context(|| format!("Failed to install {}", package.name))?;
+    }
+    
+    info!("Package {} installed successfully", package_name);
+    Ok(served_by)
+}
+
+/// Archive of a package version this node holds, for serving to peers
+///
+/// Only the version in the local index is served, and only while its
+/// archive still matches the index hash.
+pub fn local_archive(package_name: &str, version: &str) -> Result<Vec<u8>> {
+    let index = load_index()?;
     let package = index.packages.get(package_name)
-        .ok_or_else(|| anyhow::anyhow!("Package not found: {}", package_name))?;
+        .filter(|p| p.version == version)
+        .ok_or_else(|| anyhow::anyhow!("{} {} is not in the local index", package_name, version))?;
+    
+    download::read_archive(package, &package_path(package_name))?
+        .ok_or_else(|| anyhow::anyhow!("No intact archive of {} {} on this node", package_name, version))
+}
+
+/// Fetch a package's archive from peers into its directory
+///
+/// A peer serving a corrupted archive counts as a failure, so the next
+/// candidate is tried. Returns the ID of the peer that served it.
+fn fetch_from_peer(package: &Package, package_dir: &Path, peer_id: Option<&str>) -> Result<String> {
+    let what = format!("archive of {} {}", package.name, package.version);
+    let candidates = crate::gossip::select::candidates(None)?;
+    crate::gossip::select::with_failover(&what, candidates, peer_id, |peer| {
+        let content = crate::gossip::protocol::get_package_archive(&peer.id, &peer.endpoint,
+                                                                  &package.name, &package.version)?;
+        download::store_archive(package, package_dir, &content)?;
+        Ok((peer.id.clone(), content.len() as u64))
+    })
+}
+
+/// Install a single package whose dependencies are in place
+fn install_resolved(package: &Package, progress: impl FnMut(DownloadProgress)) -> Result<()> {
+    let packages_dir = constants::root_dir().join(STORE_DIR).join(PACKAGES_DIR);
     
     // 2. Download package
     info!("Downloading package: {} v{}", package.name, package.version);
     
-    // In a real implementation, this would download from package.url
-    // For now, we'll create a placeholder package
+    // 3. Verify package hash, which the download does before finalizing
     let package_dir = packages_dir.join(&package.name);
-    fs::create_dir_all(&package_dir)?;
-    
-    // 3. Verify package hash
-    debug!("Verifying package hash");
+    let archive = download::download_package(package, &package_dir, progress)?;
+    debug!("Verified package hash of {:?}", archive);
     
     // 4. Verify ZK contract if available
     if let Some(contract_name) = &package.zk_contract {
@@ -201,6 +406,12 @@ pub fn install_package(package_name: &str) -> Result<()> {
         if !verified {
             return Err(anyhow::anyhow!("Package ZK contract verification failed"));
         }
+        
+        // State persisted by the previous version must fit the new one
+        if let Some(from_version) = zk::state::migrate(&contract)
+            .with_context(|| format!("Failed to migrate state of contract {}", contract.name))? {
+            info!("Migrated state of contract {} from {} to {}", contract.name, from_version, contract.version);
+        }
     }
     
     // 5. Install package as MatrixBox container
@@ -214,32 +425,90 @@ pub fn install_package(package_name: &str) -> Result<()> {
     
     matrixbox::create_container(&package_dir, container_config)?;
     
-    info!("Package {} installed successfully", package_name);
+    // Keep the installed version's manifest so later versions can be diffed
+    diff::record_installed(package, &package_dir)?;
+    
+    // An updated package may move past an advisory's affected range
+    advisory::refresh_remediation()?;
     Ok(())
 }
 
 /// Remove installed package
-pub fn remove_package(package_name: &str) -> Result<()> {
+///
+/// The package directory is moved to the trash unless `purge` is set, in
+/// which case it is deleted immediately. Packages other installed packages
+/// depend on are only removed with `force`. Returns the trash entry ID, if any.
+pub fn remove_package(package_name: &str, purge: bool, force: bool) -> Result<Option<String>> {
     info!("Removing package: {}", package_name);
     
-    let store_dir = PathBuf::from(constants::ROOT_DIR).join(STORE_DIR);
-    let packages_dir = store_dir.join(PACKAGES_DIR);
-    let package_dir = packages_dir.join(package_name);
+    let plan = plan_remove_package(package_name, purge, force)?;
+    let package_dir = package_path(package_name);
+    let mut trash_id = None;
+    
+    for action in &plan.actions {
+        match action {
+            PlannedAction::DeleteFiles { .. } => {
+                // Remove package directory
+                fs::remove_dir_all(&package_dir)?;
+            }
+            PlannedAction::MoveToTrash { .. } => {
+                trash_id = Some(crate::trash::move_to_trash(
+                    crate::trash::TrashKind::Package,
+                    package_name,
+                    Some(&package_dir),
+                    &package_dir.to_string_lossy(),
+                    serde_json::Value::Null,
+                )?);
+            }
+            other => anyhow::bail!("Unexpected action in package removal plan: {}", other),
+        }
+    }
+    
+    info!("Package {} removed successfully", package_name);
+    Ok(trash_id)
+}
+
+/// Plan the removal of an installed package without changing anything
+pub fn plan_remove_package(package_name: &str, purge: bool, force: bool) -> Result<Plan> {
+    let package_dir = package_path(package_name);
     
     if !package_dir.exists() {
         return Err(anyhow::anyhow!("Package not installed: {}", package_name));
     }
     
-    // Remove package directory
-    fs::remove_dir_all(&package_dir)?;
+    let dependents = deps::installed_dependents(&load_index()?, package_name)?;
+    if !dependents.is_empty() {
+        if !force {
+            anyhow::bail!("Package {} is required by {}; use --force to remove it anyway",
+                          package_name, dependents.join(", "));
+        }
+        warn!("Removing {} although {} depend on it", package_name, dependents.join(", "));
+    }
+    
+    let path = package_dir.to_string_lossy().to_string();
+    let bytes = crate::core::plan::path_size(&package_dir);
     
-    info!("Package {} removed successfully", package_name);
-    Ok(())
+    let mut plan = Plan::new(&format!("store remove {}", package_name));
+    if purge {
+        plan.push(PlannedAction::DeleteFiles { path, bytes });
+    } else {
+        plan.push(PlannedAction::MoveToTrash { item: package_name.to_string(), path: Some(path), bytes });
+    }
+    
+    Ok(plan)
+}
+
+/// Path of an installed package's directory
+pub fn package_path(package_name: &str) -> PathBuf {
+    constants::root_dir()
+        .join(STORE_DIR)
+        .join(PACKAGES_DIR)
+        .join(package_name)
 }
 
 /// List all installed packages
 pub fn list_installed_packages() -> Result<Vec<String>> {
-    let store_dir = PathBuf::from(constants::ROOT_DIR).join(STORE_DIR);
+    let store_dir = constants::root_dir().join(STORE_DIR);
     let packages_dir = store_dir.join(PACKAGES_DIR);
     
     if !packages_dir.exists() {
@@ -259,9 +528,127 @@ pub fn list_installed_packages() -> Result<Vec<String>> {
     Ok(packages)
 }
 
+/// Public keys (hex) trusted to sign package index entries
+pub fn trusted_keys() -> Result<Vec<String>> {
+    let path = constants::root_dir().join(STORE_DIR).join(TRUSTED_KEYS_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let data = fs::read_to_string(&path)?;
+    serde_json::from_str(&data).context("Invalid trusted store keys")
+}
+
+/// Trust a public key (hex) to sign package index entries
+pub fn trust_key(key: &str) -> Result<()> {
+    if key.len() != 64 || !key.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!("Invalid store key: expected 64 hex characters");
+    }
+    
+    let mut keys = trusted_keys()?;
+    let key = key.to_lowercase();
+    if !keys.contains(&key) {
+        keys.push(key);
+        let path = constants::root_dir().join(STORE_DIR).join(TRUSTED_KEYS_FILE);
+        fs::write(&path, serde_json::to_string_pretty(&keys)?)?;
+    }
+    
+    Ok(())
+}
+
+/// Configured package repositories
+pub fn list_repositories() -> Result<Vec<Repository>> {
+    let path = constants::root_dir().join(STORE_DIR).join(REPOSITORIES_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let data = fs::read_to_string(&path)?;
+    serde_json::from_str(&data).context("Invalid store repositories")
+}
+
+/// Add a package repository, replacing one with the same name, and trust its key
+pub fn add_repository(repository: Repository) -> Result<()> {
+    info!("Adding store repository: {} ({})", repository.name, repository.url);
+    
+    if let Some(key) = &repository.key {
+        trust_key(key)?;
+    }
+    
+    let mut repositories = list_repositories()?;
+    repositories.retain(|r| r.name != repository.name);
+    repositories.push(repository);
+    
+    let path = constants::root_dir().join(STORE_DIR).join(REPOSITORIES_FILE);
+    fs::write(&path, serde_json::to_string_pretty(&repositories)?)?;
+    Ok(())
+}
+
+/// Pinned public key (hex) of the store publisher
+fn publisher_key() -> Result<String> {
+    let path = constants::root_dir().join(constants::AUTH_DIR).join(PUBLISHER_KEY_FILE);
+    let key = fs::read_to_string(&path)
+        .with_context(|| format!("No store publisher key pinned at {:?}; refusing to apply an unverified index", path))?;
+    let key = key.trim().to_lowercase();
+    if key.len() != 64 || !key.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!("Invalid store publisher key in {:?}: expected 64 hex characters", path);
+    }
+    Ok(key)
+}
+
+/// Fetch the index or its signature over http(s), or from a local path
+fn fetch_index(url: &str) -> Result<Vec<u8>> {
+    if let Some(path) = url.strip_prefix("file://").or_else(|| url.starts_with('/').then_some(url)) {
+        return fs::read(path).with_context(|| format!("Failed to read {}", path));
+    }
+    
+    debug!("Fetching {}", url);
+    let response = ureq::AgentBuilder::new()
+        .timeout(INDEX_FETCH_TIMEOUT)
+        .build()
+        .get(url)
+        .call()
+        .map_err(|e| match e {
+            ureq::Error::Status(code, response) => anyhow::anyhow!("{} answered {} {}", url, code, response.status_text()),
+            e => anyhow::anyhow!("Failed to fetch {}: {}", url, e),
+        })?;
+    
+    let mut body = Vec::new();
+    response.into_reader().read_to_end(&mut body)
+        .with_context(|| format!("Failed to read {}", url))?;
+    Ok(body)
+}
+
+/// Detached signature next to an index file
+fn signature_path(index_path: &Path) -> PathBuf {
+    let mut name = index_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(SIGNATURE_EXTENSION);
+    index_path.with_file_name(name)
+}
+
+/// Remove a file, ignoring one that is not there
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Whether a package entry is signed by a trusted store key
+///
+/// The signature covers the package name, version and hash, one per line.
+pub fn signature_is_valid(package: &Package) -> bool {
+    let message = format!("{}\n{}\n{}", package.name, package.version, package.hash);
+    
+    trusted_keys().unwrap_or_default().iter().any(|key| {
+        crate::core::identity::verify(key, message.as_bytes(), &package.signature).is_ok()
+    })
+}
+
 /// Show package details
 pub fn show_package_details(package_name: &str) -> Result<Option<Package>> {
-    let store_dir = PathBuf::from(constants::ROOT_DIR).join(STORE_DIR);
+    let store_dir = constants::root_dir().join(STORE_DIR);
     let index_path = store_dir.join(INDEX_FILE);
     
     if !index_path.exists() {
@@ -278,7 +665,7 @@ pub fn show_package_details(package_name: &str) -> Result<Option<Package>> {
 pub fn verify_package(package_name: &str) -> Result<bool> {
     info!("Verifying package integrity: {}", package_name);
     
-    let store_dir = PathBuf::from(constants::ROOT_DIR).join(STORE_DIR);
+    let store_dir = constants::root_dir().join(STORE_DIR);
     let packages_dir = store_dir.join(PACKAGES_DIR);
     let package_dir = packages_dir.join(package_name);
     
@@ -286,17 +673,135 @@ pub fn verify_package(package_name: &str) -> Result<bool> {
         return Err(anyhow::anyhow!("Package not installed: {}", package_name));
     }
     
-    // Verify package integrity using ZK proofs
-    let index_path = store_dir.join(INDEX_FILE);
-    let index_data = fs::read_to_string(&index_path)?;
-    let index: PackageIndex = serde_json::from_str(&index_data)?;
+    // Compare the files with the manifest recorded at install time
+    match diff::changed_files(package_name)? {
+        Some(changed) if !changed.is_empty() => {
+            warn!("Package {} has {} file(s) changed since it was installed: {}",
+                  package_name, changed.len(), changed.join(", "));
+            Ok(false)
+        }
+        Some(_) => Ok(true),
+        None => {
+            warn!("No install manifest recorded for {}; only its presence was checked", package_name);
+            Ok(true)
+        }
+    }
+}
+
+/// Verify every installed package, failing with those that did not verify
+///
+/// Runs on the worker pool and stops early when the pool is cancelled.
+pub fn integrity_sweep(cancel: &CancelToken) -> Result<()> {
+    let mut failed = Vec::new();
+    for package_name in list_installed_packages()? {
+        if cancel.is_cancelled() {
+            debug!("Package integrity sweep cancelled");
+            return Ok(());
+        }
+        match verify_package(&package_name) {
+            Ok(true) => {}
+            Ok(false) => failed.push(package_name),
+            Err(e) => {
+                warn!("Failed to verify package {}: {:#}", package_name, e);
+                failed.push(package_name);
+            }
+        }
+    }
     
-    let package = index.packages.get(package_name)
-        .ok_or_else(|| anyhow::anyhow!("Package not found in index: {}", package_name))?;
+    if !failed.is_empty() {
+        anyhow::bail!("Package(s) failed the integrity sweep: {}", failed.join(", "));
+    }
+    Ok(())
+}
+
+/// Download the archives of installed packages the index has another version of
+///
+/// Archives land where `install_package` looks for them, so the upgrade
+/// does not wait on the network. Runs on the worker pool and stops early
+/// when the pool is cancelled.
+pub fn prefetch_updates(cancel: &CancelToken) -> Result<()> {
+    let index = load_index()?;
+    for package_name in list_installed_packages()? {
+        if cancel.is_cancelled() {
+            debug!("Package prefetch cancelled");
+            return Ok(());
+        }
+        let package = match index.packages.get(&package_name) {
+            Some(package) => package,
+            None => continue,
+        };
+        if diff::installed_version(&package_name)?.as_deref() == Some(package.version.as_str()) {
+            continue;
+        }
+        
+        match download::download_package(package, &package_path(&package_name), |_| {}) {
+            Ok(archive) => debug!("Prefetched {} {} to {:?}", package_name, package.version, archive),
+            Err(e) => warn!("Failed to prefetch {} {}: {:#}", package_name, package.version, e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
     
-    // In a real implementation, this would verify the package contents
-    // against the hash in the index
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+    
+    /// Publish an index, signed by `key`, where `update_index_from` can fetch it
+    fn publish(dir: &Path, last_updated: u64, key: &SigningKey) -> String {
+        let index = PackageIndex { last_updated, packages: HashMap::new(), advisories: Vec::new() };
+        let bytes = serde_json::to_vec(&index).unwrap();
+        let path = dir.join(INDEX_FILE);
+        fs::write(&path, &bytes).unwrap();
+        fs::write(signature_path(&path), hex(&key.sign(&bytes).to_bytes())).unwrap();
+        path.to_str().unwrap().to_string()
+    }
     
-    // For now, we'll just check if the directory exists
-    Ok(true)
+    #[test]
+    fn indexes_are_applied_only_with_the_publisher_signature() {
+        let dir = std::env::temp_dir().join(format!("sentient-index-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let publisher = SigningKey::from_bytes(&[11; 32]);
+        let url = publish(&dir, 100, &publisher);
+        assert!(rollback_index().is_err());
+        
+        let key_path = constants::root_dir().join(constants::AUTH_DIR).join(PUBLISHER_KEY_FILE);
+        let _ = fs::remove_file(&key_path);
+        let error = update_index_from(&url).unwrap_err();
+        assert!(error.to_string().contains("No store publisher key pinned"), "{:#}", error);
+        
+        fs::create_dir_all(key_path.parent().unwrap()).unwrap();
+        fs::write(&key_path, "not a key").unwrap();
+        assert!(update_index_from(&url).is_err());
+        fs::write(&key_path, hex(publisher.verifying_key().as_bytes())).unwrap();
+        
+        let update = update_index_from(&url).unwrap();
+        assert_eq!((update.last_updated, update.packages), (100, 0));
+        assert_eq!(update.publisher_key, hex(publisher.verifying_key().as_bytes()));
+        assert_eq!(load_index().unwrap().last_updated, 100);
+        
+        // Another key's signature, a missing one and a forged index are all refused
+        let impostor = SigningKey::from_bytes(&[12; 32]);
+        publish(&dir, 200, &impostor);
+        assert!(update_index_from(&url).is_err());
+        fs::remove_file(signature_path(Path::new(&url))).unwrap();
+        assert!(update_index_from(&url).is_err());
+        publish(&dir, 200, &publisher);
+        fs::write(&url, br#"{"last_updated": 999, "packages": {}}"#).unwrap();
+        assert!(update_index_from(&url).is_err());
+        assert_eq!(load_index().unwrap().last_updated, 100);
+        
+        // Rolling back swaps the current and previous indexes
+        publish(&dir, 200, &publisher);
+        update_index_from(&url).unwrap();
+        assert_eq!(rollback_index().unwrap().last_updated, 100);
+        assert_eq!(load_index().unwrap().last_updated, 100);
+        assert_eq!(rollback_index().unwrap().last_updated, 200);
+        
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
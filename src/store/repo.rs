@@ -0,0 +1,642 @@
+// SentientOS ZK-Store Repository Publishing
+// Builds, signs and checks a package repository that any static file host can serve
+
+use anyhow::{Result, Context};
+use tracing::{info, debug};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use ed25519_dalek::{Signer, SigningKey};
+
+use crate::core::{constants, lock};
+use super::{Package, PackageIndex, INDEX_FILE};
+use super::diff::check_component;
+
+// Constants
+const INDEX_SIGNATURE_FILE: &str = "index.json.sig";
+const CHANGELOG_DIR: &str = "changelog";
+const CHANGELOG_HEAD_FILE: &str = "head.json";
+const PACKAGES_DIR: &str = "packages";
+const MANIFESTS_DIR: &str = "manifests";
+const KEYS_DIR: &str = "keys";
+const PUBLIC_KEY_FILE: &str = "repository.pub";
+const ARCHIVE_EXTENSION: &str = "tso";
+const STAGING_DIR: &str = ".store/repo-staging";
+const SIGNING_KEY_PREFIX: &str = "store-repo-";
+
+/// Entries per change-log generation; a full generation is sealed and a new one started
+const GENERATION_ENTRIES: usize = 256;
+
+/// One published change
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeEntry {
+    /// Package name
+    pub name: String,
+    
+    /// Published version
+    pub version: String,
+    
+    /// Archive hash
+    pub hash: String,
+    
+    /// When the version was published
+    pub published_at: u64,
+}
+
+/// A generation of the change log, in `changelog/<generation>.json`
+///
+/// Each generation names the hash of the one before, so clients holding
+/// any generation can check that later ones extend it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeLogGeneration {
+    /// Generation number, starting at 1
+    pub generation: u64,
+    
+    /// Hash of the previous generation, `None` for the first
+    pub previous_hash: Option<String>,
+    
+    /// Changes, oldest first
+    pub entries: Vec<ChangeEntry>,
+    
+    /// Repository key signature over the generation
+    pub signature: String,
+}
+
+/// Latest change-log generation, in `changelog/head.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeLogHead {
+    /// Latest generation number
+    pub generation: u64,
+    
+    /// Hash of the latest generation
+    pub hash: String,
+}
+
+/// Outcome of adding a package to a repository
+#[derive(Debug, Clone)]
+pub struct AddedPackage {
+    /// Index entry of the package
+    pub package: Package,
+    
+    /// Whether the same archive was already published
+    pub already_published: bool,
+}
+
+/// Create an empty repository layout with a new repository key
+///
+/// The private key stays in `.auth/keys`, outside the layout, so the whole
+/// directory can be published as is.
+pub fn init(dir: &Path) -> Result<String> {
+    if dir.join(INDEX_FILE).exists() {
+        anyhow::bail!("{:?} already holds a repository", dir);
+    }
+    for sub in [CHANGELOG_DIR, PACKAGES_DIR, MANIFESTS_DIR, KEYS_DIR] {
+        fs::create_dir_all(dir.join(sub))?;
+    }
+    
+    let public_key = generate_key()?;
+    fs::write(dir.join(KEYS_DIR).join(PUBLIC_KEY_FILE), &public_key)?;
+    
+    let index = PackageIndex { last_updated: now(), packages: HashMap::new(), advisories: Vec::new() };
+    write_index(dir, &index, &load_signing_key(&public_key)?)?;
+    
+    info!("Initialized store repository in {:?} with key {}", dir, public_key);
+    Ok(public_key)
+}
+
+/// Validate a package archive and publish it into a repository
+///
+/// The archive is copied to `packages/<name>/<name>-<version>.tso`, its
+/// manifest to `manifests/<name>/<version>.json`, and the change is appended
+/// to the change log before the index is updated, so the index never names
+/// a file that isn't there. A published version can't be replaced by a
+/// different archive.
+pub fn add_package(dir: &Path, archive: &Path, license: &str) -> Result<AddedPackage> {
+    let _lock = lock_repository(dir)?;
+    let public_key = public_key(dir)?;
+    let signing_key = load_signing_key(&public_key)?;
+    
+    let data = fs::read(archive).with_context(|| format!("Failed to read archive {:?}", archive))?;
+    if !crate::matrixbox::tso::is_valid_tso_archive(archive)? {
+        anyhow::bail!("{:?} is not a TSO archive", archive);
+    }
+    
+    // Extracting checks every file hash and that the container loads
+    let staging = constants::root_dir().join(STAGING_DIR).join(format!("{}", now_nanos()));
+    let extracted = crate::matrixbox::tso::extract_tso_archive(archive, &staging);
+    let result = extracted.and_then(|container| {
+        check_component(&container.name, "package name")?;
+        check_component(&container.version, "version")?;
+        
+        let hash = blake3::hash(&data).to_hex().to_string();
+        let mut package = Package {
+            name: container.name.clone(),
+            version: container.version.clone(),
+            description: container.description.clone().unwrap_or_default(),
+            author: container.author.clone().unwrap_or_default(),
+            license: license.to_string(),
+            dependencies: container.metadata.dependencies.clone(),
+            url: archive_relative_path(&container.name, &container.version),
+            hash,
+            signature: String::new(),
+            zk_contract: None,
+            size: data.len() as u64,
+        };
+        package.signature = sign_hex(&signing_key, package_message(&package).as_bytes());
+        let manifest = super::diff::build_manifest(&package, &staging)?;
+        Ok((package, manifest))
+    });
+    fs::remove_dir_all(&staging).ok();
+    let (package, manifest) = result.with_context(|| format!("Invalid package archive {:?}", archive))?;
+    
+    let archive_path = dir.join(&package.url);
+    if archive_path.exists() {
+        let existing = blake3::hash(&fs::read(&archive_path)?).to_hex().to_string();
+        if existing != package.hash {
+            anyhow::bail!("{} {} is already published with a different archive; publish a new version instead",
+                          package.name, package.version);
+        }
+        debug!("{} {} is already published", package.name, package.version);
+        return Ok(AddedPackage { package, already_published: true });
+    }
+    
+    fs::create_dir_all(archive_path.parent().unwrap())?;
+    fs::write(&archive_path, &data)?;
+    let manifest_path = dir.join(MANIFESTS_DIR).join(&package.name).join(format!("{}.json", package.version));
+    fs::create_dir_all(manifest_path.parent().unwrap())?;
+    fs::write(&manifest_path, serde_json::to_string_pretty(&manifest)?)?;
+    
+    append_change(dir, &signing_key, ChangeEntry {
+        name: package.name.clone(),
+        version: package.version.clone(),
+        hash: package.hash.clone(),
+        published_at: now(),
+    })?;
+    
+    let mut index = load_index(dir)?;
+    index.packages.insert(package.name.clone(), package.clone());
+    index.last_updated = now();
+    write_index(dir, &index, &signing_key)?;
+    
+    info!("Published {} {} to {:?}", package.name, package.version, dir);
+    Ok(AddedPackage { package, already_published: false })
+}
+
+/// Re-sign the index, its package entries and every change-log generation
+///
+/// Generations are rehashed in order, so the chain stays intact.
+pub fn sign(dir: &Path) -> Result<usize> {
+    let _lock = lock_repository(dir)?;
+    let public_key = public_key(dir)?;
+    let signing_key = load_signing_key(&public_key)?;
+    
+    let head = load_head(dir)?;
+    let mut previous_hash = None;
+    let generations = head.as_ref().map_or(0, |h| h.generation);
+    for number in 1..=generations {
+        let mut generation = load_generation(dir, number)?;
+        generation.previous_hash = previous_hash;
+        previous_hash = Some(write_generation(dir, &mut generation, &signing_key)?);
+    }
+    if let Some(hash) = previous_hash {
+        write_head(dir, &ChangeLogHead { generation: generations, hash })?;
+    }
+    
+    let mut index = load_index(dir)?;
+    for package in index.packages.values_mut() {
+        package.signature = sign_hex(&signing_key, package_message(package).as_bytes());
+    }
+    write_index(dir, &index, &signing_key)?;
+    
+    info!("Re-signed repository {:?} ({} change-log generation(s))", dir, generations);
+    Ok(generations as usize)
+}
+
+/// Check a repository's internal consistency, returning every problem found
+///
+/// Covers the index signature, every listed package's archive, hash, size,
+/// manifest and entry signature, and the change-log chain, whose latest
+/// entry per package must match the index.
+pub fn verify(dir: &Path) -> Result<Vec<String>> {
+    let public_key = public_key(dir)?;
+    let mut problems = Vec::new();
+    
+    let index_bytes = fs::read(dir.join(INDEX_FILE)).context("Repository has no index")?;
+    match fs::read_to_string(dir.join(INDEX_SIGNATURE_FILE)) {
+        Ok(signature) => {
+            if crate::core::identity::verify(&public_key, &index_bytes, signature.trim()).is_err() {
+                problems.push("index signature is invalid".to_string());
+            }
+        }
+        Err(_) => problems.push(format!("{} is missing", INDEX_SIGNATURE_FILE)),
+    }
+    let index: PackageIndex = serde_json::from_slice(&index_bytes).context("Invalid repository index")?;
+    
+    let mut names: Vec<&String> = index.packages.keys().collect();
+    names.sort();
+    for name in names {
+        let package = &index.packages[name];
+        if &package.name != name {
+            problems.push(format!("{}: index entry names package {}", name, package.name));
+        }
+        if crate::core::identity::verify(&public_key, package_message(package).as_bytes(), &package.signature).is_err() {
+            problems.push(format!("{} {}: entry signature is invalid", package.name, package.version));
+        }
+        if check_component(&package.name, "package name").is_err() || check_component(&package.version, "version").is_err() {
+            problems.push(format!("{} {}: invalid name or version", package.name, package.version));
+            continue;
+        }
+        
+        match fs::read(dir.join(&package.url)) {
+            Ok(data) => {
+                if blake3::hash(&data).to_hex().to_string() != package.hash {
+                    problems.push(format!("{} {}: archive hash does not match the index", package.name, package.version));
+                }
+                if data.len() as u64 != package.size {
+                    problems.push(format!("{} {}: archive is {} bytes, index says {}",
+                                          package.name, package.version, data.len(), package.size));
+                }
+            }
+            Err(_) => problems.push(format!("{} {}: archive {} is missing", package.name, package.version, package.url)),
+        }
+        
+        let manifest = dir.join(MANIFESTS_DIR).join(&package.name).join(format!("{}.json", package.version));
+        if !manifest.exists() {
+            problems.push(format!("{} {}: manifest is missing", package.name, package.version));
+        }
+    }
+    
+    let latest = verify_changelog(dir, &public_key, &mut problems)?;
+    for package in index.packages.values() {
+        match latest.get(&package.name) {
+            Some(entry) if entry.version == package.version && entry.hash == package.hash => {}
+            Some(entry) => problems.push(format!("{}: index lists {}, change log last published {}",
+                                                 package.name, package.version, entry.version)),
+            None => problems.push(format!("{} {}: not in the change log", package.name, package.version)),
+        }
+    }
+    
+    debug!("Verified repository {:?}: {} problem(s)", dir, problems.len());
+    Ok(problems)
+}
+
+/// Check the change-log chain, returning the latest entry per package
+fn verify_changelog(dir: &Path, public_key: &str, problems: &mut Vec<String>) -> Result<BTreeMap<String, ChangeEntry>> {
+    let mut latest = BTreeMap::new();
+    let head = match load_head(dir) {
+        Ok(Some(head)) => head,
+        Ok(None) => return Ok(latest),
+        Err(e) => {
+            problems.push(format!("change-log head is unreadable: {}", e));
+            return Ok(latest);
+        }
+    };
+    
+    let mut previous_hash: Option<String> = None;
+    for number in 1..=head.generation {
+        let generation = match load_generation(dir, number) {
+            Ok(generation) => generation,
+            Err(e) => {
+                problems.push(format!("change-log generation {}: {}", number, e));
+                return Ok(latest);
+            }
+        };
+        if generation.generation != number {
+            problems.push(format!("change-log generation {} is numbered {}", number, generation.generation));
+        }
+        if generation.previous_hash != previous_hash {
+            problems.push(format!("change-log generation {} does not chain to generation {}", number, number - 1));
+        }
+        
+        let payload = generation_payload(&generation)?;
+        if crate::core::identity::verify(public_key, payload.as_bytes(), &generation.signature).is_err() {
+            problems.push(format!("change-log generation {}: signature is invalid", number));
+        }
+        for entry in &generation.entries {
+            latest.insert(entry.name.clone(), entry.clone());
+        }
+        previous_hash = Some(hash_hex(payload.as_bytes()));
+    }
+    
+    if previous_hash.as_deref() != Some(head.hash.as_str()) {
+        problems.push("change-log head hash does not match the latest generation".to_string());
+    }
+    Ok(latest)
+}
+
+/// Append a change to the latest generation, starting a new one when it is full
+fn append_change(dir: &Path, signing_key: &SigningKey, entry: ChangeEntry) -> Result<()> {
+    let head = load_head(dir)?;
+    let mut generation = match &head {
+        Some(head) => {
+            let latest = load_generation(dir, head.generation)?;
+            if latest.entries.len() < GENERATION_ENTRIES {
+                latest
+            } else {
+                ChangeLogGeneration {
+                    generation: head.generation + 1,
+                    previous_hash: Some(head.hash.clone()),
+                    entries: Vec::new(),
+                    signature: String::new(),
+                }
+            }
+        }
+        None => ChangeLogGeneration { generation: 1, previous_hash: None, entries: Vec::new(), signature: String::new() },
+    };
+    
+    generation.entries.push(entry);
+    let hash = write_generation(dir, &mut generation, signing_key)?;
+    write_head(dir, &ChangeLogHead { generation: generation.generation, hash })
+}
+
+/// Sign and write a generation, returning its hash
+fn write_generation(dir: &Path, generation: &mut ChangeLogGeneration, signing_key: &SigningKey) -> Result<String> {
+    let payload = generation_payload(generation)?;
+    generation.signature = sign_hex(signing_key, payload.as_bytes());
+    fs::write(generation_path(dir, generation.generation), serde_json::to_string_pretty(generation)?)?;
+    Ok(hash_hex(payload.as_bytes()))
+}
+
+fn load_generation(dir: &Path, number: u64) -> Result<ChangeLogGeneration> {
+    let path = generation_path(dir, number);
+    let content = fs::read_to_string(&path).with_context(|| format!("Failed to read {:?}", path))?;
+    serde_json::from_str(&content).with_context(|| format!("Invalid change-log generation {:?}", path))
+}
+
+fn load_head(dir: &Path) -> Result<Option<ChangeLogHead>> {
+    let path = dir.join(CHANGELOG_DIR).join(CHANGELOG_HEAD_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&fs::read_to_string(&path)?)
+        .with_context(|| format!("Invalid change-log head {:?}", path))?))
+}
+
+fn write_head(dir: &Path, head: &ChangeLogHead) -> Result<()> {
+    fs::write(dir.join(CHANGELOG_DIR).join(CHANGELOG_HEAD_FILE), serde_json::to_string_pretty(head)?)?;
+    Ok(())
+}
+
+/// Canonical bytes covered by a generation signature and hash
+fn generation_payload(generation: &ChangeLogGeneration) -> Result<String> {
+    Ok(serde_json::to_string(&serde_json::json!({
+        "generation": generation.generation,
+        "previous_hash": generation.previous_hash,
+        "entries": generation.entries,
+    }))?)
+}
+
+/// Message covered by a package entry signature, as checked by `signature_is_valid`
+fn package_message(package: &Package) -> String {
+    format!("{}\n{}\n{}", package.name, package.version, package.hash)
+}
+
+fn load_index(dir: &Path) -> Result<PackageIndex> {
+    let content = fs::read_to_string(dir.join(INDEX_FILE)).context("Repository has no index")?;
+    serde_json::from_str(&content).context("Invalid repository index")
+}
+
+/// Write the index and its detached signature
+fn write_index(dir: &Path, index: &PackageIndex, signing_key: &SigningKey) -> Result<()> {
+    let bytes = serde_json::to_string_pretty(index)?;
+    fs::write(dir.join(INDEX_FILE), &bytes)?;
+    fs::write(dir.join(INDEX_SIGNATURE_FILE), sign_hex(signing_key, bytes.as_bytes()))?;
+    Ok(())
+}
+
+/// The repository's public key (hex)
+fn public_key(dir: &Path) -> Result<String> {
+    let path = dir.join(KEYS_DIR).join(PUBLIC_KEY_FILE);
+    let key = fs::read_to_string(&path)
+        .with_context(|| format!("{:?} is not a repository (no {:?})", dir, path))?;
+    Ok(key.trim().to_string())
+}
+
+/// Generate a repository key, returning its public key (hex)
+fn generate_key() -> Result<String> {
+    use rand::{thread_rng, Rng};
+    
+    let seed: [u8; 32] = thread_rng().gen();
+    let public_key = to_hex(SigningKey::from_bytes(&seed).verifying_key().as_bytes());
+    
+    let path = signing_key_path(&public_key);
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(&path, to_hex(&seed)).with_context(|| format!("Failed to write repository key {:?}", path))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(public_key)
+}
+
+/// Load the private key matching a repository's public key
+fn load_signing_key(public_key: &str) -> Result<SigningKey> {
+    let path = signing_key_path(public_key);
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("This node does not hold the repository key ({:?})", path))?;
+    let seed: [u8; 32] = from_hex(content.trim())?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Invalid repository key {:?}", path))?;
+    
+    let key = SigningKey::from_bytes(&seed);
+    if to_hex(key.verifying_key().as_bytes()) != public_key {
+        anyhow::bail!("Repository key {:?} does not match the repository's public key", path);
+    }
+    Ok(key)
+}
+
+fn signing_key_path(public_key: &str) -> PathBuf {
+    constants::root_dir()
+        .join(constants::AUTH_DIR)
+        .join("keys")
+        .join(format!("{}{}.key", SIGNING_KEY_PREFIX, &public_key[..public_key.len().min(16)]))
+}
+
+/// Serialize writers of one repository directory
+fn lock_repository(dir: &Path) -> Result<lock::LockGuard> {
+    let canonical = fs::canonicalize(dir).with_context(|| format!("Repository {:?} does not exist", dir))?;
+    let name = format!("store-repo-{}", &hash_hex(canonical.to_string_lossy().as_bytes())[..16]);
+    lock::lock(&name, "store repository", lock::DEFAULT_TIMEOUT)
+}
+
+/// Archive path relative to the repository root, used as the package URL
+fn archive_relative_path(name: &str, version: &str) -> String {
+    format!("{}/{}/{}-{}.{}", PACKAGES_DIR, name, name, version, ARCHIVE_EXTENSION)
+}
+
+fn generation_path(dir: &Path, number: u64) -> PathBuf {
+    dir.join(CHANGELOG_DIR).join(format!("{}.json", number))
+}
+
+fn sign_hex(signing_key: &SigningKey, message: &[u8]) -> String {
+    to_hex(&signing_key.sign(message).to_bytes())
+}
+
+fn hash_hex(data: &[u8]) -> String {
+    blake3::hash(data).to_hex().to_string()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        anyhow::bail!("Invalid hex string length");
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).context("Invalid hex string"))
+        .collect()
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn now_nanos() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_nanos()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Fresh repository directory outside the root
+    fn repository(name: &str) -> (PathBuf, SigningKey) {
+        let dir = std::env::temp_dir().join(format!("sentient-repo-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let public_key = init(&dir).unwrap();
+        let key = load_signing_key(&public_key).unwrap();
+        (dir, key)
+    }
+    
+    /// Publish an archive the way `add_package` lays it out
+    fn publish(dir: &Path, key: &SigningKey, name: &str, version: &str, data: &[u8]) {
+        let mut package = Package {
+            name: name.to_string(),
+            version: version.to_string(),
+            description: String::new(),
+            author: String::new(),
+            license: "MIT".to_string(),
+            dependencies: Vec::new(),
+            url: archive_relative_path(name, version),
+            hash: hash_hex(data),
+            signature: String::new(),
+            zk_contract: None,
+            size: data.len() as u64,
+        };
+        package.signature = sign_hex(key, package_message(&package).as_bytes());
+        
+        fs::create_dir_all(dir.join(&package.url).parent().unwrap()).unwrap();
+        fs::write(dir.join(&package.url), data).unwrap();
+        let manifest = dir.join(MANIFESTS_DIR).join(name).join(format!("{}.json", version));
+        fs::create_dir_all(manifest.parent().unwrap()).unwrap();
+        fs::write(manifest, "{}").unwrap();
+        append_change(dir, key, ChangeEntry {
+            name: name.to_string(),
+            version: version.to_string(),
+            hash: package.hash.clone(),
+            published_at: 1,
+        }).unwrap();
+        
+        let mut index = load_index(dir).unwrap();
+        index.packages.insert(name.to_string(), package);
+        write_index(dir, &index, key).unwrap();
+    }
+    
+    #[test]
+    fn new_repositories_verify_and_are_not_reinitialized() {
+        let (dir, _) = repository("init");
+        assert!(verify(&dir).unwrap().is_empty());
+        assert!(dir.join(KEYS_DIR).join(PUBLIC_KEY_FILE).exists());
+        assert!(init(&dir).is_err());
+        assert!(verify(&dir.join("missing")).is_err());
+    }
+    
+    #[test]
+    fn published_packages_verify_until_tampered_with() {
+        let (dir, key) = repository("tamper");
+        publish(&dir, &key, "editor", "1.0.0", b"archive v1");
+        publish(&dir, &key, "editor", "1.1.0", b"archive v1.1");
+        publish(&dir, &key, "viewer", "0.1.0", b"viewer");
+        assert_eq!(verify(&dir).unwrap(), Vec::<String>::new());
+        
+        fs::write(dir.join(archive_relative_path("viewer", "0.1.0")), b"viewer!").unwrap();
+        fs::remove_file(dir.join(MANIFESTS_DIR).join("editor").join("1.1.0.json")).unwrap();
+        let problems = verify(&dir).unwrap();
+        assert!(problems.contains(&"viewer 0.1.0: archive hash does not match the index".to_string()));
+        assert!(problems.contains(&"viewer 0.1.0: archive is 7 bytes, index says 6".to_string()));
+        assert!(problems.contains(&"editor 1.1.0: manifest is missing".to_string()));
+        assert_eq!(problems.len(), 3);
+    }
+    
+    #[test]
+    fn the_index_must_match_the_change_log() {
+        let (dir, key) = repository("changelog");
+        publish(&dir, &key, "editor", "1.0.0", b"archive v1");
+        publish(&dir, &key, "editor", "1.1.0", b"archive v1.1");
+        
+        // Roll the index back to the first version without touching the change log
+        let mut index = load_index(&dir).unwrap();
+        let mut old = index.packages["editor"].clone();
+        old.version = "1.0.0".to_string();
+        old.url = archive_relative_path("editor", "1.0.0");
+        old.hash = hash_hex(b"archive v1");
+        old.size = 10;
+        old.signature = sign_hex(&key, package_message(&old).as_bytes());
+        index.packages.insert("editor".to_string(), old);
+        write_index(&dir, &index, &key).unwrap();
+        
+        assert_eq!(verify(&dir).unwrap(), ["editor: index lists 1.0.0, change log last published 1.1.0"]);
+    }
+    
+    #[test]
+    fn full_generations_are_sealed_and_chained() {
+        let (dir, key) = repository("generations");
+        for i in 0..=GENERATION_ENTRIES {
+            append_change(&dir, &key, ChangeEntry {
+                name: format!("pkg{}", i),
+                version: "1.0.0".to_string(),
+                hash: hash_hex(&[i as u8]),
+                published_at: i as u64,
+            }).unwrap();
+        }
+        
+        let head = load_head(&dir).unwrap().unwrap();
+        assert_eq!(head.generation, 2);
+        assert_eq!(load_generation(&dir, 1).unwrap().entries.len(), GENERATION_ENTRIES);
+        let second = load_generation(&dir, 2).unwrap();
+        assert_eq!(second.entries.len(), 1);
+        assert_eq!(second.previous_hash, Some(hash_hex(generation_payload(&load_generation(&dir, 1).unwrap()).unwrap().as_bytes())));
+        
+        let mut problems = Vec::new();
+        let latest = verify_changelog(&dir, &to_hex(key.verifying_key().as_bytes()), &mut problems).unwrap();
+        assert!(problems.is_empty());
+        assert_eq!(latest.len(), GENERATION_ENTRIES + 1);
+    }
+    
+    #[test]
+    fn signing_repairs_edited_generations_and_entries() {
+        let (dir, key) = repository("resign");
+        publish(&dir, &key, "editor", "1.0.0", b"archive v1");
+        
+        let mut generation = load_generation(&dir, 1).unwrap();
+        generation.entries[0].published_at = 99;
+        fs::write(generation_path(&dir, 1), serde_json::to_string(&generation).unwrap()).unwrap();
+        let mut index = load_index(&dir).unwrap();
+        index.packages.get_mut("editor").unwrap().description = "edited".to_string();
+        fs::write(dir.join(INDEX_FILE), serde_json::to_string(&index).unwrap()).unwrap();
+        
+        let problems = verify(&dir).unwrap();
+        assert!(problems.contains(&"index signature is invalid".to_string()));
+        assert!(problems.contains(&"change-log generation 1: signature is invalid".to_string()));
+        
+        assert_eq!(sign(&dir).unwrap(), 1);
+        assert!(verify(&dir).unwrap().is_empty());
+        assert_eq!(load_generation(&dir, 1).unwrap().entries[0].published_at, 99);
+    }
+}
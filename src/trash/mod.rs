@@ -0,0 +1,442 @@
+// SentientOS Trash
+// Soft-delete for removed packages and containers with timed retention
+
+use anyhow::{Result, Context};
+use tracing::{info, debug, warn};
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use crate::core::constants;
+use crate::core::plan::{Plan, PlannedAction};
+
+// Constants
+const TRASH_DIR: &str = ".trash";
+const CONFIG_FILE: &str = "config.json";
+const TOMBSTONE_FILE: &str = "tombstone.json";
+const DATA_DIR: &str = "data";
+
+/// Kind of item held in the trash
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrashKind {
+    /// Native store package
+    Package,
+    
+    /// MatrixBox container
+    Container,
+}
+
+/// Record of a removed item, kept alongside its data for restore
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tombstone {
+    /// Trash entry ID (`<timestamp>-<name>`, with a `-<n>` suffix when the
+    /// same name was removed more than once that second)
+    pub id: String,
+    
+    /// Kind of removed item
+    pub kind: TrashKind,
+    
+    /// Original name of the item
+    pub name: String,
+    
+    /// Where the item's data lived before removal
+    pub original_path: String,
+    
+    /// Removal timestamp
+    pub removed_at: u64,
+    
+    /// Whether the item's data was moved into the trash
+    pub has_data: bool,
+    
+    /// Original registry entry, used to re-register on restore
+    pub registry_entry: serde_json::Value,
+}
+
+/// Trash configuration
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrashConfig {
+    /// Days to keep trash entries before purging them
+    pub retention_days: u64,
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self { retention_days: 7 }
+    }
+}
+
+/// Initialize the trash and purge expired entries
+pub fn init() -> Result<()> {
+    info!("Initializing trash");
+    
+    let trash_dir = trash_dir();
+    fs::create_dir_all(&trash_dir)
+        .context("Failed to create trash directory")?;
+    
+    let config_path = trash_dir.join(CONFIG_FILE);
+    if !config_path.exists() {
+        fs::write(&config_path, serde_json::to_string_pretty(&TrashConfig::default())?)?;
+    }
+    
+    let purged = purge_expired()?;
+    if purged > 0 {
+        info!("Purged {} expired trash entries", purged);
+    }
+    
+    info!("Trash initialized successfully");
+    Ok(())
+}
+
+/// Load trash configuration
+pub fn load_config() -> Result<TrashConfig> {
+    let config_path = trash_dir().join(CONFIG_FILE);
+    if !config_path.exists() {
+        return Ok(TrashConfig::default());
+    }
+    
+    let config_data = fs::read_to_string(&config_path)?;
+    Ok(serde_json::from_str(&config_data)?)
+}
+
+/// Move an item into the trash
+///
+/// `data_path` is renamed into the trash entry, so it must be on the same
+/// filesystem as the SentientOS root. Returns the trash entry ID.
+pub fn move_to_trash(
+    kind: TrashKind,
+    name: &str,
+    data_path: Option<&Path>,
+    original_path: &str,
+    registry_entry: serde_json::Value,
+) -> Result<String> {
+    let removed_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let (id, entry_dir) = create_entry_dir(&format!("{}-{}", removed_at, sanitize_name(name)))?;
+    
+    let has_data = match data_path {
+        Some(path) if path.exists() => {
+            fs::rename(path, entry_dir.join(DATA_DIR)).with_context(|| format!(
+                "Failed to move {:?} to trash (is it on another filesystem? use --purge to delete it)",
+                path
+            ))?;
+            true
+        }
+        _ => false,
+    };
+    
+    let tombstone = Tombstone {
+        id: id.clone(),
+        kind,
+        name: name.to_string(),
+        original_path: original_path.to_string(),
+        removed_at,
+        has_data,
+        registry_entry,
+    };
+    write_tombstone(&tombstone)?;
+    
+    info!("Moved {:?} {} to trash as {}", kind, name, id);
+    Ok(id)
+}
+
+/// Replace the registry entry recorded in a tombstone
+pub fn set_registry_entry(id: &str, registry_entry: serde_json::Value) -> Result<()> {
+    let mut tombstone = get_entry(id)?;
+    tombstone.registry_entry = registry_entry;
+    write_tombstone(&tombstone)
+}
+
+/// Get a trash entry by ID
+pub fn get_entry(id: &str) -> Result<Tombstone> {
+    let tombstone_path = trash_dir().join(id).join(TOMBSTONE_FILE);
+    if !tombstone_path.exists() {
+        anyhow::bail!("Trash entry not found: {}", id);
+    }
+    
+    let content = fs::read_to_string(&tombstone_path)
+        .with_context(|| format!("Failed to read tombstone: {:?}", tombstone_path))?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// List all trash entries, oldest first
+pub fn list_entries() -> Result<Vec<Tombstone>> {
+    let trash_dir = trash_dir();
+    if !trash_dir.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(&trash_dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        
+        let id = entry.file_name().to_string_lossy().to_string();
+        match get_entry(&id) {
+            Ok(tombstone) => entries.push(tombstone),
+            Err(e) => warn!("Skipping unreadable trash entry {}: {}", id, e),
+        }
+    }
+    
+    entries.sort_by_key(|e| e.removed_at);
+    Ok(entries)
+}
+
+/// Restore a trash entry
+///
+/// Refuses if the original name is taken, unless `rename` supplies a new
+/// name. Returns the name the item was restored under.
+pub fn restore_entry(id: &str, rename: Option<&str>) -> Result<String> {
+    let tombstone = get_entry(id)?;
+    let target_name = rename.unwrap_or(&tombstone.name).to_string();
+    let data_path = trash_dir().join(id).join(DATA_DIR);
+    let data_path = if tombstone.has_data { Some(data_path.as_path()) } else { None };
+    
+    info!("Restoring {:?} {} from trash entry {}", tombstone.kind, target_name, id);
+    
+    match tombstone.kind {
+        TrashKind::Package => crate::package::restore_from_trash(&tombstone, data_path, &target_name)?,
+        TrashKind::Container => crate::matrixbox::restore_from_trash(&tombstone, data_path, &target_name)?,
+    }
+    
+    fs::remove_dir_all(trash_dir().join(id))
+        .with_context(|| format!("Failed to remove restored trash entry: {}", id))?;
+    
+    info!("Restored trash entry {} as {}", id, target_name);
+    Ok(target_name)
+}
+
+/// Permanently delete trash entries
+///
+/// With `older_than`, only entries removed longer ago than that are deleted.
+pub fn empty_trash(older_than: Option<Duration>) -> Result<usize> {
+    let plan = plan_empty(older_than)?;
+    
+    for action in &plan.actions {
+        if let PlannedAction::DeleteFiles { path, .. } = action {
+            let id = Path::new(path).file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .ok_or_else(|| anyhow::anyhow!("Invalid trash entry path: {}", path))?;
+            purge_entry(&id)?;
+        }
+    }
+    
+    Ok(plan.actions.len())
+}
+
+/// Plan emptying the trash without deleting anything
+pub fn plan_empty(older_than: Option<Duration>) -> Result<Plan> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let mut plan = Plan::new("trash empty");
+    
+    for tombstone in list_entries()? {
+        if let Some(age) = older_than {
+            if now.saturating_sub(tombstone.removed_at) < age.as_secs() {
+                continue;
+            }
+        }
+        
+        let entry_dir = trash_dir().join(&tombstone.id);
+        plan.push(PlannedAction::DeleteFiles {
+            path: entry_dir.to_string_lossy().to_string(),
+            bytes: crate::core::plan::path_size(&entry_dir),
+        });
+    }
+    
+    Ok(plan)
+}
+
+/// Purge entries older than the configured retention
+pub fn purge_expired() -> Result<usize> {
+    let config = load_config()?;
+    empty_trash(Some(Duration::from_secs(config.retention_days * 24 * 60 * 60)))
+}
+
+/// Purge the oldest entries until at least `bytes_needed` have been freed
+///
+/// Returns the number of bytes actually freed.
+pub fn free_space(bytes_needed: u64) -> Result<u64> {
+    let mut freed = 0;
+    
+    for tombstone in list_entries()? {
+        if freed >= bytes_needed {
+            break;
+        }
+        
+        let entry_dir = trash_dir().join(&tombstone.id);
+        freed += dir_size(&entry_dir).unwrap_or(0);
+        purge_entry(&tombstone.id)?;
+    }
+    
+    debug!("Freed {} bytes from trash", freed);
+    Ok(freed)
+}
+
+/// Parse an age such as `30m`, `12h`, `7d` or `2w`
+pub fn parse_age(age: &str) -> Result<Duration> {
+    let age = age.trim();
+    let split = age.find(|c: char| !c.is_ascii_digit()).unwrap_or(age.len());
+    let (value, unit) = age.split_at(split);
+    
+    let value: u64 = value.parse()
+        .with_context(|| format!("Invalid age: {}", age))?;
+    
+    let seconds = match unit {
+        "" | "s" => value,
+        "m" => value * 60,
+        "h" => value * 60 * 60,
+        "d" => value * 24 * 60 * 60,
+        "w" => value * 7 * 24 * 60 * 60,
+        _ => anyhow::bail!("Invalid age unit in {} (expected s, m, h, d or w)", age),
+    };
+    
+    Ok(Duration::from_secs(seconds))
+}
+
+/// Permanently delete a single trash entry
+fn purge_entry(id: &str) -> Result<()> {
+    let entry_dir = trash_dir().join(id);
+    fs::remove_dir_all(&entry_dir)
+        .with_context(|| format!("Failed to purge trash entry: {}", id))?;
+    
+    info!("Purged trash entry: {}", id);
+    Ok(())
+}
+
+/// Write a tombstone into its trash entry
+fn write_tombstone(tombstone: &Tombstone) -> Result<()> {
+    let tombstone_path = trash_dir().join(&tombstone.id).join(TOMBSTONE_FILE);
+    fs::write(&tombstone_path, serde_json::to_string_pretty(tombstone)?)
+        .with_context(|| format!("Failed to write tombstone: {:?}", tombstone_path))
+}
+
+/// Total size of a directory tree in bytes
+fn dir_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
+
+/// Make a name safe for use in a trash entry directory name
+fn sanitize_name(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '_' })
+        .collect()
+}
+
+/// Create the directory of a new trash entry, returning its ID and path
+///
+/// `base` gets a counter suffix when an entry by that ID already exists.
+/// The directory is created without `create_dir_all` so that two removals
+/// racing for one ID cannot both claim it.
+fn create_entry_dir(base: &str) -> Result<(String, PathBuf)> {
+    let trash_dir = trash_dir();
+    fs::create_dir_all(&trash_dir)
+        .context("Failed to create trash directory")?;
+    
+    let mut attempt = 1;
+    loop {
+        let id = if attempt == 1 { base.to_string() } else { format!("{}-{}", base, attempt) };
+        let entry_dir = trash_dir.join(&id);
+        match fs::create_dir(&entry_dir) {
+            Ok(()) => return Ok((id, entry_dir)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => attempt += 1,
+            Err(e) => return Err(e).with_context(|| format!("Failed to create trash entry: {:?}", entry_dir)),
+        }
+    }
+}
+
+/// Absolute path of the trash directory
+fn trash_dir() -> PathBuf {
+    constants::root_dir().join(TRASH_DIR)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Directory with one file, standing in for a package or container
+    fn item(name: &str) -> PathBuf {
+        let path = constants::root_dir().join("trash-tests").join(name);
+        fs::create_dir_all(&path).unwrap();
+        fs::write(path.join("payload"), b"12345").unwrap();
+        path
+    }
+    
+    #[test]
+    fn moved_items_keep_their_data_and_registry_entry() {
+        let path = item("moved");
+        let entry = serde_json::json!({ "version": "1.2.3" });
+        let id = move_to_trash(TrashKind::Package, "moved", Some(&path), "/store/moved", entry.clone()).unwrap();
+        
+        assert!(!path.exists());
+        assert_eq!(fs::read(trash_dir().join(&id).join(DATA_DIR).join("payload")).unwrap(), b"12345");
+        
+        let tombstone = get_entry(&id).unwrap();
+        assert_eq!((tombstone.kind, tombstone.name.as_str()), (TrashKind::Package, "moved"));
+        assert_eq!(tombstone.original_path, "/store/moved");
+        assert!(tombstone.has_data);
+        assert_eq!(tombstone.registry_entry, entry);
+        assert!(list_entries().unwrap().iter().any(|t| t.id == id));
+        
+        set_registry_entry(&id, serde_json::json!({ "version": "2.0.0" })).unwrap();
+        assert_eq!(get_entry(&id).unwrap().registry_entry["version"], "2.0.0");
+    }
+    
+    #[test]
+    fn items_without_data_are_recorded() {
+        let id = move_to_trash(TrashKind::Container, "no data", None, "", serde_json::Value::Null).unwrap();
+        let tombstone = get_entry(&id).unwrap();
+        assert!(!tombstone.has_data);
+        assert!(id.ends_with("-no_data"), "{}", id);
+        assert!(!trash_dir().join(&id).join(DATA_DIR).exists());
+    }
+    
+    #[test]
+    fn entry_ids_are_unique_within_a_second() {
+        let base = "1700000000-twice";
+        let (first, _) = create_entry_dir(base).unwrap();
+        let (second, _) = create_entry_dir(base).unwrap();
+        assert_eq!(first, base);
+        assert_eq!(second, format!("{}-2", base));
+        assert!(get_entry("missing-entry").is_err());
+    }
+    
+    #[test]
+    fn only_old_enough_entries_are_planned_for_deletion() {
+        let id = move_to_trash(TrashKind::Package, "fresh", Some(&item("fresh")), "", serde_json::Value::Null).unwrap();
+        let planned = |plan: Plan| plan.actions.iter().any(|action| matches!(
+            action,
+            PlannedAction::DeleteFiles { path, bytes } if path.ends_with(&id) && *bytes >= 5
+        ));
+        
+        assert!(planned(plan_empty(None).unwrap()));
+        assert!(!planned(plan_empty(Some(Duration::from_secs(3600))).unwrap()));
+    }
+    
+    #[test]
+    fn ages_take_a_unit() {
+        assert_eq!(parse_age("45").unwrap(), Duration::from_secs(45));
+        assert_eq!(parse_age("30m").unwrap(), Duration::from_secs(30 * 60));
+        assert_eq!(parse_age(" 12h ").unwrap(), Duration::from_secs(12 * 3600));
+        assert_eq!(parse_age("7d").unwrap(), Duration::from_secs(7 * 86400));
+        assert_eq!(parse_age("2w").unwrap(), Duration::from_secs(14 * 86400));
+        assert!(parse_age("3y").is_err());
+        assert!(parse_age("d").is_err());
+    }
+    
+    #[test]
+    fn names_are_made_safe_for_directories() {
+        assert_eq!(sanitize_name("../etc/passwd"), ".._etc_passwd");
+        assert_eq!(sanitize_name("my app v1.0"), "my_app_v1.0");
+    }
+}
@@ -0,0 +1,270 @@
+// SentientOS Migration Check
+// Read-only preview of the data migrations a build would run against a root
+
+use anyhow::{Result, Context};
+use tracing::debug;
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::time::Instant;
+use serde::{Serialize, Deserialize};
+
+use crate::core::constants;
+
+// Constants
+const SKIPPED_DIRS: &[&str] = &[constants::LOCK_DIR, ".trash"];
+
+/// A data migration this build runs at startup
+struct Migration {
+    /// Stable identifier
+    id: &'static str,
+    
+    /// What the migration does
+    description: &'static str,
+    
+    /// Whether the previous build can still read the data afterwards
+    reversible: bool,
+    
+    /// Rough cost per document, in microseconds
+    micros_per_document: u64,
+    
+    /// Documents the migration would write under a root, empty if it won't run
+    pending: fn(&Path) -> Vec<PathBuf>,
+}
+
+/// Migrations of this build, in the order they run
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        id: "identity-legacy-ids",
+        description: "Create the signed node identity from the legacy system.json and gossip node IDs",
+        reversible: true,
+        micros_per_document: 5_000,
+        pending: identity_pending,
+    },
+];
+
+/// A migration the check found would run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlannedMigration {
+    /// Migration identifier
+    pub id: String,
+    
+    /// What the migration does
+    pub description: String,
+    
+    /// Whether the previous build can still read the data afterwards
+    pub reversible: bool,
+    
+    /// Documents it would write, relative to the root
+    pub documents: Vec<PathBuf>,
+    
+    /// Estimated duration, in milliseconds
+    pub estimated_ms: u64,
+}
+
+/// A persisted document that failed to load
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentFailure {
+    /// Document path, relative to the root
+    pub path: PathBuf,
+    
+    /// Why it failed
+    pub error: String,
+}
+
+/// Outcome of a migration check, as printed by `--migration-check`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationReport {
+    /// Version of the build that ran the check
+    pub version: String,
+    
+    /// Root directory checked
+    pub root: PathBuf,
+    
+    /// Persisted documents loaded
+    pub documents: usize,
+    
+    /// Migrations that would run, in order
+    pub migrations: Vec<PlannedMigration>,
+    
+    /// Documents that would fail to load
+    pub failures: Vec<DocumentFailure>,
+    
+    /// Estimated duration of all migrations, in milliseconds
+    pub estimated_ms: u64,
+    
+    /// How long the check took, in milliseconds
+    pub check_ms: u64,
+}
+
+impl MigrationReport {
+    /// Whether any migration that would run is irreversible
+    pub fn irreversible(&self) -> bool {
+        self.migrations.iter().any(|m| !m.reversible)
+    }
+    
+    /// Whether the update can apply without operator attention
+    pub fn is_clean(&self) -> bool {
+        self.failures.is_empty() && !self.irreversible()
+    }
+}
+
+/// Check a root directory against this build without changing anything
+///
+/// Loads every JSON and YAML document under the root and asks each
+/// migration which documents it would write. Nothing is created, locked or
+/// written, so it is safe against the root of a running node.
+pub fn check(root: &Path) -> Result<MigrationReport> {
+    let started = Instant::now();
+    if !root.is_dir() {
+        anyhow::bail!("Root directory {:?} does not exist", root);
+    }
+    
+    let mut documents = 0;
+    let mut failures = Vec::new();
+    for path in collect_documents(root)? {
+        documents += 1;
+        if let Err(e) = load_document(&path) {
+            failures.push(DocumentFailure {
+                path: path.strip_prefix(root).unwrap_or(&path).to_path_buf(),
+                error: format!("{:#}", e),
+            });
+        }
+    }
+    
+    let migrations: Vec<PlannedMigration> = MIGRATIONS.iter()
+        .filter_map(|migration| {
+            let documents = (migration.pending)(root);
+            if documents.is_empty() {
+                return None;
+            }
+            Some(PlannedMigration {
+                id: migration.id.to_string(),
+                description: migration.description.to_string(),
+                reversible: migration.reversible,
+                estimated_ms: (documents.len() as u64 * migration.micros_per_document).div_ceil(1000),
+                documents,
+            })
+        })
+        .collect();
+    
+    debug!("Migration check of {:?}: {} documents, {} migrations, {} failures",
+           root, documents, migrations.len(), failures.len());
+    Ok(MigrationReport {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        root: root.to_path_buf(),
+        documents,
+        estimated_ms: migrations.iter().map(|m| m.estimated_ms).sum(),
+        migrations,
+        failures,
+        check_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+/// JSON and YAML documents under a root, skipping locks and the trash
+fn collect_documents(root: &Path) -> Result<Vec<PathBuf>> {
+    let mut documents = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    
+    while let Some(dir) = pending.pop() {
+        let entries = fs::read_dir(&dir).with_context(|| format!("Failed to read {:?}", dir))?;
+        for entry in entries {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            
+            if file_type.is_dir() {
+                let skipped = dir == root && SKIPPED_DIRS.iter().any(|d| entry.file_name() == *d);
+                if !skipped {
+                    pending.push(path);
+                }
+            } else if file_type.is_file() {
+                let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+                if matches!(extension, "json" | "yaml" | "yml") {
+                    documents.push(path);
+                }
+            }
+        }
+    }
+    
+    documents.sort();
+    Ok(documents)
+}
+
+/// Parse a document the way its loaders will
+fn load_document(path: &Path) -> Result<()> {
+    let content = fs::read_to_string(path)?;
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => {
+            serde_json::from_str::<serde_json::Value>(&content)?;
+        }
+        _ => {
+            serde_yaml::from_str::<serde_yaml::Value>(&content)?;
+        }
+    }
+    Ok(())
+}
+
+/// The identity is built from the legacy IDs when `.auth/identity.json` is missing
+fn identity_pending(root: &Path) -> Vec<PathBuf> {
+    let identity = Path::new(constants::AUTH_DIR).join("identity.json");
+    if root.join(&identity).exists() {
+        Vec::new()
+    } else {
+        vec![identity]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Fresh root directory outside the node's root
+    fn root(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("sentient-migrate-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        root
+    }
+    
+    #[test]
+    fn broken_documents_are_reported_outside_locks_and_trash() {
+        let root = root("documents");
+        fs::create_dir_all(root.join(".config")).unwrap();
+        fs::write(root.join(".config/system.json"), "{\"a\": 1}").unwrap();
+        fs::write(root.join(".config/broken.json"), "{\"a\": ").unwrap();
+        fs::write(root.join(".config/broken.yml"), "a: [1").unwrap();
+        fs::write(root.join(".config/notes.txt"), "{").unwrap();
+        for skipped in [constants::LOCK_DIR, ".trash"] {
+            fs::create_dir_all(root.join(skipped)).unwrap();
+            fs::write(root.join(skipped).join("broken.json"), "{").unwrap();
+        }
+        
+        let report = check(&root).unwrap();
+        assert_eq!(report.documents, 3);
+        let failed: Vec<_> = report.failures.iter().map(|f| f.path.clone()).collect();
+        assert_eq!(failed, [PathBuf::from(".config/broken.json"), PathBuf::from(".config/broken.yml")]);
+        assert!(!report.is_clean());
+    }
+    
+    #[test]
+    fn the_identity_migration_runs_until_the_identity_exists() {
+        let root = root("identity");
+        let report = check(&root).unwrap();
+        assert_eq!(report.migrations.len(), 1);
+        assert_eq!(report.migrations[0].id, "identity-legacy-ids");
+        assert_eq!(report.migrations[0].documents, [Path::new(constants::AUTH_DIR).join("identity.json")]);
+        assert_eq!(report.estimated_ms, 5);
+        assert!(!report.irreversible() && report.is_clean());
+        
+        fs::create_dir_all(root.join(constants::AUTH_DIR)).unwrap();
+        fs::write(root.join(constants::AUTH_DIR).join("identity.json"), "{}").unwrap();
+        let report = check(&root).unwrap();
+        assert!(report.migrations.is_empty());
+        assert_eq!(report.estimated_ms, 0);
+    }
+    
+    #[test]
+    fn missing_roots_are_refused() {
+        assert!(check(&root("missing").join("nowhere")).is_err());
+    }
+}
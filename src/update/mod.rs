@@ -0,0 +1,272 @@
+// SentientOS Self-Update
+// Stages a new build, previews its data migrations and swaps it in per policy
+
+pub mod migrate;
+
+use anyhow::{Result, Context};
+use tracing::{info, warn};
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::process::Command;
+use serde::{Serialize, Deserialize};
+
+use crate::core::constants;
+use migrate::MigrationReport;
+
+// Constants
+const UPDATE_DIR: &str = "update";
+const STAGED_FILE: &str = "sentient_os.staged";
+const PREVIOUS_SUFFIX: &str = "previous";
+
+/// What the updater does with a staged build
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    /// Apply the update now
+    Proceed,
+    
+    /// Keep the build staged until a maintenance window
+    Defer,
+    
+    /// Refuse the update
+    Abort,
+}
+
+/// Self-update policy, stored under `self_update` in system.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UpdatePolicy {
+    /// What to do when a migration is irreversible
+    pub on_irreversible: Decision,
+    
+    /// What to do when documents would fail to load
+    pub on_failure: Decision,
+    
+    /// Longest estimated migration applied outside a maintenance window, in milliseconds
+    pub max_online_ms: u64,
+}
+
+impl Default for UpdatePolicy {
+    fn default() -> Self {
+        Self {
+            on_irreversible: Decision::Defer,
+            on_failure: Decision::Abort,
+            max_online_ms: 5_000,
+        }
+    }
+}
+
+/// Decide what to do with a build from its migration report
+///
+/// Within a maintenance window, what would be deferred proceeds.
+pub fn decide(report: &MigrationReport, policy: &UpdatePolicy, in_window: bool) -> Decision {
+    let mut decision = Decision::Proceed;
+    if !report.failures.is_empty() {
+        decision = decision.max(policy.on_failure);
+    }
+    if report.irreversible() {
+        decision = decision.max(policy.on_irreversible);
+    }
+    if report.estimated_ms > policy.max_online_ms {
+        decision = decision.max(Decision::Defer);
+    }
+    
+    if in_window && decision == Decision::Defer {
+        Decision::Proceed
+    } else {
+        decision
+    }
+}
+
+/// Run a build's migration check against this node's root and decide
+///
+/// The report and decision are attached to the `update.check` audit entry.
+pub fn check_build(binary: &Path, in_window: bool) -> Result<(MigrationReport, Decision)> {
+    let report = run_check(binary, &constants::root_dir())?;
+    let decision = decide(&report, &load_policy()?, in_window);
+    
+    crate::logs::ship::ship_audit("update.check", &serde_json::to_string(&serde_json::json!({
+        "binary": binary,
+        "decision": decision,
+        "report": report,
+    }))?);
+    Ok((report, decision))
+}
+
+/// Stage a build for the next update
+pub fn stage(binary: &Path) -> Result<PathBuf> {
+    let path = staged_path();
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::copy(binary, &path).with_context(|| format!("Failed to stage {:?}", binary))?;
+    set_executable(&path)?;
+    
+    info!("Staged update {:?}", binary);
+    Ok(path)
+}
+
+/// Drop the staged build, if any
+pub fn unstage() -> Result<()> {
+    let path = staged_path();
+    if path.exists() {
+        fs::remove_file(&path)?;
+    }
+    Ok(())
+}
+
+/// Swap the staged build in for the running one
+///
+/// Runs inside a maintenance window. The staged build is checked again
+/// against the current data first; the replaced binary is kept next to the
+/// new one for rollback.
+pub fn apply_staged() -> Result<()> {
+    let staged = staged_path();
+    if !staged.exists() {
+        anyhow::bail!("No update is staged");
+    }
+    
+    let (report, decision) = check_build(&staged, true)?;
+    if decision != Decision::Proceed {
+        anyhow::bail!("Staged update refused by policy ({:?}): {} migration(s), {} failing document(s)",
+                      decision, report.migrations.len(), report.failures.len());
+    }
+    
+    let exe = std::env::current_exe().context("Failed to locate the running binary")?;
+    let file_name = exe.file_name().unwrap().to_string_lossy().to_string();
+    let previous = exe.with_file_name(format!("{}.{}", file_name, PREVIOUS_SUFFIX));
+    let incoming = exe.with_file_name(format!("{}.update-tmp", file_name));
+    
+    fs::copy(&exe, &previous).with_context(|| format!("Failed to keep the previous binary as {:?}", previous))?;
+    fs::copy(&staged, &incoming)?;
+    set_executable(&incoming)?;
+    fs::rename(&incoming, &exe).with_context(|| format!("Failed to replace {:?}", exe))?;
+    if let Err(e) = fs::remove_file(&staged) {
+        warn!("Failed to remove the staged update: {}", e);
+    }
+    
+    crate::logs::ship::ship_audit("update.apply", &format!(
+        "Updated {:?} to {} ({} migration(s) pending at next start); previous binary kept as {:?}",
+        exe, report.version, report.migrations.len(), previous));
+    info!("Update applied; migrations run when {} restarts", file_name);
+    Ok(())
+}
+
+/// Run a build with `--migration-check` and parse its report
+fn run_check(binary: &Path, root: &Path) -> Result<MigrationReport> {
+    let output = Command::new(binary)
+        .arg("--migration-check")
+        .arg("--root")
+        .arg(root)
+        .output()
+        .with_context(|| format!("Failed to run migration check of {:?}", binary))?;
+    
+    if !output.status.success() {
+        anyhow::bail!("Migration check of {:?} failed: {}", binary, String::from_utf8_lossy(&output.stderr).trim());
+    }
+    serde_json::from_slice(&output.stdout)
+        .with_context(|| format!("Invalid migration report from {:?}", binary))
+}
+
+/// Load the self-update policy
+pub fn load_policy() -> Result<UpdatePolicy> {
+    let path = constants::root_dir().join(".config").join("system.json");
+    if !path.exists() {
+        return Ok(UpdatePolicy::default());
+    }
+    
+    let system_config: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    match system_config.get("self_update") {
+        Some(value) => Ok(serde_json::from_value(value.clone())
+            .context("Invalid self_update policy in system.json")?),
+        None => Ok(UpdatePolicy::default()),
+    }
+}
+
+fn set_executable(path: &Path) -> Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+/// Path of the staged build
+fn staged_path() -> PathBuf {
+    constants::root_dir().join(constants::RUNTIME_DIR).join(UPDATE_DIR).join(STAGED_FILE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use migrate::{DocumentFailure, PlannedMigration};
+    
+    fn report(reversible: bool, failures: usize, estimated_ms: u64) -> MigrationReport {
+        MigrationReport {
+            version: "9.9.9".to_string(),
+            root: PathBuf::from("/tmp/root"),
+            documents: 4,
+            migrations: vec![PlannedMigration {
+                id: "test".to_string(),
+                description: String::new(),
+                reversible,
+                documents: Vec::new(),
+                estimated_ms,
+            }],
+            failures: (0..failures)
+                .map(|i| DocumentFailure { path: PathBuf::from(format!("{}.json", i)), error: String::new() })
+                .collect(),
+            estimated_ms,
+            check_ms: 1,
+        }
+    }
+    
+    /// Executable script standing in for another build
+    fn build(name: &str, script: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sentient-update-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+        set_executable(&path).unwrap();
+        path
+    }
+    
+    #[test]
+    fn decisions_follow_the_policy_and_the_window() {
+        let policy = UpdatePolicy::default();
+        assert_eq!(decide(&report(true, 0, 10), &policy, false), Decision::Proceed);
+        assert_eq!(decide(&report(false, 0, 10), &policy, false), Decision::Defer);
+        assert_eq!(decide(&report(false, 0, 10), &policy, true), Decision::Proceed);
+        assert_eq!(decide(&report(true, 0, 60_000), &policy, false), Decision::Defer);
+        assert_eq!(decide(&report(true, 1, 10), &policy, false), Decision::Abort);
+        assert_eq!(decide(&report(true, 1, 10), &policy, true), Decision::Abort);
+        
+        let strict = UpdatePolicy { on_irreversible: Decision::Abort, ..UpdatePolicy::default() };
+        assert_eq!(decide(&report(false, 0, 10), &strict, true), Decision::Abort);
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn migration_checks_run_the_other_build() {
+        let json = serde_json::to_string(&report(true, 0, 10)).unwrap();
+        let good = build("good", &format!("[ \"$1\" = --migration-check ] && [ \"$2\" = --root ] && echo '{}'", json));
+        let parsed = run_check(&good, Path::new("/tmp/root")).unwrap();
+        assert_eq!(parsed.version, "9.9.9");
+        assert_eq!(parsed.migrations.len(), 1);
+        
+        let failing = build("failing", "echo 'unknown flag' >&2; exit 2");
+        let error = run_check(&failing, Path::new("/tmp/root")).unwrap_err();
+        assert!(format!("{:#}", error).contains("unknown flag"));
+        assert!(run_check(&build("garbled", "echo not-json"), Path::new("/tmp/root")).is_err());
+    }
+    
+    #[test]
+    fn builds_can_be_staged_and_dropped() {
+        let binary = build("staged", "exit 0");
+        let staged = stage(&binary).unwrap();
+        assert_eq!(fs::read(&staged).unwrap(), fs::read(&binary).unwrap());
+        
+        unstage().unwrap();
+        assert!(!staged.exists());
+        unstage().unwrap();
+    }
+}
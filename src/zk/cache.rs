@@ -0,0 +1,549 @@
+// SentientOS ZK Proof Cache
+// Reuses verification results and proofs of unchanged contracts and inputs
+
+use anyhow::{Result, Context};
+use tracing::{debug, warn};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use crate::core::{constants, lock};
+
+// Constants
+const CACHE_DIR: &str = ".zk/proofs/cache";
+const CONTRACTS_FILE: &str = "contracts.json";
+const KEYS_FILE: &str = "keys.json";
+const STATS_FILE: &str = "stats.json";
+const CACHE_LOCK: &str = "zk-proof-cache";
+
+/// Operation under which contract verification results are cached
+pub const VERIFY_OPERATION: &str = "verify";
+
+/// Operation under which successful proof verifications are cached
+pub const VERIFY_PROOF_OPERATION: &str = "verify-proof";
+
+/// Proof cache settings, stored under `proof_cache` in system.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Whether results are cached at all
+    pub enabled: bool,
+    
+    /// How long an entry stays valid, in seconds
+    pub ttl_secs: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self { enabled: true, ttl_secs: 86_400 }
+    }
+}
+
+/// A cached result, in `.zk/proofs/cache/<key>.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CacheEntry {
+    /// Contract the result belongs to
+    contract: String,
+    
+    /// Hash of the contract or input the result was computed from
+    content_hash: String,
+    
+    /// Operation that produced the result
+    operation: String,
+    
+    /// Resulting proof bytes (hex)
+    proof: String,
+    
+    /// When the entry was stored
+    created_at: u64,
+    
+    /// When the entry stops being valid
+    valid_until: u64,
+}
+
+/// Hit rates and size of the cache
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheStats {
+    /// Lookups answered from the cache
+    pub hits: u64,
+    
+    /// Lookups that had to compute
+    pub misses: u64,
+    
+    /// Entries evicted for age, a changed contract or keys, or a clear
+    pub evictions: u64,
+    
+    /// Entries on disk
+    #[serde(skip)]
+    pub entries: usize,
+    
+    /// Bytes on disk
+    #[serde(skip)]
+    pub bytes: u64,
+}
+
+impl CacheStats {
+    /// Share of lookups answered from the cache
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 { 0.0 } else { self.hits as f64 / lookups as f64 }
+    }
+}
+
+/// Persisted cache of proofs keyed by `(contract, content hash, operation)`
+pub struct ProofCache {
+    dir: PathBuf,
+    config: CacheConfig,
+}
+
+impl ProofCache {
+    /// Open the node's proof cache with its configured TTL
+    pub fn open() -> Result<Self> {
+        Ok(Self::at(constants::root_dir().join(CACHE_DIR), load_config()?))
+    }
+    
+    /// Open a cache in a directory
+    pub fn at(dir: PathBuf, config: CacheConfig) -> Self {
+        Self { dir, config }
+    }
+    
+    /// Cached proof, or compute it with `prove` and store it
+    ///
+    /// Only successful computations are stored; failures are returned as is.
+    pub fn get_or_prove(
+        &self,
+        contract: &str,
+        content_hash: &str,
+        operation: &str,
+        prove: impl FnOnce() -> Result<Vec<u8>>,
+    ) -> Result<Vec<u8>> {
+        if !self.config.enabled {
+            return prove();
+        }
+        
+        if let Some(proof) = self.get(contract, content_hash, operation)? {
+            self.count(|stats| stats.hits += 1);
+            crate::logs::metrics::increment("zk.proof_cache.hits");
+            debug!("Proof cache hit: {} {}", contract, operation);
+            return Ok(proof);
+        }
+        
+        self.count(|stats| stats.misses += 1);
+        crate::logs::metrics::increment("zk.proof_cache.misses");
+        let proof = prove()?;
+        if let Err(e) = self.put(contract, content_hash, operation, &proof) {
+            warn!("Failed to cache proof of {} {}: {}", contract, operation, e);
+        }
+        Ok(proof)
+    }
+    
+    /// Valid cached proof, evicting the entry if it expired
+    pub fn get(&self, contract: &str, content_hash: &str, operation: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.entry_path(contract, content_hash, operation);
+        let entry = match read_entry(&path) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        
+        // Keys are hashes, so check the entry really is the one asked for
+        if entry.contract != contract || entry.content_hash != content_hash || entry.operation != operation {
+            return Ok(None);
+        }
+        if entry.valid_until <= now() {
+            self.evict(&[path]);
+            return Ok(None);
+        }
+        Ok(Some(from_hex(&entry.proof)?))
+    }
+    
+    /// Store a proof
+    pub fn put(&self, contract: &str, content_hash: &str, operation: &str, proof: &[u8]) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let now = now();
+        let entry = CacheEntry {
+            contract: contract.to_string(),
+            content_hash: content_hash.to_string(),
+            operation: operation.to_string(),
+            proof: to_hex(proof),
+            created_at: now,
+            valid_until: now + self.config.ttl_secs,
+        };
+        
+        let path = self.entry_path(contract, content_hash, operation);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&entry)?)?;
+        fs::rename(&tmp, &path).context("Failed to store proof cache entry")?;
+        Ok(())
+    }
+    
+    /// Cached successful verification, or run `verify` and cache it if it succeeds
+    ///
+    /// Failed verifications are never cached, so a proof that fails before its
+    /// circuit's setup has run is checked again afterwards.
+    pub fn get_or_verify(
+        &self,
+        contract: &str,
+        content_hash: &str,
+        verify: impl FnOnce() -> Result<bool>,
+    ) -> Result<bool> {
+        if !self.config.enabled {
+            return verify();
+        }
+        
+        if self.get(contract, content_hash, VERIFY_PROOF_OPERATION)?.is_some() {
+            self.count(|stats| stats.hits += 1);
+            crate::logs::metrics::increment("zk.proof_cache.hits");
+            debug!("Proof cache hit: {} {}", contract, VERIFY_PROOF_OPERATION);
+            return Ok(true);
+        }
+        
+        self.count(|stats| stats.misses += 1);
+        crate::logs::metrics::increment("zk.proof_cache.misses");
+        let verified = verify()?;
+        if verified {
+            if let Err(e) = self.put(contract, content_hash, VERIFY_PROOF_OPERATION, &[1]) {
+                warn!("Failed to cache verification of {}: {}", contract, e);
+            }
+        }
+        Ok(verified)
+    }
+    
+    /// Note a contract's current hash, evicting its entries if it changed
+    pub fn track_contract(&self, contract: &str, contract_hash: &str) -> Result<()> {
+        if self.track(CONTRACTS_FILE, contract, contract_hash)? {
+            debug!("Contract {} changed; evicting its cached proofs", contract);
+            self.invalidate(contract)?;
+        }
+        Ok(())
+    }
+    
+    /// Note the hash of a circuit's keys under `.zk/keys`, evicting its
+    /// entries if they changed, since its proofs no longer verify
+    pub fn track_keys(&self, circuit: &str, keys_hash: &str) -> Result<()> {
+        if self.track(KEYS_FILE, circuit, keys_hash)? {
+            debug!("Keys of {} changed; evicting its cached proofs", circuit);
+            self.invalidate(circuit)?;
+        }
+        Ok(())
+    }
+    
+    /// Record a hash in a tracking file, returning whether it replaced a different one
+    fn track(&self, file: &str, name: &str, hash: &str) -> Result<bool> {
+        let _lock = lock::lock(CACHE_LOCK, &format!("track hash in {}", file), lock::DEFAULT_TIMEOUT)?;
+        let path = self.dir.join(file);
+        let mut hashes: BTreeMap<String, String> = fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        
+        let previous = hashes.insert(name.to_string(), hash.to_string());
+        if previous.as_deref() == Some(hash) {
+            return Ok(false);
+        }
+        fs::create_dir_all(&self.dir)?;
+        fs::write(&path, serde_json::to_string_pretty(&hashes)?)?;
+        Ok(previous.is_some())
+    }
+    
+    /// Evict every entry of a contract, returning how many were removed
+    pub fn invalidate(&self, contract: &str) -> Result<usize> {
+        let stale: Vec<PathBuf> = self.entries()
+            .into_iter()
+            .filter(|(_, entry)| entry.contract == contract)
+            .map(|(path, _)| path)
+            .collect();
+        self.evict(&stale);
+        Ok(stale.len())
+    }
+    
+    /// Evict every entry, returning how many were removed
+    pub fn clear(&self) -> Result<usize> {
+        let all: Vec<PathBuf> = self.entries().into_iter().map(|(path, _)| path).collect();
+        self.evict(&all);
+        Ok(all.len())
+    }
+    
+    /// Evict every expired entry, returning how many were removed
+    pub fn prune(&self) -> Result<usize> {
+        let now = now();
+        let expired: Vec<PathBuf> = self.entries()
+            .into_iter()
+            .filter(|(_, entry)| entry.valid_until <= now)
+            .map(|(path, _)| path)
+            .collect();
+        self.evict(&expired);
+        Ok(expired.len())
+    }
+    
+    /// Hit rates and size
+    pub fn stats(&self) -> Result<CacheStats> {
+        let mut stats = self.load_stats();
+        for (path, _) in self.entries() {
+            stats.entries += 1;
+            stats.bytes += fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        }
+        Ok(stats)
+    }
+    
+    fn evict(&self, paths: &[PathBuf]) {
+        let mut evicted = 0;
+        for path in paths {
+            if fs::remove_file(path).is_ok() {
+                evicted += 1;
+            }
+        }
+        if evicted > 0 {
+            self.count(|stats| stats.evictions += evicted);
+        }
+    }
+    
+    /// Entries on disk, with their paths
+    fn entries(&self) -> Vec<(PathBuf, CacheEntry)> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+        entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().map_or(false, |ext| ext == "json"))
+            .filter(|p| !p.ends_with(CONTRACTS_FILE) && !p.ends_with(KEYS_FILE) && !p.ends_with(STATS_FILE))
+            .filter_map(|p| read_entry(&p).map(|entry| (p, entry)))
+            .collect()
+    }
+    
+    /// Update the persisted counters, logging rather than failing the lookup
+    fn count(&self, update: impl FnOnce(&mut CacheStats)) {
+        let result = (|| -> Result<()> {
+            let _lock = lock::lock(CACHE_LOCK, "count proof cache lookup", lock::DEFAULT_TIMEOUT)?;
+            let mut stats = self.load_stats();
+            update(&mut stats);
+            fs::create_dir_all(&self.dir)?;
+            fs::write(self.dir.join(STATS_FILE), serde_json::to_string_pretty(&stats)?)?;
+            Ok(())
+        })();
+        if let Err(e) = result {
+            debug!("Failed to update proof cache stats: {}", e);
+        }
+    }
+    
+    fn load_stats(&self) -> CacheStats {
+        fs::read_to_string(self.dir.join(STATS_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+    
+    fn entry_path(&self, contract: &str, content_hash: &str, operation: &str) -> PathBuf {
+        let key = blake3::hash(format!("{}\0{}\0{}", contract, content_hash, operation).as_bytes());
+        self.dir.join(format!("{}.json", key.to_hex()))
+    }
+}
+
+/// Evict every cached result of a contract
+pub fn invalidate(contract_name: &str) -> Result<usize> {
+    ProofCache::open()?.invalidate(contract_name)
+}
+
+/// Hit rates and size of the node's proof cache
+pub fn stats() -> Result<CacheStats> {
+    ProofCache::open()?.stats()
+}
+
+/// Evict every cached result
+pub fn clear() -> Result<usize> {
+    ProofCache::open()?.clear()
+}
+
+/// Contract a proof operation belongs to (`<contract>.<method>` or a bare name)
+pub fn operation_contract(operation: &str) -> &str {
+    operation.split_once('.').map_or(operation, |(contract, _)| contract)
+}
+
+/// Load the proof cache configuration
+pub fn load_config() -> Result<CacheConfig> {
+    let path = constants::root_dir().join(".config").join("system.json");
+    if !path.exists() {
+        return Ok(CacheConfig::default());
+    }
+    
+    let system_config: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    match system_config.get("proof_cache") {
+        Some(value) => Ok(serde_json::from_value(value.clone())
+            .context("Invalid proof_cache configuration in system.json")?),
+        None => Ok(CacheConfig::default()),
+    }
+}
+
+fn read_entry(path: &Path) -> Option<CacheEntry> {
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Result<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        anyhow::bail!("Invalid hex string length");
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).context("Invalid hex string"))
+        .collect()
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    
+    const CONTRACT: &str = "ledger";
+    const HASH: &str = "hash-v1";
+    
+    /// Cache in a directory of its own, so tests don't share entries or counters
+    fn cache(name: &str, ttl_secs: u64) -> ProofCache {
+        let dir = constants::root_dir().join("zk-cache-tests").join(name);
+        let _ = fs::remove_dir_all(&dir);
+        ProofCache::at(dir, CacheConfig { enabled: true, ttl_secs })
+    }
+    
+    /// Prover that counts its calls
+    fn prover(calls: &Cell<u32>) -> impl FnOnce() -> Result<Vec<u8>> + '_ {
+        move || {
+            calls.set(calls.get() + 1);
+            Ok(vec![0xab, 0xcd])
+        }
+    }
+    
+    #[test]
+    fn contract_verified_twice_is_proven_once() {
+        let cache = cache("twice", 3600);
+        let calls = Cell::new(0);
+        
+        let first = cache.get_or_prove(CONTRACT, HASH, VERIFY_OPERATION, prover(&calls)).unwrap();
+        let second = cache.get_or_prove(CONTRACT, HASH, VERIFY_OPERATION, prover(&calls)).unwrap();
+        assert_eq!(calls.get(), 1);
+        assert_eq!(first, second);
+        
+        let stats = cache.stats().unwrap();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+        assert_eq!(stats.hit_rate(), 0.5);
+    }
+    
+    #[test]
+    fn key_covers_contract_hash_and_operation() {
+        let cache = cache("key", 3600);
+        let calls = Cell::new(0);
+        
+        cache.get_or_prove(CONTRACT, HASH, VERIFY_OPERATION, prover(&calls)).unwrap();
+        cache.get_or_prove(CONTRACT, "hash-v2", VERIFY_OPERATION, prover(&calls)).unwrap();
+        cache.get_or_prove(CONTRACT, HASH, "ledger.transfer", prover(&calls)).unwrap();
+        cache.get_or_prove("other", HASH, VERIFY_OPERATION, prover(&calls)).unwrap();
+        assert_eq!(calls.get(), 4);
+    }
+    
+    #[test]
+    fn failed_proofs_are_not_cached() {
+        let cache = cache("failed", 3600);
+        assert!(cache.get_or_prove(CONTRACT, HASH, VERIFY_OPERATION, || anyhow::bail!("prover failed")).is_err());
+        
+        let calls = Cell::new(0);
+        cache.get_or_prove(CONTRACT, HASH, VERIFY_OPERATION, prover(&calls)).unwrap();
+        assert_eq!(calls.get(), 1);
+    }
+    
+    #[test]
+    fn expired_entries_are_evicted() {
+        let cache = cache("expired", 0);
+        let calls = Cell::new(0);
+        
+        cache.get_or_prove(CONTRACT, HASH, VERIFY_OPERATION, prover(&calls)).unwrap();
+        cache.get_or_prove(CONTRACT, HASH, VERIFY_OPERATION, prover(&calls)).unwrap();
+        assert_eq!(calls.get(), 2);
+        assert_eq!(cache.stats().unwrap().evictions, 1);
+        
+        assert_eq!(cache.prune().unwrap(), 1);
+        assert_eq!(cache.stats().unwrap().entries, 0);
+    }
+    
+    #[test]
+    fn changed_contract_evicts_its_entries() {
+        let cache = cache("changed", 3600);
+        let calls = Cell::new(0);
+        
+        cache.track_contract(CONTRACT, "source-v1").unwrap();
+        cache.get_or_prove(CONTRACT, HASH, VERIFY_OPERATION, prover(&calls)).unwrap();
+        cache.get_or_prove("other", HASH, VERIFY_OPERATION, prover(&calls)).unwrap();
+        
+        // The same hash again keeps the entries
+        cache.track_contract(CONTRACT, "source-v1").unwrap();
+        assert!(cache.get(CONTRACT, HASH, VERIFY_OPERATION).unwrap().is_some());
+        
+        cache.track_contract(CONTRACT, "source-v2").unwrap();
+        assert!(cache.get(CONTRACT, HASH, VERIFY_OPERATION).unwrap().is_none());
+        assert!(cache.get("other", HASH, VERIFY_OPERATION).unwrap().is_some());
+        
+        // Changed keys of a circuit evict it the same way
+        cache.track_keys("other", "keys-v1").unwrap();
+        cache.track_keys("other", "keys-v2").unwrap();
+        assert!(cache.get("other", HASH, VERIFY_OPERATION).unwrap().is_none());
+    }
+    
+    #[test]
+    fn invalidate_and_clear_count_evictions() {
+        let cache = cache("invalidate", 3600);
+        let calls = Cell::new(0);
+        for operation in ["a", "b", "c"] {
+            cache.get_or_prove(CONTRACT, HASH, operation, prover(&calls)).unwrap();
+        }
+        cache.get_or_prove("other", HASH, "a", prover(&calls)).unwrap();
+        
+        assert_eq!(cache.invalidate(CONTRACT).unwrap(), 3);
+        assert_eq!(cache.clear().unwrap(), 1);
+        let stats = cache.stats().unwrap();
+        assert_eq!((stats.entries, stats.evictions), (0, 4));
+    }
+    
+    #[test]
+    fn only_successful_verifications_are_cached() {
+        let cache = cache("verify", 3600);
+        let calls = Cell::new(0);
+        let verify = |result: bool| {
+            let calls = &calls;
+            move || {
+                calls.set(calls.get() + 1);
+                Ok(result)
+            }
+        };
+        
+        assert!(!cache.get_or_verify(CONTRACT, HASH, verify(false)).unwrap());
+        assert!(cache.get_or_verify(CONTRACT, HASH, verify(true)).unwrap());
+        assert!(cache.get_or_verify(CONTRACT, HASH, verify(false)).unwrap());
+        assert_eq!(calls.get(), 2);
+    }
+    
+    #[test]
+    fn disabled_cache_always_computes() {
+        let cache = ProofCache::at(constants::root_dir().join("zk-cache-tests").join("disabled"),
+                                   CacheConfig { enabled: false, ttl_secs: 3600 });
+        let calls = Cell::new(0);
+        cache.get_or_prove(CONTRACT, HASH, VERIFY_OPERATION, prover(&calls)).unwrap();
+        cache.get_or_prove(CONTRACT, HASH, VERIFY_OPERATION, prover(&calls)).unwrap();
+        assert_eq!(calls.get(), 2);
+        assert_eq!(cache.stats().unwrap().entries, 0);
+    }
+    
+    #[test]
+    fn operations_name_their_contract() {
+        assert_eq!(operation_contract("ledger.transfer"), "ledger");
+        assert_eq!(operation_contract("snapshot"), "snapshot");
+    }
+}
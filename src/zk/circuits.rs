@@ -0,0 +1,181 @@
+// SentientOS ZK Circuits
+// Circuits that contract methods prove their results with
+
+use anyhow::Result;
+use ark_bn254::Fr;
+use ark_ff::{Field, PrimeField};
+use ark_r1cs_std::prelude::*;
+use ark_r1cs_std::fields::fp::FpVar;
+use ark_relations::r1cs::{ConstraintSynthesizer, ConstraintSystemRef, SynthesisError};
+use std::sync::OnceLock;
+
+// Constants
+const MIMC_ROUNDS: usize = 91;
+const MIMC_EXPONENT: u64 = 7;
+const ROUND_CONSTANT_DOMAIN: &str = "sentientos.zk.mimc";
+
+/// A circuit a contract proves its method results with
+///
+/// Circuits are synthesized twice: once without a witness to generate the
+/// contract's parameters, and once with the witness to prove. Public inputs
+/// must be allocated in the order `public_inputs` returns them.
+pub trait ZkCircuit {
+    /// Name the circuit's parameters are stored under, usually the contract name
+    fn name(&self) -> &str;
+    
+    /// Public inputs a verifier checks a proof of this witness against
+    fn public_inputs(&self, witness: &[u8]) -> Result<Vec<Fr>>;
+    
+    /// Enforce the circuit's constraints; `witness` is `None` during setup
+    fn synthesize(&self, cs: ConstraintSystemRef<Fr>, witness: Option<&[u8]>) -> Result<(), SynthesisError>;
+}
+
+/// Adapts a `ZkCircuit` to the proving backend
+pub(super) struct Synthesizer<'a> {
+    circuit: &'a dyn ZkCircuit,
+    witness: Option<&'a [u8]>,
+}
+
+impl<'a> Synthesizer<'a> {
+    /// Synthesizer for parameter generation
+    pub(super) fn setup(circuit: &'a dyn ZkCircuit) -> Self {
+        Self { circuit, witness: None }
+    }
+    
+    /// Synthesizer for proving a witness
+    pub(super) fn prove(circuit: &'a dyn ZkCircuit, witness: &'a [u8]) -> Self {
+        Self { circuit, witness: Some(witness) }
+    }
+}
+
+impl ConstraintSynthesizer<Fr> for Synthesizer<'_> {
+    fn generate_constraints(self, cs: ConstraintSystemRef<Fr>) -> Result<(), SynthesisError> {
+        self.circuit.synthesize(cs, self.witness)
+    }
+}
+
+/// Proves knowledge of data committed to under a contract operation
+///
+/// The witness is the proven data. Its hash is the private input; the
+/// operation tag and the MiMC commitment of the hash under that tag are
+/// public, so one set of parameters serves every method of a contract.
+pub struct DigestCircuit {
+    /// Contract the parameters belong to
+    contract: String,
+    
+    /// Operation the proof is bound to
+    operation: String,
+}
+
+impl DigestCircuit {
+    /// Circuit of an operation (`<contract>.<method>` or a bare name)
+    pub fn new(operation: &str) -> Self {
+        Self {
+            contract: super::cache::operation_contract(operation).to_string(),
+            operation: operation.to_string(),
+        }
+    }
+}
+
+impl ZkCircuit for DigestCircuit {
+    fn name(&self) -> &str {
+        &self.contract
+    }
+    
+    fn public_inputs(&self, witness: &[u8]) -> Result<Vec<Fr>> {
+        let tag = hash_to_field(self.operation.as_bytes());
+        Ok(vec![tag, mimc(hash_to_field(witness), tag)])
+    }
+    
+    fn synthesize(&self, cs: ConstraintSystemRef<Fr>, witness: Option<&[u8]>) -> Result<(), SynthesisError> {
+        let tag = FpVar::new_input(cs.clone(), || Ok(hash_to_field(self.operation.as_bytes())))?;
+        let commitment = FpVar::new_input(cs.clone(), || {
+            let digest = hash_to_field(witness.ok_or(SynthesisError::AssignmentMissing)?);
+            Ok(mimc(digest, hash_to_field(self.operation.as_bytes())))
+        })?;
+        let digest = FpVar::new_witness(cs, || {
+            Ok(hash_to_field(witness.ok_or(SynthesisError::AssignmentMissing)?))
+        })?;
+        
+        let mut state = digest;
+        for constant in round_constants() {
+            let t = &state + &tag + FpVar::constant(*constant);
+            let t2 = t.square()?;
+            let t4 = t2.square()?;
+            state = t4 * t2 * t;
+        }
+        (state + tag).enforce_equal(&commitment)
+    }
+}
+
+/// MiMC-7 of `x` under `key`, as enforced by `DigestCircuit`
+pub fn mimc(x: Fr, key: Fr) -> Fr {
+    let mut state = x;
+    for constant in round_constants() {
+        state = (state + key + constant).pow([MIMC_EXPONENT]);
+    }
+    state + key
+}
+
+/// Map bytes to a field element through their BLAKE3 hash
+pub fn hash_to_field(bytes: &[u8]) -> Fr {
+    Fr::from_le_bytes_mod_order(blake3::hash(bytes).as_bytes())
+}
+
+/// Round constants, derived deterministically so every node agrees on them
+fn round_constants() -> &'static [Fr] {
+    static CONSTANTS: OnceLock<Vec<Fr>> = OnceLock::new();
+    CONSTANTS.get_or_init(|| {
+        (0..MIMC_ROUNDS)
+            .map(|round| hash_to_field(format!("{}.{}", ROUND_CONSTANT_DOMAIN, round).as_bytes()))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ark_relations::r1cs::ConstraintSystem;
+    
+    fn satisfied(circuit: &DigestCircuit, witness: &[u8]) -> bool {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        circuit.synthesize(cs.clone(), Some(witness)).unwrap();
+        cs.is_satisfied().unwrap()
+    }
+    
+    #[test]
+    fn witness_satisfies_the_digest_circuit() {
+        let circuit = DigestCircuit::new("ledger.transfer");
+        assert!(satisfied(&circuit, b"balance: 42"));
+        assert!(satisfied(&circuit, b""));
+    }
+    
+    #[test]
+    fn public_inputs_are_the_tag_and_commitment() {
+        let circuit = DigestCircuit::new("ledger.transfer");
+        let tag = hash_to_field(b"ledger.transfer");
+        let inputs = circuit.public_inputs(b"balance: 42").unwrap();
+        assert_eq!(inputs, vec![tag, mimc(hash_to_field(b"balance: 42"), tag)]);
+        
+        // Every method of a contract shares its parameters, but not its tag
+        let burn = DigestCircuit::new("ledger.burn");
+        assert_eq!(burn.name(), circuit.name());
+        assert_ne!(burn.public_inputs(b"balance: 42").unwrap()[0], tag);
+    }
+    
+    #[test]
+    fn setup_synthesizes_without_a_witness() {
+        let cs = ConstraintSystem::<Fr>::new_ref();
+        cs.set_mode(ark_relations::r1cs::SynthesisMode::Setup);
+        DigestCircuit::new("ledger.transfer").synthesize(cs.clone(), None).unwrap();
+        assert_eq!(cs.num_instance_variables(), 3);
+        assert_eq!(cs.num_constraints(), 4 * MIMC_ROUNDS + 1);
+    }
+    
+    #[test]
+    fn round_constants_are_deterministic() {
+        assert_eq!(round_constants().len(), MIMC_ROUNDS);
+        assert_eq!(round_constants()[0], hash_to_field(format!("{}.0", ROUND_CONSTANT_DOMAIN).as_bytes()));
+        assert_ne!(round_constants()[0], round_constants()[1]);
+    }
+}
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
-use anyhow::Result;
+use anyhow::{Result, Context};
+use thiserror::Error;
 
 /// ZK-YAML contract structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +18,14 @@ pub struct ZkContract {
     /// Contract description
     pub description: Option<String>,
     
+    /// Contracts whose rules and methods are imported, by name in .zk/contracts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub imports: Vec<String>,
+    
+    /// Contracts whose methods are callable as helpers, as `name@version`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
+    
     /// Contract permissions
     pub permissions: Permissions,
     
@@ -28,6 +37,80 @@ pub struct ZkContract {
     
     /// Contract methods
     pub methods: HashMap<String, Method>,
+    
+    /// Execution budgets by method name
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub limits: HashMap<String, MethodLimits>,
+    
+    /// Encrypt persisted state at rest with a per-contract key
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub state_encryption: bool,
+    
+    /// Steps migrating persisted state written by earlier versions
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub migrations: Vec<MigrationStep>,
+}
+
+/// Migration of persisted state from one contract version to another
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationStep {
+    /// Version the state was written by
+    pub from_version: String,
+    
+    /// Version the state is in after the step
+    pub to_version: String,
+    
+    /// Operations, one per line: `set <var> = <json>`, `default <var> = <json>`,
+    /// `rename <var> to <var>` or `remove <var>`; lines starting with `#` are comments
+    pub script: String,
+}
+
+/// A state migration step that failed
+#[derive(Debug, Clone, Error)]
+#[error("State migration step {step} ({from_version} -> {to_version}) failed: {reason}")]
+pub struct MigrationFailed {
+    /// Index of the step in the contract's `migrations`
+    pub step: usize,
+    
+    /// Version the step migrates from
+    pub from_version: String,
+    
+    /// Version the step migrates to
+    pub to_version: String,
+    
+    /// What went wrong
+    pub reason: String,
+    
+    /// State as it was when the step failed
+    pub state: serde_json::Value,
+}
+
+/// One operation of a migration script
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum MigrationOp {
+    /// Set a variable, replacing its value
+    Set(String, serde_json::Value),
+    
+    /// Set a variable unless it already has a value
+    Default(String, serde_json::Value),
+    
+    /// Rename a variable
+    Rename(String, String),
+    
+    /// Drop a variable
+    Remove(String),
+}
+
+/// Execution budget of a method; invocations over budget fail
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MethodLimits {
+    /// Maximum interpreter steps, statements and expressions evaluated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_steps: Option<u64>,
+    
+    /// Maximum bytes of contract state read or written
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_state_bytes: Option<u64>,
 }
 
 /// Contract permissions
@@ -95,6 +178,29 @@ pub struct StateVariable {
     pub zk_verified: bool,
 }
 
+impl StateVariable {
+    /// Initial value of the variable; defaults that are not JSON are strings
+    pub fn default_value(&self) -> serde_json::Value {
+        self.default.as_deref()
+            .map(|d| serde_json::from_str(d).unwrap_or_else(|_| serde_json::Value::String(d.to_string())))
+            .unwrap_or(serde_json::Value::Null)
+    }
+    
+    /// Whether a value fits the variable's type; unknown types accept anything
+    pub fn accepts(&self, value: &serde_json::Value) -> bool {
+        let width = |prefix: char| self.var_type.strip_prefix(prefix).map_or(false, |w| w.parse::<u32>().is_ok());
+        match self.var_type.as_str() {
+            _ if value.is_null() => true,
+            "bool" => value.is_boolean(),
+            "string" | "address" => value.is_string(),
+            "f32" | "f64" => value.is_number(),
+            _ if width('u') => value.is_u64(),
+            _ if width('i') => value.is_i64(),
+            _ => true,
+        }
+    }
+}
+
 /// Contract rule
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Rule {
@@ -140,6 +246,8 @@ pub fn new_contract(name: &str, version: &str) -> ZkContract {
         version: version.to_string(),
         author: None,
         description: None,
+        imports: Vec::new(),
+        dependencies: Vec::new(),
         permissions: Permissions {
             filesystem: FilesystemPermissions {
                 read: Vec::new(),
@@ -159,9 +267,138 @@ pub fn new_contract(name: &str, version: &str) -> ZkContract {
         state: HashMap::new(),
         rules: Vec::new(),
         methods: HashMap::new(),
+        limits: HashMap::new(),
+        state_encryption: false,
+        migrations: Vec::new(),
+    }
+}
+
+/// Migrate state written by `from_version` of a contract to its current version
+///
+/// Steps are chained from `from_version` until the contract's version is
+/// reached. After each step, variables the contract declares must hold
+/// values of their type; after the last, declared variables still missing
+/// get their defaults and undeclared ones are an error. A failing step is
+/// reported as `MigrationFailed`.
+pub fn migrate_state(contract: &ZkContract, from_version: &str, old_state: serde_json::Value) -> Result<serde_json::Value> {
+    let mut state = old_state;
+    let mut version = from_version.to_string();
+    let mut applied = 0;
+    
+    while version != contract.version {
+        // A chain longer than the step list loops
+        if applied == contract.migrations.len() {
+            anyhow::bail!("Migrations of contract {} do not lead from {} to {}", contract.name, from_version, contract.version);
+        }
+        let (index, step) = contract.migrations.iter()
+            .enumerate()
+            .find(|(_, step)| step.from_version == version)
+            .ok_or_else(|| anyhow::anyhow!("Contract {} has no migration from version {}", contract.name, version))?;
+        
+        let last = step.to_version == contract.version;
+        apply_migration_step(contract, step, &mut state, last).map_err(|e| MigrationFailed {
+            step: index,
+            from_version: step.from_version.clone(),
+            to_version: step.to_version.clone(),
+            reason: format!("{:#}", e),
+            state: state.clone(),
+        })?;
+        
+        version = step.to_version.clone();
+        applied += 1;
+    }
+    
+    Ok(state)
+}
+
+/// Run one migration step and check the state it leaves
+fn apply_migration_step(contract: &ZkContract, step: &MigrationStep, state: &mut serde_json::Value, last: bool) -> Result<()> {
+    let variables = state.as_object_mut().ok_or_else(|| anyhow::anyhow!("state is not an object"))?;
+    
+    for op in parse_migration_script(&step.script)? {
+        match op {
+            MigrationOp::Set(name, value) => {
+                variables.insert(name, value);
+            }
+            MigrationOp::Default(name, value) => {
+                variables.entry(name).or_insert(value);
+            }
+            MigrationOp::Rename(from, to) => {
+                if variables.contains_key(&to) {
+                    anyhow::bail!("cannot rename `{}` to `{}`, which already exists", from, to);
+                }
+                let value = variables.remove(&from)
+                    .ok_or_else(|| anyhow::anyhow!("cannot rename `{}`, which does not exist", from))?;
+                variables.insert(to, value);
+            }
+            MigrationOp::Remove(name) => {
+                variables.remove(&name);
+            }
+        }
+    }
+    
+    if last {
+        for (name, variable) in &contract.state {
+            variables.entry(name.clone()).or_insert_with(|| variable.default_value());
+        }
+    }
+    
+    let mut names: Vec<&String> = variables.keys().collect();
+    names.sort();
+    for name in names {
+        match contract.state.get(name) {
+            Some(variable) if !variable.accepts(&variables[name]) => {
+                anyhow::bail!("`{}` holds {} but is declared {}", name, variables[name], variable.var_type);
+            }
+            None if last => anyhow::bail!("`{}` is not declared by version {}", name, contract.version),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Parse a migration script into its operations
+pub(super) fn parse_migration_script(script: &str) -> Result<Vec<MigrationOp>> {
+    script.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .map(|(number, line)| parse_migration_op(line).with_context(|| format!("line {}: `{}`", number, line)))
+        .collect()
+}
+
+/// Parse one line of a migration script
+fn parse_migration_op(line: &str) -> Result<MigrationOp> {
+    let (keyword, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let rest = rest.trim();
+    
+    match keyword {
+        "set" | "default" => {
+            let (name, value) = rest.split_once('=')
+                .ok_or_else(|| anyhow::anyhow!("expected `{} <variable> = <json value>`", keyword))?;
+            let name = migration_variable(name)?;
+            let value = serde_json::from_str(value.trim()).context("invalid JSON value")?;
+            Ok(if keyword == "set" { MigrationOp::Set(name, value) } else { MigrationOp::Default(name, value) })
+        }
+        "rename" => {
+            let (from, to) = rest.split_once(" to ")
+                .ok_or_else(|| anyhow::anyhow!("expected `rename <variable> to <variable>`"))?;
+            Ok(MigrationOp::Rename(migration_variable(from)?, migration_variable(to)?))
+        }
+        "remove" => Ok(MigrationOp::Remove(migration_variable(rest)?)),
+        _ => anyhow::bail!("unknown operation `{}`", keyword),
     }
 }
 
+/// Variable name in a migration script
+fn migration_variable(name: &str) -> Result<String> {
+    let name = name.trim();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        anyhow::bail!("invalid variable name `{}`", name);
+    }
+    Ok(name.to_string())
+}
+
 /// Example ZK-YAML contract
 pub fn example_contract() -> String {
     r#"# Example ZK-YAML Contract
@@ -259,6 +496,11 @@ methods:
       return state.counter;
     pure: true
     zk_verified: false
+
+# Execution budgets
+limits:
+  increment:
+    max_steps: 10000
 "#.to_string()
 }
 
@@ -275,3 +517,116 @@ pub fn load_contract(path: &str) -> Result<ZkContract> {
     let contract: ZkContract = serde_yaml::from_str(&yaml)?;
     Ok(contract)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    
+    fn variable(var_type: &str, default: Option<&str>) -> StateVariable {
+        StateVariable {
+            var_type: var_type.to_string(),
+            default: default.map(str::to_string),
+            mutable: true,
+            zk_verified: false,
+        }
+    }
+    
+    fn step(from_version: &str, to_version: &str, script: &str) -> MigrationStep {
+        MigrationStep { from_version: from_version.to_string(), to_version: to_version.to_string(), script: script.to_string() }
+    }
+    
+    /// Version 2.0.0 of a contract whose `counter` became `count` in 1.1.0
+    fn migrating_contract() -> ZkContract {
+        let mut contract = new_contract("migrate-test", "2.0.0");
+        contract.state.insert("count".to_string(), variable("u64", Some("0")));
+        contract.state.insert("label".to_string(), variable("string", Some("none")));
+        contract.state.insert("enabled".to_string(), variable("bool", None));
+        contract.migrations = vec![
+            step("1.1.0", "2.0.0", "remove legacy\ndefault enabled = false"),
+            step("1.0.0", "1.1.0", "rename counter to count\n# enabled is new\nset enabled = true"),
+        ];
+        contract
+    }
+    
+    #[test]
+    fn steps_are_chained_to_the_current_version() {
+        let contract = migrating_contract();
+        let migrated = migrate_state(&contract, "1.0.0", json!({"counter": 5, "legacy": "x"})).unwrap();
+        assert_eq!(migrated, json!({"count": 5, "enabled": true, "label": "none"}));
+        
+        // Starting part-way skips the earlier steps
+        let migrated = migrate_state(&contract, "1.1.0", json!({"count": 2})).unwrap();
+        assert_eq!(migrated, json!({"count": 2, "enabled": false, "label": "none"}));
+        
+        // Current state is left alone
+        let current = json!({"count": 1, "label": "x", "enabled": null});
+        assert_eq!(migrate_state(&contract, "2.0.0", current.clone()).unwrap(), current);
+    }
+    
+    #[test]
+    fn failing_steps_report_where_they_stopped() {
+        let contract = migrating_contract();
+        
+        let error = migrate_state(&contract, "1.0.0", json!({"counter": "five"})).unwrap_err();
+        let failed = error.downcast_ref::<MigrationFailed>().unwrap();
+        assert_eq!((failed.step, failed.from_version.as_str(), failed.to_version.as_str()), (1, "1.0.0", "1.1.0"));
+        assert_eq!(failed.state, json!({"count": "five", "enabled": true}));
+        assert_eq!(error.to_string(), "State migration step 1 (1.0.0 -> 1.1.0) failed: `count` holds \"five\" but is declared u64");
+        
+        // Variables the last version does not declare cannot be carried over
+        let error = migrate_state(&contract, "1.0.0", json!({"counter": 1, "extra": 2})).unwrap_err();
+        assert_eq!(error.downcast_ref::<MigrationFailed>().unwrap().step, 0);
+        assert!(error.to_string().ends_with("`extra` is not declared by version 2.0.0"), "{}", error);
+        
+        let error = migrate_state(&contract, "1.0.0", json!({"counter": 1, "count": 2})).unwrap_err();
+        assert!(error.to_string().ends_with("cannot rename `counter` to `count`, which already exists"), "{}", error);
+        assert!(migrate_state(&contract, "1.0.0", json!([1, 2])).unwrap_err().to_string().ends_with("state is not an object"));
+    }
+    
+    #[test]
+    fn missing_and_looping_chains_are_refused() {
+        let mut contract = migrating_contract();
+        let error = migrate_state(&contract, "0.9.0", json!({})).unwrap_err();
+        assert_eq!(error.to_string(), "Contract migrate-test has no migration from version 0.9.0");
+        
+        contract.migrations = vec![step("1.0.0", "1.1.0", ""), step("1.1.0", "1.0.0", "")];
+        let error = migrate_state(&contract, "1.0.0", json!({})).unwrap_err();
+        assert_eq!(error.to_string(), "Migrations of contract migrate-test do not lead from 1.0.0 to 2.0.0");
+    }
+    
+    #[test]
+    fn scripts_parse_line_by_line() {
+        let ops = parse_migration_script("set a = {\"x\": [1]}\n\n  # note\ndefault b = \"s\"\nrename a to c\nremove b").unwrap();
+        assert_eq!(ops, vec![
+            MigrationOp::Set("a".to_string(), json!({"x": [1]})),
+            MigrationOp::Default("b".to_string(), json!("s")),
+            MigrationOp::Rename("a".to_string(), "c".to_string()),
+            MigrationOp::Remove("b".to_string()),
+        ]);
+        
+        let error = parse_migration_script("remove a\nfrobnicate a").unwrap_err();
+        assert_eq!(format!("{:#}", error), "line 2: `frobnicate a`: unknown operation `frobnicate`");
+        assert!(parse_migration_script("set a = not json").is_err());
+        assert!(parse_migration_script("set a 1").is_err());
+        assert!(parse_migration_script("rename a b").is_err());
+        assert!(parse_migration_script("remove a b").is_err());
+        assert!(parse_migration_script("remove").is_err());
+    }
+    
+    #[test]
+    fn variables_accept_values_of_their_type() {
+        assert!(variable("u64", None).accepts(&json!(3)));
+        assert!(!variable("u64", None).accepts(&json!(-3)));
+        assert!(variable("i32", None).accepts(&json!(-3)));
+        assert!(!variable("bool", None).accepts(&json!("true")));
+        assert!(!variable("address", None).accepts(&json!(1)));
+        assert!(variable("f64", None).accepts(&json!(1.5)));
+        assert!(variable("map", None).accepts(&json!({"any": "thing"})));
+        assert!(variable("string", None).accepts(&serde_json::Value::Null));
+        
+        assert_eq!(variable("u64", Some("7")).default_value(), json!(7));
+        assert_eq!(variable("string", Some("plain")).default_value(), json!("plain"));
+        assert_eq!(variable("bool", None).default_value(), serde_json::Value::Null);
+    }
+}
@@ -0,0 +1,633 @@
+// SentientOS ZK Contract Deployment
+// Peer-reviewed proposal, approval and deployment of contract versions
+
+use anyhow::{Result, Context};
+use tracing::{info, debug, warn};
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use crate::core::{constants, identity, lock};
+use crate::gossip::protocol::MessageType;
+use super::verify::CONTRACT_STATE_LOCK;
+use super::simulate::Simulation;
+
+// Constants
+const PROPOSALS_DIR: &str = "proposals";
+const APPROVALS_DIR: &str = "approvals";
+const PROPOSAL_FILE: &str = "proposal.json";
+const DEPLOYMENT_SUFFIX: &str = "deployment.json";
+
+/// Contract deployment policy, stored under `contract_deployment` in security.json
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeploymentPolicy {
+    /// Node IDs allowed to approve proposals
+    #[serde(default)]
+    pub approvers: Vec<String>,
+    
+    /// Number of distinct approvals required to deploy
+    #[serde(default = "default_quorum")]
+    pub quorum: usize,
+}
+
+fn default_quorum() -> usize {
+    1
+}
+
+impl Default for DeploymentPolicy {
+    fn default() -> Self {
+        Self {
+            approvers: Vec::new(),
+            quorum: default_quorum(),
+        }
+    }
+}
+
+/// Signed proposal to deploy a contract version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Proposal {
+    /// Proposal ID
+    pub id: String,
+    
+    /// Name of the proposed contract
+    pub contract_name: String,
+    
+    /// Hash of the contract file content
+    pub contract_hash: String,
+    
+    /// Contract file content
+    pub content: String,
+    
+    /// Proposing node
+    pub author: String,
+    
+    /// Description of the change
+    pub changelog: String,
+    
+    /// Proposal timestamp
+    pub created_at: u64,
+    
+    /// Simulated calls of the proposed version, so approvers see its effects
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub simulations: Vec<Simulation>,
+    
+    /// Author signature over the proposal
+    pub signature: String,
+}
+
+/// Signed approval of a proposal
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Approval {
+    /// Approved proposal
+    pub proposal_id: String,
+    
+    /// Contract hash the approval covers
+    pub contract_hash: String,
+    
+    /// Approving node
+    pub approver: String,
+    
+    /// Approval timestamp
+    pub approved_at: u64,
+    
+    /// Approver signature
+    pub signature: String,
+}
+
+/// Record installed alongside a deployed contract
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeploymentRecord {
+    /// Deployed proposal
+    pub proposal_id: String,
+    
+    /// Hash of the deployed contract content
+    pub contract_hash: String,
+    
+    /// Proposing node
+    pub author: String,
+    
+    /// Description of the change
+    pub changelog: String,
+    
+    /// Approvals that formed the quorum
+    pub approvals: Vec<Approval>,
+    
+    /// Deployment timestamp
+    pub deployed_at: u64,
+}
+
+/// Where a deployed contract's current content came from
+#[derive(Debug, Clone)]
+pub enum ContractProvenance {
+    /// Installed from an approved proposal and unchanged since
+    Approved { proposal_id: String, approvals: usize },
+    
+    /// Edited or installed locally, outside the deployment workflow
+    LocalEdit,
+}
+
+/// Propose a contract file for deployment
+///
+/// Each call in `simulate` (method and arguments) is simulated on the proposed
+/// version against the current persisted state, and the transcripts are signed
+/// into the proposal.
+pub fn propose(path: &str, changelog: &str, simulate: &[(String, Vec<serde_json::Value>)]) -> Result<Proposal> {
+    let full_path = constants::root_dir().join(path);
+    let content = fs::read_to_string(&full_path)
+        .with_context(|| format!("Failed to read contract: {:?}", full_path))?;
+    
+    // Refuse to propose anything that wouldn't load
+    let contract = super::parser::parse_zk_yaml(&content)?;
+    
+    let simulations = simulate.iter()
+        .map(|(method, args)| super::simulate_contract_method(&contract, method, args)
+            .with_context(|| format!("Failed to simulate {}.{}", contract.name, method)))
+        .collect::<Result<Vec<_>>>()?;
+    
+    let author = identity::node_id()?;
+    let created_at = now();
+    let contract_hash = blake3::hash(content.as_bytes()).to_hex().to_string();
+    let id = blake3::hash(format!("{}:{}:{}", contract_hash, author, created_at).as_bytes())
+        .to_hex()[..16].to_string();
+    
+    let mut proposal = Proposal {
+        id,
+        contract_name: contract.name,
+        contract_hash,
+        content,
+        author,
+        changelog: changelog.to_string(),
+        created_at,
+        simulations,
+        signature: String::new(),
+    };
+    proposal.signature = identity::sign(proposal_payload(&proposal)?.as_bytes())?;
+    
+    save_proposal(&proposal)?;
+    broadcast(MessageType::ContractProposal, &proposal);
+    
+    info!("Created contract proposal {} for {}", proposal.id, proposal.contract_name);
+    Ok(proposal)
+}
+
+/// Approve a proposal as this node
+pub fn approve(proposal_id: &str) -> Result<Approval> {
+    let proposal = load_proposal(proposal_id)?;
+    let approver = identity::node_id()?;
+    
+    let policy = load_policy()?;
+    if !policy.approvers.contains(&approver) {
+        anyhow::bail!("This node ({}) is not a designated contract approver", approver);
+    }
+    
+    verify_signature(&proposal.author, proposal_payload(&proposal)?.as_bytes(), &proposal.signature)
+        .context("Proposal signature is invalid")?;
+    
+    let mut approval = Approval {
+        proposal_id: proposal.id.clone(),
+        contract_hash: proposal.contract_hash.clone(),
+        approver,
+        approved_at: now(),
+        signature: String::new(),
+    };
+    approval.signature = identity::sign(approval_payload(&approval)?.as_bytes())?;
+    
+    save_approval(&approval)?;
+    broadcast(MessageType::ContractApproval, &approval);
+    
+    info!("Approved contract proposal {}", proposal_id);
+    Ok(approval)
+}
+
+/// Deploy a proposal that has reached quorum, then hot-reload the contract
+pub fn deploy(proposal_id: &str) -> Result<DeploymentRecord> {
+    let proposal = load_proposal(proposal_id)?;
+    verify_signature(&proposal.author, proposal_payload(&proposal)?.as_bytes(), &proposal.signature)
+        .context("Proposal signature is invalid")?;
+    
+    check_file_name("contract name", &proposal.contract_name)?;
+    
+    let policy = load_policy()?;
+    let approvals = valid_approvals(&proposal, &policy)?;
+    if approvals.len() < policy.quorum.max(1) {
+        anyhow::bail!("Proposal {} lacks quorum: {} of {} required approvals",
+                      proposal_id, approvals.len(), policy.quorum.max(1));
+    }
+    
+    let record = DeploymentRecord {
+        proposal_id: proposal.id.clone(),
+        contract_hash: proposal.contract_hash.clone(),
+        author: proposal.author.clone(),
+        changelog: proposal.changelog.clone(),
+        approvals,
+        deployed_at: now(),
+    };
+    
+    let relative_path = format!(".zk/contracts/{}.yaml", proposal.contract_name);
+    {
+        let _lock = lock::lock(CONTRACT_STATE_LOCK, &format!("deploy {}", proposal.id), lock::DEFAULT_TIMEOUT)?;
+        
+        let contracts_dir = constants::root_dir().join(".zk").join("contracts");
+        fs::create_dir_all(&contracts_dir)?;
+        fs::write(contracts_dir.join(format!("{}.yaml", proposal.contract_name)), &proposal.content)
+            .context("Failed to install contract")?;
+        fs::write(deployment_record_path(&proposal.contract_name), serde_json::to_string_pretty(&record)?)
+            .context("Failed to record contract approvals")?;
+    }
+    
+    // Hot-reload the installed contract
+    let contract = super::load_contract(&relative_path)?;
+    if !super::verify_contract(&contract)? {
+        warn!("Deployed contract {} failed verification after reload", contract.name);
+    }
+    
+    info!("Deployed contract {} from proposal {} ({} approvals)",
+          proposal.contract_name, proposal.id, record.approvals.len());
+    Ok(record)
+}
+
+/// List proposals with their number of valid approvals
+pub fn list_proposals() -> Result<Vec<(Proposal, usize)>> {
+    let dir = proposals_dir();
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let policy = load_policy()?;
+    let mut proposals = Vec::new();
+    for entry in fs::read_dir(&dir)?.filter_map(Result::ok) {
+        let id = entry.file_name().to_string_lossy().to_string();
+        if let Ok(proposal) = load_proposal(&id) {
+            let approvals = valid_approvals(&proposal, &policy)?.len();
+            proposals.push((proposal, approvals));
+        }
+    }
+    
+    proposals.sort_by_key(|(p, _)| p.created_at);
+    Ok(proposals)
+}
+
+/// Whether a deployed contract carries an approval record for its current content
+pub fn provenance(contract_name: &str) -> Result<ContractProvenance> {
+    let record_path = deployment_record_path(contract_name);
+    let contract_path = constants::root_dir().join(".zk").join("contracts")
+        .join(format!("{}.yaml", contract_name));
+    
+    if !record_path.exists() || !contract_path.exists() {
+        return Ok(ContractProvenance::LocalEdit);
+    }
+    
+    let record: DeploymentRecord = serde_json::from_str(&fs::read_to_string(&record_path)?)?;
+    let current_hash = blake3::hash(&fs::read(&contract_path)?).to_hex().to_string();
+    
+    if current_hash == record.contract_hash {
+        Ok(ContractProvenance::Approved { proposal_id: record.proposal_id, approvals: record.approvals.len() })
+    } else {
+        Ok(ContractProvenance::LocalEdit)
+    }
+}
+
+/// Store a proposal gossiped by a trusted peer
+pub fn handle_peer_proposal(source_id: &str, payload: &[u8]) -> Result<()> {
+    if !crate::gossip::is_trusted_peer(source_id) {
+        debug!("Ignoring contract proposal from untrusted peer: {}", source_id);
+        return Ok(());
+    }
+    
+    let proposal: Proposal = serde_json::from_slice(payload)
+        .context("Failed to parse contract proposal")?;
+    
+    if blake3::hash(proposal.content.as_bytes()).to_hex().to_string() != proposal.contract_hash {
+        anyhow::bail!("Contract proposal {} from {} has a mismatched content hash", proposal.id, source_id);
+    }
+    verify_signature(&proposal.author, proposal_payload(&proposal)?.as_bytes(), &proposal.signature)
+        .with_context(|| format!("Invalid contract proposal signature from {}", source_id))?;
+    
+    if !proposal_dir(&proposal.id).join(PROPOSAL_FILE).exists() {
+        save_proposal(&proposal)?;
+        info!("Received contract proposal {} for {} from {}", proposal.id, proposal.contract_name, source_id);
+    }
+    Ok(())
+}
+
+/// Store an approval gossiped by a trusted peer
+pub fn handle_peer_approval(source_id: &str, payload: &[u8]) -> Result<()> {
+    if !crate::gossip::is_trusted_peer(source_id) {
+        debug!("Ignoring contract approval from untrusted peer: {}", source_id);
+        return Ok(());
+    }
+    
+    let approval: Approval = serde_json::from_slice(payload)
+        .context("Failed to parse contract approval")?;
+    
+    verify_signature(&approval.approver, approval_payload(&approval)?.as_bytes(), &approval.signature)
+        .with_context(|| format!("Invalid contract approval signature from {}", source_id))?;
+    
+    save_approval(&approval)?;
+    debug!("Stored approval of {} by {}", approval.proposal_id, approval.approver);
+    Ok(())
+}
+
+/// Approvals of a proposal that count towards quorum
+///
+/// An approval counts if it comes from a designated approver, covers the
+/// proposal's content hash, and carries a valid signature. Each approver
+/// counts once.
+fn valid_approvals(proposal: &Proposal, policy: &DeploymentPolicy) -> Result<Vec<Approval>> {
+    let dir = proposal_dir(&proposal.id).join(APPROVALS_DIR);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let mut seen = BTreeSet::new();
+    let mut approvals = Vec::new();
+    
+    for entry in fs::read_dir(&dir)?.filter_map(Result::ok) {
+        let approval: Approval = match fs::read_to_string(entry.path()).ok().and_then(|c| serde_json::from_str(&c).ok()) {
+            Some(approval) => approval,
+            None => continue,
+        };
+        
+        if approval.proposal_id != proposal.id
+            || approval.contract_hash != proposal.contract_hash
+            || !policy.approvers.contains(&approval.approver)
+            || seen.contains(&approval.approver)
+        {
+            continue;
+        }
+        
+        if let Err(e) = verify_signature(&approval.approver, approval_payload(&approval)?.as_bytes(), &approval.signature) {
+            warn!("Ignoring approval of {} by {}: {}", proposal.id, approval.approver, e);
+            continue;
+        }
+        
+        seen.insert(approval.approver.clone());
+        approvals.push(approval);
+    }
+    
+    Ok(approvals)
+}
+
+/// Verify a signature by a node, using our own key or the peer's verified key
+fn verify_signature(node_id: &str, message: &[u8], signature: &str) -> Result<()> {
+    let own = identity::current()?;
+    let public_key = if own.id == node_id {
+        own.public_key
+    } else {
+        crate::gossip::peer_public_key(node_id)
+            .ok_or_else(|| anyhow::anyhow!("No verified public key for node {}", node_id))?
+    };
+    
+    identity::verify(&public_key, message, signature)
+}
+
+/// Canonical bytes covered by a proposal signature
+fn proposal_payload(proposal: &Proposal) -> Result<String> {
+    let mut payload = serde_json::json!({
+        "id": proposal.id,
+        "contract_name": proposal.contract_name,
+        "contract_hash": proposal.contract_hash,
+        "author": proposal.author,
+        "changelog": proposal.changelog,
+        "created_at": proposal.created_at,
+    });
+    // Only covered when present, so proposals without transcripts keep verifying
+    if !proposal.simulations.is_empty() {
+        let transcripts = serde_json::to_string(&proposal.simulations)?;
+        payload["simulations_hash"] = blake3::hash(transcripts.as_bytes()).to_hex().to_string().into();
+    }
+    Ok(serde_json::to_string(&payload)?)
+}
+
+/// Canonical bytes covered by an approval signature
+fn approval_payload(approval: &Approval) -> Result<String> {
+    Ok(serde_json::to_string(&serde_json::json!({
+        "proposal_id": approval.proposal_id,
+        "contract_hash": approval.contract_hash,
+        "approver": approval.approver,
+        "approved_at": approval.approved_at,
+    }))?)
+}
+
+/// Send a proposal or approval to trusted peers
+fn broadcast<T: Serialize>(message_type: MessageType, item: &T) {
+    let payload = match serde_json::to_vec(item) {
+        Ok(payload) => payload,
+        Err(e) => {
+            warn!("Failed to serialize {:?}: {}", message_type, e);
+            return;
+        }
+    };
+    
+    let peers = match crate::gossip::list_peers() {
+        Ok(peers) => peers,
+        Err(e) => {
+            warn!("Failed to list peers for {:?}: {}", message_type, e);
+            return;
+        }
+    };
+    
+    for peer in peers.iter().filter(|p| crate::gossip::is_trusted_peer(&p.id)) {
+        if let Err(e) = crate::gossip::protocol::send_message(&peer.endpoint, message_type, &payload) {
+            warn!("Failed to send {:?} to {}: {}", message_type, peer.id, e);
+        }
+    }
+}
+
+/// Load the deployment policy from security.json
+fn load_policy() -> Result<DeploymentPolicy> {
+    let path = constants::root_dir().join(".config").join("security.json");
+    if !path.exists() {
+        return Ok(DeploymentPolicy::default());
+    }
+    
+    let security: serde_json::Value = serde_json::from_str(&fs::read_to_string(&path)?)?;
+    match security.get("contract_deployment") {
+        Some(value) => Ok(serde_json::from_value(value.clone())
+            .context("Invalid contract_deployment policy in security.json")?),
+        None => Ok(DeploymentPolicy::default()),
+    }
+}
+
+fn load_proposal(proposal_id: &str) -> Result<Proposal> {
+    if proposal_id.is_empty() || !proposal_id.chars().all(|c| c.is_ascii_hexdigit()) {
+        anyhow::bail!("Invalid proposal ID: {}", proposal_id);
+    }
+    
+    let path = proposal_dir(proposal_id).join(PROPOSAL_FILE);
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Proposal not found: {}", proposal_id))?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+fn save_proposal(proposal: &Proposal) -> Result<()> {
+    let dir = proposal_dir(&proposal.id);
+    fs::create_dir_all(dir.join(APPROVALS_DIR))?;
+    fs::write(dir.join(PROPOSAL_FILE), serde_json::to_string_pretty(proposal)?)?;
+    Ok(())
+}
+
+fn save_approval(approval: &Approval) -> Result<()> {
+    check_file_name("approver ID", &approval.approver)?;
+    
+    let dir = proposal_dir(&approval.proposal_id).join(APPROVALS_DIR);
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join(format!("{}.json", approval.approver)), serde_json::to_string_pretty(approval)?)?;
+    Ok(())
+}
+
+/// Reject values from proposals that would escape their directory when used as file names
+fn check_file_name(kind: &str, value: &str) -> Result<()> {
+    if value.is_empty() || value.contains(['/', '\\']) || value.starts_with('.') {
+        anyhow::bail!("Invalid {}: {}", kind, value);
+    }
+    Ok(())
+}
+
+/// Current time in seconds since the epoch
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+fn proposals_dir() -> PathBuf {
+    constants::root_dir().join(".zk").join(PROPOSALS_DIR)
+}
+
+fn proposal_dir(proposal_id: &str) -> PathBuf {
+    proposals_dir().join(proposal_id)
+}
+
+fn deployment_record_path(contract_name: &str) -> PathBuf {
+    constants::root_dir().join(".zk").join("contracts")
+        .join(format!("{}.{}", contract_name, DEPLOYMENT_SUFFIX))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Approval of a proposal signed by this node
+    fn own_approval(proposal: &Proposal, contract_hash: &str) -> Approval {
+        let mut approval = Approval {
+            proposal_id: proposal.id.clone(),
+            contract_hash: contract_hash.to_string(),
+            approver: identity::node_id().unwrap(),
+            approved_at: 1_700_000_000,
+            signature: String::new(),
+        };
+        approval.signature = identity::sign(approval_payload(&approval).unwrap().as_bytes()).unwrap();
+        approval
+    }
+    
+    /// Store an approval under an arbitrary file name
+    fn store_as(approval: &Approval, file_name: &str) {
+        let dir = proposal_dir(&approval.proposal_id).join(APPROVALS_DIR);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(file_name), serde_json::to_string(approval).unwrap()).unwrap();
+    }
+    
+    #[test]
+    fn only_matching_signed_approvals_from_approvers_count_once() {
+        let proposal = Proposal {
+            id: "00d3a1a7".to_string(),
+            contract_name: "quorum_contract".to_string(),
+            contract_hash: "c0ffee".to_string(),
+            content: String::new(),
+            author: identity::node_id().unwrap(),
+            changelog: "Initial version".to_string(),
+            created_at: 1_700_000_000,
+            simulations: Vec::new(),
+            signature: String::new(),
+        };
+        
+        let valid = own_approval(&proposal, "c0ffee");
+        save_approval(&valid).unwrap();
+        store_as(&valid, "duplicate.json");
+        store_as(&own_approval(&proposal, "5ca1ab1e"), "stale.json");
+        let mut forged = valid.clone();
+        forged.approved_at += 1;
+        store_as(&forged, "forged.json");
+        let mut stranger = valid.clone();
+        stranger.approver = "stranger".to_string();
+        save_approval(&stranger).unwrap();
+        
+        let policy = DeploymentPolicy { approvers: vec![valid.approver.clone()], quorum: 2 };
+        let approvals = valid_approvals(&proposal, &policy).unwrap();
+        assert_eq!(approvals.len(), 1);
+        assert_eq!(approvals[0].signature, valid.signature);
+        
+        let nobody = DeploymentPolicy::default();
+        assert!(valid_approvals(&proposal, &nobody).unwrap().is_empty());
+    }
+    
+    #[test]
+    fn provenance_follows_the_installed_content() {
+        let contracts_dir = constants::root_dir().join(".zk").join("contracts");
+        fs::create_dir_all(&contracts_dir).unwrap();
+        let contract_path = contracts_dir.join("provenance_contract.yaml");
+        fs::write(&contract_path, "name: provenance_contract\n").unwrap();
+        assert!(matches!(provenance("provenance_contract").unwrap(), ContractProvenance::LocalEdit));
+        
+        let record = DeploymentRecord {
+            proposal_id: "0badcafe".to_string(),
+            contract_hash: blake3::hash(b"name: provenance_contract\n").to_hex().to_string(),
+            author: "node-a".to_string(),
+            changelog: String::new(),
+            approvals: Vec::new(),
+            deployed_at: 1_700_000_000,
+        };
+        fs::write(deployment_record_path("provenance_contract"), serde_json::to_string(&record).unwrap()).unwrap();
+        assert!(matches!(provenance("provenance_contract").unwrap(),
+                         ContractProvenance::Approved { ref proposal_id, approvals: 0 } if proposal_id == "0badcafe"));
+        
+        fs::write(&contract_path, "name: provenance_contract\nversion: edited\n").unwrap();
+        assert!(matches!(provenance("provenance_contract").unwrap(), ContractProvenance::LocalEdit));
+    }
+    
+    #[test]
+    fn proposal_ids_and_names_stay_in_their_directories() {
+        assert!(load_proposal("../contracts").is_err());
+        assert!(load_proposal("").is_err());
+        
+        for name in ["../escape", "a\\b", ".hidden", ""] {
+            assert!(check_file_name("contract name", name).is_err(), "{:?}", name);
+        }
+        check_file_name("contract name", "counter").unwrap();
+    }
+    
+    #[test]
+    fn attached_transcripts_are_covered_by_the_signature() {
+        let mut proposal = Proposal {
+            id: "5e1f0001".to_string(),
+            contract_name: "simulated_contract".to_string(),
+            contract_hash: "c0ffee".to_string(),
+            content: String::new(),
+            author: "node-a".to_string(),
+            changelog: String::new(),
+            created_at: 1_700_000_000,
+            simulations: Vec::new(),
+            signature: String::new(),
+        };
+        let plain = proposal_payload(&proposal).unwrap();
+        assert!(!plain.contains("simulations_hash"));
+        
+        proposal.simulations.push(super::super::simulate::Simulation {
+            contract: "simulated_contract".to_string(),
+            method: "increment".to_string(),
+            args: Vec::new(),
+            pre_state_hash: "00".to_string(),
+            value: serde_json::json!(1),
+            cost: Default::default(),
+            changes: Vec::new(),
+            rules: Vec::new(),
+            simulated_at: 1_700_000_000,
+        });
+        let with_transcript = proposal_payload(&proposal).unwrap();
+        assert!(with_transcript.contains("simulations_hash"));
+        
+        proposal.simulations[0].value = serde_json::json!(2);
+        assert_ne!(proposal_payload(&proposal).unwrap(), with_transcript);
+    }
+}
@@ -1,25 +1,53 @@
 // SentientOS ZK-YAML Contract Executor
-// Handles execution of ZK-YAML contracts in a WASM environment
+// Handles execution of ZK-YAML contracts by interpreting their method implementations
 
-use anyhow::{Result, Context};
-use tracing::{info, debug, warn, error};
-use std::path::{Path, PathBuf};
-use std::collections::HashMap;
-use wasmer::{Instance, Module, Store, Value, Function, imports, WasmerEnv};
-use wasmer_wasi::WasiEnv;
-use serde::{Serialize, Deserialize};
+use anyhow::Result;
+use tracing::{info, warn};
 use serde_json;
 
-use crate::core::constants;
-use super::contracts::{ZkContract, ContractMethod, ContractRule};
-use super::verification;
+use crate::core::{constants, lock};
+use super::contracts::ZkContract;
+use super::interpreter::{self, OutOfSteps};
+use super::metering::{self, ExecutionCost};
+use super::state::{self, ContractState};
+use super::simulate::RuleOutcome;
+use super::verify::CONTRACT_STATE_LOCK;
+
+/// How a method invocation treats persisted state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionMode {
+    /// Persist the state changes of non-pure methods
+    Commit,
+    
+    /// Run deterministically against a copy of the state and persist nothing
+    Simulate,
+}
+
+/// Everything a method invocation produced
+#[derive(Debug, Clone)]
+pub struct MethodRun {
+    /// Value returned by the method
+    pub value: serde_json::Value,
+    
+    /// What the invocation cost
+    pub cost: ExecutionCost,
+    
+    /// Persisted state the method ran against
+    pub pre_state: ContractState,
+    
+    /// State after the call
+    pub post_state: ContractState,
+    
+    /// Rules the method evaluated, in order
+    pub rules: Vec<RuleOutcome>,
+}
 
 /// Initialize the ZK-YAML executor
 pub fn init() -> Result<()> {
     info!("Initializing ZK-YAML contract executor");
     
     // Create necessary directories
-    let zk_runtime_dir = PathBuf::from(constants::ROOT_DIR).join(".zk").join("runtime");
+    let zk_runtime_dir = constants::root_dir().join(".zk").join("runtime");
     std::fs::create_dir_all(&zk_runtime_dir)?;
     
     info!("ZK-YAML contract executor initialized successfully");
@@ -36,90 +64,115 @@ pub fn shutdown() -> Result<()> {
     Ok(())
 }
 
-/// Execute a ZK contract method
+/// Execute a ZK contract method, metering its cost against the method's budget
+///
+/// Returns the method's value, its cost and the contract state after the call.
+/// State changes of non-pure methods are persisted.
 pub fn execute_contract_method(
     contract: &ZkContract,
     method_name: &str,
     args: &[serde_json::Value],
-) -> Result<serde_json::Value> {
-    info!("Executing ZK contract method: {}.{}", contract.name, method_name);
+) -> Result<(serde_json::Value, ExecutionCost, ContractState)> {
+    let run = run_method(contract, method_name, args, ExecutionMode::Commit)?;
+    Ok((run.value, run.cost, run.post_state))
+}
+
+/// Run a ZK contract method in the given mode
+///
+/// Budgets are enforced in both modes, so a simulation fails where the real
+/// call would. A committed call holds the contract state lock from loading
+/// the state to saving it, so concurrent calls cannot lose each other's
+/// changes.
+pub fn run_method(
+    contract: &ZkContract,
+    method_name: &str,
+    args: &[serde_json::Value],
+    mode: ExecutionMode,
+) -> Result<MethodRun> {
+    let _lock = match mode {
+        ExecutionMode::Commit => Some(lock::lock(
+            CONTRACT_STATE_LOCK,
+            &format!("execute {}.{}", contract.name, method_name),
+            lock::DEFAULT_TIMEOUT,
+        )?),
+        ExecutionMode::Simulate => None,
+    };
+    
+    // Load persisted state, decrypting it if needed
+    let pre_state = state::load_state(contract)?;
+    run_method_on(contract, method_name, args, pre_state, mode)
+}
+
+/// Run a ZK contract method against the given state instead of the persisted one
+///
+/// In `Commit` mode the resulting state is still persisted.
+pub fn run_method_on(
+    contract: &ZkContract,
+    method_name: &str,
+    args: &[serde_json::Value],
+    pre_state: ContractState,
+    mode: ExecutionMode,
+) -> Result<MethodRun> {
+    info!("Running ZK contract method: {}.{} ({:?})", contract.name, method_name, mode);
     
     // Find the method
     let method = contract.methods.get(method_name)
         .ok_or_else(|| anyhow::anyhow!("Method not found: {}", method_name))?;
     
-    // Generate the WASM environment for this method
-    let (wasm_bytes, imports) = generate_method_wasm_environment(contract, method)?;
-    
-    // Create a wasmer store
-    let mut store = Store::default();
-    
-    // Compile the WASM module
-    let module = Module::new(&store, &wasm_bytes)?;
-    
-    // Create WASI environment for isolated execution
-    let mut wasi_env = WasiState::new("zk-contract")
-        .env("CONTRACT_NAME", &contract.name)
-        .env("METHOD_NAME", method_name)
-        .finalize()?;
-    
-    // Get import object
-    let import_object = imports::imports! {
-        "env" => {
-            "verify_rule" => Function::new_typed(&mut store, verify_rule_callback),
-        },
+    // Methods of dependencies are callable as helpers
+    let dependencies = super::resolver::resolve_dependencies(contract)?.dependencies;
+    
+    // Run the implementation against a working copy; every statement and expression is one step
+    let step_budget = metering::step_budget(contract, method_name);
+    let mut working = pre_state.clone();
+    let outcome = interpreter::run_method(contract, &dependencies, method_name, args, &mut working, step_budget)
+        .map_err(|e| match e.downcast_ref::<OutOfSteps>() {
+            Some(out) => metering::budget_exceeded(contract, method_name, "max_steps", out.steps, out.steps),
+            None => e,
+        })?;
+    
+    let cost = ExecutionCost {
+        steps: outcome.steps,
+        state_bytes: state_bytes_touched(method, &pre_state),
     };
+    metering::check_state_budget(contract, method_name, &cost)?;
     
-    // Create context for passing contract information
-    let context = ZkContractContext {
-        contract: contract.clone(),
-        current_method: method_name.to_string(),
-        state: HashMap::new(),
-    };
-    
-    // Set the context
-    let wasi_env = WasiContextBuilder::new()
-        .with_context(context)
-        .build();
-    
-    // Instantiate the module
-    let instance = Instance::new(&mut store, &module, &import_object)?;
-    
-    // Prepare arguments
-    let wasm_args: Vec<Value> = args.iter()
-        .map(|arg| match arg {
-            serde_json::Value::Null => Value::Null,
-            serde_json::Value::Bool(b) => Value::I32(*b as i32),
-            serde_json::Value::Number(n) if n.is_i64() => Value::I64(n.as_i64().unwrap()),
-            serde_json::Value::Number(n) if n.is_f64() => Value::F64(n.as_f64().unwrap()),
-            _ => Value::I32(0), // Default for incompatible types
-        })
-        .collect();
-    
-    // Get the method export
-    let method_fn = instance.exports.get_function("main")?;
-    
-    // Execute the method
-    let result = method_fn.call(&mut store, &wasm_args)?;
-    
-    // Convert result back to JSON
-    let json_result = match result[0] {
-        Value::I32(i) => serde_json::Value::Number(i.into()),
-        Value::I64(i) => serde_json::Value::Number(i.into()),
-        Value::F32(f) => serde_json::Value::Number((f as f64).into()),
-        Value::F64(f) => serde_json::Value::Number(f.into()),
-        _ => serde_json::Value::Null,
+    // Persist state changes; pure methods cannot change state
+    let post_state = if method.pure {
+        pre_state.clone()
+    } else {
+        if mode == ExecutionMode::Commit {
+            state::save_state(contract, &working)?;
+        }
+        working
     };
     
-    info!("Successfully executed ZK contract method: {}.{}", contract.name, method_name);
-    Ok(json_result)
+    info!("Successfully ran ZK contract method: {}.{} ({} steps)", contract.name, method_name, cost.steps);
+    Ok(MethodRun {
+        value: outcome.value,
+        cost,
+        pre_state,
+        post_state,
+        rules: outcome.rules,
+    })
 }
 
-/// Verify a rule in a contract
+/// Bytes of contract state a method reads or writes
+///
+/// Counts the current serialized size of every state variable the
+/// implementation references, once per variable.
+fn state_bytes_touched(method: &super::contracts::Method, state: &ContractState) -> u64 {
+    state.iter()
+        .filter(|(name, _)| method.implementation.contains(&format!("state.{}", name)))
+        .map(|(_, value)| serde_json::to_string(value).map_or(0, |v| v.len()) as u64)
+        .sum()
+}
+
+/// Verify a rule in a contract against a state
 pub fn verify_rule(
     contract: &ZkContract,
     rule_name: &str,
-    state: &HashMap<String, serde_json::Value>,
+    state: &ContractState,
 ) -> Result<bool> {
     info!("Verifying ZK contract rule: {}.{}", contract.name, rule_name);
     
@@ -128,12 +181,8 @@ pub fn verify_rule(
         .find(|r| r.name == rule_name)
         .ok_or_else(|| anyhow::anyhow!("Rule not found: {}", rule_name))?;
     
-    // In a real implementation, we would evaluate the rule condition
-    // against the current state using a proper expression evaluator
-    // For now, we'll use a simple placeholder implementation
-    
     // Check if state satisfies rule condition
-    let rule_result = evaluate_rule_condition(rule, state)?;
+    let rule_result = interpreter::evaluate_condition(contract, &rule.condition, state)?;
     
     if rule_result {
         info!("Rule verified successfully: {}.{}", contract.name, rule_name);
@@ -144,127 +193,135 @@ pub fn verify_rule(
     Ok(rule_result)
 }
 
-/// Generate a WASM module for executing a contract method
-fn generate_method_wasm_environment(
-    contract: &ZkContract,
-    method: &ContractMethod,
-) -> Result<(Vec<u8>, String)> {
-    debug!("Generating WASM environment for method: {}.{}", 
-           contract.name, method.name);
-    
-    // In a real implementation, we would compile the method implementation
-    // into a WASM module. For this prototype, we'll generate a simple
-    // WASM module with embedded JavaScript-like code.
-    
-    // Create a JavaScript-like implementation for execution
-    let js_impl = format!(r#"
-    // Contract: {}
-    // Method: {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use super::super::metering::BudgetExceeded;
+    
+    /// Counter contract; `name` keeps each test's persisted state apart
+    fn counter_yaml(name: &str) -> String {
+        format!(r#"
+name: {}
+version: 0.1.0
+permissions:
+  filesystem: {{ read: [], write: [] }}
+  network: {{ outbound: false, inbound: false, allowed_hosts: [] }}
+  system: {{ exec: false, memory_limit: null, cpu_limit: null }}
+state:
+  counter: {{ var_type: u64, default: "0", mutable: true, zk_verified: true }}
+methods:
+  increment:
+    name: increment
+    params: {{}}
+    return_type: u64
+    implementation: |
+      state.counter += 1;
+      verify_rule("counter_positive");
+      return state.counter;
+    pure: false
+    zk_verified: true
+  peek_next:
+    name: peek_next
+    params: {{}}
+    return_type: u64
+    implementation: |
+      state.counter += 1;
+      return state.counter;
+    pure: true
+    zk_verified: false
+  spin:
+    name: spin
+    params: {{}}
+    return_type: u64
+    implementation: |
+      while true {{ state.counter += 1; }}
+    pure: false
+    zk_verified: false
+rules:
+  - name: counter_positive
+    condition: state.counter >= 0
+    effect: revert if counter becomes negative
+    zk_verified: true
+limits:
+  spin: {{ max_steps: 500, max_state_bytes: null }}
+"#, name)
+    }
     
-    // State variables
-    let state = {{}};
-    {}
+    /// Install a counter contract under `.zk/contracts` and load it through the registry
+    fn install(name: &str) -> std::sync::Arc<ZkContract> {
+        let dir = constants::root_dir().join(".zk").join("contracts");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(format!("{}.yaml", name)), counter_yaml(name)).unwrap();
+        super::super::registry::get(name).unwrap()
+    }
     
-    // Implementation
-    function main() {{
-        {}
+    #[test]
+    fn increments_are_persisted() {
+        let contract = install("executor_counter");
         
-        return 0;
-    }}
-    
-    // Rule verification helper
-    function verify_rule(ruleName) {{
-        return env.verify_rule(ruleName);
-    }}
-    "#,
-        contract.name,
-        method.name,
-        contract.state.iter()
-            .map(|(name, default)| format!("state.{} = {};", name, default))
-            .collect::<Vec<_>>()
-            .join("\n    "),
-        method.implementation
-    );
-    
-    // In a real implementation, we would compile this to WASM
-    // For now, return a placeholder
-    let wasm_bytes = vec![0, 0, 0, 0]; // Placeholder
-    
-    Ok((wasm_bytes, js_impl))
-}
-
-/// Evaluate a rule condition against the current state
-fn evaluate_rule_condition(
-    rule: &ContractRule,
-    state: &HashMap<String, serde_json::Value>,
-) -> Result<bool> {
-    // In a real implementation, we would parse and evaluate the condition
-    // For now, return a placeholder result
-    Ok(true)
-}
-
-/// Verify rule callback for WASM environment
-fn verify_rule_callback(
-    ctx: &mut WasmerEnv,
-    rule_name_ptr: i32,
-    rule_name_len: i32,
-) -> i32 {
-    let context = ctx.downcast_mut::<ZkContractContext>()
-        .expect("Invalid context type");
-    
-    // Read rule name from WASM memory
-    // (In a real implementation, we would actually read from memory)
-    let rule_name = "placeholder_rule"; // Placeholder
-    
-    // Verify the rule
-    match verify_rule(&context.contract, rule_name, &context.state) {
-        Ok(true) => 1,
-        _ => 0,
+        let (value, cost, post_state) = execute_contract_method(&contract, "increment", &[]).unwrap();
+        assert_eq!(value, json!(1));
+        assert_eq!(post_state["counter"], json!(1));
+        assert!(cost.steps > 0);
+        let (value, _, _) = execute_contract_method(&contract, "increment", &[]).unwrap();
+        assert_eq!(value, json!(2));
+        
+        let persisted = super::super::get_contract_state("executor_counter").unwrap();
+        assert_eq!(persisted["counter"], json!(2));
     }
-}
-
-/// Context for ZK contract execution
-#[derive(Clone)]
-struct ZkContractContext {
-    /// The contract being executed
-    contract: ZkContract,
     
-    /// The current method being executed
-    current_method: String,
+    #[test]
+    fn concurrent_increments_are_not_lost() {
+        let contract = install("executor_concurrent");
+        
+        let threads: Vec<_> = (0..4).map(|_| {
+            let contract = contract.clone();
+            std::thread::spawn(move || {
+                for _ in 0..5 {
+                    execute_contract_method(&contract, "increment", &[]).unwrap();
+                }
+            })
+        }).collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        
+        assert_eq!(state::load_state(&contract).unwrap()["counter"], json!(20));
+    }
     
-    /// The current state
-    state: HashMap<String, serde_json::Value>,
-}
-
-/// WASI context builder for ZK contracts
-struct WasiContextBuilder {
-    context: Option<ZkContractContext>,
-}
-
-impl WasiContextBuilder {
-    /// Create a new WASI context builder
-    fn new() -> Self {
-        Self { context: None }
+    #[test]
+    fn simulations_and_pure_methods_persist_nothing() {
+        let contract = install("executor_pure");
+        
+        let run = run_method(&contract, "increment", &[], ExecutionMode::Simulate).unwrap();
+        assert_eq!(run.post_state["counter"], json!(1));
+        assert_eq!(run.rules, [RuleOutcome { rule: "counter_positive".to_string(), passed: true }]);
+        let (value, _, post_state) = execute_contract_method(&contract, "peek_next", &[]).unwrap();
+        assert_eq!(value, json!(1));
+        assert_eq!(post_state["counter"], json!(0));
+        
+        assert_eq!(state::load_state(&contract).unwrap()["counter"], json!(0));
     }
     
-    /// Set the ZK contract context
-    fn with_context(mut self, context: ZkContractContext) -> Self {
-        self.context = Some(context);
-        self
+    #[test]
+    fn step_budgets_stop_endless_methods() {
+        let contract = install("executor_spin");
+        
+        let error = execute_contract_method(&contract, "spin", &[]).unwrap_err();
+        let exceeded = error.downcast_ref::<BudgetExceeded>().expect("budget error");
+        assert_eq!(exceeded.limit, "max_steps");
+        assert_eq!(state::load_state(&contract).unwrap()["counter"], json!(0));
     }
     
-    /// Build the WASI context
-    fn build(self) -> WasmerEnv {
-        WasmerEnv::new(self.context.unwrap_or_else(|| ZkContractContext {
-            contract: ZkContract {
-                name: "empty".to_string(),
-                version: "0.0.0".to_string(),
-                state: HashMap::new(),
-                methods: HashMap::new(),
-                rules: Vec::new(),
-            },
-            current_method: "".to_string(),
-            state: HashMap::new(),
-        }))
+    #[test]
+    fn rules_are_checked_against_the_given_state() {
+        let contract = install("executor_rules");
+        let mut state = state::default_state(&contract);
+        assert!(verify_rule(&contract, "counter_positive", &state).unwrap());
+        
+        state.insert("counter".to_string(), json!("many"));
+        assert!(verify_rule(&contract, "counter_positive", &state).is_err());
+        assert!(verify_rule(&contract, "missing", &state).is_err());
     }
 }
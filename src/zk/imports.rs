@@ -0,0 +1,289 @@
+// SentientOS ZK Contract Imports
+// Resolves `imports:` in ZK-YAML contracts into a single effective contract
+
+use anyhow::{Result, Context};
+use tracing::{info, debug};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use super::contracts::ZkContract;
+use crate::core::constants;
+
+/// Maximum depth of nested imports
+pub const MAX_IMPORT_DEPTH: usize = 8;
+
+/// Namespace prefix for imported rules and methods
+const LIBRARY_NAMESPACE: &str = "lib";
+
+/// A node in a contract's resolved import tree
+#[derive(Debug, Clone)]
+pub struct ImportNode {
+    /// Contract or library name
+    pub name: String,
+    
+    /// Hash of the library file content (empty for the root contract)
+    pub hash: String,
+    
+    /// Libraries imported by this node
+    pub children: Vec<ImportNode>,
+}
+
+impl fmt::Display for ImportNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.name)?;
+        write_children(f, &self.children, "")
+    }
+}
+
+/// Write the children of an import tree node with box-drawing guides
+fn write_children(f: &mut fmt::Formatter<'_>, children: &[ImportNode], prefix: &str) -> fmt::Result {
+    for (i, child) in children.iter().enumerate() {
+        let last = i == children.len() - 1;
+        let short_hash = &child.hash[..child.hash.len().min(12)];
+        writeln!(f, "{}{} {} ({})", prefix, if last { "└──" } else { "├──" }, child.name, short_hash)?;
+        
+        let child_prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+        write_children(f, &child.children, &child_prefix)?;
+    }
+    Ok(())
+}
+
+/// Namespaced name of an item imported from a library (`lib.<library>.<item>`)
+pub fn namespaced(library: &str, item: &str) -> String {
+    format!("{}.{}.{}", LIBRARY_NAMESPACE, library, item)
+}
+
+/// Inline all imported rules and methods into the contract
+///
+/// Every library reachable through `imports:` is inlined once, with its
+/// rules and methods namespaced by library name. The contract's own
+/// `imports:` list is kept so dependents can be tracked.
+pub fn resolve(mut contract: ZkContract) -> Result<ZkContract> {
+    if contract.imports.is_empty() {
+        return Ok(contract);
+    }
+    
+    debug!("Resolving imports for contract: {}", contract.name);
+    
+    let tree = import_tree(&contract)?;
+    let mut inlined = HashSet::new();
+    inline_children(&mut contract, &tree.children, &mut inlined)?;
+    
+    info!("Resolved {} imported libraries for contract: {}", inlined.len(), contract.name);
+    Ok(contract)
+}
+
+/// Build the import tree of a contract, detecting cycles and enforcing the depth limit
+pub fn import_tree(contract: &ZkContract) -> Result<ImportNode> {
+    let mut stack = vec![contract.name.clone()];
+    let children = build_children(&contract.imports, &mut stack)?;
+    
+    Ok(ImportNode {
+        name: contract.name.clone(),
+        hash: String::new(),
+        children,
+    })
+}
+
+/// Hashes of every library a contract transitively imports, keyed by library name
+pub fn import_hashes(contract: &ZkContract) -> Result<BTreeMap<String, String>> {
+    let tree = import_tree(contract)?;
+    let mut hashes = BTreeMap::new();
+    collect_hashes(&tree.children, &mut hashes);
+    Ok(hashes)
+}
+
+/// Recursively build import tree nodes for a list of imports
+fn build_children(imports: &[String], stack: &mut Vec<String>) -> Result<Vec<ImportNode>> {
+    if stack.len() > MAX_IMPORT_DEPTH {
+        anyhow::bail!("Import depth limit ({}) exceeded: {}", MAX_IMPORT_DEPTH, stack.join(" -> "));
+    }
+    
+    let mut nodes = Vec::new();
+    for name in imports {
+        if stack.contains(name) {
+            anyhow::bail!("Import cycle detected: {} -> {}", stack.join(" -> "), name);
+        }
+        
+        let content = read_library(name)?;
+        let library: ZkContract = serde_yaml::from_str(&content)
+            .with_context(|| format!("Failed to parse imported library: {}", name))?;
+        
+        stack.push(name.clone());
+        let children = build_children(&library.imports, stack)?;
+        stack.pop();
+        
+        nodes.push(ImportNode {
+            name: name.clone(),
+            hash: blake3::hash(content.as_bytes()).to_hex().to_string(),
+            children,
+        });
+    }
+    
+    Ok(nodes)
+}
+
+/// Inline the libraries of an import tree into the contract, each at most once
+fn inline_children(contract: &mut ZkContract, children: &[ImportNode], inlined: &mut HashSet<String>) -> Result<()> {
+    for node in children {
+        if !inlined.insert(node.name.clone()) {
+            continue;
+        }
+        
+        let library: ZkContract = serde_yaml::from_str(&read_library(&node.name)?)
+            .with_context(|| format!("Failed to parse imported library: {}", node.name))?;
+        
+        let local_rules: Vec<String> = library.rules.iter().map(|r| r.name.clone()).collect();
+        
+        for mut rule in library.rules {
+            rule.name = namespaced(&node.name, &rule.name);
+            contract.rules.push(rule);
+        }
+        
+        for (method_name, mut method) in library.methods {
+            let name = namespaced(&node.name, &method_name);
+            method.name = name.clone();
+            
+            // Point the library's own rule checks at their namespaced names
+            for rule_name in &local_rules {
+                method.implementation = method.implementation.replace(
+                    &format!("verify_rule(\"{}\")", rule_name),
+                    &format!("verify_rule(\"{}\")", namespaced(&node.name, rule_name)),
+                );
+            }
+            
+            if contract.methods.insert(name.clone(), method).is_some() {
+                anyhow::bail!("Imported method conflicts with existing method: {}", name);
+            }
+        }
+        
+        inline_children(contract, &node.children, inlined)?;
+    }
+    
+    Ok(())
+}
+
+/// Collect library hashes from an import tree
+fn collect_hashes(children: &[ImportNode], hashes: &mut BTreeMap<String, String>) {
+    for node in children {
+        hashes.insert(node.name.clone(), node.hash.clone());
+        collect_hashes(&node.children, hashes);
+    }
+}
+
+/// Read a library's YAML from `.zk/contracts`
+fn read_library(name: &str) -> Result<String> {
+    if name.is_empty() || name.contains('/') || name.contains('\\') || name.starts_with('.') {
+        anyhow::bail!("Invalid import name: {:?}", name);
+    }
+    
+    let path = constants::root_dir()
+        .join(".zk")
+        .join("contracts")
+        .join(format!("{}.yaml", name));
+    
+    std::fs::read_to_string(&path)
+        .with_context(|| format!("Imported contract not found: {} ({:?})", name, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::contracts::new_contract;
+    
+    /// Write a library to `.zk/contracts` with one rule and one method checking it
+    fn write_library(name: &str, imports: &[&str]) {
+        let yaml = format!(r#"
+name: {name}
+version: 0.1.0
+imports: [{imports}]
+permissions:
+  filesystem: {{ read: [], write: [] }}
+  network: {{ outbound: false, inbound: false, allowed_hosts: [] }}
+  system: {{ exec: false, memory_limit: null, cpu_limit: null }}
+state: {{}}
+rules:
+  - name: bounded
+    condition: state.counter < 100
+    effect: revert
+    zk_verified: true
+methods:
+  check:
+    name: check
+    params: {{}}
+    return_type: null
+    implementation: |
+      verify_rule("bounded");
+    pure: true
+    zk_verified: false
+"#, name = name, imports = imports.join(", "));
+        let dir = constants::root_dir().join(".zk").join("contracts");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(format!("{}.yaml", name)), yaml).unwrap();
+    }
+    
+    /// Contract importing the given libraries
+    fn importer(imports: &[&str]) -> ZkContract {
+        let mut contract = new_contract("imports_test_app", "0.1.0");
+        contract.imports = imports.iter().map(|i| i.to_string()).collect();
+        contract
+    }
+    
+    #[test]
+    fn imported_items_are_namespaced() {
+        write_library("imports_base", &[]);
+        write_library("imports_middle", &["imports_base"]);
+        
+        let contract = resolve(importer(&["imports_middle", "imports_base"])).unwrap();
+        let rules: Vec<&str> = contract.rules.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(rules, ["lib.imports_middle.bounded", "lib.imports_base.bounded"]);
+        
+        let check = &contract.methods["lib.imports_middle.check"];
+        assert_eq!(check.name, "lib.imports_middle.check");
+        assert!(check.implementation.contains("verify_rule(\"lib.imports_middle.bounded\")"));
+        assert!(contract.methods.contains_key("lib.imports_base.check"));
+        assert_eq!(contract.imports, ["imports_middle", "imports_base"]);
+    }
+    
+    #[test]
+    fn import_trees_show_every_library_with_its_hash() {
+        write_library("imports_leaf", &[]);
+        write_library("imports_branch", &["imports_leaf"]);
+        write_library("imports_side", &[]);
+        
+        let tree = import_tree(&importer(&["imports_branch", "imports_side"])).unwrap();
+        let hashes = import_hashes(&importer(&["imports_branch", "imports_side"])).unwrap();
+        assert_eq!(hashes.keys().collect::<Vec<_>>(), ["imports_branch", "imports_leaf", "imports_side"]);
+        
+        let short = |name: &str| hashes[name][..12].to_string();
+        let expected = format!(
+            "imports_test_app\n├── imports_branch ({})\n│   └── imports_leaf ({})\n└── imports_side ({})\n",
+            short("imports_branch"), short("imports_leaf"), short("imports_side"),
+        );
+        assert_eq!(tree.to_string(), expected);
+    }
+    
+    #[test]
+    fn cycles_and_deep_chains_are_rejected() {
+        write_library("imports_ping", &["imports_pong"]);
+        write_library("imports_pong", &["imports_ping"]);
+        let error = import_tree(&importer(&["imports_ping"])).unwrap_err();
+        assert!(error.to_string().contains("Import cycle detected"), "{}", error);
+        
+        for level in 0..=MAX_IMPORT_DEPTH {
+            let next = format!("imports_chain{}", level + 1);
+            let imports: &[&str] = if level == MAX_IMPORT_DEPTH { &[] } else { &[next.as_str()] };
+            write_library(&format!("imports_chain{}", level), imports);
+        }
+        let error = import_tree(&importer(&["imports_chain0"])).unwrap_err();
+        assert!(error.to_string().contains("Import depth limit"), "{}", error);
+    }
+    
+    #[test]
+    fn import_names_cannot_leave_the_contracts_directory() {
+        for name in ["../secrets", ".hidden", ""] {
+            let error = import_tree(&importer(&[name])).unwrap_err();
+            assert!(error.to_string().contains("Invalid import name"), "{}", error);
+        }
+    }
+}
@@ -0,0 +1,930 @@
+// SentientOS ZK Method Interpreter
+// Runs contract method implementations and rule conditions against contract state
+
+use anyhow::{Result, Context};
+use tracing::{debug, warn};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use serde_json::Value;
+use thiserror::Error;
+
+use super::contracts::ZkContract;
+use super::simulate::RuleOutcome;
+use super::state::{self, ContractState};
+
+// Constants
+const MAX_CALL_DEPTH: usize = 32;
+const PUNCTUATION: &[&str] = &[
+    "+=", "-=", "*=", "/=", "%=", "==", "!=", "<=", ">=", "&&", "||",
+    "+", "-", "*", "/", "%", "<", ">", "!", "=", "(", ")", "{", "}", ",", ";", ".",
+];
+const ASSIGNMENTS: &[&str] = &["=", "+=", "-=", "*=", "/=", "%="];
+/// Binary operators by precedence, loosest first
+const BINARY_OPERATORS: &[&[&str]] = &[
+    &["||"],
+    &["&&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+/// A call ran out of steps before it returned
+#[derive(Debug, Clone, Error)]
+#[error("execution stopped after {steps} steps")]
+pub struct OutOfSteps {
+    /// Steps executed, the whole budget
+    pub steps: u64,
+}
+
+/// What a method call did, besides changing the state it was given
+#[derive(Debug, Clone)]
+pub struct Outcome {
+    /// Value the method returned; `null` if it returned nothing
+    pub value: Value,
+    
+    /// Statements and expressions evaluated, nested calls included
+    pub steps: u64,
+    
+    /// Rules the call verified, in order
+    pub rules: Vec<RuleOutcome>,
+}
+
+/// Run a method of a contract against `state`, which it updates in place
+///
+/// Arguments are bound to the method's parameters in name order. Each
+/// statement and expression evaluated is one step; going over
+/// `step_budget` stops the call with `OutOfSteps`. Writes to state are
+/// checked against the declared variables: they must exist, be mutable and
+/// take a value of their type.
+pub fn run_method(
+    contract: &ZkContract,
+    dependencies: &[ZkContract],
+    method_name: &str,
+    args: &[Value],
+    state: &mut ContractState,
+    step_budget: Option<u64>,
+) -> Result<Outcome> {
+    let mut machine = Machine { dependencies, step_budget, steps: 0, rules: Vec::new(), depth: 0 };
+    let value = machine.call(contract, method_name, args.to_vec(), state)?;
+    debug!("{}.{} returned {} after {} steps", contract.name, method_name, value, machine.steps);
+    Ok(Outcome { value, steps: machine.steps, rules: machine.rules })
+}
+
+/// Evaluate a rule condition against a state
+pub fn evaluate_condition(contract: &ZkContract, condition: &str, state: &ContractState) -> Result<bool> {
+    let condition = parse_condition(condition)?;
+    let mut machine = Machine { dependencies: &[], step_budget: None, steps: 0, rules: Vec::new(), depth: 0 };
+    let value = machine.eval(contract, &condition, &mut HashMap::new(), &mut state.clone())?;
+    truth(&value)
+}
+
+/// A lexical token
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Name or keyword
+    Ident(String),
+    
+    /// Number literal
+    Number(serde_json::Number),
+    
+    /// String literal, unescaped
+    Str(String),
+    
+    /// Operator or punctuation
+    Punct(&'static str),
+}
+
+/// An expression
+#[derive(Debug, Clone)]
+enum Expr {
+    /// Constant value
+    Literal(Value),
+    
+    /// Parameter or `let` variable
+    Local(String),
+    
+    /// `state.<name>`
+    State(String),
+    
+    /// `!` or `-` applied to an operand
+    Unary(&'static str, Box<Expr>),
+    
+    /// Binary operator applied to two operands
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    
+    /// Call of a builtin or method
+    Call(Callee, Vec<Expr>),
+}
+
+/// What a call expression calls
+#[derive(Debug, Clone)]
+enum Callee {
+    /// `verify_rule("<rule>")`
+    VerifyRule,
+    
+    /// `<method>(...)` or `self.<method>(...)`, a method of the same contract
+    Method(String),
+    
+    /// `deps.<contract>.<method>(...)`, a method of a dependency
+    Dependency(String, String),
+}
+
+/// Where an assignment writes
+#[derive(Debug, Clone)]
+enum Target {
+    /// Parameter or `let` variable
+    Local(String),
+    
+    /// `state.<name>`
+    State(String),
+}
+
+/// A statement
+#[derive(Debug, Clone)]
+enum Stmt {
+    /// `let <name> = <expr>;`
+    Let(String, Expr),
+    
+    /// `<target> = <expr>;` or a compound assignment such as `+=`
+    Assign(Target, &'static str, Expr),
+    
+    /// `if <cond> { ... } else { ... }`
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    
+    /// `while <cond> { ... }`
+    While(Expr, Vec<Stmt>),
+    
+    /// `return;` or `return <expr>;`
+    Return(Option<Expr>),
+    
+    /// Expression evaluated for its effect, such as a call
+    Expr(Expr),
+}
+
+/// How a block finished
+enum Flow {
+    /// Ran to its end
+    Normal,
+    
+    /// Hit a `return`
+    Return(Value),
+}
+
+/// Parse a method implementation
+fn parse_program(source: &str) -> Result<Vec<Stmt>> {
+    let mut parser = Parser { tokens: tokenize(source)?, pos: 0 };
+    let statements = parser.block_body()?;
+    if let Some(token) = parser.peek() {
+        anyhow::bail!("Unexpected {:?}", token);
+    }
+    Ok(statements)
+}
+
+/// Parse a rule condition, a single expression
+fn parse_condition(source: &str) -> Result<Expr> {
+    let mut parser = Parser { tokens: tokenize(source)?, pos: 0 };
+    let condition = parser.expr()?;
+    if let Some(token) = parser.peek() {
+        anyhow::bail!("Unexpected {:?} in condition {:?}", token, source);
+    }
+    Ok(condition)
+}
+
+/// Split source into tokens; `//` comments run to the end of the line
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '/' && chars.get(i + 1) == Some(&'/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.' || chars[i] == '_') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().filter(|c| **c != '_').collect();
+            let number = if text.contains('.') {
+                text.parse::<f64>().ok().and_then(serde_json::Number::from_f64)
+            } else {
+                text.parse::<u64>().ok().map(serde_json::Number::from)
+            };
+            tokens.push(Token::Number(number.ok_or_else(|| anyhow::anyhow!("Invalid number {}", text))?));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else if c == '"' || c == '\'' {
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => anyhow::bail!("Unterminated string"),
+                    Some(&end) if end == c => break,
+                    Some('\\') => {
+                        i += 1;
+                        text.push(match chars.get(i) {
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some(&escaped) => escaped,
+                            None => anyhow::bail!("Unterminated string"),
+                        });
+                    }
+                    Some(&other) => text.push(other),
+                }
+                i += 1;
+            }
+            i += 1;
+            tokens.push(Token::Str(text));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let punct = PUNCTUATION.iter().find(|p| rest.starts_with(**p))
+                .ok_or_else(|| anyhow::anyhow!("Unexpected character {:?}", c))?;
+            tokens.push(Token::Punct(punct));
+            i += punct.len();
+        }
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser over tokens
+struct Parser {
+    /// Tokens of the whole source
+    tokens: Vec<Token>,
+    
+    /// Index of the next token
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+    
+    fn next(&mut self) -> Result<Token> {
+        let token = self.tokens.get(self.pos).cloned().ok_or_else(|| anyhow::anyhow!("Unexpected end of input"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+    
+    /// Consume the next token if it is one of `puncts`
+    fn eat(&mut self, puncts: &[&'static str]) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Punct(p)) if puncts.contains(p) => {
+                let p = *p;
+                self.pos += 1;
+                Some(p)
+            }
+            _ => None,
+        }
+    }
+    
+    fn expect(&mut self, punct: &'static str) -> Result<()> {
+        match self.next()? {
+            Token::Punct(p) if p == punct => Ok(()),
+            other => anyhow::bail!("Expected {:?}, found {:?}", punct, other),
+        }
+    }
+    
+    fn ident(&mut self) -> Result<String> {
+        match self.next()? {
+            Token::Ident(name) => Ok(name),
+            other => anyhow::bail!("Expected a name, found {:?}", other),
+        }
+    }
+    
+    fn keyword(&mut self, keyword: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Ident(name)) if name == keyword) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+    
+    /// Statements up to a closing `}` or the end of input
+    fn block_body(&mut self) -> Result<Vec<Stmt>> {
+        let mut statements = Vec::new();
+        while !matches!(self.peek(), None | Some(Token::Punct("}"))) {
+            statements.push(self.statement()?);
+        }
+        Ok(statements)
+    }
+    
+    fn block(&mut self) -> Result<Vec<Stmt>> {
+        self.expect("{")?;
+        let statements = self.block_body()?;
+        self.expect("}")?;
+        Ok(statements)
+    }
+    
+    /// `;` ending a statement; optional before `}` and at the end of input
+    fn end_statement(&mut self) -> Result<()> {
+        if self.eat(&[";"]).is_none() && !matches!(self.peek(), None | Some(Token::Punct("}"))) {
+            anyhow::bail!("Expected \";\", found {:?}", self.peek().unwrap());
+        }
+        Ok(())
+    }
+    
+    fn statement(&mut self) -> Result<Stmt> {
+        if self.keyword("let") {
+            let name = self.ident()?;
+            self.expect("=")?;
+            let value = self.expr()?;
+            self.end_statement()?;
+            return Ok(Stmt::Let(name, value));
+        }
+        if self.keyword("if") {
+            return self.if_statement();
+        }
+        if self.keyword("while") {
+            let condition = self.expr()?;
+            return Ok(Stmt::While(condition, self.block()?));
+        }
+        if self.keyword("return") {
+            let value = match self.peek() {
+                None | Some(Token::Punct(";")) | Some(Token::Punct("}")) => None,
+                _ => Some(self.expr()?),
+            };
+            self.end_statement()?;
+            return Ok(Stmt::Return(value));
+        }
+        
+        let expr = self.expr()?;
+        let statement = match self.eat(ASSIGNMENTS) {
+            Some(op) => {
+                let target = match expr {
+                    Expr::Local(name) => Target::Local(name),
+                    Expr::State(name) => Target::State(name),
+                    other => anyhow::bail!("Cannot assign to {:?}", other),
+                };
+                Stmt::Assign(target, op, self.expr()?)
+            }
+            None => Stmt::Expr(expr),
+        };
+        self.end_statement()?;
+        Ok(statement)
+    }
+    
+    /// The rest of an `if` statement, after the keyword
+    fn if_statement(&mut self) -> Result<Stmt> {
+        let condition = self.expr()?;
+        let then = self.block()?;
+        let otherwise = if !self.keyword("else") {
+            Vec::new()
+        } else if self.keyword("if") {
+            vec![self.if_statement()?]
+        } else {
+            self.block()?
+        };
+        Ok(Stmt::If(condition, then, otherwise))
+    }
+    
+    fn expr(&mut self) -> Result<Expr> {
+        self.binary(0)
+    }
+    
+    fn binary(&mut self, level: usize) -> Result<Expr> {
+        if level == BINARY_OPERATORS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        while let Some(op) = self.eat(BINARY_OPERATORS[level]) {
+            let right = self.binary(level + 1)?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+    
+    fn unary(&mut self) -> Result<Expr> {
+        match self.eat(&["!", "-"]) {
+            Some(op) => Ok(Expr::Unary(op, Box::new(self.unary()?))),
+            None => self.primary(),
+        }
+    }
+    
+    fn primary(&mut self) -> Result<Expr> {
+        let name = match self.next()? {
+            Token::Number(n) => return Ok(Expr::Literal(Value::Number(n))),
+            Token::Str(s) => return Ok(Expr::Literal(Value::String(s))),
+            Token::Punct("(") => {
+                let inner = self.expr()?;
+                self.expect(")")?;
+                return Ok(inner);
+            }
+            Token::Ident(name) => name,
+            other => anyhow::bail!("Unexpected {:?}", other),
+        };
+        
+        match name.as_str() {
+            "true" => Ok(Expr::Literal(Value::Bool(true))),
+            "false" => Ok(Expr::Literal(Value::Bool(false))),
+            "null" => Ok(Expr::Literal(Value::Null)),
+            "state" => {
+                self.expect(".")?;
+                Ok(Expr::State(self.ident()?))
+            }
+            "self" => {
+                self.expect(".")?;
+                let method = self.ident()?;
+                Ok(Expr::Call(Callee::Method(method), self.args()?))
+            }
+            "deps" => {
+                self.expect(".")?;
+                let contract = self.ident()?;
+                self.expect(".")?;
+                let method = self.ident()?;
+                Ok(Expr::Call(Callee::Dependency(contract, method), self.args()?))
+            }
+            "msg" | "env" => anyhow::bail!("{} is not available to contract methods", name),
+            _ if matches!(self.peek(), Some(Token::Punct("("))) => {
+                let callee = if name == "verify_rule" { Callee::VerifyRule } else { Callee::Method(name) };
+                Ok(Expr::Call(callee, self.args()?))
+            }
+            _ => Ok(Expr::Local(name)),
+        }
+    }
+    
+    /// Parenthesized, comma-separated call arguments
+    fn args(&mut self) -> Result<Vec<Expr>> {
+        self.expect("(")?;
+        let mut args = Vec::new();
+        if self.eat(&[")"]).is_some() {
+            return Ok(args);
+        }
+        loop {
+            args.push(self.expr()?);
+            if self.eat(&[")"]).is_some() {
+                return Ok(args);
+            }
+            self.expect(",")?;
+        }
+    }
+}
+
+/// Evaluation of one top-level call
+struct Machine<'a> {
+    /// Contracts whose methods are callable through `deps`
+    dependencies: &'a [ZkContract],
+    
+    /// Steps the call may take, if limited
+    step_budget: Option<u64>,
+    
+    /// Steps taken so far
+    steps: u64,
+    
+    /// Rules verified so far
+    rules: Vec<RuleOutcome>,
+    
+    /// Methods currently being run
+    depth: usize,
+}
+
+impl Machine<'_> {
+    /// Count a step against the budget
+    fn step(&mut self) -> Result<()> {
+        self.steps += 1;
+        match self.step_budget {
+            Some(budget) if self.steps > budget => Err(OutOfSteps { steps: budget }.into()),
+            _ => Ok(()),
+        }
+    }
+    
+    fn call(&mut self, contract: &ZkContract, method_name: &str, args: Vec<Value>, state: &mut ContractState) -> Result<Value> {
+        let method = contract.methods.get(method_name)
+            .ok_or_else(|| anyhow::anyhow!("Method not found: {}.{}", contract.name, method_name))?;
+        if self.depth == MAX_CALL_DEPTH {
+            anyhow::bail!("Calls nested deeper than {} at {}.{}", MAX_CALL_DEPTH, contract.name, method_name);
+        }
+        
+        let mut params: Vec<&String> = method.params.keys().collect();
+        params.sort();
+        if args.len() != params.len() {
+            anyhow::bail!("{}.{} takes {} argument(s), got {}", contract.name, method_name, params.len(), args.len());
+        }
+        let mut locals: HashMap<String, Value> = params.into_iter().cloned().zip(args).collect();
+        let body = parse_program(&method.implementation)
+            .with_context(|| format!("Invalid implementation of {}.{}", contract.name, method_name))?;
+        
+        self.depth += 1;
+        let flow = self.block(contract, &body, &mut locals, state);
+        self.depth -= 1;
+        Ok(match flow? {
+            Flow::Return(value) => value,
+            Flow::Normal => Value::Null,
+        })
+    }
+    
+    fn block(
+        &mut self,
+        contract: &ZkContract,
+        statements: &[Stmt],
+        locals: &mut HashMap<String, Value>,
+        state: &mut ContractState,
+    ) -> Result<Flow> {
+        for statement in statements {
+            if let Flow::Return(value) = self.statement(contract, statement, locals, state)? {
+                return Ok(Flow::Return(value));
+            }
+        }
+        Ok(Flow::Normal)
+    }
+    
+    fn statement(
+        &mut self,
+        contract: &ZkContract,
+        statement: &Stmt,
+        locals: &mut HashMap<String, Value>,
+        state: &mut ContractState,
+    ) -> Result<Flow> {
+        self.step()?;
+        match statement {
+            Stmt::Let(name, value) => {
+                let value = self.eval(contract, value, locals, state)?;
+                locals.insert(name.clone(), value);
+            }
+            Stmt::Assign(target, op, value) => {
+                let mut value = self.eval(contract, value, locals, state)?;
+                if let Some(op) = op.strip_suffix('=').filter(|op| !op.is_empty()) {
+                    let current = match target {
+                        Target::Local(name) => read_local(locals, name)?,
+                        Target::State(name) => read_state(contract, state, name)?,
+                    };
+                    value = binary(op, &current, &value)?;
+                }
+                match target {
+                    Target::Local(name) => {
+                        read_local(locals, name)?;
+                        locals.insert(name.clone(), value);
+                    }
+                    Target::State(name) => write_state(contract, state, name, value)?,
+                }
+            }
+            Stmt::If(condition, then, otherwise) => {
+                let condition = self.eval(contract, condition, locals, state)?;
+                let branch = if truth(&condition)? { then } else { otherwise };
+                return self.block(contract, branch, locals, state);
+            }
+            Stmt::While(condition, body) => {
+                loop {
+                    let condition = self.eval(contract, condition, locals, state)?;
+                    if !truth(&condition)? {
+                        break;
+                    }
+                    if let Flow::Return(value) = self.block(contract, body, locals, state)? {
+                        return Ok(Flow::Return(value));
+                    }
+                    self.step()?;
+                }
+            }
+            Stmt::Return(value) => {
+                let value = match value {
+                    Some(value) => self.eval(contract, value, locals, state)?,
+                    None => Value::Null,
+                };
+                return Ok(Flow::Return(value));
+            }
+            Stmt::Expr(expr) => {
+                self.eval(contract, expr, locals, state)?;
+            }
+        }
+        Ok(Flow::Normal)
+    }
+    
+    fn eval(
+        &mut self,
+        contract: &ZkContract,
+        expr: &Expr,
+        locals: &mut HashMap<String, Value>,
+        state: &mut ContractState,
+    ) -> Result<Value> {
+        self.step()?;
+        match expr {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Local(name) => read_local(locals, name),
+            Expr::State(name) => read_state(contract, state, name),
+            Expr::Unary(op, operand) => {
+                let operand = self.eval(contract, operand, locals, state)?;
+                match *op {
+                    "!" => Ok(Value::Bool(!truth(&operand)?)),
+                    _ => binary("-", &Value::from(0), &operand),
+                }
+            }
+            Expr::Binary(op @ ("&&" | "||"), left, right) => {
+                let left = truth(&self.eval(contract, left, locals, state)?)?;
+                if left == (*op == "||") {
+                    return Ok(Value::Bool(left));
+                }
+                Ok(Value::Bool(truth(&self.eval(contract, right, locals, state)?)?))
+            }
+            Expr::Binary(op, left, right) => {
+                let left = self.eval(contract, left, locals, state)?;
+                let right = self.eval(contract, right, locals, state)?;
+                binary(op, &left, &right)
+            }
+            Expr::Call(callee, args) => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.eval(contract, arg, locals, state)?);
+                }
+                self.invoke(contract, callee, values, state)
+            }
+        }
+    }
+    
+    fn invoke(&mut self, contract: &ZkContract, callee: &Callee, args: Vec<Value>, state: &mut ContractState) -> Result<Value> {
+        match callee {
+            Callee::VerifyRule => {
+                let rule_name = match args.as_slice() {
+                    [Value::String(name)] => name,
+                    _ => anyhow::bail!("verify_rule takes the name of a rule"),
+                };
+                let rule = contract.rules.iter().find(|rule| &rule.name == rule_name)
+                    .ok_or_else(|| anyhow::anyhow!("Rule not found: {}", rule_name))?;
+                
+                // A condition that cannot be evaluated does not hold
+                let passed = parse_condition(&rule.condition)
+                    .and_then(|condition| self.eval(contract, &condition, &mut HashMap::new(), &mut state.clone()))
+                    .and_then(|value| truth(&value))
+                    .unwrap_or_else(|e| {
+                        if e.downcast_ref::<OutOfSteps>().is_none() {
+                            warn!("Rule {}.{} could not be evaluated: {:#}", contract.name, rule_name, e);
+                        }
+                        false
+                    });
+                self.step()?;
+                self.rules.push(RuleOutcome { rule: rule_name.clone(), passed });
+                Ok(Value::Bool(passed))
+            }
+            Callee::Method(method_name) => self.call(contract, method_name, args, state),
+            Callee::Dependency(dependency_name, method_name) => {
+                let dependency = self.dependencies.iter().find(|d| &d.name == dependency_name)
+                    .ok_or_else(|| anyhow::anyhow!("{} is not a dependency of {}", dependency_name, contract.name))?;
+                
+                // Helpers run against the dependency's defaults; what they write is dropped
+                let mut scratch = state::default_state(dependency);
+                self.call(dependency, method_name, args, &mut scratch)
+            }
+        }
+    }
+}
+
+fn read_local(locals: &HashMap<String, Value>, name: &str) -> Result<Value> {
+    locals.get(name).cloned().ok_or_else(|| anyhow::anyhow!("Unknown variable: {}", name))
+}
+
+fn read_state(contract: &ZkContract, state: &ContractState, name: &str) -> Result<Value> {
+    match state.get(name) {
+        Some(value) => Ok(value.clone()),
+        None if contract.state.contains_key(name) => Ok(Value::Null),
+        None => anyhow::bail!("Contract {} has no state variable {}", contract.name, name),
+    }
+}
+
+fn write_state(contract: &ZkContract, state: &mut ContractState, name: &str, value: Value) -> Result<()> {
+    let variable = contract.state.get(name)
+        .ok_or_else(|| anyhow::anyhow!("Contract {} has no state variable {}", contract.name, name))?;
+    if !variable.mutable {
+        anyhow::bail!("state.{} is not mutable", name);
+    }
+    if !variable.accepts(&value) {
+        anyhow::bail!("Value {} does not fit state.{} of type {}", value, name, variable.var_type);
+    }
+    state.insert(name.to_string(), value);
+    Ok(())
+}
+
+/// Value of a condition, which must be a boolean
+fn truth(value: &Value) -> Result<bool> {
+    value.as_bool().ok_or_else(|| anyhow::anyhow!("Expected a boolean, got {}", value))
+}
+
+/// Apply a binary operator other than `&&` and `||`
+///
+/// Integer arithmetic is checked, so overflow and division by zero are
+/// errors rather than wrapping.
+fn binary(op: &str, left: &Value, right: &Value) -> Result<Value> {
+    match op {
+        "==" => return Ok(Value::Bool(equal(left, right))),
+        "!=" => return Ok(Value::Bool(!equal(left, right))),
+        "<" | "<=" | ">" | ">=" => {
+            let ordering = compare(left, right)
+                .ok_or_else(|| anyhow::anyhow!("Cannot compare {} and {}", left, right))?;
+            return Ok(Value::Bool(match op {
+                "<" => ordering.is_lt(),
+                "<=" => ordering.is_le(),
+                ">" => ordering.is_gt(),
+                _ => ordering.is_ge(),
+            }));
+        }
+        _ => {}
+    }
+    
+    if let (Value::String(a), Value::String(b), "+") = (left, right, op) {
+        return Ok(Value::String(format!("{}{}", a, b)));
+    }
+    if let (Some(a), Some(b)) = (integer(left), integer(right)) {
+        let result = match op {
+            "+" => a.checked_add(b),
+            "-" => a.checked_sub(b),
+            "*" => a.checked_mul(b),
+            "/" => a.checked_div(b),
+            _ => a.checked_rem(b),
+        };
+        return result.and_then(integer_value)
+            .ok_or_else(|| anyhow::anyhow!("Arithmetic error: {} {} {}", left, op, right));
+    }
+    if let (Some(a), Some(b)) = (left.as_f64(), right.as_f64()) {
+        let result = match op {
+            "+" => a + b,
+            "-" => a - b,
+            "*" => a * b,
+            "/" => a / b,
+            _ => a % b,
+        };
+        return serde_json::Number::from_f64(result).map(Value::Number)
+            .ok_or_else(|| anyhow::anyhow!("Arithmetic error: {} {} {}", left, op, right));
+    }
+    anyhow::bail!("Cannot apply {} to {} and {}", op, left, right)
+}
+
+fn integer(value: &Value) -> Option<i128> {
+    value.as_u64().map(i128::from).or_else(|| value.as_i64().map(i128::from))
+}
+
+/// An integer as JSON, unsigned when it is not negative; `None` if it fits neither
+fn integer_value(n: i128) -> Option<Value> {
+    if n >= 0 {
+        u64::try_from(n).ok().map(Value::from)
+    } else {
+        i64::try_from(n).ok().map(Value::from)
+    }
+}
+
+/// Equality where numbers compare by value, so `1 == 1.0`
+fn equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(_), Value::Number(_)) => compare(left, right) == Some(Ordering::Equal),
+        _ => left == right,
+    }
+}
+
+fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    if let (Some(a), Some(b)) = (integer(left), integer(right)) {
+        return Some(a.cmp(&b));
+    }
+    match (left, right) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use super::super::contracts::{self, Method, Rule, StateVariable};
+    
+    /// Contract with a `u64` counter, a fixed `limit` and the given methods
+    fn contract(methods: &[(&str, &[&str], &str)]) -> ZkContract {
+        let mut contract = contracts::new_contract("interpreter-test", "1.0.0");
+        for (name, var_type, default, mutable) in [("counter", "u64", "0", true), ("limit", "u64", "3", false)] {
+            contract.state.insert(name.to_string(), StateVariable {
+                var_type: var_type.to_string(),
+                default: Some(default.to_string()),
+                mutable,
+                zk_verified: false,
+            });
+        }
+        contract.rules.push(Rule {
+            name: "under_limit".to_string(),
+            condition: "state.counter <= state.limit".to_string(),
+            effect: "revert".to_string(),
+            zk_verified: false,
+        });
+        for (name, params, implementation) in methods {
+            contract.methods.insert(name.to_string(), Method {
+                name: name.to_string(),
+                params: params.iter().map(|p| (p.to_string(), "u64".to_string())).collect(),
+                return_type: Some("u64".to_string()),
+                implementation: implementation.to_string(),
+                pure: false,
+                zk_verified: false,
+            });
+        }
+        contract
+    }
+    
+    fn run(contract: &ZkContract, method: &str, args: &[Value], state: &mut ContractState) -> Result<Outcome> {
+        run_method(contract, &[], method, args, state, None)
+    }
+    
+    #[test]
+    fn methods_update_state_and_return_values() {
+        let contract = contract(&[
+            ("increment", &[], "state.counter += 1;\nreturn state.counter;"),
+            ("add", &["amount"], "let before = state.counter;\nstate.counter = before + amount;\nreturn state.counter - before;"),
+            ("decrement", &[], "if state.counter > 0 {\n  state.counter -= 1;\n}\nreturn state.counter;"),
+        ]);
+        let mut state = state::default_state(&contract);
+        
+        assert_eq!(run(&contract, "increment", &[], &mut state).unwrap().value, json!(1));
+        assert_eq!(run(&contract, "increment", &[], &mut state).unwrap().value, json!(2));
+        assert_eq!(run(&contract, "add", &[json!(5)], &mut state).unwrap().value, json!(5));
+        assert_eq!(state["counter"], json!(7));
+        
+        state.insert("counter".to_string(), json!(0));
+        assert_eq!(run(&contract, "decrement", &[], &mut state).unwrap().value, json!(0));
+        assert!(run(&contract, "add", &[], &mut state).is_err());
+    }
+    
+    #[test]
+    fn loops_and_nested_calls_share_the_state() {
+        let contract = contract(&[
+            ("bump", &[], "state.counter += 1;"),
+            ("bump_times", &["n"], "let i = 0;\nwhile i < n {\n  self.bump();\n  i += 1;\n}\nreturn state.counter;"),
+            ("parity", &[], "if state.counter % 2 == 0 { return \"even\"; } else if state.counter > 100 { return \"big\"; } else { return \"odd\"; }"),
+        ]);
+        let mut state = state::default_state(&contract);
+        
+        assert_eq!(run(&contract, "bump_times", &[json!(5)], &mut state).unwrap().value, json!(5));
+        assert_eq!(run(&contract, "parity", &[], &mut state).unwrap().value, json!("odd"));
+    }
+    
+    #[test]
+    fn verified_rules_are_evaluated_against_the_current_state() {
+        let contract = contract(&[("increment", &[], "state.counter += 1;\nverify_rule(\"under_limit\");\nreturn state.counter;")]);
+        let mut state = state::default_state(&contract);
+        
+        for _ in 0..3 {
+            let outcome = run(&contract, "increment", &[], &mut state).unwrap();
+            assert_eq!(outcome.rules, [RuleOutcome { rule: "under_limit".to_string(), passed: true }]);
+        }
+        let outcome = run(&contract, "increment", &[], &mut state).unwrap();
+        assert_eq!(outcome.rules, [RuleOutcome { rule: "under_limit".to_string(), passed: false }]);
+        
+        assert!(evaluate_condition(&contract, "state.counter <= state.limit", &state::default_state(&contract)).unwrap());
+        assert!(evaluate_condition(&contract, "state.counter", &state).is_err());
+    }
+    
+    #[test]
+    fn writes_must_fit_the_declared_variables() {
+        let contract = contract(&[
+            ("underflow", &[], "state.counter -= 1;"),
+            ("wrong_type", &[], "state.counter = \"many\";"),
+            ("immutable", &[], "state.limit = 10;"),
+            ("undeclared", &[], "state.owner = 1;"),
+            ("overflow", &[], "state.counter = 18446744073709551615 + 1;"),
+        ]);
+        for method in ["underflow", "wrong_type", "immutable", "undeclared", "overflow"] {
+            let mut state = state::default_state(&contract);
+            assert!(run(&contract, method, &[], &mut state).is_err(), "{} succeeded", method);
+            assert_eq!(state, state::default_state(&contract), "{} changed the state", method);
+        }
+    }
+    
+    #[test]
+    fn endless_loops_run_out_of_steps() {
+        let contract = contract(&[("spin", &[], "while true { state.counter += 1; }")]);
+        let mut state = state::default_state(&contract);
+        let error = run_method(&contract, &[], "spin", &[], &mut state, Some(1000)).unwrap_err();
+        assert_eq!(error.downcast_ref::<OutOfSteps>().map(|e| e.steps), Some(1000));
+    }
+    
+    #[test]
+    fn syntax_errors_name_the_method() {
+        let contract = contract(&[("broken", &[], "state.counter += ;")]);
+        let error = run(&contract, "broken", &[], &mut state::default_state(&contract)).unwrap_err();
+        assert!(format!("{:#}", error).contains("Invalid implementation of interpreter-test.broken"), "{:#}", error);
+    }
+    
+    #[test]
+    fn dependency_methods_run_against_their_own_defaults() {
+        let mut helper = contract(&[
+            ("double", &["x"], "state.counter = x;\nreturn x * 2;"),
+            ("peek", &[], "return state.counter;"),
+        ]);
+        helper.name = "helper".to_string();
+        let contract = contract(&[
+            ("use_helper", &[], "state.counter = deps.helper.double(state.limit);\nreturn deps.helper.peek();"),
+            ("use_missing", &[], "return deps.missing.peek();"),
+        ]);
+        let mut state = state::default_state(&contract);
+        
+        // What the helper writes stays out of both contracts' state
+        let outcome = run_method(&contract, std::slice::from_ref(&helper), "use_helper", &[], &mut state, None).unwrap();
+        assert_eq!((outcome.value, &state["counter"]), (json!(0), &json!(6)));
+        
+        let error = run_method(&contract, &[helper], "use_missing", &[], &mut state, None).unwrap_err();
+        assert_eq!(error.to_string(), "missing is not a dependency of interpreter-test");
+    }
+}
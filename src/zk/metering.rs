@@ -0,0 +1,489 @@
+// SentientOS ZK Execution Metering
+// Step and state accounting for contract invocations, with per-method budgets
+
+use anyhow::{Result, Context};
+use tracing::{debug, warn};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::fs;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use thiserror::Error;
+
+use crate::core::constants;
+use super::contracts::ZkContract;
+use super::verify::ProofLifetime;
+
+// Constants
+const METERING_DIR: &str = ".zk/metering";
+const INVOCATION_EXTENSION: &str = "jsonl";
+const MAX_INVOCATIONS: usize = 1000;
+
+/// Cost of one contract method invocation
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionCost {
+    /// Statements and expressions the interpreter evaluated
+    pub steps: u64,
+    
+    /// Bytes of contract state read or written
+    pub state_bytes: u64,
+}
+
+/// Result of a contract method invocation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionResult {
+    /// Value returned by the method
+    pub value: serde_json::Value,
+    
+    /// What the invocation cost
+    pub cost: ExecutionCost,
+    
+    /// ID of the proof covering the result, its cost and the state transition
+    pub proof_id: String,
+    
+    /// Hash of the plaintext contract state before the call
+    pub pre_state_hash: String,
+    
+    /// Hash of the plaintext contract state after the call
+    pub post_state_hash: String,
+}
+
+/// Proof of an invocation, stored in `.zk/proofs/<contract>/<proof_id>.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProofEnvelope {
+    /// Proof ID (hash of the proof)
+    pub proof_id: String,
+    
+    /// Contract name
+    pub contract: String,
+    
+    /// Method name
+    pub method: String,
+    
+    /// Proof bytes (hex)
+    pub proof: String,
+    
+    /// What the invocation cost; covered by the proof
+    pub cost: ExecutionCost,
+    
+    /// Hash of the plaintext contract state before the call; covered by the
+    /// proof. Envelopes written before state transitions were proven have none
+    #[serde(default)]
+    pub pre_state_hash: Option<String>,
+    
+    /// Hash of the plaintext contract state after the call; covered by the proof
+    #[serde(default)]
+    pub post_state_hash: String,
+    
+    /// Value returned by the method; covered by the proof and kept so the
+    /// proof can be re-generated when it nears expiry
+    #[serde(default)]
+    pub value: Option<serde_json::Value>,
+    
+    /// When the proof was generated and when it expires; envelopes written
+    /// before proofs expired have none and never expire
+    #[serde(default)]
+    pub lifetime: Option<ProofLifetime>,
+    
+    /// Proof this one renewed, if it was re-generated
+    #[serde(default)]
+    pub renewed_from: Option<String>,
+    
+    /// Creation timestamp
+    pub created_at: u64,
+}
+
+/// An invocation exceeded a budget declared under `limits` in the contract
+#[derive(Debug, Clone, Error)]
+#[error("{contract}.{method} exceeded its {limit} budget of {budget} (used {used})")]
+pub struct BudgetExceeded {
+    /// Contract name
+    pub contract: String,
+    
+    /// Method name
+    pub method: String,
+    
+    /// Which limit was exceeded (`max_steps` or `max_state_bytes`)
+    pub limit: &'static str,
+    
+    /// Declared budget
+    pub budget: u64,
+    
+    /// Amount used; for steps, at least the budget since execution stops there
+    pub used: u64,
+}
+
+/// A recorded invocation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Invocation {
+    /// Invocation timestamp
+    pub timestamp: u64,
+    
+    /// Method name
+    pub method: String,
+    
+    /// What the invocation cost
+    pub cost: ExecutionCost,
+    
+    /// Proof ID, for investigation
+    pub proof_id: String,
+}
+
+/// Cost statistics of one method
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MethodStats {
+    /// Recorded invocations
+    pub invocations: usize,
+    
+    /// Average steps
+    pub avg_steps: f64,
+    
+    /// Median steps
+    pub p50_steps: u64,
+    
+    /// 95th percentile steps
+    pub p95_steps: u64,
+    
+    /// 99th percentile steps
+    pub p99_steps: u64,
+    
+    /// Average state bytes touched
+    pub avg_state_bytes: f64,
+}
+
+/// Cost statistics of a contract
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContractStats {
+    /// Contract name
+    pub contract: String,
+    
+    /// Statistics by method
+    pub methods: BTreeMap<String, MethodStats>,
+    
+    /// Most expensive recent invocations by steps, most expensive first
+    pub most_expensive: Vec<Invocation>,
+}
+
+/// Step budget of a method, if declared
+pub fn step_budget(contract: &ZkContract, method: &str) -> Option<u64> {
+    contract.limits.get(method).and_then(|l| l.max_steps)
+}
+
+/// Check the state budget of a method
+pub fn check_state_budget(contract: &ZkContract, method: &str, cost: &ExecutionCost) -> Result<()> {
+    if let Some(budget) = contract.limits.get(method).and_then(|l| l.max_state_bytes) {
+        if cost.state_bytes > budget {
+            return Err(budget_exceeded(contract, method, "max_state_bytes", budget, cost.state_bytes));
+        }
+    }
+    Ok(())
+}
+
+/// Build a budget error and count it
+pub fn budget_exceeded(contract: &ZkContract, method: &str, limit: &'static str, budget: u64, used: u64) -> anyhow::Error {
+    warn!("Contract {}.{} exceeded {} budget {}", contract.name, method, limit, budget);
+    crate::logs::metrics::increment(&format!("zk.{}.{}.budget_exceeded", contract.name, method));
+    
+    BudgetExceeded {
+        contract: contract.name.clone(),
+        method: method.to_string(),
+        limit,
+        budget,
+        used,
+    }.into()
+}
+
+/// Write the proof envelope of an invocation
+pub fn store_envelope(envelope: &ProofEnvelope) -> Result<()> {
+    let dir = envelope_dir(&envelope.contract);
+    fs::create_dir_all(&dir)?;
+    
+    let path = dir.join(format!("{}.json", envelope.proof_id));
+    fs::write(&path, serde_json::to_string_pretty(envelope)?)
+        .with_context(|| format!("Failed to write proof envelope {:?}", path))
+}
+
+/// Stored proof envelope of a contract by proof ID
+pub fn load_envelope(contract: &str, proof_id: &str) -> Result<Option<ProofEnvelope>> {
+    let path = envelope_dir(contract).join(format!("{}.json", proof_id));
+    if !path.exists() {
+        return Ok(None);
+    }
+    
+    let envelope = serde_json::from_str(&fs::read_to_string(&path)?)
+        .with_context(|| format!("Failed to parse proof envelope {:?}", path))?;
+    Ok(Some(envelope))
+}
+
+/// Every stored proof envelope of a contract
+pub fn load_envelopes(contract: &str) -> Result<Vec<ProofEnvelope>> {
+    let dir = envelope_dir(contract);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let mut envelopes = Vec::new();
+    for entry in fs::read_dir(&dir)? {
+        let path = entry?.path();
+        if path.extension().map_or(true, |ext| ext != "json") {
+            continue;
+        }
+        match serde_json::from_str(&fs::read_to_string(&path)?) {
+            Ok(envelope) => envelopes.push(envelope),
+            Err(e) => warn!("Skipping unreadable proof envelope {:?}: {}", path, e),
+        }
+    }
+    Ok(envelopes)
+}
+
+/// Delete a stored proof envelope
+pub fn remove_envelope(contract: &str, proof_id: &str) -> Result<()> {
+    let path = envelope_dir(contract).join(format!("{}.json", proof_id));
+    if path.exists() {
+        fs::remove_file(&path)
+            .with_context(|| format!("Failed to remove proof envelope {:?}", path))?;
+    }
+    Ok(())
+}
+
+/// Number of stored proof envelopes, keyed by contract
+pub fn envelope_counts() -> Result<BTreeMap<String, usize>> {
+    let proofs_dir = constants::root_dir().join(".zk").join("proofs");
+    let mut counts = BTreeMap::new();
+    if !proofs_dir.exists() {
+        return Ok(counts);
+    }
+    
+    for entry in fs::read_dir(&proofs_dir)? {
+        let entry = entry?;
+        // The proof cache shares the directory but holds no envelopes
+        if !entry.file_type()?.is_dir() || entry.file_name() == "cache" {
+            continue;
+        }
+        let count = fs::read_dir(entry.path())?
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().extension().map_or(false, |ext| ext == "json"))
+            .count();
+        counts.insert(entry.file_name().to_string_lossy().to_string(), count);
+    }
+    Ok(counts)
+}
+
+/// Record an invocation in the metrics registry and the contract's history
+pub fn record(contract: &str, method: &str, cost: &ExecutionCost, proof_id: &str) -> Result<()> {
+    crate::logs::metrics::increment(&format!("zk.{}.{}.invocations", contract, method));
+    crate::logs::metrics::record(&format!("zk.{}.{}.steps", contract, method), cost.steps as f64);
+    crate::logs::metrics::record(&format!("zk.{}.{}.state_bytes", contract, method), cost.state_bytes as f64);
+    
+    let invocation = Invocation {
+        timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        method: method.to_string(),
+        cost: *cost,
+        proof_id: proof_id.to_string(),
+    };
+    
+    let dir = constants::root_dir().join(METERING_DIR);
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(format!("{}.{}", contract, INVOCATION_EXTENSION));
+    
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(&path)?;
+    writeln!(file, "{}", serde_json::to_string(&invocation)?)?;
+    drop(file);
+    
+    // Keep the history bounded; trimming halfway avoids rewriting on every call
+    let invocations = load_invocations(contract)?;
+    if invocations.len() > MAX_INVOCATIONS * 2 {
+        let kept = &invocations[invocations.len() - MAX_INVOCATIONS..];
+        let lines: Vec<String> = kept.iter().map(serde_json::to_string).collect::<Result<_, _>>()?;
+        fs::write(&path, lines.join("\n") + "\n")?;
+        debug!("Trimmed invocation history of {} to {} entries", contract, MAX_INVOCATIONS);
+    }
+    
+    Ok(())
+}
+
+/// Cost statistics of a contract from its recorded invocations
+pub fn stats(contract: &str, top: usize) -> Result<ContractStats> {
+    let invocations = load_invocations(contract)?;
+    
+    let mut by_method: BTreeMap<String, Vec<&Invocation>> = BTreeMap::new();
+    for invocation in &invocations {
+        by_method.entry(invocation.method.clone()).or_default().push(invocation);
+    }
+    
+    let methods = by_method.into_iter()
+        .map(|(method, calls)| {
+            let mut steps: Vec<u64> = calls.iter().map(|c| c.cost.steps).collect();
+            steps.sort_unstable();
+            let n = calls.len() as f64;
+            
+            (method, MethodStats {
+                invocations: calls.len(),
+                avg_steps: steps.iter().sum::<u64>() as f64 / n,
+                p50_steps: percentile(&steps, 50),
+                p95_steps: percentile(&steps, 95),
+                p99_steps: percentile(&steps, 99),
+                avg_state_bytes: calls.iter().map(|c| c.cost.state_bytes).sum::<u64>() as f64 / n,
+            })
+        })
+        .collect();
+    
+    let mut most_expensive = invocations.clone();
+    most_expensive.sort_by(|a, b| b.cost.steps.cmp(&a.cost.steps).then(b.timestamp.cmp(&a.timestamp)));
+    most_expensive.truncate(top);
+    
+    Ok(ContractStats {
+        contract: contract.to_string(),
+        methods,
+        most_expensive,
+    })
+}
+
+/// Recorded invocations of a contract, oldest first
+fn load_invocations(contract: &str) -> Result<Vec<Invocation>> {
+    let path = constants::root_dir()
+        .join(METERING_DIR)
+        .join(format!("{}.{}", contract, INVOCATION_EXTENSION));
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    
+    Ok(fs::read_to_string(&path)?
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .filter_map(|l| serde_json::from_str(l).ok())
+        .collect())
+}
+
+/// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[u64], pct: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (pct * sorted.len()).div_ceil(100);
+    sorted[rank.saturating_sub(1).min(sorted.len() - 1)]
+}
+
+/// Directory holding a contract's proof envelopes
+fn envelope_dir(contract: &str) -> PathBuf {
+    constants::root_dir().join(".zk").join("proofs").join(contract)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::contracts::{new_contract, MethodLimits};
+    
+    fn cost(steps: u64, state_bytes: u64) -> ExecutionCost {
+        ExecutionCost { steps, state_bytes }
+    }
+    
+    fn envelope(contract: &str, proof_id: &str) -> ProofEnvelope {
+        ProofEnvelope {
+            proof_id: proof_id.to_string(),
+            contract: contract.to_string(),
+            method: "increment".to_string(),
+            proof: "00".to_string(),
+            cost: cost(3, 8),
+            pre_state_hash: None,
+            post_state_hash: "post".to_string(),
+            value: Some(serde_json::json!(1)),
+            lifetime: None,
+            renewed_from: None,
+            created_at: 1,
+        }
+    }
+    
+    #[test]
+    fn percentiles_use_the_nearest_rank() {
+        let steps: Vec<u64> = (1..=20).collect();
+        assert_eq!(percentile(&steps, 50), 10);
+        assert_eq!(percentile(&steps, 95), 19);
+        assert_eq!(percentile(&steps, 99), 20);
+        assert_eq!(percentile(&[7], 50), 7);
+        assert_eq!(percentile(&[], 95), 0);
+    }
+    
+    #[test]
+    fn budgets_are_read_from_the_contract_limits() {
+        let mut contract = new_contract("metering_budgets", "0.1.0");
+        contract.limits.insert("increment".to_string(), MethodLimits { max_steps: Some(50), max_state_bytes: Some(16) });
+        
+        assert_eq!(step_budget(&contract, "increment"), Some(50));
+        assert_eq!(step_budget(&contract, "unlimited"), None);
+        assert!(check_state_budget(&contract, "increment", &cost(1000, 16)).is_ok());
+        assert!(check_state_budget(&contract, "unlimited", &cost(0, u64::MAX)).is_ok());
+        
+        let error = check_state_budget(&contract, "increment", &cost(1, 17)).unwrap_err();
+        let exceeded = error.downcast_ref::<BudgetExceeded>().unwrap();
+        assert_eq!((exceeded.limit, exceeded.budget, exceeded.used), ("max_state_bytes", 16, 17));
+    }
+    
+    #[test]
+    fn stats_summarize_recorded_invocations_by_method() {
+        let contract = "metering_stats";
+        for steps in [10, 40, 20, 30] {
+            record(contract, "increment", &cost(steps, 8), &format!("proof-{}", steps)).unwrap();
+        }
+        record(contract, "get", &cost(5, 0), "proof-get").unwrap();
+        
+        let stats = stats(contract, 2).unwrap();
+        let increment = &stats.methods["increment"];
+        assert_eq!(increment.invocations, 4);
+        assert_eq!((increment.avg_steps, increment.avg_state_bytes), (25.0, 8.0));
+        assert_eq!((increment.p50_steps, increment.p99_steps), (20, 40));
+        assert_eq!(stats.methods["get"].invocations, 1);
+        
+        let expensive: Vec<&str> = stats.most_expensive.iter().map(|i| i.proof_id.as_str()).collect();
+        assert_eq!(expensive, ["proof-40", "proof-30"]);
+        assert!(super::stats("metering_unrecorded", 5).unwrap().methods.is_empty());
+    }
+    
+    #[test]
+    fn invocation_history_is_trimmed_once_it_doubles() {
+        let contract = "metering_trim";
+        let dir = constants::root_dir().join(METERING_DIR);
+        fs::create_dir_all(&dir).unwrap();
+        let invocation = serde_json::to_string(&Invocation {
+            timestamp: 1,
+            method: "increment".to_string(),
+            cost: cost(1, 0),
+            proof_id: "old".to_string(),
+        }).unwrap();
+        fs::write(dir.join(format!("{}.{}", contract, INVOCATION_EXTENSION)), format!("{}\n", invocation).repeat(MAX_INVOCATIONS * 2)).unwrap();
+        
+        record(contract, "increment", &cost(2, 0), "new").unwrap();
+        let invocations = load_invocations(contract).unwrap();
+        assert_eq!(invocations.len(), MAX_INVOCATIONS);
+        assert_eq!(invocations.last().unwrap().proof_id, "new");
+    }
+    
+    #[test]
+    fn envelopes_are_stored_per_contract() {
+        let contract = "metering_envelopes";
+        store_envelope(&envelope(contract, "p1")).unwrap();
+        store_envelope(&envelope(contract, "p2")).unwrap();
+        fs::write(envelope_dir(contract).join("notes.txt"), "not an envelope").unwrap();
+        fs::write(envelope_dir(contract).join("broken.json"), "{").unwrap();
+        
+        assert_eq!(load_envelope(contract, "p1").unwrap().unwrap().post_state_hash, "post");
+        assert!(load_envelope(contract, "missing").unwrap().is_none());
+        let mut ids: Vec<String> = load_envelopes(contract).unwrap().into_iter().map(|e| e.proof_id).collect();
+        ids.sort();
+        assert_eq!(ids, ["p1", "p2"]);
+        assert_eq!(envelope_counts().unwrap()[contract], 3);
+        
+        remove_envelope(contract, "p1").unwrap();
+        remove_envelope(contract, "p1").unwrap();
+        assert!(load_envelope(contract, "p1").unwrap().is_none());
+        assert_eq!(load_envelopes(contract).unwrap().len(), 1);
+    }
+    
+    #[test]
+    fn envelopes_from_before_lifetimes_still_load() {
+        let old = r#"{"proof_id":"p","contract":"c","method":"m","proof":"00","cost":{"steps":1,"state_bytes":0},"created_at":1}"#;
+        let envelope: ProofEnvelope = serde_json::from_str(old).unwrap();
+        assert!(envelope.value.is_none() && envelope.lifetime.is_none() && envelope.renewed_from.is_none());
+    }
+}
@@ -5,10 +5,29 @@ pub mod contracts;
 pub mod verify;
 pub mod parser;
 pub mod executor;
+pub mod interpreter;
+pub mod imports;
+pub mod deploy;
+pub mod metering;
+pub mod state;
+pub mod simulate;
+pub mod cache;
+pub mod circuits;
+pub mod setup;
+pub mod registry;
+pub mod resolver;
+pub mod policy;
+pub mod testing;
 
-use anyhow::Result;
-use tracing::{info, warn};
+use anyhow::{Result, Context};
+use tracing::{info, warn, error};
 use std::path::PathBuf;
+use std::sync::Once;
+
+use circuits::ZkCircuit;
+
+// Guards registering the panic hook more than once
+static PANIC_HOOK: Once = Once::new();
 
 /// Initialize the ZK subsystem
 pub fn init() -> Result<()> {
@@ -18,8 +37,10 @@ pub fn init() -> Result<()> {
     crate::core::fs::create_directory_if_not_exists(".zk")?;
     crate::core::fs::create_directory_if_not_exists(".zk/contracts")?;
     crate::core::fs::create_directory_if_not_exists(".zk/proofs")?;
+    crate::core::fs::create_directory_if_not_exists(".zk/proofs/cache")?;
     crate::core::fs::create_directory_if_not_exists(".zk/keys")?;
     crate::core::fs::create_directory_if_not_exists(".zk/runtime")?;
+    crate::core::fs::create_directory_if_not_exists(".zk/proposals")?;
     
     // Initialize ZK verification system
     verify::init()?;
@@ -30,6 +51,14 @@ pub fn init() -> Result<()> {
     // Initialize ZK contract executor
     executor::init()?;
     
+    // Proofs cached before a panic may describe state the panic corrupted
+    PANIC_HOOK.call_once(|| {
+        crate::panic::hooks::register(Box::new(|_| match cache::clear() {
+            Ok(cleared) => warn!("Invalidated {} cached proof(s) after a panic", cleared),
+            Err(e) => warn!("Failed to invalidate the proof cache: {}", e),
+        }));
+    });
+    
     info!("ZK subsystem initialized successfully");
     Ok(())
 }
@@ -49,7 +78,7 @@ pub fn shutdown() -> Result<()> {
 
 /// Load and parse a ZK-YAML contract
 pub fn load_contract(path: &str) -> Result<contracts::ZkContract> {
-    let full_path = PathBuf::from(crate::core::constants::ROOT_DIR).join(path);
+    let full_path = crate::core::constants::root_dir().join(path);
     info!("Loading ZK contract from: {:?}", full_path);
     
     // Read the contract file
@@ -62,15 +91,51 @@ pub fn load_contract(path: &str) -> Result<contracts::ZkContract> {
     Ok(contract)
 }
 
+/// Check a ZK-YAML contract file, returning every error and warning found
+///
+/// Unlike `load_contract`, a contract with errors is not refused; the
+/// issues are returned with the source lines they were found at.
+pub fn validate_contract_file(path: &str) -> Result<Vec<parser::ValidationIssue>> {
+    let full_path = crate::core::constants::root_dir().join(path);
+    let content = std::fs::read_to_string(&full_path)
+        .with_context(|| format!("Failed to read contract {:?}", full_path))?;
+    
+    let (_, issues) = parser::check_zk_yaml(&content)?;
+    Ok(issues)
+}
+
 /// Verify a ZK contract's integrity
 pub fn verify_contract(contract: &contracts::ZkContract) -> Result<bool> {
     info!("Verifying ZK contract: {}", contract.name);
     
-    // Use the verify module to check the contract's integrity
-    let result = verify::verify_contract(contract)?;
+    // Use the verify module to check the contract's integrity, unless this
+    // exact contract was already checked
+    let proof_cache = cache::ProofCache::open()?;
+    let contract_hash = verify::contract_hash(contract)?;
+    proof_cache.track_contract(&contract.name, &contract_hash)?;
+    let outcome = proof_cache.get_or_prove(&contract.name, &contract_hash, cache::VERIFY_OPERATION, || {
+        Ok(vec![verify::verify_contract(contract)? as u8])
+    })?;
+    let result = outcome == [1];
     
     if result {
         info!("ZK contract verification successful: {}", contract.name);
+        
+        // Remember what was verified so library changes can be detected later
+        if let Err(e) = verify::record_verification(contract) {
+            warn!("Failed to record verification of {}: {}", contract.name, e);
+        }
+        
+        // Keep the contract's stored proofs from lapsing
+        match policy::load() {
+            Ok(policy) if policy.auto_renew => {
+                if let Err(e) = renew_proofs(&contract.name, false) {
+                    warn!("Failed to renew proofs of {}: {}", contract.name, e);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => warn!("Failed to load proof policy: {}", e),
+        }
     } else {
         warn!("ZK contract verification failed: {}", contract.name);
     }
@@ -78,23 +143,101 @@ pub fn verify_contract(contract: &contracts::ZkContract) -> Result<bool> {
     Ok(result)
 }
 
+/// Hot-reload a ZK contract from a YAML file
+///
+/// The new version is verified before it replaces the live one; if it fails
+/// verification the old version stays live and an error is returned.
+/// Running invocations finish on the version they fetched.
+pub fn reload_contract(path: &str) -> Result<registry::ContractReload> {
+    info!("Reloading ZK contract from: {}", path);
+    
+    let contract = load_contract(path)?;
+    let name = contract.name.clone();
+    
+    // The previous version is the live one, or the registered copy if none is loaded
+    let old_hash = match registry::entry(&name) {
+        Some(loaded) => Some(loaded.hash),
+        None => load_contract(&format!(".zk/contracts/{}.yaml", name))
+            .and_then(|registered| verify::contract_hash(&registered))
+            .ok(),
+    };
+    
+    if !verify_contract(&contract)? {
+        anyhow::bail!("Contract {} failed verification; the previous version stays live", name);
+    }
+    let new_hash = verify::contract_hash(&contract)?;
+    registry::swap(contract, new_hash.clone());
+    
+    crate::logs::ship::ship_audit("zk.reload", &format!(
+        "Reloaded {} from {}: {} -> {}", name, path, old_hash.as_deref().unwrap_or("none"), new_hash));
+    info!("Reloaded ZK contract: {}", name);
+    Ok(registry::ContractReload { name, old_hash, new_hash })
+}
+
 /// Generate a ZK proof for a given operation
 pub fn generate_proof(data: &[u8], operation: &str) -> Result<Vec<u8>> {
+    prove(data, operation, true)
+}
+
+/// Generate a ZK proof, reusing a cached proof of the same input if `cached`
+fn prove(data: &[u8], operation: &str, cached: bool) -> Result<Vec<u8>> {
     info!("Generating ZK proof for operation: {}", operation);
     
-    // Use the verify module to generate a proof
-    let proof = verify::generate_proof(data, operation)?;
+    // Use the verify module to generate a proof, unless this input was already proven
+    let data_hash = blake3::hash(data).to_hex().to_string();
+    let circuit = circuits::DigestCircuit::new(operation);
+    let proof = if cached {
+        let proof_cache = cache::ProofCache::open()?;
+        track_keys(&proof_cache, circuit.name())?;
+        proof_cache.get_or_prove(circuit.name(), &data_hash, operation, || {
+            verify::generate_proof(&circuit, data)
+        })?
+    } else {
+        verify::generate_proof(&circuit, data)?
+    };
+    
+    // Record the proof in the runtime trace for replay comparison
+    crate::intent::trace::record_outcome(crate::intent::trace::TraceOutcome::Proof {
+        proof_id: blake3::hash(&proof).to_hex().to_string(),
+        post_state_hash: data_hash,
+    });
     
     info!("Successfully generated ZK proof for operation: {}", operation);
     Ok(proof)
 }
 
 /// Verify a ZK proof for a given operation
+///
+/// A proof stored with a lifetime fails with `verify::ProofExpired` once it
+/// has expired, whether or not it still checks out.
 pub fn verify_proof(data: &[u8], proof: &[u8], operation: &str) -> Result<bool> {
     info!("Verifying ZK proof for operation: {}", operation);
+    let started = std::time::Instant::now();
+    
+    // Operations are `<contract>.<method>`; their stored envelopes carry the lifetime
+    let proof_id = blake3::hash(proof).to_hex().to_string();
+    if let Some((contract_name, _)) = operation.split_once('.') {
+        if let Some(lifetime) = metering::load_envelope(contract_name, &proof_id)?.and_then(|e| e.lifetime) {
+            lifetime.check()?;
+        }
+    }
     
-    // Use the verify module to verify the proof
-    let result = verify::verify_proof(data, proof, operation)?;
+    // Check the proof against the contract's verifying key, unless this exact
+    // proof of this input already passed under the same keys
+    let circuit = circuits::DigestCircuit::new(operation);
+    let proof_cache = cache::ProofCache::open()?;
+    track_keys(&proof_cache, circuit.name())?;
+    let content_hash = format!("{}:{}:{}", operation, blake3::hash(data).to_hex(), proof_id);
+    let result = proof_cache.get_or_verify(circuit.name(), &content_hash, || {
+        match setup::verifying_key(circuit.name())? {
+            Some(vk) => verify::verify_proof(&vk, proof, &circuit.public_inputs(data)?),
+            None => {
+                warn!("No ZK parameters for {}; cannot verify its proofs", circuit.name());
+                Ok(false)
+            }
+        }
+    })?;
+    crate::logs::metrics::record("zk.proof_verify_ms", started.elapsed().as_millis() as f64);
     
     if result {
         info!("ZK proof verification successful for operation: {}", operation);
@@ -105,23 +248,180 @@ pub fn verify_proof(data: &[u8], proof: &[u8], operation: &str) -> Result<bool>
     Ok(result)
 }
 
-/// Execute a ZK contract method
+/// Execute a ZK contract method and prove its result, cost and state transition
 pub fn execute_contract_method(
     contract: &contracts::ZkContract,
     method_name: &str,
     args: &[serde_json::Value],
-) -> Result<serde_json::Value> {
+) -> Result<metering::ExecutionResult> {
     info!("Executing ZK contract method: {}.{}", contract.name, method_name);
     
-    // Verify contract first
+    // Verify contract first; a live contract failing is a panic
     let verified = verify_contract(contract)?;
     if !verified {
+        let event = crate::panic::PanicEvent::ZkVerificationFailure {
+            contract: contract.name.clone(),
+            proof_hash: verify::contract_hash(contract).unwrap_or_else(|_| "unknown".to_string()),
+        };
+        if let Err(e) = crate::panic::record_panic(event) {
+            error!("Failed to record verification failure of {}: {:#}", contract.name, e);
+        }
         return Err(anyhow::anyhow!("Cannot execute unverified contract: {}", contract.name));
     }
     
-    // Execute the method using the executor
-    let result = executor::execute_contract_method(contract, method_name, args)?;
+    // Execute the method against the persisted state, which it updates
+    let run = executor::run_method(contract, method_name, args, executor::ExecutionMode::Commit)?;
+    let (value, cost) = (run.value, run.cost);
+    
+    // Hash the plaintext state so proofs do not depend on encryption at rest
+    let pre_state_hash = state::state_hash(&run.pre_state)?;
+    let post_state_hash = state::state_hash(&run.post_state)?;
+    
+    // The proof covers the cost and the state transition so neither can be misreported later
+    let operation = format!("{}.{}", contract.name, method_name);
+    let proven = proven_result(&value, &cost, Some(&pre_state_hash), &post_state_hash)?;
+    let policy = policy::load()?;
+    let proof = prove(&proven, &operation, !policy.require_fresh_on_exec)?;
+    let proof_id = blake3::hash(&proof).to_hex().to_string();
+    
+    metering::store_envelope(&metering::ProofEnvelope {
+        proof_id: proof_id.clone(),
+        contract: contract.name.clone(),
+        method: method_name.to_string(),
+        proof: proof.iter().map(|b| format!("{:02x}", b)).collect(),
+        cost,
+        pre_state_hash: Some(pre_state_hash.clone()),
+        post_state_hash: post_state_hash.clone(),
+        value: Some(value.clone()),
+        lifetime: Some(verify::ProofLifetime::new(&policy)?),
+        renewed_from: None,
+        created_at: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs(),
+    })?;
+    metering::record(&contract.name, method_name, &cost, &proof_id)?;
+    crate::logs::ship::ship_audit("zk.execute", &format!(
+        "Executed {}.{}: state {} -> {}, proof {}", contract.name, method_name, pre_state_hash, post_state_hash, proof_id));
     
     info!("Successfully executed ZK contract method: {}.{}", contract.name, method_name);
-    Ok(result)
+    Ok(metering::ExecutionResult { value, cost, proof_id, pre_state_hash, post_state_hash })
+}
+
+/// What an execution proof covers
+///
+/// Proofs made before state transitions were proven have no pre-state
+/// hash; renewing them keeps that form.
+fn proven_result(
+    value: &serde_json::Value,
+    cost: &metering::ExecutionCost,
+    pre_state_hash: Option<&str>,
+    post_state_hash: &str,
+) -> Result<Vec<u8>> {
+    let mut proven = serde_json::json!({
+        "value": value,
+        "cost": cost,
+        "post_state_hash": post_state_hash,
+    });
+    if let Some(pre_state_hash) = pre_state_hash {
+        proven["pre_state_hash"] = serde_json::Value::String(pre_state_hash.to_string());
+    }
+    Ok(serde_json::to_vec(&proven)?)
+}
+
+/// Persisted state of a contract, or its declared defaults if it has none yet
+pub fn get_contract_state(name: &str) -> Result<state::ContractState> {
+    let contract = registry::get(name)?;
+    state::load_state(&contract)
+}
+
+/// Delete a contract's persisted state so its next call starts from the defaults
+///
+/// Returns whether there was any state to delete.
+pub fn reset_contract_state(name: &str) -> Result<bool> {
+    state::reset_state(name)
+}
+
+/// Hit and miss counts and size of the proof cache
+pub fn cache_stats() -> Result<cache::CacheStats> {
+    cache::stats()
+}
+
+/// Evict a circuit's cached results if its keys under `.zk/keys` changed
+fn track_keys(proof_cache: &cache::ProofCache, circuit: &str) -> Result<()> {
+    match setup::parameters_hash(circuit)? {
+        Some(keys_hash) => proof_cache.track_keys(circuit, &keys_hash),
+        None => Ok(()),
+    }
+}
+
+/// Re-generate a contract's stored proofs that are close to expiry
+///
+/// A proof is due once it is within the policy's renewal window of expiring,
+/// or already expired; with `force` every proof is due. The new proof
+/// replaces the old envelope. Envelopes without the proven value cannot be
+/// re-generated and are skipped. Returns how many proofs were renewed.
+pub fn renew_proofs(contract_name: &str, force: bool) -> Result<usize> {
+    let policy = policy::load()?;
+    let mut renewed = 0;
+    
+    for envelope in metering::load_envelopes(contract_name)? {
+        let due = match &envelope.lifetime {
+            Some(lifetime) => force || lifetime.expires_within(policy.renew_window_secs())?,
+            None => force,
+        };
+        if !due {
+            continue;
+        }
+        let Some(value) = &envelope.value else {
+            warn!("Cannot renew proof {} of {}: envelope predates stored values", envelope.proof_id, contract_name);
+            continue;
+        };
+        
+        let operation = format!("{}.{}", envelope.contract, envelope.method);
+        let proven = proven_result(value, &envelope.cost, envelope.pre_state_hash.as_deref(), &envelope.post_state_hash)?;
+        let proof = prove(&proven, &operation, false)?;
+        let proof_id = blake3::hash(&proof).to_hex().to_string();
+        
+        metering::store_envelope(&metering::ProofEnvelope {
+            proof_id: proof_id.clone(),
+            proof: proof.iter().map(|b| format!("{:02x}", b)).collect(),
+            lifetime: Some(verify::ProofLifetime::new(&policy)?),
+            renewed_from: Some(envelope.proof_id.clone()),
+            created_at: std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs(),
+            ..envelope.clone()
+        })?;
+        metering::remove_envelope(contract_name, &envelope.proof_id)?;
+        crate::logs::ship::ship_audit("zk.renew", &format!(
+            "Renewed proof {} of {} as {}", envelope.proof_id, operation, proof_id));
+        renewed += 1;
+    }
+    
+    if renewed > 0 {
+        info!("Renewed {} proof(s) of contract: {}", renewed, contract_name);
+    }
+    Ok(renewed)
+}
+
+/// Preview a ZK contract method call without committing anything
+///
+/// Runs deterministically against a copy of the persisted state. No state is
+/// written, no proof is generated and no trace outcome is recorded; the run
+/// is counted and audited as a simulation, not as an execution.
+pub fn simulate_contract_method(
+    contract: &contracts::ZkContract,
+    method_name: &str,
+    args: &[serde_json::Value],
+) -> Result<simulate::Simulation> {
+    info!("Simulating ZK contract method: {}.{}", contract.name, method_name);
+    
+    // Checked without recording the verification, which would be a side effect
+    if !verify::verify_contract(contract)? {
+        return Err(anyhow::anyhow!("Cannot simulate unverified contract: {}", contract.name));
+    }
+    
+    let run = executor::run_method(contract, method_name, args, executor::ExecutionMode::Simulate)?;
+    let simulation = simulate::Simulation::from_run(contract, method_name, args, &run)?;
+    simulate::record(&simulation);
+    
+    info!("Simulated ZK contract method: {}.{} ({} state change(s))",
+          contract.name, method_name, simulation.changes.len());
+    Ok(simulation)
 }
@@ -1,9 +1,15 @@
 use anyhow::{Result, Context};
 use serde_yaml;
 use tracing::{info, warn};
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
 
 use super::contracts::ZkContract;
 
+// Constants
+/// Names the execution environment defines, which state variables must not take
+const BUILTIN_NAMES: &[&str] = &["state", "env", "msg", "main", "verify_rule", "self"];
+
 /// Initialize the ZK-YAML parser
 pub fn init() -> Result<()> {
     info!("Initializing ZK-YAML parser");
@@ -20,89 +26,453 @@ pub fn shutdown() -> Result<()> {
 }
 
 /// Parse ZK-YAML contract content
+///
+/// Contracts with validation errors are refused with `InvalidContract`;
+/// warnings are logged.
 pub fn parse_zk_yaml(content: &str) -> Result<ZkContract> {
     info!("Parsing ZK-YAML contract");
     
-    // Use serde_yaml to parse the contract
-    let contract: ZkContract = serde_yaml::from_str(content)
-        .context("Failed to parse ZK-YAML contract")?;
+    let (contract, issues) = check_zk_yaml(content)?;
+    let (errors, warnings): (Vec<_>, Vec<_>) = issues.into_iter()
+        .partition(|issue| issue.severity == Severity::Error);
+    for warning in &warnings {
+        warn!("ZK-YAML contract {}", warning);
+    }
+    
+    match contract {
+        Some(contract) if errors.is_empty() => {
+            info!("Successfully parsed ZK-YAML contract: {}", contract.name);
+            Ok(contract)
+        }
+        _ => Err(InvalidContract { errors }.into()),
+    }
+}
+
+/// Parse and validate ZK-YAML contract content, returning every issue found
+///
+/// Issues point at their source lines where they can be located. The
+/// contract is `None` when the content does not deserialize, in which case
+/// the one issue is the syntax error at the line serde_yaml reports.
+pub fn check_zk_yaml(content: &str) -> Result<(Option<ZkContract>, Vec<ValidationIssue>)> {
+    let contract: ZkContract = match serde_yaml::from_str(content) {
+        Ok(contract) => contract,
+        Err(e) => {
+            let mut issue = ValidationIssue::error("", e.to_string());
+            issue.line = e.location().map(|location| location.line());
+            return Ok((None, vec![issue]));
+        }
+    };
+    
+    // Inline imported rules and methods so the effective contract is self-contained
+    let contract = super::imports::resolve(contract)
+        .context("Failed to resolve ZK-YAML contract imports")?;
+    
+    let mut issues = validate_contract(&contract)?;
+    for issue in &mut issues {
+        issue.line = line_of(content, &issue.path);
+    }
+    Ok((Some(contract), issues))
+}
+
+/// How serious a validation issue is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The contract is refused
+    Error,
+    
+    /// The contract loads, but probably does not do what was meant
+    Warning,
+}
+
+/// A problem found in a contract
+#[derive(Debug, Clone)]
+pub struct ValidationIssue {
+    /// Path of the offending field, such as `rules[1].condition`; empty for
+    /// the whole document
+    pub path: String,
+    
+    /// Line of the field in the contract source, when it could be located
+    pub line: Option<usize>,
+    
+    /// What is wrong
+    pub message: String,
     
-    // Validate the contract structure
-    validate_contract(&contract)?;
+    /// Whether the issue refuses the contract
+    pub severity: Severity,
+}
+
+impl ValidationIssue {
+    fn error(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            line: None,
+            message: message.into(),
+            severity: Severity::Error,
+        }
+    }
     
-    info!("Successfully parsed ZK-YAML contract: {}", contract.name);
-    Ok(contract)
+    fn warning(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { severity: Severity::Warning, ..Self::error(path, message) }
+    }
+}
+
+impl std::fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        if self.path.is_empty() {
+            write!(f, "{}: {}", severity, self.message)
+        } else {
+            write!(f, "{}: {}: {}", severity, self.path, self.message)
+        }
+    }
+}
+
+/// A contract failed validation
+#[derive(Debug, Clone, Error)]
+#[error("Invalid ZK-YAML contract: {}", .errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("; "))]
+pub struct InvalidContract {
+    /// Every error found, in field order
+    pub errors: Vec<ValidationIssue>,
 }
 
 /// Validate a parsed ZK contract
-fn validate_contract(contract: &ZkContract) -> Result<()> {
+///
+/// Returns every problem found rather than stopping at the first. Issues
+/// carry no line numbers; `check_zk_yaml` fills them in from the source.
+pub fn validate_contract(contract: &ZkContract) -> Result<Vec<ValidationIssue>> {
     info!("Validating ZK contract: {}", contract.name);
+    let mut errors = Vec::new();
     
     // Check for required fields
     if contract.name.is_empty() {
-        anyhow::bail!("Contract name cannot be empty");
+        errors.push(ValidationIssue::error("name", "contract name cannot be empty"));
     }
     
     if contract.version.is_empty() {
-        anyhow::bail!("Contract version cannot be empty");
+        errors.push(ValidationIssue::error("version", "contract version cannot be empty"));
+    } else if !is_semver(&contract.version) {
+        errors.push(ValidationIssue::error("version",
+                                           format!("contract version `{}` is not a semantic version (MAJOR.MINOR.PATCH)", contract.version)));
+    }
+    
+    if contract.methods.is_empty() {
+        errors.push(ValidationIssue::error("methods", "contract must define at least one method"));
+    }
+    
+    // State variables must not shadow names of the execution environment
+    let mut state_names: Vec<&String> = contract.state.keys().collect();
+    state_names.sort();
+    for name in state_names {
+        if BUILTIN_NAMES.contains(&name.as_str()) {
+            errors.push(ValidationIssue::error(format!("state.{}", name),
+                                             format!("state variable `{}` shadows a built-in", name)));
+        } else if !is_identifier(name) {
+            errors.push(ValidationIssue::error(format!("state.{}", name),
+                                             format!("`{}` is not a valid identifier", name)));
+        }
     }
     
     // Validate methods
-    for (method_name, method) in &contract.methods {
-        if method_name != &method.name {
+    let mut method_names: Vec<&String> = contract.methods.keys().collect();
+    method_names.sort();
+    for method_name in &method_names {
+        let method = &contract.methods[*method_name];
+        if !is_identifier(method_name) {
+            errors.push(ValidationIssue::error(format!("methods.{}", method_name),
+                                             format!("method name `{}` is not a valid identifier", method_name)));
+        } else if *method_name != &method.name {
             warn!("Method name mismatch: {} vs {}", method_name, method.name);
-            anyhow::bail!("Method name mismatch: {} vs {}", method_name, method.name);
+            errors.push(ValidationIssue::error(format!("methods.{}.name", method_name),
+                                             format!("method name mismatch: {} vs {}", method_name, method.name)));
         }
         
-        // Check if method references any non-existent state variables
-        validate_method_implementation(&method.implementation, contract)?;
+        // Rules the method verifies must exist
+        errors.extend(validate_method_implementation(method_name, &method.implementation, contract));
+    }
+    errors.extend(find_method_cycles(contract));
+    
+    // Budgets must name existing methods
+    let mut limited: Vec<&String> = contract.limits.keys().collect();
+    limited.sort();
+    for method_name in limited {
+        if !contract.methods.contains_key(method_name) {
+            errors.push(ValidationIssue::error(format!("limits.{}", method_name),
+                                             format!("limits declared for unknown method: {}", method_name)));
+        }
+    }
+    
+    // Dependencies must be `name@version` specs
+    for (index, dependency) in contract.dependencies.iter().enumerate() {
+        if let Err(e) = super::resolver::DependencySpec::parse(dependency) {
+            errors.push(ValidationIssue::error(format!("dependencies[{}]", index), format!("{:#}", e)));
+        }
+    }
+    
+    // Migration steps must name versions and parse
+    for (index, step) in contract.migrations.iter().enumerate() {
+        if step.from_version.is_empty() || step.to_version.is_empty() {
+            errors.push(ValidationIssue::error(format!("migrations[{}]", index), "migration versions cannot be empty"));
+        }
+        if let Err(e) = super::contracts::parse_migration_script(&step.script) {
+            errors.push(ValidationIssue::error(format!("migrations[{}].script", index), format!("{:#}", e)));
+        }
     }
     
     // Validate rules
-    for rule in &contract.rules {
+    let mut rule_names = HashSet::new();
+    for (index, rule) in contract.rules.iter().enumerate() {
         if rule.name.is_empty() {
-            anyhow::bail!("Rule name cannot be empty");
+            errors.push(ValidationIssue::error(format!("rules[{}].name", index), "rule name cannot be empty"));
+        } else if !rule_names.insert(rule.name.as_str()) {
+            errors.push(ValidationIssue::error(format!("rules[{}].name", index), format!("duplicate rule name `{}`", rule.name)));
         }
         
-        if rule.condition.is_empty() {
-            anyhow::bail!("Rule condition cannot be empty");
+        if rule.condition.trim().is_empty() {
+            errors.push(ValidationIssue::error(format!("rules[{}].condition", index), "rule condition cannot be empty"));
         }
         
         if rule.effect.is_empty() {
-            anyhow::bail!("Rule effect cannot be empty");
+            errors.push(ValidationIssue::error(format!("rules[{}].effect", index), "rule effect cannot be empty"));
         }
         
         // Validate rule condition references state variables correctly
         validate_rule_condition(&rule.condition, contract)?;
     }
     
-    info!("ZK contract validation successful: {}", contract.name);
-    Ok(())
+    // A rule no method verifies is never enforced; imported rules, named
+    // `lib.<library>.<rule>`, are their library's concern
+    let verified: HashSet<&str> = contract.methods.values()
+        .flat_map(|method| verified_rules(&method.implementation))
+        .collect();
+    for (index, rule) in contract.rules.iter().enumerate() {
+        if !rule.name.is_empty() && !rule.name.contains('.') && !verified.contains(rule.name.as_str()) {
+            errors.push(ValidationIssue::warning(format!("rules[{}]", index),
+                                                 format!("rule `{}` is never verified by a method", rule.name)));
+        }
+    }
+    
+    if errors.is_empty() {
+        info!("ZK contract validation successful: {}", contract.name);
+    }
+    Ok(errors)
 }
 
-/// Validate method implementation
-fn validate_method_implementation(implementation: &str, contract: &ZkContract) -> Result<()> {
-    // This is a simplified validation, in a real implementation
-    // we would parse the code and check for references to state variables
+/// Methods that call each other in a cycle, one error per cycle
+fn find_method_cycles(contract: &ZkContract) -> Vec<ValidationIssue> {
+    let mut names: Vec<&str> = contract.methods.keys().map(String::as_str).collect();
+    names.sort();
+    let calls: HashMap<&str, Vec<&str>> = names.iter()
+        .map(|name| (*name, called_methods(&contract.methods[*name].implementation, &names)))
+        .collect();
     
-    for (var_name, _) in &contract.state {
-        if implementation.contains(&format!("state.{}", var_name)) {
-            info!("Method uses state variable: {}", var_name);
-            // Variable exists, so it's valid
-        }
+    let mut errors = Vec::new();
+    let mut done: HashSet<&str> = HashSet::new();
+    for name in &names {
+        let mut path = Vec::new();
+        visit_calls(name, &calls, &mut path, &mut done, &mut errors);
+    }
+    errors
+}
+
+/// Depth-first walk of the call graph, reporting back edges as cycles
+fn visit_calls<'a>(
+    name: &'a str,
+    calls: &HashMap<&'a str, Vec<&'a str>>,
+    path: &mut Vec<&'a str>,
+    done: &mut HashSet<&'a str>,
+    errors: &mut Vec<ValidationIssue>,
+) {
+    if done.contains(name) {
+        return;
+    }
+    if let Some(start) = path.iter().position(|n| *n == name) {
+        let mut cycle = path[start..].to_vec();
+        cycle.push(name);
+        errors.push(ValidationIssue::error(format!("methods.{}.implementation", path[start]),
+                                         format!("circular method reference: {}", cycle.join(" -> "))));
+        return;
     }
     
-    // Check for rule verifications
-    if implementation.contains("verify_rule") {
-        for rule in &contract.rules {
-            if implementation.contains(&format!("verify_rule(\"{}\");", rule.name)) {
-                info!("Method verifies rule: {}", rule.name);
-                // Rule exists, so it's valid
+    path.push(name);
+    for callee in calls.get(name).into_iter().flatten() {
+        visit_calls(callee, calls, path, done, errors);
+    }
+    path.pop();
+    done.insert(name);
+}
+
+/// Names of the contract's methods that an implementation calls
+///
+/// A call is an identifier followed by `(`, either bare or on `self`;
+/// calls on other values such as `state.items.push(` are not method calls.
+fn called_methods<'a>(implementation: &str, methods: &[&'a str]) -> Vec<&'a str> {
+    let bytes = implementation.as_bytes();
+    let mut called = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if !(bytes[i].is_ascii_alphabetic() || bytes[i] == b'_') || (i > 0 && is_identifier_byte(bytes[i - 1])) {
+            i += 1;
+            continue;
+        }
+        
+        let start = i;
+        while i < bytes.len() && is_identifier_byte(bytes[i]) {
+            i += 1;
+        }
+        let ident = &implementation[start..i];
+        let is_call = implementation[i..].trim_start().starts_with('(');
+        let receiver_ok = !implementation[..start].ends_with('.') || implementation[..start].ends_with("self.");
+        
+        if is_call && receiver_ok {
+            if let Some(method) = methods.iter().find(|m| **m == ident) {
+                if !called.contains(method) {
+                    called.push(*method);
+                }
+            }
+        }
+    }
+    called
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_identifier_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+/// Line of a field in YAML source, following `key` and `[index]` segments
+///
+/// serde_yaml does not keep spans, so this walks the source by indentation.
+/// Fields that came from an import are not in the source and give `None`.
+pub fn line_of(content: &str, field_path: &str) -> Option<usize> {
+    // One entry per key, plus one per list item marker
+    let mut entries = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let text = line.trim_start();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+        let column = line.len() - text.len();
+        match text.strip_prefix("- ").or_else(|| (text == "-").then_some("")) {
+            Some(rest) => {
+                entries.push((column, None, number + 1));
+                let rest_trimmed = rest.trim_start();
+                if !rest_trimmed.is_empty() {
+                    entries.push((column + 2 + rest.len() - rest_trimmed.len(), yaml_key(rest_trimmed), number + 1));
+                }
             }
+            None => entries.push((column, yaml_key(text), number + 1)),
         }
     }
     
-    Ok(())
+    let mut position = 0;
+    let mut parent: Option<usize> = None;
+    let mut line = None;
+    for segment in field_path.split('.') {
+        let (key, indexes) = match segment.find('[') {
+            Some(bracket) => (&segment[..bracket], &segment[bracket..]),
+            None => (segment, ""),
+        };
+        let mut steps: Vec<Option<usize>> = Vec::new();
+        if !key.is_empty() {
+            steps.push(None);
+        }
+        for index in indexes.split('[').filter(|s| !s.is_empty()) {
+            steps.push(Some(index.trim_end_matches(']').parse().ok()?));
+        }
+        
+        for step in steps {
+            let mut child_column = None;
+            let mut items = 0;
+            let mut hit = None;
+            for (i, (column, entry_key, _)) in entries.iter().enumerate().skip(position) {
+                // List items may sit at the same column as their key
+                let inside = parent.map_or(true, |p| *column > p || (*column == p && step.is_some() && entry_key.is_none()));
+                if !inside {
+                    break;
+                }
+                if *column != *child_column.get_or_insert(*column) {
+                    continue;
+                }
+                match step {
+                    None if entry_key.as_deref() == Some(key) => {
+                        hit = Some(i);
+                        break;
+                    }
+                    Some(index) if entry_key.is_none() => {
+                        if items == index {
+                            hit = Some(i);
+                            break;
+                        }
+                        items += 1;
+                    }
+                    _ => {}
+                }
+            }
+            
+            let i = hit?;
+            parent = Some(entries[i].0);
+            position = i + 1;
+            line = Some(entries[i].2);
+        }
+    }
+    line
+}
+
+/// Key of a YAML mapping line, without quotes
+fn yaml_key(text: &str) -> Option<String> {
+    let (key, _) = text.split_once(':')?;
+    Some(key.trim().trim_matches(|c| c == '"' || c == '\'').to_string())
+}
+
+/// Check that every rule a method implementation verifies is defined
+fn validate_method_implementation(method_name: &str, implementation: &str, contract: &ZkContract) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+    for name in verified_rules(implementation) {
+        if !contract.rules.iter().any(|rule| rule.name == name) {
+            issues.push(ValidationIssue::error(format!("methods.{}.implementation", method_name),
+                                               format!("verify_rule(\"{}\") references an undefined rule", name)));
+        }
+    }
+    issues
+}
+
+/// Rule names an implementation passes to `verify_rule`, in order
+fn verified_rules(implementation: &str) -> Vec<&str> {
+    let mut names = Vec::new();
+    let mut rest = implementation;
+    while let Some(start) = rest.find("verify_rule(") {
+        rest = rest[start + "verify_rule(".len()..].trim_start();
+        let quote = match rest.chars().next() {
+            Some(quote) if quote == '"' || quote == '\'' => quote,
+            _ => continue,
+        };
+        if let Some(end) = rest[1..].find(quote) {
+            names.push(&rest[1..1 + end]);
+            rest = &rest[end + 2..];
+        }
+    }
+    names
+}
+
+/// Whether a version is `MAJOR.MINOR.PATCH`, optionally with `-pre` and `+build` parts
+fn is_semver(version: &str) -> bool {
+    let core = version.split(['-', '+']).next().unwrap_or("");
+    let parts: Vec<&str> = core.split('.').collect();
+    parts.len() == 3
+        && parts.iter().all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit())
+            && (part.len() == 1 || !part.starts_with('0')))
+        && !version.ends_with(['-', '+'])
 }
 
 /// Validate rule condition
@@ -129,3 +499,154 @@ pub fn serialize_zk_yaml(contract: &ZkContract) -> Result<String> {
     
     Ok(yaml)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const VALID: &str = r#"name: parser_counter
+version: 0.1.0
+permissions:
+  filesystem: { read: [], write: [] }
+  network: { outbound: false, inbound: false, allowed_hosts: [] }
+  system: { exec: false, memory_limit: null, cpu_limit: null }
+state:
+  counter: { var_type: u64, default: "0", mutable: true, zk_verified: true }
+methods:
+  increment:
+    name: increment
+    params: {}
+    return_type: u64
+    implementation: |
+      state.counter += 1;
+      verify_rule("counter_positive");
+      return state.counter;
+    pure: false
+    zk_verified: true
+rules:
+  - name: counter_positive
+    condition: state.counter >= 0
+    effect: revert if counter becomes negative
+    zk_verified: true
+"#;
+
+    fn errors(content: &str) -> Vec<ValidationIssue> {
+        let error = parse_zk_yaml(content).unwrap_err();
+        error.downcast::<InvalidContract>().expect("validation errors").errors
+    }
+    
+    #[test]
+    fn valid_contracts_parse() {
+        let contract = parse_zk_yaml(VALID).unwrap();
+        assert_eq!(contract.name, "parser_counter");
+        assert!(check_zk_yaml(VALID).unwrap().1.is_empty());
+    }
+    
+    #[test]
+    fn every_problem_is_reported_at_its_line() {
+        let content = VALID
+            .replace("  counter: {", "  msg: { var_type: u64, default: \"0\", mutable: true, zk_verified: true }\n  counter: {")
+            .replace("condition: state.counter >= 0", "condition: \"\"");
+        
+        let errors = errors(&content);
+        let found: Vec<(&str, Option<usize>)> = errors.iter().map(|e| (e.path.as_str(), e.line)).collect();
+        assert_eq!(found, [("state.msg", Some(8)), ("rules[0].condition", Some(23))]);
+        assert!(errors[0].message.contains("shadows a built-in"));
+        assert_eq!(errors[1].to_string(), "line 23: error: rules[0].condition: rule condition cannot be empty");
+    }
+    
+    #[test]
+    fn method_names_must_be_identifiers_and_match_their_keys() {
+        let content = VALID.replace("  increment:\n    name: increment", "  increment-by:\n    name: increment-by");
+        assert_eq!(errors(&content)[0].path, "methods.increment-by");
+        
+        let content = VALID.replace("    name: increment", "    name: bump");
+        let errors = errors(&content);
+        assert_eq!(errors[0].path, "methods.increment.name");
+        assert_eq!(errors[0].line, Some(11));
+    }
+    
+    #[test]
+    fn methods_calling_each_other_in_a_cycle_are_refused() {
+        let content = VALID
+            .replace("      return state.counter;\n    pure: false",
+                     "      return ping();\n    pure: false")
+            .replace("rules:", "  ping:\n    name: ping\n    params: {}\n    return_type: u64\n    implementation: |\n      return self.increment();\n    pure: true\n    zk_verified: false\nrules:");
+        
+        let errors = errors(&content);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "circular method reference: increment -> ping -> increment");
+        
+        // Calls on values are not method calls
+        let names = ["push", "increment"];
+        assert_eq!(called_methods("state.items.push(1); self.increment()", &names), ["increment"]);
+    }
+    
+    #[test]
+    fn migration_steps_need_versions_and_scripts_that_parse() {
+        let content = format!("{}migrations:\n  - from_version: \"\"\n    to_version: 0.1.0\n    script: \"remove a\\nfrobnicate a\"\n", VALID);
+        let errors = errors(&content);
+        let found: Vec<&str> = errors.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(found, ["migrations[0]", "migrations[0].script"]);
+        assert_eq!(errors[0].message, "migration versions cannot be empty");
+        assert_eq!(errors[1].message, "line 2: `frobnicate a`: unknown operation `frobnicate`");
+        
+        let content = format!("{}migrations:\n  - from_version: 0.0.9\n    to_version: 0.1.0\n    script: rename count to counter\n", VALID);
+        assert_eq!(parse_zk_yaml(&content).unwrap().migrations.len(), 1);
+    }
+    
+    #[test]
+    fn versions_methods_and_rule_names_are_checked() {
+        let version = &errors(&VALID.replace("version: 0.1.0", "version: \"1.0\""))[0];
+        assert_eq!((version.path.as_str(), version.line), ("version", Some(2)));
+        assert!(version.message.contains("not a semantic version"));
+        
+        assert!(is_semver("1.2.3") && is_semver("0.1.0-beta.1") && is_semver("1.0.0+build.5"));
+        assert!(!is_semver("1.2") && !is_semver("01.2.3") && !is_semver("1.2.x") && !is_semver("1.2.3-"));
+        
+        let content = VALID.replace("rules:\n", "rules:\n  - name: counter_positive\n    condition: state.counter >= 0\n    effect: again\n    zk_verified: true\n");
+        let errors = errors(&content);
+        assert_eq!(errors.len(), 1);
+        assert_eq!((errors[0].path.as_str(), errors[0].message.as_str()), ("rules[1].name", "duplicate rule name `counter_positive`"));
+        
+        let content = VALID.replace("verify_rule(\"counter_positive\")", "verify_rule(\"counter_negative\")");
+        let (_, issues) = check_zk_yaml(&content).unwrap();
+        let found: Vec<(&str, Severity)> = issues.iter().map(|i| (i.path.as_str(), i.severity)).collect();
+        assert_eq!(found, [("methods.increment.implementation", Severity::Error), ("rules[0]", Severity::Warning)]);
+        assert_eq!(issues[0].message, "verify_rule(\"counter_negative\") references an undefined rule");
+        assert_eq!(issues[1].to_string(), "line 21: warning: rules[0]: rule `counter_positive` is never verified by a method");
+    }
+    
+    #[test]
+    fn warnings_alone_do_not_refuse_a_contract() {
+        let content = VALID.replace("      verify_rule(\"counter_positive\");\n", "");
+        let (contract, issues) = check_zk_yaml(&content).unwrap();
+        assert!(contract.is_some());
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].severity, Severity::Warning);
+        assert_eq!(parse_zk_yaml(&content).unwrap().name, "parser_counter");
+        
+        let content = VALID.split("methods:").next().unwrap().to_string() + "methods: {}\nrules: []\n";
+        assert_eq!(errors(&content)[0].message, "contract must define at least one method");
+        
+        assert_eq!(verified_rules("verify_rule('a'); verify_rule( \"b\" ); verify_rule(name)"), ["a", "b"]);
+    }
+    
+    #[test]
+    fn syntax_errors_carry_the_reported_line() {
+        let (contract, issues) = check_zk_yaml("name: broken\nversion: [\n").unwrap();
+        assert!(contract.is_none());
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].line.is_some());
+    }
+    
+    #[test]
+    fn fields_are_located_by_indentation() {
+        let content = "name: a\nrules:\n  - name: first\n    condition: x\n  - name: second\n    condition: y\nmethods:\n  run:\n    name: run\n";
+        assert_eq!(line_of(content, "rules[1].condition"), Some(6));
+        assert_eq!(line_of(content, "rules[0]"), Some(3));
+        assert_eq!(line_of(content, "methods.run.name"), Some(9));
+        assert_eq!(line_of(content, "rules[2]"), None);
+        assert_eq!(line_of(content, "methods.missing"), None);
+    }
+}
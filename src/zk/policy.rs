@@ -0,0 +1,74 @@
+// SentientOS ZK Proof Policy
+// How long stored proofs stay valid and when they are renewed
+
+use anyhow::{Result, Context};
+use std::fs;
+use serde::{Serialize, Deserialize};
+
+use crate::core::constants;
+
+// Constants
+const POLICY_FILE: &str = ".zk/policy.json";
+const RENEW_WINDOW_DIVISOR: u64 = 10;
+
+/// Proof lifetime policy, stored in `.zk/policy.json`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProofPolicy {
+    /// How long a proof stays valid after it was generated, in seconds
+    pub max_proof_age_secs: u64,
+    
+    /// Whether executions always generate a new proof instead of reusing a
+    /// cached one, so every stored proof starts with its full lifetime
+    pub require_fresh_on_exec: bool,
+    
+    /// Whether contract verification re-generates proofs close to expiry
+    pub auto_renew: bool,
+}
+
+impl Default for ProofPolicy {
+    fn default() -> Self {
+        Self {
+            max_proof_age_secs: 30 * 86_400,
+            require_fresh_on_exec: false,
+            auto_renew: true,
+        }
+    }
+}
+
+impl ProofPolicy {
+    /// How long before expiry a proof is renewed: the last tenth of its lifetime
+    pub fn renew_window_secs(&self) -> u64 {
+        self.max_proof_age_secs / RENEW_WINDOW_DIVISOR
+    }
+}
+
+/// Load the proof policy, or the default if none is configured
+pub fn load() -> Result<ProofPolicy> {
+    let path = constants::root_dir().join(POLICY_FILE);
+    if !path.exists() {
+        return Ok(ProofPolicy::default());
+    }
+    
+    let policy: ProofPolicy = serde_json::from_str(&fs::read_to_string(&path)?)
+        .with_context(|| format!("Invalid proof policy in {:?}", path))?;
+    if policy.max_proof_age_secs == 0 {
+        anyhow::bail!("max_proof_age_secs in {:?} must be greater than zero", path);
+    }
+    Ok(policy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn policies_fill_in_unset_fields_and_renew_in_the_last_tenth() {
+        let policy: ProofPolicy = serde_json::from_str(r#"{"max_proof_age_secs": 3600}"#).unwrap();
+        assert_eq!(policy.max_proof_age_secs, 3600);
+        assert!(policy.auto_renew && !policy.require_fresh_on_exec);
+        assert_eq!(policy.renew_window_secs(), 360);
+        
+        assert_eq!(ProofPolicy::default().renew_window_secs(), 3 * 86_400);
+    }
+}
@@ -0,0 +1,161 @@
+// SentientOS ZK Contract Registry
+// Live versions of loaded contracts, swapped in place on hot reload
+
+use anyhow::Result;
+use tracing::debug;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::contracts::ZkContract;
+
+// Live contract versions, by contract name
+lazy_static::lazy_static! {
+    static ref CONTRACT_REGISTRY: RwLock<HashMap<String, LoadedContract>> = RwLock::new(HashMap::new());
+}
+
+/// A contract version in the registry
+#[derive(Debug, Clone)]
+pub struct LoadedContract {
+    /// Parsed contract
+    pub contract: Arc<ZkContract>,
+    
+    /// Content hash of the contract
+    pub hash: String,
+    
+    /// When this version was loaded
+    pub loaded_at: u64,
+}
+
+/// Outcome of a contract hot reload
+#[derive(Debug, Clone)]
+pub struct ContractReload {
+    /// Contract name
+    pub name: String,
+    
+    /// Hash of the version that was live before, if any
+    pub old_hash: Option<String>,
+    
+    /// Hash of the version now live
+    pub new_hash: String,
+}
+
+/// Current version of a contract
+///
+/// Contracts not loaded yet are read from `.zk/contracts/<name>.yaml`.
+/// Callers should fetch the contract for every invocation rather than
+/// holding on to it, so they pick up reloads.
+pub fn get(name: &str) -> Result<Arc<ZkContract>> {
+    if let Some(loaded) = CONTRACT_REGISTRY.read().unwrap().get(name) {
+        return Ok(loaded.contract.clone());
+    }
+    
+    let contract = super::load_contract(&format!(".zk/contracts/{}.yaml", name))?;
+    if contract.name != name {
+        anyhow::bail!("Contract file {}.yaml declares contract {}", name, contract.name);
+    }
+    let hash = super::verify::contract_hash(&contract)?;
+    
+    // Another thread may have loaded or reloaded it meanwhile; keep theirs
+    let mut registry = CONTRACT_REGISTRY.write().unwrap();
+    let loaded = registry.entry(name.to_string()).or_insert_with(|| {
+        debug!("Loaded contract {} into the registry", name);
+        LoadedContract { contract: Arc::new(contract), hash, loaded_at: now() }
+    });
+    Ok(loaded.contract.clone())
+}
+
+/// Registry entry of a contract, if it is loaded
+pub fn entry(name: &str) -> Option<LoadedContract> {
+    CONTRACT_REGISTRY.read().unwrap().get(name).cloned()
+}
+
+/// Every loaded contract
+pub fn list() -> Vec<LoadedContract> {
+    CONTRACT_REGISTRY.read().unwrap().values().cloned().collect()
+}
+
+/// Make a contract version live, returning the version it replaced
+///
+/// Callers verify the contract first; the registry takes it as is.
+pub(super) fn swap(contract: ZkContract, hash: String) -> Option<LoadedContract> {
+    let name = contract.name.clone();
+    let loaded = LoadedContract { contract: Arc::new(contract), hash, loaded_at: now() };
+    CONTRACT_REGISTRY.write().unwrap().insert(name, loaded)
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::constants;
+    
+    /// Minimal contract declaring `name`, written as `.zk/contracts/<file>.yaml`
+    fn install(file: &str, name: &str, version: &str) {
+        let dir = constants::root_dir().join(".zk").join("contracts");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(format!("{}.yaml", file)), format!(r#"
+name: {}
+version: {}
+permissions:
+  filesystem: {{ read: [], write: [] }}
+  network: {{ outbound: false, inbound: false, allowed_hosts: [] }}
+  system: {{ exec: false, memory_limit: null, cpu_limit: null }}
+state:
+  counter: {{ var_type: u64, default: "0", mutable: true, zk_verified: true }}
+methods:
+  get:
+    name: get
+    params: {{}}
+    return_type: u64
+    implementation: |
+      return state.counter;
+    pure: true
+    zk_verified: false
+rules: []
+"#, name, version)).unwrap();
+    }
+    
+    #[test]
+    fn contracts_are_loaded_once_on_first_use() {
+        install("registry_loaded", "registry_loaded", "0.1.0");
+        assert!(entry("registry_loaded").is_none());
+        
+        let contract = get("registry_loaded").unwrap();
+        assert_eq!(contract.version, "0.1.0");
+        let loaded = entry("registry_loaded").unwrap();
+        assert_eq!(loaded.hash, super::super::verify::contract_hash(&contract).unwrap());
+        assert!(list().iter().any(|c| c.contract.name == "registry_loaded"));
+        
+        // Later edits to the file only take effect through a reload
+        install("registry_loaded", "registry_loaded", "0.2.0");
+        assert_eq!(get("registry_loaded").unwrap().version, "0.1.0");
+    }
+    
+    #[test]
+    fn swaps_replace_the_live_version() {
+        install("registry_swapped", "registry_swapped", "0.1.0");
+        let old = get("registry_swapped").unwrap();
+        
+        let mut next = (*old).clone();
+        next.version = "0.2.0".to_string();
+        let replaced = swap(next, "new-hash".to_string()).unwrap();
+        assert_eq!(replaced.contract.version, "0.1.0");
+        assert_eq!(get("registry_swapped").unwrap().version, "0.2.0");
+        assert_eq!(entry("registry_swapped").unwrap().hash, "new-hash");
+        
+        // Holders of the old version keep it
+        assert_eq!(old.version, "0.1.0");
+    }
+    
+    #[test]
+    fn files_must_declare_the_contract_they_are_named_after() {
+        install("registry_misnamed", "registry_other", "0.1.0");
+        assert!(get("registry_misnamed").is_err());
+        assert!(entry("registry_misnamed").is_none());
+        assert!(get("registry_missing").is_err());
+    }
+}
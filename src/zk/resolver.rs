@@ -0,0 +1,335 @@
+// SentientOS ZK Dependency Resolver
+// Resolves `dependencies:` of ZK-YAML contracts into the contracts to load with them
+
+use anyhow::{Result, Context};
+use tracing::{info, debug};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use super::contracts::ZkContract;
+use crate::core::constants;
+
+// Constants
+const CONTRACTS_DIR: &str = ".zk/contracts";
+const ANY_VERSION: &str = "*";
+
+/// A parsed `name@version` dependency
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DependencySpec {
+    /// Contract name
+    pub name: String,
+    
+    /// Exact version required, or `None` for any
+    pub version: Option<String>,
+}
+
+impl DependencySpec {
+    /// Parse `name@version`, `name@*` or a bare `name`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (name, version) = match spec.split_once('@') {
+            Some((name, version)) => (name.trim(), Some(version.trim())),
+            None => (spec.trim(), None),
+        };
+        
+        if name.is_empty() || name.contains('/') || name.contains('\\') || name.starts_with('.') {
+            anyhow::bail!("Invalid dependency name in {:?}", spec);
+        }
+        if version == Some("") {
+            anyhow::bail!("Empty version in dependency {:?}", spec);
+        }
+        
+        Ok(Self {
+            name: name.to_string(),
+            version: version.filter(|v| *v != ANY_VERSION).map(String::from),
+        })
+    }
+}
+
+/// A node in a contract's resolved dependency tree
+#[derive(Debug, Clone)]
+pub struct DependencyNode {
+    /// Contract name
+    pub name: String,
+    
+    /// Dependency as written by the dependent
+    pub requirement: String,
+    
+    /// Version the dependency resolved to
+    pub version: String,
+    
+    /// Versions available when the requirement did not pin one and several
+    /// matched, so the newest was picked
+    pub ambiguous: Vec<String>,
+    
+    /// Dependencies of this contract
+    pub children: Vec<DependencyNode>,
+}
+
+/// Every contract a contract depends on
+#[derive(Debug, Clone)]
+pub struct DependencyGraph {
+    /// Contract the graph was resolved for
+    pub root: String,
+    
+    /// Dependencies in load order, each after the contracts it depends on
+    pub dependencies: Vec<ZkContract>,
+    
+    /// Direct dependencies of the root, with their own below them
+    pub tree: Vec<DependencyNode>,
+}
+
+/// Resolve a contract's dependencies from `.zk/contracts`
+///
+/// Dependencies are read from `<name>.yaml` or `<name>@<version>.yaml`.
+/// A contract may only be loaded at one version, so two dependents pinning
+/// different versions of it is an error, as is a dependency cycle.
+pub fn resolve_dependencies(contract: &ZkContract) -> Result<DependencyGraph> {
+    let mut resolver = Resolver {
+        stack: vec![contract.name.clone()],
+        resolved: HashMap::new(),
+        order: Vec::new(),
+    };
+    let tree = resolver.visit(contract)?;
+    
+    if !resolver.order.is_empty() {
+        info!("Resolved {} dependencies for contract: {}", resolver.order.len(), contract.name);
+    }
+    Ok(DependencyGraph {
+        root: contract.name.clone(),
+        dependencies: resolver.order,
+        tree,
+    })
+}
+
+/// Depth-first walk over the dependency graph
+struct Resolver {
+    /// Contracts being visited, root first
+    stack: Vec<String>,
+    
+    /// Contracts already visited, with the subtree built for them
+    resolved: HashMap<String, (String, Vec<DependencyNode>)>,
+    
+    /// Visited contracts, dependencies first
+    order: Vec<ZkContract>,
+}
+
+impl Resolver {
+    /// Resolve a contract's dependencies, returning their tree nodes
+    fn visit(&mut self, contract: &ZkContract) -> Result<Vec<DependencyNode>> {
+        let mut nodes = Vec::new();
+        
+        for requirement in &contract.dependencies {
+            let spec = DependencySpec::parse(requirement)
+                .with_context(|| format!("Invalid dependency of contract {}", contract.name))?;
+            if self.stack.contains(&spec.name) {
+                anyhow::bail!("Dependency cycle detected: {} -> {}", self.stack.join(" -> "), spec.name);
+            }
+            
+            // Already resolved through another dependent; the version must agree
+            if let Some((version, children)) = self.resolved.get(&spec.name) {
+                if spec.version.as_ref().map_or(false, |pinned| pinned != version) {
+                    anyhow::bail!("Contract {} requires {} but {} is already resolved at {}",
+                                  contract.name, requirement, spec.name, version);
+                }
+                nodes.push(DependencyNode {
+                    name: spec.name.clone(),
+                    requirement: requirement.clone(),
+                    version: version.clone(),
+                    ambiguous: Vec::new(),
+                    children: children.clone(),
+                });
+                continue;
+            }
+            
+            let (dependency, ambiguous) = select(&spec)
+                .with_context(|| format!("Failed to resolve dependency {} of contract {}", requirement, contract.name))?;
+            debug!("Resolved dependency {} of {} to version {}", requirement, contract.name, dependency.version);
+            
+            self.stack.push(spec.name.clone());
+            let children = self.visit(&dependency)?;
+            self.stack.pop();
+            
+            self.resolved.insert(spec.name.clone(), (dependency.version.clone(), children.clone()));
+            nodes.push(DependencyNode {
+                name: spec.name,
+                requirement: requirement.clone(),
+                version: dependency.version.clone(),
+                ambiguous,
+                children,
+            });
+            self.order.push(dependency);
+        }
+        
+        Ok(nodes)
+    }
+}
+
+/// Pick the contract a dependency refers to
+///
+/// When the dependency pins no version and several exist, the newest is
+/// picked and every available version is returned alongside it.
+fn select(spec: &DependencySpec) -> Result<(ZkContract, Vec<String>)> {
+    let mut candidates = available_versions(&spec.name)?;
+    if candidates.is_empty() {
+        anyhow::bail!("No contract named {} in {}", spec.name, CONTRACTS_DIR);
+    }
+    
+    if let Some(version) = &spec.version {
+        let index = candidates.iter()
+            .position(|c| &c.version == version)
+            .ok_or_else(|| anyhow::anyhow!("Version {} of {} not found; available: {}", version, spec.name,
+                                           candidates.iter().map(|c| c.version.as_str()).collect::<Vec<_>>().join(", ")))?;
+        return Ok((candidates.swap_remove(index), Vec::new()));
+    }
+    
+    candidates.sort_by_key(|c| version_key(&c.version));
+    let ambiguous = if candidates.len() > 1 {
+        candidates.iter().map(|c| c.version.clone()).collect()
+    } else {
+        Vec::new()
+    };
+    Ok((candidates.pop().unwrap(), ambiguous))
+}
+
+/// Every version of a contract in `.zk/contracts`
+fn available_versions(name: &str) -> Result<Vec<ZkContract>> {
+    let dir = constants::root_dir().join(CONTRACTS_DIR);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let versioned_prefix = format!("{}@", name);
+    let mut paths: Vec<PathBuf> = fs::read_dir(&dir)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+            path.extension().map_or(false, |ext| ext == "yaml") && (stem == name || stem.starts_with(&versioned_prefix))
+        })
+        .collect();
+    paths.sort();
+    
+    let mut contracts: Vec<ZkContract> = Vec::new();
+    for path in paths {
+        let content = fs::read_to_string(&path)?;
+        let contract = super::parser::parse_zk_yaml(&content)
+            .with_context(|| format!("Failed to parse dependency {:?}", path))?;
+        if contract.name != name {
+            anyhow::bail!("{:?} declares contract {}, expected {}", path, contract.name, name);
+        }
+        if contracts.iter().any(|c| c.version == contract.version) {
+            anyhow::bail!("Version {} of {} is defined more than once in {}", contract.version, name, CONTRACTS_DIR);
+        }
+        contracts.push(contract);
+    }
+    Ok(contracts)
+}
+
+/// Sort key of a version: numeric segments compare as numbers
+fn version_key(version: &str) -> Vec<(u64, String)> {
+    version.split(['.', '-'])
+        .map(|segment| (segment.parse().unwrap_or(0), segment.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    /// Contract with dependencies, written as `.zk/contracts/<file>.yaml`
+    fn install(file: &str, name: &str, version: &str, dependencies: &[&str]) {
+        let dir = constants::root_dir().join(CONTRACTS_DIR);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(format!("{}.yaml", file)), format!(r#"
+name: {}
+version: {}
+dependencies: {:?}
+permissions:
+  filesystem: {{ read: [], write: [] }}
+  network: {{ outbound: false, inbound: false, allowed_hosts: [] }}
+  system: {{ exec: false, memory_limit: null, cpu_limit: null }}
+state: {{}}
+methods:
+  get:
+    name: get
+    params: {{}}
+    return_type: u64
+    implementation: |
+      return 1;
+    pure: true
+    zk_verified: false
+rules: []
+"#, name, version, dependencies)).unwrap();
+    }
+    
+    fn root(dependencies: &[&str]) -> ZkContract {
+        let mut contract = super::super::contracts::new_contract("resolver_root", "1.0.0");
+        contract.dependencies = dependencies.iter().map(|d| d.to_string()).collect();
+        contract
+    }
+    
+    #[test]
+    fn dependencies_load_after_their_own_and_resolve_once() {
+        install("resolver_leaf", "resolver_leaf", "1.0.0", &[]);
+        install("resolver_pinned@1.0.0", "resolver_pinned", "1.0.0", &["resolver_leaf"]);
+        install("resolver_pinned@2.0.0", "resolver_pinned", "2.0.0", &[]);
+        install("resolver_newest@1.2.0", "resolver_newest", "1.2.0", &[]);
+        install("resolver_newest@1.10.0", "resolver_newest", "1.10.0", &["resolver_leaf@*"]);
+        
+        let graph = resolve_dependencies(&root(&["resolver_pinned@1.0.0", "resolver_newest"])).unwrap();
+        let order: Vec<(&str, &str)> = graph.dependencies.iter().map(|c| (c.name.as_str(), c.version.as_str())).collect();
+        assert_eq!(order, [("resolver_leaf", "1.0.0"), ("resolver_pinned", "1.0.0"), ("resolver_newest", "1.10.0")]);
+        
+        // The newest unpinned version wins, and the pick is flagged
+        let (pinned, newest) = (&graph.tree[0], &graph.tree[1]);
+        assert!(pinned.ambiguous.is_empty());
+        assert_eq!(newest.ambiguous, ["1.2.0", "1.10.0"]);
+        
+        // A contract reached twice is shared, with the same subtree
+        assert_eq!(pinned.children[0].name, "resolver_leaf");
+        assert_eq!(newest.children[0].requirement, "resolver_leaf@*");
+        assert_eq!(newest.children[0].version, "1.0.0");
+    }
+    
+    #[test]
+    fn conflicting_pins_and_cycles_are_refused() {
+        install("resolver_base", "resolver_base", "1.0.0", &[]);
+        install("resolver_strict", "resolver_strict", "1.0.0", &["resolver_base@2.0.0"]);
+        let error = resolve_dependencies(&root(&["resolver_base", "resolver_strict"])).unwrap_err();
+        assert_eq!(error.to_string(), "Contract resolver_strict requires resolver_base@2.0.0 but resolver_base is already resolved at 1.0.0");
+        
+        install("resolver_ping", "resolver_ping", "1.0.0", &["resolver_pong"]);
+        install("resolver_pong", "resolver_pong", "1.0.0", &["resolver_ping"]);
+        let error = resolve_dependencies(&root(&["resolver_ping"])).unwrap_err();
+        assert_eq!(error.to_string(), "Dependency cycle detected: resolver_root -> resolver_ping -> resolver_pong -> resolver_ping");
+    }
+    
+    #[test]
+    fn missing_and_misfiled_dependencies_are_reported() {
+        let error = resolve_dependencies(&root(&["resolver_absent"])).unwrap_err();
+        assert!(format!("{:#}", error).ends_with("No contract named resolver_absent in .zk/contracts"), "{:#}", error);
+        
+        install("resolver_only", "resolver_only", "1.0.0", &[]);
+        let error = resolve_dependencies(&root(&["resolver_only@9.9.9"])).unwrap_err();
+        assert!(format!("{:#}", error).ends_with("Version 9.9.9 of resolver_only not found; available: 1.0.0"), "{:#}", error);
+        
+        install("resolver_misnamed", "resolver_other", "1.0.0", &[]);
+        let error = resolve_dependencies(&root(&["resolver_misnamed"])).unwrap_err();
+        assert!(format!("{:#}", error).contains("declares contract resolver_other, expected resolver_misnamed"), "{:#}", error);
+    }
+    
+    #[test]
+    fn specs_parse_names_and_versions() {
+        let spec = |name: &str, version: Option<&str>| DependencySpec { name: name.to_string(), version: version.map(String::from) };
+        assert_eq!(DependencySpec::parse("token@1.2.0").unwrap(), spec("token", Some("1.2.0")));
+        assert_eq!(DependencySpec::parse(" token @ * ").unwrap(), spec("token", None));
+        assert_eq!(DependencySpec::parse("token").unwrap(), spec("token", None));
+        for invalid in ["", "@1.0.0", "token@", "../token", "a/b@1", ".hidden"] {
+            assert!(DependencySpec::parse(invalid).is_err(), "{:?}", invalid);
+        }
+        
+        assert!(version_key("1.10.0") > version_key("1.9.0"));
+        assert!(version_key("2.0.0") > version_key("1.99.99"));
+    }
+}
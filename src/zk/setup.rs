@@ -0,0 +1,90 @@
+// SentientOS ZK Setup
+// Generates and stores the Groth16 parameters of each contract
+
+use anyhow::{Result, Context};
+use ark_bn254::Bn254;
+use ark_groth16::{Groth16, ProvingKey, VerifyingKey};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_snark::CircuitSpecificSetupSNARK;
+use ark_std::rand::{rngs::StdRng, SeedableRng};
+use tracing::info;
+use std::path::PathBuf;
+use std::fs;
+
+use super::circuits::{Synthesizer, ZkCircuit};
+use crate::core::{constants, lock};
+
+// Constants
+const KEYS_DIR: &str = ".zk/keys";
+const PARAMS_EXTENSION: &str = "params";
+const SETUP_LOCK: &str = "zk-setup";
+
+/// Proving parameters of a circuit, generating them on first use
+///
+/// Parameters are stored in `.zk/keys/<name>.params` and never regenerated,
+/// since proofs made with one setup do not verify against another.
+pub fn generate_parameters(circuit: &dyn ZkCircuit) -> Result<ProvingKey<Bn254>> {
+    if let Some(params) = load_parameters(circuit.name())? {
+        return Ok(params);
+    }
+    
+    let _lock = lock::lock(SETUP_LOCK, &format!("generate parameters of {}", circuit.name()), lock::DEFAULT_TIMEOUT)?;
+    
+    // Another process may have finished the setup while we waited
+    if let Some(params) = load_parameters(circuit.name())? {
+        return Ok(params);
+    }
+    
+    info!("Generating ZK parameters for {}", circuit.name());
+    let mut rng = StdRng::from_entropy();
+    let (params, _) = Groth16::<Bn254>::setup(Synthesizer::setup(circuit), &mut rng)
+        .map_err(|e| anyhow::anyhow!("Parameter generation for {} failed: {}", circuit.name(), e))?;
+    
+    let mut bytes = Vec::new();
+    params.serialize_compressed(&mut bytes)
+        .map_err(|e| anyhow::anyhow!("Failed to serialize parameters of {}: {}", circuit.name(), e))?;
+    
+    let path = params_path(circuit.name());
+    fs::create_dir_all(path.parent().unwrap())?;
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, &bytes)?;
+    fs::rename(&tmp, &path).with_context(|| format!("Failed to store parameters at {:?}", path))?;
+    
+    crate::logs::ship::ship_audit("zk.setup", &format!(
+        "Generated parameters of {} ({} bytes)", circuit.name(), bytes.len()));
+    Ok(params)
+}
+
+/// Stored proving parameters of a circuit, if its setup has run
+pub fn load_parameters(name: &str) -> Result<Option<ProvingKey<Bn254>>> {
+    let path = params_path(name);
+    if !path.exists() {
+        return Ok(None);
+    }
+    
+    let bytes = fs::read(&path).with_context(|| format!("Failed to read {:?}", path))?;
+    let params = ProvingKey::<Bn254>::deserialize_compressed(bytes.as_slice())
+        .map_err(|e| anyhow::anyhow!("Invalid parameters in {:?}: {}", path, e))?;
+    Ok(Some(params))
+}
+
+/// Verifying key of a circuit, if its setup has run
+pub fn verifying_key(name: &str) -> Result<Option<VerifyingKey<Bn254>>> {
+    Ok(load_parameters(name)?.map(|params| params.vk))
+}
+
+/// Hash of a circuit's stored parameters, if its setup has run
+pub fn parameters_hash(name: &str) -> Result<Option<String>> {
+    let path = params_path(name);
+    if !path.exists() {
+        return Ok(None);
+    }
+    
+    let bytes = fs::read(&path).with_context(|| format!("Failed to read {:?}", path))?;
+    Ok(Some(blake3::hash(&bytes).to_hex().to_string()))
+}
+
+/// Path of a circuit's parameters
+fn params_path(name: &str) -> PathBuf {
+    constants::root_dir().join(KEYS_DIR).join(format!("{}.{}", name, PARAMS_EXTENSION))
+}
@@ -0,0 +1,190 @@
+// SentientOS ZK Simulation
+// What-if runs of contract methods that commit nothing
+
+use anyhow::{Result, Context};
+use tracing::debug;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+
+use super::contracts::ZkContract;
+use super::executor::MethodRun;
+use super::metering::ExecutionCost;
+use super::state;
+
+/// Outcome of a rule evaluated during a call
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleOutcome {
+    /// Rule name
+    pub rule: String,
+    
+    /// Whether the rule held
+    pub passed: bool,
+}
+
+/// A state variable a call would change
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StateChange {
+    /// Variable name
+    pub key: String,
+    
+    /// Value before the call, `None` if unset
+    pub before: Option<serde_json::Value>,
+    
+    /// Value after the call, `None` if removed
+    pub after: Option<serde_json::Value>,
+}
+
+/// Transcript of a simulated call
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Simulation {
+    /// Contract name
+    pub contract: String,
+    
+    /// Simulated method
+    pub method: String,
+    
+    /// Arguments of the call
+    pub args: Vec<serde_json::Value>,
+    
+    /// Hash of the persisted state the simulation started from
+    pub pre_state_hash: String,
+    
+    /// Value the call would return
+    pub value: serde_json::Value,
+    
+    /// What the call would cost
+    pub cost: ExecutionCost,
+    
+    /// State variables the call would change, by name
+    pub changes: Vec<StateChange>,
+    
+    /// Rules evaluated and their outcomes, in order
+    pub rules: Vec<RuleOutcome>,
+    
+    /// When the simulation ran
+    pub simulated_at: u64,
+}
+
+impl Simulation {
+    /// Build the transcript of a simulated run
+    pub fn from_run(contract: &ZkContract, method: &str, args: &[serde_json::Value], run: &MethodRun) -> Result<Self> {
+        Ok(Self {
+            contract: contract.name.clone(),
+            method: method.to_string(),
+            args: args.to_vec(),
+            pre_state_hash: state::state_hash(&run.pre_state)?,
+            value: run.value.clone(),
+            cost: run.cost,
+            changes: diff(&run.pre_state, &run.post_state),
+            rules: run.rules.clone(),
+            simulated_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        })
+    }
+    
+    /// Whether any evaluated rule failed
+    pub fn rules_failed(&self) -> bool {
+        self.rules.iter().any(|r| !r.passed)
+    }
+}
+
+/// Variables that differ between two states, by name
+pub fn diff(before: &state::ContractState, after: &state::ContractState) -> Vec<StateChange> {
+    let mut keys: Vec<&String> = before.keys().chain(after.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    
+    keys.into_iter()
+        .filter(|key| before.get(*key) != after.get(*key))
+        .map(|key| StateChange {
+            key: key.clone(),
+            before: before.get(key).cloned(),
+            after: after.get(key).cloned(),
+        })
+        .collect()
+}
+
+/// Parse a call given as `method` or `method:<JSON array of arguments>`
+pub fn parse_call(spec: &str) -> Result<(String, Vec<serde_json::Value>)> {
+    let (method, args) = match spec.split_once(':') {
+        Some((method, args)) => (method, serde_json::from_str(args)
+            .with_context(|| format!("Arguments of {} must be a JSON array", method))?),
+        None => (spec, Vec::new()),
+    };
+    if method.is_empty() {
+        anyhow::bail!("Missing method name in {:?}", spec);
+    }
+    Ok((method.to_string(), args))
+}
+
+/// Count a simulation and log it, apart from real executions
+///
+/// Real invocations are counted under `zk.<contract>.<method>.invocations`
+/// and audited as `zk.execute`; simulations never touch either.
+pub fn record(simulation: &Simulation) {
+    crate::logs::metrics::increment(&format!("zk.{}.{}.simulations", simulation.contract, simulation.method));
+    crate::logs::ship::ship_audit("zk.simulate", &format!(
+        "Simulated {}.{} against state {}: {} change(s), {} steps, nothing committed",
+        simulation.contract, simulation.method, simulation.pre_state_hash,
+        simulation.changes.len(), simulation.cost.steps));
+    debug!("Recorded simulation of {}.{}", simulation.contract, simulation.method);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    
+    fn state(pairs: &[(&str, serde_json::Value)]) -> state::ContractState {
+        pairs.iter().map(|(k, v)| (k.to_string(), v.clone())).collect()
+    }
+    
+    #[test]
+    fn diffs_list_changed_added_and_removed_variables() {
+        let before = state(&[("counter", json!(1)), ("owner", json!("a")), ("gone", json!(true))]);
+        let after = state(&[("counter", json!(2)), ("owner", json!("a")), ("fresh", json!([]))]);
+        
+        let changes = diff(&before, &after);
+        assert_eq!(changes, [
+            StateChange { key: "counter".to_string(), before: Some(json!(1)), after: Some(json!(2)) },
+            StateChange { key: "fresh".to_string(), before: None, after: Some(json!([])) },
+            StateChange { key: "gone".to_string(), before: Some(json!(true)), after: None },
+        ]);
+        assert!(diff(&before, &before).is_empty());
+    }
+    
+    #[test]
+    fn calls_parse_with_and_without_arguments() {
+        assert_eq!(parse_call("increment").unwrap(), ("increment".to_string(), Vec::new()));
+        assert_eq!(parse_call("transfer:[\"bob\", 5]").unwrap(), ("transfer".to_string(), vec![json!("bob"), json!(5)]));
+        assert!(parse_call("transfer:{\"to\": \"bob\"}").is_err());
+        assert!(parse_call(":[1]").is_err());
+        assert!(parse_call("").is_err());
+    }
+    
+    #[test]
+    fn transcripts_describe_the_run() {
+        let contract = super::super::contracts::new_contract("simulate_transcript", "0.1.0");
+        let pre_state = state(&[("counter", json!(1))]);
+        let run = MethodRun {
+            value: json!(2),
+            cost: ExecutionCost { steps: 7, state_bytes: 1 },
+            pre_state: pre_state.clone(),
+            post_state: state(&[("counter", json!(2))]),
+            rules: vec![
+                RuleOutcome { rule: "positive".to_string(), passed: true },
+                RuleOutcome { rule: "capped".to_string(), passed: false },
+            ],
+        };
+        
+        let simulation = Simulation::from_run(&contract, "increment", &[json!(1)], &run).unwrap();
+        assert_eq!(simulation.contract, "simulate_transcript");
+        assert_eq!(simulation.pre_state_hash, state::state_hash(&pre_state).unwrap());
+        assert_eq!(simulation.value, json!(2));
+        assert_eq!(simulation.cost.steps, 7);
+        assert_eq!(simulation.changes.len(), 1);
+        assert!(simulation.rules_failed());
+        
+        let passing = MethodRun { rules: run.rules[..1].to_vec(), ..run };
+        assert!(!Simulation::from_run(&contract, "increment", &[], &passing).unwrap().rules_failed());
+    }
+}
@@ -0,0 +1,649 @@
+// SentientOS ZK Contract State
+// Persisted contract state, optionally encrypted at rest with per-contract keys
+
+use anyhow::{Result, Context};
+use tracing::{info, debug, warn};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::sync::Mutex;
+use serde::{Serialize, Deserialize};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce, KeyInit};
+use chacha20poly1305::aead::Aead;
+
+use crate::core::constants;
+use super::contracts::ZkContract;
+
+// Constants
+const STATE_FILE: &str = "state.json";
+const META_FILE: &str = "state.meta";
+const MASTER_KEY_FILE: &str = "state-master.key";
+const ENCRYPTED_FORMAT: &str = "sentient-encrypted-state-v1";
+const KEY_DERIVATION_CONTEXT: &str = "SentientOS contract state encryption v1";
+const PASSPHRASE_ENV: &str = "SENTIENT_STATE_PASSPHRASE";
+
+// Unlocked master key, held for the life of the process
+static MASTER_KEY: Mutex<Option<[u8; 32]>> = Mutex::new(None);
+
+/// Contract state by variable name
+pub type ContractState = BTreeMap<String, serde_json::Value>;
+
+/// Master key file in `.auth/keys`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct MasterKeyFile {
+    /// Whether the key is wrapped with a passphrase
+    protected: bool,
+    
+    /// Key (hex), when not protected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    key: Option<String>,
+    
+    /// Passphrase salt (hex), when protected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    salt: Option<String>,
+    
+    /// Wrapping nonce (hex), when protected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    nonce: Option<String>,
+    
+    /// Wrapped key (hex), when protected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    wrapped: Option<String>,
+}
+
+/// What was recorded about a state when it was saved, in `state.meta`
+///
+/// One file, so the hash and version are always replaced together.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StateMeta {
+    /// Hash of the plaintext state
+    hash: String,
+    
+    /// Contract version that wrote the state
+    version: String,
+}
+
+/// Encrypted state file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct EncryptedState {
+    /// Format marker
+    format: String,
+    
+    /// Nonce (hex)
+    nonce: String,
+    
+    /// Ciphertext of the canonical plaintext state (hex)
+    ciphertext: String,
+}
+
+/// How a contract's state is stored on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageStatus {
+    /// No state has been persisted yet
+    Absent,
+    
+    /// Plaintext JSON
+    Plaintext,
+    
+    /// Encrypted with the contract's key
+    Encrypted,
+}
+
+/// Load a contract's state, or its declared defaults if none is persisted
+///
+/// A state that does not match the hash recorded when it was saved is
+/// refused rather than executed against. A save cut short between the state
+/// and its record leaves the previous state in the `.bak` copy, matching the
+/// record; that state is used instead.
+pub fn load_state(contract: &ZkContract) -> Result<ContractState> {
+    let path = state_path(&contract.name);
+    if !path.exists() {
+        return Ok(default_state(contract));
+    }
+    
+    let state = read_state_file(&contract.name, &path)?;
+    let recorded = match recorded_hash(&contract.name)? {
+        Some(recorded) if state_hash(&state)? != recorded => recorded,
+        _ => return Ok(state),
+    };
+    
+    let backup = crate::core::fs::backup_path(&path);
+    if backup.is_file() {
+        let previous = read_state_file(&contract.name, &backup)?;
+        if state_hash(&previous)? == recorded {
+            warn!("State of contract {} was not saved completely; using the previous state from {:?}",
+                  contract.name, backup);
+            return Ok(previous);
+        }
+    }
+    anyhow::bail!(
+        "State of contract {} does not match its recorded hash {}; it was modified outside \
+         the executor. Restore {:?} from its .bak copy, or reset the contract's state",
+        contract.name, recorded, path)
+}
+
+/// Persist a contract's state, encrypting it if the contract asks for it
+///
+/// The state is replaced first and its record of hash and version second,
+/// each atomically; `load_state` recovers from a crash between the two.
+pub fn save_state(contract: &ZkContract, state: &ContractState) -> Result<()> {
+    let path = state_path(&contract.name);
+    let data = if contract.state_encryption {
+        serde_json::to_vec_pretty(&encrypt(&contract.name, state)?)?
+    } else {
+        serde_json::to_vec_pretty(state)?
+    };
+    crate::core::fs::write_atomic(&path, &data)
+        .with_context(|| format!("Failed to write contract state {:?}", path))?;
+    let meta = StateMeta { hash: state_hash(state)?, version: contract.version.clone() };
+    crate::core::fs::write_atomic(&path.with_file_name(META_FILE), &serde_json::to_vec_pretty(&meta)?)?;
+    
+    debug!("Saved state of contract {} ({})", contract.name,
+           if contract.state_encryption { "encrypted" } else { "plaintext" });
+    Ok(())
+}
+
+/// Contract version that last wrote a contract's state, if recorded
+pub fn state_version(contract_name: &str) -> Result<Option<String>> {
+    Ok(read_meta(contract_name)?.map(|meta| meta.version))
+}
+
+/// Hash recorded when a contract's state was last saved, if any
+///
+/// State saved before hashes were recorded has none.
+pub fn recorded_hash(contract_name: &str) -> Result<Option<String>> {
+    Ok(read_meta(contract_name)?.map(|meta| meta.hash))
+}
+
+/// Record of a contract's saved state, if any
+fn read_meta(contract_name: &str) -> Result<Option<StateMeta>> {
+    let path = state_path(contract_name).with_file_name(META_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let meta = serde_json::from_slice(&fs::read(&path)?)
+        .with_context(|| format!("Invalid contract state record {:?}", path))?;
+    Ok(Some(meta))
+}
+
+/// Read a state file, decrypting it if needed
+fn read_state_file(contract_name: &str, path: &Path) -> Result<ContractState> {
+    let data = fs::read(path).with_context(|| format!("Failed to read contract state {:?}", path))?;
+    match serde_json::from_slice::<EncryptedState>(&data) {
+        Ok(encrypted) if encrypted.format == ENCRYPTED_FORMAT => decrypt(contract_name, &encrypted),
+        _ => serde_json::from_slice(&data).with_context(|| format!("Invalid contract state {:?}", path)),
+    }
+}
+
+/// Delete a contract's persisted state so its next call starts from the defaults
+///
+/// Returns whether there was any state to delete.
+pub fn reset_state(contract_name: &str) -> Result<bool> {
+    if contract_name.is_empty() || contract_name.contains('/') || contract_name.contains('\\') || contract_name.starts_with('.') {
+        anyhow::bail!("Invalid contract name: {:?}", contract_name);
+    }
+    
+    let dir = state_dir(contract_name);
+    if !dir.exists() {
+        return Ok(false);
+    }
+    
+    fs::remove_dir_all(&dir).with_context(|| format!("Failed to remove contract state {:?}", dir))?;
+    info!("Reset state of contract {}", contract_name);
+    crate::logs::ship::ship_audit("zk.state.reset", &format!("Reset state of {}", contract_name));
+    Ok(true)
+}
+
+/// Migrate a contract's persisted state to the contract's version
+///
+/// Returns the version the state was migrated from, or `None` if there was
+/// nothing to migrate. State written before versions were recorded is
+/// assumed to be current.
+pub fn migrate(contract: &ZkContract) -> Result<Option<String>> {
+    if storage_status(&contract.name)? == StorageStatus::Absent {
+        return Ok(None);
+    }
+    let from_version = match state_version(&contract.name)? {
+        Some(version) if version != contract.version => version,
+        _ => return Ok(None),
+    };
+    
+    info!("Migrating state of contract {} from {} to {}", contract.name, from_version, contract.version);
+    let old_state = serde_json::to_value(load_state(contract)?)?;
+    let new_state = super::contracts::migrate_state(contract, &from_version, old_state)?;
+    save_state(contract, &serde_json::from_value(new_state)?)?;
+    
+    crate::logs::ship::ship_audit("zk.migrate", &format!(
+        "Migrated state of {} from {} to {}", contract.name, from_version, contract.version));
+    Ok(Some(from_version))
+}
+
+/// Hash of the canonical plaintext form of a state
+///
+/// Used as the proof post-state hash, so it does not depend on whether
+/// the state is encrypted on disk.
+pub fn state_hash(state: &ContractState) -> Result<String> {
+    Ok(blake3::hash(&serde_json::to_vec(state)?).to_hex().to_string())
+}
+
+/// How a contract's state is stored on disk
+pub fn storage_status(contract_name: &str) -> Result<StorageStatus> {
+    let path = state_path(contract_name);
+    if !path.exists() {
+        return Ok(StorageStatus::Absent);
+    }
+    
+    let data = fs::read(&path)?;
+    Ok(match serde_json::from_slice::<EncryptedState>(&data) {
+        Ok(encrypted) if encrypted.format == ENCRYPTED_FORMAT => StorageStatus::Encrypted,
+        _ => StorageStatus::Plaintext,
+    })
+}
+
+/// Names of contracts with encrypted state on disk
+pub fn encrypted_contracts() -> Result<Vec<String>> {
+    let runtime_dir = constants::root_dir().join(".zk").join("runtime");
+    if !runtime_dir.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let mut names = Vec::new();
+    for entry in fs::read_dir(&runtime_dir)?.filter_map(Result::ok) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if entry.path().is_dir() && storage_status(&name)? == StorageStatus::Encrypted {
+            names.push(name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Check that a contract's persisted state can be read with the current key
+pub fn check_readable(contract_name: &str) -> Result<()> {
+    let path = state_path(contract_name);
+    if !path.exists() {
+        return Ok(());
+    }
+    
+    let data = fs::read(&path)?;
+    match serde_json::from_slice::<EncryptedState>(&data) {
+        Ok(encrypted) if encrypted.format == ENCRYPTED_FORMAT => decrypt(contract_name, &encrypted).map(drop),
+        _ => serde_json::from_slice::<ContractState>(&data)
+            .map(drop)
+            .with_context(|| format!("Invalid contract state {:?}", path)),
+    }
+}
+
+/// Whether a master key exists
+pub fn master_key_exists() -> bool {
+    master_key_path().exists()
+}
+
+/// Whether the master key is passphrase-protected
+pub fn master_key_protected() -> Result<bool> {
+    Ok(load_master_key_file()?.map_or(false, |f| f.protected))
+}
+
+/// Unlock the master key for this process
+///
+/// A protected key is unlocked with `passphrase`, falling back to
+/// $SENTIENT_STATE_PASSPHRASE and then a terminal prompt.
+pub fn unlock(passphrase: Option<&str>) -> Result<()> {
+    if MASTER_KEY.lock().unwrap().is_some() {
+        return Ok(());
+    }
+    
+    let file = match load_master_key_file()? {
+        Some(file) => file,
+        None => return Ok(()),
+    };
+    
+    let key = if file.protected {
+        let passphrase = match passphrase.map(str::to_string).or_else(|| std::env::var(PASSPHRASE_ENV).ok()) {
+            Some(passphrase) => passphrase,
+            None => rpassword::prompt_password("Contract state passphrase: ")
+                .context("Failed to read contract state passphrase")?,
+        };
+        unwrap_master_key(&file, &passphrase)?
+    } else {
+        to_key(&from_hex(file.key.as_deref().unwrap_or_default())?)?
+    };
+    
+    *MASTER_KEY.lock().unwrap() = Some(key);
+    info!("Contract state master key unlocked");
+    Ok(())
+}
+
+/// Protect the master key with a passphrase, creating the key if needed
+pub fn protect_master_key(passphrase: &str) -> Result<()> {
+    if passphrase.len() < 8 {
+        anyhow::bail!("Passphrase must be at least 8 characters");
+    }
+    
+    let key = master_key()?;
+    let salt: [u8; 16] = rand::random();
+    let nonce: [u8; 12] = rand::random();
+    let wrapping_key = passphrase_key(passphrase, &salt)?;
+    let wrapped = ChaCha20Poly1305::new(Key::from_slice(&wrapping_key))
+        .encrypt(Nonce::from_slice(&nonce), key.as_slice())
+        .map_err(|_| anyhow::anyhow!("Failed to wrap master key"))?;
+    
+    save_master_key_file(&MasterKeyFile {
+        protected: true,
+        key: None,
+        salt: Some(to_hex(&salt)),
+        nonce: Some(to_hex(&nonce)),
+        wrapped: Some(to_hex(&wrapped)),
+    })?;
+    info!("Contract state master key is now passphrase-protected");
+    Ok(())
+}
+
+/// Default state from the contract's declared state variables
+pub(super) fn default_state(contract: &ZkContract) -> ContractState {
+    contract.state.iter()
+        .map(|(name, variable)| (name.clone(), variable.default_value()))
+        .collect()
+}
+
+/// Encrypt a state with the contract's key
+fn encrypt(contract_name: &str, state: &ContractState) -> Result<EncryptedState> {
+    let key = contract_key(contract_name)?;
+    let nonce: [u8; 12] = rand::random();
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&key))
+        .encrypt(Nonce::from_slice(&nonce), serde_json::to_vec(state)?.as_slice())
+        .map_err(|_| anyhow::anyhow!("Failed to encrypt state of contract {}", contract_name))?;
+    
+    Ok(EncryptedState {
+        format: ENCRYPTED_FORMAT.to_string(),
+        nonce: to_hex(&nonce),
+        ciphertext: to_hex(&ciphertext),
+    })
+}
+
+/// Decrypt a state with the contract's key
+fn decrypt(contract_name: &str, encrypted: &EncryptedState) -> Result<ContractState> {
+    if !master_key_exists() {
+        anyhow::bail!(
+            "State of contract {} is encrypted but the master key {:?} is missing. \
+             Without it the state cannot be recovered: restore the key from a backup, \
+             or delete {:?} to reset the contract to its default state",
+            contract_name, master_key_path(), state_path(contract_name));
+    }
+    
+    let key = contract_key(contract_name)?;
+    let plaintext = ChaCha20Poly1305::new(Key::from_slice(&key))
+        .decrypt(Nonce::from_slice(&from_hex(&encrypted.nonce)?), from_hex(&encrypted.ciphertext)?.as_slice())
+        .map_err(|_| anyhow::anyhow!(
+            "State of contract {} cannot be decrypted with the current master key; it was encrypted \
+             with a different key or has been modified. Without the original key the state cannot \
+             be recovered", contract_name))?;
+    
+    serde_json::from_slice(&plaintext).context("Decrypted contract state is invalid")
+}
+
+/// Per-contract key derived from the master key
+fn contract_key(contract_name: &str) -> Result<[u8; 32]> {
+    let master = master_key()?;
+    let mut material = master.to_vec();
+    material.extend_from_slice(contract_name.as_bytes());
+    Ok(blake3::derive_key(KEY_DERIVATION_CONTEXT, &material))
+}
+
+/// The unlocked master key, created on first use
+fn master_key() -> Result<[u8; 32]> {
+    if let Some(key) = *MASTER_KEY.lock().unwrap() {
+        return Ok(key);
+    }
+    
+    if !master_key_exists() {
+        let key: [u8; 32] = rand::random();
+        save_master_key_file(&MasterKeyFile {
+            protected: false,
+            key: Some(to_hex(&key)),
+            salt: None,
+            nonce: None,
+            wrapped: None,
+        })?;
+        info!("Created contract state master key: {:?}", master_key_path());
+    }
+    
+    unlock(None)?;
+    MASTER_KEY.lock().unwrap().ok_or_else(|| anyhow::anyhow!("Contract state master key is locked"))
+}
+
+/// Unwrap a protected master key
+fn unwrap_master_key(file: &MasterKeyFile, passphrase: &str) -> Result<[u8; 32]> {
+    let field = |value: &Option<String>, name: &str| -> Result<Vec<u8>> {
+        from_hex(value.as_deref().ok_or_else(|| anyhow::anyhow!("Master key file has no {}", name))?)
+    };
+    
+    let wrapping_key = passphrase_key(passphrase, &field(&file.salt, "salt")?)?;
+    let key = ChaCha20Poly1305::new(Key::from_slice(&wrapping_key))
+        .decrypt(Nonce::from_slice(&field(&file.nonce, "nonce")?), field(&file.wrapped, "wrapped key")?.as_slice())
+        .map_err(|_| anyhow::anyhow!("Wrong contract state passphrase"))?;
+    to_key(&key)
+}
+
+/// Key derived from a passphrase
+fn passphrase_key(passphrase: &str, salt: &[u8]) -> Result<[u8; 32]> {
+    let mut key = [0u8; 32];
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| anyhow::anyhow!("Failed to derive key from passphrase: {}", e))?;
+    Ok(key)
+}
+
+/// Read the master key file, if present
+fn load_master_key_file() -> Result<Option<MasterKeyFile>> {
+    let path = master_key_path();
+    if !path.exists() {
+        return Ok(None);
+    }
+    let data = fs::read_to_string(&path)?;
+    Ok(Some(serde_json::from_str(&data).with_context(|| format!("Invalid master key file {:?}", path))?))
+}
+
+/// Write the master key file, readable by the owner only
+fn save_master_key_file(file: &MasterKeyFile) -> Result<()> {
+    let path = master_key_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, serde_json::to_string_pretty(file)?)?;
+    
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(())
+}
+
+/// Path of the master key
+pub fn master_key_path() -> PathBuf {
+    constants::root_dir().join(constants::AUTH_DIR).join("keys").join(MASTER_KEY_FILE)
+}
+
+/// Directory of a contract's persisted state
+fn state_dir(contract_name: &str) -> PathBuf {
+    constants::root_dir().join(".zk").join("runtime").join(contract_name)
+}
+
+/// Path of a contract's state file
+fn state_path(contract_name: &str) -> PathBuf {
+    state_dir(contract_name).join(STATE_FILE)
+}
+
+/// Convert bytes to a key
+fn to_key(bytes: &[u8]) -> Result<[u8; 32]> {
+    bytes.try_into().map_err(|_| anyhow::anyhow!("Invalid contract state master key"))
+}
+
+/// Encode bytes as hex
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decode a hex string
+fn from_hex(hex: &str) -> Result<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.is_ascii() {
+        anyhow::bail!("Invalid hex string length");
+    }
+    
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).context("Invalid hex string"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use super::super::contracts::{self, StateVariable};
+    
+    /// Plaintext contract with one `u64` counter
+    fn counter_contract(name: &str) -> ZkContract {
+        let mut contract = contracts::new_contract(name, "1.0.0");
+        contract.state.insert("counter".to_string(), StateVariable {
+            var_type: "u64".to_string(),
+            default: Some("0".to_string()),
+            mutable: true,
+            zk_verified: false,
+        });
+        contract
+    }
+    
+    fn counter(value: u64) -> ContractState {
+        ContractState::from([("counter".to_string(), json!(value))])
+    }
+    
+    #[test]
+    fn saved_state_is_loaded_with_its_hash_and_version() {
+        let contract = counter_contract("state-test-saved");
+        assert_eq!(load_state(&contract).unwrap(), counter(0));
+        assert_eq!(recorded_hash(&contract.name).unwrap(), None);
+        
+        save_state(&contract, &counter(4)).unwrap();
+        assert_eq!(load_state(&contract).unwrap(), counter(4));
+        assert_eq!(recorded_hash(&contract.name).unwrap(), Some(state_hash(&counter(4)).unwrap()));
+        assert_eq!(state_version(&contract.name).unwrap().as_deref(), Some("1.0.0"));
+    }
+    
+    #[test]
+    fn save_cut_short_before_its_record_falls_back_to_the_previous_state() {
+        let contract = counter_contract("state-test-torn");
+        save_state(&contract, &counter(1)).unwrap();
+        save_state(&contract, &counter(2)).unwrap();
+        
+        // What a crash after the state file but before state.meta leaves behind
+        let path = state_path(&contract.name);
+        crate::core::fs::write_atomic(&path, &serde_json::to_vec_pretty(&counter(3)).unwrap()).unwrap();
+        assert_eq!(load_state(&contract).unwrap(), counter(2));
+        
+        // The next save makes the state and its record agree again
+        save_state(&contract, &counter(3)).unwrap();
+        assert_eq!(load_state(&contract).unwrap(), counter(3));
+    }
+    
+    #[test]
+    fn state_modified_outside_the_executor_is_refused() {
+        let contract = counter_contract("state-test-tampered");
+        save_state(&contract, &counter(1)).unwrap();
+        save_state(&contract, &counter(2)).unwrap();
+        
+        let path = state_path(&contract.name);
+        fs::write(&path, serde_json::to_vec(&counter(9)).unwrap()).unwrap();
+        fs::write(crate::core::fs::backup_path(&path), serde_json::to_vec(&counter(8)).unwrap()).unwrap();
+        let error = load_state(&contract).unwrap_err();
+        assert!(error.to_string().contains("does not match its recorded hash"), "{:#}", error);
+        
+        assert!(reset_state(&contract.name).unwrap());
+        assert_eq!(load_state(&contract).unwrap(), counter(0));
+    }
+    
+    #[test]
+    fn encrypted_state_round_trips_and_hides_its_values() {
+        let mut contract = counter_contract("state-test-encrypted");
+        contract.state_encryption = true;
+        save_state(&contract, &counter(424242)).unwrap();
+        
+        let on_disk = fs::read_to_string(state_path(&contract.name)).unwrap();
+        assert!(on_disk.contains(ENCRYPTED_FORMAT) && !on_disk.contains("424242"));
+        assert_eq!(storage_status(&contract.name).unwrap(), StorageStatus::Encrypted);
+        assert!(encrypted_contracts().unwrap().contains(&contract.name));
+        assert!(check_readable(&contract.name).is_ok());
+        
+        // The record holds the plaintext hash, so proofs do not depend on the storage
+        assert_eq!(load_state(&contract).unwrap(), counter(424242));
+        assert_eq!(recorded_hash(&contract.name).unwrap(), Some(state_hash(&counter(424242)).unwrap()));
+    }
+    
+    #[test]
+    fn encrypted_state_only_opens_with_its_own_contract_key() {
+        let mut contract = counter_contract("state-test-keyed");
+        contract.state_encryption = true;
+        save_state(&contract, &counter(7)).unwrap();
+        
+        // Another contract's ciphertext does not decrypt under this contract's key
+        let copied = counter_contract("state-test-keyed-copy");
+        fs::create_dir_all(state_dir(&copied.name)).unwrap();
+        fs::copy(state_path(&contract.name), state_path(&copied.name)).unwrap();
+        assert!(check_readable(&copied.name).unwrap_err().to_string().contains("cannot be decrypted"));
+        
+        let path = state_path(&contract.name);
+        let mut encrypted: EncryptedState = serde_json::from_slice(&fs::read(&path).unwrap()).unwrap();
+        let flipped = if encrypted.ciphertext.starts_with('0') { "1" } else { "0" };
+        encrypted.ciphertext.replace_range(..1, flipped);
+        fs::write(&path, serde_json::to_vec(&encrypted).unwrap()).unwrap();
+        assert!(check_readable(&contract.name).is_err());
+        assert!(load_state(&contract).is_err());
+    }
+    
+    #[test]
+    fn persisted_state_is_migrated_to_the_contract_version() {
+        let old = counter_contract("state-test-migrate");
+        assert_eq!(migrate(&old).unwrap(), None);
+        save_state(&old, &counter(6)).unwrap();
+        assert_eq!(migrate(&old).unwrap(), None);
+        
+        let mut new = contracts::new_contract("state-test-migrate", "2.0.0");
+        new.state.insert("total".to_string(), StateVariable {
+            var_type: "u64".to_string(),
+            default: Some("0".to_string()),
+            mutable: true,
+            zk_verified: false,
+        });
+        new.migrations.push(contracts::MigrationStep {
+            from_version: "1.0.0".to_string(),
+            to_version: "2.0.0".to_string(),
+            script: "rename counter to total".to_string(),
+        });
+        
+        assert_eq!(migrate(&new).unwrap().as_deref(), Some("1.0.0"));
+        assert_eq!(load_state(&new).unwrap(), ContractState::from([("total".to_string(), json!(6))]));
+        assert_eq!(state_version(&new.name).unwrap().as_deref(), Some("2.0.0"));
+        assert_eq!(migrate(&new).unwrap(), None);
+        
+        // A failed migration leaves the persisted state as it was
+        let failing = counter_contract("state-test-migrate-failing");
+        save_state(&failing, &counter(1)).unwrap();
+        let mut next = failing.clone();
+        next.version = "1.1.0".to_string();
+        assert!(migrate(&next).is_err());
+        assert_eq!(load_state(&failing).unwrap(), counter(1));
+        assert_eq!(state_version(&failing.name).unwrap().as_deref(), Some("1.0.0"));
+    }
+    
+    #[test]
+    fn protected_master_keys_unwrap_only_with_the_passphrase() {
+        assert!(protect_master_key("short").is_err());
+        protect_master_key("correct horse battery").unwrap();
+        
+        let file = load_master_key_file().unwrap().unwrap();
+        assert!(file.protected && file.key.is_none());
+        assert_eq!(unwrap_master_key(&file, "correct horse battery").unwrap(), master_key().unwrap());
+        assert!(unwrap_master_key(&file, "wrong horse battery").is_err());
+    }
+}
@@ -0,0 +1,440 @@
+// SentientOS ZK Contract Testing
+// Runs contract methods against in-memory state, and test cases from .zk/tests
+
+use anyhow::{Result, Context};
+use tracing::{info, warn};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use serde::{Serialize, Deserialize};
+use thiserror::Error;
+
+use super::contracts::ZkContract;
+use super::executor::{self, ExecutionMode};
+use super::state::{self, ContractState};
+use crate::core::constants;
+
+// Constants
+const TESTS_DIR: &str = ".zk/tests";
+
+/// Why a test call or assertion failed
+#[derive(Debug, Clone, Error)]
+pub enum TestFailure {
+    /// A rule the method checked did not hold; the call's state changes are dropped
+    #[error("{method} violated rule {rule}")]
+    RuleViolation {
+        /// Method called
+        method: String,
+        
+        /// Rule that failed
+        rule: String,
+    },
+    
+    /// The method could not be run
+    #[error("{method} failed: {error}")]
+    Execution {
+        /// Method called
+        method: String,
+        
+        /// Error the executor returned
+        error: String,
+    },
+    
+    /// The method returned something else than expected
+    #[error("{method} returned {actual}, expected {expected}")]
+    UnexpectedReturn {
+        /// Method called
+        method: String,
+        
+        /// Expected value
+        expected: serde_json::Value,
+        
+        /// Returned value
+        actual: serde_json::Value,
+    },
+    
+    /// A state variable holds something else than expected
+    #[error("state.{key} is {}, expected {}", display(.actual), display(.expected))]
+    UnexpectedState {
+        /// Variable name
+        key: String,
+        
+        /// Expected value, `None` if it should be unset
+        expected: Option<serde_json::Value>,
+        
+        /// Actual value, `None` if unset
+        actual: Option<serde_json::Value>,
+    },
+}
+
+/// Runs a contract's methods against in-memory state
+///
+/// Nothing is persisted and no proofs are generated. Calls run in simulation
+/// mode, so they see no clock or randomness and enforce the method budgets.
+pub struct ContractTestHarness {
+    /// Contract under test
+    contract: ZkContract,
+    
+    /// State the next call runs against
+    state: ContractState,
+}
+
+impl ContractTestHarness {
+    /// Harness starting from the contract's declared defaults
+    pub fn new(contract: ZkContract) -> Self {
+        let state = state::default_state(&contract);
+        Self { contract, state }
+    }
+    
+    /// Set state variables from a JSON object; variables it omits keep their defaults
+    pub fn set_state(&mut self, values: serde_json::Value) -> Result<()> {
+        let values = match values {
+            serde_json::Value::Object(values) => values,
+            other => anyhow::bail!("Test state must be a JSON object, got {}", other),
+        };
+        
+        let mut state = state::default_state(&self.contract);
+        for (key, value) in values {
+            let variable = self.contract.state.get(&key)
+                .ok_or_else(|| anyhow::anyhow!("Contract {} has no state variable {}", self.contract.name, key))?;
+            if !variable.accepts(&value) {
+                anyhow::bail!("Value {} does not fit state.{} of type {}", value, key, variable.var_type);
+            }
+            state.insert(key, value);
+        }
+        self.state = state;
+        Ok(())
+    }
+    
+    /// Current state
+    pub fn state(&self) -> &ContractState {
+        &self.state
+    }
+    
+    /// Call a method, keeping its state changes unless a rule failed
+    pub fn call(&mut self, method: &str, args: &[serde_json::Value]) -> Result<serde_json::Value, TestFailure> {
+        let run = executor::run_method_on(&self.contract, method, args, self.state.clone(), ExecutionMode::Simulate)
+            .map_err(|e| TestFailure::Execution { method: method.to_string(), error: format!("{:#}", e) })?;
+        if let Some(violated) = run.rules.iter().find(|outcome| !outcome.passed) {
+            return Err(TestFailure::RuleViolation { method: method.to_string(), rule: violated.rule.clone() });
+        }
+        self.state = run.post_state;
+        Ok(run.value)
+    }
+    
+    /// Call a method and check what it returned
+    pub fn assert_call(
+        &mut self,
+        method: &str,
+        args: &[serde_json::Value],
+        expected: &serde_json::Value,
+    ) -> Result<(), TestFailure> {
+        let actual = self.call(method, args)?;
+        if &actual != expected {
+            return Err(TestFailure::UnexpectedReturn {
+                method: method.to_string(),
+                expected: expected.clone(),
+                actual,
+            });
+        }
+        Ok(())
+    }
+    
+    /// Check state variables; variables not listed are not checked
+    pub fn assert_state(&self, expected: &BTreeMap<String, serde_json::Value>) -> Result<(), TestFailure> {
+        for (key, value) in expected {
+            let actual = self.state.get(key);
+            if actual != Some(value) {
+                return Err(TestFailure::UnexpectedState {
+                    key: key.clone(),
+                    expected: Some(value.clone()),
+                    actual: actual.cloned(),
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A test case in `.zk/tests/*.yaml`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TestCase {
+    /// Case name; defaults to the file name
+    #[serde(default)]
+    pub name: String,
+    
+    /// Contract under test
+    pub contract: String,
+    
+    /// Method to call
+    pub method: String,
+    
+    /// State variables to set before the call; others keep their defaults
+    #[serde(default)]
+    pub initial_state: Option<serde_json::Value>,
+    
+    /// Arguments of the call
+    #[serde(default)]
+    pub inputs: Vec<serde_json::Value>,
+    
+    /// Value the call must return, if checked
+    #[serde(default)]
+    pub expected_return: Option<serde_json::Value>,
+    
+    /// State variables the call must leave behind, if checked
+    #[serde(default)]
+    pub expected_state: BTreeMap<String, serde_json::Value>,
+}
+
+/// Outcome of one test case
+#[derive(Debug, Clone)]
+pub struct TestResult {
+    /// Case name
+    pub name: String,
+    
+    /// File the case was read from
+    pub path: PathBuf,
+    
+    /// Why the case failed, `None` if it passed
+    pub failure: Option<TestFailure>,
+}
+
+/// Run one test case against a contract
+pub fn run_case(contract: &ZkContract, case: &TestCase) -> Result<Option<TestFailure>> {
+    let mut harness = ContractTestHarness::new(contract.clone());
+    if let Some(initial_state) = &case.initial_state {
+        harness.set_state(initial_state.clone())
+            .with_context(|| format!("Invalid initial_state in test case {}", case.name))?;
+    }
+    
+    let outcome = match &case.expected_return {
+        Some(expected) => harness.assert_call(&case.method, &case.inputs, expected),
+        None => harness.call(&case.method, &case.inputs).map(|_| ()),
+    };
+    Ok(outcome.and_then(|_| harness.assert_state(&case.expected_state)).err())
+}
+
+/// Run every test case of a contract found in `.zk/tests`
+///
+/// Cases are matched to the contract by their `contract` field and run in
+/// file name order, each from a fresh harness.
+pub fn run_tests(contract_name: &str) -> Result<Vec<TestResult>> {
+    let contract = super::load_contract(&format!(".zk/contracts/{}.yaml", contract_name))?;
+    
+    let mut results = Vec::new();
+    for (path, case) in discover(contract_name)? {
+        let failure = run_case(&contract, &case)?;
+        if let Some(failure) = &failure {
+            warn!("Test case {} of {} failed: {}", case.name, contract_name, failure);
+        }
+        results.push(TestResult { name: case.name, path, failure });
+    }
+    
+    let failed = results.iter().filter(|r| r.failure.is_some()).count();
+    info!("Ran {} test case(s) of {}: {} failed", results.len(), contract_name, failed);
+    Ok(results)
+}
+
+/// Test cases of a contract in `.zk/tests`, by file name
+fn discover(contract_name: &str) -> Result<Vec<(PathBuf, TestCase)>> {
+    let dir = constants::root_dir().join(TESTS_DIR);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    
+    let mut paths: Vec<PathBuf> = fs::read_dir(&dir)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "yaml" || ext == "yml"))
+        .collect();
+    paths.sort();
+    
+    let mut cases = Vec::new();
+    for path in paths {
+        let mut case: TestCase = serde_yaml::from_str(&fs::read_to_string(&path)?)
+            .with_context(|| format!("Invalid test case {:?}", path))?;
+        if case.contract != contract_name {
+            continue;
+        }
+        if case.name.is_empty() {
+            case.name = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        }
+        cases.push((path, case));
+    }
+    Ok(cases)
+}
+
+/// A state value for messages
+fn display(value: &Option<serde_json::Value>) -> String {
+    value.as_ref().map_or_else(|| "unset".to_string(), |v| v.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use super::super::parser;
+    
+    /// The `basic` template of `sentctl zk create`, as a full contract
+    const BASIC: &str = r#"
+name: basic
+version: 0.1.0
+permissions:
+  filesystem: { read: [], write: [] }
+  network: { outbound: false, inbound: false, allowed_hosts: [] }
+  system: { exec: false, memory_limit: null, cpu_limit: null }
+state:
+  counter: { var_type: u64, default: "0", mutable: true, zk_verified: true }
+  last_updated: { var_type: string, default: '""', mutable: true, zk_verified: false }
+methods:
+  increment:
+    name: increment
+    params: {}
+    return_type: u64
+    implementation: |
+      state.counter += 1;
+      verify_rule("counter_positive");
+      return state.counter;
+    pure: false
+    zk_verified: true
+  get_counter:
+    name: get_counter
+    params: {}
+    return_type: u64
+    implementation: |
+      return state.counter;
+    pure: true
+    zk_verified: false
+rules:
+  - name: counter_positive
+    condition: state.counter >= 0
+    effect: revert if counter becomes negative
+    zk_verified: true
+"#;
+
+    fn basic() -> ZkContract {
+        parser::parse_zk_yaml(BASIC).unwrap()
+    }
+    
+    fn expected(values: serde_json::Value) -> BTreeMap<String, serde_json::Value> {
+        serde_json::from_value(values).unwrap()
+    }
+    
+    #[test]
+    fn harness_starts_from_the_declared_defaults() {
+        let harness = ContractTestHarness::new(basic());
+        assert_eq!(harness.state(), &expected(json!({ "counter": 0, "last_updated": "" })));
+    }
+    
+    #[test]
+    fn set_state_keeps_unlisted_defaults_and_refuses_bad_values() {
+        let mut harness = ContractTestHarness::new(basic());
+        harness.set_state(json!({ "counter": 5 })).unwrap();
+        assert!(harness.assert_state(&expected(json!({ "counter": 5, "last_updated": "" }))).is_ok());
+        
+        assert!(harness.set_state(json!({ "counter": -1 })).is_err());
+        assert!(harness.set_state(json!({ "counter": "five" })).is_err());
+        assert!(harness.set_state(json!({ "owner": "alice" })).is_err());
+        assert!(harness.set_state(json!([5])).is_err());
+        assert_eq!(harness.state()["counter"], json!(5));
+    }
+    
+    #[test]
+    fn passing_calls_keep_their_state_changes() {
+        let mut harness = ContractTestHarness::new(basic());
+        for expected_value in 1..=2u64 {
+            assert_eq!(harness.call("increment", &[]).unwrap(), json!(expected_value));
+        }
+        assert!(harness.assert_state(&expected(json!({ "counter": 2 }))).is_ok());
+        assert!(harness.assert_call("get_counter", &[], &json!(2)).is_ok());
+        
+        match harness.assert_state(&expected(json!({ "counter": 3 }))) {
+            Err(TestFailure::UnexpectedState { key, expected, actual }) => {
+                assert_eq!(key, "counter");
+                assert_eq!((expected, actual), (Some(json!(3)), Some(json!(2))));
+            }
+            other => panic!("expected UnexpectedState, got {:?}", other),
+        }
+    }
+    
+    #[test]
+    fn rule_violations_fail_the_call_and_drop_its_state() {
+        let mut contract = basic();
+        contract.rules[0].condition = "state.counter < 8".to_string();
+        let mut harness = ContractTestHarness::new(contract);
+        harness.set_state(json!({ "counter": 6 })).unwrap();
+        assert_eq!(harness.call("increment", &[]).unwrap(), json!(7));
+        
+        match harness.call("increment", &[]) {
+            Err(TestFailure::RuleViolation { method, rule }) => {
+                assert_eq!((method.as_str(), rule.as_str()), ("increment", "counter_positive"));
+            }
+            other => panic!("expected RuleViolation, got {:?}", other),
+        }
+        assert_eq!(harness.state()["counter"], json!(7));
+        assert!(harness.assert_call("get_counter", &[], &json!(7)).is_ok());
+    }
+    
+    #[test]
+    fn unknown_methods_fail_without_panicking() {
+        let mut harness = ContractTestHarness::new(basic());
+        match harness.call("decrement", &[]) {
+            Err(TestFailure::Execution { method, error }) => {
+                assert_eq!(method, "decrement");
+                assert!(error.contains("Method not found"), "{}", error);
+            }
+            other => panic!("expected Execution, got {:?}", other),
+        }
+        assert_eq!(harness.state(), &state::default_state(&basic()));
+    }
+    
+    #[test]
+    fn test_cases_are_discovered_by_contract_in_file_order() {
+        let contracts = constants::root_dir().join(".zk/contracts");
+        let tests = constants::root_dir().join(TESTS_DIR);
+        fs::create_dir_all(&contracts).unwrap();
+        fs::create_dir_all(&tests).unwrap();
+        fs::write(contracts.join("discovered.yaml"), BASIC.replace("name: basic", "name: discovered")).unwrap();
+        
+        fs::write(tests.join("discovered-2.yaml"), "contract: discovered\nmethod: get_counter\nexpected_return: 0\n").unwrap();
+        fs::write(tests.join("discovered-1.yml"), concat!(
+            "name: increments from five\n",
+            "contract: discovered\n",
+            "method: increment\n",
+            "initial_state: { counter: 5 }\n",
+            "expected_return: 6\n",
+            "expected_state: { counter: 6 }\n",
+        )).unwrap();
+        fs::write(tests.join("discovered-other.yaml"), "contract: other\nmethod: increment\n").unwrap();
+        fs::write(tests.join("discovered-notes.txt"), "not a test case").unwrap();
+        
+        let cases = discover("discovered").unwrap();
+        let names: Vec<&str> = cases.iter().map(|(_, case)| case.name.as_str()).collect();
+        assert_eq!(names, ["increments from five", "discovered-2"]);
+        
+        let (_, case) = &cases[0];
+        assert_eq!(case.initial_state, Some(json!({ "counter": 5 })));
+        assert_eq!(case.expected_return, Some(json!(6)));
+        assert_eq!(case.expected_state, expected(json!({ "counter": 6 })));
+        
+        let results = run_tests("discovered").unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].path, tests.join("discovered-2.yaml"));
+        assert!(results.iter().all(|result| result.failure.is_none()), "{:?}", results);
+    }
+    
+    #[test]
+    fn invalid_initial_state_fails_the_run() {
+        let case = TestCase {
+            name: "bad state".to_string(),
+            contract: "basic".to_string(),
+            method: "increment".to_string(),
+            initial_state: Some(json!({ "counter": "many" })),
+            inputs: Vec::new(),
+            expected_return: None,
+            expected_state: BTreeMap::new(),
+        };
+        let error = run_case(&basic(), &case).unwrap_err();
+        assert!(format!("{:#}", error).contains("bad state"), "{:#}", error);
+    }
+}
@@ -50,7 +50,7 @@ pub fn init() -> Result<()> {
     info!("Initializing ZK verification system");
     
     // Create verification directories
-    let zk_dir = PathBuf::from(constants::ROOT_DIR).join(".zk");
+    let zk_dir = constants::root_dir().join(".zk");
     fs::create_dir_all(&zk_dir)?;
     
     let proofs_dir = zk_dir.join("proofs");
@@ -146,7 +146,7 @@ pub fn verify_proof(contract_name: &str, proof: &str, expected_output: &str) ->
 
 /// Store a proof for later verification
 fn store_proof(contract_name: &str, proof: &str, input_data: &str) -> Result<()> {
-    let zk_dir = PathBuf::from(constants::ROOT_DIR).join(".zk");
+    let zk_dir = constants::root_dir().join(".zk");
     let proofs_dir = zk_dir.join("proofs");
     
     // Create contract directory if it doesn't exist
@@ -164,7 +164,7 @@ fn store_proof(contract_name: &str, proof: &str, input_data: &str) -> Result<()>
 
 /// Get the input data for a stored proof
 fn get_proof_input(contract_name: &str, proof: &str) -> Result<String> {
-    let zk_dir = PathBuf::from(constants::ROOT_DIR).join(".zk");
+    let zk_dir = constants::root_dir().join(".zk");
     let proofs_dir = zk_dir.join("proofs");
     
     let contract_dir = proofs_dir.join(contract_name);
@@ -181,7 +181,7 @@ fn get_proof_input(contract_name: &str, proof: &str) -> Result<String> {
 
 /// Store a verification result
 fn store_verification_result(result: &VerificationResult) -> Result<()> {
-    let zk_dir = PathBuf::from(constants::ROOT_DIR).join(".zk");
+    let zk_dir = constants::root_dir().join(".zk");
     let results_dir = zk_dir.join("results");
     
     // Create contract directory if it doesn't exist
@@ -200,7 +200,7 @@ fn store_verification_result(result: &VerificationResult) -> Result<()> {
 
 /// Load a ZK contract by name
 fn load_contract(contract_name: &str) -> Result<ZkContract> {
-    let zk_dir = PathBuf::from(constants::ROOT_DIR).join(".zk");
+    let zk_dir = constants::root_dir().join(".zk");
     let contracts_dir = zk_dir.join("contracts");
     
     let contract_file = contracts_dir.join(format!("{}.yaml", contract_name));
@@ -217,7 +217,7 @@ fn load_contract(contract_name: &str) -> Result<ZkContract> {
 
 /// List all verification results for a contract
 pub fn list_verification_results(contract_name: &str) -> Result<Vec<VerificationResult>> {
-    let zk_dir = PathBuf::from(constants::ROOT_DIR).join(".zk");
+    let zk_dir = constants::root_dir().join(".zk");
     let results_dir = zk_dir.join("results");
     
     let contract_dir = results_dir.join(contract_name);
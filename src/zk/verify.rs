@@ -1,17 +1,34 @@
 use anyhow::{Result, Context};
 use tracing::{info, warn};
 use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use thiserror::Error;
 use blake3;
+use ark_bn254::{Bn254, Fr, G1Affine, G1Projective};
+use ark_ec::pairing::Pairing;
+use ark_ec::{CurveGroup, VariableBaseMSM};
+use ark_ff::{UniformRand, Zero};
+use ark_groth16::{prepare_verifying_key, Groth16, Proof, VerifyingKey};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_snark::SNARK;
+use ark_std::rand::{rngs::StdRng, SeedableRng};
 
 use super::contracts::ZkContract;
+use super::circuits::{Synthesizer, ZkCircuit};
 use crate::core::constants;
+use crate::core::lock;
+
+/// Lock guarding persisted contract state under .zk/contracts
+pub const CONTRACT_STATE_LOCK: &str = "contract-state";
 
 /// Initialize the ZK verification system
 pub fn init() -> Result<()> {
     info!("Initializing ZK verification system");
     
     // Create necessary directories
-    let zk_dir = PathBuf::from(constants::ROOT_DIR).join(".zk");
+    let zk_dir = constants::root_dir().join(".zk");
     std::fs::create_dir_all(&zk_dir)
         .context("Failed to create .zk directory")?;
     
@@ -19,19 +36,11 @@ pub fn init() -> Result<()> {
     std::fs::create_dir_all(&proofs_dir)
         .context("Failed to create .zk/proofs directory")?;
     
+    // Contract parameters are generated here on first proof
     let keys_dir = zk_dir.join("keys");
     std::fs::create_dir_all(&keys_dir)
         .context("Failed to create .zk/keys directory")?;
     
-    // Create example verification key if it doesn't exist
-    let example_key_path = keys_dir.join("example_key.json");
-    if !example_key_path.exists() {
-        // In a real implementation, this would be a proper verification key
-        // For now, just create a placeholder
-        std::fs::write(example_key_path, "{\"key\": \"example_verification_key\"}")
-            .context("Failed to create example verification key")?;
-    }
-    
     info!("ZK verification system initialized");
     Ok(())
 }
@@ -80,63 +89,155 @@ pub fn verify_contract(contract: &ZkContract) -> Result<bool> {
     Ok(true)
 }
 
-/// Generate a ZK proof for a given operation
-pub fn generate_proof(data: &[u8], operation: &str) -> Result<Vec<u8>> {
-    info!("Generating ZK proof for operation: {}", operation);
+/// Generate a Groth16 proof of a witness for a circuit
+///
+/// The circuit's parameters are generated on first use. Returns the
+/// compressed serialized proof.
+pub fn generate_proof(circuit: &dyn ZkCircuit, witness: &[u8]) -> Result<Vec<u8>> {
+    info!("Generating ZK proof for circuit: {}", circuit.name());
     
-    // In a real implementation, this would:
-    // 1. Create a ZK circuit for the operation
-    // 2. Generate witnesses from the data
-    // 3. Create a proof using the circuit and witnesses
+    let params = super::setup::generate_parameters(circuit)?;
+    let mut rng = StdRng::from_entropy();
+    let proof = Groth16::<Bn254>::prove(&params, Synthesizer::prove(circuit, witness), &mut rng)
+        .map_err(|e| anyhow::anyhow!("Proving {} failed: {}", circuit.name(), e))?;
     
-    // For now, we'll just create a mock proof using Blake3 hash
-    let hash = blake3::hash(data);
-    let mut proof = hash.as_bytes().to_vec();
+    let mut bytes = Vec::new();
+    proof.serialize_compressed(&mut bytes)
+        .map_err(|e| anyhow::anyhow!("Failed to serialize proof of {}: {}", circuit.name(), e))?;
     
-    // Add the operation name to the proof
-    proof.extend_from_slice(operation.as_bytes());
-    
-    info!("Generated mock ZK proof for operation: {} ({} bytes)", operation, proof.len());
-    
-    Ok(proof)
+    info!("Generated ZK proof for circuit: {} ({} bytes)", circuit.name(), bytes.len());
+    Ok(bytes)
 }
 
-/// Verify a ZK proof for a given operation
-pub fn verify_proof(data: &[u8], proof: &[u8], operation: &str) -> Result<bool> {
-    info!("Verifying ZK proof for operation: {}", operation);
-    
-    // In a real implementation, this would:
-    // 1. Load the verification key for the operation
-    // 2. Verify the proof against the data using the key
+/// Verify a serialized Groth16 proof against a verifying key and public inputs
+///
+/// Proofs that do not deserialize are reported as invalid rather than as errors.
+pub fn verify_proof(vk: &VerifyingKey<Bn254>, proof: &[u8], public_inputs: &[Fr]) -> Result<bool> {
+    let proof = match Proof::<Bn254>::deserialize_compressed(proof) {
+        Ok(proof) => proof,
+        Err(e) => {
+            warn!("Malformed ZK proof: {}", e);
+            return Ok(false);
+        }
+    };
     
-    // For now, we'll just verify our mock proof using Blake3 hash
-    let hash = blake3::hash(data);
-    let expected_proof_prefix = hash.as_bytes();
+    let pvk = prepare_verifying_key(vk);
+    Groth16::<Bn254>::verify_proof(&pvk, &proof, public_inputs)
+        .map_err(|e| anyhow::anyhow!("ZK proof verification failed: {}", e))
+}
+
+/// Serialize a proof with the public inputs it was made for
+///
+/// This is the form `batch_verify` takes proofs in: a bundle can be checked
+/// with nothing but its contract's verifying key.
+pub fn bundle_proof(proof: &[u8], public_inputs: &[Fr]) -> Result<Vec<u8>> {
+    let mut bundle = proof.to_vec();
+    public_inputs.to_vec().serialize_compressed(&mut bundle)
+        .map_err(|e| anyhow::anyhow!("Failed to serialize public inputs: {}", e))?;
+    Ok(bundle)
+}
+
+/// Split a bundle made by `bundle_proof` into its proof and public inputs
+fn unbundle_proof(mut bundle: &[u8]) -> Result<(Proof<Bn254>, Vec<Fr>), ark_serialize::SerializationError> {
+    let proof = Proof::<Bn254>::deserialize_compressed(&mut bundle)?;
+    let inputs = Vec::<Fr>::deserialize_compressed(&mut bundle)?;
+    Ok((proof, inputs))
+}
+
+/// Verify many proofs together, returning whether each one is valid
+///
+/// Each item is a contract and a proof made with its parameters, bundled
+/// with its public inputs by `bundle_proof`. All verification equations are
+/// combined with random weights into a single multi-pairing, with the input
+/// and `C` terms of each contract folded by multi-scalar multiplication. If
+/// the combined check fails, the proofs are checked one by one to find the
+/// invalid ones.
+pub fn batch_verify(proofs: &[(ZkContract, Vec<u8>)]) -> Result<Vec<bool>> {
+    let mut results = vec![false; proofs.len()];
     
-    // Check if the proof starts with the expected hash
-    if proof.len() < expected_proof_prefix.len() {
-        warn!("Proof too short for operation: {}", operation);
-        return Ok(false);
+    // Group well-formed proofs by contract, since each contract has its own key
+    let mut groups: BTreeMap<&str, (VerifyingKey<Bn254>, Vec<(usize, Proof<Bn254>, Vec<Fr>)>)> = BTreeMap::new();
+    for (index, (contract, bundle)) in proofs.iter().enumerate() {
+        let (proof, inputs) = match unbundle_proof(bundle) {
+            Ok(unbundled) => unbundled,
+            Err(e) => {
+                warn!("Malformed ZK proof for {}: {}", contract.name, e);
+                continue;
+            }
+        };
+        if !groups.contains_key(contract.name.as_str()) {
+            match super::setup::verifying_key(&contract.name)? {
+                Some(vk) => {
+                    groups.insert(&contract.name, (vk, Vec::new()));
+                }
+                None => {
+                    warn!("No ZK parameters for {}; cannot verify its proofs", contract.name);
+                    continue;
+                }
+            }
+        }
+        
+        let (vk, members) = groups.get_mut(contract.name.as_str()).unwrap();
+        if inputs.len() + 1 != vk.gamma_abc_g1.len() {
+            warn!("Wrong number of public inputs for {}: {}", contract.name, inputs.len());
+            continue;
+        }
+        members.push((index, proof, inputs));
     }
     
-    let proof_prefix = &proof[0..expected_proof_prefix.len()];
-    if proof_prefix != expected_proof_prefix {
-        warn!("Proof hash mismatch for operation: {}", operation);
-        return Ok(false);
+    // Σ r·e(A, B) = e(Σ r·α, β) + e(Σ r·L, γ) + e(Σ r·C, δ) for every contract
+    let mut rng = StdRng::from_entropy();
+    let mut g1 = Vec::new();
+    let mut g2 = Vec::new();
+    for (vk, members) in groups.values() {
+        if members.is_empty() {
+            continue;
+        }
+        let weights: Vec<Fr> = members.iter().map(|_| Fr::rand(&mut rng)).collect();
+        let weight_sum: Fr = weights.iter().sum();
+        
+        let input_scalars: Vec<Fr> = std::iter::once(weight_sum)
+            .chain((0..vk.gamma_abc_g1.len() - 1).map(|j| {
+                members.iter().zip(&weights).map(|((_, _, inputs), weight)| *weight * inputs[j]).sum()
+            }))
+            .collect();
+        let inputs = G1Projective::msm(&vk.gamma_abc_g1, &input_scalars)
+            .map_err(|_| anyhow::anyhow!("Input combination failed"))?;
+        let c_points: Vec<G1Affine> = members.iter().map(|(_, proof, _)| proof.c).collect();
+        let c = G1Projective::msm(&c_points, &weights)
+            .map_err(|_| anyhow::anyhow!("Proof combination failed"))?;
+        
+        for ((_, proof, _), weight) in members.iter().zip(&weights) {
+            g1.push((proof.a * *weight).into_affine());
+            g2.push(proof.b);
+        }
+        g1.push((vk.alpha_g1 * -weight_sum).into_affine());
+        g2.push(vk.beta_g2);
+        g1.push((-inputs).into_affine());
+        g2.push(vk.gamma_g2);
+        g1.push((-c).into_affine());
+        g2.push(vk.delta_g2);
     }
     
-    // Check if the proof contains the operation name
-    let operation_bytes = operation.as_bytes();
-    let proof_suffix = &proof[expected_proof_prefix.len()..];
-    if proof_suffix != operation_bytes {
-        warn!("Proof operation mismatch: expected '{}', found '{}'", 
-              operation, 
-              String::from_utf8_lossy(proof_suffix));
-        return Ok(false);
+    let batch_valid = g1.is_empty() || Bn254::multi_pairing(g1, g2).is_zero();
+    if batch_valid {
+        for (_, members) in groups.values() {
+            for (index, _, _) in members {
+                results[*index] = true;
+            }
+        }
+    } else {
+        let pvks: BTreeMap<&str, _> = groups.iter().map(|(name, (vk, _))| (*name, prepare_verifying_key(vk))).collect();
+        for (name, (_, members)) in &groups {
+            for (index, proof, inputs) in members {
+                results[*index] = Groth16::<Bn254>::verify_proof(&pvks[name], proof, inputs)
+                    .map_err(|e| anyhow::anyhow!("ZK proof verification failed: {}", e))?;
+            }
+        }
     }
     
-    info!("ZK proof verification successful for operation: {}", operation);
-    Ok(true)
+    info!("Batch verified {} proof(s), {} valid", proofs.len(), results.iter().filter(|valid| **valid).count());
+    Ok(results)
 }
 
 /// Register a new ZK contract in the verification system
@@ -148,10 +249,12 @@ pub fn register_contract(contract: &ZkContract) -> Result<()> {
     // 2. Store the circuit and verification keys
     
     // For now, we'll just store the contract name
-    let contracts_dir = PathBuf::from(constants::ROOT_DIR).join(".zk").join("contracts");
+    let contracts_dir = constants::root_dir().join(".zk").join("contracts");
     std::fs::create_dir_all(&contracts_dir)
         .context("Failed to create .zk/contracts directory")?;
     
+    let _lock = lock::lock(CONTRACT_STATE_LOCK, &format!("register {}", contract.name), lock::DEFAULT_TIMEOUT)?;
+    
     let contract_path = contracts_dir.join(format!("{}.yaml", contract.name));
     let yaml = serde_yaml::to_string(contract)
         .context("Failed to serialize contract to YAML")?;
@@ -162,3 +265,318 @@ pub fn register_contract(contract: &ZkContract) -> Result<()> {
     info!("Registered ZK contract: {}", contract.name);
     Ok(())
 }
+
+/// Record of a successful contract verification
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VerificationRecord {
+    /// Hash of the effective (import-resolved) contract
+    pub contract_hash: String,
+    
+    /// Hashes of the imported libraries at verification time
+    pub imports: BTreeMap<String, String>,
+    
+    /// Verification timestamp
+    pub verified_at: u64,
+}
+
+/// Validity window of a stored proof
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofLifetime {
+    /// When the proof was generated
+    pub generated_at: u64,
+    
+    /// When the proof stops being accepted
+    pub expires_at: u64,
+}
+
+/// A stored proof was verified after its lifetime ended
+#[derive(Debug, Clone, Error)]
+#[error("Proof generated at {generated_at} expired at {expires_at}")]
+pub struct ProofExpired {
+    /// When the proof was generated
+    pub generated_at: u64,
+    
+    /// When the proof expired
+    pub expires_at: u64,
+}
+
+impl ProofLifetime {
+    /// Lifetime of a proof generated now under the given policy
+    pub fn new(policy: &super::policy::ProofPolicy) -> Result<Self> {
+        let generated_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        Ok(Self {
+            generated_at,
+            expires_at: generated_at.saturating_add(policy.max_proof_age_secs),
+        })
+    }
+    
+    /// Fail with `ProofExpired` once the proof is past its expiry
+    pub fn check(&self) -> Result<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        if now >= self.expires_at {
+            return Err(ProofExpired { generated_at: self.generated_at, expires_at: self.expires_at }.into());
+        }
+        Ok(())
+    }
+    
+    /// Whether the proof expires within `secs` from now
+    pub fn expires_within(&self, secs: u64) -> Result<bool> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        Ok(now.saturating_add(secs) >= self.expires_at)
+    }
+}
+
+/// Verification state of a contract in the zk-verify report
+#[derive(Debug, Clone)]
+pub enum ContractVerificationStatus {
+    /// Verified and unchanged since
+    Verified,
+    
+    /// Never verified
+    Unverified,
+    
+    /// Contract or an imported library changed since verification
+    NeedsReverification(Vec<String>),
+    
+    /// Contract could not be loaded or resolved
+    Invalid(String),
+}
+
+/// Hash of an effective contract, covering all inlined imports
+pub fn contract_hash(contract: &ZkContract) -> Result<String> {
+    // Go through serde_json::Value so map keys are serialized in sorted order
+    let value = serde_json::to_value(contract)?;
+    let canonical = serde_json::to_string(&value)?;
+    Ok(blake3::hash(canonical.as_bytes()).to_hex().to_string())
+}
+
+/// Record that a contract passed verification, along with its import hashes
+pub fn record_verification(contract: &ZkContract) -> Result<()> {
+    let record = VerificationRecord {
+        contract_hash: contract_hash(contract)?,
+        imports: super::imports::import_hashes(contract)?,
+        verified_at: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+    };
+    
+    let _lock = lock::lock(CONTRACT_STATE_LOCK, &format!("record verification of {}", contract.name), lock::DEFAULT_TIMEOUT)?;
+    
+    let mut records = load_verification_records()?;
+    records.insert(contract.name.clone(), record);
+    
+    std::fs::write(verification_records_path(), serde_json::to_string_pretty(&records)?)
+        .context("Failed to write verification records")?;
+    
+    Ok(())
+}
+
+/// Verification status of every contract in .zk/contracts
+pub fn contract_verification_report() -> Result<BTreeMap<String, ContractVerificationStatus>> {
+    let contracts_dir = constants::root_dir().join(".zk").join("contracts");
+    let records = load_verification_records()?;
+    let mut report = BTreeMap::new();
+    
+    if !contracts_dir.exists() {
+        return Ok(report);
+    }
+    
+    for entry in std::fs::read_dir(&contracts_dir)? {
+        let path = entry?.path();
+        if path.extension().map_or(true, |ext| ext != "yaml") {
+            continue;
+        }
+        
+        let name = match path.file_stem() {
+            Some(stem) => stem.to_string_lossy().to_string(),
+            None => continue,
+        };
+        
+        let status = match load_effective_contract(&path) {
+            Err(e) => ContractVerificationStatus::Invalid(e.to_string()),
+            Ok(contract) => match records.get(&contract.name) {
+                None => ContractVerificationStatus::Unverified,
+                Some(record) => compare_with_record(&contract, record)?,
+            },
+        };
+        
+        report.insert(name, status);
+    }
+    
+    Ok(report)
+}
+
+/// Compare a contract's current hashes against its verification record
+fn compare_with_record(contract: &ZkContract, record: &VerificationRecord) -> Result<ContractVerificationStatus> {
+    let mut changed = Vec::new();
+    
+    let current_imports = super::imports::import_hashes(contract)?;
+    for (library, hash) in &current_imports {
+        match record.imports.get(library) {
+            Some(recorded) if recorded == hash => {}
+            Some(_) => changed.push(format!("imported library changed: {}", library)),
+            None => changed.push(format!("new import: {}", library)),
+        }
+    }
+    for library in record.imports.keys() {
+        if !current_imports.contains_key(library) {
+            changed.push(format!("import removed: {}", library));
+        }
+    }
+    
+    if changed.is_empty() && contract_hash(contract)? != record.contract_hash {
+        changed.push("contract changed".to_string());
+    }
+    
+    if changed.is_empty() {
+        Ok(ContractVerificationStatus::Verified)
+    } else {
+        Ok(ContractVerificationStatus::NeedsReverification(changed))
+    }
+}
+
+/// Load and resolve a contract file
+fn load_effective_contract(path: &std::path::Path) -> Result<ZkContract> {
+    let content = std::fs::read_to_string(path)?;
+    super::parser::parse_zk_yaml(&content)
+}
+
+/// Load recorded verifications, keyed by contract name
+fn load_verification_records() -> Result<BTreeMap<String, VerificationRecord>> {
+    let path = verification_records_path();
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    
+    let content = std::fs::read_to_string(&path)
+        .context("Failed to read verification records")?;
+    Ok(serde_json::from_str(&content)?)
+}
+
+/// Path of the verification records file
+fn verification_records_path() -> PathBuf {
+    constants::root_dir().join(".zk").join("verified.json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use super::super::circuits::DigestCircuit;
+    use super::super::contracts::new_contract;
+    use super::super::setup;
+    
+    #[test]
+    fn proof_round_trips_through_disk_and_verifies() {
+        let circuit = DigestCircuit::new("verify-test-e2e.transfer");
+        let witness = b"balance: 42";
+        
+        let proof = generate_proof(&circuit, witness).unwrap();
+        let path = constants::root_dir().join("zk-verify-tests").join("transfer.proof");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, &proof).unwrap();
+        let stored = fs::read(&path).unwrap();
+        assert_eq!(stored, proof);
+        
+        let vk = setup::verifying_key(circuit.name()).unwrap().expect("setup stored the parameters");
+        assert!(verify_proof(&vk, &stored, &circuit.public_inputs(witness).unwrap()).unwrap());
+        
+        // The proof is bound to its witness and operation
+        assert!(!verify_proof(&vk, &stored, &circuit.public_inputs(b"balance: 43").unwrap()).unwrap());
+        let other_method = DigestCircuit::new("verify-test-e2e.burn");
+        assert!(!verify_proof(&vk, &stored, &other_method.public_inputs(witness).unwrap()).unwrap());
+    }
+    
+    #[test]
+    fn parameters_are_generated_once_and_shared_by_methods() {
+        let transfer = DigestCircuit::new("verify-test-params.transfer");
+        let burn = DigestCircuit::new("verify-test-params.burn");
+        
+        generate_proof(&transfer, b"first").unwrap();
+        let hash = setup::parameters_hash("verify-test-params").unwrap().expect("parameters are stored");
+        let proof = generate_proof(&burn, b"second").unwrap();
+        assert_eq!(setup::parameters_hash("verify-test-params").unwrap(), Some(hash));
+        
+        let vk = setup::verifying_key("verify-test-params").unwrap().unwrap();
+        assert!(verify_proof(&vk, &proof, &burn.public_inputs(b"second").unwrap()).unwrap());
+    }
+    
+    #[test]
+    fn damaged_proofs_are_invalid_not_errors() {
+        let circuit = DigestCircuit::new("verify-test-damaged.op");
+        let witness = b"payload";
+        let proof = generate_proof(&circuit, witness).unwrap();
+        let vk = setup::verifying_key(circuit.name()).unwrap().unwrap();
+        let inputs = circuit.public_inputs(witness).unwrap();
+        
+        assert!(!verify_proof(&vk, &proof[..proof.len() / 2], &inputs).unwrap());
+        assert!(!verify_proof(&vk, b"not a proof", &inputs).unwrap());
+        
+        // A flipped bit either fails to decode or fails the pairing check
+        let mut flipped = proof.clone();
+        flipped[proof.len() / 3] ^= 0x01;
+        assert!(!verify_proof(&vk, &flipped, &inputs).unwrap_or(false));
+    }
+    
+    #[test]
+    fn bundles_carry_their_public_inputs() {
+        let circuit = DigestCircuit::new("verify-test-bundle.op");
+        let witness = b"bundled";
+        let proof = generate_proof(&circuit, witness).unwrap();
+        let inputs = circuit.public_inputs(witness).unwrap();
+        
+        let (unbundled, unbundled_inputs) = unbundle_proof(&bundle_proof(&proof, &inputs).unwrap()).unwrap();
+        let mut bytes = Vec::new();
+        unbundled.serialize_compressed(&mut bytes).unwrap();
+        assert_eq!(bytes, proof);
+        assert_eq!(unbundled_inputs, inputs);
+    }
+    
+    #[test]
+    fn lifetimes_follow_the_policy_and_expire() {
+        let policy = super::super::policy::ProofPolicy { max_proof_age_secs: 1000, ..Default::default() };
+        let lifetime = ProofLifetime::new(&policy).unwrap();
+        assert_eq!(lifetime.expires_at - lifetime.generated_at, 1000);
+        assert!(lifetime.check().is_ok());
+        assert!(!lifetime.expires_within(policy.renew_window_secs()).unwrap());
+        assert!(lifetime.expires_within(1000).unwrap());
+        
+        let expired = ProofLifetime { generated_at: 1, expires_at: 2 };
+        let error = expired.check().unwrap_err();
+        let details = error.downcast_ref::<ProofExpired>().unwrap();
+        assert_eq!((details.generated_at, details.expires_at), (1, 2));
+        assert_eq!(error.to_string(), "Proof generated at 1 expired at 2");
+        assert!(expired.expires_within(0).unwrap());
+    }
+    
+    #[test]
+    fn batches_report_each_proof() {
+        let bundle = |circuit: &DigestCircuit, witness: &[u8]| {
+            let proof = generate_proof(circuit, witness).unwrap();
+            bundle_proof(&proof, &circuit.public_inputs(witness).unwrap()).unwrap()
+        };
+        let transfer = DigestCircuit::new("verify-test-batch.transfer");
+        let burn = DigestCircuit::new("verify-test-batch.burn");
+        let mint = DigestCircuit::new("verify-test-batch-other.mint");
+        let batch = new_contract("verify-test-batch", "1.0.0");
+        let other = new_contract("verify-test-batch-other", "1.0.0");
+        
+        let mut proofs = vec![
+            (batch.clone(), bundle(&transfer, b"first")),
+            (batch.clone(), bundle(&burn, b"second")),
+            (other.clone(), bundle(&mint, b"third")),
+        ];
+        assert_eq!(batch_verify(&proofs).unwrap(), vec![true, true, true]);
+        assert!(batch_verify(&[]).unwrap().is_empty());
+        
+        // A proof checked against another witness fails the combined check,
+        // and is then singled out without failing its neighbours
+        let proof = generate_proof(&transfer, b"fourth").unwrap();
+        proofs.push((batch.clone(), bundle_proof(&proof, &transfer.public_inputs(b"fifth").unwrap()).unwrap()));
+        
+        // Malformed, unknown and wrongly shaped proofs are invalid too
+        proofs.push((batch.clone(), b"not a proof".to_vec()));
+        proofs.push((new_contract("verify-test-batch-unknown", "1.0.0"), proofs[0].1.clone()));
+        proofs.push((batch.clone(), bundle_proof(&proof, &[]).unwrap()));
+        
+        assert_eq!(batch_verify(&proofs).unwrap(), vec![true, true, true, false, false, false, false]);
+    }
+}
@@ -1,16 +1,19 @@
 ;; Simple SentientOS WebAssembly Application
 (module
-  ;; Import the function for printing to console
-  (import "env" "print" (func $print (param i32 i32)))
-  
-  ;; Memory section
-  (memory 1)
-  
+  ;; Import the host ABI function for logging, sentient.log(level, ptr, len)
+  (import "sentient" "log" (func $log (param i32 i32 i32)))
+
+  ;; Memory section, exported so the host can read the messages
+  (memory (export "memory") 1)
+
+  ;; Number of completed runs of main
+  (global $runs (export "runs") (mut i32) (i32.const 0))
+
   ;; Data section - strings we'll print
   (data (i32.const 0) "Hello from inside SentientOS!\n")
   (data (i32.const 50) "Running in the burn environment\n")
   (data (i32.const 100) "Calculation result: ")
-  
+
   ;; Function to run calculation
   (func $calculate (result i32)
     (i32.add
@@ -21,32 +24,46 @@
       (i32.const 7)
     )
   )
-  
-  ;; Main function
-  (func $main
+
+  ;; Main function; returns the number of runs so far
+  (func $main (result i32)
     ;; Print first message
-    (call $print
+    (call $log
+      (i32.const 1)
       (i32.const 0)
       (i32.const 30)
     )
-    
+
     ;; Print second message
-    (call $print
+    (call $log
+      (i32.const 1)
       (i32.const 50)
       (i32.const 31)
     )
-    
+
     ;; Print calculation message
-    (call $print
+    (call $log
+      (i32.const 1)
       (i32.const 100)
       (i32.const 19)
     )
-    
-    ;; Do calculation and print result
-    (call $calculate)
-    drop
+
+    ;; Do calculation and keep the result at offset 200
+    (i32.store
+      (i32.const 200)
+      (call $calculate)
+    )
+
+    ;; Count the run
+    (global.set $runs
+      (i32.add
+        (global.get $runs)
+        (i32.const 1)
+      )
+    )
+    (global.get $runs)
   )
-  
+
   ;; Export the main function
   (export "main" (func $main))
 )
@@ -0,0 +1,33 @@
+// SentientOS Burn App - Simple calculator application
+// Runs under the MatrixBox WASM runtime and exercises the sentient.* host ABI
+
+use sentient_abi as sentient;
+
+/// Entry point called by MatrixBox when the container starts
+#[no_mangle]
+pub extern "C" fn _start() {
+    sentient::info("==== SentientOS Burn Calculator ====");
+    sentient::info(&format!("Running in container {}", sentient::container_id()));
+    
+    // Perform some calculations to demonstrate functionality
+    let a = 42;
+    let b = 27;
+    let started = sentient::now_ms();
+    
+    sentient::info(&format!("Addition: {} + {} = {}", a, b, a + b));
+    sentient::info(&format!("Subtraction: {} - {} = {}", a, b, a - b));
+    sentient::info(&format!("Multiplication: {} * {} = {}", a, b, a * b));
+    
+    if b != 0 {
+        sentient::info(&format!("Division: {} / {} = {}", a, b, a / b));
+    }
+    
+    let elapsed = sentient::now_ms() - started;
+    sentient::info(&format!("Application completed in {} ms", elapsed));
+}
+
+/// Health probe; returns 0 when the app is healthy
+#[no_mangle]
+pub extern "C" fn health() -> i32 {
+    0
+}